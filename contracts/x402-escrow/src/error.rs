@@ -0,0 +1,115 @@
+#[soroban_sdk::contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    /// No escrow exists with the given ID
+    EscrowNotFound = 1,
+    /// An escrow already exists for this client-server pair
+    EscrowAlreadyExists = 2,
+    /// The escrow balance does not cover the payment
+    InsufficientBalance = 3,
+    /// No payment exists with the given ID
+    PaymentNotFound = 4,
+    /// The payment has already been settled
+    PaymentAlreadySettled = 5,
+    /// The caller is not the contract admin
+    Unauthorized = 6,
+    /// No price feed is configured
+    OracleNotSet = 7,
+    /// The price feed has no usable price for the escrow asset
+    PriceUnavailable = 8,
+    /// The latest oracle price is older than the staleness limit
+    StalePrice = 9,
+    /// The latest oracle price deviates from its TWAP beyond the slippage limit
+    PriceSlippage = 10,
+    /// The server has no USD price for the resource
+    PriceNotSet = 11,
+    /// The public key is not the key of the escrow party who must sign
+    InvalidSigner = 12,
+    /// The signed message is for another network
+    NetworkMismatch = 13,
+    /// The transfer proven by the memo hash has already been credited
+    DepositAlreadyClaimed = 14,
+    /// The escrow was exported for migration and awaits its import
+    EscrowFrozen = 15,
+    /// Both parties did not consent to the migration, or the target does
+    /// not accept escrows from the source
+    MigrationNotApproved = 16,
+    /// The migration summary is for another target or does not match its
+    /// proof
+    MigrationMismatch = 17,
+    /// The escrow has more unsettled payments than an export carries
+    TooManyPendingPayments = 18,
+    /// No dust policy is configured
+    DustPolicyNotSet = 19,
+    /// The dust threshold is not positive or the inactivity window too short
+    InvalidDustPolicy = 20,
+    /// The escrow holds more than dust or was active too recently
+    EscrowNotDust = 21,
+    /// The escrow has unsettled payments
+    PendingPayments = 22,
+    /// An allowance cap is not positive, the per-payment cap exceeds the
+    /// total, the allowance does not expire in the future, or the agent is
+    /// the client
+    InvalidAllowance = 23,
+    /// The agent holds no allowance from the client
+    AllowanceNotFound = 24,
+    /// The agent's allowance has expired
+    AllowanceExpired = 25,
+    /// The amount is not positive or exceeds the agent's per-payment cap or
+    /// remaining allowance
+    AllowanceExceeded = 26,
+    /// The authorization's nonce has already paid from the escrow
+    AuthorizationUsed = 27,
+    /// The authorization has expired
+    AuthorizationExpired = 28,
+    /// The payment has not been settled
+    PaymentNotSettled = 29,
+    /// The refund is not positive or the payment's refunds would exceed its
+    /// amount
+    InvalidRefund = 30,
+    /// The client holds as many open escrows as the admin allows
+    TooManyEscrows = 31,
+    /// The promo price is negative, its window is empty or over, or the
+    /// resource has too many promos
+    InvalidPromo = 32,
+    /// The payment exceeds the server's current price of the resource
+    PriceExceeded = 33,
+    /// The note is empty or longer than the contract accepts
+    InvalidNote = 34,
+    /// The party already posted a note in this ledger
+    NoteRateLimited = 35,
+    /// The settlement's terms are not those of the payment
+    SettlementMismatch = 36,
+    /// The terms of service accepted are not those the server publishes
+    TermsMismatch = 37,
+    /// The notification URL is longer than the contract accepts
+    InvalidNotificationUrl = 38,
+    /// The authorization is for another escrow than the one opened
+    AuthorizationMismatch = 39,
+    /// Settlements of the escrow are paused, a settlement having breached
+    /// its spend limit
+    SettlementsPaused = 40,
+    /// The spend limit is not positive
+    InvalidSpendLimit = 41,
+    /// The server suspended the sale of the resource
+    ResourceSuspended = 42,
+    /// The amount split off is not positive or exceeds the client's funds
+    /// left once unsettled payments are covered, or the metadata is longer
+    /// than the contract accepts
+    InvalidSplit = 43,
+    /// The direct payment's amount is not positive, or its client already
+    /// recorded its reference
+    InvalidDirectPayment = 44,
+    /// The trial amount is not positive
+    InvalidTrial = 45,
+    /// The contract admin is already set
+    AlreadyInitialized = 46,
+    /// No contract admin is set, `initialize` was not called
+    NotInitialized = 47,
+    /// The amount deposited or claimed is not positive
+    InvalidAmount = 48,
+    /// An authorization spent from the escrow has not expired, so its
+    /// nonce must stay spent where it was
+    AuthorizationsLive = 49,
+}
@@ -0,0 +1,239 @@
+//! Event payloads
+//!
+//! Topics are unchanged since the first deployment. Payloads used to be
+//! bare values and tuples (version 1); they are now structs carrying their
+//! `event_version`, so fields can be added without breaking decoders.
+
+use soroban_sdk::{contracttype, Address, Bytes, BytesN, Symbol};
+#[cfg(feature = "oracle")]
+use soroban_sdk::String;
+
+/// Version of the event payloads emitted by this build
+pub const EVENT_VERSION: u32 = 2;
+
+/// `("open", client, server)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OpenedEvent {
+    pub event_version: u32,
+    pub escrow_id: u64,
+}
+
+/// `("cloned", source_escrow_id)`, after the `open` event of the new escrow
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClonedEvent {
+    pub event_version: u32,
+    /// Escrow opened with the configuration of the source
+    pub escrow_id: u64,
+}
+
+/// `("split", escrow_id)`, after the `open` event of the child escrow
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SplitEvent {
+    pub event_version: u32,
+    /// Escrow split off
+    pub child_escrow_id: u64,
+    /// Balance moved to the child
+    pub amount: i128,
+    pub metadata: Bytes,
+}
+
+/// `("sponsor", escrow_id)`, after the `open` event of a sponsored escrow
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SponsoredEvent {
+    pub event_version: u32,
+    /// Trial funds the server deposited
+    pub amount: i128,
+}
+
+/// `("pay", server, client)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PaymentCreatedEvent {
+    pub event_version: u32,
+    pub payment_id: u64,
+    pub amount: i128,
+}
+
+/// `("pay_usd", payment_id)`, after the `pay` event of the payment
+#[cfg(feature = "oracle")]
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UsdPaymentEvent {
+    pub event_version: u32,
+    pub usd_cents: i128,
+    pub amount: i128,
+    pub resource: String,
+}
+
+/// `("settled", payment_id)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PaymentSettledEvent {
+    pub event_version: u32,
+    pub amount: i128,
+    /// USD equivalent of the amount in cents, see `Payment::quote_amount`
+    pub quote_amount: Option<i128>,
+    pub quote_currency: Option<Symbol>,
+}
+
+/// `("deposit", escrow_id)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DepositedEvent {
+    pub event_version: u32,
+    pub amount: i128,
+}
+
+/// `("claim", escrow_id, from)`, after the `deposit` event of the transfer
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DepositClaimedEvent {
+    pub event_version: u32,
+    pub memo_hash: BytesN<32>,
+}
+
+/// `("direct", client, server)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirectPaymentEvent {
+    pub event_version: u32,
+    pub payment_id: u64,
+    /// Token transferred
+    pub token: Address,
+    pub amount: i128,
+    pub reference: BytesN<32>,
+}
+
+/// `("closed", escrow_id)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClosedEvent {
+    pub event_version: u32,
+    /// Remaining balance released to the client, without trial funds
+    pub released: i128,
+}
+
+/// `("reclaim", escrow_id)`, before the `closed` or `swept` event of a
+/// sponsored escrow whose trial funds were not all spent
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReclaimedEvent {
+    pub event_version: u32,
+    /// Server the trial funds return to
+    pub server: Address,
+    pub amount: i128,
+}
+
+/// `("export", escrow_id)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExportedEvent {
+    pub event_version: u32,
+    pub target: Address,
+    pub proof: BytesN<32>,
+}
+
+/// `("migrated", escrow_id)`, closing an escrow imported by its target
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MigratedEvent {
+    pub event_version: u32,
+    pub target: Address,
+    pub balance: i128,
+}
+
+/// `("import", escrow_id)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ImportedEvent {
+    pub event_version: u32,
+    pub source: Address,
+    /// Escrow ID at the source
+    pub source_escrow_id: u64,
+}
+
+/// `("swept", escrow_id)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SweptEvent {
+    pub event_version: u32,
+    pub client: Address,
+    /// Where the dust is released to
+    pub refund_to: Address,
+    /// Balance released, without trial funds
+    pub balance: i128,
+}
+
+/// `("grant", client, agent)`
+#[cfg(feature = "signed-auth")]
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AgentGrantedEvent {
+    pub event_version: u32,
+    pub total_cap: i128,
+    pub per_payment_cap: i128,
+    pub expires_at: u64,
+}
+
+/// `("revoke", client, agent)`
+#[cfg(feature = "signed-auth")]
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AgentRevokedEvent {
+    pub event_version: u32,
+    /// Allowance left unspent
+    pub remaining: i128,
+}
+
+/// `("refunded", payment_id)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PaymentRefundedEvent {
+    pub event_version: u32,
+    pub amount: i128,
+    /// Total refunded for the payment, this refund included
+    pub refunded: i128,
+}
+
+/// `("note", escrow_id, author)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NoteEvent {
+    pub event_version: u32,
+    pub body: Bytes,
+}
+
+/// `("anomaly", escrow_id)`, pausing the escrow's settlements
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AnomalyEvent {
+    pub event_version: u32,
+    /// Settled in the current window
+    pub settled: i128,
+    /// Settlement that would have breached the limit
+    pub attempted: i128,
+    pub max_settled_per_hour: i128,
+}
+
+/// `("terms", server)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TermsUpdatedEvent {
+    pub event_version: u32,
+    pub terms_hash: BytesN<32>,
+    /// Terms replaced, which escrows opened under them keep
+    pub previous: Option<BytesN<32>>,
+}
+
+/// `("notify", server)`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NotificationUrlEvent {
+    pub event_version: u32,
+    /// URL the server's notifications are delivered to, None once removed
+    pub url: Option<Bytes>,
+}
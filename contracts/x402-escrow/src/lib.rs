@@ -8,10 +8,190 @@
 //! ## Key Features
 //! - Instant API responses (no blockchain wait)
 //! - Guaranteed payment for servers (escrow buffer)
-//! - Automatic fallback if direct payment fails
+//! - Direct payments, transferred to the server and recorded as settled
+//!   payments, the fallback of clients without an escrow
 //! - Two-party consent for escrow closure
+//! - USD-denominated prices converted through a SEP-40 price feed
+//! - Verification of the ed25519 signatures of payment authorizations,
+//!   vouchers, and channel states, encoded as `x402-types` encodes them for
+//!   the SDK
+//! - Two-party consent for moving an escrow to a new deployment
+//! - Sweeping of abandoned dust escrows, released to their clients
+//! - Versioned event payloads, see [`EVENT_VERSION`]
+//! - Capped, expiring allowances letting agents sign payment authorizations
+//!   on a client's behalf
+//! - Server refunds of settled payments, credited back to their escrow
+//! - An admin cap on the open escrows of each client
+//! - Settlements authorized for the terms the server expects of the
+//!   payment, not a bare payment ID
+//! - Short notes between the parties of an escrow, keeping the latest few
+//! - Time-bounded promo prices, capping USD payments while their window is
+//!   open
+//! - Records of escrows, payments, and clients kept in persistent storage,
+//!   one ledger entry each, so unrelated escrows do not contend for writes
+//! - USD equivalents of settled payments, quoted by the price feed when
+//!   they settle and totalled per escrow, for accounts kept in USD
+//! - Payments created and settled in atomic batches, each server
+//!   authorizing its part of the batch once
+//! - Terms of service published by servers as a hash, which clients
+//!   acknowledge when opening an escrow and which stays with the escrow
+//!   when the server publishes new terms
+//! - Escrows cloned for another server with the configuration of an
+//!   existing one, e.g. to onboard a staging environment
+//! - Escrows split by their client into a child with the same parties,
+//!   e.g. to keep separate production and staging budgets, both linked in
+//!   their records
+//! - An admin tolerance for clock skew, accepting authorizations and
+//!   allowances that expired within it of the ledger's close time
+//! - Notification URLs registered by servers, which indexers deliver
+//!   settlement and dispute webhooks to
+//! - Escrows opened together with their first payment, authorized by the
+//!   client, so a new client pays its first request in one transaction
+//! - Trial escrows sponsored by servers, whose trial funds only pay the
+//!   sponsoring server and are never released to the client
+//! - A client cap on what the server settles per hour, pausing settlements
+//!   of an escrow being drained until the client acknowledges it
+//! - Sales of a single resource suspended by its server, e.g. sold out or
+//!   under maintenance, keeping its price published
+//!
+//! ## Features
+//!
+//! Deployments wanting a smaller wasm can build with
+//! `--no-default-features`, enabling back the entry points they use:
+//!
+//! - `oracle` - USD pricing through a SEP-40 price feed
+//!   (`set_price_oracle`, `set_usd_price`, `set_promo`, `quote`,
+//!   `create_payment_usd`, ...), and the USD quotes of settled payments,
+//!   which are not recorded without it
+//! - `signed-auth` - Payments from authorizations signed off-chain
+//!   (`create_authorized_payment`, `open_and_authorize`), agent
+//!   allowances, and the `verify_*` checks of authorizations, vouchers,
+//!   and channel states
+//!
+//! Both are enabled by default. Storage keys and error codes are the same
+//! in every build. Disputes, streams, and subscriptions have no entry
+//! points here to compile out: disputes of unsettled payments are reported
+//! off-chain by `x402-indexer`.
+//!
+//! ## IDs
+//!
+//! Escrow and payment IDs are allocated without a global counter, which
+//! every open and payment would otherwise write:
+//!
+//! - Escrows are counted per shard, the shard taken from the SHA-256 of
+//!   the client's address. Escrow `n` of shard `s`, counting from 0, gets
+//!   ID `legacy + n * ESCROW_SHARDS + s`, so IDs stay dense across shards.
+//! - Payments are numbered per escrow: payment `i` of escrow `e`, counting
+//!   from 0, gets ID `e << 32 | i`, the next free one if a legacy payment
+//!   holds it.
+//! - Direct payments are numbered per server: direct payment `i` of a
+//!   server, counting from 0, gets ID `(1 << 63 | h) << 32 | i`, `h` taken
+//!   from the SHA-256 of the server's address, the next free one if
+//!   another payment holds it.
+//!
+//! `legacy` is the number of escrows the former global counter
+//! (`EscrowCounter`) opened, sequentially from 0. Those escrows and the
+//! payments of `PaymentCounter` keep their IDs; their records are read
+//! from instance storage until next written, then moved to persistent
+//! storage.
+//!
+//! ## Indexes
+//!
+//! Each client has an index of its escrows, listed by `get_escrow_index`.
+//! Escrow IDs are appended to fixed-size pages of `ESCROW_INDEX_PAGE` IDs,
+//! so no entry grows with a busy client's escrows; only the last page of
+//! the client is written by its opens. Servers are not indexed, so opens
+//! of unrelated clients of a server write no index entry in common.
+//! Removed escrows are appended to archive pages of the same size, and
+//! skipped by the live listing until the client drops them from its live
+//! pages with `compact_escrow_index`. The escrows of a server, and those
+//! opened before the indexes were kept, are listed by `export_escrows`,
+//! which pages through all escrow IDs filtering by server. `find_escrow`
+//! looks up the one escrow of a client-server pair.
+
+use soroban_sdk::{contract, contractimpl, contracttype, token, xdr::ToXdr, Address, Bytes, BytesN, Env, IntoVal, Map, String, Symbol, TryFromVal, Val, Vec, symbol_short};
+
+mod error;
+mod events;
+mod migration;
+#[cfg(feature = "oracle")]
+mod oracle;
+#[cfg(feature = "signed-auth")]
+mod signing;
+
+pub use error::Error;
+pub use events::*;
+pub use migration::{
+    MigrationConsent, MigrationExport, MigrationSource, MigrationSourceClient, MigrationSummary,
+};
+#[cfg(feature = "oracle")]
+pub use oracle::{Asset, OracleConfig, PriceData, PriceOracle, PriceOracleClient};
+#[cfg(feature = "signed-auth")]
+pub use signing::{Authorization, ChannelState, Voucher};
+
+/// Default age in seconds after which an oracle price is stale
+#[cfg(feature = "oracle")]
+pub const DEFAULT_MAX_PRICE_AGE: u64 = 300;
+
+/// Default largest deviation of the oracle price from its TWAP, in basis points
+#[cfg(feature = "oracle")]
+pub const DEFAULT_MAX_SLIPPAGE_BPS: u32 = 100;
+
+/// Oracle records averaged by the TWAP the last price is checked against
+#[cfg(feature = "oracle")]
+pub const TWAP_RECORDS: u32 = 5;
 
-use soroban_sdk::{contract, contractimpl, contracttype, Address, Env, symbol_short};
+/// Decimals of escrow amounts (stroops)
+#[cfg(feature = "oracle")]
+const AMOUNT_DECIMALS: u32 = 7;
+
+/// Most payments `get_payments` looks at in one call
+pub const MAX_PAYMENTS_PAGE: u32 = 50;
+
+/// Most escrow IDs `export_escrows` looks at in one call
+pub const MAX_ESCROWS_PAGE: u32 = 50;
+
+/// Escrow IDs per page of a client's escrow index
+pub const ESCROW_INDEX_PAGE: u32 = 32;
+
+/// Most index pages `compact_escrow_index` rewrites in one call
+pub const MAX_COMPACT_PAGES: u32 = 10;
+
+/// Shortest inactivity window a dust policy may set, in seconds (90 days)
+pub const MIN_DUST_IDLE: u64 = 90 * 24 * 60 * 60;
+
+/// Version of the contract interface, returned by `version`
+pub const CONTRACT_VERSION: u32 = 1;
+
+/// Longest note `post_note` accepts, in bytes
+pub const MAX_NOTE_LEN: u32 = 256;
+
+/// Longest metadata `split_escrow` accepts, in bytes
+pub const MAX_SPLIT_METADATA_LEN: u32 = 64;
+
+/// Longest URL `set_notification_url` accepts, in bytes
+pub const MAX_NOTIFY_URL_LEN: u32 = 256;
+
+/// Notes kept per escrow, the oldest evicted first
+pub const MAX_NOTES: u32 = 8;
+
+/// Most promos of one resource that may be scheduled or running at once
+#[cfg(feature = "oracle")]
+pub const MAX_PROMOS: u32 = 10;
+
+/// Length of the windows spend limits count settlements over, in seconds
+pub const SPEND_WINDOW: u64 = 60 * 60;
+
+/// Shards escrow IDs are counted in, so that unrelated clients rarely
+/// open escrows through the same counter
+pub const ESCROW_SHARDS: u32 = 64;
+
+/// Escrow ID recorded on direct payments, which no escrow is allocated,
+/// see [`X402EscrowContract::record_direct_payment`]
+///
+/// Direct payments are listed per server by
+/// [`X402EscrowContract::get_direct_payments`].
+pub const DIRECT_PAYMENT_ESCROW: u64 = u64::MAX;
 
 /// Escrow account for a client-server pair
 #[contracttype]
@@ -22,6 +202,56 @@ pub struct Escrow {
     pub balance: i128,
     pub client_closed: bool,
     pub server_closed: bool,
+    /// Hash of the server's terms of service the client accepted when
+    /// opening, None if the server published none
+    pub terms_hash: Option<BytesN<32>>,
+    /// Whether the server sponsored the escrow as a trial
+    pub trial: bool,
+    /// Part of the balance the server sponsored and settlements have not
+    /// spent yet, never released to the client
+    pub trial_balance: i128,
+    /// Sum of the payments not settled yet
+    pub pending: i128,
+    /// Number of the payments not settled yet
+    pub pending_payments: u32,
+}
+
+/// Escrow as recorded before unsettled payments were totalled, see
+/// [`read_escrow`]
+#[contracttype(export = false)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct TrialEscrow {
+    client: Address,
+    server: Address,
+    balance: i128,
+    client_closed: bool,
+    server_closed: bool,
+    terms_hash: Option<BytesN<32>>,
+    trial: bool,
+    trial_balance: i128,
+}
+
+/// Escrow as recorded before sponsored trials, see [`read_escrow`]
+#[contracttype(export = false)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct TermsEscrow {
+    client: Address,
+    server: Address,
+    balance: i128,
+    client_closed: bool,
+    server_closed: bool,
+    terms_hash: Option<BytesN<32>>,
+}
+
+/// Escrow as recorded before terms of service, see [`read_escrow`]
+#[contracttype(export = false)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct LegacyEscrow {
+    client: Address,
+    server: Address,
+    balance: i128,
+    client_closed: bool,
+    server_closed: bool,
 }
 
 /// Payment record
@@ -32,6 +262,21 @@ pub struct Payment {
     pub amount: i128,
     pub settled: bool,
     pub timestamp: u64,
+    /// Worth of the amount in `quote_currency` when settled, in cents,
+    /// None while pending or if the price feed had no usable price
+    pub quote_amount: Option<i128>,
+    /// Currency of `quote_amount` ("USD"), None without it
+    pub quote_currency: Option<Symbol>,
+}
+
+/// Payment as recorded before quotes, see [`read_payment`]
+#[contracttype(export = false)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct LegacyPayment {
+    escrow_id: u64,
+    amount: i128,
+    settled: bool,
+    timestamp: u64,
 }
 
 /// Payment status enum
@@ -43,6 +288,204 @@ pub enum PaymentStatus {
     Failed,
 }
 
+/// Payment record with its ID
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PaymentEntry {
+    pub payment_id: u64,
+    pub payment: Payment,
+}
+
+/// Page of the payments of an escrow, or of the direct payments of a server
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PaymentPage {
+    /// Matching payments, oldest first
+    pub payments: Vec<PaymentEntry>,
+    /// Offset of the next page, None after the last one
+    pub next_offset: Option<u32>,
+}
+
+/// Escrow account with its ID
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowEntry {
+    pub escrow_id: u64,
+    pub escrow: Escrow,
+}
+
+/// Page of the escrows of the contract
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowPage {
+    /// Matching open escrows, oldest first within each shard
+    pub escrows: Vec<EscrowEntry>,
+    /// Escrow ID the next page starts at, None after the last one
+    pub next_start: Option<u64>,
+}
+
+/// Pages of the escrow index of a client
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowIndex {
+    /// Pages of escrows indexed while open, the last one appended to
+    pub live_pages: u32,
+    /// Pages of removed escrows, the last one appended to
+    pub archived_pages: u32,
+}
+
+/// Page of the escrow index of a client
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowIdPage {
+    /// Escrow IDs, oldest first; only those still open in live pages
+    pub escrow_ids: Vec<u64>,
+    /// Next page, None after the last one
+    pub next_page: Option<u32>,
+}
+
+/// Which escrows `sweep_dust` may archive
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DustPolicy {
+    /// Balances below this are dust (in stroops)
+    pub threshold: i128,
+    /// Seconds without activity after which an escrow is abandoned
+    pub max_idle: u64,
+}
+
+/// Spending a client granted an agent over its escrows
+#[cfg(feature = "signed-auth")]
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Allowance {
+    /// Total the agent may authorize (in stroops)
+    pub total_cap: i128,
+    /// What is left of the total cap
+    pub remaining: i128,
+    /// Most the agent may authorize in one payment
+    pub per_payment_cap: i128,
+    /// Ledger timestamp after which the allowance is void
+    pub expires_at: u64,
+}
+
+/// Escrows an escrow was split from and into, see `split_escrow`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowLinks {
+    /// Escrow this one was split from, None if it was opened
+    pub parent: Option<u64>,
+    /// Escrows split from this one, oldest first
+    pub children: Vec<u64>,
+    /// Metadata the client gave when splitting this escrow off, empty if
+    /// it was opened
+    pub metadata: Bytes,
+}
+
+/// Cap on what a server settles from an escrow per window, see
+/// `set_max_settled_per_hour`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SpendLimit {
+    /// Most settled per window (in stroops)
+    pub max_settled_per_hour: i128,
+    /// Ledger timestamp the current window started at
+    pub window_start: u64,
+    /// Settled in the current window
+    pub settled: i128,
+    /// Whether a settlement breached the limit in the current window
+    pub paused: bool,
+}
+
+/// Payment a server settles, with the terms it expects of it
+///
+/// Servers authorize these terms, which the contract checks against the
+/// payment, rather than the payment ID alone.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Settlement {
+    pub payment_id: u64,
+    /// Escrow the payment draws from
+    pub escrow_id: u64,
+    /// Payment amount (in stroops)
+    pub amount: i128,
+    /// Client of the escrow
+    pub client: Address,
+}
+
+/// Payment a server creates in a batch, see `create_payments`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PaymentIntent {
+    /// Escrow the payment draws from
+    pub escrow_id: u64,
+    /// Payment amount (in stroops)
+    pub amount: i128,
+}
+
+/// Escrow opened by `open_and_authorize`, with its first payment
+#[cfg(feature = "signed-auth")]
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OpenedEscrow {
+    pub escrow_id: u64,
+    /// Payment created from the authorization, for the server to settle
+    pub payment_id: u64,
+}
+
+/// Message a party left on an escrow
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Note {
+    /// Party who posted the note
+    pub author: Address,
+    /// Ledger the note was posted in
+    pub ledger: u32,
+    /// Ledger timestamp the note was posted at
+    pub timestamp: u64,
+    pub body: Bytes,
+}
+
+/// Discounted USD price of a resource for a time window
+#[cfg(feature = "oracle")]
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Promo {
+    /// Price in USD cents while the promo runs
+    pub usd_cents: i128,
+    /// Ledger timestamp the promo starts at
+    pub starts_at: u64,
+    /// Ledger timestamp the promo ends at, itself excluded
+    pub ends_at: u64,
+}
+
+/// Escrow amount of a resource's USD price, and whether it is on sale
+#[cfg(feature = "oracle")]
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Quote {
+    /// Amount (in stroops), rounded up
+    pub amount: i128,
+    /// Whether the server suspended the sale of the resource
+    pub suspended: bool,
+}
+
+/// Settled payments of an escrow, totalled in their quote currency
+///
+/// Refunds are not deducted.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QuoteTotal {
+    /// Currency of `quote_amount` ("USD")
+    pub currency: Symbol,
+    /// Sum of the quotes of the settled payments, in cents
+    pub quote_amount: i128,
+    /// Sum of the amounts quoted (in stroops)
+    pub quoted: i128,
+    /// Sum of the amounts settled without a quote (in stroops)
+    pub unquoted: i128,
+}
+
 /// Storage keys
 #[contracttype]
 pub enum DataKey {
@@ -51,6 +494,42 @@ pub enum DataKey {
     EscrowCounter,
     PaymentCounter,
     ClientServerEscrow(Address, Address),
+    Admin,
+    Oracle,
+    UsdPrice(Address, String),
+    EscrowPaymentCount(u64),
+    EscrowPayment(u64, u32),
+    ClaimedDeposit(BytesN<32>),
+    MigrationConsent(u64),
+    Migration(u64),
+    MigrationSource(Address),
+    EscrowActivity(u64),
+    DustPolicy,
+    RefundAddress(Address),
+    Allowance(Address, Address),
+    UsedAuthorization(u64, u64),
+    Refunded(u64),
+    MaxEscrowsPerClient,
+    ClientEscrowCount(Address),
+    Promos(Address, String),
+    Notes(u64),
+    EscrowShardCount(u32),
+    QuoteTotal(u64),
+    Terms(Address),
+    ClockSkew,
+    NotifyUrl(Address),
+    PreAuthorizedPayment(u64, u64),
+    SpendLimit(u64),
+    Suspended(Address, String),
+    EscrowLinks(u64),
+    DirectReference(Address, BytesN<32>),
+    DirectPaymentCount(Address),
+    DirectPayment(Address, u32),
+    EscrowIndex(Address),
+    ClientEscrowsPage(Address, u32),
+    ClientArchivePage(Address, u32),
+    Unclaimed(u64),
+    AuthorizedUntil(u64),
 }
 
 #[contract]
@@ -64,57 +543,261 @@ impl X402EscrowContract {
     /// * `client` - Client address
     /// * `server` - Server address
     /// * `amount` - Initial deposit amount (in stroops)
+    /// * `terms_hash` - Hash of the server's terms of service the client
+    ///   accepts, None if the server published none
     ///
     /// # Returns
     /// * Escrow ID
     ///
-    /// # Panics
-    /// * If escrow already exists for this client-server pair
+    /// # Errors
+    /// * `EscrowAlreadyExists` - If escrow already exists for this client-server pair
+    /// * `TooManyEscrows` - If the client holds as many open escrows as the
+    ///   admin allows
+    /// * `TermsMismatch` - If `terms_hash` is not the hash the server
+    ///   currently publishes
     pub fn open_escrow(
         env: Env,
         client: Address,
         server: Address,
         amount: i128,
-    ) -> u64 {
+        terms_hash: Option<BytesN<32>>,
+    ) -> Result<u64, Error> {
         // Verify authorization
         client.require_auth();
 
-        // Check if escrow already exists
-        let lookup_key = DataKey::ClientServerEscrow(client.clone(), server.clone());
-        if env.storage().instance().has(&lookup_key) {
-            panic!("Escrow already exists for this client-server pair");
-        }
+        open(&env, client, server, amount, terms_hash)
+    }
 
-        // Get next escrow ID
-        let counter_key = DataKey::EscrowCounter;
-        let escrow_id: u64 = env
-            .storage()
-            .instance()
-            .get(&counter_key)
-            .unwrap_or(0);
+    /// Open an escrow with another server, configured as an existing escrow
+    ///
+    /// The new escrow accepts the terms of service the source escrow
+    /// accepted, which the new server must publish too. Its balance is
+    /// `amount`, the source's balance staying where it is. Refund addresses
+    /// and agent allowances are set per client, so they already cover the
+    /// new escrow.
+    ///
+    /// # Arguments
+    /// * `source_escrow_id` - Escrow whose configuration is copied
+    /// * `new_server` - Server of the new escrow
+    /// * `amount` - Initial deposit amount (in stroops)
+    ///
+    /// # Returns
+    /// * Escrow ID of the new escrow
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If the source escrow doesn't exist
+    /// * `EscrowAlreadyExists` - If the client already has an escrow with
+    ///   `new_server`
+    /// * `TooManyEscrows` - If the client holds as many open escrows as the
+    ///   admin allows
+    /// * `TermsMismatch` - If `new_server` does not publish the terms the
+    ///   source escrow accepted
+    pub fn clone_escrow_config(
+        env: Env,
+        source_escrow_id: u64,
+        new_server: Address,
+        amount: i128,
+    ) -> Result<u64, Error> {
+        let source = read_escrow(&env, source_escrow_id).ok_or(Error::EscrowNotFound)?;
+        source.client.require_auth();
+
+        let escrow_id = open(&env, source.client, new_server, amount, source.terms_hash)?;
+        env.events().publish(
+            (symbol_short!("cloned"), source_escrow_id),
+            ClonedEvent { event_version: EVENT_VERSION, escrow_id },
+        );
+
+        Ok(escrow_id)
+    }
+
+    /// Open an escrow for a client, funded by the server as a trial
+    ///
+    /// The server deposits `trial_amount`, which only settlements of the
+    /// escrow spend, ahead of the client's deposits. Closing or sweeping
+    /// the escrow never releases trial funds left to the client: the server
+    /// reclaims them. The escrow is otherwise the client's, who deposits,
+    /// authorizes payments, and closes it as usual, and funds it once the
+    /// trial funds are spent.
+    ///
+    /// The client does not sign, so the escrow accepts the terms of service
+    /// the server currently publishes. Until the client first deposits into
+    /// it, the escrow is unclaimed: it does not count against the client's
+    /// open escrows, and `find_escrow` does not find it.
+    ///
+    /// # Arguments
+    /// * `server` - Server sponsoring the escrow
+    /// * `client` - Client address
+    /// * `trial_amount` - Trial deposit (in stroops)
+    ///
+    /// # Returns
+    /// * Escrow ID
+    ///
+    /// # Errors
+    /// * `InvalidTrial` - If the trial amount is not positive
+    /// * `EscrowAlreadyExists` - If escrow already exists for this client-server pair
+    pub fn open_sponsored_escrow(
+        env: Env,
+        server: Address,
+        client: Address,
+        trial_amount: i128,
+    ) -> Result<u64, Error> {
+        // The server funds it
+        server.require_auth();
 
-        env.storage().instance().set(&counter_key, &(escrow_id + 1));
+        if trial_amount <= 0 {
+            return Err(Error::InvalidTrial);
+        }
+        let lookup_key = DataKey::ClientServerEscrow(client.clone(), server.clone());
+        if has_record(&env, &lookup_key) {
+            return Err(Error::EscrowAlreadyExists);
+        }
 
-        // Create escrow account
+        // The client claims the escrow, taking its slot and lookup, when
+        // first depositing, see `deposit`
+        let escrow_id = allocate_escrow_id(&env, &client);
         let escrow = Escrow {
             client: client.clone(),
             server: server.clone(),
-            balance: amount,
+            balance: trial_amount,
             client_closed: false,
             server_closed: false,
+            terms_hash: read_record(&env, &DataKey::Terms(server.clone())),
+            trial: true,
+            trial_balance: trial_amount,
+            pending: 0,
+            pending_payments: 0,
         };
+        write_record(&env, &DataKey::Escrow(escrow_id), &escrow);
+        write_record(&env, &DataKey::Unclaimed(escrow_id), &true);
+        index_escrow(&env, escrow_id, &escrow);
+        record_activity(&env, escrow_id);
+
+        env.events().publish(
+            (symbol_short!("open"), client, server),
+            OpenedEvent { event_version: EVENT_VERSION, escrow_id },
+        );
+        env.events().publish(
+            (symbol_short!("sponsor"), escrow_id),
+            SponsoredEvent { event_version: EVENT_VERSION, amount: trial_amount },
+        );
+
+        Ok(escrow_id)
+    }
 
-        // Store escrow
+    /// Split part of an escrow's balance into a new escrow with the same
+    /// parties
+    ///
+    /// The child escrow accepts the terms of service the parent accepted
+    /// and takes its spend limit, in a new window. Refund addresses and
+    /// agent allowances are set per client, so they already cover the
+    /// child. Unsettled payments and trial funds stay with the parent,
+    /// which must still cover the payments; while it has any, the server
+    /// acknowledges the split by authorizing it too.
+    ///
+    /// `find_escrow` keeps finding the parent for the client-server pair.
+    /// Both escrows record the link, see `get_escrow_links`, and so does
+    /// the `split` event, after the `open` event of the child.
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow split
+    /// * `amount_to_new` - Balance moved to the child (in stroops)
+    /// * `metadata` - What the child is for, e.g. "staging", at most
+    ///   `MAX_SPLIT_METADATA_LEN` bytes
+    ///
+    /// # Returns
+    /// * Escrow ID of the child
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `EscrowFrozen` - If the escrow was exported for migration
+    /// * `InvalidSplit` - If the amount is not positive or exceeds the
+    ///   client's funds left once unsettled payments are covered, or the
+    ///   metadata is longer than `MAX_SPLIT_METADATA_LEN`
+    /// * `TooManyEscrows` - If the client holds as many open escrows as the
+    ///   admin allows
+    pub fn split_escrow(
+        env: Env,
+        escrow_id: u64,
+        amount_to_new: i128,
+        metadata: Bytes,
+    ) -> Result<u64, Error> {
         let escrow_key = DataKey::Escrow(escrow_id);
-        env.storage().instance().set(&escrow_key, &escrow);
+        let mut escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+        require_not_frozen(&env, escrow_id)?;
 
-        // Store lookup mapping
-        env.storage().instance().set(&lookup_key, &escrow_id);
+        escrow.client.require_auth();
+        if escrow.pending_payments > 0 {
+            escrow.server.require_auth();
+        }
 
-        // Emit event
-        env.events().publish((symbol_short!("open"), client, server), escrow_id);
+        // Settlements spend trial funds first, the client's funds after
+        let movable = escrow.balance - escrow.trial_balance.max(escrow.pending);
+        if amount_to_new <= 0
+            || amount_to_new > movable
+            || metadata.len() > MAX_SPLIT_METADATA_LEN
+        {
+            return Err(Error::InvalidSplit);
+        }
+        take_escrow_slot(&env, &escrow.client, true)?;
 
-        escrow_id
+        let child_id = allocate_escrow_id(&env, &escrow.client);
+        let child = Escrow {
+            client: escrow.client.clone(),
+            server: escrow.server.clone(),
+            balance: amount_to_new,
+            client_closed: false,
+            server_closed: false,
+            terms_hash: escrow.terms_hash.clone(),
+            trial: false,
+            trial_balance: 0,
+            pending: 0,
+            pending_payments: 0,
+        };
+        write_record(&env, &DataKey::Escrow(child_id), &child);
+        index_escrow(&env, child_id, &child);
+        record_activity(&env, child_id);
+        if let Some(limit) = read_record::<SpendLimit>(&env, &DataKey::SpendLimit(escrow_id)) {
+            let limit = SpendLimit {
+                max_settled_per_hour: limit.max_settled_per_hour,
+                window_start: env.ledger().timestamp(),
+                settled: 0,
+                paused: false,
+            };
+            write_record(&env, &DataKey::SpendLimit(child_id), &limit);
+        }
+
+        escrow.balance -= amount_to_new;
+        write_record(&env, &escrow_key, &escrow);
+        record_activity(&env, escrow_id);
+
+        let mut links = Self::get_escrow_links(env.clone(), escrow_id);
+        links.children.push_back(child_id);
+        write_record(&env, &DataKey::EscrowLinks(escrow_id), &links);
+        write_record(
+            &env,
+            &DataKey::EscrowLinks(child_id),
+            &EscrowLinks {
+                parent: Some(escrow_id),
+                children: Vec::new(&env),
+                metadata: metadata.clone(),
+            },
+        );
+
+        env.events().publish(
+            (symbol_short!("open"), escrow.client, escrow.server),
+            OpenedEvent { event_version: EVENT_VERSION, escrow_id: child_id },
+        );
+        env.events().publish(
+            (symbol_short!("split"), escrow_id),
+            SplitEvent {
+                event_version: EVENT_VERSION,
+                child_escrow_id: child_id,
+                amount: amount_to_new,
+                metadata,
+            },
+        );
+
+        Ok(child_id)
     }
 
     /// Create a payment intent (returns immediately for instant API response)
@@ -126,113 +809,220 @@ impl X402EscrowContract {
     /// # Returns
     /// * Payment ID
     ///
-    /// # Panics
-    /// * If escrow doesn't exist
-    /// * If insufficient escrow balance
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `InsufficientBalance` - If insufficient escrow balance
+    /// * `EscrowFrozen` - If the escrow was exported for migration
     pub fn create_payment(
         env: Env,
         escrow_id: u64,
         amount: i128,
-    ) -> u64 {
+    ) -> Result<u64, Error> {
         // Get escrow
-        let escrow_key = DataKey::Escrow(escrow_id);
-        let escrow: Escrow = env
-            .storage()
-            .instance()
-            .get(&escrow_key)
-            .expect("Escrow not found");
+        let escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+        require_not_frozen(&env, escrow_id)?;
 
         // Verify server authorization
         escrow.server.require_auth();
 
-        // Check balance
-        if escrow.balance < amount {
-            panic!("Insufficient escrow balance");
+        create(&env, escrow_id, escrow, amount)
+    }
+
+    /// Create a batch of payments in one invocation
+    ///
+    /// The batch is atomic: if any payment cannot be created, none is. Each
+    /// server authorizes the intents on its own escrows, in batch order.
+    /// Each escrow's balance is checked against the total of its payments
+    /// in the batch, not against each payment alone.
+    ///
+    /// # Arguments
+    /// * `intents` - Payments to create, possibly across escrows
+    ///
+    /// # Returns
+    /// * Payment IDs, in batch order
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If an escrow doesn't exist
+    /// * `InsufficientBalance` - If an escrow balance doesn't cover the
+    ///   total of its payments in the batch
+    /// * `EscrowFrozen` - If an escrow was exported for migration
+    pub fn create_payments(env: Env, intents: Vec<PaymentIntent>) -> Result<Vec<u64>, Error> {
+        let mut by_server: Map<Address, Vec<PaymentIntent>> = Map::new(&env);
+        let mut totals: Map<u64, i128> = Map::new(&env);
+        for intent in intents.iter() {
+            let escrow = read_escrow(&env, intent.escrow_id)
+                .ok_or(Error::EscrowNotFound)?;
+            require_not_frozen(&env, intent.escrow_id)?;
+            let total = totals.get(intent.escrow_id).unwrap_or(0) + intent.amount;
+            if total > escrow.balance {
+                return Err(Error::InsufficientBalance);
+            }
+            totals.set(intent.escrow_id, total);
+            let mut batch = by_server.get(escrow.server.clone()).unwrap_or(Vec::new(&env));
+            batch.push_back(intent);
+            by_server.set(escrow.server, batch);
         }
 
-        // Get next payment ID
-        let counter_key = DataKey::PaymentCounter;
-        let payment_id: u64 = env
-            .storage()
-            .instance()
-            .get(&counter_key)
-            .unwrap_or(0);
+        // Verify server authorization, once per server
+        for (server, batch) in by_server.iter() {
+            server.require_auth_for_args((batch,).into_val(&env));
+        }
 
-        env.storage().instance().set(&counter_key, &(payment_id + 1));
+        let mut payment_ids = Vec::new(&env);
+        for intent in intents.iter() {
+            let escrow = read_escrow(&env, intent.escrow_id)
+                .ok_or(Error::EscrowNotFound)?;
+            payment_ids.push_back(create(&env, intent.escrow_id, escrow, intent.amount)?);
+        }
+        Ok(payment_ids)
+    }
 
-        // Create payment record
-        let payment = Payment {
-            escrow_id,
-            amount,
-            settled: false,
-            timestamp: env.ledger().timestamp(),
-        };
+    /// Settle a payment (deduct from escrow balance)
+    ///
+    /// The server authorizes the settlement's terms rather than a bare
+    /// payment ID, so an auth entry signed for one payment cannot settle
+    /// another.
+    ///
+    /// With a price feed configured, the payment records its USD equivalent
+    /// at the current price, added to the escrow's `get_quote_total`. A feed
+    /// without a usable price leaves the quote None, never failing the
+    /// settlement.
+    ///
+    /// # Arguments
+    /// * `settlement` - Payment to settle, with the terms the server expects
+    ///
+    /// # Returns
+    /// * true if settled successfully, false if the settlement breached the
+    ///   escrow's spend limit, pausing its settlements
+    ///
+    /// # Errors
+    /// * `PaymentNotFound` - If payment doesn't exist
+    /// * `PaymentAlreadySettled` - If payment already settled
+    /// * `EscrowNotFound` - If the payment's escrow no longer exists
+    /// * `SettlementMismatch` - If the terms are not the payment's
+    /// * `InsufficientBalance` - If the escrow balance no longer covers it
+    /// * `EscrowFrozen` - If the escrow was exported for migration
+    /// * `SettlementsPaused` - If settlements of the escrow are paused
+    pub fn settle_payment(env: Env, settlement: Settlement) -> Result<bool, Error> {
+        let server = settlement_server(&env, &settlement)?;
 
-        // Store payment
-        let payment_key = DataKey::Payment(payment_id);
-        env.storage().instance().set(&payment_key, &payment);
+        // Verify server authorization of these terms
+        server.require_auth_for_args((settlement.clone(),).into_val(&env));
 
-        // Emit event
-        env.events().publish(
-            (symbol_short!("pay"), escrow.server, escrow.client),
-            (payment_id, amount),
-        );
+        // A breach is recorded rather than failed, so the pause sticks
+        if !spend(&env, settlement.escrow_id, settlement.amount)? {
+            return Ok(false);
+        }
+        settle(&env, settlement.payment_id)?;
+        Ok(true)
+    }
 
-        payment_id
+    /// Settle a batch of payments in one invocation
+    ///
+    /// The batch is atomic: if any payment cannot be settled, none is. Each
+    /// server authorizes the settlements of its own escrows, in batch order.
+    /// Payments are quoted in USD as by `settle_payment`.
+    ///
+    /// # Arguments
+    /// * `settlements` - Payments to settle, possibly across escrows, with
+    ///   the terms their servers expect
+    ///
+    /// # Returns
+    /// * Total amount settled
+    ///
+    /// # Errors
+    /// * `PaymentNotFound` - If a payment doesn't exist
+    /// * `PaymentAlreadySettled` - If a payment is already settled or listed twice
+    /// * `EscrowNotFound` - If a payment's escrow no longer exists
+    /// * `SettlementMismatch` - If terms are not their payment's
+    /// * `InsufficientBalance` - If an escrow balance no longer covers its
+    ///   payments
+    /// * `EscrowFrozen` - If a payment's escrow was exported for migration
+    /// * `SettlementsPaused` - If settlements of an escrow are paused, or
+    ///   the batch breaches an escrow's spend limit, which being atomic it
+    ///   cannot record
+    pub fn settle_payments(env: Env, settlements: Vec<Settlement>) -> Result<i128, Error> {
+        let mut by_server: Map<Address, Vec<Settlement>> = Map::new(&env);
+        for settlement in settlements.iter() {
+            let server = settlement_server(&env, &settlement)?;
+            let mut terms = by_server.get(server.clone()).unwrap_or(Vec::new(&env));
+            terms.push_back(settlement);
+            by_server.set(server, terms);
+        }
+
+        // Verify server authorization, once per server
+        for (server, terms) in by_server.iter() {
+            server.require_auth_for_args((terms,).into_val(&env));
+        }
+
+        let mut total: i128 = 0;
+        for settlement in settlements.iter() {
+            if !spend(&env, settlement.escrow_id, settlement.amount)? {
+                return Err(Error::SettlementsPaused);
+            }
+            total += settle(&env, settlement.payment_id)?;
+        }
+        Ok(total)
     }
 
-    /// Settle a payment (deduct from escrow balance)
+    /// Refund part or all of a settled payment into its escrow
+    ///
+    /// Refunds of a payment add up and may not exceed its amount, so a
+    /// refund retried after an unknown outcome fails rather than paying
+    /// twice once the payment is fully refunded.
     ///
     /// # Arguments
-    /// * `payment_id` - Payment ID to settle
+    /// * `payment_id` - Settled payment to refund
+    /// * `amount` - Amount credited back to the escrow (in stroops)
     ///
     /// # Returns
-    /// * true if settled successfully
+    /// * Total refunded for the payment, this refund included
     ///
-    /// # Panics
-    /// * If payment doesn't exist
-    /// * If payment already settled
-    pub fn settle_payment(env: Env, payment_id: u64) -> bool {
-        // Get payment
-        let payment_key = DataKey::Payment(payment_id);
-        let mut payment: Payment = env
-            .storage()
-            .instance()
-            .get(&payment_key)
-            .expect("Payment not found");
+    /// # Errors
+    /// * `PaymentNotFound` - If payment doesn't exist
+    /// * `PaymentNotSettled` - If the payment is still pending
+    /// * `InvalidRefund` - If the amount is not positive or the payment's
+    ///   refunds would exceed its amount
+    /// * `EscrowNotFound` - If the payment's escrow no longer exists
+    /// * `EscrowFrozen` - If the escrow was exported for migration
+    pub fn refund_payment(env: Env, payment_id: u64, amount: i128) -> Result<i128, Error> {
+        let payment = read_payment(&env, payment_id).ok_or(Error::PaymentNotFound)?;
+        if !payment.settled {
+            return Err(Error::PaymentNotSettled);
+        }
 
-        if payment.settled {
-            panic!("Payment already settled");
+        let refunded_key = DataKey::Refunded(payment_id);
+        let refunded: i128 = read_record(&env, &refunded_key).unwrap_or(0);
+        if amount <= 0 || refunded + amount > payment.amount {
+            return Err(Error::InvalidRefund);
         }
 
         // Get escrow
         let escrow_key = DataKey::Escrow(payment.escrow_id);
-        let mut escrow: Escrow = env
-            .storage()
-            .instance()
-            .get(&escrow_key)
-            .expect("Escrow not found");
+        let mut escrow = read_escrow(&env, payment.escrow_id).ok_or(Error::EscrowNotFound)?;
+        require_not_frozen(&env, payment.escrow_id)?;
 
-        // Verify server authorization
+        // The server gives back what it was paid
         escrow.server.require_auth();
 
-        // Deduct from escrow balance
-        escrow.balance -= payment.amount;
-
-        // Mark payment as settled
-        payment.settled = true;
-
-        // Save updated records
-        env.storage().instance().set(&escrow_key, &escrow);
-        env.storage().instance().set(&payment_key, &payment);
+        escrow.balance += amount;
+        let refunded = refunded + amount;
+        write_record(&env, &escrow_key, &escrow);
+        write_record(&env, &refunded_key, &refunded);
+        record_activity(&env, payment.escrow_id);
 
         // Emit event
         env.events().publish(
-            (symbol_short!("settled"), payment_id),
-            payment.amount,
+            (symbol_short!("refunded"), payment_id),
+            PaymentRefundedEvent { event_version: EVENT_VERSION, amount, refunded },
         );
 
-        true
+        Ok(refunded)
+    }
+
+    /// Get the total refunded for a payment, 0 if none was
+    pub fn get_refunded(env: Env, payment_id: u64) -> i128 {
+        read_record(&env, &DataKey::Refunded(payment_id)).unwrap_or(0)
     }
 
     /// Deposit additional funds into escrow
@@ -240,29 +1030,177 @@ impl X402EscrowContract {
     /// # Arguments
     /// * `escrow_id` - Escrow account ID
     /// * `amount` - Amount to deposit (in stroops)
-    pub fn deposit(env: Env, escrow_id: u64, amount: i128) {
+    ///
+    /// The first deposit into a sponsored escrow claims it for the client,
+    /// see `open_sponsored_escrow`.
+    ///
+    /// # Errors
+    /// * `InvalidAmount` - If `amount` is not positive
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `EscrowFrozen` - If the escrow was exported for migration
+    /// * `TooManyEscrows` - If the escrow is unclaimed and the client holds
+    ///   as many open escrows as the admin allows
+    pub fn deposit(env: Env, escrow_id: u64, amount: i128) -> Result<(), Error> {
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
         // Get escrow
         let escrow_key = DataKey::Escrow(escrow_id);
-        let mut escrow: Escrow = env
-            .storage()
-            .instance()
-            .get(&escrow_key)
-            .expect("Escrow not found");
+        let mut escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+        require_not_frozen(&env, escrow_id)?;
 
         // Verify client authorization
         escrow.client.require_auth();
+        claim_sponsored(&env, escrow_id, &escrow)?;
 
         // Add to balance
         escrow.balance += amount;
 
         // Save updated escrow
-        env.storage().instance().set(&escrow_key, &escrow);
+        write_record(&env, &escrow_key, &escrow);
+        record_activity(&env, escrow_id);
 
         // Emit event
         env.events().publish(
             (symbol_short!("deposit"), escrow_id),
-            amount,
+            DepositedEvent { event_version: EVENT_VERSION, amount },
         );
+
+        Ok(())
+    }
+
+    /// Credit an escrow with a plain token transfer to its server
+    ///
+    /// For clients whose custodian can only send payments: the transfer
+    /// goes to the server's deposit address, with a memo naming the escrow,
+    /// and the server vouches for it by claiming it here. Each transfer is
+    /// credited at most once.
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow account ID
+    /// * `from` - Account the transfer came from
+    /// * `amount` - Amount transferred (in stroops)
+    /// * `memo_hash` - Hash identifying the transfer, e.g. its transaction hash
+    ///
+    /// # Errors
+    /// * `InvalidAmount` - If `amount` is not positive
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `DepositAlreadyClaimed` - If the transfer has already been credited
+    /// * `EscrowFrozen` - If the escrow was exported for migration
+    pub fn claim_pending_deposit(
+        env: Env,
+        escrow_id: u64,
+        from: Address,
+        amount: i128,
+        memo_hash: BytesN<32>,
+    ) -> Result<(), Error> {
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        let escrow_key = DataKey::Escrow(escrow_id);
+        let mut escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+        require_not_frozen(&env, escrow_id)?;
+
+        // The transfer went to the server, so only it can vouch for it
+        escrow.server.require_auth();
+
+        let claim_key = DataKey::ClaimedDeposit(memo_hash.clone());
+        if has_record(&env, &claim_key) {
+            return Err(Error::DepositAlreadyClaimed);
+        }
+        write_record(&env, &claim_key, &escrow_id);
+
+        escrow.balance += amount;
+        write_record(&env, &escrow_key, &escrow);
+        record_activity(&env, escrow_id);
+
+        env.events().publish(
+            (symbol_short!("deposit"), escrow_id),
+            DepositedEvent { event_version: EVENT_VERSION, amount },
+        );
+        env.events().publish(
+            (symbol_short!("claim"), escrow_id, from),
+            DepositClaimedEvent { event_version: EVENT_VERSION, memo_hash },
+        );
+
+        Ok(())
+    }
+
+    /// Check whether a transfer has been credited through
+    /// [`claim_pending_deposit`](Self::claim_pending_deposit)
+    ///
+    /// # Arguments
+    /// * `memo_hash` - Hash identifying the transfer
+    pub fn is_deposit_claimed(env: Env, memo_hash: BytesN<32>) -> bool {
+        has_record(&env, &DataKey::ClaimedDeposit(memo_hash))
+    }
+
+    /// Pay a server directly, without an escrow
+    ///
+    /// For clients without an escrow with the server: `amount` of `token`
+    /// is transferred from the client to the server at once, and recorded
+    /// as a settled payment of [`DIRECT_PAYMENT_ESCROW`], so that payments
+    /// are looked up alike either way. The server's direct payments are
+    /// listed by [`get_direct_payments`](Self::get_direct_payments), page
+    /// by page as those of its escrows. Each client records a reference at
+    /// most once; references of other clients never collide with its own.
+    ///
+    /// # Arguments
+    /// * `client` - Account paying, which authorizes the transfer too
+    /// * `server` - Account paid
+    /// * `token` - Token contract transferred, e.g. a Stellar Asset Contract
+    /// * `amount` - Amount paid (in stroops)
+    /// * `reference` - Hash identifying the payment, e.g. of the client's
+    ///   authorization
+    ///
+    /// # Returns
+    /// * Payment ID
+    ///
+    /// # Errors
+    /// * `InvalidDirectPayment` - If `amount` is not positive, or the
+    ///   client already recorded `reference`
+    pub fn record_direct_payment(
+        env: Env,
+        client: Address,
+        server: Address,
+        token: Address,
+        amount: i128,
+        reference: BytesN<32>,
+    ) -> Result<u64, Error> {
+        client.require_auth();
+
+        let reference_key = DataKey::DirectReference(client.clone(), reference.clone());
+        if amount <= 0 || has_record(&env, &reference_key) {
+            return Err(Error::InvalidDirectPayment);
+        }
+
+        token::Client::new(&env, &token).transfer(&client, &server, &amount);
+
+        let payment = Payment {
+            escrow_id: DIRECT_PAYMENT_ESCROW,
+            amount,
+            settled: true,
+            timestamp: env.ledger().timestamp(),
+            // Quotes are of the escrow asset, which `token` may not be
+            quote_amount: None,
+            quote_currency: None,
+        };
+        let payment_id = record_direct(&env, &server, &payment);
+        write_record(&env, &reference_key, &payment_id);
+
+        env.events().publish(
+            (symbol_short!("direct"), client, server),
+            DirectPaymentEvent {
+                event_version: EVENT_VERSION,
+                payment_id,
+                token,
+                amount,
+                reference,
+            },
+        );
+
+        Ok(payment_id)
     }
 
     /// Client initiates escrow closure
@@ -271,15 +1209,17 @@ impl X402EscrowContract {
     /// * `escrow_id` - Escrow account ID
     ///
     /// # Returns
-    /// * Remaining balance (if both parties closed)
-    pub fn client_close_escrow(env: Env, escrow_id: u64) -> Option<i128> {
+    /// * Remaining balance released to the client, without trial funds (if
+    ///   both parties closed)
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `EscrowFrozen` - If the escrow was exported for migration
+    pub fn client_close_escrow(env: Env, escrow_id: u64) -> Result<Option<i128>, Error> {
         // Get escrow
         let escrow_key = DataKey::Escrow(escrow_id);
-        let mut escrow: Escrow = env
-            .storage()
-            .instance()
-            .get(&escrow_key)
-            .expect("Escrow not found");
+        let mut escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+        require_not_frozen(&env, escrow_id)?;
 
         // Verify client authorization
         escrow.client.require_auth();
@@ -289,29 +1229,31 @@ impl X402EscrowContract {
 
         // Check if both parties closed
         if escrow.server_closed {
-            let remaining_balance = escrow.balance;
+            let remaining_balance = reclaim_trial(&env, escrow_id, &escrow);
 
             // Remove escrow
-            env.storage().instance().remove(&escrow_key);
+            remove_record(&env, &escrow_key);
 
             // Remove lookup mapping
-            let lookup_key = DataKey::ClientServerEscrow(
-                escrow.client.clone(),
-                escrow.server.clone(),
-            );
-            env.storage().instance().remove(&lookup_key);
+            remove_lookup(&env, escrow_id, &escrow);
+            remove_record(&env, &DataKey::EscrowActivity(escrow_id));
+            remove_record(&env, &DataKey::Notes(escrow_id));
+            remove_record(&env, &DataKey::SpendLimit(escrow_id));
+            archive_escrow(&env, escrow_id, &escrow);
+            release_escrow_slot(&env, escrow_id, &escrow.client);
 
             // Emit event
             env.events().publish(
                 (symbol_short!("closed"), escrow_id),
-                remaining_balance,
+                ClosedEvent { event_version: EVENT_VERSION, released: remaining_balance },
             );
 
-            Some(remaining_balance)
+            Ok(Some(remaining_balance))
         } else {
             // Save updated escrow
-            env.storage().instance().set(&escrow_key, &escrow);
-            None
+            write_record(&env, &escrow_key, &escrow);
+            record_activity(&env, escrow_id);
+            Ok(None)
         }
     }
 
@@ -321,15 +1263,17 @@ impl X402EscrowContract {
     /// * `escrow_id` - Escrow account ID
     ///
     /// # Returns
-    /// * Remaining balance (if both parties closed)
-    pub fn server_close_escrow(env: Env, escrow_id: u64) -> Option<i128> {
+    /// * Remaining balance released to the client, without trial funds (if
+    ///   both parties closed)
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `EscrowFrozen` - If the escrow was exported for migration
+    pub fn server_close_escrow(env: Env, escrow_id: u64) -> Result<Option<i128>, Error> {
         // Get escrow
         let escrow_key = DataKey::Escrow(escrow_id);
-        let mut escrow: Escrow = env
-            .storage()
-            .instance()
-            .get(&escrow_key)
-            .expect("Escrow not found");
+        let mut escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+        require_not_frozen(&env, escrow_id)?;
 
         // Verify server authorization
         escrow.server.require_auth();
@@ -339,92 +1283,2430 @@ impl X402EscrowContract {
 
         // Check if both parties closed
         if escrow.client_closed {
-            let remaining_balance = escrow.balance;
+            let remaining_balance = reclaim_trial(&env, escrow_id, &escrow);
 
             // Remove escrow
-            env.storage().instance().remove(&escrow_key);
+            remove_record(&env, &escrow_key);
 
             // Remove lookup mapping
-            let lookup_key = DataKey::ClientServerEscrow(
-                escrow.client.clone(),
-                escrow.server.clone(),
-            );
-            env.storage().instance().remove(&lookup_key);
+            remove_lookup(&env, escrow_id, &escrow);
+            remove_record(&env, &DataKey::EscrowActivity(escrow_id));
+            remove_record(&env, &DataKey::Notes(escrow_id));
+            remove_record(&env, &DataKey::SpendLimit(escrow_id));
+            archive_escrow(&env, escrow_id, &escrow);
+            release_escrow_slot(&env, escrow_id, &escrow.client);
 
             // Emit event
             env.events().publish(
                 (symbol_short!("closed"), escrow_id),
-                remaining_balance,
+                ClosedEvent { event_version: EVENT_VERSION, released: remaining_balance },
             );
 
-            Some(remaining_balance)
+            Ok(Some(remaining_balance))
         } else {
             // Save updated escrow
-            env.storage().instance().set(&escrow_key, &escrow);
-            None
+            write_record(&env, &escrow_key, &escrow);
+            record_activity(&env, escrow_id);
+            Ok(None)
         }
     }
 
-    /// Get escrow balance
+    /// Leave a note on an escrow for the other party
+    ///
+    /// The escrow keeps its latest `MAX_NOTES` notes, evicting the oldest.
+    /// Each party may post one note per ledger. Notes are kept while the
+    /// escrow is frozen for migration, and removed with the escrow.
     ///
     /// # Arguments
     /// * `escrow_id` - Escrow account ID
+    /// * `author` - Escrow client or server
+    /// * `body` - Note, at most `MAX_NOTE_LEN` bytes
     ///
-    /// # Returns
-    /// * Current escrow balance (in stroops)
-    pub fn get_escrow_balance(env: Env, escrow_id: u64) -> i128 {
-        let escrow_key = DataKey::Escrow(escrow_id);
-        let escrow: Escrow = env
-            .storage()
-            .instance()
-            .get(&escrow_key)
-            .expect("Escrow not found");
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `InvalidSigner` - If `author` is neither the client nor the server
+    /// * `InvalidNote` - If the note is empty or longer than `MAX_NOTE_LEN`
+    /// * `NoteRateLimited` - If the author already posted a note in this
+    ///   ledger
+    pub fn post_note(env: Env, escrow_id: u64, author: Address, body: Bytes) -> Result<(), Error> {
+        let escrow = Self::get_escrow(env.clone(), escrow_id)?;
+
+        author.require_auth();
+        if author != escrow.client && author != escrow.server {
+            return Err(Error::InvalidSigner);
+        }
+        if body.is_empty() || body.len() > MAX_NOTE_LEN {
+            return Err(Error::InvalidNote);
+        }
+
+        let ledger = env.ledger().sequence();
+        let mut notes = Self::get_notes(env.clone(), escrow_id);
+        if notes.iter().any(|note| note.author == author && note.ledger == ledger) {
+            return Err(Error::NoteRateLimited);
+        }
+        if notes.len() >= MAX_NOTES {
+            notes.pop_front();
+        }
+        notes.push_back(Note {
+            author: author.clone(),
+            ledger,
+            timestamp: env.ledger().timestamp(),
+            body: body.clone(),
+        });
+        write_record(&env, &DataKey::Notes(escrow_id), &notes);
 
-        escrow.balance
+        env.events().publish(
+            (symbol_short!("note"), escrow_id, author),
+            NoteEvent { event_version: EVENT_VERSION, body },
+        );
+
+        Ok(())
     }
 
-    /// Get escrow details
+    /// Get the notes left on an escrow, oldest first
+    pub fn get_notes(env: Env, escrow_id: u64) -> Vec<Note> {
+        read_record(&env, &DataKey::Notes(escrow_id)).unwrap_or(Vec::new(&env))
+    }
+
+    /// Consent to moving an escrow to another deployment of the contract
+    ///
+    /// The admin may export the escrow once both parties consented to the
+    /// same target. Consenting to another target withdraws any consent
+    /// given to the previous one.
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow account ID
+    /// * `party` - Escrow client or server
+    /// * `target` - Contract the escrow moves to
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `EscrowFrozen` - If the escrow was already exported
+    /// * `InvalidSigner` - If `party` is neither the client nor the server
+    pub fn consent_to_migration(
+        env: Env,
+        escrow_id: u64,
+        party: Address,
+        target: Address,
+    ) -> Result<(), Error> {
+        let escrow = read_escrow(&env, escrow_id)
+            .ok_or(Error::EscrowNotFound)?;
+        require_not_frozen(&env, escrow_id)?;
+
+        party.require_auth();
+        if party != escrow.client && party != escrow.server {
+            return Err(Error::InvalidSigner);
+        }
+
+        let consent_key = DataKey::MigrationConsent(escrow_id);
+        let mut consent = read_record::<MigrationConsent>(&env, &consent_key)
+            .filter(|consent| consent.target == target)
+            .unwrap_or(MigrationConsent { target, client: false, server: false });
+        if party == escrow.client {
+            consent.client = true;
+        }
+        if party == escrow.server {
+            consent.server = true;
+        }
+        write_record(&env, &consent_key, &consent);
+        record_activity(&env, escrow_id);
+
+        Ok(())
+    }
+
+    /// Freeze an escrow both parties agreed to move, and export it
+    ///
+    /// The frozen escrow takes no payment, settlement, deposit, or closure
+    /// until its target imports it, which closes it here. Its unsettled
+    /// payments move with it, so the payments of the escrow are looked at
+    /// up to the last unsettled one, as do its spend limit, links, and
+    /// notes. Nonces of spent authorizations stay spent here only, so the
+    /// escrow is exported once the last of them expired.
     ///
     /// # Arguments
+    /// * `admin` - Contract admin
     /// * `escrow_id` - Escrow account ID
     ///
     /// # Returns
-    /// * Escrow struct
-    pub fn get_escrow(env: Env, escrow_id: u64) -> Escrow {
+    /// * Summary for `import_escrow` on the target, with its proof
+    ///
+    /// # Errors
+    /// * `Unauthorized` - If `admin` is not the contract admin
+    /// * `NotInitialized` - If no admin is set
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `EscrowFrozen` - If the escrow was already exported
+    /// * `MigrationNotApproved` - If both parties did not consent to a target
+    /// * `TooManyPendingPayments` - If more than `MAX_PAYMENTS_PAGE` payments
+    ///   are unsettled
+    /// * `AuthorizationsLive` - If an authorization spent from the escrow
+    ///   has not expired
+    pub fn export_for_migration(
+        env: Env,
+        admin: Address,
+        escrow_id: u64,
+    ) -> Result<MigrationExport, Error> {
+        require_admin(&env, &admin)?;
+
+        let escrow = read_escrow(&env, escrow_id)
+            .ok_or(Error::EscrowNotFound)?;
+        require_not_frozen(&env, escrow_id)?;
+
+        let consent_key = DataKey::MigrationConsent(escrow_id);
+        let consent = read_record::<MigrationConsent>(&env, &consent_key)
+            .filter(|consent| consent.client && consent.server)
+            .ok_or(Error::MigrationNotApproved)?;
+        let authorized_until: Option<u64> =
+            read_record(&env, &DataKey::AuthorizedUntil(escrow_id));
+        if authorized_until.is_some_and(|expires_at| !expired(&env, expires_at)) {
+            return Err(Error::AuthorizationsLive);
+        }
+
+        // Collect the unsettled payments, looking no further than the last one
+        if escrow.pending_payments > MAX_PAYMENTS_PAGE {
+            return Err(Error::TooManyPendingPayments);
+        }
+        let payment_count: u32 = read_record(&env, &DataKey::EscrowPaymentCount(escrow_id))
+            .unwrap_or(0);
+        let mut pending = Vec::new(&env);
+        for index in 0..payment_count {
+            if pending.len() == escrow.pending_payments {
+                break;
+            }
+            let index_key = DataKey::EscrowPayment(escrow_id, index);
+            let Some(payment_id) = read_record(&env, &index_key) else {
+                continue;
+            };
+            let Some(payment) = read_payment(&env, payment_id) else {
+                continue;
+            };
+            if payment.settled {
+                continue;
+            }
+            pending.push_back(payment);
+        }
+
+        let export = MigrationExport::new(
+            &env,
+            MigrationSummary {
+                source: env.current_contract_address(),
+                target: consent.target.clone(),
+                escrow_id,
+                escrow,
+                pending,
+                payment_count,
+                spend_limit: read_record(&env, &DataKey::SpendLimit(escrow_id)),
+                links: Self::get_escrow_links(env.clone(), escrow_id),
+                notes: Self::get_notes(env.clone(), escrow_id),
+                exported_at: env.ledger().timestamp(),
+            },
+        );
+
+        // Freeze the escrow until the target imports it
+        write_record(&env, &DataKey::Migration(escrow_id), &export);
+        remove_record(&env, &consent_key);
+
+        env.events().publish(
+            (symbol_short!("export"), escrow_id),
+            ExportedEvent {
+                event_version: EVENT_VERSION,
+                target: consent.target,
+                proof: export.proof.clone(),
+            },
+        );
+
+        Ok(export)
+    }
+
+    /// Get the export of an escrow, frozen or already moved
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow account ID
+    pub fn get_migration(env: Env, escrow_id: u64) -> Option<MigrationExport> {
+        read_record(&env, &DataKey::Migration(escrow_id))
+    }
+
+    /// Close an exported escrow, called by its target from `import_escrow`
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow account ID
+    /// * `proof` - Proof of the export imported
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist, e.g. it moved already
+    /// * `MigrationMismatch` - If the escrow was not exported with this proof
+    pub fn complete_migration(env: Env, escrow_id: u64, proof: BytesN<32>) -> Result<(), Error> {
         let escrow_key = DataKey::Escrow(escrow_id);
+        let escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+        let export: MigrationExport = read_record(&env, &DataKey::Migration(escrow_id))
+            .filter(|export: &MigrationExport| export.proof == proof)
+            .ok_or(Error::MigrationMismatch)?;
+
+        // Only the target may close it
+        export.summary.target.require_auth();
+
+        // Remove escrow and lookup mapping, keeping the export as a record
+        remove_record(&env, &escrow_key);
+        remove_lookup(&env, escrow_id, &escrow);
+        remove_record(&env, &DataKey::EscrowActivity(escrow_id));
+        remove_record(&env, &DataKey::Notes(escrow_id));
+        remove_record(&env, &DataKey::SpendLimit(escrow_id));
+        archive_escrow(&env, escrow_id, &escrow);
+        release_escrow_slot(&env, escrow_id, &escrow.client);
+
+        env.events().publish(
+            (symbol_short!("migrated"), escrow_id),
+            MigratedEvent {
+                event_version: EVENT_VERSION,
+                target: export.summary.target,
+                balance: escrow.balance,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Accept escrows exported by another deployment of the contract
+    ///
+    /// # Arguments
+    /// * `admin` - Contract admin
+    /// * `source` - Contract escrows are exported by
+    ///
+    /// # Errors
+    /// * `Unauthorized` - If `admin` is not the contract admin
+    /// * `NotInitialized` - If no admin is set
+    pub fn allow_migration_source(env: Env, admin: Address, source: Address) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
         env.storage()
             .instance()
-            .get(&escrow_key)
-            .expect("Escrow not found")
+            .set(&DataKey::MigrationSource(source), &true);
+        Ok(())
     }
 
-    /// Get payment status
+    /// Recreate an escrow exported by another deployment
+    ///
+    /// The source checks the proof and closes its escrow in the same
+    /// invocation. Escrow balances are bookkeeping of the contract, which
+    /// holds no tokens, so the balance moves with the record and no token
+    /// is transferred. Unsettled payments are recreated under new IDs,
+    /// oldest first; the spend limit, links, and notes are kept as
+    /// exported, links naming escrows by their IDs at the source.
     ///
     /// # Arguments
-    /// * `payment_id` - Payment ID
+    /// * `summary` - Summary returned by `export_for_migration`
+    /// * `proof` - Proof returned with it
     ///
     /// # Returns
-    /// * Payment struct
-    pub fn get_payment(env: Env, payment_id: u64) -> Payment {
-        let payment_key = DataKey::Payment(payment_id);
+    /// * Escrow ID here
+    ///
+    /// # Errors
+    /// * `MigrationMismatch` - If the summary is for another target, or
+    ///   does not match the proof
+    /// * `MigrationNotApproved` - If escrows of the source are not accepted
+    /// * `EscrowAlreadyExists` - If an escrow already exists here for the
+    ///   client-server pair
+    pub fn import_escrow(
+        env: Env,
+        summary: MigrationSummary,
+        proof: BytesN<32>,
+    ) -> Result<u64, Error> {
+        if summary.target != env.current_contract_address()
+            || migration::digest(&env, &summary) != proof
+        {
+            return Err(Error::MigrationMismatch);
+        }
+        if !env
+            .storage()
+            .instance()
+            .has(&DataKey::MigrationSource(summary.source.clone()))
+        {
+            return Err(Error::MigrationNotApproved);
+        }
+
+        let escrow = summary.escrow;
+        let lookup_key = DataKey::ClientServerEscrow(escrow.client.clone(), escrow.server.clone());
+        if has_record(&env, &lookup_key) {
+            return Err(Error::EscrowAlreadyExists);
+        }
+
+        // Close it at the source, which fails unless it exported this summary
+        MigrationSourceClient::new(&env, &summary.source)
+            .complete_migration(&summary.escrow_id, &proof);
+        // Migrated escrows count but are not capped, the source already
+        // released theirs
+        take_escrow_slot(&env, &escrow.client, false)?;
+
+        let escrow_id = allocate_escrow_id(&env, &escrow.client);
+        write_record(&env, &DataKey::Escrow(escrow_id), &escrow);
+        write_record(&env, &lookup_key, &escrow_id);
+        index_escrow(&env, escrow_id, &escrow);
+        record_activity(&env, escrow_id);
+
+        // Recreate the unsettled payments
+        for mut payment in summary.pending.iter() {
+            payment.escrow_id = escrow_id;
+            record_payment(&env, &payment);
+        }
+        if let Some(limit) = summary.spend_limit {
+            write_record(&env, &DataKey::SpendLimit(escrow_id), &limit);
+        }
+        if summary.links.parent.is_some() || !summary.links.children.is_empty() {
+            write_record(&env, &DataKey::EscrowLinks(escrow_id), &summary.links);
+        }
+        if !summary.notes.is_empty() {
+            write_record(&env, &DataKey::Notes(escrow_id), &summary.notes);
+        }
+
+        env.events().publish(
+            (symbol_short!("import"), escrow_id),
+            ImportedEvent {
+                event_version: EVENT_VERSION,
+                source: summary.source,
+                source_escrow_id: summary.escrow_id,
+            },
+        );
+
+        Ok(escrow_id)
+    }
+
+    /// Set which escrows `sweep_dust` may archive
+    ///
+    /// # Arguments
+    /// * `admin` - Contract admin
+    /// * `threshold` - Balances below this are dust (in stroops)
+    /// * `max_idle` - Seconds without activity after which an escrow is
+    ///   abandoned, at least `MIN_DUST_IDLE`
+    ///
+    /// # Errors
+    /// * `Unauthorized` - If `admin` is not the contract admin
+    /// * `NotInitialized` - If no admin is set
+    /// * `InvalidDustPolicy` - If `threshold` is not positive or `max_idle`
+    ///   is below `MIN_DUST_IDLE`
+    pub fn set_dust_policy(
+        env: Env,
+        admin: Address,
+        threshold: i128,
+        max_idle: u64,
+    ) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+        if threshold <= 0 || max_idle < MIN_DUST_IDLE {
+            return Err(Error::InvalidDustPolicy);
+        }
         env.storage()
             .instance()
-            .get(&payment_key)
-            .expect("Payment not found")
+            .set(&DataKey::DustPolicy, &DustPolicy { threshold, max_idle });
+        Ok(())
     }
 
-    /// Find escrow ID for a client-server pair
+    /// Get the dust policy, None until the admin set one
+    pub fn get_dust_policy(env: Env) -> Option<DustPolicy> {
+        env.storage().instance().get(&DataKey::DustPolicy)
+    }
+
+    /// Cap the open escrows a client may hold, across servers
+    ///
+    /// Closed, migrated, and swept escrows no longer count. Clients already
+    /// over a new cap keep their escrows but open no more until under it.
+    ///
+    /// # Arguments
+    /// * `admin` - Contract admin
+    /// * `max_escrows_per_client` - Most open escrows per client, None to
+    ///   lift the cap
+    ///
+    /// # Errors
+    /// * `Unauthorized` - If `admin` is not the contract admin
+    /// * `NotInitialized` - If no admin is set
+    pub fn set_max_escrows_per_client(
+        env: Env,
+        admin: Address,
+        max_escrows_per_client: Option<u32>,
+    ) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+        match max_escrows_per_client {
+            Some(max) => env.storage().instance().set(&DataKey::MaxEscrowsPerClient, &max),
+            None => env.storage().instance().remove(&DataKey::MaxEscrowsPerClient),
+        }
+        Ok(())
+    }
+
+    /// Set the contract admin, once
+    ///
+    /// Admin functions fail until it is set, so this should be called right
+    /// after deployment.
+    ///
+    /// # Arguments
+    /// * `admin` - Contract admin
+    ///
+    /// # Errors
+    /// * `AlreadyInitialized` - If the admin is already set
+    pub fn initialize(env: Env, admin: Address) -> Result<(), Error> {
+        admin.require_auth();
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(Error::AlreadyInitialized);
+        }
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        Ok(())
+    }
+
+    /// Get the version of the contract interface
+    ///
+    /// Reads no storage, so simulating it is a cheap check that the
+    /// contract is deployed and reachable.
+    pub fn version(_env: Env) -> u32 {
+        CONTRACT_VERSION
+    }
+
+    /// Tolerate clock skew when checking expiries
+    ///
+    /// Authorizations and allowances are accepted until `seconds` after
+    /// they expire by the ledger's close time, so that one checked by a
+    /// facilitator whose clock runs behind is not rejected at the boundary.
+    ///
+    /// # Arguments
+    /// * `admin` - Contract admin
+    /// * `seconds` - Tolerance in seconds, 0 to accept nothing expired
+    ///
+    /// # Errors
+    /// * `Unauthorized` - If `admin` is not the contract admin
+    /// * `NotInitialized` - If no admin is set
+    pub fn set_clock_skew(env: Env, admin: Address, seconds: u64) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+        if seconds == 0 {
+            env.storage().instance().remove(&DataKey::ClockSkew);
+        } else {
+            env.storage().instance().set(&DataKey::ClockSkew, &seconds);
+        }
+        Ok(())
+    }
+
+    /// Get the clock skew tolerance in seconds, 0 until the admin set one
+    pub fn get_clock_skew(env: Env) -> u64 {
+        env.storage().instance().get(&DataKey::ClockSkew).unwrap_or(0)
+    }
+
+    /// Get the cap on open escrows per client, None if there is none
+    pub fn get_max_escrows_per_client(env: Env) -> Option<u32> {
+        env.storage().instance().get(&DataKey::MaxEscrowsPerClient)
+    }
+
+    /// Get the number of open escrows a client holds
+    ///
+    /// Escrows opened before the count was kept are not included.
+    pub fn get_client_escrow_count(env: Env, client: Address) -> u32 {
+        read_record(&env, &DataKey::ClientEscrowCount(client)).unwrap_or(0)
+    }
+
+    /// Set where dust swept from the client's escrows is released to
+    ///
+    /// # Arguments
+    /// * `client` - Escrow client
+    /// * `refund_to` - Refund address of the client
+    pub fn set_refund_address(env: Env, client: Address, refund_to: Address) {
+        client.require_auth();
+        write_record(&env, &DataKey::RefundAddress(client), &refund_to);
+    }
+
+    /// Get where dust swept from the client's escrows is released to, the
+    /// client itself unless it set a refund address
+    pub fn get_refund_address(env: Env, client: Address) -> Address {
+        read_record(&env, &DataKey::RefundAddress(client.clone())).unwrap_or(client)
+    }
+
+    /// Publish the hash of the server's terms of service
+    ///
+    /// Escrows opened from now on must accept these terms. Escrows already
+    /// open keep the terms they were opened under.
+    ///
+    /// # Arguments
+    /// * `server` - Server address
+    /// * `terms_hash` - Hash of the terms, e.g. the SHA-256 of their text
+    pub fn set_terms(env: Env, server: Address, terms_hash: BytesN<32>) {
+        server.require_auth();
+
+        let key = DataKey::Terms(server.clone());
+        let previous: Option<BytesN<32>> = read_record(&env, &key);
+        write_record(&env, &key, &terms_hash);
+
+        env.events().publish(
+            (symbol_short!("terms"), server),
+            TermsUpdatedEvent {
+                event_version: EVENT_VERSION,
+                terms_hash,
+                previous,
+            },
+        );
+    }
+
+    /// Get the hash of the server's terms of service, None if it published
+    /// none
+    pub fn get_terms(env: Env, server: Address) -> Option<BytesN<32>> {
+        read_record(&env, &DataKey::Terms(server))
+    }
+
+    /// Register the URL the server's settlement and dispute notifications
+    /// are delivered to
+    ///
+    /// Indexers follow the `notify` event to deliver webhooks there, signed
+    /// with a key exchanged with the server out of band.
     ///
     /// # Arguments
-    /// * `client` - Client address
     /// * `server` - Server address
+    /// * `url` - URL, at most `MAX_NOTIFY_URL_LEN` bytes, empty to remove it
     ///
-    /// # Returns
-    /// * Escrow ID if exists, None otherwise
-    pub fn find_escrow(env: Env, client: Address, server: Address) -> Option<u64> {
-        let lookup_key = DataKey::ClientServerEscrow(client, server);
-        env.storage().instance().get(&lookup_key)
+    /// # Errors
+    /// * `InvalidNotificationUrl` - If the URL is longer than
+    ///   `MAX_NOTIFY_URL_LEN`
+    pub fn set_notification_url(env: Env, server: Address, url: Bytes) -> Result<(), Error> {
+        server.require_auth();
+        if url.len() > MAX_NOTIFY_URL_LEN {
+            return Err(Error::InvalidNotificationUrl);
+        }
+
+        let key = DataKey::NotifyUrl(server.clone());
+        let url = if url.is_empty() {
+            remove_record(&env, &key);
+            None
+        } else {
+            write_record(&env, &key, &url);
+            Some(url)
+        };
+
+        env.events().publish(
+            (symbol_short!("notify"), server),
+            NotificationUrlEvent {
+                event_version: EVENT_VERSION,
+                url,
+            },
+        );
+        Ok(())
     }
-}
+
+    /// Get the URL the server's notifications are delivered to, None if it
+    /// registered none
+    pub fn get_notification_url(env: Env, server: Address) -> Option<Bytes> {
+        read_record(&env, &DataKey::NotifyUrl(server))
+    }
+
+    /// Get the timestamp of the last activity on an escrow
+    ///
+    /// Escrows opened before activity was recorded count their newest
+    /// payment, or 0 without payments.
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    pub fn get_last_activity(env: Env, escrow_id: u64) -> Result<u64, Error> {
+        if !has_record(&env, &DataKey::Escrow(escrow_id)) {
+            return Err(Error::EscrowNotFound);
+        }
+        Ok(last_activity(&env, escrow_id))
+    }
+
+    /// Archive abandoned escrows holding dust
+    ///
+    /// An escrow is swept when its balance is below the dust threshold and
+    /// nothing happened on it for the policy's inactivity window. Its
+    /// balance is released to the client's refund address, never to the
+    /// admin, as closing releases it, and the escrow is removed with its
+    /// `swept` event as the archive. Trial funds left return to the server.
+    ///
+    /// The sweep is atomic: if any escrow cannot be swept, none is.
+    ///
+    /// # Arguments
+    /// * `admin` - Contract admin
+    /// * `escrow_ids` - Escrows to sweep
+    ///
+    /// # Returns
+    /// * Total balance released to clients
+    ///
+    /// # Errors
+    /// * `Unauthorized` - If `admin` is not the contract admin
+    /// * `NotInitialized` - If no admin is set
+    /// * `DustPolicyNotSet` - If no dust policy is set
+    /// * `EscrowNotFound` - If an escrow doesn't exist or is listed twice
+    /// * `EscrowFrozen` - If an escrow was exported for migration
+    /// * `EscrowNotDust` - If an escrow holds more than dust or was active
+    ///   within the inactivity window
+    /// * `PendingPayments` - If an escrow has unsettled payments
+    pub fn sweep_dust(env: Env, admin: Address, escrow_ids: Vec<u64>) -> Result<i128, Error> {
+        require_admin(&env, &admin)?;
+        let policy: DustPolicy = env
+            .storage()
+            .instance()
+            .get(&DataKey::DustPolicy)
+            .ok_or(Error::DustPolicyNotSet)?;
+        let now = env.ledger().timestamp();
+        let mut total: i128 = 0;
+
+        for escrow_id in escrow_ids.iter() {
+            let escrow_key = DataKey::Escrow(escrow_id);
+            let escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+            require_not_frozen(&env, escrow_id)?;
+
+            if escrow.balance >= policy.threshold
+                || now.saturating_sub(last_activity(&env, escrow_id)) < policy.max_idle
+            {
+                return Err(Error::EscrowNotDust);
+            }
+            if escrow.pending_payments > 0 {
+                return Err(Error::PendingPayments);
+            }
+
+            // Remove escrow, lookup mapping, and activity
+            remove_record(&env, &escrow_key);
+            remove_lookup(&env, escrow_id, &escrow);
+            remove_record(&env, &DataKey::EscrowActivity(escrow_id));
+            remove_record(&env, &DataKey::Notes(escrow_id));
+            remove_record(&env, &DataKey::SpendLimit(escrow_id));
+            archive_escrow(&env, escrow_id, &escrow);
+            release_escrow_slot(&env, escrow_id, &escrow.client);
+            let released = reclaim_trial(&env, escrow_id, &escrow);
+            total += released;
+
+            let refund_to = Self::get_refund_address(env.clone(), escrow.client.clone());
+            env.events().publish(
+                (symbol_short!("swept"), escrow_id),
+                SweptEvent {
+                    event_version: EVENT_VERSION,
+                    client: escrow.client,
+                    refund_to,
+                    balance: released,
+                },
+            );
+        }
+
+        Ok(total)
+    }
+
+    /// Cap what the server settles from an escrow per hour
+    ///
+    /// Guards against a compromised server key draining the escrow through
+    /// rapid settlements. A settlement taking the hour's total above the cap
+    /// is not made: it pauses settlements of the escrow, with an `anomaly`
+    /// event, until the client calls `acknowledge_anomaly` or the hour
+    /// rolls over. Payments may still be created while paused.
+    ///
+    /// Each window starts with the first settlement after the previous one
+    /// ended. Payments above the cap are never settled while it is set.
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow account ID
+    /// * `max_settled_per_hour` - Cap (in stroops), None to remove it
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `InvalidSpendLimit` - If the cap is not positive
+    pub fn set_max_settled_per_hour(
+        env: Env,
+        escrow_id: u64,
+        max_settled_per_hour: Option<i128>,
+    ) -> Result<(), Error> {
+        let escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+        escrow.client.require_auth();
+
+        let key = DataKey::SpendLimit(escrow_id);
+        let Some(max_settled_per_hour) = max_settled_per_hour else {
+            remove_record(&env, &key);
+            return Ok(());
+        };
+        if max_settled_per_hour <= 0 {
+            return Err(Error::InvalidSpendLimit);
+        }
+        // Changing the cap keeps the window and its pause
+        let limit = match read_record::<SpendLimit>(&env, &key) {
+            Some(limit) => SpendLimit { max_settled_per_hour, ..limit },
+            None => SpendLimit {
+                max_settled_per_hour,
+                window_start: env.ledger().timestamp(),
+                settled: 0,
+                paused: false,
+            },
+        };
+        write_record(&env, &key, &limit);
+        Ok(())
+    }
+
+    /// Resume settlements of an escrow paused by its spend limit, starting
+    /// a new window
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    pub fn acknowledge_anomaly(env: Env, escrow_id: u64) -> Result<(), Error> {
+        let escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+        escrow.client.require_auth();
+
+        let key = DataKey::SpendLimit(escrow_id);
+        if let Some(mut limit) = read_record::<SpendLimit>(&env, &key) {
+            limit.window_start = env.ledger().timestamp();
+            limit.settled = 0;
+            limit.paused = false;
+            write_record(&env, &key, &limit);
+        }
+        Ok(())
+    }
+
+    /// Get the spend limit of an escrow, None if it has none
+    ///
+    /// The window is returned as recorded, even once it rolled over.
+    pub fn get_spend_limit(env: Env, escrow_id: u64) -> Option<SpendLimit> {
+        read_record(&env, &DataKey::SpendLimit(escrow_id))
+    }
+
+    /// Get the escrows an escrow was split from and into
+    ///
+    /// Links are kept once the escrows are removed. Escrows never split
+    /// have no parent, children, or metadata.
+    pub fn get_escrow_links(env: Env, escrow_id: u64) -> EscrowLinks {
+        read_record(&env, &DataKey::EscrowLinks(escrow_id)).unwrap_or(EscrowLinks {
+            parent: None,
+            children: Vec::new(&env),
+            metadata: Bytes::new(&env),
+        })
+    }
+
+    /// Get escrow balance
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow account ID
+    ///
+    /// # Returns
+    /// * Current escrow balance (in stroops)
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    pub fn get_escrow_balance(env: Env, escrow_id: u64) -> Result<i128, Error> {
+        let escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+
+        Ok(escrow.balance)
+    }
+
+    /// Get escrow details
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow account ID
+    ///
+    /// # Returns
+    /// * Escrow struct
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    pub fn get_escrow(env: Env, escrow_id: u64) -> Result<Escrow, Error> {
+        read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)
+    }
+
+    /// Get payment status
+    ///
+    /// # Arguments
+    /// * `payment_id` - Payment ID
+    ///
+    /// # Returns
+    /// * Payment struct
+    ///
+    /// # Errors
+    /// * `PaymentNotFound` - If payment doesn't exist
+    pub fn get_payment(env: Env, payment_id: u64) -> Result<Payment, Error> {
+        read_payment(&env, payment_id).ok_or(Error::PaymentNotFound)
+    }
+
+    /// Get the USD equivalent of an escrow's settled payments
+    ///
+    /// Payments settled while the price feed had no usable price count as
+    /// `unquoted`. Totals remain once the escrow is closed.
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow account ID
+    pub fn get_quote_total(env: Env, escrow_id: u64) -> QuoteTotal {
+        read_record(&env, &DataKey::QuoteTotal(escrow_id)).unwrap_or(QuoteTotal {
+            currency: symbol_short!("USD"),
+            quote_amount: 0,
+            quoted: 0,
+            unquoted: 0,
+        })
+    }
+
+    /// List the payments of an escrow, page by page
+    ///
+    /// Looks at up to `limit` payments of the escrow from `offset` on, in
+    /// creation order, and returns those with the given status. A page may
+    /// hold fewer payments than `limit` when some are filtered out, so callers
+    /// continue from `next_offset` until it is None.
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow whose payments are listed, closed or not
+    /// * `status` - Only return payments with this status, all if None
+    /// * `offset` - Index of the first payment looked at
+    /// * `limit` - Payments looked at, capped at `MAX_PAYMENTS_PAGE`
+    ///
+    /// # Returns
+    /// * Matching payments and the offset of the next page
+    pub fn get_payments(
+        env: Env,
+        escrow_id: u64,
+        status: Option<PaymentStatus>,
+        offset: u32,
+        limit: u32,
+    ) -> PaymentPage {
+        let count: u32 = read_record(&env, &DataKey::EscrowPaymentCount(escrow_id)).unwrap_or(0);
+        payment_page(&env, count, status, offset, limit, |index| {
+            DataKey::EscrowPayment(escrow_id, index)
+        })
+    }
+
+    /// List the direct payments of a server, page by page
+    ///
+    /// Pages as [`get_payments`](Self::get_payments) does, through the
+    /// payments [`record_direct_payment`](Self::record_direct_payment)
+    /// recorded to the server. Direct payments are settled when recorded.
+    ///
+    /// # Arguments
+    /// * `server` - Server paid
+    /// * `status` - Only return payments with this status, all if None
+    /// * `offset` - Index of the first payment looked at
+    /// * `limit` - Payments looked at, capped at `MAX_PAYMENTS_PAGE`
+    ///
+    /// # Returns
+    /// * Matching payments and the offset of the next page
+    pub fn get_direct_payments(
+        env: Env,
+        server: Address,
+        status: Option<PaymentStatus>,
+        offset: u32,
+        limit: u32,
+    ) -> PaymentPage {
+        let count_key = DataKey::DirectPaymentCount(server.clone());
+        let count: u32 = read_record(&env, &count_key).unwrap_or(0);
+        payment_page(&env, count, status, offset, limit, |index| {
+            DataKey::DirectPayment(server.clone(), index)
+        })
+    }
+
+    /// List the open escrows of the contract, page by page
+    ///
+    /// Escrows opened by the former global counter are looked at first, in
+    /// ID order, then the escrows of each shard in turn, oldest first. A
+    /// call reads at most `limit + 1` entries besides the contract instance,
+    /// escrows and shard counters together, so a page stays within the
+    /// transaction's read limit however many shards are in use. Closed
+    /// escrows are removed from storage and shards fill unevenly, so a page
+    /// may hold fewer escrows than `limit`, even none; callers continue from
+    /// `next_start` until it is None.
+    ///
+    /// # Arguments
+    /// * `server` - Only return escrows with this server, all if None
+    /// * `start` - First escrow ID looked at, 0 or the `next_start` of the
+    ///   previous page
+    /// * `limit` - Escrow IDs looked at, capped at `MAX_ESCROWS_PAGE`
+    ///
+    /// # Returns
+    /// * Matching escrows and the escrow ID of the next page
+    pub fn export_escrows(
+        env: Env,
+        server: Option<Address>,
+        start: u64,
+        limit: u32,
+    ) -> EscrowPage {
+        let legacy = legacy_escrow_count(&env);
+        let shards = u64::from(ESCROW_SHARDS);
+        let mut reads = limit.clamp(1, MAX_ESCROWS_PAGE) + 1;
+
+        let mut escrows = Vec::new(&env);
+        let mut look_at = |escrow_id| {
+            let Some(escrow) = read_escrow(&env, escrow_id) else {
+                return;
+            };
+            if server.as_ref().is_none_or(|server| *server == escrow.server) {
+                escrows.push_back(EscrowEntry { escrow_id, escrow });
+            }
+        };
+
+        // Escrows of the cursor's shard, once its counter is read
+        let mut shard_count = None;
+        let mut cursor = start;
+        let next_start = loop {
+            if reads == 0 {
+                break Some(cursor);
+            }
+            if cursor < legacy {
+                look_at(cursor);
+                reads -= 1;
+                cursor += 1;
+                continue;
+            }
+            let (round, shard) = ((cursor - legacy) / shards, (cursor - legacy) % shards);
+            let Some(count) = shard_count else {
+                let key = DataKey::EscrowShardCount(shard as u32);
+                shard_count = Some(read_record::<u64>(&env, &key).unwrap_or(0));
+                reads -= 1;
+                continue;
+            };
+            if round < count {
+                look_at(cursor);
+                reads -= 1;
+                cursor += shards;
+            } else if shard + 1 < shards {
+                cursor = legacy + shard + 1;
+                shard_count = None;
+            } else {
+                break None;
+            }
+        };
+
+        EscrowPage { escrows, next_start }
+    }
+
+    /// Find escrow ID for a client-server pair
+    ///
+    /// Escrows split from it are not found, see `get_escrow_links`.
+    ///
+    /// # Arguments
+    /// * `client` - Client address
+    /// * `server` - Server address
+    ///
+    /// # Returns
+    /// * Escrow ID if exists, None otherwise
+    pub fn find_escrow(env: Env, client: Address, server: Address) -> Option<u64> {
+        let lookup_key = DataKey::ClientServerEscrow(client, server);
+        read_record(&env, &lookup_key)
+    }
+
+    /// List the escrows of a client, page by page
+    ///
+    /// Live pages list the escrows indexed while open, skipping those
+    /// removed since; archive pages list removed escrows, in the order they
+    /// were removed. Pages hold up to `ESCROW_INDEX_PAGE` IDs, fewer once
+    /// compacted or skipped, so callers continue from `next_page` until it
+    /// is None. Escrows the client opened before the indexes were kept
+    /// are not listed, see the crate docs.
+    ///
+    /// # Arguments
+    /// * `client` - Client whose escrows are listed
+    /// * `archived` - Whether to list removed escrows instead of open ones
+    /// * `page` - Page listed, from 0
+    ///
+    /// # Returns
+    /// * Escrow IDs of the page and the next page
+    pub fn get_escrow_index(env: Env, client: Address, archived: bool, page: u32) -> EscrowIdPage {
+        let index = read_escrow_index(&env, &client);
+        let pages = if archived { index.archived_pages } else { index.live_pages };
+        let key = index_page_key(&client, archived, page);
+        let mut escrow_ids = Vec::new(&env);
+        if page < pages {
+            let ids: Vec<u64> = read_record(&env, &key).unwrap_or_else(|| Vec::new(&env));
+            for escrow_id in ids.iter() {
+                if archived || read_escrow(&env, escrow_id).is_some() {
+                    escrow_ids.push_back(escrow_id);
+                }
+            }
+        }
+        EscrowIdPage {
+            escrow_ids,
+            next_page: (page.saturating_add(1) < pages).then_some(page + 1),
+        }
+    }
+
+    /// Drop removed escrows from the live pages of an escrow index,
+    /// packing the IDs left into fewer pages
+    ///
+    /// Compacts up to `pages` live pages from `start` on; pages emptied
+    /// before the last one are removed and listed empty. Once the last page
+    /// is compacted, the index shrinks to the pages left. Removed escrows
+    /// stay listed in the archive pages.
+    ///
+    /// # Arguments
+    /// * `client` - Client whose index is compacted
+    /// * `start` - First live page compacted
+    /// * `pages` - Pages compacted, capped at `MAX_COMPACT_PAGES`
+    ///
+    /// # Returns
+    /// * Page the next call starts at, None once the last page was compacted
+    pub fn compact_escrow_index(env: Env, client: Address, start: u32, pages: u32) -> Option<u32> {
+        client.require_auth();
+
+        let index_key = DataKey::EscrowIndex(client.clone());
+        let mut index = read_escrow_index(&env, &client);
+        let start = start.min(index.live_pages);
+        let end = start
+            .saturating_add(pages.clamp(1, MAX_COMPACT_PAGES))
+            .min(index.live_pages);
+
+        // Pack the open escrows of the pages into the first ones
+        let mut target = start;
+        let mut packed = Vec::new(&env);
+        for page in start..end {
+            let key = index_page_key(&client, false, page);
+            let ids: Vec<u64> = read_record(&env, &key).unwrap_or_else(|| Vec::new(&env));
+            remove_record(&env, &key);
+            for escrow_id in ids.iter() {
+                if read_escrow(&env, escrow_id).is_none() {
+                    continue;
+                }
+                packed.push_back(escrow_id);
+                if packed.len() == ESCROW_INDEX_PAGE {
+                    write_record(&env, &index_page_key(&client, false, target), &packed);
+                    target += 1;
+                    packed = Vec::new(&env);
+                }
+            }
+        }
+        if !packed.is_empty() {
+            write_record(&env, &index_page_key(&client, false, target), &packed);
+            target += 1;
+        }
+
+        if end < index.live_pages {
+            return Some(end);
+        }
+        index.live_pages = target;
+        write_record(&env, &index_key, &index);
+        None
+    }
+}
+
+#[cfg(feature = "signed-auth")]
+#[contractimpl]
+impl X402EscrowContract {
+    /// Create a payment from an authorization signed off-chain by the
+    /// escrow's client, or by an agent within its allowance
+    ///
+    /// Each authorization pays once: its nonce is spent with the payment.
+    /// Agent payments count against the agent's remaining allowance.
+    ///
+    /// # Arguments
+    /// * `authorization` - Signed fields of the authorization
+    /// * `public_key` - ed25519 key of the client or of one of its agents
+    /// * `signature` - Signature of the SHA-256 of the authorization
+    ///
+    /// # Returns
+    /// * Payment ID
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `AuthorizationExpired` - If the authorization expired longer ago
+    ///   than the clock skew tolerance
+    /// * `AuthorizationUsed` - If the nonce already paid from the escrow
+    /// * `NetworkMismatch` - If the authorization is for another network
+    /// * `InvalidSigner` - If the key is neither the client's nor that of an
+    ///   agent holding an allowance from the client
+    /// * `AllowanceExpired` - If the agent's allowance has expired
+    /// * `AllowanceExceeded` - If the amount is not positive or exceeds the
+    ///   agent's caps
+    /// * `InsufficientBalance` - If insufficient escrow balance
+    /// * `EscrowFrozen` - If the escrow was exported for migration
+    ///
+    /// # Panics
+    /// * If the signature is invalid
+    pub fn create_authorized_payment(
+        env: Env,
+        authorization: Authorization,
+        public_key: BytesN<32>,
+        signature: BytesN<64>,
+    ) -> Result<u64, Error> {
+        let escrow_id = authorization.escrow_id;
+        let escrow = Self::get_escrow(env.clone(), escrow_id)?;
+        if expired(&env, authorization.expires_at) {
+            return Err(Error::AuthorizationExpired);
+        }
+        let used_key = DataKey::UsedAuthorization(escrow_id, authorization.nonce);
+        if has_record(&env, &used_key) {
+            return Err(Error::AuthorizationUsed);
+        }
+        signing::verify_authorization(&env, &authorization, &public_key, &signature)?;
+
+        // Agents spend from their allowance, the client from its balance only
+        let signer = signing::signer(&env, &public_key);
+        if signer != escrow.client {
+            let mut allowance =
+                check_allowance(&env, &escrow.client, &signer, authorization.amount)?;
+            allowance.remaining -= authorization.amount;
+            write_record(&env, &DataKey::Allowance(escrow.client, signer), &allowance);
+        }
+        spend_authorization(&env, &authorization);
+
+        Self::create_payment(env, escrow_id, authorization.amount)
+    }
+
+    /// Open an escrow and create its first payment from an authorization
+    /// signed off-chain by the client, in one invocation
+    ///
+    /// The authorization names the ID the escrow is opened with, which the
+    /// client learns by simulating `open_escrow`. Its payment is created as
+    /// by `create_authorized_payment`, without the server's authorization,
+    /// and recorded for `get_preauthorized_payment`, so the server settles
+    /// it rather than creating it again. If the authorization is not
+    /// accepted, the escrow is not opened either.
+    ///
+    /// # Arguments
+    /// * `client` - Client address
+    /// * `server` - Server address
+    /// * `amount` - Initial deposit amount (in stroops)
+    /// * `terms_hash` - Hash of the server's terms of service the client
+    ///   accepts, None if the server published none
+    /// * `authorization` - Signed fields of the first payment's
+    ///   authorization
+    /// * `public_key` - ed25519 key of the client
+    /// * `signature` - Signature of the SHA-256 of the authorization
+    ///
+    /// # Returns
+    /// * Escrow ID and payment ID
+    ///
+    /// # Errors
+    /// * `EscrowAlreadyExists` - If escrow already exists for this client-server pair
+    /// * `TooManyEscrows` - If the client holds as many open escrows as the
+    ///   admin allows
+    /// * `TermsMismatch` - If `terms_hash` is not the hash the server
+    ///   currently publishes
+    /// * `AuthorizationMismatch` - If the authorization is for another
+    ///   escrow ID
+    /// * `AuthorizationExpired` - If the authorization expired longer ago
+    ///   than the clock skew tolerance
+    /// * `NetworkMismatch` - If the authorization is for another network
+    /// * `InvalidSigner` - If the key is not the client's
+    /// * `InsufficientBalance` - If the deposit doesn't cover the payment
+    ///
+    /// # Panics
+    /// * If the signature is invalid
+    pub fn open_and_authorize(
+        env: Env,
+        client: Address,
+        server: Address,
+        amount: i128,
+        terms_hash: Option<BytesN<32>>,
+        authorization: Authorization,
+        public_key: BytesN<32>,
+        signature: BytesN<64>,
+    ) -> Result<OpenedEscrow, Error> {
+        // Verify authorization
+        client.require_auth();
+
+        let escrow_id = open(&env, client.clone(), server, amount, terms_hash)?;
+        if authorization.escrow_id != escrow_id {
+            return Err(Error::AuthorizationMismatch);
+        }
+        if expired(&env, authorization.expires_at) {
+            return Err(Error::AuthorizationExpired);
+        }
+        signing::verify_authorization(&env, &authorization, &public_key, &signature)?;
+        if signing::signer(&env, &public_key) != client {
+            return Err(Error::InvalidSigner);
+        }
+
+        let escrow = read_escrow(&env, escrow_id).ok_or(Error::EscrowNotFound)?;
+        let payment_id = create(&env, escrow_id, escrow, authorization.amount)?;
+        spend_authorization(&env, &authorization);
+        write_record(
+            &env,
+            &DataKey::PreAuthorizedPayment(escrow_id, authorization.nonce),
+            &payment_id,
+        );
+
+        Ok(OpenedEscrow { escrow_id, payment_id })
+    }
+
+    /// Get the payment `open_and_authorize` created from an escrow's
+    /// authorization, None if the nonce paid no such payment
+    pub fn get_preauthorized_payment(env: Env, escrow_id: u64, nonce: u64) -> Option<u64> {
+        read_record(&env, &DataKey::PreAuthorizedPayment(escrow_id, nonce))
+    }
+
+    /// Let an agent sign payment authorizations for the client's escrows
+    ///
+    /// Replaces any allowance the agent held, its remaining cap included.
+    /// Agents only authorize payments: closing and migrating escrows stay
+    /// the client's.
+    ///
+    /// # Arguments
+    /// * `client` - Escrow client
+    /// * `agent` - Account of the agent's ed25519 key
+    /// * `total_cap` - Total the agent may authorize (in stroops)
+    /// * `per_payment_cap` - Most the agent may authorize in one payment
+    /// * `expires_at` - Ledger timestamp after which the allowance is void
+    ///
+    /// # Errors
+    /// * `InvalidAllowance` - If a cap is not positive, the per-payment cap
+    ///   exceeds the total, `expires_at` is not in the future, or the agent
+    ///   is the client
+    pub fn grant_agent(
+        env: Env,
+        client: Address,
+        agent: Address,
+        total_cap: i128,
+        per_payment_cap: i128,
+        expires_at: u64,
+    ) -> Result<(), Error> {
+        client.require_auth();
+        if total_cap <= 0
+            || per_payment_cap <= 0
+            || per_payment_cap > total_cap
+            || expires_at <= env.ledger().timestamp()
+            || agent == client
+        {
+            return Err(Error::InvalidAllowance);
+        }
+
+        let allowance = Allowance {
+            total_cap,
+            remaining: total_cap,
+            per_payment_cap,
+            expires_at,
+        };
+        write_record(&env, &DataKey::Allowance(client.clone(), agent.clone()), &allowance);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("grant"), client, agent),
+            AgentGrantedEvent { event_version: EVENT_VERSION, total_cap, per_payment_cap, expires_at },
+        );
+
+        Ok(())
+    }
+
+    /// Withdraw the allowance of an agent
+    ///
+    /// Authorizations the agent signed no longer pay, even those already
+    /// handed to a server; payments already created are unaffected.
+    ///
+    /// # Arguments
+    /// * `client` - Escrow client
+    /// * `agent` - Account of the agent's ed25519 key
+    ///
+    /// # Errors
+    /// * `AllowanceNotFound` - If the agent holds no allowance from the client
+    pub fn revoke_agent(env: Env, client: Address, agent: Address) -> Result<(), Error> {
+        client.require_auth();
+        let key = DataKey::Allowance(client.clone(), agent.clone());
+        let allowance: Allowance = read_record(&env, &key).ok_or(Error::AllowanceNotFound)?;
+        remove_record(&env, &key);
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("revoke"), client, agent),
+            AgentRevokedEvent { event_version: EVENT_VERSION, remaining: allowance.remaining },
+        );
+
+        Ok(())
+    }
+
+    /// Get the allowance a client granted an agent, None if it holds none
+    ///
+    /// Expired allowances are returned until revoked, as they are stored.
+    pub fn get_allowance(env: Env, client: Address, agent: Address) -> Option<Allowance> {
+        read_record(&env, &DataKey::Allowance(client, agent))
+    }
+
+    /// Verify a client's signature of a payment authorization
+    ///
+    /// The authorization must name the network the contract runs on. An
+    /// agent's signature is valid while its allowance covers the amount,
+    /// which this check does not spend.
+    ///
+    /// # Arguments
+    /// * `authorization` - Signed fields of the authorization
+    /// * `public_key` - ed25519 key of the escrow's client or of one of its
+    ///   agents
+    /// * `signature` - Signature of the SHA-256 of the authorization
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `InvalidSigner` - If the key is neither the client's nor that of an
+    ///   agent holding an allowance from the client
+    /// * `AllowanceExpired` - If the agent's allowance has expired
+    /// * `AllowanceExceeded` - If the amount is not positive or exceeds the
+    ///   agent's caps
+    /// * `NetworkMismatch` - If the authorization is for another network
+    ///
+    /// # Panics
+    /// * If the signature is invalid
+    pub fn verify_authorization(
+        env: Env,
+        authorization: Authorization,
+        public_key: BytesN<32>,
+        signature: BytesN<64>,
+    ) -> Result<(), Error> {
+        let escrow = Self::get_escrow(env.clone(), authorization.escrow_id)?;
+        let signer = signing::signer(&env, &public_key);
+        if signer != escrow.client {
+            check_allowance(&env, &escrow.client, &signer, authorization.amount)?;
+        }
+        signing::verify_authorization(&env, &authorization, &public_key, &signature)
+    }
+
+    /// Verify a client's signature of a voucher
+    ///
+    /// # Arguments
+    /// * `voucher` - Signed fields of the voucher
+    /// * `public_key` - ed25519 key of the escrow's client
+    /// * `signature` - Signature of the SHA-256 of the voucher
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `InvalidSigner` - If the key is not the escrow client's
+    ///
+    /// # Panics
+    /// * If the signature is invalid
+    pub fn verify_voucher(
+        env: Env,
+        voucher: Voucher,
+        public_key: BytesN<32>,
+        signature: BytesN<64>,
+    ) -> Result<(), Error> {
+        let escrow = Self::get_escrow(env.clone(), voucher.escrow_id)?;
+        if signing::signer(&env, &public_key) != escrow.client {
+            return Err(Error::InvalidSigner);
+        }
+        signing::verify_voucher(&env, &voucher, &public_key, &signature);
+        Ok(())
+    }
+
+    /// Verify a party's signature of a channel state
+    ///
+    /// # Arguments
+    /// * `state` - Signed fields of the state
+    /// * `public_key` - ed25519 key of the escrow's client or server
+    /// * `signature` - Signature of the SHA-256 of the state
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `InvalidSigner` - If the key is neither party's
+    ///
+    /// # Panics
+    /// * If the signature is invalid
+    pub fn verify_channel_state(
+        env: Env,
+        state: ChannelState,
+        public_key: BytesN<32>,
+        signature: BytesN<64>,
+    ) -> Result<(), Error> {
+        let escrow = Self::get_escrow(env.clone(), state.escrow_id)?;
+        let signer = signing::signer(&env, &public_key);
+        if signer != escrow.client && signer != escrow.server {
+            return Err(Error::InvalidSigner);
+        }
+        signing::verify_channel_state(&env, &state, &public_key, &signature);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "oracle")]
+#[contractimpl]
+impl X402EscrowContract {
+    /// Point USD pricing at a SEP-40 price feed
+    ///
+    /// Staleness and slippage limits are reset to their defaults.
+    ///
+    /// # Arguments
+    /// * `admin` - Contract admin
+    /// * `oracle` - Price feed contract
+    /// * `asset` - Asset escrow balances are denominated in, as quoted by the feed
+    ///
+    /// # Errors
+    /// * `Unauthorized` - If another address is the admin
+    /// * `NotInitialized` - If no admin is set
+    pub fn set_price_oracle(
+        env: Env,
+        admin: Address,
+        oracle: Address,
+        asset: Asset,
+    ) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        let config = OracleConfig {
+            oracle,
+            asset,
+            max_age: DEFAULT_MAX_PRICE_AGE,
+            max_slippage_bps: DEFAULT_MAX_SLIPPAGE_BPS,
+        };
+        env.storage().instance().set(&DataKey::Oracle, &config);
+
+        Ok(())
+    }
+
+    /// Change the staleness and slippage limits of oracle prices
+    ///
+    /// # Arguments
+    /// * `admin` - Contract admin
+    /// * `max_age` - Age in seconds after which a price is stale
+    /// * `max_slippage_bps` - Largest deviation of the last price from its TWAP
+    ///   over `TWAP_RECORDS` records, 0 to disable the check
+    ///
+    /// # Errors
+    /// * `Unauthorized` - If `admin` is not the admin
+    /// * `NotInitialized` - If no admin is set
+    /// * `OracleNotSet` - If no price feed is configured
+    pub fn set_price_limits(
+        env: Env,
+        admin: Address,
+        max_age: u64,
+        max_slippage_bps: u32,
+    ) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        let mut config = Self::get_price_oracle(env.clone())?;
+        config.max_age = max_age;
+        config.max_slippage_bps = max_slippage_bps;
+        env.storage().instance().set(&DataKey::Oracle, &config);
+
+        Ok(())
+    }
+
+    /// Get the price feed settings
+    ///
+    /// # Errors
+    /// * `OracleNotSet` - If no price feed is configured
+    pub fn get_price_oracle(env: Env) -> Result<OracleConfig, Error> {
+        env.storage()
+            .instance()
+            .get(&DataKey::Oracle)
+            .ok_or(Error::OracleNotSet)
+    }
+
+    /// Set the USD price of one of the server's resources
+    ///
+    /// # Arguments
+    /// * `server` - Server address
+    /// * `resource` - Resource identifier (e.g., "/weather")
+    /// * `usd_cents` - Price in USD cents
+    pub fn set_usd_price(env: Env, server: Address, resource: String, usd_cents: i128) {
+        server.require_auth();
+
+        write_record(&env, &DataKey::UsdPrice(server, resource), &usd_cents);
+    }
+
+    /// Suspend the sale of one of the server's resources, e.g. sold out or
+    /// under maintenance
+    ///
+    /// Its price stays published and quoted, flagged as suspended, but USD
+    /// payments for it are refused until the server resumes it. Other
+    /// resources paid from the same escrows are not affected.
+    ///
+    /// # Arguments
+    /// * `server` - Server address
+    /// * `resource` - Resource identifier (e.g., "/weather")
+    pub fn suspend_price(env: Env, server: Address, resource: String) {
+        server.require_auth();
+
+        write_record(&env, &DataKey::Suspended(server, resource), &true);
+    }
+
+    /// Resume the sale of one of the server's resources suspended by
+    /// `suspend_price`
+    ///
+    /// # Arguments
+    /// * `server` - Server address
+    /// * `resource` - Resource identifier (e.g., "/weather")
+    pub fn resume_price(env: Env, server: Address, resource: String) {
+        server.require_auth();
+
+        remove_record(&env, &DataKey::Suspended(server, resource));
+    }
+
+    /// Discount one of the server's resources for a time window
+    ///
+    /// While the window is open, the promo price replaces the regular price
+    /// in `quote_usd` and caps `create_payment_usd`. Of overlapping promos,
+    /// the one set last applies. Promos that ended are dropped.
+    ///
+    /// # Arguments
+    /// * `server` - Server address
+    /// * `resource` - Resource identifier (e.g., "/weather")
+    /// * `usd_cents` - Promo price in USD cents
+    /// * `starts_at` - Ledger timestamp the promo starts at
+    /// * `ends_at` - Ledger timestamp the promo ends at, itself excluded
+    ///
+    /// # Errors
+    /// * `InvalidPromo` - If the price is negative, the window is empty or
+    ///   over, or the resource has `MAX_PROMOS` promos that have not ended
+    pub fn set_promo(
+        env: Env,
+        server: Address,
+        resource: String,
+        usd_cents: i128,
+        starts_at: u64,
+        ends_at: u64,
+    ) -> Result<(), Error> {
+        server.require_auth();
+
+        if usd_cents < 0 || starts_at >= ends_at || ends_at <= env.ledger().timestamp() {
+            return Err(Error::InvalidPromo);
+        }
+        let mut promos = Self::get_promos(env.clone(), server.clone(), resource.clone());
+        if promos.len() >= MAX_PROMOS {
+            return Err(Error::InvalidPromo);
+        }
+        promos.push_back(Promo { usd_cents, starts_at, ends_at });
+        write_record(&env, &DataKey::Promos(server, resource), &promos);
+        Ok(())
+    }
+
+    /// Get the promos of one of the server's resources that have not ended,
+    /// in the order they were set
+    pub fn get_promos(env: Env, server: Address, resource: String) -> Vec<Promo> {
+        let now = env.ledger().timestamp();
+        let promos: Vec<Promo> =
+            read_record(&env, &DataKey::Promos(server, resource)).unwrap_or(Vec::new(&env));
+        let mut live = Vec::new(&env);
+        for promo in promos.iter().filter(|promo| promo.ends_at > now) {
+            live.push_back(promo);
+        }
+        live
+    }
+
+    /// Quote a resource's USD price in escrow amount at the oracle price
+    ///
+    /// A promo running for the resource takes precedence over its regular
+    /// price.
+    ///
+    /// # Arguments
+    /// * `server` - Server address
+    /// * `resource` - Resource identifier
+    ///
+    /// # Returns
+    /// * Amount (in stroops), rounded up
+    ///
+    /// # Errors
+    /// * `PriceNotSet` - If the server has no USD price for the resource
+    /// * `OracleNotSet` - If no price feed is configured
+    /// * `PriceUnavailable` - If the feed has no usable price
+    /// * `StalePrice` - If the latest price is older than the limit
+    /// * `PriceSlippage` - If the latest price deviates too much from its TWAP
+    pub fn quote_usd(env: Env, server: Address, resource: String) -> Result<i128, Error> {
+        let usd_cents = usd_price(&env, server, resource).ok_or(Error::PriceNotSet)?;
+
+        usd_to_amount(&env, usd_cents)
+    }
+
+    /// Quote a resource's USD price like `quote_usd`, flagging whether its
+    /// sale is suspended
+    ///
+    /// # Arguments
+    /// * `server` - Server address
+    /// * `resource` - Resource identifier
+    ///
+    /// # Errors
+    /// * Those of `quote_usd`
+    pub fn quote(env: Env, server: Address, resource: String) -> Result<Quote, Error> {
+        let suspended = is_suspended(&env, server.clone(), resource.clone());
+        let amount = Self::quote_usd(env, server, resource)?;
+
+        Ok(Quote { amount, suspended })
+    }
+
+    /// Create a payment of a USD amount converted at the oracle price
+    ///
+    /// The amount may not exceed the server's current price of the resource,
+    /// if it set one, a running promo included.
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow account ID
+    /// * `usd_cents` - Payment amount in USD cents
+    /// * `resource` - Resource paid for, recorded in the event
+    ///
+    /// # Returns
+    /// * Payment ID
+    ///
+    /// # Errors
+    /// * `EscrowNotFound` - If escrow doesn't exist
+    /// * `InsufficientBalance` - If insufficient escrow balance
+    /// * `PriceExceeded` - If the amount exceeds the server's price
+    /// * `ResourceSuspended` - If the server suspended the resource's sale
+    /// * `OracleNotSet` - If no price feed is configured
+    /// * `PriceUnavailable` - If the feed has no usable price
+    /// * `StalePrice` - If the latest price is older than the limit
+    /// * `PriceSlippage` - If the latest price deviates too much from its TWAP
+    pub fn create_payment_usd(
+        env: Env,
+        escrow_id: u64,
+        usd_cents: i128,
+        resource: String,
+    ) -> Result<u64, Error> {
+        let escrow = Self::get_escrow(env.clone(), escrow_id)?;
+        if is_suspended(&env, escrow.server.clone(), resource.clone()) {
+            return Err(Error::ResourceSuspended);
+        }
+        if usd_price(&env, escrow.server, resource.clone()).is_some_and(|price| usd_cents > price) {
+            return Err(Error::PriceExceeded);
+        }
+        let amount = usd_to_amount(&env, usd_cents)?;
+        let payment_id = Self::create_payment(env.clone(), escrow_id, amount)?;
+
+        // Emit event
+        env.events().publish(
+            (symbol_short!("pay_usd"), payment_id),
+            UsdPaymentEvent { event_version: EVENT_VERSION, usd_cents, amount, resource },
+        );
+
+        Ok(payment_id)
+    }
+}
+
+/// Open an escrow the client authorized
+///
+/// # Returns
+/// * Escrow ID
+fn open(
+    env: &Env,
+    client: Address,
+    server: Address,
+    amount: i128,
+    terms_hash: Option<BytesN<32>>,
+) -> Result<u64, Error> {
+    if terms_hash != read_record(env, &DataKey::Terms(server.clone())) {
+        return Err(Error::TermsMismatch);
+    }
+
+    // Check if escrow already exists
+    let lookup_key = DataKey::ClientServerEscrow(client.clone(), server.clone());
+    if has_record(env, &lookup_key) {
+        return Err(Error::EscrowAlreadyExists);
+    }
+    take_escrow_slot(env, &client, true)?;
+
+    // Allocate an escrow ID, see the crate docs for its format
+    let escrow_id = allocate_escrow_id(env, &client);
+
+    // Create escrow account
+    let escrow = Escrow {
+        client: client.clone(),
+        server: server.clone(),
+        balance: amount,
+        client_closed: false,
+        server_closed: false,
+        terms_hash,
+        trial: false,
+        trial_balance: 0,
+        pending: 0,
+        pending_payments: 0,
+    };
+
+    // Store escrow
+    let escrow_key = DataKey::Escrow(escrow_id);
+    write_record(env, &escrow_key, &escrow);
+
+    // Store lookup mapping
+    write_record(env, &lookup_key, &escrow_id);
+    index_escrow(env, escrow_id, &escrow);
+    record_activity(env, escrow_id);
+
+    // Emit event
+    env.events().publish(
+        (symbol_short!("open"), client, server),
+        OpenedEvent { event_version: EVENT_VERSION, escrow_id },
+    );
+
+    Ok(escrow_id)
+}
+
+/// Record an authorized payment against its escrow
+///
+/// # Returns
+/// * Payment ID
+fn create(env: &Env, escrow_id: u64, mut escrow: Escrow, amount: i128) -> Result<u64, Error> {
+    // Check balance
+    if escrow.balance < amount {
+        return Err(Error::InsufficientBalance);
+    }
+    escrow.pending += amount;
+    escrow.pending_payments += 1;
+    write_record(env, &DataKey::Escrow(escrow_id), &escrow);
+
+    // Create payment record
+    let payment = Payment {
+        escrow_id,
+        amount,
+        settled: false,
+        timestamp: env.ledger().timestamp(),
+        quote_amount: None,
+        quote_currency: None,
+    };
+    let payment_id = record_payment(env, &payment);
+    record_activity(env, escrow_id);
+
+    // Emit event
+    env.events().publish(
+        (symbol_short!("pay"), escrow.server, escrow.client),
+        PaymentCreatedEvent { event_version: EVENT_VERSION, payment_id, amount },
+    );
+
+    Ok(payment_id)
+}
+
+/// Check a settlement's terms against its payment
+///
+/// # Returns
+/// * Server that must authorize the settlement
+fn settlement_server(env: &Env, settlement: &Settlement) -> Result<Address, Error> {
+    let payment = read_payment(env, settlement.payment_id).ok_or(Error::PaymentNotFound)?;
+    if payment.settled {
+        return Err(Error::PaymentAlreadySettled);
+    }
+    let escrow = read_escrow(env, payment.escrow_id)
+        .ok_or(Error::EscrowNotFound)?;
+
+    if payment.escrow_id != settlement.escrow_id
+        || payment.amount != settlement.amount
+        || escrow.client != settlement.client
+    {
+        return Err(Error::SettlementMismatch);
+    }
+    Ok(escrow.server)
+}
+
+/// Deduct an authorized payment from its escrow and mark it settled
+///
+/// # Returns
+/// * Amount settled
+fn settle(env: &Env, payment_id: u64) -> Result<i128, Error> {
+    let payment_key = DataKey::Payment(payment_id);
+    let mut payment = read_payment(env, payment_id).ok_or(Error::PaymentNotFound)?;
+    // A batch may list a payment twice
+    if payment.settled {
+        return Err(Error::PaymentAlreadySettled);
+    }
+
+    let escrow_key = DataKey::Escrow(payment.escrow_id);
+    let mut escrow = read_escrow(env, payment.escrow_id).ok_or(Error::EscrowNotFound)?;
+    require_not_frozen(env, payment.escrow_id)?;
+
+    // Payments only check the balance when created, and may outgrow it
+    if escrow.balance < payment.amount {
+        return Err(Error::InsufficientBalance);
+    }
+
+    // Deduct from escrow balance, spending trial funds first
+    escrow.balance -= payment.amount;
+    escrow.trial_balance = (escrow.trial_balance - payment.amount).max(0);
+    escrow.pending -= payment.amount;
+    escrow.pending_payments -= 1;
+    payment.settled = true;
+
+    // Quote the amount in USD, settling without a quote if the feed cannot
+    payment.quote_amount = amount_to_usd(env, payment.amount);
+    payment.quote_currency = payment.quote_amount.map(|_| symbol_short!("USD"));
+
+    // Save updated records
+    write_record(env, &escrow_key, &escrow);
+    write_record(env, &payment_key, &payment);
+    record_activity(env, payment.escrow_id);
+    add_quote(env, &payment);
+
+    env.events().publish(
+        (symbol_short!("settled"), payment_id),
+        PaymentSettledEvent {
+            event_version: EVENT_VERSION,
+            amount: payment.amount,
+            quote_amount: payment.quote_amount,
+            quote_currency: payment.quote_currency,
+        },
+    );
+    Ok(payment.amount)
+}
+
+/// Check `admin` against the admin set by `initialize`
+fn require_admin(env: &Env, admin: &Address) -> Result<(), Error> {
+    admin.require_auth();
+
+    match env.storage().instance().get::<_, Address>(&DataKey::Admin) {
+        Some(stored) if stored != *admin => Err(Error::Unauthorized),
+        Some(_) => Ok(()),
+        None => Err(Error::NotInitialized),
+    }
+}
+
+/// Fail if the escrow was exported for migration
+fn require_not_frozen(env: &Env, escrow_id: u64) -> Result<(), Error> {
+    if has_record(env, &DataKey::Migration(escrow_id)) {
+        return Err(Error::EscrowFrozen);
+    }
+    Ok(())
+}
+
+/// Count a new open escrow of `client`, failing if `enforce` and it holds
+/// as many as the admin allows
+fn take_escrow_slot(env: &Env, client: &Address, enforce: bool) -> Result<(), Error> {
+    let key = DataKey::ClientEscrowCount(client.clone());
+    let count: u32 = read_record(env, &key).unwrap_or(0);
+    if enforce {
+        if let Some(max) = env.storage().instance().get::<_, u32>(&DataKey::MaxEscrowsPerClient) {
+            if count >= max {
+                return Err(Error::TooManyEscrows);
+            }
+        }
+    }
+    write_record(env, &key, &(count + 1));
+    Ok(())
+}
+
+/// Count a settlement against its escrow's spend limit, if it has one
+///
+/// # Returns
+/// * false if the settlement breaches the limit, which pauses settlements
+///
+/// # Errors
+/// * `SettlementsPaused` - If settlements are paused already
+fn spend(env: &Env, escrow_id: u64, amount: i128) -> Result<bool, Error> {
+    let key = DataKey::SpendLimit(escrow_id);
+    let Some(mut limit) = read_record::<SpendLimit>(env, &key) else {
+        return Ok(true);
+    };
+    let now = env.ledger().timestamp();
+    if now >= limit.window_start.saturating_add(SPEND_WINDOW) {
+        limit.window_start = now;
+        limit.settled = 0;
+        limit.paused = false;
+    }
+    if limit.paused {
+        return Err(Error::SettlementsPaused);
+    }
+
+    let breached = limit.settled + amount > limit.max_settled_per_hour;
+    if breached {
+        limit.paused = true;
+        env.events().publish(
+            (symbol_short!("anomaly"), escrow_id),
+            AnomalyEvent {
+                event_version: EVENT_VERSION,
+                settled: limit.settled,
+                attempted: amount,
+                max_settled_per_hour: limit.max_settled_per_hour,
+            },
+        );
+    } else {
+        limit.settled += amount;
+    }
+    write_record(env, &key, &limit);
+    Ok(!breached)
+}
+
+/// Return the trial funds left in a removed escrow to its server
+///
+/// # Returns
+/// * Balance released to the client
+fn reclaim_trial(env: &Env, escrow_id: u64, escrow: &Escrow) -> i128 {
+    if escrow.trial_balance > 0 {
+        env.events().publish(
+            (symbol_short!("reclaim"), escrow_id),
+            ReclaimedEvent {
+                event_version: EVENT_VERSION,
+                server: escrow.server.clone(),
+                amount: escrow.trial_balance,
+            },
+        );
+    }
+    escrow.balance - escrow.trial_balance
+}
+
+/// Claim an unclaimed sponsored escrow for its client, counting it and
+/// recording its lookup unless the pair has another escrow
+///
+/// # Errors
+/// * `TooManyEscrows` - If the client holds as many open escrows as the
+///   admin allows
+fn claim_sponsored(env: &Env, escrow_id: u64, escrow: &Escrow) -> Result<(), Error> {
+    let unclaimed_key = DataKey::Unclaimed(escrow_id);
+    if !has_record(env, &unclaimed_key) {
+        return Ok(());
+    }
+    take_escrow_slot(env, &escrow.client, true)?;
+    let lookup_key = DataKey::ClientServerEscrow(escrow.client.clone(), escrow.server.clone());
+    if !has_record(env, &lookup_key) {
+        write_record(env, &lookup_key, &escrow_id);
+    }
+    remove_record(env, &unclaimed_key);
+    append_to_index(env, &escrow.client, false, escrow_id);
+    Ok(())
+}
+
+/// Key of a page of the escrow index of `client`
+fn index_page_key(client: &Address, archived: bool, page: u32) -> DataKey {
+    if archived {
+        DataKey::ClientArchivePage(client.clone(), page)
+    } else {
+        DataKey::ClientEscrowsPage(client.clone(), page)
+    }
+}
+
+/// Escrow index of `client`, without pages until its first escrow
+fn read_escrow_index(env: &Env, client: &Address) -> EscrowIndex {
+    read_record(env, &DataKey::EscrowIndex(client.clone())).unwrap_or(EscrowIndex {
+        live_pages: 0,
+        archived_pages: 0,
+    })
+}
+
+/// Append an escrow to the last live or archive page of the escrow index
+/// of `client`, starting a new page once it is full
+fn append_to_index(env: &Env, client: &Address, archived: bool, escrow_id: u64) {
+    let index_key = DataKey::EscrowIndex(client.clone());
+    let mut index = read_escrow_index(env, client);
+    let pages = if archived { &mut index.archived_pages } else { &mut index.live_pages };
+
+    let last: Option<Vec<u64>> = pages
+        .checked_sub(1)
+        .and_then(|last| read_record(env, &index_page_key(client, archived, last)));
+    let (page, mut ids) = match last {
+        Some(ids) if ids.len() < ESCROW_INDEX_PAGE => (*pages - 1, ids),
+        // Full, or emptied by a compaction
+        _ => (*pages, Vec::new(env)),
+    };
+    ids.push_back(escrow_id);
+    write_record(env, &index_page_key(client, archived, page), &ids);
+    *pages = page + 1;
+    write_record(env, &index_key, &index);
+}
+
+/// Index a new escrow under its client, once claimed
+fn index_escrow(env: &Env, escrow_id: u64, escrow: &Escrow) {
+    if !has_record(env, &DataKey::Unclaimed(escrow_id)) {
+        append_to_index(env, &escrow.client, false, escrow_id);
+    }
+}
+
+/// Archive a removed escrow in its client's index, if listed there
+///
+/// Called before its slot is released, while an unclaimed escrow is still
+/// known to be.
+fn archive_escrow(env: &Env, escrow_id: u64, escrow: &Escrow) {
+    if !has_record(env, &DataKey::Unclaimed(escrow_id)) {
+        append_to_index(env, &escrow.client, true, escrow_id);
+    }
+}
+
+/// Stop counting a removed escrow of `client`, unless it was never claimed
+fn release_escrow_slot(env: &Env, escrow_id: u64, client: &Address) {
+    let unclaimed_key = DataKey::Unclaimed(escrow_id);
+    if has_record(env, &unclaimed_key) {
+        remove_record(env, &unclaimed_key);
+        return;
+    }
+    let key = DataKey::ClientEscrowCount(client.clone());
+    match read_record::<u32>(env, &key) {
+        Some(count) if count > 1 => write_record(env, &key, &(count - 1)),
+        // Escrows opened before the count was kept are not in it
+        _ => remove_record(env, &key),
+    }
+}
+
+/// Whether `expires_at` passed longer ago than the clock skew tolerance
+fn expired(env: &Env, expires_at: u64) -> bool {
+    let skew: u64 = env.storage().instance().get(&DataKey::ClockSkew).unwrap_or(0);
+    env.ledger().timestamp() > expires_at.saturating_add(skew)
+}
+
+/// Spend the nonce of an authorization, keeping the latest expiry of those
+/// spent from its escrow
+#[cfg(feature = "signed-auth")]
+fn spend_authorization(env: &Env, authorization: &Authorization) {
+    let escrow_id = authorization.escrow_id;
+    write_record(env, &DataKey::UsedAuthorization(escrow_id, authorization.nonce), &true);
+    let key = DataKey::AuthorizedUntil(escrow_id);
+    let until: u64 = read_record(env, &key).unwrap_or(0);
+    if authorization.expires_at > until {
+        write_record(env, &key, &authorization.expires_at);
+    }
+}
+
+/// Allowance of an agent over the client's escrows, checked to cover a
+/// payment of `amount`
+///
+/// # Errors
+/// * `InvalidSigner` - If the agent holds no allowance from the client
+/// * `AllowanceExpired` - If the allowance has expired
+/// * `AllowanceExceeded` - If the amount is not positive or exceeds a cap
+#[cfg(feature = "signed-auth")]
+fn check_allowance(
+    env: &Env,
+    client: &Address,
+    agent: &Address,
+    amount: i128,
+) -> Result<Allowance, Error> {
+    let allowance: Allowance = read_record(env, &DataKey::Allowance(client.clone(), agent.clone()))
+        .ok_or(Error::InvalidSigner)?;
+    if expired(env, allowance.expires_at) {
+        return Err(Error::AllowanceExpired);
+    }
+    if amount <= 0 || amount > allowance.per_payment_cap || amount > allowance.remaining {
+        return Err(Error::AllowanceExceeded);
+    }
+    Ok(allowance)
+}
+
+/// Store a payment and index it under its escrow
+///
+/// # Returns
+/// * Payment ID
+fn record_payment(env: &Env, payment: &Payment) -> u64 {
+    // Numbered within its escrow, see the crate docs for the ID format
+    let count_key = DataKey::EscrowPaymentCount(payment.escrow_id);
+    let index: u32 = read_record(env, &count_key).unwrap_or(0);
+    let payment_id = free_payment_id(env, (payment.escrow_id << 32) | u64::from(index));
+
+    // Store payment
+    let payment_key = DataKey::Payment(payment_id);
+    write_record(env, &payment_key, payment);
+
+    // Index it under its escrow
+    write_record(env, &DataKey::EscrowPayment(payment.escrow_id, index), &payment_id);
+    write_record(env, &count_key, &(index + 1));
+
+    payment_id
+}
+
+/// Store a direct payment and index it under its server
+///
+/// # Returns
+/// * Payment ID
+fn record_direct(env: &Env, server: &Address, payment: &Payment) -> u64 {
+    // Numbered within its server, see the crate docs for the ID format
+    let count_key = DataKey::DirectPaymentCount(server.clone());
+    let index: u32 = read_record(env, &count_key).unwrap_or(0);
+    let hash = env.crypto().sha256(&server.clone().to_xdr(env)).to_array();
+    let prefix = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]) | (1 << 31);
+    let payment_id = free_payment_id(env, (u64::from(prefix) << 32) | u64::from(index));
+
+    write_record(env, &DataKey::Payment(payment_id), payment);
+    write_record(env, &DataKey::DirectPayment(server.clone(), index), &payment_id);
+    write_record(env, &count_key, &(index + 1));
+
+    payment_id
+}
+
+/// First payment ID from `payment_id` on that no payment holds
+fn free_payment_id(env: &Env, mut payment_id: u64) -> u64 {
+    while has_record(env, &DataKey::Payment(payment_id)) {
+        payment_id += 1;
+    }
+    payment_id
+}
+
+/// Page of the payments indexed by `index_key`, from `offset` on, see
+/// `get_payments`
+fn payment_page(
+    env: &Env,
+    count: u32,
+    status: Option<PaymentStatus>,
+    offset: u32,
+    limit: u32,
+    index_key: impl Fn(u32) -> DataKey,
+) -> PaymentPage {
+    let end = offset
+        .saturating_add(limit.clamp(1, MAX_PAYMENTS_PAGE))
+        .min(count);
+
+    let mut payments = Vec::new(env);
+    for index in offset..end {
+        let Some(payment_id) = read_record(env, &index_key(index)) else {
+            continue;
+        };
+        let Some(payment) = read_payment(env, payment_id) else {
+            continue;
+        };
+        let matches = match &status {
+            None => true,
+            Some(PaymentStatus::Pending) => !payment.settled,
+            Some(PaymentStatus::Settled) => payment.settled,
+            // Settlement cannot fail on-chain, it is retried off-chain
+            Some(PaymentStatus::Failed) => false,
+        };
+        if matches {
+            payments.push_back(PaymentEntry { payment_id, payment });
+        }
+    }
+
+    PaymentPage {
+        payments,
+        next_offset: (end < count).then_some(end),
+    }
+}
+
+/// Record activity on an escrow, for `sweep_dust`
+fn record_activity(env: &Env, escrow_id: u64) {
+    write_record(env, &DataKey::EscrowActivity(escrow_id), &env.ledger().timestamp());
+}
+
+/// Timestamp of the last activity on an escrow, that of its newest payment
+/// if none was recorded
+fn last_activity(env: &Env, escrow_id: u64) -> u64 {
+    if let Some(timestamp) = read_record(env, &DataKey::EscrowActivity(escrow_id)) {
+        return timestamp;
+    }
+    let count: u32 = read_record(env, &DataKey::EscrowPaymentCount(escrow_id)).unwrap_or(0);
+    count
+        .checked_sub(1)
+        .and_then(|index| read_record::<u64>(env, &DataKey::EscrowPayment(escrow_id, index)))
+        .and_then(|payment_id| read_payment(env, payment_id))
+        .map_or(0, |payment| payment.timestamp)
+}
+
+/// Sum and number of the unsettled payments of the escrow, looking at
+/// every payment it ever had
+fn count_pending(env: &Env, escrow_id: u64) -> (i128, u32) {
+    let count: u32 = read_record(env, &DataKey::EscrowPaymentCount(escrow_id)).unwrap_or(0);
+    (0..count)
+        .filter_map(|index| read_record::<u64>(env, &DataKey::EscrowPayment(escrow_id, index)))
+        .filter_map(|payment_id| read_payment(env, payment_id))
+        .filter(|payment| !payment.settled)
+        .fold((0, 0), |(total, payments), payment| (total + payment.amount, payments + 1))
+}
+
+/// Remove the client-server lookup of a removed escrow, unless it finds
+/// another escrow of the pair, e.g. the parent of a split escrow
+fn remove_lookup(env: &Env, escrow_id: u64, escrow: &Escrow) {
+    let lookup_key = DataKey::ClientServerEscrow(escrow.client.clone(), escrow.server.clone());
+    if read_record::<u64>(env, &lookup_key) == Some(escrow_id) {
+        remove_record(env, &lookup_key);
+    }
+}
+
+/// Allocate the ID of a new escrow of `client`, counted in the client's
+/// shard, see the crate docs for the ID format
+fn allocate_escrow_id(env: &Env, client: &Address) -> u64 {
+    let hash = env.crypto().sha256(&client.clone().to_xdr(env)).to_array();
+    let shard = u32::from(hash[0]) % ESCROW_SHARDS;
+    let key = DataKey::EscrowShardCount(shard);
+    let count: u64 = read_record(env, &key).unwrap_or(0);
+    write_record(env, &key, &(count + 1));
+    legacy_escrow_count(env) + count * u64::from(ESCROW_SHARDS) + u64::from(shard)
+}
+
+/// Escrows opened by the former global counter, whose IDs precede the
+/// sharded ones
+fn legacy_escrow_count(env: &Env) -> u64 {
+    env.storage()
+        .instance()
+        .get(&DataKey::EscrowCounter)
+        .unwrap_or(0)
+}
+
+/// Read a record of an escrow, payment, or client
+///
+/// Records live in persistent storage, one ledger entry each, so that
+/// unrelated escrows never write the same entry. Records written while
+/// they lived in instance storage are read from there until rewritten.
+fn read_record<V: TryFromVal<Env, Val>>(env: &Env, key: &DataKey) -> Option<V> {
+    env.storage()
+        .persistent()
+        .get(key)
+        .or_else(|| env.storage().instance().get(key))
+}
+
+/// Read an escrow, recorded with or without its terms of service, trial
+/// and unsettled payment total
+fn read_escrow(env: &Env, escrow_id: u64) -> Option<Escrow> {
+    let value: Val = read_record(env, &DataKey::Escrow(escrow_id))?;
+    let fields = Map::<Symbol, Val>::try_from_val(env, &value).ok()?;
+    if fields.contains_key(Symbol::new(env, "pending")) {
+        return Escrow::try_from_val(env, &value).ok();
+    }
+
+    // Totalled from its payments until next written
+    let (pending, pending_payments) = count_pending(env, escrow_id);
+    if fields.contains_key(Symbol::new(env, "trial")) {
+        let escrow = TrialEscrow::try_from_val(env, &value).ok()?;
+        return Some(Escrow {
+            client: escrow.client,
+            server: escrow.server,
+            balance: escrow.balance,
+            client_closed: escrow.client_closed,
+            server_closed: escrow.server_closed,
+            terms_hash: escrow.terms_hash,
+            trial: escrow.trial,
+            trial_balance: escrow.trial_balance,
+            pending,
+            pending_payments,
+        });
+    }
+    if fields.contains_key(Symbol::new(env, "terms_hash")) {
+        let escrow = TermsEscrow::try_from_val(env, &value).ok()?;
+        return Some(Escrow {
+            client: escrow.client,
+            server: escrow.server,
+            balance: escrow.balance,
+            client_closed: escrow.client_closed,
+            server_closed: escrow.server_closed,
+            terms_hash: escrow.terms_hash,
+            trial: false,
+            trial_balance: 0,
+            pending,
+            pending_payments,
+        });
+    }
+    let legacy = LegacyEscrow::try_from_val(env, &value).ok()?;
+    Some(Escrow {
+        client: legacy.client,
+        server: legacy.server,
+        balance: legacy.balance,
+        client_closed: legacy.client_closed,
+        server_closed: legacy.server_closed,
+        terms_hash: None,
+        trial: false,
+        trial_balance: 0,
+        pending,
+        pending_payments,
+    })
+}
+
+/// Read a payment, recorded with or without its quote
+fn read_payment(env: &Env, payment_id: u64) -> Option<Payment> {
+    let value: Val = read_record(env, &DataKey::Payment(payment_id))?;
+    let fields = Map::<Symbol, Val>::try_from_val(env, &value).ok()?;
+    if fields.contains_key(Symbol::new(env, "quote_amount")) {
+        return Payment::try_from_val(env, &value).ok();
+    }
+    let legacy = LegacyPayment::try_from_val(env, &value).ok()?;
+    Some(Payment {
+        escrow_id: legacy.escrow_id,
+        amount: legacy.amount,
+        settled: legacy.settled,
+        timestamp: legacy.timestamp,
+        quote_amount: None,
+        quote_currency: None,
+    })
+}
+
+/// Add a settled payment to the quote total of its escrow
+fn add_quote(env: &Env, payment: &Payment) {
+    let key = DataKey::QuoteTotal(payment.escrow_id);
+    let mut total = X402EscrowContract::get_quote_total(env.clone(), payment.escrow_id);
+    match payment.quote_amount {
+        Some(quote) => {
+            total.quote_amount += quote;
+            total.quoted += payment.amount;
+        }
+        None => total.unquoted += payment.amount,
+    }
+    write_record(env, &key, &total);
+}
+
+/// Whether a record exists, see [`read_record`]
+fn has_record(env: &Env, key: &DataKey) -> bool {
+    env.storage().persistent().has(key) || env.storage().instance().has(key)
+}
+
+/// Write a record to persistent storage, dropping its legacy instance copy
+fn write_record<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+    env.storage().persistent().set(key, value);
+    if env.storage().instance().has(key) {
+        env.storage().instance().remove(key);
+    }
+}
+
+/// Remove a record, and its legacy instance copy
+fn remove_record(env: &Env, key: &DataKey) {
+    env.storage().persistent().remove(key);
+    if env.storage().instance().has(key) {
+        env.storage().instance().remove(key);
+    }
+}
+
+/// USD price of a server's resource now, a running promo taking precedence
+/// over the regular price
+#[cfg(feature = "oracle")]
+fn usd_price(env: &Env, server: Address, resource: String) -> Option<i128> {
+    let now = env.ledger().timestamp();
+    let promos: Vec<Promo> = read_record(env, &DataKey::Promos(server.clone(), resource.clone()))
+        .unwrap_or(Vec::new(env));
+    // Latest wins where promos overlap
+    promos
+        .iter()
+        .rev()
+        .find(|promo| promo.starts_at <= now && now < promo.ends_at)
+        .map(|promo| promo.usd_cents)
+        .or_else(|| read_record(env, &DataKey::UsdPrice(server, resource)))
+}
+
+/// Whether the server suspended the sale of a resource
+#[cfg(feature = "oracle")]
+fn is_suspended(env: &Env, server: Address, resource: String) -> bool {
+    has_record(env, &DataKey::Suspended(server, resource))
+}
+
+/// Convert USD cents to escrow amount at the oracle's latest price
+#[cfg(feature = "oracle")]
+fn usd_to_amount(env: &Env, usd_cents: i128) -> Result<i128, Error> {
+    let (price, decimals) = oracle_price(env)?;
+
+    // amount = usd_cents / 100 / (price / 10^decimals) * 10^AMOUNT_DECIMALS
+    let scale = 10i128
+        .checked_pow(decimals + AMOUNT_DECIMALS)
+        .ok_or(Error::PriceUnavailable)?;
+    let numerator = usd_cents
+        .checked_mul(scale)
+        .ok_or(Error::PriceUnavailable)?;
+    let denominator = price * 100;
+
+    // Round up so the server is never paid less than the USD price
+    Ok((numerator + denominator - 1) / denominator)
+}
+
+/// Convert escrow amount to USD cents at the oracle's latest price,
+/// rounded down
+///
+/// # Returns
+/// * None if no price feed is configured or it has no usable price
+#[cfg(feature = "oracle")]
+fn amount_to_usd(env: &Env, amount: i128) -> Option<i128> {
+    let (price, decimals) = oracle_price(env).ok()?;
+
+    // usd_cents = amount / 10^AMOUNT_DECIMALS * price / 10^decimals * 100
+    let scale = 10i128.checked_pow(decimals + AMOUNT_DECIMALS)?;
+    amount.checked_mul(price)?.checked_mul(100)?.checked_div(scale)
+}
+
+/// Without the price feed, payments settle unquoted
+#[cfg(not(feature = "oracle"))]
+fn amount_to_usd(_env: &Env, _amount: i128) -> Option<i128> {
+    None
+}
+
+/// Latest USD price of the escrow asset and the decimals it is scaled by,
+/// checked against the configured limits
+///
+/// A failing price feed is reported as `PriceUnavailable` rather than
+/// aborting the call.
+#[cfg(feature = "oracle")]
+fn oracle_price(env: &Env) -> Result<(i128, u32), Error> {
+    let config: OracleConfig = env
+        .storage()
+        .instance()
+        .get(&DataKey::Oracle)
+        .ok_or(Error::OracleNotSet)?;
+    let oracle = PriceOracleClient::new(env, &config.oracle);
+
+    let last = oracle
+        .try_lastprice(&config.asset)
+        .ok()
+        .and_then(Result::ok)
+        .flatten()
+        .ok_or(Error::PriceUnavailable)?;
+    if last.price <= 0 {
+        return Err(Error::PriceUnavailable);
+    }
+    if env.ledger().timestamp().saturating_sub(last.timestamp) > config.max_age {
+        return Err(Error::StalePrice);
+    }
+
+    if config.max_slippage_bps > 0 {
+        let twap = oracle
+            .try_twap(&config.asset, &TWAP_RECORDS)
+            .ok()
+            .and_then(Result::ok)
+            .flatten()
+            .ok_or(Error::PriceUnavailable)?;
+        if twap <= 0 {
+            return Err(Error::PriceUnavailable);
+        }
+        let deviation_bps = (last.price - twap).abs() * 10_000 / twap;
+        if deviation_bps > config.max_slippage_bps as i128 {
+            return Err(Error::PriceSlippage);
+        }
+    }
+
+    let decimals = oracle
+        .try_decimals()
+        .ok()
+        .and_then(Result::ok)
+        .ok_or(Error::PriceUnavailable)?;
+    Ok((last.price, decimals))
+}
+
+#[cfg(test)]
+extern crate std;
 
 mod test;
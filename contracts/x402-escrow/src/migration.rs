@@ -0,0 +1,67 @@
+//! Moving escrows between deployments of the contract
+
+use soroban_sdk::{contractclient, contracttype, xdr::ToXdr, Address, BytesN, Env, Vec};
+
+use crate::{Error, Escrow, EscrowLinks, Note, Payment, SpendLimit};
+
+/// Consent of the escrow parties to move it to another deployment
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MigrationConsent {
+    /// Contract the escrow moves to
+    pub target: Address,
+    pub client: bool,
+    pub server: bool,
+}
+
+/// Escrow as exported by the deployment it leaves
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MigrationSummary {
+    /// Contract the escrow was exported by
+    pub source: Address,
+    /// Contract the escrow moves to
+    pub target: Address,
+    /// Escrow ID at the source
+    pub escrow_id: u64,
+    pub escrow: Escrow,
+    /// Unsettled payments, oldest first
+    pub pending: Vec<Payment>,
+    /// Payments the escrow had at the source, settled or not
+    pub payment_count: u32,
+    /// Spend limit, with its current window
+    pub spend_limit: Option<SpendLimit>,
+    /// Escrows it was split from and into, by their IDs at the source
+    pub links: EscrowLinks,
+    pub notes: Vec<Note>,
+    /// Ledger timestamp of the export
+    pub exported_at: u64,
+}
+
+/// Export of a frozen escrow
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MigrationExport {
+    pub summary: MigrationSummary,
+    /// SHA-256 of the summary's XDR, kept by the source to check the import
+    pub proof: BytesN<32>,
+}
+
+impl MigrationExport {
+    pub fn new(env: &Env, summary: MigrationSummary) -> Self {
+        let proof = digest(env, &summary);
+        Self { summary, proof }
+    }
+}
+
+/// SHA-256 of the XDR of a summary
+pub fn digest(env: &Env, summary: &MigrationSummary) -> BytesN<32> {
+    env.crypto().sha256(&summary.clone().to_xdr(env)).to_bytes()
+}
+
+/// Interface the importing deployment closes the exported escrow through
+#[contractclient(name = "MigrationSourceClient")]
+pub trait MigrationSource {
+    /// Close an exported escrow, once imported with the export's proof
+    fn complete_migration(env: Env, escrow_id: u64, proof: BytesN<32>) -> Result<(), Error>;
+}
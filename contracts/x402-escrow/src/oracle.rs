@@ -0,0 +1,50 @@
+//! SEP-40 price feed interface, as implemented by Reflector
+
+use soroban_sdk::{contractclient, contracttype, Address, Env, Symbol};
+
+/// Asset quoted by a price feed
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Asset {
+    /// Stellar asset contract
+    Stellar(Address),
+    /// Off-chain asset by ticker (e.g., "XLM")
+    Other(Symbol),
+}
+
+/// Price record of a price feed
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PriceData {
+    /// USD price of one whole unit, scaled by the feed's decimals
+    pub price: i128,
+    /// Unix timestamp of the record
+    pub timestamp: u64,
+}
+
+/// Subset of the SEP-40 price feed interface used for USD pricing
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    /// Decimals of the prices returned
+    fn decimals(env: Env) -> u32;
+
+    /// Most recent price of `asset`
+    fn lastprice(env: Env, asset: Asset) -> Option<PriceData>;
+
+    /// Time-weighted average price of `asset` over the last `records`
+    fn twap(env: Env, asset: Asset, records: u32) -> Option<i128>;
+}
+
+/// Price feed settings of the escrow contract
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OracleConfig {
+    /// SEP-40 price feed contract
+    pub oracle: Address,
+    /// Asset escrow balances are denominated in
+    pub asset: Asset,
+    /// Age in seconds after which a price is stale
+    pub max_age: u64,
+    /// Largest deviation of the last price from the TWAP, in basis points
+    pub max_slippage_bps: u32,
+}
@@ -0,0 +1,181 @@
+//! ed25519 signatures of off-chain messages, encoded by the canonical
+//! encoders of `x402-types` the SDK signs with
+
+use soroban_sdk::{contracttype, Address, Bytes, BytesN, Env, String};
+use x402_types::{
+    account_strkey, network_passphrase, Decimal, EscrowAuthorization, EscrowChannelState,
+    EscrowVoucher, SigningDomain, SliceSink, MAX_MESSAGE_LEN, STRKEY_LEN,
+};
+
+use crate::Error;
+
+/// Longest x402 network id an authorization may name, so its message fits
+/// `MAX_MESSAGE_LEN`
+const MAX_NETWORK_LEN: usize = 32;
+
+/// Payment authorization signed off-chain by an escrow's client
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Authorization {
+    pub escrow_id: u64,
+    /// x402 network id (e.g., "stellar-testnet") the authorization is for
+    pub network: String,
+    pub amount: i128,
+    pub nonce: u64,
+    pub expires_at: u64,
+}
+
+/// Running total of payments signed off-chain by an escrow's client
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Voucher {
+    pub escrow_id: u64,
+    /// Total amount authorized so far
+    pub amount: i128,
+    /// Number of the voucher, superseding those with lower numbers
+    pub sequence: u64,
+    pub expires_at: u64,
+}
+
+/// State of an escrow signed off-chain by one of its parties
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChannelState {
+    pub escrow_id: u64,
+    /// Number of the state, superseding those with lower numbers
+    pub sequence: u64,
+    /// Balance left to the client
+    pub balance: i128,
+    /// Total paid to the server
+    pub settled: i128,
+}
+
+/// Account of an ed25519 public key
+pub(crate) fn signer(env: &Env, public_key: &BytesN<32>) -> Address {
+    let strkey = account_strkey(&public_key.to_array());
+    Address::from_string_bytes(&Bytes::from_slice(env, &strkey))
+}
+
+/// Verify the signature of an authorization by `public_key`, bound to this
+/// contract
+///
+/// # Errors
+/// * `NetworkMismatch` - If the authorization names another network
+pub(crate) fn verify_authorization(
+    env: &Env,
+    authorization: &Authorization,
+    public_key: &BytesN<32>,
+    signature: &BytesN<64>,
+) -> Result<(), Error> {
+    let mut network = [0; MAX_NETWORK_LEN];
+    let network = network_name(env, &authorization.network, &mut network)?;
+    let amount = Decimal::new(authorization.amount);
+    let contract_id = contract_strkey(env);
+    let message = EscrowAuthorization {
+        domain: domain(env, &contract_id),
+        network,
+        escrow_id: authorization.escrow_id,
+        amount: amount.as_str(),
+        nonce: authorization.nonce,
+        expires_at: authorization.expires_at,
+    };
+    verify(env, public_key, signature, |sink| message.encode(sink));
+    Ok(())
+}
+
+/// Verify the signature of a voucher by `public_key`
+pub(crate) fn verify_voucher(
+    env: &Env,
+    voucher: &Voucher,
+    public_key: &BytesN<32>,
+    signature: &BytesN<64>,
+) {
+    let contract_id = contract_strkey(env);
+    let message = EscrowVoucher {
+        domain: domain(env, &contract_id),
+        escrow_id: voucher.escrow_id,
+        amount: voucher.amount,
+        sequence: voucher.sequence,
+        expires_at: voucher.expires_at,
+    };
+    verify(env, public_key, signature, |sink| message.encode(sink));
+}
+
+/// Verify the signature of a channel state by `public_key`
+pub(crate) fn verify_channel_state(
+    env: &Env,
+    state: &ChannelState,
+    public_key: &BytesN<32>,
+    signature: &BytesN<64>,
+) {
+    let contract_id = contract_strkey(env);
+    let message = EscrowChannelState {
+        domain: domain(env, &contract_id),
+        escrow_id: state.escrow_id,
+        sequence: state.sequence,
+        balance: state.balance,
+        settled: state.settled,
+    };
+    verify(env, public_key, signature, |sink| message.encode(sink));
+}
+
+/// Verify the signature of the SHA-256 of the message `encode` writes
+///
+/// # Panics
+/// * If the signature is invalid, as the host's ed25519 verification does
+fn verify(
+    env: &Env,
+    public_key: &BytesN<32>,
+    signature: &BytesN<64>,
+    encode: impl FnOnce(&mut SliceSink),
+) {
+    let mut buf = [0; MAX_MESSAGE_LEN];
+    let mut sink = SliceSink::new(&mut buf);
+    encode(&mut sink);
+    let message = sink.written().expect("message fits the buffer");
+    let hash = env.crypto().sha256(&Bytes::from_slice(env, message));
+    env.crypto()
+        .ed25519_verify(public_key, &hash.to_bytes().into(), signature);
+}
+
+/// Domain binding a message to this network and contract
+fn domain<'a>(env: &Env, contract_id: &'a [u8; STRKEY_LEN]) -> SigningDomain<'a> {
+    SigningDomain {
+        network_id: env.ledger().network_id().to_array(),
+        // Strkeys are ASCII
+        contract_id: core::str::from_utf8(contract_id).unwrap_or_default(),
+    }
+}
+
+/// Strkey of this contract (C... format)
+fn contract_strkey(env: &Env) -> [u8; STRKEY_LEN] {
+    let mut strkey = [0; STRKEY_LEN];
+    env.current_contract_address()
+        .to_string()
+        .copy_into_slice(&mut strkey);
+    strkey
+}
+
+/// Name of a network, checked to be the one the contract runs on
+///
+/// # Errors
+/// * `NetworkMismatch` - If the network is unknown or another one
+fn network_name<'a>(
+    env: &Env,
+    network: &String,
+    buf: &'a mut [u8; MAX_NETWORK_LEN],
+) -> Result<&'a str, Error> {
+    let name = buf
+        .get_mut(..network.len() as usize)
+        .ok_or(Error::NetworkMismatch)?;
+    network.copy_into_slice(name);
+    let name = core::str::from_utf8(name).map_err(|_| Error::NetworkMismatch)?;
+    let passphrase = network_passphrase(name).ok_or(Error::NetworkMismatch)?;
+    let network_id = env
+        .crypto()
+        .sha256(&Bytes::from_slice(env, passphrase.as_bytes()));
+    if network_id.to_bytes() != env.ledger().network_id() {
+        return Err(Error::NetworkMismatch);
+    }
+    Ok(name)
+}
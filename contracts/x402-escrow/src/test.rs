@@ -1,7 +1,30 @@
 #![cfg(test)]
 
-use crate::{X402EscrowContract, X402EscrowContractClient};
-use soroban_sdk::{testutils::Address as _, Address, Env};
+use crate::{
+    AnomalyEvent, ClonedEvent, ClosedEvent, DataKey, DirectPaymentEvent, Error, Escrow,
+    LegacyEscrow, LegacyPayment, Note, NoteEvent, NotificationUrlEvent, OpenedEvent, Payment,
+    PaymentCreatedEvent, PaymentIntent, PaymentRefundedEvent, PaymentSettledEvent, PaymentStatus,
+    ReclaimedEvent, Settlement, SplitEvent, SponsoredEvent, SweptEvent, TermsEscrow,
+    TermsUpdatedEvent, X402EscrowContract, X402EscrowContractClient, DIRECT_PAYMENT_ESCROW,
+    ESCROW_INDEX_PAGE, ESCROW_SHARDS, EVENT_VERSION, MAX_COMPACT_PAGES, MAX_ESCROWS_PAGE, MAX_NOTES,
+    MAX_NOTE_LEN, MAX_NOTIFY_URL_LEN, MAX_PAYMENTS_PAGE, MAX_SPLIT_METADATA_LEN, MIN_DUST_IDLE,
+    SPEND_WINDOW,
+};
+#[cfg(feature = "oracle")]
+use crate::{Asset, PriceData, Promo, Quote, QuoteTotal, DEFAULT_MAX_PRICE_AGE, MAX_PROMOS};
+#[cfg(feature = "signed-auth")]
+use crate::{Allowance, Authorization, ChannelState, Voucher};
+use soroban_sdk::{
+    symbol_short,
+    testutils::{Address as _, Events as _, Ledger as _},
+    vec,
+    xdr::LedgerKey,
+    token, Address, Bytes, BytesN, Env, IntoVal, Vec,
+};
+#[cfg(feature = "oracle")]
+use soroban_sdk::{contract, contractimpl};
+#[cfg(any(feature = "oracle", feature = "signed-auth"))]
+use soroban_sdk::String;
 
 #[test]
 fn test_open_escrow() {
@@ -15,17 +38,17 @@ fn test_open_escrow() {
     let server_addr = Address::generate(&env);
     let amount: i128 = 1_000_000; // 0.1 XLM in stroops
 
-    // Open escrow
-    let escrow_id = client.open_escrow(&client_addr, &server_addr, &amount);
-    assert_eq!(escrow_id, 0);
+    // Open escrow, the first of its shard
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &amount, &None);
+    assert!(escrow_id < ESCROW_SHARDS.into());
 
     // Verify escrow was created
     let escrow = client.get_escrow(&escrow_id);
     assert_eq!(escrow.client, client_addr);
     assert_eq!(escrow.server, server_addr);
     assert_eq!(escrow.balance, amount);
-    assert_eq!(escrow.client_closed, false);
-    assert_eq!(escrow.server_closed, false);
+    assert!(!escrow.client_closed);
+    assert!(!escrow.server_closed);
 
     // Verify balance
     let balance = client.get_escrow_balance(&escrow_id);
@@ -45,7 +68,7 @@ fn test_find_escrow() {
     let amount: i128 = 1_000_000;
 
     // Open escrow
-    let escrow_id = client.open_escrow(&client_addr, &server_addr, &amount);
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &amount, &None);
 
     // Find escrow
     let found_id = client.find_escrow(&client_addr, &server_addr);
@@ -57,6 +80,34 @@ fn test_find_escrow() {
     assert_eq!(not_found, None);
 }
 
+/// Settlement terms of a payment as its server sees them, made up for a
+/// payment or escrow that does not exist
+fn terms(env: &Env, client: &X402EscrowContractClient, payment_id: u64) -> Settlement {
+    let payment = client
+        .try_get_payment(&payment_id)
+        .ok()
+        .and_then(Result::ok);
+    let escrow = payment
+        .as_ref()
+        .and_then(|payment| client.try_get_escrow(&payment.escrow_id).ok())
+        .and_then(Result::ok);
+    Settlement {
+        payment_id,
+        escrow_id: payment.as_ref().map_or(0, |payment| payment.escrow_id),
+        amount: payment.map_or(0, |payment| payment.amount),
+        client: escrow.map_or_else(|| Address::generate(env), |escrow| escrow.client),
+    }
+}
+
+/// Settlement terms of several payments, in order
+fn batch(env: &Env, client: &X402EscrowContractClient, payment_ids: &[u64]) -> Vec<Settlement> {
+    let mut settlements = Vec::new(env);
+    for payment_id in payment_ids {
+        settlements.push_back(terms(env, client, *payment_id));
+    }
+    settlements
+}
+
 #[test]
 fn test_create_and_settle_payment() {
     let env = Env::default();
@@ -71,33 +122,37 @@ fn test_create_and_settle_payment() {
     let payment_amount: i128 = 1_000_000; // 0.1 XLM
 
     // Open escrow
-    let escrow_id = client.open_escrow(&client_addr, &server_addr, &escrow_amount);
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &escrow_amount, &None);
 
     // Create payment
     let payment_id = client.create_payment(&escrow_id, &payment_amount);
-    assert_eq!(payment_id, 0);
+    assert_eq!(payment_id, escrow_id << 32);
 
     // Verify payment was created but not settled
     let payment = client.get_payment(&payment_id);
     assert_eq!(payment.escrow_id, escrow_id);
     assert_eq!(payment.amount, payment_amount);
-    assert_eq!(payment.settled, false);
+    assert!(!payment.settled);
 
     // Balance should still be the same (payment not settled yet)
     let balance_before = client.get_escrow_balance(&escrow_id);
     assert_eq!(balance_before, escrow_amount);
+    let escrow = client.get_escrow(&escrow_id);
+    assert_eq!((escrow.pending, escrow.pending_payments), (payment_amount, 1));
 
     // Settle payment
-    let settled = client.settle_payment(&payment_id);
-    assert_eq!(settled, true);
+    let settled = client.settle_payment(&terms(&env, &client, payment_id));
+    assert!(settled);
 
     // Verify payment is now settled
     let payment_after = client.get_payment(&payment_id);
-    assert_eq!(payment_after.settled, true);
+    assert!(payment_after.settled);
 
     // Balance should be reduced
     let balance_after = client.get_escrow_balance(&escrow_id);
     assert_eq!(balance_after, escrow_amount - payment_amount);
+    let escrow = client.get_escrow(&escrow_id);
+    assert_eq!((escrow.pending, escrow.pending_payments), (0, 0));
 }
 
 #[test]
@@ -114,16 +169,189 @@ fn test_deposit() {
     let deposit_amount: i128 = 2_000_000; // 0.2 XLM
 
     // Open escrow
-    let escrow_id = client.open_escrow(&client_addr, &server_addr, &initial_amount);
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &initial_amount, &None);
 
     // Deposit additional funds
     client.deposit(&escrow_id, &deposit_amount);
+    assert_eq!(
+        client.try_deposit(&escrow_id, &-deposit_amount),
+        Err(Ok(Error::InvalidAmount))
+    );
 
     // Verify balance increased
     let balance = client.get_escrow_balance(&escrow_id);
     assert_eq!(balance, initial_amount + deposit_amount);
 }
 
+#[test]
+fn test_claim_pending_deposit() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+
+    let client_addr = Address::generate(&env);
+    let server_addr = Address::generate(&env);
+    let custodian = Address::generate(&env);
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &1_000_000, &None);
+    let memo_hash = BytesN::from_array(&env, &[7; 32]);
+
+    assert!(!client.is_deposit_claimed(&memo_hash));
+    client.claim_pending_deposit(&escrow_id, &custodian, &400_000, &memo_hash);
+    assert_eq!(
+        env.auths()[0].0,
+        server_addr,
+        "the server vouches for the transfer"
+    );
+    assert!(client.is_deposit_claimed(&memo_hash));
+    assert_eq!(client.get_escrow_balance(&escrow_id), 1_400_000);
+
+    // The same transfer cannot be credited twice, to this escrow or another
+    assert_eq!(
+        client.try_claim_pending_deposit(&escrow_id, &custodian, &400_000, &memo_hash),
+        Err(Ok(Error::DepositAlreadyClaimed))
+    );
+    let other_id = client.open_escrow(&Address::generate(&env), &server_addr, &0, &None);
+    assert_eq!(
+        client.try_claim_pending_deposit(&other_id, &custodian, &400_000, &memo_hash),
+        Err(Ok(Error::DepositAlreadyClaimed))
+    );
+    assert_eq!(client.get_escrow_balance(&escrow_id), 1_400_000);
+    assert_eq!(client.get_escrow_balance(&other_id), 0);
+
+    // Another transfer is credited
+    let next_hash = BytesN::from_array(&env, &[8; 32]);
+    client.claim_pending_deposit(&escrow_id, &custodian, &100_000, &next_hash);
+    assert_eq!(client.get_escrow_balance(&escrow_id), 1_500_000);
+
+    // A claim cannot take funds out, nor mark a transfer credited
+    let refund_hash = BytesN::from_array(&env, &[10; 32]);
+    for amount in [-100_000, 0] {
+        assert_eq!(
+            client.try_claim_pending_deposit(&escrow_id, &custodian, &amount, &refund_hash),
+            Err(Ok(Error::InvalidAmount))
+        );
+    }
+    assert!(!client.is_deposit_claimed(&refund_hash));
+    assert_eq!(client.get_escrow_balance(&escrow_id), 1_500_000);
+
+    assert_eq!(
+        client.try_claim_pending_deposit(&99, &custodian, &1, &BytesN::from_array(&env, &[9; 32])),
+        Err(Ok(Error::EscrowNotFound))
+    );
+}
+
+#[test]
+fn test_record_direct_payment() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let client_addr = Address::generate(&env);
+    let server_addr = Address::generate(&env);
+    let token_id = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+    token::StellarAssetClient::new(&env, &token_id).mint(&client_addr, &1_000_000);
+    let token = token::Client::new(&env, &token_id);
+    let reference = BytesN::from_array(&env, &[7; 32]);
+
+    // Transferred at once, by the client
+    let payment_id =
+        client.record_direct_payment(&client_addr, &server_addr, &token_id, &300_000, &reference);
+    assert_eq!(env.auths()[0].0, client_addr, "the client pays");
+    assert_eq!(token.balance(&client_addr), 700_000);
+    assert_eq!(token.balance(&server_addr), 300_000);
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(
+        topics,
+        (symbol_short!("direct"), client_addr.clone(), server_addr.clone()).into_val(&env)
+    );
+    let event: DirectPaymentEvent = data.into_val(&env);
+    assert_eq!(
+        event,
+        DirectPaymentEvent {
+            event_version: EVENT_VERSION,
+            payment_id,
+            token: token_id.clone(),
+            amount: 300_000,
+            reference: reference.clone(),
+        }
+    );
+
+    // Recorded as settled, listed with the server's other direct payments
+    let payment = client.get_payment(&payment_id);
+    assert_eq!(payment.escrow_id, DIRECT_PAYMENT_ESCROW);
+    assert_eq!(payment.amount, 300_000);
+    assert!(payment.settled);
+    assert_eq!(payment_id >> 63, 1, "direct payment IDs are apart");
+    let page = client.get_direct_payments(&server_addr, &None, &0, &10);
+    assert_eq!(page.payments.len(), 1);
+    assert_eq!(page.payments.get(0).unwrap().payment_id, payment_id);
+    assert!(client.get_payments(&DIRECT_PAYMENT_ESCROW, &None, &0, &10).payments.is_empty());
+    assert_eq!(
+        client.try_settle_payment(&Settlement {
+            payment_id,
+            escrow_id: DIRECT_PAYMENT_ESCROW,
+            amount: 300_000,
+            client: client_addr.clone(),
+        }),
+        Err(Ok(Error::PaymentAlreadySettled))
+    );
+
+    // Each reference once, and only positive amounts
+    assert_eq!(
+        client.try_record_direct_payment(&client_addr, &server_addr, &token_id, &1, &reference),
+        Err(Ok(Error::InvalidDirectPayment))
+    );
+    let next = BytesN::from_array(&env, &[8; 32]);
+    assert_eq!(
+        client.try_record_direct_payment(&client_addr, &server_addr, &token_id, &0, &next),
+        Err(Ok(Error::InvalidDirectPayment))
+    );
+    let second =
+        client.record_direct_payment(&client_addr, &server_addr, &token_id, &200_000, &next);
+    assert_ne!(second, payment_id);
+    assert_eq!(token.balance(&server_addr), 500_000);
+
+    // A transfer the client cannot fund records nothing
+    let last = BytesN::from_array(&env, &[9; 32]);
+    assert!(client
+        .try_record_direct_payment(&client_addr, &server_addr, &token_id, &600_000, &last)
+        .is_err());
+    let page = client.get_direct_payments(&server_addr, &Some(PaymentStatus::Settled), &0, &1);
+    assert_eq!(page.payments.len(), 1);
+    assert_eq!(page.next_offset, Some(1));
+    let page = client.get_direct_payments(&server_addr, &None, &1, &10);
+    assert_eq!(page.payments.get(0).unwrap().payment_id, second);
+    assert_eq!(page.next_offset, None);
+
+    // Each server numbers its own
+    let other_server = Address::generate(&env);
+    let other = BytesN::from_array(&env, &[10; 32]);
+    let third =
+        client.record_direct_payment(&client_addr, &other_server, &token_id, &100_000, &other);
+    assert_eq!(third & u64::from(u32::MAX), 0);
+    assert_eq!(client.get_direct_payments(&other_server, &None, &0, &10).payments.len(), 1);
+    assert_eq!(client.get_direct_payments(&server_addr, &None, &0, &10).payments.len(), 2);
+
+    // References are the client's own: another client recording one first
+    // does not keep the client from recording it
+    let (first_client, second_client) = (Address::generate(&env), Address::generate(&env));
+    let shared = BytesN::from_array(&env, &[11; 32]);
+    token::StellarAssetClient::new(&env, &token_id).mint(&first_client, &1_000);
+    token::StellarAssetClient::new(&env, &token_id).mint(&second_client, &1_000);
+    client.record_direct_payment(&second_client, &server_addr, &token_id, &1, &shared);
+    client.record_direct_payment(&first_client, &server_addr, &token_id, &1_000, &shared);
+    assert_eq!(token.balance(&first_client), 0);
+    assert_eq!(
+        client.try_record_direct_payment(&second_client, &server_addr, &token_id, &1, &shared),
+        Err(Ok(Error::InvalidDirectPayment))
+    );
+}
+
 #[test]
 fn test_escrow_closure() {
     let env = Env::default();
@@ -137,7 +365,7 @@ fn test_escrow_closure() {
     let amount: i128 = 3_000_000;
 
     // Open escrow
-    let escrow_id = client.open_escrow(&client_addr, &server_addr, &amount);
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &amount, &None);
 
     // Client closes first - should return None
     let result1 = client.client_close_escrow(&escrow_id);
@@ -145,8 +373,8 @@ fn test_escrow_closure() {
 
     // Escrow should still exist
     let escrow = client.get_escrow(&escrow_id);
-    assert_eq!(escrow.client_closed, true);
-    assert_eq!(escrow.server_closed, false);
+    assert!(escrow.client_closed);
+    assert!(!escrow.server_closed);
 
     // Server closes - should return remaining balance
     let result2 = client.server_close_escrow(&escrow_id);
@@ -158,7 +386,147 @@ fn test_escrow_closure() {
 }
 
 #[test]
-#[should_panic(expected = "Insufficient escrow balance")]
+fn test_initialize() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+
+    // Admin functions fail until the admin is set, rather than taking it
+    assert_eq!(
+        client.try_set_clock_skew(&admin, &30),
+        Err(Ok(Error::NotInitialized))
+    );
+    client.initialize(&admin);
+    assert_eq!(
+        client.try_initialize(&Address::generate(&env)),
+        Err(Ok(Error::AlreadyInitialized))
+    );
+    assert_eq!(
+        client.try_set_clock_skew(&Address::generate(&env), &30),
+        Err(Ok(Error::Unauthorized))
+    );
+    client.set_clock_skew(&admin, &30);
+    assert_eq!(client.get_clock_skew(), 30);
+}
+
+#[test]
+fn test_max_escrows_per_client() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    let client_addr = Address::generate(&env);
+    let servers: std::vec::Vec<Address> = (0..3).map(|_| Address::generate(&env)).collect();
+
+    // Uncapped until the admin sets a cap
+    assert_eq!(client.get_max_escrows_per_client(), None);
+    let first = client.open_escrow(&client_addr, &servers[0], &1_000, &None);
+    client.set_max_escrows_per_client(&admin, &Some(2));
+    assert_eq!(
+        client.try_set_max_escrows_per_client(&Address::generate(&env), &None),
+        Err(Ok(Error::Unauthorized))
+    );
+    client.open_escrow(&client_addr, &servers[1], &1_000, &None);
+    assert_eq!(client.get_client_escrow_count(&client_addr), 2);
+    assert_eq!(
+        client.try_open_escrow(&client_addr, &servers[2], &1_000, &None),
+        Err(Ok(Error::TooManyEscrows))
+    );
+
+    // Other clients have caps of their own
+    client.open_escrow(&Address::generate(&env), &servers[2], &1_000, &None);
+
+    // Closed escrows no longer count
+    client.client_close_escrow(&first);
+    assert_eq!(
+        client.try_open_escrow(&client_addr, &servers[2], &1_000, &None),
+        Err(Ok(Error::TooManyEscrows))
+    );
+    client.server_close_escrow(&first);
+    assert_eq!(client.get_client_escrow_count(&client_addr), 1);
+    client.open_escrow(&client_addr, &servers[2], &1_000, &None);
+
+    client.set_max_escrows_per_client(&admin, &None);
+    client.open_escrow(&client_addr, &servers[0], &1_000, &None);
+    assert_eq!(client.get_client_escrow_count(&client_addr), 3);
+}
+
+#[test]
+fn test_event_payloads() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+
+    let client_addr = Address::generate(&env);
+    let server_addr = Address::generate(&env);
+
+    // Topics are unchanged, payloads are structs carrying the version
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &1_000, &None);
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(
+        topics,
+        (
+            symbol_short!("open"),
+            client_addr.clone(),
+            server_addr.clone()
+        )
+            .into_val(&env)
+    );
+    let opened: OpenedEvent = data.into_val(&env);
+    assert_eq!(
+        opened,
+        OpenedEvent {
+            event_version: EVENT_VERSION,
+            escrow_id,
+        }
+    );
+
+    let payment_id = client.create_payment(&escrow_id, &100);
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(
+        topics,
+        (symbol_short!("pay"), server_addr, client_addr).into_val(&env)
+    );
+    let paid: PaymentCreatedEvent = data.into_val(&env);
+    assert_eq!(
+        paid,
+        PaymentCreatedEvent {
+            event_version: EVENT_VERSION,
+            payment_id,
+            amount: 100,
+        }
+    );
+
+    client.settle_payment(&terms(&env, &client, payment_id));
+    let (_, _, data) = env.events().all().last().unwrap();
+    let settled: PaymentSettledEvent = data.into_val(&env);
+    assert_eq!(settled.event_version, EVENT_VERSION);
+    assert_eq!(settled.amount, 100);
+    assert_eq!(settled.quote_amount, None);
+
+    client.client_close_escrow(&escrow_id);
+    client.server_close_escrow(&escrow_id);
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(topics, (symbol_short!("closed"), escrow_id).into_val(&env));
+    let closed: ClosedEvent = data.into_val(&env);
+    assert_eq!(
+        closed,
+        ClosedEvent {
+            event_version: EVENT_VERSION,
+            released: 900,
+        }
+    );
+}
+
+#[test]
 fn test_insufficient_balance() {
     let env = Env::default();
     env.mock_all_auths();
@@ -172,14 +540,30 @@ fn test_insufficient_balance() {
     let payment_amount: i128 = 2_000_000; // 0.2 XLM (more than escrow)
 
     // Open escrow
-    let escrow_id = client.open_escrow(&client_addr, &server_addr, &escrow_amount);
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &escrow_amount, &None);
+
+    // Try to create payment exceeding escrow balance - should fail
+    assert_eq!(
+        client.try_create_payment(&escrow_id, &payment_amount),
+        Err(Ok(Error::InsufficientBalance))
+    );
 
-    // Try to create payment exceeding escrow balance - should panic
-    client.create_payment(&escrow_id, &payment_amount);
+    // Payments each covered when created cannot overdraw the escrow
+    let first = client.create_payment(&escrow_id, &600_000);
+    let second = client.create_payment(&escrow_id, &600_000);
+    client.settle_payment(&terms(&env, &client, first));
+    assert_eq!(
+        client.try_settle_payment(&terms(&env, &client, second)),
+        Err(Ok(Error::InsufficientBalance))
+    );
+    assert_eq!(
+        client.try_settle_payments(&batch(&env, &client, &[second])),
+        Err(Ok(Error::InsufficientBalance))
+    );
+    assert_eq!(client.get_escrow_balance(&escrow_id), 400_000);
 }
 
 #[test]
-#[should_panic(expected = "Escrow already exists for this client-server pair")]
 fn test_duplicate_escrow() {
     let env = Env::default();
     env.mock_all_auths();
@@ -192,14 +576,463 @@ fn test_duplicate_escrow() {
     let amount: i128 = 1_000_000;
 
     // Open escrow
-    client.open_escrow(&client_addr, &server_addr, &amount);
+    client.open_escrow(&client_addr, &server_addr, &amount, &None);
+
+    // Try to open same escrow again - should fail
+    assert_eq!(
+        client.try_open_escrow(&client_addr, &server_addr, &amount, &None),
+        Err(Ok(Error::EscrowAlreadyExists))
+    );
+}
+
+#[test]
+fn test_terms_of_service() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let server_addr = Address::generate(&env);
+    let first_terms = BytesN::from_array(&env, &[1; 32]);
+    let second_terms = BytesN::from_array(&env, &[2; 32]);
+
+    // Without published terms, escrows open accepting none
+    assert_eq!(client.get_terms(&server_addr), None);
+    let untermed = client.open_escrow(&Address::generate(&env), &server_addr, &1_000, &None);
+
+    client.set_terms(&server_addr, &first_terms);
+    assert_eq!(client.get_terms(&server_addr), Some(first_terms.clone()));
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(topics, (symbol_short!("terms"), server_addr.clone()).into_val(&env));
+    let updated: TermsUpdatedEvent = data.into_val(&env);
+    assert_eq!(
+        updated,
+        TermsUpdatedEvent {
+            event_version: EVENT_VERSION,
+            terms_hash: first_terms.clone(),
+            previous: None,
+        }
+    );
+
+    // Opens must accept the published terms
+    let client_addr = Address::generate(&env);
+    assert_eq!(
+        client.try_open_escrow(&client_addr, &server_addr, &1_000, &None),
+        Err(Ok(Error::TermsMismatch))
+    );
+    assert_eq!(
+        client.try_open_escrow(&client_addr, &server_addr, &1_000, &Some(second_terms.clone())),
+        Err(Ok(Error::TermsMismatch))
+    );
+    let accepted = Some(first_terms.clone());
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &1_000, &accepted);
+    assert_eq!(client.get_escrow(&escrow_id).terms_hash, Some(first_terms.clone()));
+
+    // New terms apply to new escrows, open ones keep theirs
+    client.set_terms(&server_addr, &second_terms);
+    let (_, _, data) = env.events().all().last().unwrap();
+    let updated: TermsUpdatedEvent = data.into_val(&env);
+    assert_eq!(updated.previous, Some(first_terms.clone()));
+    let other_client = Address::generate(&env);
+    assert_eq!(
+        client.try_open_escrow(&other_client, &server_addr, &1_000, &accepted),
+        Err(Ok(Error::TermsMismatch))
+    );
+    let current = Some(second_terms.clone());
+    let newer = client.open_escrow(&other_client, &server_addr, &1_000, &current);
+    assert_eq!(client.get_escrow(&newer).terms_hash, Some(second_terms));
+    assert_eq!(client.get_escrow(&escrow_id).terms_hash, Some(first_terms));
+    assert_eq!(client.get_escrow(&untermed).terms_hash, None);
+
+    // Grandfathered escrows keep paying and being settled
+    let payment_id = client.create_payment(&escrow_id, &100);
+    client.settle_payment(&terms(&env, &client, payment_id));
+    assert_eq!(client.get_escrow_balance(&escrow_id), 900);
+}
+
+#[test]
+fn test_notification_url() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let server_addr = Address::generate(&env);
+    let notified = |url: Option<Bytes>| {
+        let (_, topics, data) = env.events().all().last().unwrap();
+        assert_eq!(topics, (symbol_short!("notify"), server_addr.clone()).into_val(&env));
+        let event: NotificationUrlEvent = data.into_val(&env);
+        assert_eq!(
+            event,
+            NotificationUrlEvent {
+                event_version: EVENT_VERSION,
+                url,
+            }
+        );
+    };
+    assert_eq!(client.get_notification_url(&server_addr), None);
+
+    // Registration
+    let first = Bytes::from_slice(&env, b"https://api.example.com/x402/hooks");
+    client.set_notification_url(&server_addr, &first);
+    assert_eq!(env.auths()[0].0, server_addr, "the server registers its URL");
+    assert_eq!(client.get_notification_url(&server_addr), Some(first.clone()));
+    notified(Some(first));
+
+    // Update, up to the length cap
+    let longest = Bytes::from_slice(&env, &[b'a'; MAX_NOTIFY_URL_LEN as usize]);
+    client.set_notification_url(&server_addr, &longest);
+    assert_eq!(client.get_notification_url(&server_addr), Some(longest.clone()));
+    notified(Some(longest.clone()));
+    let too_long = Bytes::from_slice(&env, &[b'a'; MAX_NOTIFY_URL_LEN as usize + 1]);
+    assert_eq!(
+        client.try_set_notification_url(&server_addr, &too_long),
+        Err(Ok(Error::InvalidNotificationUrl))
+    );
+    assert_eq!(client.get_notification_url(&server_addr), Some(longest));
+
+    // Other servers are unaffected
+    assert_eq!(client.get_notification_url(&Address::generate(&env)), None);
+
+    // Removal
+    client.set_notification_url(&server_addr, &Bytes::new(&env));
+    assert_eq!(client.get_notification_url(&server_addr), None);
+    notified(None);
+}
+
+#[test]
+fn test_clone_escrow_config() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let client_addr = Address::generate(&env);
+    let (production, staging) = (Address::generate(&env), Address::generate(&env));
+    let terms_hash = BytesN::from_array(&env, &[1; 32]);
+    client.set_terms(&production, &terms_hash);
+    client.set_terms(&staging, &terms_hash);
+
+    let accepted = Some(terms_hash.clone());
+    let source_id = client.open_escrow(&client_addr, &production, &1_000_000, &accepted);
+    client.deposit(&source_id, &500_000);
+    let payment_id = client.create_payment(&source_id, &100_000);
+    client.settle_payment(&terms(&env, &client, payment_id));
+
+    // Configuration is copied, the balance is not
+    let escrow_id = client.clone_escrow_config(&source_id, &staging, &1_000);
+    assert_eq!(
+        env.auths()[0].0,
+        client_addr.clone(),
+        "the client authorizes the clone"
+    );
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(topics, (symbol_short!("cloned"), source_id).into_val(&env));
+    let cloned: ClonedEvent = data.into_val(&env);
+    assert_eq!(
+        cloned,
+        ClonedEvent {
+            event_version: EVENT_VERSION,
+            escrow_id,
+        }
+    );
+    assert_eq!(
+        client.get_escrow(&escrow_id),
+        Escrow {
+            client: client_addr.clone(),
+            server: staging.clone(),
+            balance: 1_000,
+            client_closed: false,
+            server_closed: false,
+            terms_hash: accepted,
+            trial: false,
+            trial_balance: 0,
+            pending: 0,
+            pending_payments: 0,
+        }
+    );
+    assert_eq!(client.find_escrow(&client_addr, &staging), Some(escrow_id));
+    assert_eq!(client.get_escrow_balance(&source_id), 1_400_000);
+    assert_eq!(client.get_payments(&escrow_id, &None, &0, &10).payments.len(), 0);
+    assert_eq!(client.get_client_escrow_count(&client_addr), 2);
+
+    // The new server must publish the terms the source accepted
+    let other = Address::generate(&env);
+    client.set_terms(&other, &BytesN::from_array(&env, &[2; 32]));
+    assert_eq!(
+        client.try_clone_escrow_config(&source_id, &other, &1_000),
+        Err(Ok(Error::TermsMismatch))
+    );
+    assert_eq!(
+        client.try_clone_escrow_config(&source_id, &staging, &1_000),
+        Err(Ok(Error::EscrowAlreadyExists))
+    );
+    assert_eq!(
+        client.try_clone_escrow_config(&99, &Address::generate(&env), &1_000),
+        Err(Ok(Error::EscrowNotFound))
+    );
+}
+
+#[test]
+fn test_split_escrow() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let client_addr = Address::generate(&env);
+    let server_addr = Address::generate(&env);
+    let terms_hash = BytesN::from_array(&env, &[1; 32]);
+    client.set_terms(&server_addr, &terms_hash);
+    let accepted = Some(terms_hash);
+    let parent_id = client.open_escrow(&client_addr, &server_addr, &1_000_000, &accepted);
+    client.set_max_settled_per_hour(&parent_id, &Some(500_000));
+
+    // Without pending payments, the client alone splits
+    let staging = Bytes::from_slice(&env, b"staging");
+    let child_id = client.split_escrow(&parent_id, &400_000, &staging);
+    assert_eq!(env.auths().len(), 1);
+    assert_eq!(env.auths()[0].0, client_addr.clone(), "the client splits");
+    let events = env.events().all();
+    let (_, topics, data) = events.get(events.len() - 2).unwrap();
+    assert_eq!(
+        topics,
+        (symbol_short!("open"), client_addr.clone(), server_addr.clone()).into_val(&env)
+    );
+    let opened: OpenedEvent = data.into_val(&env);
+    assert_eq!(opened.escrow_id, child_id);
+    let (_, topics, data) = events.last().unwrap();
+    assert_eq!(topics, (symbol_short!("split"), parent_id).into_val(&env));
+    let split: SplitEvent = data.into_val(&env);
+    assert_eq!(
+        split,
+        SplitEvent {
+            event_version: EVENT_VERSION,
+            child_escrow_id: child_id,
+            amount: 400_000,
+            metadata: staging.clone(),
+        }
+    );
+
+    // Same parties and configuration, the balance conserved
+    assert_eq!(
+        client.get_escrow(&child_id),
+        Escrow {
+            client: client_addr.clone(),
+            server: server_addr.clone(),
+            balance: 400_000,
+            client_closed: false,
+            server_closed: false,
+            terms_hash: accepted,
+            trial: false,
+            trial_balance: 0,
+            pending: 0,
+            pending_payments: 0,
+        }
+    );
+    assert_eq!(client.get_escrow_balance(&parent_id), 600_000);
+    let limit = client.get_spend_limit(&child_id).unwrap();
+    assert_eq!(limit.max_settled_per_hour, 500_000);
+    assert_eq!(limit.settled, 0);
+    assert_eq!(client.get_client_escrow_count(&client_addr), 2);
+    assert_eq!(client.find_escrow(&client_addr, &server_addr), Some(parent_id));
+
+    // Both records link the escrows
+    let parent_links = client.get_escrow_links(&parent_id);
+    assert_eq!(parent_links.parent, None);
+    assert_eq!(parent_links.children, vec![&env, child_id]);
+    let child_links = client.get_escrow_links(&child_id);
+    assert_eq!(child_links.parent, Some(parent_id));
+    assert_eq!(child_links.children, Vec::new(&env));
+    assert_eq!(child_links.metadata, staging);
+
+    // Pending payments stay with the parent, which must still cover them,
+    // and the server acknowledges the split
+    let payment_id = client.create_payment(&parent_id, &500_000);
+    let empty = Bytes::new(&env);
+    assert_eq!(
+        client.try_split_escrow(&parent_id, &100_001, &empty),
+        Err(Ok(Error::InvalidSplit))
+    );
+    let second_id = client.split_escrow(&parent_id, &100_000, &empty);
+    let signers: std::vec::Vec<_> = env.auths().into_iter().map(|(signer, _)| signer).collect();
+    assert_eq!(signers, [client_addr.clone(), server_addr.clone()]);
+    assert_eq!(client.get_escrow_balance(&parent_id), 500_000);
+    assert_eq!(client.get_payment(&payment_id).escrow_id, parent_id);
+    assert_eq!(
+        client.get_escrow_links(&parent_id).children,
+        vec![&env, child_id, second_id]
+    );
+    let total: i128 = [parent_id, child_id, second_id]
+        .iter()
+        .map(|id| client.get_escrow_balance(id))
+        .sum();
+    assert_eq!(total, 1_000_000);
+    client.settle_payment(&terms(&env, &client, payment_id));
+    assert_eq!(client.get_escrow_balance(&parent_id), 0);
+
+    assert_eq!(
+        client.try_split_escrow(&child_id, &0, &empty),
+        Err(Ok(Error::InvalidSplit))
+    );
+    let long = Bytes::from_slice(&env, &[b'x'; MAX_SPLIT_METADATA_LEN as usize + 1]);
+    assert_eq!(
+        client.try_split_escrow(&child_id, &1, &long),
+        Err(Ok(Error::InvalidSplit))
+    );
+    assert_eq!(
+        client.try_split_escrow(&99, &1, &empty),
+        Err(Ok(Error::EscrowNotFound))
+    );
+
+    // Closing a child keeps the pair's lookup on the parent, and the links
+    client.client_close_escrow(&child_id);
+    assert_eq!(client.server_close_escrow(&child_id), Some(400_000));
+    assert_eq!(client.find_escrow(&client_addr, &server_addr), Some(parent_id));
+    assert_eq!(client.get_escrow_links(&child_id).parent, Some(parent_id));
+}
+
+#[test]
+fn test_sponsored_escrow() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let client_addr = Address::generate(&env);
+    let server_addr = Address::generate(&env);
+    let terms_hash = BytesN::from_array(&env, &[1; 32]);
+    client.set_terms(&server_addr, &terms_hash);
+
+    // The server funds the trial
+    let escrow_id = client.open_sponsored_escrow(&server_addr, &client_addr, &1_000);
+    assert_eq!(env.auths()[0].0, server_addr.clone(), "the server authorizes the trial");
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(topics, (symbol_short!("sponsor"), escrow_id).into_val(&env));
+    let sponsored: SponsoredEvent = data.into_val(&env);
+    assert_eq!(sponsored.amount, 1_000);
+    assert_eq!(
+        client.get_escrow(&escrow_id),
+        Escrow {
+            client: client_addr.clone(),
+            server: server_addr.clone(),
+            balance: 1_000,
+            client_closed: false,
+            server_closed: false,
+            terms_hash: Some(terms_hash.clone()),
+            trial: true,
+            trial_balance: 1_000,
+            pending: 0,
+            pending_payments: 0,
+        }
+    );
+
+    // Settlements spend the trial funds before the client's deposits
+    client.deposit(&escrow_id, &300);
+    let payment_id = client.create_payment(&escrow_id, &200);
+    client.settle_payment(&terms(&env, &client, payment_id));
+    let escrow = client.get_escrow(&escrow_id);
+    assert_eq!((escrow.balance, escrow.trial_balance), (1_100, 800));
+
+    // Closing releases the client's deposits, the server reclaims the rest
+    client.server_close_escrow(&escrow_id);
+    assert_eq!(client.client_close_escrow(&escrow_id), Some(300));
+    let events = env.events().all();
+    let (_, topics, data) = events.get(events.len() - 2).unwrap();
+    assert_eq!(topics, (symbol_short!("reclaim"), escrow_id).into_val(&env));
+    let reclaimed: ReclaimedEvent = data.into_val(&env);
+    assert_eq!(
+        reclaimed,
+        ReclaimedEvent {
+            event_version: EVENT_VERSION,
+            server: server_addr.clone(),
+            amount: 800,
+        }
+    );
+    let (_, _, data) = events.last().unwrap();
+    let closed: ClosedEvent = data.into_val(&env);
+    assert_eq!(closed.released, 300);
+
+    // Once the trial funds are spent, deposits are released in full
+    let escrow_id = client.open_sponsored_escrow(&server_addr, &client_addr, &100);
+    let payment_id = client.create_payment(&escrow_id, &100);
+    client.settle_payment(&terms(&env, &client, payment_id));
+    client.deposit(&escrow_id, &500);
+    let escrow = client.get_escrow(&escrow_id);
+    assert!(escrow.trial);
+    assert_eq!((escrow.balance, escrow.trial_balance), (500, 0));
+    client.client_close_escrow(&escrow_id);
+    assert_eq!(client.server_close_escrow(&escrow_id), Some(500));
+    let (_, topics, _) = env.events().all().first().unwrap();
+    assert_eq!(topics, (symbol_short!("closed"), escrow_id).into_val(&env));
+
+    // Escrows recorded before trials are not sponsored
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &1_000, &Some(terms_hash));
+    env.as_contract(&contract_id, || {
+        let escrow = TermsEscrow {
+            client: client_addr.clone(),
+            server: server_addr.clone(),
+            balance: 1_000,
+            client_closed: false,
+            server_closed: false,
+            terms_hash: None,
+        };
+        env.storage().persistent().set(&DataKey::Escrow(escrow_id), &escrow);
+    });
+    let escrow = client.get_escrow(&escrow_id);
+    assert_eq!((escrow.trial, escrow.trial_balance), (false, 0));
+}
+
+#[test]
+fn test_sponsored_escrow_unclaimed() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    let client_addr = Address::generate(&env);
+    let server_addr = Address::generate(&env);
+    let other_server = Address::generate(&env);
+
+    for trial_amount in [0, -100] {
+        assert_eq!(
+            client.try_open_sponsored_escrow(&server_addr, &client_addr, &trial_amount),
+            Err(Ok(Error::InvalidTrial))
+        );
+    }
 
-    // Try to open same escrow again - should panic
-    client.open_escrow(&client_addr, &server_addr, &amount);
+    // Servers sponsoring escrows the client never claims take none of its
+    // slots, nor its lookups
+    client.set_max_escrows_per_client(&admin, &Some(1));
+    let unclaimed = client.open_sponsored_escrow(&server_addr, &client_addr, &100);
+    let sponsored = client.open_sponsored_escrow(&other_server, &client_addr, &100);
+    assert_eq!(client.get_client_escrow_count(&client_addr), 0);
+    assert_eq!(client.find_escrow(&client_addr, &server_addr), None);
+    let own = client.open_escrow(&client_addr, &server_addr, &1_000, &None);
+    assert_eq!(client.find_escrow(&client_addr, &server_addr), Some(own));
+
+    // The first deposit claims the escrow, within the client's cap
+    assert_eq!(
+        client.try_deposit(&sponsored, &500),
+        Err(Ok(Error::TooManyEscrows))
+    );
+    client.client_close_escrow(&own);
+    client.server_close_escrow(&own);
+    client.deposit(&sponsored, &500);
+    assert_eq!(client.get_client_escrow_count(&client_addr), 1);
+    assert_eq!(client.find_escrow(&client_addr, &other_server), Some(sponsored));
+    client.deposit(&sponsored, &500);
+    assert_eq!(client.get_client_escrow_count(&client_addr), 1);
+
+    // Closing an unclaimed escrow releases no slot
+    client.server_close_escrow(&unclaimed);
+    client.client_close_escrow(&unclaimed);
+    assert_eq!(client.get_client_escrow_count(&client_addr), 1);
 }
 
 #[test]
-#[should_panic(expected = "Payment already settled")]
 fn test_double_settlement() {
     let env = Env::default();
     env.mock_all_auths();
@@ -213,12 +1046,2566 @@ fn test_double_settlement() {
     let payment_amount: i128 = 1_000_000;
 
     // Open escrow and create payment
-    let escrow_id = client.open_escrow(&client_addr, &server_addr, &escrow_amount);
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &escrow_amount, &None);
     let payment_id = client.create_payment(&escrow_id, &payment_amount);
 
     // Settle payment
-    client.settle_payment(&payment_id);
+    client.settle_payment(&terms(&env, &client, payment_id));
+
+    // Try to settle again - should fail
+    assert_eq!(
+        client.try_settle_payment(&terms(&env, &client, payment_id)),
+        Err(Ok(Error::PaymentAlreadySettled))
+    );
+}
+
+#[test]
+fn test_batch_settlement() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+
+    let server_addr = Address::generate(&env);
+    let first = client.open_escrow(&Address::generate(&env), &server_addr, &1_000_000, &None);
+    let second = client.open_escrow(&Address::generate(&env), &server_addr, &1_000_000, &None);
+
+    // Payments across both escrows of the server
+    let a = client.create_payment(&first, &100_000);
+    let b = client.create_payment(&second, &200_000);
+    let c = client.create_payment(&first, &300_000);
+
+    assert_eq!(
+        client.settle_payments(&batch(&env, &client, &[a, b, c])),
+        600_000
+    );
+    assert!(client.get_payment(&b).settled);
+    assert_eq!(client.get_escrow_balance(&first), 600_000);
+    assert_eq!(client.get_escrow_balance(&second), 800_000);
+    assert_eq!(client.settle_payments(&batch(&env, &client, &[])), 0);
+
+    // A batch with a bad payment settles nothing
+    let d = client.create_payment(&first, &50_000);
+    assert_eq!(
+        client.try_settle_payments(&batch(&env, &client, &[d, a])),
+        Err(Ok(Error::PaymentAlreadySettled))
+    );
+    assert_eq!(
+        client.try_settle_payments(&batch(&env, &client, &[d, d])),
+        Err(Ok(Error::PaymentAlreadySettled))
+    );
+    assert_eq!(
+        client.try_settle_payments(&batch(&env, &client, &[d, 99])),
+        Err(Ok(Error::PaymentNotFound))
+    );
+    assert!(!client.get_payment(&d).settled);
+    assert_eq!(client.get_escrow_balance(&first), 600_000);
+}
+
+#[test]
+fn test_batch_creation() {
+    use soroban_sdk::testutils::{MockAuth, MockAuthInvoke};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+
+    let server_addr = Address::generate(&env);
+    let first = client.open_escrow(&Address::generate(&env), &server_addr, &1_000_000, &None);
+    let second = client.open_escrow(&Address::generate(&env), &server_addr, &1_000_000, &None);
+    let intent = |escrow_id, amount| PaymentIntent { escrow_id, amount };
+
+    // Payments across both escrows, numbered in batch order
+    let ids = client.create_payments(&vec![
+        &env,
+        intent(first, 100_000),
+        intent(second, 200_000),
+        intent(first, 300_000),
+    ]);
+    assert_eq!(ids.len(), 3);
+    assert_eq!(ids.get(2).unwrap(), ids.get(0).unwrap() + 1);
+    let payment = client.get_payment(&ids.get(1).unwrap());
+    assert_eq!((payment.escrow_id, payment.amount), (second, 200_000));
+    assert!(!payment.settled);
+    assert_eq!(client.create_payments(&vec![&env]), vec![&env]);
 
-    // Try to settle again - should panic
-    client.settle_payment(&payment_id);
+    // A batch with a bad intent creates nothing
+    assert_eq!(
+        client.try_create_payments(&vec![&env, intent(first, 100), intent(second, 2_000_000)]),
+        Err(Ok(Error::InsufficientBalance))
+    );
+    assert_eq!(
+        client.try_create_payments(&vec![&env, intent(first, 100), intent(99, 100)]),
+        Err(Ok(Error::EscrowNotFound))
+    );
+
+    // Payments each within the balance, but not together
+    assert_eq!(
+        client.try_create_payments(&vec![&env, intent(first, 600_000), intent(first, 600_000)]),
+        Err(Ok(Error::InsufficientBalance))
+    );
+    assert_eq!(
+        client.get_payments(&first, &None, &0, &10).payments.len(),
+        2
+    );
+
+    // The server authorizes the intents on its escrows, in batch order
+    let authorized = vec![&env, intent(second, 100), intent(first, 100)];
+    env.mock_auths(&[MockAuth {
+        address: &server_addr,
+        invoke: &MockAuthInvoke {
+            contract: &contract_id,
+            fn_name: "create_payments",
+            args: (authorized.clone(),).into_val(&env),
+            sub_invokes: &[],
+        },
+    }]);
+    assert!(client
+        .try_create_payments(&vec![&env, intent(first, 100), intent(second, 100)])
+        .is_err());
+    env.mock_auths(&[MockAuth {
+        address: &server_addr,
+        invoke: &MockAuthInvoke {
+            contract: &contract_id,
+            fn_name: "create_payments",
+            args: (authorized.clone(),).into_val(&env),
+            sub_invokes: &[],
+        },
+    }]);
+    assert_eq!(client.create_payments(&authorized).len(), 2);
+}
+
+#[test]
+fn test_settlement_bound_to_terms() {
+    use soroban_sdk::testutils::{MockAuth, MockAuthInvoke};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let server_addr = Address::generate(&env);
+    let first = client.open_escrow(&Address::generate(&env), &server_addr, &1_000_000, &None);
+    let second = client.open_escrow(&Address::generate(&env), &server_addr, &1_000_000, &None);
+    let a = client.create_payment(&first, &100_000);
+    let b = client.create_payment(&second, &900_000);
+
+    // Terms that are not the payment's are rejected
+    let expected = terms(&env, &client, a);
+    for wrong in [
+        Settlement {
+            escrow_id: second,
+            ..expected.clone()
+        },
+        Settlement {
+            amount: 900_000,
+            ..expected.clone()
+        },
+        Settlement {
+            client: Address::generate(&env),
+            ..expected.clone()
+        },
+    ] {
+        assert_eq!(
+            client.try_settle_payment(&wrong),
+            Err(Ok(Error::SettlementMismatch))
+        );
+        assert_eq!(
+            client.try_settle_payments(&vec![&env, wrong]),
+            Err(Ok(Error::SettlementMismatch))
+        );
+    }
+
+    // The server authorized settling `a`, which cannot settle `b`
+    let as_server = |fn_name: &'static str, args: Vec<soroban_sdk::Val>| {
+        env.mock_auths(&[MockAuth {
+            address: &server_addr,
+            invoke: &MockAuthInvoke {
+                contract: &contract_id,
+                fn_name,
+                args,
+                sub_invokes: &[],
+            },
+        }]);
+    };
+    let substituted = terms(&env, &client, b);
+    as_server("settle_payment", (expected.clone(),).into_val(&env));
+    assert!(client.try_settle_payment(&substituted).is_err());
+    as_server(
+        "settle_payments",
+        (batch(&env, &client, &[a]),).into_val(&env),
+    );
+    assert!(client
+        .try_settle_payments(&batch(&env, &client, &[a, b]))
+        .is_err());
+    assert!(!client.get_payment(&b).settled);
+
+    // Each server authorizes its own settlements, in batch order
+    as_server(
+        "settle_payments",
+        (batch(&env, &client, &[b, a]),).into_val(&env),
+    );
+    assert_eq!(
+        client.settle_payments(&batch(&env, &client, &[b, a])),
+        1_000_000
+    );
+    assert!(client.get_payment(&a).settled);
+}
+
+#[test]
+fn test_spend_limit() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let client_addr = Address::generate(&env);
+    let server_addr = Address::generate(&env);
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &10_000, &None);
+    assert_eq!(
+        client.try_set_max_settled_per_hour(&escrow_id, &Some(0)),
+        Err(Ok(Error::InvalidSpendLimit))
+    );
+    env.ledger().set_timestamp(1_000);
+    client.set_max_settled_per_hour(&escrow_id, &Some(1_000));
+    assert_eq!(env.auths()[0].0, client_addr.clone(), "the client sets the cap");
+    let settle = |amount| {
+        let payment_id = client.create_payment(&escrow_id, &amount);
+        (payment_id, client.settle_payment(&terms(&env, &client, payment_id)))
+    };
+
+    // Normal traffic stays under the cap, which each window renews
+    for _ in 0..4 {
+        assert!(settle(200).1);
+    }
+    env.ledger().set_timestamp(1_000 + SPEND_WINDOW);
+    for _ in 0..5 {
+        assert!(settle(200).1);
+    }
+    assert_eq!(client.get_spend_limit(&escrow_id).unwrap().settled, 1_000);
+
+    // A drain breaches the cap, pausing settlements but not payments
+    env.ledger().set_timestamp(1_000 + 2 * SPEND_WINDOW);
+    assert!(settle(600).1);
+    let (drained, settled) = settle(600);
+    assert!(!settled);
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(topics, (symbol_short!("anomaly"), escrow_id).into_val(&env));
+    let anomaly: AnomalyEvent = data.into_val(&env);
+    assert_eq!(
+        anomaly,
+        AnomalyEvent {
+            event_version: EVENT_VERSION,
+            settled: 600,
+            attempted: 600,
+            max_settled_per_hour: 1_000,
+        }
+    );
+    assert!(!client.get_payment(&drained).settled);
+    assert_eq!(client.get_escrow_balance(&escrow_id), 7_600);
+    assert!(client.get_spend_limit(&escrow_id).unwrap().paused);
+    let pending = client.create_payment(&escrow_id, &10);
+    assert_eq!(
+        client.try_settle_payment(&terms(&env, &client, pending)),
+        Err(Ok(Error::SettlementsPaused))
+    );
+    assert_eq!(
+        client.try_settle_payments(&vec![&env, terms(&env, &client, pending)]),
+        Err(Ok(Error::SettlementsPaused))
+    );
+
+    // The client acknowledges, resuming settlements in a new window
+    client.acknowledge_anomaly(&escrow_id);
+    assert!(client.settle_payment(&terms(&env, &client, drained)));
+
+    // A batch breaching the cap fails as a whole, without pausing
+    let large = client.create_payment(&escrow_id, &500);
+    let batch = vec![&env, terms(&env, &client, pending), terms(&env, &client, large)];
+    assert_eq!(client.try_settle_payments(&batch), Err(Ok(Error::SettlementsPaused)));
+    assert!(!client.get_spend_limit(&escrow_id).unwrap().paused);
+
+    // Removing the cap lifts it
+    client.set_max_settled_per_hour(&escrow_id, &None);
+    assert_eq!(client.get_spend_limit(&escrow_id), None);
+    assert_eq!(client.settle_payments(&batch), 510);
+}
+
+#[test]
+fn test_refund_payment() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+
+    let server_addr = Address::generate(&env);
+    let escrow_id = client.open_escrow(&Address::generate(&env), &server_addr, &1_000_000, &None);
+    let payment_id = client.create_payment(&escrow_id, &300_000);
+
+    // Only settled payments are refunded
+    assert_eq!(
+        client.try_refund_payment(&payment_id, &100_000),
+        Err(Ok(Error::PaymentNotSettled))
+    );
+    client.settle_payment(&terms(&env, &client, payment_id));
+    assert_eq!(client.get_escrow_balance(&escrow_id), 700_000);
+
+    // Partial refunds add up to at most the payment's amount
+    assert_eq!(client.refund_payment(&payment_id, &100_000), 100_000);
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(
+        topics,
+        (symbol_short!("refunded"), payment_id).into_val(&env)
+    );
+    let refunded: PaymentRefundedEvent = data.into_val(&env);
+    assert_eq!(
+        refunded,
+        PaymentRefundedEvent {
+            event_version: EVENT_VERSION,
+            amount: 100_000,
+            refunded: 100_000,
+        }
+    );
+    assert_eq!(client.refund_payment(&payment_id, &150_000), 250_000);
+    assert_eq!(
+        client.try_refund_payment(&payment_id, &50_001),
+        Err(Ok(Error::InvalidRefund))
+    );
+    assert_eq!(
+        client.try_refund_payment(&payment_id, &0),
+        Err(Ok(Error::InvalidRefund))
+    );
+    assert_eq!(client.refund_payment(&payment_id, &50_000), 300_000);
+    assert_eq!(client.get_refunded(&payment_id), 300_000);
+    assert_eq!(client.get_escrow_balance(&escrow_id), 1_000_000);
+    assert_eq!(client.get_refunded(&99), 0);
+    assert_eq!(
+        client.try_refund_payment(&99, &1),
+        Err(Ok(Error::PaymentNotFound))
+    );
+}
+
+#[test]
+fn test_missing_records() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+
+    // Neither escrows nor payments exist yet
+    assert_eq!(client.try_get_escrow(&0), Err(Ok(Error::EscrowNotFound)));
+    assert_eq!(client.try_deposit(&0, &1), Err(Ok(Error::EscrowNotFound)));
+    assert_eq!(client.try_get_payment(&0), Err(Ok(Error::PaymentNotFound)));
+    assert_eq!(
+        client.try_settle_payment(&terms(&env, &client, 0)),
+        Err(Ok(Error::PaymentNotFound))
+    );
 }
+
+#[test]
+fn test_get_payments() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+
+    let server_addr = Address::generate(&env);
+    let first = client.open_escrow(&Address::generate(&env), &server_addr, &10_000_000, &None);
+    let second = client.open_escrow(&Address::generate(&env), &server_addr, &10_000_000, &None);
+
+    // Payments of both escrows interleaved, every third one of the first settled
+    let mut ids = Vec::new(&env);
+    for i in 0..60 {
+        ids.push_back(client.create_payment(&first, &(i + 1)));
+        client.create_payment(&second, &1);
+    }
+    for id in ids.iter().step_by(3) {
+        client.settle_payment(&terms(&env, &client, id));
+    }
+
+    // Pages hold at most MAX_PAYMENTS_PAGE payments, oldest first
+    let page = client.get_payments(&first, &None, &0, &100);
+    assert_eq!(page.payments.len(), MAX_PAYMENTS_PAGE);
+    assert_eq!(page.next_offset, Some(MAX_PAYMENTS_PAGE));
+    let entry = page.payments.get(1).unwrap();
+    assert_eq!(entry.payment_id, ids.get(1).unwrap());
+    assert_eq!(entry.payment.escrow_id, first);
+    assert_eq!(entry.payment.amount, 2);
+    let last = client.get_payments(&first, &None, &MAX_PAYMENTS_PAGE, &100);
+    assert_eq!(last.payments.len(), 10);
+    assert_eq!(last.next_offset, None);
+    assert_eq!(
+        last.payments.get(9).unwrap().payment_id,
+        ids.get(59).unwrap()
+    );
+
+    // Filtered pages may be short, the offset still advances
+    let settled = client.get_payments(&first, &Some(PaymentStatus::Settled), &0, &30);
+    assert_eq!(settled.payments.len(), 10);
+    assert!(settled.payments.iter().all(|entry| entry.payment.settled));
+    assert_eq!(settled.next_offset, Some(30));
+    let pending = client.get_payments(&first, &Some(PaymentStatus::Pending), &0, &30);
+    assert_eq!(pending.payments.len(), 20);
+    let failed = client.get_payments(&first, &Some(PaymentStatus::Failed), &0, &30);
+    assert!(failed.payments.is_empty());
+
+    // A zero limit still looks at one payment, unknown escrows have none
+    assert_eq!(
+        client.get_payments(&second, &None, &0, &0).payments.len(),
+        1
+    );
+    let none = client.get_payments(&u64::MAX, &None, &0, &10);
+    assert!(none.payments.is_empty());
+    assert_eq!(none.next_offset, None);
+}
+
+#[test]
+fn test_export_escrows() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+
+    // Every fourth escrow is with another server, the second one is closed
+    let (server_addr, other_server) = (Address::generate(&env), Address::generate(&env));
+    let mut escrow_ids = std::vec::Vec::new();
+    for i in 0..60u64 {
+        let server = if i % 4 == 3 {
+            &other_server
+        } else {
+            &server_addr
+        };
+        let amount = (i + 1) as i128;
+        escrow_ids.push(client.open_escrow(&Address::generate(&env), server, &amount, &None));
+    }
+    client.client_close_escrow(&escrow_ids[1]);
+    client.server_close_escrow(&escrow_ids[1]);
+
+    // Pages read at most `limit + 1` entries, escrows and shard counters
+    // together, the contract's instance and code besides
+    let export = |server: Option<Address>, limit: u32| {
+        let mut escrows = std::vec::Vec::new();
+        let mut start = Some(0);
+        while let Some(cursor) = start {
+            let page = client.export_escrows(&server, &cursor, &limit);
+            assert!(env.cost_estimate().resources().read_entries <= limit + 3);
+            assert!(page.escrows.len() <= limit as usize);
+            escrows.extend(page.escrows.iter());
+            start = page.next_start;
+        }
+        escrows
+    };
+    let all = export(None, MAX_ESCROWS_PAGE);
+    assert_eq!(all.len(), 59);
+    let mut listed: std::vec::Vec<_> = all.iter().map(|entry| entry.escrow_id).collect();
+    listed.sort_unstable();
+    listed.dedup();
+    assert_eq!(listed.len(), 59);
+    let entry = all
+        .iter()
+        .find(|entry| entry.escrow_id == escrow_ids[2])
+        .unwrap();
+    assert_eq!(entry.escrow.balance, 3);
+    assert_eq!(entry.escrow.server, server_addr);
+    assert!(all.iter().all(|entry| entry.escrow_id != escrow_ids[1]));
+
+    // Filtered by server, the start still advances
+    let own = export(Some(server_addr.clone()), 20);
+    assert_eq!(own.len(), 44);
+    assert!(own.iter().all(|entry| entry.escrow.server == server_addr));
+    assert_eq!(export(Some(other_server), 20).len(), 15);
+
+    // A zero limit still looks at one escrow, past the end there are none
+    let one = client.export_escrows(&None, &escrow_ids[2], &0);
+    assert_eq!(one.escrows.len(), 1);
+    assert_eq!(one.escrows.get(0).unwrap().escrow_id, escrow_ids[2]);
+    let none = client.export_escrows(&None, &u64::MAX, &10);
+    assert!(none.escrows.is_empty());
+    assert_eq!(none.next_start, None);
+}
+
+/// Ledger entries a Soroban transaction may read, as configured on mainnet
+const MAX_READ_ENTRIES: u32 = 100;
+
+#[test]
+fn test_export_escrows_read_limit() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+
+    // Enough clients for every shard to hold escrows, most several
+    let server_addr = Address::generate(&env);
+    let mut escrow_ids = std::collections::BTreeSet::new();
+    for _ in 0..640 {
+        escrow_ids.insert(client.open_escrow(&Address::generate(&env), &server_addr, &1, &None));
+    }
+    let shards: std::collections::BTreeSet<_> = escrow_ids
+        .iter()
+        .map(|escrow_id| escrow_id % u64::from(ESCROW_SHARDS))
+        .collect();
+    assert_eq!(shards.len(), ESCROW_SHARDS as usize);
+
+    // Every page of the largest size fits one transaction, with the
+    // default budget
+    let mut exported = std::collections::BTreeSet::new();
+    let mut start = Some(0);
+    while let Some(cursor) = start {
+        let page = client.export_escrows(&Some(server_addr.clone()), &cursor, &MAX_ESCROWS_PAGE);
+        let reads = env.cost_estimate().resources().read_entries;
+        assert!(reads <= MAX_READ_ENTRIES, "page at {cursor} reads {reads} entries");
+        exported.extend(page.escrows.iter().map(|entry| entry.escrow_id));
+        start = page.next_start;
+    }
+    assert_eq!(exported, escrow_ids);
+}
+
+#[test]
+fn test_escrow_index() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let client_addr = Address::generate(&env);
+    let server_addr = Address::generate(&env);
+
+    // Three pages of escrows of the client, the first with the server
+    let mut escrow_ids = std::vec::Vec::new();
+    for i in 0..(2 * ESCROW_INDEX_PAGE + 5) {
+        let server_addr = if i == 0 {
+            server_addr.clone()
+        } else {
+            Address::generate(&env)
+        };
+        escrow_ids.push(client.open_escrow(&client_addr, &server_addr, &1_000, &None));
+    }
+    let list = |address: &Address, archived: bool| {
+        let mut ids = std::vec::Vec::new();
+        let mut pages = 0;
+        let mut page = Some(0);
+        while let Some(current) = page {
+            let listed = client.get_escrow_index(address, &archived, &current);
+            assert!(listed.escrow_ids.len() <= ESCROW_INDEX_PAGE);
+            ids.extend(listed.escrow_ids.iter());
+            pages += 1;
+            page = listed.next_page;
+        }
+        (ids, pages)
+    };
+    assert_eq!(list(&client_addr, false), (escrow_ids.clone(), 3));
+    // Servers are not indexed
+    assert_eq!(list(&server_addr, false), (std::vec::Vec::new(), 1));
+
+    // Closed escrows move to the archive, in the order they were closed
+    let closed: std::vec::Vec<u64> = escrow_ids.iter().copied().step_by(2).collect();
+    for escrow_id in closed.iter().rev() {
+        client.client_close_escrow(escrow_id);
+        client.server_close_escrow(escrow_id);
+    }
+    let open: std::vec::Vec<u64> = escrow_ids.iter().copied().skip(1).step_by(2).collect();
+    let archived: std::vec::Vec<u64> = closed.iter().copied().rev().collect();
+    assert_eq!(list(&client_addr, false), (open.clone(), 3));
+    assert_eq!(list(&client_addr, true), (archived, 2));
+    assert_eq!(list(&server_addr, true), (std::vec::Vec::new(), 1));
+
+    // The client compacts the live pages, a page per call
+    let mut start = Some(0);
+    while let Some(page) = start {
+        start = client.compact_escrow_index(&client_addr, &page, &1);
+        assert_eq!(env.auths()[0].0, client_addr, "the client compacts its index");
+    }
+    let (ids, _) = list(&client_addr, false);
+    assert_eq!(ids, open);
+    let page = client.get_escrow_index(&client_addr, &false, &0);
+    assert_eq!(page.escrow_ids.len(), ESCROW_INDEX_PAGE / 2);
+
+    // In one call, the IDs left are packed into the first pages
+    assert_eq!(client.compact_escrow_index(&client_addr, &0, &MAX_COMPACT_PAGES), None);
+    assert_eq!(list(&client_addr, false), (open.clone(), 2));
+    let page = client.get_escrow_index(&client_addr, &false, &0);
+    assert_eq!(page.escrow_ids.len(), ESCROW_INDEX_PAGE);
+
+    // New escrows fill the last page
+    let newest = client.open_escrow(&client_addr, &Address::generate(&env), &1_000, &None);
+    let (ids, pages) = list(&client_addr, false);
+    assert_eq!((ids.last(), pages), (Some(&newest), 2));
+}
+
+/// Ledger entries `operation` writes, from snapshots of the ledger around it
+///
+/// The ledger time advances first, so that records rewritten with the time
+/// differ.
+fn written(env: &Env, operation: impl FnOnce()) -> std::collections::BTreeSet<LedgerKey> {
+    env.ledger().with_mut(|ledger| ledger.timestamp += 1);
+    let before: std::collections::BTreeMap<_, _> = env
+        .to_ledger_snapshot()
+        .ledger_entries
+        .into_iter()
+        .collect();
+    operation();
+    env.to_ledger_snapshot()
+        .ledger_entries
+        .into_iter()
+        .filter(|(key, entry)| before.get(key) != Some(entry))
+        .map(|(key, _)| *key)
+        .collect()
+}
+
+#[test]
+fn test_unrelated_footprints() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let server_addr = Address::generate(&env);
+    let shard = |escrow_id: u64| escrow_id % u64::from(ESCROW_SHARDS);
+
+    let first_client = Address::generate(&env);
+    let mut first_id = 0;
+    let first_open = written(&env, || {
+        first_id = client.open_escrow(&first_client, &server_addr, &1_000, &None);
+    });
+    let first_pay = written(&env, || {
+        client.create_payment(&first_id, &10);
+    });
+    assert!(!first_open.is_empty() && !first_pay.is_empty());
+
+    // Opens and payments of clients in other shards write no entry in
+    // common with the first client's, the contract instance included; in
+    // the same shard they share the shard's counter only
+    let mut compared = 0;
+    for _ in 0..8 {
+        let other_client = Address::generate(&env);
+        let mut other_id = 0;
+        let open = written(&env, || {
+            other_id = client.open_escrow(&other_client, &server_addr, &1_000, &None);
+        });
+        let pay = written(&env, || {
+            client.create_payment(&other_id, &10);
+        });
+        let shared = open.intersection(&first_open).count();
+        if shard(other_id) == shard(first_id) {
+            assert_eq!(shared, 1);
+        } else {
+            assert_eq!(shared, 0);
+            compared += 1;
+        }
+        assert_eq!(pay.intersection(&first_pay).count(), 0);
+        assert_eq!(pay.intersection(&first_open).count(), 0);
+    }
+    assert!(compared > 0);
+}
+
+#[test]
+fn test_legacy_records() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let (client_addr, server_addr) = (Address::generate(&env), Address::generate(&env));
+    let other_client = Address::generate(&env);
+
+    // Two escrows and their payments as the global counters stored them,
+    // payment 0 of escrow 1 and payment 1 of escrow 0
+    let escrow = |client: &Address| LegacyEscrow {
+        client: client.clone(),
+        server: server_addr.clone(),
+        balance: 1_000,
+        client_closed: false,
+        server_closed: false,
+    };
+    let payment = |escrow_id: u64| LegacyPayment {
+        escrow_id,
+        amount: 100,
+        settled: false,
+        timestamp: 0,
+    };
+    env.as_contract(&contract_id, || {
+        let storage = env.storage().instance();
+        storage.set(&DataKey::EscrowCounter, &2u64);
+        storage.set(&DataKey::PaymentCounter, &2u64);
+        for (escrow_id, client) in [(0u64, &client_addr), (1, &other_client)] {
+            storage.set(&DataKey::Escrow(escrow_id), &escrow(client));
+            let lookup = DataKey::ClientServerEscrow(client.clone(), server_addr.clone());
+            storage.set(&lookup, &escrow_id);
+            storage.set(&DataKey::EscrowPaymentCount(escrow_id), &1u32);
+            storage.set(&DataKey::EscrowPayment(escrow_id, 0), &(1 - escrow_id));
+            storage.set(&DataKey::Payment(1 - escrow_id), &payment(escrow_id));
+        }
+    });
+
+    // Legacy IDs resolve, and are listed ahead of newer escrows
+    assert_eq!(
+        client.get_escrow(&0),
+        Escrow {
+            client: client_addr.clone(),
+            server: server_addr.clone(),
+            balance: 1_000,
+            client_closed: false,
+            server_closed: false,
+            terms_hash: None,
+            trial: false,
+            trial_balance: 0,
+            pending: 100,
+            pending_payments: 1,
+        }
+    );
+    assert_eq!(client.find_escrow(&other_client, &server_addr), Some(1));
+    assert_eq!(
+        client.get_payment(&1),
+        Payment {
+            escrow_id: 0,
+            amount: 100,
+            settled: false,
+            timestamp: 0,
+            quote_amount: None,
+            quote_currency: None,
+        }
+    );
+    let escrow_id = client.open_escrow(&Address::generate(&env), &server_addr, &1_000, &None);
+    assert!((2..2 + u64::from(ESCROW_SHARDS)).contains(&escrow_id));
+    let page = client.export_escrows(&None, &0, &2);
+    assert_eq!(page.escrows.get(0).unwrap().escrow_id, 0);
+    assert_eq!(page.escrows.get(1).unwrap().escrow_id, 1);
+    assert_eq!(page.next_start, Some(2));
+
+    // A new payment of escrow 0 skips the ID a legacy payment holds
+    assert_eq!(client.create_payment(&0, &100), 2);
+    let listed = client.get_payments(&0, &None, &0, &10).payments;
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed.get(0).unwrap().payment_id, 1);
+
+    // Rewritten records move to persistent storage, with their unsettled
+    // payments totalled
+    client.settle_payment(&terms(&env, &client, 1));
+    assert_eq!(client.get_escrow_balance(&0), 900);
+    let escrow = client.get_escrow(&0);
+    assert_eq!((escrow.pending, escrow.pending_payments), (100, 1));
+    env.as_contract(&contract_id, || {
+        for key in [DataKey::Escrow(0), DataKey::Payment(1)] {
+            assert!(!env.storage().instance().has(&key));
+            assert!(env.storage().persistent().has(&key));
+        }
+        assert!(env.storage().instance().has(&DataKey::Escrow(1)));
+    });
+}
+
+#[test]
+fn test_escrow_migration() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    // Old and new deployments in one environment
+    let old_id = env.register(X402EscrowContract, ());
+    let old = X402EscrowContractClient::new(&env, &old_id);
+    let new_id = env.register(X402EscrowContract, ());
+    let new = X402EscrowContractClient::new(&env, &new_id);
+    let admin = Address::generate(&env);
+    old.initialize(&admin);
+    new.initialize(&admin);
+    new.allow_migration_source(&admin, &old_id);
+
+    let client_addr = Address::generate(&env);
+    let server_addr = Address::generate(&env);
+    let escrow_id = old.open_escrow(&client_addr, &server_addr, &1_000_000, &None);
+    let child_id = old.split_escrow(&escrow_id, &100_000, &Bytes::new(&env));
+    old.set_max_settled_per_hour(&escrow_id, &Some(500_000));
+    old.post_note(&escrow_id, &server_addr, &Bytes::from_slice(&env, b"moving"));
+    let settled = old.create_payment(&escrow_id, &100_000);
+    old.settle_payment(&terms(&env, &old, settled));
+    old.create_payment(&escrow_id, &200_000);
+    old.create_payment(&escrow_id, &300_000);
+
+    // Both parties consent before the admin exports
+    old.consent_to_migration(&escrow_id, &client_addr, &new_id);
+    assert_eq!(
+        old.try_export_for_migration(&admin, &escrow_id),
+        Err(Ok(Error::MigrationNotApproved))
+    );
+    old.consent_to_migration(&escrow_id, &server_addr, &new_id);
+    let export = old.export_for_migration(&admin, &escrow_id);
+    assert_eq!(export.summary.source, old_id);
+    assert_eq!(export.summary.escrow.balance, 800_000);
+    assert_eq!(export.summary.pending.len(), 2);
+    assert_eq!(export.summary.payment_count, 3);
+    assert_eq!(export.summary.spend_limit, old.get_spend_limit(&escrow_id));
+    assert_eq!(export.summary.links.children, vec![&env, child_id]);
+    assert_eq!(export.summary.notes, old.get_notes(&escrow_id));
+    assert_eq!(old.get_migration(&escrow_id), Some(export.clone()));
+
+    let escrow_id_new = new.import_escrow(&export.summary, &export.proof);
+
+    // The escrow moved with its balance, unsettled payments, spend limit,
+    // links, and notes
+    assert_eq!(new.get_escrow_balance(&escrow_id_new), 800_000);
+    assert_eq!(new.get_spend_limit(&escrow_id_new), export.summary.spend_limit);
+    assert_eq!(new.get_escrow_links(&escrow_id_new), export.summary.links);
+    assert_eq!(new.get_notes(&escrow_id_new), export.summary.notes);
+    assert_eq!(
+        new.find_escrow(&client_addr, &server_addr),
+        Some(escrow_id_new)
+    );
+    let pending = new.get_payments(&escrow_id_new, &Some(PaymentStatus::Pending), &0, &10);
+    assert_eq!(pending.payments.len(), 2);
+    let first = pending.payments.get(0).unwrap();
+    assert_eq!(first.payment.amount, 200_000);
+    assert_eq!(first.payment.escrow_id, escrow_id_new);
+    new.settle_payment(&terms(&env, &new, first.payment_id));
+    assert_eq!(new.get_escrow_balance(&escrow_id_new), 600_000);
+
+    // The old one is closed, its export kept as a record
+    assert_eq!(
+        old.try_get_escrow(&escrow_id),
+        Err(Ok(Error::EscrowNotFound))
+    );
+    assert_eq!(old.find_escrow(&client_addr, &server_addr), None);
+    assert_eq!(old.get_migration(&escrow_id), Some(export));
+}
+
+#[test]
+fn test_migration_checks() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let old_id = env.register(X402EscrowContract, ());
+    let old = X402EscrowContractClient::new(&env, &old_id);
+    let new_id = env.register(X402EscrowContract, ());
+    let new = X402EscrowContractClient::new(&env, &new_id);
+    let admin = Address::generate(&env);
+    old.initialize(&admin);
+    new.initialize(&admin);
+
+    let client_addr = Address::generate(&env);
+    let server_addr = Address::generate(&env);
+    let escrow_id = old.open_escrow(&client_addr, &server_addr, &1_000_000, &None);
+    let payment_id = old.create_payment(&escrow_id, &100_000);
+
+    // Only the parties consent, and only to the same target
+    assert_eq!(
+        old.try_consent_to_migration(&escrow_id, &Address::generate(&env), &new_id),
+        Err(Ok(Error::InvalidSigner))
+    );
+    old.consent_to_migration(&escrow_id, &client_addr, &new_id);
+    old.consent_to_migration(&escrow_id, &server_addr, &Address::generate(&env));
+    assert_eq!(
+        old.try_export_for_migration(&admin, &escrow_id),
+        Err(Ok(Error::MigrationNotApproved))
+    );
+    old.consent_to_migration(&escrow_id, &client_addr, &new_id);
+    old.consent_to_migration(&escrow_id, &server_addr, &new_id);
+    let export = old.export_for_migration(&admin, &escrow_id);
+
+    // An escrow with more unsettled payments than one summary holds stays
+    let busy_client = Address::generate(&env);
+    let busy_id = old.open_escrow(&busy_client, &server_addr, &1_000_000, &None);
+    for _ in 0..=MAX_PAYMENTS_PAGE {
+        old.create_payment(&busy_id, &1);
+    }
+    old.consent_to_migration(&busy_id, &busy_client, &new_id);
+    old.consent_to_migration(&busy_id, &server_addr, &new_id);
+    assert_eq!(
+        old.try_export_for_migration(&admin, &busy_id),
+        Err(Ok(Error::TooManyPendingPayments))
+    );
+
+    // The frozen escrow takes nothing until imported
+    assert_eq!(
+        old.try_create_payment(&escrow_id, &1),
+        Err(Ok(Error::EscrowFrozen))
+    );
+    assert_eq!(
+        old.try_settle_payment(&terms(&env, &old, payment_id)),
+        Err(Ok(Error::EscrowFrozen))
+    );
+    assert_eq!(
+        old.try_deposit(&escrow_id, &1),
+        Err(Ok(Error::EscrowFrozen))
+    );
+    assert_eq!(
+        old.try_client_close_escrow(&escrow_id),
+        Err(Ok(Error::EscrowFrozen))
+    );
+    assert_eq!(
+        old.try_export_for_migration(&admin, &escrow_id),
+        Err(Ok(Error::EscrowFrozen))
+    );
+
+    // The target accepts only allowed sources, and only the summary proven
+    assert_eq!(
+        new.try_import_escrow(&export.summary, &export.proof),
+        Err(Ok(Error::MigrationNotApproved))
+    );
+    new.allow_migration_source(&admin, &old_id);
+    let mut inflated = export.summary.clone();
+    inflated.escrow.balance *= 10;
+    assert_eq!(
+        new.try_import_escrow(&inflated, &export.proof),
+        Err(Ok(Error::MigrationMismatch))
+    );
+    let other_id = env.register(X402EscrowContract, ());
+    let other = X402EscrowContractClient::new(&env, &other_id);
+    other.initialize(&admin);
+    other.allow_migration_source(&admin, &old_id);
+    assert_eq!(
+        other.try_import_escrow(&export.summary, &export.proof),
+        Err(Ok(Error::MigrationMismatch))
+    );
+
+    // Imported once only
+    new.import_escrow(&export.summary, &export.proof);
+    assert_eq!(
+        new.try_import_escrow(&export.summary, &export.proof),
+        Err(Ok(Error::EscrowAlreadyExists))
+    );
+    assert_eq!(
+        old.try_complete_migration(&escrow_id, &export.proof),
+        Err(Ok(Error::EscrowNotFound))
+    );
+}
+
+/// Escrows are exported once the authorizations spent from them expired,
+/// as their nonces stay spent at the source only
+#[cfg(feature = "signed-auth")]
+#[test]
+fn test_migration_waits_for_authorizations() {
+    let env = Env::default();
+    env.mock_all_auths();
+    use_testnet(&env);
+
+    let old_id = env.register(X402EscrowContract, ());
+    let old = X402EscrowContractClient::new(&env, &old_id);
+    let new_id = env.register(X402EscrowContract, ());
+    let new = X402EscrowContractClient::new(&env, &new_id);
+    let admin = Address::generate(&env);
+    old.initialize(&admin);
+    new.initialize(&admin);
+    new.allow_migration_source(&admin, &old_id);
+
+    let (client_key, client_addr) = keypair(&env, 1);
+    let server_addr = Address::generate(&env);
+    let escrow_id = old.open_escrow(&client_addr, &server_addr, &1_000_000, &None);
+    let (authorization, key, signature) =
+        authorize(&env, &old_id, &client_key, escrow_id, 100_000, 1);
+    old.create_authorized_payment(&authorization, &key, &signature);
+    old.consent_to_migration(&escrow_id, &client_addr, &new_id);
+    old.consent_to_migration(&escrow_id, &server_addr, &new_id);
+    assert_eq!(
+        old.try_export_for_migration(&admin, &escrow_id),
+        Err(Ok(Error::AuthorizationsLive))
+    );
+
+    // Within the clock skew tolerance, it may still be presented
+    old.set_clock_skew(&admin, &60);
+    env.ledger()
+        .with_mut(|ledger| ledger.timestamp = authorization.expires_at + 60);
+    assert_eq!(
+        old.try_export_for_migration(&admin, &escrow_id),
+        Err(Ok(Error::AuthorizationsLive))
+    );
+    env.ledger()
+        .with_mut(|ledger| ledger.timestamp = authorization.expires_at + 61);
+    let export = old.export_for_migration(&admin, &escrow_id);
+    let escrow_id_new = new.import_escrow(&export.summary, &export.proof);
+    assert_eq!(new.get_escrow(&escrow_id_new).pending_payments, 1);
+}
+
+#[test]
+fn test_sweep_dust() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    let server_addr = Address::generate(&env);
+    let ids = |ids: &[u64]| Vec::from_slice(&env, ids);
+
+    // Nothing is swept before the admin sets a policy, at least MIN_DUST_IDLE
+    assert_eq!(
+        client.try_sweep_dust(&admin, &ids(&[])),
+        Err(Ok(Error::DustPolicyNotSet))
+    );
+    assert_eq!(
+        client.try_set_dust_policy(&admin, &100, &(MIN_DUST_IDLE - 1)),
+        Err(Ok(Error::InvalidDustPolicy))
+    );
+    client.set_dust_policy(&admin, &100, &MIN_DUST_IDLE);
+
+    env.ledger().set_timestamp(1_000);
+    let (dust_client, refund_to) = (Address::generate(&env), Address::generate(&env));
+    client.set_refund_address(&dust_client, &refund_to);
+    let dust = client.open_escrow(&dust_client, &server_addr, &99, &None);
+    let funded = client.open_escrow(&Address::generate(&env), &server_addr, &100, &None);
+    let pending = client.open_escrow(&Address::generate(&env), &server_addr, &150, &None);
+    client.create_payment(&pending, &60);
+    let active = client.open_escrow(&Address::generate(&env), &server_addr, &10, &None);
+
+    // Escrows are swept only once idle for the whole window
+    env.ledger().set_timestamp(1_000 + MIN_DUST_IDLE - 1);
+    client.deposit(&active, &1);
+    assert_eq!(
+        client.try_sweep_dust(&admin, &ids(&[dust])),
+        Err(Ok(Error::EscrowNotDust))
+    );
+    env.ledger().set_timestamp(1_000 + MIN_DUST_IDLE);
+    assert_eq!(client.get_last_activity(&dust), 1_000);
+    assert_eq!(
+        client.try_sweep_dust(&admin, &ids(&[dust, active])),
+        Err(Ok(Error::EscrowNotDust))
+    );
+    assert_eq!(
+        client.try_sweep_dust(&admin, &ids(&[dust, funded])),
+        Err(Ok(Error::EscrowNotDust))
+    );
+    assert_eq!(
+        client.try_sweep_dust(&admin, &ids(&[pending])),
+        Err(Ok(Error::PendingPayments))
+    );
+    assert_eq!(
+        client.try_sweep_dust(&Address::generate(&env), &ids(&[dust])),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(client.get_escrow_balance(&dust), 99);
+
+    // The dust goes to the client's refund address, never the admin
+    assert_eq!(client.sweep_dust(&admin, &ids(&[dust])), 99);
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(topics, (symbol_short!("swept"), dust).into_val(&env));
+    let swept: SweptEvent = data.into_val(&env);
+    assert_eq!(
+        swept,
+        SweptEvent {
+            event_version: EVENT_VERSION,
+            client: dust_client.clone(),
+            refund_to,
+            balance: 99,
+        }
+    );
+    assert_eq!(client.try_get_escrow(&dust), Err(Ok(Error::EscrowNotFound)));
+    assert_eq!(client.find_escrow(&dust_client, &server_addr), None);
+    assert_eq!(
+        client.try_sweep_dust(&admin, &ids(&[dust])),
+        Err(Ok(Error::EscrowNotFound))
+    );
+
+    // Without a refund address the client itself gets the dust
+    let pending_client = client.get_escrow(&pending).client;
+    assert_eq!(client.get_refund_address(&pending_client), pending_client);
+    client.settle_payment(&terms(&env, &client, 0));
+    env.ledger().set_timestamp(1_000 + 2 * MIN_DUST_IDLE);
+    assert_eq!(client.sweep_dust(&admin, &ids(&[pending, active])), 101);
+}
+
+/// Price feed returning the prices it was given, with 14 decimals
+#[cfg(feature = "oracle")]
+#[contract]
+struct MockOracle;
+
+#[cfg(feature = "oracle")]
+#[contractimpl]
+impl MockOracle {
+    pub fn set_price(env: Env, price: i128, twap: i128, timestamp: u64) {
+        let last = PriceData { price, timestamp };
+        env.storage().instance().set(&symbol_short!("last"), &last);
+        env.storage().instance().set(&symbol_short!("twap"), &twap);
+    }
+
+    pub fn decimals(_env: Env) -> u32 {
+        14
+    }
+
+    pub fn lastprice(env: Env, _asset: Asset) -> Option<PriceData> {
+        env.storage().instance().get(&symbol_short!("last"))
+    }
+
+    pub fn twap(env: Env, _asset: Asset, _records: u32) -> Option<i128> {
+        env.storage().instance().get(&symbol_short!("twap"))
+    }
+}
+
+/// USD price with 14 decimals
+#[cfg(feature = "oracle")]
+const fn usd(cents: i128) -> i128 {
+    cents * 1_000_000_000_000
+}
+
+#[cfg(feature = "oracle")]
+struct Priced<'a> {
+    env: Env,
+    client: X402EscrowContractClient<'a>,
+    oracle: MockOracleClient<'a>,
+    admin: Address,
+    server: Address,
+    escrow_id: u64,
+}
+
+#[cfg(feature = "oracle")]
+fn setup_pricing<'a>() -> Priced<'a> {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().set_timestamp(10_000);
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let oracle = MockOracleClient::new(&env, &env.register(MockOracle, ()));
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    client.set_price_oracle(&admin, &oracle.address, &Asset::Other(symbol_short!("XLM")));
+    let server = Address::generate(&env);
+    let escrow_id = client.open_escrow(&Address::generate(&env), &server, &100_000_000, &None);
+
+    Priced {
+        env,
+        client,
+        oracle,
+        admin,
+        server,
+        escrow_id,
+    }
+}
+
+#[cfg(feature = "oracle")]
+#[test]
+fn test_usd_pricing() {
+    let p = setup_pricing();
+    let weather = String::from_str(&p.env, "/weather");
+
+    // 50 cents at $0.25 per XLM
+    p.oracle.set_price(&usd(25), &usd(25), &10_000);
+    assert_eq!(
+        p.client.try_quote_usd(&p.server, &weather),
+        Err(Ok(Error::PriceNotSet))
+    );
+    p.client.set_usd_price(&p.server, &weather, &50);
+    assert_eq!(p.client.quote_usd(&p.server, &weather), 20_000_000);
+
+    let payment_id = p.client.create_payment_usd(&p.escrow_id, &50, &weather);
+    assert_eq!(p.client.get_payment(&payment_id).amount, 20_000_000);
+
+    // Rounded up: 1 cent at $0.30 is 333_333.33 stroops
+    p.oracle.set_price(&usd(30), &usd(30), &10_000);
+    let payment_id = p.client.create_payment_usd(&p.escrow_id, &1, &weather);
+    assert_eq!(p.client.get_payment(&payment_id).amount, 333_334);
+
+    // Only the admin points pricing at a feed
+    let other = Address::generate(&p.env);
+    assert_eq!(
+        p.client
+            .try_set_price_oracle(&other, &other, &Asset::Other(symbol_short!("XLM"))),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(p.client.get_price_oracle().oracle, p.oracle.address);
+}
+
+#[cfg(feature = "oracle")]
+#[test]
+fn test_stale_price_rejected() {
+    let p = setup_pricing();
+    let weather = String::from_str(&p.env, "/weather");
+    p.client.set_usd_price(&p.server, &weather, &50);
+
+    let published = 10_000 - DEFAULT_MAX_PRICE_AGE - 1;
+    p.oracle.set_price(&usd(25), &usd(25), &published);
+    assert_eq!(
+        p.client.try_quote_usd(&p.server, &weather),
+        Err(Ok(Error::StalePrice))
+    );
+    assert_eq!(
+        p.client.try_create_payment_usd(&p.escrow_id, &50, &weather),
+        Err(Ok(Error::StalePrice))
+    );
+
+    p.client.set_price_limits(&p.admin, &600, &100);
+    assert_eq!(p.client.quote_usd(&p.server, &weather), 20_000_000);
+}
+
+#[cfg(feature = "oracle")]
+#[test]
+fn test_price_slippage_rejected() {
+    let p = setup_pricing();
+    let weather = String::from_str(&p.env, "/weather");
+    p.client.set_usd_price(&p.server, &weather, &50);
+
+    // 4.17% above the average, beyond the 1% default
+    p.oracle.set_price(&usd(25), &usd(24), &10_000);
+    assert_eq!(
+        p.client.try_quote_usd(&p.server, &weather),
+        Err(Ok(Error::PriceSlippage))
+    );
+
+    p.client
+        .set_price_limits(&p.admin, &DEFAULT_MAX_PRICE_AGE, &500);
+    assert_eq!(p.client.quote_usd(&p.server, &weather), 20_000_000);
+    assert_eq!(
+        p.client.try_set_price_limits(&p.server, &600, &0),
+        Err(Ok(Error::Unauthorized))
+    );
+}
+
+#[test]
+fn test_oracle_not_set() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let (client_addr, server_addr) = (Address::generate(&env), Address::generate(&env));
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &1_000, &None);
+
+    let resource = String::from_str(&env, "/weather");
+    assert_eq!(
+        client.try_create_payment_usd(&escrow_id, &50, &resource),
+        Err(Ok(Error::OracleNotSet))
+    );
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    assert_eq!(
+        client.try_set_price_limits(&admin, &600, &0),
+        Err(Ok(Error::OracleNotSet))
+    );
+}
+
+#[cfg(feature = "oracle")]
+#[test]
+fn test_settlement_quote() {
+    let p = setup_pricing();
+    let terms = |payment_id| terms(&p.env, &p.client, payment_id);
+
+    // 2 XLM at $0.25, then 1 stroop, worth less than a cent
+    p.oracle.set_price(&usd(25), &usd(25), &10_000);
+    let payment_id = p.client.create_payment(&p.escrow_id, &20_000_000);
+    assert_eq!(p.client.get_payment(&payment_id).quote_amount, None);
+    p.client.settle_payment(&terms(payment_id));
+
+    let payment = p.client.get_payment(&payment_id);
+    assert_eq!(payment.quote_amount, Some(50));
+    assert_eq!(payment.quote_currency, Some(symbol_short!("USD")));
+    let (_, _, data) = p.env.events().all().last().unwrap();
+    let settled: PaymentSettledEvent = data.into_val(&p.env);
+    assert_eq!(settled.quote_amount, Some(50));
+    assert_eq!(settled.quote_currency, Some(symbol_short!("USD")));
+
+    let dust = p.client.create_payment(&p.escrow_id, &1);
+    p.oracle.set_price(&usd(30), &usd(30), &10_000);
+    let later = p.client.create_payment(&p.escrow_id, &10_000_000);
+    p.client.settle_payments(&vec![&p.env, terms(dust), terms(later)]);
+    assert_eq!(p.client.get_payment(&dust).quote_amount, Some(0));
+    assert_eq!(p.client.get_payment(&later).quote_amount, Some(30));
+
+    assert_eq!(
+        p.client.get_quote_total(&p.escrow_id),
+        QuoteTotal {
+            currency: symbol_short!("USD"),
+            quote_amount: 80,
+            quoted: 30_000_001,
+            unquoted: 0,
+        }
+    );
+}
+
+#[cfg(feature = "oracle")]
+#[test]
+fn test_settlement_quote_unavailable() {
+    let p = setup_pricing();
+
+    // Nothing published yet, then a stale price: settled unquoted
+    let unpriced = p.client.create_payment(&p.escrow_id, &1_000);
+    p.client.settle_payment(&terms(&p.env, &p.client, unpriced));
+    p.oracle
+        .set_price(&usd(25), &usd(25), &(10_000 - DEFAULT_MAX_PRICE_AGE - 1));
+    let stale = p.client.create_payment(&p.escrow_id, &2_000);
+    p.client.settle_payment(&terms(&p.env, &p.client, stale));
+
+    for payment_id in [unpriced, stale] {
+        let payment = p.client.get_payment(&payment_id);
+        assert!(payment.settled);
+        assert_eq!(payment.quote_amount, None);
+        assert_eq!(payment.quote_currency, None);
+    }
+    let total = p.client.get_quote_total(&p.escrow_id);
+    assert_eq!((total.quote_amount, total.quoted, total.unquoted), (0, 0, 3_000));
+    assert_eq!(p.client.get_escrow_balance(&p.escrow_id), 100_000_000 - 3_000);
+
+    // Without a price feed at all
+    let env = Env::default();
+    env.mock_all_auths();
+    let client = X402EscrowContractClient::new(&env, &env.register(X402EscrowContract, ()));
+    let (client_addr, server_addr) = (Address::generate(&env), Address::generate(&env));
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &1_000, &None);
+    let payment_id = client.create_payment(&escrow_id, &400);
+    client.settle_payment(&terms(&env, &client, payment_id));
+    assert_eq!(client.get_payment(&payment_id).quote_amount, None);
+    assert_eq!(client.get_quote_total(&escrow_id).unquoted, 400);
+}
+
+#[cfg(feature = "oracle")]
+#[test]
+fn test_promo_pricing() {
+    let p = setup_pricing();
+    let weather = String::from_str(&p.env, "/weather");
+    p.oracle.set_price(&usd(25), &usd(25), &10_000);
+    p.client.set_usd_price(&p.server, &weather, &50);
+
+    // Half off from 10_100 until 10_200, the end itself excluded
+    p.client
+        .set_promo(&p.server, &weather, &25, &10_100, &10_200);
+    for (timestamp, quote) in [
+        (10_099, 20_000_000),
+        (10_100, 10_000_000),
+        (10_199, 10_000_000),
+        (10_200, 20_000_000),
+    ] {
+        p.env.ledger().set_timestamp(timestamp);
+        assert_eq!(
+            p.client.quote_usd(&p.server, &weather),
+            quote,
+            "at {timestamp}"
+        );
+    }
+
+    // The promo caps what the server may charge while it runs
+    p.env.ledger().set_timestamp(10_100);
+    assert_eq!(
+        p.client.try_create_payment_usd(&p.escrow_id, &50, &weather),
+        Err(Ok(Error::PriceExceeded))
+    );
+    let payment_id = p.client.create_payment_usd(&p.escrow_id, &25, &weather);
+    assert_eq!(p.client.get_payment(&payment_id).amount, 10_000_000);
+    p.env.ledger().set_timestamp(10_200);
+    p.client.create_payment_usd(&p.escrow_id, &50, &weather);
+
+    // Of overlapping promos, the one set last wins
+    p.env.ledger().set_timestamp(10_150);
+    p.client
+        .set_promo(&p.server, &weather, &40, &10_150, &10_250);
+    assert_eq!(p.client.quote_usd(&p.server, &weather), 16_000_000);
+    p.client
+        .set_promo(&p.server, &weather, &30, &10_000, &10_300);
+    assert_eq!(p.client.quote_usd(&p.server, &weather), 12_000_000);
+
+    // Ended promos are dropped
+    p.env.ledger().set_timestamp(10_260);
+    assert_eq!(
+        p.client.get_promos(&p.server, &weather),
+        vec![
+            &p.env,
+            Promo {
+                usd_cents: 30,
+                starts_at: 10_000,
+                ends_at: 10_300
+            }
+        ]
+    );
+    p.env.ledger().set_timestamp(10_300);
+    assert_eq!(p.client.quote_usd(&p.server, &weather), 20_000_000);
+
+    // A promo is the only price of a resource without a regular one
+    let maps = String::from_str(&p.env, "/maps");
+    p.client.set_promo(&p.server, &maps, &25, &10_300, &10_400);
+    assert_eq!(p.client.quote_usd(&p.server, &maps), 10_000_000);
+    p.env.ledger().set_timestamp(10_400);
+    assert_eq!(
+        p.client.try_quote_usd(&p.server, &maps),
+        Err(Ok(Error::PriceNotSet))
+    );
+
+    // Prices and promos are records of their own, outside the contract
+    // instance
+    p.env.as_contract(&p.client.address, || {
+        for key in [
+            DataKey::UsdPrice(p.server.clone(), weather.clone()),
+            DataKey::Promos(p.server.clone(), weather.clone()),
+            DataKey::Promos(p.server.clone(), maps.clone()),
+        ] {
+            assert!(p.env.storage().persistent().has(&key));
+            assert!(!p.env.storage().instance().has(&key));
+        }
+    });
+}
+
+#[cfg(feature = "oracle")]
+#[test]
+fn test_invalid_promo() {
+    let p = setup_pricing();
+    let weather = String::from_str(&p.env, "/weather");
+
+    for (usd_cents, starts_at, ends_at) in [
+        (-1, 10_000, 10_100),
+        (25, 10_100, 10_100),
+        (25, 9_000, 10_000),
+    ] {
+        assert_eq!(
+            p.client
+                .try_set_promo(&p.server, &weather, &usd_cents, &starts_at, &ends_at),
+            Err(Ok(Error::InvalidPromo))
+        );
+    }
+
+    for i in 0..MAX_PROMOS as u64 {
+        p.client
+            .set_promo(&p.server, &weather, &25, &(10_100 + i), &10_200);
+    }
+    assert_eq!(
+        p.client
+            .try_set_promo(&p.server, &weather, &25, &10_100, &10_200),
+        Err(Ok(Error::InvalidPromo))
+    );
+
+    // Room frees up as promos end
+    p.env.ledger().set_timestamp(10_200);
+    p.client
+        .set_promo(&p.server, &weather, &25, &10_200, &10_300);
+    assert_eq!(p.client.get_promos(&p.server, &weather).len(), 1);
+}
+
+#[cfg(feature = "oracle")]
+#[test]
+fn test_suspended_price() {
+    let p = setup_pricing();
+    let weather = String::from_str(&p.env, "/weather");
+    let maps = String::from_str(&p.env, "/maps");
+    p.oracle.set_price(&usd(25), &usd(25), &10_000);
+    p.client.set_usd_price(&p.server, &weather, &50);
+    p.client.set_usd_price(&p.server, &maps, &25);
+
+    p.client.suspend_price(&p.server, &weather);
+    assert_eq!(
+        p.client.quote(&p.server, &weather),
+        Quote { amount: 20_000_000, suspended: true }
+    );
+    // Each suspension is a record of its own, outside the contract instance
+    let suspended_key = DataKey::Suspended(p.server.clone(), weather.clone());
+    p.env.as_contract(&p.client.address, || {
+        assert!(p.env.storage().persistent().has(&suspended_key));
+        assert!(!p.env.storage().instance().has(&suspended_key));
+    });
+    assert_eq!(
+        p.client.try_create_payment_usd(&p.escrow_id, &50, &weather),
+        Err(Ok(Error::ResourceSuspended))
+    );
+
+    // Other resources of the escrow are still sold
+    assert_eq!(
+        p.client.quote(&p.server, &maps),
+        Quote { amount: 10_000_000, suspended: false }
+    );
+    let payment_id = p.client.create_payment_usd(&p.escrow_id, &25, &maps);
+    assert_eq!(p.client.get_payment(&payment_id).amount, 10_000_000);
+
+    // Suspending applies to the server's own resource only
+    let other = Address::generate(&p.env);
+    p.client.suspend_price(&other, &maps);
+    assert!(!p.client.quote(&p.server, &maps).suspended);
+
+    p.client.resume_price(&p.server, &weather);
+    assert!(!p.client.quote(&p.server, &weather).suspended);
+    p.env.as_contract(&p.client.address, || {
+        assert!(!p.env.storage().persistent().has(&suspended_key));
+    });
+    let payment_id = p.client.create_payment_usd(&p.escrow_id, &50, &weather);
+    assert_eq!(p.client.get_payment(&payment_id).amount, 20_000_000);
+}
+
+#[test]
+fn test_escrow_notes() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().set_timestamp(1_000);
+    env.ledger().set_sequence_number(10);
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let client_addr = Address::generate(&env);
+    let server_addr = Address::generate(&env);
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &1_000, &None);
+
+    let top_up = Bytes::from_slice(&env, b"please top up before Friday");
+    client.post_note(&escrow_id, &server_addr, &top_up);
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(
+        topics,
+        (symbol_short!("note"), escrow_id, server_addr.clone()).into_val(&env)
+    );
+    let posted: NoteEvent = data.into_val(&env);
+    assert_eq!(posted.body, top_up);
+
+    // One note per party per ledger
+    assert_eq!(
+        client.try_post_note(&escrow_id, &server_addr, &top_up),
+        Err(Ok(Error::NoteRateLimited))
+    );
+    let ack = Bytes::from_slice(&env, b"done");
+    client.post_note(&escrow_id, &client_addr, &ack);
+    assert_eq!(
+        client.get_notes(&escrow_id),
+        vec![
+            &env,
+            Note {
+                author: server_addr.clone(),
+                ledger: 10,
+                timestamp: 1_000,
+                body: top_up.clone(),
+            },
+            Note {
+                author: client_addr.clone(),
+                ledger: 10,
+                timestamp: 1_000,
+                body: ack,
+            },
+        ]
+    );
+
+    // Only the parties post, and only short notes
+    assert_eq!(
+        client.try_post_note(&escrow_id, &Address::generate(&env), &top_up),
+        Err(Ok(Error::InvalidSigner))
+    );
+    for body in [
+        Bytes::new(&env),
+        Bytes::from_slice(&env, &[b'x'; MAX_NOTE_LEN as usize + 1]),
+    ] {
+        assert_eq!(
+            client.try_post_note(&escrow_id, &client_addr, &body),
+            Err(Ok(Error::InvalidNote))
+        );
+    }
+    assert_eq!(
+        client.try_post_note(&99, &client_addr, &top_up),
+        Err(Ok(Error::EscrowNotFound))
+    );
+
+    // The oldest notes are evicted once the ring is full
+    for i in 0..MAX_NOTES {
+        env.ledger().set_sequence_number(11 + i);
+        client.post_note(
+            &escrow_id,
+            &client_addr,
+            &Bytes::from_slice(&env, &[i as u8]),
+        );
+    }
+    let notes = client.get_notes(&escrow_id);
+    assert_eq!(notes.len(), MAX_NOTES);
+    assert_eq!(notes.first().unwrap().body, Bytes::from_slice(&env, &[0]));
+    assert_eq!(
+        notes.last().unwrap().body,
+        Bytes::from_slice(&env, &[MAX_NOTES as u8 - 1])
+    );
+
+    // Notes go with the escrow
+    client.client_close_escrow(&escrow_id);
+    client.server_close_escrow(&escrow_id);
+    assert_eq!(client.get_notes(&escrow_id).len(), 0);
+}
+
+/// Account of the ed25519 key derived from `seed`, with the key
+#[cfg(feature = "signed-auth")]
+fn keypair(env: &Env, seed: u8) -> (ed25519_dalek::SigningKey, Address) {
+    let key = ed25519_dalek::SigningKey::from_bytes(&[seed; 32]);
+    let strkey = x402_types::account_strkey(key.verifying_key().as_bytes());
+    let account = Address::from_str(env, core::str::from_utf8(&strkey).unwrap());
+    (key, account)
+}
+
+#[cfg(feature = "signed-auth")]
+#[test]
+fn test_verify_voucher() {
+    use ed25519_dalek::Signer as _;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let (client_key, client_addr) = keypair(&env, 1);
+    let (server_key, server_addr) = keypair(&env, 2);
+    let escrow_id = client.open_escrow(&client_addr, &server_addr, &1_000_000, &None);
+
+    let mut strkey = [0; x402_types::STRKEY_LEN];
+    contract_id.to_string().copy_into_slice(&mut strkey);
+    let domain = x402_types::SigningDomain {
+        network_id: env.ledger().network_id().to_array(),
+        contract_id: core::str::from_utf8(&strkey).unwrap(),
+    };
+    let hash = x402_types::EscrowVoucher {
+        domain,
+        escrow_id,
+        amount: 400,
+        sequence: 2,
+        expires_at: 1_000,
+    }
+    .signing_hash();
+    let sign =
+        |key: &ed25519_dalek::SigningKey| BytesN::from_array(&env, &key.sign(&hash).to_bytes());
+    let public_key =
+        |key: &ed25519_dalek::SigningKey| BytesN::from_array(&env, key.verifying_key().as_bytes());
+
+    let mut voucher = Voucher {
+        escrow_id,
+        amount: 400,
+        sequence: 2,
+        expires_at: 1_000,
+    };
+    client.verify_voucher(&voucher, &public_key(&client_key), &sign(&client_key));
+
+    // Vouchers are the client's to sign
+    assert_eq!(
+        client.try_verify_voucher(&voucher, &public_key(&server_key), &sign(&server_key)),
+        Err(Ok(Error::InvalidSigner))
+    );
+    // Signatures cover every field
+    voucher.sequence = 3;
+    assert!(client
+        .try_verify_voucher(&voucher, &public_key(&client_key), &sign(&client_key))
+        .is_err());
+    voucher.escrow_id = 99;
+    assert_eq!(
+        client.try_verify_voucher(&voucher, &public_key(&client_key), &sign(&client_key)),
+        Err(Ok(Error::EscrowNotFound))
+    );
+
+    // Channel states are either party's to sign
+    let hash = x402_types::EscrowChannelState {
+        domain,
+        escrow_id,
+        sequence: 1,
+        balance: 600,
+        settled: 400,
+    }
+    .signing_hash();
+    let state = ChannelState {
+        escrow_id,
+        sequence: 1,
+        balance: 600,
+        settled: 400,
+    };
+    let signature = BytesN::from_array(&env, &server_key.sign(&hash).to_bytes());
+    client.verify_channel_state(&state, &public_key(&server_key), &signature);
+    let (other_key, _) = keypair(&env, 3);
+    let signature = BytesN::from_array(&env, &other_key.sign(&hash).to_bytes());
+    assert_eq!(
+        client.try_verify_channel_state(&state, &public_key(&other_key), &signature),
+        Err(Ok(Error::InvalidSigner))
+    );
+}
+
+#[cfg(feature = "signed-auth")]
+#[test]
+fn test_verify_authorization() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let (client_key, client_addr) = keypair(&env, 1);
+    let escrow_id = client.open_escrow(&client_addr, &Address::generate(&env), &1_000_000, &None);
+
+    let (authorization, public_key, signature) =
+        authorize(&env, &contract_id, &client_key, escrow_id, 2500, 9);
+
+    // The test ledger is on no known network
+    assert_eq!(
+        client.try_verify_authorization(&authorization, &public_key, &signature),
+        Err(Ok(Error::NetworkMismatch))
+    );
+
+    // Signed on the earlier network, the signature is void on testnet
+    use_testnet(&env);
+    assert!(client
+        .try_verify_authorization(&authorization, &public_key, &signature)
+        .is_err());
+    let (authorization, public_key, signature) =
+        authorize(&env, &contract_id, &client_key, escrow_id, 2500, 9);
+    client.verify_authorization(&authorization, &public_key, &signature);
+
+    // An authorization for one deployment is void on any other
+    let other_id = env.register(X402EscrowContract, ());
+    let other = X402EscrowContractClient::new(&env, &other_id);
+    assert_eq!(
+        other.open_escrow(&client_addr, &Address::generate(&env), &1_000_000, &None),
+        escrow_id
+    );
+    assert!(other
+        .try_verify_authorization(&authorization, &public_key, &signature)
+        .is_err());
+}
+
+/// Move the test ledger to testnet, the network authorizations name
+#[cfg(feature = "signed-auth")]
+fn use_testnet(env: &Env) {
+    let passphrase = x402_types::network_passphrase(x402_types::STELLAR_TESTNET).unwrap();
+    let network_id = env
+        .crypto()
+        .sha256(&Bytes::from_slice(env, passphrase.as_bytes()))
+        .to_array();
+    env.ledger()
+        .with_mut(|ledger| ledger.network_id = network_id);
+}
+
+/// Testnet authorization of `amount` from an escrow of `contract_id`, with
+/// the key and signature of `key`
+#[cfg(feature = "signed-auth")]
+fn authorize(
+    env: &Env,
+    contract_id: &Address,
+    key: &ed25519_dalek::SigningKey,
+    escrow_id: u64,
+    amount: i128,
+    nonce: u64,
+) -> (Authorization, BytesN<32>, BytesN<64>) {
+    use ed25519_dalek::Signer as _;
+
+    let expires_at = 10_000;
+    let mut strkey = [0; x402_types::STRKEY_LEN];
+    contract_id.to_string().copy_into_slice(&mut strkey);
+    let hash = x402_types::EscrowAuthorization {
+        domain: x402_types::SigningDomain {
+            network_id: env.ledger().network_id().to_array(),
+            contract_id: core::str::from_utf8(&strkey).unwrap(),
+        },
+        network: x402_types::STELLAR_TESTNET,
+        escrow_id,
+        amount: x402_types::Decimal::new(amount).as_str(),
+        nonce,
+        expires_at,
+    }
+    .signing_hash();
+    let authorization = Authorization {
+        escrow_id,
+        network: String::from_str(env, x402_types::STELLAR_TESTNET),
+        amount,
+        nonce,
+        expires_at,
+    };
+    (
+        authorization,
+        BytesN::from_array(env, key.verifying_key().as_bytes()),
+        BytesN::from_array(env, &key.sign(&hash).to_bytes()),
+    )
+}
+
+#[cfg(feature = "signed-auth")]
+#[test]
+fn test_agent_allowance() {
+    let env = Env::default();
+    env.mock_all_auths();
+    use_testnet(&env);
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let (client_key, client_addr) = keypair(&env, 1);
+    let (agent_key, agent_addr) = keypair(&env, 3);
+    let escrow_id = client.open_escrow(&client_addr, &Address::generate(&env), &10_000, &None);
+
+    for (agent, total_cap, per_payment_cap, expires_at) in [
+        (&agent_addr, 0, 0, 100),
+        (&agent_addr, 1_000, 0, 100),
+        (&agent_addr, 1_000, 1_001, 100),
+        (&agent_addr, 1_000, 400, 0),
+        (&client_addr, 1_000, 400, 100),
+    ] {
+        assert_eq!(
+            client.try_grant_agent(
+                &client_addr,
+                agent,
+                &total_cap,
+                &per_payment_cap,
+                &expires_at
+            ),
+            Err(Ok(Error::InvalidAllowance))
+        );
+    }
+    assert_eq!(client.get_allowance(&client_addr, &agent_addr), None);
+
+    client.grant_agent(&client_addr, &agent_addr, &1_000, &400, &100);
+    assert_eq!(
+        client.get_allowance(&client_addr, &agent_addr),
+        Some(Allowance {
+            total_cap: 1_000,
+            remaining: 1_000,
+            per_payment_cap: 400,
+            expires_at: 100,
+        })
+    );
+
+    // Verifying leaves the allowance untouched, paying spends it
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &agent_key, escrow_id, 300, 1);
+    client.verify_authorization(&authorization, &key, &signature);
+    let payment_id = client.create_authorized_payment(&authorization, &key, &signature);
+    assert_eq!(client.get_payment(&payment_id).amount, 300);
+    let remaining = |client: &X402EscrowContractClient| {
+        client
+            .get_allowance(&client_addr, &agent_addr)
+            .unwrap()
+            .remaining
+    };
+    assert_eq!(remaining(&client), 700);
+    assert_eq!(
+        client.try_create_authorized_payment(&authorization, &key, &signature),
+        Err(Ok(Error::AuthorizationUsed))
+    );
+
+    // Above the per-payment cap, then until the total cap is exhausted
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &agent_key, escrow_id, 401, 2);
+    assert_eq!(
+        client.try_verify_authorization(&authorization, &key, &signature),
+        Err(Ok(Error::AllowanceExceeded))
+    );
+    assert_eq!(
+        client.try_create_authorized_payment(&authorization, &key, &signature),
+        Err(Ok(Error::AllowanceExceeded))
+    );
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &agent_key, escrow_id, 400, 3);
+    client.create_authorized_payment(&authorization, &key, &signature);
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &agent_key, escrow_id, 301, 4);
+    assert_eq!(
+        client.try_create_authorized_payment(&authorization, &key, &signature),
+        Err(Ok(Error::AllowanceExceeded))
+    );
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &agent_key, escrow_id, 300, 5);
+    client.create_authorized_payment(&authorization, &key, &signature);
+    assert_eq!(remaining(&client), 0);
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &agent_key, escrow_id, 1, 6);
+    assert_eq!(
+        client.try_create_authorized_payment(&authorization, &key, &signature),
+        Err(Ok(Error::AllowanceExceeded))
+    );
+
+    // The client's own authorizations are bound by its balance only
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &client_key, escrow_id, 5_000, 7);
+    client.create_authorized_payment(&authorization, &key, &signature);
+    let (other_key, _) = keypair(&env, 4);
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &other_key, escrow_id, 1, 8);
+    assert_eq!(
+        client.try_create_authorized_payment(&authorization, &key, &signature),
+        Err(Ok(Error::InvalidSigner))
+    );
+    let page = client.get_payments(&escrow_id, &None, &0, &MAX_PAYMENTS_PAGE);
+    assert_eq!(page.payments.len(), 4);
+}
+
+#[cfg(feature = "signed-auth")]
+#[test]
+fn test_agent_allowance_expiry() {
+    let env = Env::default();
+    env.mock_all_auths();
+    use_testnet(&env);
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let (_, client_addr) = keypair(&env, 1);
+    let (agent_key, agent_addr) = keypair(&env, 3);
+    let escrow_id = client.open_escrow(&client_addr, &Address::generate(&env), &10_000, &None);
+    client.grant_agent(&client_addr, &agent_addr, &1_000, &400, &100);
+
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &agent_key, escrow_id, 100, 1);
+    env.ledger().set_timestamp(100);
+    client.verify_authorization(&authorization, &key, &signature);
+    env.ledger().set_timestamp(101);
+    assert_eq!(
+        client.try_verify_authorization(&authorization, &key, &signature),
+        Err(Ok(Error::AllowanceExpired))
+    );
+    assert_eq!(
+        client.try_create_authorized_payment(&authorization, &key, &signature),
+        Err(Ok(Error::AllowanceExpired))
+    );
+
+    // Granting again starts a fresh allowance
+    client.grant_agent(&client_addr, &agent_addr, &1_000, &400, &200);
+    client.create_authorized_payment(&authorization, &key, &signature);
+    assert_eq!(
+        client
+            .get_allowance(&client_addr, &agent_addr)
+            .unwrap()
+            .remaining,
+        900
+    );
+
+    // Authorizations expire on their own
+    env.ledger().set_timestamp(10_001);
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &agent_key, escrow_id, 100, 2);
+    assert_eq!(
+        client.try_create_authorized_payment(&authorization, &key, &signature),
+        Err(Ok(Error::AuthorizationExpired))
+    );
+}
+
+#[cfg(feature = "signed-auth")]
+#[test]
+fn test_clock_skew() {
+    let env = Env::default();
+    env.mock_all_auths();
+    use_testnet(&env);
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    let (client_key, client_addr) = keypair(&env, 1);
+    let (agent_key, agent_addr) = keypair(&env, 3);
+    let escrow_id = client.open_escrow(&client_addr, &Address::generate(&env), &10_000, &None);
+    let pay = |key: &ed25519_dalek::SigningKey, nonce: u64, now: u64| {
+        env.ledger().set_timestamp(now);
+        let (authorization, key, signature) =
+            authorize(&env, &contract_id, key, escrow_id, 1, nonce);
+        client.try_create_authorized_payment(&authorization, &key, &signature)
+    };
+
+    // Without a tolerance, authorizations expire right after `expires_at`
+    assert_eq!(client.get_clock_skew(), 0);
+    assert!(pay(&client_key, 1, 10_000).is_ok());
+    assert_eq!(pay(&client_key, 2, 10_001), Err(Ok(Error::AuthorizationExpired)));
+
+    client.set_clock_skew(&admin, &30);
+    assert_eq!(client.get_clock_skew(), 30);
+    assert_eq!(
+        client.try_set_clock_skew(&Address::generate(&env), &0),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert!(pay(&client_key, 3, 9_970).is_ok());
+    assert!(pay(&client_key, 4, 10_000).is_ok());
+    assert!(pay(&client_key, 5, 10_001).is_ok());
+    assert!(pay(&client_key, 6, 10_030).is_ok());
+    assert_eq!(pay(&client_key, 7, 10_031), Err(Ok(Error::AuthorizationExpired)));
+    assert_eq!(pay(&client_key, 8, 20_000), Err(Ok(Error::AuthorizationExpired)));
+
+    // Allowances get the same tolerance
+    env.ledger().set_timestamp(0);
+    client.grant_agent(&client_addr, &agent_addr, &1_000, &400, &100);
+    assert!(pay(&agent_key, 9, 130).is_ok());
+    assert_eq!(pay(&agent_key, 10, 131), Err(Ok(Error::AllowanceExpired)));
+
+    client.set_clock_skew(&admin, &0);
+    assert_eq!(client.get_clock_skew(), 0);
+    assert_eq!(pay(&client_key, 11, 10_001), Err(Ok(Error::AuthorizationExpired)));
+}
+
+#[cfg(feature = "signed-auth")]
+#[test]
+fn test_open_and_authorize() {
+    use soroban_sdk::xdr::ToXdr as _;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    use_testnet(&env);
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let server_addr = Address::generate(&env);
+    let (client_key, client_addr) = keypair(&env, 1);
+    let (other_key, _) = keypair(&env, 4);
+
+    // The client's first escrow takes the first ID of its shard
+    let hash = env.crypto().sha256(&client_addr.clone().to_xdr(&env));
+    let escrow_id = u64::from(hash.to_array()[0]) % u64::from(ESCROW_SHARDS);
+
+    // A rejected authorization leaves no escrow behind
+    let (wrong_escrow, key, signature) =
+        authorize(&env, &contract_id, &client_key, escrow_id + 1, 100, 1);
+    let (mut wrong_network, _, _) = authorize(&env, &contract_id, &client_key, escrow_id, 100, 1);
+    wrong_network.network = String::from_str(&env, x402_types::STELLAR_MAINNET);
+    let (agent_signed, agent_key, agent_signature) =
+        authorize(&env, &contract_id, &other_key, escrow_id, 100, 1);
+    let (overdrawn, overdrawn_key, overdrawn_signature) =
+        authorize(&env, &contract_id, &client_key, escrow_id, 10_001, 1);
+    let open = |authorization: &Authorization, key: &BytesN<32>, signature: &BytesN<64>| {
+        client.try_open_and_authorize(
+            &client_addr,
+            &server_addr,
+            &10_000,
+            &None,
+            authorization,
+            key,
+            signature,
+        )
+    };
+    assert_eq!(open(&wrong_escrow, &key, &signature), Err(Ok(Error::AuthorizationMismatch)));
+    assert_eq!(open(&wrong_network, &key, &signature), Err(Ok(Error::NetworkMismatch)));
+    assert_eq!(
+        open(&agent_signed, &agent_key, &agent_signature),
+        Err(Ok(Error::InvalidSigner))
+    );
+    assert_eq!(
+        open(&overdrawn, &overdrawn_key, &overdrawn_signature),
+        Err(Ok(Error::InsufficientBalance))
+    );
+    env.ledger().set_timestamp(10_001);
+    let (expired, key, signature) = authorize(&env, &contract_id, &client_key, escrow_id, 100, 1);
+    assert_eq!(open(&expired, &key, &signature), Err(Ok(Error::AuthorizationExpired)));
+    env.ledger().set_timestamp(0);
+    assert_eq!(client.find_escrow(&client_addr, &server_addr), None);
+    assert_eq!(client.get_client_escrow_count(&client_addr), 0);
+    assert_eq!(client.get_preauthorized_payment(&escrow_id, &1), None);
+
+    // Accepted, the payment waits for the server to settle it
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &client_key, escrow_id, 100, 1);
+    let opened = open(&authorization, &key, &signature).unwrap().unwrap();
+    assert_eq!(opened.escrow_id, escrow_id);
+    assert_eq!(client.find_escrow(&client_addr, &server_addr), Some(escrow_id));
+    assert_eq!(client.get_preauthorized_payment(&escrow_id, &1), Some(opened.payment_id));
+    let payment = client.get_payment(&opened.payment_id);
+    assert_eq!((payment.escrow_id, payment.amount, payment.settled), (escrow_id, 100, false));
+    assert_eq!(
+        client.try_create_authorized_payment(&authorization, &key, &signature),
+        Err(Ok(Error::AuthorizationUsed))
+    );
+    client.settle_payment(&terms(&env, &client, opened.payment_id));
+    assert_eq!(client.get_escrow_balance(&escrow_id), 9_900);
+
+    // Only one escrow per pair, however it is opened
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &client_key, escrow_id, 100, 2);
+    assert_eq!(open(&authorization, &key, &signature), Err(Ok(Error::EscrowAlreadyExists)));
+}
+
+#[cfg(feature = "signed-auth")]
+#[test]
+fn test_revoke_agent() {
+    let env = Env::default();
+    env.mock_all_auths();
+    use_testnet(&env);
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let (_, client_addr) = keypair(&env, 1);
+    let (agent_key, agent_addr) = keypair(&env, 3);
+    let escrow_id = client.open_escrow(&client_addr, &Address::generate(&env), &10_000, &None);
+    client.grant_agent(&client_addr, &agent_addr, &1_000, &400, &100);
+
+    let (paid, key, signature) = authorize(&env, &contract_id, &agent_key, escrow_id, 100, 1);
+    let payment_id = client.create_authorized_payment(&paid, &key, &signature);
+
+    // The server verified the authorization, then the client revokes
+    let (authorization, key, signature) =
+        authorize(&env, &contract_id, &agent_key, escrow_id, 100, 2);
+    client.verify_authorization(&authorization, &key, &signature);
+    client.revoke_agent(&client_addr, &agent_addr);
+    assert_eq!(
+        client.try_create_authorized_payment(&authorization, &key, &signature),
+        Err(Ok(Error::InvalidSigner))
+    );
+    assert_eq!(client.get_allowance(&client_addr, &agent_addr), None);
+    assert_eq!(
+        client.try_revoke_agent(&client_addr, &agent_addr),
+        Err(Ok(Error::AllowanceNotFound))
+    );
+
+    // Payments created before the revocation still settle
+    assert!(client.settle_payment(&terms(&env, &client, payment_id)));
+    assert_eq!(client.get_escrow_balance(&escrow_id), 9_900);
+}
+
+#[cfg(feature = "signed-auth")]
+#[test]
+fn test_agent_cannot_close() {
+    use soroban_sdk::testutils::{MockAuth, MockAuthInvoke};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(X402EscrowContract, ());
+    let client = X402EscrowContractClient::new(&env, &contract_id);
+    let (_, client_addr) = keypair(&env, 1);
+    let (_, agent_addr) = keypair(&env, 3);
+    let escrow_id = client.open_escrow(&client_addr, &Address::generate(&env), &10_000, &None);
+    client.grant_agent(&client_addr, &agent_addr, &1_000, &400, &100);
+
+    // Only the agent signs
+    let as_agent = |fn_name: &'static str, args: Vec<soroban_sdk::Val>| {
+        env.mock_auths(&[MockAuth {
+            address: &agent_addr,
+            invoke: &MockAuthInvoke {
+                contract: &contract_id,
+                fn_name,
+                args,
+                sub_invokes: &[],
+            },
+        }]);
+    };
+    as_agent("client_close_escrow", (escrow_id,).into_val(&env));
+    assert!(client.try_client_close_escrow(&escrow_id).is_err());
+    as_agent(
+        "grant_agent",
+        (
+            client_addr.clone(),
+            agent_addr.clone(),
+            5_000i128,
+            5_000i128,
+            100u64,
+        )
+            .into_val(&env),
+    );
+    assert!(client
+        .try_grant_agent(&client_addr, &agent_addr, &5_000, &5_000, &100)
+        .is_err());
+    let target = Address::generate(&env);
+    as_agent(
+        "consent_to_migration",
+        (escrow_id, client_addr.clone(), target.clone()).into_val(&env),
+    );
+    assert!(client
+        .try_consent_to_migration(&escrow_id, &client_addr, &target)
+        .is_err());
+
+    env.mock_all_auths();
+    assert_eq!(client.get_escrow(&escrow_id).balance, 10_000);
+    assert!(!client.get_escrow(&escrow_id).client_closed);
+}
+
+/// Number of operation sequences the model test runs, unless
+/// `PROPTEST_CASES` says otherwise
+const MODEL_CASES: u32 = 64;
+
+const MODEL_CLIENTS: usize = 3;
+const MODEL_SERVERS: usize = 2;
+
+/// Escrow and payment IDs operations pick from, some never allocated
+///
+/// The model numbers escrows and payments sequentially; [`Ids`] maps its
+/// IDs to those the contract allocated.
+const MODEL_ESCROW_IDS: u64 = 8;
+const MODEL_PAYMENT_IDS: u64 = 24;
+
+/// Contract operation driven by the model test
+#[derive(Clone, Debug)]
+enum Op {
+    Open {
+        client: usize,
+        server: usize,
+        amount: i128,
+    },
+    Deposit {
+        escrow_id: u64,
+        amount: i128,
+    },
+    Create {
+        escrow_id: u64,
+        amount: i128,
+    },
+    Settle {
+        payment_id: u64,
+    },
+    SettleBatch {
+        payment_ids: std::vec::Vec<u64>,
+    },
+    ClientClose {
+        escrow_id: u64,
+    },
+    ServerClose {
+        escrow_id: u64,
+    },
+}
+
+fn op() -> impl proptest::strategy::Strategy<Value = Op> {
+    use proptest::prelude::*;
+
+    let amount = || 0..1_000i128;
+    let escrow_id = || 0..MODEL_ESCROW_IDS;
+    let payment_id = || 0..MODEL_PAYMENT_IDS;
+    prop_oneof![
+        2 => (0..MODEL_CLIENTS, 0..MODEL_SERVERS, amount())
+            .prop_map(|(client, server, amount)| Op::Open { client, server, amount }),
+        2 => (escrow_id(), amount()).prop_map(|(escrow_id, amount)| Op::Deposit { escrow_id, amount }),
+        4 => (escrow_id(), amount()).prop_map(|(escrow_id, amount)| Op::Create { escrow_id, amount }),
+        3 => payment_id().prop_map(|payment_id| Op::Settle { payment_id }),
+        2 => proptest::collection::vec(payment_id(), 0..4)
+            .prop_map(|payment_ids| Op::SettleBatch { payment_ids }),
+        1 => escrow_id().prop_map(|escrow_id| Op::ClientClose { escrow_id }),
+        1 => escrow_id().prop_map(|escrow_id| Op::ServerClose { escrow_id }),
+    ]
+}
+
+#[derive(Clone, Debug)]
+struct ModelEscrow {
+    client: usize,
+    server: usize,
+    balance: i128,
+    client_closed: bool,
+    server_closed: bool,
+}
+
+#[derive(Clone, Debug)]
+struct ModelPayment {
+    escrow_id: u64,
+    amount: i128,
+    settled: bool,
+}
+
+/// Reference model of the contract's bookkeeping
+#[derive(Clone, Debug, Default)]
+struct Model {
+    /// Open escrows
+    escrows: std::collections::BTreeMap<u64, ModelEscrow>,
+    escrow_count: u64,
+    /// Payments, indexed by their ID
+    payments: std::vec::Vec<ModelPayment>,
+}
+
+impl Model {
+    fn escrow(&mut self, escrow_id: u64) -> Result<&mut ModelEscrow, Error> {
+        self.escrows
+            .get_mut(&escrow_id)
+            .ok_or(Error::EscrowNotFound)
+    }
+
+    /// Check a payment can be settled, before its server authorizes it
+    fn check_settlement(&mut self, payment_id: u64) -> Result<ModelPayment, Error> {
+        let payment = self
+            .payments
+            .get(payment_id as usize)
+            .cloned()
+            .ok_or(Error::PaymentNotFound)?;
+        if payment.settled {
+            return Err(Error::PaymentAlreadySettled);
+        }
+        self.escrow(payment.escrow_id)?;
+        Ok(payment)
+    }
+
+    fn settle(&mut self, payment_id: u64) -> Result<i128, Error> {
+        let payment = self.check_settlement(payment_id)?;
+        let escrow = self.escrow(payment.escrow_id)?;
+        if escrow.balance < payment.amount {
+            return Err(Error::InsufficientBalance);
+        }
+        escrow.balance -= payment.amount;
+        self.payments[payment_id as usize].settled = true;
+        Ok(payment.amount)
+    }
+
+    fn close(&mut self, escrow_id: u64, by_client: bool) -> Result<Option<i128>, Error> {
+        let escrow = self.escrow(escrow_id)?;
+        if by_client {
+            escrow.client_closed = true;
+        } else {
+            escrow.server_closed = true;
+        }
+        if escrow.client_closed && escrow.server_closed {
+            Ok(self.escrows.remove(&escrow_id).map(|escrow| escrow.balance))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Apply an operation, returning what the contract should
+    fn apply(&mut self, op: &Op) -> Result<i128, Error> {
+        match op {
+            Op::Open {
+                client,
+                server,
+                amount,
+            } => {
+                let taken = self
+                    .escrows
+                    .values()
+                    .any(|escrow| escrow.client == *client && escrow.server == *server);
+                if taken {
+                    return Err(Error::EscrowAlreadyExists);
+                }
+                self.escrows.insert(
+                    self.escrow_count,
+                    ModelEscrow {
+                        client: *client,
+                        server: *server,
+                        balance: *amount,
+                        client_closed: false,
+                        server_closed: false,
+                    },
+                );
+                self.escrow_count += 1;
+                Ok((self.escrow_count - 1).into())
+            }
+            Op::Deposit { escrow_id, amount } => {
+                self.escrow(*escrow_id)?.balance += amount;
+                Ok(0)
+            }
+            Op::Create { escrow_id, amount } => {
+                if self.escrow(*escrow_id)?.balance < *amount {
+                    return Err(Error::InsufficientBalance);
+                }
+                self.payments.push(ModelPayment {
+                    escrow_id: *escrow_id,
+                    amount: *amount,
+                    settled: false,
+                });
+                Ok((self.payments.len() - 1) as i128)
+            }
+            Op::Settle { payment_id } => self.settle(*payment_id).map(|_| 1),
+            Op::SettleBatch { payment_ids } => {
+                // Every payment is checked before any settles
+                for payment_id in payment_ids {
+                    self.check_settlement(*payment_id)?;
+                }
+                // Atomic: applied to a copy, kept only if every payment settles
+                let mut next = self.clone();
+                let mut total = 0;
+                for payment_id in payment_ids {
+                    total += next.settle(*payment_id)?;
+                }
+                *self = next;
+                Ok(total)
+            }
+            Op::ClientClose { escrow_id } => self.close(*escrow_id, true).map(|r| r.unwrap_or(-1)),
+            Op::ServerClose { escrow_id } => self.close(*escrow_id, false).map(|r| r.unwrap_or(-1)),
+        }
+    }
+}
+
+/// Client and server accounts the model test picks from
+type Parties = (std::vec::Vec<Address>, std::vec::Vec<Address>);
+
+/// IDs the contract allocated, in order, indexed by the model's IDs
+#[derive(Default)]
+struct Ids {
+    escrows: std::vec::Vec<u64>,
+    payments: std::vec::Vec<u64>,
+}
+
+impl Ids {
+    /// Contract ID of a model escrow, one never allocated if the model's is not
+    fn escrow(&self, model_id: u64) -> u64 {
+        resolve(&self.escrows, model_id)
+    }
+
+    /// Contract ID of a model payment, one never allocated if the model's is not
+    fn payment(&self, model_id: u64) -> u64 {
+        resolve(&self.payments, model_id)
+    }
+}
+
+fn resolve(allocated: &[u64], model_id: u64) -> u64 {
+    allocated
+        .get(model_id as usize)
+        .copied()
+        .unwrap_or(u64::MAX - model_id)
+}
+
+/// Record an ID the contract allocated, returning the model's
+fn allocated(ids: &mut std::vec::Vec<u64>, id: u64) -> i128 {
+    ids.push(id);
+    (ids.len() - 1) as i128
+}
+
+/// Contract error of a `try_` call, or its value
+fn outcome<T, E: core::fmt::Debug, I: core::fmt::Debug>(
+    result: Result<Result<T, E>, Result<Error, I>>,
+) -> Result<T, Error> {
+    match result {
+        Ok(value) => Ok(value.expect("value decodes")),
+        Err(error) => Err(error.expect("contract error")),
+    }
+}
+
+/// Run an operation against the contract, encoding its outcome as
+/// [`Model::apply`] does
+fn run(
+    env: &Env,
+    client: &X402EscrowContractClient,
+    parties: &Parties,
+    ids: &mut Ids,
+    op: &Op,
+) -> Result<i128, Error> {
+    match op {
+        Op::Open {
+            client: c,
+            server,
+            amount,
+        } => outcome(client.try_open_escrow(
+            &parties.0[*c],
+            &parties.1[*server],
+            amount,
+            &None,
+        ))
+        .map(|escrow_id| allocated(&mut ids.escrows, escrow_id)),
+        Op::Deposit { escrow_id, amount } => {
+            outcome(client.try_deposit(&ids.escrow(*escrow_id), amount)).map(|()| 0)
+        }
+        Op::Create { escrow_id, amount } => {
+            outcome(client.try_create_payment(&ids.escrow(*escrow_id), amount))
+                .map(|payment_id| allocated(&mut ids.payments, payment_id))
+        }
+        Op::Settle { payment_id } => {
+            let settlement = terms(env, client, ids.payment(*payment_id));
+            outcome(client.try_settle_payment(&settlement)).map(i128::from)
+        }
+        Op::SettleBatch { payment_ids } => {
+            let payment_ids: std::vec::Vec<_> =
+                payment_ids.iter().map(|id| ids.payment(*id)).collect();
+            outcome(client.try_settle_payments(&batch(env, client, &payment_ids)))
+        }
+        Op::ClientClose { escrow_id } => {
+            outcome(client.try_client_close_escrow(&ids.escrow(*escrow_id)))
+                .map(|balance| balance.unwrap_or(-1))
+        }
+        Op::ServerClose { escrow_id } => {
+            outcome(client.try_server_close_escrow(&ids.escrow(*escrow_id)))
+                .map(|balance| balance.unwrap_or(-1))
+        }
+    }
+}
+
+/// Check every record and index of the contract against the model
+fn check_model(client: &X402EscrowContractClient, parties: &Parties, ids: &Ids, model: &Model) {
+    for model_id in 0..MODEL_ESCROW_IDS {
+        let escrow_id = ids.escrow(model_id);
+        match model.escrows.get(&model_id) {
+            Some(expected) => {
+                let escrow = client.get_escrow(&escrow_id);
+                assert!(escrow.balance >= 0, "escrow {escrow_id} overdrawn");
+                assert_eq!(escrow.balance, expected.balance, "escrow {escrow_id}");
+                assert_eq!(escrow.client, parties.0[expected.client]);
+                assert_eq!(escrow.server, parties.1[expected.server]);
+                assert_eq!(escrow.client_closed, expected.client_closed);
+                assert_eq!(escrow.server_closed, expected.server_closed);
+            }
+            None => assert_eq!(
+                client.try_get_escrow(&escrow_id),
+                Err(Ok(Error::EscrowNotFound)),
+                "escrow {escrow_id}"
+            ),
+        }
+
+        // Payments stay listed under their escrow, closed or not
+        let page = client.get_payments(&escrow_id, &None, &0, &MAX_PAYMENTS_PAGE);
+        let listed: std::vec::Vec<_> = page
+            .payments
+            .iter()
+            .map(|entry| {
+                (
+                    entry.payment_id,
+                    entry.payment.amount,
+                    entry.payment.settled,
+                )
+            })
+            .collect();
+        let expected: std::vec::Vec<_> = model
+            .payments
+            .iter()
+            .enumerate()
+            .filter(|(_, payment)| payment.escrow_id == model_id)
+            .map(|(id, payment)| (ids.payment(id as u64), payment.amount, payment.settled))
+            .collect();
+        assert_eq!(listed, expected, "payments of escrow {escrow_id}");
+        let pending = client.get_payments(
+            &escrow_id,
+            &Some(PaymentStatus::Pending),
+            &0,
+            &MAX_PAYMENTS_PAGE,
+        );
+        let pending_total: i128 = pending
+            .payments
+            .iter()
+            .map(|entry| entry.payment.amount)
+            .sum();
+        let expected_total: i128 = expected
+            .iter()
+            .filter(|(_, _, settled)| !settled)
+            .map(|(_, amount, _)| amount)
+            .sum();
+        assert_eq!(
+            pending_total, expected_total,
+            "pending of escrow {escrow_id}"
+        );
+    }
+
+    for (model_id, expected) in model.payments.iter().enumerate() {
+        let payment = client.get_payment(&ids.payment(model_id as u64));
+        assert_eq!(payment.escrow_id, ids.escrow(expected.escrow_id));
+        assert_eq!(payment.amount, expected.amount);
+        assert_eq!(payment.settled, expected.settled);
+    }
+    assert_eq!(
+        client.try_get_payment(&ids.payment(model.payments.len() as u64)),
+        Err(Ok(Error::PaymentNotFound))
+    );
+
+    for (c, client_addr) in parties.0.iter().enumerate() {
+        for (s, server_addr) in parties.1.iter().enumerate() {
+            let expected = model
+                .escrows
+                .iter()
+                .find(|(_, escrow)| escrow.client == c && escrow.server == s)
+                .map(|(model_id, _)| ids.escrow(*model_id));
+            assert_eq!(client.find_escrow(client_addr, server_addr), expected);
+        }
+    }
+    let mut exported = std::collections::BTreeSet::new();
+    let mut start = Some(0);
+    while let Some(cursor) = start {
+        let page = client.export_escrows(&None, &cursor, &MAX_ESCROWS_PAGE);
+        exported.extend(
+            page.escrows
+                .iter()
+                .map(|entry| (entry.escrow_id, entry.escrow.balance)),
+        );
+        start = page.next_start;
+    }
+    let expected: std::collections::BTreeSet<_> = model
+        .escrows
+        .iter()
+        .map(|(model_id, escrow)| (ids.escrow(*model_id), escrow.balance))
+        .collect();
+    assert_eq!(exported, expected);
+}
+
+fn model_config() -> proptest::test_runner::Config {
+    proptest::test_runner::Config {
+        cases: std::env::var("PROPTEST_CASES")
+            .ok()
+            .and_then(|cases| cases.parse().ok())
+            .unwrap_or(MODEL_CASES),
+        ..Default::default()
+    }
+}
+
+proptest::proptest! {
+    #![proptest_config(model_config())]
+
+    /// Random interleavings of operations across escrows keep the contract
+    /// in step with a plain model, and never overdraw an escrow
+    #[test]
+    fn test_model_invariants(ops in proptest::collection::vec(op(), 1..40)) {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.cost_estimate().budget().reset_unlimited();
+
+        let contract_id = env.register(X402EscrowContract, ());
+        let client = X402EscrowContractClient::new(&env, &contract_id);
+        let parties: Parties = (
+            (0..MODEL_CLIENTS).map(|_| Address::generate(&env)).collect(),
+            (0..MODEL_SERVERS).map(|_| Address::generate(&env)).collect(),
+        );
+
+        let mut model = Model::default();
+        let mut ids = Ids::default();
+        for op in &ops {
+            let expected = model.apply(op);
+            proptest::prop_assert_eq!(run(&env, &client, &parties, &mut ids, op), expected, "{:?}", op);
+            check_model(&client, &parties, &ids, &model);
+        }
+    }
+}
+
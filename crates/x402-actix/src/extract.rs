@@ -0,0 +1,26 @@
+use std::future::{ready, Ready};
+
+use actix_web::{
+    dev::Payload, error::ErrorInternalServerError, Error, FromRequest, HttpMessage, HttpRequest,
+};
+use x402_tower::VerifiedPayment;
+
+/// Extractor for the payment of a request passed by [`X402Middleware`](crate::X402Middleware)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Paid(pub VerifiedPayment);
+
+impl FromRequest for Paid {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            request
+                .extensions()
+                .get::<VerifiedPayment>()
+                .cloned()
+                .map(Paid)
+                .ok_or_else(|| ErrorInternalServerError("route is not behind an X402Middleware")),
+        )
+    }
+}
@@ -0,0 +1,18 @@
+//! # x402 Actix
+//!
+//! Middleware gating actix-web routes on x402 escrow payments, sharing the
+//! gating logic of the [`x402_tower`] layer.
+//!
+//! ## Key Features
+//! - [`X402Middleware`] answering unpaid requests with `402 Payment Required`
+//! - Configured with the same [`X402Layer`] builder as tower servers
+//! - The [`Paid`] extractor giving handlers the [`VerifiedPayment`]
+
+mod extract;
+mod middleware;
+
+pub use extract::*;
+pub use middleware::*;
+pub use x402_tower::{FailoverVerifier, HttpFacilitator, VerifiedPayment, Verifier, X402Layer};
+
+mod test;
@@ -0,0 +1,162 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue, RETRY_AFTER},
+    Error, HttpMessage, HttpResponse,
+};
+use x402_tower::{Challenge, Charge, Paywall, X402Layer};
+use x402_types::PAYMENT_HEADER;
+
+/// Middleware requiring an x402 payment before the wrapped service runs
+///
+/// Behaves like the tower [`X402Layer`], which also configures it: requests
+/// without a valid `X-PAYMENT` header get a `402 Payment Required` response,
+/// valid payments are passed to handlers as a
+/// [`VerifiedPayment`](x402_tower::VerifiedPayment) extension and settled
+/// once the handler returns a success status, with the settlement in the
+/// `X-PAYMENT-RESPONSE` header. Metered handlers set their
+/// [`Charge`] as an extension of the response; unlike the tower layer, the
+/// middleware does not catch their panics.
+#[derive(Clone)]
+pub struct X402Middleware {
+    paywall: Paywall,
+}
+
+impl X402Middleware {
+    /// Create a middleware from a configured layer
+    ///
+    /// # Panics
+    /// * If the layer has no verifier
+    pub fn new(layer: &X402Layer) -> Self {
+        Self {
+            paywall: layer.paywall(),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for X402Middleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = X402MiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(X402MiddlewareService {
+            service: Rc::new(service),
+            paywall: self.paywall.clone(),
+        }))
+    }
+}
+
+/// Service produced by [`X402Middleware`]
+pub struct X402MiddlewareService<S> {
+    service: Rc<S>,
+    paywall: Paywall,
+}
+
+impl<S, B> Service<ServiceRequest> for X402MiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let paywall = self.paywall.clone();
+
+        Box::pin(async move {
+            let requirements =
+                paywall.requirements_for(request.method().as_str(), &request.uri().to_string());
+            let header = request
+                .headers()
+                .get(PAYMENT_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from);
+
+            let payment = match paywall.verify(header.as_deref(), &requirements).await {
+                Ok(payment) => payment,
+                Err(challenge) => {
+                    return Ok(request
+                        .into_response(payment_required(&challenge))
+                        .map_into_right_body())
+                }
+            };
+            let limit = payment.amount;
+            request.extensions_mut().insert(payment.clone());
+            // The header is present, verification succeeded
+            let header = header.unwrap_or_default();
+
+            let mut response = match service.call(request).await {
+                Ok(response) => response,
+                Err(e) => {
+                    if paywall.is_metered() {
+                        let _ = paywall
+                            .settle_charge(&payment, &header, &requirements, 0)
+                            .await;
+                    }
+                    return Err(e);
+                }
+            };
+            let success = response.status().is_success();
+            let charge = paywall.is_metered().then(|| {
+                match response.response().extensions().get::<Charge>() {
+                    _ if !success => 0,
+                    Some(Charge(amount)) => (*amount).clamp(0, limit),
+                    None => limit,
+                }
+            });
+            let settled = match charge {
+                Some(charge) => {
+                    paywall
+                        .settle_charge(&payment, &header, &requirements, charge)
+                        .await
+                }
+                None if success => paywall.settle(&payment, &header, &requirements).await,
+                None => return Ok(response.map_into_left_body()),
+            };
+            match settled {
+                Ok(value) => {
+                    // Base64 is always a valid header value
+                    if let Ok(value) = HeaderValue::from_str(&value) {
+                        response
+                            .headers_mut()
+                            .insert(HeaderName::from_static("x-payment-response"), value);
+                    }
+                    Ok(response.map_into_left_body())
+                }
+                // Failures of zero charges leave nothing unpaid
+                Err(_) if charge == Some(0) => Ok(response.map_into_left_body()),
+                Err(challenge) => {
+                    let (request, _) = response.into_parts();
+                    Ok(ServiceResponse::new(request, payment_required(&challenge))
+                        .map_into_right_body())
+                }
+            }
+        })
+    }
+}
+
+fn payment_required(challenge: &Challenge) -> HttpResponse {
+    let mut response = HttpResponse::PaymentRequired();
+    response.content_type("application/json");
+    if let Some(seconds) = challenge.retry_after() {
+        response.insert_header((RETRY_AFTER, seconds));
+    }
+    response.body(challenge.to_json())
+}
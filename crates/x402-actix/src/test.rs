@@ -0,0 +1,146 @@
+#![cfg(test)]
+
+use actix_web::{
+    http::StatusCode,
+    test::{self, TestRequest},
+    web, App,
+};
+use x402_testkit::{TestKit, Wallet, CLIENT_SEED};
+use x402_types::{
+    decode_payment_response_header, PaymentRequiredResponse, NATIVE_ASSET, PAYMENT_HEADER,
+    PAYMENT_RESPONSE_HEADER,
+};
+
+use crate::{Paid, X402Layer, X402Middleware};
+
+struct Setup {
+    middleware: X402Middleware,
+    kit: TestKit,
+    wallet: Wallet,
+}
+
+async fn setup(deposit: i128) -> Setup {
+    let kit = TestKit::new();
+    let wallet = kit.wallet(&CLIENT_SEED);
+    wallet.open_escrow(deposit).await;
+
+    let layer = X402Layer::new(100_000, NATIVE_ASSET, kit.server())
+        .with_description("Weather report")
+        .with_shared_verifier(kit.facilitator());
+    Setup {
+        middleware: X402Middleware::new(&layer),
+        kit,
+        wallet,
+    }
+}
+
+async fn weather(Paid(payment): Paid) -> String {
+    format!("paid {} from escrow {}", payment.amount, payment.escrow_id)
+}
+
+fn request(header: Option<String>) -> TestRequest {
+    let request = TestRequest::get().uri("/weather");
+    match header {
+        Some(header) => request.insert_header((PAYMENT_HEADER, header)),
+        None => request,
+    }
+}
+
+#[actix_web::test]
+async fn test_missing_header_returns_challenge() {
+    let s = setup(1_000_000).await;
+    let app = test::init_service(
+        App::new().service(
+            web::resource("/weather")
+                .wrap(s.middleware.clone())
+                .to(weather),
+        ),
+    )
+    .await;
+
+    let response = test::call_service(&app, request(None).to_request()).await;
+    assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+    let challenge: PaymentRequiredResponse = test::read_body_json(response).await;
+    assert_eq!(challenge.error.as_deref(), Some("payment_required"));
+    assert_eq!(challenge.accepts[0].max_amount_required, "100000");
+    assert_eq!(challenge.accepts[0].resource, "/weather");
+    assert_eq!(challenge.accepts[0].description, "Weather report");
+}
+
+#[actix_web::test]
+async fn test_rejected_payments() {
+    let s = setup(50_000).await;
+    let app = test::init_service(
+        App::new().service(
+            web::resource("/weather")
+                .wrap(s.middleware.clone())
+                .to(weather),
+        ),
+    )
+    .await;
+
+    let response =
+        test::call_service(&app, request(Some("bm90IGpzb24=".into())).to_request()).await;
+    let challenge: PaymentRequiredResponse = test::read_body_json(response).await;
+    assert_eq!(challenge.error.as_deref(), Some("invalid_payload"));
+
+    let header = s.wallet.payment_header(100_000, 1);
+    let response = test::call_service(&app, request(Some(header)).to_request()).await;
+    assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+    let challenge: PaymentRequiredResponse = test::read_body_json(response).await;
+    assert_eq!(challenge.error.as_deref(), Some("insufficient_funds"));
+    let hint = challenge.payment_error.unwrap();
+    assert_eq!(hint.required_topup_amount.as_deref(), Some("50000"));
+    assert!(hint.requirements.is_some());
+}
+
+#[actix_web::test]
+async fn test_paid_request_is_settled() {
+    let s = setup(1_000_000).await;
+    let app = test::init_service(
+        App::new().service(
+            web::resource("/weather")
+                .wrap(s.middleware.clone())
+                .to(weather),
+        ),
+    )
+    .await;
+
+    let header = s.wallet.payment_header(100_000, 1);
+    let response = test::call_service(&app, request(Some(header.clone())).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let settlement = response
+        .headers()
+        .get(PAYMENT_RESPONSE_HEADER)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body = test::read_body(response).await;
+    let escrow_id = s.wallet.escrow_id().unwrap();
+    assert_eq!(
+        body[..],
+        *format!("paid 100000 from escrow {escrow_id}").as_bytes()
+    );
+
+    let settlement = decode_payment_response_header(&settlement)
+        .unwrap()
+        .settlement;
+    assert!(settlement.success);
+    let payment_id = escrow_id << 32;
+    assert_eq!(settlement.payment_id, Some(payment_id));
+    s.kit.assert_settled(payment_id).await;
+    assert_eq!(
+        s.wallet
+            .client()
+            .get_escrow_balance(escrow_id)
+            .await
+            .unwrap(),
+        900_000
+    );
+
+    // The same header cannot pay twice
+    let response = test::call_service(&app, request(Some(header)).to_request()).await;
+    let challenge: PaymentRequiredResponse = test::read_body_json(response).await;
+    assert_eq!(challenge.error.as_deref(), Some("nonce_used"));
+}
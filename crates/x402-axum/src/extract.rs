@@ -0,0 +1,26 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+};
+
+use crate::VerifiedPayment;
+
+/// Extractor for the payment of a request passed by [`PaymentLayer`](crate::PaymentLayer)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Paid(pub VerifiedPayment);
+
+impl<S: Send + Sync> FromRequestParts<S> for Paid {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<VerifiedPayment>()
+            .cloned()
+            .map(Paid)
+            .ok_or((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "route is not behind a PaymentLayer",
+            ))
+    }
+}
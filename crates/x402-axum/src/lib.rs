@@ -0,0 +1,23 @@
+//! # x402 Axum
+//!
+//! Middleware gating axum routes on x402 escrow payments, a thin wrapper
+//! over the [`x402_tower`] layer.
+//!
+//! ## Key Features
+//! - [`PaymentLayer`] answering unpaid requests with `402 Payment Required`
+//! - Per-route prices via [`PaymentLayer::with_route`], with wildcard
+//!   patterns and per-method overrides
+//! - Verification through a remote [`HttpFacilitator`] or directly against
+//!   the contract with an in-process `Facilitator`, failing over across
+//!   several facilitators with a [`FailoverVerifier`]
+//! - The [`Paid`] extractor giving handlers the [`VerifiedPayment`]
+
+mod extract;
+
+pub use extract::*;
+pub use x402_tower::{
+    Challenge, FailoverVerifier, HttpFacilitator, Price, VerifiedPayment, Verifier,
+    X402Layer as PaymentLayer, X402Service as PaymentService, DEFAULT_MAX_TIMEOUT_SECONDS,
+};
+
+mod test;
@@ -0,0 +1,230 @@
+#![cfg(test)]
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, StatusCode},
+    routing::get,
+    Router,
+};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use tower::ServiceExt;
+use x402_testkit::{TestKit, Wallet, CLIENT_SEED, NETWORK};
+use x402_types::{
+    decode_payment_header, decode_payment_response_header, encode_payment_header,
+    PaymentRequiredResponse, ESCROW_SCHEME, NATIVE_ASSET, PAYMENT_HEADER, PAYMENT_RESPONSE_HEADER,
+    X402_VERSION, X402_VERSIONS,
+};
+
+use crate::{HttpFacilitator, Paid, PaymentLayer, Verifier};
+
+struct Setup {
+    kit: TestKit,
+    wallet: Wallet,
+}
+
+async fn setup(deposit: i128) -> Setup {
+    let kit = TestKit::new();
+    let wallet = kit.wallet(&CLIENT_SEED);
+    wallet.open_escrow(deposit).await;
+    Setup { kit, wallet }
+}
+
+fn app(s: &Setup, verifier: Arc<dyn Verifier>) -> Router {
+    async fn handler(Paid(payment): Paid) -> String {
+        format!("paid {} from escrow {}", payment.amount, payment.escrow_id)
+    }
+
+    Router::new()
+        .route("/weather", get(handler))
+        .route("/premium", get(handler))
+        .layer(
+            PaymentLayer::new(100_000, NATIVE_ASSET, s.kit.server())
+                .with_description("Weather report")
+                .with_route("/premium", 500_000, "Premium report")
+                .with_shared_verifier(verifier),
+        )
+}
+
+async fn call(app: &Router, path: &str, header: Option<String>) -> (StatusCode, HeaderMap, Body) {
+    let mut request = Request::get(path);
+    if let Some(header) = header {
+        request = request.header(PAYMENT_HEADER, header);
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let (parts, body) = response.into_parts();
+    (parts.status, parts.headers, body)
+}
+
+async fn read_challenge(body: Body) -> PaymentRequiredResponse {
+    let bytes = body.collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn test_missing_header_returns_challenge() {
+    let s = setup(1_000_000).await;
+    let app = app(&s, s.kit.facilitator());
+
+    let (status, _, body) = call(&app, "/weather", None).await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    let challenge = read_challenge(body).await;
+    assert_eq!(challenge.error.as_deref(), Some("payment_required"));
+    let requirements = &challenge.accepts[0];
+    assert_eq!(requirements.scheme, ESCROW_SCHEME);
+    assert_eq!(requirements.network, NETWORK);
+    assert_eq!(requirements.max_amount_required, "100000");
+    assert_eq!(requirements.pay_to, s.kit.server());
+    assert_eq!(requirements.asset.as_deref(), Some(NATIVE_ASSET));
+    assert_eq!(requirements.resource, "/weather");
+    assert_eq!(requirements.description, "Weather report");
+
+    // Per-route override
+    let (_, _, body) = call(&app, "/premium", None).await;
+    let challenge = read_challenge(body).await;
+    assert_eq!(challenge.accepts[0].max_amount_required, "500000");
+    assert_eq!(challenge.accepts[0].description, "Premium report");
+}
+
+#[tokio::test]
+async fn test_invalid_payload() {
+    let s = setup(1_000_000).await;
+    let app = app(&s, s.kit.facilitator());
+
+    let (status, _, body) = call(&app, "/weather", Some("bm90IGpzb24=".into())).await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    assert_eq!(
+        read_challenge(body).await.error.as_deref(),
+        Some("invalid_payload")
+    );
+
+    // Route price is enforced against the authorized amount
+    let header = s.wallet.payment_header(500_000, 1);
+    let (status, _, body) = call(&app, "/weather", Some(header)).await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    assert_eq!(
+        read_challenge(body).await.error.as_deref(),
+        Some("amount_exceeds_requirement")
+    );
+}
+
+#[tokio::test]
+async fn test_payload_versions() {
+    let s = setup(1_000_000).await;
+    let app = app(&s, s.kit.facilitator());
+    let versioned = |x402_version, nonce| {
+        let mut payload = decode_payment_header(&s.wallet.payment_header(100_000, nonce)).unwrap();
+        payload.x402_version = x402_version;
+        encode_payment_header(&payload)
+    };
+
+    // The challenge lists every version the server speaks
+    let (_, _, body) = call(&app, "/weather", None).await;
+    assert_eq!(read_challenge(body).await.x402_versions, X402_VERSIONS);
+
+    // Clients predating version 2 are still served
+    let (status, _, _) = call(&app, "/weather", Some(versioned(1, 1))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Payloads of later versions are answered with the versions supported
+    let (status, _, body) = call(&app, "/weather", Some(versioned(X402_VERSION + 1, 2))).await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    let challenge = read_challenge(body).await;
+    assert_eq!(challenge.error.as_deref(), Some("invalid_x402_version"));
+    assert_eq!(challenge.x402_versions, X402_VERSIONS);
+}
+
+#[tokio::test]
+async fn test_insufficient_escrow() {
+    let s = setup(50_000).await;
+    let app = app(&s, s.kit.facilitator());
+
+    let header = s.wallet.payment_header(100_000, 1);
+    let (status, _, body) = call(&app, "/weather", Some(header)).await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    assert_eq!(
+        read_challenge(body).await.error.as_deref(),
+        Some("insufficient_funds")
+    );
+}
+
+#[tokio::test]
+async fn test_paid_request_is_settled() {
+    let s = setup(1_000_000).await;
+    let app = app(&s, s.kit.facilitator());
+
+    let header = s.wallet.payment_header(100_000, 1);
+    let (status, headers, body) = call(&app, "/weather", Some(header.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    let body = body.collect().await.unwrap().to_bytes();
+    let escrow_id = s.wallet.escrow_id().unwrap();
+    assert_eq!(
+        body[..],
+        *format!("paid 100000 from escrow {escrow_id}").as_bytes()
+    );
+
+    let settlement = headers[PAYMENT_RESPONSE_HEADER].to_str().unwrap();
+    let settlement = decode_payment_response_header(settlement)
+        .unwrap()
+        .settlement;
+    assert!(settlement.success);
+    let payment_id = escrow_id << 32;
+    assert_eq!(settlement.payment_id, Some(payment_id));
+    s.kit.assert_settled(payment_id).await;
+    assert_eq!(
+        s.wallet
+            .client()
+            .get_escrow_balance(escrow_id)
+            .await
+            .unwrap(),
+        900_000
+    );
+
+    // The same header cannot pay twice
+    let (status, _, body) = call(&app, "/weather", Some(header)).await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    assert_eq!(
+        read_challenge(body).await.error.as_deref(),
+        Some("nonce_used")
+    );
+}
+
+#[tokio::test]
+async fn test_remote_facilitator() {
+    let s = setup(1_000_000).await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let router = x402_facilitator::router(s.kit.facilitator());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    let app = app(&s, Arc::new(HttpFacilitator::new(url, NETWORK)));
+
+    let (status, _, body) = call(
+        &app,
+        "/weather",
+        Some(s.wallet.payment_header(2_000_000, 1)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    let error: Value = serde_json::from_slice(&body.collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(error["error"], "amount_exceeds_requirement");
+
+    let header = s.wallet.payment_header(100_000, 2);
+    let (status, headers, _) = call(&app, "/weather", Some(header)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers.contains_key(PAYMENT_RESPONSE_HEADER));
+    let escrow_id = s.wallet.escrow_id().unwrap();
+    assert_eq!(
+        s.wallet
+            .client()
+            .get_escrow_balance(escrow_id)
+            .await
+            .unwrap(),
+        900_000
+    );
+}
@@ -0,0 +1,924 @@
+use soroban_sdk::{Address, Bytes, BytesN, Env, Vec};
+use stellar_xdr::curr::{
+    Int128Parts, ScAddress, ScBytes, ScMap, ScMapEntry, ScString, ScSymbol, ScVal, ScVec,
+};
+use x402_escrow::{
+    Allowance, Authorization, DustPolicy, Error, Escrow, EscrowIdPage, EscrowLinks, EscrowPage,
+    MigrationExport, MigrationSummary, Note, OpenedEscrow, Payment, PaymentIntent, PaymentPage,
+    QuoteTotal, Settlement, SpendLimit, X402EscrowContract,
+};
+
+pub use x402_escrow::{
+    PaymentStatus, DIRECT_PAYMENT_ESCROW, ESCROW_INDEX_PAGE, EVENT_VERSION, MAX_COMPACT_PAGES,
+    MAX_ESCROWS_PAGE, MAX_NOTES, MAX_NOTE_LEN, MAX_NOTIFY_URL_LEN, MAX_PAYMENTS_PAGE,
+    MAX_SPLIT_METADATA_LEN, MIN_DUST_IDLE, SPEND_WINDOW,
+};
+
+/// Contract function call, ready to be put in a transaction
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Invocation {
+    /// Contract function invoked
+    pub function: &'static str,
+    /// Arguments, in order
+    pub args: std::vec::Vec<ScVal>,
+}
+
+/// Check that a contract function still has the signature its binding encodes
+///
+/// Coercing the function to a pointer of the expected type fails to compile
+/// as soon as its arguments or return type change.
+macro_rules! check_signature {
+    ($function:ident: fn($($arg:ty),*) -> $ret:ty) => {
+        const _: fn(Env, $($arg),*) -> $ret = X402EscrowContract::$function;
+    };
+}
+
+check_signature!(open_escrow: fn(Address, Address, i128, Option<BytesN<32>>) -> Result<u64, Error>);
+check_signature!(clone_escrow_config: fn(u64, Address, i128) -> Result<u64, Error>);
+check_signature!(open_sponsored_escrow: fn(Address, Address, i128) -> Result<u64, Error>);
+check_signature!(split_escrow: fn(u64, i128, Bytes) -> Result<u64, Error>);
+check_signature!(get_escrow_links: fn(u64) -> EscrowLinks);
+check_signature!(deposit: fn(u64, i128) -> Result<(), Error>);
+check_signature!(claim_pending_deposit: fn(u64, Address, i128, BytesN<32>) -> Result<(), Error>);
+check_signature!(is_deposit_claimed: fn(BytesN<32>) -> bool);
+check_signature!(
+    record_direct_payment: fn(Address, Address, Address, i128, BytesN<32>) -> Result<u64, Error>
+);
+check_signature!(create_payment: fn(u64, i128) -> Result<u64, Error>);
+check_signature!(create_payments: fn(Vec<PaymentIntent>) -> Result<Vec<u64>, Error>);
+check_signature!(settle_payment: fn(Settlement) -> Result<bool, Error>);
+check_signature!(settle_payments: fn(Vec<Settlement>) -> Result<i128, Error>);
+check_signature!(refund_payment: fn(u64, i128) -> Result<i128, Error>);
+check_signature!(get_refunded: fn(u64) -> i128);
+check_signature!(client_close_escrow: fn(u64) -> Result<Option<i128>, Error>);
+check_signature!(server_close_escrow: fn(u64) -> Result<Option<i128>, Error>);
+check_signature!(get_escrow: fn(u64) -> Result<Escrow, Error>);
+check_signature!(get_escrow_balance: fn(u64) -> Result<i128, Error>);
+check_signature!(get_payment: fn(u64) -> Result<Payment, Error>);
+check_signature!(get_payments: fn(u64, Option<PaymentStatus>, u32, u32) -> PaymentPage);
+check_signature!(
+    get_direct_payments: fn(Address, Option<PaymentStatus>, u32, u32) -> PaymentPage
+);
+check_signature!(get_quote_total: fn(u64) -> QuoteTotal);
+check_signature!(export_escrows: fn(Option<Address>, u64, u32) -> EscrowPage);
+check_signature!(find_escrow: fn(Address, Address) -> Option<u64>);
+check_signature!(get_escrow_index: fn(Address, bool, u32) -> EscrowIdPage);
+check_signature!(compact_escrow_index: fn(Address, u32, u32) -> Option<u32>);
+check_signature!(post_note: fn(u64, Address, Bytes) -> Result<(), Error>);
+check_signature!(get_notes: fn(u64) -> Vec<Note>);
+check_signature!(consent_to_migration: fn(u64, Address, Address) -> Result<(), Error>);
+check_signature!(export_for_migration: fn(Address, u64) -> Result<MigrationExport, Error>);
+check_signature!(get_migration: fn(u64) -> Option<MigrationExport>);
+check_signature!(allow_migration_source: fn(Address, Address) -> Result<(), Error>);
+check_signature!(import_escrow: fn(MigrationSummary, BytesN<32>) -> Result<u64, Error>);
+check_signature!(set_dust_policy: fn(Address, i128, u64) -> Result<(), Error>);
+check_signature!(get_dust_policy: fn() -> Option<DustPolicy>);
+check_signature!(set_max_escrows_per_client: fn(Address, Option<u32>) -> Result<(), Error>);
+check_signature!(initialize: fn(Address) -> Result<(), Error>);
+check_signature!(version: fn() -> u32);
+check_signature!(get_max_escrows_per_client: fn() -> Option<u32>);
+check_signature!(get_client_escrow_count: fn(Address) -> u32);
+check_signature!(set_clock_skew: fn(Address, u64) -> Result<(), Error>);
+check_signature!(get_clock_skew: fn() -> u64);
+check_signature!(set_refund_address: fn(Address, Address) -> ());
+check_signature!(get_refund_address: fn(Address) -> Address);
+check_signature!(set_terms: fn(Address, BytesN<32>) -> ());
+check_signature!(get_terms: fn(Address) -> Option<BytesN<32>>);
+check_signature!(set_notification_url: fn(Address, Bytes) -> Result<(), Error>);
+check_signature!(get_notification_url: fn(Address) -> Option<Bytes>);
+check_signature!(get_last_activity: fn(u64) -> Result<u64, Error>);
+check_signature!(sweep_dust: fn(Address, Vec<u64>) -> Result<i128, Error>);
+check_signature!(grant_agent: fn(Address, Address, i128, i128, u64) -> Result<(), Error>);
+check_signature!(revoke_agent: fn(Address, Address) -> Result<(), Error>);
+check_signature!(get_allowance: fn(Address, Address) -> Option<Allowance>);
+check_signature!(set_max_settled_per_hour: fn(u64, Option<i128>) -> Result<(), Error>);
+check_signature!(acknowledge_anomaly: fn(u64) -> Result<(), Error>);
+check_signature!(get_spend_limit: fn(u64) -> Option<SpendLimit>);
+check_signature!(
+    create_authorized_payment: fn(Authorization, BytesN<32>, BytesN<64>) -> Result<u64, Error>
+);
+check_signature!(
+    open_and_authorize: fn(
+        Address,
+        Address,
+        i128,
+        Option<BytesN<32>>,
+        Authorization,
+        BytesN<32>,
+        BytesN<64>
+    ) -> Result<OpenedEscrow, Error>
+);
+check_signature!(get_preauthorized_payment: fn(u64, u64) -> Option<u64>);
+
+/// `open_escrow(client, server, amount, terms_hash) -> u64`
+pub fn open_escrow(
+    client: ScAddress,
+    server: ScAddress,
+    amount: i128,
+    terms_hash: Option<[u8; 32]>,
+) -> Invocation {
+    Invocation {
+        function: "open_escrow",
+        args: vec![
+            ScVal::Address(client),
+            ScVal::Address(server),
+            i128(amount),
+            terms_hash.map_or(ScVal::Void, bytes32),
+        ],
+    }
+}
+
+/// `clone_escrow_config(source_escrow_id, new_server, amount) -> u64`
+pub fn clone_escrow_config(
+    source_escrow_id: u64,
+    new_server: ScAddress,
+    amount: i128,
+) -> Invocation {
+    Invocation {
+        function: "clone_escrow_config",
+        args: vec![
+            ScVal::U64(source_escrow_id),
+            ScVal::Address(new_server),
+            i128(amount),
+        ],
+    }
+}
+
+/// `open_sponsored_escrow(server, client, trial_amount) -> u64`
+pub fn open_sponsored_escrow(
+    server: ScAddress,
+    client: ScAddress,
+    trial_amount: i128,
+) -> Invocation {
+    Invocation {
+        function: "open_sponsored_escrow",
+        args: vec![
+            ScVal::Address(server),
+            ScVal::Address(client),
+            i128(trial_amount),
+        ],
+    }
+}
+
+/// `split_escrow(escrow_id, amount_to_new, metadata) -> u64`
+pub fn split_escrow(
+    escrow_id: u64,
+    amount_to_new: i128,
+    metadata: &[u8],
+) -> Result<Invocation, stellar_xdr::curr::Error> {
+    Ok(Invocation {
+        function: "split_escrow",
+        args: vec![
+            ScVal::U64(escrow_id),
+            i128(amount_to_new),
+            ScVal::Bytes(ScBytes(metadata.try_into()?)),
+        ],
+    })
+}
+
+/// `get_escrow_links(escrow_id) -> EscrowLinks`
+pub fn get_escrow_links(escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "get_escrow_links",
+        args: vec![ScVal::U64(escrow_id)],
+    }
+}
+
+/// `deposit(escrow_id, amount)`
+pub fn deposit(escrow_id: u64, amount: i128) -> Invocation {
+    Invocation {
+        function: "deposit",
+        args: vec![ScVal::U64(escrow_id), i128(amount)],
+    }
+}
+
+/// `claim_pending_deposit(escrow_id, from, amount, memo_hash)`
+pub fn claim_pending_deposit(
+    escrow_id: u64,
+    from: ScAddress,
+    amount: i128,
+    memo_hash: [u8; 32],
+) -> Invocation {
+    Invocation {
+        function: "claim_pending_deposit",
+        args: vec![
+            ScVal::U64(escrow_id),
+            ScVal::Address(from),
+            i128(amount),
+            bytes32(memo_hash),
+        ],
+    }
+}
+
+/// `is_deposit_claimed(memo_hash) -> bool`
+pub fn is_deposit_claimed(memo_hash: [u8; 32]) -> Invocation {
+    Invocation {
+        function: "is_deposit_claimed",
+        args: vec![bytes32(memo_hash)],
+    }
+}
+
+/// `record_direct_payment(client, server, token, amount, reference) -> u64`
+pub fn record_direct_payment(
+    client: ScAddress,
+    server: ScAddress,
+    token: ScAddress,
+    amount: i128,
+    reference: [u8; 32],
+) -> Invocation {
+    Invocation {
+        function: "record_direct_payment",
+        args: vec![
+            ScVal::Address(client),
+            ScVal::Address(server),
+            ScVal::Address(token),
+            i128(amount),
+            bytes32(reference),
+        ],
+    }
+}
+
+/// `create_payment(escrow_id, amount) -> u64`
+pub fn create_payment(escrow_id: u64, amount: i128) -> Invocation {
+    Invocation {
+        function: "create_payment",
+        args: vec![ScVal::U64(escrow_id), i128(amount)],
+    }
+}
+
+/// `create_payments(intents) -> Vec<u64>`
+///
+/// Each of `intents` is built with [`payment_intent`].
+///
+/// # Errors
+/// * If there are more than `u32::MAX` intents
+pub fn create_payments(
+    intents: std::vec::Vec<ScVal>,
+) -> Result<Invocation, stellar_xdr::curr::Error> {
+    Ok(Invocation {
+        function: "create_payments",
+        args: vec![ScVal::Vec(Some(intents.try_into()?))],
+    })
+}
+
+/// `settle_payment(settlement) -> bool`
+///
+/// `settlement` is built with [`settlement`].
+pub fn settle_payment(settlement: ScVal) -> Invocation {
+    Invocation {
+        function: "settle_payment",
+        args: vec![settlement],
+    }
+}
+
+/// `refund_payment(payment_id, amount) -> i128`
+pub fn refund_payment(payment_id: u64, amount: i128) -> Invocation {
+    Invocation {
+        function: "refund_payment",
+        args: vec![ScVal::U64(payment_id), i128(amount)],
+    }
+}
+
+/// `get_refunded(payment_id) -> i128`
+pub fn get_refunded(payment_id: u64) -> Invocation {
+    Invocation {
+        function: "get_refunded",
+        args: vec![ScVal::U64(payment_id)],
+    }
+}
+
+/// `settle_payments(settlements) -> i128`
+///
+/// Each of `settlements` is built with [`settlement`].
+///
+/// # Errors
+/// * If there are more than `u32::MAX` settlements
+pub fn settle_payments(
+    settlements: std::vec::Vec<ScVal>,
+) -> Result<Invocation, stellar_xdr::curr::Error> {
+    Ok(Invocation {
+        function: "settle_payments",
+        args: vec![ScVal::Vec(Some(settlements.try_into()?))],
+    })
+}
+
+/// `client_close_escrow(escrow_id) -> Option<i128>`
+pub fn client_close_escrow(escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "client_close_escrow",
+        args: vec![ScVal::U64(escrow_id)],
+    }
+}
+
+/// `server_close_escrow(escrow_id) -> Option<i128>`
+pub fn server_close_escrow(escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "server_close_escrow",
+        args: vec![ScVal::U64(escrow_id)],
+    }
+}
+
+/// `get_escrow(escrow_id) -> Escrow`
+pub fn get_escrow(escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "get_escrow",
+        args: vec![ScVal::U64(escrow_id)],
+    }
+}
+
+/// `get_escrow_balance(escrow_id) -> i128`
+pub fn get_escrow_balance(escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "get_escrow_balance",
+        args: vec![ScVal::U64(escrow_id)],
+    }
+}
+
+/// `get_payment(payment_id) -> Payment`
+pub fn get_payment(payment_id: u64) -> Invocation {
+    Invocation {
+        function: "get_payment",
+        args: vec![ScVal::U64(payment_id)],
+    }
+}
+
+/// `get_quote_total(escrow_id) -> QuoteTotal`
+pub fn get_quote_total(escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "get_quote_total",
+        args: vec![ScVal::U64(escrow_id)],
+    }
+}
+
+/// `get_payments(escrow_id, status, offset, limit) -> PaymentPage`
+pub fn get_payments(
+    escrow_id: u64,
+    status: Option<PaymentStatus>,
+    offset: u32,
+    limit: u32,
+) -> Invocation {
+    Invocation {
+        function: "get_payments",
+        args: vec![
+            ScVal::U64(escrow_id),
+            status.map_or(ScVal::Void, payment_status),
+            ScVal::U32(offset),
+            ScVal::U32(limit),
+        ],
+    }
+}
+
+/// `get_direct_payments(server, status, offset, limit) -> PaymentPage`
+pub fn get_direct_payments(
+    server: ScAddress,
+    status: Option<PaymentStatus>,
+    offset: u32,
+    limit: u32,
+) -> Invocation {
+    Invocation {
+        function: "get_direct_payments",
+        args: vec![
+            ScVal::Address(server),
+            status.map_or(ScVal::Void, payment_status),
+            ScVal::U32(offset),
+            ScVal::U32(limit),
+        ],
+    }
+}
+
+/// `export_escrows(server, start, limit) -> EscrowPage`
+pub fn export_escrows(server: Option<ScAddress>, start: u64, limit: u32) -> Invocation {
+    Invocation {
+        function: "export_escrows",
+        args: vec![
+            server.map_or(ScVal::Void, ScVal::Address),
+            ScVal::U64(start),
+            ScVal::U32(limit),
+        ],
+    }
+}
+
+/// `find_escrow(client, server) -> Option<u64>`
+pub fn find_escrow(client: ScAddress, server: ScAddress) -> Invocation {
+    Invocation {
+        function: "find_escrow",
+        args: vec![ScVal::Address(client), ScVal::Address(server)],
+    }
+}
+
+/// `get_escrow_index(client, archived, page) -> EscrowIdPage`
+pub fn get_escrow_index(client: ScAddress, archived: bool, page: u32) -> Invocation {
+    Invocation {
+        function: "get_escrow_index",
+        args: vec![
+            ScVal::Address(client),
+            ScVal::Bool(archived),
+            ScVal::U32(page),
+        ],
+    }
+}
+
+/// `compact_escrow_index(client, start, pages) -> Option<u32>`
+pub fn compact_escrow_index(client: ScAddress, start: u32, pages: u32) -> Invocation {
+    Invocation {
+        function: "compact_escrow_index",
+        args: vec![ScVal::Address(client), ScVal::U32(start), ScVal::U32(pages)],
+    }
+}
+
+/// `post_note(escrow_id, author, body)`
+///
+/// # Errors
+/// * If the note does not fit an XDR byte string
+pub fn post_note(
+    escrow_id: u64,
+    author: ScAddress,
+    body: &[u8],
+) -> Result<Invocation, stellar_xdr::curr::Error> {
+    Ok(Invocation {
+        function: "post_note",
+        args: vec![
+            ScVal::U64(escrow_id),
+            ScVal::Address(author),
+            ScVal::Bytes(ScBytes(body.try_into()?)),
+        ],
+    })
+}
+
+/// `get_notes(escrow_id) -> Vec<Note>`
+pub fn get_notes(escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "get_notes",
+        args: vec![ScVal::U64(escrow_id)],
+    }
+}
+
+/// `consent_to_migration(escrow_id, party, target)`
+pub fn consent_to_migration(escrow_id: u64, party: ScAddress, target: ScAddress) -> Invocation {
+    Invocation {
+        function: "consent_to_migration",
+        args: vec![
+            ScVal::U64(escrow_id),
+            ScVal::Address(party),
+            ScVal::Address(target),
+        ],
+    }
+}
+
+/// `export_for_migration(admin, escrow_id) -> MigrationExport`
+pub fn export_for_migration(admin: ScAddress, escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "export_for_migration",
+        args: vec![ScVal::Address(admin), ScVal::U64(escrow_id)],
+    }
+}
+
+/// `get_migration(escrow_id) -> Option<MigrationExport>`
+pub fn get_migration(escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "get_migration",
+        args: vec![ScVal::U64(escrow_id)],
+    }
+}
+
+/// `allow_migration_source(admin, source)`
+pub fn allow_migration_source(admin: ScAddress, source: ScAddress) -> Invocation {
+    Invocation {
+        function: "allow_migration_source",
+        args: vec![ScVal::Address(admin), ScVal::Address(source)],
+    }
+}
+
+/// `import_escrow(summary, proof) -> u64`
+///
+/// `summary` is passed on as exported, since the proof covers its encoding.
+pub fn import_escrow(summary: ScVal, proof: [u8; 32]) -> Invocation {
+    Invocation {
+        function: "import_escrow",
+        args: vec![summary, bytes32(proof)],
+    }
+}
+
+/// `set_dust_policy(admin, threshold, max_idle)`
+pub fn set_dust_policy(admin: ScAddress, threshold: i128, max_idle: u64) -> Invocation {
+    Invocation {
+        function: "set_dust_policy",
+        args: vec![ScVal::Address(admin), i128(threshold), ScVal::U64(max_idle)],
+    }
+}
+
+/// `get_dust_policy() -> Option<DustPolicy>`
+pub fn get_dust_policy() -> Invocation {
+    Invocation {
+        function: "get_dust_policy",
+        args: vec![],
+    }
+}
+
+/// `set_max_escrows_per_client(admin, max_escrows_per_client)`
+pub fn set_max_escrows_per_client(
+    admin: ScAddress,
+    max_escrows_per_client: Option<u32>,
+) -> Invocation {
+    Invocation {
+        function: "set_max_escrows_per_client",
+        args: vec![
+            ScVal::Address(admin),
+            max_escrows_per_client.map_or(ScVal::Void, ScVal::U32),
+        ],
+    }
+}
+
+/// `initialize(admin)`
+pub fn initialize(admin: ScAddress) -> Invocation {
+    Invocation {
+        function: "initialize",
+        args: vec![ScVal::Address(admin)],
+    }
+}
+
+/// `version() -> u32`
+pub fn version() -> Invocation {
+    Invocation {
+        function: "version",
+        args: vec![],
+    }
+}
+
+/// `get_max_escrows_per_client() -> Option<u32>`
+pub fn get_max_escrows_per_client() -> Invocation {
+    Invocation {
+        function: "get_max_escrows_per_client",
+        args: vec![],
+    }
+}
+
+/// `set_clock_skew(admin, seconds)`
+pub fn set_clock_skew(admin: ScAddress, seconds: u64) -> Invocation {
+    Invocation {
+        function: "set_clock_skew",
+        args: vec![ScVal::Address(admin), ScVal::U64(seconds)],
+    }
+}
+
+/// `get_clock_skew() -> u64`
+pub fn get_clock_skew() -> Invocation {
+    Invocation {
+        function: "get_clock_skew",
+        args: vec![],
+    }
+}
+
+/// `get_client_escrow_count(client) -> u32`
+pub fn get_client_escrow_count(client: ScAddress) -> Invocation {
+    Invocation {
+        function: "get_client_escrow_count",
+        args: vec![ScVal::Address(client)],
+    }
+}
+
+/// `set_refund_address(client, refund_to)`
+pub fn set_refund_address(client: ScAddress, refund_to: ScAddress) -> Invocation {
+    Invocation {
+        function: "set_refund_address",
+        args: vec![ScVal::Address(client), ScVal::Address(refund_to)],
+    }
+}
+
+/// `get_refund_address(client) -> Address`
+pub fn get_refund_address(client: ScAddress) -> Invocation {
+    Invocation {
+        function: "get_refund_address",
+        args: vec![ScVal::Address(client)],
+    }
+}
+
+/// `set_terms(server, terms_hash)`
+pub fn set_terms(server: ScAddress, terms_hash: [u8; 32]) -> Invocation {
+    Invocation {
+        function: "set_terms",
+        args: vec![ScVal::Address(server), bytes32(terms_hash)],
+    }
+}
+
+/// `get_terms(server) -> Option<BytesN<32>>`
+pub fn get_terms(server: ScAddress) -> Invocation {
+    Invocation {
+        function: "get_terms",
+        args: vec![ScVal::Address(server)],
+    }
+}
+
+/// `set_notification_url(server, url)`, an empty URL removing it
+///
+/// # Errors
+/// * If the URL does not fit an XDR byte string
+pub fn set_notification_url(
+    server: ScAddress,
+    url: &str,
+) -> Result<Invocation, stellar_xdr::curr::Error> {
+    Ok(Invocation {
+        function: "set_notification_url",
+        args: vec![
+            ScVal::Address(server),
+            ScVal::Bytes(ScBytes(url.as_bytes().try_into()?)),
+        ],
+    })
+}
+
+/// `get_notification_url(server) -> Option<Bytes>`
+pub fn get_notification_url(server: ScAddress) -> Invocation {
+    Invocation {
+        function: "get_notification_url",
+        args: vec![ScVal::Address(server)],
+    }
+}
+
+/// `get_last_activity(escrow_id) -> u64`
+pub fn get_last_activity(escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "get_last_activity",
+        args: vec![ScVal::U64(escrow_id)],
+    }
+}
+
+/// `sweep_dust(admin, escrow_ids) -> i128`
+///
+/// # Errors
+/// * If there are more than `u32::MAX` escrows
+pub fn sweep_dust(
+    admin: ScAddress,
+    escrow_ids: &[u64],
+) -> Result<Invocation, stellar_xdr::curr::Error> {
+    let ids: std::vec::Vec<ScVal> = escrow_ids.iter().copied().map(ScVal::U64).collect();
+    Ok(Invocation {
+        function: "sweep_dust",
+        args: vec![ScVal::Address(admin), ScVal::Vec(Some(ids.try_into()?))],
+    })
+}
+
+/// `grant_agent(client, agent, total_cap, per_payment_cap, expires_at)`
+pub fn grant_agent(
+    client: ScAddress,
+    agent: ScAddress,
+    total_cap: i128,
+    per_payment_cap: i128,
+    expires_at: u64,
+) -> Invocation {
+    Invocation {
+        function: "grant_agent",
+        args: vec![
+            ScVal::Address(client),
+            ScVal::Address(agent),
+            i128(total_cap),
+            i128(per_payment_cap),
+            ScVal::U64(expires_at),
+        ],
+    }
+}
+
+/// `revoke_agent(client, agent)`
+pub fn revoke_agent(client: ScAddress, agent: ScAddress) -> Invocation {
+    Invocation {
+        function: "revoke_agent",
+        args: vec![ScVal::Address(client), ScVal::Address(agent)],
+    }
+}
+
+/// `get_allowance(client, agent) -> Option<Allowance>`
+pub fn get_allowance(client: ScAddress, agent: ScAddress) -> Invocation {
+    Invocation {
+        function: "get_allowance",
+        args: vec![ScVal::Address(client), ScVal::Address(agent)],
+    }
+}
+
+/// `set_max_settled_per_hour(escrow_id, max_settled_per_hour)`
+pub fn set_max_settled_per_hour(escrow_id: u64, max_settled_per_hour: Option<i128>) -> Invocation {
+    Invocation {
+        function: "set_max_settled_per_hour",
+        args: vec![
+            ScVal::U64(escrow_id),
+            max_settled_per_hour.map_or(ScVal::Void, i128),
+        ],
+    }
+}
+
+/// `acknowledge_anomaly(escrow_id)`
+pub fn acknowledge_anomaly(escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "acknowledge_anomaly",
+        args: vec![ScVal::U64(escrow_id)],
+    }
+}
+
+/// `get_spend_limit(escrow_id) -> Option<SpendLimit>`
+pub fn get_spend_limit(escrow_id: u64) -> Invocation {
+    Invocation {
+        function: "get_spend_limit",
+        args: vec![ScVal::U64(escrow_id)],
+    }
+}
+
+/// `create_authorized_payment(authorization, public_key, signature) -> u64`
+///
+/// `authorization` is encoded by [`authorization`].
+pub fn create_authorized_payment(
+    authorization: ScVal,
+    public_key: [u8; 32],
+    signature: [u8; 64],
+) -> Invocation {
+    Invocation {
+        function: "create_authorized_payment",
+        args: vec![
+            authorization,
+            bytes32(public_key),
+            ScVal::Bytes(ScBytes(signature.try_into().expect("64 bytes fit"))),
+        ],
+    }
+}
+
+/// `open_and_authorize(client, server, amount, terms_hash, authorization,
+/// public_key, signature) -> OpenedEscrow`
+///
+/// `authorization` is encoded by [`authorization`], for the escrow ID
+/// `open_escrow` would assign.
+pub fn open_and_authorize(
+    client: ScAddress,
+    server: ScAddress,
+    amount: i128,
+    terms_hash: Option<[u8; 32]>,
+    authorization: ScVal,
+    public_key: [u8; 32],
+    signature: [u8; 64],
+) -> Invocation {
+    Invocation {
+        function: "open_and_authorize",
+        args: vec![
+            ScVal::Address(client),
+            ScVal::Address(server),
+            i128(amount),
+            terms_hash.map_or(ScVal::Void, bytes32),
+            authorization,
+            bytes32(public_key),
+            ScVal::Bytes(ScBytes(signature.try_into().expect("64 bytes fit"))),
+        ],
+    }
+}
+
+/// `get_preauthorized_payment(escrow_id, nonce) -> Option<u64>`
+pub fn get_preauthorized_payment(escrow_id: u64, nonce: u64) -> Invocation {
+    Invocation {
+        function: "get_preauthorized_payment",
+        args: vec![ScVal::U64(escrow_id), ScVal::U64(nonce)],
+    }
+}
+
+/// Encode the signed fields of a payment authorization as the contract's
+/// `Authorization`
+///
+/// # Errors
+/// * If `network` is longer than an XDR string allows
+pub fn authorization(
+    escrow_id: u64,
+    network: &str,
+    amount: i128,
+    nonce: u64,
+    expires_at: u64,
+) -> Result<ScVal, stellar_xdr::curr::Error> {
+    // Struct fields encode as a map keyed by name, in key order
+    Ok(fields([
+        ("amount", i128(amount)),
+        ("escrow_id", ScVal::U64(escrow_id)),
+        ("expires_at", ScVal::U64(expires_at)),
+        ("network", ScVal::String(ScString(network.try_into()?))),
+        ("nonce", ScVal::U64(nonce)),
+    ]))
+}
+
+/// Encode the terms a server expects of a payment it settles as the
+/// contract's `Settlement`
+///
+/// The server signs these terms, so a settlement whose payment does not
+/// match them in escrow, amount and client fails instead of going through.
+pub fn settlement(payment_id: u64, escrow_id: u64, amount: i128, client: ScAddress) -> ScVal {
+    // Struct fields encode as a map keyed by name, in key order
+    fields([
+        ("amount", i128(amount)),
+        ("client", ScVal::Address(client)),
+        ("escrow_id", ScVal::U64(escrow_id)),
+        ("payment_id", ScVal::U64(payment_id)),
+    ])
+}
+
+/// Encode a payment created in a batch as the contract's `PaymentIntent`
+pub fn payment_intent(escrow_id: u64, amount: i128) -> ScVal {
+    // Struct fields encode as a map keyed by name, in key order
+    fields([
+        ("amount", i128(amount)),
+        ("escrow_id", ScVal::U64(escrow_id)),
+    ])
+}
+
+/// Encode named struct fields, given in key order, as a map
+fn fields<const N: usize>(fields: [(&str, ScVal); N]) -> ScVal {
+    let entries: std::vec::Vec<ScMapEntry> = fields
+        .into_iter()
+        .map(|(name, val)| ScMapEntry {
+            key: ScVal::Symbol(ScSymbol(
+                name.try_into().expect("field name is a valid symbol"),
+            )),
+            val,
+        })
+        .collect();
+    // A handful of fields is well within XDR limits
+    ScVal::Map(Some(ScMap(
+        entries.try_into().expect("fields fit in a map"),
+    )))
+}
+
+fn payment_status(status: PaymentStatus) -> ScVal {
+    unit_variant(match status {
+        PaymentStatus::Pending => "Pending",
+        PaymentStatus::Settled => "Settled",
+        PaymentStatus::Failed => "Failed",
+    })
+}
+
+/// Unit enum variants encode as a vector holding their name
+fn unit_variant(name: &str) -> ScVal {
+    // Variant names are short symbols, well within XDR limits
+    let symbol = ScSymbol(name.try_into().expect("variant name is a valid symbol"));
+    let variant = ScVec(
+        vec![ScVal::Symbol(symbol)]
+            .try_into()
+            .expect("one element fits"),
+    );
+    ScVal::Vec(Some(variant))
+}
+
+fn bytes32(value: [u8; 32]) -> ScVal {
+    ScVal::Bytes(ScBytes(value.try_into().expect("32 bytes fit")))
+}
+
+fn i128(value: i128) -> ScVal {
+    ScVal::I128(Int128Parts {
+        hi: (value >> 64) as i64,
+        lo: value as u64,
+    })
+}
+
+/// Contract error codes, as declared by the contract
+pub mod codes {
+    use x402_escrow::Error;
+
+    pub const ESCROW_NOT_FOUND: u32 = Error::EscrowNotFound as u32;
+    pub const ESCROW_ALREADY_EXISTS: u32 = Error::EscrowAlreadyExists as u32;
+    pub const INSUFFICIENT_BALANCE: u32 = Error::InsufficientBalance as u32;
+    pub const PAYMENT_NOT_FOUND: u32 = Error::PaymentNotFound as u32;
+    pub const PAYMENT_ALREADY_SETTLED: u32 = Error::PaymentAlreadySettled as u32;
+    pub const UNAUTHORIZED: u32 = Error::Unauthorized as u32;
+    pub const ORACLE_NOT_SET: u32 = Error::OracleNotSet as u32;
+    pub const PRICE_UNAVAILABLE: u32 = Error::PriceUnavailable as u32;
+    pub const STALE_PRICE: u32 = Error::StalePrice as u32;
+    pub const PRICE_SLIPPAGE: u32 = Error::PriceSlippage as u32;
+    pub const PRICE_NOT_SET: u32 = Error::PriceNotSet as u32;
+    pub const INVALID_SIGNER: u32 = Error::InvalidSigner as u32;
+    pub const NETWORK_MISMATCH: u32 = Error::NetworkMismatch as u32;
+    pub const DEPOSIT_ALREADY_CLAIMED: u32 = Error::DepositAlreadyClaimed as u32;
+    pub const ESCROW_FROZEN: u32 = Error::EscrowFrozen as u32;
+    pub const MIGRATION_NOT_APPROVED: u32 = Error::MigrationNotApproved as u32;
+    pub const MIGRATION_MISMATCH: u32 = Error::MigrationMismatch as u32;
+    pub const TOO_MANY_PENDING_PAYMENTS: u32 = Error::TooManyPendingPayments as u32;
+    pub const DUST_POLICY_NOT_SET: u32 = Error::DustPolicyNotSet as u32;
+    pub const INVALID_DUST_POLICY: u32 = Error::InvalidDustPolicy as u32;
+    pub const ESCROW_NOT_DUST: u32 = Error::EscrowNotDust as u32;
+    pub const PENDING_PAYMENTS: u32 = Error::PendingPayments as u32;
+    pub const INVALID_ALLOWANCE: u32 = Error::InvalidAllowance as u32;
+    pub const ALLOWANCE_NOT_FOUND: u32 = Error::AllowanceNotFound as u32;
+    pub const ALLOWANCE_EXPIRED: u32 = Error::AllowanceExpired as u32;
+    pub const ALLOWANCE_EXCEEDED: u32 = Error::AllowanceExceeded as u32;
+    pub const AUTHORIZATION_USED: u32 = Error::AuthorizationUsed as u32;
+    pub const AUTHORIZATION_EXPIRED: u32 = Error::AuthorizationExpired as u32;
+    pub const PAYMENT_NOT_SETTLED: u32 = Error::PaymentNotSettled as u32;
+    pub const INVALID_REFUND: u32 = Error::InvalidRefund as u32;
+    pub const TOO_MANY_ESCROWS: u32 = Error::TooManyEscrows as u32;
+    pub const INVALID_PROMO: u32 = Error::InvalidPromo as u32;
+    pub const PRICE_EXCEEDED: u32 = Error::PriceExceeded as u32;
+    pub const INVALID_NOTE: u32 = Error::InvalidNote as u32;
+    pub const NOTE_RATE_LIMITED: u32 = Error::NoteRateLimited as u32;
+    pub const SETTLEMENT_MISMATCH: u32 = Error::SettlementMismatch as u32;
+    pub const TERMS_MISMATCH: u32 = Error::TermsMismatch as u32;
+    pub const INVALID_NOTIFICATION_URL: u32 = Error::InvalidNotificationUrl as u32;
+    pub const AUTHORIZATION_MISMATCH: u32 = Error::AuthorizationMismatch as u32;
+    pub const SETTLEMENTS_PAUSED: u32 = Error::SettlementsPaused as u32;
+    pub const INVALID_SPEND_LIMIT: u32 = Error::InvalidSpendLimit as u32;
+    pub const RESOURCE_SUSPENDED: u32 = Error::ResourceSuspended as u32;
+    pub const INVALID_SPLIT: u32 = Error::InvalidSplit as u32;
+    pub const INVALID_DIRECT_PAYMENT: u32 = Error::InvalidDirectPayment as u32;
+    pub const INVALID_TRIAL: u32 = Error::InvalidTrial as u32;
+    pub const ALREADY_INITIALIZED: u32 = Error::AlreadyInitialized as u32;
+    pub const NOT_INITIALIZED: u32 = Error::NotInitialized as u32;
+    pub const INVALID_AMOUNT: u32 = Error::InvalidAmount as u32;
+    pub const AUTHORIZATIONS_LIVE: u32 = Error::AuthorizationsLive as u32;
+}
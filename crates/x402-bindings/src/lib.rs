@@ -0,0 +1,16 @@
+//! # x402 Bindings
+//!
+//! Function names and argument encodings of the x402 escrow contract, shared
+//! by the off-chain crates.
+//!
+//! ## Key Features
+//! - [`Invocation`] builders for every entry point the SDK calls
+//! - Each builder checked at compile time against the contract function it
+//!   encodes, so a changed argument or return type fails the workspace build
+//! - Contract error codes taken from the contract's `Error` enum
+
+mod escrow;
+
+pub use escrow::*;
+
+mod test;
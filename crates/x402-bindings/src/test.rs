@@ -0,0 +1,613 @@
+#![cfg(test)]
+
+use soroban_sdk::{
+    testutils::{Address as _, Ledger as _},
+    token, Address, Env, Symbol, TryFromVal, Val, Vec,
+};
+use stellar_xdr::curr::{Int128Parts, ScAddress, ScSymbol, ScVal};
+use x402_escrow::{MigrationExport, PaymentPage, PaymentStatus, X402EscrowContract};
+
+use crate::{codes, Invocation};
+
+/// Run an invocation through the host, as a transaction would
+fn invoke(env: &Env, contract: &Address, call: Invocation) -> Result<ScVal, soroban_sdk::Error> {
+    let mut args = Vec::new(env);
+    for arg in &call.args {
+        args.push_back(Val::try_from_val(env, arg).unwrap());
+    }
+    let function = Symbol::new(env, call.function);
+    match env.try_invoke_contract::<Val, soroban_sdk::Error>(contract, &function, args) {
+        Ok(Ok(value)) => Ok(ScVal::try_from_val(env, &value).unwrap()),
+        Ok(Err(e)) => panic!("{} returned an unexpected value: {e:?}", call.function),
+        Err(Ok(e)) => Err(e),
+        Err(Err(e)) => panic!("{} could not be invoked: {e:?}", call.function),
+    }
+}
+
+fn u64(value: u64) -> ScVal {
+    ScVal::U64(value)
+}
+
+/// ID returned by an open or a payment
+fn id(result: Result<ScVal, soroban_sdk::Error>) -> u64 {
+    match result {
+        Ok(ScVal::U64(id)) => id,
+        other => panic!("expected an ID, got {other:?}"),
+    }
+}
+
+fn i128(value: i128) -> ScVal {
+    ScVal::I128(Int128Parts {
+        hi: (value >> 64) as i64,
+        lo: value as u64,
+    })
+}
+
+#[test]
+fn test_bindings_invoke_contract() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract = env.register(X402EscrowContract, ());
+    let (client, server) = (Address::generate(&env), Address::generate(&env));
+    let (client_sc, server_sc) = (ScAddress::from(&client), ScAddress::from(&server));
+    let call = |invocation| invoke(&env, &contract, invocation);
+
+    assert_eq!(call(crate::version()), Ok(ScVal::U32(1)));
+    assert_eq!(call(crate::get_terms(server_sc.clone())), Ok(ScVal::Void));
+    assert_eq!(
+        call(crate::set_terms(server_sc.clone(), [7; 32])),
+        Ok(ScVal::Void)
+    );
+    assert!(matches!(
+        call(crate::get_terms(server_sc.clone())),
+        Ok(ScVal::Bytes(_))
+    ));
+    let url = "https://server.example/x402/hooks";
+    assert_eq!(
+        call(crate::set_notification_url(server_sc.clone(), url).unwrap()),
+        Ok(ScVal::Void)
+    );
+    assert!(matches!(
+        call(crate::get_notification_url(server_sc.clone())),
+        Ok(ScVal::Bytes(bytes)) if bytes.as_slice() == url.as_bytes()
+    ));
+    let escrow_id = id(call(crate::open_escrow(
+        client_sc.clone(),
+        server_sc.clone(),
+        1_000,
+        Some([7; 32]),
+    )));
+    assert_eq!(call(crate::deposit(escrow_id, 500)), Ok(ScVal::Void));
+    let staging = ScAddress::from(&Address::generate(&env));
+    assert_eq!(
+        call(crate::set_terms(staging.clone(), [7; 32])),
+        Ok(ScVal::Void)
+    );
+    let cloned = id(call(crate::clone_escrow_config(escrow_id, staging, 10)));
+    assert_ne!(cloned, escrow_id);
+    let child = id(call(crate::split_escrow(cloned, 4, b"tier:batch").unwrap()));
+    assert_ne!(child, cloned);
+    assert!(matches!(
+        call(crate::get_escrow_links(child)),
+        Ok(ScVal::Map(_))
+    ));
+    let trial_client = ScAddress::from(&Address::generate(&env));
+    let sponsored = id(call(crate::open_sponsored_escrow(
+        server_sc.clone(),
+        trial_client,
+        10,
+    )));
+    assert_ne!(sponsored, escrow_id);
+    // Payments are numbered within their escrow
+    let first = escrow_id << 32;
+    assert_eq!(call(crate::create_payment(escrow_id, 300)), Ok(u64(first)));
+    let terms =
+        |payment_id, amount| crate::settlement(payment_id, escrow_id, amount, client_sc.clone());
+    assert_eq!(
+        call(crate::settle_payment(terms(first, 300))),
+        Ok(ScVal::Bool(true))
+    );
+    assert_eq!(
+        call(crate::create_payment(escrow_id, 100)),
+        Ok(u64(first + 1))
+    );
+    assert_eq!(
+        call(crate::settle_payments(vec![terms(first + 1, 100)]).unwrap()),
+        Ok(i128(100))
+    );
+    let intents = vec![
+        crate::payment_intent(escrow_id, 10),
+        crate::payment_intent(escrow_id, 20),
+    ];
+    assert_eq!(
+        call(crate::create_payments(intents).unwrap()),
+        Ok(ScVal::Vec(Some(
+            vec![u64(first + 2), u64(first + 3)].try_into().unwrap()
+        )))
+    );
+    assert_eq!(call(crate::refund_payment(first + 1, 40)), Ok(i128(40)));
+    assert_eq!(call(crate::get_refunded(first + 1)), Ok(i128(40)));
+    assert_eq!(call(crate::get_escrow_balance(escrow_id)), Ok(i128(1_140)));
+    assert!(matches!(
+        call(crate::get_escrow(escrow_id)),
+        Ok(ScVal::Map(_))
+    ));
+    assert!(matches!(
+        call(crate::get_payment(first + 1)),
+        Ok(ScVal::Map(_))
+    ));
+    assert!(matches!(
+        call(crate::get_payments(escrow_id, None, 0, 10)),
+        Ok(ScVal::Map(_))
+    ));
+    assert!(matches!(
+        call(crate::get_quote_total(escrow_id)),
+        Ok(ScVal::Map(_))
+    ));
+    assert!(matches!(
+        call(crate::export_escrows(Some(server_sc.clone()), 0, 10)),
+        Ok(ScVal::Map(_))
+    ));
+    assert!(matches!(
+        call(crate::export_escrows(None, 0, 10)),
+        Ok(ScVal::Map(_))
+    ));
+    assert_eq!(
+        call(crate::find_escrow(client_sc.clone(), server_sc)),
+        Ok(u64(escrow_id))
+    );
+    assert_eq!(call(crate::client_close_escrow(escrow_id)), Ok(ScVal::Void));
+    assert_eq!(call(crate::server_close_escrow(escrow_id)), Ok(i128(1_140)));
+    assert!(matches!(
+        call(crate::get_escrow_index(client_sc.clone(), true, 0)),
+        Ok(ScVal::Map(_))
+    ));
+    assert_eq!(
+        call(crate::compact_escrow_index(client_sc, 0, 1)),
+        Ok(ScVal::Void)
+    );
+}
+
+#[test]
+fn test_payment_status_encoding() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract = env.register(X402EscrowContract, ());
+    let client = ScAddress::from(&Address::generate(&env));
+    let server = ScAddress::from(&Address::generate(&env));
+    let call = |invocation| invoke(&env, &contract, invocation);
+
+    let escrow_id = id(call(crate::open_escrow(
+        client.clone(),
+        server,
+        1_000,
+        None,
+    )));
+    call(crate::create_payment(escrow_id, 300)).unwrap();
+    let payment_id = id(call(crate::create_payment(escrow_id, 100)));
+    call(crate::settle_payment(crate::settlement(
+        payment_id, escrow_id, 100, client,
+    )))
+    .unwrap();
+
+    // The contract decodes each status and filters by it
+    let count = |status| {
+        let page = call(crate::get_payments(escrow_id, status, 0, 10)).unwrap();
+        let page = PaymentPage::try_from_val(&env, &Val::try_from_val(&env, &page).unwrap());
+        page.unwrap().payments.len()
+    };
+    assert_eq!(count(None), 2);
+    assert_eq!(count(Some(PaymentStatus::Pending)), 1);
+    assert_eq!(count(Some(PaymentStatus::Settled)), 1);
+    assert_eq!(count(Some(PaymentStatus::Failed)), 0);
+}
+
+#[test]
+fn test_error_codes() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract = env.register(X402EscrowContract, ());
+    let call = |invocation| invoke(&env, &contract, invocation);
+
+    assert_eq!(
+        call(crate::get_escrow(9)),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::ESCROW_NOT_FOUND
+        ))
+    );
+
+    let client = ScAddress::from(&Address::generate(&env));
+    let server = ScAddress::from(&Address::generate(&env));
+    let settle = |payment_id, escrow_id, amount| {
+        call(crate::settle_payment(crate::settlement(
+            payment_id,
+            escrow_id,
+            amount,
+            client.clone(),
+        )))
+    };
+    assert_eq!(
+        settle(9, 0, 100),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::PAYMENT_NOT_FOUND
+        ))
+    );
+    let escrow_id = id(call(crate::open_escrow(
+        client.clone(),
+        server,
+        1_000,
+        None,
+    )));
+    let claim = || crate::claim_pending_deposit(escrow_id, client.clone(), 500, [3; 32]);
+    assert_eq!(
+        call(crate::is_deposit_claimed([3; 32])),
+        Ok(ScVal::Bool(false))
+    );
+    assert_eq!(call(claim()), Ok(ScVal::Void));
+    assert_eq!(
+        call(crate::is_deposit_claimed([3; 32])),
+        Ok(ScVal::Bool(true))
+    );
+    assert_eq!(
+        call(claim()),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::DEPOSIT_ALREADY_CLAIMED
+        ))
+    );
+
+    // A payment settles only on the terms the server signed
+    let payment_id = id(call(crate::create_payment(escrow_id, 100)));
+    assert_eq!(
+        settle(payment_id, escrow_id, 99),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::SETTLEMENT_MISMATCH
+        ))
+    );
+    assert_eq!(settle(payment_id, escrow_id, 100), Ok(ScVal::Bool(true)));
+
+    let note = || crate::post_note(escrow_id, client.clone(), b"top up by Friday").unwrap();
+    assert_eq!(call(note()), Ok(ScVal::Void));
+    assert_eq!(
+        call(note()),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::NOTE_RATE_LIMITED
+        ))
+    );
+    assert!(
+        matches!(call(crate::get_notes(escrow_id)), Ok(ScVal::Vec(Some(notes))) if notes.len() == 1)
+    );
+}
+
+#[test]
+fn test_direct_payment_bindings() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract = env.register(X402EscrowContract, ());
+    let call = |invocation| invoke(&env, &contract, invocation);
+    let client = Address::generate(&env);
+    let server = Address::generate(&env);
+    let token = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+    token::StellarAssetClient::new(&env, &token).mint(&client, &1_000);
+
+    let pay = |amount| {
+        crate::record_direct_payment(
+            ScAddress::from(&client),
+            ScAddress::from(&server),
+            ScAddress::from(&token),
+            amount,
+            [5; 32],
+        )
+    };
+    let payment_id = id(call(pay(400)));
+    assert_eq!(token::Client::new(&env, &token).balance(&client), 600);
+    // Listed as a settled direct payment of the server
+    let page = call(crate::get_direct_payments(
+        ScAddress::from(&server),
+        Some(PaymentStatus::Settled),
+        0,
+        10,
+    ))
+    .unwrap();
+    let page = PaymentPage::try_from_val(&env, &Val::try_from_val(&env, &page).unwrap()).unwrap();
+    assert_eq!(page.payments.len(), 1);
+    assert!(matches!(
+        call(crate::get_payment(payment_id)),
+        Ok(ScVal::Map(_))
+    ));
+    assert_eq!(
+        call(pay(400)),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::INVALID_DIRECT_PAYMENT
+        ))
+    );
+}
+
+#[test]
+fn test_migration_bindings() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let old = env.register(X402EscrowContract, ());
+    let new = env.register(X402EscrowContract, ());
+    let (old_sc, new_sc) = (ScAddress::from(&old), ScAddress::from(&new));
+    let admin = ScAddress::from(&Address::generate(&env));
+    let client = ScAddress::from(&Address::generate(&env));
+    let server = ScAddress::from(&Address::generate(&env));
+    invoke(&env, &old, crate::initialize(admin.clone())).unwrap();
+    invoke(&env, &new, crate::initialize(admin.clone())).unwrap();
+
+    let escrow_id = id(invoke(
+        &env,
+        &old,
+        crate::open_escrow(client.clone(), server.clone(), 1_000, None),
+    ));
+    invoke(&env, &old, crate::create_payment(escrow_id, 300)).unwrap();
+    let consent = |party| crate::consent_to_migration(escrow_id, party, new_sc.clone());
+    invoke(&env, &old, consent(client)).unwrap();
+    assert_eq!(
+        invoke(
+            &env,
+            &old,
+            crate::export_for_migration(admin.clone(), escrow_id)
+        ),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::MIGRATION_NOT_APPROVED
+        ))
+    );
+    invoke(&env, &old, consent(server)).unwrap();
+    let exported = invoke(
+        &env,
+        &old,
+        crate::export_for_migration(admin.clone(), escrow_id),
+    )
+    .unwrap();
+    assert_eq!(
+        invoke(&env, &old, crate::get_migration(escrow_id)),
+        Ok(exported.clone())
+    );
+    assert_eq!(
+        invoke(&env, &old, crate::deposit(escrow_id, 1)),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::ESCROW_FROZEN
+        ))
+    );
+
+    // The summary goes to the target as exported
+    let export = MigrationExport::try_from_val(&env, &Val::try_from_val(&env, &exported).unwrap());
+    let export = export.unwrap();
+    let ScVal::Map(Some(fields)) = exported else {
+        panic!("export is not a map: {exported:?}");
+    };
+    let summary = fields
+        .iter()
+        .find(|entry| entry.key == ScVal::Symbol(ScSymbol("summary".try_into().unwrap())))
+        .map(|entry| entry.val.clone())
+        .unwrap();
+    let import = || crate::import_escrow(summary.clone(), export.proof.to_array());
+    assert_eq!(
+        invoke(&env, &new, import()),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::MIGRATION_NOT_APPROVED
+        ))
+    );
+    invoke(&env, &new, crate::allow_migration_source(admin, old_sc)).unwrap();
+    let imported = id(invoke(&env, &new, import()));
+    assert_eq!(
+        invoke(&env, &new, crate::get_escrow_balance(imported)),
+        Ok(i128(1_000))
+    );
+}
+
+#[test]
+fn test_sweep_bindings() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract = env.register(X402EscrowContract, ());
+    let call = |invocation| invoke(&env, &contract, invocation);
+    let admin = ScAddress::from(&Address::generate(&env));
+    let client = ScAddress::from(&Address::generate(&env));
+    let refund_to = ScAddress::from(&Address::generate(&env));
+
+    assert_eq!(
+        call(crate::set_clock_skew(admin.clone(), 30)),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::NOT_INITIALIZED
+        ))
+    );
+    call(crate::initialize(admin.clone())).unwrap();
+    assert_eq!(
+        call(crate::initialize(client.clone())),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::ALREADY_INITIALIZED
+        ))
+    );
+    assert_eq!(call(crate::get_dust_policy()), Ok(ScVal::Void));
+    assert_eq!(
+        call(crate::set_dust_policy(admin.clone(), 100, 1)),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::INVALID_DUST_POLICY
+        ))
+    );
+    call(crate::set_dust_policy(
+        admin.clone(),
+        100,
+        crate::MIN_DUST_IDLE,
+    ))
+    .unwrap();
+    assert!(matches!(call(crate::get_dust_policy()), Ok(ScVal::Map(_))));
+    assert_eq!(call(crate::get_max_escrows_per_client()), Ok(ScVal::Void));
+    call(crate::set_max_escrows_per_client(admin.clone(), Some(1))).unwrap();
+    assert_eq!(call(crate::get_max_escrows_per_client()), Ok(ScVal::U32(1)));
+    call(crate::set_clock_skew(admin.clone(), 30)).unwrap();
+    assert_eq!(call(crate::get_clock_skew()), Ok(u64(30)));
+    call(crate::set_refund_address(client.clone(), refund_to.clone())).unwrap();
+    assert_eq!(
+        call(crate::get_refund_address(client.clone())),
+        Ok(ScVal::Address(refund_to))
+    );
+
+    let server = ScAddress::from(&Address::generate(&env));
+    let escrow_id = id(call(crate::open_escrow(client.clone(), server, 50, None)));
+    assert_eq!(call(crate::get_last_activity(escrow_id)), Ok(u64(0)));
+    assert_eq!(
+        call(crate::get_client_escrow_count(client.clone())),
+        Ok(ScVal::U32(1))
+    );
+    assert_eq!(
+        call(crate::sweep_dust(admin.clone(), &[escrow_id]).unwrap()),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::ESCROW_NOT_DUST
+        ))
+    );
+    env.ledger().set_timestamp(crate::MIN_DUST_IDLE);
+    assert_eq!(
+        call(crate::sweep_dust(admin, &[escrow_id]).unwrap()),
+        Ok(i128(50))
+    );
+}
+
+#[test]
+fn test_spend_limit_bindings() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract = env.register(X402EscrowContract, ());
+    let call = |invocation| invoke(&env, &contract, invocation);
+    let client = ScAddress::from(&Address::generate(&env));
+    let server = ScAddress::from(&Address::generate(&env));
+    let escrow_id = id(call(crate::open_escrow(
+        client.clone(),
+        server,
+        1_000,
+        None,
+    )));
+
+    assert_eq!(
+        call(crate::set_max_settled_per_hour(escrow_id, Some(0))),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::INVALID_SPEND_LIMIT
+        ))
+    );
+    assert_eq!(
+        call(crate::set_max_settled_per_hour(escrow_id, Some(100))),
+        Ok(ScVal::Void)
+    );
+    assert!(matches!(
+        call(crate::get_spend_limit(escrow_id)),
+        Ok(ScVal::Map(_))
+    ));
+    // The breach pauses settlements
+    let payment_id = id(call(crate::create_payment(escrow_id, 150)));
+    let terms = crate::settlement(payment_id, escrow_id, 150, client);
+    assert_eq!(
+        call(crate::settle_payment(terms.clone())),
+        Ok(ScVal::Bool(false))
+    );
+    assert_eq!(
+        call(crate::settle_payment(terms)),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::SETTLEMENTS_PAUSED
+        ))
+    );
+    assert_eq!(call(crate::acknowledge_anomaly(escrow_id)), Ok(ScVal::Void));
+    assert_eq!(
+        call(crate::set_max_settled_per_hour(escrow_id, None)),
+        Ok(ScVal::Void)
+    );
+    assert_eq!(call(crate::get_spend_limit(escrow_id)), Ok(ScVal::Void));
+}
+
+#[test]
+fn test_agent_bindings() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract = env.register(X402EscrowContract, ());
+    let call = |invocation| invoke(&env, &contract, invocation);
+    let client = ScAddress::from(&Address::generate(&env));
+    let agent = ScAddress::from(&Address::generate(&env));
+
+    assert_eq!(
+        call(crate::get_allowance(client.clone(), agent.clone())),
+        Ok(ScVal::Void)
+    );
+    assert_eq!(
+        call(crate::grant_agent(
+            client.clone(),
+            agent.clone(),
+            100,
+            200,
+            50
+        )),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::INVALID_ALLOWANCE
+        ))
+    );
+    call(crate::grant_agent(
+        client.clone(),
+        agent.clone(),
+        100,
+        20,
+        50,
+    ))
+    .unwrap();
+    assert!(matches!(
+        call(crate::get_allowance(client.clone(), agent.clone())),
+        Ok(ScVal::Map(_))
+    ));
+    call(crate::revoke_agent(client.clone(), agent.clone())).unwrap();
+    assert_eq!(
+        call(crate::revoke_agent(client.clone(), agent)),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::ALLOWANCE_NOT_FOUND
+        ))
+    );
+
+    // The authorization decodes, then fails on the network of the test ledger
+    let server = ScAddress::from(&Address::generate(&env));
+    let escrow_id = id(call(crate::open_escrow(client, server.clone(), 500, None)));
+    let pay = |escrow_id| {
+        let authorization = crate::authorization(escrow_id, "stellar-testnet", 10, 1, 100).unwrap();
+        crate::create_authorized_payment(authorization, [1; 32], [2; 64])
+    };
+    assert_eq!(
+        call(pay(escrow_id)),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::NETWORK_MISMATCH
+        ))
+    );
+    assert_eq!(
+        call(pay(escrow_id + 1)),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::ESCROW_NOT_FOUND
+        ))
+    );
+    env.ledger().set_timestamp(101);
+    assert_eq!(
+        call(pay(escrow_id)),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::AUTHORIZATION_EXPIRED
+        ))
+    );
+    // Opening with an authorization for another escrow opens nothing
+    let newcomer = ScAddress::from(&Address::generate(&env));
+    let authorization = crate::authorization(u64::MAX, "stellar-testnet", 10, 1, 200).unwrap();
+    assert_eq!(
+        call(crate::open_and_authorize(
+            newcomer.clone(),
+            server.clone(),
+            500,
+            None,
+            authorization,
+            [1; 32],
+            [2; 64]
+        )),
+        Err(soroban_sdk::Error::from_contract_error(
+            codes::AUTHORIZATION_MISMATCH
+        ))
+    );
+    assert_eq!(call(crate::find_escrow(newcomer, server)), Ok(ScVal::Void));
+    assert_eq!(
+        call(crate::get_preauthorized_payment(escrow_id, 1)),
+        Ok(ScVal::Void)
+    );
+}
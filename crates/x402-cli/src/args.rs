@@ -0,0 +1,341 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use x402_types::{StellarAmount, NATIVE_ASSET};
+
+use crate::{Error, HealthPolicy, KeySource, DAY};
+
+/// Manage x402 escrows from the command line
+#[derive(Clone, Debug, Parser)]
+#[command(name = "x402-cli", version)]
+pub struct Cli {
+    /// Soroban RPC URL
+    #[arg(long, env = "X402_RPC_URL", global = true)]
+    pub rpc_url: Option<String>,
+    /// Escrow contract address (C... format)
+    #[arg(long, env = "X402_CONTRACT_ID", global = true)]
+    pub contract_id: Option<String>,
+    /// Network passphrase, read from the RPC server when omitted
+    #[arg(long, env = "X402_NETWORK_PASSPHRASE", global = true)]
+    pub network_passphrase: Option<String>,
+    #[command(flatten)]
+    pub key: KeyArgs,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Table, global = true)]
+    pub output: Format,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Where the signing key comes from
+#[derive(Clone, Debug, Args)]
+#[group(multiple = false)]
+pub struct KeyArgs {
+    /// File holding an S... secret key
+    #[arg(long, global = true)]
+    pub key_file: Option<PathBuf>,
+    /// Environment variable holding an S... secret key
+    #[arg(long, global = true)]
+    pub key_env: Option<String>,
+    /// stellar-cli identity name
+    #[arg(long, global = true)]
+    pub identity: Option<String>,
+}
+
+impl KeyArgs {
+    /// Key source selected by the flags, `X402_SECRET_KEY` by default
+    pub fn source(&self) -> KeySource {
+        if let Some(path) = &self.key_file {
+            KeySource::File(path.clone())
+        } else if let Some(name) = &self.identity {
+            KeySource::Identity(name.clone())
+        } else {
+            KeySource::Env(
+                self.key_env
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_KEY_ENV.into()),
+            )
+        }
+    }
+}
+
+/// Environment variable read when no key source is given
+pub const DEFAULT_KEY_ENV: &str = "X402_SECRET_KEY";
+
+/// Output format
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum Format {
+    /// Human-readable table
+    #[default]
+    Table,
+    /// JSON document
+    Json,
+}
+
+/// Party closing an escrow
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum Party {
+    #[default]
+    Client,
+    Server,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Open an escrow with a server, signing as the client
+    Open {
+        /// Server address (G... format)
+        #[arg(long)]
+        server: String,
+        /// Initial deposit, in units of the asset with up to 7 decimals
+        #[arg(long)]
+        amount: StellarAmount,
+    },
+    /// Deposit into an escrow, signing as the client
+    Deposit {
+        /// Escrow ID
+        #[arg(long)]
+        escrow: u64,
+        /// Amount, in units of the asset with up to 7 decimals
+        #[arg(long)]
+        amount: StellarAmount,
+    },
+    /// Show an escrow and its balance
+    Balance {
+        /// Escrow ID
+        #[arg(long)]
+        escrow: u64,
+    },
+    /// Inspect payments
+    Payments {
+        #[command(subcommand)]
+        command: PaymentsCommand,
+    },
+    /// Settle a payment, signing as the server
+    Settle {
+        /// Payment ID
+        #[arg(long)]
+        payment: u64,
+    },
+    /// Write a signed receipt of a settled payment, signing as its server
+    Receipt {
+        /// Payment ID
+        #[arg(long)]
+        payment: u64,
+        /// Ledger at or before the payment's creation, within the RPC
+        /// server's event retention
+        #[arg(long)]
+        from_ledger: u32,
+        /// Asset the escrow holds
+        #[arg(long, default_value = NATIVE_ASSET)]
+        asset: String,
+        /// File the receipt JSON is written to
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+    },
+    /// Check a receipt's hash and signature, without the RPC server
+    VerifyReceipt {
+        /// Receipt JSON written by `receipt`
+        #[arg(long, value_name = "PATH")]
+        file: PathBuf,
+    },
+    /// Reconstruct how an escrow's balance evolved from its events, with a
+    /// running balance and gaps the events do not explain
+    History {
+        /// Escrow ID
+        escrow: u64,
+        /// First ledger searched, within the RPC server's event retention
+        #[arg(long)]
+        from: u32,
+        /// Last ledger searched, the latest by default; the history only
+        /// ends at the current balance without it
+        #[arg(long)]
+        to: Option<u32>,
+    },
+    /// Close an escrow for one party, releasing it once both closed
+    Close {
+        /// Escrow ID
+        #[arg(long)]
+        escrow: u64,
+        /// Party the signer closes for
+        #[arg(long = "as", value_enum, default_value_t = Party::Client)]
+        party: Party,
+    },
+    /// Summarize payments of open escrows, or of one escrow
+    Stats {
+        /// Only count payments of this escrow
+        #[arg(long)]
+        escrow: Option<u64>,
+    },
+    /// Inspect the payment journal of an SDK client, without the RPC server
+    Journal {
+        #[command(subcommand)]
+        command: JournalCommand,
+    },
+    /// Classify the health of every escrow of a server, exiting with status
+    /// 2 if any is red
+    Monitor(MonitorArgs),
+    /// Move an escrow to another deployment of the contract
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+    /// Refund settled payments listed in a CSV, signing as their server,
+    /// exiting with status 1 if any refund was not applied
+    Refund(RefundArgs),
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct RefundArgs {
+    /// CSV of `payment_id,amount` rows, amounts in units of the asset with
+    /// up to 7 decimals
+    #[arg(long, value_name = "PATH")]
+    pub from_csv: PathBuf,
+    /// Results CSV, `<from-csv>.results.csv` by default; refunds it records
+    /// are skipped when resuming
+    #[arg(long, value_name = "PATH")]
+    pub results: Option<PathBuf>,
+    /// Check every refund against the chain without submitting any
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Refunds checked and submitted per batch
+    #[arg(long, default_value_t = 20)]
+    pub batch_size: usize,
+    /// Retries of a call failing with a transient RPC error
+    #[arg(long, default_value_t = 3)]
+    pub retries: u32,
+}
+
+impl RefundArgs {
+    /// Where results are written
+    pub fn results_path(&self) -> PathBuf {
+        self.results
+            .clone()
+            .unwrap_or_else(|| self.from_csv.with_extension("results.csv"))
+    }
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct MonitorArgs {
+    /// Server address (G... format)
+    #[arg(long)]
+    pub server: String,
+    /// Print JSON, like `--output json`
+    #[arg(long)]
+    pub json: bool,
+    /// Print again every this many seconds, until interrupted
+    #[arg(long, value_name = "SECONDS")]
+    pub watch: Option<u64>,
+    /// Days without a payment after which an escrow is red
+    #[arg(long, default_value_t = 30)]
+    pub red_inactive_days: u64,
+    /// Days without a payment after which an escrow is yellow
+    #[arg(long, default_value_t = 7)]
+    pub yellow_inactive_days: u64,
+    /// Percentage of the balance pending payments may reach before an
+    /// escrow is yellow
+    #[arg(long, default_value_t = 80)]
+    pub max_exposure: u32,
+}
+
+impl MonitorArgs {
+    /// Thresholds set by the flags
+    pub fn policy(&self) -> HealthPolicy {
+        HealthPolicy {
+            red_inactive_secs: self.red_inactive_days * DAY,
+            yellow_inactive_secs: self.yellow_inactive_days * DAY,
+            yellow_exposure_pct: self.max_exposure,
+        }
+    }
+
+    /// Output format, JSON with `--json`
+    pub fn format(&self, output: Format) -> Format {
+        if self.json {
+            Format::Json
+        } else {
+            output
+        }
+    }
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum PaymentsCommand {
+    /// List payments of open escrows by ID
+    List {
+        /// Only list payments of this escrow, open or closed
+        #[arg(long)]
+        escrow: Option<u64>,
+        /// Lowest payment ID to list
+        #[arg(long, default_value_t = 0)]
+        from: u64,
+        /// Maximum number of payments to list
+        #[arg(long, default_value_t = 100)]
+        limit: u64,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum MigrateCommand {
+    /// Consent to moving an escrow, signing as its client or server
+    Consent {
+        /// Escrow ID
+        #[arg(long)]
+        escrow: u64,
+        /// Contract the escrow moves to (C... format)
+        #[arg(long)]
+        to: String,
+    },
+    /// Accept escrows exported by another deployment, signing as the admin
+    /// of this one
+    AllowSource {
+        /// Contract escrows are exported by (C... format)
+        #[arg(long)]
+        from: String,
+    },
+    /// Export an escrow both parties consented to move and import it into
+    /// its target, signing as the admin
+    Move {
+        /// Escrow ID
+        #[arg(long)]
+        escrow: u64,
+        /// Contract the escrow moves to (C... format)
+        #[arg(long)]
+        to: String,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum JournalCommand {
+    /// Export the settlements recorded in a journal
+    Export {
+        /// Journal file written by the SDK
+        #[arg(long)]
+        file: PathBuf,
+        /// Only export settlements recorded at or after this Unix time
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+        /// Only export settlements of payments to this host
+        #[arg(long)]
+        host: Option<String>,
+    },
+}
+
+impl Cli {
+    /// RPC URL, required by every command
+    ///
+    /// # Errors
+    /// * `Missing` - If neither `--rpc-url` nor `X402_RPC_URL` is set
+    pub fn rpc_url(&self) -> Result<&str, Error> {
+        self.rpc_url.as_deref().ok_or(Error::Missing("--rpc-url"))
+    }
+
+    /// Contract ID, required by every command
+    ///
+    /// # Errors
+    /// * `Missing` - If neither `--contract-id` nor `X402_CONTRACT_ID` is set
+    pub fn contract_id(&self) -> Result<&str, Error> {
+        self.contract_id
+            .as_deref()
+            .ok_or(Error::Missing("--contract-id"))
+    }
+}
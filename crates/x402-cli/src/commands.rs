@@ -0,0 +1,375 @@
+use std::path::Path;
+
+use serde_json::{json, Value};
+use x402_client::{
+    ContractError, Error as ClientError, EscrowClient, JournalEntry, Payment, PaymentJournal,
+    SettlementReceipt, Submitted,
+};
+use x402_types::StellarAmount;
+
+use crate::{
+    history, history_report, monitor, monitor_report, refund, refund_report, unix_now, Command,
+    Error, JournalCommand, MigrateCommand, Party, PaymentsCommand, Report,
+};
+
+/// Run `command` with `client`, signing as its signer
+///
+/// # Errors
+/// * `Client` - If a contract call fails
+pub async fn run(command: &Command, client: &EscrowClient) -> Result<Report, Error> {
+    let report = match command {
+        Command::Open { server, amount } => {
+            let opened = client
+                .open_escrow(&client.address(), server, amount.stroops(), None)
+                .await?;
+            Report::record(vec![
+                ("escrowId", json!(opened.value)),
+                ("client", json!(client.address())),
+                ("server", json!(server)),
+                ("amount", json!(amount.to_decimal_string())),
+                ("hash", json!(opened.hash)),
+                ("ledger", json!(opened.ledger)),
+            ])
+        }
+        Command::Deposit { escrow, amount } => {
+            let deposited = client.deposit(*escrow, amount.stroops()).await?;
+            let balance = client.get_escrow_balance(*escrow).await?;
+            Report::record(vec![
+                ("escrowId", json!(escrow)),
+                ("amount", json!(amount.to_decimal_string())),
+                ("balance", decimal(balance)),
+                ("hash", json!(deposited.hash)),
+                ("ledger", json!(deposited.ledger)),
+            ])
+        }
+        Command::Balance { escrow } => {
+            let details = client.get_escrow(*escrow).await?;
+            Report::record(vec![
+                ("escrowId", json!(escrow)),
+                ("client", json!(details.client)),
+                ("server", json!(details.server)),
+                ("balance", decimal(details.balance)),
+                ("clientClosed", json!(details.client_closed)),
+                ("serverClosed", json!(details.server_closed)),
+            ])
+        }
+        Command::Payments {
+            command:
+                PaymentsCommand::List {
+                    escrow,
+                    from,
+                    limit,
+                },
+        } => {
+            let rows = payments(client, *escrow)
+                .await?
+                .into_iter()
+                .filter(|(id, _)| id >= from)
+                .take(usize::try_from(*limit).unwrap_or(usize::MAX))
+                .map(|(id, payment)| {
+                    vec![
+                        json!(id),
+                        json!(payment.escrow_id),
+                        decimal(payment.amount),
+                        json!(payment.settled),
+                        json!(payment.timestamp),
+                        payment.quote_amount.map_or(Value::Null, usd),
+                    ]
+                })
+                .collect();
+            Report::list(
+                vec![
+                    "paymentId",
+                    "escrowId",
+                    "amount",
+                    "settled",
+                    "timestamp",
+                    "usd",
+                ],
+                rows,
+            )
+        }
+        Command::Settle { payment } => {
+            let settled = client.settle_payment(*payment).await?;
+            Report::record(vec![
+                ("paymentId", json!(payment)),
+                ("settled", json!(settled.value)),
+                ("hash", json!(settled.hash)),
+                ("ledger", json!(settled.ledger)),
+            ])
+        }
+        Command::Receipt {
+            payment,
+            from_ledger,
+            asset,
+            out,
+        } => {
+            let receipt = client
+                .settlement_receipt(*payment, asset, *from_ledger)
+                .await?;
+            let json = serde_json::to_string_pretty(&receipt).unwrap_or_default();
+            std::fs::write(out, json + "\n")?;
+            Report::record(vec![
+                ("paymentId", json!(payment)),
+                ("amount", decimal(payment_amount(&receipt)?)),
+                ("txHash", json!(receipt.body.tx_hash)),
+                ("receiptHash", json!(receipt.receipt_hash)),
+                ("file", json!(out.display().to_string())),
+            ])
+        }
+        Command::VerifyReceipt { file } => verify_receipt(file)?,
+        Command::History { escrow, from, to } => {
+            history_report(&history(client, *escrow, *from, *to).await?)
+        }
+        Command::Close { escrow, party } => {
+            let closed = match party {
+                Party::Client => client.client_close_escrow(*escrow).await?,
+                Party::Server => client.server_close_escrow(*escrow).await?,
+            };
+            close_report(*escrow, *party, closed)
+        }
+        Command::Stats { escrow } => {
+            let mut stats = Stats::default();
+            for (_, payment) in payments(client, *escrow).await? {
+                stats.add(&payment);
+            }
+            let mut fields = Vec::new();
+            if let Some(escrow) = escrow {
+                // Closed escrows are removed, their payments remain
+                let balance = match client.get_escrow_balance(*escrow).await {
+                    Ok(balance) => decimal(balance),
+                    Err(ClientError::Contract(ContractError::EscrowNotFound, _)) => Value::Null,
+                    Err(e) => return Err(e.into()),
+                };
+                fields.push(("escrowId", json!(escrow)));
+                fields.push(("balance", balance));
+            }
+            fields.extend([
+                ("payments", json!(stats.payments)),
+                ("settled", json!(stats.settled)),
+                ("pending", json!(stats.payments - stats.settled)),
+                ("settledAmount", decimal(stats.settled_amount)),
+                ("pendingAmount", decimal(stats.pending_amount)),
+                ("settledUsd", usd(stats.settled_usd)),
+                ("unquotedAmount", decimal(stats.unquoted_amount)),
+            ]);
+            Report::record(fields)
+        }
+        Command::Journal { command } => journal(command)?,
+        Command::Monitor(args) => {
+            let now = unix_now();
+            let escrows = monitor(client, &args.server, &args.policy(), now).await?;
+            monitor_report(&escrows, now)
+        }
+        Command::Migrate { command } => migrate(command, client).await?,
+        Command::Refund(args) => refund_report(&refund(args, client, |_| {}).await?),
+    };
+    Ok(report)
+}
+
+/// Run a journal `command`, which needs neither a key nor the RPC server
+///
+/// # Errors
+/// * `Client` - If the journal cannot be read
+pub fn journal(command: &JournalCommand) -> Result<Report, Error> {
+    let JournalCommand::Export { file, since, host } = command;
+    let journal = PaymentJournal::open(file)?;
+    let rows = journal
+        .entries()?
+        .into_iter()
+        .filter(|record| record.at >= *since)
+        .filter(|record| host.as_ref().is_none_or(|host| record.host == *host))
+        .filter_map(|record| {
+            let JournalEntry::Settled {
+                escrow_id,
+                nonce,
+                amount,
+                settlement,
+                receipt_hash,
+            } = record.entry
+            else {
+                return None;
+            };
+            let amount = amount.parse().map_or(Value::Null, decimal);
+            let settlement = settlement.as_ref();
+            Some(vec![
+                json!(record.at),
+                json!(record.host),
+                json!(escrow_id),
+                json!(nonce),
+                amount,
+                json!(settlement.map(|s| s.success)),
+                json!(settlement.and_then(|s| s.payment_id)),
+                json!(settlement.and_then(|s| s.tx_hash.clone())),
+                json!(receipt_hash),
+            ])
+        })
+        .collect();
+    Ok(Report::list(
+        vec![
+            "at",
+            "host",
+            "escrowId",
+            "nonce",
+            "amount",
+            "success",
+            "paymentId",
+            "txHash",
+            "receiptHash",
+        ],
+        rows,
+    ))
+}
+
+/// Check the receipt in `file`, which needs neither a key nor the RPC server
+///
+/// # Errors
+/// * `Io` - If the file cannot be read
+/// * `Client` - If it is not a receipt, or its hash or signature is invalid
+pub fn verify_receipt(file: &Path) -> Result<Report, Error> {
+    let receipt: SettlementReceipt = serde_json::from_str(&std::fs::read_to_string(file)?)
+        .map_err(|e| ClientError::InvalidReceipt(e.to_string()))?;
+    receipt.verify()?;
+    Ok(Report::record(vec![
+        ("paymentId", json!(receipt.body.payment_id)),
+        ("escrowId", json!(receipt.body.escrow_id)),
+        ("client", json!(receipt.body.client)),
+        ("server", json!(receipt.body.server)),
+        ("amount", decimal(payment_amount(&receipt)?)),
+        ("asset", json!(receipt.body.asset)),
+        ("settledAt", json!(receipt.body.settled_at)),
+        ("ledger", json!(receipt.body.ledger)),
+        ("txHash", json!(receipt.body.tx_hash)),
+        ("receiptHash", json!(receipt.receipt_hash)),
+        ("valid", json!(true)),
+    ]))
+}
+
+/// Amount of a receipt, in stroops
+fn payment_amount(receipt: &SettlementReceipt) -> Result<i128, Error> {
+    let amount = &receipt.body.amount;
+    amount
+        .parse()
+        .map_err(|_| ClientError::InvalidReceipt(format!("invalid amount {amount:?}")).into())
+}
+
+/// Run a migration `command`, `client` being on the contract escrows leave
+/// or are imported into
+///
+/// # Errors
+/// * `Client` - If a contract call fails or `--to` is not a contract
+async fn migrate(command: &MigrateCommand, client: &EscrowClient) -> Result<Report, Error> {
+    let report = match command {
+        MigrateCommand::Consent { escrow, to } => {
+            let consented = client.consent_to_migration(*escrow, to).await?;
+            Report::record(vec![
+                ("escrowId", json!(escrow)),
+                ("party", json!(client.address())),
+                ("target", json!(to)),
+                ("hash", json!(consented.hash)),
+                ("ledger", json!(consented.ledger)),
+            ])
+        }
+        MigrateCommand::AllowSource { from } => {
+            let allowed = client.allow_migration_source(from).await?;
+            Report::record(vec![
+                ("source", json!(from)),
+                ("hash", json!(allowed.hash)),
+                ("ledger", json!(allowed.ledger)),
+            ])
+        }
+        MigrateCommand::Move { escrow, to } => {
+            let target = client.for_contract(to)?;
+            let (export, imported) = client.migrate_escrow(*escrow, &target).await?;
+            Report::record(vec![
+                ("escrowId", json!(escrow)),
+                ("target", json!(to)),
+                ("targetEscrowId", json!(imported.value)),
+                ("balance", decimal(export.summary.escrow.balance)),
+                ("pendingPayments", json!(export.summary.pending.len())),
+                ("hash", json!(imported.hash)),
+                ("ledger", json!(imported.ledger)),
+            ])
+        }
+    };
+    Ok(report)
+}
+
+fn close_report(escrow: u64, party: Party, closed: Submitted<Option<i128>>) -> Report {
+    let party = match party {
+        Party::Client => "client",
+        Party::Server => "server",
+    };
+    Report::record(vec![
+        ("escrowId", json!(escrow)),
+        ("closedBy", json!(party)),
+        ("released", closed.value.map_or(Value::Null, decimal)),
+        ("hash", json!(closed.hash)),
+        ("ledger", json!(closed.ledger)),
+    ])
+}
+
+/// Amount in stroops as decimal units of the asset
+pub(crate) fn decimal(stroops: i128) -> Value {
+    json!(StellarAmount::from_stroops(stroops).to_decimal_string())
+}
+
+/// USD cents as a decimal string
+fn usd(cents: i128) -> Value {
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = cents.unsigned_abs();
+    json!(format!("{sign}{}.{:02}", cents / 100, cents % 100))
+}
+
+#[derive(Default)]
+struct Stats {
+    payments: u64,
+    settled: u64,
+    settled_amount: i128,
+    pending_amount: i128,
+    /// USD equivalent of the settled payments quoted when they settled
+    settled_usd: i128,
+    /// Settled without a quote
+    unquoted_amount: i128,
+}
+
+impl Stats {
+    fn add(&mut self, payment: &Payment) {
+        self.payments += 1;
+        if payment.settled {
+            self.settled += 1;
+            self.settled_amount += payment.amount;
+            match payment.quote_amount {
+                Some(cents) => self.settled_usd += cents,
+                None => self.unquoted_amount += payment.amount,
+            }
+        } else {
+            self.pending_amount += payment.amount;
+        }
+    }
+}
+
+/// Read the payments of `escrow`, or of every open escrow, ordered by ID
+///
+/// Payment IDs are allocated per escrow, so payments are read escrow by
+/// escrow. Those of a closed escrow are only read when it is given.
+async fn payments(
+    client: &EscrowClient,
+    escrow: Option<u64>,
+) -> Result<Vec<(u64, Payment)>, Error> {
+    let escrows = match escrow {
+        Some(id) => vec![id],
+        None => client
+            .export_escrows(None)
+            .await?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect(),
+    };
+    let mut payments = Vec::new();
+    for id in escrows {
+        payments.extend(client.payments(id).collect_all(usize::MAX).await?);
+    }
+    payments.sort_by_key(|(id, _)| *id);
+    Ok(payments)
+}
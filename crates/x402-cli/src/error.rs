@@ -0,0 +1,19 @@
+/// Errors returned by the CLI
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A required option was not given
+    #[error("missing {0}")]
+    Missing(&'static str),
+    /// The signing key could not be loaded
+    #[error("cannot load key: {0}")]
+    Key(String),
+    /// A row of a refunds or results CSV is invalid
+    #[error("line {line}: {reason}")]
+    Csv { line: usize, reason: String },
+    /// A file could not be read or written
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// An escrow client call failed
+    #[error(transparent)]
+    Client(#[from] x402_client::Error),
+}
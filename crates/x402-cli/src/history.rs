@@ -0,0 +1,311 @@
+use std::collections::HashSet;
+
+use serde_json::json;
+use x402_client::{
+    describe, ContractError, Error as ClientError, EscrowClient, EscrowEvent, EventsFrom,
+    FactValue, PreparedTransaction, EVENT_PAGE_LIMIT,
+};
+
+use crate::{commands::decimal, Error, Report};
+
+/// Operation moving the balance of an escrow
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Operation {
+    /// Opening deposit
+    Open,
+    Deposit,
+    Settle,
+    Refund,
+    /// Trial funds returned to the server of a removed escrow
+    Reclaim,
+    /// Remaining balance released once both parties closed
+    Close,
+    /// Dust balance released by `sweep_dust`
+    Sweep,
+    /// Balance moved to a child escrow, which opens with it
+    Split,
+    /// Change of the balance no event explains, a missed event or a bug
+    Gap,
+}
+
+impl Operation {
+    /// Lowercase name, as printed
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Deposit => "deposit",
+            Self::Settle => "settle",
+            Self::Refund => "refund",
+            Self::Reclaim => "reclaim",
+            Self::Close => "close",
+            Self::Sweep => "sweep",
+            Self::Split => "split",
+            Self::Gap => "gap",
+        }
+    }
+}
+
+/// Change of an escrow's balance
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Movement {
+    pub operation: Operation,
+    /// Ledger of the event, or of the event a gap was found before; None
+    /// for a gap found after the last event
+    pub ledger: Option<u32>,
+    /// Hex-encoded hash of the emitting transaction
+    pub tx_hash: Option<String>,
+    /// Payment settled or refunded
+    pub payment_id: Option<u64>,
+    /// Change of the balance, in stroops
+    pub amount: i128,
+}
+
+impl Movement {
+    fn gap(ledger: Option<u32>, amount: i128) -> Self {
+        Self {
+            operation: Operation::Gap,
+            ledger,
+            tx_hash: None,
+            payment_id: None,
+            amount,
+        }
+    }
+}
+
+/// Line of an escrow's history
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HistoryRow {
+    pub movement: Movement,
+    /// Balance after the movement, in stroops
+    pub balance: i128,
+}
+
+/// Events moving the balance of `escrow_id`, oldest first
+///
+/// Events are paged through `getEvents`. Settlements and refunds are
+/// matched to the escrow by the IDs of its payments, which remain once it
+/// is closed. The opening deposit, which the `open` event does not
+/// publish, is read from the opening transaction, and is 0 if it cannot be
+/// described. An escrow split from another opens with the amount split.
+///
+/// # Arguments
+/// * `from` - First ledger searched, within the RPC server's event
+///   retention
+/// * `to` - Last ledger searched, the latest if None
+///
+/// # Errors
+/// * `Client` - If the payments, events, or opening transaction cannot be
+///   read
+pub async fn escrow_movements(
+    client: &EscrowClient,
+    escrow_id: u64,
+    from: u32,
+    to: Option<u32>,
+) -> Result<Vec<Movement>, Error> {
+    let payments: HashSet<u64> = client
+        .payments(escrow_id)
+        .collect_all(usize::MAX)
+        .await?
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+
+    let contract_id = client.contract_id();
+    let mut movements = Vec::new();
+    let mut cursor = EventsFrom::Ledger(from);
+    'pages: loop {
+        let page = client
+            .rpc()
+            .get_events(&contract_id, &cursor, EVENT_PAGE_LIMIT)
+            .await?;
+        for info in &page.events {
+            if to.is_some_and(|to| info.ledger > to) {
+                break 'pages;
+            }
+            let Some(event) = EscrowEvent::from_info(info)? else {
+                continue;
+            };
+            let (operation, payment_id, amount) = match event {
+                EscrowEvent::Opened { escrow_id: id, .. } if id == escrow_id => {
+                    let deposit = match &info.tx_hash {
+                        Some(hash) => opening_deposit(client, hash).await?,
+                        None => None,
+                    };
+                    (Operation::Open, None, deposit.unwrap_or_default())
+                }
+                EscrowEvent::Deposited {
+                    escrow_id: id,
+                    amount,
+                } if id == escrow_id => (Operation::Deposit, None, amount),
+                EscrowEvent::PaymentSettled { payment_id, amount }
+                    if payments.contains(&payment_id) =>
+                {
+                    (Operation::Settle, Some(payment_id), -amount)
+                }
+                EscrowEvent::PaymentRefunded { payment_id, amount }
+                    if payments.contains(&payment_id) =>
+                {
+                    (Operation::Refund, Some(payment_id), amount)
+                }
+                EscrowEvent::Reclaimed {
+                    escrow_id: id,
+                    amount,
+                } if id == escrow_id => (Operation::Reclaim, None, -amount),
+                EscrowEvent::Closed {
+                    escrow_id: id,
+                    released,
+                } if id == escrow_id => (Operation::Close, None, -released),
+                EscrowEvent::Swept {
+                    escrow_id: id,
+                    balance,
+                } if id == escrow_id => (Operation::Sweep, None, -balance),
+                EscrowEvent::Split {
+                    escrow_id: id,
+                    amount,
+                    ..
+                } if id == escrow_id => (Operation::Split, None, -amount),
+                _ => continue,
+            };
+            movements.push(Movement {
+                operation,
+                ledger: Some(info.ledger),
+                tx_hash: info.tx_hash.clone(),
+                payment_id,
+                amount,
+            });
+        }
+        match page.cursor {
+            Some(next) if page.events.len() == EVENT_PAGE_LIMIT as usize => {
+                cursor = EventsFrom::Cursor(next);
+            }
+            _ => break,
+        }
+    }
+    Ok(movements)
+}
+
+/// Deposit of the transaction that opened an escrow, None if it cannot be
+/// described
+async fn opening_deposit(client: &EscrowClient, hash: &str) -> Result<Option<i128>, Error> {
+    let Some(envelope) = client.rpc().get_transaction(hash).await?.envelope_xdr else {
+        return Ok(None);
+    };
+    let tx = PreparedTransaction {
+        envelope,
+        hash: hash.into(),
+    };
+    Ok(
+        match describe(&tx).map(|summary| summary.title.arg("amount").cloned()) {
+            Ok(Some(FactValue::Amount(amount))) => Some(amount),
+            _ => None,
+        },
+    )
+}
+
+/// Running balance of an escrow through `movements`, with a gap wherever
+/// they do not explain it
+///
+/// The balance is known to be 0 before an escrow opens, to equal what a
+/// closure or sweep releases, and to be `end` after the last movement.
+/// Wherever the running balance disagrees, a [`Operation::Gap`] row makes
+/// up the difference. Without an opening in `movements`, the starting
+/// balance is inferred from the first of the others, or is 0 if there is
+/// none.
+///
+/// # Arguments
+/// * `movements` - Movements of one escrow, oldest first
+/// * `end` - Balance after the last movement, if known
+pub fn reconcile(movements: &[Movement], end: Option<i128>) -> Vec<HistoryRow> {
+    // Balance before each movement a closure or sweep requires
+    let required = |movement: &Movement| match movement.operation {
+        Operation::Open => Some(0),
+        Operation::Close | Operation::Sweep => Some(-movement.amount),
+        _ => None,
+    };
+    let mut moved = 0;
+    let mut start = None;
+    for movement in movements {
+        if let Some(before) = required(movement) {
+            start = Some(before - moved);
+            break;
+        }
+        moved += movement.amount;
+    }
+    let mut balance = start.or(end.map(|end| end - moved)).unwrap_or_default();
+
+    let mut rows = Vec::new();
+    let mut push = |movement: Movement, balance: &mut i128| {
+        *balance += movement.amount;
+        rows.push(HistoryRow {
+            movement,
+            balance: *balance,
+        });
+    };
+    for movement in movements {
+        if let Some(before) = required(movement).filter(|before| *before != balance) {
+            push(
+                Movement::gap(movement.ledger, before - balance),
+                &mut balance,
+            );
+        }
+        push(movement.clone(), &mut balance);
+    }
+    if let Some(end) = end.filter(|end| *end != balance) {
+        push(Movement::gap(None, end - balance), &mut balance);
+    }
+    rows
+}
+
+/// Reconciled history of `escrow_id`
+///
+/// Without `to`, the history ends at the escrow's current balance, 0 once
+/// it was removed.
+///
+/// # Errors
+/// * `Client` - If the events or the escrow cannot be read
+pub async fn history(
+    client: &EscrowClient,
+    escrow_id: u64,
+    from: u32,
+    to: Option<u32>,
+) -> Result<Vec<HistoryRow>, Error> {
+    let movements = escrow_movements(client, escrow_id, from, to).await?;
+    let end = match to {
+        Some(_) => None,
+        None => match client.get_escrow_balance(escrow_id).await {
+            Ok(balance) => Some(balance),
+            Err(ClientError::Contract(ContractError::EscrowNotFound, _)) => Some(0),
+            Err(e) => return Err(e.into()),
+        },
+    };
+    Ok(reconcile(&movements, end))
+}
+
+/// Report of an escrow's history, one row per movement
+pub fn history_report(rows: &[HistoryRow]) -> Report {
+    let rows = rows
+        .iter()
+        .map(|row| {
+            let movement = &row.movement;
+            vec![
+                json!(movement.ledger),
+                json!(movement.operation.as_str()),
+                json!(movement.payment_id),
+                decimal(movement.amount),
+                decimal(row.balance),
+                json!(movement.tx_hash),
+            ]
+        })
+        .collect();
+    Report::list(
+        vec![
+            "ledger",
+            "operation",
+            "paymentId",
+            "amount",
+            "balance",
+            "txHash",
+        ],
+        rows,
+    )
+}
@@ -0,0 +1,93 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use x402_client::LocalSigner;
+
+use crate::Error;
+
+/// Where to read the signing key from
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum KeySource {
+    /// File holding an `S...` secret key
+    File(PathBuf),
+    /// Environment variable holding an `S...` secret key
+    Env(String),
+    /// Identity saved by `stellar keys generate` or `stellar keys add`
+    Identity(String),
+}
+
+/// Identity file written by stellar-cli
+#[derive(Deserialize)]
+struct Identity {
+    secret_key: Option<String>,
+    seed_phrase: Option<String>,
+}
+
+impl KeySource {
+    /// Load the signer
+    ///
+    /// Identities are looked up in `.stellar/identity` of the current
+    /// directory, then in the stellar-cli configuration directory
+    /// (`$STELLAR_CONFIG_HOME`, `$XDG_CONFIG_HOME/stellar`, or
+    /// `~/.config/stellar`).
+    ///
+    /// # Errors
+    /// * `Key` - If the key cannot be read or is not an `S...` secret key
+    pub fn load(&self) -> Result<LocalSigner, Error> {
+        match self {
+            Self::File(path) => {
+                let secret = fs::read_to_string(path)
+                    .map_err(|e| Error::Key(format!("{}: {e}", path.display())))?;
+                from_secret(secret.trim())
+            }
+            Self::Env(name) => {
+                let secret = env::var(name)
+                    .ok()
+                    .filter(|secret| !secret.is_empty())
+                    .ok_or_else(|| Error::Key(format!("{name} is not set")))?;
+                from_secret(secret.trim())
+            }
+            Self::Identity(name) => load_identity(name, &identity_dirs()),
+        }
+    }
+}
+
+/// Load identity `name` from the first of `dirs` holding it
+pub(crate) fn load_identity(name: &str, dirs: &[PathBuf]) -> Result<LocalSigner, Error> {
+    let path = dirs
+        .iter()
+        .map(|dir| dir.join(format!("{name}.toml")))
+        .find(|path| path.is_file())
+        .ok_or_else(|| Error::Key(format!("identity {name} not found")))?;
+    let identity = read_identity(&path)?;
+    match (identity.secret_key, identity.seed_phrase) {
+        (Some(secret), _) => from_secret(&secret),
+        (None, Some(_)) => Err(Error::Key(format!(
+            "identity {name} is a seed phrase, export its secret key with `stellar keys show {name}`"
+        ))),
+        (None, None) => Err(Error::Key(format!("identity {name} has no secret key"))),
+    }
+}
+
+fn read_identity(path: &Path) -> Result<Identity, Error> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| Error::Key(format!("{}: {e}", path.display())))?;
+    toml::from_str(&contents).map_err(|e| Error::Key(format!("{}: {e}", path.display())))
+}
+
+fn identity_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from(".stellar/identity")];
+    let config = env::var_os("STELLAR_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("XDG_CONFIG_HOME").map(|dir| PathBuf::from(dir).join("stellar")))
+        .or_else(|| env::var_os("HOME").map(|dir| PathBuf::from(dir).join(".config/stellar")));
+    dirs.extend(config.map(|dir| dir.join("identity")));
+    dirs
+}
+
+fn from_secret(secret: &str) -> Result<LocalSigner, Error> {
+    LocalSigner::from_secret(secret).map_err(|e| Error::Key(e.to_string()))
+}
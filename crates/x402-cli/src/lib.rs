@@ -0,0 +1,49 @@
+//! # x402 CLI
+//!
+//! Command-line tool managing x402 escrows through the [`x402_client`] SDK.
+//!
+//! ## Commands
+//! - `open`, `deposit`, `balance` - Client side of an escrow
+//! - `payments list`, `settle` - Payments created against escrows, with
+//!   the USD equivalent the contract quoted when they settled
+//! - `receipt`, `verify-receipt` - Signed receipts of settled payments for
+//!   auditors, checked without an RPC server
+//! - `history` - Every deposit, settlement, refund, and release of an
+//!   escrow with its running balance, flagging gaps its events do not
+//!   explain, for support investigations
+//! - `close` - Close an escrow for the client or the server
+//! - `stats` - Payment totals, overall or for one escrow, in USD too
+//! - `journal export` - Settlements recorded by an SDK client's payment
+//!   journal, read without an RPC server
+//! - `monitor` - Balance, pending exposure, last activity, and red, yellow,
+//!   or green health of every escrow of a server, once for cron jobs or
+//!   repeatedly with `--watch`
+//! - `migrate consent`, `migrate allow-source`, `migrate move` - Move an
+//!   escrow to a new deployment of the contract, once both parties
+//!   consented
+//! - `refund --from-csv` - Refund settled payments in bulk, e.g. after an
+//!   incident, checked against their settled amounts before submission and
+//!   resumable from the results CSV
+//!
+//! Keys are read from a file, an environment variable, or a stellar-cli
+//! identity, and results are printed as a table or JSON.
+
+mod args;
+mod commands;
+mod error;
+mod history;
+mod keys;
+mod monitor;
+mod output;
+mod refund;
+
+pub use args::*;
+pub use commands::*;
+pub use error::*;
+pub use history::*;
+pub use keys::*;
+pub use monitor::*;
+pub use output::*;
+pub use refund::*;
+
+mod test;
@@ -0,0 +1,81 @@
+use std::{process::ExitCode, time::Duration};
+
+use clap::Parser;
+use x402_cli::{
+    journal, monitor, monitor_report, refund, refund_report, refunds_succeeded, run, unix_now,
+    verify_receipt, worst_health, Cli, Command, Error, Health, MonitorArgs,
+};
+use x402_client::{EscrowClient, HttpTransport, Rpc};
+
+/// Exit status of `monitor` when an escrow is red
+const RED_EXIT: u8 = 2;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match execute(&cli).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("x402-cli: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn execute(cli: &Cli) -> Result<ExitCode, Error> {
+    if let Command::Journal { command } = &cli.command {
+        println!("{}", journal(command)?.render(cli.output));
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Command::VerifyReceipt { file } = &cli.command {
+        println!("{}", verify_receipt(file)?.render(cli.output));
+        return Ok(ExitCode::SUCCESS);
+    }
+    let signer = cli.key.source().load()?;
+    let rpc = Rpc::new(HttpTransport::new(cli.rpc_url()?));
+    let passphrase = match &cli.network_passphrase {
+        Some(passphrase) => passphrase.clone(),
+        None => rpc.get_network().await?.passphrase,
+    };
+    let client = EscrowClient::new(rpc, cli.contract_id()?, &passphrase, signer)?;
+    if let Command::Monitor(args) = &cli.command {
+        return watch(cli, args, &client).await;
+    }
+    if let Command::Refund(args) = &cli.command {
+        let outcomes = refund(args, &client, |line| eprintln!("{line}")).await?;
+        println!("{}", refund_report(&outcomes).render(cli.output));
+        return Ok(if refunds_succeeded(&outcomes) {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+    println!("{}", run(&cli.command, &client).await?.render(cli.output));
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Print the health of the server's escrows, every `--watch` seconds if set
+///
+/// A one-shot run exits with [`RED_EXIT`] if any escrow is red. Watching
+/// runs until interrupted, reporting failed rounds and carrying on.
+async fn watch(cli: &Cli, args: &MonitorArgs, client: &EscrowClient) -> Result<ExitCode, Error> {
+    let policy = args.policy();
+    let format = args.format(cli.output);
+    let Some(interval) = args.watch else {
+        let now = unix_now();
+        let escrows = monitor(client, &args.server, &policy, now).await?;
+        println!("{}", monitor_report(&escrows, now).render(format));
+        return Ok(match worst_health(&escrows) {
+            Health::Red => ExitCode::from(RED_EXIT),
+            _ => ExitCode::SUCCESS,
+        });
+    };
+    loop {
+        let now = unix_now();
+        match monitor(client, &args.server, &policy, now).await {
+            Ok(escrows) => println!("{}", monitor_report(&escrows, now).render(format)),
+            Err(e) => eprintln!("x402-cli: {e}"),
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
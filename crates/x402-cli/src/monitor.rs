@@ -0,0 +1,214 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use x402_client::{Escrow, EscrowClient, Payment};
+
+use crate::{commands::decimal, Error, Report};
+
+/// Seconds in a day
+pub const DAY: u64 = 24 * 60 * 60;
+
+/// Health of an escrow, from best to worst
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Health {
+    Green,
+    Yellow,
+    Red,
+}
+
+impl Health {
+    /// Lowercase name, as printed
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Health::Green => "green",
+            Health::Yellow => "yellow",
+            Health::Red => "red",
+        }
+    }
+}
+
+/// Thresholds classifying escrow health
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HealthPolicy {
+    /// Seconds without a payment after which an escrow is red
+    pub red_inactive_secs: u64,
+    /// Seconds without a payment after which an escrow is yellow
+    pub yellow_inactive_secs: u64,
+    /// Share of the balance, in percent, pending payments may reach before
+    /// an escrow is yellow
+    pub yellow_exposure_pct: u32,
+}
+
+impl Default for HealthPolicy {
+    /// Red after 30 days of inactivity, yellow after 7 days or with pending
+    /// payments over 80% of the balance
+    fn default() -> Self {
+        Self {
+            red_inactive_secs: 30 * DAY,
+            yellow_inactive_secs: 7 * DAY,
+            yellow_exposure_pct: 80,
+        }
+    }
+}
+
+/// State and health of one escrow of a server
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowHealth {
+    pub escrow_id: u64,
+    pub escrow: Escrow,
+    /// Sum of the unsettled payments, in stroops
+    pub pending: i128,
+    /// Timestamp of the latest payment, None if there is none
+    pub last_activity: Option<u64>,
+    pub health: Health,
+    /// Why the escrow is not green
+    pub reasons: Vec<&'static str>,
+}
+
+impl EscrowHealth {
+    /// Classify an escrow from its payments
+    ///
+    /// An escrow is red when its pending payments exceed its balance, or
+    /// nothing was paid from it for `red_inactive_secs`. It is yellow when
+    /// pending payments exceed `yellow_exposure_pct` of the balance, nothing
+    /// was paid for `yellow_inactive_secs`, its balance is empty, or a party
+    /// closed it. Escrows without payments are not judged on inactivity.
+    ///
+    /// # Arguments
+    /// * `payments` - Every payment of the escrow
+    /// * `now` - Unix timestamp inactivity is measured at
+    pub fn classify(
+        escrow_id: u64,
+        escrow: Escrow,
+        payments: &[Payment],
+        policy: &HealthPolicy,
+        now: u64,
+    ) -> Self {
+        let pending: i128 = payments
+            .iter()
+            .filter(|payment| !payment.settled)
+            .map(|payment| payment.amount)
+            .sum();
+        let last_activity = payments.iter().map(|payment| payment.timestamp).max();
+        let inactive = last_activity.map(|at| now.saturating_sub(at));
+
+        let mut reasons = Vec::new();
+        let mut health = Health::Green;
+        let mut flag = |level: Health, reason| {
+            health = health.max(level);
+            reasons.push(reason);
+        };
+        if pending > escrow.balance {
+            flag(Health::Red, "pending_exceeds_balance");
+        } else if pending * 100 > escrow.balance * i128::from(policy.yellow_exposure_pct) {
+            flag(Health::Yellow, "high_exposure");
+        }
+        match inactive {
+            Some(secs) if secs > policy.red_inactive_secs => flag(Health::Red, "inactive"),
+            Some(secs) if secs > policy.yellow_inactive_secs => flag(Health::Yellow, "inactive"),
+            _ => {}
+        }
+        if escrow.balance == 0 {
+            flag(Health::Yellow, "empty");
+        }
+        if escrow.client_closed || escrow.server_closed {
+            flag(Health::Yellow, "closing");
+        }
+        Self {
+            escrow_id,
+            escrow,
+            pending,
+            last_activity,
+            health,
+            reasons,
+        }
+    }
+}
+
+/// Classify every open escrow of `server`
+///
+/// Escrows are paged through `export_escrows`, and the payments of each
+/// through `get_payments`.
+///
+/// # Arguments
+/// * `server` - Server address (G... format)
+/// * `now` - Unix timestamp inactivity is measured at, see [`unix_now`]
+///
+/// # Errors
+/// * `Client` - If a contract call fails
+pub async fn monitor(
+    client: &EscrowClient,
+    server: &str,
+    policy: &HealthPolicy,
+    now: u64,
+) -> Result<Vec<EscrowHealth>, Error> {
+    let mut escrows = Vec::new();
+    for (escrow_id, escrow) in client.export_escrows(Some(server)).await? {
+        let payments: Vec<_> = client
+            .payments(escrow_id)
+            .collect_all(usize::MAX)
+            .await?
+            .into_iter()
+            .map(|(_, payment)| payment)
+            .collect();
+        escrows.push(EscrowHealth::classify(
+            escrow_id, escrow, &payments, policy, now,
+        ));
+    }
+    Ok(escrows)
+}
+
+/// Report of classified escrows, one row each
+///
+/// # Arguments
+/// * `now` - Unix timestamp the ages are measured at
+pub fn monitor_report(escrows: &[EscrowHealth], now: u64) -> Report {
+    let rows = escrows
+        .iter()
+        .map(|escrow| {
+            vec![
+                json!(escrow.escrow_id),
+                json!(escrow.escrow.client),
+                decimal(escrow.escrow.balance),
+                decimal(escrow.pending),
+                json!(escrow.last_activity),
+                json!(escrow.last_activity.map(|at| now.saturating_sub(at) / DAY)),
+                json!(escrow.health.as_str()),
+                if escrow.reasons.is_empty() {
+                    Value::Null
+                } else {
+                    json!(escrow.reasons.join(","))
+                },
+            ]
+        })
+        .collect();
+    Report::list(
+        vec![
+            "escrowId",
+            "client",
+            "balance",
+            "pending",
+            "lastActivity",
+            "inactiveDays",
+            "health",
+            "reasons",
+        ],
+        rows,
+    )
+}
+
+/// Worst health among `escrows`, green if there are none
+pub fn worst_health(escrows: &[EscrowHealth]) -> Health {
+    escrows
+        .iter()
+        .map(|escrow| escrow.health)
+        .max()
+        .unwrap_or(Health::Green)
+}
+
+/// Current Unix timestamp
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
@@ -0,0 +1,118 @@
+use serde::{
+    ser::{SerializeMap, SerializeSeq},
+    Serialize, Serializer,
+};
+use serde_json::Value;
+
+use crate::Format;
+
+/// Result of a command, printed as a table or JSON
+///
+/// Columns keep their order in both formats. A single record is printed
+/// as a two-column table or a JSON object, a list as a table with one row
+/// per record or a JSON array.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    columns: Vec<&'static str>,
+    rows: Vec<Vec<Value>>,
+    list: bool,
+}
+
+impl Report {
+    /// Single record of `(column, value)` pairs
+    pub fn record(fields: Vec<(&'static str, Value)>) -> Self {
+        let (columns, row) = fields.into_iter().unzip();
+        Self {
+            columns,
+            rows: vec![row],
+            list: false,
+        }
+    }
+
+    /// List of records sharing `columns`
+    pub fn list(columns: Vec<&'static str>, rows: Vec<Vec<Value>>) -> Self {
+        Self {
+            columns,
+            rows,
+            list: true,
+        }
+    }
+
+    /// Render in `format`, without a trailing newline
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+            Format::Table if self.list => self.list_table(),
+            Format::Table => self.record_table(),
+        }
+    }
+
+    fn record_table(&self) -> String {
+        let rows = self
+            .columns
+            .iter()
+            .zip(&self.rows[0])
+            .map(|(column, value)| vec![column.to_string(), cell(value)])
+            .collect();
+        table(rows)
+    }
+
+    fn list_table(&self) -> String {
+        let header = self.columns.iter().map(|c| c.to_uppercase()).collect();
+        let rows = self.rows.iter().map(|row| row.iter().map(cell).collect());
+        table(std::iter::once(header).chain(rows).collect())
+    }
+}
+
+impl Serialize for Report {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct Row<'a>(&'a [&'static str], &'a [Value]);
+
+        impl Serialize for Row<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut map = serializer.serialize_map(Some(self.0.len()))?;
+                for (column, value) in self.0.iter().zip(self.1) {
+                    map.serialize_entry(column, value)?;
+                }
+                map.end()
+            }
+        }
+
+        if !self.list {
+            return Row(&self.columns, &self.rows[0]).serialize(serializer);
+        }
+        let mut seq = serializer.serialize_seq(Some(self.rows.len()))?;
+        for row in &self.rows {
+            seq.serialize_element(&Row(&self.columns, row))?;
+        }
+        seq.end()
+    }
+}
+
+/// Table cell for `value`, strings unquoted and nulls as `-`
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => "-".into(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Left-aligned columns separated by two spaces
+fn table(rows: Vec<Vec<String>>) -> String {
+    let columns = rows.first().map_or(0, Vec::len);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect();
+    rows.iter()
+        .map(|row| {
+            let line: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            line.join("  ").trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
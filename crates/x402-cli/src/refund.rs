@@ -0,0 +1,436 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Write,
+    path::Path,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use x402_client::{ContractError, Error as ClientError, EscrowClient};
+use x402_types::StellarAmount;
+
+use crate::{commands::decimal, Error, RefundArgs, Report};
+
+/// Delay before the first retry of a transient failure, doubled after each
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Header of the results CSV
+const RESULTS_HEADER: &str = "payment_id,amount,status,refunded,tx_hash,error";
+
+/// Refund listed in the input CSV
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RefundRow {
+    /// Line of the CSV the refund is on, from 1
+    pub line: usize,
+    pub payment_id: u64,
+    pub amount: StellarAmount,
+}
+
+/// What became of a refund
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RefundStatus {
+    /// Submitted and applied, possibly by an earlier run
+    Refunded,
+    /// Checked but not submitted, under `--dry-run`
+    DryRun,
+    /// Not submitted, the payment cannot take it
+    Rejected,
+    /// Submitted and failed, or its payment could not be read
+    Failed,
+}
+
+impl RefundStatus {
+    /// Lowercase name, as written to the results
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Refunded => "refunded",
+            Self::DryRun => "dry-run",
+            Self::Rejected => "rejected",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// Outcome of one refund
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RefundOutcome {
+    pub payment_id: u64,
+    pub amount: StellarAmount,
+    pub status: RefundStatus,
+    /// Total refunded for the payment afterwards, in stroops, if known
+    pub refunded: Option<i128>,
+    /// Refund transaction, None if its outcome was only seen on-chain
+    pub hash: Option<String>,
+    /// Why the refund was rejected or failed
+    pub error: Option<String>,
+}
+
+impl RefundOutcome {
+    fn new(row: &RefundRow, status: RefundStatus) -> Self {
+        Self {
+            payment_id: row.payment_id,
+            amount: row.amount,
+            status,
+            refunded: None,
+            hash: None,
+            error: None,
+        }
+    }
+
+    fn error(row: &RefundRow, status: RefundStatus, error: impl ToString) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            ..Self::new(row, status)
+        }
+    }
+
+    /// Line of the results CSV
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{}",
+            self.payment_id,
+            self.amount,
+            self.status.as_str(),
+            self.refunded
+                .map(|r| StellarAmount::from_stroops(r).to_string())
+                .unwrap_or_default(),
+            self.hash.as_deref().unwrap_or_default(),
+            quote(self.error.as_deref().unwrap_or_default()),
+        )
+    }
+}
+
+/// Parse the refunds of a `payment_id,amount` CSV
+///
+/// Amounts are in units of the asset with up to 7 decimals, as `--amount`
+/// takes them. An optional `payment_id,amount` header, blank lines, and
+/// lines starting with `#` are skipped. A payment may be listed once.
+///
+/// # Errors
+/// * `Csv` - If a row is malformed, its amount is not positive, or its
+///   payment was listed before
+pub fn parse_refunds(text: &str) -> Result<Vec<RefundRow>, Error> {
+    let mut rows = Vec::new();
+    let mut seen = HashSet::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |reason: String| Error::Csv {
+            line: line_no,
+            reason,
+        };
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if rows.is_empty() && fields.first() == Some(&"payment_id") {
+            continue;
+        }
+        let [payment_id, amount] = fields[..] else {
+            return Err(invalid(format!(
+                "expected payment_id,amount, found {} fields",
+                fields.len()
+            )));
+        };
+        let payment_id: u64 = payment_id
+            .parse()
+            .map_err(|_| invalid(format!("invalid payment ID {payment_id:?}")))?;
+        let amount: StellarAmount = amount.parse().map_err(|e| invalid(format!("{e}")))?;
+        if amount.stroops() <= 0 {
+            return Err(invalid(format!("amount {amount} is not positive")));
+        }
+        if !seen.insert(payment_id) {
+            return Err(invalid(format!("payment {payment_id} is listed twice")));
+        }
+        rows.push(RefundRow {
+            line: line_no,
+            payment_id,
+            amount,
+        });
+    }
+    Ok(rows)
+}
+
+/// Refunds a results CSV records as applied, by payment ID
+///
+/// # Errors
+/// * `Csv` - If a line is not a results row
+pub fn read_results(text: &str) -> Result<HashMap<u64, RefundOutcome>, Error> {
+    let mut done = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        if index == 0 || line.is_empty() {
+            continue;
+        }
+        let invalid = || Error::Csv {
+            line: index + 1,
+            reason: "not a refund result".into(),
+        };
+        let fields: Vec<&str> = line.splitn(6, ',').collect();
+        let [payment_id, amount, status, refunded, hash, _] = fields[..] else {
+            return Err(invalid());
+        };
+        if status != RefundStatus::Refunded.as_str() {
+            continue;
+        }
+        let payment_id = payment_id.parse().map_err(|_| invalid())?;
+        done.insert(
+            payment_id,
+            RefundOutcome {
+                payment_id,
+                amount: amount.parse().map_err(|_| invalid())?,
+                status: RefundStatus::Refunded,
+                refunded: refunded
+                    .parse::<StellarAmount>()
+                    .ok()
+                    .map(StellarAmount::stroops),
+                hash: Some(hash.to_string()).filter(|h| !h.is_empty()),
+                error: None,
+            },
+        );
+    }
+    Ok(done)
+}
+
+/// Refund the payments listed in `--from-csv`, signing as their server
+///
+/// Rows are worked through in batches of `--batch-size`, each checked
+/// against the chain before it is submitted: the payment must be settled,
+/// and its refunds, this one included, may not exceed its amount. Transient
+/// RPC failures are retried; a refund whose submission failed that way is
+/// looked up before being sent again, so it is never applied twice.
+///
+/// Outcomes are appended to the results CSV as they come. Rows it records
+/// as refunded are skipped, so a run that stopped part way resumes with
+/// the same arguments. Under `--dry-run` nothing is submitted or written.
+///
+/// # Arguments
+/// * `progress` - Called with a line per batch and per refund
+///
+/// # Errors
+/// * `Csv` - If the input or results CSV is malformed, before any refund
+/// * `Io` - If a file cannot be read or written
+pub async fn refund(
+    args: &RefundArgs,
+    client: &EscrowClient,
+    mut progress: impl FnMut(String),
+) -> Result<Vec<RefundOutcome>, Error> {
+    let rows = parse_refunds(&fs::read_to_string(&args.from_csv)?)?;
+    let results_path = args.results_path();
+    let done = match fs::read_to_string(&results_path) {
+        Ok(text) => read_results(&text)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut outcomes = Vec::with_capacity(rows.len());
+    let mut pending = Vec::new();
+    for row in &rows {
+        match done.get(&row.payment_id) {
+            Some(outcome) if outcome.amount == row.amount => {
+                progress(format!(
+                    "payment {}: already refunded {}",
+                    row.payment_id, row.amount
+                ));
+                outcomes.push(outcome.clone());
+            }
+            Some(outcome) => outcomes.push(RefundOutcome::error(
+                row,
+                RefundStatus::Rejected,
+                format!("an earlier run refunded {}", outcome.amount),
+            )),
+            None => pending.push(row),
+        }
+    }
+
+    let mut results = if args.dry_run {
+        None
+    } else {
+        // Refunds of earlier runs stay recorded, listed in this CSV or not
+        let mut earlier: Vec<&RefundOutcome> =
+            done.values().filter(|o| !outcomes.contains(*o)).collect();
+        earlier.sort_by_key(|o| o.payment_id);
+        Some(start_results(
+            &results_path,
+            earlier.into_iter().chain(&outcomes),
+        )?)
+    };
+    let batches = pending.len().div_ceil(args.batch_size.max(1));
+    for (index, batch) in pending.chunks(args.batch_size.max(1)).enumerate() {
+        progress(format!(
+            "batch {}/{batches}: {} refunds",
+            index + 1,
+            batch.len()
+        ));
+        for row in batch {
+            let outcome = refund_row(args, client, row).await;
+            progress(progress_line(&outcome));
+            if let Some(results) = &mut results {
+                writeln!(results, "{}", outcome.to_csv())?;
+                results.flush()?;
+            }
+            outcomes.push(outcome);
+        }
+    }
+    Ok(outcomes)
+}
+
+/// Check one refund against the chain and submit it unless dry-running
+async fn refund_row(args: &RefundArgs, client: &EscrowClient, row: &RefundRow) -> RefundOutcome {
+    let amount = row.amount.stroops();
+    let (payment, refunded) = match retry(args.retries, || async move {
+        let payment = client.get_payment(row.payment_id).await?;
+        Ok((payment, client.get_refunded(row.payment_id).await?))
+    })
+    .await
+    {
+        Ok(read) => read,
+        Err(ClientError::Contract(ContractError::PaymentNotFound, _)) => {
+            return RefundOutcome::error(row, RefundStatus::Rejected, "payment not found");
+        }
+        Err(e) => return RefundOutcome::error(row, RefundStatus::Failed, e),
+    };
+    if !payment.settled {
+        return RefundOutcome::error(row, RefundStatus::Rejected, "payment not settled");
+    }
+    if refunded + amount > payment.amount {
+        return RefundOutcome::error(
+            row,
+            RefundStatus::Rejected,
+            format!(
+                "exceeds the settled {}, {} already refunded",
+                StellarAmount::from_stroops(payment.amount),
+                StellarAmount::from_stroops(refunded)
+            ),
+        );
+    }
+    if args.dry_run {
+        return RefundOutcome {
+            refunded: Some(refunded + amount),
+            ..RefundOutcome::new(row, RefundStatus::DryRun)
+        };
+    }
+
+    let mut backoff = RETRY_BACKOFF;
+    let mut retries = 0;
+    loop {
+        let error = match client.refund_payment(row.payment_id, amount).await {
+            Ok(submitted) => {
+                return RefundOutcome {
+                    refunded: Some(submitted.value),
+                    hash: Some(submitted.hash),
+                    ..RefundOutcome::new(row, RefundStatus::Refunded)
+                };
+            }
+            Err(e) if e.is_transient() && retries < args.retries => e,
+            Err(e) => return RefundOutcome::error(row, RefundStatus::Failed, e),
+        };
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+        retries += 1;
+
+        // The failed submission may have applied before the error
+        match client.get_refunded(row.payment_id).await {
+            Ok(now) if now >= refunded + amount => {
+                return RefundOutcome {
+                    refunded: Some(now),
+                    ..RefundOutcome::new(row, RefundStatus::Refunded)
+                };
+            }
+            Ok(_) => {}
+            Err(_) => return RefundOutcome::error(row, RefundStatus::Failed, error),
+        }
+    }
+}
+
+/// Run `call`, retrying transient failures up to `retries` times
+async fn retry<T, F, Fut>(retries: u32, mut call: F) -> Result<T, ClientError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, ClientError>>,
+{
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match call().await {
+            Err(e) if e.is_transient() && attempt < retries => {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Rewrite the results CSV with the outcomes known before any submission,
+/// the refunds of earlier runs among them, then open it for appending
+fn start_results<'a>(
+    path: &Path,
+    known: impl IntoIterator<Item = &'a RefundOutcome>,
+) -> Result<fs::File, Error> {
+    let mut text = format!("{RESULTS_HEADER}\n");
+    for outcome in known {
+        text.push_str(&outcome.to_csv());
+        text.push('\n');
+    }
+    // Replaced whole, so a crash leaves the old or the new file
+    let partial = path.with_extension("partial");
+    fs::write(&partial, text)?;
+    fs::rename(&partial, path)?;
+    Ok(fs::OpenOptions::new().append(true).open(path)?)
+}
+
+fn progress_line(outcome: &RefundOutcome) -> String {
+    let mut line = format!(
+        "payment {}: {} {}",
+        outcome.payment_id,
+        outcome.status.as_str(),
+        outcome.amount
+    );
+    if let Some(hash) = &outcome.hash {
+        line.push_str(&format!(" in {hash}"));
+    }
+    if let Some(error) = &outcome.error {
+        line.push_str(&format!(": {error}"));
+    }
+    line
+}
+
+/// Outcomes of a refund run, one row per refund
+pub fn refund_report(outcomes: &[RefundOutcome]) -> Report {
+    let rows = outcomes
+        .iter()
+        .map(|outcome| {
+            vec![
+                json!(outcome.payment_id),
+                json!(outcome.amount.to_decimal_string()),
+                json!(outcome.status.as_str()),
+                outcome.refunded.map_or(Value::Null, decimal),
+                json!(outcome.hash),
+                json!(outcome.error),
+            ]
+        })
+        .collect();
+    Report::list(
+        vec!["paymentId", "amount", "status", "refunded", "hash", "error"],
+        rows,
+    )
+}
+
+/// Whether every refund was applied, or would be under `--dry-run`
+pub fn refunds_succeeded(outcomes: &[RefundOutcome]) -> bool {
+    outcomes
+        .iter()
+        .all(|o| matches!(o.status, RefundStatus::Refunded | RefundStatus::DryRun))
+}
+
+/// CSV field holding `value` on one line, quoted if it must be
+fn quote(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+    if value.contains([',', '"']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
@@ -0,0 +1,1046 @@
+#![cfg(test)]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use serde_json::{json, Value};
+use x402_client::{
+    scval,
+    testutils::{EnvTransport, NETWORK_PASSPHRASE},
+    Escrow, EscrowClient, JournalEntry, LocalSigner, Payment, PaymentJournal, Rpc,
+};
+use x402_escrow::X402EscrowContract;
+use x402_types::{SettleResponse, StellarAmount};
+
+use crate::{
+    escrow_movements, journal, keys::load_identity, monitor, parse_refunds, reconcile, run,
+    verify_receipt, worst_health, Cli, Command, Error, EscrowHealth, Format, Health, HealthPolicy,
+    KeySource, Operation, RefundRow, Report, DAY,
+};
+
+const CLIENT_SEED: [u8; 32] = [1; 32];
+const SERVER_SEED: [u8; 32] = [2; 32];
+
+struct Setup {
+    client: EscrowClient,
+    server: EscrowClient,
+}
+
+fn setup() -> Setup {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport);
+    let client = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    Setup {
+        client: client(&CLIENT_SEED),
+        server: client(&SERVER_SEED),
+    }
+}
+
+/// Parse `args` and run the command, returning its JSON output
+async fn cli(client: &EscrowClient, args: &[&str]) -> Result<Value, Error> {
+    let cli = Cli::try_parse_from(std::iter::once("x402-cli").chain(args.iter().copied())).unwrap();
+    let report = run(&cli.command, client).await?;
+    Ok(serde_json::to_value(&report).unwrap())
+}
+
+/// Scratch directory unique to a test
+fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("x402-cli-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_record_rendering() {
+    let report = Report::record(vec![
+        ("escrowId", json!(3)),
+        ("balance", json!("1000000")),
+        ("released", Value::Null),
+        ("clientClosed", json!(true)),
+    ]);
+
+    assert_eq!(
+        report.render(Format::Table),
+        "\
+escrowId      3
+balance       1000000
+released      -
+clientClosed  true"
+    );
+    assert_eq!(
+        report.render(Format::Json),
+        r#"{
+  "escrowId": 3,
+  "balance": "1000000",
+  "released": null,
+  "clientClosed": true
+}"#
+    );
+}
+
+#[test]
+fn test_list_rendering() {
+    let report = Report::list(
+        vec!["paymentId", "amount", "settled"],
+        vec![
+            vec![json!(0), json!("100000"), json!(true)],
+            vec![json!(12), json!("5"), json!(false)],
+        ],
+    );
+
+    assert_eq!(
+        report.render(Format::Table),
+        "\
+PAYMENTID  AMOUNT  SETTLED
+0          100000  true
+12         5       false"
+    );
+    assert_eq!(
+        report.render(Format::Json),
+        r#"[
+  {
+    "paymentId": 0,
+    "amount": "100000",
+    "settled": true
+  },
+  {
+    "paymentId": 12,
+    "amount": "5",
+    "settled": false
+  }
+]"#
+    );
+
+    let empty = Report::list(vec!["paymentId", "amount"], vec![]);
+    assert_eq!(empty.render(Format::Table), "PAYMENTID  AMOUNT");
+    assert_eq!(empty.render(Format::Json), "[]");
+}
+
+#[tokio::test]
+async fn test_escrow_lifecycle() {
+    let s = setup();
+    let server_addr = s.server.address();
+
+    let opened = cli(
+        &s.client,
+        &["open", "--server", &server_addr, "--amount", "0.1"],
+    )
+    .await
+    .unwrap();
+    let escrow = opened["escrowId"].as_u64().unwrap();
+    let id = escrow.to_string();
+    assert_eq!(opened["client"], s.client.address());
+    assert_eq!(opened["hash"].as_str().unwrap().len(), 64);
+
+    let deposited = cli(&s.client, &["deposit", "--escrow", &id, "--amount", "0.05"])
+        .await
+        .unwrap();
+    assert_eq!(deposited["amount"], "0.0500000");
+    assert_eq!(deposited["balance"], "0.1500000");
+
+    // Two payments, one settled through the CLI
+    let first = s
+        .server
+        .create_payment(escrow, 200_000)
+        .await
+        .unwrap()
+        .value;
+    let second = s
+        .server
+        .create_payment(escrow, 300_000)
+        .await
+        .unwrap()
+        .value;
+    let settled = cli(&s.server, &["settle", "--payment", &second.to_string()])
+        .await
+        .unwrap();
+    assert_eq!(settled["settled"], true);
+
+    let cli_args = Cli::try_parse_from(["x402-cli", "balance", "--escrow", &id]).unwrap();
+    let balance = run(&cli_args.command, &s.client).await.unwrap();
+    assert_eq!(
+        balance.render(Format::Table),
+        format!(
+            "\
+escrowId      {escrow}
+client        {}
+server        {server_addr}
+balance       0.1200000
+clientClosed  false
+serverClosed  false",
+            s.client.address()
+        )
+    );
+
+    let payments = cli(&s.client, &["payments", "list", "--escrow", &id])
+        .await
+        .unwrap();
+    assert_eq!(payments.as_array().unwrap().len(), 2);
+    assert_eq!(payments[0]["paymentId"], first);
+    assert_eq!(payments[0]["amount"], "0.0200000");
+    assert_eq!(payments[0]["settled"], false);
+    assert_eq!(payments[1]["settled"], true);
+    // Without a price feed, settled unquoted
+    assert_eq!(payments[1]["usd"], Value::Null);
+    let page = cli(
+        &s.client,
+        &[
+            "payments",
+            "list",
+            "--from",
+            &second.to_string(),
+            "--limit",
+            "5",
+        ],
+    )
+    .await
+    .unwrap();
+    assert_eq!(page.as_array().unwrap().len(), 1);
+    assert_eq!(page[0]["paymentId"], second);
+
+    let stats = cli(&s.client, &["stats", "--escrow", &id]).await.unwrap();
+    assert_eq!(
+        stats,
+        json!({
+            "escrowId": escrow,
+            "balance": "0.1200000",
+            "payments": 2,
+            "settled": 1,
+            "pending": 1,
+            "settledAmount": "0.0300000",
+            "pendingAmount": "0.0200000",
+            "settledUsd": "0.00",
+            "unquotedAmount": "0.0300000",
+        })
+    );
+
+    // The escrow is released once both parties closed
+    let closed = cli(&s.client, &["close", "--escrow", &id]).await.unwrap();
+    assert_eq!(closed["closedBy"], "client");
+    assert_eq!(closed["released"], Value::Null);
+    let closed = cli(&s.server, &["close", "--escrow", &id, "--as", "server"])
+        .await
+        .unwrap();
+    assert_eq!(closed["released"], "0.1200000");
+
+    let stats = cli(&s.client, &["stats", "--escrow", &id]).await.unwrap();
+    assert_eq!(stats["balance"], Value::Null);
+    assert_eq!(stats["payments"], 2);
+    let err = cli(&s.client, &["balance", "--escrow", &id])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Client(_)), "{err}");
+}
+
+#[tokio::test]
+async fn test_migrate() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let old_id = transport.contract_id().to_string();
+    let new_id = transport.with_env(|env| {
+        let contract = env.register(X402EscrowContract, ());
+        scval::format_address(&(&contract).into())
+    });
+    let rpc = Rpc::new(transport);
+    let on = |contract: &str, seed| {
+        let key = LocalSigner::from_bytes(seed);
+        EscrowClient::new(rpc.clone(), contract, NETWORK_PASSPHRASE, key).unwrap()
+    };
+    let (client, server) = (on(&old_id, &CLIENT_SEED), on(&old_id, &SERVER_SEED));
+    let admin = on(&old_id, &[3; 32]);
+    admin.initialize().await.unwrap();
+    admin
+        .for_contract(&new_id)
+        .unwrap()
+        .initialize()
+        .await
+        .unwrap();
+
+    let escrow = client
+        .open_escrow(&client.address(), &server.address(), 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let id = escrow.to_string();
+    server.create_payment(escrow, 200_000).await.unwrap();
+    let allowed = cli(
+        &admin.for_contract(&new_id).unwrap(),
+        &["migrate", "allow-source", "--from", &old_id],
+    )
+    .await
+    .unwrap();
+    assert_eq!(allowed["source"], old_id);
+    for party in [&client, &server] {
+        let consented = cli(
+            party,
+            &["migrate", "consent", "--escrow", &id, "--to", &new_id],
+        )
+        .await
+        .unwrap();
+        assert_eq!(consented["party"], party.address());
+    }
+
+    let moved = cli(
+        &admin,
+        &["migrate", "move", "--escrow", &id, "--to", &new_id],
+    )
+    .await
+    .unwrap();
+    // The client's escrows take the same shard on both contracts
+    assert_eq!(moved["targetEscrowId"], escrow);
+    assert_eq!(moved["balance"], "0.1000000");
+    assert_eq!(moved["pendingPayments"], 1);
+    let balance = cli(
+        &client.for_contract(&new_id).unwrap(),
+        &["balance", "--escrow", &id],
+    )
+    .await
+    .unwrap();
+    assert_eq!(balance["balance"], "0.1000000");
+    let err = cli(&client, &["balance", "--escrow", &id])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Client(_)), "{err}");
+}
+
+fn escrow(balance: i128, closed: bool) -> Escrow {
+    Escrow {
+        client: "GCLIENT".into(),
+        server: "GSERVER".into(),
+        balance,
+        client_closed: closed,
+        server_closed: false,
+        terms_hash: None,
+        trial: false,
+        trial_balance: 0,
+        pending: 0,
+        pending_payments: 0,
+    }
+}
+
+fn payment(amount: i128, settled: bool, timestamp: u64) -> Payment {
+    Payment {
+        escrow_id: 0,
+        amount,
+        settled,
+        timestamp,
+        quote_amount: None,
+        quote_currency: None,
+    }
+}
+
+#[test]
+fn test_health_classification() {
+    let now = 100 * DAY;
+    let policy = HealthPolicy::default();
+    let fixtures = [
+        // Active, with little pending
+        (
+            escrow(1_000, false),
+            vec![payment(500, true, now - DAY), payment(100, false, now)],
+            Health::Green,
+            vec![],
+        ),
+        // No payments yet is not inactivity
+        (escrow(1_000, false), vec![], Health::Green, vec![]),
+        // Exactly at the thresholds
+        (
+            escrow(1_000, false),
+            vec![payment(800, false, now - 7 * DAY)],
+            Health::Green,
+            vec![],
+        ),
+        (
+            escrow(1_000, false),
+            vec![payment(801, false, now)],
+            Health::Yellow,
+            vec!["high_exposure"],
+        ),
+        (
+            escrow(1_000, false),
+            vec![payment(1_001, false, now)],
+            Health::Red,
+            vec!["pending_exceeds_balance"],
+        ),
+        (
+            escrow(1_000, false),
+            vec![payment(10, true, now - 7 * DAY - 1)],
+            Health::Yellow,
+            vec!["inactive"],
+        ),
+        (
+            escrow(1_000, false),
+            vec![
+                payment(10, true, now - 31 * DAY),
+                payment(10, true, now - 40 * DAY),
+            ],
+            Health::Red,
+            vec!["inactive"],
+        ),
+        (escrow(0, false), vec![], Health::Yellow, vec!["empty"]),
+        (escrow(1_000, true), vec![], Health::Yellow, vec!["closing"]),
+        // Red wins over yellow, every reason is kept
+        (
+            escrow(0, true),
+            vec![payment(5, false, now - 8 * DAY)],
+            Health::Red,
+            vec!["pending_exceeds_balance", "inactive", "empty", "closing"],
+        ),
+    ];
+
+    let mut classified = Vec::new();
+    for (i, (escrow, payments, health, reasons)) in fixtures.into_iter().enumerate() {
+        let escrow = EscrowHealth::classify(i as u64, escrow, &payments, &policy, now);
+        assert_eq!(escrow.health, health, "fixture {i}");
+        assert_eq!(escrow.reasons, reasons, "fixture {i}");
+        classified.push(escrow);
+    }
+    assert_eq!(worst_health(&classified), Health::Red);
+    assert_eq!(worst_health(&classified[..3]), Health::Green);
+    assert_eq!(worst_health(&[]), Health::Green);
+
+    // Thresholds are the policy's
+    let lenient = HealthPolicy {
+        red_inactive_secs: 60 * DAY,
+        yellow_inactive_secs: 45 * DAY,
+        yellow_exposure_pct: 100,
+    };
+    let escrow = EscrowHealth::classify(
+        0,
+        escrow(1_000, false),
+        &[payment(1_000, false, now - 40 * DAY)],
+        &lenient,
+        now,
+    );
+    assert_eq!(escrow.health, Health::Green);
+    assert_eq!(escrow.pending, 1_000);
+    assert_eq!(escrow.last_activity, Some(now - 40 * DAY));
+}
+
+#[tokio::test]
+async fn test_monitor() {
+    let s = setup();
+    let server_addr = s.server.address();
+    let escrow = s
+        .client
+        .open_escrow(&s.client.address(), &server_addr, 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    s.server.create_payment(escrow, 900_000).await.unwrap();
+    let paid = s
+        .server
+        .create_payment(escrow, 300_000)
+        .await
+        .unwrap()
+        .value;
+    let paid_at = s.server.get_payment(paid).await.unwrap().timestamp;
+
+    let policy = HealthPolicy::default();
+    let escrows = monitor(&s.server, &server_addr, &policy, paid_at)
+        .await
+        .unwrap();
+    assert_eq!(escrows.len(), 1);
+    assert_eq!(escrows[0].pending, 1_200_000);
+    assert_eq!(escrows[0].last_activity, Some(paid_at));
+    assert_eq!(escrows[0].health, Health::Red);
+
+    let report = cli(&s.server, &["monitor", "--server", &server_addr])
+        .await
+        .unwrap();
+    assert_eq!(report[0]["escrowId"], escrow);
+    assert_eq!(report[0]["client"], s.client.address());
+    assert_eq!(report[0]["balance"], "0.1000000");
+    assert_eq!(report[0]["pending"], "0.1200000");
+    assert_eq!(report[0]["health"], "red");
+    // Measured against the clock, the test ledger's payments may be old
+    let reasons = report[0]["reasons"].as_str().unwrap();
+    assert!(reasons.starts_with("pending_exceeds_balance"), "{reasons}");
+
+    // Escrows of other servers are left out
+    let others = monitor(&s.server, &s.client.address(), &policy, paid_at)
+        .await
+        .unwrap();
+    assert!(others.is_empty());
+
+    let args = Cli::try_parse_from([
+        "x402-cli",
+        "monitor",
+        "--server",
+        &server_addr,
+        "--json",
+        "--watch",
+        "60",
+        "--red-inactive-days",
+        "90",
+    ])
+    .unwrap();
+    let Command::Monitor(args) = args.command else {
+        panic!("not a monitor command");
+    };
+    assert_eq!(args.format(Format::Table), Format::Json);
+    assert_eq!(args.watch, Some(60));
+    assert_eq!(args.policy().red_inactive_secs, 90 * DAY);
+    assert_eq!(args.policy().yellow_inactive_secs, 7 * DAY);
+}
+
+#[test]
+fn test_key_sources() {
+    let signer = LocalSigner::from_bytes(&CLIENT_SEED);
+    let secret = stellar_secret(&CLIENT_SEED);
+    let dir = scratch("keys");
+
+    let path = dir.join("secret");
+    fs::write(&path, format!("{secret}\n")).unwrap();
+    let loaded = KeySource::File(path).load().unwrap();
+    assert_eq!(loaded.address(), signer.address());
+
+    env::set_var("X402_CLI_TEST_SECRET", &secret);
+    let loaded = KeySource::Env("X402_CLI_TEST_SECRET".into())
+        .load()
+        .unwrap();
+    assert_eq!(loaded.address(), signer.address());
+    let unset = KeySource::Env("X402_CLI_TEST_UNSET".into()).load();
+    assert!(matches!(unset, Err(Error::Key(_))));
+
+    fs::write(
+        dir.join("alice.toml"),
+        format!("secret_key = \"{secret}\"\n"),
+    )
+    .unwrap();
+    fs::write(dir.join("bob.toml"), "seed_phrase = \"seed words\"\n").unwrap();
+    let dirs = [dir.join("missing"), dir.clone()];
+    let loaded = load_identity("alice", &dirs).unwrap();
+    assert_eq!(loaded.address(), signer.address());
+    assert!(matches!(load_identity("bob", &dirs), Err(Error::Key(_))));
+    assert!(matches!(load_identity("carol", &dirs), Err(Error::Key(_))));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_key_flags() {
+    let cli = Cli::try_parse_from([
+        "x402-cli",
+        "--identity",
+        "alice",
+        "balance",
+        "--escrow",
+        "0",
+    ])
+    .unwrap();
+    assert_eq!(cli.key.source(), KeySource::Identity("alice".into()));
+
+    let cli = Cli::try_parse_from(["x402-cli", "stats"]).unwrap();
+    assert_eq!(cli.key.source(), KeySource::Env("X402_SECRET_KEY".into()));
+
+    // Only one key source at a time
+    let result = Cli::try_parse_from([
+        "x402-cli",
+        "--identity",
+        "alice",
+        "--key-env",
+        "SECRET",
+        "stats",
+    ]);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_amount_flags() {
+    let cli =
+        Cli::try_parse_from(["x402-cli", "deposit", "--escrow", "0", "--amount", "1.25"]).unwrap();
+    match cli.command {
+        Command::Deposit { amount, .. } => assert_eq!(amount.stroops(), 12_500_000),
+        other => panic!("expected Deposit, got {other:?}"),
+    }
+
+    // Amounts finer than a stroop are rejected rather than rounded
+    for amount in ["0.00000001", "1,5", "-"] {
+        let result =
+            Cli::try_parse_from(["x402-cli", "deposit", "--escrow", "0", "--amount", amount]);
+        assert!(result.is_err(), "{amount}");
+    }
+}
+
+#[test]
+fn test_journal_export() {
+    let dir = scratch("journal");
+    let path = dir.join("journal.jsonl");
+    let journal_file = PaymentJournal::open(&path).unwrap();
+    let settled = |nonce, success| JournalEntry::Settled {
+        escrow_id: 0,
+        nonce,
+        amount: "1250000".into(),
+        settlement: Some(SettleResponse {
+            success,
+            error: None,
+            error_code: None,
+            tx_hash: Some("ab".repeat(32)),
+            network_id: None,
+            payment_id: Some(nonce),
+            ledger: None,
+            finality: None,
+            simulation: None,
+        }),
+        receipt_hash: Some("cd".repeat(32)),
+    };
+    journal_file
+        .record("api.example.com", settled(1, true))
+        .unwrap();
+    journal_file
+        .record("other.example.com", settled(2, false))
+        .unwrap();
+    drop(journal_file);
+
+    // Needs no RPC URL, contract, or key
+    let export = |filters: &[&str]| {
+        let args = [
+            "x402-cli",
+            "journal",
+            "export",
+            "--file",
+            path.to_str().unwrap(),
+        ];
+        let cli = Cli::try_parse_from(args.iter().chain(filters)).unwrap();
+        let Command::Journal { command } = cli.command else {
+            panic!("expected Journal, got {:?}", cli.command);
+        };
+        serde_json::to_value(journal(&command).unwrap()).unwrap()
+    };
+    let rows = export(&[]);
+    assert_eq!(rows.as_array().unwrap().len(), 2);
+    assert_eq!(rows[0]["host"], "api.example.com");
+    assert_eq!(rows[0]["amount"], "0.1250000");
+    assert_eq!(rows[0]["success"], true);
+    assert_eq!(rows[0]["paymentId"], 1);
+    assert_eq!(rows[0]["receiptHash"], "cd".repeat(32));
+    assert_eq!(rows[1]["success"], false);
+
+    let rows = export(&["--host", "other.example.com"]);
+    assert_eq!(rows.as_array().unwrap().len(), 1);
+    assert_eq!(rows[0]["nonce"], 2);
+    assert_eq!(export(&["--since", &u64::MAX.to_string()]), json!([]));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_receipts() {
+    let s = setup();
+    let dir = scratch("receipts");
+    let path = dir.join("receipt.json");
+    let escrow = s
+        .client
+        .open_escrow(&s.client.address(), &s.server.address(), 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let payment = s
+        .server
+        .create_payment(escrow, 250_000)
+        .await
+        .unwrap()
+        .value;
+    let id = payment.to_string();
+    let args = [
+        "receipt",
+        "--payment",
+        &id,
+        "--from-ledger",
+        "0",
+        "--out",
+        path.to_str().unwrap(),
+    ];
+    let err = cli(&s.server, &args).await.unwrap_err();
+    assert!(matches!(err, Error::Client(_)), "{err}");
+    s.server.settle_payment(payment).await.unwrap();
+
+    let issued = cli(&s.server, &args).await.unwrap();
+    assert_eq!(issued["amount"], "0.0250000");
+    assert_eq!(issued["receiptHash"].as_str().unwrap().len(), 64);
+
+    // Needs no RPC URL, contract, or key
+    let verify = |path: &Path| {
+        let cli = Cli::try_parse_from([
+            "x402-cli",
+            "verify-receipt",
+            "--file",
+            path.to_str().unwrap(),
+        ])
+        .unwrap();
+        let Command::VerifyReceipt { file } = cli.command else {
+            panic!("expected VerifyReceipt, got {:?}", cli.command);
+        };
+        verify_receipt(&file).map(|report| serde_json::to_value(report).unwrap())
+    };
+    let verified = verify(&path).unwrap();
+    assert_eq!(verified["valid"], true);
+    assert_eq!(verified["server"], s.server.address());
+    assert_eq!(verified["receiptHash"], issued["receiptHash"]);
+    let fixture =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../x402-client/fixtures/receipt_v1.json");
+    assert_eq!(verify(&fixture).unwrap()["paymentId"], 7);
+
+    // A receipt edited after signing is rejected
+    let mut edited: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    edited["amount"] = json!("2500000");
+    fs::write(&path, edited.to_string()).unwrap();
+    let err = verify(&path).unwrap_err();
+    assert!(matches!(err, Error::Client(_)), "{err}");
+    fs::write(&path, "not a receipt").unwrap();
+    assert!(verify(&path).is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_history() {
+    let s = setup();
+    let opened = s
+        .client
+        .open_escrow(&s.client.address(), &s.server.address(), 1_000_000, None)
+        .await
+        .unwrap();
+    let (escrow, from) = (opened.value, opened.ledger);
+    let deposited = s.client.deposit(escrow, 500_000).await.unwrap();
+    let first = s
+        .server
+        .create_payment(escrow, 200_000)
+        .await
+        .unwrap()
+        .value;
+    let second = s
+        .server
+        .create_payment(escrow, 300_000)
+        .await
+        .unwrap()
+        .value;
+    s.server.settle_payment(second).await.unwrap();
+    s.server.settle_payment(first).await.unwrap();
+    s.server.refund_payment(first, 50_000).await.unwrap();
+
+    let id = escrow.to_string();
+    let (from, deposit_ledger) = (from.to_string(), deposited.ledger.to_string());
+    let history = cli(&s.client, &["history", &id, "--from", &from])
+        .await
+        .unwrap();
+    let rows: Vec<_> = history
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row["operation"].as_str().unwrap(),
+                row["paymentId"].as_u64(),
+                row["amount"].as_str().unwrap(),
+                row["balance"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            ("open", None, "0.1000000", "0.1000000"),
+            ("deposit", None, "0.0500000", "0.1500000"),
+            ("settle", Some(second), "-0.0300000", "0.1200000"),
+            ("settle", Some(first), "-0.0200000", "0.1000000"),
+            ("refund", Some(first), "0.0050000", "0.1050000"),
+        ]
+    );
+    assert_eq!(history[0]["ledger"], opened.ledger);
+    assert_eq!(history[1]["txHash"], deposited.hash);
+
+    // Bounded, the history neither starts at the opening nor ends at the
+    // current balance
+    let bounded = cli(
+        &s.client,
+        &[
+            "history",
+            &id,
+            "--from",
+            &deposit_ledger,
+            "--to",
+            &deposit_ledger,
+        ],
+    )
+    .await
+    .unwrap();
+    assert_eq!(bounded.as_array().unwrap().len(), 1);
+    assert_eq!(bounded[0]["operation"], "deposit");
+    let late = cli(&s.client, &["history", &id, "--from", &deposit_ledger])
+        .await
+        .unwrap();
+    assert_eq!(late[0]["balance"], "0.1500000");
+    assert_eq!(late.as_array().unwrap().len(), 4);
+
+    // Released on closure, after which the escrow holds nothing
+    s.client.client_close_escrow(escrow).await.unwrap();
+    s.server.server_close_escrow(escrow).await.unwrap();
+    let closed = cli(&s.client, &["history", &id, "--from", &from])
+        .await
+        .unwrap();
+    let closed = closed.as_array().unwrap();
+    assert_eq!(closed.len(), 6);
+    assert_eq!(closed[5]["operation"], "close");
+    assert_eq!(closed[5]["amount"], "-0.1050000");
+    assert_eq!(closed[5]["balance"], "0.0000000");
+
+    // A missed deposit shows as a gap before the closure it fails to explain
+    let mut movements = escrow_movements(&s.client, escrow, opened.ledger, None)
+        .await
+        .unwrap();
+    assert_eq!(movements[1].operation, Operation::Deposit);
+    movements.remove(1);
+    let rows = reconcile(&movements, Some(0));
+    assert_eq!(rows.len(), 6);
+    let gap = &rows[4];
+    assert_eq!(gap.movement.operation, Operation::Gap);
+    assert_eq!(gap.movement.amount, 500_000);
+    assert_eq!(gap.movement.ledger, movements[4].ledger);
+    assert_eq!(gap.balance, 1_050_000);
+    assert_eq!(rows[5].balance, 0);
+
+    // As does a missed settlement, at the end without a closure
+    let mut movements = movements[..4].to_vec();
+    movements.remove(1);
+    let rows = reconcile(&movements, Some(550_000));
+    let gap = rows.last().unwrap();
+    assert_eq!(gap.movement.operation, Operation::Gap);
+    assert_eq!(gap.movement.ledger, None);
+    assert_eq!(gap.movement.amount, -300_000);
+    assert_eq!(gap.balance, 550_000);
+
+    // A split leaves the parent, and opens the child with the amount split
+    let parent = s
+        .client
+        .open_escrow(&s.client.address(), &s.server.address(), 1_000_000, None)
+        .await
+        .unwrap();
+    let child = s
+        .client
+        .split_escrow(parent.value, 400_000, b"tier:batch")
+        .await
+        .unwrap()
+        .value;
+    let (id, from) = (parent.value.to_string(), parent.ledger.to_string());
+    let rows = cli(&s.client, &["history", &id, "--from", &from])
+        .await
+        .unwrap();
+    assert_eq!(rows.as_array().unwrap().len(), 2);
+    assert_eq!(rows[1]["operation"], "split");
+    assert_eq!(rows[1]["amount"], "-0.0400000");
+    assert_eq!(rows[1]["balance"], "0.0600000");
+    let rows = cli(&s.client, &["history", &child.to_string(), "--from", &from])
+        .await
+        .unwrap();
+    assert_eq!(rows.as_array().unwrap().len(), 1);
+    assert_eq!(rows[0]["operation"], "open");
+    assert_eq!(rows[0]["balance"], "0.0400000");
+}
+
+#[test]
+fn test_parse_refunds() {
+    let rows = parse_refunds(
+        "\
+payment_id,amount
+# refunds for the outage
+3, 1.5
+
+7,0.0000001
+",
+    )
+    .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            RefundRow {
+                line: 3,
+                payment_id: 3,
+                amount: StellarAmount::from_stroops(15_000_000),
+            },
+            RefundRow {
+                line: 5,
+                payment_id: 7,
+                amount: StellarAmount::from_stroops(1),
+            },
+        ]
+    );
+    assert_eq!(parse_refunds("").unwrap(), vec![]);
+
+    for (csv, reason) in [
+        ("3", "line 1: expected payment_id,amount, found 1 fields"),
+        (
+            "3,1,2",
+            "line 1: expected payment_id,amount, found 3 fields",
+        ),
+        ("x,1", "line 1: invalid payment ID \"x\""),
+        (
+            "3,0.00000001",
+            "line 1: amount \"0.00000001\" is more precise than a stroop",
+        ),
+        ("3,0", "line 1: amount 0.0000000 is not positive"),
+        ("3,-1", "line 1: amount -1.0000000 is not positive"),
+        ("3,1\n\n3,2", "line 3: payment 3 is listed twice"),
+        (
+            "3,1\npayment_id,amount",
+            "line 2: invalid payment ID \"payment_id\"",
+        ),
+    ] {
+        let err = parse_refunds(csv).unwrap_err();
+        assert_eq!(err.to_string(), reason, "{csv:?}");
+    }
+}
+
+/// Refund run over `csv`, returning the outcomes
+async fn refund_csv(server: &EscrowClient, dir: &Path, csv: &str, flags: &[&str]) -> Value {
+    let path = dir.join("refunds.csv");
+    fs::write(&path, csv).unwrap();
+    let args = ["refund", "--from-csv", path.to_str().unwrap()];
+    let args: Vec<&str> = args.iter().chain(flags).copied().collect();
+    cli(server, &args).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_refund_dry_run() {
+    let s = setup();
+    let dir = scratch("refund-dry-run");
+    let escrow = s
+        .client
+        .open_escrow(&s.client.address(), &s.server.address(), 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let mut ids = Vec::new();
+    for amount in [300_000, 200_000, 100_000] {
+        ids.push(s.server.create_payment(escrow, amount).await.unwrap().value);
+    }
+    s.server.settle_payment(ids[0]).await.unwrap();
+    s.server.settle_payment(ids[1]).await.unwrap();
+    s.server.refund_payment(ids[1], 150_000).await.unwrap();
+
+    // Refunds are checked against what was settled and already refunded
+    let csv = format!(
+        "{},0.03\n{},0.01\n{},0.01\n{},0.01\n",
+        ids[0],
+        ids[1],
+        ids[2],
+        u64::MAX
+    );
+    let outcomes = refund_csv(&s.server, &dir, &csv, &["--dry-run"]).await;
+    let statuses: Vec<_> = outcomes
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|o| (o["status"].clone(), o["error"].clone()))
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            (json!("dry-run"), Value::Null),
+            (
+                json!("rejected"),
+                json!("exceeds the settled 0.0200000, 0.0150000 already refunded")
+            ),
+            (json!("rejected"), json!("payment not settled")),
+            (json!("rejected"), json!("payment not found")),
+        ]
+    );
+    assert_eq!(outcomes[0]["refunded"], "0.0300000");
+    assert_eq!(outcomes[0]["hash"], Value::Null);
+
+    // Nothing was submitted or written
+    assert_eq!(s.server.get_refunded(ids[0]).await.unwrap(), 0);
+    assert_eq!(s.server.get_escrow_balance(escrow).await.unwrap(), 650_000);
+    assert!(!dir.join("refunds.results.csv").exists());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_refund_resume() {
+    let s = setup();
+    let dir = scratch("refund-resume");
+    let escrow = s
+        .client
+        .open_escrow(&s.client.address(), &s.server.address(), 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let mut ids = Vec::new();
+    for amount in [300_000, 200_000, 100_000] {
+        ids.push(s.server.create_payment(escrow, amount).await.unwrap().value);
+    }
+    s.server.settle_payment(ids[0]).await.unwrap();
+    s.server.settle_payment(ids[2]).await.unwrap();
+
+    // The second payment is not settled yet, the others are refunded
+    let csv = format!(
+        "payment_id,amount\n{},0.01\n{},0.02\n{},0.01\n",
+        ids[0], ids[1], ids[2]
+    );
+    let results = dir.join("refunds.results.csv");
+    let outcomes = refund_csv(&s.server, &dir, &csv, &["--batch-size", "2"]).await;
+    assert_eq!(outcomes[0]["status"], "refunded");
+    assert_eq!(outcomes[1]["status"], "rejected");
+    assert_eq!(outcomes[2]["status"], "refunded");
+    let hash = outcomes[0]["hash"].as_str().unwrap().to_string();
+    assert_eq!(hash.len(), 64);
+    let written = fs::read_to_string(&results).unwrap();
+    assert_eq!(
+        written.lines().collect::<Vec<_>>(),
+        vec![
+            "payment_id,amount,status,refunded,tx_hash,error".to_string(),
+            format!("{},0.0100000,refunded,0.0100000,{hash},", ids[0]),
+            format!("{},0.0200000,rejected,,,payment not settled", ids[1]),
+            format!(
+                "{},0.0100000,refunded,0.0100000,{},",
+                ids[2],
+                outcomes[2]["hash"].as_str().unwrap()
+            ),
+        ]
+    );
+
+    // Rerunning only retries what was not refunded
+    s.server.settle_payment(ids[1]).await.unwrap();
+    let outcomes = refund_csv(&s.server, &dir, &csv, &[]).await;
+    assert_eq!(outcomes.as_array().unwrap().len(), 3);
+    assert_eq!(outcomes[0]["hash"], hash);
+    assert_eq!(outcomes[1]["paymentId"], ids[2]);
+    assert_eq!(outcomes[2]["paymentId"], ids[1]);
+    assert_eq!(outcomes[2]["status"], "refunded");
+    for payment_id in [ids[0], ids[2]] {
+        assert_eq!(s.server.get_refunded(payment_id).await.unwrap(), 100_000);
+    }
+    assert_eq!(s.server.get_refunded(ids[1]).await.unwrap(), 200_000);
+    let written = fs::read_to_string(&results).unwrap();
+    assert_eq!(written.lines().count(), 4);
+    assert!(written.lines().all(|line| !line.contains("rejected")));
+
+    // A results file claiming another amount is not trusted
+    let csv = format!("{},0.02\n", ids[0]);
+    let outcomes = refund_csv(&s.server, &dir, &csv, &[]).await;
+    assert_eq!(outcomes[0]["status"], "rejected");
+    assert_eq!(outcomes[0]["error"], "an earlier run refunded 0.0100000");
+    assert_eq!(s.server.get_refunded(ids[0]).await.unwrap(), 100_000);
+    let written = fs::read_to_string(&results).unwrap();
+    assert_eq!(written.lines().count(), 5);
+    assert!(written.contains(&format!(
+        "\n{},0.0100000,refunded,0.0100000,{hash},\n",
+        ids[0]
+    )));
+    assert!(written.contains(&format!("\n{},0.0200000,refunded,", ids[1])));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+fn stellar_secret(seed: &[u8; 32]) -> String {
+    stellar_strkey::ed25519::PrivateKey(*seed).to_string()
+}
@@ -0,0 +1,270 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ed25519_dalek::{Signature as Ed25519Signature, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+use stellar_xdr::curr::{
+    Hash, HashIdPreimage, HashIdPreimageSorobanAuthorization, InvokeContractArgs, Limits, ReadXdr,
+    ScAddress, ScBytes, ScMap, ScMapEntry, ScSymbol, ScVal, SorobanAddressCredentials,
+    SorobanAuthorizationEntry, SorobanAuthorizedFunction, SorobanAuthorizedInvocation,
+    SorobanCredentials, WriteXdr,
+};
+
+use crate::{estimate::EscrowOp, scval, Error};
+
+/// Soroban authorization entry of an account for one escrow contract call
+///
+/// The entry authorizes exactly one invocation, with the arguments it was
+/// signed for, until its expiration ledger. Any account may submit it, see
+/// [`EscrowClient::submit_authorized`](crate::EscrowClient::submit_authorized),
+/// so an agent can make the call later without holding the key. Its nonce
+/// is consumed on use, so it applies at most once.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuthorizationEntry(SorobanAuthorizationEntry);
+
+impl AuthorizationEntry {
+    /// Unsigned entry authorizing `op` on the contract at `contract_id`
+    ///
+    /// An [`EscrowOp::RecordDirectPayment`] also authorizes the token
+    /// transfer the contract makes on the account's behalf.
+    ///
+    /// # Arguments
+    /// * `contract_id` - Escrow contract address (C... format)
+    /// * `op` - Call authorized
+    /// * `address` - Account authorizing it (G... format)
+    /// * `nonce` - Unique per account until the entry expires, see [`random_nonce`]
+    /// * `expiration_ledger` - Last ledger the entry may be used in
+    ///
+    /// # Errors
+    /// * `InvalidAddress` - If an address is not valid strkey
+    pub fn new(
+        contract_id: &str,
+        op: &EscrowOp,
+        address: &str,
+        nonce: i64,
+        expiration_ledger: u32,
+    ) -> Result<Self, Error> {
+        let ScAddress::Contract(contract) = scval::parse_address(contract_id)? else {
+            return Err(Error::InvalidAddress(contract_id.to_string()));
+        };
+        let call = op.invocation()?;
+        let mut sub_invocations = vec![];
+        if let EscrowOp::RecordDirectPayment {
+            client,
+            server,
+            token,
+            amount,
+            ..
+        } = op
+        {
+            sub_invocations.push(SorobanAuthorizedInvocation {
+                function: SorobanAuthorizedFunction::ContractFn(InvokeContractArgs {
+                    contract_address: scval::parse_address(token)?,
+                    function_name: ScSymbol("transfer".try_into()?),
+                    args: vec![
+                        scval::parse_address(client).map(ScVal::Address)?,
+                        scval::parse_address(server).map(ScVal::Address)?,
+                        scval::i128(*amount),
+                    ]
+                    .try_into()?,
+                }),
+                sub_invocations: Default::default(),
+            });
+        }
+        Ok(Self(SorobanAuthorizationEntry {
+            credentials: SorobanCredentials::Address(SorobanAddressCredentials {
+                address: scval::parse_address(address)?,
+                nonce,
+                signature_expiration_ledger: expiration_ledger,
+                signature: ScVal::Void,
+            }),
+            root_invocation: SorobanAuthorizedInvocation {
+                function: SorobanAuthorizedFunction::ContractFn(InvokeContractArgs {
+                    contract_address: ScAddress::Contract(contract),
+                    function_name: ScSymbol(call.function.try_into()?),
+                    args: call.args.try_into()?,
+                }),
+                sub_invocations: sub_invocations.try_into()?,
+            },
+        }))
+    }
+
+    /// Decode an entry from base64 XDR
+    ///
+    /// # Errors
+    /// * `InvalidAuthorization` - If it is not an entry of an address for a
+    ///   contract call
+    pub fn from_xdr_base64(entry: &str) -> Result<Self, Error> {
+        SorobanAuthorizationEntry::from_xdr_base64(entry, Limits::none())
+            .map_err(|e| Error::InvalidAuthorization(e.to_string()))?
+            .try_into()
+    }
+
+    /// Encode the entry as base64 XDR, e.g. to hand it to another party
+    pub fn to_xdr_base64(&self) -> Result<String, Error> {
+        Ok(self.0.to_xdr_base64(Limits::none())?)
+    }
+
+    /// Underlying XDR entry
+    pub fn entry(&self) -> &SorobanAuthorizationEntry {
+        &self.0
+    }
+
+    /// Account authorizing the call (G... format)
+    pub fn address(&self) -> Result<String, Error> {
+        Ok(scval::format_address(&self.credentials()?.address))
+    }
+
+    /// Nonce consumed when the entry is used
+    pub fn nonce(&self) -> Result<i64, Error> {
+        Ok(self.credentials()?.nonce)
+    }
+
+    /// Last ledger the entry may be used in
+    pub fn expiration_ledger(&self) -> Result<u32, Error> {
+        Ok(self.credentials()?.signature_expiration_ledger)
+    }
+
+    /// Address of the contract called (C... format)
+    pub fn contract_id(&self) -> Result<String, Error> {
+        Ok(scval::format_address(&self.call()?.contract_address))
+    }
+
+    /// Call authorized
+    ///
+    /// # Errors
+    /// * `InvalidAuthorization` - If it is not an escrow contract operation
+    pub fn op(&self) -> Result<EscrowOp, Error> {
+        let call = self.call()?;
+        EscrowOp::from_invocation(&call.function_name.to_utf8_string_lossy(), &call.args)
+            .map_err(|e| Error::InvalidAuthorization(e.to_string()))
+    }
+
+    /// Hash the account signs, binding the network, nonce, expiration
+    /// ledger, and call
+    ///
+    /// # Arguments
+    /// * `network_id` - SHA-256 of the network passphrase
+    pub fn signing_hash(&self, network_id: &[u8; 32]) -> Result<[u8; 32], Error> {
+        let credentials = self.credentials()?;
+        let preimage = HashIdPreimage::SorobanAuthorization(HashIdPreimageSorobanAuthorization {
+            network_id: Hash(*network_id),
+            nonce: credentials.nonce,
+            signature_expiration_ledger: credentials.signature_expiration_ledger,
+            invocation: self.0.root_invocation.clone(),
+        });
+        Ok(Sha256::digest(preimage.to_xdr(Limits::none())?).into())
+    }
+
+    /// Attach the signature of a key of the account over the
+    /// [signing hash](Self::signing_hash), replacing any other
+    ///
+    /// # Arguments
+    /// * `public_key` - Key that signed, the account's own or another signer of it
+    /// * `signature` - Its ed25519 signature
+    pub fn with_signature(
+        mut self,
+        public_key: [u8; 32],
+        signature: [u8; 64],
+    ) -> Result<Self, Error> {
+        let field = |name: &str, value: Vec<u8>| -> Result<ScMapEntry, Error> {
+            Ok(ScMapEntry {
+                key: ScVal::Symbol(ScSymbol(name.try_into()?)),
+                val: ScVal::Bytes(ScBytes(value.try_into()?)),
+            })
+        };
+        let signature = ScVal::Map(Some(ScMap(
+            vec![
+                field("public_key", public_key.to_vec())?,
+                field("signature", signature.to_vec())?,
+            ]
+            .try_into()?,
+        )));
+        let SorobanCredentials::Address(credentials) = &mut self.0.credentials else {
+            return Err(Error::InvalidAuthorization(
+                "entry has no address credentials".into(),
+            ));
+        };
+        credentials.signature = scval::vec(vec![signature])?;
+        Ok(self)
+    }
+
+    /// Public keys the entry is signed with, whose signatures are valid
+    ///
+    /// Whether they are signers of the account is up to the network to
+    /// check.
+    ///
+    /// # Errors
+    /// * `InvalidAuthorization` - If the entry is unsigned, or a signature
+    ///   is malformed or does not match the signing hash
+    pub fn verify(&self, network_id: &[u8; 32]) -> Result<Vec<[u8; 32]>, Error> {
+        let invalid = |reason: &str| Error::InvalidAuthorization(reason.into());
+        let hash = self.signing_hash(network_id)?;
+        let signatures = match &self.credentials()?.signature {
+            ScVal::Vec(Some(signatures)) if !signatures.is_empty() => signatures,
+            _ => return Err(invalid("entry is unsigned")),
+        };
+        let mut keys = vec![];
+        for signature in signatures.iter() {
+            let fields =
+                scval::Fields::new(signature).map_err(|_| invalid("malformed signature"))?;
+            let bytes = |name| match fields.get(name) {
+                Ok(ScVal::Bytes(bytes)) => Ok(bytes.as_slice()),
+                _ => Err(invalid("malformed signature")),
+            };
+            let public_key: [u8; 32] = bytes("public_key")?
+                .try_into()
+                .map_err(|_| invalid("malformed public key"))?;
+            let key = VerifyingKey::from_bytes(&public_key)
+                .map_err(|_| invalid("malformed public key"))?;
+            let signature = Ed25519Signature::from_slice(bytes("signature")?)
+                .map_err(|_| invalid("malformed signature"))?;
+            key.verify(&hash, &signature)
+                .map_err(|_| invalid("signature does not match the entry"))?;
+            keys.push(public_key);
+        }
+        Ok(keys)
+    }
+
+    fn credentials(&self) -> Result<&SorobanAddressCredentials, Error> {
+        match &self.0.credentials {
+            SorobanCredentials::Address(credentials) => Ok(credentials),
+            SorobanCredentials::SourceAccount => Err(Error::InvalidAuthorization(
+                "entry has no address credentials".into(),
+            )),
+        }
+    }
+
+    fn call(&self) -> Result<&InvokeContractArgs, Error> {
+        match &self.0.root_invocation.function {
+            SorobanAuthorizedFunction::ContractFn(call) => Ok(call),
+            _ => Err(Error::InvalidAuthorization(
+                "entry does not authorize a contract call".into(),
+            )),
+        }
+    }
+}
+
+impl TryFrom<SorobanAuthorizationEntry> for AuthorizationEntry {
+    type Error = Error;
+
+    fn try_from(entry: SorobanAuthorizationEntry) -> Result<Self, Error> {
+        let entry = Self(entry);
+        entry.credentials()?;
+        entry.call()?;
+        Ok(entry)
+    }
+}
+
+/// Nonce unlikely to have been used by the account before
+pub fn random_nonce() -> i64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    // Hashers are seeded randomly per process, and anew per instance
+    RandomState::new().hash_one(nanos) as i64
+}
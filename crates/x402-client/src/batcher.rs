@@ -0,0 +1,485 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    ops::Range,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::{stream, StreamExt};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError},
+    oneshot,
+};
+
+use crate::{Error, EscrowClient, Settlement, Submitted};
+
+/// Tunables of a [`PaymentBatcher`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BatchOptions {
+    /// Most payments created, then settled, by one transaction
+    pub max_batch: usize,
+    /// Longest the first payment of a batch waits for the batch to fill
+    pub max_delay: Duration,
+    /// Most payments recorded and not taken into a batch yet, past which
+    /// recording waits for room
+    pub max_queued: usize,
+    /// Most batches in flight at once
+    pub concurrency: usize,
+    /// Settle the payments of a batch once created, rather than leaving
+    /// them pending
+    pub settle: bool,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            max_batch: 50,
+            max_delay: Duration::from_millis(500),
+            max_queued: 10_000,
+            concurrency: 4,
+            settle: true,
+        }
+    }
+}
+
+/// Payment recorded by a [`PaymentBatcher`], once created on-chain
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BatchedPayment {
+    /// Reference given when recording, e.g. the ID of the request paid for
+    pub reference: String,
+    pub escrow_id: u64,
+    pub amount: i128,
+    pub payment_id: u64,
+    /// Hex-encoded hash of the transaction creating the payment
+    pub created_in: String,
+    /// Hex-encoded hash of the transaction settling it, None if it was
+    /// left pending
+    pub settled_in: Option<String>,
+}
+
+/// Counters of a [`PaymentBatcher`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BatcherMetrics {
+    /// Payments recorded
+    pub recorded: u64,
+    /// Batches flushed
+    pub batches: u64,
+    /// Batches being created or settled
+    pub in_flight: usize,
+    /// Payments created on-chain
+    pub created: u64,
+    /// Payments settled on-chain
+    pub settled: u64,
+    /// Payments that could not be created
+    pub failed: u64,
+    /// Most batches in flight at once
+    pub peak_in_flight: usize,
+}
+
+/// Completion of a payment recorded by a [`PaymentBatcher`]
+///
+/// Resolves once the batch holding the payment was created and, if the
+/// batcher settles, settled.
+///
+/// # Errors
+/// * `Contract` - If the contract rejected the payment itself
+/// * `BatchFailed` - If the batch holding the payment failed as a whole,
+///   e.g. RPC could not be reached
+/// * `BatcherStopped` - If the worker stopped before the batch completed,
+///   the payment having possibly been created
+pub struct PaymentTicket {
+    reference: String,
+    done: oneshot::Receiver<Result<BatchedPayment, Error>>,
+}
+
+impl PaymentTicket {
+    /// Reference given when recording the payment
+    pub fn reference(&self) -> &str {
+        &self.reference
+    }
+}
+
+impl Future for PaymentTicket {
+    type Output = Result<BatchedPayment, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.done)
+            .poll(cx)
+            .map(|done| done.unwrap_or(Err(Error::BatcherStopped)))
+    }
+}
+
+/// Payment waiting in the queue of a [`PaymentBatcher`]
+struct Record {
+    escrow_id: u64,
+    amount: i128,
+    reference: String,
+    done: oneshot::Sender<Result<BatchedPayment, Error>>,
+}
+
+/// Batch of records, created once the previous batch was
+struct Batch {
+    records: Vec<Record>,
+    /// Closed once the previous batch was created
+    turn: Option<oneshot::Receiver<()>>,
+    /// Closed once this batch was created
+    created: oneshot::Sender<()>,
+}
+
+/// Payments of a high-volume server, created and settled in batches
+///
+/// Handlers record each payment and get a [`PaymentTicket`] back at once,
+/// while a [`BatchWorker`] flushes the queue through the contract's
+/// `create_payments` and `settle_payments`: a batch leaves once it holds
+/// `max_batch` payments, or once its first payment waited `max_delay`.
+///
+/// Memory stays bounded under load. Past `max_queued` waiting payments,
+/// [`PaymentBatcher::record`] waits for room and
+/// [`PaymentBatcher::try_record`] fails, and at most `concurrency` batches
+/// are in flight. Batches are created in the order their payments were
+/// recorded, so the payments of an escrow are numbered in that order.
+///
+/// A batch the contract rejects is halved until the payments at fault
+/// stand alone, their tickets failing with the contract error while the
+/// others go through. Payments whose settlement fails stay pending for the
+/// server to settle later.
+///
+/// The worker runs as a background task of the resource server:
+/// `tokio::spawn(worker.run())`. Clones of the batcher share its queue.
+#[derive(Clone)]
+pub struct PaymentBatcher {
+    records: mpsc::Sender<Record>,
+    max_queued: usize,
+    metrics: Arc<Mutex<BatcherMetrics>>,
+}
+
+impl PaymentBatcher {
+    /// Batch the payments of the signer of `escrow`
+    ///
+    /// # Returns
+    /// * The batcher, and the worker flushing its queue
+    pub fn new(escrow: EscrowClient, options: BatchOptions) -> (Self, BatchWorker) {
+        let max_queued = options.max_queued.max(1);
+        let (sender, receiver) = mpsc::channel(max_queued);
+        let metrics = Arc::new(Mutex::new(BatcherMetrics::default()));
+        let batcher = Self {
+            records: sender,
+            max_queued,
+            metrics: metrics.clone(),
+        };
+        let worker = BatchWorker {
+            escrow,
+            options,
+            records: receiver,
+            metrics,
+        };
+        (batcher, worker)
+    }
+
+    /// Queue a payment, waiting for room if the queue is full
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow charged
+    /// * `amount` - Payment amount (in stroops)
+    /// * `reference` - Reported back with the payment
+    ///
+    /// # Errors
+    /// * `BatcherStopped` - If the worker stopped
+    pub async fn record(
+        &self,
+        escrow_id: u64,
+        amount: i128,
+        reference: impl Into<String>,
+    ) -> Result<PaymentTicket, Error> {
+        let (record, ticket) = record(escrow_id, amount, reference.into());
+        self.records
+            .send(record)
+            .await
+            .map_err(|_| Error::BatcherStopped)?;
+        self.metrics.lock().unwrap().recorded += 1;
+        Ok(ticket)
+    }
+
+    /// Queue a payment if there is room
+    ///
+    /// # Errors
+    /// * `QueueFull` - If `max_queued` payments are waiting
+    /// * `BatcherStopped` - If the worker stopped
+    pub fn try_record(
+        &self,
+        escrow_id: u64,
+        amount: i128,
+        reference: impl Into<String>,
+    ) -> Result<PaymentTicket, Error> {
+        let (record, ticket) = record(escrow_id, amount, reference.into());
+        match self.records.try_send(record) {
+            Ok(()) => {
+                self.metrics.lock().unwrap().recorded += 1;
+                Ok(ticket)
+            }
+            Err(TrySendError::Full(_)) => Err(Error::QueueFull(self.max_queued)),
+            Err(TrySendError::Closed(_)) => Err(Error::BatcherStopped),
+        }
+    }
+
+    /// Payments recorded and not taken into a batch yet
+    pub fn queued(&self) -> usize {
+        self.max_queued - self.records.capacity()
+    }
+
+    /// Counters of the batcher so far
+    pub fn metrics(&self) -> BatcherMetrics {
+        self.metrics.lock().unwrap().clone()
+    }
+}
+
+fn record(escrow_id: u64, amount: i128, reference: String) -> (Record, PaymentTicket) {
+    let (done, receiver) = oneshot::channel();
+    let ticket = PaymentTicket {
+        reference: reference.clone(),
+        done: receiver,
+    };
+    let record = Record {
+        escrow_id,
+        amount,
+        reference,
+        done,
+    };
+    (record, ticket)
+}
+
+/// Flushes the queue of a [`PaymentBatcher`]
+pub struct BatchWorker {
+    escrow: EscrowClient,
+    options: BatchOptions,
+    records: mpsc::Receiver<Record>,
+    metrics: Arc<Mutex<BatcherMetrics>>,
+}
+
+impl BatchWorker {
+    /// Flush batches until every batcher was dropped and the queue is
+    /// empty
+    pub async fn run(self) {
+        let Self {
+            escrow,
+            options,
+            records,
+            metrics,
+        } = self;
+        let (max_batch, max_delay) = (options.max_batch.max(1), options.max_delay);
+        let batches = stream::unfold(
+            (records, None),
+            move |(mut records, turn): (mpsc::Receiver<Record>, _)| async move {
+                let mut batch = vec![records.recv().await?];
+                let deadline = tokio::time::Instant::now() + max_delay;
+                while batch.len() < max_batch {
+                    match tokio::time::timeout_at(deadline, records.recv()).await {
+                        Ok(Some(record)) => batch.push(record),
+                        // Out of time, or the last batcher was dropped
+                        Ok(None) | Err(_) => break,
+                    }
+                }
+                let (created, next) = oneshot::channel();
+                let batch = Batch {
+                    records: batch,
+                    turn,
+                    created,
+                };
+                Some((batch, (records, Some(next))))
+            },
+        );
+
+        let flusher = Flusher {
+            escrow,
+            settle: options.settle,
+            metrics,
+        };
+        batches
+            .map(|batch| flusher.flush(batch))
+            .buffered(options.concurrency.max(1))
+            .for_each(|()| async {})
+            .await;
+    }
+}
+
+struct Flusher {
+    escrow: EscrowClient,
+    settle: bool,
+    metrics: Arc<Mutex<BatcherMetrics>>,
+}
+
+impl Flusher {
+    /// Create then settle the payments of a batch, completing their tickets
+    async fn flush(&self, batch: Batch) {
+        let Batch {
+            records,
+            turn,
+            created,
+        } = batch;
+        {
+            let mut metrics = self.metrics.lock().unwrap();
+            metrics.in_flight += 1;
+            metrics.peak_in_flight = metrics.peak_in_flight.max(metrics.in_flight);
+        }
+
+        // Payments of an escrow are numbered in the order they were recorded
+        if let Some(turn) = turn {
+            let _ = turn.await;
+        }
+        let intents: Vec<(u64, i128)> = records
+            .iter()
+            .map(|record| (record.escrow_id, record.amount))
+            .collect();
+        let mut results = Vec::with_capacity(records.len());
+        for (part, outcome) in bisect(&intents, |part| self.escrow.create_payments(part)).await {
+            match outcome.and_then(|created| check_len(created, part.len())) {
+                Ok(created) => {
+                    for (index, payment_id) in part.zip(created.value) {
+                        let (escrow_id, amount) = intents[index];
+                        results.push(Ok(BatchedPayment {
+                            reference: records[index].reference.clone(),
+                            escrow_id,
+                            amount,
+                            payment_id,
+                            created_in: created.hash.clone(),
+                            settled_in: None,
+                        }));
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, payments = part.len(), "payment batch failed");
+                    results.extend(fail(e, part.len()));
+                }
+            }
+        }
+        drop(created);
+
+        if self.settle {
+            self.settle(&mut results).await;
+        }
+
+        {
+            let mut metrics = self.metrics.lock().unwrap();
+            metrics.in_flight -= 1;
+            metrics.batches += 1;
+            for result in &results {
+                match result {
+                    Ok(payment) => {
+                        metrics.created += 1;
+                        metrics.settled += u64::from(payment.settled_in.is_some());
+                    }
+                    Err(_) => metrics.failed += 1,
+                }
+            }
+        }
+        for (record, result) in records.into_iter().zip(results) {
+            let _ = record.done.send(result);
+        }
+    }
+
+    /// Settle the created payments of a batch, leaving those that cannot
+    /// be pending
+    async fn settle(&self, results: &mut [Result<BatchedPayment, Error>]) {
+        // Terms are those of the payments just created, with the clients
+        // this client reads on-chain
+        let mut clients: HashMap<u64, String> = HashMap::new();
+        let mut terms = Vec::new();
+        let mut indices = Vec::new();
+        for (index, result) in results.iter().enumerate() {
+            let Ok(payment) = result else { continue };
+            if !clients.contains_key(&payment.escrow_id) {
+                match self.escrow.get_escrow(payment.escrow_id).await {
+                    Ok(escrow) => {
+                        clients.insert(payment.escrow_id, escrow.client);
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            error = %e,
+                            escrow_id = payment.escrow_id,
+                            "payments left pending, escrow cannot be read"
+                        );
+                        continue;
+                    }
+                }
+            }
+            terms.push(Settlement {
+                payment_id: payment.payment_id,
+                escrow_id: payment.escrow_id,
+                amount: payment.amount,
+                client: clients[&payment.escrow_id].clone(),
+            });
+            indices.push(index);
+        }
+        if terms.is_empty() {
+            return;
+        }
+
+        for (part, outcome) in bisect(&terms, |part| self.escrow.settle_terms(part)).await {
+            match outcome {
+                Ok(settled) => {
+                    for index in part {
+                        if let Ok(payment) = &mut results[indices[index]] {
+                            payment.settled_in = Some(settled.hash.clone());
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, payments = part.len(), "payments left pending");
+                }
+            }
+        }
+    }
+}
+
+/// Call `call` on `items`, halving the parts the contract rejects until
+/// the rejected items stand alone
+///
+/// # Returns
+/// * Outcome of each part called last, in item order
+async fn bisect<'a, T, V, F, Fut>(
+    items: &'a [T],
+    call: F,
+) -> Vec<(Range<usize>, Result<Submitted<V>, Error>)>
+where
+    F: Fn(&'a [T]) -> Fut,
+    Fut: Future<Output = Result<Submitted<V>, Error>>,
+{
+    let mut parts = VecDeque::from([0..items.len()]);
+    let mut outcomes = Vec::new();
+    while let Some(part) = parts.pop_front() {
+        match call(&items[part.clone()]).await {
+            Err(Error::Contract(..)) if part.len() > 1 => {
+                let middle = part.start + part.len() / 2;
+                parts.push_front(middle..part.end);
+                parts.push_front(part.start..middle);
+            }
+            outcome => outcomes.push((part, outcome)),
+        }
+    }
+    outcomes
+}
+
+/// Check the contract created one payment per intent
+fn check_len(created: Submitted<Vec<u64>>, len: usize) -> Result<Submitted<Vec<u64>>, Error> {
+    if created.value.len() != len {
+        return Err(Error::InvalidResponse(format!(
+            "{} payment IDs for {len} payments",
+            created.value.len()
+        )));
+    }
+    Ok(created)
+}
+
+/// Failure of each of `count` payments, sharing `error` between them
+fn fail(error: Error, count: usize) -> Vec<Result<BatchedPayment, Error>> {
+    if count == 1 {
+        return vec![Err(error)];
+    }
+    let error = Arc::new(error);
+    (0..count)
+        .map(|_| Err(Error::BatchFailed(error.clone())))
+        .collect()
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::HashSet,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{ContractError, Error, EscrowClient};
+
+/// Default time between reconciliations of a [`ChannelState`]
+pub const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Payment authorized by the client and not yet seen settled on-chain
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PendingPayment {
+    /// Nonce of the authorization
+    pub nonce: u64,
+    /// Authorized amount, in stroops
+    pub amount: i128,
+    /// Unix timestamp after which the authorization is void
+    pub expires_at: u64,
+    /// On-chain payment created from the authorization, once reconciled
+    pub payment_id: Option<u64>,
+    /// Whether the server reported its settlement, already deducted from the
+    /// expected balance
+    pub reported_settled: bool,
+}
+
+/// Differences between cached and on-chain state found by reconciliations
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DriftMetrics {
+    /// Reconciliations performed
+    pub syncs: u64,
+    /// Reconciliations finding a balance other than the expected one
+    pub drifted_syncs: u64,
+    /// On-chain minus expected balance at the last reconciliation, in stroops
+    pub last_drift: i128,
+    /// Largest absolute drift seen, in stroops
+    pub max_drift: i128,
+    /// On-chain payments matching no authorization of the cache, e.g.
+    /// authorized by another instance of the client
+    pub unknown_payments: u64,
+}
+
+#[derive(Debug, Default)]
+struct Channel {
+    /// Expected on-chain balance
+    balance: i128,
+    pending: Vec<PendingPayment>,
+    /// Payments of the escrow already reconciled
+    payments_seen: u32,
+    synced_at: Option<Instant>,
+    stale: bool,
+    drift: DriftMetrics,
+}
+
+/// Locally cached state of the escrow between a client and a server
+///
+/// Reading the escrow balance before every payment costs an RPC round trip.
+/// The cache instead tracks the expected on-chain balance and the payments
+/// authorized against it, updated optimistically as the client deposits,
+/// authorizes payments, and hears of their settlement.
+///
+/// It reconciles against on-chain state when first used, once the sync
+/// interval has passed since the last reconciliation, after being marked
+/// stale, e.g. because the facilitator rejected a payment, and on
+/// [`ChannelState::force_sync`]. Payments created since the last
+/// reconciliation are matched to pending authorizations of the same amount,
+/// oldest first; those matching none are counted as unknown. Settled
+/// payments leave the pending list, authorizations that expired unused are
+/// dropped, and the on-chain balance replaces the expected one, their
+/// difference being recorded in the [`DriftMetrics`].
+pub struct ChannelState {
+    escrow: EscrowClient,
+    escrow_id: u64,
+    sync_interval: Duration,
+    channel: Mutex<Channel>,
+    syncing: tokio::sync::Mutex<()>,
+}
+
+impl ChannelState {
+    /// Create a cache of an escrow, reconciled on first use
+    ///
+    /// # Arguments
+    /// * `escrow` - Escrow client reading on-chain state
+    /// * `escrow_id` - Escrow to track
+    pub fn new(escrow: EscrowClient, escrow_id: u64) -> Self {
+        Self {
+            escrow,
+            escrow_id,
+            sync_interval: DEFAULT_SYNC_INTERVAL,
+            channel: Mutex::default(),
+            syncing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Reconcile every `interval` rather than [`DEFAULT_SYNC_INTERVAL`]
+    pub fn with_sync_interval(mut self, interval: Duration) -> Self {
+        self.sync_interval = interval;
+        self
+    }
+
+    /// Tracked escrow
+    pub fn escrow_id(&self) -> u64 {
+        self.escrow_id
+    }
+
+    /// Expected on-chain balance, in stroops
+    pub fn balance(&self) -> i128 {
+        self.channel.lock().unwrap().balance
+    }
+
+    /// Expected balance minus pending payments not yet reported settled, in
+    /// stroops
+    pub fn available(&self) -> i128 {
+        let channel = self.channel.lock().unwrap();
+        let committed: i128 = channel
+            .pending
+            .iter()
+            .filter(|payment| !payment.reported_settled)
+            .map(|payment| payment.amount)
+            .sum();
+        channel.balance - committed
+    }
+
+    /// Payments authorized and not yet seen settled on-chain, oldest first
+    pub fn pending(&self) -> Vec<PendingPayment> {
+        self.channel.lock().unwrap().pending.clone()
+    }
+
+    /// Drift found by the reconciliations so far
+    pub fn drift(&self) -> DriftMetrics {
+        self.channel.lock().unwrap().drift.clone()
+    }
+
+    /// Whether the next [`ChannelState::sync_if_due`] reconciles
+    pub fn is_due(&self) -> bool {
+        let channel = self.channel.lock().unwrap();
+        channel.stale
+            || channel
+                .synced_at
+                .is_none_or(|at| at.elapsed() >= self.sync_interval)
+    }
+
+    /// Record a deposit made by the client
+    pub fn record_deposit(&self, amount: i128) {
+        self.channel.lock().unwrap().balance += amount;
+    }
+
+    /// Record a payment authorized by the client
+    ///
+    /// # Arguments
+    /// * `nonce` - Nonce of the authorization
+    /// * `amount` - Authorized amount, in stroops
+    /// * `expires_at` - Unix timestamp after which the authorization is void
+    pub fn record_payment(&self, nonce: u64, amount: i128, expires_at: u64) {
+        self.channel.lock().unwrap().pending.push(PendingPayment {
+            nonce,
+            amount,
+            expires_at,
+            payment_id: None,
+            reported_settled: false,
+        });
+    }
+
+    /// Record the settlement of a payment as reported by the server,
+    /// deducting it from the expected balance until reconciliation confirms
+    /// it
+    pub fn record_settlement(&self, nonce: u64) {
+        let mut channel = self.channel.lock().unwrap();
+        let Some(payment) = channel
+            .pending
+            .iter_mut()
+            .find(|payment| payment.nonce == nonce && !payment.reported_settled)
+        else {
+            return;
+        };
+        payment.reported_settled = true;
+        let amount = payment.amount;
+        channel.balance -= amount;
+    }
+
+    /// Reconcile on the next [`ChannelState::sync_if_due`], e.g. after an
+    /// error hinting at a discrepancy
+    pub fn mark_stale(&self) {
+        self.channel.lock().unwrap().stale = true;
+    }
+
+    /// Reconcile if never done, marked stale, or the sync interval passed
+    ///
+    /// # Errors
+    /// * If on-chain state could not be read, leaving the cache stale
+    pub async fn sync_if_due(&self) -> Result<(), Error> {
+        if self.is_due() {
+            self.force_sync().await?;
+        }
+        Ok(())
+    }
+
+    /// Reconcile against on-chain state now
+    ///
+    /// # Returns
+    /// * The drift metrics, updated with this reconciliation
+    ///
+    /// # Errors
+    /// * If on-chain state could not be read, leaving the cache stale
+    pub async fn force_sync(&self) -> Result<DriftMetrics, Error> {
+        let _syncing = self.syncing.lock().await;
+        let result = self.reconcile().await;
+        if result.is_err() {
+            self.mark_stale();
+        }
+        result
+    }
+
+    async fn reconcile(&self) -> Result<DriftMetrics, Error> {
+        let (seen, watched): (u32, Vec<u64>) = {
+            let channel = self.channel.lock().unwrap();
+            let watched = channel.pending.iter().filter_map(|p| p.payment_id);
+            (channel.payments_seen, watched.collect())
+        };
+
+        // Payments are read before the balance, so a settlement in between
+        // makes the balance look lower rather than higher than it is
+        let created = self
+            .escrow
+            .payments(self.escrow_id)
+            .offset(seen)
+            .collect_all(usize::MAX)
+            .await?;
+        let mut settled = HashSet::new();
+        for payment_id in watched {
+            match self.escrow.get_payment(payment_id).await {
+                Ok(payment) if payment.settled => {
+                    settled.insert(payment_id);
+                }
+                Ok(_) => {}
+                Err(e) if e.contract_error() == Some(ContractError::PaymentNotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let balance = self.escrow.get_escrow_balance(self.escrow_id).await?;
+
+        let mut channel = self.channel.lock().unwrap();
+        let first = channel.synced_at.is_none();
+        channel.payments_seen = seen + created.len() as u32;
+        // Payments made before the cache existed are part of the balance
+        for (payment_id, payment) in created.iter().filter(|_| !first) {
+            let matched = channel
+                .pending
+                .iter_mut()
+                .find(|pending| pending.payment_id.is_none() && pending.amount == payment.amount);
+            match matched {
+                Some(pending) => pending.payment_id = Some(*payment_id),
+                None => channel.drift.unknown_payments += 1,
+            }
+            if payment.settled {
+                settled.insert(*payment_id);
+            }
+        }
+
+        let now = unix_now();
+        let mut expected = channel.balance;
+        channel.pending.retain_mut(|pending| {
+            if pending.payment_id.is_some_and(|id| settled.contains(&id)) {
+                if !pending.reported_settled {
+                    expected -= pending.amount;
+                }
+                return false;
+            }
+            // Not settled yet whatever the server said, so it is committed
+            // rather than deducted from the on-chain balance
+            if pending.reported_settled {
+                expected += pending.amount;
+                pending.reported_settled = false;
+            }
+            pending.payment_id.is_some() || pending.expires_at >= now
+        });
+
+        let drift = &mut channel.drift;
+        drift.syncs += 1;
+        if !first {
+            drift.last_drift = balance - expected;
+            drift.max_drift = drift.max_drift.max(drift.last_drift.abs());
+            if drift.last_drift != 0 {
+                drift.drifted_syncs += 1;
+            }
+        }
+        let metrics = drift.clone();
+        channel.balance = balance;
+        channel.synced_at = Some(Instant::now());
+        channel.stale = false;
+        Ok(metrics)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,2743 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use futures_util::Stream;
+use sha2::{Digest, Sha256};
+use stellar_strkey::{ed25519, Strkey};
+use stellar_xdr::curr::{
+    DecoratedSignature, FeeBumpTransaction, FeeBumpTransactionEnvelope, FeeBumpTransactionExt,
+    FeeBumpTransactionInnerTx, Hash, HostFunction, InvokeContractArgs, InvokeHostFunctionOp,
+    Limits, Memo, MuxedAccount, Operation, OperationBody, Preconditions, ReadXdr, ScAddress,
+    ScSymbol, ScVal, SequenceNumber, Signature, SignatureHint, SorobanAuthorizationEntry,
+    SorobanTransactionData, Transaction, TransactionEnvelope, TransactionExt, TransactionMeta,
+    TransactionSignaturePayload, TransactionSignaturePayloadTaggedTransaction,
+    TransactionV1Envelope, Uint256, WriteXdr,
+};
+use tokio::sync::Mutex;
+
+use x402_bindings::{self as bindings, Invocation};
+use x402_types::{EscrowChannelState, EscrowPayload, EscrowVoucher, SigningDomain};
+
+use crate::{
+    auth::{random_nonce, AuthorizationEntry},
+    describe::format_timestamp,
+    estimate::{DryRun, EscrowOp, EstimateResult},
+    event::{Emitted, EscrowEvent, EventFilter, Subscription, EVENT_PAGE_LIMIT, MAX_EVENT_BACKOFF},
+    feebump::FeeBumpPolicy,
+    finality::{Finality, FinalityPolicy},
+    payments::{
+        PaymentList, PaymentQuery, PaymentStatus, MAX_COMPACT_PAGES, MAX_ESCROWS_PAGE,
+        PAYMENT_PAGE_RETRIES,
+    },
+    receipt::{ReceiptBody, SettlementReceipt, RECEIPT_VERSION},
+    rpc::{EventsFrom, Rpc, SimulateTransactionResponse},
+    scval::{self, Fields},
+    submission::{Disposition, SubmissionLog, SUBMIT_ATTEMPTS},
+    ContractError, Error, Signer,
+};
+
+/// Message whose hash [`EscrowClient::check_signer`] signs, separated from
+/// transaction and authorization hashes
+const SIGNER_PROBE: &[u8] = b"x402-signer-probe";
+
+/// Escrow account for a client-server pair
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Escrow {
+    pub client: String,
+    pub server: String,
+    pub balance: i128,
+    pub client_closed: bool,
+    pub server_closed: bool,
+    /// Hash of the server's terms of service accepted when opening, None if
+    /// the server published none
+    pub terms_hash: Option<[u8; 32]>,
+    /// Whether the server sponsored the escrow as a trial
+    pub trial: bool,
+    /// Part of the balance the server sponsored and settlements have not
+    /// spent yet, never released to the client
+    pub trial_balance: i128,
+    /// Sum of the payments not settled yet
+    pub pending: i128,
+    /// Number of the payments not settled yet
+    pub pending_payments: u32,
+}
+
+impl TryFrom<&ScVal> for Escrow {
+    type Error = Error;
+
+    fn try_from(value: &ScVal) -> Result<Self, Error> {
+        let fields = Fields::new(value)?;
+        Ok(Self {
+            client: scval::to_address(fields.get("client")?)?,
+            server: scval::to_address(fields.get("server")?)?,
+            balance: scval::to_i128(fields.get("balance")?)?,
+            client_closed: scval::to_bool(fields.get("client_closed")?)?,
+            server_closed: scval::to_bool(fields.get("server_closed")?)?,
+            // Contracts without terms of service do not record them
+            terms_hash: fields
+                .find("terms_hash")
+                .map_or(Ok(None), |value| scval::to_option(value, scval::to_bytes32))?,
+            // Nor do contracts without sponsored trials
+            trial: fields.find("trial").map_or(Ok(false), scval::to_bool)?,
+            trial_balance: fields.find("trial_balance").map_or(Ok(0), scval::to_i128)?,
+            // Nor those not totalling unsettled payments
+            pending: fields.find("pending").map_or(Ok(0), scval::to_i128)?,
+            pending_payments: fields
+                .find("pending_payments")
+                .map_or(Ok(0), scval::to_u32)?,
+        })
+    }
+}
+
+/// Payment record
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Payment {
+    pub escrow_id: u64,
+    pub amount: i128,
+    pub settled: bool,
+    pub timestamp: u64,
+    /// Worth of the amount in `quote_currency` when settled, in cents,
+    /// None while pending or if the contract's price feed had no price
+    pub quote_amount: Option<i128>,
+    /// Currency of `quote_amount` ("USD")
+    pub quote_currency: Option<String>,
+}
+
+impl TryFrom<&ScVal> for Payment {
+    type Error = Error;
+
+    fn try_from(value: &ScVal) -> Result<Self, Error> {
+        let fields = Fields::new(value)?;
+        Ok(Self {
+            escrow_id: scval::to_u64(fields.get("escrow_id")?)?,
+            amount: scval::to_i128(fields.get("amount")?)?,
+            settled: scval::to_bool(fields.get("settled")?)?,
+            timestamp: scval::to_u64(fields.get("timestamp")?)?,
+            // Contracts without quotes do not record them
+            quote_amount: fields
+                .find("quote_amount")
+                .map_or(Ok(None), |value| scval::to_option(value, scval::to_i128))?,
+            quote_currency: fields
+                .find("quote_currency")
+                .map_or(Ok(None), |value| scval::to_option(value, scval::to_symbol))?,
+        })
+    }
+}
+
+/// USD equivalent of the settled payments of an escrow, refunds not deducted
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QuoteTotal {
+    /// Currency of `quote_amount` ("USD")
+    pub currency: String,
+    /// Sum of the quotes of the settled payments, in cents
+    pub quote_amount: i128,
+    /// Sum of the amounts quoted, in stroops
+    pub quoted: i128,
+    /// Sum of the amounts settled without a quote, in stroops
+    pub unquoted: i128,
+}
+
+impl TryFrom<&ScVal> for QuoteTotal {
+    type Error = Error;
+
+    fn try_from(value: &ScVal) -> Result<Self, Error> {
+        let fields = Fields::new(value)?;
+        Ok(Self {
+            currency: scval::to_symbol(fields.get("currency")?)?,
+            quote_amount: scval::to_i128(fields.get("quote_amount")?)?,
+            quoted: scval::to_i128(fields.get("quoted")?)?,
+            unquoted: scval::to_i128(fields.get("unquoted")?)?,
+        })
+    }
+}
+
+/// Terms a server settles a payment on, signed along with the settlement
+///
+/// The contract refuses the settlement if the payment's escrow, amount or
+/// client differ, so a signed settlement cannot be replayed against
+/// another payment.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Settlement {
+    pub payment_id: u64,
+    pub escrow_id: u64,
+    pub amount: i128,
+    /// Client the payment is charged to (G... format)
+    pub client: String,
+}
+
+impl Settlement {
+    /// Encode the terms as the contract's `Settlement`
+    ///
+    /// # Errors
+    /// * `InvalidAddress` - If the client is not valid strkey
+    pub(crate) fn to_scval(&self) -> Result<ScVal, Error> {
+        Ok(bindings::settlement(
+            self.payment_id,
+            self.escrow_id,
+            self.amount,
+            scval::parse_address(&self.client)?,
+        ))
+    }
+}
+
+impl TryFrom<&ScVal> for Settlement {
+    type Error = Error;
+
+    fn try_from(value: &ScVal) -> Result<Self, Error> {
+        let fields = Fields::new(value)?;
+        Ok(Self {
+            payment_id: scval::to_u64(fields.get("payment_id")?)?,
+            escrow_id: scval::to_u64(fields.get("escrow_id")?)?,
+            amount: scval::to_i128(fields.get("amount")?)?,
+            client: scval::to_address(fields.get("client")?)?,
+        })
+    }
+}
+
+/// Which escrows `sweep_dust` may archive
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DustPolicy {
+    /// Balances below this are dust, in stroops
+    pub threshold: i128,
+    /// Seconds without activity after which an escrow is abandoned
+    pub max_idle: u64,
+}
+
+impl TryFrom<&ScVal> for DustPolicy {
+    type Error = Error;
+
+    fn try_from(value: &ScVal) -> Result<Self, Error> {
+        let fields = Fields::new(value)?;
+        Ok(Self {
+            threshold: scval::to_i128(fields.get("threshold")?)?,
+            max_idle: scval::to_u64(fields.get("max_idle")?)?,
+        })
+    }
+}
+
+/// Spending a client granted an agent over its escrows
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Allowance {
+    /// Total the agent may authorize, in stroops
+    pub total_cap: i128,
+    /// What is left of the total cap
+    pub remaining: i128,
+    /// Most the agent may authorize in one payment
+    pub per_payment_cap: i128,
+    /// Unix timestamp after which the allowance is void
+    pub expires_at: u64,
+}
+
+impl TryFrom<&ScVal> for Allowance {
+    type Error = Error;
+
+    fn try_from(value: &ScVal) -> Result<Self, Error> {
+        let fields = Fields::new(value)?;
+        Ok(Self {
+            total_cap: scval::to_i128(fields.get("total_cap")?)?,
+            remaining: scval::to_i128(fields.get("remaining")?)?,
+            per_payment_cap: scval::to_i128(fields.get("per_payment_cap")?)?,
+            expires_at: scval::to_u64(fields.get("expires_at")?)?,
+        })
+    }
+}
+
+/// Cap a client set on what the server settles from an escrow per hour
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SpendLimit {
+    /// Most settled per window, in stroops
+    pub max_settled_per_hour: i128,
+    /// Unix timestamp the current window started at
+    pub window_start: u64,
+    /// Settled in the current window
+    pub settled: i128,
+    /// Whether a settlement breached the limit, pausing settlements
+    pub paused: bool,
+}
+
+impl TryFrom<&ScVal> for SpendLimit {
+    type Error = Error;
+
+    fn try_from(value: &ScVal) -> Result<Self, Error> {
+        let fields = Fields::new(value)?;
+        Ok(Self {
+            max_settled_per_hour: scval::to_i128(fields.get("max_settled_per_hour")?)?,
+            window_start: scval::to_u64(fields.get("window_start")?)?,
+            settled: scval::to_i128(fields.get("settled")?)?,
+            paused: scval::to_bool(fields.get("paused")?)?,
+        })
+    }
+}
+
+impl SpendLimit {
+    /// Whether settling `amount` at ledger time `now` passes the limit, as
+    /// the contract checks it
+    ///
+    /// The window rolls over, resuming settlements, once
+    /// [`SPEND_WINDOW`](bindings::SPEND_WINDOW) seconds passed since it
+    /// started.
+    pub fn allows(&self, now: u64, amount: i128) -> bool {
+        if now >= self.window_start.saturating_add(bindings::SPEND_WINDOW) {
+            return amount <= self.max_settled_per_hour;
+        }
+        !self.paused && self.settled + amount <= self.max_settled_per_hour
+    }
+}
+
+/// Message a party left on an escrow
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Note {
+    /// Party who posted the note (G... format)
+    pub author: String,
+    /// Ledger the note was posted in
+    pub ledger: u32,
+    /// Unix timestamp the note was posted at
+    pub timestamp: u64,
+    pub body: Vec<u8>,
+}
+
+impl TryFrom<&ScVal> for Note {
+    type Error = Error;
+
+    fn try_from(value: &ScVal) -> Result<Self, Error> {
+        let fields = Fields::new(value)?;
+        Ok(Self {
+            author: scval::to_address(fields.get("author")?)?,
+            ledger: scval::to_u32(fields.get("ledger")?)?,
+            timestamp: scval::to_u64(fields.get("timestamp")?)?,
+            body: scval::to_bytes(fields.get("body")?)?,
+        })
+    }
+}
+
+/// Escrows split from one another, see [`EscrowClient::split_escrow`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EscrowLinks {
+    /// Escrow this one was split from, None if it was opened
+    pub parent: Option<u64>,
+    /// Escrows split from this one, oldest first
+    pub children: Vec<u64>,
+    /// Metadata the client attached when splitting this escrow from its
+    /// parent, empty if it was opened
+    pub metadata: Vec<u8>,
+}
+
+impl TryFrom<&ScVal> for EscrowLinks {
+    type Error = Error;
+
+    fn try_from(value: &ScVal) -> Result<Self, Error> {
+        let fields = Fields::new(value)?;
+        Ok(Self {
+            parent: scval::to_option(fields.get("parent")?, scval::to_u64)?,
+            children: scval::to_vec(fields.get("children")?)?
+                .iter()
+                .map(scval::to_u64)
+                .collect::<Result<_, _>>()?,
+            metadata: scval::to_bytes(fields.get("metadata")?)?,
+        })
+    }
+}
+
+/// Escrow as exported by the deployment it leaves
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MigrationSummary {
+    /// Contract the escrow was exported by (C... format)
+    pub source: String,
+    /// Contract the escrow moves to (C... format)
+    pub target: String,
+    /// Escrow ID at the source
+    pub escrow_id: u64,
+    pub escrow: Escrow,
+    /// Unsettled payments, oldest first
+    pub pending: Vec<Payment>,
+    /// Payments the escrow had at the source, settled or not
+    pub payment_count: u32,
+    /// Spend limit, with its current window
+    pub spend_limit: Option<SpendLimit>,
+    /// Escrows it was split from and into, by their IDs at the source
+    pub links: EscrowLinks,
+    pub notes: Vec<Note>,
+    /// Unix timestamp of the export
+    pub exported_at: u64,
+}
+
+impl TryFrom<&ScVal> for MigrationSummary {
+    type Error = Error;
+
+    fn try_from(value: &ScVal) -> Result<Self, Error> {
+        let fields = Fields::new(value)?;
+        Ok(Self {
+            source: scval::to_address(fields.get("source")?)?,
+            target: scval::to_address(fields.get("target")?)?,
+            escrow_id: scval::to_u64(fields.get("escrow_id")?)?,
+            escrow: Escrow::try_from(fields.get("escrow")?)?,
+            pending: scval::to_vec(fields.get("pending")?)?
+                .iter()
+                .map(Payment::try_from)
+                .collect::<Result<_, _>>()?,
+            payment_count: scval::to_u32(fields.get("payment_count")?)?,
+            spend_limit: scval::to_option(fields.get("spend_limit")?, |v| SpendLimit::try_from(v))?,
+            links: EscrowLinks::try_from(fields.get("links")?)?,
+            notes: scval::to_vec(fields.get("notes")?)?
+                .iter()
+                .map(Note::try_from)
+                .collect::<Result<_, _>>()?,
+            exported_at: scval::to_u64(fields.get("exported_at")?)?,
+        })
+    }
+}
+
+/// Export of an escrow frozen for migration
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MigrationExport {
+    pub summary: MigrationSummary,
+    /// SHA-256 of the summary's XDR
+    pub proof: [u8; 32],
+    /// Summary as encoded by the source, which the proof covers
+    pub encoded: ScVal,
+}
+
+impl TryFrom<&ScVal> for MigrationExport {
+    type Error = Error;
+
+    fn try_from(value: &ScVal) -> Result<Self, Error> {
+        let fields = Fields::new(value)?;
+        let encoded = fields.get("summary")?;
+        Ok(Self {
+            summary: MigrationSummary::try_from(encoded)?,
+            proof: scval::to_bytes32(fields.get("proof")?)?,
+            encoded: encoded.clone(),
+        })
+    }
+}
+
+/// Result of a confirmed contract invocation
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Submitted<T> {
+    /// Value returned by the contract function
+    pub value: T,
+    /// Hex-encoded transaction hash
+    pub hash: String,
+    /// Ledger the transaction was included in
+    pub ledger: u32,
+}
+
+impl<T> Submitted<T> {
+    fn map<U>(self, f: impl FnOnce(T) -> Result<U, Error>) -> Result<Submitted<U>, Error> {
+        Ok(Submitted {
+            value: f(self.value)?,
+            hash: self.hash,
+            ledger: self.ledger,
+        })
+    }
+}
+
+/// Signed transaction that has not been confirmed yet
+///
+/// A signed transaction consumes the source account sequence number it was
+/// built with, so submitting it again can never apply it twice. Persisting it
+/// before submission makes a call safely retryable across restarts.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PreparedTransaction {
+    /// Base64-encoded `TransactionEnvelope`
+    pub envelope: String,
+    /// Hex-encoded transaction hash
+    pub hash: String,
+}
+
+/// Whereabouts of a submitted transaction
+enum Located {
+    /// Included, successfully or not
+    Done(Disposition),
+    /// Cannot be included anymore
+    Dropped,
+    /// Not included yet, or not known to be
+    Pending,
+}
+
+/// Tunables for transaction submission
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    /// Inclusion fee per operation, in stroops
+    pub base_fee: u32,
+    /// How long to wait for a submitted transaction to be confirmed
+    pub timeout: Duration,
+    /// Delay between `getTransaction` polls
+    pub poll_interval: Duration,
+    /// How long to wait for the signer to produce a signature
+    pub sign_timeout: Duration,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            base_fee: 100,
+            timeout: Duration::from_secs(30),
+            poll_interval: Duration::from_secs(1),
+            sign_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Async client for the x402 escrow contract
+///
+/// Every state-changing call is built, simulated, signed by the configured
+/// [`Signer`], submitted, and polled until it is confirmed. The signer's
+/// account is the transaction source, so it must be the party whose
+/// authorization the contract function requires.
+///
+/// A client and its clones make one call at a time: each transaction takes
+/// the next sequence number of the signer's account, which concurrent calls
+/// would all take, every transaction but one being rejected.
+#[derive(Clone)]
+pub struct EscrowClient {
+    rpc: Rpc,
+    contract: Hash,
+    network_id: Hash,
+    signer: Arc<dyn Signer>,
+    options: ClientOptions,
+    fee_bump: Option<(Arc<dyn Signer>, FeeBumpPolicy)>,
+    correlation_id: Option<String>,
+    /// Held from preparing a call until its transaction is confirmed
+    sequence: Arc<Mutex<()>>,
+}
+
+impl EscrowClient {
+    /// Create a client for the contract at `contract_id`
+    ///
+    /// # Arguments
+    /// * `rpc` - Soroban RPC client
+    /// * `contract_id` - Escrow contract address (C... format)
+    /// * `network_passphrase` - Passphrase of the network the contract lives on
+    /// * `signer` - Signer of submitted transactions
+    ///
+    /// # Errors
+    /// * `InvalidAddress` - If `contract_id` is not a contract strkey
+    pub fn new(
+        rpc: Rpc,
+        contract_id: &str,
+        network_passphrase: &str,
+        signer: impl Signer + 'static,
+    ) -> Result<Self, Error> {
+        let contract = match Strkey::from_string(contract_id) {
+            Ok(Strkey::Contract(contract)) => Hash(contract.0),
+            _ => return Err(Error::InvalidAddress(contract_id.to_string())),
+        };
+        Ok(Self {
+            rpc,
+            contract,
+            network_id: Hash(Sha256::digest(network_passphrase.as_bytes()).into()),
+            signer: Arc::new(signer),
+            options: ClientOptions::default(),
+            fee_bump: None,
+            correlation_id: None,
+            sequence: Arc::default(),
+        })
+    }
+
+    /// Client of another deployment of the contract, with the same RPC,
+    /// signer, and options
+    ///
+    /// # Errors
+    /// * `InvalidAddress` - If `contract_id` is not a contract strkey
+    pub fn for_contract(&self, contract_id: &str) -> Result<Self, Error> {
+        let contract = match Strkey::from_string(contract_id) {
+            Ok(Strkey::Contract(contract)) => Hash(contract.0),
+            _ => return Err(Error::InvalidAddress(contract_id.to_string())),
+        };
+        Ok(Self {
+            contract,
+            ..self.clone()
+        })
+    }
+
+    /// Replace the submission options
+    pub fn with_options(mut self, options: ClientOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Fee-bump transactions stuck pending, as described by [`FeeBumpPolicy`]
+    ///
+    /// # Arguments
+    /// * `fee_account` - Signer of the account paying the bumped fees
+    /// * `policy` - When and by how much fees are bumped
+    pub fn with_fee_bump(
+        mut self,
+        fee_account: impl Signer + 'static,
+        policy: FeeBumpPolicy,
+    ) -> Self {
+        self.fee_bump = Some((Arc::new(fee_account), policy));
+        self
+    }
+
+    /// Tag the transactions of this client with `correlation_id`
+    ///
+    /// The ID is the text memo of every transaction the client signs, and a
+    /// field of its submission spans, so on-chain records link back to the
+    /// traces of the payment, see [`x402_types::correlation_id`].
+    ///
+    /// # Arguments
+    /// * `correlation_id` - At most 28 bytes, the length of a text memo
+    pub fn with_correlation_id(mut self, correlation_id: impl Into<String>) -> Self {
+        self.correlation_id = Some(correlation_id.into());
+        self
+    }
+
+    /// Correlation ID the transactions of this client are tagged with
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.correlation_id.as_deref()
+    }
+
+    /// Underlying RPC client
+    pub fn rpc(&self) -> &Rpc {
+        &self.rpc
+    }
+
+    /// Escrow contract address (C... format)
+    pub fn contract_id(&self) -> String {
+        stellar_strkey::Contract(self.contract.0).to_string()
+    }
+
+    /// SHA-256 of the network passphrase, which transactions are signed for
+    pub fn network_id(&self) -> [u8; 32] {
+        self.network_id.0
+    }
+
+    /// `G...` address of the signing account
+    pub fn address(&self) -> String {
+        ed25519::PublicKey(self.signer.public_key()).to_string()
+    }
+
+    /// Check that the signer is available and signs with its signing key
+    ///
+    /// A fixed probe hash is signed, within the sign timeout, and the
+    /// signature verified against [`Signer::signing_key`]. Nothing signed
+    /// this way is valid as a transaction or an authorization.
+    ///
+    /// # Errors
+    /// * `Signer` - If the signer fails, times out, or signs with another key
+    pub async fn check_signer(&self) -> Result<(), Error> {
+        let probe: [u8; 32] = Sha256::digest(SIGNER_PROBE).into();
+        let signature = self.sign_hash(self.signer.as_ref(), &probe).await?;
+        let key = ed25519_dalek::VerifyingKey::from_bytes(&self.signer.signing_key())
+            .map_err(|e| Error::Signer(e.to_string()))?;
+        key.verify_strict(&probe, &ed25519_dalek::Signature::from_bytes(&signature))
+            .map_err(|_| Error::Signer("signature does not match the signing key".into()))
+    }
+
+    /// Make the signer the contract admin, once, right after deployment
+    ///
+    /// # Errors
+    /// * `Contract(AlreadyInitialized)` - If the contract already has an admin
+    pub async fn initialize(&self) -> Result<Submitted<()>, Error> {
+        let admin = scval::parse_address(&self.address())?;
+        self.invoke(bindings::initialize(admin))
+            .await?
+            .map(|_| Ok(()))
+    }
+
+    /// Get the version of the contract interface
+    pub async fn version(&self) -> Result<u32, Error> {
+        scval::to_u32(&self.read(bindings::version()).await?)
+    }
+
+    /// Open an escrow for a client-server pair (signer must be the client)
+    ///
+    /// # Arguments
+    /// * `client` - Client address (G... format)
+    /// * `server` - Server address (G... format)
+    /// * `amount` - Initial deposit, in stroops
+    /// * `terms_hash` - Hash of the server's terms of service the client
+    ///   accepts, see [`Self::get_terms`], None if it published none
+    ///
+    /// # Errors
+    /// * `Contract(TermsMismatch)` - If `terms_hash` is not the hash the
+    ///   server currently publishes
+    pub async fn open_escrow(
+        &self,
+        client: &str,
+        server: &str,
+        amount: i128,
+        terms_hash: Option<[u8; 32]>,
+    ) -> Result<Submitted<u64>, Error> {
+        let call = bindings::open_escrow(
+            scval::parse_address(client)?,
+            scval::parse_address(server)?,
+            amount,
+            terms_hash,
+        );
+        self.invoke(call).await?.map(|v| scval::to_u64(&v))
+    }
+
+    /// Open an escrow with another server, configured as an existing
+    /// escrow of the client (signer must be the client)
+    ///
+    /// The new escrow accepts the terms of service the source escrow
+    /// accepted, and holds `amount` rather than the source's balance.
+    ///
+    /// # Errors
+    /// * `Contract(TermsMismatch)` - If `new_server` does not publish the
+    ///   terms the source escrow accepted
+    pub async fn clone_escrow_config(
+        &self,
+        source_escrow_id: u64,
+        new_server: &str,
+        amount: i128,
+    ) -> Result<Submitted<u64>, Error> {
+        let call = bindings::clone_escrow_config(
+            source_escrow_id,
+            scval::parse_address(new_server)?,
+            amount,
+        );
+        self.invoke(call)
+            .await
+            .map_err(|e| e.with_escrow(source_escrow_id))?
+            .map(|v| scval::to_u64(&v))
+    }
+
+    /// Open an escrow for a client, funded by the server as a trial (signer
+    /// must be the server)
+    ///
+    /// Settlements spend the trial funds before the client's deposits, and
+    /// the trial funds left when the escrow closes return to the server.
+    /// The escrow accepts the terms of service the server publishes. Until
+    /// the client's first deposit, [`Self::find_escrow`] does not find it.
+    ///
+    /// # Arguments
+    /// * `client` - Client address (G... format)
+    /// * `trial_amount` - Trial deposit, in stroops
+    ///
+    /// # Errors
+    /// * `Contract(InvalidTrial)` - If `trial_amount` is not positive
+    pub async fn open_sponsored_escrow(
+        &self,
+        client: &str,
+        trial_amount: i128,
+    ) -> Result<Submitted<u64>, Error> {
+        let call = bindings::open_sponsored_escrow(
+            scval::parse_address(&self.address())?,
+            scval::parse_address(client)?,
+            trial_amount,
+        );
+        self.invoke(call).await?.map(|v| scval::to_u64(&v))
+    }
+
+    /// Move part of an escrow's balance to a new escrow between the same
+    /// parties (signer must be the client, and the server while payments
+    /// are pending)
+    ///
+    /// The new escrow accepts the same terms of service and spend limit,
+    /// and is linked to its parent, see [`Self::get_escrow_links`]. Pending
+    /// payments and trial funds stay with the parent, which keeps enough
+    /// balance to cover them. [`Self::find_escrow`] keeps returning the
+    /// parent.
+    ///
+    /// # Arguments
+    /// * `amount` - Balance moved to the new escrow, in stroops
+    /// * `metadata` - What the new escrow is for, at most
+    ///   [`MAX_SPLIT_METADATA_LEN`](bindings::MAX_SPLIT_METADATA_LEN) bytes
+    ///
+    /// # Errors
+    /// * `Contract(InvalidSplit)` - If `amount` is not positive or exceeds
+    ///   what the parent can spare, or `metadata` is too long
+    pub async fn split_escrow(
+        &self,
+        escrow_id: u64,
+        amount: i128,
+        metadata: &[u8],
+    ) -> Result<Submitted<u64>, Error> {
+        let call = bindings::split_escrow(escrow_id, amount, metadata)?;
+        self.invoke(call)
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?
+            .map(|v| scval::to_u64(&v))
+    }
+
+    /// Deposit additional funds (signer must be the client)
+    ///
+    /// # Errors
+    /// * `Contract(InvalidAmount)` - If `amount` is not positive
+    pub async fn deposit(&self, escrow_id: u64, amount: i128) -> Result<Submitted<()>, Error> {
+        self.invoke(bindings::deposit(escrow_id, amount))
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?
+            .map(|_| Ok(()))
+    }
+
+    /// Credit an escrow with a plain token transfer to its server (signer
+    /// must be the server)
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow the transfer is for
+    /// * `from` - Account the transfer came from (G... format)
+    /// * `amount` - Amount transferred, in stroops
+    /// * `memo_hash` - Hash identifying the transfer, e.g. its transaction hash
+    ///
+    /// # Errors
+    /// * `Contract(InvalidAmount)` - If `amount` is not positive
+    /// * `Contract(DepositAlreadyClaimed)` - If the transfer has already
+    ///   been credited
+    pub async fn claim_pending_deposit(
+        &self,
+        escrow_id: u64,
+        from: &str,
+        amount: i128,
+        memo_hash: [u8; 32],
+    ) -> Result<Submitted<()>, Error> {
+        let call = bindings::claim_pending_deposit(
+            escrow_id,
+            scval::parse_address(from)?,
+            amount,
+            memo_hash,
+        );
+        self.invoke(call)
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?
+            .map(|_| Ok(()))
+    }
+
+    /// Pay a server directly, without an escrow (signer must be the client)
+    ///
+    /// The amount is transferred to the server at once and recorded as a
+    /// settled payment of no escrow,
+    /// [`DIRECT_PAYMENT_ESCROW`](bindings::DIRECT_PAYMENT_ESCROW), listed
+    /// by the server's [`Self::direct_payments`].
+    ///
+    /// # Arguments
+    /// * `server` - Account paid (G... format)
+    /// * `token` - Token contract transferred (C... format)
+    /// * `amount` - Amount transferred, in stroops
+    /// * `reference` - Unique per payment of the signer, e.g. the hash of
+    ///   what it pays for
+    ///
+    /// # Returns
+    /// * ID of the payment recorded
+    ///
+    /// # Errors
+    /// * `Contract(InvalidDirectPayment)` - If `amount` is not positive, or
+    ///   the signer already recorded `reference`
+    pub async fn record_direct_payment(
+        &self,
+        server: &str,
+        token: &str,
+        amount: i128,
+        reference: [u8; 32],
+    ) -> Result<Submitted<u64>, Error> {
+        let call = bindings::record_direct_payment(
+            scval::parse_address(&self.address())?,
+            scval::parse_address(server)?,
+            scval::parse_address(token)?,
+            amount,
+            reference,
+        );
+        self.invoke(call).await?.map(|v| scval::to_u64(&v))
+    }
+
+    /// Create a payment against an escrow (signer must be the server)
+    pub async fn create_payment(
+        &self,
+        escrow_id: u64,
+        amount: i128,
+    ) -> Result<Submitted<u64>, Error> {
+        self.invoke(bindings::create_payment(escrow_id, amount))
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?
+            .map(|v| scval::to_u64(&v))
+    }
+
+    /// Create a batch of payments atomically (signer must be their server)
+    ///
+    /// # Arguments
+    /// * `intents` - Escrow and amount of each payment
+    ///
+    /// # Returns
+    /// * Payment IDs, in batch order
+    pub async fn create_payments(
+        &self,
+        intents: &[(u64, i128)],
+    ) -> Result<Submitted<Vec<u64>>, Error> {
+        let intents = intents
+            .iter()
+            .map(|&(escrow_id, amount)| bindings::payment_intent(escrow_id, amount))
+            .collect();
+        self.invoke(bindings::create_payments(intents)?)
+            .await?
+            .map(|v| scval::to_vec(&v)?.iter().map(scval::to_u64).collect())
+    }
+
+    /// Settle a batch of payments atomically (signer must be their server)
+    ///
+    /// # Returns
+    /// * Total amount settled
+    pub async fn settle_payments(&self, payment_ids: &[u64]) -> Result<Submitted<i128>, Error> {
+        self.invoke(self.settle_payments_call(payment_ids).await?)
+            .await?
+            .map(|v| scval::to_i128(&v))
+    }
+
+    /// Settle payments on terms this client created or read on-chain
+    /// itself (signer must be their server)
+    pub(crate) async fn settle_terms(
+        &self,
+        settlements: &[Settlement],
+    ) -> Result<Submitted<i128>, Error> {
+        let settlements = settlements
+            .iter()
+            .map(Settlement::to_scval)
+            .collect::<Result<_, _>>()?;
+        self.invoke(bindings::settle_payments(settlements)?)
+            .await?
+            .map(|v| scval::to_i128(&v))
+    }
+
+    /// Terms of a payment as this client reads them on-chain
+    ///
+    /// Settlements are built from these rather than from terms supplied
+    /// by whoever asks for the settlement, so the server only ever signs
+    /// what its own RPC reports.
+    ///
+    /// # Errors
+    /// * `Contract(PaymentNotFound)` - If the payment does not exist
+    /// * `Contract(EscrowNotFound)` - If its escrow was removed
+    pub async fn settlement(&self, payment_id: u64) -> Result<Settlement, Error> {
+        let payment = self.get_payment(payment_id).await?;
+        let escrow = self
+            .get_escrow(payment.escrow_id)
+            .await
+            .map_err(|e| e.with_payment(payment_id))?;
+        Ok(Settlement {
+            payment_id,
+            escrow_id: payment.escrow_id,
+            amount: payment.amount,
+            client: escrow.client,
+        })
+    }
+
+    /// `settle_payment` call on the terms read for the payment
+    async fn settle_payment_call(&self, payment_id: u64) -> Result<Invocation, Error> {
+        let settlement = self.settlement(payment_id).await?;
+        Ok(bindings::settle_payment(settlement.to_scval()?))
+    }
+
+    /// `settle_payments` call on the terms read for each payment
+    async fn settle_payments_call(&self, payment_ids: &[u64]) -> Result<Invocation, Error> {
+        let mut settlements = Vec::with_capacity(payment_ids.len());
+        for &payment_id in payment_ids {
+            settlements.push(self.settlement(payment_id).await?.to_scval()?);
+        }
+        Ok(bindings::settle_payments(settlements)?)
+    }
+
+    /// Create a payment from an authorization signed by the escrow's client
+    /// or by one of its agents (signer must be the server)
+    ///
+    /// The contract checks the signature, spends the nonce, and charges
+    /// agent payments to the agent's allowance.
+    ///
+    /// # Arguments
+    /// * `payload` - Signed authorization, see [`Self::authorize_payment`]
+    /// * `network` - x402 network id the authorization was signed for
+    ///
+    /// # Errors
+    /// * `InvalidAddress` - If the payload's client is not an account
+    /// * `InvalidAuthorization` - If its amount or signature is malformed
+    /// * `Contract(AuthorizationUsed)` - If the nonce already paid
+    /// * `Contract(AllowanceExceeded)` - If an agent's allowance does not
+    ///   cover the amount
+    pub async fn create_authorized_payment(
+        &self,
+        payload: &EscrowPayload,
+        network: &str,
+    ) -> Result<Submitted<u64>, Error> {
+        let (authorization, public_key, signature) = authorization_args(payload, network)?;
+        self.invoke(bindings::create_authorized_payment(
+            authorization,
+            public_key,
+            signature,
+        ))
+        .await
+        .map_err(|e| e.with_escrow(payload.escrow_id))?
+        .map(|v| scval::to_u64(&v))
+    }
+
+    /// Open an escrow and create its first payment from an authorization
+    /// signed by the client, in one transaction (signer must be the client)
+    ///
+    /// The payload must be signed for the escrow ID the contract will
+    /// assign, which a [dry run](Self::dry_run) of `open_escrow` answers.
+    /// If the authorization is rejected, the escrow is not opened either.
+    ///
+    /// # Arguments
+    /// * `server` - Server address (G... format)
+    /// * `amount` - Initial deposit, in stroops
+    /// * `terms_hash` - Hash of the server's terms of service the client
+    ///   accepts, see [`Self::get_terms`], None if it published none
+    /// * `payload` - Signed authorization, see [`Self::authorize_payment`]
+    /// * `network` - x402 network id the authorization was signed for
+    ///
+    /// # Returns
+    /// * ID of the payment created, which the server settles as is
+    ///
+    /// # Errors
+    /// * `InvalidAuthorization` - If the payload's amount or signature is
+    ///   malformed
+    /// * `Contract(AuthorizationMismatch)` - If the payload is for another
+    ///   escrow ID, e.g. because another escrow of the client's shard was
+    ///   opened since the dry run
+    /// * `Contract(EscrowAlreadyExists)` - If the client already has an
+    ///   escrow with `server`
+    pub async fn open_and_authorize(
+        &self,
+        server: &str,
+        amount: i128,
+        terms_hash: Option<[u8; 32]>,
+        payload: &EscrowPayload,
+        network: &str,
+    ) -> Result<Submitted<u64>, Error> {
+        let (authorization, public_key, signature) = authorization_args(payload, network)?;
+        let call = bindings::open_and_authorize(
+            scval::parse_address(&payload.client)?,
+            scval::parse_address(server)?,
+            amount,
+            terms_hash,
+            authorization,
+            public_key,
+            signature,
+        );
+        self.invoke(call).await?.map(|v| {
+            let fields = Fields::new(&v)?;
+            scval::to_u64(fields.get("payment_id")?)
+        })
+    }
+
+    /// Get the payment `open_and_authorize` created from an escrow's
+    /// authorization, None if its nonce paid no such payment
+    pub async fn get_preauthorized_payment(
+        &self,
+        escrow_id: u64,
+        nonce: u64,
+    ) -> Result<Option<u64>, Error> {
+        let value = self
+            .read(bindings::get_preauthorized_payment(escrow_id, nonce))
+            .await?;
+        scval::to_option(&value, scval::to_u64)
+    }
+
+    /// Build and sign a `create_payment` transaction without submitting it
+    pub async fn prepare_create_payment(
+        &self,
+        escrow_id: u64,
+        amount: i128,
+    ) -> Result<PreparedTransaction, Error> {
+        self.prepare(bindings::create_payment(escrow_id, amount))
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))
+    }
+
+    /// Build and sign a `settle_payment` transaction without submitting it
+    pub async fn prepare_settle_payment(
+        &self,
+        payment_id: u64,
+    ) -> Result<PreparedTransaction, Error> {
+        let call = self.settle_payment_call(payment_id).await?;
+        self.prepare(call)
+            .await
+            .map_err(|e| e.with_payment(payment_id))
+    }
+
+    /// Build and sign a `settle_payments` transaction without submitting it
+    pub async fn prepare_settle_payments(
+        &self,
+        payment_ids: &[u64],
+    ) -> Result<PreparedTransaction, Error> {
+        let call = self.settle_payments_call(payment_ids).await?;
+        self.prepare(call).await
+    }
+
+    /// Submit a prepared transaction and wait for its confirmation
+    ///
+    /// A transaction already pending is not an error, its confirmation is
+    /// awaited.
+    ///
+    /// # Errors
+    /// * `Rejected` - If the transaction was not accepted, e.g. because its
+    ///   sequence number was consumed
+    /// * `TransactionFailed` - If it was included but failed
+    pub async fn submit(&self, tx: &PreparedTransaction) -> Result<Submitted<ScVal>, Error> {
+        self.submit_with(tx, |_| true).await
+    }
+
+    /// Submit a signed transaction, fee-bumping it while it is stuck pending
+    ///
+    /// With [`Self::with_fee_bump`], a transaction pending for longer than
+    /// the policy allows is wrapped in a fee-bump transaction signed by the
+    /// fee account, itself replaced by higher bumps while it stays pending.
+    /// The original and its bumps share a sequence number, so whichever
+    /// lands first is returned and the others are rejected; a bump rejected
+    /// because the original landed meanwhile is ignored.
+    ///
+    /// # Arguments
+    /// * `tx` - Transaction to submit, possibly a fee bump of a previous call
+    /// * `on_bump` - Called with each bump before it is sent, e.g. to
+    ///   persist it in place of `tx`; returning false holds the bump back
+    ///
+    /// # Errors
+    /// * `Rejected` - If the transaction was not accepted, e.g. because its
+    ///   sequence number was consumed
+    /// * `TransactionFailed` - If it was included but failed
+    /// * `Timeout` - If none of the transactions was confirmed in time
+    #[tracing::instrument(
+        name = "escrow.submit",
+        skip_all,
+        fields(hash = %tx.hash, correlation_id = self.correlation_id())
+    )]
+    pub async fn submit_with(
+        &self,
+        tx: &PreparedTransaction,
+        on_bump: impl FnMut(&PreparedTransaction) -> bool,
+    ) -> Result<Submitted<ScVal>, Error> {
+        let submitted = self.send_with(tx, on_bump).await;
+        match &submitted {
+            Ok(included) => {
+                tracing::info!(hash = %included.hash, ledger = included.ledger, "transaction included")
+            }
+            Err(e) => tracing::warn!(error = %e, "transaction not included"),
+        }
+        submitted
+    }
+
+    async fn send_with(
+        &self,
+        tx: &PreparedTransaction,
+        mut on_bump: impl FnMut(&PreparedTransaction) -> bool,
+    ) -> Result<Submitted<ScVal>, Error> {
+        let envelope = TransactionEnvelope::from_xdr_base64(&tx.envelope, Limits::none())?;
+        let sent = self.rpc.send_transaction(&envelope).await?;
+        match sent.status.as_str() {
+            "PENDING" | "DUPLICATE" => {}
+            _ => {
+                return Err(Error::Rejected {
+                    hash: sent.hash,
+                    status: sent.status,
+                })
+            }
+        }
+
+        let Some((_, policy)) = &self.fee_bump else {
+            return self.wait_for(&sent.hash).await;
+        };
+        let deadline = tokio::time::Instant::now() + self.options.timeout;
+        let mut bump_at = Some(tokio::time::Instant::now() + policy.after);
+        let mut hashes = self.hashes(tx)?;
+        let mut latest = tx.clone();
+        loop {
+            for hash in &hashes {
+                if let Some(submitted) = self.transaction(hash).await? {
+                    return Ok(submitted);
+                }
+            }
+
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return Err(Error::Timeout {
+                    hash: latest.hash,
+                    timeout: self.options.timeout,
+                });
+            }
+            if bump_at.is_some_and(|at| now >= at) {
+                // Stops bumping once the maximum fee is reached
+                bump_at = match self.fee_bump(&latest).await? {
+                    Some(bump) => {
+                        if on_bump(&bump) && self.send_bump(&bump).await? {
+                            hashes.push(bump.hash.clone());
+                            latest = bump;
+                        }
+                        Some(now + policy.after)
+                    }
+                    None => None,
+                };
+            }
+            tokio::time::sleep(self.options.poll_interval).await;
+        }
+    }
+
+    /// Send a fee bump, ignoring its rejection
+    ///
+    /// # Returns
+    /// * Whether the bump may be pending, which it is not when rejected,
+    ///   e.g. because the original landed meanwhile
+    async fn send_bump(&self, bump: &PreparedTransaction) -> Result<bool, Error> {
+        let envelope = TransactionEnvelope::from_xdr_base64(&bump.envelope, Limits::none())?;
+        match self.rpc.send_transaction(&envelope).await {
+            Ok(sent) => Ok(matches!(sent.status.as_str(), "PENDING" | "DUPLICATE")),
+            // Lost responses leave the bump possibly pending
+            Err(e) if e.is_transient() => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Wrap a transaction in a fee bump paying the next fee of the policy
+    ///
+    /// # Returns
+    /// * None if fee bumping is disabled or the maximum fee is reached
+    async fn fee_bump(
+        &self,
+        tx: &PreparedTransaction,
+    ) -> Result<Option<PreparedTransaction>, Error> {
+        let Some((fee_account, policy)) = &self.fee_bump else {
+            return Ok(None);
+        };
+        let envelope = TransactionEnvelope::from_xdr_base64(&tx.envelope, Limits::none())?;
+        let bumped_fee = match &envelope {
+            TransactionEnvelope::TxFeeBump(bump) => Some(bump.tx.fee),
+            _ => None,
+        };
+        let inner = inner_envelope(envelope)?;
+        let fee = bumped_fee.unwrap_or(inner.tx.fee.into());
+        let resource_fee = match &inner.tx.ext {
+            TransactionExt::V1(data) => data.resource_fee,
+            TransactionExt::V0 => 0,
+        };
+        let operations = inner.tx.operations.len() as i64;
+        let Some(fee) = policy.next_fee(fee, resource_fee, operations, bumped_fee.is_some()) else {
+            return Ok(None);
+        };
+
+        let bump = FeeBumpTransaction {
+            fee_source: MuxedAccount::Ed25519(Uint256(fee_account.public_key())),
+            fee,
+            inner_tx: FeeBumpTransactionInnerTx::Tx(inner),
+            ext: FeeBumpTransactionExt::V0,
+        };
+        let hash = self.payload_hash(TransactionSignaturePayloadTaggedTransaction::TxFeeBump(
+            bump.clone(),
+        ))?;
+        let signature = self.sign_hash(fee_account.as_ref(), &hash).await?;
+        let envelope = TransactionEnvelope::TxFeeBump(FeeBumpTransactionEnvelope {
+            tx: bump,
+            signatures: vec![decorated(fee_account.signing_key(), signature)?].try_into()?,
+        });
+        Ok(Some(PreparedTransaction {
+            envelope: envelope.to_xdr_base64(Limits::none())?,
+            hash: hex::encode(hash),
+        }))
+    }
+
+    /// Hashes a transaction may apply under: its own, preceded for a fee
+    /// bump by that of the transaction it wraps
+    fn hashes(&self, tx: &PreparedTransaction) -> Result<Vec<String>, Error> {
+        let envelope = TransactionEnvelope::from_xdr_base64(&tx.envelope, Limits::none())?;
+        let mut hashes = vec![];
+        if matches!(envelope, TransactionEnvelope::TxFeeBump(_)) {
+            hashes.push(hex::encode(self.hash(&inner_envelope(envelope)?.tx)?));
+        }
+        hashes.push(tx.hash.clone());
+        Ok(hashes)
+    }
+
+    /// Submit a call at most once, however its submissions turn out
+    ///
+    /// The signed transaction is recorded in `log` under `key` before it is
+    /// first sent. When a submission times out or its response is lost, the
+    /// transaction is looked up before being resubmitted, and a new one is
+    /// signed with a fresh sequence number only once the recorded one
+    /// provably cannot apply: it is unknown to the RPC server while its
+    /// sequence number was consumed. Calling again with the same key returns
+    /// the outcome of the recorded transaction instead of signing another.
+    ///
+    /// # Arguments
+    /// * `key` - Idempotency key of the call
+    /// * `log` - Where the transaction is recorded before it is sent
+    /// * `prepare` - Signs the call, e.g. [`Self::prepare_settle_payment`]
+    ///
+    /// # Returns
+    /// * The [`Disposition`] of the last transaction submitted
+    ///
+    /// # Errors
+    /// * If the call cannot be prepared, is rejected for a reason other than
+    ///   its sequence number, or the RPC server answers unexpectedly
+    /// * The last submission error if the attempts ran out right after the
+    ///   transaction was provably dropped, in which case nothing applied
+    pub async fn submit_idempotent<F, Fut>(
+        &self,
+        key: &str,
+        log: &dyn SubmissionLog,
+        mut prepare: F,
+    ) -> Result<Disposition, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<PreparedTransaction, Error>>,
+    {
+        let (mut tx, mut fresh) = match log.get(key) {
+            Some(tx) => (tx, false),
+            None => {
+                let tx = prepare().await?;
+                log.record(key, &tx);
+                (tx, true)
+            }
+        };
+
+        let mut dropped = None;
+        for attempt in 0..SUBMIT_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(self.options.poll_interval).await;
+            }
+            if dropped.take().is_some() {
+                // Provably not included, so a new transaction cannot duplicate it
+                tx = prepare().await?;
+                log.record(key, &tx);
+                fresh = true;
+            }
+            if !fresh {
+                // It may have applied during a previous attempt or call
+                if let Located::Done(disposition) = self.locate(&tx).await? {
+                    return Ok(disposition);
+                }
+            }
+            fresh = false;
+
+            // Bumps replace the transaction in the log, since they may apply
+            // instead of it
+            let mut bumped = None;
+            let submitted = self
+                .submit_with(&tx, |bump| {
+                    log.record(key, bump);
+                    bumped = Some(bump.clone());
+                    true
+                })
+                .await;
+            if let Some(bump) = bumped {
+                tx = bump;
+            }
+            let error = match submitted {
+                Ok(submitted) => return Ok(Disposition::Included(submitted)),
+                Err(Error::TransactionFailed { hash }) => return Ok(Disposition::Failed { hash }),
+                Err(e @ Error::Rejected { .. }) => e,
+                // The outcome is ambiguous, so the transaction may be pending
+                Err(e) if e.is_transient() || matches!(e, Error::Timeout { .. }) => e,
+                Err(e) => return Err(e),
+            };
+            match self.locate(&tx).await? {
+                Located::Done(disposition) => return Ok(disposition),
+                Located::Dropped => dropped = Some(error),
+                Located::Pending if matches!(error, Error::Rejected { .. }) => return Err(error),
+                Located::Pending => {}
+            }
+        }
+        match dropped {
+            // Nothing applied, the call can be retried
+            Some(error) => Err(error),
+            None => Ok(Disposition::Unknown { hash: tx.hash }),
+        }
+    }
+
+    /// Find out whether a transaction applied, or provably cannot apply
+    /// because it is unknown while its sequence number was consumed
+    async fn locate(&self, tx: &PreparedTransaction) -> Result<Located, Error> {
+        let envelope = TransactionEnvelope::from_xdr_base64(&tx.envelope, Limits::none())?;
+        let TransactionV1Envelope { tx: signed, .. } = inner_envelope(envelope)?;
+        let MuxedAccount::Ed25519(Uint256(source)) = signed.source_account else {
+            return Err(Error::InvalidResponse("expected an ed25519 source".into()));
+        };
+
+        // Read the account first, so a transaction applied after it was
+        // read shows up in the lookup
+        let consumed = match self.rpc.get_account(&source).await {
+            Ok(account) => account.seq_num.0 >= signed.seq_num.0,
+            Err(e) if e.is_transient() => false,
+            Err(e) => return Err(e),
+        };
+        match self.lookup(tx).await {
+            Ok(Some(submitted)) => Ok(Located::Done(Disposition::Included(submitted))),
+            Err(Error::TransactionFailed { hash }) => {
+                Ok(Located::Done(Disposition::Failed { hash }))
+            }
+            Ok(None) if consumed => Ok(Located::Dropped),
+            Ok(None) => Ok(Located::Pending),
+            Err(e) if e.is_transient() => Ok(Located::Pending),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Look up the outcome of a prepared transaction, or of the transaction
+    /// a fee bump wraps, which applies instead when it lands first
+    ///
+    /// # Returns
+    /// * None if the RPC server knows none of them, or they are not
+    ///   confirmed yet
+    ///
+    /// # Errors
+    /// * `TransactionFailed` - If one was included but failed
+    pub async fn lookup(
+        &self,
+        tx: &PreparedTransaction,
+    ) -> Result<Option<Submitted<ScVal>>, Error> {
+        for hash in self.hashes(tx)? {
+            if let Some(submitted) = self.transaction(&hash).await? {
+                return Ok(Some(submitted));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Look up the outcome of a submitted transaction
+    ///
+    /// # Returns
+    /// * None if the RPC server does not know the transaction, or it is not
+    ///   confirmed yet
+    ///
+    /// # Errors
+    /// * `TransactionFailed` - If it was included but failed
+    pub async fn transaction(&self, hash: &str) -> Result<Option<Submitted<ScVal>>, Error> {
+        let response = self.rpc.get_transaction(hash).await?;
+        match response.status.as_str() {
+            "SUCCESS" => {
+                let meta = response
+                    .result_meta_xdr
+                    .ok_or_else(|| Error::InvalidResponse("missing resultMetaXdr".into()))?;
+                Ok(Some(Submitted {
+                    value: return_value(&meta)?,
+                    hash: hash.to_string(),
+                    ledger: response.ledger.unwrap_or(response.latest_ledger),
+                }))
+            }
+            "FAILED" => Err(Error::TransactionFailed {
+                hash: hash.to_string(),
+            }),
+            _ => Ok(None),
+        }
+    }
+
+    /// Settle a payment (signer must be the server)
+    ///
+    /// The server signs the payment's terms as it reads them, see
+    /// [`Self::settlement`].
+    ///
+    /// # Errors
+    /// * `Contract(SettlementsPaused)` - If settlements of the escrow are
+    ///   paused, or this one breached its spend limit and paused them
+    pub async fn settle_payment(&self, payment_id: u64) -> Result<Submitted<bool>, Error> {
+        let settled = self
+            .invoke(self.settle_payment_call(payment_id).await?)
+            .await
+            .map_err(|e| e.with_payment(payment_id))?;
+        // The contract records a breach of the spend limit rather than
+        // failing, so the pause sticks
+        if settled.value == ScVal::Bool(false) {
+            return Err(Error::settlements_paused(payment_id));
+        }
+        settled.map(|v| scval::to_bool(&v))
+    }
+
+    /// Refund `amount` of a settled payment into its escrow (signer must be
+    /// the server)
+    ///
+    /// # Returns
+    /// * Total refunded for the payment, this refund included
+    pub async fn refund_payment(
+        &self,
+        payment_id: u64,
+        amount: i128,
+    ) -> Result<Submitted<i128>, Error> {
+        self.invoke(bindings::refund_payment(payment_id, amount))
+            .await
+            .map_err(|e| e.with_payment(payment_id))?
+            .map(|v| scval::to_i128(&v))
+    }
+
+    /// Mark the escrow closed by the client (signer must be the client)
+    pub async fn client_close_escrow(
+        &self,
+        escrow_id: u64,
+    ) -> Result<Submitted<Option<i128>>, Error> {
+        self.invoke(bindings::client_close_escrow(escrow_id))
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?
+            .map(|v| scval::to_option(&v, scval::to_i128))
+    }
+
+    /// Mark the escrow closed by the server (signer must be the server)
+    pub async fn server_close_escrow(
+        &self,
+        escrow_id: u64,
+    ) -> Result<Submitted<Option<i128>>, Error> {
+        self.invoke(bindings::server_close_escrow(escrow_id))
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?
+            .map(|v| scval::to_option(&v, scval::to_i128))
+    }
+
+    /// Get escrow details
+    pub async fn get_escrow(&self, escrow_id: u64) -> Result<Escrow, Error> {
+        let value = self
+            .read(bindings::get_escrow(escrow_id))
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?;
+        Escrow::try_from(&value)
+    }
+
+    /// Get escrow balance, in stroops
+    pub async fn get_escrow_balance(&self, escrow_id: u64) -> Result<i128, Error> {
+        let value = self
+            .read(bindings::get_escrow_balance(escrow_id))
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?;
+        scval::to_i128(&value)
+    }
+
+    /// Escrows an escrow was split from or into, empty if it was never
+    /// split
+    pub async fn get_escrow_links(&self, escrow_id: u64) -> Result<EscrowLinks, Error> {
+        let value = self.read(bindings::get_escrow_links(escrow_id)).await?;
+        EscrowLinks::try_from(&value)
+    }
+
+    /// Get a payment record
+    pub async fn get_payment(&self, payment_id: u64) -> Result<Payment, Error> {
+        let value = self
+            .read(bindings::get_payment(payment_id))
+            .await
+            .map_err(|e| e.with_payment(payment_id))?;
+        Payment::try_from(&value)
+    }
+
+    /// USD equivalent of the payments of an escrow settled so far, closed
+    /// or not
+    pub async fn get_quote_total(&self, escrow_id: u64) -> Result<QuoteTotal, Error> {
+        let value = self.read(bindings::get_quote_total(escrow_id)).await?;
+        QuoteTotal::try_from(&value)
+    }
+
+    /// Total refunded for a payment, 0 if none was
+    pub async fn get_refunded(&self, payment_id: u64) -> Result<i128, Error> {
+        let value = self.read(bindings::get_refunded(payment_id)).await?;
+        scval::to_i128(&value)
+    }
+
+    /// Query the payments of an escrow, walking `get_payments` page by page
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow whose payments are listed, closed or not
+    pub fn payments(&self, escrow_id: u64) -> PaymentQuery<'_> {
+        PaymentQuery::new(self, PaymentList::Escrow(escrow_id))
+    }
+
+    /// Query the direct payments to a server, walking
+    /// `get_direct_payments` page by page
+    ///
+    /// # Arguments
+    /// * `server` - Server paid (G... format)
+    ///
+    /// # Errors
+    /// * `InvalidAddress` - If `server` is not a valid address
+    pub fn direct_payments(&self, server: &str) -> Result<PaymentQuery<'_>, Error> {
+        let server = scval::parse_address(server)?;
+        Ok(PaymentQuery::new(self, PaymentList::Direct(server)))
+    }
+
+    /// Fetch one page of `get_payments` or `get_direct_payments`, retrying
+    /// transient RPC failures
+    ///
+    /// # Returns
+    /// * Matching payments with their IDs, and the offset of the next page
+    pub(crate) async fn payments_page(
+        &self,
+        list: &PaymentList,
+        status: Option<PaymentStatus>,
+        offset: u32,
+        limit: u32,
+    ) -> Result<(Vec<(u64, Payment)>, Option<u32>), Error> {
+        let value = match list {
+            PaymentList::Escrow(escrow_id) => self
+                .read_page(|| bindings::get_payments(*escrow_id, status.clone(), offset, limit))
+                .await
+                .map_err(|e| e.with_escrow(*escrow_id))?,
+            PaymentList::Direct(server) => {
+                self.read_page(|| {
+                    bindings::get_direct_payments(server.clone(), status.clone(), offset, limit)
+                })
+                .await?
+            }
+        };
+
+        let page = Fields::new(&value)?;
+        let payments = scval::to_vec(page.get("payments")?)?
+            .iter()
+            .map(|entry| {
+                let entry = Fields::new(entry)?;
+                Ok((
+                    scval::to_u64(entry.get("payment_id")?)?,
+                    Payment::try_from(entry.get("payment")?)?,
+                ))
+            })
+            .collect::<Result<_, Error>>()?;
+        let next_offset = scval::to_option(page.get("next_offset")?, scval::to_u32)?;
+        Ok((payments, next_offset))
+    }
+
+    /// List the open escrows of the contract, walking `export_escrows` page
+    /// by page
+    ///
+    /// Pages are retried on transient RPC failures like those of
+    /// [`EscrowClient::payments`].
+    ///
+    /// # Arguments
+    /// * `server` - Only list escrows with this server (G... format), all if None
+    ///
+    /// # Returns
+    /// * Open escrows with their IDs, shard by shard, oldest first in each
+    pub async fn export_escrows(&self, server: Option<&str>) -> Result<Vec<(u64, Escrow)>, Error> {
+        let server = server.map(scval::parse_address).transpose()?;
+        let mut escrows = Vec::new();
+        let mut start = Some(0);
+        while let Some(offset) = start {
+            let value = self
+                .read_page(|| bindings::export_escrows(server.clone(), offset, MAX_ESCROWS_PAGE))
+                .await?;
+            let page = Fields::new(&value)?;
+            for entry in scval::to_vec(page.get("escrows")?)? {
+                let entry = Fields::new(entry)?;
+                escrows.push((
+                    scval::to_u64(entry.get("escrow_id")?)?,
+                    Escrow::try_from(entry.get("escrow")?)?,
+                ));
+            }
+            start = scval::to_option(page.get("next_start")?, scval::to_u64)?;
+        }
+        Ok(escrows)
+    }
+
+    /// Read a page of a listing, retrying transient RPC failures
+    ///
+    /// Failures are retried up to [`PAYMENT_PAGE_RETRIES`] times, the first after
+    /// the poll interval and each later one after twice the previous delay.
+    async fn read_page(&self, call: impl Fn() -> Invocation) -> Result<ScVal, Error> {
+        let mut backoff = self.options.poll_interval;
+        let mut retries = 0;
+        loop {
+            match self.read(call()).await {
+                Ok(value) => return Ok(value),
+                Err(e) if e.is_transient() && retries < PAYMENT_PAGE_RETRIES => {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_EVENT_BACKOFF);
+                    retries += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Find the escrow ID for a client-server pair
+    pub async fn find_escrow(&self, client: &str, server: &str) -> Result<Option<u64>, Error> {
+        let call =
+            bindings::find_escrow(scval::parse_address(client)?, scval::parse_address(server)?);
+        let value = self.read(call).await?;
+        scval::to_option(&value, scval::to_u64)
+    }
+
+    /// List the escrows of a client, walking `get_escrow_index` page by
+    /// page
+    ///
+    /// Pages are retried on transient RPC failures like those of
+    /// [`EscrowClient::payments`]. Servers are not indexed, nor escrows
+    /// opened before the contract kept indexes; see
+    /// [`Self::export_escrows`].
+    ///
+    /// # Arguments
+    /// * `client` - Client whose escrows are listed (G... format)
+    /// * `archived` - Whether to list closed escrows instead of open ones
+    ///
+    /// # Returns
+    /// * Escrow IDs, oldest first; closed ones in the order they closed
+    pub async fn escrow_index(&self, client: &str, archived: bool) -> Result<Vec<u64>, Error> {
+        let client = scval::parse_address(client)?;
+        let mut escrow_ids = Vec::new();
+        let mut next = Some(0);
+        while let Some(page) = next {
+            let value = self
+                .read_page(|| bindings::get_escrow_index(client.clone(), archived, page))
+                .await?;
+            let listed = Fields::new(&value)?;
+            for escrow_id in scval::to_vec(listed.get("escrow_ids")?)? {
+                escrow_ids.push(scval::to_u64(escrow_id)?);
+            }
+            next = scval::to_option(listed.get("next_page")?, scval::to_u32)?;
+        }
+        Ok(escrow_ids)
+    }
+
+    /// Drop closed escrows from the live pages of the signer's escrow
+    /// index, up to [`MAX_COMPACT_PAGES`] pages from `start` on
+    ///
+    /// # Returns
+    /// * Page the next call starts at, None once the index is compacted
+    pub async fn compact_escrow_index(&self, start: u32) -> Result<Submitted<Option<u32>>, Error> {
+        let client = scval::parse_address(&self.address())?;
+        self.invoke(bindings::compact_escrow_index(
+            client,
+            start,
+            MAX_COMPACT_PAGES,
+        ))
+        .await?
+        .map(|v| scval::to_option(&v, scval::to_u32))
+    }
+
+    /// Whether a transfer has been credited to an escrow by
+    /// [`Self::claim_pending_deposit`]
+    pub async fn is_deposit_claimed(&self, memo_hash: [u8; 32]) -> Result<bool, Error> {
+        let value = self.read(bindings::is_deposit_claimed(memo_hash)).await?;
+        scval::to_bool(&value)
+    }
+
+    /// Consent to moving an escrow to another deployment (signer must be
+    /// its client or server)
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow to move
+    /// * `target` - Contract it moves to (C... format)
+    pub async fn consent_to_migration(
+        &self,
+        escrow_id: u64,
+        target: &str,
+    ) -> Result<Submitted<()>, Error> {
+        let call = bindings::consent_to_migration(
+            escrow_id,
+            scval::parse_address(&self.address())?,
+            scval::parse_address(target)?,
+        );
+        self.invoke(call)
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?
+            .map(|_| Ok(()))
+    }
+
+    /// Leave a note on an escrow for the other party (signer must be the
+    /// client or the server)
+    ///
+    /// The escrow keeps its latest [`MAX_NOTES`](bindings::MAX_NOTES) notes.
+    ///
+    /// # Errors
+    /// * `Contract(InvalidNote)` - If the note is empty or longer than
+    ///   [`MAX_NOTE_LEN`](bindings::MAX_NOTE_LEN) bytes
+    /// * `Contract(NoteRateLimited)` - If the signer already posted a note
+    ///   in this ledger
+    pub async fn post_note(&self, escrow_id: u64, body: &[u8]) -> Result<Submitted<()>, Error> {
+        let call = bindings::post_note(escrow_id, scval::parse_address(&self.address())?, body)?;
+        self.invoke(call)
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?
+            .map(|_| Ok(()))
+    }
+
+    /// Get the notes left on an escrow, oldest first
+    pub async fn get_notes(&self, escrow_id: u64) -> Result<Vec<Note>, Error> {
+        let value = self.read(bindings::get_notes(escrow_id)).await?;
+        scval::to_vec(&value)?.iter().map(Note::try_from).collect()
+    }
+
+    /// Freeze an escrow both parties agreed to move, and export it (signer
+    /// must be the contract admin)
+    ///
+    /// # Errors
+    /// * `Contract(MigrationNotApproved)` - If both parties did not consent
+    ///   to a target
+    /// * `Contract(EscrowFrozen)` - If the escrow was already exported, see
+    ///   [`Self::get_migration`]
+    pub async fn export_for_migration(
+        &self,
+        escrow_id: u64,
+    ) -> Result<Submitted<MigrationExport>, Error> {
+        let call =
+            bindings::export_for_migration(scval::parse_address(&self.address())?, escrow_id);
+        self.invoke(call)
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?
+            .map(|v| MigrationExport::try_from(&v))
+    }
+
+    /// Get the export of an escrow, frozen or already moved
+    pub async fn get_migration(&self, escrow_id: u64) -> Result<Option<MigrationExport>, Error> {
+        let value = self.read(bindings::get_migration(escrow_id)).await?;
+        scval::to_option(&value, |v| MigrationExport::try_from(v))
+    }
+
+    /// Accept escrows exported by another deployment (signer must be the
+    /// contract admin)
+    ///
+    /// # Arguments
+    /// * `source` - Contract escrows are exported by (C... format)
+    pub async fn allow_migration_source(&self, source: &str) -> Result<Submitted<()>, Error> {
+        let call = bindings::allow_migration_source(
+            scval::parse_address(&self.address())?,
+            scval::parse_address(source)?,
+        );
+        self.invoke(call).await?.map(|_| Ok(()))
+    }
+
+    /// Recreate an escrow exported by another deployment, closing it there
+    ///
+    /// # Returns
+    /// * Escrow ID in this contract
+    ///
+    /// # Errors
+    /// * `Contract(MigrationNotApproved)` - If escrows of the source are not
+    ///   accepted
+    /// * `Contract(MigrationMismatch)` - If the export is for another target
+    pub async fn import_escrow(&self, export: &MigrationExport) -> Result<Submitted<u64>, Error> {
+        let call = bindings::import_escrow(export.encoded.clone(), export.proof);
+        self.invoke(call).await?.map(|v| scval::to_u64(&v))
+    }
+
+    /// Move an escrow from this contract to `target`, once both parties
+    /// consented to it
+    ///
+    /// Exports the escrow, signed by this client's account as admin of this
+    /// contract, and imports it into the target with `target`'s account. An
+    /// escrow already exported, e.g. by a move interrupted before the
+    /// import, is imported from its recorded export.
+    ///
+    /// # Returns
+    /// * The export, and the escrow ID in the target
+    pub async fn migrate_escrow(
+        &self,
+        escrow_id: u64,
+        target: &EscrowClient,
+    ) -> Result<(MigrationExport, Submitted<u64>), Error> {
+        let export = match self.export_for_migration(escrow_id).await {
+            Ok(exported) => exported.value,
+            Err(e) if e.contract_error() == Some(ContractError::EscrowFrozen) => {
+                self.get_migration(escrow_id).await?.ok_or(e)?
+            }
+            Err(e) => return Err(e),
+        };
+        let imported = target.import_escrow(&export).await?;
+        Ok((export, imported))
+    }
+
+    /// Set which escrows [`Self::sweep_dust`] may archive (signer must be
+    /// the contract admin)
+    ///
+    /// # Errors
+    /// * `Contract(InvalidDustPolicy)` - If `threshold` is not positive or
+    ///   `max_idle` is below [`MIN_DUST_IDLE`](bindings::MIN_DUST_IDLE)
+    pub async fn set_dust_policy(
+        &self,
+        threshold: i128,
+        max_idle: Duration,
+    ) -> Result<Submitted<()>, Error> {
+        let admin = scval::parse_address(&self.address())?;
+        self.invoke(bindings::set_dust_policy(
+            admin,
+            threshold,
+            max_idle.as_secs(),
+        ))
+        .await?
+        .map(|_| Ok(()))
+    }
+
+    /// Get the dust policy, None until the admin set one
+    pub async fn get_dust_policy(&self) -> Result<Option<DustPolicy>, Error> {
+        let value = self.read(bindings::get_dust_policy()).await?;
+        scval::to_option(&value, |v| DustPolicy::try_from(v))
+    }
+
+    /// Cap the open escrows each client may hold, None to lift the cap
+    /// (signer must be the contract admin)
+    pub async fn set_max_escrows_per_client(
+        &self,
+        max_escrows_per_client: Option<u32>,
+    ) -> Result<Submitted<()>, Error> {
+        let admin = scval::parse_address(&self.address())?;
+        self.invoke(bindings::set_max_escrows_per_client(
+            admin,
+            max_escrows_per_client,
+        ))
+        .await?
+        .map(|_| Ok(()))
+    }
+
+    /// Get the cap on open escrows per client, None if there is none
+    pub async fn get_max_escrows_per_client(&self) -> Result<Option<u32>, Error> {
+        let value = self.read(bindings::get_max_escrows_per_client()).await?;
+        scval::to_option(&value, scval::to_u32)
+    }
+
+    /// Accept authorizations and allowances until `seconds` after they
+    /// expire by ledger time, 0 to accept none expired (signer must be the
+    /// contract admin)
+    pub async fn set_clock_skew(&self, seconds: u64) -> Result<Submitted<()>, Error> {
+        let admin = scval::parse_address(&self.address())?;
+        self.invoke(bindings::set_clock_skew(admin, seconds))
+            .await?
+            .map(|_| Ok(()))
+    }
+
+    /// Get the contract's clock skew tolerance in seconds
+    pub async fn get_clock_skew(&self) -> Result<u64, Error> {
+        scval::to_u64(&self.read(bindings::get_clock_skew()).await?)
+    }
+
+    /// Get the number of open escrows a client holds
+    pub async fn get_client_escrow_count(&self, client: &str) -> Result<u32, Error> {
+        let call = bindings::get_client_escrow_count(scval::parse_address(client)?);
+        scval::to_u32(&self.read(call).await?)
+    }
+
+    /// Set where dust swept from the signer's escrows is released to
+    /// (signer must be the client)
+    pub async fn set_refund_address(&self, refund_to: &str) -> Result<Submitted<()>, Error> {
+        let call = bindings::set_refund_address(
+            scval::parse_address(&self.address())?,
+            scval::parse_address(refund_to)?,
+        );
+        self.invoke(call).await?.map(|_| Ok(()))
+    }
+
+    /// Publish the hash of the signer's terms of service (signer must be
+    /// the server)
+    ///
+    /// Escrows opened from then on must accept these terms, those already
+    /// open keep theirs.
+    pub async fn set_terms(
+        &self,
+        server: &str,
+        terms_hash: [u8; 32],
+    ) -> Result<Submitted<()>, Error> {
+        self.invoke(bindings::set_terms(
+            scval::parse_address(server)?,
+            terms_hash,
+        ))
+        .await?
+        .map(|_| Ok(()))
+    }
+
+    /// Get the hash of a server's terms of service, None if it published
+    /// none
+    pub async fn get_terms(&self, server: &str) -> Result<Option<[u8; 32]>, Error> {
+        let value = self
+            .read(bindings::get_terms(scval::parse_address(server)?))
+            .await?;
+        scval::to_option(&value, scval::to_bytes32)
+    }
+
+    /// Register the URL the signer's settlement and dispute notifications
+    /// are delivered to (signer must be the server), an empty URL removing
+    /// it
+    ///
+    /// Indexers deliver webhooks there, signed with a key exchanged with
+    /// them out of band.
+    ///
+    /// # Errors
+    /// * `Contract(InvalidNotificationUrl)` - If the URL is longer than
+    ///   [`MAX_NOTIFY_URL_LEN`](bindings::MAX_NOTIFY_URL_LEN) bytes
+    pub async fn set_notification_url(&self, url: &str) -> Result<Submitted<()>, Error> {
+        let call = bindings::set_notification_url(scval::parse_address(&self.address())?, url)?;
+        self.invoke(call).await?.map(|_| Ok(()))
+    }
+
+    /// Get the URL a server's notifications are delivered to, None if it
+    /// registered none
+    pub async fn get_notification_url(&self, server: &str) -> Result<Option<String>, Error> {
+        let value = self
+            .read(bindings::get_notification_url(scval::parse_address(
+                server,
+            )?))
+            .await?;
+        scval::to_option(&value, |v| {
+            String::from_utf8(scval::to_bytes(v)?)
+                .map_err(|e| Error::InvalidResponse(format!("notification URL: {e}")))
+        })
+    }
+
+    /// Get where dust swept from a client's escrows is released to
+    pub async fn get_refund_address(&self, client: &str) -> Result<String, Error> {
+        let value = self
+            .read(bindings::get_refund_address(scval::parse_address(client)?))
+            .await?;
+        scval::to_address(&value)
+    }
+
+    /// Let an agent sign payment authorizations for the signer's escrows
+    /// (signer must be the client)
+    ///
+    /// Replaces any allowance the agent held. Agents cannot close or
+    /// migrate escrows.
+    ///
+    /// # Arguments
+    /// * `agent` - Account of the agent's key (G... format)
+    /// * `total_cap` - Total the agent may authorize, in stroops
+    /// * `per_payment_cap` - Most the agent may authorize in one payment
+    /// * `expires_at` - Unix timestamp after which the allowance is void
+    ///
+    /// # Errors
+    /// * `Contract(InvalidAllowance)` - If a cap is not positive, the
+    ///   per-payment cap exceeds the total, or `expires_at` has passed
+    pub async fn grant_agent(
+        &self,
+        agent: &str,
+        total_cap: i128,
+        per_payment_cap: i128,
+        expires_at: u64,
+    ) -> Result<Submitted<()>, Error> {
+        let call = bindings::grant_agent(
+            scval::parse_address(&self.address())?,
+            scval::parse_address(agent)?,
+            total_cap,
+            per_payment_cap,
+            expires_at,
+        );
+        self.invoke(call).await?.map(|_| Ok(()))
+    }
+
+    /// Withdraw the allowance of an agent (signer must be the client)
+    ///
+    /// Authorizations the agent signed stop paying, even those a server
+    /// already accepted.
+    ///
+    /// # Errors
+    /// * `Contract(AllowanceNotFound)` - If the agent holds no allowance
+    pub async fn revoke_agent(&self, agent: &str) -> Result<Submitted<()>, Error> {
+        let call = bindings::revoke_agent(
+            scval::parse_address(&self.address())?,
+            scval::parse_address(agent)?,
+        );
+        self.invoke(call).await?.map(|_| Ok(()))
+    }
+
+    /// Get the allowance a client granted an agent, None if it holds none
+    pub async fn get_allowance(
+        &self,
+        client: &str,
+        agent: &str,
+    ) -> Result<Option<Allowance>, Error> {
+        let call =
+            bindings::get_allowance(scval::parse_address(client)?, scval::parse_address(agent)?);
+        let value = self.read(call).await?;
+        scval::to_option(&value, |v| Allowance::try_from(v))
+    }
+
+    /// Cap what the server settles from an escrow per hour, None to remove
+    /// the cap (signer must be the client)
+    ///
+    /// A settlement breaching the cap pauses settlements of the escrow
+    /// until [`Self::acknowledge_anomaly`] or the hour is over.
+    ///
+    /// # Errors
+    /// * `Contract(InvalidSpendLimit)` - If the cap is not positive
+    pub async fn set_max_settled_per_hour(
+        &self,
+        escrow_id: u64,
+        max_settled_per_hour: Option<i128>,
+    ) -> Result<Submitted<()>, Error> {
+        self.invoke(bindings::set_max_settled_per_hour(
+            escrow_id,
+            max_settled_per_hour,
+        ))
+        .await
+        .map_err(|e| e.with_escrow(escrow_id))?
+        .map(|_| Ok(()))
+    }
+
+    /// Resume settlements of an escrow paused by its spend limit (signer
+    /// must be the client)
+    pub async fn acknowledge_anomaly(&self, escrow_id: u64) -> Result<Submitted<()>, Error> {
+        self.invoke(bindings::acknowledge_anomaly(escrow_id))
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?
+            .map(|_| Ok(()))
+    }
+
+    /// Get the spend limit of an escrow, None if it has none
+    pub async fn get_spend_limit(&self, escrow_id: u64) -> Result<Option<SpendLimit>, Error> {
+        let value = self.read(bindings::get_spend_limit(escrow_id)).await?;
+        scval::to_option(&value, |v| SpendLimit::try_from(v))
+    }
+
+    /// Unix timestamp of the last activity on an escrow
+    pub async fn get_last_activity(&self, escrow_id: u64) -> Result<u64, Error> {
+        let value = self
+            .read(bindings::get_last_activity(escrow_id))
+            .await
+            .map_err(|e| e.with_escrow(escrow_id))?;
+        scval::to_u64(&value)
+    }
+
+    /// Archive abandoned escrows holding dust, atomically (signer must be
+    /// the contract admin)
+    ///
+    /// # Returns
+    /// * Total balance released to the clients' refund addresses
+    ///
+    /// # Errors
+    /// * `Contract(EscrowNotDust)` - If an escrow holds more than dust or was
+    ///   active too recently
+    /// * `Contract(PendingPayments)` - If an escrow has unsettled payments
+    pub async fn sweep_dust(&self, escrow_ids: &[u64]) -> Result<Submitted<i128>, Error> {
+        let admin = scval::parse_address(&self.address())?;
+        self.invoke(bindings::sweep_dust(admin, escrow_ids)?)
+            .await?
+            .map(|v| scval::to_i128(&v))
+    }
+
+    /// Finality of a settlement, read against the latest ledger
+    ///
+    /// # Arguments
+    /// * `policy` - When settlements are final
+    /// * `amount` - Settled amount, in stroops
+    /// * `included` - Ledger the settlement transaction was included in
+    pub async fn finality(
+        &self,
+        policy: &FinalityPolicy,
+        amount: i128,
+        included: u32,
+    ) -> Result<Finality, Error> {
+        let latest = self.rpc.get_latest_ledger().await?.sequence;
+        Ok(policy.finality(amount, included, latest))
+    }
+
+    /// Wait for a settlement to be final, polling the latest ledger every
+    /// poll interval
+    ///
+    /// Ledgers close every few seconds, so a deep policy may wait for
+    /// minutes; callers bound the wait with their own timeout.
+    ///
+    /// # Returns
+    /// * The latest ledger once the settlement is final
+    ///
+    /// # Errors
+    /// * If the latest ledger cannot be read
+    pub async fn wait_for_finality(
+        &self,
+        policy: &FinalityPolicy,
+        amount: i128,
+        included: u32,
+    ) -> Result<u32, Error> {
+        loop {
+            let latest = self.rpc.get_latest_ledger().await?.sequence;
+            if policy.finality(amount, included, latest) == Finality::Final {
+                return Ok(latest);
+            }
+            tokio::time::sleep(self.options.poll_interval).await;
+        }
+    }
+
+    /// Sign an authorization for the server to charge an escrow (signer must be the client)
+    ///
+    /// The authorization is bound to this client's network and contract, and
+    /// can be checked on-chain with the contract's `verify_authorization`.
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow to charge
+    /// * `amount` - Authorized amount, in stroops
+    /// * `nonce` - Unique per escrow
+    /// * `expires_at` - Unix timestamp after which the authorization is void
+    /// * `network` - x402 network id (e.g., "stellar-testnet")
+    pub async fn authorize_payment(
+        &self,
+        escrow_id: u64,
+        amount: i128,
+        nonce: u64,
+        expires_at: u64,
+        network: &str,
+    ) -> Result<EscrowPayload, Error> {
+        let mut payload = EscrowPayload {
+            escrow_id,
+            client: self.address(),
+            amount: amount.to_string(),
+            nonce,
+            expires_at,
+            signature: String::new(),
+            deposit_authorization: None,
+            payment_id: None,
+            direct_authorization: None,
+        };
+        let contract_id = self.contract_id();
+        let domain = SigningDomain {
+            network_id: self.network_id(),
+            contract_id: &contract_id,
+        };
+        let signature = self
+            .sign_hash(self.signer.as_ref(), &payload.signing_hash(network, domain))
+            .await?;
+        payload.signature = hex::encode(signature);
+        Ok(payload)
+    }
+
+    /// Sign a voucher for the server to claim from an escrow (signer must be the client)
+    ///
+    /// The voucher is bound to this client's network and contract, and can be
+    /// checked on-chain with the contract's `verify_voucher`.
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow the voucher draws on
+    /// * `amount` - Total amount authorized so far, in stroops
+    /// * `sequence` - Increasing per escrow, the latest voucher supersedes the others
+    /// * `expires_at` - Unix timestamp after which the voucher is void
+    pub async fn sign_voucher(
+        &self,
+        escrow_id: u64,
+        amount: i128,
+        sequence: u64,
+        expires_at: u64,
+    ) -> Result<[u8; 64], Error> {
+        let contract_id = self.contract_id();
+        let voucher = EscrowVoucher {
+            domain: SigningDomain {
+                network_id: self.network_id(),
+                contract_id: &contract_id,
+            },
+            escrow_id,
+            amount,
+            sequence,
+            expires_at,
+        };
+        self.sign_hash(self.signer.as_ref(), &voucher.signing_hash())
+            .await
+    }
+
+    /// Sign the state of a payment channel over an escrow (signer must be either party)
+    ///
+    /// Checked on-chain with the contract's `verify_channel_state`.
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow backing the channel
+    /// * `sequence` - Increasing per escrow, the latest state supersedes the others
+    /// * `balance` - Balance left to the client, in stroops
+    /// * `settled` - Total paid to the server, in stroops
+    pub async fn sign_channel_state(
+        &self,
+        escrow_id: u64,
+        sequence: u64,
+        balance: i128,
+        settled: i128,
+    ) -> Result<[u8; 64], Error> {
+        let contract_id = self.contract_id();
+        let state = EscrowChannelState {
+            domain: SigningDomain {
+                network_id: self.network_id(),
+                contract_id: &contract_id,
+            },
+            escrow_id,
+            sequence,
+            balance,
+            settled,
+        };
+        self.sign_hash(self.signer.as_ref(), &state.signing_hash())
+            .await
+    }
+
+    /// Issue a signed receipt for a settled payment (signer must be the
+    /// payment's server)
+    ///
+    /// The payment's creation and settlement are looked up in the contract's
+    /// events, so `from_ledger` must be at or before its creation and within
+    /// the RPC server's event retention.
+    ///
+    /// # Arguments
+    /// * `payment_id` - Settled payment
+    /// * `asset` - Asset the escrow holds, `native` for XLM
+    /// * `from_ledger` - Ledger the event search starts at
+    ///
+    /// # Errors
+    /// * `Contract(PaymentNotFound)` - If the payment does not exist
+    /// * `NoReceipt` - If it is not settled, its events are not found from
+    ///   `from_ledger` on, or the signer is not its server
+    pub async fn settlement_receipt(
+        &self,
+        payment_id: u64,
+        asset: &str,
+        from_ledger: u32,
+    ) -> Result<SettlementReceipt, Error> {
+        let no_receipt =
+            |reason: String| Error::NoReceipt(format!("payment {payment_id}: {reason}"));
+        let payment = self.get_payment(payment_id).await?;
+        if !payment.settled {
+            return Err(no_receipt("not settled".into()));
+        }
+
+        // Escrows are removed once closed, their events remain
+        let contract_id = self.contract_id();
+        let (mut parties, mut settled) = (None, None);
+        let mut from = EventsFrom::Ledger(from_ledger);
+        while settled.is_none() {
+            let page = self
+                .rpc
+                .get_events(&contract_id, &from, EVENT_PAGE_LIMIT)
+                .await?;
+            for info in &page.events {
+                match EscrowEvent::from_info(info) {
+                    Ok(Some(EscrowEvent::PaymentCreated {
+                        payment_id: id,
+                        client,
+                        server,
+                        ..
+                    })) if id == payment_id => parties = Some((client, server)),
+                    Ok(Some(EscrowEvent::PaymentSettled { payment_id: id, .. }))
+                        if id == payment_id =>
+                    {
+                        settled = Some(info.clone());
+                    }
+                    _ => {}
+                }
+            }
+            match page.cursor {
+                Some(cursor) if page.events.len() == EVENT_PAGE_LIMIT as usize => {
+                    from = EventsFrom::Cursor(cursor);
+                }
+                _ => break,
+            }
+        }
+        let Some((client, server)) = parties else {
+            return Err(no_receipt(format!(
+                "not created at or after ledger {from_ledger}"
+            )));
+        };
+        let Some(settled) = settled else {
+            return Err(no_receipt(format!(
+                "no settlement found after ledger {from_ledger}"
+            )));
+        };
+        if ed25519::PublicKey(self.signer.signing_key()).to_string() != server {
+            return Err(no_receipt(format!(
+                "receipts are signed by its server {server}"
+            )));
+        }
+
+        let body = ReceiptBody {
+            version: RECEIPT_VERSION,
+            network_id: hex::encode(self.network_id()),
+            contract_id,
+            payment_id,
+            escrow_id: payment.escrow_id,
+            client,
+            server,
+            amount: payment.amount.to_string(),
+            asset: asset.into(),
+            created_at: format_timestamp(payment.timestamp),
+            settled_at: settled.ledger_closed_at,
+            ledger: settled.ledger,
+            tx_hash: settled
+                .tx_hash
+                .ok_or_else(|| no_receipt("settlement event has no transaction hash".into()))?,
+        };
+        let hash = body.hash();
+        let signature = self.sign_hash(self.signer.as_ref(), &hash).await?;
+        Ok(SettlementReceipt {
+            body,
+            receipt_hash: hex::encode(hash),
+            signature: hex::encode(signature),
+        })
+    }
+
+    /// Pre-sign an operation for another account to submit later (signer
+    /// must be the party whose authorization the operation requires)
+    ///
+    /// The entry authorizes one call with exactly these arguments, so e.g.
+    /// three deposits of up to 1 XLM take three entries, one per amount
+    /// deposited. Nothing is submitted and no sequence number is consumed.
+    ///
+    /// # Arguments
+    /// * `op` - Call authorized
+    /// * `valid_for` - Ledgers after the latest one the entry stays valid for
+    pub async fn presign(
+        &self,
+        op: &EscrowOp,
+        valid_for: u32,
+    ) -> Result<AuthorizationEntry, Error> {
+        let latest = self.rpc.get_latest_ledger().await?.sequence;
+        let entry = AuthorizationEntry::new(
+            &self.contract_id(),
+            op,
+            &self.address(),
+            random_nonce(),
+            latest.saturating_add(valid_for),
+        )?;
+        let hash = entry.signing_hash(&self.network_id())?;
+        let signature = self.sign_hash(self.signer.as_ref(), &hash).await?;
+        entry.with_signature(self.signer.signing_key(), signature)
+    }
+
+    /// Submit a call pre-signed by another account, the signer being the
+    /// transaction source paying its fees
+    ///
+    /// # Errors
+    /// * `InvalidAuthorization` - If the entry is for another contract, or
+    ///   not an escrow operation
+    /// * `Contract` - If the contract would reject the call
+    /// * `TransactionFailed` - If the entry expired, or its nonce was used,
+    ///   by the time the transaction applied
+    pub async fn submit_authorized(
+        &self,
+        entry: &AuthorizationEntry,
+    ) -> Result<Submitted<ScVal>, Error> {
+        let _sequence = self.sequence.lock().await;
+        let tx = self.prepare_authorized(entry).await?;
+        self.submit(&tx).await
+    }
+
+    /// Build and sign a transaction submitting a pre-signed call without
+    /// submitting it
+    pub async fn prepare_authorized(
+        &self,
+        entry: &AuthorizationEntry,
+    ) -> Result<PreparedTransaction, Error> {
+        let contract_id = entry.contract_id()?;
+        if contract_id != self.contract_id() {
+            return Err(Error::InvalidAuthorization(format!(
+                "entry is for contract {contract_id}"
+            )));
+        }
+        let op = entry.op()?;
+        self.prepare_with(op.invocation()?, Some(entry.entry().clone()))
+            .await
+            .map_err(|e| op.annotate(e))
+    }
+
+    /// Estimate the fees and resources of an operation without submitting it
+    ///
+    /// # Errors
+    /// * `Contract` - If the contract would reject the operation, with the
+    ///   reason as a [`crate::ContractError`]
+    /// * `Simulation` - If simulation failed for another reason
+    pub async fn estimate(&self, op: &EscrowOp) -> Result<EstimateResult, Error> {
+        Ok(self.dry_run(op).await?.estimate)
+    }
+
+    /// Simulate an operation without submitting it
+    ///
+    /// Nothing is signed and no sequence number is consumed.
+    ///
+    /// # Returns
+    /// * The value the contract would return, and the operation's estimate
+    ///
+    /// # Errors
+    /// * `Contract` - If the contract would reject the operation, with the
+    ///   reason as a [`crate::ContractError`]
+    /// * `Simulation` - If simulation failed for another reason
+    pub async fn dry_run(&self, op: &EscrowOp) -> Result<DryRun, Error> {
+        // Sequence numbers are not checked during simulation
+        let call = op.invocation()?;
+        let tx = self.build_transaction(0, &call)?;
+        let simulation = self
+            .simulate(&tx, call.function)
+            .await
+            .map_err(|e| op.annotate(e))?;
+        Ok(DryRun {
+            value: simulation_result(&simulation)?,
+            estimate: EstimateResult::from_assembled(
+                &assemble(tx, &simulation)?,
+                self.options.base_fee,
+            )?,
+        })
+    }
+
+    /// Subscribe to the contract's events
+    ///
+    /// Polls `getEvents` with a cursor every `poll_interval`, yielding each
+    /// event once and in order. RPC errors are retried with exponential
+    /// backoff up to [`crate::MAX_EVENT_BACKOFF`]; only malformed events are
+    /// yielded as errors.
+    ///
+    /// # Arguments
+    /// * `filter` - Start position and kinds of events yielded
+    pub fn subscribe_events(
+        &self,
+        filter: EventFilter,
+    ) -> impl Stream<Item = Result<Emitted, Error>> + Send + 'static {
+        let subscription = Subscription::new(
+            self.rpc.clone(),
+            self.contract_id(),
+            filter,
+            self.options.poll_interval,
+        );
+        futures_util::stream::unfold(subscription, |mut subscription| async move {
+            let item = subscription.next().await;
+            Some((item, subscription))
+        })
+    }
+
+    /// Simulate a read-only call and return its result
+    #[tracing::instrument(level = "debug", name = "escrow.read", skip_all, fields(function = call.function))]
+    async fn read(&self, call: Invocation) -> Result<ScVal, Error> {
+        // Sequence numbers are not checked during simulation
+        let tx = self.build_transaction(0, &call)?;
+        let simulation = self.simulate(&tx, call.function).await?;
+        simulation_result(&simulation)
+    }
+
+    /// Run the full build, simulate, sign, submit, and poll pipeline
+    async fn invoke(&self, call: Invocation) -> Result<Submitted<ScVal>, Error> {
+        let _sequence = self.sequence.lock().await;
+        let tx = self.prepare(call).await?;
+        self.submit(&tx).await
+    }
+
+    /// Build, simulate, and sign an invocation
+    async fn prepare(&self, call: Invocation) -> Result<PreparedTransaction, Error> {
+        self.prepare_with(call, None).await
+    }
+
+    /// Build, simulate, and sign an invocation, authorized by a pre-signed
+    /// entry rather than by the simulation's
+    #[tracing::instrument(
+        name = "escrow.prepare",
+        skip_all,
+        fields(function = call.function, correlation_id = self.correlation_id())
+    )]
+    async fn prepare_with(
+        &self,
+        call: Invocation,
+        auth: Option<SorobanAuthorizationEntry>,
+    ) -> Result<PreparedTransaction, Error> {
+        let account = self.rpc.get_account(&self.signer.public_key()).await?;
+        let mut tx = self.build_transaction(account.seq_num.0 + 1, &call)?;
+        if let Some(entry) = &auth {
+            // Simulated in enforcing mode, with the signed entry
+            tx = with_auth(tx, vec![entry.clone()])?;
+        }
+
+        let simulation = self.simulate(&tx, call.function).await?;
+        let mut tx = assemble(tx, &simulation)?;
+        if let Some(entry) = auth {
+            tx = with_auth(tx, vec![entry])?;
+        }
+        let hash = hex::encode(self.hash(&tx)?);
+        let envelope = self.sign(tx).await?;
+        Ok(PreparedTransaction {
+            envelope: envelope.to_xdr_base64(Limits::none())?,
+            hash,
+        })
+    }
+
+    fn build_transaction(&self, sequence: i64, call: &Invocation) -> Result<Transaction, Error> {
+        let invoke = InvokeHostFunctionOp {
+            host_function: HostFunction::InvokeContract(InvokeContractArgs {
+                contract_address: ScAddress::Contract(self.contract.clone()),
+                function_name: ScSymbol(call.function.try_into()?),
+                args: call.args.clone().try_into()?,
+            }),
+            auth: Default::default(),
+        };
+
+        Ok(Transaction {
+            source_account: MuxedAccount::Ed25519(Uint256(self.signer.public_key())),
+            fee: self.options.base_fee,
+            seq_num: SequenceNumber(sequence),
+            cond: Preconditions::None,
+            memo: match &self.correlation_id {
+                Some(id) => Memo::Text(id.as_str().try_into()?),
+                None => Memo::None,
+            },
+            operations: vec![Operation {
+                source_account: None,
+                body: OperationBody::InvokeHostFunction(invoke),
+            }]
+            .try_into()?,
+            ext: TransactionExt::V0,
+        })
+    }
+
+    async fn simulate(
+        &self,
+        tx: &Transaction,
+        function: &str,
+    ) -> Result<SimulateTransactionResponse, Error> {
+        let envelope = TransactionEnvelope::Tx(TransactionV1Envelope {
+            tx: tx.clone(),
+            signatures: Default::default(),
+        });
+        let simulation = self.rpc.simulate_transaction(&envelope).await?;
+        match simulation.error {
+            Some(error) => Err(Error::from_simulation(error, function)),
+            None => Ok(simulation),
+        }
+    }
+
+    /// Hash of the transaction as signed on this network
+    fn hash(&self, tx: &Transaction) -> Result<[u8; 32], Error> {
+        self.payload_hash(TransactionSignaturePayloadTaggedTransaction::Tx(tx.clone()))
+    }
+
+    fn payload_hash(
+        &self,
+        tagged_transaction: TransactionSignaturePayloadTaggedTransaction,
+    ) -> Result<[u8; 32], Error> {
+        let payload = TransactionSignaturePayload {
+            network_id: self.network_id.clone(),
+            tagged_transaction,
+        };
+        Ok(Sha256::digest(payload.to_xdr(Limits::none())?).into())
+    }
+
+    async fn sign(&self, tx: Transaction) -> Result<TransactionEnvelope, Error> {
+        let hash = self.hash(&tx)?;
+        let signature = self.sign_hash(self.signer.as_ref(), &hash).await?;
+        Ok(TransactionEnvelope::Tx(TransactionV1Envelope {
+            tx,
+            signatures: vec![decorated(self.signer.signing_key(), signature)?].try_into()?,
+        }))
+    }
+
+    /// Sign a hash, giving up after the signing timeout
+    ///
+    /// # Errors
+    /// * `Signer` - If the signer failed or did not answer in time
+    async fn sign_hash(&self, signer: &dyn Signer, hash: &[u8; 32]) -> Result<[u8; 64], Error> {
+        let timeout = self.options.sign_timeout;
+        tokio::time::timeout(timeout, signer.sign(hash))
+            .await
+            .map_err(|_| Error::Signer(format!("no signature within {timeout:?}")))?
+    }
+
+    /// Poll `getTransaction` until the transaction is confirmed or times out
+    async fn wait_for(&self, hash: &str) -> Result<Submitted<ScVal>, Error> {
+        let deadline = tokio::time::Instant::now() + self.options.timeout;
+        loop {
+            if let Some(submitted) = self.transaction(hash).await? {
+                return Ok(submitted);
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::Timeout {
+                    hash: hash.to_string(),
+                    timeout: self.options.timeout,
+                });
+            }
+            tokio::time::sleep(self.options.poll_interval).await;
+        }
+    }
+}
+
+/// Contract `Authorization`, public key, and signature of a payload
+///
+/// # Errors
+/// * `InvalidAddress` - If the payload's client is not an account
+/// * `InvalidAuthorization` - If its amount or signature is malformed
+fn authorization_args(
+    payload: &EscrowPayload,
+    network: &str,
+) -> Result<(ScVal, [u8; 32], [u8; 64]), Error> {
+    let public_key = match Strkey::from_string(&payload.client) {
+        Ok(Strkey::PublicKeyEd25519(key)) => key.0,
+        _ => return Err(Error::InvalidAddress(payload.client.clone())),
+    };
+    let amount: i128 = payload
+        .amount
+        .parse()
+        .map_err(|_| Error::InvalidAuthorization(format!("invalid amount {:?}", payload.amount)))?;
+    let mut signature = [0; 64];
+    hex::decode_to_slice(&payload.signature, &mut signature).map_err(|_| {
+        Error::InvalidAuthorization("expected a hex-encoded 64-byte signature".into())
+    })?;
+    let authorization = bindings::authorization(
+        payload.escrow_id,
+        network,
+        amount,
+        payload.nonce,
+        payload.expires_at,
+    )?;
+    Ok((authorization, public_key, signature))
+}
+
+fn decorated(signing_key: [u8; 32], signature: [u8; 64]) -> Result<DecoratedSignature, Error> {
+    Ok(DecoratedSignature {
+        hint: SignatureHint(signing_key[28..].try_into().expect("4-byte hint")),
+        signature: Signature(signature.try_into()?),
+    })
+}
+
+/// Transaction an envelope carries, unwrapping fee bumps
+pub(crate) fn inner_envelope(
+    envelope: TransactionEnvelope,
+) -> Result<TransactionV1Envelope, Error> {
+    match envelope {
+        TransactionEnvelope::Tx(inner) => Ok(inner),
+        TransactionEnvelope::TxFeeBump(bump) => match bump.tx.inner_tx {
+            FeeBumpTransactionInnerTx::Tx(inner) => Ok(inner),
+        },
+        TransactionEnvelope::TxV0(_) => {
+            Err(Error::InvalidResponse("expected a v1 envelope".into()))
+        }
+    }
+}
+
+/// Replace the authorization entries of a transaction's invocation
+fn with_auth(
+    mut tx: Transaction,
+    auth: Vec<SorobanAuthorizationEntry>,
+) -> Result<Transaction, Error> {
+    if let Some(Operation {
+        body: OperationBody::InvokeHostFunction(op),
+        ..
+    }) = tx.operations.first().cloned()
+    {
+        let op = InvokeHostFunctionOp {
+            auth: auth.try_into()?,
+            ..op
+        };
+        tx.operations = vec![Operation {
+            source_account: None,
+            body: OperationBody::InvokeHostFunction(op),
+        }]
+        .try_into()?;
+    }
+    Ok(tx)
+}
+
+/// Apply simulation results (resources, fees, and auth) to a transaction
+fn assemble(
+    mut tx: Transaction,
+    simulation: &SimulateTransactionResponse,
+) -> Result<Transaction, Error> {
+    let data = simulation
+        .transaction_data
+        .as_deref()
+        .ok_or_else(|| Error::InvalidResponse("missing transactionData".into()))?;
+    let resource_fee: u32 = simulation
+        .min_resource_fee
+        .as_deref()
+        .unwrap_or("0")
+        .parse()
+        .map_err(|_| Error::InvalidResponse("invalid minResourceFee".into()))?;
+
+    let auth = simulation
+        .results
+        .first()
+        .map(|result| {
+            result
+                .auth
+                .iter()
+                .map(|entry| SorobanAuthorizationEntry::from_xdr_base64(entry, Limits::none()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut tx = with_auth(tx, auth)?;
+    tx.fee = tx.fee.saturating_add(resource_fee);
+    tx.ext = TransactionExt::V1(SorobanTransactionData::from_xdr_base64(
+        data,
+        Limits::none(),
+    )?);
+    Ok(tx)
+}
+
+fn simulation_result(simulation: &SimulateTransactionResponse) -> Result<ScVal, Error> {
+    let result = simulation
+        .results
+        .first()
+        .ok_or_else(|| Error::InvalidResponse("simulation returned no results".into()))?;
+    Ok(ScVal::from_xdr_base64(&result.xdr, Limits::none())?)
+}
+
+fn return_value(meta: &str) -> Result<ScVal, Error> {
+    match TransactionMeta::from_xdr_base64(meta, Limits::none())? {
+        TransactionMeta::V3(meta) => meta
+            .soroban_meta
+            .map(|soroban| soroban.return_value)
+            .ok_or_else(|| Error::InvalidResponse("missing Soroban meta".into())),
+        _ => Err(Error::InvalidResponse(
+            "unsupported TransactionMeta version".into(),
+        )),
+    }
+}
@@ -0,0 +1,1365 @@
+use std::collections::HashMap;
+
+use stellar_strkey::ed25519;
+use stellar_xdr::curr::{HostFunction, Limits, OperationBody, ReadXdr, ScVal, TransactionEnvelope};
+use x402_bindings::Invocation;
+use x402_types::StellarAmount;
+
+use crate::{client::inner_envelope, scval, scval::Fields, Error, PreparedTransaction};
+
+/// Value a [`Fact`] refers to, formatted by the wallet for its locale
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FactValue {
+    /// Amount in the escrow asset, in stroops
+    Amount(i128),
+    /// Amount in US cents
+    UsdCents(i128),
+    /// Account or contract (G... or C... format)
+    Address(String),
+    /// Escrow account ID
+    Escrow(u64),
+    /// Payment ID
+    Payment(u64),
+    Count(u64),
+    /// Unix timestamp, in seconds
+    Timestamp(u64),
+    /// Length of time, in seconds
+    Seconds(u64),
+    /// Share in basis points
+    Bps(u32),
+    Text(String),
+}
+
+/// One human-readable statement about an operation
+///
+/// Wallets translating the summary look `key` up in their catalog and
+/// substitute `args` by name, e.g. `escrow.deposit` as "Deposit {amount}
+/// into {escrow}". `text` is the English rendering, for the others.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Fact {
+    /// Localization key of the message
+    pub key: &'static str,
+    /// Values of the message, by placeholder name
+    pub args: Vec<(&'static str, FactValue)>,
+    /// Message in English
+    pub text: String,
+}
+
+impl Fact {
+    /// Value of the placeholder `name`
+    pub fn arg(&self, name: &str) -> Option<&FactValue> {
+        self.args
+            .iter()
+            .find(|(arg, _)| *arg == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Most an operation may move out of an escrow or account
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Exposure {
+    /// The operation moves no funds
+    None,
+    /// At most this many stroops
+    UpTo(i128),
+    /// Funds whose amount is held on-chain rather than in the arguments,
+    /// e.g. the balance of a closing escrow
+    OnChain,
+}
+
+/// Human-readable account of an escrow contract call, for wallets to show
+/// rather than XDR
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OperationSummary {
+    /// Contract invoked (C... format), None when describing an
+    /// [`Invocation`]
+    pub contract: Option<String>,
+    /// Contract function invoked
+    pub function: String,
+    /// What the operation does
+    pub title: Fact,
+    /// Parties, limits, and expiries worth confirming
+    pub details: Vec<Fact>,
+    /// Worst case of the funds the operation moves
+    pub exposure: Exposure,
+    /// Whether the call only reads contract state
+    pub read_only: bool,
+}
+
+/// Summarize a prepared escrow contract call in plain language
+///
+/// Addresses are shown abbreviated and amounts in XLM, see [`Describer`]
+/// to name known parties and assets.
+///
+/// # Errors
+/// * `NotDescribable` - If the transaction is not a single escrow
+///   contract call, or its arguments do not decode
+pub fn describe(tx: &PreparedTransaction) -> Result<OperationSummary, Error> {
+    Describer::default().describe(tx)
+}
+
+/// Summarizes escrow contract calls, with what the wallet knows of the
+/// parties
+#[derive(Clone, Debug)]
+pub struct Describer {
+    asset: String,
+    labels: HashMap<String, String>,
+    servers: HashMap<u64, String>,
+}
+
+impl Default for Describer {
+    fn default() -> Self {
+        Self {
+            asset: "XLM".into(),
+            labels: HashMap::new(),
+            servers: HashMap::new(),
+        }
+    }
+}
+
+impl Describer {
+    /// Show amounts in `asset` (e.g., "USDC") rather than XLM
+    pub fn with_asset(mut self, asset: impl Into<String>) -> Self {
+        self.asset = asset.into();
+        self
+    }
+
+    /// Show `address` (G... or C... format) as `label`, e.g. the domain of
+    /// a server
+    pub fn with_label(mut self, address: impl Into<String>, label: impl Into<String>) -> Self {
+        self.labels.insert(address.into(), label.into());
+        self
+    }
+
+    /// Show escrow `escrow_id` by its server (G... format), for calls
+    /// naming the escrow only
+    pub fn with_escrow(mut self, escrow_id: u64, server: impl Into<String>) -> Self {
+        self.servers.insert(escrow_id, server.into());
+        self
+    }
+
+    /// Summarize the contract call of a prepared transaction
+    ///
+    /// # Errors
+    /// * `NotDescribable` - If the transaction is not a single contract
+    ///   call, or its arguments do not decode
+    pub fn describe(&self, tx: &PreparedTransaction) -> Result<OperationSummary, Error> {
+        let envelope = TransactionEnvelope::from_xdr_base64(&tx.envelope, Limits::none())?;
+        let signed = inner_envelope(envelope)?.tx;
+        let [operation] = signed.operations.as_slice() else {
+            return Err(not_describable("expected a single operation"));
+        };
+        let OperationBody::InvokeHostFunction(op) = &operation.body else {
+            return Err(not_describable("expected a contract call"));
+        };
+        let HostFunction::InvokeContract(call) = &op.host_function else {
+            return Err(not_describable("expected a contract call"));
+        };
+        let function = call.function_name.to_utf8_string_lossy();
+        let mut summary = self.describe_call(&function, &call.args)?;
+        summary.contract = Some(scval::format_address(&call.contract_address));
+        Ok(summary)
+    }
+
+    /// Summarize a contract call not built into a transaction yet
+    ///
+    /// # Errors
+    /// * `NotDescribable` - If the function is not an escrow contract
+    ///   function, or the arguments do not decode
+    pub fn describe_invocation(&self, invocation: &Invocation) -> Result<OperationSummary, Error> {
+        self.describe_call(invocation.function, &invocation.args)
+    }
+
+    fn describe_call(&self, function: &str, args: &[ScVal]) -> Result<OperationSummary, Error> {
+        use FactValue as V;
+
+        let a = Args { function, args };
+        let write = |title: Fact, details: Vec<Fact>, exposure: Exposure| OperationSummary {
+            contract: None,
+            function: function.into(),
+            title,
+            details,
+            exposure,
+            read_only: false,
+        };
+        let read = |title: Fact| OperationSummary {
+            read_only: true,
+            ..write(title, vec![], Exposure::None)
+        };
+
+        Ok(match function {
+            "open_escrow" => {
+                let (client, server, amount) = (a.address(0)?, a.address(1)?, a.i128(2)?);
+                let mut details = vec![self.funded_by(client)];
+                if let Some(terms_hash) = a.option(3, scval::to_bytes32)? {
+                    details.push(self.fact(
+                        "escrow.detail.terms",
+                        [("hash", V::Text(hex::encode(terms_hash)))],
+                        |v| format!("Accepts terms of service {}", v[0]),
+                    ));
+                }
+                write(
+                    self.fact(
+                        "escrow.open_escrow",
+                        [
+                            ("server", V::Address(server)),
+                            ("amount", V::Amount(amount)),
+                        ],
+                        |v| format!("Open an escrow with {} holding {}", v[0], v[1]),
+                    ),
+                    details,
+                    Exposure::UpTo(amount),
+                )
+            }
+            "clone_escrow_config" => {
+                let (source, server, amount) = (a.u64(0)?, a.address(1)?, a.i128(2)?);
+                write(
+                    self.fact(
+                        "escrow.clone_escrow_config",
+                        [
+                            ("server", V::Address(server)),
+                            ("amount", V::Amount(amount)),
+                            ("source", V::Escrow(source)),
+                        ],
+                        |v| {
+                            format!(
+                                "Open an escrow with {} holding {}, configured as {}",
+                                v[0], v[1], v[2]
+                            )
+                        },
+                    ),
+                    vec![],
+                    Exposure::UpTo(amount),
+                )
+            }
+            "open_sponsored_escrow" => {
+                let (client, amount) = (a.address(1)?, a.i128(2)?);
+                write(
+                    self.fact(
+                        "escrow.open_sponsored_escrow",
+                        [
+                            ("client", V::Address(client)),
+                            ("amount", V::Amount(amount)),
+                        ],
+                        |v| format!("Sponsor a trial escrow for {} holding {}", v[0], v[1]),
+                    ),
+                    vec![],
+                    Exposure::UpTo(amount),
+                )
+            }
+            "split_escrow" => {
+                let (escrow_id, amount) = (a.u64(0)?, a.i128(1)?);
+                let metadata = a.decode(scval::to_bytes(a.get(2)?))?;
+                let mut details = vec![];
+                if !metadata.is_empty() {
+                    let metadata = String::from_utf8_lossy(&metadata).into_owned();
+                    details.push(self.fact(
+                        "escrow.detail.split_metadata",
+                        [("metadata", V::Text(metadata))],
+                        |v| format!("Labelled \"{}\"", v[0]),
+                    ));
+                }
+                // The funds stay between the same parties
+                write(
+                    self.fact(
+                        "escrow.split_escrow",
+                        [
+                            ("amount", V::Amount(amount)),
+                            ("escrow", V::Escrow(escrow_id)),
+                        ],
+                        |v| format!("Move {} from {} to a new escrow", v[0], v[1]),
+                    ),
+                    details,
+                    Exposure::None,
+                )
+            }
+            "deposit" => {
+                let (escrow_id, amount) = (a.u64(0)?, a.i128(1)?);
+                write(
+                    self.fact(
+                        "escrow.deposit",
+                        [
+                            ("amount", V::Amount(amount)),
+                            ("escrow", V::Escrow(escrow_id)),
+                        ],
+                        |v| format!("Deposit {} into {}", v[0], v[1]),
+                    ),
+                    vec![],
+                    Exposure::UpTo(amount),
+                )
+            }
+            "claim_pending_deposit" => {
+                let (escrow_id, from, amount) = (a.u64(0)?, a.address(1)?, a.i128(2)?);
+                let memo_hash = a.bytes32(3)?;
+                write(
+                    self.fact(
+                        "escrow.claim_pending_deposit",
+                        [
+                            ("escrow", V::Escrow(escrow_id)),
+                            ("amount", V::Amount(amount)),
+                            ("from", V::Address(from)),
+                        ],
+                        |v| format!("Credit {} with {} transferred by {}", v[0], v[1], v[2]),
+                    ),
+                    vec![self.fact(
+                        "escrow.detail.transfer",
+                        [("hash", V::Text(hex::encode(memo_hash)))],
+                        |v| format!("Transfer {}", v[0]),
+                    )],
+                    Exposure::None,
+                )
+            }
+            "record_direct_payment" => {
+                let (server, token, amount) = (a.address(1)?, a.address(2)?, a.i128(3)?);
+                write(
+                    self.fact(
+                        "escrow.record_direct_payment",
+                        [
+                            ("amount", V::Amount(amount)),
+                            ("server", V::Address(server)),
+                        ],
+                        |v| format!("Pay {} directly to {}", v[0], v[1]),
+                    ),
+                    vec![
+                        self.fact("escrow.detail.token", [("token", V::Address(token))], |v| {
+                            format!("Transfers token {}", v[0])
+                        }),
+                    ],
+                    Exposure::UpTo(amount),
+                )
+            }
+            "create_payment" => {
+                let (escrow_id, amount) = (a.u64(0)?, a.i128(1)?);
+                write(
+                    self.fact(
+                        "escrow.create_payment",
+                        [
+                            ("amount", V::Amount(amount)),
+                            ("escrow", V::Escrow(escrow_id)),
+                        ],
+                        |v| format!("Charge {} from {}", v[0], v[1]),
+                    ),
+                    vec![],
+                    Exposure::UpTo(amount),
+                )
+            }
+            "create_payments" => {
+                let intents = a.vec(0)?;
+                let mut total: i128 = 0;
+                for intent in intents {
+                    let intent = a.decode(Fields::new(intent))?;
+                    let amount = a.decode(intent.get("amount").and_then(scval::to_i128))?;
+                    total = total.saturating_add(amount);
+                }
+                write(
+                    self.fact(
+                        "escrow.create_payments",
+                        [
+                            ("count", V::Count(intents.len() as u64)),
+                            ("amount", V::Amount(total)),
+                        ],
+                        |v| format!("Charge {} payments totalling {}", v[0], v[1]),
+                    ),
+                    vec![],
+                    Exposure::UpTo(total),
+                )
+            }
+            "create_authorized_payment" => {
+                let authorization = a.fields(0)?;
+                let escrow_id = a.decode(authorization.get("escrow_id").and_then(scval::to_u64))?;
+                let amount = a.decode(authorization.get("amount").and_then(scval::to_i128))?;
+                let signer = ed25519::PublicKey(a.bytes32(1)?).to_string();
+                write(
+                    self.fact(
+                        "escrow.create_authorized_payment",
+                        [
+                            ("amount", V::Amount(amount)),
+                            ("escrow", V::Escrow(escrow_id)),
+                            ("signer", V::Address(signer)),
+                        ],
+                        |v| format!("Charge {} from {} as authorized by {}", v[0], v[1], v[2]),
+                    ),
+                    a.authorization_details(self, &authorization)?,
+                    Exposure::UpTo(amount),
+                )
+            }
+            "open_and_authorize" => {
+                let (client, server, amount) = (a.address(0)?, a.address(1)?, a.i128(2)?);
+                let authorization = a.fields(4)?;
+                let payment = a.decode(authorization.get("amount").and_then(scval::to_i128))?;
+                let mut details = vec![self.funded_by(client)];
+                if let Some(terms_hash) = a.option(3, scval::to_bytes32)? {
+                    details.push(self.fact(
+                        "escrow.detail.terms",
+                        [("hash", V::Text(hex::encode(terms_hash)))],
+                        |v| format!("Accepts terms of service {}", v[0]),
+                    ));
+                }
+                details.extend(a.authorization_details(self, &authorization)?);
+                write(
+                    self.fact(
+                        "escrow.open_and_authorize",
+                        [
+                            ("server", V::Address(server)),
+                            ("amount", V::Amount(amount)),
+                            ("payment", V::Amount(payment)),
+                        ],
+                        |v| {
+                            format!(
+                                "Open an escrow with {} holding {}, and pay {} from it",
+                                v[0], v[1], v[2]
+                            )
+                        },
+                    ),
+                    details,
+                    Exposure::UpTo(amount),
+                )
+            }
+            "get_preauthorized_payment" => read(self.fact(
+                "escrow.get_preauthorized_payment",
+                [
+                    ("escrow", V::Escrow(a.u64(0)?)),
+                    ("nonce", V::Text(a.u64(1)?.to_string())),
+                ],
+                |v| format!("Read the payment created for nonce {} of {}", v[1], v[0]),
+            )),
+            "create_payment_usd" => {
+                let (escrow_id, usd_cents, resource) = (a.u64(0)?, a.i128(1)?, a.string(2)?);
+                write(
+                    self.fact(
+                        "escrow.create_payment_usd",
+                        [
+                            ("usd", V::UsdCents(usd_cents)),
+                            ("escrow", V::Escrow(escrow_id)),
+                            ("resource", V::Text(resource)),
+                        ],
+                        |v| format!("Charge {} worth from {} for {}", v[0], v[1], v[2]),
+                    ),
+                    vec![self.fact("escrow.detail.oracle_priced", [], |_| {
+                        "Converted at the price feed's rate when charged".into()
+                    })],
+                    Exposure::OnChain,
+                )
+            }
+            "settle_payment" => {
+                let settlement = a.fields(0)?;
+                let payment_id = a.decode(settlement.get("payment_id").and_then(scval::to_u64))?;
+                let escrow_id = a.decode(settlement.get("escrow_id").and_then(scval::to_u64))?;
+                let amount = a.decode(settlement.get("amount").and_then(scval::to_i128))?;
+                write(
+                    self.fact(
+                        "escrow.settle_payment",
+                        [
+                            ("payment", V::Payment(payment_id)),
+                            ("amount", V::Amount(amount)),
+                            ("escrow", V::Escrow(escrow_id)),
+                        ],
+                        |v| format!("Settle {} of {} from {}", v[0], v[1], v[2]),
+                    ),
+                    vec![],
+                    Exposure::OnChain,
+                )
+            }
+            "settle_payments" => {
+                let count = a.vec(0)?.len() as u64;
+                write(
+                    self.fact(
+                        "escrow.settle_payments",
+                        [("count", V::Count(count))],
+                        |v| format!("Settle {} payments", v[0]),
+                    ),
+                    vec![],
+                    Exposure::OnChain,
+                )
+            }
+            "refund_payment" => {
+                let (payment_id, amount) = (a.u64(0)?, a.i128(1)?);
+                write(
+                    self.fact(
+                        "escrow.refund_payment",
+                        [
+                            ("amount", V::Amount(amount)),
+                            ("payment", V::Payment(payment_id)),
+                        ],
+                        |v| format!("Refund {} of {} to its escrow", v[0], v[1]),
+                    ),
+                    vec![],
+                    Exposure::UpTo(amount),
+                )
+            }
+            "client_close_escrow" | "server_close_escrow" => {
+                let escrow_id = a.u64(0)?;
+                write(
+                    self.fact(
+                        if function == "client_close_escrow" {
+                            "escrow.client_close_escrow"
+                        } else {
+                            "escrow.server_close_escrow"
+                        },
+                        [("escrow", V::Escrow(escrow_id))],
+                        |v| format!("Agree to close {}", v[0]),
+                    ),
+                    vec![self.fact("escrow.detail.close_refund", [], |_| {
+                        "Its balance returns to the client once both parties agreed".into()
+                    })],
+                    Exposure::OnChain,
+                )
+            }
+            "consent_to_migration" => {
+                let (escrow_id, party, target) = (a.u64(0)?, a.address(1)?, a.address(2)?);
+                write(
+                    self.fact(
+                        "escrow.consent_to_migration",
+                        [
+                            ("escrow", V::Escrow(escrow_id)),
+                            ("target", V::Address(target)),
+                        ],
+                        |v| format!("Agree to move {} to contract {}", v[0], v[1]),
+                    ),
+                    vec![
+                        self.fact("escrow.detail.party", [("party", V::Address(party))], |v| {
+                            format!("Agreed as {}", v[0])
+                        }),
+                        self.fact("escrow.detail.migration_balance", [], |_| {
+                            "Its whole balance moves once both parties agreed".into()
+                        }),
+                    ],
+                    Exposure::OnChain,
+                )
+            }
+            "post_note" => {
+                let (escrow_id, author) = (a.u64(0)?, a.address(1)?);
+                let body = a.decode(scval::to_bytes(a.get(2)?))?;
+                write(
+                    self.fact(
+                        "escrow.post_note",
+                        [
+                            ("escrow", V::Escrow(escrow_id)),
+                            ("note", V::Text(String::from_utf8_lossy(&body).into_owned())),
+                        ],
+                        |v| format!("Leave a note on {}: \"{}\"", v[0], v[1]),
+                    ),
+                    vec![self.fact(
+                        "escrow.detail.author",
+                        [("author", V::Address(author))],
+                        |v| format!("Posted as {}", v[0]),
+                    )],
+                    Exposure::None,
+                )
+            }
+            "export_for_migration" => {
+                let (admin, escrow_id) = (a.address(0)?, a.u64(1)?);
+                write(
+                    self.fact(
+                        "escrow.export_for_migration",
+                        [("escrow", V::Escrow(escrow_id))],
+                        |v| format!("Export {} to the contract its parties agreed on", v[0]),
+                    ),
+                    vec![self.as_admin(admin)],
+                    Exposure::OnChain,
+                )
+            }
+            "complete_migration" => {
+                let escrow_id = a.u64(0)?;
+                a.bytes32(1)?;
+                write(
+                    self.fact(
+                        "escrow.complete_migration",
+                        [("escrow", V::Escrow(escrow_id))],
+                        |v| format!("Close {}, moved to another contract", v[0]),
+                    ),
+                    vec![],
+                    Exposure::None,
+                )
+            }
+            "allow_migration_source" => {
+                let (admin, source) = (a.address(0)?, a.address(1)?);
+                write(
+                    self.fact(
+                        "escrow.allow_migration_source",
+                        [("source", V::Address(source))],
+                        |v| format!("Accept escrows moved from contract {}", v[0]),
+                    ),
+                    vec![self.as_admin(admin)],
+                    Exposure::None,
+                )
+            }
+            "import_escrow" => {
+                let summary = a.fields(0)?;
+                a.bytes32(1)?;
+                let source = a.decode(summary.get("source").and_then(scval::to_address))?;
+                let escrow = a.decode(summary.get("escrow").and_then(Fields::new))?;
+                let client = a.decode(escrow.get("client").and_then(scval::to_address))?;
+                let server = a.decode(escrow.get("server").and_then(scval::to_address))?;
+                let balance = a.decode(escrow.get("balance").and_then(scval::to_i128))?;
+                write(
+                    self.fact(
+                        "escrow.import_escrow",
+                        [
+                            ("client", V::Address(client)),
+                            ("server", V::Address(server)),
+                            ("balance", V::Amount(balance)),
+                            ("source", V::Address(source)),
+                        ],
+                        |v| {
+                            format!(
+                                "Import the escrow of {} with {} holding {} from contract {}",
+                                v[0], v[1], v[2], v[3]
+                            )
+                        },
+                    ),
+                    vec![],
+                    Exposure::None,
+                )
+            }
+            "set_dust_policy" => {
+                let (admin, threshold, max_idle) = (a.address(0)?, a.i128(1)?, a.u64(2)?);
+                write(
+                    self.fact(
+                        "escrow.set_dust_policy",
+                        [("threshold", V::Amount(threshold)), ("idle", V::Seconds(max_idle))],
+                        |v| {
+                            format!(
+                                "Let escrows holding less than {} and idle for {} be swept to their clients",
+                                v[0], v[1]
+                            )
+                        },
+                    ),
+                    vec![self.as_admin(admin)],
+                    Exposure::None,
+                )
+            }
+            "set_max_escrows_per_client" => {
+                let admin = a.address(0)?;
+                let title = match a.option(1, scval::to_u32)? {
+                    Some(max) => self.fact(
+                        "escrow.set_max_escrows_per_client",
+                        [("max", V::Count(max.into()))],
+                        |v| format!("Allow each client at most {} open escrows", v[0]),
+                    ),
+                    None => self.fact("escrow.remove_max_escrows_per_client", [], |_| {
+                        "Remove the cap on open escrows per client".into()
+                    }),
+                };
+                write(title, vec![self.as_admin(admin)], Exposure::None)
+            }
+            "set_refund_address" => {
+                let (client, refund_to) = (a.address(0)?, a.address(1)?);
+                write(
+                    self.fact(
+                        "escrow.set_refund_address",
+                        [
+                            ("client", V::Address(client)),
+                            ("refund_to", V::Address(refund_to)),
+                        ],
+                        |v| {
+                            format!(
+                                "Release dust swept from the escrows of {} to {}",
+                                v[0], v[1]
+                            )
+                        },
+                    ),
+                    vec![],
+                    Exposure::None,
+                )
+            }
+            "compact_escrow_index" => {
+                let client = a.address(0)?;
+                a.u32(1)?;
+                a.u32(2)?;
+                write(
+                    self.fact(
+                        "escrow.compact_escrow_index",
+                        [("client", V::Address(client))],
+                        |v| format!("Drop closed escrows from the index of {}", v[0]),
+                    ),
+                    vec![],
+                    Exposure::None,
+                )
+            }
+            "sweep_dust" => {
+                let (admin, count) = (a.address(0)?, a.vec(1)?.len() as u64);
+                write(
+                    self.fact("escrow.sweep_dust", [("count", V::Count(count))], |v| {
+                        format!("Sweep {} abandoned escrows to their clients", v[0])
+                    }),
+                    vec![self.as_admin(admin)],
+                    Exposure::OnChain,
+                )
+            }
+            "grant_agent" => {
+                let (client, agent) = (a.address(0)?, a.address(1)?);
+                let (total_cap, per_payment_cap, expires_at) = (a.i128(2)?, a.i128(3)?, a.u64(4)?);
+                write(
+                    self.fact(
+                        "escrow.grant_agent",
+                        [
+                            ("agent", V::Address(agent)),
+                            ("per_payment", V::Amount(per_payment_cap)),
+                            ("expires_at", V::Timestamp(expires_at)),
+                        ],
+                        |v| {
+                            format!(
+                                "Authorize {} to charge up to {} per payment until {}",
+                                v[0], v[1], v[2]
+                            )
+                        },
+                    ),
+                    vec![
+                        self.fact(
+                            "escrow.detail.total_cap",
+                            [("total", V::Amount(total_cap))],
+                            |v| format!("Up to {} in total", v[0]),
+                        ),
+                        self.funded_by(client),
+                    ],
+                    Exposure::UpTo(total_cap),
+                )
+            }
+            "revoke_agent" => {
+                let (client, agent) = (a.address(0)?, a.address(1)?);
+                write(
+                    self.fact(
+                        "escrow.revoke_agent",
+                        [("agent", V::Address(agent)), ("client", V::Address(client))],
+                        |v| format!("Revoke the allowance of {} from {}", v[0], v[1]),
+                    ),
+                    vec![],
+                    Exposure::None,
+                )
+            }
+            "set_max_settled_per_hour" => {
+                let escrow = V::Escrow(a.u64(0)?);
+                let title = match a.option(1, scval::to_i128)? {
+                    Some(max) => self.fact(
+                        "escrow.set_max_settled_per_hour",
+                        [("max", V::Amount(max)), ("escrow", escrow)],
+                        |v| format!("Let at most {} be settled per hour from {}", v[0], v[1]),
+                    ),
+                    None => self.fact(
+                        "escrow.remove_max_settled_per_hour",
+                        [("escrow", escrow)],
+                        |v| format!("Remove the cap on settlements per hour from {}", v[0]),
+                    ),
+                };
+                write(title, vec![], Exposure::None)
+            }
+            "acknowledge_anomaly" => write(
+                self.fact(
+                    "escrow.acknowledge_anomaly",
+                    [("escrow", V::Escrow(a.u64(0)?))],
+                    |v| format!("Resume settlements from {} paused by its cap", v[0]),
+                ),
+                vec![],
+                Exposure::None,
+            ),
+            "set_price_oracle" => {
+                let (admin, oracle) = (a.address(0)?, a.address(1)?);
+                let asset = a.asset(2)?;
+                write(
+                    self.fact(
+                        "escrow.set_price_oracle",
+                        [("oracle", V::Address(oracle)), ("asset", asset)],
+                        |v| format!("Convert USD prices with feed {} quoting {}", v[0], v[1]),
+                    ),
+                    vec![self.as_admin(admin)],
+                    Exposure::None,
+                )
+            }
+            "set_price_limits" => {
+                let (admin, max_age, max_slippage_bps) = (a.address(0)?, a.u64(1)?, a.u32(2)?);
+                write(
+                    self.fact(
+                        "escrow.set_price_limits",
+                        [("max_age", V::Seconds(max_age)), ("max_slippage", V::Bps(max_slippage_bps))],
+                        |v| {
+                            format!(
+                                "Reject prices older than {} or deviating more than {} from their average",
+                                v[0], v[1]
+                            )
+                        },
+                    ),
+                    vec![self.as_admin(admin)],
+                    Exposure::None,
+                )
+            }
+            "set_usd_price" => {
+                let (server, resource, usd_cents) = (a.address(0)?, a.string(1)?, a.i128(2)?);
+                write(
+                    self.fact(
+                        "escrow.set_usd_price",
+                        [
+                            ("resource", V::Text(resource)),
+                            ("usd", V::UsdCents(usd_cents)),
+                            ("server", V::Address(server)),
+                        ],
+                        |v| format!("Price {} at {} for {}", v[0], v[1], v[2]),
+                    ),
+                    vec![],
+                    Exposure::None,
+                )
+            }
+            "suspend_price" => {
+                let (server, resource) = (a.address(0)?, a.string(1)?);
+                write(
+                    self.fact(
+                        "escrow.suspend_price",
+                        [
+                            ("resource", V::Text(resource)),
+                            ("server", V::Address(server)),
+                        ],
+                        |v| format!("Suspend the sale of {} at {}", v[0], v[1]),
+                    ),
+                    vec![],
+                    Exposure::None,
+                )
+            }
+            "resume_price" => {
+                let (server, resource) = (a.address(0)?, a.string(1)?);
+                write(
+                    self.fact(
+                        "escrow.resume_price",
+                        [
+                            ("resource", V::Text(resource)),
+                            ("server", V::Address(server)),
+                        ],
+                        |v| format!("Resume the sale of {} at {}", v[0], v[1]),
+                    ),
+                    vec![],
+                    Exposure::None,
+                )
+            }
+            "set_promo" => {
+                let (server, resource, usd_cents) = (a.address(0)?, a.string(1)?, a.i128(2)?);
+                let (starts_at, ends_at) = (a.u64(3)?, a.u64(4)?);
+                write(
+                    self.fact(
+                        "escrow.set_promo",
+                        [
+                            ("resource", V::Text(resource)),
+                            ("usd", V::UsdCents(usd_cents)),
+                            ("starts_at", V::Timestamp(starts_at)),
+                            ("ends_at", V::Timestamp(ends_at)),
+                        ],
+                        |v| format!("Discount {} to {} from {} until {}", v[0], v[1], v[2], v[3]),
+                    ),
+                    vec![self.fact(
+                        "escrow.detail.server",
+                        [("server", V::Address(server))],
+                        |v| format!("For {}", v[0]),
+                    )],
+                    Exposure::None,
+                )
+            }
+            "is_deposit_claimed" => read(self.fact(
+                "escrow.is_deposit_claimed",
+                [("hash", V::Text(hex::encode(a.bytes32(0)?)))],
+                |v| format!("Check whether transfer {} was credited", v[0]),
+            )),
+            "get_refunded" => read(self.fact(
+                "escrow.get_refunded",
+                [("payment", V::Payment(a.u64(0)?))],
+                |v| format!("Read the amount refunded of {}", v[0]),
+            )),
+            "get_escrow" => read(self.fact(
+                "escrow.get_escrow",
+                [("escrow", V::Escrow(a.u64(0)?))],
+                |v| format!("Read {}", v[0]),
+            )),
+            "get_escrow_balance" => read(self.fact(
+                "escrow.get_escrow_balance",
+                [("escrow", V::Escrow(a.u64(0)?))],
+                |v| format!("Read the balance of {}", v[0]),
+            )),
+            "get_escrow_links" => read(self.fact(
+                "escrow.get_escrow_links",
+                [("escrow", V::Escrow(a.u64(0)?))],
+                |v| format!("Read the escrows split from or into {}", v[0]),
+            )),
+            "get_payment" => read(self.fact(
+                "escrow.get_payment",
+                [("payment", V::Payment(a.u64(0)?))],
+                |v| format!("Read {}", v[0]),
+            )),
+            "get_quote_total" => read(self.fact(
+                "escrow.get_quote_total",
+                [("escrow", V::Escrow(a.u64(0)?))],
+                |v| format!("Read the USD equivalent of the payments of {}", v[0]),
+            )),
+            "get_payments" => {
+                let escrow_id = a.u64(0)?;
+                a.option(1, |status| scval::to_vec(status).map(|_| ()))?;
+                a.u32(2)?;
+                a.u32(3)?;
+                read(self.fact(
+                    "escrow.get_payments",
+                    [("escrow", V::Escrow(escrow_id))],
+                    |v| format!("List the payments of {}", v[0]),
+                ))
+            }
+            "get_direct_payments" => {
+                let server = a.address(0)?;
+                a.option(1, |status| scval::to_vec(status).map(|_| ()))?;
+                a.u32(2)?;
+                a.u32(3)?;
+                read(self.fact(
+                    "escrow.get_direct_payments",
+                    [("server", V::Address(server))],
+                    |v| format!("List the direct payments to {}", v[0]),
+                ))
+            }
+            "export_escrows" => {
+                let server = a.option(0, scval::to_address)?;
+                a.u64(1)?;
+                a.u32(2)?;
+                read(match server {
+                    Some(server) => self.fact(
+                        "escrow.export_escrows",
+                        [("server", V::Address(server))],
+                        |v| format!("List the open escrows with {}", v[0]),
+                    ),
+                    None => self.fact("escrow.export_all_escrows", [], |_| {
+                        "List the open escrows".into()
+                    }),
+                })
+            }
+            "find_escrow" => read(self.fact(
+                "escrow.find_escrow",
+                [
+                    ("client", V::Address(a.address(0)?)),
+                    ("server", V::Address(a.address(1)?)),
+                ],
+                |v| format!("Look up the escrow of {} with {}", v[0], v[1]),
+            )),
+            "get_escrow_index" => {
+                let client = a.address(0)?;
+                let archived = a.decode(scval::to_bool(a.get(1)?))?;
+                a.u32(2)?;
+                read(if archived {
+                    self.fact(
+                        "escrow.get_archived_escrows",
+                        [("client", V::Address(client))],
+                        |v| format!("List the closed escrows of {}", v[0]),
+                    )
+                } else {
+                    self.fact(
+                        "escrow.get_escrow_index",
+                        [("client", V::Address(client))],
+                        |v| format!("List the open escrows of {}", v[0]),
+                    )
+                })
+            }
+            "get_notes" => read(self.fact(
+                "escrow.get_notes",
+                [("escrow", V::Escrow(a.u64(0)?))],
+                |v| format!("Read the notes left on {}", v[0]),
+            )),
+            "get_migration" => read(self.fact(
+                "escrow.get_migration",
+                [("escrow", V::Escrow(a.u64(0)?))],
+                |v| format!("Read the export of {}", v[0]),
+            )),
+            "get_dust_policy" => read(self.fact("escrow.get_dust_policy", [], |_| {
+                "Read which escrows may be swept".into()
+            })),
+            "initialize" => write(
+                self.fact(
+                    "escrow.initialize",
+                    [("admin", V::Address(a.address(0)?))],
+                    |v| format!("Make {} the contract admin", v[0]),
+                ),
+                vec![],
+                Exposure::None,
+            ),
+            "version" => {
+                read(self.fact("escrow.version", [], |_| "Read the contract version".into()))
+            }
+            "get_max_escrows_per_client" => {
+                read(self.fact("escrow.get_max_escrows_per_client", [], |_| {
+                    "Read the cap on open escrows per client".into()
+                }))
+            }
+            "set_clock_skew" => {
+                let (admin, seconds) = (a.address(0)?, a.u64(1)?);
+                write(
+                    self.fact(
+                        "escrow.set_clock_skew",
+                        [("skew", V::Seconds(seconds))],
+                        |v| format!("Accept authorizations up to {} past their expiry", v[0]),
+                    ),
+                    vec![self.as_admin(admin)],
+                    Exposure::None,
+                )
+            }
+            "get_clock_skew" => read(self.fact("escrow.get_clock_skew", [], |_| {
+                "Read the clock skew tolerance".into()
+            })),
+            "get_client_escrow_count" => read(self.fact(
+                "escrow.get_client_escrow_count",
+                [("client", V::Address(a.address(0)?))],
+                |v| format!("Count the open escrows of {}", v[0]),
+            )),
+            "set_terms" => {
+                let (server, terms_hash) = (a.address(0)?, a.bytes32(1)?);
+                write(
+                    self.fact(
+                        "escrow.set_terms",
+                        [
+                            ("server", V::Address(server)),
+                            ("hash", V::Text(hex::encode(terms_hash))),
+                        ],
+                        |v| format!("Publish terms of service {1} of {0}", v[0], v[1]),
+                    ),
+                    vec![],
+                    Exposure::None,
+                )
+            }
+            "get_terms" => read(self.fact(
+                "escrow.get_terms",
+                [("server", V::Address(a.address(0)?))],
+                |v| format!("Read the terms of service of {}", v[0]),
+            )),
+            "set_notification_url" => {
+                let server = a.address(0)?;
+                let url = a.decode(scval::to_bytes(a.get(1)?))?;
+                let fact = if url.is_empty() {
+                    self.fact(
+                        "escrow.remove_notification_url",
+                        [("server", V::Address(server))],
+                        |v| format!("Stop delivering notifications of {}", v[0]),
+                    )
+                } else {
+                    self.fact(
+                        "escrow.set_notification_url",
+                        [
+                            ("server", V::Address(server)),
+                            ("url", V::Text(String::from_utf8_lossy(&url).into_owned())),
+                        ],
+                        |v| format!("Deliver notifications of {} to {}", v[0], v[1]),
+                    )
+                };
+                write(fact, vec![], Exposure::None)
+            }
+            "get_notification_url" => read(self.fact(
+                "escrow.get_notification_url",
+                [("server", V::Address(a.address(0)?))],
+                |v| format!("Read where notifications of {} are delivered", v[0]),
+            )),
+            "get_refund_address" => read(self.fact(
+                "escrow.get_refund_address",
+                [("client", V::Address(a.address(0)?))],
+                |v| format!("Read where dust of {} is released to", v[0]),
+            )),
+            "get_last_activity" => read(self.fact(
+                "escrow.get_last_activity",
+                [("escrow", V::Escrow(a.u64(0)?))],
+                |v| format!("Read when {} was last active", v[0]),
+            )),
+            "get_allowance" => read(self.fact(
+                "escrow.get_allowance",
+                [
+                    ("agent", V::Address(a.address(1)?)),
+                    ("client", V::Address(a.address(0)?)),
+                ],
+                |v| format!("Read the allowance of {} from {}", v[0], v[1]),
+            )),
+            "get_spend_limit" => read(self.fact(
+                "escrow.get_spend_limit",
+                [("escrow", V::Escrow(a.u64(0)?))],
+                |v| format!("Read the cap on settlements per hour from {}", v[0]),
+            )),
+            "verify_authorization" => {
+                let authorization = a.fields(0)?;
+                let escrow_id = a.decode(authorization.get("escrow_id").and_then(scval::to_u64))?;
+                let amount = a.decode(authorization.get("amount").and_then(scval::to_i128))?;
+                a.bytes32(1)?;
+                read(self.fact(
+                    "escrow.verify_authorization",
+                    [
+                        ("amount", V::Amount(amount)),
+                        ("escrow", V::Escrow(escrow_id)),
+                    ],
+                    |v| format!("Check an authorization to charge {} from {}", v[0], v[1]),
+                ))
+            }
+            "verify_voucher" => {
+                let voucher = a.fields(0)?;
+                let escrow_id = a.decode(voucher.get("escrow_id").and_then(scval::to_u64))?;
+                let amount = a.decode(voucher.get("amount").and_then(scval::to_i128))?;
+                a.bytes32(1)?;
+                read(self.fact(
+                    "escrow.verify_voucher",
+                    [
+                        ("amount", V::Amount(amount)),
+                        ("escrow", V::Escrow(escrow_id)),
+                    ],
+                    |v| format!("Check a voucher for {} in total from {}", v[0], v[1]),
+                ))
+            }
+            "verify_channel_state" => {
+                let state = a.fields(0)?;
+                let escrow_id = a.decode(state.get("escrow_id").and_then(scval::to_u64))?;
+                let balance = a.decode(state.get("balance").and_then(scval::to_i128))?;
+                a.bytes32(1)?;
+                read(self.fact(
+                    "escrow.verify_channel_state",
+                    [
+                        ("escrow", V::Escrow(escrow_id)),
+                        ("balance", V::Amount(balance)),
+                    ],
+                    |v| format!("Check a state of {} leaving {} to the client", v[0], v[1]),
+                ))
+            }
+            "get_price_oracle" => read(self.fact("escrow.get_price_oracle", [], |_| {
+                "Read the price feed".into()
+            })),
+            "quote_usd" => read(self.fact(
+                "escrow.quote_usd",
+                [
+                    ("resource", V::Text(a.string(1)?)),
+                    ("server", V::Address(a.address(0)?)),
+                ],
+                |v| format!("Quote the price of {} at {}", v[0], v[1]),
+            )),
+            "quote" => read(self.fact(
+                "escrow.quote",
+                [
+                    ("resource", V::Text(a.string(1)?)),
+                    ("server", V::Address(a.address(0)?)),
+                ],
+                |v| {
+                    format!(
+                        "Quote the price of {} at {}, and whether it is on sale",
+                        v[0], v[1]
+                    )
+                },
+            )),
+            "get_promos" => read(self.fact(
+                "escrow.get_promos",
+                [
+                    ("resource", V::Text(a.string(1)?)),
+                    ("server", V::Address(a.address(0)?)),
+                ],
+                |v| format!("List the promos of {} at {}", v[0], v[1]),
+            )),
+            other => return Err(not_describable(&format!("unknown function `{other}`"))),
+        })
+    }
+
+    /// Build a fact, rendering its English text from its values
+    fn fact<const N: usize>(
+        &self,
+        key: &'static str,
+        args: [(&'static str, FactValue); N],
+        text: impl FnOnce(&[String]) -> String,
+    ) -> Fact {
+        let shown: Vec<String> = args.iter().map(|(_, value)| self.show(value)).collect();
+        Fact {
+            key,
+            text: text(&shown),
+            args: args.into(),
+        }
+    }
+
+    fn funded_by(&self, client: String) -> Fact {
+        self.fact(
+            "escrow.detail.funded_by",
+            [("client", FactValue::Address(client))],
+            |v| format!("Paid from {}", v[0]),
+        )
+    }
+
+    fn as_admin(&self, admin: String) -> Fact {
+        self.fact(
+            "escrow.detail.admin",
+            [("admin", FactValue::Address(admin))],
+            |v| format!("As contract admin {}", v[0]),
+        )
+    }
+
+    /// English rendering of a value
+    fn show(&self, value: &FactValue) -> String {
+        match value {
+            FactValue::Amount(stroops) => format!("{} {}", decimal(*stroops), self.asset),
+            FactValue::UsdCents(cents) => {
+                let sign = if *cents < 0 { "-" } else { "" };
+                let cents = cents.unsigned_abs();
+                format!("{sign}{}.{:02} USD", cents / 100, cents % 100)
+            }
+            FactValue::Address(address) => self.party(address),
+            FactValue::Escrow(escrow_id) => match self.servers.get(escrow_id) {
+                Some(server) => format!("escrow with {}", self.party(server)),
+                None => format!("escrow {escrow_id}"),
+            },
+            FactValue::Payment(payment_id) => format!("payment {payment_id}"),
+            FactValue::Count(count) => count.to_string(),
+            FactValue::Timestamp(timestamp) => format_timestamp(*timestamp),
+            FactValue::Seconds(seconds) => match seconds {
+                s if s % 86_400 == 0 && *s > 0 => format!("{} days", s / 86_400),
+                s => format!("{s} seconds"),
+            },
+            FactValue::Bps(bps) => format!("{}.{:02}%", bps / 100, bps % 100),
+            FactValue::Text(text) => text.clone(),
+        }
+    }
+
+    /// Label of an address, or the address abbreviated
+    fn party(&self, address: &str) -> String {
+        if let Some(label) = self.labels.get(address) {
+            return label.clone();
+        }
+        match (
+            address.get(..5),
+            address.get(address.len().saturating_sub(4)..),
+        ) {
+            (Some(head), Some(tail)) if address.len() > 12 => format!("{head}…{tail}"),
+            _ => address.into(),
+        }
+    }
+}
+
+/// Arguments of a call, decoded by position
+struct Args<'a> {
+    function: &'a str,
+    args: &'a [ScVal],
+}
+
+impl<'a> Args<'a> {
+    fn get(&self, index: usize) -> Result<&'a ScVal, Error> {
+        self.args.get(index).ok_or_else(|| {
+            not_describable(&format!("`{}` is missing argument {index}", self.function))
+        })
+    }
+
+    /// Reword a decoding error as the call's
+    fn decode<T>(&self, decoded: Result<T, Error>) -> Result<T, Error> {
+        decoded.map_err(|e| not_describable(&format!("`{}`: {e}", self.function)))
+    }
+
+    fn u32(&self, index: usize) -> Result<u32, Error> {
+        self.decode(scval::to_u32(self.get(index)?))
+    }
+
+    fn u64(&self, index: usize) -> Result<u64, Error> {
+        self.decode(scval::to_u64(self.get(index)?))
+    }
+
+    fn i128(&self, index: usize) -> Result<i128, Error> {
+        self.decode(scval::to_i128(self.get(index)?))
+    }
+
+    fn address(&self, index: usize) -> Result<String, Error> {
+        self.decode(scval::to_address(self.get(index)?))
+    }
+
+    fn bytes32(&self, index: usize) -> Result<[u8; 32], Error> {
+        self.decode(scval::to_bytes32(self.get(index)?))
+    }
+
+    fn vec(&self, index: usize) -> Result<&'a [ScVal], Error> {
+        self.decode(scval::to_vec(self.get(index)?))
+    }
+
+    fn fields(&self, index: usize) -> Result<Fields<'a>, Error> {
+        self.decode(Fields::new(self.get(index)?))
+    }
+
+    fn option<T>(
+        &self,
+        index: usize,
+        decode: impl FnOnce(&ScVal) -> Result<T, Error>,
+    ) -> Result<Option<T>, Error> {
+        self.decode(scval::to_option(self.get(index)?, decode))
+    }
+
+    fn string(&self, index: usize) -> Result<String, Error> {
+        match self.get(index)? {
+            ScVal::String(s) => Ok(s.to_utf8_string_lossy()),
+            other => Err(not_describable(&format!(
+                "`{}`: expected string, got {other:?}",
+                self.function
+            ))),
+        }
+    }
+
+    /// Asset quoted by a price feed, a Stellar asset contract or a ticker
+    fn asset(&self, index: usize) -> Result<FactValue, Error> {
+        let variant = self.vec(index)?;
+        match variant {
+            [ScVal::Symbol(name), ScVal::Address(address)] if name.0.as_slice() == b"Stellar" => {
+                Ok(FactValue::Address(scval::format_address(address)))
+            }
+            [ScVal::Symbol(name), ScVal::Symbol(ticker)] if name.0.as_slice() == b"Other" => {
+                Ok(FactValue::Text(ticker.to_utf8_string_lossy()))
+            }
+            _ => Err(not_describable(&format!(
+                "`{}`: expected an asset, got {variant:?}",
+                self.function
+            ))),
+        }
+    }
+
+    /// Expiry and network of a signed payment authorization
+    fn authorization_details(
+        &self,
+        describer: &Describer,
+        authorization: &Fields<'_>,
+    ) -> Result<Vec<Fact>, Error> {
+        let expires_at = self.decode(authorization.get("expires_at").and_then(scval::to_u64))?;
+        let network = match self.decode(authorization.get("network"))? {
+            ScVal::String(network) => network.to_utf8_string_lossy(),
+            other => {
+                return Err(not_describable(&format!(
+                    "`{}`: expected string, got {other:?}",
+                    self.function
+                )))
+            }
+        };
+        Ok(vec![
+            describer.fact(
+                "escrow.detail.expires_at",
+                [("expires_at", FactValue::Timestamp(expires_at))],
+                |v| format!("Valid until {}", v[0]),
+            ),
+            describer.fact(
+                "escrow.detail.network",
+                [("network", FactValue::Text(network))],
+                |v| format!("On network {}", v[0]),
+            ),
+        ])
+    }
+}
+
+fn not_describable(reason: &str) -> Error {
+    Error::NotDescribable(reason.into())
+}
+
+/// Decimal amount of stroops without trailing zeros, e.g. "1.5"
+fn decimal(stroops: i128) -> String {
+    let decimal = StellarAmount::from_stroops(stroops).to_decimal_string();
+    decimal
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// Format a unix timestamp as ISO 8601, like `ledgerClosedAt`
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (days, seconds) = (timestamp / 86_400, timestamp % 86_400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,224 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+pub use x402_errors::{ContractError, PolicyError, ProtocolError, TransportError, X402Error};
+
+/// Contract call a [`ContractError`] was returned from
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CallContext {
+    /// Contract function invoked
+    pub operation: String,
+    /// Escrow the call was about, if any
+    pub escrow_id: Option<u64>,
+    /// Payment the call was about, if any
+    pub payment_id: Option<u64>,
+}
+
+impl fmt::Display for CallContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.operation)?;
+        if let Some(escrow_id) = self.escrow_id {
+            write!(f, ", escrow {escrow_id}")?;
+        }
+        if let Some(payment_id) = self.payment_id {
+            write!(f, ", payment {payment_id}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors returned by the escrow client
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The contract rejected the invocation
+    #[error("contract error: {0} ({1})")]
+    Contract(ContractError, CallContext),
+    /// The RPC server asked to slow down (`429 Too Many Requests`)
+    #[error("rate limited by the RPC server")]
+    RateLimited {
+        /// How long to wait, when the server said
+        retry_after: Option<Duration>,
+    },
+    /// The RPC server answered with a JSON-RPC error
+    #[error("RPC error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    /// The RPC server could not be reached
+    #[error("transport error: {0}")]
+    Transport(String),
+    /// The RPC response did not have the expected shape
+    #[error("invalid RPC response: {0}")]
+    InvalidResponse(String),
+    /// Simulation failed for a reason other than a contract error
+    #[error("simulation failed: {0}")]
+    Simulation(String),
+    /// The transaction was rejected before inclusion
+    #[error("transaction {hash} rejected with status {status}")]
+    Rejected { hash: String, status: String },
+    /// The transaction was included but failed
+    #[error("transaction {hash} failed")]
+    TransactionFailed { hash: String },
+    /// The transaction was not confirmed in time
+    #[error("transaction {hash} not confirmed within {timeout:?}")]
+    Timeout { hash: String, timeout: Duration },
+    /// The signer could not produce a signature
+    #[error("signer error: {0}")]
+    Signer(String),
+    /// An address or key was not valid strkey
+    #[error("invalid address: {0}")]
+    InvalidAddress(String),
+    /// A 402 response did not offer a payment this client can make
+    #[error("invalid payment challenge: {0}")]
+    InvalidChallenge(String),
+    /// The payment was not made because of the client's spending rules
+    #[error("payment declined: {0}")]
+    PaymentDeclined(String),
+    /// The server rejected the payment, and the client could not recover
+    #[error("payment rejected: {message}")]
+    PaymentRejected {
+        /// Error the server reported
+        error: X402Error,
+        /// Description of the rejection by the server
+        message: String,
+        /// How long to wait before paying again, when the server said
+        retry_after: Option<Duration>,
+    },
+    /// The payment journal could not be written or read
+    #[error("payment journal error: {0}")]
+    Journal(String),
+    /// A pre-signed authorization entry was malformed or not validly signed
+    #[error("invalid authorization entry: {0}")]
+    InvalidAuthorization(String),
+    /// A transaction is not an escrow contract call that can be summarized
+    #[error("cannot describe operation: {0}")]
+    NotDescribable(String),
+    /// A settlement receipt could not be issued for the payment
+    #[error("cannot issue receipt: {0}")]
+    NoReceipt(String),
+    /// A settlement receipt does not match its hash or signature
+    #[error("invalid receipt: {0}")]
+    InvalidReceipt(String),
+    /// The queue of a [`crate::PaymentBatcher`] holds as many payments as
+    /// it may
+    #[error("payment queue full ({0} queued)")]
+    QueueFull(usize),
+    /// The worker of a [`crate::PaymentBatcher`] stopped
+    #[error("payment batcher stopped")]
+    BatcherStopped,
+    /// The batch a payment was part of failed as a whole
+    #[error("payment batch failed: {0}")]
+    BatchFailed(Arc<Error>),
+    #[error("XDR error: {0}")]
+    Xdr(#[from] stellar_xdr::curr::Error),
+}
+
+impl Error {
+    /// Build an error from a simulation failure message
+    ///
+    /// Contract errors appear as `Error(Contract, #N)` in the host error text.
+    ///
+    /// # Arguments
+    /// * `message` - Simulation error text
+    /// * `operation` - Contract function simulated
+    pub(crate) fn from_simulation(message: String, operation: &str) -> Self {
+        match contract_error_code(&message) {
+            Some(code) => Self::Contract(
+                ContractError::from_code(code),
+                CallContext {
+                    operation: operation.to_string(),
+                    ..CallContext::default()
+                },
+            ),
+            None => Self::Simulation(message),
+        }
+    }
+
+    /// Contract error code of the rejection, if the contract rejected the call
+    pub fn contract_error(&self) -> Option<ContractError> {
+        match self {
+            Self::Contract(error, _) => Some(*error),
+            _ => None,
+        }
+    }
+
+    /// Whether the call may succeed if retried, see [`X402Error::retryable`]
+    pub fn is_transient(&self) -> bool {
+        X402Error::from(self).retryable()
+    }
+
+    /// Settlement of a payment the contract declined, its escrow's spend
+    /// limit pausing settlements
+    pub fn settlements_paused(payment_id: u64) -> Self {
+        Self::Contract(
+            ContractError::SettlementsPaused,
+            CallContext {
+                operation: "settle_payment".into(),
+                escrow_id: None,
+                payment_id: Some(payment_id),
+            },
+        )
+    }
+
+    /// Record the escrow a rejected call was about
+    pub(crate) fn with_escrow(mut self, escrow_id: u64) -> Self {
+        if let Self::Contract(_, context) = &mut self {
+            context.escrow_id = Some(escrow_id);
+        }
+        self
+    }
+
+    /// Record the payment a rejected call was about
+    pub(crate) fn with_payment(mut self, payment_id: u64) -> Self {
+        if let Self::Contract(_, context) = &mut self {
+            context.payment_id = Some(payment_id);
+        }
+        self
+    }
+}
+
+impl From<&Error> for X402Error {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::Contract(e, _) => Self::Contract(*e),
+            Error::RateLimited { retry_after } => Self::Transport(TransportError::RateLimited {
+                retry_after: *retry_after,
+            }),
+            Error::Rpc { .. } => Self::Transport(TransportError::Rpc),
+            Error::Transport(_) => Self::Transport(TransportError::Unreachable),
+            Error::InvalidResponse(_) | Error::Xdr(_) => {
+                Self::Transport(TransportError::InvalidResponse)
+            }
+            Error::Simulation(_) => Self::Transport(TransportError::Simulation),
+            Error::Rejected { .. } => Self::Transport(TransportError::Rejected),
+            Error::TransactionFailed { .. } => Self::Transport(TransportError::TransactionFailed),
+            Error::Timeout { .. } => Self::Transport(TransportError::Timeout),
+            Error::InvalidChallenge(_) => Self::Protocol(ProtocolError::InvalidChallenge),
+            Error::InvalidAuthorization(_) => Self::Protocol(ProtocolError::InvalidAuthorization),
+            Error::InvalidReceipt(_) => Self::Protocol(ProtocolError::InvalidReceipt),
+            Error::PaymentDeclined(_) => Self::Policy(PolicyError::PaymentDeclined),
+            Error::PaymentRejected { error, .. } => error.clone(),
+            Error::QueueFull(_) => Self::Policy(PolicyError::QueueFull),
+            Error::BatchFailed(e) => Self::from(e.as_ref()),
+            Error::Signer(_)
+            | Error::InvalidAddress(_)
+            | Error::Journal(_)
+            | Error::NotDescribable(_)
+            | Error::NoReceipt(_)
+            | Error::BatcherStopped => Self::Internal,
+        }
+    }
+}
+
+impl From<Error> for X402Error {
+    fn from(error: Error) -> Self {
+        Self::from(&error)
+    }
+}
+
+fn contract_error_code(message: &str) -> Option<u32> {
+    const MARKER: &str = "Error(Contract, #";
+    let start = message.find(MARKER)? + MARKER.len();
+    let digits: String = message[start..]
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
@@ -0,0 +1,287 @@
+use std::fmt;
+
+use stellar_xdr::curr::{LedgerKey, ScVal, Transaction, TransactionExt};
+use x402_bindings::{self as bindings, Invocation};
+
+use crate::{scval, Error, Settlement};
+
+/// Escrow contract operation whose cost can be estimated
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EscrowOp {
+    OpenEscrow {
+        client: String,
+        server: String,
+        amount: i128,
+        terms_hash: Option<[u8; 32]>,
+    },
+    Deposit {
+        escrow_id: u64,
+        amount: i128,
+    },
+    ClaimPendingDeposit {
+        escrow_id: u64,
+        from: String,
+        amount: i128,
+        memo_hash: [u8; 32],
+    },
+    CreatePayment {
+        escrow_id: u64,
+        amount: i128,
+    },
+    SettlePayment {
+        settlement: Settlement,
+    },
+    SettlePayments {
+        settlements: Vec<Settlement>,
+    },
+    ClientCloseEscrow {
+        escrow_id: u64,
+    },
+    ServerCloseEscrow {
+        escrow_id: u64,
+    },
+    RecordDirectPayment {
+        client: String,
+        server: String,
+        /// Token contract transferred (C... format)
+        token: String,
+        amount: i128,
+        reference: [u8; 32],
+    },
+}
+
+impl EscrowOp {
+    /// Contract invocation performing the operation
+    ///
+    /// # Errors
+    /// * `InvalidAddress` - If an address is not valid strkey
+    pub fn invocation(&self) -> Result<Invocation, Error> {
+        Ok(match self {
+            Self::OpenEscrow {
+                client,
+                server,
+                amount,
+                terms_hash,
+            } => bindings::open_escrow(
+                scval::parse_address(client)?,
+                scval::parse_address(server)?,
+                *amount,
+                *terms_hash,
+            ),
+            Self::Deposit { escrow_id, amount } => bindings::deposit(*escrow_id, *amount),
+            Self::ClaimPendingDeposit {
+                escrow_id,
+                from,
+                amount,
+                memo_hash,
+            } => bindings::claim_pending_deposit(
+                *escrow_id,
+                scval::parse_address(from)?,
+                *amount,
+                *memo_hash,
+            ),
+            Self::CreatePayment { escrow_id, amount } => {
+                bindings::create_payment(*escrow_id, *amount)
+            }
+            Self::SettlePayment { settlement } => bindings::settle_payment(settlement.to_scval()?),
+            Self::SettlePayments { settlements } => bindings::settle_payments(
+                settlements
+                    .iter()
+                    .map(Settlement::to_scval)
+                    .collect::<Result<_, _>>()?,
+            )?,
+            Self::ClientCloseEscrow { escrow_id } => bindings::client_close_escrow(*escrow_id),
+            Self::ServerCloseEscrow { escrow_id } => bindings::server_close_escrow(*escrow_id),
+            Self::RecordDirectPayment {
+                client,
+                server,
+                token,
+                amount,
+                reference,
+            } => bindings::record_direct_payment(
+                scval::parse_address(client)?,
+                scval::parse_address(server)?,
+                scval::parse_address(token)?,
+                *amount,
+                *reference,
+            ),
+        })
+    }
+
+    /// Operation performed by a contract invocation, the inverse of
+    /// [`Self::invocation`]
+    ///
+    /// # Errors
+    /// * `InvalidResponse` - If it is not an escrow operation, or its
+    ///   arguments do not match the function's
+    pub fn from_invocation(function: &str, args: &[ScVal]) -> Result<Self, Error> {
+        let arity = |count| {
+            if args.len() == count {
+                Ok(())
+            } else {
+                Err(Error::InvalidResponse(format!(
+                    "{function} takes {count} arguments, got {}",
+                    args.len()
+                )))
+            }
+        };
+        Ok(match function {
+            "open_escrow" => {
+                arity(4)?;
+                Self::OpenEscrow {
+                    client: scval::to_address(&args[0])?,
+                    server: scval::to_address(&args[1])?,
+                    amount: scval::to_i128(&args[2])?,
+                    terms_hash: scval::to_option(&args[3], scval::to_bytes32)?,
+                }
+            }
+            "deposit" | "create_payment" => {
+                arity(2)?;
+                let (escrow_id, amount) = (scval::to_u64(&args[0])?, scval::to_i128(&args[1])?);
+                match function {
+                    "deposit" => Self::Deposit { escrow_id, amount },
+                    _ => Self::CreatePayment { escrow_id, amount },
+                }
+            }
+            "claim_pending_deposit" => {
+                arity(4)?;
+                Self::ClaimPendingDeposit {
+                    escrow_id: scval::to_u64(&args[0])?,
+                    from: scval::to_address(&args[1])?,
+                    amount: scval::to_i128(&args[2])?,
+                    memo_hash: scval::to_bytes32(&args[3])?,
+                }
+            }
+            "settle_payment" => {
+                arity(1)?;
+                Self::SettlePayment {
+                    settlement: Settlement::try_from(&args[0])?,
+                }
+            }
+            "settle_payments" => {
+                arity(1)?;
+                Self::SettlePayments {
+                    settlements: scval::to_vec(&args[0])?
+                        .iter()
+                        .map(Settlement::try_from)
+                        .collect::<Result<_, _>>()?,
+                }
+            }
+            "client_close_escrow" => {
+                arity(1)?;
+                Self::ClientCloseEscrow {
+                    escrow_id: scval::to_u64(&args[0])?,
+                }
+            }
+            "server_close_escrow" => {
+                arity(1)?;
+                Self::ServerCloseEscrow {
+                    escrow_id: scval::to_u64(&args[0])?,
+                }
+            }
+            "record_direct_payment" => {
+                arity(5)?;
+                Self::RecordDirectPayment {
+                    client: scval::to_address(&args[0])?,
+                    server: scval::to_address(&args[1])?,
+                    token: scval::to_address(&args[2])?,
+                    amount: scval::to_i128(&args[3])?,
+                    reference: scval::to_bytes32(&args[4])?,
+                }
+            }
+            other => {
+                return Err(Error::InvalidResponse(format!(
+                    "{other} is not an escrow operation"
+                )))
+            }
+        })
+    }
+
+    /// Record the escrow or payment the operation is about on a contract error
+    pub(crate) fn annotate(&self, error: Error) -> Error {
+        match self {
+            Self::Deposit { escrow_id, .. }
+            | Self::ClaimPendingDeposit { escrow_id, .. }
+            | Self::CreatePayment { escrow_id, .. }
+            | Self::ClientCloseEscrow { escrow_id }
+            | Self::ServerCloseEscrow { escrow_id } => error.with_escrow(*escrow_id),
+            Self::SettlePayment { settlement } => error.with_payment(settlement.payment_id),
+            Self::OpenEscrow { .. }
+            | Self::SettlePayments { .. }
+            | Self::RecordDirectPayment { .. } => error,
+        }
+    }
+}
+
+/// Fees and resources an operation would consume, from its simulation
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EstimateResult {
+    /// Inclusion fee bid, in stroops
+    pub inclusion_fee: u32,
+    /// Minimum resource fee reported by the simulation, in stroops
+    pub resource_fee: i64,
+    /// Fee the transaction would be submitted with, in stroops
+    pub total_fee: u32,
+    /// CPU instructions consumed
+    pub instructions: u32,
+    /// Ledger bytes read
+    pub read_bytes: u32,
+    /// Ledger bytes written
+    pub write_bytes: u32,
+    /// Ledger entries only read
+    pub read_only: Vec<LedgerKey>,
+    /// Ledger entries read and written
+    pub read_write: Vec<LedgerKey>,
+}
+
+impl EstimateResult {
+    /// Read the estimate off a transaction assembled from its simulation
+    ///
+    /// # Errors
+    /// * `InvalidResponse` - If the transaction carries no Soroban data
+    pub(crate) fn from_assembled(tx: &Transaction, inclusion_fee: u32) -> Result<Self, Error> {
+        let TransactionExt::V1(data) = &tx.ext else {
+            return Err(Error::InvalidResponse(
+                "simulation returned no Soroban data".into(),
+            ));
+        };
+        let resources = &data.resources;
+        Ok(Self {
+            inclusion_fee,
+            resource_fee: data.resource_fee,
+            total_fee: tx.fee,
+            instructions: resources.instructions,
+            read_bytes: resources.read_bytes,
+            write_bytes: resources.write_bytes,
+            read_only: resources.footprint.read_only.to_vec(),
+            read_write: resources.footprint.read_write.to_vec(),
+        })
+    }
+}
+
+/// Simulated outcome of an operation
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DryRun {
+    /// Value the contract function would return
+    pub value: ScVal,
+    /// Fees and resources the operation would consume
+    pub estimate: EstimateResult,
+}
+
+impl fmt::Display for EstimateResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} stroops ({} inclusion + {} resource), {} instructions, \
+             {} bytes read, {} bytes written, {} entries read, {} entries written",
+            self.total_fee,
+            self.inclusion_fee,
+            self.resource_fee,
+            self.instructions,
+            self.read_bytes,
+            self.write_bytes,
+            self.read_only.len(),
+            self.read_write.len(),
+        )
+    }
+}
@@ -0,0 +1,426 @@
+use std::{collections::VecDeque, time::Duration};
+
+use stellar_xdr::curr::{Limits, ReadXdr, ScSymbol, ScVal};
+
+use crate::{scval, Error, EventInfo, EventsFrom, Rpc};
+
+pub use x402_bindings::EVENT_VERSION;
+
+/// Number of events fetched per `getEvents` page by subscriptions
+pub const EVENT_PAGE_LIMIT: u32 = 100;
+
+/// Longest delay between retries of a failing `getEvents` poll
+pub const MAX_EVENT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Typed event emitted by the escrow contract
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EscrowEvent {
+    /// `open_escrow`, which does not publish the opening deposit
+    Opened {
+        escrow_id: u64,
+        client: String,
+        server: String,
+    },
+    /// `create_payment`, identifying the escrow by its parties only
+    PaymentCreated {
+        payment_id: u64,
+        client: String,
+        server: String,
+        amount: i128,
+    },
+    /// `settle_payment`, or one payment of `settle_payments`
+    PaymentSettled { payment_id: u64, amount: i128 },
+    /// `refund_payment`, returning part of a settled payment to its escrow
+    PaymentRefunded { payment_id: u64, amount: i128 },
+    /// `deposit`, or a claimed pending deposit
+    Deposited { escrow_id: u64, amount: i128 },
+    /// Trial funds of a removed sponsored escrow returned to its server,
+    /// before its `Closed` or `Swept` event
+    Reclaimed { escrow_id: u64, amount: i128 },
+    /// Closure by the second party, releasing the remaining balance
+    Closed { escrow_id: u64, released: i128 },
+    /// `sweep_dust`, releasing a dust balance
+    Swept { escrow_id: u64, balance: i128 },
+    /// `split_escrow`, moving part of the balance to a child escrow, after
+    /// the child's `Opened` event
+    Split {
+        escrow_id: u64,
+        child_escrow_id: u64,
+        amount: i128,
+    },
+    /// `record_direct_payment`, transferred at once without an escrow
+    DirectPaid {
+        payment_id: u64,
+        client: String,
+        server: String,
+        /// Token contract transferred (C... format)
+        token: String,
+        amount: i128,
+    },
+    /// `set_terms`, which escrows opened from then on must accept
+    TermsUpdated {
+        server: String,
+        terms_hash: [u8; 32],
+        /// Terms replaced, which escrows opened under them keep
+        previous: Option<[u8; 32]>,
+    },
+    /// `set_notification_url`
+    NotificationUrlUpdated {
+        server: String,
+        /// URL notifications are delivered to, None once removed
+        url: Option<String>,
+    },
+}
+
+/// Kind of an [`EscrowEvent`], for filtering subscriptions
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum EventKind {
+    Opened,
+    PaymentCreated,
+    PaymentSettled,
+    PaymentRefunded,
+    Deposited,
+    Reclaimed,
+    Closed,
+    Swept,
+    Split,
+    DirectPaid,
+    TermsUpdated,
+    NotificationUrlUpdated,
+}
+
+impl EscrowEvent {
+    /// Kind of the event
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::Opened { .. } => EventKind::Opened,
+            Self::PaymentCreated { .. } => EventKind::PaymentCreated,
+            Self::PaymentSettled { .. } => EventKind::PaymentSettled,
+            Self::PaymentRefunded { .. } => EventKind::PaymentRefunded,
+            Self::Deposited { .. } => EventKind::Deposited,
+            Self::Reclaimed { .. } => EventKind::Reclaimed,
+            Self::Closed { .. } => EventKind::Closed,
+            Self::Swept { .. } => EventKind::Swept,
+            Self::Split { .. } => EventKind::Split,
+            Self::DirectPaid { .. } => EventKind::DirectPaid,
+            Self::TermsUpdated { .. } => EventKind::TermsUpdated,
+            Self::NotificationUrlUpdated { .. } => EventKind::NotificationUrlUpdated,
+        }
+    }
+
+    /// Version of an event payload
+    ///
+    /// Payloads emitted before versioning are bare values and tuples,
+    /// version 1. Later payloads are structs carrying their `event_version`.
+    ///
+    /// # Errors
+    /// * `InvalidResponse` - If a struct payload has no valid `event_version`
+    pub fn payload_version(value: &ScVal) -> Result<u32, Error> {
+        Payload::new(value)?.version()
+    }
+
+    /// Decode an event from its topics and data
+    ///
+    /// Version 1 payloads are read by position, later ones by field name,
+    /// ignoring fields added after [`EVENT_VERSION`].
+    ///
+    /// # Returns
+    /// * None for events this SDK does not know about
+    ///
+    /// # Errors
+    /// * `InvalidResponse` - If a known event has unexpected topics or data
+    pub fn decode(topics: &[ScVal], value: &ScVal) -> Result<Option<Self>, Error> {
+        let Some(ScVal::Symbol(ScSymbol(name))) = topics.first() else {
+            return Ok(None);
+        };
+        let topic = |index: usize| {
+            topics
+                .get(index)
+                .ok_or_else(|| Error::InvalidResponse(format!("missing topic {index}")))
+        };
+
+        let payload = Payload::new(value)?;
+
+        let event = match name.as_slice() {
+            b"open" => Self::Opened {
+                escrow_id: scval::to_u64(payload.value("escrow_id")?)?,
+                client: scval::to_address(topic(1)?)?,
+                server: scval::to_address(topic(2)?)?,
+            },
+            b"pay" => {
+                let fields = payload.tuple(&["payment_id", "amount"])?;
+                Self::PaymentCreated {
+                    payment_id: scval::to_u64(fields[0])?,
+                    server: scval::to_address(topic(1)?)?,
+                    client: scval::to_address(topic(2)?)?,
+                    amount: scval::to_i128(fields[1])?,
+                }
+            }
+            b"settled" => Self::PaymentSettled {
+                payment_id: scval::to_u64(topic(1)?)?,
+                amount: scval::to_i128(payload.value("amount")?)?,
+            },
+            b"refunded" => Self::PaymentRefunded {
+                payment_id: scval::to_u64(topic(1)?)?,
+                amount: scval::to_i128(payload.value("amount")?)?,
+            },
+            b"deposit" => Self::Deposited {
+                escrow_id: scval::to_u64(topic(1)?)?,
+                amount: scval::to_i128(payload.value("amount")?)?,
+            },
+            b"reclaim" => Self::Reclaimed {
+                escrow_id: scval::to_u64(topic(1)?)?,
+                amount: scval::to_i128(payload.value("amount")?)?,
+            },
+            b"closed" => Self::Closed {
+                escrow_id: scval::to_u64(topic(1)?)?,
+                released: scval::to_i128(payload.value("released")?)?,
+            },
+            b"swept" => {
+                let fields = payload.tuple(&["client", "refund_to", "balance"])?;
+                Self::Swept {
+                    escrow_id: scval::to_u64(topic(1)?)?,
+                    balance: scval::to_i128(fields[2])?,
+                }
+            }
+            b"split" => Self::Split {
+                escrow_id: scval::to_u64(topic(1)?)?,
+                child_escrow_id: scval::to_u64(payload.value("child_escrow_id")?)?,
+                amount: scval::to_i128(payload.value("amount")?)?,
+            },
+            b"direct" => Self::DirectPaid {
+                payment_id: scval::to_u64(payload.value("payment_id")?)?,
+                client: scval::to_address(topic(1)?)?,
+                server: scval::to_address(topic(2)?)?,
+                token: scval::to_address(payload.value("token")?)?,
+                amount: scval::to_i128(payload.value("amount")?)?,
+            },
+            b"terms" => Self::TermsUpdated {
+                server: scval::to_address(topic(1)?)?,
+                terms_hash: scval::to_bytes32(payload.value("terms_hash")?)?,
+                previous: scval::to_option(payload.value("previous")?, scval::to_bytes32)?,
+            },
+            b"notify" => Self::NotificationUrlUpdated {
+                server: scval::to_address(topic(1)?)?,
+                url: scval::to_option(payload.value("url")?, |url| {
+                    String::from_utf8(scval::to_bytes(url)?)
+                        .map_err(|e| Error::InvalidResponse(format!("notification URL: {e}")))
+                })?,
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+
+    /// Decode a `getEvents` entry
+    ///
+    /// # Errors
+    /// * `InvalidResponse` - If the XDR or a known event payload is malformed
+    pub fn from_info(info: &EventInfo) -> Result<Option<Self>, Error> {
+        let topics = info
+            .topic
+            .iter()
+            .map(|topic| ScVal::from_xdr_base64(topic, Limits::none()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let value = ScVal::from_xdr_base64(&info.value, Limits::none())?;
+        Self::decode(&topics, &value)
+    }
+}
+
+/// Payload of an escrow event, in whichever version it was emitted
+enum Payload<'a> {
+    /// Bare value or tuple, emitted before payloads were versioned
+    V1(&'a ScVal),
+    /// Struct carrying its `event_version`
+    Struct(scval::Fields<'a>),
+}
+
+impl<'a> Payload<'a> {
+    fn new(value: &'a ScVal) -> Result<Self, Error> {
+        match value {
+            ScVal::Map(Some(_)) => scval::Fields::new(value).map(Self::Struct),
+            other => Ok(Self::V1(other)),
+        }
+    }
+
+    fn version(&self) -> Result<u32, Error> {
+        match self {
+            Self::V1(_) => Ok(1),
+            Self::Struct(fields) => scval::to_u32(fields.get("event_version")?),
+        }
+    }
+
+    /// Single field of the payload, the whole payload in version 1
+    fn value(&self, name: &str) -> Result<&'a ScVal, Error> {
+        match self {
+            Self::V1(value) => Ok(value),
+            Self::Struct(fields) => fields.get(name),
+        }
+    }
+
+    /// Fields of the payload, by position in version 1
+    fn tuple(&self, names: &[&str]) -> Result<Vec<&'a ScVal>, Error> {
+        match self {
+            Self::V1(value) => match value {
+                ScVal::Vec(Some(fields)) if fields.len() == names.len() => {
+                    Ok(fields.iter().collect())
+                }
+                other => Err(Error::InvalidResponse(format!(
+                    "expected ({}), got {other:?}",
+                    names.join(", ")
+                ))),
+            },
+            Self::Struct(fields) => names.iter().map(|name| fields.get(name)).collect(),
+        }
+    }
+}
+
+/// Event yielded by [`crate::EscrowClient::subscribe_events`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Emitted {
+    pub event: EscrowEvent,
+    /// Unique, ordered event ID, usable as a cursor to resume after it
+    pub id: String,
+    /// Ledger the event was emitted in
+    pub ledger: u32,
+    /// Hex-encoded hash of the emitting transaction
+    pub tx_hash: Option<String>,
+}
+
+/// Which events a subscription yields
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EventFilter {
+    /// Where the subscription starts, the latest ledger if unset
+    pub from: Option<EventsFrom>,
+    /// Kinds of events yielded, all if empty
+    pub kinds: Vec<EventKind>,
+}
+
+impl EventFilter {
+    /// Start at `ledger` rather than the latest ledger
+    pub fn from_ledger(mut self, ledger: u32) -> Self {
+        self.from = Some(EventsFrom::Ledger(ledger));
+        self
+    }
+
+    /// Resume strictly after the event with ID `cursor`
+    pub fn after(mut self, cursor: impl Into<String>) -> Self {
+        self.from = Some(EventsFrom::Cursor(cursor.into()));
+        self
+    }
+
+    /// Only yield events of `kind`, in addition to kinds already selected
+    pub fn kind(mut self, kind: EventKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    fn matches(&self, event: &EscrowEvent) -> bool {
+        self.kinds.is_empty() || self.kinds.contains(&event.kind())
+    }
+}
+
+/// Cursor-following `getEvents` poller behind a subscription
+pub(crate) struct Subscription {
+    rpc: Rpc,
+    contract_id: String,
+    filter: EventFilter,
+    from: Option<EventsFrom>,
+    last_id: Option<String>,
+    pending: VecDeque<Result<Emitted, Error>>,
+    poll_interval: Duration,
+    backoff: Duration,
+}
+
+impl Subscription {
+    pub(crate) fn new(
+        rpc: Rpc,
+        contract_id: String,
+        filter: EventFilter,
+        poll_interval: Duration,
+    ) -> Self {
+        Self {
+            rpc,
+            contract_id,
+            from: filter.from.clone(),
+            filter,
+            last_id: None,
+            pending: VecDeque::new(),
+            poll_interval,
+            backoff: poll_interval,
+        }
+    }
+
+    /// Wait for the next matching event
+    ///
+    /// RPC errors are retried with exponential backoff, so only malformed
+    /// events surface as errors.
+    pub(crate) async fn next(&mut self) -> Result<Emitted, Error> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return item;
+            }
+            match self.poll().await {
+                Ok(fetched) => {
+                    self.backoff = self.poll_interval;
+                    if fetched < EVENT_PAGE_LIMIT as usize && self.pending.is_empty() {
+                        tokio::time::sleep(self.poll_interval).await;
+                    }
+                }
+                Err(_) => {
+                    tokio::time::sleep(self.backoff).await;
+                    self.backoff = (self.backoff * 2).min(MAX_EVENT_BACKOFF);
+                }
+            }
+        }
+    }
+
+    /// Fetch one page of events into `pending`
+    ///
+    /// # Returns
+    /// * Number of events in the page
+    async fn poll(&mut self) -> Result<usize, Error> {
+        let from = match &self.from {
+            Some(from) => from.clone(),
+            None => EventsFrom::Ledger(self.rpc.get_latest_ledger().await?.sequence),
+        };
+        let page = self
+            .rpc
+            .get_events(&self.contract_id, &from, EVENT_PAGE_LIMIT)
+            .await?;
+
+        for info in &page.events {
+            // IDs are ordered, so anything not after the last one was seen
+            if self.last_id.as_ref().is_some_and(|last| info.id <= *last) {
+                continue;
+            }
+            self.last_id = Some(info.id.clone());
+            match EscrowEvent::from_info(info) {
+                Ok(Some(event)) if self.filter.matches(&event) => {
+                    self.pending.push_back(Ok(Emitted {
+                        event,
+                        id: info.id.clone(),
+                        ledger: info.ledger,
+                        tx_hash: info.tx_hash.clone(),
+                    }))
+                }
+                Ok(_) => {}
+                Err(e) => self.pending.push_back(Err(Error::InvalidResponse(format!(
+                    "event {}: {e}",
+                    info.id
+                )))),
+            }
+        }
+
+        let cursor = page
+            .cursor
+            .filter(|cursor| !cursor.is_empty())
+            .or_else(|| page.events.last().map(|event| event.id.clone()));
+        match cursor {
+            Some(cursor) => self.from = Some(EventsFrom::Cursor(cursor)),
+            // Nothing emitted yet; stay at the ledger first polled
+            None => self.from = Some(from),
+        }
+        Ok(page.events.len())
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+/// Fee multiplier a transaction must beat to replace another with the same
+/// sequence number in the stellar-core mempool
+pub const REPLACE_BY_FEE_MULTIPLIER: u32 = 10;
+
+/// Escalation of the fee of a transaction stuck pending, e.g. during surge
+/// pricing
+///
+/// A transaction pending for longer than `after` is wrapped in a fee-bump
+/// transaction paid by a dedicated fee account, bidding `multiplier` times
+/// its inclusion fee. Every further `after` the bump is replaced by one
+/// bidding `multiplier` times more, until `max_fee`. The original and its
+/// bumps share a sequence number, so at most one of them ever applies.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FeeBumpPolicy {
+    /// How long a transaction stays pending before its fee is bumped
+    pub after: Duration,
+    /// Factor each bump multiplies the inclusion fee by, at least
+    /// [`REPLACE_BY_FEE_MULTIPLIER`] for bumps to replace each other
+    pub multiplier: u32,
+    /// Most a bump pays, resource fee included, in stroops
+    pub max_fee: i64,
+}
+
+impl Default for FeeBumpPolicy {
+    fn default() -> Self {
+        Self {
+            after: Duration::from_secs(10),
+            multiplier: REPLACE_BY_FEE_MULTIPLIER,
+            max_fee: 10_000_000,
+        }
+    }
+}
+
+impl FeeBumpPolicy {
+    /// Fee of the bump replacing a transaction paying `fee`
+    ///
+    /// Only the inclusion fee is bid, per operation; a fee bump counts as an
+    /// extra operation.
+    ///
+    /// # Arguments
+    /// * `fee` - Fee of the transaction being replaced, in stroops
+    /// * `resource_fee` - Soroban resource fee included in `fee`
+    /// * `operations` - Operations of the transaction being bumped
+    /// * `bumped` - Whether the transaction being replaced is a fee bump
+    ///
+    /// # Returns
+    /// * None once the fee cannot grow without exceeding `max_fee`
+    pub fn next_fee(
+        &self,
+        fee: i64,
+        resource_fee: i64,
+        operations: i64,
+        bumped: bool,
+    ) -> Option<i64> {
+        let slots = if bumped { operations + 1 } else { operations }.max(1);
+        let rate = (fee - resource_fee).max(1) / slots;
+        let next = rate
+            .saturating_mul(self.multiplier.into())
+            .saturating_mul(operations + 1)
+            .saturating_add(resource_fee)
+            .min(self.max_fee);
+        (next > fee).then_some(next)
+    }
+}
@@ -0,0 +1,48 @@
+pub use x402_types::Finality;
+
+/// When a settlement is treated as final
+///
+/// A settlement is final once `depth` ledgers closed after the one its
+/// transaction was included in. Settlements of at most `final_up_to` are
+/// final as soon as they are included, so only larger payments wait.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FinalityPolicy {
+    /// Ledgers that must close after the including one
+    pub depth: u32,
+    /// Largest amount, in stroops, final on inclusion
+    pub final_up_to: Option<i128>,
+}
+
+impl FinalityPolicy {
+    /// Treat every settlement as final `depth` ledgers after its own
+    pub fn new(depth: u32) -> Self {
+        Self {
+            depth,
+            final_up_to: None,
+        }
+    }
+
+    /// Treat settlements of at most `amount` as final on inclusion
+    pub fn final_up_to(mut self, amount: i128) -> Self {
+        self.final_up_to = Some(amount);
+        self
+    }
+
+    /// Ledgers a settlement of `amount` waits for after its own
+    pub fn depth_for(&self, amount: i128) -> u32 {
+        match self.final_up_to {
+            Some(max) if amount <= max => 0,
+            _ => self.depth,
+        }
+    }
+
+    /// Finality of a settlement of `amount` included in ledger `included`,
+    /// when the last closed ledger is `latest`
+    pub fn finality(&self, amount: i128, included: u32, latest: u32) -> Finality {
+        if latest >= included.saturating_add(self.depth_for(amount)) {
+            Finality::Final
+        } else {
+            Finality::Included
+        }
+    }
+}
@@ -0,0 +1,866 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{
+    header::{HeaderValue, RETRY_AFTER},
+    Client, IntoUrl, Method, Request, Response, StatusCode,
+};
+use sha2::{Digest, Sha256};
+use x402_types::{
+    correlation_id, decode_payment_response_header, encode_payment_header, negotiate_version,
+    network_passphrase, EscrowPayload, PaymentErrorBody, PaymentPayload, PaymentRequiredResponse,
+    PaymentRequirements, SchemePayload, SettleResponse, StellarAmount, ESCROW_SCHEME, NATIVE_ASSET,
+    PAYMENT_HEADER, PAYMENT_RESPONSE_HEADER, X402_VERSION, X402_VERSIONS,
+};
+
+use crate::{
+    receipt_hash, scval, ChannelState, ContractError, Decision, Error, EscrowClient, EscrowOp,
+    JournalEntry, PaymentJournal, ProtocolError, SpendPolicy, X402Error,
+};
+
+/// Callback deciding whether to pay the given requirements
+pub type Inspector = Arc<dyn Fn(&PaymentRequirements) -> bool + Send + Sync>;
+
+/// Payment made for a request
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaymentReceipt {
+    /// Requirements that were paid
+    pub requirements: PaymentRequirements,
+    /// Escrow the payment was drawn from
+    pub escrow_id: u64,
+    /// Authorized amount, in stroops
+    pub amount: i128,
+    /// Nonce of the authorization
+    pub nonce: u64,
+    /// Settlement reported in the X-PAYMENT-RESPONSE header, if any
+    pub settlement: Option<SettleResponse>,
+}
+
+/// Response to a request, with the payment made for it
+#[derive(Debug)]
+pub struct PaidResponse {
+    /// Final response
+    pub response: Response,
+    /// Payment made, None if the resource was free
+    pub receipt: Option<PaymentReceipt>,
+}
+
+/// Builder for [`X402HttpClient`]
+pub struct X402HttpClientBuilder {
+    escrow: EscrowClient,
+    asset_escrows: HashMap<String, EscrowClient>,
+    network: String,
+    http: Client,
+    policy: SpendPolicy,
+    deposit: i128,
+    initial_deposit: Option<i128>,
+    inspector: Option<Inspector>,
+    confirm: Option<Inspector>,
+    sync_interval: Option<Duration>,
+    journal: Option<PaymentJournal>,
+}
+
+impl X402HttpClientBuilder {
+    /// Pay in `asset` from escrows of `escrow`'s contract, other assets
+    /// being paid from the default escrow client's
+    ///
+    /// Of requirements offered in several assets, the client pays those an
+    /// existing escrow already covers, then the policy's
+    /// [preferred assets](SpendPolicy::prefer_asset), then the first offered.
+    pub fn asset_escrow(mut self, asset: impl Into<String>, escrow: EscrowClient) -> Self {
+        self.asset_escrows.insert(asset.into(), escrow);
+        self
+    }
+
+    /// Use a preconfigured reqwest client
+    pub fn http_client(mut self, http: Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Evaluate every payment against `policy` before making it
+    pub fn policy(mut self, policy: SpendPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Amount to fund an escrow with when opening or topping it up
+    ///
+    /// The escrow always receives at least the payment amount.
+    pub fn deposit(mut self, amount: i128) -> Self {
+        self.deposit = amount;
+        self
+    }
+
+    /// Amount to open an escrow with on the first payment to a server,
+    /// the [deposit](Self::deposit) if unset
+    ///
+    /// The escrow always receives at least the payment amount, within the
+    /// policy's [deposit cap](SpendPolicy::max_deposit).
+    pub fn initial_deposit(mut self, amount: i128) -> Self {
+        self.initial_deposit = Some(amount);
+        self
+    }
+
+    /// Inspect requirements before paying, declining when `inspector` returns false
+    pub fn inspect(
+        mut self,
+        inspector: impl Fn(&PaymentRequirements) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.inspector = Some(Arc::new(inspector));
+        self
+    }
+
+    /// Confirm payments the policy asks confirmation for, declining when
+    /// `confirm` returns false
+    ///
+    /// Without a callback, such payments are declined.
+    pub fn confirm(
+        mut self,
+        confirm: impl Fn(&PaymentRequirements) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.confirm = Some(Arc::new(confirm));
+        self
+    }
+
+    /// Track escrow balances in a [`ChannelState`] per server rather than
+    /// reading them before every payment
+    ///
+    /// # Arguments
+    /// * `sync_interval` - Time between reconciliations with on-chain state
+    pub fn channel_cache(mut self, sync_interval: Duration) -> Self {
+        self.sync_interval = Some(sync_interval);
+        self
+    }
+
+    /// Record every payment in `journal`
+    ///
+    /// Requests fail if their payment cannot be recorded, so the journal
+    /// never misses a payment the client made.
+    pub fn journal(mut self, journal: PaymentJournal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Build the client
+    pub fn build(self) -> X402HttpClient {
+        // Seeded from the clock so nonces stay unique across sessions
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or_default();
+        X402HttpClient {
+            escrow: self.escrow,
+            asset_escrows: self.asset_escrows,
+            network: self.network,
+            http: self.http,
+            policy: self.policy,
+            deposit: self.deposit,
+            initial_deposit: self.initial_deposit.unwrap_or(self.deposit),
+            inspector: self.inspector,
+            confirm: self.confirm,
+            nonce: AtomicU64::new(nonce),
+            sync_interval: self.sync_interval,
+            channels: Mutex::default(),
+            journal: self.journal,
+        }
+    }
+}
+
+/// HTTP client that pays `402 Payment Required` responses from an escrow
+///
+/// On a 402 response the client picks the escrow requirements for its
+/// network, in the asset it is best placed to pay, and the highest x402
+/// version both it and the server speak, checks it against its
+/// [`SpendPolicy`], makes sure an escrow with the server
+/// holds enough funds (opening or topping it up through the
+/// [`EscrowClient`]), then retries the request with a signed `X-PAYMENT`
+/// header.
+///
+/// The first payment to a server without an escrow opens one, waiting for
+/// it to be confirmed before paying. If a concurrent request opened it in
+/// the meantime, the payment is drawn from that escrow instead.
+pub struct X402HttpClient {
+    escrow: EscrowClient,
+    asset_escrows: HashMap<String, EscrowClient>,
+    network: String,
+    http: Client,
+    policy: SpendPolicy,
+    deposit: i128,
+    initial_deposit: i128,
+    inspector: Option<Inspector>,
+    confirm: Option<Inspector>,
+    nonce: AtomicU64,
+    sync_interval: Option<Duration>,
+    channels: Mutex<HashMap<String, Arc<ChannelState>>>,
+    journal: Option<PaymentJournal>,
+}
+
+impl X402HttpClient {
+    /// Start building a client paying from escrows of the `escrow` signer
+    ///
+    /// # Arguments
+    /// * `escrow` - Escrow client signing as the paying client
+    /// * `network` - x402 network id payments are made on
+    pub fn builder(escrow: EscrowClient, network: impl Into<String>) -> X402HttpClientBuilder {
+        X402HttpClientBuilder {
+            escrow,
+            asset_escrows: HashMap::new(),
+            network: network.into(),
+            http: Client::new(),
+            policy: SpendPolicy::new(),
+            deposit: 0,
+            initial_deposit: None,
+            inspector: None,
+            confirm: None,
+            sync_interval: None,
+            journal: None,
+        }
+    }
+
+    /// Spend policy of the client, with its accounting
+    pub fn policy(&self) -> &SpendPolicy {
+        &self.policy
+    }
+
+    /// Total paid by this client, in stroops
+    pub fn spent(&self) -> i128 {
+        self.policy.spent()
+    }
+
+    /// Journal the client records its payments in, if any
+    pub fn journal(&self) -> Option<&PaymentJournal> {
+        self.journal.as_ref()
+    }
+
+    /// Cached state of the escrow with `server`, if the channel cache is
+    /// enabled and a payment was made to it
+    ///
+    /// Escrows of an [asset escrow client](X402HttpClientBuilder::asset_escrow)
+    /// are cached apart, see [`X402HttpClient::asset_channel`].
+    pub fn channel(&self, server: &str) -> Option<Arc<ChannelState>> {
+        self.channels.lock().unwrap().get(server).cloned()
+    }
+
+    /// Cached state of the escrow with `server` paying in `asset`
+    pub fn asset_channel(&self, asset: &str, server: &str) -> Option<Arc<ChannelState>> {
+        let key = self.channel_key(Some(asset), server);
+        self.channels.lock().unwrap().get(&key).cloned()
+    }
+
+    /// Reconcile every cached channel against on-chain state now
+    ///
+    /// # Errors
+    /// * The error of the first channel that could not be reconciled
+    pub async fn force_sync(&self) -> Result<(), Error> {
+        let channels: Vec<_> = self.channels.lock().unwrap().values().cloned().collect();
+        for channel in channels {
+            channel.force_sync().await?;
+        }
+        Ok(())
+    }
+
+    /// GET `url`, paying for it if required
+    pub async fn get(&self, url: impl IntoUrl) -> Result<PaidResponse, Error> {
+        let request = self
+            .http
+            .request(Method::GET, url)
+            .build()
+            .map_err(transport)?;
+        self.execute(request).await
+    }
+
+    /// Send `request`, paying for it if required
+    ///
+    /// A payment the server rejects is made again once if its
+    /// [`PaymentErrorBody`] says how to recover: after topping the escrow
+    /// up by the amount missing, or signed anew for an expired or already
+    /// used authorization.
+    ///
+    /// # Errors
+    /// * `InvalidChallenge` - If the 402 response offers no usable escrow payment
+    /// * `PaymentDeclined` - If the policy, the inspector, or the confirmation refused the payment
+    /// * `PaymentRejected` - If the server rejected the payment, and again after recovering
+    /// * `Transport` - If the request failed or its body cannot be replayed
+    /// * `Journal` - If the payment could not be recorded in the journal
+    /// * Escrow client errors from funding the escrow
+    #[tracing::instrument(
+        name = "x402.pay",
+        skip_all,
+        fields(url = %request.url(), correlation_id = tracing::field::Empty)
+    )]
+    pub async fn execute(&self, request: Request) -> Result<PaidResponse, Error> {
+        let host = request.url().host_str().unwrap_or_default().to_string();
+        let retry = request.try_clone();
+        let response = self.http.execute(request).await.map_err(transport)?;
+        if response.status() != StatusCode::PAYMENT_REQUIRED {
+            return Ok(PaidResponse {
+                response,
+                receipt: None,
+            });
+        }
+
+        let challenge: PaymentRequiredResponse = response
+            .json()
+            .await
+            .map_err(|e| Error::InvalidChallenge(e.to_string()))?;
+        let requirements = self.select(&host, challenge).await?;
+        self.record(&host, || JournalEntry::Required {
+            requirements: requirements.clone(),
+        })?;
+        let retry = retry.ok_or_else(|| Error::Transport("request cannot be retried".into()))?;
+
+        let (response, receipt) = self.send_paid(&host, &retry, requirements).await?;
+        if response.status() != StatusCode::PAYMENT_REQUIRED {
+            return Ok(PaidResponse {
+                response,
+                receipt: Some(receipt),
+            });
+        }
+        let hint = self.rejection(&host, response, &receipt).await;
+        let topup = hint
+            .required_topup_amount
+            .as_deref()
+            .and_then(|amount| amount.parse::<i128>().ok())
+            .filter(|amount| *amount > 0);
+        let requirements = match (X402Error::from_code(hint.code), topup) {
+            (Some(X402Error::Contract(ContractError::InsufficientBalance)), Some(topup)) => {
+                tracing::info!(
+                    escrow_id = receipt.escrow_id,
+                    topup,
+                    "payment rejected, topping up"
+                );
+                self.top_up(&receipt, topup).await?;
+                receipt.requirements
+            }
+            (
+                Some(
+                    X402Error::Protocol(
+                        ProtocolError::Expired
+                        | ProtocolError::NonceUsed
+                        | ProtocolError::NonceReplayed,
+                    )
+                    | X402Error::Contract(
+                        ContractError::AuthorizationExpired | ContractError::AuthorizationUsed,
+                    ),
+                ),
+                _,
+            ) => {
+                tracing::info!(code = hint.code, "payment rejected, signing again");
+                let paid = receipt.requirements;
+                let mut requirements = hint
+                    .requirements
+                    .filter(|r| {
+                        r.scheme == ESCROW_SCHEME
+                            && r.network == self.network
+                            && r.pay_to == paid.pay_to
+                    })
+                    .unwrap_or_else(|| paid.clone());
+                requirements.x402_version = paid.x402_version;
+                requirements
+            }
+            _ => return Err(rejected(hint)),
+        };
+
+        let (response, receipt) = self.send_paid(&host, &retry, requirements).await?;
+        if response.status() != StatusCode::PAYMENT_REQUIRED {
+            return Ok(PaidResponse {
+                response,
+                receipt: Some(receipt),
+            });
+        }
+        Err(rejected(self.rejection(&host, response, &receipt).await))
+    }
+
+    /// Pay `requirements` and send a copy of `request` with the payment
+    async fn send_paid(
+        &self,
+        host: &str,
+        request: &Request,
+        requirements: PaymentRequirements,
+    ) -> Result<(Response, PaymentReceipt), Error> {
+        let mut retry = request
+            .try_clone()
+            .ok_or_else(|| Error::Transport("request cannot be retried".into()))?;
+        let (header, mut receipt) = self.pay(host, requirements).await?;
+        let correlation_id = correlation_id(receipt.escrow_id, receipt.nonce);
+        tracing::Span::current().record("correlation_id", correlation_id.as_str());
+        let header = HeaderValue::from_str(&header).map_err(|e| Error::Transport(e.to_string()))?;
+        retry.headers_mut().insert(PAYMENT_HEADER, header);
+
+        let response = self.http.execute(retry).await;
+        let channel = self.cached_channel(&receipt.requirements);
+        let response = response.inspect_err(|_| {
+            // The server may or may not have settled
+            if let Some(channel) = &channel {
+                channel.mark_stale();
+            }
+        });
+        let response = response.map_err(transport)?;
+        let payment_response = response
+            .headers()
+            .get(PAYMENT_RESPONSE_HEADER)
+            .and_then(|value| value.to_str().ok());
+        let decoded = payment_response.and_then(|value| decode_payment_response_header(value).ok());
+        let charged = decoded
+            .as_ref()
+            .and_then(|header| header.amount.clone())
+            .unwrap_or_else(|| receipt.amount.to_string());
+        receipt.settlement = decoded.map(|header| header.settlement);
+        self.record(host, || JournalEntry::Settled {
+            escrow_id: receipt.escrow_id,
+            nonce: receipt.nonce,
+            amount: charged,
+            settlement: receipt.settlement.clone(),
+            receipt_hash: payment_response.map(receipt_hash),
+        })?;
+        if let Some(channel) = channel {
+            match &receipt.settlement {
+                Some(settlement) if settlement.success => channel.record_settlement(receipt.nonce),
+                // A rejected payment hints that the cached balance is off
+                Some(_) => channel.mark_stale(),
+                None if response.status() == StatusCode::PAYMENT_REQUIRED => channel.mark_stale(),
+                None => {}
+            }
+        }
+        tracing::info!(
+            escrow_id = receipt.escrow_id,
+            nonce = receipt.nonce,
+            amount = %receipt.amount,
+            status = response.status().as_u16(),
+            settled = receipt.settlement.as_ref().map(|s| s.success),
+            "payment made"
+        );
+        Ok((response, receipt))
+    }
+
+    /// Why the server answered a payment with a 402, refunding it to the
+    /// policy
+    ///
+    /// Servers describing rejections by their reason alone have the error
+    /// of the reason, and the delay of their `Retry-After` header.
+    async fn rejection(
+        &self,
+        host: &str,
+        response: Response,
+        receipt: &PaymentReceipt,
+    ) -> PaymentErrorBody {
+        self.policy.refund(host, receipt.amount);
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok()?.parse().ok());
+        let challenge = response.json::<PaymentRequiredResponse>().await.ok();
+        let reason = challenge.as_ref().and_then(|c| c.error.clone());
+        let mut hint = match challenge.and_then(|c| c.payment_error) {
+            Some(hint) => hint,
+            None => {
+                let error = reason
+                    .as_deref()
+                    .and_then(X402Error::from_reason)
+                    .unwrap_or(X402Error::Internal);
+                PaymentErrorBody {
+                    code: error.code(),
+                    message: reason.unwrap_or_else(|| error.to_string()),
+                    retry_after: None,
+                    required_topup_amount: None,
+                    requirements: None,
+                }
+            }
+        };
+        hint.retry_after = hint.retry_after.or(retry_after);
+        tracing::warn!(code = hint.code, message = %hint.message, "payment rejected");
+        hint
+    }
+
+    /// Deposit at least `amount` into the escrow of a payment rejected for
+    /// want of funds
+    async fn top_up(&self, receipt: &PaymentReceipt, amount: i128) -> Result<(), Error> {
+        let top_up = self.policy.cap_deposit(self.deposit.max(amount), amount)?;
+        let deposited = self
+            .escrow_for(&receipt.requirements)
+            .deposit(receipt.escrow_id, top_up)
+            .await;
+        if let Some(channel) = self.cached_channel(&receipt.requirements) {
+            match &deposited {
+                Ok(_) => channel.record_deposit(top_up),
+                Err(_) => channel.mark_stale(),
+            }
+        }
+        deposited.map(|_| ())
+    }
+
+    /// Append the entry built by `entry` to the journal, if there is one
+    fn record(&self, host: &str, entry: impl FnOnce() -> JournalEntry) -> Result<(), Error> {
+        match &self.journal {
+            Some(journal) => journal.record(host, entry()),
+            None => Ok(()),
+        }
+    }
+
+    /// Pick the escrow requirements for this client's network
+    ///
+    /// Of requirements in several assets, those the policy allows come
+    /// first, then those an existing escrow covers, then by the policy's
+    /// asset preferences, then as offered. The requirements are returned
+    /// with the x402 version to pay them with.
+    async fn select(
+        &self,
+        host: &str,
+        challenge: PaymentRequiredResponse,
+    ) -> Result<PaymentRequirements, Error> {
+        let Some(version) = negotiate_version(&challenge.versions()) else {
+            return Err(Error::InvalidChallenge(format!(
+                "no supported x402 version in {:?}",
+                challenge.versions()
+            )));
+        };
+        let mut offers: Vec<PaymentRequirements> = challenge
+            .accepts
+            .iter()
+            .filter(|r| r.scheme == ESCROW_SCHEME && r.network == self.network)
+            .filter(|r| r.x402_version.is_none_or(|v| X402_VERSIONS.contains(&v)))
+            .flat_map(PaymentRequirements::expand)
+            .collect();
+        if offers.is_empty() {
+            return Err(Error::InvalidChallenge(format!(
+                "no escrow payment on {}",
+                self.network
+            )));
+        }
+        let mut best = 0;
+        if offers.len() > 1 {
+            let mut ranks = Vec::with_capacity(offers.len());
+            for (index, offer) in offers.iter().enumerate() {
+                let amount = parse_amount(offer).unwrap_or_default();
+                let denied = matches!(self.policy.evaluate(host, amount), Decision::Deny(_));
+                let funded = self.is_funded(offer, amount).await;
+                let preference = self.policy.asset_preference(asset_of(offer));
+                ranks.push((denied, !funded, preference, index));
+            }
+            best = ranks.iter().min().map_or(0, |rank| rank.3);
+        }
+        let mut requirements = offers.swap_remove(best);
+        requirements.x402_version.get_or_insert(version);
+        if let Some(inspector) = &self.inspector {
+            if !inspector(&requirements) {
+                return Err(Error::PaymentDeclined("declined by inspector".into()));
+            }
+        }
+        Ok(requirements)
+    }
+
+    /// Whether an existing escrow with the server holds `amount` in the
+    /// asset of `requirements`, from the cached channel if there is one
+    async fn is_funded(&self, requirements: &PaymentRequirements, amount: i128) -> bool {
+        if let Some(channel) = self.cached_channel(requirements) {
+            return channel.available() >= amount;
+        }
+        let escrow = self.escrow_for(requirements);
+        let Ok(Some(escrow_id)) = escrow
+            .find_escrow(&escrow.address(), &requirements.pay_to)
+            .await
+        else {
+            return false;
+        };
+        escrow
+            .get_escrow_balance(escrow_id)
+            .await
+            .is_ok_and(|balance| balance >= amount)
+    }
+
+    /// Escrow client paying in the asset of `requirements`
+    fn escrow_for(&self, requirements: &PaymentRequirements) -> &EscrowClient {
+        self.asset_escrows
+            .get(asset_of(requirements))
+            .unwrap_or(&self.escrow)
+    }
+
+    /// Key of the cached channel with `server`, the server itself for the
+    /// default escrow client
+    fn channel_key(&self, asset: Option<&str>, server: &str) -> String {
+        match asset.filter(|asset| self.asset_escrows.contains_key(*asset)) {
+            Some(asset) => format!("{asset}:{server}"),
+            None => server.into(),
+        }
+    }
+
+    /// Cached channel paying `requirements`, if any
+    fn cached_channel(&self, requirements: &PaymentRequirements) -> Option<Arc<ChannelState>> {
+        let key = self.channel_key(Some(asset_of(requirements)), &requirements.pay_to);
+        self.channels.lock().unwrap().get(&key).cloned()
+    }
+
+    /// Fund the escrow and sign the payment header
+    async fn pay(
+        &self,
+        host: &str,
+        requirements: PaymentRequirements,
+    ) -> Result<(String, PaymentReceipt), Error> {
+        let amount = parse_amount(&requirements)?;
+        self.policy.reserve(host, amount, || {
+            self.confirm
+                .as_ref()
+                .is_some_and(|confirm| confirm(&requirements))
+        })?;
+
+        let paid = self.authorize(&requirements, amount).await;
+        if paid.is_err() {
+            self.policy.refund(host, amount);
+        }
+        let (escrow_id, nonce, payload) = paid?;
+        self.record(host, || JournalEntry::Signed {
+            escrow_id,
+            nonce,
+            amount: amount.to_string(),
+            payload: payload.clone(),
+        })?;
+        let header = encode_payment_header(&PaymentPayload {
+            x402_version: requirements.x402_version.unwrap_or(X402_VERSION),
+            scheme: ESCROW_SCHEME.into(),
+            network: self.network.clone(),
+            asset: requirements.asset.clone(),
+            payload: SchemePayload::Escrow(payload),
+            extensions: Default::default(),
+        });
+        Ok((
+            header,
+            PaymentReceipt {
+                requirements,
+                escrow_id,
+                amount,
+                nonce,
+                settlement: None,
+            },
+        ))
+    }
+
+    async fn authorize(
+        &self,
+        requirements: &PaymentRequirements,
+        amount: i128,
+    ) -> Result<(u64, u64, EscrowPayload), Error> {
+        let escrow = self.escrow_for(requirements);
+        let nonce = self.nonce.fetch_add(1, Ordering::Relaxed);
+        let expires_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+            + requirements.max_timeout_seconds;
+        let (escrow_id, channel, opened) = match self.cached_channel(requirements) {
+            Some(channel) => {
+                self.fund_channel(escrow, &channel, amount).await?;
+                (channel.escrow_id(), Some(channel), None)
+            }
+            None => {
+                let (escrow_id, opened) = self
+                    .fund(escrow, &requirements.pay_to, amount, nonce, expires_at)
+                    .await?;
+                let channel = self.open_channel(escrow, requirements, escrow_id).await?;
+                (escrow_id, channel, opened)
+            }
+        };
+
+        let payload = match opened {
+            Some(payload) => payload,
+            None => {
+                escrow
+                    .authorize_payment(escrow_id, amount, nonce, expires_at, &self.network)
+                    .await?
+            }
+        };
+        if let Some(channel) = channel {
+            channel.record_payment(nonce, amount, expires_at);
+        }
+        Ok((escrow_id, nonce, payload))
+    }
+
+    /// Make sure an escrow with `server` holds `amount`, reading its balance
+    /// on-chain
+    ///
+    /// A missing escrow is opened in the same transaction as the payment of
+    /// `amount`, authorized with `nonce` until `expires_at`, on the networks
+    /// x402 names. The contract cannot check authorizations for others,
+    /// e.g. a local network, where the escrow is opened on its own.
+    ///
+    /// # Returns
+    /// * Escrow ID, and the payment's authorization if it was created with
+    ///   the escrow
+    async fn fund(
+        &self,
+        escrow: &EscrowClient,
+        server: &str,
+        amount: i128,
+        nonce: u64,
+        expires_at: u64,
+    ) -> Result<(u64, Option<EscrowPayload>), Error> {
+        let client = escrow.address();
+        let escrow_id = match escrow.find_escrow(&client, server).await? {
+            Some(escrow_id) => escrow_id,
+            None => {
+                let funding = self
+                    .policy
+                    .cap_deposit(self.initial_deposit.max(amount), amount)?;
+                // The contract checks authorizations of known networks only
+                let opened = if self.names_network_of(escrow) {
+                    self.open_with_payment(escrow, server, funding, amount, nonce, expires_at)
+                        .await
+                        .map(|payload| (payload.escrow_id, Some(payload)))
+                } else {
+                    escrow
+                        .open_escrow(&client, server, funding, None)
+                        .await
+                        .map(|opened| (opened.value, None))
+                };
+                match opened {
+                    Ok(opened) => return Ok(opened),
+                    // Opened by another request since the lookup
+                    Err(e) if e.contract_error() == Some(ContractError::EscrowAlreadyExists) => {
+                        escrow.find_escrow(&client, server).await?.ok_or(e)?
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+
+        let balance = escrow.get_escrow_balance(escrow_id).await?;
+        if balance < amount {
+            let missing = amount - balance;
+            let top_up = self
+                .policy
+                .cap_deposit(self.deposit.max(amount).max(missing), missing)?;
+            escrow.deposit(escrow_id, top_up).await?;
+        }
+        Ok((escrow_id, None))
+    }
+
+    /// Open an escrow holding `funding` with its first payment of `amount`,
+    /// in one transaction
+    ///
+    /// # Returns
+    /// * Authorization of the payment, naming the payment created
+    async fn open_with_payment(
+        &self,
+        escrow: &EscrowClient,
+        server: &str,
+        funding: i128,
+        amount: i128,
+        nonce: u64,
+        expires_at: u64,
+    ) -> Result<EscrowPayload, Error> {
+        // The authorization names the ID the escrow is opened with
+        let op = EscrowOp::OpenEscrow {
+            client: escrow.address(),
+            server: server.to_string(),
+            amount: funding,
+            terms_hash: None,
+        };
+        let escrow_id = scval::to_u64(&escrow.dry_run(&op).await?.value)?;
+        let mut payload = escrow
+            .authorize_payment(escrow_id, amount, nonce, expires_at, &self.network)
+            .await?;
+        let created = escrow
+            .open_and_authorize(server, funding, None, &payload, &self.network)
+            .await?;
+        payload.payment_id = Some(created.value);
+        Ok(payload)
+    }
+
+    /// Whether the x402 network paid on is the escrow contract's network
+    fn names_network_of(&self, escrow: &EscrowClient) -> bool {
+        network_passphrase(&self.network).is_some_and(|passphrase| {
+            <[u8; 32]>::from(Sha256::digest(passphrase.as_bytes())) == escrow.network_id()
+        })
+    }
+
+    /// Make sure a cached escrow has `amount` available, reconciling it if
+    /// due
+    async fn fund_channel(
+        &self,
+        escrow: &EscrowClient,
+        channel: &ChannelState,
+        amount: i128,
+    ) -> Result<(), Error> {
+        channel.sync_if_due().await?;
+        let available = channel.available();
+        if available < amount {
+            let missing = amount - available;
+            let top_up = self
+                .policy
+                .cap_deposit(self.deposit.max(amount).max(missing), missing)?;
+            let deposited = escrow.deposit(channel.escrow_id(), top_up).await;
+            match deposited {
+                Ok(_) => channel.record_deposit(top_up),
+                Err(e) => {
+                    channel.mark_stale();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Start caching the escrow paying `requirements` if the channel cache
+    /// is enabled
+    ///
+    /// A channel started meanwhile by a concurrent request is kept, so every
+    /// payment to the server is recorded in the same one.
+    async fn open_channel(
+        &self,
+        escrow: &EscrowClient,
+        requirements: &PaymentRequirements,
+        escrow_id: u64,
+    ) -> Result<Option<Arc<ChannelState>>, Error> {
+        let Some(interval) = self.sync_interval else {
+            return Ok(None);
+        };
+        let channel = ChannelState::new(escrow.clone(), escrow_id).with_sync_interval(interval);
+        channel.force_sync().await?;
+        let key = self.channel_key(Some(asset_of(requirements)), &requirements.pay_to);
+        let channel = self
+            .channels
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Arc::new(channel))
+            .clone();
+        Ok(Some(channel))
+    }
+}
+
+/// Asset requirements are paid in, "native" if they name none
+fn asset_of(requirements: &PaymentRequirements) -> &str {
+    requirements.asset.as_deref().unwrap_or(NATIVE_ASSET)
+}
+
+/// Positive amount required, in stroops
+fn parse_amount(requirements: &PaymentRequirements) -> Result<i128, Error> {
+    match StellarAmount::from_stroops_str(&requirements.max_amount_required) {
+        Ok(amount) if amount.is_positive() => Ok(amount.stroops()),
+        _ => Err(Error::InvalidChallenge("invalid maxAmountRequired".into())),
+    }
+}
+
+fn transport(e: reqwest::Error) -> Error {
+    Error::Transport(e.to_string())
+}
+
+/// Error of a payment the server rejected as `hint` describes
+fn rejected(hint: PaymentErrorBody) -> Error {
+    let retry_after = hint.retry_after.map(Duration::from_secs);
+    let error = X402Error::from_code(hint.code).unwrap_or(X402Error::Internal);
+    Error::PaymentRejected {
+        error: match retry_after {
+            Some(delay) => error.with_retry_after(delay),
+            None => error,
+        },
+        message: hint.message,
+        retry_after,
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use x402_types::{EscrowPayload, PaymentRequirements, SettleResponse};
+
+use crate::Error;
+
+/// What happened to a payment, as recorded in a [`PaymentJournal`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum JournalEntry {
+    /// Requirements of a 402 response the client chose to pay
+    Required { requirements: PaymentRequirements },
+    /// Authorization signed to pay them
+    Signed {
+        escrow_id: u64,
+        nonce: u64,
+        /// Authorized amount, in stroops
+        amount: String,
+        payload: EscrowPayload,
+    },
+    /// Response of the server to the paid request
+    Settled {
+        escrow_id: u64,
+        nonce: u64,
+        /// Amount charged, in stroops
+        amount: String,
+        /// Settlement reported in the X-PAYMENT-RESPONSE header, if any
+        settlement: Option<SettleResponse>,
+        /// SHA-256 of the X-PAYMENT-RESPONSE header, hex encoded
+        receipt_hash: Option<String>,
+    },
+}
+
+/// Line of a [`PaymentJournal`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalRecord {
+    /// When the entry was written, in seconds since the Unix epoch
+    pub at: u64,
+    /// Host the payment was made to
+    pub host: String,
+    #[serde(flatten)]
+    pub entry: JournalEntry,
+}
+
+impl JournalRecord {
+    /// Amount charged by a successful settlement, in stroops
+    pub fn charged(&self) -> Option<i128> {
+        match &self.entry {
+            JournalEntry::Settled {
+                amount,
+                settlement: Some(settlement),
+                ..
+            } if settlement.success => amount.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Append-only journal of the payments made by a client
+///
+/// Entries are written as JSON lines and synced before the call that wrote
+/// them returns, so the journal survives restarts and crashes. A torn last
+/// line, left by a crash mid-write, is skipped when reading.
+#[derive(Debug)]
+pub struct PaymentJournal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl PaymentJournal {
+    /// Open the journal at `path`, creating it if missing
+    ///
+    /// # Errors
+    /// * `Journal` - If the file cannot be opened for appending
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| journal(&path, e))?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Path of the journal file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `entry` about a payment to `host`
+    ///
+    /// # Errors
+    /// * `Journal` - If the entry could not be written and synced
+    pub fn record(&self, host: &str, entry: JournalEntry) -> Result<(), Error> {
+        let record = JournalRecord {
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            host: host.into(),
+            entry,
+        };
+        let mut line = serde_json::to_vec(&record).map_err(|e| journal(&self.path, e))?;
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&line)
+            .and_then(|()| file.sync_data())
+            .map_err(|e| journal(&self.path, e))
+    }
+
+    /// Every entry of the journal, oldest first
+    ///
+    /// # Errors
+    /// * `Journal` - If the file cannot be read, or a line other than the
+    ///   last is not a journal record
+    pub fn entries(&self) -> Result<Vec<JournalRecord>, Error> {
+        let file = File::open(&self.path).map_err(|e| journal(&self.path, e))?;
+        let lines: Vec<String> = BufReader::new(file)
+            .lines()
+            .collect::<Result<_, _>>()
+            .map_err(|e| journal(&self.path, e))?;
+        let mut records = Vec::with_capacity(lines.len());
+        for (index, line) in lines.iter().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                Err(_) if index + 1 == lines.len() => {}
+                Err(e) => return Err(journal(&self.path, format!("line {}: {e}", index + 1))),
+            }
+        }
+        Ok(records)
+    }
+
+    /// Total charged by successful settlements since `since`, in stroops
+    ///
+    /// # Arguments
+    /// * `since` - Seconds since the Unix epoch, inclusive
+    ///
+    /// # Errors
+    /// * `Journal` - If the journal cannot be read
+    pub fn total_spent_since(&self, since: u64) -> Result<i128, Error> {
+        Ok(self
+            .entries()?
+            .iter()
+            .filter(|record| record.at >= since)
+            .filter_map(JournalRecord::charged)
+            .sum())
+    }
+
+    /// Server responses to payments made to `host`, oldest first
+    ///
+    /// # Errors
+    /// * `Journal` - If the journal cannot be read
+    pub fn receipts_for_host(&self, host: &str) -> Result<Vec<JournalRecord>, Error> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter(|record| record.host == host)
+            .filter(|record| matches!(record.entry, JournalEntry::Settled { .. }))
+            .collect())
+    }
+}
+
+/// Hash of an X-PAYMENT-RESPONSE header, as recorded in the journal
+pub fn receipt_hash(header: &str) -> String {
+    hex::encode(Sha256::digest(header.as_bytes()))
+}
+
+fn journal(path: &Path, e: impl std::fmt::Display) -> Error {
+    Error::Journal(format!("{}: {e}", path.display()))
+}
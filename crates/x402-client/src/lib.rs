@@ -0,0 +1,114 @@
+//! # x402 Client
+//!
+//! Async SDK for invoking the x402 escrow contract over Soroban RPC.
+//!
+//! ## Key Features
+//! - [`EscrowClient`] methods mirroring the contract entry points, encoded by
+//!   the contract-checked `x402-bindings`
+//! - Transaction building, simulation, signing, submission, and polling
+//! - Idempotent submission via [`EscrowClient::submit_idempotent`], which
+//!   never signs a new transaction while the previous one may still apply
+//! - Fee bumping of transactions stuck pending, e.g. during surge pricing,
+//!   via [`EscrowClient::with_fee_bump`]
+//! - [`FinalityPolicy`] confirmation depths, settlements being final once
+//!   enough ledgers closed after their own
+//! - Fee and resource estimates and dry runs of escrow operations via
+//!   [`EscrowClient::estimate`] and [`EscrowClient::dry_run`]
+//! - Vouchers and channel states signed with [`EscrowClient::sign_voucher`]
+//!   and [`EscrowClient::sign_channel_state`], in the canonical encoding the
+//!   contract verifies
+//! - Soroban authorization entries pre-signed for later calls with
+//!   [`EscrowClient::presign`], submitted from any account by
+//!   [`EscrowClient::submit_authorized`]
+//! - Plain-language [`OperationSummary`] of any escrow call via [`describe`],
+//!   with localization keys and the most funds it may move, for wallets to
+//!   show before signing
+//! - Pluggable [`Signer`] and RPC [`Transport`]
+//! - [`EndpointPool`] of RPC endpoints scored by health, failing reads over
+//!   to the next endpoint on timeouts, errors, and stale ledgers, keeping
+//!   submissions on a sticky primary, and skipping rate-limited endpoints
+//!   for as long as their `Retry-After` asks
+//! - [`LocalSigner`] keys, plus [`CommandSigner`] and [`HttpSigner`] delegating to
+//!   external signing tools or services, bounded by a signing timeout
+//! - [`X402HttpClient`] paying `402 Payment Required` responses from an escrow,
+//!   opening one on the first payment to a new server, in the same
+//!   transaction as that payment, in the asset an existing escrow or the
+//!   [`SpendPolicy`] prefers when several are offered
+//! - Payments rejected by the server made again once after topping the
+//!   escrow up or signing anew, as its `paymentError` hints, and otherwise
+//!   surfaced as [`Error::PaymentRejected`]
+//! - [`ChannelState`] caching escrow balances between payments, reconciled
+//!   with on-chain state periodically or on demand
+//! - [`SpendPolicy`] guardrails evaluated before any payment
+//! - [`PaymentJournal`] recording the requirements, authorizations, and
+//!   settlements of an [`X402HttpClient`]'s payments across restarts
+//! - Contract error codes surfaced as [`ContractError`] variants, with the
+//!   [`CallContext`] of the rejected call, and every error mapping to the
+//!   stable code of its [`X402Error`] from `x402-errors`
+//! - [`EscrowEvent`] streams via [`EscrowClient::subscribe_events`], decoding
+//!   every [`EVENT_VERSION`] of the payloads back to the first deployment
+//! - Payment history of an escrow via [`EscrowClient::payments`], paging
+//!   through `get_payments` and retrying transient RPC failures
+//! - Open escrows of a server via [`EscrowClient::export_escrows`]
+//! - [`SettlementReceipt`]s of settled payments, signed by the server via
+//!   [`EscrowClient::settlement_receipt`] and checked offline by auditors
+//!   with [`SettlementReceipt::verify`]
+//! - [`ServerMonitor`] watching a server's escrows for stale payments,
+//!   lagging settlements, and clients running dry, as a background task
+//! - [`PaymentBatcher`] recording the payments of high-volume servers at
+//!   once and creating and settling them in batches, with backpressure past
+//!   a queue limit and a [`PaymentTicket`] completing with each payment
+//! - Agent allowances granted with [`EscrowClient::grant_agent`], charged by
+//!   the payments [`EscrowClient::create_authorized_payment`] creates from
+//!   signed authorizations
+//! - `tracing` spans around payments, submissions, and RPC calls, tagged
+//!   with the payment's correlation ID, also the memo of transactions sent by
+//!   [`EscrowClient::with_correlation_id`]
+//! - `testutils` feature: an in-process Soroban test env as a transport
+
+mod auth;
+mod batcher;
+mod channel;
+mod client;
+mod describe;
+mod error;
+mod estimate;
+mod event;
+mod feebump;
+mod finality;
+mod http;
+mod journal;
+mod monitor;
+mod payments;
+mod policy;
+mod pool;
+mod receipt;
+mod rpc;
+pub mod scval;
+mod signer;
+mod submission;
+#[cfg(feature = "testutils")]
+pub mod testutils;
+
+pub use auth::*;
+pub use batcher::*;
+pub use channel::*;
+pub use client::*;
+pub use describe::*;
+pub use error::*;
+pub use estimate::*;
+pub use event::*;
+pub use feebump::*;
+pub use finality::*;
+pub use http::*;
+pub use journal::*;
+pub use monitor::*;
+pub use payments::*;
+pub use policy::*;
+pub use pool::*;
+pub use receipt::*;
+pub use rpc::*;
+pub use signer::*;
+pub use submission::*;
+
+mod test;
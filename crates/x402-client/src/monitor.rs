@@ -0,0 +1,317 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Error, EscrowClient};
+
+/// Default time between checks of a [`ServerMonitor`]
+pub const DEFAULT_MONITOR_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Operational hygiene rules a [`ServerMonitor`] evaluates
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MonitorRules {
+    /// Alert on payments left unsettled for longer than this, None to
+    /// disable
+    pub max_pending_age: Option<Duration>,
+    /// Alert on escrows whose settled volume is below this share of their
+    /// created volume, in basis points, None to disable
+    pub min_settled_bps: Option<u32>,
+    /// Payments younger than this do not count towards the settled share,
+    /// having had no time to settle
+    pub settlement_grace: Duration,
+    /// Balance below which an escrow is running dry, in stroops, None to
+    /// disable
+    pub low_balance: Option<i128>,
+    /// Consecutive checks an escrow must be running dry before it is
+    /// reported, so a top-up on its way is not
+    pub dry_checks: u32,
+}
+
+impl Default for MonitorRules {
+    fn default() -> Self {
+        Self {
+            max_pending_age: Some(Duration::from_secs(15 * 60)),
+            min_settled_bps: Some(9_000),
+            settlement_grace: Duration::from_secs(5 * 60),
+            low_balance: None,
+            dry_checks: 3,
+        }
+    }
+}
+
+/// Condition reported by a [`ServerMonitor`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MonitorAlert {
+    /// A payment has been pending longer than the rules allow
+    StalePayment {
+        escrow_id: u64,
+        payment_id: u64,
+        amount: i128,
+        /// Time since the payment was created
+        age: Duration,
+    },
+    /// An escrow's settled volume lags its created volume
+    SettlementLag {
+        escrow_id: u64,
+        /// Amount of the payments past the settlement grace, in stroops
+        created: i128,
+        /// Amount of those settled, in stroops
+        settled: i128,
+    },
+    /// An escrow's balance stayed low for several checks in a row
+    RunningDry {
+        escrow_id: u64,
+        /// Client of the escrow (G... format)
+        client: String,
+        balance: i128,
+        /// Consecutive checks it was low for
+        checks: u32,
+    },
+}
+
+impl MonitorAlert {
+    /// Condition the alert is about, reported once while it holds
+    fn key(&self) -> (u8, u64) {
+        match self {
+            Self::StalePayment { payment_id, .. } => (0, *payment_id),
+            Self::SettlementLag { escrow_id, .. } => (1, *escrow_id),
+            Self::RunningDry { escrow_id, .. } => (2, *escrow_id),
+        }
+    }
+}
+
+/// Counters of a [`ServerMonitor`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MonitorMetrics {
+    /// Checks completed
+    pub checks: u64,
+    /// Checks that failed to read on-chain state
+    pub failed_checks: u64,
+    /// Open escrows of the server at the last check
+    pub escrows: usize,
+    /// Unsettled payments at the last check
+    pub pending_payments: usize,
+    /// Conditions holding at the last check
+    pub active_alerts: usize,
+    /// Alerts reported so far
+    pub alerts: u64,
+}
+
+#[derive(Default)]
+struct MonitorState {
+    /// Conditions already reported, forgotten once they clear
+    reported: HashSet<(u8, u64)>,
+    /// Consecutive checks each escrow was running dry
+    dry_streaks: HashMap<u64, u32>,
+    metrics: MonitorMetrics,
+}
+
+type AlertCallback = Box<dyn Fn(&MonitorAlert) + Send + Sync>;
+
+/// Watchlist of a server's escrows, alerting when its operational hygiene
+/// slips
+///
+/// Each check reads the server's open escrows and their payments, then
+/// evaluates the [`MonitorRules`]: payments pending too long, escrows whose
+/// settled volume lags what was created, and escrows running dry check
+/// after check. A condition is reported to the callbacks once, when first
+/// seen, and again only if it clears and comes back.
+///
+/// [`ServerMonitor::run`] checks every interval, as a background task of
+/// the resource server:
+/// `tokio::spawn(async move { monitor.run().await })`.
+pub struct ServerMonitor {
+    escrow: EscrowClient,
+    server: String,
+    rules: MonitorRules,
+    interval: Duration,
+    callbacks: Vec<AlertCallback>,
+    state: Mutex<MonitorState>,
+}
+
+impl ServerMonitor {
+    /// Monitor the escrows paying the signer of `escrow`
+    pub fn new(escrow: EscrowClient, rules: MonitorRules) -> Self {
+        Self {
+            server: escrow.address(),
+            escrow,
+            rules,
+            interval: DEFAULT_MONITOR_INTERVAL,
+            callbacks: Vec::new(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Monitor the escrows paying `server` (G... format) rather than the
+    /// signer
+    pub fn for_server(mut self, server: impl Into<String>) -> Self {
+        self.server = server.into();
+        self
+    }
+
+    /// Check every `interval` rather than [`DEFAULT_MONITOR_INTERVAL`]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Call `callback` with every alert, after those added before it
+    pub fn on_alert(mut self, callback: impl Fn(&MonitorAlert) + Send + Sync + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Counters of the checks so far
+    pub fn metrics(&self) -> MonitorMetrics {
+        self.state.lock().unwrap().metrics.clone()
+    }
+
+    /// Check forever, every interval, counting failed checks and carrying
+    /// on after them
+    pub async fn run(&self) {
+        loop {
+            if let Err(e) = self.check().await {
+                tracing::warn!(error = %e, server = %self.server, "escrow monitor check failed");
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    /// Evaluate the rules once, calling the callbacks with the conditions
+    /// not reported yet
+    ///
+    /// # Returns
+    /// * The alerts reported by this check
+    ///
+    /// # Errors
+    /// * If the escrows or their payments cannot be read, reporting nothing
+    pub async fn check(&self) -> Result<Vec<MonitorAlert>, Error> {
+        let observed = match self.observe().await {
+            Ok(observed) => observed,
+            Err(e) => {
+                self.state.lock().unwrap().metrics.failed_checks += 1;
+                return Err(e);
+            }
+        };
+
+        let alerts = {
+            let mut state = self.state.lock().unwrap();
+            let state = &mut *state;
+
+            // Low balances alert once they stayed low for enough checks
+            let mut streaks = HashMap::new();
+            let mut active = observed.alerts;
+            for (escrow_id, client, balance) in observed.low {
+                let checks = state.dry_streaks.get(&escrow_id).copied().unwrap_or(0) + 1;
+                streaks.insert(escrow_id, checks);
+                if checks >= self.rules.dry_checks {
+                    active.push(MonitorAlert::RunningDry {
+                        escrow_id,
+                        client,
+                        balance,
+                        checks,
+                    });
+                }
+            }
+            state.dry_streaks = streaks;
+
+            let alerts: Vec<MonitorAlert> = active
+                .iter()
+                .filter(|alert| !state.reported.contains(&alert.key()))
+                .cloned()
+                .collect();
+            state.reported = active.iter().map(MonitorAlert::key).collect();
+
+            let metrics = &mut state.metrics;
+            metrics.checks += 1;
+            metrics.escrows = observed.escrows;
+            metrics.pending_payments = observed.pending_payments;
+            metrics.active_alerts = active.len();
+            metrics.alerts += alerts.len() as u64;
+            alerts
+        };
+
+        for alert in &alerts {
+            for callback in &self.callbacks {
+                callback(alert);
+            }
+        }
+        Ok(alerts)
+    }
+
+    /// Read the server's escrows and evaluate the rules that need no history
+    async fn observe(&self) -> Result<Observed, Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let escrows = self.escrow.export_escrows(Some(&self.server)).await?;
+        let mut observed = Observed {
+            escrows: escrows.len(),
+            ..Observed::default()
+        };
+
+        for (escrow_id, escrow) in escrows {
+            if self
+                .rules
+                .low_balance
+                .is_some_and(|low| escrow.balance < low)
+            {
+                observed
+                    .low
+                    .push((escrow_id, escrow.client, escrow.balance));
+            }
+
+            let payments = self
+                .escrow
+                .payments(escrow_id)
+                .collect_all(usize::MAX)
+                .await?;
+            let (mut created, mut settled) = (0i128, 0i128);
+            for (payment_id, payment) in payments {
+                let age = Duration::from_secs(now.saturating_sub(payment.timestamp));
+                if !payment.settled {
+                    observed.pending_payments += 1;
+                    if self.rules.max_pending_age.is_some_and(|max| age > max) {
+                        observed.alerts.push(MonitorAlert::StalePayment {
+                            escrow_id,
+                            payment_id,
+                            amount: payment.amount,
+                            age,
+                        });
+                    }
+                }
+                if age >= self.rules.settlement_grace {
+                    created += payment.amount;
+                    if payment.settled {
+                        settled += payment.amount;
+                    }
+                }
+            }
+
+            if let Some(bps) = self.rules.min_settled_bps {
+                if created > 0 && settled * 10_000 < created * bps as i128 {
+                    observed.alerts.push(MonitorAlert::SettlementLag {
+                        escrow_id,
+                        created,
+                        settled,
+                    });
+                }
+            }
+        }
+        Ok(observed)
+    }
+}
+
+/// State of the server's escrows read by one check
+#[derive(Default)]
+struct Observed {
+    escrows: usize,
+    pending_payments: usize,
+    /// Stale payments and lagging escrows
+    alerts: Vec<MonitorAlert>,
+    /// Escrows below the low balance, with their client and balance
+    low: Vec<(u64, String, i128)>,
+}
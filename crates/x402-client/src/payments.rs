@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+
+use futures_util::{Stream, StreamExt};
+use stellar_xdr::curr::ScAddress;
+
+use crate::{Error, EscrowClient, Payment};
+
+pub use x402_bindings::{PaymentStatus, MAX_COMPACT_PAGES, MAX_ESCROWS_PAGE, MAX_PAYMENTS_PAGE};
+
+/// Retries of a `get_payments` page failing with a transient error
+pub const PAYMENT_PAGE_RETRIES: u32 = 3;
+
+/// Payments a [`PaymentQuery`] pages through
+#[derive(Clone)]
+pub(crate) enum PaymentList {
+    /// Payments of an escrow, through `get_payments`
+    Escrow(u64),
+    /// Direct payments to a server, through `get_direct_payments`
+    Direct(ScAddress),
+}
+
+/// Query of the payments of an escrow, built by [`EscrowClient::payments`],
+/// or of the direct payments to a server, built by
+/// [`EscrowClient::direct_payments`]
+///
+/// Payments are yielded oldest first with their IDs. The contract looks at
+/// no more than [`MAX_PAYMENTS_PAGE`] payments per call, so the query follows
+/// the offset of each page until the last one.
+#[derive(Clone)]
+pub struct PaymentQuery<'a> {
+    client: &'a EscrowClient,
+    list: PaymentList,
+    status: Option<PaymentStatus>,
+    offset: u32,
+    page_size: u32,
+}
+
+impl<'a> PaymentQuery<'a> {
+    pub(crate) fn new(client: &'a EscrowClient, list: PaymentList) -> Self {
+        Self {
+            client,
+            list,
+            status: None,
+            offset: 0,
+            page_size: MAX_PAYMENTS_PAGE,
+        }
+    }
+
+    /// Only yield payments with `status`
+    pub fn status(mut self, status: PaymentStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Skip the first `offset` payments listed, whatever their status
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Look at `page_size` payments per call, capped by the contract at
+    /// [`MAX_PAYMENTS_PAGE`]
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Stream the matching payments, fetching pages as they are consumed
+    ///
+    /// The stream ends after the last page, or after yielding the error of
+    /// a page that could not be fetched.
+    pub fn stream(self) -> impl Stream<Item = Result<(u64, Payment), Error>> + 'a {
+        let state = Pages {
+            next_offset: Some(self.offset),
+            query: self,
+            buffered: VecDeque::new(),
+        };
+        futures_util::stream::unfold(state, |mut state| async move {
+            let item = state.next().await?;
+            Some((item, state))
+        })
+    }
+
+    /// Collect the matching payments
+    ///
+    /// # Arguments
+    /// * `max_items` - Most payments returned, the first ones in order
+    ///
+    /// # Errors
+    /// * The error of the first page that could not be fetched
+    pub async fn collect_all(self, max_items: usize) -> Result<Vec<(u64, Payment)>, Error> {
+        let mut payments = Vec::new();
+        let mut stream = std::pin::pin!(self.stream().take(max_items));
+        while let Some(payment) = stream.next().await {
+            payments.push(payment?);
+        }
+        Ok(payments)
+    }
+}
+
+/// Paging state of [`PaymentQuery::stream`]
+struct Pages<'a> {
+    query: PaymentQuery<'a>,
+    next_offset: Option<u32>,
+    buffered: VecDeque<(u64, Payment)>,
+}
+
+impl Pages<'_> {
+    /// Next payment, fetching pages until one has a match
+    async fn next(&mut self) -> Option<Result<(u64, Payment), Error>> {
+        loop {
+            if let Some(payment) = self.buffered.pop_front() {
+                return Some(Ok(payment));
+            }
+            let offset = self.next_offset?;
+            let query = &self.query;
+            match query
+                .client
+                .payments_page(&query.list, query.status.clone(), offset, query.page_size)
+                .await
+            {
+                Ok((payments, next_offset)) => {
+                    self.buffered.extend(payments);
+                    self.next_offset = next_offset;
+                }
+                Err(e) => {
+                    self.next_offset = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
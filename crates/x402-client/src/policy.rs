@@ -0,0 +1,251 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::Error;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// Outcome of evaluating a payment against a [`SpendPolicy`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Decision {
+    /// The payment may be made
+    Allow,
+    /// The payment must not be made, with the rule that denied it
+    Deny(String),
+    /// The payment needs the user's confirmation
+    Confirm,
+}
+
+#[derive(Debug, Default)]
+struct Ledger {
+    session: i128,
+    hosts: HashMap<String, VecDeque<(Instant, i128)>>,
+}
+
+impl Ledger {
+    /// Spent on `host` over the hour before `now`, dropping older entries
+    fn last_hour(&mut self, host: &str, now: Instant) -> i128 {
+        let Some(entries) = self.hosts.get_mut(host) else {
+            return 0;
+        };
+        while entries
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) >= HOUR)
+        {
+            entries.pop_front();
+        }
+        entries.iter().map(|(_, amount)| amount).sum()
+    }
+}
+
+/// Guardrails evaluated before a payment is constructed
+///
+/// Rules are checked in order: host allowlist, per-request cap, per-host
+/// hourly cap, session cap, then the confirmation threshold. Amounts are in
+/// stroops; spend is accounted per host for the lifetime of the policy.
+/// Escrow deposits made to pay are capped apart, by
+/// [`max_deposit`](Self::max_deposit).
+#[derive(Debug, Default)]
+pub struct SpendPolicy {
+    max_per_request: Option<i128>,
+    max_per_host_per_hour: Option<i128>,
+    max_per_session: Option<i128>,
+    allowed_hosts: Option<HashSet<String>>,
+    confirm_above: Option<i128>,
+    max_deposit: Option<i128>,
+    preferred_assets: Vec<String>,
+    ledger: Mutex<Ledger>,
+}
+
+impl SpendPolicy {
+    /// Create a policy allowing every payment
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deny payments above `amount` for a single request
+    pub fn max_per_request(mut self, amount: i128) -> Self {
+        self.max_per_request = Some(amount);
+        self
+    }
+
+    /// Deny payments taking a host's spend over the last hour above `amount`
+    pub fn max_per_host_per_hour(mut self, amount: i128) -> Self {
+        self.max_per_host_per_hour = Some(amount);
+        self
+    }
+
+    /// Deny payments taking the total spend above `amount`
+    pub fn max_per_session(mut self, amount: i128) -> Self {
+        self.max_per_session = Some(amount);
+        self
+    }
+
+    /// Only pay `host`, may be called for several hosts
+    ///
+    /// Without any allowed host, every host is allowed.
+    pub fn allow_host(mut self, host: impl Into<String>) -> Self {
+        self.allowed_hosts
+            .get_or_insert_with(HashSet::new)
+            .insert(host.into());
+        self
+    }
+
+    /// Ask for confirmation of payments above `amount`
+    pub fn confirm_above(mut self, amount: i128) -> Self {
+        self.confirm_above = Some(amount);
+        self
+    }
+
+    /// Fund escrows with at most `amount` per deposit
+    ///
+    /// Deposits are not spend, the client can close the escrow to get them
+    /// back, but they lock funds with a server.
+    pub fn max_deposit(mut self, amount: i128) -> Self {
+        self.max_deposit = Some(amount);
+        self
+    }
+
+    /// Prefer paying in `asset` when a server accepts several, may be called
+    /// for several assets, most preferred first
+    ///
+    /// Preferences only rank assets no existing escrow covers, funded escrows
+    /// being paid from first.
+    pub fn prefer_asset(mut self, asset: impl Into<String>) -> Self {
+        self.preferred_assets.push(asset.into());
+        self
+    }
+
+    /// Rank of `asset` among the preferred assets, lower ranking first,
+    /// assets without a preference ranking last
+    pub fn asset_preference(&self, asset: &str) -> usize {
+        self.preferred_assets
+            .iter()
+            .position(|preferred| preferred == asset)
+            .unwrap_or(self.preferred_assets.len())
+    }
+
+    /// Amount to deposit into an escrow lacking `needed`, when `wanted`
+    /// would be deposited without a cap
+    ///
+    /// # Errors
+    /// * `PaymentDeclined` - If `needed` is above the deposit cap
+    pub fn cap_deposit(&self, wanted: i128, needed: i128) -> Result<i128, Error> {
+        match self.max_deposit {
+            Some(max) if needed > max => Err(Error::PaymentDeclined(format!(
+                "deposit of {needed} exceeds the deposit cap"
+            ))),
+            Some(max) => Ok(wanted.min(max)),
+            None => Ok(wanted),
+        }
+    }
+
+    /// Total spent under this policy
+    pub fn spent(&self) -> i128 {
+        self.ledger.lock().unwrap().session
+    }
+
+    /// Spent on `host` over the last hour
+    pub fn spent_on(&self, host: &str) -> i128 {
+        self.ledger.lock().unwrap().last_hour(host, Instant::now())
+    }
+
+    /// Evaluate paying `amount` to `host`, without recording it
+    pub fn evaluate(&self, host: &str, amount: i128) -> Decision {
+        let mut ledger = self.ledger.lock().unwrap();
+        self.decide(&mut ledger, host, amount, Instant::now())
+    }
+
+    /// Evaluate and record paying `amount` to `host`
+    ///
+    /// The ledger stays locked while `confirm` runs, so concurrent payments
+    /// cannot overrun the caps.
+    ///
+    /// # Arguments
+    /// * `host` - Host being paid
+    /// * `amount` - Amount to pay, in stroops
+    /// * `confirm` - Called when the policy asks for confirmation
+    ///
+    /// # Errors
+    /// * `PaymentDeclined` - If a rule denied the payment or it was not confirmed
+    pub fn reserve(
+        &self,
+        host: &str,
+        amount: i128,
+        confirm: impl FnOnce() -> bool,
+    ) -> Result<(), Error> {
+        self.reserve_at(host, amount, Instant::now(), confirm)
+    }
+
+    pub(crate) fn reserve_at(
+        &self,
+        host: &str,
+        amount: i128,
+        now: Instant,
+        confirm: impl FnOnce() -> bool,
+    ) -> Result<(), Error> {
+        let mut ledger = self.ledger.lock().unwrap();
+        match self.decide(&mut ledger, host, amount, now) {
+            Decision::Allow => {}
+            Decision::Deny(reason) => return Err(Error::PaymentDeclined(reason)),
+            Decision::Confirm => {
+                if !confirm() {
+                    return Err(Error::PaymentDeclined(format!("{amount} not confirmed")));
+                }
+            }
+        }
+        ledger.session += amount;
+        ledger
+            .hosts
+            .entry(host.into())
+            .or_default()
+            .push_back((now, amount));
+        Ok(())
+    }
+
+    /// Undo a reservation whose payment was not made
+    pub(crate) fn refund(&self, host: &str, amount: i128) {
+        let mut ledger = self.ledger.lock().unwrap();
+        ledger.session -= amount;
+        if let Some(entries) = ledger.hosts.get_mut(host) {
+            if let Some(index) = entries.iter().rposition(|(_, a)| *a == amount) {
+                entries.remove(index);
+            }
+        }
+    }
+
+    fn decide(&self, ledger: &mut Ledger, host: &str, amount: i128, now: Instant) -> Decision {
+        if self
+            .allowed_hosts
+            .as_ref()
+            .is_some_and(|hosts| !hosts.contains(host))
+        {
+            return Decision::Deny(format!("{host} is not an allowed host"));
+        }
+        if self.max_per_request.is_some_and(|max| amount > max) {
+            return Decision::Deny(format!("{amount} exceeds the per-request cap"));
+        }
+        if self
+            .max_per_host_per_hour
+            .is_some_and(|max| ledger.last_hour(host, now) + amount > max)
+        {
+            return Decision::Deny(format!("{amount} exceeds the hourly cap for {host}"));
+        }
+        if self
+            .max_per_session
+            .is_some_and(|max| ledger.session + amount > max)
+        {
+            return Decision::Deny(format!("{amount} exceeds the session cap"));
+        }
+        if self
+            .confirm_above
+            .is_some_and(|threshold| amount > threshold)
+        {
+            return Decision::Confirm;
+        }
+        Decision::Allow
+    }
+}
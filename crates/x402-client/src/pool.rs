@@ -0,0 +1,313 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::{rpc::Transport, Error, HttpTransport};
+
+/// Health of an endpoint that never failed, out of 100
+pub const MAX_HEALTH: u32 = 100;
+
+/// Tunables of an [`EndpointPool`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PoolOptions {
+    /// How long an endpoint may take to answer before the request fails
+    /// over
+    pub request_timeout: Duration,
+    /// Ledgers an endpoint may trail the latest ledger seen before its
+    /// answers are stale
+    pub max_ledger_lag: u32,
+    /// How long a rate-limited endpoint is skipped when it does not say
+    pub rate_limit_backoff: Duration,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(10),
+            max_ledger_lag: 5,
+            rate_limit_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Counters of one endpoint of an [`EndpointPool`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EndpointMetrics {
+    /// Name the endpoint was added under, e.g. its URL
+    pub name: String,
+    /// Health score, up to [`MAX_HEALTH`], lowered by failures and stale
+    /// answers and restored by successes
+    pub health: u32,
+    /// Requests sent
+    pub requests: u64,
+    /// Requests that failed or timed out
+    pub failures: u64,
+    /// Requests answered `429 Too Many Requests`
+    pub rate_limited: u64,
+    /// Answers trailing the latest ledger seen
+    pub stale: u64,
+}
+
+/// Counters of an [`EndpointPool`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PoolMetrics {
+    /// Each endpoint, in the order they were added
+    pub endpoints: Vec<EndpointMetrics>,
+    /// Endpoint submissions are sent to
+    pub primary: usize,
+    /// Requests retried on another endpoint
+    pub failovers: u64,
+    /// Latest ledger an endpoint reported
+    pub latest_ledger: u32,
+}
+
+struct EndpointState {
+    metrics: EndpointMetrics,
+    /// Skipped until then, having been rate limited
+    limited_until: Option<Instant>,
+}
+
+struct PoolState {
+    endpoints: Vec<EndpointState>,
+    primary: usize,
+    failovers: u64,
+    latest_ledger: u32,
+}
+
+/// Outcome of one request to one endpoint
+enum Attempt {
+    Answered(Value),
+    /// Answered for a ledger trailing the latest one seen
+    Stale(Value, u32),
+    /// The endpoint failed, another may not
+    Failed(Error),
+}
+
+/// RPC transport spreading requests over several Soroban RPC endpoints
+///
+/// Reads (simulations, `getEvents`, `getLedgerEntries`, and the other
+/// methods) go to the healthiest endpoint and fail over to the next when it
+/// times out, cannot be reached, answers with an error, or answers for a
+/// ledger more than `max_ledger_lag` behind the latest one seen. When every
+/// endpoint is stale, the freshest answer is returned.
+///
+/// `sendTransaction` sticks to a primary endpoint, so a transaction and
+/// its retries reach the same server. Submissions move to the healthiest
+/// other endpoint only when the primary fails, which becomes the primary.
+/// Sending a signed transaction again is safe, as it applies at most once.
+///
+/// An endpoint answering `429 Too Many Requests` is skipped for as long as
+/// its `Retry-After` asks. When every endpoint is rate limited, requests
+/// fail with `RateLimited` rather than wait.
+pub struct EndpointPool {
+    transports: Vec<Arc<dyn Transport>>,
+    options: PoolOptions,
+    state: Mutex<PoolState>,
+}
+
+impl Default for EndpointPool {
+    fn default() -> Self {
+        Self::new(PoolOptions::default())
+    }
+}
+
+impl EndpointPool {
+    /// Create a pool without endpoints
+    pub fn new(options: PoolOptions) -> Self {
+        Self {
+            transports: Vec::new(),
+            options,
+            state: Mutex::new(PoolState {
+                endpoints: Vec::new(),
+                primary: 0,
+                failovers: 0,
+                latest_ledger: 0,
+            }),
+        }
+    }
+
+    /// Create a pool of HTTP(S) endpoints, the first being the primary
+    pub fn from_urls<I, S>(urls: I, options: PoolOptions) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        urls.into_iter().fold(Self::new(options), |pool, url| {
+            let url = url.into();
+            pool.with_endpoint(url.clone(), HttpTransport::new(url))
+        })
+    }
+
+    /// Add an endpoint, the first added being the primary
+    pub fn with_endpoint(
+        mut self,
+        name: impl Into<String>,
+        transport: impl Transport + 'static,
+    ) -> Self {
+        self.transports.push(Arc::new(transport));
+        self.state.get_mut().unwrap().endpoints.push(EndpointState {
+            metrics: EndpointMetrics {
+                name: name.into(),
+                health: MAX_HEALTH,
+                ..EndpointMetrics::default()
+            },
+            limited_until: None,
+        });
+        self
+    }
+
+    /// Counters of the pool so far
+    pub fn metrics(&self) -> PoolMetrics {
+        let state = self.state.lock().unwrap();
+        PoolMetrics {
+            endpoints: state
+                .endpoints
+                .iter()
+                .map(|endpoint| endpoint.metrics.clone())
+                .collect(),
+            primary: state.primary,
+            failovers: state.failovers,
+            latest_ledger: state.latest_ledger,
+        }
+    }
+
+    /// Endpoints to try, in order, skipping those rate limited
+    ///
+    /// # Errors
+    /// * `RateLimited` - If every endpoint is, retrying after the first is
+    ///   available again
+    /// * `Transport` - If the pool has no endpoint
+    fn order(&self, submission: bool) -> Result<Vec<usize>, Error> {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let mut order: Vec<usize> = (0..state.endpoints.len())
+            .filter(|&index| {
+                state.endpoints[index]
+                    .limited_until
+                    .is_none_or(|until| until <= now)
+            })
+            .collect();
+        if order.is_empty() {
+            let retry_after = state
+                .endpoints
+                .iter()
+                .filter_map(|endpoint| endpoint.limited_until)
+                .min()
+                .map(|until| until - now);
+            return Err(match retry_after {
+                Some(retry_after) => Error::RateLimited {
+                    retry_after: Some(retry_after),
+                },
+                None => Error::Transport("no RPC endpoint configured".into()),
+            });
+        }
+        // Healthiest first, in the order added among equals
+        order.sort_by_key(|&index| std::cmp::Reverse(state.endpoints[index].metrics.health));
+        if submission {
+            if let Some(position) = order.iter().position(|&index| index == state.primary) {
+                let primary = order.remove(position);
+                order.insert(0, primary);
+            }
+        }
+        Ok(order)
+    }
+
+    /// Send a request to one endpoint, scoring its answer
+    async fn attempt(&self, index: usize, method: &str, params: Value) -> Attempt {
+        self.state.lock().unwrap().endpoints[index].metrics.requests += 1;
+        let timeout = self.options.request_timeout;
+        let result = tokio::time::timeout(timeout, self.transports[index].request(method, params))
+            .await
+            .unwrap_or_else(|_| Err(Error::Transport(format!("no answer within {timeout:?}"))));
+
+        let mut state = self.state.lock().unwrap();
+        let latest_ledger = state.latest_ledger;
+        let endpoint = &mut state.endpoints[index];
+        match result {
+            Ok(value) => match answered_ledger(&value) {
+                Some(ledger)
+                    if ledger.saturating_add(self.options.max_ledger_lag) < latest_ledger =>
+                {
+                    endpoint.metrics.stale += 1;
+                    endpoint.metrics.health = endpoint.metrics.health.saturating_sub(10);
+                    Attempt::Stale(value, ledger)
+                }
+                ledger => {
+                    endpoint.metrics.health = (endpoint.metrics.health + 10).min(MAX_HEALTH);
+                    state.latest_ledger = latest_ledger.max(ledger.unwrap_or_default());
+                    Attempt::Answered(value)
+                }
+            },
+            Err(Error::RateLimited { retry_after }) => {
+                endpoint.metrics.rate_limited += 1;
+                let backoff = retry_after.unwrap_or(self.options.rate_limit_backoff);
+                endpoint.limited_until = Some(Instant::now() + backoff);
+                Attempt::Failed(Error::RateLimited { retry_after })
+            }
+            Err(e) => {
+                endpoint.metrics.failures += 1;
+                endpoint.metrics.health = endpoint.metrics.health.saturating_sub(30);
+                Attempt::Failed(e)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for EndpointPool {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let submission = method == "sendTransaction";
+        let order = self.order(submission)?;
+        let mut freshest: Option<(Value, u32)> = None;
+        let mut error = None;
+        for (attempt, &index) in order.iter().enumerate() {
+            if attempt > 0 {
+                self.state.lock().unwrap().failovers += 1;
+                tracing::debug!(method, endpoint = index, "rpc failover");
+            }
+            match self.attempt(index, method, params.clone()).await {
+                Attempt::Answered(value) => {
+                    if submission {
+                        self.state.lock().unwrap().primary = index;
+                    }
+                    return Ok(value);
+                }
+                // A submission went through, however stale its endpoint
+                Attempt::Stale(value, _) if submission => {
+                    self.state.lock().unwrap().primary = index;
+                    return Ok(value);
+                }
+                Attempt::Stale(value, ledger) => {
+                    if freshest.as_ref().is_none_or(|(_, best)| ledger > *best) {
+                        freshest = Some((value, ledger));
+                    }
+                }
+                Attempt::Failed(e) if e.is_transient() => {
+                    tracing::warn!(method, endpoint = index, error = %e, "rpc endpoint failed");
+                    error = Some(e);
+                }
+                Attempt::Failed(e) => return Err(e),
+            }
+        }
+        match (freshest, error) {
+            (Some((value, _)), _) => Ok(value),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(Error::Transport("no RPC endpoint answered".into())),
+        }
+    }
+}
+
+/// Ledger an answer was given for, if it says
+fn answered_ledger(value: &Value) -> Option<u32> {
+    value
+        .get("latestLedger")
+        // `getLatestLedger` answers with the ledger itself
+        .or_else(|| value.get("sequence"))
+        .and_then(Value::as_u64)
+        .and_then(|ledger| u32::try_from(ledger).ok())
+}
@@ -0,0 +1,93 @@
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use stellar_strkey::ed25519;
+use x402_types::canonical_json_hash;
+
+use crate::Error;
+
+/// Version of the settlement receipt format issued by this SDK
+pub const RECEIPT_VERSION: u32 = 1;
+
+/// Facts of a settled payment, as read on-chain by its server
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptBody {
+    /// Format of the receipt, [`RECEIPT_VERSION`] when issued
+    pub version: u32,
+    /// Hex-encoded SHA-256 of the network passphrase
+    pub network_id: String,
+    /// Escrow contract (C... format)
+    pub contract_id: String,
+    pub payment_id: u64,
+    pub escrow_id: u64,
+    /// Client charged (G... format)
+    pub client: String,
+    /// Server paid, whose key signs the receipt (G... format)
+    pub server: String,
+    /// Amount settled, in stroops
+    pub amount: String,
+    /// Asset the escrow holds, `native` for XLM
+    pub asset: String,
+    /// ISO 8601 time the payment was created
+    pub created_at: String,
+    /// ISO 8601 close time of the ledger the payment settled in
+    pub settled_at: String,
+    /// Ledger the payment settled in
+    pub ledger: u32,
+    /// Hex-encoded hash of the settling transaction
+    pub tx_hash: String,
+}
+
+impl ReceiptBody {
+    /// Receipt hash: SHA-256 of the canonical JSON of the body
+    pub fn hash(&self) -> [u8; 32] {
+        canonical_json_hash(self).expect("receipt bodies have a JSON form")
+    }
+}
+
+/// Settlement receipt signed by the payment's server, for auditors without
+/// chain access
+///
+/// Serialized as a flat JSON object: the body's fields, the receipt hash,
+/// and the signature.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementReceipt {
+    #[serde(flatten)]
+    pub body: ReceiptBody,
+    /// Hex-encoded [`ReceiptBody::hash`]
+    pub receipt_hash: String,
+    /// Hex-encoded ed25519 signature of the receipt hash by the server
+    pub signature: String,
+}
+
+impl SettlementReceipt {
+    /// Check the receipt offline: its hash is recomputed from its fields,
+    /// and must be signed by its server
+    ///
+    /// # Errors
+    /// * `InvalidReceipt` - If the version is unknown, the hash does not
+    ///   match the fields, or the signature is not the server's
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.body.version != RECEIPT_VERSION {
+            return Err(invalid(format!("unknown version {}", self.body.version)));
+        }
+        let hash = self.body.hash();
+        if hex::decode(&self.receipt_hash).ok().as_deref() != Some(hash.as_slice()) {
+            return Err(invalid("receipt hash does not match its fields"));
+        }
+        let server = ed25519::PublicKey::from_string(&self.body.server)
+            .map_err(|_| invalid(format!("server {} is not an account", self.body.server)))?;
+        let key = VerifyingKey::from_bytes(&server.0)
+            .map_err(|_| invalid("server key is not a valid ed25519 key"))?;
+        let mut signature = [0; 64];
+        hex::decode_to_slice(&self.signature, &mut signature)
+            .map_err(|_| invalid("expected a hex-encoded 64-byte signature"))?;
+        key.verify_strict(&hash, &Signature::from_bytes(&signature))
+            .map_err(|_| invalid("signature is not the server's"))
+    }
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidReceipt(reason.into())
+}
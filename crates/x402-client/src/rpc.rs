@@ -0,0 +1,409 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use stellar_xdr::curr::{
+    AccountEntry, AccountId, LedgerEntryData, LedgerKey, LedgerKeyAccount, Limits, PublicKey,
+    ReadXdr, TransactionEnvelope, Uint256, WriteXdr,
+};
+
+use crate::Error;
+
+/// JSON-RPC transport to a Soroban RPC server
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Send a request and return the `result` member of the response
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error>;
+}
+
+/// Transport over HTTP(S)
+pub struct HttpTransport {
+    http: reqwest::Client,
+    url: String,
+    next_id: AtomicU64,
+}
+
+impl HttpTransport {
+    /// Create a transport for the RPC endpoint at `url`
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.into(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for HttpTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+
+        let response = self
+            .http
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| Error::Transport(e.to_string()))?;
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(Error::RateLimited {
+                retry_after: retry_after(response.headers()),
+            });
+        }
+        let response: Value = response
+            .json()
+            .await
+            .map_err(|e| Error::Transport(e.to_string()))?;
+
+        parse_response(response)
+    }
+}
+
+/// Delay a `Retry-After` header asks for, in seconds
+///
+/// HTTP dates are not supported, leaving the delay to the caller.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Extract the result of a JSON-RPC response, mapping errors
+pub(crate) fn parse_response(mut response: Value) -> Result<Value, Error> {
+    if let Some(error) = response.get("error") {
+        return Err(Error::Rpc {
+            code: error["code"].as_i64().unwrap_or_default(),
+            message: error["message"].as_str().unwrap_or_default().to_string(),
+        });
+    }
+    match response.get_mut("result") {
+        Some(result) => Ok(result.take()),
+        None => Err(Error::InvalidResponse("missing result".into())),
+    }
+}
+
+/// Response of `getNetwork`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetNetworkResponse {
+    pub passphrase: String,
+    pub protocol_version: u32,
+}
+
+/// Response of `getLatestLedger`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetLatestLedgerResponse {
+    pub id: String,
+    pub protocol_version: u32,
+    pub sequence: u32,
+    /// Unix timestamp, as a string, the ledger closed at, reported by
+    /// protocol 23 servers onwards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub close_time: Option<String>,
+}
+
+/// Response of `getLedgers`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetLedgersResponse {
+    pub ledgers: Vec<LedgerInfo>,
+    pub latest_ledger: u32,
+}
+
+/// One ledger of a `getLedgers` response
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerInfo {
+    pub hash: String,
+    pub sequence: u32,
+    /// Unix timestamp, as a string, the ledger closed at
+    pub ledger_close_time: String,
+}
+
+/// One entry of a `getLedgerEntries` response
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerEntryResult {
+    /// Base64 `LedgerKey`
+    pub key: String,
+    /// Base64 `LedgerEntryData`
+    pub xdr: String,
+    pub last_modified_ledger_seq: u32,
+}
+
+/// Response of `getLedgerEntries`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetLedgerEntriesResponse {
+    #[serde(default)]
+    pub entries: Vec<LedgerEntryResult>,
+    pub latest_ledger: u32,
+}
+
+/// Result of the simulated host function
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulateHostFunctionResult {
+    /// Base64 `SorobanAuthorizationEntry` values
+    #[serde(default)]
+    pub auth: Vec<String>,
+    /// Base64 `ScVal` return value
+    pub xdr: String,
+}
+
+/// Response of `simulateTransaction`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulateTransactionResponse {
+    pub latest_ledger: u32,
+    /// Host error text when simulation failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Base64 `SorobanTransactionData`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_data: Option<String>,
+    /// Minimum resource fee, in stroops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_resource_fee: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<SimulateHostFunctionResult>,
+}
+
+/// Response of `sendTransaction`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendTransactionResponse {
+    /// PENDING, DUPLICATE, TRY_AGAIN_LATER, or ERROR
+    pub status: String,
+    pub hash: String,
+    pub latest_ledger: u32,
+    /// Base64 `TransactionResult` when status is ERROR
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_result_xdr: Option<String>,
+}
+
+/// Response of `getTransaction`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTransactionResponse {
+    /// SUCCESS, NOT_FOUND, or FAILED
+    pub status: String,
+    pub latest_ledger: u32,
+    /// Ledger the transaction was included in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ledger: Option<u32>,
+    /// Unix timestamp, as a string, the including ledger closed at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    /// Base64 `TransactionEnvelope`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub envelope_xdr: Option<String>,
+    /// Base64 `TransactionMeta`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_meta_xdr: Option<String>,
+}
+
+/// Contract event returned by `getEvents`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventInfo {
+    /// contract, system, or diagnostic
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub ledger: u32,
+    /// ISO 8601 close time of the ledger
+    pub ledger_closed_at: String,
+    pub contract_id: String,
+    /// Unique, ordered event ID, usable as a cursor
+    pub id: String,
+    /// Base64 `ScVal` topics
+    pub topic: Vec<String>,
+    /// Base64 `ScVal` data
+    pub value: String,
+    #[serde(default)]
+    pub in_successful_contract_call: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+}
+
+/// Response of `getEvents`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetEventsResponse {
+    #[serde(default)]
+    pub events: Vec<EventInfo>,
+    pub latest_ledger: u32,
+    /// Cursor to resume after this page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// Where a `getEvents` page starts
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EventsFrom {
+    /// First ledger to include
+    Ledger(u32),
+    /// Events strictly after this cursor
+    Cursor(String),
+}
+
+/// Typed wrapper over the Soroban RPC methods used by the SDK
+#[derive(Clone)]
+pub struct Rpc {
+    transport: Arc<dyn Transport>,
+}
+
+impl Rpc {
+    /// Create an RPC client over the given transport
+    pub fn new(transport: impl Transport + 'static) -> Self {
+        Self {
+            transport: Arc::new(transport),
+        }
+    }
+
+    #[tracing::instrument(level = "debug", name = "rpc", skip(self, params))]
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, Error> {
+        let start = Instant::now();
+        let result = self.transport.request(method, params).await;
+        let elapsed_ms = start.elapsed().as_millis() as u64;
+        match &result {
+            Ok(_) => tracing::debug!(elapsed_ms, "rpc call"),
+            Err(e) => tracing::debug!(elapsed_ms, error = %e, "rpc call failed"),
+        }
+        serde_json::from_value(result?).map_err(|e| Error::InvalidResponse(e.to_string()))
+    }
+
+    pub async fn get_network(&self) -> Result<GetNetworkResponse, Error> {
+        self.call("getNetwork", Value::Null).await
+    }
+
+    pub async fn get_latest_ledger(&self) -> Result<GetLatestLedgerResponse, Error> {
+        self.call("getLatestLedger", Value::Null).await
+    }
+
+    /// Unix timestamp the latest ledger closed at, which contracts see as
+    /// `env.ledger().timestamp()`
+    ///
+    /// Servers not reporting it with the latest ledger are asked for the
+    /// ledger with `getLedgers`.
+    ///
+    /// # Errors
+    /// * `InvalidResponse` - If the close time is missing or malformed
+    pub async fn get_ledger_time(&self) -> Result<u64, Error> {
+        let latest = self.get_latest_ledger().await?;
+        let close_time = match latest.close_time {
+            Some(close_time) => close_time,
+            None => {
+                let response: GetLedgersResponse = self
+                    .call(
+                        "getLedgers",
+                        json!({ "startLedger": latest.sequence, "pagination": { "limit": 1 } }),
+                    )
+                    .await?;
+                response
+                    .ledgers
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| Error::InvalidResponse("ledger not found".into()))?
+                    .ledger_close_time
+            }
+        };
+        close_time
+            .parse()
+            .map_err(|_| Error::InvalidResponse(format!("invalid close time {close_time}")))
+    }
+
+    /// Load the account entry of an ed25519 account
+    ///
+    /// # Errors
+    /// * `InvalidResponse` - If the account does not exist
+    pub async fn get_account(&self, public_key: &[u8; 32]) -> Result<AccountEntry, Error> {
+        let key = LedgerKey::Account(LedgerKeyAccount {
+            account_id: AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(*public_key))),
+        });
+        let response: GetLedgerEntriesResponse = self
+            .call(
+                "getLedgerEntries",
+                json!({ "keys": [key.to_xdr_base64(Limits::none())?] }),
+            )
+            .await?;
+
+        let entry = response
+            .entries
+            .first()
+            .ok_or_else(|| Error::InvalidResponse("account not found".into()))?;
+        match LedgerEntryData::from_xdr_base64(&entry.xdr, Limits::none())? {
+            LedgerEntryData::Account(account) => Ok(account),
+            _ => Err(Error::InvalidResponse("expected an account entry".into())),
+        }
+    }
+
+    pub async fn simulate_transaction(
+        &self,
+        envelope: &TransactionEnvelope,
+    ) -> Result<SimulateTransactionResponse, Error> {
+        self.call(
+            "simulateTransaction",
+            json!({ "transaction": envelope.to_xdr_base64(Limits::none())? }),
+        )
+        .await
+    }
+
+    pub async fn send_transaction(
+        &self,
+        envelope: &TransactionEnvelope,
+    ) -> Result<SendTransactionResponse, Error> {
+        self.call(
+            "sendTransaction",
+            json!({ "transaction": envelope.to_xdr_base64(Limits::none())? }),
+        )
+        .await
+    }
+
+    pub async fn get_transaction(&self, hash: &str) -> Result<GetTransactionResponse, Error> {
+        self.call("getTransaction", json!({ "hash": hash })).await
+    }
+
+    /// Fetch a page of events emitted by `contract_id`
+    ///
+    /// # Arguments
+    /// * `contract_id` - Contract address (C... format)
+    /// * `from` - Start ledger or cursor of the page
+    /// * `limit` - Maximum number of events returned
+    pub async fn get_events(
+        &self,
+        contract_id: &str,
+        from: &EventsFrom,
+        limit: u32,
+    ) -> Result<GetEventsResponse, Error> {
+        let mut params = json!({
+            "filters": [{ "type": "contract", "contractIds": [contract_id] }],
+            "pagination": { "limit": limit },
+        });
+        match from {
+            EventsFrom::Ledger(ledger) => params["startLedger"] = json!(ledger),
+            EventsFrom::Cursor(cursor) => params["pagination"]["cursor"] = json!(cursor),
+        }
+        self.call("getEvents", params).await
+    }
+}
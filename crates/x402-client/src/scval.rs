@@ -0,0 +1,155 @@
+//! Conversions between contract values and Rust types
+
+use stellar_strkey::{ed25519, Contract, Strkey};
+use stellar_xdr::curr::{
+    AccountId, Hash, Int128Parts, PublicKey, ScAddress, ScMap, ScSymbol, ScVal, Uint256,
+};
+
+use crate::Error;
+
+/// Parse a `G...` or `C...` strkey into a contract address
+pub fn parse_address(address: &str) -> Result<ScAddress, Error> {
+    match Strkey::from_string(address) {
+        Ok(Strkey::PublicKeyEd25519(key)) => Ok(ScAddress::Account(AccountId(
+            PublicKey::PublicKeyTypeEd25519(Uint256(key.0)),
+        ))),
+        Ok(Strkey::Contract(contract)) => Ok(ScAddress::Contract(Hash(contract.0))),
+        _ => Err(Error::InvalidAddress(address.to_string())),
+    }
+}
+
+/// Format a contract address as a strkey
+pub fn format_address(address: &ScAddress) -> String {
+    match address {
+        ScAddress::Account(AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(key)))) => {
+            ed25519::PublicKey(*key).to_string()
+        }
+        ScAddress::Contract(Hash(id)) => Contract(*id).to_string(),
+    }
+}
+
+pub fn address(address: &str) -> Result<ScVal, Error> {
+    Ok(ScVal::Address(parse_address(address)?))
+}
+
+pub fn u64(value: u64) -> ScVal {
+    ScVal::U64(value)
+}
+
+pub fn vec(values: Vec<ScVal>) -> Result<ScVal, Error> {
+    Ok(ScVal::Vec(Some(values.try_into()?)))
+}
+
+pub fn i128(value: i128) -> ScVal {
+    ScVal::I128(Int128Parts {
+        hi: (value >> 64) as i64,
+        lo: value as u64,
+    })
+}
+
+fn unexpected(expected: &str, value: &ScVal) -> Error {
+    Error::InvalidResponse(format!("expected {expected}, got {value:?}"))
+}
+
+pub fn to_u64(value: &ScVal) -> Result<u64, Error> {
+    match value {
+        ScVal::U64(v) => Ok(*v),
+        other => Err(unexpected("u64", other)),
+    }
+}
+
+pub fn to_u32(value: &ScVal) -> Result<u32, Error> {
+    match value {
+        ScVal::U32(v) => Ok(*v),
+        other => Err(unexpected("u32", other)),
+    }
+}
+
+pub fn to_i128(value: &ScVal) -> Result<i128, Error> {
+    match value {
+        ScVal::I128(parts) => Ok(((parts.hi as i128) << 64) | parts.lo as i128),
+        other => Err(unexpected("i128", other)),
+    }
+}
+
+pub fn to_bool(value: &ScVal) -> Result<bool, Error> {
+    match value {
+        ScVal::Bool(v) => Ok(*v),
+        other => Err(unexpected("bool", other)),
+    }
+}
+
+pub fn to_address(value: &ScVal) -> Result<String, Error> {
+    match value {
+        ScVal::Address(address) => Ok(format_address(address)),
+        other => Err(unexpected("address", other)),
+    }
+}
+
+pub fn to_symbol(value: &ScVal) -> Result<String, Error> {
+    match value {
+        ScVal::Symbol(ScSymbol(symbol)) => Ok(symbol.to_utf8_string_lossy()),
+        other => Err(unexpected("symbol", other)),
+    }
+}
+
+pub fn to_bytes(value: &ScVal) -> Result<Vec<u8>, Error> {
+    match value {
+        ScVal::Bytes(bytes) => Ok(bytes.to_vec()),
+        other => Err(unexpected("bytes", other)),
+    }
+}
+
+pub fn to_bytes32(value: &ScVal) -> Result<[u8; 32], Error> {
+    match value {
+        ScVal::Bytes(bytes) => bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| unexpected("32 bytes", value)),
+        other => Err(unexpected("bytes", other)),
+    }
+}
+
+pub fn to_vec(value: &ScVal) -> Result<&[ScVal], Error> {
+    match value {
+        ScVal::Vec(Some(values)) => Ok(values.as_slice()),
+        other => Err(unexpected("vec", other)),
+    }
+}
+
+/// Decode an `Option<T>`, which contracts encode as void or the value
+pub fn to_option<T>(
+    value: &ScVal,
+    decode: impl FnOnce(&ScVal) -> Result<T, Error>,
+) -> Result<Option<T>, Error> {
+    match value {
+        ScVal::Void => Ok(None),
+        other => decode(other).map(Some),
+    }
+}
+
+/// Field accessor for `#[contracttype]` structs, which encode as symbol-keyed maps
+pub struct Fields<'a>(&'a ScMap);
+
+impl<'a> Fields<'a> {
+    pub fn new(value: &'a ScVal) -> Result<Self, Error> {
+        match value {
+            ScVal::Map(Some(map)) => Ok(Self(map)),
+            other => Err(unexpected("struct", other)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&'a ScVal, Error> {
+        self.find(name)
+            .ok_or_else(|| Error::InvalidResponse(format!("missing field `{name}`")))
+    }
+
+    /// Field added after the first deployment, None if the contract
+    /// recorded the struct before it
+    pub fn find(&self, name: &str) -> Option<&'a ScVal> {
+        self.0
+            .iter()
+            .find(|entry| matches!(&entry.key, ScVal::Symbol(ScSymbol(s)) if s.as_slice() == name.as_bytes()))
+            .map(|entry| &entry.val)
+    }
+}
@@ -0,0 +1,215 @@
+use async_trait::async_trait;
+use ed25519_dalek::{Signer as _, SigningKey};
+use stellar_strkey::{ed25519, Strkey};
+
+use crate::Error;
+
+/// Signs transaction hashes on behalf of a Stellar account
+#[async_trait]
+pub trait Signer: Send + Sync {
+    /// Ed25519 public key of the signing account
+    fn public_key(&self) -> [u8; 32];
+
+    /// Ed25519 public key of the key signing, the account's own unless it
+    /// is another key added as a signer of the account
+    ///
+    /// Signatures are hinted with it.
+    fn signing_key(&self) -> [u8; 32] {
+        self.public_key()
+    }
+
+    /// Sign a 32-byte transaction hash
+    async fn sign(&self, hash: &[u8; 32]) -> Result<[u8; 64], Error>;
+}
+
+/// Signer holding an ed25519 secret key in memory
+pub struct LocalSigner {
+    key: SigningKey,
+    /// Account signed for, when not the key's own
+    account: Option<[u8; 32]>,
+}
+
+impl LocalSigner {
+    /// Create a signer from a secret seed
+    pub fn from_bytes(seed: &[u8; 32]) -> Self {
+        Self {
+            key: SigningKey::from_bytes(seed),
+            account: None,
+        }
+    }
+
+    /// Create a signer from an `S...` secret key
+    ///
+    /// # Errors
+    /// * `InvalidAddress` - If `secret` is not a valid secret strkey
+    pub fn from_secret(secret: &str) -> Result<Self, Error> {
+        match Strkey::from_string(secret) {
+            Ok(Strkey::PrivateKeyEd25519(key)) => Ok(Self::from_bytes(&key.0)),
+            _ => Err(Error::InvalidAddress("expected an S... secret key".into())),
+        }
+    }
+
+    /// Sign for the account at `address`, the key being one of its signers
+    ///
+    /// A key rotated into an account is added as a signer of the account,
+    /// since the account's own key never changes.
+    ///
+    /// # Errors
+    /// * `InvalidAddress` - If `address` is not a valid account strkey
+    pub fn for_account(self, address: &str) -> Result<Self, Error> {
+        Ok(Self {
+            account: Some(account_key(address)?),
+            ..self
+        })
+    }
+
+    /// `G...` address of the signing account
+    pub fn address(&self) -> String {
+        ed25519::PublicKey(self.public_key()).to_string()
+    }
+}
+
+#[async_trait]
+impl Signer for LocalSigner {
+    fn public_key(&self) -> [u8; 32] {
+        self.account.unwrap_or_else(|| self.signing_key())
+    }
+
+    fn signing_key(&self) -> [u8; 32] {
+        self.key.verifying_key().to_bytes()
+    }
+
+    async fn sign(&self, hash: &[u8; 32]) -> Result<[u8; 64], Error> {
+        Ok(self.key.sign(hash).to_bytes())
+    }
+}
+
+/// Signer delegating to an external program, e.g. a hardware wallet or KMS
+/// helper
+///
+/// The program receives the hex-encoded hash as its last argument and must
+/// print the hex-encoded signature on stdout. It is killed if the client's
+/// signing timeout elapses first.
+pub struct CommandSigner {
+    public_key: [u8; 32],
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandSigner {
+    /// Create a signer running `program` for the account at `address`
+    ///
+    /// # Arguments
+    /// * `address` - `G...` address of the account the program signs for
+    /// * `program` - Program to run
+    /// * `args` - Arguments passed before the hash
+    ///
+    /// # Errors
+    /// * `InvalidAddress` - If `address` is not a valid account strkey
+    pub fn new<I, S>(address: &str, program: impl Into<String>, args: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Ok(Self {
+            public_key: account_key(address)?,
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+        })
+    }
+}
+
+#[async_trait]
+impl Signer for CommandSigner {
+    fn public_key(&self) -> [u8; 32] {
+        self.public_key
+    }
+
+    async fn sign(&self, hash: &[u8; 32]) -> Result<[u8; 64], Error> {
+        let output = tokio::process::Command::new(&self.program)
+            .args(&self.args)
+            .arg(hex::encode(hash))
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| Error::Signer(format!("cannot run {}: {e}", self.program)))?;
+        if !output.status.success() {
+            return Err(Error::Signer(format!(
+                "{} exited with {}: {}",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        parse_signature(String::from_utf8_lossy(&output.stdout).trim())
+    }
+}
+
+/// Signer delegating to an HTTP signing service
+///
+/// Each hash is POSTed as `{"public_key": "G...", "hash": "<hex>"}` and the
+/// service answers `{"signature": "<hex>"}`.
+pub struct HttpSigner {
+    http: reqwest::Client,
+    url: String,
+    public_key: [u8; 32],
+}
+
+impl HttpSigner {
+    /// Create a signer calling the service at `url` for the account at
+    /// `address`
+    ///
+    /// # Errors
+    /// * `InvalidAddress` - If `address` is not a valid account strkey
+    pub fn new(address: &str, url: impl Into<String>) -> Result<Self, Error> {
+        Ok(Self {
+            http: reqwest::Client::new(),
+            url: url.into(),
+            public_key: account_key(address)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Signer for HttpSigner {
+    fn public_key(&self) -> [u8; 32] {
+        self.public_key
+    }
+
+    async fn sign(&self, hash: &[u8; 32]) -> Result<[u8; 64], Error> {
+        let body = serde_json::json!({
+            "public_key": ed25519::PublicKey(self.public_key).to_string(),
+            "hash": hex::encode(hash),
+        });
+        let response = self
+            .http
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::Signer(e.to_string()))?;
+        let response: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| Error::Signer(e.to_string()))?;
+        match response["signature"].as_str() {
+            Some(signature) => parse_signature(signature),
+            None => Err(Error::Signer("response has no signature".into())),
+        }
+    }
+}
+
+fn account_key(address: &str) -> Result<[u8; 32], Error> {
+    match Strkey::from_string(address) {
+        Ok(Strkey::PublicKeyEd25519(key)) => Ok(key.0),
+        _ => Err(Error::InvalidAddress(address.to_string())),
+    }
+}
+
+fn parse_signature(signature: &str) -> Result<[u8; 64], Error> {
+    hex::decode(signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| Error::Signer("expected a hex-encoded 64-byte signature".into()))
+}
@@ -0,0 +1,72 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use stellar_xdr::curr::ScVal;
+
+use crate::{PreparedTransaction, Submitted};
+
+/// Submissions of a transaction before [`EscrowClient::submit_idempotent`]
+/// gives up on learning its outcome
+///
+/// [`EscrowClient::submit_idempotent`]: crate::EscrowClient::submit_idempotent
+pub const SUBMIT_ATTEMPTS: u32 = 3;
+
+/// Final outcome of an idempotent submission
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Disposition {
+    /// The transaction applied
+    Included(Submitted<ScVal>),
+    /// The transaction was included but failed, consuming its sequence number
+    Failed { hash: String },
+    /// The transaction was neither seen included nor provably dropped when
+    /// the attempts ran out; it may still apply
+    Unknown { hash: String },
+}
+
+impl Disposition {
+    /// Hash of the last transaction submitted
+    pub fn hash(&self) -> &str {
+        match self {
+            Self::Included(submitted) => &submitted.hash,
+            Self::Failed { hash } | Self::Unknown { hash } => hash,
+        }
+    }
+}
+
+/// Signed transactions recorded before they are sent, by idempotency key
+///
+/// A call retried with the same key picks up the recorded transaction, so it
+/// is looked up and resubmitted rather than signed again. Implementations
+/// backed by durable storage make calls retryable across restarts.
+pub trait SubmissionLog: Send + Sync {
+    /// Transaction last recorded under `key`
+    fn get(&self, key: &str) -> Option<PreparedTransaction>;
+
+    /// Record `tx` under `key`, replacing any previous transaction
+    fn record(&self, key: &str, tx: &PreparedTransaction);
+}
+
+/// Submission log of a single process
+#[derive(Debug, Default)]
+pub struct MemorySubmissionLog {
+    entries: Mutex<HashMap<String, PreparedTransaction>>,
+}
+
+impl MemorySubmissionLog {
+    /// Create an empty log
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SubmissionLog for MemorySubmissionLog {
+    fn get(&self, key: &str) -> Option<PreparedTransaction> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    fn record(&self, key: &str, tx: &PreparedTransaction) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), tx.clone());
+    }
+}
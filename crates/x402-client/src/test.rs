@@ -0,0 +1,5247 @@
+#![cfg(test)]
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use ed25519_dalek::{Signature, VerifyingKey};
+use futures_util::{Stream, StreamExt};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use soroban_sdk::testutils::{Address as _, Ledger as _};
+use stellar_xdr::curr::{
+    HostFunction, Limits, Memo, OperationBody, ReadXdr, ScBytes, ScMap, ScMapEntry, ScString,
+    ScVal, TransactionEnvelope, WriteXdr,
+};
+use tracing_test::traced_test;
+use x402_bindings::Invocation;
+use x402_escrow::{X402EscrowContract, X402EscrowContractClient};
+use x402_types::{
+    decode_payment_header, encode_payment_response_header, network_passphrase, AssetAmount,
+    PaymentErrorBody, PaymentRequiredResponse, PaymentRequirements, PaymentResponseHeader,
+    SchemePayload, SettleResponse, SigningDomain, ESCROW_SCHEME, NATIVE_ASSET, PAYMENT_HEADER,
+    PAYMENT_RESPONSE_HEADER, STELLAR_MAINNET, STELLAR_TESTNET, X402_VERSION,
+};
+
+use crate::{
+    random_nonce, scval,
+    testutils::{format_timestamp, EnvTransport, MIN_RESOURCE_FEE, NETWORK_PASSPHRASE},
+    Allowance, AuthorizationEntry, BatchOptions, CallContext, ChannelState, ClientOptions,
+    CommandSigner, ContractError, Decision, Describer, Disposition, Emitted, EndpointPool, Error,
+    EscrowClient, EscrowEvent, EscrowLinks, EscrowOp, EventFilter, EventInfo, EventKind,
+    EventsFrom, Exposure, FactValue, FeeBumpPolicy, Finality, FinalityPolicy, GetEventsResponse,
+    HttpSigner, HttpTransport, JournalEntry, LocalSigner, MemorySubmissionLog, MonitorAlert,
+    MonitorRules, Payment, PaymentBatcher, PaymentJournal, PaymentStatus, PolicyError, PoolOptions,
+    PreparedTransaction, ProtocolError, Rpc, ServerMonitor, Settlement, SettlementReceipt, Signer,
+    SpendPolicy, SubmissionLog, Submitted, Transport, TransportError, X402Error, X402HttpClient,
+    EVENT_VERSION, PAYMENT_PAGE_RETRIES,
+};
+
+struct Setup {
+    client: EscrowClient,
+    server: EscrowClient,
+    client_addr: String,
+    server_addr: String,
+}
+
+/// ID of the payment at `index` of `escrow_id`, as the contract allocates it
+fn payment_id(escrow_id: u64, index: u32) -> u64 {
+    escrow_id << 32 | u64::from(index)
+}
+
+fn setup() -> Setup {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport);
+
+    let client_key = LocalSigner::from_bytes(&[1; 32]);
+    let server_key = LocalSigner::from_bytes(&[2; 32]);
+    let client_addr = client_key.address();
+    let server_addr = server_key.address();
+
+    Setup {
+        client: EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, client_key)
+            .unwrap(),
+        server: EscrowClient::new(rpc, &contract_id, NETWORK_PASSPHRASE, server_key).unwrap(),
+        client_addr,
+        server_addr,
+    }
+}
+
+#[tokio::test]
+async fn test_escrow_lifecycle() {
+    let s = setup();
+
+    // Client opens and tops up the escrow
+    let opened = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 10_000_000, None)
+        .await
+        .unwrap();
+    let escrow_id = opened.value;
+    assert_eq!(opened.hash.len(), 64);
+    s.client.deposit(escrow_id, 5_000_000).await.unwrap();
+
+    let escrow = s.client.get_escrow(escrow_id).await.unwrap();
+    assert_eq!(escrow.client, s.client_addr);
+    assert_eq!(escrow.server, s.server_addr);
+    assert_eq!(escrow.balance, 15_000_000);
+    assert_eq!(
+        s.client
+            .find_escrow(&s.client_addr, &s.server_addr)
+            .await
+            .unwrap(),
+        Some(escrow_id)
+    );
+
+    // Server charges and settles a payment
+    let payment_id = s
+        .server
+        .create_payment(escrow_id, 1_000_000)
+        .await
+        .unwrap()
+        .value;
+    let payment = s.server.get_payment(payment_id).await.unwrap();
+    assert_eq!(payment.amount, 1_000_000);
+    assert!(!payment.settled);
+
+    assert!(s.server.settle_payment(payment_id).await.unwrap().value);
+    let payment = s.server.get_payment(payment_id).await.unwrap();
+    assert!(payment.settled);
+    // No price feed to quote it
+    assert_eq!(payment.quote_amount, None);
+    let total = s.client.get_quote_total(escrow_id).await.unwrap();
+    assert_eq!(total.currency, "USD");
+    assert_eq!(total.unquoted, 1_000_000);
+    assert_eq!(
+        s.server.get_escrow_balance(escrow_id).await.unwrap(),
+        14_000_000
+    );
+
+    // Both parties close
+    assert_eq!(
+        s.client.client_close_escrow(escrow_id).await.unwrap().value,
+        None
+    );
+    assert_eq!(
+        s.server.server_close_escrow(escrow_id).await.unwrap().value,
+        Some(14_000_000)
+    );
+    assert_eq!(
+        s.client
+            .find_escrow(&s.client_addr, &s.server_addr)
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_terms_of_service() {
+    let s = setup();
+    assert_eq!(s.client.get_terms(&s.server_addr).await.unwrap(), None);
+    s.server.set_terms(&s.server_addr, [9; 32]).await.unwrap();
+    assert_eq!(
+        s.client.get_terms(&s.server_addr).await.unwrap(),
+        Some([9; 32])
+    );
+
+    // Opens must accept the published terms, which the escrow records
+    let err = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::TermsMismatch));
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, Some([9; 32]))
+        .await
+        .unwrap()
+        .value;
+    s.server.set_terms(&s.server_addr, [10; 32]).await.unwrap();
+    let escrow = s.client.get_escrow(escrow_id).await.unwrap();
+    assert_eq!(escrow.terms_hash, Some([9; 32]));
+}
+
+#[tokio::test]
+async fn test_notification_url() {
+    let s = setup();
+    let url = "https://api.example.com/x402/hooks";
+    assert_eq!(
+        s.client.get_notification_url(&s.server_addr).await.unwrap(),
+        None
+    );
+    s.server.set_notification_url(url).await.unwrap();
+    assert_eq!(
+        s.client.get_notification_url(&s.server_addr).await.unwrap(),
+        Some(url.into())
+    );
+
+    let err = s
+        .server
+        .set_notification_url(&"a".repeat(x402_bindings::MAX_NOTIFY_URL_LEN as usize + 1))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.contract_error(),
+        Some(ContractError::InvalidNotificationUrl)
+    );
+
+    s.server.set_notification_url("").await.unwrap();
+    assert_eq!(
+        s.client.get_notification_url(&s.server_addr).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_clone_escrow_config() {
+    let s = setup();
+    s.server.set_terms(&s.server_addr, [9; 32]).await.unwrap();
+    let source_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, Some([9; 32]))
+        .await
+        .unwrap()
+        .value;
+
+    let staging = LocalSigner::from_bytes(&[3; 32]).address();
+    let err = s
+        .client
+        .clone_escrow_config(source_id, &staging, 1_000)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "contract error: terms of service accepted are not those the server publishes \
+             (clone_escrow_config, escrow {source_id})"
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_open_sponsored_escrow() {
+    let s = setup();
+    let escrow_id = s
+        .server
+        .open_sponsored_escrow(&s.client_addr, 1_000_000)
+        .await
+        .unwrap()
+        .value;
+    let escrow = s.client.get_escrow(escrow_id).await.unwrap();
+    assert_eq!(escrow.client, s.client_addr);
+    assert!(escrow.trial);
+    assert_eq!(
+        (escrow.balance, escrow.trial_balance),
+        (1_000_000, 1_000_000)
+    );
+
+    // The client's deposits are released, the trial funds left are not
+    s.client.deposit(escrow_id, 500).await.unwrap();
+    s.server.server_close_escrow(escrow_id).await.unwrap();
+    let released = s.client.client_close_escrow(escrow_id).await.unwrap();
+    assert_eq!(released.value, Some(500));
+
+    let err = s
+        .server
+        .open_sponsored_escrow(&s.client_addr, 0)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Contract(ContractError::InvalidTrial, _)
+    ));
+}
+
+#[tokio::test]
+async fn test_split_escrow() {
+    let s = setup();
+    let parent = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let child = s
+        .client
+        .split_escrow(parent, 400_000, b"tier:batch")
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(s.client.get_escrow_balance(parent).await.unwrap(), 600_000);
+    assert_eq!(s.client.get_escrow_balance(child).await.unwrap(), 400_000);
+    assert_eq!(
+        s.client.get_escrow_links(parent).await.unwrap(),
+        EscrowLinks {
+            parent: None,
+            children: vec![child],
+            metadata: vec![],
+        }
+    );
+    assert_eq!(
+        s.client.get_escrow_links(child).await.unwrap(),
+        EscrowLinks {
+            parent: Some(parent),
+            children: vec![],
+            metadata: b"tier:batch".to_vec(),
+        }
+    );
+    // The pair still finds the parent
+    assert_eq!(
+        s.client
+            .find_escrow(&s.client_addr, &s.server_addr)
+            .await
+            .unwrap(),
+        Some(parent)
+    );
+
+    let err = s
+        .client
+        .split_escrow(parent, 600_001, b"")
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Contract(ContractError::InvalidSplit, _)
+    ));
+}
+
+#[tokio::test]
+async fn test_spend_limit() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 15_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    s.client
+        .set_max_settled_per_hour(escrow_id, Some(1_000_000))
+        .await
+        .unwrap();
+
+    let payment_id = s
+        .server
+        .create_payment(escrow_id, 600_000)
+        .await
+        .unwrap()
+        .value;
+    assert!(s.server.settle_payment(payment_id).await.unwrap().value);
+
+    // The breach pauses settlements, reported as an error
+    let payment_id = s
+        .server
+        .create_payment(escrow_id, 600_000)
+        .await
+        .unwrap()
+        .value;
+    let err = s.server.settle_payment(payment_id).await.unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::SettlementsPaused));
+    let limit = s.client.get_spend_limit(escrow_id).await.unwrap().unwrap();
+    assert!(limit.paused);
+    assert_eq!(
+        (limit.max_settled_per_hour, limit.settled),
+        (1_000_000, 600_000)
+    );
+    let now = s.client.rpc().get_ledger_time().await.unwrap();
+    assert!(!limit.allows(now, 1));
+    assert!(limit.allows(now + x402_bindings::SPEND_WINDOW, 1_000_000));
+
+    s.client.acknowledge_anomaly(escrow_id).await.unwrap();
+    assert!(s.server.settle_payment(payment_id).await.unwrap().value);
+    s.client
+        .set_max_settled_per_hour(escrow_id, None)
+        .await
+        .unwrap();
+    assert_eq!(s.client.get_spend_limit(escrow_id).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_open_and_authorize() {
+    let s = setup();
+    let op = EscrowOp::OpenEscrow {
+        client: s.client_addr.clone(),
+        server: s.server_addr.clone(),
+        amount: 1_000_000,
+        terms_hash: None,
+    };
+    let escrow_id = scval::to_u64(&s.client.dry_run(&op).await.unwrap().value).unwrap();
+
+    // Signed for another escrow, the open is rolled back with it
+    let payload = s
+        .client
+        .authorize_payment(escrow_id + 1, 100_000, 1, u64::MAX, NETWORK)
+        .await
+        .unwrap();
+    let err = s
+        .client
+        .open_and_authorize(&s.server_addr, 1_000_000, None, &payload, NETWORK)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.contract_error(),
+        Some(ContractError::AuthorizationMismatch)
+    );
+    assert_eq!(
+        s.client
+            .find_escrow(&s.client_addr, &s.server_addr)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        s.server
+            .get_preauthorized_payment(escrow_id, 1)
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_simulation_has_no_side_effects() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    // Reads are simulations; repeating them must not change state
+    for _ in 0..3 {
+        assert_eq!(
+            s.client.get_escrow_balance(escrow_id).await.unwrap(),
+            1_000_000
+        );
+    }
+    // A failing invocation is rejected at simulation and never submitted
+    assert!(s.server.create_payment(escrow_id, 2_000_000).await.is_err());
+    let created = s.server.create_payment(escrow_id, 500_000).await.unwrap();
+
+    assert_eq!(created.value, payment_id(escrow_id, 0));
+}
+
+#[test]
+fn test_errors_map_to_x402() {
+    let limited = Error::RateLimited {
+        retry_after: Some(Duration::from_secs(2)),
+    };
+    let error = X402Error::from(&limited);
+    assert_eq!(error.code(), 1002);
+    assert_eq!(error.retry_after(), Some(Duration::from_secs(2)));
+    assert!(limited.is_transient());
+
+    // A batch fails as its cause did
+    let batch = Error::BatchFailed(Arc::new(Error::Transport("down".into())));
+    assert_eq!(
+        X402Error::from(&batch),
+        X402Error::Transport(TransportError::Unreachable)
+    );
+    assert!(batch.is_transient());
+
+    let timeout = Error::Timeout {
+        hash: "ab".into(),
+        timeout: Duration::from_secs(30),
+    };
+    assert_eq!(
+        X402Error::from(&timeout),
+        X402Error::Transport(TransportError::Timeout)
+    );
+    assert!(!timeout.is_transient());
+    let declined = X402Error::from(&Error::PaymentDeclined("over budget".into()));
+    assert_eq!(declined, X402Error::Policy(PolicyError::PaymentDeclined));
+    assert_eq!(
+        X402Error::from(&Error::Journal("disk full".into())),
+        X402Error::Internal
+    );
+}
+
+#[tokio::test]
+async fn test_contract_errors_are_typed() {
+    let s = setup();
+
+    let err = s.client.get_escrow(9).await.unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Contract(ContractError::EscrowNotFound, _)
+    ));
+    let err = s.client.deposit(9, 100).await.unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::EscrowNotFound));
+    assert_eq!(
+        err.to_string(),
+        "contract error: escrow not found (deposit, escrow 9)"
+    );
+
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let err = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Contract(ContractError::EscrowAlreadyExists, _)
+    ));
+
+    let err = s
+        .server
+        .create_payment(escrow_id, 2_000_000)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Contract(ContractError::InsufficientBalance, _)
+    ));
+    assert_eq!(
+        err.to_string(),
+        format!("contract error: insufficient escrow balance (create_payment, escrow {escrow_id})")
+    );
+    let error = X402Error::from(&err);
+    assert_eq!(
+        error,
+        X402Error::Contract(ContractError::InsufficientBalance)
+    );
+    assert_eq!(error.code(), 3);
+    assert!(!err.is_transient());
+
+    // The terms of a missing payment cannot be read to settle it
+    let err = s.server.settle_payment(3).await.unwrap_err();
+    let Error::Contract(ContractError::PaymentNotFound, context) = err else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!(
+        context,
+        CallContext {
+            operation: "get_payment".into(),
+            escrow_id: None,
+            payment_id: Some(3),
+        }
+    );
+
+    let payment_id = s.server.create_payment(escrow_id, 100).await.unwrap().value;
+    s.server.settle_payment(payment_id).await.unwrap();
+    let err = s.server.settle_payment(payment_id).await.unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Contract(ContractError::PaymentAlreadySettled, _)
+    ));
+
+    // Refunds are capped by the settled amount
+    assert_eq!(
+        s.server.refund_payment(payment_id, 60).await.unwrap().value,
+        60
+    );
+    assert_eq!(s.server.get_refunded(payment_id).await.unwrap(), 60);
+    let err = s.server.refund_payment(payment_id, 41).await.unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::InvalidRefund));
+}
+
+#[tokio::test]
+async fn test_estimate() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    let estimate = s
+        .server
+        .estimate(&EscrowOp::CreatePayment {
+            escrow_id,
+            amount: 500_000,
+        })
+        .await
+        .unwrap();
+    assert_eq!(estimate.inclusion_fee, 100);
+    assert_eq!(estimate.resource_fee, i64::from(MIN_RESOURCE_FEE));
+    assert_eq!(estimate.total_fee, 100 + MIN_RESOURCE_FEE);
+    assert!(estimate.instructions > 0);
+    assert_eq!(estimate.read_write.len(), 1);
+    assert!(estimate
+        .to_string()
+        .starts_with("200 stroops (100 inclusion + 100 resource)"));
+    // Estimating submits nothing
+    assert_eq!(
+        s.client.get_escrow_balance(escrow_id).await.unwrap(),
+        1_000_000
+    );
+
+    // A dry run also returns what the contract would
+    let dry_run = s
+        .server
+        .dry_run(&EscrowOp::CreatePayment {
+            escrow_id,
+            amount: 500_000,
+        })
+        .await
+        .unwrap();
+    let payment_id = scval::to_u64(&dry_run.value).unwrap();
+    assert_eq!(payment_id, self::payment_id(escrow_id, 0));
+    assert_eq!(dry_run.estimate, estimate);
+    assert!(matches!(
+        s.client.get_payment(payment_id).await,
+        Err(Error::Contract(ContractError::PaymentNotFound, _))
+    ));
+
+    let err = s
+        .server
+        .estimate(&EscrowOp::CreatePayment {
+            escrow_id,
+            amount: 2_000_000,
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Contract(ContractError::InsufficientBalance, _)
+    ));
+    let err = s
+        .server
+        .estimate(&EscrowOp::SettlePayments {
+            settlements: vec![Settlement {
+                payment_id: 7,
+                escrow_id,
+                amount: 500_000,
+                client: s.client_addr.clone(),
+            }],
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Contract(ContractError::PaymentNotFound, _)
+    ));
+}
+
+#[tokio::test]
+async fn test_transactions_use_fresh_sequence_numbers() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000, None)
+        .await
+        .unwrap()
+        .value;
+    s.client.deposit(escrow_id, 1_000).await.unwrap();
+    s.client.deposit(escrow_id, 1_000).await.unwrap();
+
+    let signer = LocalSigner::from_bytes(&[1; 32]);
+    let account = s
+        .client
+        .rpc()
+        .get_account(&signer.public_key())
+        .await
+        .unwrap();
+    assert_eq!(account.seq_num.0, 3);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_calls_share_sequence() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    // Concurrent calls of clones would otherwise sign the same sequence number
+    let tasks: Vec<_> = (0..8)
+        .map(|_| {
+            let server = s.server.clone();
+            tokio::spawn(async move { server.create_payment(escrow_id, 10).await })
+        })
+        .collect();
+    let mut payment_ids = Vec::new();
+    for task in tasks {
+        payment_ids.push(task.await.unwrap().unwrap().value);
+    }
+    payment_ids.sort_unstable();
+    let expected: Vec<_> = (0..8).map(|i| payment_id(escrow_id, i)).collect();
+    assert_eq!(payment_ids, expected);
+}
+
+#[tokio::test]
+#[traced_test]
+async fn test_correlation_id_memo() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000, None)
+        .await
+        .unwrap()
+        .value;
+    let correlation_id = x402_types::correlation_id(0, 1);
+    let server = s.server.clone().with_correlation_id(correlation_id.clone());
+    assert_eq!(server.correlation_id(), Some(correlation_id.as_str()));
+
+    let tx = server.prepare_create_payment(escrow_id, 100).await.unwrap();
+    let TransactionEnvelope::Tx(envelope) =
+        TransactionEnvelope::from_xdr_base64(&tx.envelope, Limits::none()).unwrap()
+    else {
+        panic!("unexpected envelope");
+    };
+    assert_eq!(
+        envelope.tx.memo,
+        Memo::Text(correlation_id.as_str().try_into().unwrap())
+    );
+    server.submit(&tx).await.unwrap();
+    assert!(logs_contain("escrow.submit"));
+    assert!(logs_contain(&format!(
+        "correlation_id=\"{correlation_id}\""
+    )));
+    assert!(logs_contain("transaction included"));
+
+    // Untagged clients leave the memo empty
+    let tx = s
+        .server
+        .prepare_create_payment(escrow_id, 100)
+        .await
+        .unwrap();
+    let TransactionEnvelope::Tx(envelope) =
+        TransactionEnvelope::from_xdr_base64(&tx.envelope, Limits::none()).unwrap()
+    else {
+        panic!("unexpected envelope");
+    };
+    assert_eq!(envelope.tx.memo, Memo::None);
+}
+
+#[tokio::test]
+async fn test_batch_settlement() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000, None)
+        .await
+        .unwrap()
+        .value;
+    let mut ids = Vec::new();
+    for amount in [100, 200, 300] {
+        ids.push(
+            s.server
+                .create_payment(escrow_id, amount)
+                .await
+                .unwrap()
+                .value,
+        );
+    }
+
+    let settled = s.server.settle_payments(&[ids[0], ids[2]]).await.unwrap();
+    assert_eq!(settled.value, 400);
+    assert_eq!(s.client.get_escrow_balance(escrow_id).await.unwrap(), 600);
+    assert!(!s.client.get_payment(ids[1]).await.unwrap().settled);
+
+    let err = s
+        .server
+        .settle_payments(&[ids[1], ids[2]])
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            Error::Contract(ContractError::PaymentAlreadySettled, _)
+        ),
+        "{err}"
+    );
+    assert!(!s.client.get_payment(ids[1]).await.unwrap().settled);
+}
+
+/// Server client of a fresh environment with escrows of `count` clients,
+/// each funded with `balance`
+async fn batching_setup(count: u8, balance: i128) -> (EscrowClient, Vec<u64>) {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport);
+    let on = |seed| {
+        let key = LocalSigner::from_bytes(&[seed; 32]);
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, key)
+            .unwrap()
+            .with_options(ClientOptions {
+                poll_interval: Duration::from_millis(1),
+                ..ClientOptions::default()
+            })
+    };
+    let server = on(100);
+    let mut escrow_ids = Vec::new();
+    for seed in 1..=count {
+        let client = on(seed);
+        let opened = client
+            .open_escrow(&client.address(), &server.address(), balance, None)
+            .await
+            .unwrap();
+        escrow_ids.push(opened.value);
+    }
+    (server, escrow_ids)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_payment_batcher() {
+    const RECORDS: u32 = 2_000;
+    let (server, escrow_ids) = batching_setup(2, 10_000).await;
+    let options = BatchOptions {
+        max_batch: 50,
+        max_delay: Duration::from_millis(10),
+        max_queued: 64,
+        concurrency: 3,
+        settle: true,
+    };
+    let (batcher, worker) = PaymentBatcher::new(server.clone(), options);
+    let worker = tokio::spawn(worker.run());
+
+    // Recording waits for room rather than queueing without bound
+    let mut tickets = Vec::new();
+    for i in 0..RECORDS {
+        let escrow_id = escrow_ids[i as usize % 2];
+        tickets.push(
+            batcher
+                .record(escrow_id, 1, format!("request-{i}"))
+                .await
+                .unwrap(),
+        );
+        assert!(batcher.queued() <= 64);
+    }
+
+    // Payments of each escrow are numbered in the order they were recorded
+    for (i, ticket) in tickets.into_iter().enumerate() {
+        assert_eq!(ticket.reference(), format!("request-{i}"));
+        let payment = ticket.await.unwrap();
+        let escrow_id = escrow_ids[i % 2];
+        assert_eq!(payment.reference, format!("request-{i}"));
+        assert_eq!(payment.escrow_id, escrow_id);
+        assert_eq!(payment.payment_id, payment_id(escrow_id, i as u32 / 2));
+        assert!(payment.settled_in.is_some());
+    }
+    for escrow_id in escrow_ids {
+        assert_eq!(
+            server.get_escrow_balance(escrow_id).await.unwrap(),
+            10_000 - i128::from(RECORDS / 2)
+        );
+    }
+
+    let metrics = batcher.metrics();
+    assert_eq!(metrics.recorded, u64::from(RECORDS));
+    assert_eq!(metrics.created, u64::from(RECORDS));
+    assert_eq!(metrics.settled, u64::from(RECORDS));
+    assert_eq!(metrics.failed, 0);
+    assert!(metrics.batches >= u64::from(RECORDS / 50));
+    assert!((1..=3).contains(&metrics.peak_in_flight));
+    assert_eq!(metrics.in_flight, 0);
+
+    // The worker stops once the batcher is dropped
+    drop(batcher);
+    worker.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_payment_batcher_isolates_failures() {
+    let (server, escrow_ids) = batching_setup(1, 1_000).await;
+    let escrow_id = escrow_ids[0];
+    let options = BatchOptions {
+        max_batch: 8,
+        max_delay: Duration::from_millis(10),
+        max_queued: 4,
+        concurrency: 1,
+        settle: true,
+    };
+    let (batcher, worker) = PaymentBatcher::new(server.clone(), options.clone());
+
+    // Queued in full before the worker starts, so they form one batch
+    let tickets: Vec<_> = [300, 5_000, 400, 500]
+        .into_iter()
+        .map(|amount| batcher.try_record(escrow_id, amount, "").unwrap())
+        .collect();
+    assert_eq!(batcher.queued(), 4);
+    assert!(matches!(
+        batcher.try_record(escrow_id, 1, ""),
+        Err(Error::QueueFull(4))
+    ));
+    let worker = tokio::spawn(worker.run());
+    let mut outcomes = Vec::new();
+    for ticket in tickets {
+        outcomes.push(ticket.await);
+    }
+
+    // The payment over the balance is not created, the others are
+    let err = outcomes[1].as_ref().unwrap_err();
+    assert!(
+        matches!(err, Error::Contract(ContractError::InsufficientBalance, _)),
+        "{err}"
+    );
+    let created: Vec<_> = [0, 2, 3]
+        .map(|i| outcomes[i].as_ref().unwrap().clone())
+        .to_vec();
+    assert_eq!(
+        created.iter().map(|p| p.payment_id).collect::<Vec<_>>(),
+        (0..3)
+            .map(|index| payment_id(escrow_id, index))
+            .collect::<Vec<_>>()
+    );
+    assert_ne!(created[0].created_in, created[1].created_in);
+    assert_eq!(created[1].created_in, created[2].created_in);
+
+    // Settling all three would overdraw the escrow, so the last one waits
+    assert!(created[0].settled_in.is_some());
+    assert!(created[1].settled_in.is_some());
+    assert_eq!(created[2].settled_in, None);
+    assert!(
+        !server
+            .get_payment(created[2].payment_id)
+            .await
+            .unwrap()
+            .settled
+    );
+    assert_eq!(server.get_escrow_balance(escrow_id).await.unwrap(), 300);
+    let metrics = batcher.metrics();
+    assert_eq!(
+        (metrics.created, metrics.settled, metrics.failed),
+        (3, 2, 1)
+    );
+    drop(batcher);
+    worker.await.unwrap();
+
+    // Without a worker, tickets and recording fail
+    let (batcher, worker) = PaymentBatcher::new(server, options);
+    let ticket = batcher.try_record(escrow_id, 1, "").unwrap();
+    drop(worker);
+    assert!(matches!(ticket.await, Err(Error::BatcherStopped)));
+    assert!(matches!(
+        batcher.record(escrow_id, 1, "").await,
+        Err(Error::BatcherStopped)
+    ));
+}
+
+#[tokio::test]
+async fn test_settlement_terms() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000, None)
+        .await
+        .unwrap()
+        .value;
+    let first = s.server.create_payment(escrow_id, 100).await.unwrap().value;
+    let second = s.server.create_payment(escrow_id, 200).await.unwrap().value;
+
+    // The server settles on the terms its own RPC reports
+    let terms = s.server.settlement(second).await.unwrap();
+    assert_eq!(
+        terms,
+        Settlement {
+            payment_id: second,
+            escrow_id,
+            amount: 200,
+            client: s.client_addr.clone(),
+        }
+    );
+
+    // Terms signed for one payment do not settle another
+    for settlement in [
+        Settlement {
+            payment_id: first,
+            ..terms.clone()
+        },
+        Settlement {
+            amount: 100,
+            ..terms.clone()
+        },
+        Settlement {
+            client: s.server_addr.clone(),
+            ..terms.clone()
+        },
+    ] {
+        let err = s
+            .server
+            .estimate(&EscrowOp::SettlePayment { settlement })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.contract_error(),
+            Some(ContractError::SettlementMismatch),
+            "{err}"
+        );
+    }
+    assert!(s.server.settle_payment(second).await.unwrap().value);
+    assert_eq!(s.client.get_escrow_balance(escrow_id).await.unwrap(), 800);
+}
+
+#[tokio::test]
+async fn test_settlement_receipt() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let payment_id = s
+        .server
+        .create_payment(escrow_id, 300_000)
+        .await
+        .unwrap()
+        .value;
+    let err = s
+        .server
+        .settlement_receipt(payment_id, NATIVE_ASSET, 0)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::NoReceipt(_)), "{err}");
+    let settled = s.server.settle_payment(payment_id).await.unwrap();
+
+    // Only the server signs, and the payment must be created after the
+    // search starts
+    let err = s
+        .client
+        .settlement_receipt(payment_id, NATIVE_ASSET, 0)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::NoReceipt(_)), "{err}");
+    let err = s
+        .server
+        .settlement_receipt(payment_id, NATIVE_ASSET, settled.ledger + 1)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::NoReceipt(_)), "{err}");
+
+    let issued = s
+        .server
+        .settlement_receipt(payment_id, NATIVE_ASSET, 0)
+        .await
+        .unwrap();
+    assert_eq!(issued.body.payment_id, payment_id);
+    assert_eq!(issued.body.client, s.client_addr);
+    assert_eq!(issued.body.server, s.server_addr);
+    assert_eq!(issued.body.amount, "300000");
+    assert_eq!(issued.body.asset, NATIVE_ASSET);
+    assert_eq!(issued.body.ledger, settled.ledger);
+    assert_eq!(issued.body.tx_hash, settled.hash);
+    issued.verify().unwrap();
+
+    // Receipts travel as JSON, and any edit breaks them
+    let json = serde_json::to_string(&issued).unwrap();
+    let decoded: SettlementReceipt = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, issued);
+    let mut tampered = issued.clone();
+    tampered.body.amount = "3000000".into();
+    assert!(matches!(tampered.verify(), Err(Error::InvalidReceipt(_))));
+    tampered.receipt_hash = hex::encode(tampered.body.hash());
+    assert!(matches!(tampered.verify(), Err(Error::InvalidReceipt(_))));
+
+    // Other implementations check their receipts against the fixture
+    let fixture: SettlementReceipt =
+        serde_json::from_str(include_str!("../fixtures/receipt_v1.json")).unwrap();
+    assert_eq!(hex::encode(fixture.body.hash()), fixture.receipt_hash);
+    fixture.verify().unwrap();
+}
+
+/// Transport failing every `fail_every`-th simulation with a transport error
+struct FlakyTransport {
+    inner: EnvTransport,
+    fail_every: usize,
+    simulations: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl Transport for FlakyTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        if method == "simulateTransaction"
+            && self.simulations.fetch_add(1, Ordering::SeqCst) % self.fail_every == 0
+        {
+            return Err(Error::Transport("connection reset".into()));
+        }
+        self.inner.request(method, params).await
+    }
+}
+
+/// Client of an escrow seeded with 250 payments, interleaved with those of
+/// another escrow, every fourth one settled
+///
+/// # Returns
+/// * The client, its simulation count, and the IDs of both escrows
+fn payments_setup(fail_every: usize) -> (EscrowClient, Arc<AtomicUsize>, (u64, u64)) {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let escrow_contract = contract_id.clone();
+    let escrow_ids = transport.with_env(move |env| {
+        let contract = soroban_sdk::Address::from_str(env, &escrow_contract);
+        let escrow = X402EscrowContractClient::new(env, &contract);
+        let server = soroban_sdk::Address::generate(env);
+        let escrow_id = escrow.open_escrow(
+            &soroban_sdk::Address::generate(env),
+            &server,
+            &1_000_000,
+            &None,
+        );
+        let other = escrow.open_escrow(
+            &soroban_sdk::Address::generate(env),
+            &server,
+            &1_000_000,
+            &None,
+        );
+        for i in 0..250 {
+            let payment_id = escrow.create_payment(&escrow_id, &(i + 1));
+            if i % 4 == 0 {
+                escrow.settle_payment(&payment_id);
+            }
+            if i % 10 == 0 {
+                escrow.create_payment(&other, &1);
+            }
+        }
+        (escrow_id, other)
+    });
+
+    let simulations = Arc::new(AtomicUsize::new(1));
+    let transport = FlakyTransport {
+        inner: transport,
+        fail_every,
+        simulations: simulations.clone(),
+    };
+    let client = EscrowClient::new(
+        Rpc::new(transport),
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&[1; 32]),
+    )
+    .unwrap()
+    .with_options(ClientOptions {
+        poll_interval: Duration::from_millis(1),
+        ..ClientOptions::default()
+    });
+    (client, simulations, escrow_ids)
+}
+
+#[tokio::test]
+async fn test_payment_history() {
+    // Every third page fetch fails once and is retried
+    let (client, _, (escrow_id, other_id)) = payments_setup(3);
+
+    // Pages are stitched in creation order, without gaps or repeats
+    let payments = client
+        .payments(escrow_id)
+        .collect_all(usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(payments.len(), 250);
+    for (i, (_, payment)) in payments.iter().enumerate() {
+        assert_eq!(payment.escrow_id, escrow_id);
+        assert_eq!(payment.amount, i as i128 + 1);
+        assert_eq!(payment.settled, i % 4 == 0);
+    }
+    assert!(payments.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+    // Filtered and smaller pages give the same payments
+    let pending = client
+        .payments(escrow_id)
+        .status(PaymentStatus::Pending)
+        .page_size(7)
+        .collect_all(usize::MAX)
+        .await
+        .unwrap();
+    let expected: Vec<_> = payments
+        .iter()
+        .filter(|(_, payment)| !payment.settled)
+        .cloned()
+        .collect();
+    assert_eq!(pending.len(), 187);
+    assert_eq!(pending, expected);
+
+    // Page sizes above the contract cap are capped rather than rejected
+    let settled = client
+        .payments(escrow_id)
+        .status(PaymentStatus::Settled)
+        .page_size(1_000)
+        .stream();
+    let settled: Vec<_> = settled.map(Result::unwrap).collect().await;
+    assert_eq!(settled.len(), 63);
+    assert!(settled.iter().all(|(_, payment)| payment.settled));
+
+    let first = client.payments(escrow_id).collect_all(60).await.unwrap();
+    assert_eq!(first, payments[..60]);
+    let other = client
+        .payments(other_id)
+        .collect_all(usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(other.len(), 25);
+    assert!(client
+        .payments(u64::MAX)
+        .collect_all(10)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_export_escrows() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let escrow_contract = contract_id.clone();
+    let server = LocalSigner::from_bytes(&[2; 32]).address();
+    let other_server = LocalSigner::from_bytes(&[3; 32]).address();
+    let (server_addr, other_addr) = (server.clone(), other_server.clone());
+    let closed = transport.with_env(move |env| {
+        let contract = soroban_sdk::Address::from_str(env, &escrow_contract);
+        let escrow = X402EscrowContractClient::new(env, &contract);
+        let server = soroban_sdk::Address::from_str(env, &server_addr);
+        let other = soroban_sdk::Address::from_str(env, &other_addr);
+        // Every seventh escrow is with the other server, the first is closed
+        let mut ids = Vec::new();
+        for i in 0..120 {
+            let server = if i % 7 == 6 { &other } else { &server };
+            ids.push(escrow.open_escrow(
+                &soroban_sdk::Address::generate(env),
+                server,
+                &(i + 1),
+                &None,
+            ));
+        }
+        escrow.client_close_escrow(&ids[0]);
+        escrow.server_close_escrow(&ids[0]);
+        ids[0]
+    });
+
+    // Every third page fetch fails once and is retried
+    let client = EscrowClient::new(
+        Rpc::new(FlakyTransport {
+            inner: transport,
+            fail_every: 3,
+            simulations: Arc::new(AtomicUsize::new(1)),
+        }),
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&[1; 32]),
+    )
+    .unwrap()
+    .with_options(ClientOptions {
+        poll_interval: Duration::from_millis(1),
+        ..ClientOptions::default()
+    });
+
+    // Pages are stitched without repeats, closed escrows left out
+    let all = client.export_escrows(None).await.unwrap();
+    assert_eq!(all.len(), 119);
+    assert!(all.iter().all(|(id, _)| *id != closed));
+    let mut balances: Vec<_> = all.iter().map(|(_, escrow)| escrow.balance).collect();
+    balances.sort_unstable();
+    assert_eq!(balances, (2..=120).collect::<Vec<_>>());
+    let own = client.export_escrows(Some(&server)).await.unwrap();
+    assert_eq!(own.len(), 102);
+    assert!(own.iter().all(|(_, escrow)| escrow.server == server));
+    let other = client.export_escrows(Some(&other_server)).await.unwrap();
+    assert_eq!(other.len(), 17);
+    assert!(client.export_escrows(Some("not an address")).await.is_err());
+}
+
+#[tokio::test]
+async fn test_migrate_escrow() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let old_id = transport.contract_id().to_string();
+    let new_id = transport.with_env(|env| {
+        let contract = env.register(X402EscrowContract, ());
+        scval::format_address(&stellar_xdr::curr::ScAddress::from(&contract))
+    });
+    let rpc = Rpc::new(transport);
+    let on = |contract: &str, seed| {
+        let key = LocalSigner::from_bytes(&[seed; 32]);
+        EscrowClient::new(rpc.clone(), contract, NETWORK_PASSPHRASE, key).unwrap()
+    };
+    let (client, server, admin) = (on(&old_id, 1), on(&old_id, 2), on(&old_id, 3));
+    let new_admin = on(&new_id, 3);
+    admin.initialize().await.unwrap();
+    new_admin.initialize().await.unwrap();
+
+    let escrow_id = client
+        .open_escrow(&client.address(), &server.address(), 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    server.create_payment(escrow_id, 250_000).await.unwrap();
+    client
+        .set_max_settled_per_hour(escrow_id, Some(500_000))
+        .await
+        .unwrap();
+    server.post_note(escrow_id, b"moving").await.unwrap();
+    new_admin.allow_migration_source(&old_id).await.unwrap();
+
+    // The admin cannot move it before both parties consent
+    client
+        .consent_to_migration(escrow_id, &new_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        admin
+            .migrate_escrow(escrow_id, &new_admin)
+            .await
+            .unwrap_err()
+            .contract_error(),
+        Some(ContractError::MigrationNotApproved)
+    );
+    server
+        .consent_to_migration(escrow_id, &new_id)
+        .await
+        .unwrap();
+    let (export, imported) = admin.migrate_escrow(escrow_id, &new_admin).await.unwrap();
+    assert_eq!(export.summary.source, old_id);
+    assert_eq!(export.summary.target, new_id);
+    assert_eq!(export.summary.escrow.balance, 1_000_000);
+    assert_eq!(export.summary.pending.len(), 1);
+    assert_eq!(export.summary.pending[0].amount, 250_000);
+
+    // The escrow lives on in the new contract only, with its spend limit
+    // and notes
+    let moved = new_admin.get_escrow(imported.value).await.unwrap();
+    assert_eq!(moved.client, client.address());
+    assert_eq!(moved.balance, 1_000_000);
+    let limit = new_admin.get_spend_limit(imported.value).await.unwrap();
+    assert_eq!(limit, export.summary.spend_limit);
+    assert_eq!(limit.unwrap().max_settled_per_hour, 500_000);
+    let notes = new_admin.get_notes(imported.value).await.unwrap();
+    assert_eq!(notes, export.summary.notes);
+    assert_eq!(notes[0].body, b"moving");
+    assert!(matches!(
+        client.get_escrow(escrow_id).await,
+        Err(Error::Contract(ContractError::EscrowNotFound, _))
+    ));
+    assert_eq!(admin.get_migration(escrow_id).await.unwrap(), Some(export));
+}
+
+#[tokio::test]
+async fn test_sweep_dust() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport.clone());
+    let on = |seed| {
+        let key = LocalSigner::from_bytes(&[seed; 32]);
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, key).unwrap()
+    };
+    let (client, server, admin) = (on(1), on(2), on(3));
+    let refund_to = LocalSigner::from_bytes(&[4; 32]).address();
+    let idle = Duration::from_secs(x402_bindings::MIN_DUST_IDLE);
+
+    admin.initialize().await.unwrap();
+    assert_eq!(admin.get_dust_policy().await.unwrap(), None);
+    admin.set_dust_policy(100, idle).await.unwrap();
+    assert_eq!(
+        admin.get_dust_policy().await.unwrap().unwrap().max_idle,
+        x402_bindings::MIN_DUST_IDLE
+    );
+    client.set_refund_address(&refund_to).await.unwrap();
+    assert_eq!(
+        admin.get_refund_address(&client.address()).await.unwrap(),
+        refund_to
+    );
+
+    let escrow_id = client
+        .open_escrow(&client.address(), &server.address(), 40, None)
+        .await
+        .unwrap()
+        .value;
+    let opened_at = admin.get_last_activity(escrow_id).await.unwrap();
+    assert_eq!(
+        admin
+            .sweep_dust(&[escrow_id])
+            .await
+            .unwrap_err()
+            .contract_error(),
+        Some(ContractError::EscrowNotDust)
+    );
+    transport.with_env(move |env| {
+        env.ledger()
+            .set_timestamp(opened_at + x402_bindings::MIN_DUST_IDLE)
+    });
+    assert_eq!(admin.sweep_dust(&[escrow_id]).await.unwrap().value, 40);
+    assert!(matches!(
+        client.get_escrow(escrow_id).await,
+        Err(Error::Contract(ContractError::EscrowNotFound, _))
+    ));
+}
+
+#[tokio::test]
+async fn test_direct_payment() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport.clone());
+    let on = |seed| {
+        let key = LocalSigner::from_bytes(&[seed; 32]);
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, key).unwrap()
+    };
+    let (client, server) = (on(1), on(2));
+    let token = transport.register_token();
+    transport.mint(&token, &client.address(), 1_000_000);
+
+    let payment_id = client
+        .record_direct_payment(&server.address(), &token, 300_000, [7; 32])
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(transport.token_balance(&token, &client.address()), 700_000);
+    assert_eq!(transport.token_balance(&token, &server.address()), 300_000);
+    let payment = client.get_payment(payment_id).await.unwrap();
+    assert_eq!(payment.escrow_id, x402_bindings::DIRECT_PAYMENT_ESCROW);
+    assert!(payment.settled);
+    let err = client
+        .record_direct_payment(&server.address(), &token, 300_000, [7; 32])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.contract_error(),
+        Some(ContractError::InvalidDirectPayment)
+    );
+
+    // Pre-signed by the client, the token transfer included, and submitted
+    // by the server
+    let op = EscrowOp::RecordDirectPayment {
+        client: client.address(),
+        server: server.address(),
+        token: token.clone(),
+        amount: 200_000,
+        reference: [8; 32],
+    };
+    let entry = client.presign(&op, 100).await.unwrap();
+    assert_eq!(entry.op().unwrap(), op);
+    assert_eq!(entry.entry().root_invocation.sub_invocations.len(), 1);
+    let submitted = server.submit_authorized(&entry).await.unwrap();
+    assert_eq!(scval::to_u64(&submitted.value).unwrap(), payment_id + 1);
+    assert_eq!(transport.token_balance(&token, &server.address()), 500_000);
+
+    // Listed among the server's direct payments, not those of its escrows
+    let listed = server
+        .direct_payments(&server.address())
+        .unwrap()
+        .page_size(1)
+        .collect_all(10)
+        .await
+        .unwrap();
+    let ids: Vec<_> = listed.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, [payment_id, payment_id + 1]);
+    assert!(client.direct_payments("not an address").is_err());
+}
+
+#[tokio::test]
+async fn test_max_escrows_per_client() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport);
+    let on = |seed| {
+        let key = LocalSigner::from_bytes(&[seed; 32]);
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, key).unwrap()
+    };
+    let (client, admin) = (on(1), on(3));
+    let (first, second) = (on(4).address(), on(5).address());
+
+    admin.initialize().await.unwrap();
+    admin.set_max_escrows_per_client(Some(1)).await.unwrap();
+    assert_eq!(admin.get_max_escrows_per_client().await.unwrap(), Some(1));
+    client
+        .open_escrow(&client.address(), &first, 1_000, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        admin
+            .get_client_escrow_count(&client.address())
+            .await
+            .unwrap(),
+        1
+    );
+    let err = client
+        .open_escrow(&client.address(), &second, 1_000, None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::TooManyEscrows));
+
+    admin.set_max_escrows_per_client(None).await.unwrap();
+    client
+        .open_escrow(&client.address(), &second, 1_000, None)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_clock_skew() {
+    let s = setup();
+    assert_eq!(s.server.get_clock_skew().await.unwrap(), 0);
+    let err = s.server.set_clock_skew(30).await.unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::NotInitialized));
+    s.server.initialize().await.unwrap();
+    let err = s.client.initialize().await.unwrap_err();
+    assert_eq!(
+        err.contract_error(),
+        Some(ContractError::AlreadyInitialized)
+    );
+    s.server.set_clock_skew(30).await.unwrap();
+    assert_eq!(s.client.get_clock_skew().await.unwrap(), 30);
+    let err = s.client.set_clock_skew(5).await.unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::Unauthorized));
+    s.server.set_clock_skew(0).await.unwrap();
+    assert_eq!(s.client.get_clock_skew().await.unwrap(), 0);
+}
+
+/// RPC server predating `closeTime` in `getLatestLedger`
+struct LegacyLedgerRpc;
+
+#[async_trait]
+impl Transport for LegacyLedgerRpc {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        match method {
+            "getLatestLedger" => Ok(json!({ "id": "ab", "protocolVersion": 22, "sequence": 42 })),
+            "getLedgers" => {
+                assert_eq!(params["startLedger"], 42);
+                Ok(json!({
+                    "ledgers": [{ "hash": "ab", "sequence": 42, "ledgerCloseTime": "1700000000" }],
+                    "latestLedger": 42,
+                }))
+            }
+            other => panic!("unexpected RPC call {other}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_ledger_time() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    transport.with_env(|env| env.ledger().set_timestamp(1_234));
+    assert_eq!(Rpc::new(transport).get_ledger_time().await.unwrap(), 1_234);
+
+    let rpc = Rpc::new(LegacyLedgerRpc);
+    assert_eq!(rpc.get_ledger_time().await.unwrap(), 1_700_000_000);
+}
+
+#[tokio::test]
+async fn test_escrow_notes() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    let posted = s
+        .server
+        .post_note(escrow_id, b"please top up before Friday")
+        .await
+        .unwrap();
+    s.client.post_note(escrow_id, b"done").await.unwrap();
+    let notes = s.client.get_notes(escrow_id).await.unwrap();
+    assert_eq!(notes.len(), 2);
+    assert_eq!(notes[0].author, s.server_addr);
+    assert_eq!(notes[0].ledger, posted.ledger);
+    assert_eq!(notes[0].body, b"please top up before Friday");
+    assert_eq!(notes[1].author, s.client_addr);
+
+    let err = s.client.post_note(escrow_id, &[]).await.unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::InvalidNote));
+}
+
+#[tokio::test]
+async fn test_agent_allowance() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport.clone());
+    let on = |seed| {
+        let key = LocalSigner::from_bytes(&[seed; 32]);
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, key).unwrap()
+    };
+    let (client, server, agent) = (on(1), on(2), on(3));
+
+    assert_eq!(
+        client
+            .grant_agent(&agent.address(), 100, 200, u64::MAX)
+            .await
+            .unwrap_err()
+            .contract_error(),
+        Some(ContractError::InvalidAllowance)
+    );
+    client
+        .grant_agent(&agent.address(), 1_000, 400, u64::MAX)
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .get_allowance(&client.address(), &agent.address())
+            .await
+            .unwrap(),
+        Some(Allowance {
+            total_cap: 1_000,
+            remaining: 1_000,
+            per_payment_cap: 400,
+            expires_at: u64::MAX,
+        })
+    );
+
+    // The agent's authorization reaches the contract, which only accepts
+    // networks it knows
+    let escrow_id = client
+        .open_escrow(&client.address(), &server.address(), 5_000, None)
+        .await
+        .unwrap()
+        .value;
+    let payload = agent
+        .authorize_payment(escrow_id, 300, 1, u64::MAX, STELLAR_TESTNET)
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .create_authorized_payment(&payload, STELLAR_TESTNET)
+            .await
+            .unwrap_err()
+            .contract_error(),
+        Some(ContractError::NetworkMismatch)
+    );
+    let mut malformed = payload.clone();
+    malformed.signature = "00".into();
+    assert!(matches!(
+        server
+            .create_authorized_payment(&malformed, STELLAR_TESTNET)
+            .await,
+        Err(Error::InvalidAuthorization(_))
+    ));
+
+    client.revoke_agent(&agent.address()).await.unwrap();
+    assert_eq!(
+        client
+            .revoke_agent(&agent.address())
+            .await
+            .unwrap_err()
+            .contract_error(),
+        Some(ContractError::AllowanceNotFound)
+    );
+    assert_eq!(
+        server
+            .get_allowance(&client.address(), &agent.address())
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_server_monitor() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport.clone());
+    let on = |seed| {
+        let key = LocalSigner::from_bytes(&[seed; 32]);
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, key).unwrap()
+    };
+    let (client, server, healthy) = (on(1), on(2), on(3));
+
+    // Payments created an hour ago, two of them never settled
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    transport.with_env(move |env| env.ledger().set_timestamp(now - 3_600));
+    let escrow_id = client
+        .open_escrow(&client.address(), &server.address(), 1_500, None)
+        .await
+        .unwrap()
+        .value;
+    let mut payment_ids = vec![];
+    for amount in [300, 400, 500] {
+        payment_ids.push(
+            server
+                .create_payment(escrow_id, amount)
+                .await
+                .unwrap()
+                .value,
+        );
+    }
+    server.settle_payment(payment_ids[0]).await.unwrap();
+    let other_id = healthy
+        .open_escrow(&healthy.address(), &server.address(), 5_000, None)
+        .await
+        .unwrap()
+        .value;
+    let paid = server.create_payment(other_id, 100).await.unwrap().value;
+    server.settle_payment(paid).await.unwrap();
+
+    let fired = Arc::new(Mutex::new(vec![]));
+    let monitor = ServerMonitor::new(
+        server.clone(),
+        MonitorRules {
+            max_pending_age: Some(Duration::from_secs(600)),
+            min_settled_bps: Some(9_000),
+            settlement_grace: Duration::ZERO,
+            low_balance: Some(1_300),
+            dry_checks: 2,
+        },
+    )
+    .on_alert({
+        let fired = fired.clone();
+        move |alert| fired.lock().unwrap().push(alert.clone())
+    });
+
+    let alerts = monitor.check().await.unwrap();
+    assert_eq!(alerts.len(), 3, "{alerts:?}");
+    assert!(matches!(
+        alerts[0],
+        MonitorAlert::StalePayment { payment_id, amount: 400, age, .. }
+            if payment_id == payment_ids[1] && age >= Duration::from_secs(3_600)
+    ));
+    assert!(matches!(
+        alerts[1],
+        MonitorAlert::StalePayment { amount: 500, .. }
+    ));
+    assert_eq!(
+        alerts[2],
+        MonitorAlert::SettlementLag {
+            escrow_id,
+            created: 1_200,
+            settled: 300,
+        }
+    );
+
+    // The escrow is reported dry on its second low check, nothing else again
+    assert_eq!(
+        monitor.check().await.unwrap(),
+        vec![MonitorAlert::RunningDry {
+            escrow_id,
+            client: client.address(),
+            balance: 1_200,
+            checks: 2,
+        }]
+    );
+    assert_eq!(monitor.check().await.unwrap(), vec![]);
+
+    // Clearing a condition does not report anything
+    server.settle_payment(payment_ids[1]).await.unwrap();
+    assert_eq!(monitor.check().await.unwrap(), vec![]);
+    assert_eq!(fired.lock().unwrap().len(), 4);
+    let metrics = monitor.metrics();
+    assert_eq!(
+        (metrics.checks, metrics.escrows, metrics.pending_payments),
+        (4, 2, 1)
+    );
+    assert_eq!((metrics.active_alerts, metrics.alerts), (3, 4));
+}
+
+#[tokio::test]
+async fn test_payment_history_gives_up() {
+    // Every page fetch fails, so the retries run out
+    let (client, simulations, (escrow_id, _)) = payments_setup(1);
+    let calls = simulations.load(Ordering::SeqCst);
+
+    let mut stream = std::pin::pin!(client.payments(escrow_id).stream());
+    let err = stream.next().await.unwrap().unwrap_err();
+    assert!(matches!(err, Error::Transport(_)), "{err}");
+    assert!(stream.next().await.is_none());
+    assert_eq!(
+        simulations.load(Ordering::SeqCst) - calls,
+        1 + PAYMENT_PAGE_RETRIES as usize
+    );
+}
+
+/// Faults injected into `sendTransaction` by [`LossyTransport`]
+#[derive(Default)]
+struct Faults {
+    /// Sends applied, then answered with a transport error
+    lose_responses: AtomicUsize,
+    /// Sends answered with a transport error without being applied
+    drop_sends: AtomicUsize,
+    /// Envelope never applied, as if evicted from the mempool
+    drop_envelope: Mutex<Option<String>>,
+    sends: AtomicUsize,
+}
+
+/// Transport losing transactions or their responses as told by [`Faults`]
+struct LossyTransport {
+    inner: EnvTransport,
+    faults: Arc<Faults>,
+}
+
+#[async_trait]
+impl Transport for LossyTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        if method != "sendTransaction" {
+            return self.inner.request(method, params).await;
+        }
+        let faults = &self.faults;
+        faults.sends.fetch_add(1, Ordering::SeqCst);
+        let take = |count: &AtomicUsize| {
+            count
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+        };
+        let evicted =
+            faults.drop_envelope.lock().unwrap().as_deref() == params["transaction"].as_str();
+        if evicted || take(&faults.drop_sends) {
+            return Err(Error::Transport("request timed out".into()));
+        }
+        let response = self.inner.request(method, params).await?;
+        if take(&faults.lose_responses) {
+            return Err(Error::Transport("request timed out".into()));
+        }
+        Ok(response)
+    }
+}
+
+/// Setup submitting through a [`LossyTransport`], with an open escrow
+/// whose ID is returned
+async fn lossy_setup() -> (Setup, Arc<Faults>, u64) {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let faults = Arc::new(Faults::default());
+    let rpc = Rpc::new(LossyTransport {
+        inner: transport,
+        faults: faults.clone(),
+    });
+    let options = ClientOptions {
+        poll_interval: Duration::from_millis(1),
+        ..ClientOptions::default()
+    };
+    let client_key = LocalSigner::from_bytes(&[1; 32]);
+    let server_key = LocalSigner::from_bytes(&[2; 32]);
+    let s = Setup {
+        client_addr: client_key.address(),
+        server_addr: server_key.address(),
+        client: EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, client_key)
+            .unwrap()
+            .with_options(options.clone()),
+        server: EscrowClient::new(rpc, &contract_id, NETWORK_PASSPHRASE, server_key)
+            .unwrap()
+            .with_options(options),
+    };
+    let escrow_id = open_escrow(&s.client).await.unwrap().value;
+    faults.sends.store(0, Ordering::SeqCst);
+    (s, faults, escrow_id)
+}
+
+#[tokio::test]
+async fn test_submit_idempotent_response_lost() {
+    let (s, faults, escrow_id) = lossy_setup().await;
+    let log = MemorySubmissionLog::new();
+    let prepared = AtomicUsize::new(0);
+    let prepare = || {
+        prepared.fetch_add(1, Ordering::SeqCst);
+        s.server.prepare_create_payment(escrow_id, 100)
+    };
+
+    // The transaction applied but its response was lost, so it is looked up
+    // rather than sent again
+    faults.lose_responses.store(1, Ordering::SeqCst);
+    let disposition = s
+        .server
+        .submit_idempotent("pay-1", &log, prepare)
+        .await
+        .unwrap();
+    let Disposition::Included(submitted) = &disposition else {
+        panic!("expected Included, got {disposition:?}");
+    };
+    assert_eq!(submitted.value, scval::u64(payment_id(escrow_id, 0)));
+    assert_eq!(log.get("pay-1").unwrap().hash, submitted.hash);
+    assert_eq!(faults.sends.load(Ordering::SeqCst), 1);
+
+    // The same key returns the recorded outcome without signing or sending
+    let again = s
+        .server
+        .submit_idempotent("pay-1", &log, prepare)
+        .await
+        .unwrap();
+    assert_eq!(again, disposition);
+    assert_eq!(prepared.load(Ordering::SeqCst), 1);
+    assert_eq!(faults.sends.load(Ordering::SeqCst), 1);
+    let err = s
+        .client
+        .get_payment(payment_id(escrow_id, 1))
+        .await
+        .unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::PaymentNotFound));
+}
+
+#[tokio::test]
+async fn test_submit_idempotent_dropped() {
+    let (s, faults, escrow_id) = lossy_setup().await;
+    let log = MemorySubmissionLog::new();
+
+    // Lost before inclusion with its sequence number unused, the same
+    // transaction is sent again
+    faults.drop_sends.store(1, Ordering::SeqCst);
+    let disposition = s
+        .server
+        .submit_idempotent("pay-1", &log, || {
+            s.server.prepare_create_payment(escrow_id, 100)
+        })
+        .await
+        .unwrap();
+    assert!(
+        matches!(disposition, Disposition::Included(_)),
+        "{disposition:?}"
+    );
+    assert_eq!(faults.sends.load(Ordering::SeqCst), 2);
+
+    // Evicted while another transaction used its sequence number, a new one
+    // is signed
+    let prepared = AtomicUsize::new(0);
+    let evicted = Mutex::new(None);
+    let disposition = s
+        .server
+        .submit_idempotent("pay-2", &log, || async {
+            if prepared.fetch_add(1, Ordering::SeqCst) > 0 {
+                return s.server.prepare_create_payment(escrow_id, 300).await;
+            }
+            let tx = s.server.prepare_create_payment(escrow_id, 200).await?;
+            let competing = s.server.prepare_create_payment(escrow_id, 250).await?;
+            s.server.submit(&competing).await?;
+            *faults.drop_envelope.lock().unwrap() = Some(tx.envelope.clone());
+            *evicted.lock().unwrap() = Some(tx.hash.clone());
+            Ok(tx)
+        })
+        .await
+        .unwrap();
+    let Disposition::Included(submitted) = &disposition else {
+        panic!("expected Included, got {disposition:?}");
+    };
+    assert_ne!(Some(&submitted.hash), evicted.lock().unwrap().as_ref());
+    assert_eq!(log.get("pay-2").unwrap().hash, submitted.hash);
+    assert_eq!(prepared.load(Ordering::SeqCst), 2);
+    let payment = s
+        .client
+        .get_payment(payment_id(escrow_id, 2))
+        .await
+        .unwrap();
+    assert_eq!(payment.amount, 300);
+
+    // Never seen included nor provably dropped, the outcome stays unknown
+    faults.drop_sends.store(usize::MAX, Ordering::SeqCst);
+    let disposition = s
+        .server
+        .submit_idempotent("pay-3", &log, || {
+            s.server.prepare_create_payment(escrow_id, 400)
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        disposition,
+        Disposition::Unknown {
+            hash: log.get("pay-3").unwrap().hash
+        }
+    );
+    let err = s
+        .client
+        .get_payment(payment_id(escrow_id, 3))
+        .await
+        .unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::PaymentNotFound));
+}
+
+/// Transport leaving a transaction stuck pending, as during surge pricing
+#[derive(Clone)]
+struct StuckTransport {
+    inner: Arc<EnvTransport>,
+    /// Transaction answered as pending without being applied
+    stuck: Arc<Mutex<Option<PreparedTransaction>>>,
+    /// Whether the stuck transaction lands right before the next send
+    lands: Arc<Mutex<bool>>,
+}
+
+#[async_trait]
+impl Transport for StuckTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        if method == "sendTransaction" {
+            let stuck = self.stuck.lock().unwrap().clone();
+            if let Some(tx) = stuck {
+                if params["transaction"].as_str() == Some(tx.envelope.as_str()) {
+                    return Ok(json!({ "status": "PENDING", "hash": tx.hash, "latestLedger": 0 }));
+                }
+                if std::mem::take(&mut *self.lands.lock().unwrap()) {
+                    *self.stuck.lock().unwrap() = None;
+                    let original = json!({ "transaction": tx.envelope });
+                    self.inner.request(method, original).await?;
+                }
+            }
+        }
+        self.inner.request(method, params).await
+    }
+}
+
+/// Server client fee-bumping through a [`StuckTransport`], with an open
+/// escrow whose ID is returned
+async fn stuck_setup() -> (EscrowClient, StuckTransport, u64) {
+    let inner = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = inner.contract_id().to_string();
+    let transport = StuckTransport {
+        inner: Arc::new(inner),
+        stuck: Default::default(),
+        lands: Default::default(),
+    };
+    let rpc = Rpc::new(transport.clone());
+    let client_key = LocalSigner::from_bytes(&[1; 32]);
+    let server_key = LocalSigner::from_bytes(&[2; 32]);
+    let (client_addr, server_addr) = (client_key.address(), server_key.address());
+    let client =
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, client_key).unwrap();
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 1_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    let options = ClientOptions {
+        poll_interval: Duration::from_millis(1),
+        timeout: Duration::from_secs(5),
+        ..ClientOptions::default()
+    };
+    let policy = FeeBumpPolicy {
+        after: Duration::from_millis(20),
+        ..FeeBumpPolicy::default()
+    };
+    let server = EscrowClient::new(rpc, &contract_id, NETWORK_PASSPHRASE, server_key)
+        .unwrap()
+        .with_options(options)
+        .with_fee_bump(LocalSigner::from_bytes(&[3; 32]), policy);
+    (server, transport, escrow_id)
+}
+
+#[tokio::test]
+async fn test_fee_bump_stuck_transaction() {
+    let (server, transport, escrow_id) = stuck_setup().await;
+    let tx = server.prepare_create_payment(escrow_id, 100).await.unwrap();
+    *transport.stuck.lock().unwrap() = Some(tx.clone());
+
+    // Stuck past the policy's delay, the transaction is fee-bumped
+    let mut bumps = vec![];
+    let submitted = server
+        .submit_with(&tx, |bump| {
+            bumps.push(bump.clone());
+            true
+        })
+        .await
+        .unwrap();
+    assert_eq!(bumps.len(), 1);
+    assert_eq!(submitted.hash, bumps[0].hash);
+    assert_eq!(submitted.value, scval::u64(payment_id(escrow_id, 0)));
+    assert_eq!(server.lookup(&bumps[0]).await.unwrap(), Some(submitted));
+
+    // The original can no longer apply once its bump did
+    *transport.stuck.lock().unwrap() = None;
+    let err = server.submit(&tx).await.unwrap_err();
+    assert!(matches!(err, Error::Rejected { .. }), "{err}");
+    let payment = server.get_payment(payment_id(escrow_id, 0)).await.unwrap();
+    assert_eq!(payment.amount, 100);
+    let err = server
+        .get_payment(payment_id(escrow_id, 1))
+        .await
+        .unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::PaymentNotFound));
+}
+
+#[tokio::test]
+async fn test_fee_bump_original_lands() {
+    let (server, transport, escrow_id) = stuck_setup().await;
+    let log = MemorySubmissionLog::new();
+
+    // The original lands just before its bump is sent, so the bump is
+    // rejected and the original's outcome is returned
+    *transport.lands.lock().unwrap() = true;
+    let prepared = Mutex::new(None);
+    let disposition = server
+        .submit_idempotent("pay-1", &log, || async {
+            let tx = server.prepare_create_payment(escrow_id, 100).await?;
+            *transport.stuck.lock().unwrap() = Some(tx.clone());
+            *prepared.lock().unwrap() = Some(tx.clone());
+            Ok(tx)
+        })
+        .await
+        .unwrap();
+    let original = prepared.lock().unwrap().clone().unwrap();
+    let Disposition::Included(submitted) = &disposition else {
+        panic!("expected Included, got {disposition:?}");
+    };
+    assert_eq!(submitted.hash, original.hash);
+
+    // The bump was logged in its place, and is found through the original
+    let bump = log.get("pay-1").unwrap();
+    assert_ne!(bump.hash, original.hash);
+    assert_eq!(server.transaction(&bump.hash).await.unwrap(), None);
+    assert_eq!(server.lookup(&bump).await.unwrap(), Some(submitted.clone()));
+    let again = server
+        .submit_idempotent("pay-1", &log, || {
+            server.prepare_create_payment(escrow_id, 200)
+        })
+        .await
+        .unwrap();
+    assert_eq!(again, disposition);
+    let payment = server.get_payment(payment_id(escrow_id, 0)).await.unwrap();
+    assert_eq!(payment.amount, 100);
+    let err = server
+        .get_payment(payment_id(escrow_id, 1))
+        .await
+        .unwrap_err();
+    assert_eq!(err.contract_error(), Some(ContractError::PaymentNotFound));
+}
+
+#[test]
+fn test_fee_bump_policy() {
+    let policy = FeeBumpPolicy {
+        max_fee: 30_000,
+        ..FeeBumpPolicy::default()
+    };
+    // Inclusion fees of 100 per operation, the bump counting as one more
+    assert_eq!(policy.next_fee(200, 100, 1, false), Some(2_100));
+    assert_eq!(policy.next_fee(2_100, 100, 1, true), Some(20_100));
+    assert_eq!(policy.next_fee(20_100, 100, 1, true), Some(30_000));
+    assert_eq!(policy.next_fee(30_000, 100, 1, true), None);
+}
+
+/// Transport closing one ledger on every `getLatestLedger`, on top of those
+/// the test env closed
+struct AdvancingLedger {
+    inner: EnvTransport,
+    closed: Arc<AtomicU32>,
+}
+
+#[async_trait]
+impl Transport for AdvancingLedger {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let mut response = self.inner.request(method, params).await?;
+        if method == "getLatestLedger" {
+            let closed = self.closed.fetch_add(1, Ordering::SeqCst) + 1;
+            let sequence = response["sequence"].as_u64().unwrap() as u32;
+            response["sequence"] = json!(sequence + closed);
+        }
+        Ok(response)
+    }
+}
+
+#[tokio::test]
+async fn test_finality_policy() {
+    let policy = FinalityPolicy::new(5).final_up_to(1_000);
+    assert_eq!(policy.depth_for(1_000), 0);
+    assert_eq!(policy.depth_for(1_001), 5);
+    assert_eq!(policy.finality(2_000, 10, 14), Finality::Included);
+    assert_eq!(policy.finality(2_000, 10, 15), Finality::Final);
+    assert_eq!(policy.finality(500, 10, 10), Finality::Final);
+    assert_eq!(
+        FinalityPolicy::new(5).finality(1, u32::MAX, u32::MAX),
+        Finality::Final
+    );
+
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let closed = Arc::new(AtomicU32::new(0));
+    let rpc = Rpc::new(AdvancingLedger {
+        inner: transport,
+        closed: closed.clone(),
+    });
+    let options = ClientOptions {
+        poll_interval: Duration::from_millis(1),
+        ..ClientOptions::default()
+    };
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+        .with_options(options.clone())
+    };
+    let (client, server) = (signer(&[1; 32]), signer(&[2; 32]));
+    let escrow_id = client
+        .open_escrow(&client.address(), &server.address(), 10_000, None)
+        .await
+        .unwrap()
+        .value;
+    let payment_id = server.create_payment(escrow_id, 2_000).await.unwrap().value;
+    let settled = server.settle_payment(payment_id).await.unwrap();
+
+    // One ledger later, only small settlements are final
+    closed.store(0, Ordering::SeqCst);
+    let included = settled.ledger;
+    let finality = server.finality(&policy, 2_000, included).await.unwrap();
+    assert_eq!(finality, Finality::Included);
+    let finality = server.finality(&policy, 500, included).await.unwrap();
+    assert_eq!(finality, Finality::Final);
+
+    // Polled until five ledgers closed after the settlement's
+    let latest = server
+        .wait_for_finality(&policy, 2_000, included)
+        .await
+        .unwrap();
+    assert_eq!(latest, included + 5);
+    assert_eq!(closed.load(Ordering::SeqCst), 5);
+}
+
+#[tokio::test]
+async fn test_prepared_transactions_apply_once() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    let tx = s
+        .server
+        .prepare_create_payment(escrow_id, 400)
+        .await
+        .unwrap();
+    assert_eq!(s.server.transaction(&tx.hash).await.unwrap(), None);
+    let created = s.server.submit(&tx).await.unwrap();
+    assert_eq!(created.hash, tx.hash);
+    assert_eq!(
+        scval::to_u64(&created.value).unwrap(),
+        payment_id(escrow_id, 0)
+    );
+    assert_eq!(s.server.transaction(&tx.hash).await.unwrap(), Some(created));
+
+    // Its sequence number is spent, so a resubmission is rejected
+    let err = s.server.submit(&tx).await.unwrap_err();
+    assert!(matches!(err, Error::Rejected { .. }), "{err}");
+    assert!(matches!(
+        s.server.get_payment(payment_id(escrow_id, 1)).await,
+        Err(Error::Contract(ContractError::PaymentNotFound, _))
+    ));
+}
+
+#[test]
+fn test_contract_error_codes() {
+    for code in 1..=36 {
+        assert_eq!(ContractError::from_code(code).code(), code);
+    }
+    assert_eq!(ContractError::from_code(99), ContractError::Unknown(99));
+    assert!(matches!(
+        Error::from_simulation(
+            "HostError: Error(Contract, #3)\n\nEvent log".into(),
+            "create_payment"
+        ),
+        Error::Contract(ContractError::InsufficientBalance, _)
+    ));
+    // Codes this SDK does not know keep their raw value
+    let err = Error::from_simulation("HostError: Error(Contract, #99)".into(), "deposit");
+    assert_eq!(err.contract_error(), Some(ContractError::Unknown(99)));
+    assert_eq!(
+        err.to_string(),
+        "contract error: unknown contract error #99 (deposit)"
+    );
+    assert!(matches!(
+        Error::from_simulation("HostError: Error(Budget, ExceededLimit)".into(), "deposit"),
+        Error::Simulation(_)
+    ));
+}
+
+/// ed25519 key of a `G...` address
+fn public_key(address: &str) -> [u8; 32] {
+    stellar_strkey::ed25519::PublicKey::from_string(address)
+        .unwrap()
+        .0
+}
+
+#[tokio::test]
+async fn test_contract_verifies_sdk_signatures() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let client_key = LocalSigner::from_bytes(&[1; 32]);
+    let server_key = LocalSigner::from_bytes(&[2; 32]);
+    let client_addr = client_key.address();
+    let server_addr = server_key.address();
+    let escrow_id = transport.with_env({
+        let (contract_id, client_addr, server_addr) = (
+            contract_id.clone(),
+            client_addr.clone(),
+            server_addr.clone(),
+        );
+        move |env| {
+            let address = |strkey: &str| soroban_sdk::Address::from_str(env, strkey);
+            X402EscrowContractClient::new(env, &address(&contract_id)).open_escrow(
+                &address(&client_addr),
+                &address(&server_addr),
+                &1_000_000,
+                &None,
+            )
+        }
+    });
+
+    // Signing needs no RPC
+    let rpc = Rpc::new(HttpTransport::new("http://127.0.0.1:9"));
+    let client =
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, client_key).unwrap();
+    let server = EscrowClient::new(rpc, &contract_id, NETWORK_PASSPHRASE, server_key).unwrap();
+    let voucher = client
+        .sign_voucher(escrow_id, 250_000, 3, 2_000_000_000)
+        .await
+        .unwrap();
+    let by_server = server
+        .sign_voucher(escrow_id, 250_000, 3, 2_000_000_000)
+        .await
+        .unwrap();
+    let state = server
+        .sign_channel_state(escrow_id, 4, 750_000, 250_000)
+        .await
+        .unwrap();
+    let (client_key, server_key) = (public_key(&client_addr), public_key(&server_addr));
+    transport.with_env(move |env| {
+        let escrow =
+            X402EscrowContractClient::new(env, &soroban_sdk::Address::from_str(env, &contract_id));
+        let bytes = |bytes: [u8; 64]| soroban_sdk::BytesN::from_array(env, &bytes);
+        let key = |key: [u8; 32]| soroban_sdk::BytesN::from_array(env, &key);
+        let mut signed = x402_escrow::Voucher {
+            escrow_id,
+            amount: 250_000,
+            sequence: 3,
+            expires_at: 2_000_000_000,
+        };
+        escrow.verify_voucher(&signed, &key(client_key), &bytes(voucher));
+        // Only the client signs vouchers
+        assert_eq!(
+            escrow.try_verify_voucher(&signed, &key(server_key), &bytes(by_server)),
+            Err(Ok(x402_escrow::Error::InvalidSigner))
+        );
+        // Any other field fails the signature
+        signed.amount += 1;
+        assert!(escrow
+            .try_verify_voucher(&signed, &key(client_key), &bytes(voucher))
+            .is_err());
+
+        let mut signed = x402_escrow::ChannelState {
+            escrow_id,
+            sequence: 4,
+            balance: 750_000,
+            settled: 250_000,
+        };
+        escrow.verify_channel_state(&signed, &key(server_key), &bytes(state));
+        signed.sequence = 5;
+        assert!(escrow
+            .try_verify_channel_state(&signed, &key(server_key), &bytes(state))
+            .is_err());
+    });
+}
+
+#[tokio::test]
+async fn test_contract_verifies_sdk_authorizations() {
+    let s = setup();
+
+    // The contract under test runs on the testnet
+    let testnet = network_passphrase(STELLAR_TESTNET).unwrap();
+    let transport = EnvTransport::new(|env| {
+        env.ledger()
+            .with_mut(|ledger| ledger.network_id = Sha256::digest(testnet.as_bytes()).into());
+        env.register(X402EscrowContract, ())
+    });
+    let contract_id = transport.contract_id().to_string();
+    let (client_addr, server_addr) = (s.client_addr.clone(), s.server_addr.clone());
+    let escrow_id = transport.with_env({
+        let contract_id = contract_id.clone();
+        move |env| {
+            let address = |strkey: &str| soroban_sdk::Address::from_str(env, strkey);
+            X402EscrowContractClient::new(env, &address(&contract_id)).open_escrow(
+                &address(&client_addr),
+                &address(&server_addr),
+                &1_000_000,
+                &None,
+            )
+        }
+    });
+
+    let client = EscrowClient::new(
+        Rpc::new(transport.clone()),
+        &contract_id,
+        testnet,
+        LocalSigner::from_bytes(&[1; 32]),
+    )
+    .unwrap();
+    let signature =
+        |signature: &str| -> [u8; 64] { hex::decode(signature).unwrap().try_into().unwrap() };
+    let payload = client
+        .authorize_payment(escrow_id, 10_000, 7, 2_000_000_000, STELLAR_TESTNET)
+        .await
+        .unwrap();
+    let signed = signature(&payload.signature);
+    // The same authorization signed for the contract of another network
+    let payload = s
+        .client
+        .authorize_payment(escrow_id, 10_000, 7, 2_000_000_000, STELLAR_TESTNET)
+        .await
+        .unwrap();
+    let elsewhere = signature(&payload.signature);
+
+    let client_addr = s.client_addr;
+    transport.with_env(move |env| {
+        let escrow =
+            X402EscrowContractClient::new(env, &soroban_sdk::Address::from_str(env, &contract_id));
+        let key = soroban_sdk::BytesN::from_array(env, &public_key(&client_addr));
+        let signature = soroban_sdk::BytesN::from_array(env, &signed);
+        let mut authorization = x402_escrow::Authorization {
+            escrow_id,
+            network: soroban_sdk::String::from_str(env, STELLAR_TESTNET),
+            amount: 10_000,
+            nonce: 7,
+            expires_at: 2_000_000_000,
+        };
+        escrow.verify_authorization(&authorization, &key, &signature);
+        assert!(escrow
+            .try_verify_authorization(
+                &authorization,
+                &key,
+                &soroban_sdk::BytesN::from_array(env, &elsewhere)
+            )
+            .is_err());
+
+        authorization.network = soroban_sdk::String::from_str(env, STELLAR_MAINNET);
+        assert_eq!(
+            escrow.try_verify_authorization(&authorization, &key, &signature),
+            Err(Ok(x402_escrow::Error::NetworkMismatch))
+        );
+    });
+}
+
+#[test]
+fn test_signer_strkeys() {
+    let signer = LocalSigner::from_bytes(&[7; 32]);
+    assert!(signer.address().starts_with('G'));
+    assert!(LocalSigner::from_secret(&signer.address()).is_err());
+}
+
+/// Client on a fresh environment signing with `signer`
+fn signed_by(signer: impl Signer + 'static) -> EscrowClient {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    EscrowClient::new(
+        Rpc::new(transport),
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        signer,
+    )
+    .unwrap()
+}
+
+async fn open_escrow(client: &EscrowClient) -> Result<Submitted<u64>, Error> {
+    let server = LocalSigner::from_bytes(&[2; 32]).address();
+    client
+        .open_escrow(&client.address(), &server, 1_000, None)
+        .await
+}
+
+/// Signer that takes longer than any reasonable timeout
+struct SlowSigner(LocalSigner);
+
+#[async_trait]
+impl Signer for SlowSigner {
+    fn public_key(&self) -> [u8; 32] {
+        self.0.public_key()
+    }
+
+    async fn sign(&self, hash: &[u8; 32]) -> Result<[u8; 64], Error> {
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        self.0.sign(hash).await
+    }
+}
+
+/// Signer claiming one account but signing with another key
+struct WrongKeySigner {
+    claimed: LocalSigner,
+    actual: LocalSigner,
+}
+
+#[async_trait]
+impl Signer for WrongKeySigner {
+    fn public_key(&self) -> [u8; 32] {
+        self.claimed.public_key()
+    }
+
+    async fn sign(&self, hash: &[u8; 32]) -> Result<[u8; 64], Error> {
+        self.actual.sign(hash).await
+    }
+}
+
+#[tokio::test]
+async fn test_signer_timeout() {
+    let client =
+        signed_by(SlowSigner(LocalSigner::from_bytes(&[1; 32]))).with_options(ClientOptions {
+            sign_timeout: Duration::from_millis(50),
+            ..ClientOptions::default()
+        });
+
+    let started = Instant::now();
+    let err = open_escrow(&client).await.unwrap_err();
+    assert!(matches!(err, Error::Signer(_)), "{err}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_wrong_key_signer_is_rejected() {
+    let client = signed_by(WrongKeySigner {
+        claimed: LocalSigner::from_bytes(&[1; 32]),
+        actual: LocalSigner::from_bytes(&[9; 32]),
+    });
+    let err = open_escrow(&client).await.unwrap_err();
+    assert!(matches!(err, Error::Rejected { .. }), "{err}");
+    assert_eq!(
+        client
+            .find_escrow(
+                &client.address(),
+                &LocalSigner::from_bytes(&[2; 32]).address()
+            )
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_check_signer() {
+    let client = signed_by(LocalSigner::from_bytes(&[1; 32]));
+    client.check_signer().await.unwrap();
+    assert_eq!(client.version().await.unwrap(), 1);
+
+    let wrong_key = signed_by(WrongKeySigner {
+        claimed: LocalSigner::from_bytes(&[1; 32]),
+        actual: LocalSigner::from_bytes(&[9; 32]),
+    });
+    let err = wrong_key.check_signer().await.unwrap_err();
+    assert!(matches!(err, Error::Signer(_)), "{err}");
+
+    let slow =
+        signed_by(SlowSigner(LocalSigner::from_bytes(&[1; 32]))).with_options(ClientOptions {
+            sign_timeout: Duration::from_millis(50),
+            ..ClientOptions::default()
+        });
+    let err = slow.check_signer().await.unwrap_err();
+    assert!(matches!(err, Error::Signer(_)), "{err}");
+}
+
+#[tokio::test]
+async fn test_account_signer() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport.clone());
+    let account = LocalSigner::from_bytes(&[1; 32]);
+    let address = account.address();
+    let client = |signer: LocalSigner| {
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, signer).unwrap()
+    };
+    let rotated = || {
+        LocalSigner::from_bytes(&[9; 32])
+            .for_account(&address)
+            .unwrap()
+    };
+    assert!(LocalSigner::from_bytes(&[9; 32])
+        .for_account("not-an-address")
+        .is_err());
+    assert_eq!(rotated().address(), address);
+
+    // Signs for the account once added as one of its signers
+    let err = open_escrow(&client(rotated())).await.unwrap_err();
+    assert!(matches!(err, Error::Rejected { .. }), "{err}");
+    transport.add_signer(&address, &LocalSigner::from_bytes(&[9; 32]).address());
+    let escrow_id = open_escrow(&client(rotated())).await.unwrap().value;
+    let escrow = client(rotated()).get_escrow(escrow_id).await.unwrap();
+    assert_eq!(escrow.client, address);
+
+    // The account's own key stops signing once removed
+    transport.remove_signer(&address, &address);
+    let err = open_escrow(&client(account)).await.unwrap_err();
+    assert!(matches!(err, Error::Rejected { .. }), "{err}");
+}
+
+#[tokio::test]
+async fn test_presigned_deposit() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let network_id = s.client.network_id();
+    let deposit = |amount| EscrowOp::Deposit { escrow_id, amount };
+
+    // Expires before the agent gets to it
+    let short = s.client.presign(&deposit(1), 0).await.unwrap();
+    // Up to three deposits of at most 1 XLM, valid for the next 1000 ledgers
+    let latest = s.client.rpc().get_latest_ledger().await.unwrap().sequence;
+    let mut entries = vec![];
+    for amount in [10_000_000, 5_000_000, 2_500_000] {
+        entries.push(s.client.presign(&deposit(amount), 1000).await.unwrap());
+    }
+    assert_eq!(entries[0].address().unwrap(), s.client_addr);
+    assert_eq!(entries[0].op().unwrap(), deposit(10_000_000));
+    assert_eq!(entries[0].expiration_ledger().unwrap(), latest + 1000);
+    assert_ne!(entries[0].nonce().unwrap(), entries[1].nonce().unwrap());
+
+    // Handed over serialized, and checked by whoever receives it
+    let encoded = entries[0].to_xdr_base64().unwrap();
+    let decoded = AuthorizationEntry::from_xdr_base64(&encoded).unwrap();
+    assert_eq!(decoded, entries[0]);
+    let client_key = LocalSigner::from_bytes(&[1; 32]).public_key();
+    assert_eq!(decoded.verify(&network_id).unwrap(), vec![client_key]);
+    assert!(matches!(
+        AuthorizationEntry::from_xdr_base64("not xdr"),
+        Err(Error::InvalidAuthorization(_))
+    ));
+    let unsigned = AuthorizationEntry::new(
+        &s.client.contract_id(),
+        &deposit(1),
+        &s.client_addr,
+        random_nonce(),
+        latest + 1000,
+    )
+    .unwrap();
+    assert!(matches!(
+        unsigned.verify(&network_id),
+        Err(Error::InvalidAuthorization(_))
+    ));
+
+    // Submitted by an agent holding none of the client's keys
+    let agent = EscrowClient::new(
+        s.client.rpc().clone(),
+        &s.client.contract_id(),
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&[3; 32]),
+    )
+    .unwrap();
+    for entry in &entries {
+        agent.submit_authorized(entry).await.unwrap();
+    }
+    assert_eq!(
+        s.client.get_escrow_balance(escrow_id).await.unwrap(),
+        1_000_000 + 17_500_000
+    );
+
+    // Each entry applies once, until it expires
+    let err = agent.submit_authorized(&entries[0]).await.unwrap_err();
+    assert!(matches!(err, Error::TransactionFailed { .. }), "{err}");
+    let err = agent.submit_authorized(&short).await.unwrap_err();
+    assert!(matches!(err, Error::TransactionFailed { .. }), "{err}");
+
+    // Signed by a key that is not the client's
+    let agent_key = LocalSigner::from_bytes(&[3; 32]);
+    let hash = unsigned.signing_hash(&network_id).unwrap();
+    let forged = unsigned
+        .with_signature(agent_key.public_key(), agent_key.sign(&hash).await.unwrap())
+        .unwrap();
+    assert_eq!(
+        forged.verify(&network_id).unwrap(),
+        vec![agent_key.public_key()]
+    );
+    let err = agent.submit_authorized(&forged).await.unwrap_err();
+    assert!(matches!(err, Error::TransactionFailed { .. }), "{err}");
+
+    // For another contract
+    let elsewhere = AuthorizationEntry::new(
+        &stellar_strkey::Contract([9; 32]).to_string(),
+        &deposit(1),
+        &s.client_addr,
+        random_nonce(),
+        latest + 1000,
+    )
+    .unwrap();
+    let err = agent.submit_authorized(&elsewhere).await.unwrap_err();
+    assert!(matches!(err, Error::InvalidAuthorization(_)), "{err}");
+    assert_eq!(
+        s.client.get_escrow_balance(escrow_id).await.unwrap(),
+        1_000_000 + 17_500_000
+    );
+}
+
+#[tokio::test]
+async fn test_command_signer() {
+    let address = LocalSigner::from_bytes(&[1; 32]).address();
+    assert!(matches!(
+        CommandSigner::new("not-an-address", "true", Vec::<String>::new()),
+        Err(Error::InvalidAddress(_))
+    ));
+
+    let failing = signed_by(CommandSigner::new(&address, "false", Vec::<String>::new()).unwrap());
+    let err = open_escrow(&failing).await.unwrap_err();
+    assert!(matches!(err, Error::Signer(_)), "{err}");
+
+    let garbled = signed_by(CommandSigner::new(&address, "echo", ["zz"]).unwrap());
+    let err = open_escrow(&garbled).await.unwrap_err();
+    assert!(matches!(err, Error::Signer(_)), "{err}");
+
+    // A well-formed signature by the wrong key reaches the network
+    let zeros =
+        signed_by(CommandSigner::new(&address, "sh", ["-c", "printf %0128d 0", "sh"]).unwrap());
+    let err = open_escrow(&zeros).await.unwrap_err();
+    assert!(matches!(err, Error::Rejected { .. }), "{err}");
+}
+
+async fn sign(State(key): State<Arc<LocalSigner>>, Json(body): Json<Value>) -> Json<Value> {
+    assert_eq!(body["public_key"], key.address());
+    let hash: [u8; 32] = hex::decode(body["hash"].as_str().unwrap())
+        .unwrap()
+        .try_into()
+        .unwrap();
+    Json(json!({ "signature": hex::encode(key.sign(&hash).await.unwrap()) }))
+}
+
+#[tokio::test]
+async fn test_http_signer() {
+    let key = Arc::new(LocalSigner::from_bytes(&[1; 32]));
+    let address = key.address();
+    let app = Router::new().route("/sign", post(sign)).with_state(key);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/sign", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let client = signed_by(HttpSigner::new(&address, url).unwrap());
+    let escrow_id = open_escrow(&client).await.unwrap().value;
+    assert_eq!(client.get_escrow(escrow_id).await.unwrap().client, address);
+
+    let unreachable = signed_by(HttpSigner::new(&address, "http://127.0.0.1:9/sign").unwrap());
+    let err = open_escrow(&unreachable).await.unwrap_err();
+    assert!(matches!(err, Error::Signer(_)), "{err}");
+}
+
+const NETWORK: &str = "stellar-local";
+const PRICE: i128 = 100_000;
+
+/// Resource server answering 402 until it gets a correctly signed payment
+#[derive(Clone)]
+struct MockServer {
+    pay_to: String,
+    client_key: [u8; 32],
+    /// Escrow contract the client's authorizations are bound to
+    contract_id: String,
+    requests: Arc<Mutex<Vec<Option<u64>>>>,
+    /// Only x402 version the server speaks, advertised alone
+    x402_version: u32,
+    /// Rejection of the next correctly signed payment, if set
+    rejection: Arc<Mutex<Option<PaymentErrorBody>>>,
+}
+
+fn weather_requirements(pay_to: &str) -> PaymentRequirements {
+    PaymentRequirements {
+        x402_version: None,
+        scheme: ESCROW_SCHEME.into(),
+        network: NETWORK.into(),
+        max_amount_required: PRICE.to_string(),
+        resource: "/weather".into(),
+        description: "Weather report".into(),
+        mime_type: "text/plain".into(),
+        output_schema: None,
+        pay_to: pay_to.into(),
+        asset: None,
+        alternatives: vec![],
+        max_timeout_seconds: 60,
+        extra: None,
+    }
+}
+
+async fn weather(State(server): State<MockServer>, headers: HeaderMap) -> Response {
+    let payload = headers
+        .get(PAYMENT_HEADER)
+        .and_then(|value| decode_payment_header(value.to_str().ok()?).ok());
+    let Some((version, SchemePayload::Escrow(payload))) =
+        payload.map(|p| (p.x402_version, p.payload))
+    else {
+        server.requests.lock().unwrap().push(None);
+        let challenge = PaymentRequiredResponse {
+            x402_version: server.x402_version,
+            x402_versions: Vec::new(),
+            accepts: vec![weather_requirements(&server.pay_to)],
+            error: None,
+            payment_error: None,
+        };
+        return (StatusCode::PAYMENT_REQUIRED, Json(challenge)).into_response();
+    };
+
+    if version != server.x402_version {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    server.requests.lock().unwrap().push(Some(payload.nonce));
+    let signature: [u8; 64] = hex::decode(&payload.signature).unwrap().try_into().unwrap();
+    let domain = SigningDomain {
+        network_id: Sha256::digest(NETWORK_PASSPHRASE.as_bytes()).into(),
+        contract_id: &server.contract_id,
+    };
+    VerifyingKey::from_bytes(&server.client_key)
+        .unwrap()
+        .verify_strict(
+            &payload.signing_hash(NETWORK, domain),
+            &Signature::from_bytes(&signature),
+        )
+        .unwrap();
+    assert_eq!(payload.amount, PRICE.to_string());
+    if let Some(rejection) = server.rejection.lock().unwrap().take() {
+        let challenge = PaymentRequiredResponse {
+            x402_version: server.x402_version,
+            x402_versions: Vec::new(),
+            accepts: vec![weather_requirements(&server.pay_to)],
+            error: Some(rejection.message.clone()),
+            payment_error: Some(rejection),
+        };
+        return (StatusCode::PAYMENT_REQUIRED, Json(challenge)).into_response();
+    }
+
+    let settlement = encode_payment_response_header(&PaymentResponseHeader {
+        settlement: SettleResponse {
+            success: true,
+            error: None,
+            error_code: None,
+            tx_hash: Some("ab".repeat(32)),
+            network_id: Some(NETWORK.into()),
+            payment_id: Some(9),
+            ledger: None,
+            finality: None,
+            simulation: None,
+        },
+        amount: None,
+    });
+    ([(PAYMENT_RESPONSE_HEADER, settlement)], "sunny").into_response()
+}
+
+async fn serve(server: MockServer) -> String {
+    let app = Router::new()
+        .route("/weather", get(weather))
+        .route("/free", get(|| async { "free" }))
+        .with_state(server);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    url
+}
+
+const USDC: &str = "CUSDC";
+
+/// Resource server quoting `PRICE` in USDC or twice as much in native,
+/// recording the asset and amount of every payment
+#[derive(Clone, Default)]
+struct QuoteServer {
+    pay_to: String,
+    paid: Arc<Mutex<Vec<(Option<String>, String)>>>,
+}
+
+async fn quote(State(server): State<QuoteServer>, headers: HeaderMap) -> Response {
+    let payload = headers
+        .get(PAYMENT_HEADER)
+        .and_then(|value| decode_payment_header(value.to_str().ok()?).ok());
+    let Some(payload) = payload else {
+        let requirements = PaymentRequirements {
+            x402_version: None,
+            scheme: ESCROW_SCHEME.into(),
+            network: NETWORK.into(),
+            max_amount_required: PRICE.to_string(),
+            resource: "/quote".into(),
+            description: "Quote".into(),
+            mime_type: "text/plain".into(),
+            output_schema: None,
+            pay_to: server.pay_to.clone(),
+            asset: Some(USDC.into()),
+            alternatives: vec![AssetAmount {
+                asset: NATIVE_ASSET.into(),
+                max_amount_required: (2 * PRICE).to_string(),
+            }],
+            max_timeout_seconds: 60,
+            extra: None,
+        };
+        let challenge = PaymentRequiredResponse {
+            x402_version: X402_VERSION,
+            x402_versions: Vec::new(),
+            accepts: requirements.expand(),
+            error: None,
+            payment_error: None,
+        };
+        return (StatusCode::PAYMENT_REQUIRED, Json(challenge)).into_response();
+    };
+    let SchemePayload::Escrow(escrow) = payload.payload else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    server
+        .paid
+        .lock()
+        .unwrap()
+        .push((payload.asset, escrow.amount));
+    "quoted".into_response()
+}
+
+/// Quote server paid by the setup's client, whose USDC escrows live in
+/// another contract
+async fn quote_setup() -> (Setup, EscrowClient, QuoteServer, String) {
+    let s = setup();
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let usdc = EscrowClient::new(
+        Rpc::new(transport),
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&[1; 32]),
+    )
+    .unwrap();
+    let server = QuoteServer {
+        pay_to: s.server_addr.clone(),
+        ..Default::default()
+    };
+    let app = Router::new()
+        .route("/quote", get(quote))
+        .with_state(server.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/quote", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (s, usdc, server, url)
+}
+
+#[tokio::test]
+async fn test_http_client_pays_funded_asset() {
+    let (s, usdc, server, url) = quote_setup().await;
+    // The client only holds the second-choice asset
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .asset_escrow(USDC, usdc.clone())
+        .build();
+
+    let paid = http.get(&url).await.unwrap();
+    assert_eq!(paid.response.status(), 200);
+    let receipt = paid.receipt.unwrap();
+    assert_eq!(receipt.requirements.asset.as_deref(), Some(NATIVE_ASSET));
+    assert_eq!(receipt.amount, 2 * PRICE);
+    assert_eq!(
+        *server.paid.lock().unwrap(),
+        [(Some(NATIVE_ASSET.into()), (2 * PRICE).to_string())]
+    );
+    // Nothing was opened or deposited for the first choice
+    assert_eq!(
+        usdc.find_escrow(&s.client_addr, &s.server_addr)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        s.client.get_escrow_balance(escrow_id).await.unwrap(),
+        1_000_000
+    );
+}
+
+#[tokio::test]
+async fn test_http_client_asset_preferences() {
+    let (s, usdc, server, url) = quote_setup().await;
+    let paying = |policy: SpendPolicy| {
+        X402HttpClient::builder(s.client.clone(), NETWORK)
+            .asset_escrow(USDC, usdc.clone())
+            .policy(policy)
+            .build()
+    };
+
+    // Without escrows nor preferences, the first asset offered is paid
+    let receipt = paying(SpendPolicy::new()).get(&url).await.unwrap().receipt;
+    let receipt = receipt.unwrap();
+    assert_eq!(receipt.requirements.asset.as_deref(), Some(USDC));
+    assert_eq!(
+        usdc.get_escrow_balance(receipt.escrow_id).await.unwrap(),
+        PRICE
+    );
+
+    // A funded escrow outranks the preferences
+    let http = paying(SpendPolicy::new().prefer_asset(NATIVE_ASSET));
+    let receipt = http.get(&url).await.unwrap().receipt;
+    assert_eq!(receipt.unwrap().requirements.asset.as_deref(), Some(USDC));
+    assert_eq!(server.paid.lock().unwrap().len(), 2);
+
+    // Without any escrow, the preferred asset is paid
+    let (s, usdc, _, url) = quote_setup().await;
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .asset_escrow(USDC, usdc)
+        .policy(SpendPolicy::new().prefer_asset(NATIVE_ASSET))
+        .build();
+    let receipt = http.get(&url).await.unwrap().receipt.unwrap();
+    assert_eq!(receipt.requirements.asset.as_deref(), Some(NATIVE_ASSET));
+    assert_eq!(
+        s.client
+            .get_escrow_balance(receipt.escrow_id)
+            .await
+            .unwrap(),
+        2 * PRICE
+    );
+}
+
+async fn paying_setup() -> (Setup, MockServer, String) {
+    let s = setup();
+    let server = MockServer {
+        pay_to: s.server_addr.clone(),
+        client_key: LocalSigner::from_bytes(&[1; 32]).public_key(),
+        contract_id: s.client.contract_id(),
+        requests: Arc::default(),
+        x402_version: X402_VERSION,
+        rejection: Arc::default(),
+    };
+    let url = serve(server.clone()).await;
+    (s, server, url)
+}
+
+#[tokio::test]
+async fn test_http_client_pays_402() {
+    let (s, server, url) = paying_setup().await;
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .deposit(150_000)
+        .build();
+
+    // Free resources are not paid
+    let free = http.get(format!("{url}/free")).await.unwrap();
+    assert!(free.receipt.is_none());
+
+    // First payment opens the escrow
+    let paid = http.get(format!("{url}/weather")).await.unwrap();
+    assert_eq!(paid.response.status(), 200);
+    let receipt = paid.receipt.unwrap();
+    let escrow_id = receipt.escrow_id;
+    assert_eq!(
+        s.client
+            .find_escrow(&s.client_addr, &s.server_addr)
+            .await
+            .unwrap(),
+        Some(escrow_id)
+    );
+    assert_eq!(receipt.amount, PRICE);
+    assert_eq!(receipt.settlement.unwrap().payment_id, Some(9));
+    assert_eq!(paid.response.text().await.unwrap(), "sunny");
+    assert_eq!(
+        s.client.get_escrow_balance(escrow_id).await.unwrap(),
+        150_000
+    );
+
+    // The server charges, so the next payment tops the escrow up
+    let payment_id = s
+        .server
+        .create_payment(escrow_id, PRICE)
+        .await
+        .unwrap()
+        .value;
+    s.server.settle_payment(payment_id).await.unwrap();
+    let paid = http.get(format!("{url}/weather")).await.unwrap();
+    assert_eq!(paid.receipt.as_ref().unwrap().escrow_id, escrow_id);
+    assert_eq!(
+        s.client.get_escrow_balance(escrow_id).await.unwrap(),
+        200_000
+    );
+    assert_eq!(http.spent(), 2 * PRICE);
+
+    // Each paid retry carries a fresh nonce
+    let requests = server.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 4);
+    assert_eq!(requests[0], None);
+    assert_eq!(requests[2], None);
+    assert_ne!(requests[1], requests[3]);
+}
+
+#[tokio::test]
+async fn test_http_client_channel_cache() {
+    let (s, server, url) = paying_setup().await;
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .deposit(150_000)
+        .channel_cache(Duration::from_secs(3600))
+        .build();
+
+    // The first payment opens the escrow and starts caching it
+    let paid = http.get(format!("{url}/weather")).await.unwrap();
+    let escrow_id = paid.receipt.unwrap().escrow_id;
+    let channel = http.channel(&s.server_addr).unwrap();
+    assert_eq!(channel.escrow_id(), escrow_id);
+    assert_eq!(channel.pending().len(), 1);
+    assert!(channel.pending()[0].reported_settled);
+    assert_eq!(channel.available(), 150_000 - PRICE);
+
+    // The cached balance does not cover the next payment, so it tops up
+    http.get(format!("{url}/weather")).await.unwrap();
+    assert_eq!(
+        s.client.get_escrow_balance(escrow_id).await.unwrap(),
+        300_000
+    );
+    assert_eq!(channel.balance(), 300_000 - 2 * PRICE);
+    assert_eq!(server.requests.lock().unwrap().len(), 4);
+
+    // Settlements reported by the mock server never land, which is no drift
+    // but leaves the payments committed
+    http.force_sync().await.unwrap();
+    assert_eq!(channel.balance(), 300_000);
+    assert_eq!(channel.available(), 300_000 - 2 * PRICE);
+    assert_eq!(channel.drift().last_drift, 0);
+    assert!(channel.pending().iter().all(|p| !p.reported_settled));
+}
+
+#[tokio::test]
+async fn test_payment_journal() {
+    let (s, _, url) = paying_setup().await;
+    let path = std::env::temp_dir().join(format!("x402-journal-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .deposit(1_000_000)
+        .journal(PaymentJournal::open(&path).unwrap())
+        .build();
+
+    // The mock server reports settlements without landing them, so land
+    // each payment on-chain as a facilitator would
+    let mut escrow_id = None;
+    for _ in 0..3 {
+        let receipt = http.get(format!("{url}/weather")).await.unwrap().receipt;
+        let receipt = receipt.unwrap();
+        escrow_id = Some(receipt.escrow_id);
+        let payment_id = s
+            .server
+            .create_payment(receipt.escrow_id, receipt.amount)
+            .await
+            .unwrap()
+            .value;
+        s.server.settle_payment(payment_id).await.unwrap();
+    }
+    let escrow_id = escrow_id.unwrap();
+    http.get(format!("{url}/free")).await.unwrap();
+    drop(http);
+
+    // Reopened after a restart, with a torn last line from a crash
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, b"{\"at\":"))
+        .unwrap();
+    let journal = PaymentJournal::open(&path).unwrap();
+    let entries = journal.entries().unwrap();
+    assert_eq!(entries.len(), 9);
+    assert!(matches!(entries[0].entry, JournalEntry::Required { .. }));
+    assert!(matches!(
+        entries[1].entry,
+        JournalEntry::Signed { escrow_id: signed, .. } if signed == escrow_id
+    ));
+
+    let settled: i128 = s
+        .client
+        .payments(escrow_id)
+        .status(PaymentStatus::Settled)
+        .collect_all(usize::MAX)
+        .await
+        .unwrap()
+        .iter()
+        .map(|(_, payment)| payment.amount)
+        .sum();
+    assert_eq!(settled, 3 * PRICE);
+    assert_eq!(journal.total_spent_since(0).unwrap(), settled);
+    assert_eq!(journal.total_spent_since(u64::MAX).unwrap(), 0);
+
+    let receipts = journal.receipts_for_host("127.0.0.1").unwrap();
+    assert_eq!(receipts.len(), 3);
+    let JournalEntry::Settled {
+        settlement,
+        receipt_hash,
+        ..
+    } = &receipts[0].entry
+    else {
+        panic!("not a receipt: {:?}", receipts[0]);
+    };
+    assert_eq!(settlement.as_ref().unwrap().payment_id, Some(9));
+    assert_eq!(receipt_hash.as_ref().unwrap().len(), 64);
+    assert!(journal.receipts_for_host("example.com").unwrap().is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_channel_state_reconciles() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let channel = ChannelState::new(s.client.clone(), escrow_id)
+        .with_sync_interval(Duration::from_secs(3600));
+    assert!(channel.is_due());
+    channel.sync_if_due().await.unwrap();
+    assert_eq!(channel.balance(), 1_000_000);
+    assert!(!channel.is_due());
+
+    // Updated optimistically as payments are authorized and settled
+    let far = u64::MAX;
+    channel.record_payment(1, 100_000, far);
+    channel.record_payment(2, 50_000, far);
+    channel.record_settlement(1);
+    assert_eq!(channel.balance(), 900_000);
+    assert_eq!(channel.available(), 850_000);
+
+    // The server charges both, and settles the first along with a payment
+    // out of band
+    let first = s
+        .server
+        .create_payment(escrow_id, 100_000)
+        .await
+        .unwrap()
+        .value;
+    let second = s
+        .server
+        .create_payment(escrow_id, 50_000)
+        .await
+        .unwrap()
+        .value;
+    let out_of_band = s
+        .server
+        .create_payment(escrow_id, 30_000)
+        .await
+        .unwrap()
+        .value;
+    s.server
+        .settle_payments(&[first, out_of_band])
+        .await
+        .unwrap();
+    channel.sync_if_due().await.unwrap();
+    assert_eq!(channel.balance(), 900_000);
+
+    // Reconciliation corrects the cache and records the drift
+    let drift = channel.force_sync().await.unwrap();
+    assert_eq!(drift.last_drift, -30_000);
+    assert_eq!(drift.unknown_payments, 1);
+    assert_eq!(channel.balance(), 870_000);
+    assert_eq!(channel.available(), 820_000);
+    let pending = channel.pending();
+    assert_eq!(pending.len(), 1);
+    assert_eq!((pending[0].nonce, pending[0].payment_id), (2, Some(second)));
+
+    // A payment settled since is seen once the cache is marked stale
+    s.server.settle_payment(second).await.unwrap();
+    channel.mark_stale();
+    channel.sync_if_due().await.unwrap();
+    assert!(channel.pending().is_empty());
+    assert_eq!(channel.available(), 820_000);
+    let drift = channel.drift();
+    assert_eq!((drift.syncs, drift.drifted_syncs), (3, 1));
+    assert_eq!((drift.last_drift, drift.max_drift), (0, 30_000));
+
+    // Authorizations expiring unused stop being committed
+    channel.record_payment(3, 10_000, 0);
+    assert_eq!(channel.available(), 810_000);
+    channel.force_sync().await.unwrap();
+    assert!(channel.pending().is_empty());
+    assert_eq!(channel.available(), 820_000);
+}
+
+#[tokio::test]
+async fn test_http_client_spend_caps() {
+    let (s, server, url) = paying_setup().await;
+
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .policy(SpendPolicy::new().max_per_request(PRICE - 1))
+        .build();
+    let err = http.get(format!("{url}/weather")).await.unwrap_err();
+    assert!(matches!(err, Error::PaymentDeclined(_)), "{err}");
+
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .policy(SpendPolicy::new().max_per_session(PRICE * 3 / 2))
+        .build();
+    http.get(format!("{url}/weather")).await.unwrap();
+    let err = http.get(format!("{url}/weather")).await.unwrap_err();
+    assert!(matches!(err, Error::PaymentDeclined(_)), "{err}");
+    assert_eq!(http.spent(), PRICE);
+
+    // The inspector sees the requirements before anything is paid
+    let seen = Arc::new(Mutex::new(None));
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .inspect({
+            let seen = seen.clone();
+            move |requirements| {
+                *seen.lock().unwrap() = Some(requirements.description.clone());
+                false
+            }
+        })
+        .build();
+    let err = http.get(format!("{url}/weather")).await.unwrap_err();
+    assert!(matches!(err, Error::PaymentDeclined(_)), "{err}");
+    assert_eq!(seen.lock().unwrap().as_deref(), Some("Weather report"));
+
+    // Only one paid request reached the server
+    let paid = server.requests.lock().unwrap().iter().flatten().count();
+    assert_eq!(paid, 1);
+}
+
+/// Rejection of a payment for `error`, without hints
+fn rejection(error: impl Into<X402Error>) -> PaymentErrorBody {
+    let error = error.into();
+    PaymentErrorBody {
+        code: error.code(),
+        message: error.to_string(),
+        retry_after: None,
+        required_topup_amount: None,
+        requirements: None,
+    }
+}
+
+#[tokio::test]
+async fn test_http_client_tops_up_rejected_payment() {
+    let (s, server, url) = paying_setup().await;
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .deposit(150_000)
+        .build();
+    let paid = http.get(format!("{url}/weather")).await.unwrap();
+    let escrow_id = paid.receipt.unwrap().escrow_id;
+
+    // The server finds the escrow short, the client tops it up by its
+    // deposit, which covers what is missing, and pays again
+    *server.rejection.lock().unwrap() = Some(PaymentErrorBody {
+        required_topup_amount: Some("20000".into()),
+        requirements: Some(weather_requirements(&server.pay_to)),
+        ..rejection(ContractError::InsufficientBalance)
+    });
+    let paid = http.get(format!("{url}/weather")).await.unwrap();
+    assert_eq!(paid.response.status(), 200);
+    assert_eq!(paid.receipt.unwrap().escrow_id, escrow_id);
+    assert_eq!(
+        s.client.get_escrow_balance(escrow_id).await.unwrap(),
+        300_000
+    );
+
+    // The rejected payment is not counted
+    assert_eq!(http.spent(), 2 * PRICE);
+    let requests = server.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 5);
+    assert_ne!(requests[3], requests[4]);
+}
+
+#[tokio::test]
+async fn test_http_client_resigns_rejected_payment() {
+    let (s, server, url) = paying_setup().await;
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .deposit(150_000)
+        .build();
+    let mut requirements = weather_requirements(&server.pay_to);
+    requirements.max_timeout_seconds = 600;
+
+    for error in [
+        X402Error::from(ProtocolError::Expired),
+        ProtocolError::NonceUsed.into(),
+        ContractError::AuthorizationUsed.into(),
+    ] {
+        *server.rejection.lock().unwrap() = Some(PaymentErrorBody {
+            requirements: Some(requirements.clone()),
+            ..rejection(error.clone())
+        });
+        let paid = http.get(format!("{url}/weather")).await.unwrap();
+        assert_eq!(paid.response.status(), 200, "{error}");
+
+        // Signed anew, for the requirements the server sent back
+        let receipt = paid.receipt.unwrap();
+        assert_eq!(receipt.requirements.max_timeout_seconds, 600);
+        let requests = server.requests.lock().unwrap().clone();
+        let [.., rejected, accepted] = requests[..] else {
+            unreachable!()
+        };
+        assert!(rejected.is_some() && rejected != accepted, "{error}");
+        assert_eq!(accepted, Some(receipt.nonce));
+    }
+    assert_eq!(http.spent(), 3 * PRICE);
+}
+
+#[tokio::test]
+async fn test_http_client_fails_fast_on_rejection() {
+    let (s, server, url) = paying_setup().await;
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .deposit(150_000)
+        .build();
+    let reject = |rejection: PaymentErrorBody| *server.rejection.lock().unwrap() = Some(rejection);
+
+    // Suspended resources and paused settlements are not paid again
+    for error in [
+        ContractError::ResourceSuspended,
+        ContractError::SettlementsPaused,
+    ] {
+        reject(rejection(error));
+        let err = http.get(format!("{url}/weather")).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::PaymentRejected {
+                    retry_after: None,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        assert_eq!(X402Error::from(&err), X402Error::Contract(error));
+        assert!(!err.is_transient());
+    }
+
+    // Nor is an escrow short of funds topped up without the amount missing
+    reject(rejection(ContractError::InsufficientBalance));
+    let err = http.get(format!("{url}/weather")).await.unwrap_err();
+    assert!(matches!(err, Error::PaymentRejected { .. }), "{err}");
+
+    // Transient rejections tell when to pay again
+    reject(PaymentErrorBody {
+        retry_after: Some(5),
+        ..rejection(TransportError::Unreachable)
+    });
+    let err = http.get(format!("{url}/weather")).await.unwrap_err();
+    assert!(
+        matches!(err, Error::PaymentRejected { retry_after: Some(delay), .. }
+            if delay == Duration::from_secs(5)),
+        "{err}"
+    );
+    assert!(err.is_transient());
+
+    // Each payment was made once, and none is counted
+    let paid = server.requests.lock().unwrap().iter().flatten().count();
+    assert_eq!(paid, 4);
+    assert_eq!(http.spent(), 0);
+}
+
+#[tokio::test]
+async fn test_http_client_provisions_escrow() {
+    let (s, _, url) = paying_setup().await;
+    assert_eq!(
+        s.client
+            .find_escrow(&s.client_addr, &s.server_addr)
+            .await
+            .unwrap(),
+        None
+    );
+
+    // A deposit cap below the price declines before anything is opened
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .initial_deposit(500_000)
+        .policy(SpendPolicy::new().max_deposit(PRICE - 1))
+        .build();
+    let err = http.get(format!("{url}/weather")).await.unwrap_err();
+    assert!(matches!(err, Error::PaymentDeclined(_)), "{err}");
+    assert_eq!(http.spent(), 0);
+    assert_eq!(
+        s.client
+            .find_escrow(&s.client_addr, &s.server_addr)
+            .await
+            .unwrap(),
+        None
+    );
+
+    // The first 402 opens the escrow with the initial deposit, within the cap
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .initial_deposit(500_000)
+        .policy(SpendPolicy::new().max_deposit(300_000))
+        .build();
+    let paid = http.get(format!("{url}/weather")).await.unwrap();
+    assert_eq!(paid.response.status(), 200);
+    let escrow_id = paid.receipt.unwrap().escrow_id;
+    assert_eq!(
+        s.client
+            .find_escrow(&s.client_addr, &s.server_addr)
+            .await
+            .unwrap(),
+        Some(escrow_id)
+    );
+    assert_eq!(
+        s.client.get_escrow_balance(escrow_id).await.unwrap(),
+        300_000
+    );
+}
+
+/// Transport opening the escrow out of band right after the client's first
+/// escrow lookup, as a concurrent request would
+struct RacingTransport {
+    inner: EnvTransport,
+    /// Client, server, and deposit of the escrow to open
+    rival: Mutex<Option<(String, String, i128)>>,
+}
+
+fn invokes(params: &Value, function: &str) -> bool {
+    let Some(envelope) = params["transaction"]
+        .as_str()
+        .and_then(|xdr| TransactionEnvelope::from_xdr_base64(xdr, Limits::none()).ok())
+    else {
+        return false;
+    };
+    let TransactionEnvelope::Tx(envelope) = envelope else {
+        return false;
+    };
+    envelope.tx.operations.iter().any(|op| match &op.body {
+        OperationBody::InvokeHostFunction(op) => matches!(
+            &op.host_function,
+            HostFunction::InvokeContract(args) if args.function_name.0.as_slice() == function.as_bytes()
+        ),
+        _ => false,
+    })
+}
+
+#[async_trait]
+impl Transport for RacingTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let lookup = method == "simulateTransaction" && invokes(&params, "find_escrow");
+        let response = self.inner.request(method, params).await?;
+        let rival = if lookup {
+            self.rival.lock().unwrap().take()
+        } else {
+            None
+        };
+        if let Some((client, server, deposit)) = rival {
+            let contract = self.inner.contract_id().to_string();
+            self.inner.with_env(move |env| {
+                let address = |strkey: &str| soroban_sdk::Address::from_str(env, strkey);
+                X402EscrowContractClient::new(env, &address(&contract)).open_escrow(
+                    &address(&client),
+                    &address(&server),
+                    &deposit,
+                    &None,
+                );
+            });
+        }
+        Ok(response)
+    }
+}
+
+#[tokio::test]
+async fn test_http_client_escrow_open_race() {
+    let client_key = LocalSigner::from_bytes(&[1; 32]);
+    let server_key = LocalSigner::from_bytes(&[2; 32]);
+    let transport = RacingTransport {
+        inner: EnvTransport::new(|env| env.register(X402EscrowContract, ())),
+        rival: Mutex::new(Some((client_key.address(), server_key.address(), 50_000))),
+    };
+    let contract_id = transport.inner.contract_id().to_string();
+    let client = EscrowClient::new(
+        Rpc::new(transport),
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        client_key,
+    )
+    .unwrap();
+    let server = MockServer {
+        pay_to: server_key.address(),
+        client_key: LocalSigner::from_bytes(&[1; 32]).public_key(),
+        contract_id: contract_id.clone(),
+        requests: Arc::default(),
+        x402_version: X402_VERSION,
+        rejection: Arc::default(),
+    };
+    let url = serve(server).await;
+
+    // The lookup finds nothing, then opening fails as the escrow exists:
+    // the payment is drawn from it, topped up to cover the price
+    let http = X402HttpClient::builder(client.clone(), NETWORK)
+        .initial_deposit(500_000)
+        .build();
+    let paid = http.get(format!("{url}/weather")).await.unwrap();
+    assert_eq!(paid.response.status(), 200);
+    let escrow_id = paid.receipt.unwrap().escrow_id;
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        50_000 + PRICE
+    );
+    assert_eq!(
+        client
+            .get_client_escrow_count(&client.address())
+            .await
+            .unwrap(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_http_client_negotiates_version() {
+    let s = setup();
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .deposit(150_000)
+        .build();
+    let server = |x402_version| MockServer {
+        pay_to: s.server_addr.clone(),
+        client_key: LocalSigner::from_bytes(&[1; 32]).public_key(),
+        contract_id: s.client.contract_id(),
+        requests: Arc::default(),
+        x402_version,
+        rejection: Arc::default(),
+    };
+
+    // Servers predating version 2 are paid with version 1
+    let url = serve(server(1)).await;
+    let paid = http.get(format!("{url}/weather")).await.unwrap();
+    assert_eq!(paid.response.status(), 200);
+    assert_eq!(paid.receipt.unwrap().requirements.x402_version, Some(1));
+
+    // Servers speaking no version the client knows are not paid
+    let url = serve(server(X402_VERSION + 1)).await;
+    let err = http.get(format!("{url}/weather")).await.unwrap_err();
+    assert!(matches!(err, Error::InvalidChallenge(_)), "{err}");
+    assert_eq!(http.spent(), PRICE);
+}
+
+#[tokio::test]
+async fn test_http_client_rejects_other_networks() {
+    let (s, _, url) = paying_setup().await;
+    let http = X402HttpClient::builder(s.client.clone(), "stellar-mainnet").build();
+    let err = http.get(format!("{url}/weather")).await.unwrap_err();
+    assert!(matches!(err, Error::InvalidChallenge(_)), "{err}");
+}
+
+#[test]
+fn test_spend_policy_rules() {
+    let policy = SpendPolicy::new()
+        .allow_host("a.example")
+        .allow_host("b.example")
+        .max_per_request(500)
+        .max_per_host_per_hour(800)
+        .max_per_session(1_200);
+    let denied = |decision| matches!(decision, Decision::Deny(_));
+
+    assert_eq!(policy.evaluate("a.example", 500), Decision::Allow);
+    assert!(denied(policy.evaluate("c.example", 1)));
+    assert!(denied(policy.evaluate("a.example", 501)));
+
+    // Hourly cap, per host
+    policy.reserve("a.example", 500, || true).unwrap();
+    assert!(denied(policy.evaluate("a.example", 400)));
+    assert_eq!(policy.evaluate("b.example", 400), Decision::Allow);
+    assert_eq!(policy.spent_on("a.example"), 500);
+
+    // Session cap, across hosts
+    policy.reserve("b.example", 500, || true).unwrap();
+    let err = policy.reserve("b.example", 300, || true).unwrap_err();
+    assert!(matches!(err, Error::PaymentDeclined(_)), "{err}");
+    assert_eq!(policy.spent(), 1_000);
+    policy.refund("b.example", 500);
+    assert_eq!(policy.spent(), 500);
+    assert_eq!(policy.spent_on("b.example"), 0);
+}
+
+#[test]
+fn test_spend_policy_hourly_window() {
+    let policy = SpendPolicy::new().max_per_host_per_hour(100);
+    let now = Instant::now();
+    let earlier = now.checked_sub(Duration::from_secs(3600)).unwrap();
+
+    policy
+        .reserve_at("a.example", 100, earlier, || true)
+        .unwrap();
+    assert_eq!(policy.spent_on("a.example"), 0);
+    policy.reserve_at("a.example", 100, now, || true).unwrap();
+    let err = policy.reserve_at("a.example", 1, now, || true).unwrap_err();
+    assert!(matches!(err, Error::PaymentDeclined(_)), "{err}");
+    assert_eq!(policy.spent(), 200);
+}
+
+#[test]
+fn test_spend_policy_confirmation() {
+    let policy = SpendPolicy::new().confirm_above(100);
+    assert_eq!(policy.evaluate("a.example", 100), Decision::Allow);
+    assert_eq!(policy.evaluate("a.example", 101), Decision::Confirm);
+
+    // Allowed payments skip the callback
+    policy
+        .reserve("a.example", 100, || unreachable!("no confirmation needed"))
+        .unwrap();
+    let err = policy.reserve("a.example", 101, || false).unwrap_err();
+    assert!(matches!(err, Error::PaymentDeclined(_)), "{err}");
+    policy.reserve("a.example", 101, || true).unwrap();
+    assert_eq!(policy.spent(), 201);
+}
+
+#[tokio::test]
+async fn test_http_client_confirms_large_payments() {
+    let (s, _, url) = paying_setup().await;
+    let policy = || {
+        SpendPolicy::new()
+            .allow_host("127.0.0.1")
+            .confirm_above(PRICE - 1)
+    };
+
+    // Unconfirmed without a callback
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .policy(policy())
+        .build();
+    let err = http.get(format!("{url}/weather")).await.unwrap_err();
+    assert!(matches!(err, Error::PaymentDeclined(_)), "{err}");
+
+    let confirmed = Arc::new(Mutex::new(Vec::new()));
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .policy(policy())
+        .confirm({
+            let confirmed = confirmed.clone();
+            move |requirements| {
+                confirmed
+                    .lock()
+                    .unwrap()
+                    .push(requirements.max_amount_required.clone());
+                true
+            }
+        })
+        .build();
+    let paid = http.get(format!("{url}/weather")).await.unwrap();
+    assert_eq!(paid.response.status(), 200);
+    assert_eq!(*confirmed.lock().unwrap(), vec![PRICE.to_string()]);
+    assert_eq!(http.policy().spent_on("127.0.0.1"), PRICE);
+
+    // Hosts outside the allowlist are never paid
+    let http = X402HttpClient::builder(s.client.clone(), NETWORK)
+        .policy(SpendPolicy::new().allow_host("example.com"))
+        .build();
+    let err = http.get(format!("{url}/weather")).await.unwrap_err();
+    assert!(matches!(err, Error::PaymentDeclined(_)), "{err}");
+}
+
+#[tokio::test]
+async fn test_get_events() {
+    let s = setup();
+    let contract_id = s.client.contract_id();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000, None)
+        .await
+        .unwrap()
+        .value;
+    s.client.deposit(escrow_id, 500).await.unwrap();
+    // Failed invocations emit nothing
+    s.client.deposit(u64::MAX, 500).await.unwrap_err();
+    let payment_id = s.server.create_payment(escrow_id, 300).await.unwrap().value;
+    s.server.settle_payment(payment_id).await.unwrap();
+
+    let rpc = s.client.rpc();
+    let page = rpc
+        .get_events(&contract_id, &EventsFrom::Ledger(0), 10)
+        .await
+        .unwrap();
+    let symbols: Vec<ScVal> = page
+        .events
+        .iter()
+        .map(|event| ScVal::from_xdr_base64(&event.topic[0], Limits::none()).unwrap())
+        .collect();
+    let expected: Vec<ScVal> = ["open", "deposit", "pay", "settled"]
+        .into_iter()
+        .map(|s| ScVal::Symbol(s.try_into().unwrap()))
+        .collect();
+    assert_eq!(symbols, expected);
+    let deposit = &page.events[1];
+    assert_eq!(deposit.contract_id, contract_id);
+    let value = ScVal::from_xdr_base64(&deposit.value, Limits::none()).unwrap();
+    assert_eq!(EscrowEvent::payload_version(&value).unwrap(), EVENT_VERSION);
+    assert_eq!(
+        EscrowEvent::from_info(deposit).unwrap(),
+        Some(EscrowEvent::Deposited {
+            escrow_id,
+            amount: 500
+        })
+    );
+    assert!(deposit.ledger > page.events[0].ledger);
+
+    // Paging resumes strictly after the cursor
+    let first = rpc
+        .get_events(&contract_id, &EventsFrom::Ledger(0), 1)
+        .await
+        .unwrap();
+    assert_eq!(first.events.len(), 1);
+    let rest = rpc
+        .get_events(&contract_id, &EventsFrom::Cursor(first.cursor.unwrap()), 10)
+        .await
+        .unwrap();
+    assert_eq!(rest.events.len(), 3);
+    assert_eq!(rest.events[0].id, page.events[1].id);
+
+    let other = rpc
+        .get_events(&s.server_addr, &EventsFrom::Ledger(0), 10)
+        .await
+        .unwrap();
+    assert!(other.events.is_empty());
+}
+
+#[test]
+fn test_decode_v1_events() {
+    // Captured from the deployment emitting bare values and tuples
+    let page: GetEventsResponse =
+        serde_json::from_str(include_str!("../fixtures/events_v1.json")).unwrap();
+    let client = stellar_strkey::ed25519::PublicKey([1; 32]).to_string();
+    let server = stellar_strkey::ed25519::PublicKey([2; 32]).to_string();
+
+    for info in &page.events {
+        let value = ScVal::from_xdr_base64(&info.value, Limits::none()).unwrap();
+        assert_eq!(EscrowEvent::payload_version(&value).unwrap(), 1);
+    }
+    let events: Vec<_> = page
+        .events
+        .iter()
+        .map(|info| EscrowEvent::from_info(info).unwrap())
+        .collect();
+    assert_eq!(
+        events,
+        [
+            Some(EscrowEvent::Opened {
+                escrow_id: 0,
+                client: client.clone(),
+                server: server.clone(),
+            }),
+            Some(EscrowEvent::Deposited {
+                escrow_id: 0,
+                amount: 500
+            }),
+            // Claims are not decoded by this SDK
+            None,
+            Some(EscrowEvent::PaymentCreated {
+                payment_id: 0,
+                client,
+                server,
+                amount: 300
+            }),
+            Some(EscrowEvent::PaymentSettled {
+                payment_id: 0,
+                amount: 300
+            }),
+            Some(EscrowEvent::Closed {
+                escrow_id: 0,
+                released: 1_200
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_decode_payment_without_quote() {
+    let entries: Vec<ScMapEntry> = [
+        ("amount", scval::i128(300)),
+        ("escrow_id", scval::u64(4)),
+        ("settled", ScVal::Bool(true)),
+        ("timestamp", scval::u64(1_000)),
+    ]
+    .into_iter()
+    .map(|(key, val)| ScMapEntry {
+        key: symbol(key),
+        val,
+    })
+    .collect();
+    let legacy = ScVal::Map(Some(ScMap(entries.try_into().unwrap())));
+
+    let payment = Payment::try_from(&legacy).unwrap();
+    assert!(payment.settled);
+    assert_eq!(payment.quote_amount, None);
+    assert_eq!(payment.quote_currency, None);
+}
+
+#[test]
+fn test_decode_versioned_events() {
+    let payload = |fields: Vec<(&str, ScVal)>| {
+        let entries: Vec<ScMapEntry> = fields
+            .into_iter()
+            .map(|(key, val)| ScMapEntry {
+                key: symbol(key),
+                val,
+            })
+            .collect();
+        ScVal::Map(Some(ScMap(entries.try_into().unwrap())))
+    };
+    let account = |key: u8| stellar_strkey::ed25519::PublicKey([key; 32]).to_string();
+    let topics = [
+        symbol("pay"),
+        scval::address(&account(2)).unwrap(),
+        scval::address(&account(1)).unwrap(),
+    ];
+
+    let v2 = payload(vec![
+        ("amount", scval::i128(300)),
+        ("event_version", ScVal::U32(2)),
+        ("payment_id", scval::u64(4)),
+    ]);
+    let created = EscrowEvent::PaymentCreated {
+        payment_id: 4,
+        client: account(1),
+        server: account(2),
+        amount: 300,
+    };
+    assert_eq!(EscrowEvent::payload_version(&v2).unwrap(), 2);
+    assert_eq!(
+        EscrowEvent::decode(&topics, &v2).unwrap(),
+        Some(created.clone())
+    );
+
+    // Later versions decode as long as the known fields are kept
+    let v3 = payload(vec![
+        ("amount", scval::i128(300)),
+        ("event_version", ScVal::U32(3)),
+        ("memo", ScVal::Void),
+        ("payment_id", scval::u64(4)),
+    ]);
+    assert_eq!(EscrowEvent::payload_version(&v3).unwrap(), 3);
+    assert_eq!(EscrowEvent::decode(&topics, &v3).unwrap(), Some(created));
+
+    let unversioned = payload(vec![("amount", scval::i128(300))]);
+    assert!(matches!(
+        EscrowEvent::payload_version(&unversioned),
+        Err(Error::InvalidResponse(_))
+    ));
+    let missing = payload(vec![
+        ("event_version", ScVal::U32(2)),
+        ("payment_id", scval::u64(4)),
+    ]);
+    assert!(matches!(
+        EscrowEvent::decode(&topics, &missing),
+        Err(Error::InvalidResponse(_))
+    ));
+    let short = scval::vec(vec![scval::u64(4)]).unwrap();
+    assert!(matches!(
+        EscrowEvent::decode(&topics, &short),
+        Err(Error::InvalidResponse(_))
+    ));
+
+    let hash = |byte: u8| ScVal::Bytes(ScBytes(vec![byte; 32].try_into().unwrap()));
+    let terms = payload(vec![
+        ("event_version", ScVal::U32(2)),
+        ("previous", hash(1)),
+        ("terms_hash", hash(2)),
+    ]);
+    let topics = [symbol("terms"), scval::address(&account(2)).unwrap()];
+    assert_eq!(
+        EscrowEvent::decode(&topics, &terms).unwrap(),
+        Some(EscrowEvent::TermsUpdated {
+            server: account(2),
+            terms_hash: [2; 32],
+            previous: Some([1; 32]),
+        })
+    );
+
+    let url = |url: &[u8]| ScVal::Bytes(ScBytes(url.try_into().unwrap()));
+    let topics = [symbol("notify"), scval::address(&account(2)).unwrap()];
+    let registered = payload(vec![
+        ("event_version", ScVal::U32(2)),
+        ("url", url(b"https://api.example.com/hooks")),
+    ]);
+    assert_eq!(
+        EscrowEvent::decode(&topics, &registered).unwrap(),
+        Some(EscrowEvent::NotificationUrlUpdated {
+            server: account(2),
+            url: Some("https://api.example.com/hooks".into()),
+        })
+    );
+    let removed = payload(vec![("event_version", ScVal::U32(2)), ("url", ScVal::Void)]);
+    assert_eq!(
+        EscrowEvent::decode(&topics, &removed).unwrap(),
+        Some(EscrowEvent::NotificationUrlUpdated {
+            server: account(2),
+            url: None,
+        })
+    );
+    let invalid = payload(vec![
+        ("event_version", ScVal::U32(2)),
+        ("url", url(&[0xff])),
+    ]);
+    assert!(matches!(
+        EscrowEvent::decode(&topics, &invalid),
+        Err(Error::InvalidResponse(_))
+    ));
+
+    let refunded = payload(vec![
+        ("amount", scval::i128(100)),
+        ("event_version", ScVal::U32(2)),
+        ("refunded", scval::i128(150)),
+    ]);
+    assert_eq!(
+        EscrowEvent::decode(&[symbol("refunded"), scval::u64(4)], &refunded).unwrap(),
+        Some(EscrowEvent::PaymentRefunded {
+            payment_id: 4,
+            amount: 100,
+        })
+    );
+
+    // Sweeps were emitted before payloads were versioned
+    let topics = [symbol("swept"), scval::u64(7)];
+    let swept = EscrowEvent::Swept {
+        escrow_id: 7,
+        balance: 20,
+    };
+    let v1 = scval::vec(vec![
+        scval::address(&account(1)).unwrap(),
+        scval::address(&account(3)).unwrap(),
+        scval::i128(20),
+    ])
+    .unwrap();
+    assert_eq!(
+        EscrowEvent::decode(&topics, &v1).unwrap(),
+        Some(swept.clone())
+    );
+    let v2 = payload(vec![
+        ("balance", scval::i128(20)),
+        ("client", scval::address(&account(1)).unwrap()),
+        ("event_version", ScVal::U32(2)),
+        ("refund_to", scval::address(&account(3)).unwrap()),
+    ]);
+    assert_eq!(EscrowEvent::decode(&topics, &v2).unwrap(), Some(swept));
+
+    let split = payload(vec![
+        ("amount", scval::i128(300)),
+        ("child_escrow_id", scval::u64(8)),
+        ("event_version", ScVal::U32(2)),
+        ("metadata", url(b"tier:batch")),
+    ]);
+    assert_eq!(
+        EscrowEvent::decode(&[symbol("split"), scval::u64(7)], &split).unwrap(),
+        Some(EscrowEvent::Split {
+            escrow_id: 7,
+            child_escrow_id: 8,
+            amount: 300,
+        })
+    );
+
+    let token = stellar_strkey::Contract([9; 32]).to_string();
+    let direct = payload(vec![
+        ("amount", scval::i128(300)),
+        ("event_version", ScVal::U32(2)),
+        ("payment_id", scval::u64(4)),
+        (
+            "reference",
+            ScVal::Bytes(ScBytes(vec![7; 32].try_into().unwrap())),
+        ),
+        ("token", scval::address(&token).unwrap()),
+    ]);
+    let topics = [
+        symbol("direct"),
+        scval::address(&account(1)).unwrap(),
+        scval::address(&account(2)).unwrap(),
+    ];
+    assert_eq!(
+        EscrowEvent::decode(&topics, &direct).unwrap(),
+        Some(EscrowEvent::DirectPaid {
+            payment_id: 4,
+            client: account(1),
+            server: account(2),
+            token,
+            amount: 300,
+        })
+    );
+}
+
+#[test]
+fn test_format_timestamp() {
+    assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+    assert_eq!(format_timestamp(951_827_696), "2000-02-29T12:34:56Z");
+    assert_eq!(format_timestamp(1_735_689_599), "2024-12-31T23:59:59Z");
+}
+
+/// RPC answering `getEvents` from a script of pages, then with empty pages
+struct PagedRpc {
+    pages: Mutex<VecDeque<Result<GetEventsResponse, Error>>>,
+    requests: Arc<Mutex<Vec<Value>>>,
+}
+
+#[async_trait]
+impl Transport for PagedRpc {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        assert_eq!(method, "getEvents");
+        self.requests.lock().unwrap().push(params);
+        let page = self.pages.lock().unwrap().pop_front();
+        let page = page.unwrap_or_else(|| {
+            Ok(GetEventsResponse {
+                events: vec![],
+                latest_ledger: 9,
+                cursor: None,
+            })
+        })?;
+        Ok(serde_json::to_value(page).unwrap())
+    }
+}
+
+fn contract_event(ledger: u32, topic: Vec<ScVal>, value: ScVal) -> EventInfo {
+    EventInfo {
+        kind: "contract".into(),
+        ledger,
+        ledger_closed_at: format_timestamp(u64::from(ledger)),
+        contract_id: stellar_strkey::Contract([0; 32]).to_string(),
+        id: format!("{ledger:019}-0000000001"),
+        topic: topic
+            .iter()
+            .map(|topic| topic.to_xdr_base64(Limits::none()).unwrap())
+            .collect(),
+        value: value.to_xdr_base64(Limits::none()).unwrap(),
+        in_successful_contract_call: true,
+        tx_hash: Some("ab".repeat(32)),
+    }
+}
+
+fn symbol(name: &str) -> ScVal {
+    ScVal::Symbol(name.try_into().unwrap())
+}
+
+fn page(events: Vec<EventInfo>) -> Result<GetEventsResponse, Error> {
+    Ok(GetEventsResponse {
+        cursor: events.last().map(|event| event.id.clone()),
+        events,
+        latest_ledger: 9,
+    })
+}
+
+fn paged_client(
+    pages: Vec<Result<GetEventsResponse, Error>>,
+) -> (EscrowClient, Arc<Mutex<Vec<Value>>>) {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let rpc = Rpc::new(PagedRpc {
+        pages: Mutex::new(pages.into()),
+        requests: requests.clone(),
+    });
+    let contract_id = stellar_strkey::Contract([0; 32]).to_string();
+    let client = EscrowClient::new(
+        rpc,
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&[1; 32]),
+    )
+    .unwrap()
+    .with_options(ClientOptions {
+        poll_interval: Duration::from_millis(5),
+        ..ClientOptions::default()
+    });
+    (client, requests)
+}
+
+async fn next_event(
+    events: &mut (impl Stream<Item = Result<Emitted, Error>> + Unpin),
+) -> EscrowEvent {
+    tokio::time::timeout(Duration::from_secs(5), events.next())
+        .await
+        .expect("event within 5s")
+        .unwrap()
+        .unwrap()
+        .event
+}
+
+#[tokio::test]
+async fn test_subscribe_events_pages() {
+    let opened = contract_event(
+        2,
+        vec![
+            symbol("open"),
+            scval::address(&LocalSigner::from_bytes(&[1; 32]).address()).unwrap(),
+            scval::address(&LocalSigner::from_bytes(&[2; 32]).address()).unwrap(),
+        ],
+        scval::u64(0),
+    );
+    let deposited = contract_event(3, vec![symbol("deposit"), scval::u64(0)], scval::i128(500));
+    let deposited_id = deposited.id.clone();
+    let upgraded = contract_event(4, vec![symbol("upgrade")], ScVal::Void);
+    let settled = contract_event(5, vec![symbol("settled"), scval::u64(7)], scval::i128(300));
+    let (client, requests) = paged_client(vec![
+        Err(Error::Transport("connection refused".into())),
+        page(vec![opened.clone(), deposited.clone()]),
+        // Redelivered events are not yielded twice, unknown ones are skipped
+        page(vec![deposited, upgraded, settled.clone()]),
+    ]);
+
+    let mut events = Box::pin(client.subscribe_events(EventFilter::default().from_ledger(2)));
+    assert!(matches!(
+        next_event(&mut events).await,
+        EscrowEvent::Opened { escrow_id: 0, .. }
+    ));
+    assert_eq!(
+        next_event(&mut events).await,
+        EscrowEvent::Deposited {
+            escrow_id: 0,
+            amount: 500
+        }
+    );
+    assert_eq!(
+        next_event(&mut events).await,
+        EscrowEvent::PaymentSettled {
+            payment_id: 7,
+            amount: 300
+        }
+    );
+
+    let requests = requests.lock().unwrap().clone();
+    // The failed poll is retried from the same ledger, then the cursor is followed
+    assert_eq!(requests[0]["startLedger"], 2);
+    assert_eq!(requests[1]["startLedger"], 2);
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[2]["pagination"]["cursor"], deposited_id);
+
+    let (client, _) = paged_client(vec![page(vec![opened, settled])]);
+    let filter = EventFilter::default()
+        .from_ledger(2)
+        .kind(EventKind::PaymentSettled);
+    let mut events = Box::pin(client.subscribe_events(filter));
+    assert!(matches!(
+        next_event(&mut events).await,
+        EscrowEvent::PaymentSettled { payment_id: 7, .. }
+    ));
+}
+
+#[tokio::test]
+async fn test_subscribe_events_sees_settlement() {
+    let s = setup();
+    let server = s.server.clone().with_options(ClientOptions {
+        poll_interval: Duration::from_millis(5),
+        ..ClientOptions::default()
+    });
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 1_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    let filter = EventFilter::default().kind(EventKind::PaymentSettled);
+    let mut settlements = Box::pin(server.subscribe_events(filter));
+    let payment_id = s.server.create_payment(escrow_id, 400).await.unwrap().value;
+    let settled = s.server.settle_payment(payment_id).await.unwrap();
+
+    let emitted = tokio::time::timeout(Duration::from_secs(5), settlements.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        emitted.event,
+        EscrowEvent::PaymentSettled {
+            payment_id,
+            amount: 400
+        }
+    );
+    assert_eq!(emitted.ledger, settled.ledger);
+}
+
+#[test]
+fn test_describe_every_function() {
+    use x402_bindings as b;
+
+    let account = |key: u8| stellar_strkey::ed25519::PublicKey([key; 32]).to_string();
+    let contract = |key: u8| stellar_strkey::Contract([key; 32]).to_string();
+    let (client, server, admin, agent) = (account(1), account(2), account(3), account(4));
+    let (target, oracle, stranger) = (contract(5), contract(6), account(7));
+    let at = |address: &str| scval::parse_address(address).unwrap();
+    let string = |s: &str| ScVal::String(ScString(s.try_into().unwrap()));
+    let bytes = |b: &[u8]| ScVal::Bytes(ScBytes(b.to_vec().try_into().unwrap()));
+    let map = |fields: Vec<(&str, ScVal)>| {
+        let entries: Vec<ScMapEntry> = fields
+            .into_iter()
+            .map(|(key, val)| ScMapEntry {
+                key: symbol(key),
+                val,
+            })
+            .collect();
+        ScVal::Map(Some(ScMap(entries.try_into().unwrap())))
+    };
+    let raw = |function: &'static str, args: Vec<ScVal>| Invocation { function, args };
+    let short = format!("{}…{}", &stranger[..5], &stranger[stranger.len() - 4..]);
+    let hash = "ab".repeat(32);
+    let until = "2026-01-01T00:00:00Z";
+    let expires_at = 1_767_225_600;
+    let authorization = b::authorization(
+        0,
+        "Test SDF Network ; September 2015",
+        1_000_000,
+        1,
+        expires_at,
+    )
+    .unwrap();
+    let summary = map(vec![
+        (
+            "escrow",
+            map(vec![
+                ("balance", scval::i128(20_000_000)),
+                ("client", scval::address(&client).unwrap()),
+                ("server", scval::address(&server).unwrap()),
+            ]),
+        ),
+        ("escrow_id", scval::u64(0)),
+        ("source", scval::address(&target).unwrap()),
+    ]);
+    let (none, onchain, up_to) = (Exposure::None, Exposure::OnChain, Exposure::UpTo);
+
+    let describer = Describer::default()
+        .with_label(&client, "alice")
+        .with_label(&server, "api.example.com")
+        .with_label(&admin, "admin")
+        .with_label(&agent, "agent")
+        .with_label(&target, "v2")
+        .with_label(&oracle, "oracle")
+        .with_escrow(0, &server);
+
+    // (call, title key, title text, exposure, read-only)
+    let cases: Vec<(Invocation, &str, String, Exposure, bool)> = vec![
+        (
+            b::open_escrow(at(&client), at(&server), 15_000_000, None),
+            "escrow.open_escrow",
+            "Open an escrow with api.example.com holding 1.5 XLM".into(),
+            up_to(15_000_000),
+            false,
+        ),
+        (
+            b::clone_escrow_config(0, at(&stranger), 15_000_000),
+            "escrow.clone_escrow_config",
+            format!(
+                "Open an escrow with {short} holding 1.5 XLM, configured as escrow with \
+                 api.example.com"
+            ),
+            up_to(15_000_000),
+            false,
+        ),
+        (
+            b::open_sponsored_escrow(at(&server), at(&client), 15_000_000),
+            "escrow.open_sponsored_escrow",
+            "Sponsor a trial escrow for alice holding 1.5 XLM".into(),
+            up_to(15_000_000),
+            false,
+        ),
+        (
+            b::split_escrow(0, 5_000_000, b"tier:batch").unwrap(),
+            "escrow.split_escrow",
+            "Move 0.5 XLM from escrow with api.example.com to a new escrow".into(),
+            none,
+            false,
+        ),
+        (
+            b::get_escrow_links(0),
+            "escrow.get_escrow_links",
+            "Read the escrows split from or into escrow with api.example.com".into(),
+            none,
+            true,
+        ),
+        (
+            b::deposit(0, 15_000_000),
+            "escrow.deposit",
+            "Deposit 1.5 XLM into escrow with api.example.com".into(),
+            up_to(15_000_000),
+            false,
+        ),
+        (
+            b::claim_pending_deposit(7, at(&client), 5_000_000, [0xab; 32]),
+            "escrow.claim_pending_deposit",
+            "Credit escrow 7 with 0.5 XLM transferred by alice".into(),
+            none,
+            false,
+        ),
+        (
+            b::is_deposit_claimed([0xab; 32]),
+            "escrow.is_deposit_claimed",
+            format!("Check whether transfer {hash} was credited"),
+            none,
+            true,
+        ),
+        (
+            b::record_direct_payment(at(&client), at(&server), at(&oracle), 5_000_000, [1; 32]),
+            "escrow.record_direct_payment",
+            "Pay 0.5 XLM directly to api.example.com".into(),
+            up_to(5_000_000),
+            false,
+        ),
+        (
+            b::create_payment(0, 1_000_000),
+            "escrow.create_payment",
+            "Charge 0.1 XLM from escrow with api.example.com".into(),
+            up_to(1_000_000),
+            false,
+        ),
+        (
+            b::create_payments(vec![
+                b::payment_intent(0, 1_000_000),
+                b::payment_intent(7, 500_000),
+            ])
+            .unwrap(),
+            "escrow.create_payments",
+            "Charge 2 payments totalling 0.15 XLM".into(),
+            up_to(1_500_000),
+            false,
+        ),
+        (
+            b::create_authorized_payment(authorization.clone(), [1; 32], [0; 64]),
+            "escrow.create_authorized_payment",
+            "Charge 0.1 XLM from escrow with api.example.com as authorized by alice".into(),
+            up_to(1_000_000),
+            false,
+        ),
+        (
+            b::open_and_authorize(
+                at(&client),
+                at(&server),
+                15_000_000,
+                None,
+                authorization.clone(),
+                [1; 32],
+                [0; 64],
+            ),
+            "escrow.open_and_authorize",
+            "Open an escrow with api.example.com holding 1.5 XLM, and pay 0.1 XLM from it".into(),
+            up_to(15_000_000),
+            false,
+        ),
+        (
+            b::get_preauthorized_payment(0, 1),
+            "escrow.get_preauthorized_payment",
+            "Read the payment created for nonce 1 of escrow with api.example.com".into(),
+            none,
+            true,
+        ),
+        (
+            raw(
+                "create_payment_usd",
+                vec![scval::u64(0), scval::i128(150), string("/weather")],
+            ),
+            "escrow.create_payment_usd",
+            "Charge 1.50 USD worth from escrow with api.example.com for /weather".into(),
+            onchain,
+            false,
+        ),
+        (
+            b::settle_payment(b::settlement(3, 0, 1_000_000, at(&client))),
+            "escrow.settle_payment",
+            "Settle payment 3 of 0.1 XLM from escrow with api.example.com".into(),
+            onchain,
+            false,
+        ),
+        (
+            b::settle_payments(vec![
+                b::settlement(3, 0, 1_000_000, at(&client)),
+                b::settlement(4, 0, 500_000, at(&client)),
+            ])
+            .unwrap(),
+            "escrow.settle_payments",
+            "Settle 2 payments".into(),
+            onchain,
+            false,
+        ),
+        (
+            b::refund_payment(3, 500_000),
+            "escrow.refund_payment",
+            "Refund 0.05 XLM of payment 3 to its escrow".into(),
+            up_to(500_000),
+            false,
+        ),
+        (
+            b::get_refunded(3),
+            "escrow.get_refunded",
+            "Read the amount refunded of payment 3".into(),
+            none,
+            true,
+        ),
+        (
+            b::client_close_escrow(0),
+            "escrow.client_close_escrow",
+            "Agree to close escrow with api.example.com".into(),
+            onchain,
+            false,
+        ),
+        (
+            b::server_close_escrow(7),
+            "escrow.server_close_escrow",
+            "Agree to close escrow 7".into(),
+            onchain,
+            false,
+        ),
+        (
+            b::consent_to_migration(0, at(&client), at(&target)),
+            "escrow.consent_to_migration",
+            "Agree to move escrow with api.example.com to contract v2".into(),
+            onchain,
+            false,
+        ),
+        (
+            b::export_for_migration(at(&admin), 0),
+            "escrow.export_for_migration",
+            "Export escrow with api.example.com to the contract its parties agreed on".into(),
+            onchain,
+            false,
+        ),
+        (
+            b::post_note(0, at(&client), b"switching payout address on March 1").unwrap(),
+            "escrow.post_note",
+            "Leave a note on escrow with api.example.com: \"switching payout address on March 1\""
+                .into(),
+            none,
+            false,
+        ),
+        (
+            b::get_quote_total(7),
+            "escrow.get_quote_total",
+            "Read the USD equivalent of the payments of escrow 7".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_notes(7),
+            "escrow.get_notes",
+            "Read the notes left on escrow 7".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_migration(0),
+            "escrow.get_migration",
+            "Read the export of escrow with api.example.com".into(),
+            none,
+            true,
+        ),
+        (
+            raw(
+                "complete_migration",
+                vec![scval::u64(7), bytes(&[0; 32])],
+            ),
+            "escrow.complete_migration",
+            "Close escrow 7, moved to another contract".into(),
+            none,
+            false,
+        ),
+        (
+            b::allow_migration_source(at(&admin), at(&target)),
+            "escrow.allow_migration_source",
+            "Accept escrows moved from contract v2".into(),
+            none,
+            false,
+        ),
+        (
+            b::import_escrow(summary, [0; 32]),
+            "escrow.import_escrow",
+            "Import the escrow of alice with api.example.com holding 2 XLM from contract v2"
+                .into(),
+            none,
+            false,
+        ),
+        (
+            b::set_dust_policy(at(&admin), 1_000, 30 * 86_400),
+            "escrow.set_dust_policy",
+            "Let escrows holding less than 0.0001 XLM and idle for 30 days be swept to their clients"
+                .into(),
+            none,
+            false,
+        ),
+        (
+            b::get_dust_policy(),
+            "escrow.get_dust_policy",
+            "Read which escrows may be swept".into(),
+            none,
+            true,
+        ),
+        (
+            b::set_max_escrows_per_client(at(&admin), Some(5)),
+            "escrow.set_max_escrows_per_client",
+            "Allow each client at most 5 open escrows".into(),
+            none,
+            false,
+        ),
+        (
+            b::set_max_escrows_per_client(at(&admin), None),
+            "escrow.remove_max_escrows_per_client",
+            "Remove the cap on open escrows per client".into(),
+            none,
+            false,
+        ),
+        (
+            b::initialize(at(&admin)),
+            "escrow.initialize",
+            "Make admin the contract admin".into(),
+            none,
+            false,
+        ),
+        (
+            b::version(),
+            "escrow.version",
+            "Read the contract version".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_max_escrows_per_client(),
+            "escrow.get_max_escrows_per_client",
+            "Read the cap on open escrows per client".into(),
+            none,
+            true,
+        ),
+        (
+            b::set_clock_skew(at(&admin), 30),
+            "escrow.set_clock_skew",
+            "Accept authorizations up to 30 seconds past their expiry".into(),
+            none,
+            false,
+        ),
+        (
+            b::get_clock_skew(),
+            "escrow.get_clock_skew",
+            "Read the clock skew tolerance".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_client_escrow_count(at(&client)),
+            "escrow.get_client_escrow_count",
+            "Count the open escrows of alice".into(),
+            none,
+            true,
+        ),
+        (
+            b::set_refund_address(at(&client), at(&stranger)),
+            "escrow.set_refund_address",
+            format!("Release dust swept from the escrows of alice to {short}"),
+            none,
+            false,
+        ),
+        (
+            b::get_refund_address(at(&client)),
+            "escrow.get_refund_address",
+            "Read where dust of alice is released to".into(),
+            none,
+            true,
+        ),
+        (
+            b::set_terms(at(&server), [0xab; 32]),
+            "escrow.set_terms",
+            format!("Publish terms of service {} of api.example.com", "ab".repeat(32)),
+            none,
+            false,
+        ),
+        (
+            b::get_terms(at(&server)),
+            "escrow.get_terms",
+            "Read the terms of service of api.example.com".into(),
+            none,
+            true,
+        ),
+        (
+            b::set_notification_url(at(&server), "https://api.example.com/hooks").unwrap(),
+            "escrow.set_notification_url",
+            "Deliver notifications of api.example.com to https://api.example.com/hooks".into(),
+            none,
+            false,
+        ),
+        (
+            b::set_notification_url(at(&server), "").unwrap(),
+            "escrow.remove_notification_url",
+            "Stop delivering notifications of api.example.com".into(),
+            none,
+            false,
+        ),
+        (
+            b::get_notification_url(at(&server)),
+            "escrow.get_notification_url",
+            "Read where notifications of api.example.com are delivered".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_last_activity(7),
+            "escrow.get_last_activity",
+            "Read when escrow 7 was last active".into(),
+            none,
+            true,
+        ),
+        (
+            b::sweep_dust(at(&admin), &[1, 2, 3]).unwrap(),
+            "escrow.sweep_dust",
+            "Sweep 3 abandoned escrows to their clients".into(),
+            onchain,
+            false,
+        ),
+        (
+            b::grant_agent(at(&client), at(&agent), 10_000_000, 1_000_000, expires_at),
+            "escrow.grant_agent",
+            format!("Authorize agent to charge up to 0.1 XLM per payment until {until}"),
+            up_to(10_000_000),
+            false,
+        ),
+        (
+            b::revoke_agent(at(&client), at(&agent)),
+            "escrow.revoke_agent",
+            "Revoke the allowance of agent from alice".into(),
+            none,
+            false,
+        ),
+        (
+            b::get_allowance(at(&client), at(&agent)),
+            "escrow.get_allowance",
+            "Read the allowance of agent from alice".into(),
+            none,
+            true,
+        ),
+        (
+            b::set_max_settled_per_hour(0, Some(50_000_000)),
+            "escrow.set_max_settled_per_hour",
+            "Let at most 5 XLM be settled per hour from escrow with api.example.com".into(),
+            none,
+            false,
+        ),
+        (
+            b::set_max_settled_per_hour(7, None),
+            "escrow.remove_max_settled_per_hour",
+            "Remove the cap on settlements per hour from escrow 7".into(),
+            none,
+            false,
+        ),
+        (
+            b::acknowledge_anomaly(0),
+            "escrow.acknowledge_anomaly",
+            "Resume settlements from escrow with api.example.com paused by its cap".into(),
+            none,
+            false,
+        ),
+        (
+            b::get_spend_limit(7),
+            "escrow.get_spend_limit",
+            "Read the cap on settlements per hour from escrow 7".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_escrow_balance(0),
+            "escrow.get_escrow_balance",
+            "Read the balance of escrow with api.example.com".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_escrow(7),
+            "escrow.get_escrow",
+            "Read escrow 7".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_payment(3),
+            "escrow.get_payment",
+            "Read payment 3".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_payments(0, None, 0, 10),
+            "escrow.get_payments",
+            "List the payments of escrow with api.example.com".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_direct_payments(at(&server), None, 0, 10),
+            "escrow.get_direct_payments",
+            "List the direct payments to api.example.com".into(),
+            none,
+            true,
+        ),
+        (
+            b::export_escrows(Some(at(&server)), 0, 10),
+            "escrow.export_escrows",
+            "List the open escrows with api.example.com".into(),
+            none,
+            true,
+        ),
+        (
+            b::export_escrows(None, 0, 10),
+            "escrow.export_all_escrows",
+            "List the open escrows".into(),
+            none,
+            true,
+        ),
+        (
+            b::find_escrow(at(&client), at(&server)),
+            "escrow.find_escrow",
+            "Look up the escrow of alice with api.example.com".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_escrow_index(at(&client), false, 0),
+            "escrow.get_escrow_index",
+            "List the open escrows of alice".into(),
+            none,
+            true,
+        ),
+        (
+            b::get_escrow_index(at(&client), true, 2),
+            "escrow.get_archived_escrows",
+            "List the closed escrows of alice".into(),
+            none,
+            true,
+        ),
+        (
+            b::compact_escrow_index(at(&client), 0, 10),
+            "escrow.compact_escrow_index",
+            "Drop closed escrows from the index of alice".into(),
+            none,
+            false,
+        ),
+        (
+            raw(
+                "verify_authorization",
+                vec![
+                    authorization,
+                    bytes(&[1; 32]),
+                    bytes(&[0; 64]),
+                ],
+            ),
+            "escrow.verify_authorization",
+            "Check an authorization to charge 0.1 XLM from escrow with api.example.com".into(),
+            none,
+            true,
+        ),
+        (
+            raw(
+                "verify_voucher",
+                vec![
+                    map(vec![
+                        ("amount", scval::i128(3_000_000)),
+                        ("escrow_id", scval::u64(7)),
+                        ("expires_at", scval::u64(expires_at)),
+                        ("sequence", scval::u64(2)),
+                    ]),
+                    bytes(&[1; 32]),
+                    bytes(&[0; 64]),
+                ],
+            ),
+            "escrow.verify_voucher",
+            "Check a voucher for 0.3 XLM in total from escrow 7".into(),
+            none,
+            true,
+        ),
+        (
+            raw(
+                "verify_channel_state",
+                vec![
+                    map(vec![
+                        ("balance", scval::i128(7_000_000)),
+                        ("escrow_id", scval::u64(7)),
+                        ("sequence", scval::u64(2)),
+                        ("settled", scval::i128(3_000_000)),
+                    ]),
+                    bytes(&[1; 32]),
+                    bytes(&[0; 64]),
+                ],
+            ),
+            "escrow.verify_channel_state",
+            "Check a state of escrow 7 leaving 0.7 XLM to the client".into(),
+            none,
+            true,
+        ),
+        (
+            raw(
+                "set_price_oracle",
+                vec![
+                    scval::address(&admin).unwrap(),
+                    scval::address(&oracle).unwrap(),
+                    scval::vec(vec![symbol("Other"), symbol("XLM")]).unwrap(),
+                ],
+            ),
+            "escrow.set_price_oracle",
+            "Convert USD prices with feed oracle quoting XLM".into(),
+            none,
+            false,
+        ),
+        (
+            raw(
+                "set_price_limits",
+                vec![
+                    scval::address(&admin).unwrap(),
+                    scval::u64(300),
+                    ScVal::U32(250),
+                ],
+            ),
+            "escrow.set_price_limits",
+            "Reject prices older than 300 seconds or deviating more than 2.50% from their average"
+                .into(),
+            none,
+            false,
+        ),
+        (
+            raw("get_price_oracle", vec![]),
+            "escrow.get_price_oracle",
+            "Read the price feed".into(),
+            none,
+            true,
+        ),
+        (
+            raw(
+                "set_usd_price",
+                vec![
+                    scval::address(&server).unwrap(),
+                    string("/weather"),
+                    scval::i128(150),
+                ],
+            ),
+            "escrow.set_usd_price",
+            "Price /weather at 1.50 USD for api.example.com".into(),
+            none,
+            false,
+        ),
+        (
+            raw(
+                "quote_usd",
+                vec![scval::address(&server).unwrap(), string("/weather")],
+            ),
+            "escrow.quote_usd",
+            "Quote the price of /weather at api.example.com".into(),
+            none,
+            true,
+        ),
+        (
+            raw(
+                "suspend_price",
+                vec![scval::address(&server).unwrap(), string("/weather")],
+            ),
+            "escrow.suspend_price",
+            "Suspend the sale of /weather at api.example.com".into(),
+            none,
+            false,
+        ),
+        (
+            raw(
+                "resume_price",
+                vec![scval::address(&server).unwrap(), string("/weather")],
+            ),
+            "escrow.resume_price",
+            "Resume the sale of /weather at api.example.com".into(),
+            none,
+            false,
+        ),
+        (
+            raw(
+                "quote",
+                vec![scval::address(&server).unwrap(), string("/weather")],
+            ),
+            "escrow.quote",
+            "Quote the price of /weather at api.example.com, and whether it is on sale".into(),
+            none,
+            true,
+        ),
+        (
+            raw(
+                "set_promo",
+                vec![
+                    scval::address(&server).unwrap(),
+                    string("/weather"),
+                    scval::i128(25),
+                    scval::u64(expires_at - 86_400),
+                    scval::u64(expires_at),
+                ],
+            ),
+            "escrow.set_promo",
+            format!("Discount /weather to 0.25 USD from 2025-12-31T00:00:00Z until {until}"),
+            none,
+            false,
+        ),
+        (
+            raw(
+                "get_promos",
+                vec![scval::address(&server).unwrap(), string("/weather")],
+            ),
+            "escrow.get_promos",
+            "List the promos of /weather at api.example.com".into(),
+            none,
+            true,
+        ),
+    ];
+
+    for (invocation, key, text, exposure, read_only) in cases {
+        let function = invocation.function;
+        let summary = describer.describe_invocation(&invocation).unwrap();
+        assert_eq!(summary.function, function);
+        assert_eq!(summary.contract, None);
+        assert_eq!(
+            (summary.title.key, summary.title.text),
+            (key, text),
+            "{function}"
+        );
+        assert_eq!(summary.exposure, exposure, "{function}");
+        assert_eq!(summary.read_only, read_only, "{function}");
+    }
+
+    // Details carry the limits worth confirming, with their raw values
+    let grant = describer
+        .describe_invocation(&b::grant_agent(
+            at(&client),
+            at(&agent),
+            10_000_000,
+            1_000_000,
+            expires_at,
+        ))
+        .unwrap();
+    let texts: Vec<&str> = grant
+        .details
+        .iter()
+        .map(|fact| fact.text.as_str())
+        .collect();
+    assert_eq!(texts, ["Up to 1 XLM in total", "Paid from alice"]);
+    assert_eq!(
+        grant.title.arg("expires_at"),
+        Some(&FactValue::Timestamp(expires_at))
+    );
+    assert_eq!(grant.details[0].key, "escrow.detail.total_cap");
+    let open = describer
+        .describe_invocation(&b::open_escrow(
+            at(&client),
+            at(&server),
+            15_000_000,
+            Some([0xab; 32]),
+        ))
+        .unwrap();
+    assert_eq!(open.details[1].key, "escrow.detail.terms");
+    assert_eq!(
+        open.details[1].text,
+        format!("Accepts terms of service {}", "ab".repeat(32))
+    );
+
+    // Unknown parties and assets
+    let plain = Describer::default().with_asset("USDC");
+    let deposit = plain
+        .describe_invocation(&b::deposit(0, 15_000_000))
+        .unwrap();
+    assert_eq!(deposit.title.text, "Deposit 1.5 USDC into escrow 0");
+    let find = plain
+        .describe_invocation(&b::find_escrow(at(&stranger), at(&stranger)))
+        .unwrap();
+    assert_eq!(
+        find.title.text,
+        format!("Look up the escrow of {short} with {short}")
+    );
+
+    // Calls outside the escrow interface or with mistyped arguments
+    assert!(matches!(
+        plain.describe_invocation(&raw("transfer", vec![])),
+        Err(Error::NotDescribable(_))
+    ));
+    assert!(matches!(
+        plain.describe_invocation(&raw("deposit", vec![scval::u64(0)])),
+        Err(Error::NotDescribable(_))
+    ));
+    assert!(matches!(
+        plain.describe_invocation(&raw("deposit", vec![scval::u64(0), scval::u64(1)])),
+        Err(Error::NotDescribable(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_describe_prepared_transaction() {
+    let s = setup();
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    let prepared = s
+        .client
+        .prepare_create_payment(escrow_id, 2_500_000)
+        .await
+        .unwrap();
+    let summary = Describer::default()
+        .with_label(&s.server_addr, "api.example.com")
+        .with_escrow(escrow_id, &s.server_addr)
+        .describe(&prepared)
+        .unwrap();
+    assert_eq!(summary.contract, Some(s.client.contract_id()));
+    assert_eq!(summary.function, "create_payment");
+    assert_eq!(
+        summary.title.text,
+        "Charge 0.25 XLM from escrow with api.example.com"
+    );
+    assert_eq!(summary.exposure, Exposure::UpTo(2_500_000));
+    assert_eq!(
+        crate::describe(&prepared).unwrap().title.text,
+        format!("Charge 0.25 XLM from escrow {escrow_id}")
+    );
+
+    let garbled = PreparedTransaction {
+        envelope: "AAAA".into(),
+        hash: prepared.hash,
+    };
+    assert!(crate::describe(&garbled).is_err());
+}
+
+/// How a [`MockEndpoint`] answers
+#[derive(Clone, Copy, Debug)]
+enum Behavior {
+    /// Answers for this ledger
+    Answer(u32),
+    /// Never answers
+    Hang,
+    /// Answers `429 Too Many Requests`, asking to wait this long
+    RateLimit(Duration),
+}
+
+/// RPC endpoint answering with its name and ledger, as its behavior says
+#[derive(Clone)]
+struct MockEndpoint {
+    name: &'static str,
+    behavior: Arc<Mutex<Behavior>>,
+    requests: Arc<AtomicUsize>,
+}
+
+impl MockEndpoint {
+    fn new(name: &'static str, behavior: Behavior) -> Self {
+        Self {
+            name,
+            behavior: Arc::new(Mutex::new(behavior)),
+            requests: Arc::default(),
+        }
+    }
+
+    fn set(&self, behavior: Behavior) {
+        *self.behavior.lock().unwrap() = behavior;
+    }
+
+    fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl Transport for MockEndpoint {
+    async fn request(&self, _method: &str, _params: Value) -> Result<Value, Error> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let behavior = *self.behavior.lock().unwrap();
+        match behavior {
+            Behavior::Answer(ledger) => {
+                Ok(json!({ "endpoint": self.name, "latestLedger": ledger }))
+            }
+            Behavior::Hang => std::future::pending().await,
+            Behavior::RateLimit(retry_after) => Err(Error::RateLimited {
+                retry_after: Some(retry_after),
+            }),
+        }
+    }
+}
+
+/// Pool of `endpoints`, answering within 50ms
+fn pool(endpoints: &[&MockEndpoint]) -> EndpointPool {
+    let options = PoolOptions {
+        request_timeout: Duration::from_millis(50),
+        ..PoolOptions::default()
+    };
+    endpoints
+        .iter()
+        .fold(EndpointPool::new(options), |pool, endpoint| {
+            pool.with_endpoint(endpoint.name, (*endpoint).clone())
+        })
+}
+
+/// Name of the endpoint that answered
+async fn answered_by(pool: &EndpointPool, method: &str) -> String {
+    let answer = pool.request(method, Value::Null).await.unwrap();
+    answer["endpoint"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_endpoint_pool_failover() {
+    let a = MockEndpoint::new("a", Behavior::Hang);
+    let b = MockEndpoint::new("b", Behavior::Answer(100));
+    let pool = pool(&[&a, &b]);
+
+    // Reads move past an endpoint timing out, then prefer the healthy one
+    assert_eq!(answered_by(&pool, "getLedgerEntries").await, "b");
+    assert_eq!(answered_by(&pool, "simulateTransaction").await, "b");
+    assert_eq!((a.requests(), b.requests()), (1, 2));
+    let metrics = pool.metrics();
+    assert_eq!(metrics.failovers, 1);
+    assert_eq!(metrics.endpoints[0].failures, 1);
+    assert!(metrics.endpoints[0].health < metrics.endpoints[1].health);
+
+    // Submissions stay on the primary until it fails
+    assert_eq!(pool.metrics().primary, 0);
+    assert_eq!(answered_by(&pool, "sendTransaction").await, "b");
+    assert_eq!(pool.metrics().primary, 1);
+    a.set(Behavior::Answer(100));
+    for _ in 0..10 {
+        assert_eq!(answered_by(&pool, "getEvents").await, "b");
+    }
+    assert_eq!(answered_by(&pool, "sendTransaction").await, "b");
+    assert_eq!(a.requests(), 2);
+
+    // Once every endpoint fails, so does the request
+    a.set(Behavior::Hang);
+    b.set(Behavior::Hang);
+    let err = pool
+        .request("getLatestLedger", Value::Null)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Transport(_)), "{err}");
+    assert!(matches!(
+        EndpointPool::default()
+            .request("getNetwork", Value::Null)
+            .await,
+        Err(Error::Transport(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_endpoint_pool_rate_limits() {
+    let a = MockEndpoint::new("a", Behavior::RateLimit(Duration::from_millis(100)));
+    let b = MockEndpoint::new("b", Behavior::Answer(100));
+    let pool = pool(&[&a, &b]);
+
+    // A rate-limited endpoint is skipped while its Retry-After lasts
+    assert_eq!(answered_by(&pool, "getEvents").await, "b");
+    a.set(Behavior::Answer(100));
+    assert_eq!(answered_by(&pool, "getEvents").await, "b");
+    assert_eq!(a.requests(), 1);
+    let metrics = pool.metrics();
+    assert_eq!(metrics.endpoints[0].rate_limited, 1);
+    assert_eq!(metrics.endpoints[0].failures, 0);
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(answered_by(&pool, "getEvents").await, "a");
+
+    // With every endpoint rate limited, requests fail rather than wait
+    a.set(Behavior::RateLimit(Duration::from_secs(5)));
+    b.set(Behavior::RateLimit(Duration::from_secs(2)));
+    let err = pool.request("getEvents", Value::Null).await.unwrap_err();
+    assert!(err.is_transient());
+    let Err(Error::RateLimited {
+        retry_after: Some(retry_after),
+    }) = pool.request("getEvents", Value::Null).await
+    else {
+        panic!("expected to be rate limited");
+    };
+    assert!(retry_after <= Duration::from_secs(2));
+    assert_eq!((a.requests(), b.requests()), (3, 3));
+}
+
+#[tokio::test]
+async fn test_endpoint_pool_stale_ledgers() {
+    let a = MockEndpoint::new("a", Behavior::Answer(200));
+    let b = MockEndpoint::new("b", Behavior::Answer(200));
+    let pool = pool(&[&a, &b]);
+    assert_eq!(answered_by(&pool, "getLatestLedger").await, "a");
+    assert_eq!(pool.metrics().latest_ledger, 200);
+
+    // An endpoint falling behind answers stale reads, served by the other
+    a.set(Behavior::Answer(100));
+    assert_eq!(answered_by(&pool, "getLedgerEntries").await, "b");
+    let metrics = pool.metrics();
+    assert_eq!(metrics.endpoints[0].stale, 1);
+    assert_eq!(metrics.failovers, 1);
+
+    // Lagging less than the tolerance is not stale
+    b.set(Behavior::Answer(197));
+    assert_eq!(answered_by(&pool, "getLedgerEntries").await, "b");
+
+    // When every endpoint is behind, the freshest answer is returned
+    a.set(Behavior::Answer(120));
+    b.set(Behavior::Answer(150));
+    assert_eq!(answered_by(&pool, "getEvents").await, "b");
+    let metrics = pool.metrics();
+    assert_eq!(metrics.endpoints[0].stale, 2);
+    assert_eq!(metrics.endpoints[1].stale, 1);
+    assert_eq!(metrics.latest_ledger, 200);
+}
+
+#[tokio::test]
+async fn test_http_transport_rate_limited() {
+    let app = Router::new().route(
+        "/",
+        post(|| async { (StatusCode::TOO_MANY_REQUESTS, [("retry-after", "7")]) }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let err = HttpTransport::new(url)
+        .request("getLatestLedger", Value::Null)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            Error::RateLimited {
+                retry_after: Some(retry_after)
+            } if retry_after == Duration::from_secs(7)
+        ),
+        "{err}"
+    );
+}
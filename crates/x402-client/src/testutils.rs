@@ -0,0 +1,749 @@
+//! In-process Soroban test environment exposed as an RPC [`Transport`]
+//!
+//! [`EnvTransport`] answers the RPC methods used by the SDK by executing
+//! against a `soroban_sdk::Env` with all auths mocked. The `Env` lives on a
+//! dedicated thread because it is neither `Send` nor `Sync`.
+//!
+//! Authorization entries with address credentials that transactions carry
+//! are checked like the network would, though calls lacking one are not
+//! refused.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    sync::mpsc,
+    thread,
+};
+
+use async_trait::async_trait;
+use ed25519_dalek::{Signature as Ed25519Signature, Verifier, VerifyingKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use soroban_sdk::{
+    contract, contractimpl,
+    testutils::{Events as _, Ledger as _},
+    Address, Env, Symbol, TryFromVal, Val,
+};
+use stellar_strkey::Strkey;
+use stellar_xdr::curr::{
+    AccountEntry, AccountEntryExt, AccountId, ContractDataDurability, DecoratedSignature,
+    ExtensionPoint, FeeBumpTransactionInnerTx, HostFunction, LedgerEntryData, LedgerFootprint,
+    LedgerKey, LedgerKeyContractData, Limits, MuxedAccount, OperationBody, PublicKey, ReadXdr,
+    ScAddress, ScVal, SequenceNumber, SorobanAuthorizedFunction, SorobanCredentials,
+    SorobanResources, SorobanTransactionData, SorobanTransactionMeta, SorobanTransactionMetaExt,
+    Thresholds, Transaction, TransactionEnvelope, TransactionMeta, TransactionMetaV3,
+    TransactionResult, TransactionResultExt, TransactionResultResult, TransactionSignaturePayload,
+    TransactionSignaturePayloadTaggedTransaction, TransactionV1Envelope, Uint256, WriteXdr,
+};
+use tokio::sync::oneshot;
+
+pub use crate::format_timestamp;
+use crate::{rpc::Transport, scval, AuthorizationEntry, Error};
+
+/// Passphrase reported by [`EnvTransport`] and used to verify signatures
+pub const NETWORK_PASSPHRASE: &str = "Standalone Network ; February 2017";
+
+/// Minimum resource fee reported by simulations, in stroops
+pub const MIN_RESOURCE_FEE: u32 = 100;
+
+type Job = Box<dyn FnOnce(&mut EnvState) + Send>;
+
+/// RPC transport backed by an in-process Soroban test environment
+///
+/// Clones share the environment.
+#[derive(Clone)]
+pub struct EnvTransport {
+    jobs: mpsc::Sender<Job>,
+    contract_id: String,
+}
+
+impl EnvTransport {
+    /// Start a test environment and register the contract under test
+    ///
+    /// # Arguments
+    /// * `register` - Registers the contract and returns its address
+    pub fn new<F>(register: F) -> Self
+    where
+        F: FnOnce(&Env) -> Address + Send + 'static,
+    {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let (ready, contract_id) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut state = EnvState::new(register);
+            let _ = ready.send(scval::format_address(&ScAddress::from(&state.contract)));
+            // Runs until every transport handle has been dropped
+            while let Ok(job) = receiver.recv() {
+                job(&mut state);
+            }
+        });
+
+        Self {
+            jobs,
+            contract_id: contract_id.recv().expect("test environment started"),
+        }
+    }
+
+    /// Address of the contract under test (C... format)
+    pub fn contract_id(&self) -> &str {
+        &self.contract_id
+    }
+
+    /// Accept signatures of `signer` for transactions of `account`, as if
+    /// it was added as a signer of the account
+    ///
+    /// Accounts start with their own key as only signer.
+    pub fn add_signer(&self, account: &str, signer: &str) {
+        let (account, signer) = (account_key(account), account_key(signer));
+        self.with_state(move |state| {
+            state.signers_of(account).insert(signer);
+        });
+    }
+
+    /// Stop accepting signatures of `signer` for transactions of `account`,
+    /// its own key included
+    pub fn remove_signer(&self, account: &str, signer: &str) {
+        let (account, signer) = (account_key(account), account_key(signer));
+        self.with_state(move |state| {
+            state.signers_of(account).remove(&signer);
+        });
+    }
+
+    fn with_state<F>(&self, f: F)
+    where
+        F: FnOnce(&mut EnvState) + Send + 'static,
+    {
+        let (reply, done) = mpsc::channel();
+        self.jobs
+            .send(Box::new(move |state| {
+                f(state);
+                let _ = reply.send(());
+            }))
+            .expect("test environment running");
+        done.recv().expect("test environment running");
+    }
+
+    /// Register a token contract, standing in for a Stellar asset, and
+    /// return its address (C... format)
+    ///
+    /// Unlike a Stellar asset contract, it holds balances of accounts
+    /// without trustlines.
+    pub fn register_token(&self) -> String {
+        self.with_env(|env| scval::format_address(&ScAddress::from(&env.register(TestToken, ()))))
+    }
+
+    /// Credit `amount` of a token registered by [`Self::register_token`]
+    /// to `holder`
+    pub fn mint(&self, token: &str, holder: &str, amount: i128) {
+        let (token, holder) = (token.to_string(), holder.to_string());
+        self.with_env(move |env| {
+            TestTokenClient::new(env, &address(env, &token)).mint(&address(env, &holder), &amount)
+        })
+    }
+
+    /// Balance of `holder` in a token registered by [`Self::register_token`]
+    pub fn token_balance(&self, token: &str, holder: &str) -> i128 {
+        let (token, holder) = (token.to_string(), holder.to_string());
+        self.with_env(move |env| {
+            TestTokenClient::new(env, &address(env, &token)).balance(&address(env, &holder))
+        })
+    }
+
+    /// Run a closure against the test environment and return its result
+    pub fn with_env<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&Env) -> R + Send + 'static,
+    {
+        let (reply, result) = mpsc::channel();
+        self.jobs
+            .send(Box::new(move |state| {
+                let _ = reply.send(f(&state.env));
+            }))
+            .expect("test environment running");
+        result.recv().expect("test environment running")
+    }
+}
+
+#[async_trait]
+impl Transport for EnvTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let (reply, result) = oneshot::channel();
+        let method = method.to_string();
+        self.jobs
+            .send(Box::new(move |state| {
+                let _ = reply.send(state.handle(&method, params));
+            }))
+            .map_err(|_| Error::Transport("test environment stopped".into()))?;
+        result
+            .await
+            .map_err(|_| Error::Transport("test environment stopped".into()))?
+    }
+}
+
+thread_local! {
+    static SIMULATED: RefCell<Option<Result<ScVal, String>>> = const { RefCell::new(None) };
+}
+
+/// Runs an invocation, records its outcome, then fails so the host rolls
+/// back every state change it made
+#[contract]
+struct Simulator;
+
+#[contractimpl]
+impl Simulator {
+    pub fn simulate(env: Env, contract: Address, function: Symbol, args: soroban_sdk::Vec<Val>) {
+        let outcome = invoke(&env, &contract, &function, args);
+        SIMULATED.with(|cell| *cell.borrow_mut() = Some(outcome));
+        panic!("rolling back simulated invocation");
+    }
+}
+
+/// Token holding balances of any address, with the transfer signature of
+/// the token interface
+#[contract]
+struct TestToken;
+
+#[contractimpl]
+impl TestToken {
+    pub fn mint(env: Env, to: Address, amount: i128) {
+        let balance = Self::balance(env.clone(), to.clone());
+        env.storage().persistent().set(&to, &(balance + amount));
+    }
+
+    pub fn balance(env: Env, id: Address) -> i128 {
+        env.storage().persistent().get(&id).unwrap_or(0)
+    }
+
+    pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+        from.require_auth();
+        let balance = Self::balance(env.clone(), from.clone());
+        if amount < 0 || balance < amount {
+            panic!("insufficient balance");
+        }
+        env.storage().persistent().set(&from, &(balance - amount));
+        Self::mint(env, to, amount);
+    }
+}
+
+fn invoke(
+    env: &Env,
+    contract: &Address,
+    function: &Symbol,
+    args: soroban_sdk::Vec<Val>,
+) -> Result<ScVal, String> {
+    match env.try_invoke_contract::<Val, soroban_sdk::Error>(contract, function, args) {
+        Ok(Ok(value)) => ScVal::try_from_val(env, &value).map_err(|e| format!("{e:?}")),
+        Ok(Err(e)) => Err(format!("HostError: {e:?}")),
+        Err(Ok(e)) => Err(format!("HostError: {e:?}")),
+        Err(Err(soroban_sdk::InvokeError::Contract(code))) => {
+            Err(format!("HostError: Error(Contract, #{code})"))
+        }
+        Err(Err(soroban_sdk::InvokeError::Abort)) => {
+            Err("HostError: Error(WasmVm, InvalidAction)".into())
+        }
+    }
+}
+
+struct EnvState {
+    env: Env,
+    contract: Address,
+    simulator: Address,
+    network_id: [u8; 32],
+    sequences: HashMap<[u8; 32], i64>,
+    /// Nonces of the authorization entries used, by account
+    nonces: HashSet<([u8; 32], i64)>,
+    /// Signers of the accounts whose signers changed
+    signers: HashMap<[u8; 32], HashSet<[u8; 32]>>,
+    transactions: HashMap<String, Value>,
+    events: Vec<Value>,
+}
+
+impl EnvState {
+    fn new<F: FnOnce(&Env) -> Address>(register: F) -> Self {
+        let env = Env::default();
+        env.mock_all_auths_allowing_non_root_auth();
+        // Contracts see the network the transport reports
+        let network_id: [u8; 32] = Sha256::digest(NETWORK_PASSPHRASE.as_bytes()).into();
+        env.ledger()
+            .with_mut(|ledger| ledger.network_id = network_id);
+        let contract = register(&env);
+        let simulator = env.register(Simulator, ());
+        Self {
+            env,
+            contract,
+            simulator,
+            network_id,
+            sequences: HashMap::new(),
+            nonces: HashSet::new(),
+            signers: HashMap::new(),
+            transactions: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Signers of an account, its own key until they change
+    fn signers_of(&mut self, account: [u8; 32]) -> &mut HashSet<[u8; 32]> {
+        self.signers
+            .entry(account)
+            .or_insert_with(|| HashSet::from([account]))
+    }
+
+    /// Whether one of `signatures` is by a signer of `account` over `hash`
+    fn signed(
+        &mut self,
+        account: [u8; 32],
+        hash: &[u8; 32],
+        signatures: &[DecoratedSignature],
+    ) -> bool {
+        self.signers_of(account)
+            .iter()
+            .any(|signer| signed(signer, hash, signatures))
+    }
+
+    fn latest_ledger(&self) -> u32 {
+        self.env.ledger().sequence()
+    }
+
+    fn handle(&mut self, method: &str, params: Value) -> Result<Value, Error> {
+        match method {
+            "getNetwork" => Ok(json!({
+                "passphrase": NETWORK_PASSPHRASE,
+                "protocolVersion": 22,
+            })),
+            "getLatestLedger" => Ok(json!({
+                "id": hex::encode(self.latest_ledger().to_be_bytes()),
+                "protocolVersion": 22,
+                "sequence": self.latest_ledger(),
+                "closeTime": self.env.ledger().timestamp().to_string(),
+            })),
+            "getLedgerEntries" => self.get_ledger_entries(&params),
+            "getEvents" => self.get_events(&params),
+            "simulateTransaction" => self.simulate_transaction(&params),
+            "sendTransaction" => self.send_transaction(&params),
+            "getTransaction" => Ok(self
+                .transactions
+                .get(params["hash"].as_str().unwrap_or_default())
+                .cloned()
+                .unwrap_or_else(
+                    || json!({ "status": "NOT_FOUND", "latestLedger": self.latest_ledger() }),
+                )),
+            other => Err(Error::Rpc {
+                code: -32601,
+                message: format!("method not found: {other}"),
+            }),
+        }
+    }
+
+    /// Every ed25519 account exists, with its sequence tracked locally
+    fn get_ledger_entries(&mut self, params: &Value) -> Result<Value, Error> {
+        let mut entries = vec![];
+        for key in params["keys"].as_array().into_iter().flatten() {
+            let key_xdr = key.as_str().unwrap_or_default();
+            if let LedgerKey::Account(account) =
+                LedgerKey::from_xdr_base64(key_xdr, Limits::none())?
+            {
+                let AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(public_key))) =
+                    account.account_id.clone();
+                let sequence = *self.sequences.entry(public_key).or_default();
+                let entry = LedgerEntryData::Account(AccountEntry {
+                    account_id: account.account_id,
+                    balance: 10_000_000_000,
+                    seq_num: SequenceNumber(sequence),
+                    num_sub_entries: 0,
+                    inflation_dest: None,
+                    flags: 0,
+                    home_domain: Default::default(),
+                    thresholds: Thresholds([1, 0, 0, 0]),
+                    signers: Default::default(),
+                    ext: AccountEntryExt::V0,
+                });
+                entries.push(json!({
+                    "key": key_xdr,
+                    "xdr": entry.to_xdr_base64(Limits::none())?,
+                    "lastModifiedLedgerSeq": self.latest_ledger(),
+                }));
+            }
+        }
+        Ok(json!({ "entries": entries, "latestLedger": self.latest_ledger() }))
+    }
+
+    fn simulate_transaction(&mut self, params: &Value) -> Result<Value, Error> {
+        let envelope = decode_envelope(params)?;
+        let (tx, _) = transaction(&envelope)?;
+        let (contract, function, args) = self.invocation(tx)?;
+
+        SIMULATED.with(|cell| cell.borrow_mut().take());
+        let simulator_args = soroban_sdk::vec![
+            &self.env,
+            contract.to_val(),
+            function.to_val(),
+            args.to_val(),
+        ];
+        let _ = self.env.try_invoke_contract::<Val, soroban_sdk::Error>(
+            &self.simulator,
+            &Symbol::new(&self.env, "simulate"),
+            simulator_args,
+        );
+        let outcome = SIMULATED
+            .with(|cell| cell.borrow_mut().take())
+            .unwrap_or_else(|| Err("simulation did not run".into()));
+        let data = self.metered_transaction_data(&contract);
+
+        Ok(match outcome {
+            Ok(value) => json!({
+                "latestLedger": self.latest_ledger(),
+                "minResourceFee": MIN_RESOURCE_FEE.to_string(),
+                "transactionData": data.to_xdr_base64(Limits::none())?,
+                "results": [{ "auth": [], "xdr": value.to_xdr_base64(Limits::none())? }],
+            }),
+            Err(error) => json!({ "latestLedger": self.latest_ledger(), "error": error }),
+        })
+    }
+
+    /// Resources metered during the last invocation, with the invoked
+    /// contract's instance as the footprint
+    fn metered_transaction_data(&self, contract: &Address) -> SorobanTransactionData {
+        let resources = self.env.cost_estimate().resources();
+        let instance = LedgerKey::ContractData(LedgerKeyContractData {
+            contract: ScAddress::from(contract),
+            key: ScVal::LedgerKeyContractInstance,
+            durability: ContractDataDurability::Persistent,
+        });
+        let (read_only, read_write) = if resources.write_entries > 0 {
+            (vec![], vec![instance])
+        } else {
+            (vec![instance], vec![])
+        };
+        SorobanTransactionData {
+            ext: ExtensionPoint::V0,
+            resources: SorobanResources {
+                footprint: LedgerFootprint {
+                    read_only: read_only.try_into().unwrap_or_default(),
+                    read_write: read_write.try_into().unwrap_or_default(),
+                },
+                instructions: resources.instructions.try_into().unwrap_or(u32::MAX),
+                read_bytes: resources.read_bytes,
+                write_bytes: resources.write_bytes,
+            },
+            resource_fee: MIN_RESOURCE_FEE.into(),
+        }
+    }
+
+    /// Apply a transaction, or the transaction a fee bump wraps, recording
+    /// it under the hash of the envelope
+    fn send_transaction(&mut self, params: &Value) -> Result<Value, Error> {
+        let envelope = decode_envelope(params)?;
+        let (tx, signatures) = transaction(&envelope)?;
+        let hash = self.hash(TransactionSignaturePayloadTaggedTransaction::Tx(tx.clone()))?;
+        let outer = match &envelope {
+            TransactionEnvelope::TxFeeBump(bump) => self.hash(
+                TransactionSignaturePayloadTaggedTransaction::TxFeeBump(bump.tx.clone()),
+            )?,
+            _ => hash,
+        };
+        let hash_hex = hex::encode(outer);
+
+        let MuxedAccount::Ed25519(Uint256(source)) = tx.source_account else {
+            return Err(Error::InvalidResponse("expected an ed25519 source".into()));
+        };
+
+        // Reject like stellar-core would: bad sequence first, then bad auth
+        let current = *self.sequences.entry(source).or_default();
+        if tx.seq_num.0 != current + 1 {
+            return self.reject(&hash_hex, TransactionResultResult::TxBadSeq);
+        }
+        if !self.signed(source, &hash, signatures) {
+            return self.reject(&hash_hex, TransactionResultResult::TxBadAuth);
+        }
+        if let TransactionEnvelope::TxFeeBump(bump) = &envelope {
+            let MuxedAccount::Ed25519(Uint256(fee_source)) = bump.tx.fee_source else {
+                return Err(Error::InvalidResponse(
+                    "expected an ed25519 fee source".into(),
+                ));
+            };
+            if bump.tx.fee < i64::from(tx.fee) {
+                return self.reject(&hash_hex, TransactionResultResult::TxInsufficientFee);
+            }
+            if !self.signed(fee_source, &outer, &bump.signatures) {
+                return self.reject(&hash_hex, TransactionResultResult::TxBadAuth);
+            }
+        }
+
+        self.sequences.insert(source, current + 1);
+        let (contract, function, args) = self.invocation(tx)?;
+        let outcome = match self.authorized(tx) {
+            Some(nonces) => {
+                let outcome = invoke(&self.env, &contract, &function, args);
+                if outcome.is_ok() {
+                    self.nonces.extend(nonces);
+                }
+                outcome
+            }
+            None => Err("HostError: Error(Auth, InvalidInput)".into()),
+        };
+
+        // Each transaction closes a ledger
+        self.env.ledger().with_mut(|ledger| {
+            ledger.sequence_number += 1;
+            ledger.timestamp += 5;
+        });
+        if outcome.is_ok() {
+            self.record_events(&hash_hex)?;
+        }
+
+        let record = match outcome {
+            Ok(return_value) => json!({
+                "status": "SUCCESS",
+                "latestLedger": self.latest_ledger(),
+                "ledger": self.latest_ledger(),
+                "envelopeXdr": params["transaction"],
+                "resultMetaXdr": success_meta(return_value).to_xdr_base64(Limits::none())?,
+            }),
+            Err(_) => json!({
+                "status": "FAILED",
+                "latestLedger": self.latest_ledger(),
+                "ledger": self.latest_ledger(),
+                "envelopeXdr": params["transaction"],
+            }),
+        };
+        self.transactions.insert(hash_hex.clone(), record);
+
+        Ok(json!({
+            "status": "PENDING",
+            "hash": hash_hex,
+            "latestLedger": self.latest_ledger(),
+        }))
+    }
+
+    /// Check the authorization entries with address credentials of a
+    /// transaction: unexpired, with an unused nonce, for the transaction's
+    /// call, and signed by a signer of their account
+    ///
+    /// # Returns
+    /// * The nonces the entries consume, None if one does not hold
+    fn authorized(&mut self, tx: &Transaction) -> Option<Vec<([u8; 32], i64)>> {
+        let Some(OperationBody::InvokeHostFunction(op)) = tx.operations.first().map(|op| &op.body)
+        else {
+            return Some(vec![]);
+        };
+        let mut nonces = vec![];
+        for entry in op.auth.iter() {
+            if matches!(entry.credentials, SorobanCredentials::SourceAccount) {
+                continue;
+            }
+            let HostFunction::InvokeContract(call) = &op.host_function else {
+                return None;
+            };
+            let entry = AuthorizationEntry::try_from(entry.clone()).ok()?;
+            let SorobanAuthorizedFunction::ContractFn(authorized) =
+                &entry.entry().root_invocation.function
+            else {
+                return None;
+            };
+            let account = match Strkey::from_string(&entry.address().ok()?) {
+                Ok(Strkey::PublicKeyEd25519(key)) => key.0,
+                _ => return None,
+            };
+            let nonce = (account, entry.nonce().ok()?);
+            let keys = entry.verify(&self.network_id).ok()?;
+            let signers = self.signers_of(account);
+            let signed = keys.iter().any(|key| signers.contains(key));
+            if authorized != call
+                || !signed
+                || entry.expiration_ledger().ok()? < self.latest_ledger()
+                || self.nonces.contains(&nonce)
+                || nonces.contains(&nonce)
+            {
+                return None;
+            }
+            nonces.push(nonce);
+        }
+        Some(nonces)
+    }
+
+    /// Record the events of the contract under test from the last invocation
+    fn record_events(&mut self, hash: &str) -> Result<(), Error> {
+        let ledger = self.latest_ledger();
+        let closed_at = self.env.ledger().timestamp();
+        let contract_id = scval::format_address(&ScAddress::from(&self.contract));
+        let convert = |e| Error::InvalidResponse(format!("invalid event: {e:?}"));
+        for (index, (contract, topics, data)) in self.env.events().all().iter().enumerate() {
+            if contract != self.contract {
+                continue;
+            }
+            let mut topic = vec![];
+            for value in topics.iter() {
+                let value = ScVal::try_from_val(&self.env, &value).map_err(convert)?;
+                topic.push(value.to_xdr_base64(Limits::none())?);
+            }
+            let value = ScVal::try_from_val(&self.env, &data).map_err(convert)?;
+            self.events.push(json!({
+                "type": "contract",
+                "ledger": ledger,
+                "ledgerClosedAt": format_timestamp(closed_at),
+                "contractId": contract_id,
+                "id": format!("{:019}-{index:010}", u64::from(ledger) << 32),
+                "topic": topic,
+                "value": value.to_xdr_base64(Limits::none())?,
+                "inSuccessfulContractCall": true,
+                "txHash": hash,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Page through recorded events by start ledger or cursor
+    fn get_events(&self, params: &Value) -> Result<Value, Error> {
+        let limit = params["pagination"]["limit"].as_u64().unwrap_or(100) as usize;
+        let contract_ids: Vec<&str> = params["filters"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|filter| filter["contractIds"].as_array().into_iter().flatten())
+            .filter_map(Value::as_str)
+            .collect();
+        let cursor = params["pagination"]["cursor"].as_str();
+        let start_ledger = params["startLedger"].as_u64().unwrap_or_default();
+
+        let events: Vec<&Value> = self
+            .events
+            .iter()
+            .filter(|event| match cursor {
+                Some(cursor) => event["id"].as_str().unwrap_or_default() > cursor,
+                None => event["ledger"].as_u64().unwrap_or_default() >= start_ledger,
+            })
+            .filter(|event| {
+                contract_ids.is_empty()
+                    || contract_ids.contains(&event["contractId"].as_str().unwrap_or_default())
+            })
+            .take(limit)
+            .collect();
+        let cursor = events
+            .last()
+            .and_then(|event| event["id"].as_str())
+            .or(cursor)
+            .unwrap_or_default();
+        Ok(json!({
+            "events": events,
+            "latestLedger": self.latest_ledger(),
+            "cursor": cursor,
+        }))
+    }
+
+    /// Hash of a transaction as signed on the test network
+    fn hash(
+        &self,
+        tagged_transaction: TransactionSignaturePayloadTaggedTransaction,
+    ) -> Result<[u8; 32], Error> {
+        let payload = TransactionSignaturePayload {
+            network_id: self.network_id.into(),
+            tagged_transaction,
+        };
+        Ok(Sha256::digest(payload.to_xdr(Limits::none())?).into())
+    }
+
+    fn reject(&self, hash: &str, result: TransactionResultResult) -> Result<Value, Error> {
+        let result = TransactionResult {
+            fee_charged: 0,
+            result,
+            ext: TransactionResultExt::V0,
+        };
+        Ok(json!({
+            "status": "ERROR",
+            "hash": hash,
+            "latestLedger": self.latest_ledger(),
+            "errorResultXdr": result.to_xdr_base64(Limits::none())?,
+        }))
+    }
+
+    /// Extract the contract invocation from a single-operation transaction
+    fn invocation(
+        &self,
+        tx: &Transaction,
+    ) -> Result<(Address, Symbol, soroban_sdk::Vec<Val>), Error> {
+        let Some(OperationBody::InvokeHostFunction(op)) = tx.operations.first().map(|op| &op.body)
+        else {
+            return Err(Error::InvalidResponse("expected InvokeHostFunction".into()));
+        };
+        let HostFunction::InvokeContract(call) = &op.host_function else {
+            return Err(Error::InvalidResponse("expected InvokeContract".into()));
+        };
+
+        let env = &self.env;
+        fn convert(e: impl std::fmt::Debug) -> Error {
+            Error::InvalidResponse(format!("invalid argument: {e:?}"))
+        }
+        let contract = Address::try_from_val(env, &ScVal::Address(call.contract_address.clone()))
+            .map_err(convert)?;
+        let function = Symbol::new(env, &call.function_name.to_utf8_string_lossy());
+        let mut args = soroban_sdk::Vec::new(env);
+        for arg in call.args.iter() {
+            args.push_back(Val::try_from_val(env, arg).map_err(convert)?);
+        }
+        Ok((contract, function, args))
+    }
+}
+
+/// Transaction of an envelope and its signatures, unwrapping fee bumps
+fn transaction(
+    envelope: &TransactionEnvelope,
+) -> Result<(&Transaction, &[DecoratedSignature]), Error> {
+    let inner = match envelope {
+        TransactionEnvelope::Tx(inner) => inner,
+        TransactionEnvelope::TxFeeBump(bump) => match &bump.tx.inner_tx {
+            FeeBumpTransactionInnerTx::Tx(inner) => inner,
+        },
+        TransactionEnvelope::TxV0(_) => {
+            return Err(Error::InvalidResponse("expected a v1 envelope".into()))
+        }
+    };
+    let TransactionV1Envelope { tx, signatures } = inner;
+    Ok((tx, signatures.as_slice()))
+}
+
+/// Whether one of `signatures` is by `public_key` over `hash`
+fn signed(public_key: &[u8; 32], hash: &[u8; 32], signatures: &[DecoratedSignature]) -> bool {
+    let Ok(key) = VerifyingKey::from_bytes(public_key) else {
+        return false;
+    };
+    signatures.iter().any(|decorated| {
+        Ed25519Signature::from_slice(decorated.signature.as_slice())
+            .is_ok_and(|signature| key.verify(hash, &signature).is_ok())
+    })
+}
+
+fn address(env: &Env, address: &str) -> Address {
+    let address = scval::parse_address(address).expect("valid address");
+    Address::try_from_val(env, &ScVal::Address(address)).expect("valid address")
+}
+
+fn account_key(address: &str) -> [u8; 32] {
+    match Strkey::from_string(address) {
+        Ok(Strkey::PublicKeyEd25519(key)) => key.0,
+        _ => panic!("invalid account address {address}"),
+    }
+}
+
+fn decode_envelope(params: &Value) -> Result<TransactionEnvelope, Error> {
+    let transaction = params["transaction"].as_str().unwrap_or_default();
+    Ok(TransactionEnvelope::from_xdr_base64(
+        transaction,
+        Limits::none(),
+    )?)
+}
+
+fn success_meta(return_value: ScVal) -> TransactionMeta {
+    TransactionMeta::V3(TransactionMetaV3 {
+        ext: ExtensionPoint::V0,
+        tx_changes_before: Default::default(),
+        operations: Default::default(),
+        tx_changes_after: Default::default(),
+        soroban_meta: Some(SorobanTransactionMeta {
+            ext: SorobanTransactionMetaExt::V0,
+            events: Default::default(),
+            return_value,
+            diagnostic_events: Default::default(),
+        }),
+    })
+}
@@ -0,0 +1,280 @@
+/// Errors returned by the x402 escrow contract
+///
+/// Mirrors `contracts/x402-escrow/src/error.rs`. Codes are those the
+/// contract traps with, `Error(Contract, #N)`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, thiserror::Error)]
+pub enum ContractError {
+    #[error("escrow not found")]
+    EscrowNotFound,
+    #[error("escrow already exists for this client-server pair")]
+    EscrowAlreadyExists,
+    #[error("insufficient escrow balance")]
+    InsufficientBalance,
+    #[error("payment not found")]
+    PaymentNotFound,
+    #[error("payment already settled")]
+    PaymentAlreadySettled,
+    #[error("caller is not the contract admin")]
+    Unauthorized,
+    #[error("no price oracle configured")]
+    OracleNotSet,
+    #[error("oracle price unavailable")]
+    PriceUnavailable,
+    #[error("oracle price is stale")]
+    StalePrice,
+    #[error("oracle price deviates from its average beyond the slippage limit")]
+    PriceSlippage,
+    #[error("no USD price set for the resource")]
+    PriceNotSet,
+    #[error("key is not the escrow party's")]
+    InvalidSigner,
+    #[error("message signed for another network")]
+    NetworkMismatch,
+    #[error("transfer already credited to an escrow")]
+    DepositAlreadyClaimed,
+    #[error("escrow frozen for migration")]
+    EscrowFrozen,
+    #[error("migration not approved")]
+    MigrationNotApproved,
+    #[error("migration summary does not match its target or proof")]
+    MigrationMismatch,
+    #[error("too many unsettled payments to migrate the escrow")]
+    TooManyPendingPayments,
+    #[error("no dust policy configured")]
+    DustPolicyNotSet,
+    #[error("invalid dust policy")]
+    InvalidDustPolicy,
+    #[error("escrow holds more than dust or was active too recently")]
+    EscrowNotDust,
+    #[error("escrow has unsettled payments")]
+    PendingPayments,
+    #[error("invalid agent allowance")]
+    InvalidAllowance,
+    #[error("agent holds no allowance from the client")]
+    AllowanceNotFound,
+    #[error("agent allowance expired")]
+    AllowanceExpired,
+    #[error("payment exceeds the agent allowance")]
+    AllowanceExceeded,
+    #[error("authorization nonce already paid")]
+    AuthorizationUsed,
+    #[error("authorization expired")]
+    AuthorizationExpired,
+    #[error("payment not settled")]
+    PaymentNotSettled,
+    #[error("refund is not positive or exceeds the payment")]
+    InvalidRefund,
+    #[error("client holds the most open escrows allowed")]
+    TooManyEscrows,
+    #[error("invalid promo")]
+    InvalidPromo,
+    #[error("payment exceeds the server's price")]
+    PriceExceeded,
+    #[error("note is empty or too long")]
+    InvalidNote,
+    #[error("party already posted a note in this ledger")]
+    NoteRateLimited,
+    #[error("payment does not match the terms the server settled")]
+    SettlementMismatch,
+    #[error("terms of service accepted are not those the server publishes")]
+    TermsMismatch,
+    #[error("notification URL is too long")]
+    InvalidNotificationUrl,
+    #[error("authorization is for another escrow than the one opened")]
+    AuthorizationMismatch,
+    #[error("settlements of the escrow are paused by its spend limit")]
+    SettlementsPaused,
+    #[error("spend limit is not positive")]
+    InvalidSpendLimit,
+    #[error("sale of the resource is suspended by the server")]
+    ResourceSuspended,
+    #[error("split amount or metadata is out of bounds")]
+    InvalidSplit,
+    #[error("direct payment amount is not positive or its reference was already recorded")]
+    InvalidDirectPayment,
+    #[error("trial amount is not positive")]
+    InvalidTrial,
+    #[error("contract admin is already set")]
+    AlreadyInitialized,
+    #[error("contract admin is not set")]
+    NotInitialized,
+    #[error("deposit amount is not positive")]
+    InvalidAmount,
+    #[error("escrow has unexpired authorizations")]
+    AuthorizationsLive,
+    /// A code this version does not know about
+    #[error("unknown contract error #{0}")]
+    Unknown(u32),
+}
+
+impl ContractError {
+    /// Map a raw contract error code to its variant
+    pub fn from_code(code: u32) -> Self {
+        match code {
+            1 => Self::EscrowNotFound,
+            2 => Self::EscrowAlreadyExists,
+            3 => Self::InsufficientBalance,
+            4 => Self::PaymentNotFound,
+            5 => Self::PaymentAlreadySettled,
+            6 => Self::Unauthorized,
+            7 => Self::OracleNotSet,
+            8 => Self::PriceUnavailable,
+            9 => Self::StalePrice,
+            10 => Self::PriceSlippage,
+            11 => Self::PriceNotSet,
+            12 => Self::InvalidSigner,
+            13 => Self::NetworkMismatch,
+            14 => Self::DepositAlreadyClaimed,
+            15 => Self::EscrowFrozen,
+            16 => Self::MigrationNotApproved,
+            17 => Self::MigrationMismatch,
+            18 => Self::TooManyPendingPayments,
+            19 => Self::DustPolicyNotSet,
+            20 => Self::InvalidDustPolicy,
+            21 => Self::EscrowNotDust,
+            22 => Self::PendingPayments,
+            23 => Self::InvalidAllowance,
+            24 => Self::AllowanceNotFound,
+            25 => Self::AllowanceExpired,
+            26 => Self::AllowanceExceeded,
+            27 => Self::AuthorizationUsed,
+            28 => Self::AuthorizationExpired,
+            29 => Self::PaymentNotSettled,
+            30 => Self::InvalidRefund,
+            31 => Self::TooManyEscrows,
+            32 => Self::InvalidPromo,
+            33 => Self::PriceExceeded,
+            34 => Self::InvalidNote,
+            35 => Self::NoteRateLimited,
+            36 => Self::SettlementMismatch,
+            37 => Self::TermsMismatch,
+            38 => Self::InvalidNotificationUrl,
+            39 => Self::AuthorizationMismatch,
+            40 => Self::SettlementsPaused,
+            41 => Self::InvalidSpendLimit,
+            42 => Self::ResourceSuspended,
+            43 => Self::InvalidSplit,
+            44 => Self::InvalidDirectPayment,
+            45 => Self::InvalidTrial,
+            46 => Self::AlreadyInitialized,
+            47 => Self::NotInitialized,
+            48 => Self::InvalidAmount,
+            49 => Self::AuthorizationsLive,
+            other => Self::Unknown(other),
+        }
+    }
+
+    /// Raw contract error code
+    pub fn code(&self) -> u32 {
+        match self {
+            Self::EscrowNotFound => 1,
+            Self::EscrowAlreadyExists => 2,
+            Self::InsufficientBalance => 3,
+            Self::PaymentNotFound => 4,
+            Self::PaymentAlreadySettled => 5,
+            Self::Unauthorized => 6,
+            Self::OracleNotSet => 7,
+            Self::PriceUnavailable => 8,
+            Self::StalePrice => 9,
+            Self::PriceSlippage => 10,
+            Self::PriceNotSet => 11,
+            Self::InvalidSigner => 12,
+            Self::NetworkMismatch => 13,
+            Self::DepositAlreadyClaimed => 14,
+            Self::EscrowFrozen => 15,
+            Self::MigrationNotApproved => 16,
+            Self::MigrationMismatch => 17,
+            Self::TooManyPendingPayments => 18,
+            Self::DustPolicyNotSet => 19,
+            Self::InvalidDustPolicy => 20,
+            Self::EscrowNotDust => 21,
+            Self::PendingPayments => 22,
+            Self::InvalidAllowance => 23,
+            Self::AllowanceNotFound => 24,
+            Self::AllowanceExpired => 25,
+            Self::AllowanceExceeded => 26,
+            Self::AuthorizationUsed => 27,
+            Self::AuthorizationExpired => 28,
+            Self::PaymentNotSettled => 29,
+            Self::InvalidRefund => 30,
+            Self::TooManyEscrows => 31,
+            Self::InvalidPromo => 32,
+            Self::PriceExceeded => 33,
+            Self::InvalidNote => 34,
+            Self::NoteRateLimited => 35,
+            Self::SettlementMismatch => 36,
+            Self::TermsMismatch => 37,
+            Self::InvalidNotificationUrl => 38,
+            Self::AuthorizationMismatch => 39,
+            Self::SettlementsPaused => 40,
+            Self::InvalidSpendLimit => 41,
+            Self::ResourceSuspended => 42,
+            Self::InvalidSplit => 43,
+            Self::InvalidDirectPayment => 44,
+            Self::InvalidTrial => 45,
+            Self::AlreadyInitialized => 46,
+            Self::NotInitialized => 47,
+            Self::InvalidAmount => 48,
+            Self::AuthorizationsLive => 49,
+            Self::Unknown(code) => *code,
+        }
+    }
+
+    /// Machine-readable reason
+    ///
+    /// An insufficient balance is `insufficient_funds`, as x402 names it.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::EscrowNotFound => "escrow_not_found",
+            Self::EscrowAlreadyExists => "escrow_already_exists",
+            Self::InsufficientBalance => "insufficient_funds",
+            Self::PaymentNotFound => "payment_not_found",
+            Self::PaymentAlreadySettled => "payment_already_settled",
+            Self::Unauthorized => "unauthorized",
+            Self::OracleNotSet => "oracle_not_set",
+            Self::PriceUnavailable => "price_unavailable",
+            Self::StalePrice => "stale_price",
+            Self::PriceSlippage => "price_slippage",
+            Self::PriceNotSet => "price_not_set",
+            Self::InvalidSigner => "invalid_signer",
+            Self::NetworkMismatch => "network_mismatch",
+            Self::DepositAlreadyClaimed => "deposit_already_claimed",
+            Self::EscrowFrozen => "escrow_frozen",
+            Self::MigrationNotApproved => "migration_not_approved",
+            Self::MigrationMismatch => "migration_mismatch",
+            Self::TooManyPendingPayments => "too_many_pending_payments",
+            Self::DustPolicyNotSet => "dust_policy_not_set",
+            Self::InvalidDustPolicy => "invalid_dust_policy",
+            Self::EscrowNotDust => "escrow_not_dust",
+            Self::PendingPayments => "pending_payments",
+            Self::InvalidAllowance => "invalid_allowance",
+            Self::AllowanceNotFound => "allowance_not_found",
+            Self::AllowanceExpired => "allowance_expired",
+            Self::AllowanceExceeded => "allowance_exceeded",
+            Self::AuthorizationUsed => "authorization_used",
+            Self::AuthorizationExpired => "authorization_expired",
+            Self::PaymentNotSettled => "payment_not_settled",
+            Self::InvalidRefund => "invalid_refund",
+            Self::TooManyEscrows => "too_many_escrows",
+            Self::InvalidPromo => "invalid_promo",
+            Self::PriceExceeded => "price_exceeded",
+            Self::InvalidNote => "invalid_note",
+            Self::NoteRateLimited => "note_rate_limited",
+            Self::SettlementMismatch => "settlement_mismatch",
+            Self::TermsMismatch => "terms_mismatch",
+            Self::InvalidNotificationUrl => "invalid_notification_url",
+            Self::AuthorizationMismatch => "authorization_mismatch",
+            Self::SettlementsPaused => "settlements_paused",
+            Self::InvalidSpendLimit => "invalid_spend_limit",
+            Self::ResourceSuspended => "resource_suspended",
+            Self::InvalidSplit => "invalid_split",
+            Self::InvalidDirectPayment => "invalid_direct_payment",
+            Self::InvalidTrial => "invalid_trial",
+            Self::AlreadyInitialized => "already_initialized",
+            Self::NotInitialized => "not_initialized",
+            Self::InvalidAmount => "invalid_amount",
+            Self::AuthorizationsLive => "authorizations_live",
+            Self::Unknown(_) => "contract_error",
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! # x402 Errors
+//!
+//! Errors of the escrow contract, the SDK, and the facilitator, sorted into
+//! one taxonomy with stable numeric codes, so a rejection keeps its meaning
+//! from the contract trap to the HTTP response and back to the client.
+//!
+//! ## Layers
+//! - [`ContractError`] - The contract rejected the call, codes 1 to 999
+//!   being those it traps with
+//! - [`TransportError`] - Soroban RPC could not be reached or a transaction
+//!   did not get through, codes from 1001
+//! - [`ProtocolError`] - A payment breaks the x402 protocol or does not
+//!   match its requirements, codes from 2001
+//! - [`PolicyError`] - A rate limit, spending rule, or shutdown turned the
+//!   request down, codes from 3001
+//!
+//! Anything else is [`X402Error::Internal`], code 9000.
+//!
+//! ## Codes and reasons
+//! [`X402Error::code`] never changes meaning once released, and is what
+//! facilitator responses carry as `errorCode`. [`X402Error::reason`] is the
+//! snake_case label of `invalidReason` and metrics, and may be shared by
+//! errors of different layers, e.g. `transaction_failed`;
+//! [`X402Error::from_reason`] maps one back to an error.
+//!
+//! ## Retries
+//! [`X402Error::retryable`] tells whether the same request may succeed
+//! later. Contract rejections and invalid payments never do; unreachable or
+//! rate-limiting RPC servers, full queues, and facilitators shutting down
+//! may. The SDK's retry loops follow it.
+//!
+//! ## Serde
+//! [`X402Error`] serializes as its code. Delays of rate limits travel in
+//! `Retry-After` instead, see [`X402Error::with_retry_after`].
+
+mod contract;
+mod policy;
+mod protocol;
+mod transport;
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+pub use contract::ContractError;
+pub use policy::PolicyError;
+pub use protocol::ProtocolError;
+pub use transport::TransportError;
+
+/// Code of [`X402Error::Internal`]
+pub const INTERNAL_CODE: u32 = 9000;
+
+/// Any x402 error, by layer
+#[derive(Clone, Debug, Eq, PartialEq, thiserror::Error, Serialize, Deserialize)]
+#[serde(into = "u32", try_from = "u32")]
+pub enum X402Error {
+    #[error("contract error: {0}")]
+    Contract(ContractError),
+    #[error("transport error: {0}")]
+    Transport(TransportError),
+    #[error("protocol error: {0}")]
+    Protocol(ProtocolError),
+    #[error("policy error: {0}")]
+    Policy(PolicyError),
+    /// A failure of the facilitator or SDK itself, e.g. its storage or
+    /// signer
+    #[error("unexpected error")]
+    Internal,
+}
+
+/// Code not assigned to any error
+#[derive(Copy, Clone, Debug, Eq, PartialEq, thiserror::Error)]
+#[error("unknown x402 error code {0}")]
+pub struct UnknownCode(pub u32);
+
+impl X402Error {
+    /// Stable numeric code
+    pub fn code(&self) -> u32 {
+        match self {
+            Self::Contract(e) => e.code(),
+            Self::Transport(e) => e.code(),
+            Self::Protocol(e) => e.code(),
+            Self::Policy(e) => e.code(),
+            Self::Internal => INTERNAL_CODE,
+        }
+    }
+
+    /// Map a code back to its error
+    ///
+    /// Contract codes this version does not know map to
+    /// [`ContractError::Unknown`].
+    ///
+    /// # Returns
+    /// * The error, or None for codes not assigned
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            1..=999 => Some(Self::Contract(ContractError::from_code(code))),
+            INTERNAL_CODE => Some(Self::Internal),
+            _ => TransportError::from_code(code)
+                .map(Self::Transport)
+                .or_else(|| ProtocolError::from_code(code).map(Self::Protocol))
+                .or_else(|| PolicyError::from_code(code).map(Self::Policy)),
+        }
+    }
+
+    /// Map a reason back to its error, for services reporting reasons
+    /// alone, e.g. the `error` of 402 responses
+    ///
+    /// Reasons shared by several errors map to the first having it of the
+    /// protocol, contract, transport, and policy layers.
+    ///
+    /// # Returns
+    /// * The error, or None for reasons of no error
+    pub fn from_reason(reason: &str) -> Option<Self> {
+        let protocol = (2001..)
+            .map_while(ProtocolError::from_code)
+            .map(Self::Protocol);
+        let contract = (1..)
+            .map(ContractError::from_code)
+            .take_while(|e| !matches!(e, ContractError::Unknown(_)))
+            .map(Self::Contract);
+        let transport = (1001..)
+            .map_while(TransportError::from_code)
+            .map(Self::Transport);
+        let policy = (3001..).map_while(PolicyError::from_code).map(Self::Policy);
+        protocol
+            .chain(contract)
+            .chain(transport)
+            .chain(policy)
+            .chain([Self::Internal])
+            .find(|error| error.reason() == reason)
+    }
+
+    /// Machine-readable reason
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Contract(e) => e.reason(),
+            Self::Transport(e) => e.reason(),
+            Self::Protocol(e) => e.reason(),
+            Self::Policy(e) => e.reason(),
+            Self::Internal => "unexpected_error",
+        }
+    }
+
+    /// Whether the same request may succeed if retried later
+    pub fn retryable(&self) -> bool {
+        match self {
+            Self::Transport(e) => e.retryable(),
+            Self::Policy(e) => e.retryable(),
+            Self::Contract(_) | Self::Protocol(_) | Self::Internal => false,
+        }
+    }
+
+    /// How long to wait before retrying, when rate limited and told
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Transport(TransportError::RateLimited { retry_after })
+            | Self::Policy(PolicyError::RateLimited { retry_after }) => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Record the delay a rate-limited request was told to wait, e.g. by
+    /// `Retry-After`, leaving other errors as they are
+    pub fn with_retry_after(mut self, delay: Duration) -> Self {
+        if let Self::Transport(TransportError::RateLimited { retry_after })
+        | Self::Policy(PolicyError::RateLimited { retry_after }) = &mut self
+        {
+            *retry_after = Some(delay);
+        }
+        self
+    }
+}
+
+impl From<ContractError> for X402Error {
+    fn from(error: ContractError) -> Self {
+        Self::Contract(error)
+    }
+}
+
+impl From<TransportError> for X402Error {
+    fn from(error: TransportError) -> Self {
+        Self::Transport(error)
+    }
+}
+
+impl From<ProtocolError> for X402Error {
+    fn from(error: ProtocolError) -> Self {
+        Self::Protocol(error)
+    }
+}
+
+impl From<PolicyError> for X402Error {
+    fn from(error: PolicyError) -> Self {
+        Self::Policy(error)
+    }
+}
+
+impl From<X402Error> for u32 {
+    fn from(error: X402Error) -> Self {
+        error.code()
+    }
+}
+
+impl TryFrom<u32> for X402Error {
+    type Error = UnknownCode;
+
+    fn try_from(code: u32) -> Result<Self, Self::Error> {
+        Self::from_code(code).ok_or(UnknownCode(code))
+    }
+}
+
+mod test;
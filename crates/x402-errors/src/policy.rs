@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+/// Requests turned down by the facilitator's or the client's own rules,
+/// rather than for being invalid
+#[derive(Clone, Debug, Eq, PartialEq, thiserror::Error)]
+pub enum PolicyError {
+    /// The paying client sent too many requests
+    #[error("rate limited")]
+    RateLimited {
+        /// How long to wait, when known
+        retry_after: Option<Duration>,
+    },
+    /// The facilitator is shutting down and admits no settlement
+    #[error("shutting down")]
+    ShuttingDown,
+    /// Escrow credit is exhausted while RPC is unreachable
+    #[error("escrow credit exhausted while RPC is unreachable")]
+    CreditExhausted,
+    /// The verifier cannot charge part of a payment
+    #[error("partial settlement unsupported")]
+    PartialSettlementUnsupported,
+    /// The payment was not made because of the client's spending rules
+    #[error("payment declined")]
+    PaymentDeclined,
+    /// The client's payment queue holds as many payments as it may
+    #[error("payment queue full")]
+    QueueFull,
+}
+
+impl PolicyError {
+    /// Stable code, from 3001
+    pub fn code(&self) -> u32 {
+        match self {
+            Self::RateLimited { .. } => 3001,
+            Self::ShuttingDown => 3002,
+            Self::CreditExhausted => 3003,
+            Self::PartialSettlementUnsupported => 3004,
+            Self::PaymentDeclined => 3005,
+            Self::QueueFull => 3006,
+        }
+    }
+
+    /// Map a code to its variant, `None` outside this layer
+    pub fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
+            3001 => Self::RateLimited { retry_after: None },
+            3002 => Self::ShuttingDown,
+            3003 => Self::CreditExhausted,
+            3004 => Self::PartialSettlementUnsupported,
+            3005 => Self::PaymentDeclined,
+            3006 => Self::QueueFull,
+            _ => return None,
+        })
+    }
+
+    /// Machine-readable reason
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::RateLimited { .. } => "rate_limited",
+            Self::ShuttingDown => "shutting_down",
+            Self::CreditExhausted => "credit_exhausted",
+            Self::PartialSettlementUnsupported => "partial_settlement_unsupported",
+            Self::PaymentDeclined => "payment_declined",
+            Self::QueueFull => "queue_full",
+        }
+    }
+
+    /// Whether the request may succeed later: limits and backlogs clear,
+    /// and another replica takes over from one shutting down
+    pub fn retryable(&self) -> bool {
+        !matches!(
+            self,
+            Self::PartialSettlementUnsupported | Self::PaymentDeclined
+        )
+    }
+}
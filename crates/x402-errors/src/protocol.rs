@@ -0,0 +1,146 @@
+/// Payments breaking the x402 protocol or not matching their requirements
+///
+/// Reasons are the `invalidReason` of /verify responses.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, thiserror::Error)]
+pub enum ProtocolError {
+    #[error("invalid payment header")]
+    InvalidPayload,
+    #[error("unsupported x402 version")]
+    UnsupportedVersion,
+    #[error("unsupported payment scheme")]
+    UnsupportedScheme,
+    #[error("payment is for another network")]
+    NetworkMismatch,
+    #[error("payment recipient does not match the facilitator server")]
+    RecipientMismatch,
+    #[error("invalid payment amount")]
+    InvalidAmount,
+    #[error("payment amount exceeds the maximum required")]
+    AmountExceedsRequirement,
+    #[error("settlement amount exceeds the authorized amount")]
+    SettlementExceedsAuthorization,
+    #[error("payment authorization expired")]
+    Expired,
+    #[error("nonce already used")]
+    NonceUsed,
+    #[error("nonce already verified")]
+    NonceReplayed,
+    #[error("invalid client signature")]
+    InvalidSignature,
+    #[error("escrow is closed")]
+    EscrowClosed,
+    #[error("payment client does not own the escrow")]
+    ClientMismatch,
+    #[error("payment transaction not found")]
+    TransactionNotFound,
+    #[error("payment transaction failed")]
+    TransactionFailed,
+    #[error("payment transaction is not confirmed deep enough")]
+    InsufficientConfirmations,
+    #[error("payment memo does not match the requirements")]
+    MemoMismatch,
+    #[error("payment is in another asset")]
+    AssetMismatch,
+    #[error("payment amount is below the amount required")]
+    Underpaid,
+    /// A 402 response did not offer a payment the client can make
+    #[error("invalid payment challenge")]
+    InvalidChallenge,
+    /// A pre-signed authorization entry was malformed or not validly signed
+    #[error("invalid authorization entry")]
+    InvalidAuthorization,
+    /// A settlement receipt does not match its hash or signature
+    #[error("invalid receipt")]
+    InvalidReceipt,
+}
+
+impl ProtocolError {
+    const ALL: [Self; 23] = [
+        Self::InvalidPayload,
+        Self::UnsupportedVersion,
+        Self::UnsupportedScheme,
+        Self::NetworkMismatch,
+        Self::RecipientMismatch,
+        Self::InvalidAmount,
+        Self::AmountExceedsRequirement,
+        Self::SettlementExceedsAuthorization,
+        Self::Expired,
+        Self::NonceUsed,
+        Self::NonceReplayed,
+        Self::InvalidSignature,
+        Self::EscrowClosed,
+        Self::ClientMismatch,
+        Self::TransactionNotFound,
+        Self::TransactionFailed,
+        Self::InsufficientConfirmations,
+        Self::MemoMismatch,
+        Self::AssetMismatch,
+        Self::Underpaid,
+        Self::InvalidChallenge,
+        Self::InvalidAuthorization,
+        Self::InvalidReceipt,
+    ];
+
+    /// Stable code, from 2001
+    pub fn code(&self) -> u32 {
+        match self {
+            Self::InvalidPayload => 2001,
+            Self::UnsupportedVersion => 2002,
+            Self::UnsupportedScheme => 2003,
+            Self::NetworkMismatch => 2004,
+            Self::RecipientMismatch => 2005,
+            Self::InvalidAmount => 2006,
+            Self::AmountExceedsRequirement => 2007,
+            Self::SettlementExceedsAuthorization => 2008,
+            Self::Expired => 2009,
+            Self::NonceUsed => 2010,
+            Self::NonceReplayed => 2011,
+            Self::InvalidSignature => 2012,
+            Self::EscrowClosed => 2013,
+            Self::ClientMismatch => 2014,
+            Self::TransactionNotFound => 2015,
+            Self::TransactionFailed => 2016,
+            Self::InsufficientConfirmations => 2017,
+            Self::MemoMismatch => 2018,
+            Self::AssetMismatch => 2019,
+            Self::Underpaid => 2020,
+            Self::InvalidChallenge => 2021,
+            Self::InvalidAuthorization => 2022,
+            Self::InvalidReceipt => 2023,
+        }
+    }
+
+    /// Map a code to its variant, `None` outside this layer
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|error| error.code() == code)
+    }
+
+    /// Machine-readable reason
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::InvalidPayload => "invalid_payload",
+            Self::UnsupportedVersion => "invalid_x402_version",
+            Self::UnsupportedScheme => "invalid_scheme",
+            Self::NetworkMismatch => "invalid_network",
+            Self::RecipientMismatch => "invalid_pay_to",
+            Self::InvalidAmount => "invalid_amount",
+            Self::AmountExceedsRequirement => "amount_exceeds_requirement",
+            Self::SettlementExceedsAuthorization => "settlement_exceeds_authorization",
+            Self::Expired => "authorization_expired",
+            Self::NonceUsed => "nonce_used",
+            Self::NonceReplayed => "nonce_replayed",
+            Self::InvalidSignature => "invalid_signature",
+            Self::EscrowClosed => "escrow_closed",
+            Self::ClientMismatch => "invalid_client",
+            Self::TransactionNotFound => "transaction_not_found",
+            Self::TransactionFailed => "transaction_failed",
+            Self::InsufficientConfirmations => "insufficient_confirmations",
+            Self::MemoMismatch => "invalid_memo",
+            Self::AssetMismatch => "invalid_asset",
+            Self::Underpaid => "insufficient_amount",
+            Self::InvalidChallenge => "invalid_challenge",
+            Self::InvalidAuthorization => "invalid_authorization",
+            Self::InvalidReceipt => "invalid_receipt",
+        }
+    }
+}
@@ -0,0 +1,221 @@
+#![cfg(test)]
+
+use std::time::Duration;
+
+use x402_bindings::codes;
+
+use crate::*;
+
+#[test]
+fn test_contract_codes_match_the_contract() {
+    let errors = [
+        (ContractError::EscrowNotFound, codes::ESCROW_NOT_FOUND),
+        (
+            ContractError::EscrowAlreadyExists,
+            codes::ESCROW_ALREADY_EXISTS,
+        ),
+        (
+            ContractError::InsufficientBalance,
+            codes::INSUFFICIENT_BALANCE,
+        ),
+        (ContractError::PaymentNotFound, codes::PAYMENT_NOT_FOUND),
+        (
+            ContractError::PaymentAlreadySettled,
+            codes::PAYMENT_ALREADY_SETTLED,
+        ),
+        (ContractError::Unauthorized, codes::UNAUTHORIZED),
+        (ContractError::OracleNotSet, codes::ORACLE_NOT_SET),
+        (ContractError::PriceUnavailable, codes::PRICE_UNAVAILABLE),
+        (ContractError::StalePrice, codes::STALE_PRICE),
+        (ContractError::PriceSlippage, codes::PRICE_SLIPPAGE),
+        (ContractError::PriceNotSet, codes::PRICE_NOT_SET),
+        (ContractError::InvalidSigner, codes::INVALID_SIGNER),
+        (ContractError::NetworkMismatch, codes::NETWORK_MISMATCH),
+        (
+            ContractError::DepositAlreadyClaimed,
+            codes::DEPOSIT_ALREADY_CLAIMED,
+        ),
+        (ContractError::EscrowFrozen, codes::ESCROW_FROZEN),
+        (
+            ContractError::MigrationNotApproved,
+            codes::MIGRATION_NOT_APPROVED,
+        ),
+        (ContractError::MigrationMismatch, codes::MIGRATION_MISMATCH),
+        (
+            ContractError::TooManyPendingPayments,
+            codes::TOO_MANY_PENDING_PAYMENTS,
+        ),
+        (ContractError::DustPolicyNotSet, codes::DUST_POLICY_NOT_SET),
+        (ContractError::InvalidDustPolicy, codes::INVALID_DUST_POLICY),
+        (ContractError::EscrowNotDust, codes::ESCROW_NOT_DUST),
+        (ContractError::PendingPayments, codes::PENDING_PAYMENTS),
+        (ContractError::InvalidAllowance, codes::INVALID_ALLOWANCE),
+        (ContractError::AllowanceNotFound, codes::ALLOWANCE_NOT_FOUND),
+        (ContractError::AllowanceExpired, codes::ALLOWANCE_EXPIRED),
+        (ContractError::AllowanceExceeded, codes::ALLOWANCE_EXCEEDED),
+        (ContractError::AuthorizationUsed, codes::AUTHORIZATION_USED),
+        (
+            ContractError::AuthorizationExpired,
+            codes::AUTHORIZATION_EXPIRED,
+        ),
+        (ContractError::PaymentNotSettled, codes::PAYMENT_NOT_SETTLED),
+        (ContractError::InvalidRefund, codes::INVALID_REFUND),
+        (ContractError::TooManyEscrows, codes::TOO_MANY_ESCROWS),
+        (ContractError::InvalidPromo, codes::INVALID_PROMO),
+        (ContractError::PriceExceeded, codes::PRICE_EXCEEDED),
+        (ContractError::InvalidNote, codes::INVALID_NOTE),
+        (ContractError::NoteRateLimited, codes::NOTE_RATE_LIMITED),
+        (
+            ContractError::SettlementMismatch,
+            codes::SETTLEMENT_MISMATCH,
+        ),
+        (ContractError::TermsMismatch, codes::TERMS_MISMATCH),
+        (
+            ContractError::InvalidNotificationUrl,
+            codes::INVALID_NOTIFICATION_URL,
+        ),
+        (
+            ContractError::AuthorizationMismatch,
+            codes::AUTHORIZATION_MISMATCH,
+        ),
+        (ContractError::SettlementsPaused, codes::SETTLEMENTS_PAUSED),
+        (ContractError::InvalidSpendLimit, codes::INVALID_SPEND_LIMIT),
+        (ContractError::ResourceSuspended, codes::RESOURCE_SUSPENDED),
+        (ContractError::InvalidSplit, codes::INVALID_SPLIT),
+        (
+            ContractError::InvalidDirectPayment,
+            codes::INVALID_DIRECT_PAYMENT,
+        ),
+        (ContractError::InvalidTrial, codes::INVALID_TRIAL),
+        (
+            ContractError::AlreadyInitialized,
+            codes::ALREADY_INITIALIZED,
+        ),
+        (ContractError::NotInitialized, codes::NOT_INITIALIZED),
+        (ContractError::InvalidAmount, codes::INVALID_AMOUNT),
+        (
+            ContractError::AuthorizationsLive,
+            codes::AUTHORIZATIONS_LIVE,
+        ),
+    ];
+    for (error, code) in errors {
+        assert_eq!(error.code(), code, "{error:?}");
+        assert_eq!(ContractError::from_code(code), error);
+    }
+    assert_eq!(ContractError::from_code(999), ContractError::Unknown(999));
+}
+
+#[test]
+fn test_codes_round_trip() {
+    let mut seen = Vec::new();
+    for code in (1..=49)
+        .chain(1001..=1008)
+        .chain(2001..=2023)
+        .chain(3001..=3006)
+    {
+        let error = X402Error::from_code(code).unwrap();
+        assert_eq!(error.code(), code);
+        assert!(!seen.contains(&error), "{error:?}");
+        assert!(!matches!(
+            error,
+            X402Error::Contract(ContractError::Unknown(_))
+        ));
+    }
+    assert_eq!(
+        X402Error::from_code(INTERNAL_CODE),
+        Some(X402Error::Internal)
+    );
+    assert_eq!(
+        X402Error::from_code(99),
+        Some(X402Error::Contract(ContractError::Unknown(99)))
+    );
+    for code in [0, 1000, 1009, 2024, 3007, 4001] {
+        assert_eq!(X402Error::from_code(code), None, "{code}");
+    }
+}
+
+#[test]
+fn test_retryable() {
+    let limited = X402Error::Transport(TransportError::RateLimited { retry_after: None });
+    assert!(limited.retryable());
+    assert!(X402Error::Transport(TransportError::Unreachable).retryable());
+    assert!(X402Error::Transport(TransportError::Rpc).retryable());
+    assert!(!X402Error::Transport(TransportError::Timeout).retryable());
+    assert!(!X402Error::Contract(ContractError::InsufficientBalance).retryable());
+    assert!(!X402Error::Protocol(ProtocolError::NonceUsed).retryable());
+    assert!(X402Error::Policy(PolicyError::ShuttingDown).retryable());
+    assert!(!X402Error::Policy(PolicyError::PaymentDeclined).retryable());
+    assert!(!X402Error::Internal.retryable());
+
+    let delay = Duration::from_secs(3);
+    assert_eq!(limited.retry_after(), None);
+    assert_eq!(limited.with_retry_after(delay).retry_after(), Some(delay));
+    let shutting_down = X402Error::Policy(PolicyError::ShuttingDown);
+    assert_eq!(shutting_down.with_retry_after(delay).retry_after(), None);
+}
+
+#[test]
+fn test_reasons() {
+    assert_eq!(
+        X402Error::Contract(ContractError::InsufficientBalance).reason(),
+        "insufficient_funds"
+    );
+    assert_eq!(
+        X402Error::Contract(ContractError::EscrowNotFound).reason(),
+        "escrow_not_found"
+    );
+    assert_eq!(
+        X402Error::Contract(ContractError::Unknown(99)).reason(),
+        "contract_error"
+    );
+    assert_eq!(
+        X402Error::Protocol(ProtocolError::Expired).reason(),
+        "authorization_expired"
+    );
+    let limited = PolicyError::RateLimited { retry_after: None };
+    assert_eq!(X402Error::Policy(limited).reason(), "rate_limited");
+    assert_eq!(X402Error::Internal.reason(), "unexpected_error");
+}
+
+#[test]
+fn test_from_reason() {
+    assert_eq!(
+        X402Error::from_reason("insufficient_funds"),
+        Some(X402Error::Contract(ContractError::InsufficientBalance))
+    );
+    assert_eq!(
+        X402Error::from_reason("resource_suspended"),
+        Some(X402Error::Contract(ContractError::ResourceSuspended))
+    );
+    assert_eq!(
+        X402Error::from_reason("shutting_down"),
+        Some(X402Error::Policy(PolicyError::ShuttingDown))
+    );
+    assert_eq!(
+        X402Error::from_reason("unexpected_error"),
+        Some(X402Error::Internal)
+    );
+    // Shared reasons map to the protocol error first
+    assert_eq!(
+        X402Error::from_reason("authorization_expired"),
+        Some(X402Error::Protocol(ProtocolError::Expired))
+    );
+    assert_eq!(
+        X402Error::from_reason("transaction_failed"),
+        Some(X402Error::Protocol(ProtocolError::TransactionFailed))
+    );
+    assert_eq!(X402Error::from_reason("payment_required"), None);
+    assert_eq!(X402Error::from_reason("contract_error"), None);
+}
+
+#[test]
+fn test_serde() {
+    let error = X402Error::Contract(ContractError::InsufficientBalance);
+    assert_eq!(serde_json::to_string(&error).unwrap(), "3");
+    let decoded: X402Error = serde_json::from_str("2010").unwrap();
+    assert_eq!(decoded, X402Error::Protocol(ProtocolError::NonceUsed));
+    let decoded: Option<X402Error> = serde_json::from_str("null").unwrap();
+    assert_eq!(decoded, None);
+    let err = serde_json::from_str::<X402Error>("4001").unwrap_err();
+    assert!(err.to_string().contains("unknown x402 error code 4001"));
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+/// Failures reaching Soroban RPC or getting a transaction through
+#[derive(Clone, Debug, Eq, PartialEq, thiserror::Error)]
+pub enum TransportError {
+    /// The RPC server could not be reached
+    #[error("RPC server unreachable")]
+    Unreachable,
+    /// The RPC server asked to slow down (`429 Too Many Requests`)
+    #[error("rate limited by the RPC server")]
+    RateLimited {
+        /// How long to wait, when the server said
+        retry_after: Option<Duration>,
+    },
+    /// The RPC server answered with a JSON-RPC error
+    #[error("RPC error")]
+    Rpc,
+    /// The RPC response did not have the expected shape
+    #[error("invalid RPC response")]
+    InvalidResponse,
+    /// Simulation failed for a reason other than a contract error
+    #[error("simulation failed")]
+    Simulation,
+    /// The transaction was rejected before inclusion
+    #[error("transaction rejected")]
+    Rejected,
+    /// The transaction was included but failed
+    #[error("transaction failed")]
+    TransactionFailed,
+    /// The transaction was not confirmed in time, and may still be
+    #[error("transaction not confirmed in time")]
+    Timeout,
+}
+
+impl TransportError {
+    /// Stable code, from 1001
+    pub fn code(&self) -> u32 {
+        match self {
+            Self::Unreachable => 1001,
+            Self::RateLimited { .. } => 1002,
+            Self::Rpc => 1003,
+            Self::InvalidResponse => 1004,
+            Self::Simulation => 1005,
+            Self::Rejected => 1006,
+            Self::TransactionFailed => 1007,
+            Self::Timeout => 1008,
+        }
+    }
+
+    /// Map a code to its variant, `None` outside this layer
+    pub fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
+            1001 => Self::Unreachable,
+            1002 => Self::RateLimited { retry_after: None },
+            1003 => Self::Rpc,
+            1004 => Self::InvalidResponse,
+            1005 => Self::Simulation,
+            1006 => Self::Rejected,
+            1007 => Self::TransactionFailed,
+            1008 => Self::Timeout,
+            _ => return None,
+        })
+    }
+
+    /// Machine-readable reason
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Unreachable => "transport_error",
+            Self::RateLimited { .. } => "rpc_rate_limited",
+            Self::Rpc => "rpc_error",
+            Self::InvalidResponse => "invalid_rpc_response",
+            Self::Simulation => "simulation_failed",
+            Self::Rejected => "transaction_rejected",
+            Self::TransactionFailed => "transaction_failed",
+            Self::Timeout => "transaction_timeout",
+        }
+    }
+
+    /// Whether the call may succeed if retried: the RPC server could not be
+    /// reached, rate limited it, or answered with an error of its own
+    ///
+    /// A transaction that timed out may still apply, so it is not retried
+    /// blindly.
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::Unreachable | Self::RateLimited { .. } | Self::Rpc
+        )
+    }
+}
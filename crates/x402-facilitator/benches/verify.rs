@@ -0,0 +1,172 @@
+//! Benchmarks of the per-request verification hot path
+//!
+//! `cargo bench -p x402-facilitator --profile bench-release [-- <name filter>]`
+//!
+//! Measured by criterion, which also reports changes against the previous
+//! run. Every benchmark carries a regression threshold, the median time per
+//! iteration it is expected to stay under on a current x86-64 machine in
+//! release mode, with headroom for noisy CI runners. Medians above their
+//! threshold are reported as regressions, and fail the run when
+//! `X402_BENCH_STRICT` is set.
+
+use std::{
+    env, fs,
+    hint::black_box,
+    path::PathBuf,
+    process::ExitCode,
+    time::{Duration, SystemTime},
+};
+
+use criterion::Criterion;
+use ed25519_dalek::{Signer as _, SigningKey};
+use serde_json::Value;
+use stellar_strkey::ed25519;
+use x402_facilitator::verify_authorization;
+use x402_types::{
+    decode_payment_header, encode_payment_header, EscrowPayload, PaymentPayload, SchemePayload,
+    SigningDomain, SliceSink, StellarAmount, ESCROW_SCHEME, X402_VERSION,
+};
+
+const NETWORK: &str = "stellar-testnet";
+
+const DOMAIN: SigningDomain<'static> = SigningDomain {
+    network_id: [7; 32],
+    contract_id: "CADQOBYHA4DQOBYHA4DQOBYHA4DQOBYHA4DQOBYHA4DQOBYHA4DQP5KR",
+};
+
+/// Median time per iteration each benchmark is expected to stay under
+const THRESHOLDS: [(&str, Duration); 6] = [
+    ("header/decode", Duration::from_micros(10)),
+    ("header/encode", Duration::from_micros(10)),
+    ("canonical/message", Duration::from_micros(1)),
+    ("canonical/signing_hash", Duration::from_micros(2)),
+    ("ed25519/verify", Duration::from_micros(150)),
+    ("verify_locally", Duration::from_micros(200)),
+];
+
+fn main() -> ExitCode {
+    let started = SystemTime::now();
+    let strict = env::var_os("X402_BENCH_STRICT").is_some();
+
+    // Parses cargo's `--bench` and the name filter
+    let mut criterion = Criterion::default().configure_from_args();
+    benchmarks(&mut criterion);
+    criterion.final_summary();
+
+    // Benchmarks filtered out keep the estimates of an earlier run
+    let mut regressions = 0;
+    for (name, threshold) in THRESHOLDS {
+        let estimates = criterion_home().join(name).join("new/estimates.json");
+        let measured = fs::metadata(&estimates)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified >= started);
+        if !measured {
+            continue;
+        }
+        let estimates: Value = serde_json::from_slice(&fs::read(&estimates).unwrap()).unwrap();
+        let median = estimates["median"]["point_estimate"].as_f64().unwrap();
+        let regressed = median > threshold.as_nanos() as f64;
+        println!(
+            "{name:<32} {median:>10.0} ns/iter   (threshold {:>8} ns){}",
+            threshold.as_nanos(),
+            if regressed { "   REGRESSION" } else { "" }
+        );
+        regressions += usize::from(regressed);
+    }
+
+    if regressions > 0 && strict {
+        eprintln!("{regressions} benchmark(s) above their threshold");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn benchmarks(c: &mut Criterion) {
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let mut payload = EscrowPayload {
+        escrow_id: 7,
+        client: ed25519::PublicKey(key.verifying_key().to_bytes()).to_string(),
+        amount: "100000".into(),
+        nonce: 42,
+        expires_at: 1_900_000_000,
+        signature: String::new(),
+        deposit_authorization: None,
+        payment_id: None,
+        direct_authorization: None,
+    };
+    payload.signature = hex::encode(key.sign(&payload.signing_hash(NETWORK, DOMAIN)).to_bytes());
+    let header = encode_payment_header(&PaymentPayload {
+        x402_version: X402_VERSION,
+        scheme: ESCROW_SCHEME.into(),
+        network: NETWORK.into(),
+        asset: None,
+        payload: SchemePayload::Escrow(payload.clone()),
+        extensions: Default::default(),
+    });
+
+    let mut group = c.benchmark_group("header");
+    group.bench_function("decode", |b| {
+        b.iter(|| decode_payment_header(black_box(&header)).unwrap())
+    });
+    group.bench_function("encode", |b| {
+        b.iter(|| {
+            encode_payment_header(black_box(&PaymentPayload {
+                x402_version: X402_VERSION,
+                scheme: ESCROW_SCHEME.into(),
+                network: NETWORK.into(),
+                asset: None,
+                payload: SchemePayload::Escrow(payload.clone()),
+                extensions: Default::default(),
+            }))
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("canonical");
+    group.bench_function("message", |b| {
+        b.iter(|| {
+            let mut buf = [0; 256];
+            let mut sink = SliceSink::new(&mut buf);
+            black_box(&payload)
+                .authorization(NETWORK, DOMAIN)
+                .encode(&mut sink);
+            black_box(sink.written());
+        })
+    });
+    group.bench_function("signing_hash", |b| {
+        b.iter(|| black_box(&payload).signing_hash(NETWORK, DOMAIN))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("ed25519");
+    group.bench_function("verify", |b| {
+        b.iter(|| verify_authorization(black_box(&payload), NETWORK, DOMAIN).unwrap())
+    });
+    group.finish();
+
+    // Everything the facilitator does before its escrow lookup
+    c.bench_function("verify_locally", |b| {
+        b.iter(|| {
+            let decoded = decode_payment_header(black_box(&header)).unwrap();
+            let SchemePayload::Escrow(escrow) = decoded.payload else {
+                unreachable!("escrow payload")
+            };
+            assert!(StellarAmount::from_stroops_str(&escrow.amount)
+                .unwrap()
+                .is_positive());
+            verify_authorization(&escrow, &decoded.network, DOMAIN).unwrap();
+        })
+    });
+}
+
+/// Directory criterion saves its estimates in, as criterion finds it
+fn criterion_home() -> PathBuf {
+    if let Some(home) = env::var_os("CRITERION_HOME") {
+        return home.into();
+    }
+    let target = env::var_os("CARGO_TARGET_DIR").map_or_else(
+        || PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../target"),
+        PathBuf::from,
+    );
+    target.join("criterion")
+}
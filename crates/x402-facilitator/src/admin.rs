@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use x402_client::{Error as ClientError, Payment};
+
+use crate::{QueueError, SettlementJob};
+
+/// Error of an operator action on the settlement queue or signing key
+#[derive(Debug, thiserror::Error)]
+pub enum AdminError {
+    #[error("no settlement queue is configured")]
+    NoQueue,
+    #[error("settlement job {0} not found")]
+    JobNotFound(i64),
+    #[error("settlement job {0} is being processed")]
+    JobBusy(i64),
+    #[error("settlement job {0} is already settled")]
+    JobSettled(i64),
+    #[error("settlement job {0} has a transaction in flight")]
+    JobInFlight(i64),
+    #[error("signing key signs for {0}, not the facilitator's server")]
+    WrongServer(String),
+    #[error(transparent)]
+    Queue(#[from] QueueError),
+    #[error(transparent)]
+    Client(#[from] ClientError),
+}
+
+/// Settlement job as listed by the admin endpoints
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobSummary {
+    pub id: i64,
+    pub escrow_id: u64,
+    pub nonce: u64,
+    pub client: String,
+    /// Amount to charge, in stroops
+    pub amount: String,
+    pub asset: String,
+    /// "queued", "created", "settled", or "failed"
+    pub state: String,
+    pub payment_id: Option<u64>,
+    pub tx_hash: Option<String>,
+    /// Hash of the transaction saved before submission, which may still apply
+    pub pending_tx_hash: Option<String>,
+    pub attempts: u32,
+    /// Unix time in milliseconds before which the job is not retried
+    pub next_attempt_at: u64,
+    pub error: Option<String>,
+    pub batch_id: Option<i64>,
+    /// Unix time in milliseconds the job was queued at
+    pub enqueued_at: u64,
+}
+
+impl From<&SettlementJob> for JobSummary {
+    fn from(job: &SettlementJob) -> Self {
+        Self {
+            id: job.id,
+            escrow_id: job.escrow_id,
+            nonce: job.nonce,
+            client: job.client.clone(),
+            amount: job.amount.to_string(),
+            asset: job.asset.clone(),
+            state: job.state.as_str().into(),
+            payment_id: job.payment_id,
+            tx_hash: job.tx_hash.clone(),
+            pending_tx_hash: job.pending_tx.as_ref().map(|tx| tx.hash.clone()),
+            attempts: job.attempts,
+            next_attempt_at: job.next_attempt_at,
+            error: job.error.clone(),
+            batch_id: job.batch_id,
+            enqueued_at: job.enqueued_at,
+        }
+    }
+}
+
+/// On-chain payment, with the settlement job that created it if known
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentRecord {
+    pub payment_id: u64,
+    pub escrow_id: u64,
+    /// Amount, in stroops
+    pub amount: String,
+    pub settled: bool,
+    /// Ledger timestamp the payment was created at
+    pub timestamp: u64,
+    /// Worth of the amount in `quote_currency` when settled, in cents
+    pub quote_amount: Option<String>,
+    pub quote_currency: Option<String>,
+    pub job_id: Option<i64>,
+}
+
+impl PaymentRecord {
+    pub(crate) fn new(payment_id: u64, payment: &Payment, job_id: Option<i64>) -> Self {
+        Self {
+            payment_id,
+            escrow_id: payment.escrow_id,
+            amount: payment.amount.to_string(),
+            settled: payment.settled,
+            timestamp: payment.timestamp,
+            quote_amount: payment.quote_amount.map(|cents| cents.to_string()),
+            quote_currency: payment.quote_currency.clone(),
+            job_id,
+        }
+    }
+}
+
+/// Amount settled in an asset, since the facilitator started
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeEntry {
+    /// Tenant of a multi-tenant facilitator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub asset: String,
+    /// Amount, in stroops
+    pub amount: String,
+}
+
+/// Settlement job the chain contradicts
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Discrepancy {
+    pub job_id: i64,
+    pub payment_id: u64,
+    /// "missing_on_chain" if the escrow lists no such payment,
+    /// "unsettled_on_chain" if the job is settled but the payment is not
+    pub reason: String,
+}
+
+/// Outcome of a reconciliation of the settlement queue with on-chain state
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reconciliation {
+    /// Escrows whose on-chain payments were read, those of queued jobs and
+    /// the open escrows of the server
+    pub escrows_checked: usize,
+    /// Jobs with an on-chain payment compared
+    pub jobs_checked: usize,
+    /// On-chain payments of the queue's escrows read
+    pub payments_checked: usize,
+    /// Unfinished or failed jobs marked settled, their payment being settled
+    /// on-chain
+    pub repaired: Vec<i64>,
+    /// Jobs the chain contradicts, left for an operator
+    pub discrepancies: Vec<Discrepancy>,
+    /// On-chain payments of the queue's escrows created by no job, e.g. by
+    /// an attempt whose job was lost, or by another facilitator
+    pub orphaned: Vec<PaymentRecord>,
+}
@@ -0,0 +1,65 @@
+use std::fmt;
+#[cfg(any(test, feature = "chaos"))]
+use std::{collections::HashSet, sync::Mutex};
+
+/// Stage of the settlement pipeline, see [`crate::Facilitator::settle`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// The payment passed verification, nothing recorded yet
+    Verify,
+    /// The settlement was persisted: its job queued, or the nonce or
+    /// transaction of a settlement taking no job marked used
+    Persist,
+    /// The payment was created on-chain, not yet recorded by its job
+    Submit,
+    /// The settlement applied on-chain, not yet recorded
+    Confirm,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Verify => "verify",
+            Self::Persist => "persist",
+            Self::Submit => "submit",
+            Self::Confirm => "confirm",
+        })
+    }
+}
+
+/// Faults injected into the settlement pipeline, to test its recovery
+///
+/// A fault armed for a stage crashes the next settlement completing it:
+/// the task panics right there, as if the process died, leaving what was
+/// persisted so far to the next facilitator. Each fault trips once.
+#[cfg(any(test, feature = "chaos"))]
+#[derive(Debug, Default)]
+pub struct Faults {
+    armed: Mutex<HashSet<Stage>>,
+}
+
+#[cfg(any(test, feature = "chaos"))]
+impl Faults {
+    /// Create a set of faults, none armed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Crash the next settlement completing `stage`
+    pub fn crash_after(&self, stage: Stage) {
+        self.armed.lock().unwrap().insert(stage);
+    }
+
+    /// Whether a fault armed for `stage` did not trip yet
+    pub fn is_armed(&self, stage: Stage) -> bool {
+        self.armed.lock().unwrap().contains(&stage)
+    }
+
+    /// Crash if a fault is armed for `stage`, disarming it
+    pub(crate) fn reached(&self, stage: Stage) {
+        let tripped = self.armed.lock().unwrap().remove(&stage);
+        if tripped {
+            panic!("fault injected after {stage}");
+        }
+    }
+}
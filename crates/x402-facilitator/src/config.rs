@@ -0,0 +1,695 @@
+use std::{env, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+
+use x402_client::{EscrowClient, FeeBumpPolicy, FinalityPolicy, HttpTransport, LocalSigner, Rpc};
+use x402_types::{network_passphrase, FeePolicy, STELLAR_TESTNET};
+
+use crate::{
+    BatchPolicy, Degradation, DirectPayments, Endpoint, EventKind, PendingDeposits, RateLimit,
+    ReadinessPolicy, RedisRateLimiter, RedisReplayCache, TenantError, Tenants, Webhooks,
+    DEFAULT_CONFIRMATIONS,
+};
+
+/// Default address the facilitator listens on
+pub const DEFAULT_BIND: &str = "127.0.0.1:4020";
+
+/// Default delay between reconciliations of the settlement queue
+pub const DEFAULT_RECONCILE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Default longest wait for in-flight settlements on shutdown
+pub const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+/// Error reading the facilitator configuration
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("missing environment variable {0}")]
+    Missing(&'static str),
+    #[error("invalid {name}: {message}")]
+    Invalid { name: &'static str, message: String },
+}
+
+/// Facilitator configuration
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Address to listen on
+    pub bind: SocketAddr,
+    /// Soroban RPC URL
+    pub rpc_url: String,
+    /// Escrow contract address (C... format)
+    pub contract_id: String,
+    /// x402 network id (e.g., "stellar-testnet")
+    pub network: String,
+    /// Stellar network passphrase
+    pub network_passphrase: String,
+    /// Server secret key (S... format), signs settlement transactions
+    ///
+    /// Unset when the facilitator serves tenants with their own keys.
+    pub server_secret: Option<String>,
+    /// Secret key (S... format) of the account paying fee bumps of
+    /// settlement transactions stuck pending, never bumping fees when unset
+    pub fee_bump_secret: Option<String>,
+    /// When and by how much fees of stuck settlement transactions are bumped
+    pub fee_bump: FeeBumpPolicy,
+    /// Settings that may be changed at runtime
+    pub settings: Settings,
+    /// Webhook endpoints notified of settlement outcomes
+    pub webhooks: Vec<Endpoint>,
+    /// File dead-lettered webhook deliveries are appended to
+    pub webhook_dead_letter_log: Option<PathBuf>,
+    /// Bearer token of the admin endpoints, disabled when unset
+    pub admin_token: Option<String>,
+    /// SQLite database of the settlement queue, settling inline when unset
+    pub settlement_queue: Option<PathBuf>,
+    /// Batching of queued settlements, one transaction per payment when unset
+    pub batching: Option<BatchPolicy>,
+    /// Delay between reconciliations of the settlement queue with on-chain
+    /// state, never reconciling on its own when unset
+    pub reconcile_interval: Option<Duration>,
+    /// Longest wait for in-flight settlements once asked to shut down
+    pub shutdown_grace: Duration,
+    /// Confirmation depth settlements are final at, finality being left
+    /// untracked when unset
+    pub finality: Option<FinalityPolicy>,
+    /// Crediting of escrows with plain transfers into their deposit
+    /// addresses, disabled when unset
+    pub pending_deposits: Option<PendingDeposits>,
+    /// Redis URL of the replay cache and rate limiter, kept in memory when
+    /// unset
+    pub redis_url: Option<String>,
+    /// JSON file of the tenants of a multi-tenant facilitator
+    pub tenants: Option<PathBuf>,
+}
+
+/// Facilitator settings that may change while it runs
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Settings {
+    /// Accepted asset contract addresses (C... format)
+    pub assets: Vec<String>,
+    /// Fee policy advertised on /supported
+    pub fee: FeePolicy,
+    /// Token bucket of each client of /verify, /settle, and /preflight,
+    /// unlimited if unset
+    pub rate_limit: Option<RateLimit>,
+    /// Acceptance of direct payments ("exact" scheme), escrow payments only
+    /// if unset
+    pub direct_payments: Option<DirectPayments>,
+    /// Simulate every settlement without submitting it, as if each request
+    /// asked for a dry run
+    pub dry_run: bool,
+    /// Handling of escrow payments while RPC is unreachable
+    pub degradation: Degradation,
+    /// Thresholds of the readiness checks of /readyz
+    pub readiness: ReadinessPolicy,
+    /// How long after they expire, by ledger time, escrow authorizations
+    /// are still accepted, matching the contract's `get_clock_skew`
+    pub clock_skew: Duration,
+    /// Largest escrow payment, in stroops, settled by the direct payment
+    /// its client pre-signed when the escrow does not exist, never if unset
+    pub direct_fallback: Option<i128>,
+}
+
+impl Settings {
+    /// Read the settings from the environment
+    ///
+    /// # Variables
+    /// * `X402_ASSETS` - Comma-separated asset contract addresses
+    /// * `X402_FEE_BPS` - Facilitator fee in basis points (default 0)
+    /// * `X402_RATE_LIMIT_PER_MINUTE` - Requests per minute of each client,
+    ///   enabling rate limiting
+    /// * `X402_RATE_LIMIT_BURST` - Requests allowed in a burst (default the
+    ///   per-minute rate)
+    /// * `X402_DIRECT_CONFIRMATIONS` - Ledgers a direct payment must be
+    ///   confirmed by, enabling direct payments (default 1 with
+    ///   `X402_DIRECT_MAX_AGE_SECS`)
+    /// * `X402_DIRECT_MAX_AGE_SECS` - Longest a direct payment is accepted
+    ///   after it closed, enabling direct payments (default 3600 with
+    ///   `X402_DIRECT_CONFIRMATIONS`)
+    /// * `X402_DRY_RUN` - `true` to simulate settlements without submitting
+    ///   them (default `false`)
+    /// * `X402_DEGRADED_MAX_CREDIT` - Most credit, in stroops, outstanding
+    ///   per escrow while RPC is unreachable, serving and queueing payments
+    ///   rather than failing closed
+    /// * `X402_READY_MAX_QUEUE_DEPTH` - Most unfinished settlement jobs of a
+    ///   ready facilitator (default 1000)
+    /// * `X402_READY_TIMEOUT_MS` - Longest each readiness check may take
+    ///   (default 2000)
+    /// * `X402_CLOCK_SKEW_SECS` - Seconds escrow authorizations are accepted
+    ///   after they expire by ledger time (default 0)
+    /// * `X402_DIRECT_FALLBACK_MAX` - Largest escrow payment, in stroops,
+    ///   settled by its pre-signed direct payment when the escrow does not
+    ///   exist, enabling the fallback
+    ///
+    /// # Errors
+    /// * `Invalid` - If a variable cannot be parsed
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let assets = optional("X402_ASSETS")
+            .map(|assets| {
+                assets
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|asset| !asset.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let fee_bps = optional("X402_FEE_BPS")
+            .map(|bps| bps.parse())
+            .transpose()
+            .map_err(|e: std::num::ParseIntError| ConfigError::Invalid {
+                name: "X402_FEE_BPS",
+                message: e.to_string(),
+            })?
+            .unwrap_or_default();
+        let dry_run = optional("X402_DRY_RUN")
+            .map(|dry_run| dry_run.parse())
+            .transpose()
+            .map_err(|e: std::str::ParseBoolError| ConfigError::Invalid {
+                name: "X402_DRY_RUN",
+                message: e.to_string(),
+            })?
+            .unwrap_or_default();
+        let clock_skew = optional("X402_CLOCK_SKEW_SECS")
+            .map(|secs| secs.parse().map(Duration::from_secs))
+            .transpose()
+            .map_err(|e: std::num::ParseIntError| ConfigError::Invalid {
+                name: "X402_CLOCK_SKEW_SECS",
+                message: e.to_string(),
+            })?
+            .unwrap_or_default();
+
+        Ok(Self {
+            assets,
+            fee: FeePolicy {
+                fee_bps,
+                // Settlement transactions are submitted by the facilitator
+                network_fee_sponsored: true,
+            },
+            rate_limit: rate_limit()?,
+            direct_payments: direct_payments()?,
+            dry_run,
+            degradation: degradation()?,
+            readiness: readiness_policy()?,
+            clock_skew,
+            direct_fallback: direct_fallback()?,
+        })
+    }
+}
+
+impl Config {
+    /// Read the configuration from the environment
+    ///
+    /// # Variables
+    /// * `X402_BIND` - Listen address (default `127.0.0.1:4020`)
+    /// * `X402_RPC_URL` - Soroban RPC URL
+    /// * `X402_CONTRACT_ID` - Escrow contract address
+    /// * `X402_NETWORK` - x402 network id (default `stellar-testnet`)
+    /// * `X402_NETWORK_PASSPHRASE` - Passphrase, required for unknown networks
+    /// * `X402_SERVER_SECRET` - Server secret key, not needed with
+    ///   `X402_TENANTS`
+    /// * `X402_FEE_BUMP_SECRET` - Secret key of the account paying fee bumps,
+    ///   enabling fee bumping of settlement transactions stuck pending
+    /// * `X402_FEE_BUMP_AFTER_SECS` - Longest a settlement transaction stays
+    ///   pending before its fee is bumped (default 10)
+    /// * `X402_FEE_BUMP_MAX_FEE` - Most a fee bump pays, in stroops (default
+    ///   10000000)
+    /// * `X402_WEBHOOKS` - Comma-separated webhook URLs, each optionally
+    ///   prefixed by `|`-separated event types and `=`
+    ///   (e.g. `payment.failed=https://ops.example.com/hook`)
+    /// * `X402_WEBHOOK_SECRET` - HMAC key, required with `X402_WEBHOOKS`
+    /// * `X402_WEBHOOK_DEAD_LETTER` - File dead-lettered deliveries are
+    ///   appended to
+    /// * `X402_ADMIN_TOKEN` - Bearer token enabling the admin endpoints
+    /// * `X402_SETTLEMENT_QUEUE` - SQLite file of the durable settlement queue,
+    ///   required by batching and `X402_DEGRADED_MAX_CREDIT`
+    /// * `X402_BATCH_SIZE` - Payments settled per batch, enabling batching of
+    ///   queued settlements (default 50 with `X402_BATCH_DELAY_SECS`)
+    /// * `X402_BATCH_DELAY_SECS` - Longest a payment waits for its batch,
+    ///   enabling batching (default 10 with `X402_BATCH_SIZE`)
+    /// * `X402_RECONCILE_INTERVAL_SECS` - Delay between reconciliations of
+    ///   the settlement queue, 0 to disable them (default 300)
+    /// * `X402_SHUTDOWN_GRACE_SECS` - Longest wait for in-flight settlements
+    ///   on SIGTERM or Ctrl-C (default 30)
+    /// * `X402_FINALITY_DEPTH` - Ledgers closing after a settlement's before
+    ///   it is final, enabling finality tracking
+    /// * `X402_FINALITY_FINAL_UP_TO` - Largest settlement, in stroops, final
+    ///   as soon as it is included
+    /// * `X402_DEPOSIT_ASSET` - Asset escrow balances are held in, `native`
+    ///   or a Stellar Asset Contract address, enabling pending deposits
+    /// * `X402_DEPOSIT_CONFIRMATIONS` - Ledgers a transfer into a deposit
+    ///   address must be confirmed by (default 1)
+    /// * `X402_REDIS_URL` - Redis shared by replicas as the replay cache and
+    ///   rate limiter
+    ///   (e.g. `redis://:password@localhost:6379/0`)
+    /// * `X402_TENANTS` - JSON file of [`crate::TenantConfig`]s, serving
+    ///   many resource servers rather than one
+    /// * Variables of [`Settings::from_env`]
+    ///
+    /// # Errors
+    /// * `Missing` - If a required variable is not set
+    /// * `Invalid` - If a variable cannot be parsed
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let bind = optional("X402_BIND")
+            .unwrap_or_else(|| DEFAULT_BIND.into())
+            .parse()
+            .map_err(|e: std::net::AddrParseError| ConfigError::Invalid {
+                name: "X402_BIND",
+                message: e.to_string(),
+            })?;
+        let network = optional("X402_NETWORK").unwrap_or_else(|| STELLAR_TESTNET.into());
+        let network_passphrase = match optional("X402_NETWORK_PASSPHRASE") {
+            Some(passphrase) => passphrase,
+            None => network_passphrase(&network)
+                .ok_or(ConfigError::Missing("X402_NETWORK_PASSPHRASE"))?
+                .into(),
+        };
+
+        let tenants = optional("X402_TENANTS").map(PathBuf::from);
+        let server_secret = match tenants {
+            Some(_) => optional("X402_SERVER_SECRET"),
+            None => Some(required("X402_SERVER_SECRET")?),
+        };
+        let settlement_queue = optional("X402_SETTLEMENT_QUEUE").map(PathBuf::from);
+        if tenants.is_some() && settlement_queue.is_some() {
+            return Err(ConfigError::Invalid {
+                name: "X402_SETTLEMENT_QUEUE",
+                message: "not supported with X402_TENANTS".into(),
+            });
+        }
+        let batching = batch_policy()?;
+        let reconcile_interval = optional("X402_RECONCILE_INTERVAL_SECS")
+            .map(|secs| secs.parse().map(Duration::from_secs))
+            .transpose()
+            .map_err(|e: std::num::ParseIntError| ConfigError::Invalid {
+                name: "X402_RECONCILE_INTERVAL_SECS",
+                message: e.to_string(),
+            })?
+            .unwrap_or(DEFAULT_RECONCILE_INTERVAL);
+        let shutdown_grace = optional("X402_SHUTDOWN_GRACE_SECS")
+            .map(|secs| secs.parse().map(Duration::from_secs))
+            .transpose()
+            .map_err(|e: std::num::ParseIntError| ConfigError::Invalid {
+                name: "X402_SHUTDOWN_GRACE_SECS",
+                message: e.to_string(),
+            })?
+            .unwrap_or(DEFAULT_SHUTDOWN_GRACE);
+        let settings = Settings::from_env()?;
+        let queued = batching.is_some() || settings.degradation != Degradation::FailClosed;
+        if queued && settlement_queue.is_none() {
+            return Err(ConfigError::Missing("X402_SETTLEMENT_QUEUE"));
+        }
+
+        let config = Self {
+            bind,
+            rpc_url: required("X402_RPC_URL")?,
+            contract_id: required("X402_CONTRACT_ID")?,
+            network,
+            network_passphrase,
+            server_secret,
+            fee_bump_secret: optional("X402_FEE_BUMP_SECRET"),
+            fee_bump: fee_bump_policy()?,
+            settings,
+            webhooks: webhook_endpoints()?,
+            webhook_dead_letter_log: optional("X402_WEBHOOK_DEAD_LETTER").map(PathBuf::from),
+            admin_token: optional("X402_ADMIN_TOKEN"),
+            settlement_queue,
+            batching,
+            reconcile_interval: (!reconcile_interval.is_zero()).then_some(reconcile_interval),
+            shutdown_grace,
+            finality: finality_policy()?,
+            pending_deposits: pending_deposits()?,
+            redis_url: optional("X402_REDIS_URL"),
+            tenants,
+        };
+        // Malformed URLs fail at startup rather than on the first /verify
+        config.replay_cache()?;
+        Ok(config)
+    }
+
+    /// Build the Redis rate limiter, sharing the replay cache's Redis
+    ///
+    /// # Returns
+    /// * None if no Redis URL is configured
+    ///
+    /// # Errors
+    /// * `Invalid` - If the Redis URL is malformed
+    pub fn rate_limiter(&self) -> Result<Option<RedisRateLimiter>, ConfigError> {
+        self.redis_url
+            .as_deref()
+            .map(RedisRateLimiter::new)
+            .transpose()
+            .map_err(|e| ConfigError::Invalid {
+                name: "X402_REDIS_URL",
+                message: e.to_string(),
+            })
+    }
+
+    /// Build the Redis replay cache
+    ///
+    /// # Returns
+    /// * None if no Redis URL is configured
+    ///
+    /// # Errors
+    /// * `Invalid` - If the Redis URL is malformed
+    pub fn replay_cache(&self) -> Result<Option<RedisReplayCache>, ConfigError> {
+        self.redis_url
+            .as_deref()
+            .map(RedisReplayCache::new)
+            .transpose()
+            .map_err(|e| ConfigError::Invalid {
+                name: "X402_REDIS_URL",
+                message: e.to_string(),
+            })
+    }
+
+    /// Build the webhook dispatcher
+    ///
+    /// # Returns
+    /// * None if no endpoint is configured
+    pub fn webhooks(&self) -> Option<Webhooks> {
+        if self.webhooks.is_empty() {
+            return None;
+        }
+        let webhooks = Webhooks::new(self.webhooks.clone());
+        Some(match &self.webhook_dead_letter_log {
+            Some(path) => webhooks.with_dead_letter_log(path),
+            None => webhooks,
+        })
+    }
+
+    /// Load the tenants of a multi-tenant facilitator
+    ///
+    /// # Returns
+    /// * None if no tenants file is configured
+    ///
+    /// # Errors
+    /// * `Invalid` - If the tenants file cannot be read or a tenant is invalid
+    pub fn tenants(&self) -> Result<Option<Tenants>, ConfigError> {
+        let Some(path) = &self.tenants else {
+            return Ok(None);
+        };
+        let rpc = Rpc::new(HttpTransport::new(&self.rpc_url));
+        let mut tenants = Tenants::new(
+            rpc,
+            &self.contract_id,
+            &self.network,
+            &self.network_passphrase,
+        );
+        if let Some(cache) = self.replay_cache()? {
+            tenants = tenants.with_replay_cache(Arc::new(cache));
+        }
+        if let Some(limiter) = self.rate_limiter()? {
+            tenants = tenants.with_rate_limiter(Arc::new(limiter));
+        }
+        tenants
+            .with_file(path)
+            .map(Some)
+            .map_err(|e: TenantError| ConfigError::Invalid {
+                name: "X402_TENANTS",
+                message: e.to_string(),
+            })
+    }
+
+    /// Build the escrow client signing with the server key, and fee-bumping
+    /// with the fee bump key if one is configured
+    ///
+    /// # Errors
+    /// * `Missing` - If no server secret is configured
+    /// * `Invalid` - If the contract ID or a secret is malformed
+    pub fn escrow_client(&self) -> Result<EscrowClient, ConfigError> {
+        let secret = self
+            .server_secret
+            .as_deref()
+            .ok_or(ConfigError::Missing("X402_SERVER_SECRET"))?;
+        let signer = LocalSigner::from_secret(secret).map_err(|e| ConfigError::Invalid {
+            name: "X402_SERVER_SECRET",
+            message: e.to_string(),
+        })?;
+        let rpc = Rpc::new(HttpTransport::new(&self.rpc_url));
+        let client = EscrowClient::new(rpc, &self.contract_id, &self.network_passphrase, signer)
+            .map_err(|e| ConfigError::Invalid {
+                name: "X402_CONTRACT_ID",
+                message: e.to_string(),
+            })?;
+        let Some(secret) = &self.fee_bump_secret else {
+            return Ok(client);
+        };
+        let fee_account = LocalSigner::from_secret(secret).map_err(|e| ConfigError::Invalid {
+            name: "X402_FEE_BUMP_SECRET",
+            message: e.to_string(),
+        })?;
+        Ok(client.with_fee_bump(fee_account, self.fee_bump.clone()))
+    }
+}
+
+fn rate_limit() -> Result<Option<RateLimit>, ConfigError> {
+    let number = |name: &'static str| {
+        optional(name)
+            .map(|value| value.parse::<u32>())
+            .transpose()
+            .map_err(|e| ConfigError::Invalid {
+                name,
+                message: e.to_string(),
+            })
+    };
+    let Some(per_minute) = number("X402_RATE_LIMIT_PER_MINUTE")? else {
+        return Ok(None);
+    };
+    let burst = number("X402_RATE_LIMIT_BURST")?.unwrap_or(per_minute);
+    for (name, value) in [
+        ("X402_RATE_LIMIT_PER_MINUTE", per_minute),
+        ("X402_RATE_LIMIT_BURST", burst),
+    ] {
+        if value == 0 {
+            return Err(ConfigError::Invalid {
+                name,
+                message: "must be at least 1".into(),
+            });
+        }
+    }
+    Ok(Some(RateLimit { burst, per_minute }))
+}
+
+fn degradation() -> Result<Degradation, ConfigError> {
+    let name = "X402_DEGRADED_MAX_CREDIT";
+    let Some(max_credit) = optional(name) else {
+        return Ok(Degradation::FailClosed);
+    };
+    match max_credit.parse::<i128>() {
+        Ok(max_credit) if max_credit > 0 => Ok(Degradation::ServeAndQueue { max_credit }),
+        Ok(_) => Err(ConfigError::Invalid {
+            name,
+            message: "must be at least 1".into(),
+        }),
+        Err(e) => Err(ConfigError::Invalid {
+            name,
+            message: e.to_string(),
+        }),
+    }
+}
+
+fn direct_fallback() -> Result<Option<i128>, ConfigError> {
+    let name = "X402_DIRECT_FALLBACK_MAX";
+    let Some(max) = optional(name) else {
+        return Ok(None);
+    };
+    match max.parse::<i128>() {
+        Ok(max) if max > 0 => Ok(Some(max)),
+        Ok(_) => Err(ConfigError::Invalid {
+            name,
+            message: "must be at least 1".into(),
+        }),
+        Err(e) => Err(ConfigError::Invalid {
+            name,
+            message: e.to_string(),
+        }),
+    }
+}
+
+fn readiness_policy() -> Result<ReadinessPolicy, ConfigError> {
+    let defaults = ReadinessPolicy::default();
+    let max_queue_depth = optional("X402_READY_MAX_QUEUE_DEPTH")
+        .map(|depth| depth.parse())
+        .transpose()
+        .map_err(|e: std::num::ParseIntError| ConfigError::Invalid {
+            name: "X402_READY_MAX_QUEUE_DEPTH",
+            message: e.to_string(),
+        })?
+        .unwrap_or(defaults.max_queue_depth);
+    let timeout = optional("X402_READY_TIMEOUT_MS")
+        .map(|ms| ms.parse().map(Duration::from_millis))
+        .transpose()
+        .map_err(|e: std::num::ParseIntError| ConfigError::Invalid {
+            name: "X402_READY_TIMEOUT_MS",
+            message: e.to_string(),
+        })?
+        .unwrap_or(defaults.timeout);
+    Ok(ReadinessPolicy {
+        max_queue_depth,
+        timeout,
+    })
+}
+
+fn direct_payments() -> Result<Option<DirectPayments>, ConfigError> {
+    let confirmations = optional("X402_DIRECT_CONFIRMATIONS")
+        .map(|value| value.parse::<u32>())
+        .transpose()
+        .map_err(|e| ConfigError::Invalid {
+            name: "X402_DIRECT_CONFIRMATIONS",
+            message: e.to_string(),
+        })?;
+    let max_age_secs = optional("X402_DIRECT_MAX_AGE_SECS")
+        .map(|value| value.parse::<u64>())
+        .transpose()
+        .map_err(|e| ConfigError::Invalid {
+            name: "X402_DIRECT_MAX_AGE_SECS",
+            message: e.to_string(),
+        })?;
+    if confirmations.is_none() && max_age_secs.is_none() {
+        return Ok(None);
+    }
+    if max_age_secs == Some(0) {
+        return Err(ConfigError::Invalid {
+            name: "X402_DIRECT_MAX_AGE_SECS",
+            message: "must be at least 1".into(),
+        });
+    }
+    let default = DirectPayments::default();
+    Ok(Some(DirectPayments {
+        confirmations: confirmations.unwrap_or(default.confirmations),
+        max_age_secs: max_age_secs.unwrap_or(default.max_age_secs),
+    }))
+}
+
+fn batch_policy() -> Result<Option<BatchPolicy>, ConfigError> {
+    let number = |name: &'static str| {
+        optional(name)
+            .map(|value| value.parse::<u64>())
+            .transpose()
+            .map_err(|e| ConfigError::Invalid {
+                name,
+                message: e.to_string(),
+            })
+    };
+    let size = number("X402_BATCH_SIZE")?;
+    let delay = number("X402_BATCH_DELAY_SECS")?;
+    if size.is_none() && delay.is_none() {
+        return Ok(None);
+    }
+    if size == Some(0) {
+        return Err(ConfigError::Invalid {
+            name: "X402_BATCH_SIZE",
+            message: "must be at least 1".into(),
+        });
+    }
+    let default = BatchPolicy::default();
+    Ok(Some(BatchPolicy {
+        max_size: size.map_or(default.max_size, |size| size as usize),
+        max_delay: delay.map_or(default.max_delay, Duration::from_secs),
+    }))
+}
+
+fn finality_policy() -> Result<Option<FinalityPolicy>, ConfigError> {
+    let Some(depth) = optional("X402_FINALITY_DEPTH") else {
+        return Ok(None);
+    };
+    let mut policy = FinalityPolicy::new(depth.parse().map_err(|e: std::num::ParseIntError| {
+        ConfigError::Invalid {
+            name: "X402_FINALITY_DEPTH",
+            message: e.to_string(),
+        }
+    })?);
+    if let Some(amount) = optional("X402_FINALITY_FINAL_UP_TO") {
+        policy = policy.final_up_to(amount.parse().map_err(|e: std::num::ParseIntError| {
+            ConfigError::Invalid {
+                name: "X402_FINALITY_FINAL_UP_TO",
+                message: e.to_string(),
+            }
+        })?);
+    }
+    Ok(Some(policy))
+}
+
+fn pending_deposits() -> Result<Option<PendingDeposits>, ConfigError> {
+    let Some(asset) = optional("X402_DEPOSIT_ASSET") else {
+        return Ok(None);
+    };
+    let confirmations = optional("X402_DEPOSIT_CONFIRMATIONS")
+        .map(|value| value.parse())
+        .transpose()
+        .map_err(|e: std::num::ParseIntError| ConfigError::Invalid {
+            name: "X402_DEPOSIT_CONFIRMATIONS",
+            message: e.to_string(),
+        })?
+        .unwrap_or(DEFAULT_CONFIRMATIONS);
+    Ok(Some(PendingDeposits {
+        asset,
+        confirmations,
+    }))
+}
+
+fn fee_bump_policy() -> Result<FeeBumpPolicy, ConfigError> {
+    let default = FeeBumpPolicy::default();
+    let after = optional("X402_FEE_BUMP_AFTER_SECS")
+        .map(|secs| secs.parse().map(Duration::from_secs))
+        .transpose()
+        .map_err(|e: std::num::ParseIntError| ConfigError::Invalid {
+            name: "X402_FEE_BUMP_AFTER_SECS",
+            message: e.to_string(),
+        })?
+        .unwrap_or(default.after);
+    let max_fee = optional("X402_FEE_BUMP_MAX_FEE")
+        .map(|fee| fee.parse())
+        .transpose()
+        .map_err(|e: std::num::ParseIntError| ConfigError::Invalid {
+            name: "X402_FEE_BUMP_MAX_FEE",
+            message: e.to_string(),
+        })?
+        .unwrap_or(default.max_fee);
+    Ok(FeeBumpPolicy {
+        after,
+        max_fee,
+        ..default
+    })
+}
+
+fn webhook_endpoints() -> Result<Vec<Endpoint>, ConfigError> {
+    let Some(urls) = optional("X402_WEBHOOKS") else {
+        return Ok(Vec::new());
+    };
+    let secret = required("X402_WEBHOOK_SECRET")?;
+    urls.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| parse_endpoint(entry, &secret))
+        .collect()
+}
+
+/// Parse `[events=]url`, the event list having no `/` unlike a URL
+pub(crate) fn parse_endpoint(entry: &str, secret: &str) -> Result<Endpoint, ConfigError> {
+    let (events, url) = match entry.split_once('=') {
+        Some((events, url)) if !events.contains('/') => (events, url),
+        _ => ("", entry),
+    };
+    let events = events
+        .split('|')
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            EventKind::parse(name).ok_or_else(|| ConfigError::Invalid {
+                name: "X402_WEBHOOKS",
+                message: format!("unknown event type {name}"),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Endpoint {
+        url: url.into(),
+        secret: secret.into(),
+        events,
+    })
+}
+
+fn optional(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn required(name: &'static str) -> Result<String, ConfigError> {
+    optional(name).ok_or(ConfigError::Missing(name))
+}
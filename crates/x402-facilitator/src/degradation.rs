@@ -0,0 +1,110 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use x402_client::Escrow;
+
+/// Handling of escrow payments while Soroban RPC is unreachable
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Degradation {
+    /// Reject them, as any payment whose escrow cannot be read
+    #[default]
+    FailClosed,
+    /// Accept payloads passing every check that needs no RPC, on credit
+    /// from the escrow's last known state, and queue their settlement
+    ///
+    /// Needs a settlement queue, which settles the payments once RPC is
+    /// back. Escrows not read since the facilitator started are rejected.
+    ServeAndQueue {
+        /// Most credit outstanding per escrow, in stroops, the escrow's
+        /// last known balance capping it too
+        max_credit: i128,
+    },
+}
+
+/// Credit extended to escrows while RPC was unreachable
+///
+/// Credit is extended by payments settled while the escrow could not be
+/// read, and released once their settlement job settles or fails. It is
+/// kept in memory, so a restarted facilitator forgets it while still
+/// settling the queued jobs.
+#[derive(Default)]
+pub(crate) struct CreditBook {
+    /// Last state read of each escrow
+    known: Mutex<HashMap<u64, Escrow>>,
+    /// Amounts of the payments on credit, by escrow then nonce
+    extended: Mutex<HashMap<u64, HashMap<u64, i128>>>,
+}
+
+impl CreditBook {
+    /// Remember the state of an escrow just read
+    pub fn observe(&self, escrow_id: u64, escrow: &Escrow) {
+        self.known.lock().unwrap().insert(escrow_id, escrow.clone());
+    }
+
+    /// Last state read of an escrow
+    pub fn last_known(&self, escrow_id: u64) -> Option<Escrow> {
+        self.known.lock().unwrap().get(&escrow_id).cloned()
+    }
+
+    /// Credit an escrow may still be extended, in stroops
+    pub fn available(&self, escrow_id: u64, max_credit: i128) -> i128 {
+        let outstanding = self.outstanding(escrow_id);
+        self.limit(escrow_id, max_credit) - outstanding
+    }
+
+    /// Extend `amount` of credit to an escrow for the payment of `nonce`
+    ///
+    /// # Returns
+    /// * Whether the credit was extended, false if it exceeds the limit or
+    ///   the nonce is already on credit
+    pub fn extend(&self, escrow_id: u64, nonce: u64, amount: i128, max_credit: i128) -> bool {
+        let limit = self.limit(escrow_id, max_credit);
+        let mut extended = self.extended.lock().unwrap();
+        let payments = extended.entry(escrow_id).or_default();
+        if payments.contains_key(&nonce) || payments.values().sum::<i128>() + amount > limit {
+            return false;
+        }
+        payments.insert(nonce, amount);
+        true
+    }
+
+    /// Release the credit of the payment of `nonce`
+    ///
+    /// # Returns
+    /// * Whether the payment was on credit
+    pub fn release(&self, escrow_id: u64, nonce: u64) -> bool {
+        let mut extended = self.extended.lock().unwrap();
+        let Some(payments) = extended.get_mut(&escrow_id) else {
+            return false;
+        };
+        let released = payments.remove(&nonce).is_some();
+        if payments.is_empty() {
+            extended.remove(&escrow_id);
+        }
+        released
+    }
+
+    /// Credit outstanding on an escrow, in stroops
+    pub fn outstanding(&self, escrow_id: u64) -> i128 {
+        self.extended
+            .lock()
+            .unwrap()
+            .get(&escrow_id)
+            .map_or(0, |payments| payments.values().sum())
+    }
+
+    /// Credit outstanding on every escrow, in stroops
+    pub fn total(&self) -> i128 {
+        self.extended
+            .lock()
+            .unwrap()
+            .values()
+            .flat_map(HashMap::values)
+            .sum()
+    }
+
+    /// Most credit outstanding on an escrow, none if it was never read
+    fn limit(&self, escrow_id: u64, max_credit: i128) -> i128 {
+        self.last_known(escrow_id)
+            .map_or(0, |escrow| escrow.balance.min(max_credit))
+    }
+}
@@ -0,0 +1,336 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use stellar_strkey::{ed25519, Strkey};
+use stellar_xdr::curr::{
+    Asset, ContractIdPreimage, FeeBumpTransactionInnerTx, Hash, HashIdPreimage,
+    HashIdPreimageContractId, Limits, Memo, MuxedAccount, Operation, OperationBody, PaymentOp,
+    ReadXdr, TransactionEnvelope, WriteXdr,
+};
+use x402_client::GetTransactionResponse;
+use x402_types::{PaymentRequirements, StellarAmount, NATIVE_ASSET};
+
+use crate::{facilitator::parse_amount, VerifyError};
+
+/// Default number of ledgers a direct payment must be buried under
+pub const DEFAULT_CONFIRMATIONS: u32 = 1;
+
+/// Default longest time a direct payment may be presented after it closed
+pub const DEFAULT_DIRECT_MAX_AGE_SECS: u64 = 60 * 60;
+
+/// Acceptance of direct payments, classic Stellar payments proven by their
+/// transaction hash ("exact" scheme)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectPayments {
+    /// Ledgers closed since the payment, the including one counted
+    pub confirmations: u32,
+    /// Seconds after the payment closed it stops being accepted, which is
+    /// also how long its hash stays reserved by the replay cache
+    pub max_age_secs: u64,
+}
+
+impl Default for DirectPayments {
+    fn default() -> Self {
+        Self {
+            confirmations: DEFAULT_CONFIRMATIONS,
+            max_age_secs: DEFAULT_DIRECT_MAX_AGE_SECS,
+        }
+    }
+}
+
+/// Crediting of escrows with plain token transfers into their
+/// [deposit addresses](deposit_address), for clients whose custodian can
+/// only send payments
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingDeposits {
+    /// Asset escrow balances are held in, "native" or the address of its
+    /// Stellar Asset Contract (C... format)
+    pub asset: String,
+    /// Ledgers closed since the transfer, the including one counted
+    pub confirmations: u32,
+}
+
+/// Direct payment found in a transaction
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct DirectPayment {
+    /// Source account of the transaction (G... format)
+    pub payer: String,
+    /// Amount paid to `payTo` in the required asset, in stroops
+    pub amount: i128,
+    /// Unix timestamp the payment stops being accepted at
+    pub expires_at: u64,
+}
+
+/// Check a fetched transaction pays for the requirements
+///
+/// The transaction must have succeeded, be buried deep enough, carry the
+/// [payment memo](PaymentRequirements::payment_memo), and its payment
+/// operations to `payTo` in the required asset must add up to the amount.
+///
+/// # Arguments
+/// * `tx` - `getTransaction` response
+/// * `requirements` - Requirements the transaction pays for
+/// * `network_id` - SHA-256 of the network passphrase, to derive asset
+///   contract addresses
+/// * `policy` - Confirmations and lifetime of direct payments
+/// * `now` - Current Unix timestamp
+///
+/// # Errors
+/// * The [`VerifyError`] describing the first failed check
+pub(crate) fn check_transaction(
+    tx: &GetTransactionResponse,
+    requirements: &PaymentRequirements,
+    network_id: &[u8; 32],
+    policy: &DirectPayments,
+    now: u64,
+) -> Result<DirectPayment, VerifyError> {
+    let invalid = |message: &str| VerifyError::Rpc(format!("getTransaction: {message}"));
+    confirmed(tx, policy.confirmations)?;
+    let closed_at: u64 = tx
+        .created_at
+        .as_deref()
+        .and_then(|created_at| created_at.parse().ok())
+        .ok_or_else(|| invalid("missing close time"))?;
+    let expires_at = closed_at.saturating_add(policy.max_age_secs);
+    if expires_at <= now {
+        return Err(VerifyError::Expired);
+    }
+    let envelope = envelope(tx)?;
+    let (source, memo, operations) = contents(&envelope);
+    if *memo != Memo::Hash(Hash(requirements.payment_memo())) {
+        return Err(VerifyError::MemoMismatch);
+    }
+
+    let pay_to = match Strkey::from_string(&requirements.pay_to) {
+        Ok(Strkey::PublicKeyEd25519(key)) => key.0,
+        _ => return Err(VerifyError::RecipientMismatch),
+    };
+    let asset = requirements.asset.as_deref().unwrap_or(NATIVE_ASSET);
+    let (mut amount, mut other_asset) = (StellarAmount::ZERO, false);
+    for payment in operations.iter().filter_map(payment) {
+        if account_key(&payment.destination) != pay_to {
+            continue;
+        }
+        if is_asset(&payment.asset, asset, network_id) {
+            amount = amount
+                .checked_add(StellarAmount::from_stroops(payment.amount.into()))
+                .map_err(|e| invalid(&e.to_string()))?;
+        } else {
+            other_asset = true;
+        }
+    }
+    if amount == StellarAmount::ZERO && other_asset {
+        return Err(VerifyError::AssetMismatch);
+    }
+    if amount.stroops() < parse_amount(&requirements.max_amount_required)? {
+        return Err(VerifyError::Underpaid);
+    }
+
+    Ok(DirectPayment {
+        payer: ed25519::PublicKey(source).to_string(),
+        amount: amount.stroops(),
+        expires_at,
+    })
+}
+
+/// Transfer into the deposit address of an escrow, found in a transaction
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct PendingDeposit {
+    /// Escrow credited
+    pub escrow_id: u64,
+    /// Source account of the transaction (G... format)
+    pub from: String,
+    /// Amount transferred in the escrow asset, in stroops
+    pub amount: i128,
+}
+
+/// Check a fetched transaction transfers funds into the deposit address of
+/// an escrow of `server`
+///
+/// The transaction must have succeeded and be buried deep enough. Payments
+/// to the escrow's [deposit address](deposit_address), or to `server` in a
+/// transaction with the escrow ID as its memo, count towards the deposit,
+/// and must all be for one escrow.
+///
+/// # Arguments
+/// * `tx` - `getTransaction` response
+/// * `server` - Server account the escrows pay (G... format)
+/// * `policy` - Asset and confirmations of pending deposits
+/// * `network_id` - SHA-256 of the network passphrase, to derive asset
+///   contract addresses
+///
+/// # Errors
+/// * The [`VerifyError`] describing the first failed check
+pub(crate) fn check_deposit(
+    tx: &GetTransactionResponse,
+    server: &str,
+    policy: &PendingDeposits,
+    network_id: &[u8; 32],
+) -> Result<PendingDeposit, VerifyError> {
+    let invalid = |message: &str| VerifyError::Rpc(format!("getTransaction: {message}"));
+    confirmed(tx, policy.confirmations)?;
+    let envelope = envelope(tx)?;
+    let (source, memo, operations) = contents(&envelope);
+
+    let server = match Strkey::from_string(server) {
+        Ok(Strkey::PublicKeyEd25519(key)) => key.0,
+        _ => return Err(VerifyError::RecipientMismatch),
+    };
+    let memo_id = match memo {
+        Memo::Id(id) => Some(*id),
+        // Payment memos mark direct payments, which pay for a resource
+        Memo::Hash(_) => {
+            return Err(VerifyError::InvalidPayload(
+                "transaction is a direct payment".into(),
+            ))
+        }
+        _ => None,
+    };
+    let (mut escrow_id, mut amount, mut other_asset) = (None, StellarAmount::ZERO, false);
+    for payment in operations.iter().filter_map(payment) {
+        let id = match &payment.destination {
+            MuxedAccount::MuxedEd25519(muxed) if muxed.ed25519.0 == server => Some(muxed.id),
+            MuxedAccount::Ed25519(key) if key.0 == server => memo_id,
+            _ => None,
+        };
+        let Some(id) = id else {
+            continue;
+        };
+        if !is_asset(&payment.asset, &policy.asset, network_id) {
+            other_asset = true;
+            continue;
+        }
+        if escrow_id.is_some_and(|escrow_id| escrow_id != id) {
+            return Err(VerifyError::InvalidPayload(
+                "transaction deposits into several escrows".into(),
+            ));
+        }
+        escrow_id = Some(id);
+        amount = amount
+            .checked_add(StellarAmount::from_stroops(payment.amount.into()))
+            .map_err(|e| invalid(&e.to_string()))?;
+    }
+    let Some(escrow_id) = escrow_id else {
+        return Err(if other_asset {
+            VerifyError::AssetMismatch
+        } else {
+            VerifyError::RecipientMismatch
+        });
+    };
+
+    Ok(PendingDeposit {
+        escrow_id,
+        from: ed25519::PublicKey(source).to_string(),
+        amount: amount.stroops(),
+    })
+}
+
+/// Deposit address of an escrow, the server account muxed with the escrow
+/// ID (M... format)
+///
+/// Plain transfers to it are credited to the escrow by the facilitator of
+/// the server, see [`crate::Facilitator::claim_deposit`].
+///
+/// # Returns
+/// * None if `server` is not an account address
+pub fn deposit_address(server: &str, escrow_id: u64) -> Option<String> {
+    match Strkey::from_string(server) {
+        Ok(Strkey::PublicKeyEd25519(key)) => Some(
+            Strkey::MuxedAccountEd25519(ed25519::MuxedAccount {
+                ed25519: key.0,
+                id: escrow_id,
+            })
+            .to_string(),
+        ),
+        _ => None,
+    }
+}
+
+/// Check a fetched transaction succeeded, `confirmations` ledgers ago
+fn confirmed(tx: &GetTransactionResponse, confirmations: u32) -> Result<(), VerifyError> {
+    match tx.status.as_str() {
+        "SUCCESS" => {}
+        "NOT_FOUND" => return Err(VerifyError::TransactionNotFound),
+        _ => return Err(VerifyError::TransactionFailed),
+    }
+    let ledger = tx
+        .ledger
+        .ok_or_else(|| VerifyError::Rpc("getTransaction: missing ledger".into()))?;
+    if tx.latest_ledger.saturating_sub(ledger) + 1 < confirmations {
+        return Err(VerifyError::InsufficientConfirmations);
+    }
+    Ok(())
+}
+
+/// Envelope of a fetched transaction
+fn envelope(tx: &GetTransactionResponse) -> Result<TransactionEnvelope, VerifyError> {
+    let invalid = |message: &str| VerifyError::Rpc(format!("getTransaction: {message}"));
+    let envelope = tx
+        .envelope_xdr
+        .as_deref()
+        .ok_or_else(|| invalid("missing envelope"))?;
+    TransactionEnvelope::from_xdr_base64(envelope, Limits::none())
+        .map_err(|e| invalid(&e.to_string()))
+}
+
+/// Source account key, memo, and operations of a transaction, or of the
+/// transaction a fee bump wraps
+fn contents(envelope: &TransactionEnvelope) -> ([u8; 32], &Memo, &[Operation]) {
+    match envelope {
+        TransactionEnvelope::TxV0(envelope) => (
+            envelope.tx.source_account_ed25519.0,
+            &envelope.tx.memo,
+            envelope.tx.operations.as_slice(),
+        ),
+        TransactionEnvelope::Tx(envelope) => (
+            account_key(&envelope.tx.source_account),
+            &envelope.tx.memo,
+            envelope.tx.operations.as_slice(),
+        ),
+        TransactionEnvelope::TxFeeBump(envelope) => {
+            let FeeBumpTransactionInnerTx::Tx(inner) = &envelope.tx.inner_tx;
+            (
+                account_key(&inner.tx.source_account),
+                &inner.tx.memo,
+                inner.tx.operations.as_slice(),
+            )
+        }
+    }
+}
+
+/// Payment operation, None for other operations
+fn payment(operation: &Operation) -> Option<&PaymentOp> {
+    match &operation.body {
+        OperationBody::Payment(payment) => Some(payment),
+        _ => None,
+    }
+}
+
+/// Ed25519 key of an account, muxed or not
+fn account_key(account: &MuxedAccount) -> [u8; 32] {
+    match account {
+        MuxedAccount::Ed25519(key) => key.0,
+        MuxedAccount::MuxedEd25519(muxed) => muxed.ed25519.0,
+    }
+}
+
+/// Whether a classic asset is `expected`, "native" or the address of its
+/// Stellar Asset Contract (C... format)
+fn is_asset(asset: &Asset, expected: &str, network_id: &[u8; 32]) -> bool {
+    (expected == NATIVE_ASSET && *asset == Asset::Native)
+        || asset_contract_id(asset, network_id) == expected
+}
+
+/// Address of the Stellar Asset Contract of a classic asset
+pub(crate) fn asset_contract_id(asset: &Asset, network_id: &[u8; 32]) -> String {
+    let preimage = HashIdPreimage::ContractId(HashIdPreimageContractId {
+        network_id: Hash(*network_id),
+        contract_id_preimage: ContractIdPreimage::Asset(asset.clone()),
+    });
+    // Serializing a fixed-size preimage to memory cannot fail
+    let bytes = preimage
+        .to_xdr(Limits::none())
+        .expect("contract id preimage serializes to XDR");
+    stellar_strkey::Contract(Sha256::digest(bytes).into()).to_string()
+}
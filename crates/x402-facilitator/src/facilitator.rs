@@ -0,0 +1,2995 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    future::Future,
+    net::IpAddr,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde_json::{json, Value};
+use stellar_strkey::Strkey;
+use stellar_xdr::curr::{Asset, Limits, ReadXdr, ScSymbol, ScVal};
+use tokio::sync::broadcast;
+use x402_client::{
+    scval, AuthorizationEntry, ContractError, Error as ClientError, EscrowClient, EscrowOp,
+    EventInfo, EventsFrom, Finality, FinalityPolicy, Payment, PreparedTransaction, Submitted,
+    EVENT_PAGE_LIMIT,
+};
+use x402_errors::{PolicyError, ProtocolError, X402Error};
+use x402_types::{
+    correlation_id, decode_payment_header, EscrowPayload, HeaderError, PaymentRequirements,
+    PreflightResponse, SchemePayload, SettleRequest, SettleResponse, SettlementSimulation,
+    SigningDomain, StellarAmount, SupportedKind, SupportedResponse, VerifyRequest, VerifyResponse,
+    ESCROW_SCHEME, EXACT_SCHEME, X402_VERSION, X402_VERSIONS,
+};
+
+use crate::{
+    degradation::CreditBook, direct, health, now_millis, shutdown::Drain, AdminError, BatchState,
+    Claim, Degradation, DirectPayments, Discrepancy, EventKind, JobState, MemoryRateLimiter,
+    MemoryReplayCache, Metrics, PaymentRecord, PendingDeposits, QueueError, RateLimiter, Readiness,
+    Reconciliation, ReplayCache, Settings, SettlementBatch, SettlementJob, SettlementQueue,
+    ShutdownReport, Stage, WebhookEvent, Webhooks, SHUTTING_DOWN,
+};
+
+/// Asset label of settlements whose requirements name no asset
+pub const NATIVE_ASSET: &str = "native";
+
+/// Events kept for subscribers lagging behind, see [`Facilitator::subscribe`]
+pub const EVENT_BUFFER: usize = 256;
+
+/// Longest the ledger time read from RPC is reused, about one ledger
+pub const LEDGER_TIME_TTL: Duration = Duration::from_secs(5);
+
+/// Default time a /preflight verdict is reused for, see
+/// [`Facilitator::with_preflight_ttl`]
+pub const DEFAULT_PREFLIGHT_TTL: Duration = Duration::from_secs(5);
+
+/// Reason a payment was rejected
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("invalid payment header: {0}")]
+    InvalidPayload(String),
+    #[error("unsupported x402 version {0}")]
+    UnsupportedVersion(u64),
+    #[error("unsupported payment scheme {0}")]
+    UnsupportedScheme(String),
+    #[error("payment is for network {0}")]
+    NetworkMismatch(String),
+    #[error("payment recipient does not match the facilitator server")]
+    RecipientMismatch,
+    #[error("invalid payment amount {0}")]
+    InvalidAmount(String),
+    #[error("payment amount exceeds the maximum required")]
+    AmountExceedsRequirement,
+    #[error("settlement amount exceeds the authorized amount")]
+    SettlementExceedsAuthorization,
+    #[error("payment authorization expired")]
+    Expired,
+    #[error("nonce already used")]
+    NonceUsed,
+    #[error("nonce already verified")]
+    NonceReplayed,
+    #[error("invalid client signature")]
+    InvalidSignature,
+    #[error("escrow not found")]
+    EscrowNotFound,
+    #[error("escrow is closed")]
+    EscrowClosed,
+    #[error("payment client does not own the escrow")]
+    ClientMismatch,
+    #[error("insufficient escrow balance")]
+    InsufficientBalance,
+    #[error("payment transaction not found")]
+    TransactionNotFound,
+    #[error("payment transaction failed")]
+    TransactionFailed,
+    #[error("payment transaction is not confirmed deep enough")]
+    InsufficientConfirmations,
+    #[error("payment memo does not match the requirements")]
+    MemoMismatch,
+    #[error("payment is in another asset")]
+    AssetMismatch,
+    #[error("payment amount is below the amount required")]
+    Underpaid,
+    #[error("escrow credit exhausted while RPC is unreachable")]
+    CreditExhausted,
+    #[error("RPC error: {0}")]
+    Rpc(String),
+    #[error("replay cache error: {0}")]
+    ReplayCache(String),
+}
+
+impl VerifyError {
+    /// Machine-readable reason, reported as `invalidReason`
+    pub fn reason(&self) -> &'static str {
+        X402Error::from(self).reason()
+    }
+
+    /// Stable code of the reason, reported as `errorCode`
+    pub fn code(&self) -> u32 {
+        X402Error::from(self).code()
+    }
+}
+
+impl From<&VerifyError> for X402Error {
+    fn from(error: &VerifyError) -> Self {
+        match error {
+            VerifyError::InvalidPayload(_) => ProtocolError::InvalidPayload.into(),
+            VerifyError::UnsupportedVersion(_) => ProtocolError::UnsupportedVersion.into(),
+            VerifyError::UnsupportedScheme(_) => ProtocolError::UnsupportedScheme.into(),
+            VerifyError::NetworkMismatch(_) => ProtocolError::NetworkMismatch.into(),
+            VerifyError::RecipientMismatch => ProtocolError::RecipientMismatch.into(),
+            VerifyError::InvalidAmount(_) => ProtocolError::InvalidAmount.into(),
+            VerifyError::AmountExceedsRequirement => ProtocolError::AmountExceedsRequirement.into(),
+            VerifyError::SettlementExceedsAuthorization => {
+                ProtocolError::SettlementExceedsAuthorization.into()
+            }
+            VerifyError::Expired => ProtocolError::Expired.into(),
+            VerifyError::NonceUsed => ProtocolError::NonceUsed.into(),
+            VerifyError::NonceReplayed => ProtocolError::NonceReplayed.into(),
+            VerifyError::InvalidSignature => ProtocolError::InvalidSignature.into(),
+            VerifyError::EscrowNotFound => ContractError::EscrowNotFound.into(),
+            VerifyError::EscrowClosed => ProtocolError::EscrowClosed.into(),
+            VerifyError::ClientMismatch => ProtocolError::ClientMismatch.into(),
+            VerifyError::InsufficientBalance => ContractError::InsufficientBalance.into(),
+            VerifyError::TransactionNotFound => ProtocolError::TransactionNotFound.into(),
+            VerifyError::TransactionFailed => ProtocolError::TransactionFailed.into(),
+            VerifyError::InsufficientConfirmations => {
+                ProtocolError::InsufficientConfirmations.into()
+            }
+            VerifyError::MemoMismatch => ProtocolError::MemoMismatch.into(),
+            VerifyError::AssetMismatch => ProtocolError::AssetMismatch.into(),
+            VerifyError::Underpaid => ProtocolError::Underpaid.into(),
+            VerifyError::CreditExhausted => PolicyError::CreditExhausted.into(),
+            VerifyError::Rpc(_) | VerifyError::ReplayCache(_) => X402Error::Internal,
+        }
+    }
+}
+
+/// Plain token transfer credited to an escrow, see
+/// [`Facilitator::claim_deposit`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClaimedDeposit {
+    pub escrow_id: u64,
+    /// Source account of the transfer (G... format)
+    pub from: String,
+    /// Amount credited, in stroops
+    pub amount: i128,
+    /// Hash of the transfer transaction, recorded by the contract as claimed
+    pub tx_hash: String,
+    /// Hash of the claiming transaction
+    pub claim_tx_hash: String,
+}
+
+/// Payment that passed verification
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerifiedPayment {
+    /// Escrow account ID, 0 for direct payments
+    pub escrow_id: u64,
+    /// Client address (G... format), the transaction source for direct
+    /// payments, empty for direct payments verified by a remote facilitator
+    pub client: String,
+    /// Authorized amount, in stroops
+    pub amount: i128,
+    /// Client-chosen nonce, 0 for direct payments
+    pub nonce: u64,
+    /// Hash of the payment transaction of a direct payment ("exact" scheme)
+    pub tx_hash: Option<String>,
+    /// Token of the middleware verifying through several facilitators,
+    /// naming the one that verified the payment, which must settle it
+    pub context: Option<String>,
+}
+
+impl VerifiedPayment {
+    /// ID linking the traces of the payment and its settlement transactions
+    ///
+    /// The [correlation ID](x402_types::correlation_id) of an escrow payment,
+    /// the transaction hash of a direct payment.
+    pub fn correlation_id(&self) -> String {
+        match &self.tx_hash {
+            Some(tx_hash) => tx_hash.clone(),
+            None => correlation_id(self.escrow_id, self.nonce),
+        }
+    }
+}
+
+/// Payment that passed verification, as checked
+struct Checked {
+    payment: VerifiedPayment,
+    /// Unix timestamp, by the local clock, the payment is accepted until
+    expires_at: u64,
+    /// Most credit outstanding on the escrow, if the payment was accepted
+    /// on credit while RPC was unreachable
+    max_credit: Option<i128>,
+    /// Deposit pre-signed by the client, submitted before settling since
+    /// the balance falls short of the payment
+    deposit: Option<AuthorizationEntry>,
+    /// Payment the contract created from the authorization when the client
+    /// opened the escrow, settled rather than created again
+    preauthorized: Option<u64>,
+    /// Direct payment pre-signed by the client, submitted instead since
+    /// the escrow does not exist
+    direct: Option<AuthorizationEntry>,
+}
+
+/// Settlement included in a ledger, waiting to be final
+struct IncludedSettlement {
+    payment: VerifiedPayment,
+    payment_id: Option<u64>,
+    tx_hash: Option<String>,
+    ledger: u32,
+}
+
+/// Verifies and settles escrow payments for a single server
+///
+/// The escrow client signs with the server key, so the facilitator only
+/// accepts payments whose `payTo` is that server. Used nonces are tracked in
+/// memory per escrow, and persisted by the settlement queue when one is
+/// configured. Nonces passing /verify are reserved in a [`ReplayCache`]
+/// until they expire, so a payload verifies once only.
+///
+/// With [`Settings::direct_payments`], payments already made by a classic
+/// Stellar transaction are accepted too ("exact" scheme). Settling one
+/// only marks its transaction used.
+///
+/// With [`Degradation::ServeAndQueue`] and a settlement queue, escrow
+/// payments are accepted on credit while RPC is unreachable, see
+/// [`Settings::degradation`].
+pub struct Facilitator {
+    /// Signs with the server key, replaced when the key rotates
+    client: RwLock<EscrowClient>,
+    server: String,
+    network: String,
+    settings: RwLock<Settings>,
+    used_nonces: Mutex<HashSet<(u64, u64)>>,
+    used_transactions: Mutex<HashSet<String>>,
+    replay: Arc<dyn ReplayCache>,
+    limiter: Arc<dyn RateLimiter>,
+    webhooks: Option<Arc<Webhooks>>,
+    metrics: Metrics,
+    queue: Option<SettlementQueue>,
+    flushing: tokio::sync::Mutex<()>,
+    /// Discrepancies found by the last reconciliation, only new ones being
+    /// notified
+    reported: Mutex<HashSet<String>>,
+    finality: Option<FinalityPolicy>,
+    included: Mutex<Vec<IncludedSettlement>>,
+    events: broadcast::Sender<WebhookEvent>,
+    credit: CreditBook,
+    pending_deposits: Option<PendingDeposits>,
+    /// Where the next scan of transfers to the server starts
+    deposit_cursor: Mutex<Option<EventsFrom>>,
+    /// Close time of the latest ledger, and when it was read
+    ledger_time: Mutex<Option<(u64, Instant)>>,
+    preflight_ttl: Duration,
+    /// /preflight verdicts by payload and requirements, and when they were
+    /// reached
+    preflights: Mutex<HashMap<String, (PreflightResponse, Instant)>>,
+    drain: Drain,
+    #[cfg(any(test, feature = "chaos"))]
+    faults: Option<Arc<crate::Faults>>,
+}
+
+impl Facilitator {
+    /// Create a facilitator
+    ///
+    /// # Arguments
+    /// * `client` - Escrow client signing with the server key
+    /// * `network` - x402 network id payments must target
+    pub fn new(client: EscrowClient, network: impl Into<String>) -> Self {
+        let network = network.into();
+        Self {
+            metrics: Metrics::new(&client.contract_id(), &network),
+            server: client.address(),
+            client: RwLock::new(client),
+            network,
+            settings: RwLock::new(Settings::default()),
+            used_nonces: Mutex::new(HashSet::new()),
+            used_transactions: Mutex::new(HashSet::new()),
+            replay: Arc::new(MemoryReplayCache::new()),
+            limiter: Arc::new(MemoryRateLimiter::new()),
+            webhooks: None,
+            queue: None,
+            flushing: tokio::sync::Mutex::new(()),
+            reported: Mutex::new(HashSet::new()),
+            finality: None,
+            included: Mutex::new(Vec::new()),
+            events: broadcast::channel(EVENT_BUFFER).0,
+            credit: CreditBook::default(),
+            pending_deposits: None,
+            deposit_cursor: Mutex::new(None),
+            ledger_time: Mutex::new(None),
+            preflight_ttl: DEFAULT_PREFLIGHT_TTL,
+            preflights: Mutex::new(HashMap::new()),
+            drain: Drain::default(),
+            #[cfg(any(test, feature = "chaos"))]
+            faults: None,
+        }
+    }
+
+    /// Settle through a durable queue of settlement jobs
+    ///
+    /// The nonces of the queued jobs are marked used. Unfinished jobs are
+    /// resumed by [`Facilitator::process_queue`].
+    ///
+    /// # Errors
+    /// * If the queued jobs cannot be read
+    pub fn with_queue(self, queue: SettlementQueue) -> Result<Self, QueueError> {
+        self.used_nonces.lock().unwrap().extend(queue.nonces()?);
+        self.used_transactions
+            .lock()
+            .unwrap()
+            .extend(queue.used_transactions()?);
+        self.metrics.set_queue_depth(queue.depth()?);
+        Ok(Self {
+            queue: Some(queue),
+            ..self
+        })
+    }
+
+    /// Settlement queue, if configured
+    pub fn queue(&self) -> Option<&SettlementQueue> {
+        self.queue.as_ref()
+    }
+
+    /// Reserve verified nonces in `cache` rather than in memory
+    ///
+    /// Facilitator replicas behind one load balancer must share the cache,
+    /// e.g. a [`crate::RedisReplayCache`].
+    pub fn with_replay_cache(mut self, cache: Arc<dyn ReplayCache>) -> Self {
+        self.replay = cache;
+        self
+    }
+
+    /// Keep the token buckets of [`Settings::rate_limit`] in `limiter` rather
+    /// than in memory
+    pub fn with_rate_limiter(mut self, limiter: Arc<dyn RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Replace the metrics, e.g. with [`Metrics::for_tenant`]
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Notify webhook endpoints of settlement outcomes
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Track the finality of settlements, as described by `policy`
+    ///
+    /// Settlement responses and `payment.settled` events then carry the
+    /// ledger the settlement was included in and whether it is final yet.
+    /// Included settlements are kept in memory until
+    /// [`Facilitator::finalize_settlements`] finds them final, and notifies
+    /// them as `payment.finalized` events.
+    pub fn with_finality(mut self, policy: FinalityPolicy) -> Self {
+        self.finality = Some(policy);
+        self
+    }
+
+    /// Credit escrows with plain token transfers into their deposit
+    /// addresses, as described by `policy`
+    ///
+    /// Transfers are found by [`Facilitator::claim_deposits`], or claimed
+    /// one by one with [`Facilitator::claim_deposit`].
+    pub fn with_pending_deposits(mut self, policy: PendingDeposits) -> Self {
+        self.pending_deposits = Some(policy);
+        self
+    }
+
+    /// Reuse the verdict of a /preflight request for the same payload and
+    /// requirements during `ttl`, [`DEFAULT_PREFLIGHT_TTL`] by default
+    ///
+    /// A zero `ttl` simulates every request.
+    pub fn with_preflight_ttl(mut self, ttl: Duration) -> Self {
+        self.preflight_ttl = ttl;
+        self
+    }
+
+    /// Events notified from now on, the same as delivered to webhooks
+    ///
+    /// Receivers more than [`EVENT_BUFFER`] events behind miss the oldest.
+    pub fn subscribe(&self) -> broadcast::Receiver<WebhookEvent> {
+        self.events.subscribe()
+    }
+
+    /// Crash settlements after the stages `faults` are armed for
+    #[cfg(any(test, feature = "chaos"))]
+    pub fn with_faults(mut self, faults: Arc<crate::Faults>) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    /// Webhook dispatcher, if configured
+    pub fn webhooks(&self) -> Option<&Arc<Webhooks>> {
+        self.webhooks.as_ref()
+    }
+
+    /// Replace the initial settings
+    pub fn with_settings(self, settings: Settings) -> Self {
+        self.set_settings(settings);
+        self
+    }
+
+    /// Prometheus metrics, served on /metrics
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Current settings
+    pub fn settings(&self) -> Settings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Replace the settings, taking effect for the next request
+    pub fn set_settings(&self, settings: Settings) {
+        *self.settings.write().unwrap() = settings;
+    }
+
+    /// Sign settlements with `client` from now on, e.g. after the server's
+    /// key rotated
+    ///
+    /// The server address cannot change, since escrows name their server
+    /// and the contract cannot move them to another, so `client` must sign
+    /// for the same account. A rotated key is added as a signer of it, see
+    /// [`LocalSigner::for_account`](x402_client::LocalSigner::for_account).
+    ///
+    /// Queued jobs are signed with the new key from their next transaction
+    /// on. Transactions they saved signed by the old key are resubmitted as
+    /// they are, and signed anew if rejected once the old key was removed
+    /// from the account, so each payment still settles once.
+    ///
+    /// # Errors
+    /// * `WrongServer` - If `client` signs for another account
+    pub fn rotate_signer(&self, client: EscrowClient) -> Result<(), AdminError> {
+        if client.address() != self.server {
+            return Err(AdminError::WrongServer(client.address()));
+        }
+        *self.client.write().unwrap() = client;
+        Ok(())
+    }
+
+    /// Escrow client signing with the server key
+    fn client(&self) -> EscrowClient {
+        self.client.read().unwrap().clone()
+    }
+
+    /// `G...` address of the server payments are made to
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    /// x402 network id payments must target
+    pub fn network(&self) -> &str {
+        &self.network
+    }
+
+    /// SHA-256 of the network passphrase, which authorizations are bound to
+    pub fn network_id(&self) -> [u8; 32] {
+        self.client().network_id()
+    }
+
+    /// Escrow contract address (C... format) authorizations are bound to
+    pub fn escrow_contract(&self) -> String {
+        self.client().contract_id()
+    }
+
+    /// Handle a /supported request
+    ///
+    /// The network passphrase is read live from the RPC node, so a
+    /// misconfigured RPC URL shows up here rather than at settlement.
+    ///
+    /// # Errors
+    /// * The RPC error if the network cannot be read
+    pub async fn supported(&self) -> Result<SupportedResponse, ClientError> {
+        let network = self
+            .metrics
+            .rpc("get_network", self.client().rpc().get_network())
+            .await?;
+        let settings = self.settings();
+        let mut schemes = vec![ESCROW_SCHEME];
+        if settings.direct_payments.is_some() {
+            schemes.push(EXACT_SCHEME);
+        }
+        Ok(SupportedResponse {
+            kinds: schemes
+                .into_iter()
+                .map(|scheme| SupportedKind {
+                    x402_version: X402_VERSION,
+                    scheme: scheme.into(),
+                    network: self.network.clone(),
+                    network_passphrase: network.passphrase.clone(),
+                })
+                .collect(),
+            assets: settings.assets,
+            fee: settings.fee,
+            escrow_contract: self.client().contract_id(),
+        })
+    }
+
+    /// Take a token from the bucket of the client making a payment
+    ///
+    /// Buckets are keyed by the server and the client address of the payment
+    /// header, or `source` when the header does not decode. Limiter errors
+    /// let the request through, rather than failing every payment.
+    ///
+    /// # Arguments
+    /// * `endpoint` - Endpoint requested, for metrics
+    /// * `header` - X-PAYMENT header of the request
+    /// * `source` - IP address the request came from, if known
+    ///
+    /// # Returns
+    /// * The time until the client may retry, if it is rate limited
+    pub async fn rate_limit(
+        &self,
+        endpoint: &str,
+        header: &str,
+        source: Option<IpAddr>,
+    ) -> Option<Duration> {
+        let limit = self.settings.read().unwrap().rate_limit?;
+        let client = match decode_payment_header(header).map(|payload| payload.payload) {
+            Ok(SchemePayload::Escrow(payload)) => payload.client,
+            _ => source.map_or_else(|| "unknown".into(), |ip| ip.to_string()),
+        };
+        let key = format!("{}:{client}", self.server);
+        match self.limiter.try_acquire(&key, &limit).await {
+            Ok(None) => None,
+            Ok(Some(retry_after)) => {
+                self.metrics.rate_limited(endpoint);
+                Some(retry_after)
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "rate limiter unavailable");
+                None
+            }
+        }
+    }
+
+    /// Handle a /verify request
+    ///
+    /// The nonce of a valid payment is reserved in the replay cache, so the
+    /// same payload verified again, concurrently or not, is rejected with
+    /// `nonce_replayed`. Settlement is not affected by the reservation.
+    #[tracing::instrument(
+        name = "facilitator.verify",
+        skip_all,
+        fields(
+            resource = %request.payment_requirements.resource,
+            correlation_id = tracing::field::Empty,
+        )
+    )]
+    pub async fn verify(&self, request: &VerifyRequest) -> VerifyResponse {
+        let start = Instant::now();
+        let response = match self
+            .check_reserved(&request.payment_header, &request.payment_requirements)
+            .await
+        {
+            Ok(payment) => {
+                record_correlation_id(&payment);
+                tracing::info!(
+                    escrow_id = payment.escrow_id,
+                    amount = %payment.amount,
+                    "payment valid"
+                );
+                VerifyResponse {
+                    is_valid: true,
+                    invalid_reason: None,
+                    error_code: None,
+                    x402_versions: Vec::new(),
+                }
+            }
+            Err(e) => {
+                tracing::info!(reason = e.reason(), "payment invalid");
+                let x402_versions = match e {
+                    VerifyError::UnsupportedVersion(_) => X402_VERSIONS.to_vec(),
+                    _ => Vec::new(),
+                };
+                VerifyResponse {
+                    is_valid: false,
+                    invalid_reason: Some(e.reason().into()),
+                    error_code: Some(e.code()),
+                    x402_versions,
+                }
+            }
+        };
+        self.metrics.observe_request("verify", start);
+        response
+    }
+
+    /// Handle a /settle request
+    ///
+    /// Re-verifies the payment, reserves its nonce, then creates and settles
+    /// the payment on-chain. Payments that fail on-chain emit
+    /// `payment.failed`, settled ones `payment.settled`. Direct payments are
+    /// already on-chain, so their transaction is only marked used.
+    ///
+    /// With a settlement queue, the job is persisted first. A job that could
+    /// not finish yet is reported successful without a transaction hash and
+    /// retried in the background. With batching, created payments are left
+    /// to [`Facilitator::flush_batches`], run as soon as a batch is full.
+    ///
+    /// A request with a `settle_amount` charges that amount of an escrow
+    /// authorization rather than all of it. Settling zero consumes the
+    /// authorization without a transaction.
+    ///
+    /// Settlement transactions of a single payment carry its
+    /// [correlation ID](VerifiedPayment::correlation_id) as text memo.
+    ///
+    /// A dry run, requested or set in the [`Settings`], verifies the payment
+    /// and simulates its settlement, reporting the outcome as the
+    /// response's `simulation` without any side effect.
+    ///
+    /// Payments accepted on credit while RPC is unreachable extend that
+    /// credit to their escrow until their queued job settles or fails.
+    /// Payments beyond the escrow's credit are `credit_exhausted`.
+    ///
+    /// An escrow payload may carry a deposit pre-signed by the client, see
+    /// [`EscrowClient::presign`]. When the escrow's balance falls short of
+    /// the payment, the facilitator submits the deposit first, paying its
+    /// fee. Dry runs do not, so their simulation then fails.
+    ///
+    /// With [`Settings::direct_fallback`], it may instead carry a direct
+    /// payment pre-signed by the client, see
+    /// [`EscrowPayload::direct_authorization`]. When the escrow does not
+    /// exist, the facilitator submits that transfer to the server, settling
+    /// the payment in full and inline, without a queued job.
+    ///
+    /// A settlement goes through the [`Stage`]s verify, persist, submit, and
+    /// confirm. Persist is skipped without a queue, submit and confirm for
+    /// direct payments and settlements of zero.
+    #[tracing::instrument(
+        name = "facilitator.settle",
+        skip_all,
+        fields(
+            resource = %request.payment_requirements.resource,
+            correlation_id = tracing::field::Empty,
+        )
+    )]
+    pub async fn settle(&self, request: &SettleRequest) -> SettleResponse {
+        let Some(_in_flight) = self.drain.enter() else {
+            self.metrics.settlement_failed(SHUTTING_DOWN);
+            return self.rejected(Some(PolicyError::ShuttingDown.into()), SHUTTING_DOWN.into());
+        };
+        let start = Instant::now();
+        let response = self.settle_checked(request).await;
+        match &response {
+            SettleResponse {
+                success: true,
+                simulation: Some(_),
+                ..
+            } => tracing::info!("settlement simulated"),
+            SettleResponse {
+                success: true,
+                tx_hash,
+                ..
+            } => tracing::info!(tx_hash = tx_hash.as_deref(), "payment settled"),
+            SettleResponse { error, .. } => {
+                tracing::warn!(error = error.as_deref(), "settlement failed")
+            }
+        }
+        self.metrics.observe_request("settle", start);
+        response
+    }
+
+    async fn settle_checked(&self, request: &SettleRequest) -> SettleResponse {
+        let failed = |error: X402Error, message: String| {
+            self.metrics.settlement_failed(error.reason());
+            self.rejected(Some(error), message)
+        };
+        let invalid = |e: VerifyError| failed(X402Error::from(&e), e.reason().into());
+        let queue_failed = |e: QueueError| {
+            self.metrics.settlement_failed("queue_error");
+            self.rejected(Some(X402Error::Internal), e.to_string())
+        };
+
+        let (payment, max_credit, deposit, preauthorized, direct) = match self
+            .check_expiring(&request.payment_header, &request.payment_requirements)
+            .await
+            .and_then(|checked| {
+                let authorized = checked.payment.amount;
+                let payment = settled_part(checked.payment, request.settle_amount.as_deref())?;
+                // The contract settles a created payment in full
+                if checked.preauthorized.is_some() && payment.amount != authorized {
+                    return Err(VerifyError::InvalidPayload(
+                        "a payment created when opening the escrow settles in full".into(),
+                    ));
+                }
+                // The client signed the transfer of its full amount
+                if checked.direct.is_some() && payment.amount != authorized {
+                    return Err(VerifyError::InvalidPayload(
+                        "a direct payment settles in full".into(),
+                    ));
+                }
+                Ok((
+                    payment,
+                    checked.max_credit,
+                    checked.deposit,
+                    checked.preauthorized,
+                    checked.direct,
+                ))
+            }) {
+            Ok(checked) => checked,
+            Err(e) => return invalid(e),
+        };
+        record_correlation_id(&payment);
+        if request.dry_run || self.settings.read().unwrap().dry_run {
+            return self
+                .simulate_settlement(&payment, preauthorized, direct.as_ref())
+                .await;
+        }
+        self.reached(Stage::Verify);
+        let asset = &paid_asset(&request.payment_header, &request.payment_requirements);
+        if let Some(tx_hash) = &payment.tx_hash {
+            if !self
+                .used_transactions
+                .lock()
+                .unwrap()
+                .insert(tx_hash.clone())
+            {
+                return invalid(VerifyError::NonceUsed);
+            }
+            match self.persist_used(&payment) {
+                Ok(true) => {}
+                Ok(false) => {
+                    return invalid(VerifyError::NonceUsed);
+                }
+                Err(e) => {
+                    self.used_transactions.lock().unwrap().remove(tx_hash);
+                    return queue_failed(e);
+                }
+            }
+            self.metrics.settled(asset, payment.amount);
+            let (ledger, finality) = self
+                .record_settled(&payment, None, Some(tx_hash), None)
+                .await;
+            return SettleResponse {
+                success: true,
+                error: None,
+                error_code: None,
+                tx_hash: Some(tx_hash.clone()),
+                network_id: Some(self.network.clone()),
+                payment_id: None,
+                ledger,
+                finality,
+                simulation: None,
+            };
+        }
+        if !self.reserve_nonce(&payment) {
+            return invalid(VerifyError::NonceUsed);
+        }
+        if payment.amount == 0 {
+            match self.persist_used(&payment) {
+                Ok(true) => {}
+                Ok(false) => {
+                    return invalid(VerifyError::NonceUsed);
+                }
+                Err(e) => {
+                    self.release_nonce(&payment);
+                    return queue_failed(e);
+                }
+            }
+            return SettleResponse {
+                success: true,
+                error: None,
+                error_code: None,
+                tx_hash: None,
+                network_id: Some(self.network.clone()),
+                payment_id: None,
+                ledger: None,
+                finality: None,
+                simulation: None,
+            };
+        }
+        if let Some(entry) = &direct {
+            // Settled at once, the contract recording each reference of the
+            // client once
+            let client = self.tagged_client(&payment);
+            let submitted = self
+                .metrics
+                .rpc("record_direct_payment", client.submit_authorized(entry))
+                .await
+                .and_then(|submitted| Ok((scval::to_u64(&submitted.value)?, submitted)));
+            let (payment_id, submitted) = match submitted {
+                Ok(submitted) => submitted,
+                Err(e) => {
+                    // Nothing was transferred, so the authorization may be
+                    // retried
+                    self.release_nonce(&payment);
+                    self.notify_failed(&payment, None, &e.to_string());
+                    return failed(X402Error::from(&e), e.to_string());
+                }
+            };
+            self.reached(Stage::Submit);
+            self.reached(Stage::Confirm);
+            tracing::info!(payment_id, "pre-signed direct payment submitted");
+            self.metrics.settled(asset, payment.amount);
+            let (ledger, finality) = self
+                .record_settled(
+                    &payment,
+                    Some(payment_id),
+                    Some(&submitted.hash),
+                    Some(submitted.ledger),
+                )
+                .await;
+            return SettleResponse {
+                success: true,
+                error: None,
+                error_code: None,
+                tx_hash: Some(submitted.hash),
+                network_id: Some(self.network.clone()),
+                payment_id: Some(payment_id),
+                ledger,
+                finality,
+                simulation: None,
+            };
+        }
+        if let Some(max_credit) = max_credit {
+            let (escrow_id, nonce) = (payment.escrow_id, payment.nonce);
+            if !self
+                .credit
+                .extend(escrow_id, nonce, payment.amount, max_credit)
+            {
+                self.release_nonce(&payment);
+                return invalid(VerifyError::CreditExhausted);
+            }
+            tracing::warn!(escrow_id, amount = %payment.amount, "payment accepted on credit");
+            self.metrics.set_credit_outstanding(self.credit.total());
+        }
+        if let Some(entry) = &deposit {
+            // Nothing was charged, so the authorization may be retried, the
+            // deposit being skipped if it applied meanwhile
+            let client = self.tagged_client(&payment);
+            if let Err(e) = self
+                .metrics
+                .rpc("deposit", client.submit_authorized(entry))
+                .await
+            {
+                self.release_nonce(&payment);
+                return failed(X402Error::from(&e), e.to_string());
+            }
+            tracing::info!(
+                escrow_id = payment.escrow_id,
+                "pre-signed deposit submitted"
+            );
+        }
+        if let Some(queue) = &self.queue {
+            return self
+                .settle_queued(queue, &payment, asset, preauthorized)
+                .await;
+        }
+        let _pending = self.metrics.pending();
+
+        let client = self.tagged_client(&payment);
+        let created = match preauthorized {
+            Some(payment_id) => Ok(payment_id),
+            None => self
+                .metrics
+                .rpc(
+                    "create_payment",
+                    client.create_payment(payment.escrow_id, payment.amount),
+                )
+                .await
+                .map(|created| created.value),
+        };
+        let payment_id = match created {
+            Ok(payment_id) => {
+                self.reached(Stage::Submit);
+                payment_id
+            }
+            Err(e) => {
+                // Nothing was charged, so the authorization may be retried
+                self.release_nonce(&payment);
+                self.notify_failed(&payment, None, &e.to_string());
+                return failed(X402Error::from(&e), e.to_string());
+            }
+        };
+        match self
+            .metrics
+            .rpc("settle_payment", client.settle_payment(payment_id))
+            .await
+        {
+            Ok(settled) => {
+                self.reached(Stage::Confirm);
+                self.metrics.settled(asset, payment.amount);
+                let (ledger, finality) = self
+                    .record_settled(
+                        &payment,
+                        Some(payment_id),
+                        Some(&settled.hash),
+                        Some(settled.ledger),
+                    )
+                    .await;
+                SettleResponse {
+                    success: true,
+                    error: None,
+                    error_code: None,
+                    tx_hash: Some(settled.hash),
+                    network_id: Some(self.network.clone()),
+                    payment_id: Some(payment_id),
+                    ledger,
+                    finality,
+                    simulation: None,
+                }
+            }
+            Err(e) => {
+                self.notify_failed(&payment, Some(payment_id), &e.to_string());
+                SettleResponse {
+                    payment_id: Some(payment_id),
+                    ..failed(X402Error::from(&e), e.to_string())
+                }
+            }
+        }
+    }
+
+    /// Handle a /preflight request
+    ///
+    /// Forecasts whether settling the payment would succeed, without
+    /// reserving its nonce or submitting anything. The payment is checked as
+    /// by /verify, its `create_payment` simulated as by a dry run, and the
+    /// escrow's spend limit checked as `settle_payment` would, that call
+    /// needing its payment on-chain to be simulated. The verdict reports the
+    /// first check failing, the estimated fee, and the escrow's available
+    /// balance.
+    ///
+    /// Verdicts are reused for the same payload and requirements during the
+    /// [preflight TTL](Facilitator::with_preflight_ttl), marked `cached`.
+    /// They are never binding: the chain may change before the settlement.
+    #[tracing::instrument(
+        name = "facilitator.preflight",
+        skip_all,
+        fields(resource = %request.payment_requirements.resource)
+    )]
+    pub async fn preflight(&self, request: &VerifyRequest) -> PreflightResponse {
+        let start = Instant::now();
+        let requirements = serde_json::to_string(&request.payment_requirements);
+        let key = format!(
+            "{}\n{}",
+            request.payment_header,
+            requirements.unwrap_or_default()
+        );
+        let cached = {
+            let mut preflights = self.preflights.lock().unwrap();
+            preflights.retain(|_, (_, reached)| reached.elapsed() < self.preflight_ttl);
+            preflights.get(&key).map(|(verdict, _)| verdict.clone())
+        };
+        if let Some(verdict) = cached {
+            self.metrics.observe_request("preflight", start);
+            return PreflightResponse {
+                cached: true,
+                ..verdict
+            };
+        }
+
+        let verdict = self
+            .forecast(&request.payment_header, &request.payment_requirements)
+            .await;
+        tracing::info!(
+            ok = verdict.ok,
+            reason = verdict.invalid_reason.as_deref(),
+            "settlement forecast"
+        );
+        if !self.preflight_ttl.is_zero() {
+            let reached = (verdict.clone(), Instant::now());
+            self.preflights.lock().unwrap().insert(key, reached);
+        }
+        self.metrics.observe_request("preflight", start);
+        verdict
+    }
+
+    /// Verdict of a /preflight request, see [`Facilitator::preflight`]
+    async fn forecast(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> PreflightResponse {
+        let available_balance = match decode_payment_header(header).map(|payload| payload.payload) {
+            Ok(SchemePayload::Escrow(payload)) => {
+                self.available_balance(payload.escrow_id).await.ok()
+            }
+            _ => None,
+        };
+        let mut verdict = PreflightResponse {
+            ok: false,
+            invalid_reason: None,
+            error_code: None,
+            estimated_fee: None,
+            available_balance: available_balance.map(|balance| balance.to_string()),
+            binding: false,
+            cached: false,
+        };
+        let fail = |verdict: PreflightResponse, error: X402Error| PreflightResponse {
+            invalid_reason: Some(error.reason().into()),
+            error_code: Some(error.code()),
+            ..verdict
+        };
+
+        let checked = match self.check_expiring(header, requirements).await {
+            Ok(checked) => checked,
+            Err(e) => return fail(verdict, X402Error::from(&e)),
+        };
+        let payment = &checked.payment;
+        // The escrow only covers a payment relying on a pre-signed deposit
+        // once the deposit applied, checked to cover it already
+        if checked.deposit.is_none() {
+            let simulated = self
+                .simulate_settlement(payment, checked.preauthorized, checked.direct.as_ref())
+                .await;
+            if !simulated.success {
+                let error = simulated.error_code.and_then(X402Error::from_code);
+                return fail(verdict, error.unwrap_or(X402Error::Internal));
+            }
+            verdict.estimated_fee = simulated.simulation.and_then(|s| s.estimated_fee);
+        }
+
+        // Escrow payments are settled by `settle_payment`, which the spend
+        // limit may refuse
+        if payment.tx_hash.is_none() && checked.direct.is_none() && payment.amount > 0 {
+            let limit = self
+                .metrics
+                .rpc(
+                    "get_spend_limit",
+                    self.client().get_spend_limit(payment.escrow_id),
+                )
+                .await;
+            match limit {
+                Ok(Some(limit)) if !limit.allows(self.ledger_time().await, payment.amount) => {
+                    return fail(verdict, ContractError::SettlementsPaused.into());
+                }
+                Ok(_) => {}
+                Err(e) => return fail(verdict, X402Error::from(&e)),
+            }
+        }
+        verdict.ok = true;
+        verdict
+    }
+
+    /// Simulate the settlement of a verified payment
+    ///
+    /// Only `create_payment` is simulated, `settle_payment` needing its
+    /// payment on-chain, or the direct payment pre-signed for a payment
+    /// without escrow. No nonce is reserved, nothing is queued or
+    /// submitted, and no webhook is sent. Direct payments are already
+    /// on-chain and settling zero takes no transaction, so neither is
+    /// simulated, nor payments the client created when opening the escrow.
+    async fn simulate_settlement(
+        &self,
+        payment: &VerifiedPayment,
+        preauthorized: Option<u64>,
+        direct: Option<&AuthorizationEntry>,
+    ) -> SettleResponse {
+        let mut simulation = SettlementSimulation {
+            amount: payment.amount.to_string(),
+            payment_id: preauthorized,
+            estimated_fee: None,
+        };
+        if payment.tx_hash.is_none() && payment.amount > 0 && preauthorized.is_none() {
+            let (function, op) = match direct.and_then(|entry| entry.op().ok()) {
+                Some(op) => ("record_direct_payment", op),
+                None => (
+                    "create_payment",
+                    EscrowOp::CreatePayment {
+                        escrow_id: payment.escrow_id,
+                        amount: payment.amount,
+                    },
+                ),
+            };
+            let simulated = self
+                .metrics
+                .rpc(function, self.client().dry_run(&op))
+                .await
+                .and_then(|dry_run| Ok((scval::to_u64(&dry_run.value)?, dry_run.estimate)));
+            match simulated {
+                Ok((payment_id, estimate)) => {
+                    simulation.payment_id = Some(payment_id);
+                    simulation.estimated_fee = Some(estimate.total_fee);
+                }
+                Err(e) => {
+                    return SettleResponse {
+                        simulation: Some(simulation),
+                        ..self.rejected(Some(X402Error::from(&e)), e.to_string())
+                    }
+                }
+            }
+        }
+        SettleResponse {
+            success: true,
+            error: None,
+            error_code: None,
+            tx_hash: payment.tx_hash.clone(),
+            network_id: Some(self.network.clone()),
+            payment_id: None,
+            ledger: None,
+            finality: None,
+            simulation: Some(simulation),
+        }
+    }
+
+    /// Escrow client tagging its transactions with the payment's correlation ID
+    fn tagged_client(&self, payment: &VerifiedPayment) -> EscrowClient {
+        self.client().with_correlation_id(payment.correlation_id())
+    }
+
+    /// Failed settlement, with the code of its error when known
+    fn rejected(&self, error: Option<X402Error>, message: String) -> SettleResponse {
+        SettleResponse {
+            success: false,
+            error: Some(message),
+            error_code: error.map(|error| error.code()),
+            tx_hash: None,
+            network_id: Some(self.network.clone()),
+            payment_id: None,
+            ledger: None,
+            finality: None,
+            simulation: None,
+        }
+    }
+
+    async fn settle_queued(
+        &self,
+        queue: &SettlementQueue,
+        payment: &VerifiedPayment,
+        asset: &str,
+        preauthorized: Option<u64>,
+    ) -> SettleResponse {
+        let queue_failed = |message: String| {
+            self.metrics.settlement_failed("queue_error");
+            self.rejected(Some(X402Error::Internal), message)
+        };
+        let job = match queue.enqueue(payment, asset, preauthorized) {
+            Ok(Some(job)) => {
+                self.reached(Stage::Persist);
+                job
+            }
+            Ok(None) => {
+                self.release_credit(payment);
+                let reason = VerifyError::NonceUsed.reason();
+                self.metrics.settlement_failed(reason);
+                return self.rejected(Some(ProtocolError::NonceUsed.into()), reason.into());
+            }
+            Err(e) => {
+                self.release_nonce(payment);
+                self.release_credit(payment);
+                return queue_failed(e.to_string());
+            }
+        };
+        self.update_queue_depth(queue);
+
+        let Some(mut job) = self.process_job(queue, job.id).await else {
+            return queue_failed(format!("settlement job {} vanished", job.id));
+        };
+        if let Some(policy) = queue.batching() {
+            let full = queue
+                .unbatched()
+                .is_ok_and(|waiting| waiting.len() >= policy.max_size);
+            if full {
+                if let Err(e) = self.flush_admitted(queue).await {
+                    tracing::error!(error = %e, "settlement batches");
+                }
+                job = queue.job(job.id).ok().flatten().unwrap_or(job);
+            }
+        }
+        match job.state {
+            JobState::Failed => SettleResponse {
+                payment_id: job.payment_id,
+                ..self.rejected(None, job.error.unwrap_or_default())
+            },
+            JobState::Settled => {
+                let (ledger, finality) = self
+                    .finality_of(job.amount, job.tx_hash.as_deref(), None)
+                    .await;
+                SettleResponse {
+                    success: true,
+                    error: None,
+                    error_code: None,
+                    tx_hash: job.tx_hash,
+                    network_id: Some(self.network.clone()),
+                    payment_id: job.payment_id,
+                    ledger,
+                    finality,
+                    simulation: None,
+                }
+            }
+            _ => SettleResponse {
+                success: true,
+                error: None,
+                error_code: None,
+                tx_hash: job.tx_hash,
+                network_id: Some(self.network.clone()),
+                payment_id: job.payment_id,
+                ledger: None,
+                finality: None,
+                simulation: None,
+            },
+        }
+    }
+
+    /// Run the due jobs of the settlement queue once
+    ///
+    /// Once shutting down, no further job is started.
+    ///
+    /// # Returns
+    /// * The number of jobs attempted
+    ///
+    /// # Errors
+    /// * If the queue cannot be read
+    pub async fn process_queue(&self) -> Result<usize, QueueError> {
+        let Some(queue) = &self.queue else {
+            return Ok(0);
+        };
+        let Some(_in_flight) = self.drain.enter() else {
+            return Ok(0);
+        };
+        let mut attempted = 0;
+        for job in &queue.due()? {
+            if self.drain.is_draining() {
+                break;
+            }
+            self.process_job(queue, job.id).await;
+            attempted += 1;
+        }
+        Ok(attempted)
+    }
+
+    /// Run the settlement queue every `interval`, until the task is dropped
+    /// or the facilitator shuts down
+    pub async fn run_settlement_worker(&self, interval: Duration) {
+        while !self.drain.is_draining() {
+            if let Err(e) = self.process_queue().await {
+                tracing::error!(error = %e, "settlement queue");
+            }
+            if let Err(e) = self.flush_batches().await {
+                tracing::error!(error = %e, "settlement batches");
+            }
+            tokio::select! {
+                () = tokio::time::sleep(interval) => {}
+                () = self.drain.started() => {}
+            }
+        }
+    }
+
+    /// Reconcile the settlement queue with on-chain state every `interval`,
+    /// until the task is dropped or the facilitator shuts down
+    ///
+    /// See [`Facilitator::reconcile`].
+    pub async fn run_reconciler(&self, interval: Duration) {
+        loop {
+            tokio::select! {
+                () = tokio::time::sleep(interval) => {}
+                () = self.drain.started() => return,
+            }
+            if let Err(e) = self.reconcile().await {
+                tracing::error!(error = %e, "reconciliation");
+            }
+        }
+    }
+
+    /// Whether the facilitator is shutting down, see [`Facilitator::shutdown`]
+    pub fn is_shutting_down(&self) -> bool {
+        self.drain.is_draining()
+    }
+
+    /// Check the dependencies payments need, as answered on /readyz
+    ///
+    /// Soroban RPC must answer `getLatestLedger`, the escrow contract a
+    /// simulated `version`, and the signer must sign a probe with the server
+    /// key. The settlement queue, if configured, must not be backlogged.
+    /// Thresholds and timeouts are those of [`Settings::readiness`]. A
+    /// facilitator shutting down is not ready, so load balancers stop
+    /// routing to it while it drains.
+    pub async fn readiness(&self) -> Readiness {
+        let policy = self.settings().readiness;
+        let client = self.client();
+        let shutdown = health::check("shutdown", policy.timeout, async {
+            if self.is_shutting_down() {
+                return Err(SHUTTING_DOWN);
+            }
+            Ok("accepting settlements".to_string())
+        });
+        let rpc = health::check("rpc", policy.timeout, async {
+            let ledger = self
+                .metrics
+                .rpc("get_latest_ledger", client.rpc().get_latest_ledger())
+                .await?;
+            Ok::<_, ClientError>(format!("ledger {}", ledger.sequence))
+        });
+        let contract = health::check("contract", policy.timeout, async {
+            let version = self.metrics.rpc("version", client.version()).await?;
+            Ok::<_, ClientError>(format!("version {version}"))
+        });
+        let queue = health::check("queue", policy.timeout, async {
+            let Some(queue) = &self.queue else {
+                return Ok("no settlement queue".to_string());
+            };
+            let depth = queue.depth().map_err(|e| e.to_string())?;
+            if depth > policy.max_queue_depth {
+                return Err(format!(
+                    "{depth} unfinished jobs, above {}",
+                    policy.max_queue_depth
+                ));
+            }
+            Ok::<_, String>(format!("{depth} unfinished jobs"))
+        });
+        let signer = health::check("signer", policy.timeout, async {
+            client.check_signer().await?;
+            Ok::<_, ClientError>(format!("signs for {}", self.server))
+        });
+        let (shutdown, rpc, contract, queue, signer) =
+            tokio::join!(shutdown, rpc, contract, queue, signer);
+        Readiness::new(vec![shutdown, rpc, contract, queue, signer])
+    }
+
+    /// Stop admitting settlements, without waiting for those in flight
+    pub(crate) fn stop_admitting(&self) {
+        self.drain.start();
+    }
+
+    /// Stop settling, then wait up to `grace` for the settlements in flight
+    ///
+    /// New settlements are refused with [`SHUTTING_DOWN`], and the settlement
+    /// worker and reconciler return. Settlements and batches already
+    /// submitting run to their outcome, saved to the settlement queue as
+    /// usual, but no further job or batch is started: payments waiting for a
+    /// batch stay queued. Work still in flight at the deadline saved its
+    /// transactions before submitting them, so the next facilitator on the
+    /// queue resumes it without submitting twice. Without a queue, it is
+    /// lost.
+    ///
+    /// # Returns
+    /// * What was left unfinished, each unfinished job being logged
+    pub async fn shutdown(&self, grace: Duration) -> ShutdownReport {
+        self.drain.start();
+        let interrupted = self.drain.wait(grace).await;
+        if interrupted > 0 {
+            tracing::warn!(
+                interrupted,
+                queued = self.queue.is_some(),
+                "shutdown deadline passed with settlements in flight"
+            );
+        }
+        let unfinished = match &self.queue {
+            Some(queue) => {
+                self.update_queue_depth(queue);
+                queue
+                    .jobs_in(&[JobState::Queued, JobState::Created])
+                    .unwrap_or_else(|e| {
+                        tracing::error!(error = %e, "settlement queue");
+                        Vec::new()
+                    })
+            }
+            None => Vec::new(),
+        };
+        for job in &unfinished {
+            tracing::info!(
+                job = job.id,
+                escrow_id = job.escrow_id,
+                nonce = job.nonce,
+                state = job.state.as_str(),
+                pending_tx = job.pending_tx.as_ref().map(|tx| tx.hash.as_str()),
+                "settlement left for restart"
+            );
+        }
+        ShutdownReport {
+            interrupted,
+            unfinished,
+        }
+    }
+
+    /// Settle the created payments waiting for a batch
+    ///
+    /// Pending batches, interrupted by a restart or a failed attempt, are
+    /// resumed first. Waiting payments are then grouped in batches of at most
+    /// `max_size`, a partial batch only once its oldest job waited
+    /// `max_delay`. A batch the contract rejects is failed, and its payments
+    /// settled one by one. Once shutting down, no further batch is started.
+    ///
+    /// # Returns
+    /// * The number of batches settled
+    ///
+    /// # Errors
+    /// * If the queue cannot be read or written
+    pub async fn flush_batches(&self) -> Result<usize, QueueError> {
+        let Some(queue) = &self.queue else {
+            return Ok(0);
+        };
+        let Some(_in_flight) = self.drain.enter() else {
+            return Ok(0);
+        };
+        self.flush_admitted(queue).await
+    }
+
+    /// Flush batches as part of work already admitted by the drain
+    async fn flush_admitted(&self, queue: &SettlementQueue) -> Result<usize, QueueError> {
+        let Some(policy) = queue.batching() else {
+            return Ok(0);
+        };
+        let _flushing = self.flushing.lock().await;
+
+        let mut settled = 0;
+        for batch in queue.pending_batches()? {
+            if self.drain.is_draining() {
+                return Ok(settled);
+            }
+            // Members still held by a worker are picked up on the next run
+            if let Some(claims) = claim_all(queue, &batch.job_ids) {
+                settled += usize::from(self.settle_batch(queue, batch, claims).await?);
+            }
+        }
+
+        let waiting = queue.unbatched()?;
+        let overdue = waiting.first().is_some_and(|job| {
+            now_millis() >= job.enqueued_at + policy.max_delay.as_millis() as u64
+        });
+        for jobs in waiting.chunks(policy.max_size.max(1)) {
+            if (jobs.len() < policy.max_size && !overdue) || self.drain.is_draining() {
+                break;
+            }
+            let ids: Vec<_> = jobs.iter().map(|job| job.id).collect();
+            let Some(claims) = claim_all(queue, &ids) else {
+                continue;
+            };
+            let batch = queue.create_batch(jobs)?;
+            settled += usize::from(self.settle_batch(queue, batch, claims).await?);
+        }
+        self.update_queue_depth(queue);
+        Ok(settled)
+    }
+
+    /// Settle a batch with a single `settle_payments` transaction
+    ///
+    /// # Returns
+    /// * Whether the batch settled
+    async fn settle_batch(
+        &self,
+        queue: &SettlementQueue,
+        mut batch: SettlementBatch,
+        claims: Vec<Claim<'_>>,
+    ) -> Result<bool, QueueError> {
+        let payment_ids = batch.payment_ids.clone();
+        let pending = batch.pending_tx.clone();
+        let result = self
+            .submit_once(
+                "settle_payments",
+                pending,
+                self.client().prepare_settle_payments(&payment_ids),
+                |tx| {
+                    batch.pending_tx = tx;
+                    queue.save_batch(&batch)
+                },
+            )
+            .await;
+        batch.pending_tx = None;
+
+        match result {
+            Ok(submitted) => {
+                self.reached(Stage::Confirm);
+                let ledger = submitted.ledger;
+                batch.state = BatchState::Settled;
+                batch.tx_hash = Some(submitted.hash);
+                batch.error = None;
+                queue.save_batch(&batch)?;
+                for id in &batch.job_ids {
+                    let Some(job) = queue.job(*id)? else {
+                        continue;
+                    };
+                    self.metrics.settled(&job.asset, job.amount);
+                    let tx_hash = job.tx_hash.as_deref();
+                    self.record_settled(&job.payment(), job.payment_id, tx_hash, Some(ledger))
+                        .await;
+                }
+                Ok(true)
+            }
+            Err(e) if e.is_permanent() => {
+                batch.state = BatchState::Failed;
+                batch.error = Some(e.to_string());
+                queue.save_batch(&batch)?;
+                drop(claims);
+                for id in &batch.job_ids {
+                    self.process_job(queue, *id).await;
+                }
+                Ok(false)
+            }
+            Err(e) => {
+                batch.error = Some(e.to_string());
+                queue.save_batch(&batch)?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Advance a job as far as possible and record the outcome
+    ///
+    /// # Returns
+    /// * The job as saved, None if it does not exist
+    async fn process_job(&self, queue: &SettlementQueue, id: i64) -> Option<SettlementJob> {
+        // Another worker holds the job, report it as it stands
+        let Some(_claim) = queue.claim(id) else {
+            return queue.job(id).ok().flatten();
+        };
+        let mut job = queue.job(id).ok().flatten()?;
+        if job.state.is_final() {
+            return Some(job);
+        }
+
+        match self.advance(queue, &mut job).await {
+            Ok(false) => {}
+            Ok(true) => {
+                self.metrics.settled(&job.asset, job.amount);
+                self.record_settled(&job.payment(), job.payment_id, job.tx_hash.as_deref(), None)
+                    .await;
+            }
+            Err(e) => {
+                job.attempts += 1;
+                job.error = Some(e.to_string());
+                let permanent = e.is_permanent() && !e.is_paused();
+                if permanent || job.attempts >= queue.retry().max_attempts {
+                    job.state = JobState::Failed;
+                    self.metrics.settlement_failed(e.code());
+                    self.notify_failed(&job.payment(), job.payment_id, &e.to_string());
+                } else {
+                    let backoff = queue.retry().backoff(job.attempts);
+                    job.next_attempt_at = now_millis() + backoff.as_millis() as u64;
+                }
+            }
+        }
+        if let Err(e) = queue.save(&job) {
+            tracing::error!(job = id, error = %e, "cannot save settlement job");
+        }
+        self.update_queue_depth(queue);
+        Some(job)
+    }
+
+    /// Create then settle the job's payment, saving each step
+    ///
+    /// # Returns
+    /// * Whether the payment settled, false if it waits for a batch
+    async fn advance(
+        &self,
+        queue: &SettlementQueue,
+        job: &mut SettlementJob,
+    ) -> Result<bool, JobError> {
+        loop {
+            match job.state {
+                JobState::Queued => {
+                    let created = self.submit_step(queue, job).await?;
+                    self.reached(Stage::Submit);
+                    job.payment_id = Some(scval::to_u64(&created.value)?);
+                    job.state = JobState::Created;
+                }
+                JobState::Created if queue.awaits_batch(job)? => return Ok(false),
+                JobState::Created => match self.submit_step(queue, job).await {
+                    // Declined by the escrow's spend limit, applied without
+                    // settling, so the next attempt signs a new transaction
+                    Ok(settled) if settled.value == ScVal::Bool(false) => {
+                        job.pending_tx = None;
+                        let payment_id = job.payment_id.unwrap_or_default();
+                        return Err(ClientError::settlements_paused(payment_id).into());
+                    }
+                    Ok(settled) => {
+                        self.reached(Stage::Confirm);
+                        job.tx_hash = Some(settled.hash);
+                        job.state = JobState::Settled;
+                    }
+                    // Settled by an attempt whose transaction was not saved
+                    Err(JobError::Client(ClientError::Contract(
+                        ContractError::PaymentAlreadySettled,
+                        _,
+                    ))) => job.state = JobState::Settled,
+                    Err(e) => return Err(e),
+                },
+                JobState::Settled | JobState::Failed => return Ok(true),
+            }
+            job.pending_tx = None;
+            job.error = None;
+            queue.save(job)?;
+        }
+    }
+
+    /// Submit the transaction of the job's current step exactly once
+    async fn submit_step(
+        &self,
+        queue: &SettlementQueue,
+        job: &mut SettlementJob,
+    ) -> Result<Submitted<ScVal>, JobError> {
+        let settling = match (job.state, job.payment_id) {
+            (JobState::Queued, _) => None,
+            (_, Some(payment_id)) => Some(payment_id),
+            (_, None) => return Err(QueueError::Corrupt(job.id).into()),
+        };
+        let (escrow_id, amount) = (job.escrow_id, job.amount);
+        let call = match settling {
+            Some(_) => "settle_payment",
+            None => "create_payment",
+        };
+        let client = self.tagged_client(&job.payment());
+        let prepare = async move {
+            match settling {
+                Some(payment_id) => client.prepare_settle_payment(payment_id).await,
+                None => client.prepare_create_payment(escrow_id, amount).await,
+            }
+        };
+        let pending = job.pending_tx.clone();
+        self.submit_once(call, pending, prepare, |tx| {
+            job.pending_tx = tx;
+            queue.save(job)
+        })
+        .await
+    }
+
+    /// Submit a transaction exactly once across attempts and restarts
+    ///
+    /// The signed transaction is saved before it is first submitted, as is
+    /// each fee bump of it replacing it. A saved transaction is looked up,
+    /// then resubmitted, and only replaced once its sequence number was
+    /// consumed without applying it.
+    ///
+    /// # Arguments
+    /// * `call` - Contract call label of the RPC metrics
+    /// * `pending` - Transaction saved by a previous attempt
+    /// * `prepare` - Signs a new transaction
+    /// * `save` - Persists the transaction or fee bump about to be
+    ///   submitted, or its removal
+    async fn submit_once(
+        &self,
+        call: &str,
+        pending: Option<PreparedTransaction>,
+        prepare: impl Future<Output = Result<PreparedTransaction, ClientError>>,
+        mut save: impl FnMut(Option<PreparedTransaction>) -> Result<(), QueueError>,
+    ) -> Result<Submitted<ScVal>, JobError> {
+        // A bump that cannot be saved is not sent, so a restart never loses
+        // track of a transaction that may apply
+        let client = self.client();
+        if let Some(tx) = pending {
+            let lookup = client.lookup(&tx);
+            if let Some(submitted) = self.metrics.rpc("get_transaction", lookup).await? {
+                return Ok(submitted);
+            }
+            // Rejected when signed by a key rotated out, then signed anew
+            let submit = client.submit_with(&tx, |bump| save(Some(bump.clone())).is_ok());
+            match self.metrics.rpc(call, submit).await {
+                Err(ClientError::Rejected { .. }) => {
+                    if let Some(submitted) = client.lookup(&tx).await? {
+                        return Ok(submitted);
+                    }
+                    save(None)?;
+                }
+                result => return Ok(result?),
+            }
+        }
+
+        let tx = prepare.await?;
+        save(Some(tx.clone()))?;
+        let submit = client.submit_with(&tx, |bump| save(Some(bump.clone())).is_ok());
+        Ok(self.metrics.rpc(call, submit).await?)
+    }
+
+    fn update_queue_depth(&self, queue: &SettlementQueue) {
+        if let Ok(depth) = queue.depth() {
+            self.metrics.set_queue_depth(depth);
+        }
+    }
+
+    /// Settlement jobs in `state`, or unfinished ones, oldest first
+    ///
+    /// # Errors
+    /// * `NoQueue` - If no settlement queue is configured
+    /// * `Queue` - If the queue cannot be read
+    pub fn list_jobs(&self, state: Option<JobState>) -> Result<Vec<SettlementJob>, AdminError> {
+        let queue = self.queue.as_ref().ok_or(AdminError::NoQueue)?;
+        let states = match state {
+            Some(state) => vec![state],
+            None => vec![JobState::Queued, JobState::Created],
+        };
+        Ok(queue.jobs_in(&states)?)
+    }
+
+    /// Attempt a settlement job now, whatever its backoff
+    ///
+    /// A failed job resumes from its last completed step with its attempts
+    /// reset. Its nonce stayed reserved, so the authorization was not spent
+    /// elsewhere in the meantime.
+    ///
+    /// # Returns
+    /// * The job as saved after the attempt
+    ///
+    /// # Errors
+    /// * `NoQueue` - If no settlement queue is configured
+    /// * `JobNotFound` - If the job does not exist
+    /// * `JobBusy` - If a worker is processing the job
+    /// * `JobSettled` - If the job is already settled
+    /// * `Queue` - If the queue cannot be read or written
+    pub async fn retry_job(&self, id: i64) -> Result<SettlementJob, AdminError> {
+        let queue = self.queue.as_ref().ok_or(AdminError::NoQueue)?;
+        {
+            let _claim = queue.claim(id).ok_or(AdminError::JobBusy(id))?;
+            let mut job = queue.job(id)?.ok_or(AdminError::JobNotFound(id))?;
+            match job.state {
+                JobState::Settled => return Err(AdminError::JobSettled(id)),
+                JobState::Failed if job.payment_id.is_some() => job.state = JobState::Created,
+                JobState::Failed => job.state = JobState::Queued,
+                JobState::Queued | JobState::Created => {}
+            }
+            job.attempts = 0;
+            job.next_attempt_at = 0;
+            queue.save(&job)?;
+        }
+        self.process_job(queue, id)
+            .await
+            .ok_or(AdminError::JobNotFound(id))
+    }
+
+    /// Give up on a settlement job before it charges the client
+    ///
+    /// The job is failed and `payment.failed` emitted. Its nonce stays used,
+    /// so the authorization cannot be charged later. A payment already
+    /// created is left unsettled, its amount staying in the escrow.
+    ///
+    /// # Returns
+    /// * The job as saved
+    ///
+    /// # Errors
+    /// * `NoQueue` - If no settlement queue is configured
+    /// * `JobNotFound` - If the job does not exist
+    /// * `JobBusy` - If a worker is processing the job
+    /// * `JobSettled` - If the job is already settled
+    /// * `JobInFlight` - If a transaction of the job, or of its pending batch,
+    ///   may still apply
+    /// * `Queue` - If the queue cannot be read or written
+    pub fn cancel_job(&self, id: i64) -> Result<SettlementJob, AdminError> {
+        let queue = self.queue.as_ref().ok_or(AdminError::NoQueue)?;
+        let _claim = queue.claim(id).ok_or(AdminError::JobBusy(id))?;
+        let mut job = queue.job(id)?.ok_or(AdminError::JobNotFound(id))?;
+        match job.state {
+            JobState::Settled => return Err(AdminError::JobSettled(id)),
+            JobState::Failed => return Ok(job),
+            JobState::Queued | JobState::Created => {}
+        }
+        let batched = match job.batch_id {
+            Some(batch_id) => queue
+                .batch(batch_id)?
+                .is_some_and(|batch| batch.state == BatchState::Pending),
+            None => false,
+        };
+        if job.pending_tx.is_some() || batched {
+            return Err(AdminError::JobInFlight(id));
+        }
+
+        let reason = "cancelled by an operator";
+        job.state = JobState::Failed;
+        job.error = Some(reason.into());
+        queue.save(&job)?;
+        self.metrics.settlement_failed("cancelled");
+        self.notify_failed(&job.payment(), job.payment_id, reason);
+        self.update_queue_depth(queue);
+        Ok(job)
+    }
+
+    /// On-chain payments of an escrow, oldest first, with the settlement
+    /// jobs that created them
+    ///
+    /// # Errors
+    /// * `Client` - If the payments cannot be read
+    /// * `Queue` - If the settlement queue cannot be read
+    pub async fn escrow_payments(&self, escrow_id: u64) -> Result<Vec<PaymentRecord>, AdminError> {
+        let payments = self.chain_payments(escrow_id).await?;
+        let jobs = self.jobs_by_payment(escrow_id)?;
+        Ok(payments
+            .iter()
+            .map(|(id, payment)| PaymentRecord::new(*id, payment, jobs.get(id).copied()))
+            .collect())
+    }
+
+    /// On-chain payment `payment_id`, with the settlement job that created
+    /// it
+    ///
+    /// # Returns
+    /// * None if the payment does not exist
+    ///
+    /// # Errors
+    /// * `Client` - If the payment cannot be read
+    /// * `Queue` - If the settlement queue cannot be read
+    pub async fn payment_record(
+        &self,
+        payment_id: u64,
+    ) -> Result<Option<PaymentRecord>, AdminError> {
+        let client = self.client();
+        let lookup = client.get_payment(payment_id);
+        let payment = match self.metrics.rpc("get_payment", lookup).await {
+            Ok(payment) => payment,
+            Err(ClientError::Contract(ContractError::PaymentNotFound, _)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let job_id = self
+            .jobs_by_payment(payment.escrow_id)?
+            .get(&payment_id)
+            .copied();
+        Ok(Some(PaymentRecord::new(payment_id, &payment, job_id)))
+    }
+
+    /// On-chain payments settled by transaction `tx_hash`, per the
+    /// settlement queue, several for a batch
+    ///
+    /// # Errors
+    /// * `NoQueue` - If no settlement queue is configured
+    /// * `Client` - If a payment cannot be read
+    /// * `Queue` - If the queue cannot be read
+    pub async fn transaction_payments(
+        &self,
+        tx_hash: &str,
+    ) -> Result<Vec<PaymentRecord>, AdminError> {
+        let queue = self.queue.as_ref().ok_or(AdminError::NoQueue)?;
+        let mut records = vec![];
+        for payment_id in queue
+            .jobs_with_tx(tx_hash)?
+            .iter()
+            .filter_map(|job| job.payment_id)
+        {
+            records.extend(self.payment_record(payment_id).await?);
+        }
+        Ok(records)
+    }
+
+    /// Compare the settlement queue with the payments the contract lists for
+    /// the queue's escrows and the open escrows of the server
+    ///
+    /// Jobs whose payment settled on-chain, e.g. by a transaction whose
+    /// outcome was lost, are marked settled. Other contradictions are
+    /// reported without repair: counted in the metrics, and notified as a
+    /// `reconciliation.discrepancy` webhook event when not found by the
+    /// previous reconciliation. Jobs held by a worker are skipped.
+    ///
+    /// # Errors
+    /// * `NoQueue` - If no settlement queue is configured
+    /// * `Client` - If the payments of an escrow cannot be read
+    /// * `Queue` - If the queue cannot be read or written
+    pub async fn reconcile(&self) -> Result<Reconciliation, AdminError> {
+        let queue = self.queue.as_ref().ok_or(AdminError::NoQueue)?;
+        let jobs = queue.jobs()?;
+        let mut escrows: BTreeSet<u64> = jobs.iter().map(|job| job.escrow_id).collect();
+        let open = self
+            .metrics
+            .rpc(
+                "export_escrows",
+                self.client().export_escrows(Some(&self.server)),
+            )
+            .await?;
+        escrows.extend(open.iter().map(|(escrow_id, _)| escrow_id));
+
+        let mut report = Reconciliation {
+            escrows_checked: escrows.len(),
+            ..Reconciliation::default()
+        };
+        for escrow_id in escrows {
+            let payments = self.chain_payments(escrow_id).await?;
+            report.payments_checked += payments.len();
+            let mut created = HashSet::new();
+            for job in jobs.iter().filter(|job| job.escrow_id == escrow_id) {
+                let Some(payment_id) = job.payment_id else {
+                    continue;
+                };
+                created.insert(payment_id);
+                report.jobs_checked += 1;
+                let reason = match payments.get(&payment_id) {
+                    None => "missing_on_chain",
+                    Some(payment) if !payment.settled && job.state == JobState::Settled => {
+                        "unsettled_on_chain"
+                    }
+                    Some(payment) if payment.settled && job.state != JobState::Settled => {
+                        if self.mark_settled(queue, job.id).await? {
+                            report.repaired.push(job.id);
+                        }
+                        continue;
+                    }
+                    Some(_) => continue,
+                };
+                report.discrepancies.push(Discrepancy {
+                    job_id: job.id,
+                    payment_id,
+                    reason: reason.into(),
+                });
+            }
+            report.orphaned.extend(
+                payments
+                    .iter()
+                    .filter(|(id, _)| !created.contains(id))
+                    .map(|(id, payment)| PaymentRecord::new(*id, payment, None)),
+            );
+        }
+        self.update_queue_depth(queue);
+        self.metrics.reconciled(&report);
+        self.notify_discrepancies(&report);
+        Ok(report)
+    }
+
+    /// Notify the discrepancies of a reconciliation the previous one did not
+    /// find
+    fn notify_discrepancies(&self, report: &Reconciliation) {
+        let mut reported = self.reported.lock().unwrap();
+        let previous = std::mem::take(&mut *reported);
+        let mut is_new = |key: String| {
+            let new = !previous.contains(&key);
+            reported.insert(key);
+            new
+        };
+        let discrepancies: Vec<_> = report
+            .discrepancies
+            .iter()
+            .filter(|d| is_new(format!("{}:{}", d.reason, d.job_id)))
+            .collect();
+        let orphaned: Vec<_> = report
+            .orphaned
+            .iter()
+            .filter(|p| is_new(format!("orphaned:{}", p.payment_id)))
+            .collect();
+        if discrepancies.is_empty() && orphaned.is_empty() {
+            return;
+        }
+        self.notify(
+            EventKind::ReconciliationDiscrepancy,
+            json!({
+                "server": self.server,
+                "network": self.network,
+                "discrepancies": discrepancies,
+                "orphaned": orphaned,
+            }),
+        );
+    }
+
+    /// Every payment of an escrow, read through `get_payments`
+    async fn chain_payments(&self, escrow_id: u64) -> Result<BTreeMap<u64, Payment>, ClientError> {
+        let client = self.client();
+        let query = client.payments(escrow_id).collect_all(usize::MAX);
+        let payments = self.metrics.rpc("get_payments", query).await?;
+        Ok(payments.into_iter().collect())
+    }
+
+    /// Settlement jobs of an escrow by the payment they created
+    fn jobs_by_payment(&self, escrow_id: u64) -> Result<HashMap<u64, i64>, QueueError> {
+        let Some(queue) = &self.queue else {
+            return Ok(HashMap::new());
+        };
+        let jobs = queue.jobs_of_escrow(escrow_id)?;
+        Ok(jobs
+            .iter()
+            .filter_map(|job| Some((job.payment_id?, job.id)))
+            .collect())
+    }
+
+    /// Mark a job settled, its payment being settled on-chain
+    ///
+    /// # Returns
+    /// * Whether the job was marked, false if a worker holds it
+    async fn mark_settled(&self, queue: &SettlementQueue, id: i64) -> Result<bool, QueueError> {
+        let Some(_claim) = queue.claim(id) else {
+            return Ok(false);
+        };
+        let Some(mut job) = queue.job(id)? else {
+            return Ok(false);
+        };
+        if job.state == JobState::Settled {
+            return Ok(false);
+        }
+        job.state = JobState::Settled;
+        job.pending_tx = None;
+        job.error = None;
+        queue.save(&job)?;
+        self.metrics.settled(&job.asset, job.amount);
+        self.record_settled(&job.payment(), job.payment_id, job.tx_hash.as_deref(), None)
+            .await;
+        Ok(true)
+    }
+
+    /// Verify a payment header against the requirements
+    ///
+    /// # Errors
+    /// * The [`VerifyError`] describing the first failed check
+    pub async fn check(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Result<VerifiedPayment, VerifyError> {
+        self.check_expiring(header, requirements)
+            .await
+            .map(|checked| checked.payment)
+    }
+
+    /// Balance of an escrow payments may still draw on, in stroops, zero
+    /// once either party closed it
+    ///
+    /// # Errors
+    /// * The error reading the escrow
+    pub async fn available_balance(&self, escrow_id: u64) -> Result<i128, ClientError> {
+        let escrow = self
+            .metrics
+            .rpc("get_escrow", self.client().get_escrow(escrow_id))
+            .await?;
+        self.credit.observe(escrow_id, &escrow);
+        if escrow.client_closed || escrow.server_closed {
+            return Ok(0);
+        }
+        Ok(escrow.balance)
+    }
+
+    /// Verify a payment header, then reserve its nonce, or the transaction
+    /// of a direct payment, in the replay cache
+    async fn check_reserved(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Result<VerifiedPayment, VerifyError> {
+        let Checked {
+            payment,
+            expires_at,
+            ..
+        } = self.check_expiring(header, requirements).await?;
+        let reserved = match &payment.tx_hash {
+            Some(tx_hash) => {
+                self.replay
+                    .try_reserve_transaction(tx_hash, expires_at)
+                    .await
+            }
+            None => {
+                self.replay
+                    .try_reserve(payment.escrow_id, payment.nonce, expires_at)
+                    .await
+            }
+        };
+        match reserved {
+            Ok(true) => Ok(payment),
+            Ok(false) => Err(VerifyError::NonceReplayed),
+            Err(e) => Err(VerifyError::ReplayCache(e.to_string())),
+        }
+    }
+
+    /// Close time of the latest ledger, which the contract checks expiries
+    /// against
+    ///
+    /// Read from RPC at most once per [`LEDGER_TIME_TTL`], falling back to
+    /// the local clock while RPC is unreachable.
+    async fn ledger_time(&self) -> u64 {
+        let cached = *self.ledger_time.lock().unwrap();
+        if let Some((time, _)) = cached.filter(|(_, read)| read.elapsed() < LEDGER_TIME_TTL) {
+            return time;
+        }
+        let read = self
+            .metrics
+            .rpc("get_ledger_time", self.client().rpc().get_ledger_time())
+            .await;
+        match read {
+            Ok(time) => {
+                *self.ledger_time.lock().unwrap() = Some((time, Instant::now()));
+                time
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "ledger time unavailable, using the local clock");
+                now()
+            }
+        }
+    }
+
+    /// [`Facilitator::check`], also returning when the authorization expires
+    ///
+    /// With [`Degradation::ServeAndQueue`] and a settlement queue, an escrow
+    /// that cannot be read because RPC is unreachable is checked as last
+    /// read, the payment being accepted on the escrow's remaining credit.
+    async fn check_expiring(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Result<Checked, VerifyError> {
+        let payload = decode_payment_header(header).map_err(|e| match e {
+            HeaderError::UnsupportedVersion { version, .. } => {
+                VerifyError::UnsupportedVersion(version)
+            }
+            e => VerifyError::InvalidPayload(e.to_string()),
+        })?;
+        if requirements
+            .x402_version
+            .is_some_and(|version| version != payload.x402_version)
+        {
+            return Err(VerifyError::UnsupportedVersion(payload.x402_version.into()));
+        }
+        let direct_payments = self.settings.read().unwrap().direct_payments;
+        let supported = match payload.scheme.as_str() {
+            ESCROW_SCHEME => true,
+            EXACT_SCHEME => direct_payments.is_some(),
+            _ => false,
+        };
+        if !supported || requirements.scheme != payload.scheme {
+            return Err(VerifyError::UnsupportedScheme(payload.scheme));
+        }
+        if payload.network != self.network || requirements.network != self.network {
+            return Err(VerifyError::NetworkMismatch(payload.network));
+        }
+        if requirements.pay_to != self.server {
+            return Err(VerifyError::RecipientMismatch);
+        }
+        // Payments pick one of the assets offered, at its amount
+        let requirements = &requirements
+            .for_asset(payload.asset.as_deref())
+            .ok_or(VerifyError::AssetMismatch)?;
+        let escrow_payload = match (payload.payload, direct_payments) {
+            (SchemePayload::Escrow(escrow_payload), _) if payload.scheme == ESCROW_SCHEME => {
+                escrow_payload
+            }
+            (SchemePayload::TransactionHash(proof), Some(policy))
+                if payload.scheme == EXACT_SCHEME =>
+            {
+                let (payment, expires_at) = self
+                    .check_direct(&proof.tx_hash, requirements, &policy)
+                    .await?;
+                return Ok(Checked {
+                    payment,
+                    expires_at,
+                    max_credit: None,
+                    deposit: None,
+                    preauthorized: None,
+                    direct: None,
+                });
+            }
+            _ => return Err(VerifyError::UnsupportedScheme(payload.scheme)),
+        };
+
+        // Stateless checks first, the escrow lookup costs an RPC call
+        let amount = parse_amount(&escrow_payload.amount)?;
+        if amount > parse_amount(&requirements.max_amount_required)? {
+            return Err(VerifyError::AmountExceedsRequirement);
+        }
+        // Expiries are those the contract sees, by ledger time
+        let ledger_time = self.ledger_time().await;
+        let clock_skew = self.settings.read().unwrap().clock_skew.as_secs();
+        let expires_at = escrow_payload.expires_at.saturating_add(clock_skew);
+        if ledger_time > expires_at {
+            return Err(VerifyError::Expired);
+        }
+        if self.is_nonce_used(escrow_payload.escrow_id, escrow_payload.nonce) {
+            return Err(VerifyError::NonceUsed);
+        }
+        let escrow_client = self.client();
+        let contract_id = escrow_client.contract_id();
+        let domain = SigningDomain {
+            network_id: escrow_client.network_id(),
+            contract_id: &contract_id,
+        };
+        verify_authorization(&escrow_payload, &self.network, domain)?;
+
+        let escrow_id = escrow_payload.escrow_id;
+        let mut max_credit = None;
+        let escrow = match self
+            .metrics
+            .rpc("get_escrow", self.client().get_escrow(escrow_id))
+            .await
+        {
+            Ok(escrow) => {
+                self.credit.observe(escrow_id, &escrow);
+                escrow
+            }
+            Err(ClientError::Contract(ContractError::EscrowNotFound, _)) => {
+                let fallback = self.settings.read().unwrap().direct_fallback;
+                let entry = match (&escrow_payload.direct_authorization, fallback) {
+                    (Some(entry), Some(max)) if amount <= max => entry,
+                    _ => return Err(VerifyError::EscrowNotFound),
+                };
+                if escrow_payload.payment_id.is_some() {
+                    return Err(VerifyError::EscrowNotFound);
+                }
+                let direct = self
+                    .check_fallback(entry, &escrow_payload, amount, requirements)
+                    .await?;
+                return Ok(Checked {
+                    payment: VerifiedPayment {
+                        escrow_id,
+                        client: escrow_payload.client,
+                        amount,
+                        nonce: escrow_payload.nonce,
+                        tx_hash: None,
+                        context: None,
+                    },
+                    expires_at: expires_at.saturating_add(now().saturating_sub(ledger_time)),
+                    max_credit: None,
+                    deposit: None,
+                    preauthorized: None,
+                    direct: Some(direct),
+                });
+            }
+            Err(e) => {
+                let degradation = self.settings.read().unwrap().degradation;
+                let last_known = match degradation {
+                    Degradation::ServeAndQueue { max_credit: limit }
+                        if e.is_transient() && self.queue.is_some() =>
+                    {
+                        max_credit = Some(limit);
+                        self.credit.last_known(escrow_id)
+                    }
+                    _ => None,
+                };
+                last_known.ok_or_else(|| VerifyError::Rpc(e.to_string()))?
+            }
+        };
+        if escrow.client != escrow_payload.client {
+            return Err(VerifyError::ClientMismatch);
+        }
+        if escrow.server != self.server {
+            return Err(VerifyError::RecipientMismatch);
+        }
+        if escrow.client_closed || escrow.server_closed {
+            return Err(VerifyError::EscrowClosed);
+        }
+        let mut deposit = None;
+        if escrow.balance < amount {
+            // Escrows on credit are only checked as last read
+            let (entry, deposited) = match (&escrow_payload.deposit_authorization, max_credit) {
+                (Some(entry), None) => self.check_deposit(entry, escrow_id, &escrow.client).await?,
+                _ => return Err(VerifyError::InsufficientBalance),
+            };
+            if escrow.balance.saturating_add(deposited) < amount {
+                return Err(VerifyError::InsufficientBalance);
+            }
+            deposit = Some(entry);
+        }
+        if max_credit.is_some_and(|limit| self.credit.available(escrow_id, limit) < amount) {
+            return Err(VerifyError::CreditExhausted);
+        }
+        if let Some(payment_id) = escrow_payload.payment_id {
+            let nonce = escrow_payload.nonce;
+            let created = self
+                .metrics
+                .rpc(
+                    "get_preauthorized_payment",
+                    self.client().get_preauthorized_payment(escrow_id, nonce),
+                )
+                .await
+                .map_err(|e| VerifyError::Rpc(e.to_string()))?;
+            let payment = match created {
+                Some(created) if created == payment_id => self
+                    .metrics
+                    .rpc("get_payment", self.client().get_payment(payment_id))
+                    .await
+                    .map_err(|e| VerifyError::Rpc(e.to_string()))?,
+                _ => {
+                    return Err(VerifyError::InvalidPayload(format!(
+                        "payment {payment_id} was not created from this authorization"
+                    )))
+                }
+            };
+            // The nonce may have paid another amount than the payload's
+            if payment.amount != amount {
+                return Err(VerifyError::InvalidPayload(format!(
+                    "payment {payment_id} is of another amount"
+                )));
+            }
+            if payment.settled {
+                return Err(VerifyError::NonceUsed);
+            }
+        }
+
+        let payment = VerifiedPayment {
+            escrow_id,
+            client: escrow_payload.client,
+            amount,
+            nonce: escrow_payload.nonce,
+            tx_hash: None,
+            context: None,
+        };
+        Ok(Checked {
+            payment,
+            // Replay caches expire by the local clock, which the ledger's
+            // trails
+            expires_at: expires_at.saturating_add(now().saturating_sub(ledger_time)),
+            max_credit,
+            deposit,
+            preauthorized: escrow_payload.payment_id,
+            direct: None,
+        })
+    }
+
+    /// Check a deposit into an escrow pre-signed by its client
+    ///
+    /// # Returns
+    /// * The entry, and the amount it deposits
+    async fn check_deposit(
+        &self,
+        entry: &str,
+        escrow_id: u64,
+        client: &str,
+    ) -> Result<(AuthorizationEntry, i128), VerifyError> {
+        self.check_presigned(entry, "deposit", client, |op| match op {
+            EscrowOp::Deposit {
+                escrow_id: id,
+                amount,
+            } if id == escrow_id && amount > 0 => Ok(amount),
+            _ => Err("not a deposit into the escrow"),
+        })
+        .await
+    }
+
+    /// Check a direct payment pre-signed by the client of an escrow payment
+    /// whose escrow does not exist
+    ///
+    /// It must transfer the payment's amount of the asset paid to the
+    /// server, referenced by the payload's
+    /// [signing hash](EscrowPayload::signing_hash) so that it pays for this
+    /// authorization only.
+    async fn check_fallback(
+        &self,
+        entry: &str,
+        payload: &EscrowPayload,
+        amount: i128,
+        requirements: &PaymentRequirements,
+    ) -> Result<AuthorizationEntry, VerifyError> {
+        let escrow_client = self.client();
+        let token = match requirements.asset.as_deref() {
+            None | Some(NATIVE_ASSET) => {
+                direct::asset_contract_id(&Asset::Native, &escrow_client.network_id())
+            }
+            Some(asset) => asset.to_string(),
+        };
+        let contract_id = escrow_client.contract_id();
+        let domain = SigningDomain {
+            network_id: escrow_client.network_id(),
+            contract_id: &contract_id,
+        };
+        let reference = payload.signing_hash(&self.network, domain);
+        let expected = |op: EscrowOp| match op {
+            EscrowOp::RecordDirectPayment {
+                client,
+                server,
+                token: paid,
+                amount: transferred,
+                reference: referenced,
+            } if client == payload.client
+                && server == self.server
+                && paid == token
+                && transferred == amount
+                && referenced == reference =>
+            {
+                Ok(())
+            }
+            _ => Err("not a direct payment of the authorization to the server"),
+        };
+        let (entry, ()) = self
+            .check_presigned(entry, "direct payment", &payload.client, expected)
+            .await?;
+        Ok(entry)
+    }
+
+    /// Check a call pre-signed by a client
+    ///
+    /// The entry's signature is checked to be valid, whether its key may
+    /// sign for the client being up to the network.
+    ///
+    /// # Arguments
+    /// * `kind` - Call expected, naming it in errors
+    /// * `expected` - Reads the operation, or describes why it is not the
+    ///   one expected
+    ///
+    /// # Returns
+    /// * The entry, and what `expected` read
+    async fn check_presigned<T>(
+        &self,
+        entry: &str,
+        kind: &str,
+        client: &str,
+        expected: impl FnOnce(EscrowOp) -> Result<T, &'static str>,
+    ) -> Result<(AuthorizationEntry, T), VerifyError> {
+        let invalid = |reason: &str| {
+            VerifyError::InvalidPayload(format!("invalid {kind} authorization: {reason}"))
+        };
+        let entry =
+            AuthorizationEntry::from_xdr_base64(entry).map_err(|e| invalid(&e.to_string()))?;
+        let escrow_client = self.client();
+        if entry.contract_id().ok() != Some(escrow_client.contract_id()) {
+            return Err(invalid("for another contract"));
+        }
+        let read = entry
+            .op()
+            .map_err(|e| invalid(&e.to_string()))
+            .and_then(|op| expected(op).map_err(invalid))?;
+        if entry.address().ok().as_deref() != Some(client) {
+            return Err(VerifyError::ClientMismatch);
+        }
+        entry
+            .verify(&escrow_client.network_id())
+            .map_err(|_| VerifyError::InvalidSignature)?;
+        let latest = self
+            .metrics
+            .rpc("get_latest_ledger", escrow_client.rpc().get_latest_ledger())
+            .await
+            .map_err(|e| VerifyError::Rpc(e.to_string()))?;
+        if entry.expiration_ledger().unwrap_or_default() < latest.sequence {
+            return Err(VerifyError::Expired);
+        }
+        Ok((entry, read))
+    }
+
+    /// Verify the transaction of a direct payment against the requirements
+    async fn check_direct(
+        &self,
+        tx_hash: &str,
+        requirements: &PaymentRequirements,
+        policy: &DirectPayments,
+    ) -> Result<(VerifiedPayment, u64), VerifyError> {
+        let tx_hash = tx_hash.to_ascii_lowercase();
+        if tx_hash.len() != 64 || hex::decode(&tx_hash).is_err() {
+            return Err(VerifyError::InvalidPayload(format!(
+                "invalid transaction hash {tx_hash}"
+            )));
+        }
+        if self.used_transactions.lock().unwrap().contains(&tx_hash) {
+            return Err(VerifyError::NonceUsed);
+        }
+        let tx = self
+            .metrics
+            .rpc(
+                "get_transaction",
+                self.client().rpc().get_transaction(&tx_hash),
+            )
+            .await
+            .map_err(|e| VerifyError::Rpc(e.to_string()))?;
+        let ledger_time = self.ledger_time().await;
+        let paid = direct::check_transaction(
+            &tx,
+            requirements,
+            &self.client().network_id(),
+            policy,
+            ledger_time,
+        )?;
+        let payment = VerifiedPayment {
+            escrow_id: 0,
+            client: paid.payer,
+            amount: paid.amount,
+            nonce: 0,
+            tx_hash: Some(tx_hash),
+            context: None,
+        };
+        let expires_at = paid
+            .expires_at
+            .saturating_add(now().saturating_sub(ledger_time));
+        Ok((payment, expires_at))
+    }
+
+    fn notify(&self, kind: EventKind, data: Value) {
+        let event = WebhookEvent::new(kind, data);
+        // Fails only without subscribers
+        let _ = self.events.send(event.clone());
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.emit_event(event);
+        }
+    }
+
+    /// Notify a settlement, tracking it until final if it is not yet
+    ///
+    /// # Returns
+    /// * The ledger the settlement was included in and its finality, if
+    ///   known
+    async fn record_settled(
+        &self,
+        payment: &VerifiedPayment,
+        payment_id: Option<u64>,
+        tx_hash: Option<&str>,
+        ledger: Option<u32>,
+    ) -> (Option<u32>, Option<Finality>) {
+        self.release_credit(payment);
+        let (ledger, finality) = self.finality_of(payment.amount, tx_hash, ledger).await;
+        if let (Some(ledger), Some(Finality::Included)) = (ledger, finality) {
+            self.included.lock().unwrap().push(IncludedSettlement {
+                payment: payment.clone(),
+                payment_id,
+                tx_hash: tx_hash.map(String::from),
+                ledger,
+            });
+        }
+        let mut data = self.settlement_data(payment, payment_id, tx_hash);
+        if let Some(finality) = finality {
+            data["ledger"] = json!(ledger);
+            data["finality"] = json!(finality);
+        }
+        self.notify(EventKind::PaymentSettled, data);
+        (ledger, finality)
+    }
+
+    /// Ledger a settlement was included in and its finality when settled
+    ///
+    /// Without a finality policy only a known ledger is reported. With one,
+    /// an unknown ledger is looked up by the settlement's transaction hash.
+    async fn finality_of(
+        &self,
+        amount: i128,
+        tx_hash: Option<&str>,
+        ledger: Option<u32>,
+    ) -> (Option<u32>, Option<Finality>) {
+        let Some(policy) = &self.finality else {
+            return (ledger, None);
+        };
+        let ledger = match (ledger, tx_hash) {
+            (Some(ledger), _) => Some(ledger),
+            (None, Some(hash)) => self
+                .metrics
+                .rpc("get_transaction", self.client().rpc().get_transaction(hash))
+                .await
+                .ok()
+                .and_then(|tx| tx.ledger),
+            (None, None) => None,
+        };
+        let finality = ledger.map(|_| match policy.depth_for(amount) {
+            0 => Finality::Final,
+            _ => Finality::Included,
+        });
+        (ledger, finality)
+    }
+
+    /// Notify the included settlements that became final
+    ///
+    /// # Returns
+    /// * The number of settlements notified as final
+    ///
+    /// # Errors
+    /// * If the latest ledger cannot be read
+    pub async fn finalize_settlements(&self) -> Result<usize, ClientError> {
+        let Some(policy) = &self.finality else {
+            return Ok(0);
+        };
+        if self.included.lock().unwrap().is_empty() {
+            return Ok(0);
+        }
+        let latest = self
+            .metrics
+            .rpc("get_latest_ledger", self.client().rpc().get_latest_ledger())
+            .await?
+            .sequence;
+        let finalized: Vec<_> = {
+            let mut included = self.included.lock().unwrap();
+            let (finalized, waiting) = std::mem::take(&mut *included).into_iter().partition(|s| {
+                policy.finality(s.payment.amount, s.ledger, latest) == Finality::Final
+            });
+            *included = waiting;
+            finalized
+        };
+        for settlement in &finalized {
+            let tx_hash = settlement.tx_hash.as_deref();
+            let mut data =
+                self.settlement_data(&settlement.payment, settlement.payment_id, tx_hash);
+            data["ledger"] = json!(settlement.ledger);
+            data["finality"] = json!(Finality::Final);
+            data["latestLedger"] = json!(latest);
+            self.notify(EventKind::PaymentFinalized, data);
+        }
+        Ok(finalized.len())
+    }
+
+    /// Notify included settlements once final, checking every `interval`,
+    /// until the task is dropped
+    pub async fn run_finality_watcher(&self, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = self.finalize_settlements().await {
+                tracing::error!(error = %e, "finality");
+            }
+        }
+    }
+
+    /// Credit an escrow with a plain token transfer into its
+    /// [deposit address](crate::deposit_address)
+    ///
+    /// The transfer must pay an escrow of the server in the asset of
+    /// [`Facilitator::with_pending_deposits`], muxing the escrow ID into
+    /// the server address or giving it as the transaction memo. It is
+    /// claimed on-chain with its transaction hash as proof, which the
+    /// contract credits once only.
+    ///
+    /// # Arguments
+    /// * `tx_hash` - Hex-encoded hash of the transfer transaction
+    ///
+    /// # Errors
+    /// * `InvalidPayload` - If pending deposits are not accepted, the hash
+    ///   is malformed, or the transaction deposits into several escrows
+    /// * `RecipientMismatch` - If it deposits into no escrow of the server
+    /// * `AssetMismatch` - If it deposits in another asset only
+    /// * `EscrowNotFound` / `EscrowClosed` - If the escrow cannot be credited
+    /// * `NonceUsed` - If the transfer has already been credited
+    /// * The other [`VerifyError`]s of a direct payment transaction
+    pub async fn claim_deposit(&self, tx_hash: &str) -> Result<ClaimedDeposit, VerifyError> {
+        let Some(policy) = &self.pending_deposits else {
+            return Err(VerifyError::InvalidPayload(
+                "pending deposits are not accepted".into(),
+            ));
+        };
+        let tx_hash = tx_hash.to_ascii_lowercase();
+        let memo_hash: [u8; 32] = hex::decode(&tx_hash)
+            .ok()
+            .and_then(|hash| hash.try_into().ok())
+            .ok_or_else(|| {
+                VerifyError::InvalidPayload(format!("invalid transaction hash {tx_hash}"))
+            })?;
+        let client = self.client();
+        let rpc = |e: ClientError| VerifyError::Rpc(e.to_string());
+        let tx = self
+            .metrics
+            .rpc("get_transaction", client.rpc().get_transaction(&tx_hash))
+            .await
+            .map_err(rpc)?;
+        let deposit = direct::check_deposit(&tx, &self.server, policy, &client.network_id())?;
+
+        match self
+            .metrics
+            .rpc("get_escrow", client.get_escrow(deposit.escrow_id))
+            .await
+        {
+            Ok(escrow) if escrow.server != self.server => {
+                return Err(VerifyError::RecipientMismatch)
+            }
+            Ok(escrow) if escrow.client_closed || escrow.server_closed => {
+                return Err(VerifyError::EscrowClosed)
+            }
+            Ok(_) => {}
+            Err(ClientError::Contract(ContractError::EscrowNotFound, _)) => {
+                return Err(VerifyError::EscrowNotFound)
+            }
+            Err(e) => return Err(rpc(e)),
+        }
+        if self
+            .metrics
+            .rpc("is_deposit_claimed", client.is_deposit_claimed(memo_hash))
+            .await
+            .map_err(rpc)?
+        {
+            return Err(VerifyError::NonceUsed);
+        }
+        let claim = client.claim_pending_deposit(
+            deposit.escrow_id,
+            &deposit.from,
+            deposit.amount,
+            memo_hash,
+        );
+        let submitted = match self.metrics.rpc("claim_pending_deposit", claim).await {
+            Ok(submitted) => submitted,
+            Err(ClientError::Contract(ContractError::DepositAlreadyClaimed, _)) => {
+                return Err(VerifyError::NonceUsed)
+            }
+            Err(e) => return Err(rpc(e)),
+        };
+        tracing::info!(
+            escrow_id = deposit.escrow_id,
+            amount = %deposit.amount,
+            tx_hash = %tx_hash,
+            "pending deposit claimed"
+        );
+        Ok(ClaimedDeposit {
+            escrow_id: deposit.escrow_id,
+            from: deposit.from,
+            amount: deposit.amount,
+            tx_hash,
+            claim_tx_hash: submitted.hash,
+        })
+    }
+
+    /// Claim the transfers to the server made since the last call, see
+    /// [`Facilitator::claim_deposit`]
+    ///
+    /// Transfers are found by the `transfer` events of the asset contract,
+    /// starting at the latest ledger on the first call. Transfers that are
+    /// not deposits, or already credited, are skipped.
+    ///
+    /// # Returns
+    /// * The deposits claimed
+    ///
+    /// # Errors
+    /// * If the events cannot be read, the scan resuming where it stopped
+    pub async fn claim_deposits(&self) -> Result<Vec<ClaimedDeposit>, ClientError> {
+        let Some(policy) = &self.pending_deposits else {
+            return Ok(vec![]);
+        };
+        let client = self.client();
+        let asset = match policy.asset.as_str() {
+            NATIVE_ASSET => direct::asset_contract_id(&Asset::Native, &client.network_id()),
+            asset => asset.to_string(),
+        };
+        let stored = self.deposit_cursor.lock().unwrap().clone();
+        let mut from = match stored {
+            Some(from) => from,
+            None => EventsFrom::Ledger(
+                self.metrics
+                    .rpc("get_latest_ledger", client.rpc().get_latest_ledger())
+                    .await?
+                    .sequence,
+            ),
+        };
+
+        let mut transfers = vec![];
+        loop {
+            let page = self
+                .metrics
+                .rpc(
+                    "get_events",
+                    client.rpc().get_events(&asset, &from, EVENT_PAGE_LIMIT),
+                )
+                .await?;
+            let count = page.events.len();
+            for event in &page.events {
+                if let Some(tx_hash) = self.transfer_to_server(event) {
+                    if !transfers.contains(&tx_hash) {
+                        transfers.push(tx_hash);
+                    }
+                }
+            }
+            let next = page
+                .cursor
+                .or_else(|| page.events.last().map(|event| event.id.clone()));
+            if let Some(cursor) = next {
+                from = EventsFrom::Cursor(cursor);
+            }
+            if count < EVENT_PAGE_LIMIT as usize {
+                break;
+            }
+        }
+        *self.deposit_cursor.lock().unwrap() = Some(from);
+
+        let mut claimed = vec![];
+        for tx_hash in transfers {
+            match self.claim_deposit(&tx_hash).await {
+                Ok(deposit) => claimed.push(deposit),
+                Err(VerifyError::NonceUsed) => {}
+                Err(e) => tracing::warn!(tx_hash = %tx_hash, error = %e, "transfer not claimed"),
+            }
+        }
+        Ok(claimed)
+    }
+
+    /// Claim the transfers to the server every `interval`, until the task is
+    /// dropped
+    ///
+    /// See [`Facilitator::claim_deposits`].
+    pub async fn run_deposit_watcher(&self, interval: Duration) {
+        loop {
+            if let Err(e) = self.claim_deposits().await {
+                tracing::error!(error = %e, "pending deposits");
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Transaction hash of a `transfer` event paying the server
+    fn transfer_to_server(&self, event: &EventInfo) -> Option<String> {
+        let topic = |index: usize| {
+            event
+                .topic
+                .get(index)
+                .and_then(|topic| ScVal::from_xdr_base64(topic, Limits::none()).ok())
+        };
+        match (topic(0)?, topic(2)?) {
+            (ScVal::Symbol(ScSymbol(name)), to)
+                if name.as_slice() == b"transfer"
+                    && scval::to_address(&to).ok().as_deref() == Some(self.server.as_str()) =>
+            {
+                event.tx_hash.clone()
+            }
+            _ => None,
+        }
+    }
+
+    /// Webhook data describing a settlement
+    fn settlement_data(
+        &self,
+        payment: &VerifiedPayment,
+        payment_id: Option<u64>,
+        tx_hash: Option<&str>,
+    ) -> Value {
+        json!({
+            "paymentId": payment_id,
+            "escrowId": payment.escrow_id,
+            "client": payment.client,
+            "server": self.server,
+            "amount": payment.amount.to_string(),
+            "txHash": tx_hash,
+            "network": self.network,
+        })
+    }
+
+    fn notify_failed(&self, payment: &VerifiedPayment, payment_id: Option<u64>, error: &str) {
+        self.release_credit(payment);
+        self.notify(
+            EventKind::PaymentFailed,
+            json!({
+                "paymentId": payment_id,
+                "escrowId": payment.escrow_id,
+                "client": payment.client,
+                "server": self.server,
+                "amount": payment.amount.to_string(),
+                "error": error,
+                "network": self.network,
+            }),
+        );
+    }
+
+    /// Release the credit extended to the escrow of a payment, if any
+    fn release_credit(&self, payment: &VerifiedPayment) {
+        if payment.tx_hash.is_none() && self.credit.release(payment.escrow_id, payment.nonce) {
+            self.metrics.set_credit_outstanding(self.credit.total());
+        }
+    }
+
+    fn is_nonce_used(&self, escrow_id: u64, nonce: u64) -> bool {
+        self.used_nonces
+            .lock()
+            .unwrap()
+            .contains(&(escrow_id, nonce))
+    }
+
+    fn reserve_nonce(&self, payment: &VerifiedPayment) -> bool {
+        self.used_nonces
+            .lock()
+            .unwrap()
+            .insert((payment.escrow_id, payment.nonce))
+    }
+
+    fn release_nonce(&self, payment: &VerifiedPayment) {
+        self.used_nonces
+            .lock()
+            .unwrap()
+            .remove(&(payment.escrow_id, payment.nonce));
+    }
+
+    /// Persist the transaction or nonce of a settlement taking no job as
+    /// used, when a queue is configured
+    ///
+    /// # Returns
+    /// * False if a facilitator sharing the queue already used it
+    fn persist_used(&self, payment: &VerifiedPayment) -> Result<bool, QueueError> {
+        let Some(queue) = &self.queue else {
+            return Ok(true);
+        };
+        let used = match &payment.tx_hash {
+            Some(tx_hash) => queue.use_transaction(tx_hash)?,
+            None => queue.use_nonce(payment.escrow_id, payment.nonce)?,
+        };
+        if used {
+            self.reached(Stage::Persist);
+        }
+        Ok(used)
+    }
+
+    /// Crash here if a fault is armed for `stage`, see [`crate::Faults`]
+    #[cfg_attr(not(any(test, feature = "chaos")), allow(unused_variables))]
+    fn reached(&self, stage: Stage) {
+        #[cfg(any(test, feature = "chaos"))]
+        if let Some(faults) = &self.faults {
+            faults.reached(stage);
+        }
+    }
+}
+
+/// Failure of a settlement job attempt
+#[derive(Debug, thiserror::Error)]
+enum JobError {
+    #[error(transparent)]
+    Client(#[from] ClientError),
+    #[error(transparent)]
+    Queue(#[from] QueueError),
+}
+
+impl JobError {
+    /// Contract errors are final, everything else may succeed on retry
+    fn is_permanent(&self) -> bool {
+        matches!(self, Self::Client(ClientError::Contract(..)))
+    }
+
+    /// Whether the escrow's spend limit paused its settlements, which
+    /// resume once the client acknowledges the anomaly or the window rolls
+    /// over
+    fn is_paused(&self) -> bool {
+        matches!(
+            self,
+            Self::Client(ClientError::Contract(ContractError::SettlementsPaused, _))
+        )
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Client(e) => X402Error::from(e).reason(),
+            Self::Queue(_) => "queue_error",
+        }
+    }
+}
+
+/// Claim every job, or none of them
+/// Record the payment's correlation ID on the current span
+fn record_correlation_id(payment: &VerifiedPayment) {
+    tracing::Span::current().record("correlation_id", payment.correlation_id().as_str());
+}
+
+fn claim_all<'a>(queue: &'a SettlementQueue, ids: &[i64]) -> Option<Vec<Claim<'a>>> {
+    ids.iter().map(|id| queue.claim(*id)).collect()
+}
+
+/// Parse a positive protocol amount, in stroops
+/// Part of a verified payment a settlement charges
+///
+/// # Arguments
+/// * `payment` - Verified payment, charged in full without `settle_amount`
+/// * `settle_amount` - Amount to charge instead, in stroops
+///
+/// # Errors
+/// * If the amount is invalid, exceeds the authorized amount, or is part of
+///   a direct payment, which is already made in full
+fn settled_part(
+    mut payment: VerifiedPayment,
+    settle_amount: Option<&str>,
+) -> Result<VerifiedPayment, VerifyError> {
+    let Some(settle_amount) = settle_amount else {
+        return Ok(payment);
+    };
+    let amount = match StellarAmount::from_stroops_str(settle_amount) {
+        Ok(value) if value.stroops() >= 0 => value.stroops(),
+        _ => return Err(VerifyError::InvalidAmount(settle_amount.into())),
+    };
+    if amount > payment.amount {
+        return Err(VerifyError::SettlementExceedsAuthorization);
+    }
+    if payment.tx_hash.is_some() && amount != payment.amount {
+        return Err(VerifyError::UnsupportedScheme(EXACT_SCHEME.into()));
+    }
+    payment.amount = amount;
+    Ok(payment)
+}
+
+/// Asset a verified payment is in, as its header names it among the
+/// requirements' options
+fn paid_asset(header: &str, requirements: &PaymentRequirements) -> String {
+    decode_payment_header(header)
+        .ok()
+        .and_then(|payload| payload.asset)
+        .or_else(|| requirements.asset.clone())
+        .unwrap_or_else(|| NATIVE_ASSET.into())
+}
+
+pub(crate) fn parse_amount(amount: &str) -> Result<i128, VerifyError> {
+    match StellarAmount::from_stroops_str(amount) {
+        Ok(value) if value.is_positive() => Ok(value.stroops()),
+        _ => Err(VerifyError::InvalidAmount(amount.into())),
+    }
+}
+
+/// Check the client's ed25519 signature of an escrow authorization
+///
+/// Runs on every verification, so the signature is decoded in place and
+/// the signing hash is streamed, without allocating.
+///
+/// # Arguments
+/// * `payload` - Escrow payload carrying the signature
+/// * `network` - x402 network id the authorization must be bound to
+/// * `domain` - Network and escrow contract the authorization must be bound to
+///
+/// # Errors
+/// * `ClientMismatch` - If the client is not an ed25519 account address
+/// * `InvalidSignature` - If the signature is malformed or does not verify
+pub fn verify_authorization(
+    payload: &EscrowPayload,
+    network: &str,
+    domain: SigningDomain<'_>,
+) -> Result<(), VerifyError> {
+    let key = match Strkey::from_string(&payload.client) {
+        Ok(Strkey::PublicKeyEd25519(key)) => key.0,
+        _ => return Err(VerifyError::ClientMismatch),
+    };
+    let key = VerifyingKey::from_bytes(&key).map_err(|_| VerifyError::InvalidSignature)?;
+    let mut signature = [0; 64];
+    hex::decode_to_slice(&payload.signature, &mut signature)
+        .map_err(|_| VerifyError::InvalidSignature)?;
+    key.verify_strict(
+        &payload.signing_hash(network, domain),
+        &Signature::from_bytes(&signature),
+    )
+    .map_err(|_| VerifyError::InvalidSignature)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,299 @@
+//! gRPC-Web transport of the facilitator
+//!
+//! The service in `proto/facilitator.proto` is served as gRPC-Web
+//! (`application/grpc-web+proto`), not as native gRPC (`application/grpc`).
+//! Native gRPC needs HTTP/2, its status sent in HTTP trailers, while the
+//! facilitator serves its one port with `axum::serve` over HTTP/1.1 only,
+//! axum's `http2` feature being off. gRPC-Web runs over HTTP/1.1, sending
+//! the status in a trailer frame at the end of the body, so the gRPC
+//! endpoints are plain routes of the same router, behind the same rate
+//! limit, shutdown drain, and tenant handling as the HTTP endpoints.
+//!
+//! tonic is not used for the same reason: it brings its own HTTP/2 server
+//! and prost types generated from the proto file, where the messages here
+//! are encoded from the serde types of the HTTP API by the `proto` module, so
+//! both transports decode into the types their shared handling takes.
+//!
+//! Native gRPC clients therefore need a gRPC-Web client, e.g. tonic with
+//! `tonic-web`'s client layer, or a proxy translating gRPC to gRPC-Web,
+//! e.g. Envoy's `grpc_web` filter. Serving native gRPC means enabling
+//! HTTP/2 on the listener and answering `application/grpc` with trailers.
+
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Extension},
+    http::{
+        header::{CONTENT_TYPE, RETRY_AFTER},
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use serde::de::DeserializeOwned;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    proto::{self, Message, PROTO_PACKAGE, PROTO_SERVICE},
+    routes::{retry_after_secs, Peer},
+    service, EventKind, Facilitator, Rejection, Reply, RATE_LIMITED, SHUTTING_DOWN,
+};
+
+/// Content type of gRPC-Web requests and responses
+pub const GRPC_WEB_CONTENT_TYPE: &str = "application/grpc-web+proto";
+
+/// Header, or trailer, carrying the gRPC status code
+pub const GRPC_STATUS: &str = "grpc-status";
+
+/// Header, or trailer, carrying the gRPC status message
+pub const GRPC_MESSAGE: &str = "grpc-message";
+
+/// Flag of gRPC-Web frames carrying trailers rather than a message
+pub const TRAILER_FLAG: u8 = 0x80;
+
+/// Events streamed by `WatchSettlements` when the request names none
+pub const SETTLEMENT_EVENTS: [EventKind; 3] = [
+    EventKind::PaymentSettled,
+    EventKind::PaymentFailed,
+    EventKind::PaymentFinalized,
+];
+
+/// gRPC status codes answered by the facilitator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Code {
+    Ok = 0,
+    InvalidArgument = 3,
+    AlreadyExists = 6,
+    ResourceExhausted = 8,
+    Aborted = 10,
+    Unavailable = 14,
+}
+
+/// gRPC-Web endpoints, one per method of the service in [`proto_file`](crate::proto_file)
+///
+/// Each takes one framed message, from the facilitator in the request
+/// extensions as the HTTP endpoints do.
+pub(crate) fn endpoints() -> Router {
+    Router::new()
+        .route(&path("Verify"), post(verify))
+        .route(&path("Settle"), post(settle))
+        .route(&path("GetSupported"), post(supported))
+        .route(&path("WatchSettlements"), post(watch_settlements))
+}
+
+/// Path of the gRPC method `name`
+pub fn path(name: &str) -> String {
+    format!("/{PROTO_PACKAGE}.{PROTO_SERVICE}/{name}")
+}
+
+async fn verify(
+    Extension(facilitator): Extension<Arc<Facilitator>>,
+    peer: Peer,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let request = match message(&headers, &body, &proto::VERIFY_REQUEST) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    let source = peer.map(|Extension(ConnectInfo(addr))| addr.ip());
+    let reply = service::verify(&facilitator, &request, source).await;
+    unary(reply, &proto::VERIFY_RESPONSE, |r| r.invalid_reason.clone())
+}
+
+async fn settle(
+    Extension(facilitator): Extension<Arc<Facilitator>>,
+    peer: Peer,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let request = match message(&headers, &body, &proto::SETTLE_REQUEST) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    let source = peer.map(|Extension(ConnectInfo(addr))| addr.ip());
+    let reply = service::settle(&facilitator, &request, source).await;
+    unary(reply, &proto::SETTLE_RESPONSE, |r| r.error.clone())
+}
+
+async fn supported(
+    Extension(facilitator): Extension<Arc<Facilitator>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(e) = message::<serde_json::Value>(&headers, &body, &proto::GET_SUPPORTED_REQUEST) {
+        return e.into_response();
+    }
+    match facilitator.supported().await {
+        Ok(supported) => respond(&proto::encode(&proto::SUPPORTED_RESPONSE, &supported)),
+        Err(e) => status(Code::Unavailable, &e.to_string()),
+    }
+}
+
+/// Stream the facilitator's settlement events from now on
+///
+/// Streams falling more than [`crate::EVENT_BUFFER`] events behind end with
+/// `ABORTED`, so a client resubscribing knows it missed events.
+async fn watch_settlements(
+    Extension(facilitator): Extension<Arc<Facilitator>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    #[derive(serde::Deserialize)]
+    struct WatchRequest {
+        types: Vec<String>,
+    }
+    let request: WatchRequest = match message(&headers, &body, &proto::WATCH_SETTLEMENTS_REQUEST) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    let kinds: Result<Vec<EventKind>, _> = request
+        .types
+        .iter()
+        .map(|kind| serde_json::from_value(kind.as_str().into()))
+        .collect();
+    let kinds = match kinds {
+        Ok(kinds) if kinds.is_empty() => SETTLEMENT_EVENTS.to_vec(),
+        Ok(kinds) => kinds,
+        Err(_) => return status(Code::InvalidArgument, "unknown event type"),
+    };
+
+    let events = facilitator.subscribe();
+    let frames = futures_util::stream::unfold(Some(events), move |events| {
+        let kinds = kinds.clone();
+        async move {
+            let mut events = events?;
+            loop {
+                let frame = match events.recv().await {
+                    Ok(event) if kinds.contains(&event.kind) => {
+                        frame(0, &proto::encode(&proto::SETTLEMENT_EVENT, &event))
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        let message = format!("missed {missed} events");
+                        return Some((Ok(trailers(Code::Aborted, &message)), None));
+                    }
+                    Err(RecvError::Closed) => {
+                        return Some((Ok(trailers(Code::Ok, "")), None));
+                    }
+                };
+                return Some((Ok::<_, Infallible>(frame), Some(events)));
+            }
+        }
+    });
+    (
+        [(CONTENT_TYPE, GRPC_WEB_CONTENT_TYPE)],
+        Body::from_stream(frames),
+    )
+        .into_response()
+}
+
+/// Why the request message of a call could not be read
+enum BadRequest {
+    ContentType,
+    Message(String),
+}
+
+impl IntoResponse for BadRequest {
+    fn into_response(self) -> Response {
+        match self {
+            Self::ContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response(),
+            Self::Message(message) => status(Code::InvalidArgument, &message),
+        }
+    }
+}
+
+/// Request message of a gRPC-Web call
+fn message<T: DeserializeOwned>(
+    headers: &HeaderMap,
+    body: &[u8],
+    message: &Message,
+) -> Result<T, BadRequest> {
+    let content_type = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    if !content_type.is_some_and(|v| v == "application/grpc-web" || v == GRPC_WEB_CONTENT_TYPE) {
+        return Err(BadRequest::ContentType);
+    }
+    let payload = match body.split_first() {
+        Some((0, rest)) => rest
+            .split_first_chunk()
+            .and_then(|(len, rest)| rest.get(..u32::from_be_bytes(*len) as usize)),
+        _ => None,
+    };
+    let Some(payload) = payload else {
+        return Err(BadRequest::Message(
+            "expected one uncompressed message".into(),
+        ));
+    };
+    proto::decode(message, payload).map_err(|e| BadRequest::Message(e.to_string()))
+}
+
+/// Answer a unary call, rejections as errors with the response's reason
+fn unary<T: serde::Serialize>(
+    reply: Reply<T>,
+    message: &Message,
+    reason: impl Fn(&T) -> Option<String>,
+) -> Response {
+    match reply.rejection {
+        None => respond(&proto::encode(message, &reply.response)),
+        Some(Rejection::Replayed) => {
+            let reason = reason(&reply.response).unwrap_or_default();
+            status(Code::AlreadyExists, &reason)
+        }
+        Some(Rejection::RateLimited(retry_after)) => {
+            let mut response = status(Code::ResourceExhausted, RATE_LIMITED);
+            let seconds = HeaderValue::from(retry_after_secs(retry_after));
+            response.headers_mut().insert(RETRY_AFTER, seconds);
+            response
+        }
+        Some(Rejection::ShuttingDown) => status(Code::Unavailable, SHUTTING_DOWN),
+    }
+}
+
+/// Response of a successful unary call carrying `message`
+fn respond(message: &[u8]) -> Response {
+    let mut body = frame(0, message);
+    body.extend(trailers(Code::Ok, ""));
+    ([(CONTENT_TYPE, GRPC_WEB_CONTENT_TYPE)], body).into_response()
+}
+
+/// Trailers-only response of a failed call
+fn status(code: Code, message: &str) -> Response {
+    let mut response = [(CONTENT_TYPE, GRPC_WEB_CONTENT_TYPE)].into_response();
+    let headers = response.headers_mut();
+    headers.insert(GRPC_STATUS, HeaderValue::from(code as u32));
+    if let Ok(message) = HeaderValue::from_str(&percent_encode(message)) {
+        headers.insert(GRPC_MESSAGE, message);
+    }
+    response
+}
+
+/// gRPC-Web frame of `payload`, uncompressed
+fn frame(flag: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(5 + payload.len());
+    frame.push(flag);
+    frame.extend((payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Trailer frame ending a call with `code`
+fn trailers(code: Code, message: &str) -> Vec<u8> {
+    let mut trailers = format!("{GRPC_STATUS}:{}\r\n", code as u32);
+    if !message.is_empty() {
+        trailers += &format!("{GRPC_MESSAGE}:{}\r\n", percent_encode(message));
+    }
+    frame(TRAILER_FLAG, trailers.as_bytes())
+}
+
+/// Status message percent-encoded as gRPC requires
+fn percent_encode(message: &str) -> String {
+    message
+        .bytes()
+        .map(|b| match b {
+            b' '..=b'~' if b != b'%' => char::from(b).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
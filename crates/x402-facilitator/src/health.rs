@@ -0,0 +1,87 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// Default largest settlement queue backlog of a ready facilitator
+pub const DEFAULT_READY_MAX_QUEUE_DEPTH: usize = 1_000;
+
+/// Default longest a readiness check may take
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Thresholds of the readiness checks served on /readyz
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReadinessPolicy {
+    /// Most unfinished jobs the settlement queue may hold while ready
+    pub max_queue_depth: usize,
+    /// Longest each check may take before it fails
+    pub timeout: Duration,
+}
+
+impl Default for ReadinessPolicy {
+    fn default() -> Self {
+        Self {
+            max_queue_depth: DEFAULT_READY_MAX_QUEUE_DEPTH,
+            timeout: DEFAULT_READY_TIMEOUT,
+        }
+    }
+}
+
+/// Outcome of one dependency check
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessCheck {
+    /// Dependency checked: `rpc`, `contract`, `queue`, `signer`, or
+    /// `shutdown`
+    pub name: String,
+    pub ok: bool,
+    /// What was found, or why the check failed
+    pub detail: String,
+    /// Time the check took, in milliseconds
+    pub elapsed_ms: u64,
+}
+
+/// Readiness of a facilitator to serve payments, answered by /readyz
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Readiness {
+    /// Whether every check passed
+    pub ready: bool,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+impl Readiness {
+    pub(crate) fn new(checks: Vec<ReadinessCheck>) -> Self {
+        Self {
+            ready: checks.iter().all(|check| check.ok),
+            checks,
+        }
+    }
+
+    /// Check named `name`, if it ran
+    pub fn check(&self, name: &str) -> Option<&ReadinessCheck> {
+        self.checks.iter().find(|check| check.name == name)
+    }
+}
+
+/// Run one check, failing it once `timeout` passes
+pub(crate) async fn check<E: Display>(
+    name: &str,
+    timeout: Duration,
+    check: impl Future<Output = Result<String, E>>,
+) -> ReadinessCheck {
+    let start = Instant::now();
+    let (ok, detail) = match tokio::time::timeout(timeout, check).await {
+        Ok(Ok(detail)) => (true, detail),
+        Ok(Err(e)) => (false, e.to_string()),
+        Err(_) => (false, format!("no answer within {timeout:?}")),
+    };
+    ReadinessCheck {
+        name: name.into(),
+        ok,
+        detail,
+        elapsed_ms: start.elapsed().as_millis() as u64,
+    }
+}
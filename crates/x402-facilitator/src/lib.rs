@@ -0,0 +1,223 @@
+//! # x402 Facilitator
+//!
+//! HTTP service that verifies and settles x402 escrow payments on behalf of
+//! a resource server.
+//!
+//! ## Endpoints
+//! - `POST /verify` - Check an X-PAYMENT payload against payment requirements
+//! - `POST /settle` - Charge the escrow on-chain and return the transaction hash
+//! - `POST /preflight` - Forecast whether settling a payment would succeed,
+//!   without settling it
+//! - `GET /supported` - Schemes, networks, assets, and fees the facilitator accepts
+//! - `GET /metrics` - Prometheus metrics
+//! - `GET /healthz` - Liveness, answering as long as the process serves
+//! - `GET /readyz` - Readiness, checking RPC, the contract, the settlement
+//!   queue backlog, and the signer, with the outcome of each check
+//! - `GET /openapi.json` - OpenAPI description of every endpoint, rendered
+//!   by Swagger UI at `GET /docs` with the `swagger-ui` feature
+//! - `GET /admin/webhooks/failed` - Dead-lettered webhook deliveries, when an
+//!   admin token is configured
+//! - `PUT /admin/tenants/{id}` - Add or change a tenant of a multi-tenant
+//!   facilitator, when an admin token is configured
+//! - `GET /admin/jobs`, `GET /admin/payments`, `POST /admin/reconcile` -
+//!   Inspect and repair settlements, when an admin token is configured
+//!
+//! ## gRPC
+//! With the `grpc` feature, the facilitator also serves `Verify`, `Settle`,
+//! `GetSupported`, and a `WatchSettlements` stream of settlement events as
+//! gRPC-Web on the same port, described by `proto/facilitator.proto`. Calls
+//! go through the same handling as their HTTP endpoints. Native gRPC, over
+//! HTTP/2, is not served: the port only speaks HTTP/1.1, so native clients
+//! use a gRPC-Web client or connect through a gRPC-Web proxy, see the
+//! `grpc` module.
+//!
+//! ## Settlement queue
+//! With a [`SettlementQueue`], settlements are persisted in SQLite before
+//! /settle answers and every signed transaction is saved before submission,
+//! so a restarted facilitator finishes them without charging twice. With a
+//! [`BatchPolicy`], created payments are settled together by the contract's
+//! `settle_payments`, once enough are waiting or the oldest waited too long.
+//! The queue is periodically reconciled with the payments of the server's
+//! escrows: jobs settled on-chain are marked settled, other discrepancies
+//! are reported in the metrics and as `reconciliation.discrepancy` webhook
+//! events.
+//!
+//! ## Delivery guarantees
+//! With a settlement queue, each accepted payment is charged exactly once,
+//! wherever the facilitator fails. A settlement failing before its job is
+//! persisted charged nothing, and its payload may be settled again. Once
+//! persisted, it is finished by the next facilitator on the queue, and the
+//! payload is `nonce_used`. Direct payments and settlements of zero take
+//! no job, their transaction or nonce being persisted as used instead.
+//!
+//! Without a queue, used nonces live in memory, so a payload whose
+//! settlement applied before the facilitator restarted may be charged
+//! again. With the `chaos` feature, `Faults` crash settlements after
+//! any [`Stage`] of the pipeline, to check recovery.
+//!
+//! ## Shutdown
+//! On SIGTERM or Ctrl-C the facilitator stops admitting settlements, /settle
+//! answering `503 Service Unavailable` with `shutting_down`, and waits up to
+//! `X402_SHUTDOWN_GRACE_SECS` for those in flight. No new batch or queued
+//! job is started meanwhile. Jobs left unfinished are logged and resumed by
+//! the next facilitator on the queue, their transactions having been saved
+//! before submission.
+//!
+//! ## Finality
+//! With a [`FinalityPolicy`](x402_client::FinalityPolicy), settlements report
+//! the ledger they were included in and are `included` until enough ledgers
+//! closed after it, then `final`. Small payments may be final on inclusion.
+//! Included settlements are watched, and announced as `payment.finalized`
+//! webhook events once final.
+//!
+//! ## Dry runs
+//! A /settle request with `dryRun`, or every request when
+//! [`Settings::dry_run`] is set, verifies the payment and simulates its
+//! `create_payment`, answering the payment ID the contract would assign and
+//! the estimated fee as `simulation`. Nothing is submitted or queued and the
+//! nonce stays unused.
+//!
+//! ## Preflight
+//! /preflight answers whether a payment's settlement would succeed against
+//! current chain state, for resource servers about to do expensive work.
+//! The payment is checked as by /verify, without reserving its nonce, and
+//! its settlement simulated. The verdict carries the code of the first
+//! failing check, the estimated fee, and the escrow's available balance. It
+//! is reused for a few seconds, see [`Facilitator::with_preflight_ttl`],
+//! and never binding.
+//!
+//! ## Assets
+//! Requirements may offer `alternatives`, other assets accepted at their
+//! own amounts. A payload pays in the asset it names, the requirements'
+//! `asset` if it names none, and is checked against the amount of that
+//! asset. Payloads naming an asset not offered are `invalid_asset`.
+//!
+//! ## Degradation
+//! When Soroban RPC is unreachable, escrow payments are rejected unless
+//! [`Settings::degradation`] is [`Degradation::ServeAndQueue`]. Payloads
+//! passing the checks that need no RPC are then accepted on credit, up to
+//! the escrow's last known balance and the configured cap, and their
+//! settlement is queued until RPC is back. Outstanding credit is reported
+//! by the `credit_outstanding` gauge.
+//!
+//! ## Replay protection
+//! Nonces passing /verify are reserved until the payload expires, in memory
+//! or in Redis when replicas share a [`RedisReplayCache`]. A payload verified
+//! a second time, e.g. submitted to several resource servers at once, is
+//! answered `409 Conflict` with `nonce_replayed`.
+//!
+//! ## Expiry
+//! Authorizations are checked against the close time of the latest ledger,
+//! read from RPC, as the contract checks them, rather than the local clock.
+//! [`Settings::clock_skew`] accepts them for a few seconds more, like the
+//! contract's `set_clock_skew`.
+//!
+//! ## Rate limiting
+//! With a [`RateLimit`], /verify, /settle, and /preflight take a token from
+//! the bucket of the paying client, or of the source IP when the payload
+//! cannot be read. Clients out of tokens are answered `429 Too Many
+//! Requests` with a `Retry-After` header. Buckets live in memory, or in Redis with a
+//! [`RedisRateLimiter`].
+//!
+//! ## Errors
+//! Rejections carry the stable code of their
+//! [`X402Error`](x402_errors::X402Error) as `errorCode`, next to the
+//! `invalidReason` or `error` they always had, whether the contract trapped,
+//! RPC failed, the payment was invalid, or a policy turned it down. Clients
+//! map it back with `X402Error::from_code`.
+//!
+//! ## Direct payments
+//! With [`DirectPayments`], clients may also pay with a classic Stellar
+//! payment and send its transaction hash ("exact" scheme). The transaction
+//! must carry the requirements' payment memo, pay `payTo` enough of the
+//! asset, and be confirmed deep enough. Its hash is reserved like a nonce,
+//! so one transaction pays for one request.
+//!
+//! ## Pending deposits
+//! With [`PendingDeposits`], clients whose custodian can only send payments
+//! fund their escrow with a plain transfer to its [`deposit_address`], the
+//! server account muxed with the escrow ID, or to the server with the
+//! escrow ID as memo. The facilitator finds these transfers and claims
+//! them on-chain with their transaction hash, which the contract credits
+//! once only.
+//!
+//! ## Tenants
+//! One facilitator may serve many resource servers with [`Tenants`], each
+//! with its own settlement key, payout address, assets, fee share, metrics,
+//! and webhooks. Requests are routed by the tenant's API key, and tenants are
+//! managed at runtime through `/admin/tenants`.
+//!
+//! A tenant's settlement key rotates without downtime: stage the next key,
+//! add it as a signer of the server account, then promote it. Queued
+//! settlements signed by the old key are signed anew when resubmitted.
+//!
+//! ## Tracing
+//! Verification and settlement run in `facilitator.verify` and
+//! `facilitator.settle` spans recording the payment's `correlation_id`,
+//! which the client, the middleware, and the SDK derive from the payload
+//! too. Settlement transactions of a single payment carry it as their text
+//! memo, linking on-chain records back to the traces. The binary logs them
+//! as JSON lines, see [`init_tracing`].
+//!
+//! ## Webhooks
+//! Settlement outcomes are POSTed as HMAC-signed canonical JSON to the
+//! configured endpoints, retried with exponential backoff, and dead-lettered
+//! for replay once every attempt failed.
+
+mod admin;
+mod chaos;
+mod config;
+mod degradation;
+mod direct;
+mod facilitator;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod health;
+mod metrics;
+mod openapi;
+#[cfg(feature = "grpc")]
+mod proto;
+mod queue;
+mod rate_limit;
+mod redis;
+mod replay;
+mod routes;
+mod service;
+mod shutdown;
+mod telemetry;
+mod tenant;
+mod webhook;
+
+pub use admin::*;
+#[cfg(any(test, feature = "chaos"))]
+pub use chaos::Faults;
+pub use chaos::Stage;
+pub use config::*;
+pub use degradation::Degradation;
+pub use direct::{
+    deposit_address, DirectPayments, PendingDeposits, DEFAULT_CONFIRMATIONS,
+    DEFAULT_DIRECT_MAX_AGE_SECS,
+};
+pub use facilitator::*;
+#[cfg(feature = "grpc")]
+pub use grpc::{path as grpc_path, Code, GRPC_MESSAGE, GRPC_STATUS, GRPC_WEB_CONTENT_TYPE};
+pub use health::{
+    Readiness, ReadinessCheck, ReadinessPolicy, DEFAULT_READY_MAX_QUEUE_DEPTH,
+    DEFAULT_READY_TIMEOUT,
+};
+pub use metrics::*;
+pub use openapi::*;
+#[cfg(feature = "grpc")]
+pub use proto::{proto_file, DecodeError, PROTO_PACKAGE, PROTO_SERVICE};
+pub use queue::*;
+pub use rate_limit::*;
+pub use redis::{StoreError, REDIS_TIMEOUT};
+pub use replay::*;
+pub use routes::*;
+pub use service::{Rejection, Reply, RATE_LIMITED};
+pub use shutdown::{ShutdownReport, SHUTTING_DOWN};
+pub use telemetry::*;
+pub use tenant::*;
+pub use webhook::*;
+
+mod test;
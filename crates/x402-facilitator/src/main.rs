@@ -0,0 +1,224 @@
+use std::{
+    future::{Future, IntoFuture},
+    net::SocketAddr,
+    process::ExitCode,
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+use x402_facilitator::{
+    admin_router, init_tracing, operations_router, router, tenant_admin_router, tenant_router,
+    Config, Facilitator, SettlementQueue, Tenants,
+};
+
+/// Delay between runs of the settlement queue
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delay between checks of included settlements for finality, about a ledger
+const FINALITY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Delay between scans for transfers into deposit addresses, about a ledger
+const DEPOSIT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest wait for connections to close once settlements drained
+const CONNECTION_CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    if let Err(e) = init_tracing() {
+        eprintln!("x402-facilitator: tracing: {e}");
+        return ExitCode::FAILURE;
+    }
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("x402-facilitator: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match config.tenants() {
+        Ok(Some(tenants)) => return serve_tenants(&config, tenants).await,
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("x402-facilitator: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    let client = match config.escrow_client() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("x402-facilitator: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut facilitator =
+        Facilitator::new(client, &config.network).with_settings(config.settings.clone());
+    if let Some(webhooks) = config.webhooks() {
+        facilitator = facilitator.with_webhooks(Arc::new(webhooks));
+    }
+    if let Some(policy) = &config.finality {
+        facilitator = facilitator.with_finality(policy.clone());
+    }
+    if let Some(policy) = &config.pending_deposits {
+        facilitator = facilitator.with_pending_deposits(policy.clone());
+    }
+    match config.replay_cache() {
+        Ok(Some(cache)) => facilitator = facilitator.with_replay_cache(Arc::new(cache)),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("x402-facilitator: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    match config.rate_limiter() {
+        Ok(Some(limiter)) => facilitator = facilitator.with_rate_limiter(Arc::new(limiter)),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("x402-facilitator: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(path) = &config.settlement_queue {
+        let queue = SettlementQueue::open(path).map(|queue| match &config.batching {
+            Some(policy) => queue.with_batching(policy.clone()),
+            None => queue,
+        });
+        let queue = queue.and_then(|queue| facilitator.with_queue(queue));
+        facilitator = match queue {
+            Ok(facilitator) => facilitator,
+            Err(e) => {
+                eprintln!("x402-facilitator: settlement queue {}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+    println!(
+        "x402-facilitator: server {} on {}, listening on {}",
+        facilitator.server(),
+        config.network,
+        config.bind
+    );
+
+    let facilitator = Arc::new(facilitator);
+    if facilitator.queue().is_some() {
+        let worker = facilitator.clone();
+        tokio::spawn(async move { worker.run_settlement_worker(QUEUE_POLL_INTERVAL).await });
+        if let Some(interval) = config.reconcile_interval {
+            let reconciler = facilitator.clone();
+            tokio::spawn(async move { reconciler.run_reconciler(interval).await });
+        }
+    }
+    if config.finality.is_some() {
+        let watcher = facilitator.clone();
+        tokio::spawn(async move { watcher.run_finality_watcher(FINALITY_POLL_INTERVAL).await });
+    }
+    if config.pending_deposits.is_some() {
+        let watcher = facilitator.clone();
+        tokio::spawn(async move { watcher.run_deposit_watcher(DEPOSIT_POLL_INTERVAL).await });
+    }
+    let mut app = router(facilitator.clone());
+    if let Some(token) = &config.admin_token {
+        app = app.merge(operations_router(facilitator.clone(), token));
+        if let Some(webhooks) = facilitator.webhooks() {
+            app = app.merge(admin_router(webhooks.clone(), token));
+        }
+    }
+    let grace = config.shutdown_grace;
+    let drain = async move {
+        shutdown_signal().await;
+        let report = facilitator.shutdown(grace).await;
+        println!(
+            "x402-facilitator: shut down, {} settlements interrupted, {} jobs left queued",
+            report.interrupted,
+            report.unfinished.len()
+        );
+    };
+    serve(&config, app, drain).await
+}
+
+/// Serve every tenant of a multi-tenant facilitator
+async fn serve_tenants(config: &Config, tenants: Tenants) -> ExitCode {
+    println!(
+        "x402-facilitator: {} tenants on {}, listening on {}",
+        tenants.configs().len(),
+        config.network,
+        config.bind
+    );
+    let tenants = Arc::new(tenants);
+    let mut app = tenant_router(tenants.clone());
+    if let Some(token) = &config.admin_token {
+        app = app.merge(tenant_admin_router(tenants.clone(), token));
+    }
+    let grace = config.shutdown_grace;
+    let drain = async move {
+        shutdown_signal().await;
+        let reports = tenants.shutdown(grace).await;
+        let interrupted: usize = reports.iter().map(|(_, report)| report.interrupted).sum();
+        println!("x402-facilitator: shut down, {interrupted} settlements interrupted");
+    };
+    serve(config, app, drain).await
+}
+
+/// Resolve on SIGTERM or Ctrl-C
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        () = terminate => {}
+    }
+}
+
+/// Serve `app` until `drain` completes, then let open connections close
+///
+/// Requests still open past [`CONNECTION_CLOSE_TIMEOUT`] were interrupted by
+/// the drain's deadline, and are dropped.
+async fn serve(
+    config: &Config,
+    app: axum::Router,
+    drain: impl Future<Output = ()> + Send + 'static,
+) -> ExitCode {
+    let listener = match tokio::net::TcpListener::bind(config.bind).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("x402-facilitator: cannot bind {}: {e}", config.bind);
+            return ExitCode::FAILURE;
+        }
+    };
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let drained = Arc::new(Notify::new());
+    let notify = drained.clone();
+    let server = axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            drain.await;
+            notify.notify_one();
+        })
+        .into_future();
+    let closing = async {
+        drained.notified().await;
+        tokio::time::sleep(CONNECTION_CLOSE_TIMEOUT).await;
+    };
+    let result = tokio::select! {
+        result = server => result,
+        () = closing => Ok(()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("x402-facilitator: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    time::Instant,
+};
+
+use prometheus::{
+    core::Collector, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+
+use crate::Reconciliation;
+
+/// Content type of the text exposition format
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Prometheus metrics of a facilitator
+///
+/// Every metric is prefixed with `x402_facilitator_` and labelled with the
+/// escrow contract ID and x402 network, plus the tenant of multi-tenant
+/// facilitators.
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    settlements: IntCounterVec,
+    pending_settlements: IntGauge,
+    rpc_duration: HistogramVec,
+    settled_volume: IntCounterVec,
+    rate_limited: IntCounterVec,
+    reconciliation_repaired: IntCounter,
+    reconciliation_discrepancies: IntGaugeVec,
+    credit_outstanding: IntGauge,
+}
+
+impl Metrics {
+    /// Create the metrics of the facilitator for `contract_id` on `network`
+    pub fn new(contract_id: &str, network: &str) -> Self {
+        Self::with_labels(HashMap::from([
+            ("contract_id".to_string(), contract_id.to_string()),
+            ("network".to_string(), network.to_string()),
+        ]))
+    }
+
+    /// Create the metrics of the facilitator of tenant `tenant`
+    pub fn for_tenant(contract_id: &str, network: &str, tenant: &str) -> Self {
+        Self::with_labels(HashMap::from([
+            ("contract_id".to_string(), contract_id.to_string()),
+            ("network".to_string(), network.to_string()),
+            ("tenant".to_string(), tenant.to_string()),
+        ]))
+    }
+
+    fn with_labels(labels: HashMap<String, String>) -> Self {
+        let registry = Registry::new_custom(Some("x402_facilitator".into()), Some(labels))
+            .expect("valid registry labels");
+
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Verify and settle requests handled"),
+            &["endpoint"],
+        )
+        .expect("valid metric");
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "request_duration_seconds",
+                "Time to answer verify and settle requests",
+            ),
+            &["endpoint"],
+        )
+        .expect("valid metric");
+        let settlements = IntCounterVec::new(
+            Opts::new("settlements_total", "Settlement outcomes by error code"),
+            &["result", "error"],
+        )
+        .expect("valid metric");
+        let pending_settlements = IntGauge::new(
+            "pending_settlements",
+            "Settlements accepted but not yet confirmed on-chain",
+        )
+        .expect("valid metric");
+        let rpc_duration = HistogramVec::new(
+            HistogramOpts::new("rpc_duration_seconds", "Latency of escrow contract calls"),
+            &["call"],
+        )
+        .expect("valid metric");
+        let settled_volume = IntCounterVec::new(
+            Opts::new("settled_volume_total", "Amount settled, in stroops"),
+            &["asset"],
+        )
+        .expect("valid metric");
+
+        let rate_limited = IntCounterVec::new(
+            Opts::new(
+                "rate_limited_total",
+                "Requests rejected by the rate limiter",
+            ),
+            &["endpoint"],
+        )
+        .expect("valid metric");
+        let reconciliation_repaired = IntCounter::new(
+            "reconciliation_repaired_total",
+            "Jobs marked settled by reconciliation, their payment being settled on-chain",
+        )
+        .expect("valid metric");
+        let reconciliation_discrepancies = IntGaugeVec::new(
+            Opts::new(
+                "reconciliation_discrepancies",
+                "Discrepancies found by the last reconciliation, by reason",
+            ),
+            &["reason"],
+        )
+        .expect("valid metric");
+        let credit_outstanding = IntGauge::new(
+            "credit_outstanding",
+            "Credit extended while RPC was unreachable and not yet settled, in stroops",
+        )
+        .expect("valid metric");
+
+        for collector in [
+            Box::new(requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(request_duration.clone()),
+            Box::new(settlements.clone()),
+            Box::new(pending_settlements.clone()),
+            Box::new(rpc_duration.clone()),
+            Box::new(settled_volume.clone()),
+            Box::new(rate_limited.clone()),
+            Box::new(reconciliation_repaired.clone()),
+            Box::new(reconciliation_discrepancies.clone()),
+            Box::new(credit_outstanding.clone()),
+        ] {
+            registry.register(collector).expect("unique metric names");
+        }
+
+        Self {
+            registry,
+            requests,
+            request_duration,
+            settlements,
+            pending_settlements,
+            rpc_duration,
+            settled_volume,
+            rate_limited,
+            reconciliation_repaired,
+            reconciliation_discrepancies,
+            credit_outstanding,
+        }
+    }
+
+    /// Registry holding the metrics, to register more collectors
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Render the metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+
+    /// Amount settled since the facilitator started, in stroops, by asset
+    pub fn settled_volume(&self) -> BTreeMap<String, u64> {
+        let mut volume = BTreeMap::new();
+        for family in self.settled_volume.collect() {
+            for metric in family.get_metric() {
+                let asset = metric
+                    .get_label()
+                    .iter()
+                    .find(|label| label.get_name() == "asset")
+                    .map(|label| label.get_value().to_string())
+                    .unwrap_or_default();
+                volume.insert(asset, metric.get_counter().get_value() as u64);
+            }
+        }
+        volume
+    }
+
+    /// Count a request to `endpoint` started at `start`
+    pub(crate) fn observe_request(&self, endpoint: &str, start: Instant) {
+        self.requests.with_label_values(&[endpoint]).inc();
+        self.request_duration
+            .with_label_values(&[endpoint])
+            .observe(start.elapsed().as_secs_f64());
+    }
+
+    /// Run a contract call, recording its latency
+    pub(crate) async fn rpc<T>(&self, call: &str, future: impl Future<Output = T>) -> T {
+        let start = Instant::now();
+        let output = future.await;
+        self.rpc_duration
+            .with_label_values(&[call])
+            .observe(start.elapsed().as_secs_f64());
+        output
+    }
+
+    /// Track a settlement until the guard is dropped
+    pub(crate) fn pending(&self) -> PendingSettlement<'_> {
+        self.pending_settlements.inc();
+        PendingSettlement(&self.pending_settlements)
+    }
+
+    /// Report the number of unfinished queued settlements
+    pub(crate) fn set_queue_depth(&self, depth: usize) {
+        self.pending_settlements.set(depth as i64);
+    }
+
+    pub(crate) fn settled(&self, asset: &str, amount: i128) {
+        self.settlements.with_label_values(&["success", ""]).inc();
+        self.settled_volume
+            .with_label_values(&[asset])
+            .inc_by(u64::try_from(amount).unwrap_or(u64::MAX));
+    }
+
+    /// Report the credit outstanding on every escrow
+    pub(crate) fn set_credit_outstanding(&self, credit: i128) {
+        self.credit_outstanding
+            .set(i64::try_from(credit).unwrap_or(i64::MAX));
+    }
+
+    pub(crate) fn rate_limited(&self, endpoint: &str) {
+        self.rate_limited.with_label_values(&[endpoint]).inc();
+    }
+
+    /// Report the outcome of a reconciliation
+    pub(crate) fn reconciled(&self, report: &Reconciliation) {
+        self.reconciliation_repaired
+            .inc_by(report.repaired.len() as u64);
+        let count = |reason: &str| {
+            report
+                .discrepancies
+                .iter()
+                .filter(|d| d.reason == reason)
+                .count() as i64
+        };
+        for (reason, found) in [
+            ("missing_on_chain", count("missing_on_chain")),
+            ("unsettled_on_chain", count("unsettled_on_chain")),
+            ("orphaned", report.orphaned.len() as i64),
+        ] {
+            self.reconciliation_discrepancies
+                .with_label_values(&[reason])
+                .set(found);
+        }
+    }
+
+    pub(crate) fn settlement_failed(&self, error: &str) {
+        self.settlements
+            .with_label_values(&["failure", error])
+            .inc();
+    }
+}
+
+/// Settlement counted in the pending gauge
+pub(crate) struct PendingSettlement<'a>(&'a IntGauge);
+
+impl Drop for PendingSettlement<'_> {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
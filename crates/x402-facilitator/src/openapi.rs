@@ -0,0 +1,863 @@
+use serde_json::{json, Map, Value};
+use x402_types::{
+    array_schema, boolean_schema, integer_schema, nullable_schema, object_schema, schema_ref,
+    string_schema, stroops_schema, AssetAmount, EscrowPayload, FeePolicy, JsonSchema,
+    PaymentErrorBody, PaymentPayload, PaymentRequiredResponse, PaymentRequirements,
+    PaymentResponseHeader, PreflightResponse, SchemePayload, SettleRequest, SettleResponse,
+    SettlementSimulation, SupportedKind, SupportedResponse, TransactionHashPayload,
+    TransactionPayload, VerifyRequest, VerifyResponse,
+};
+
+use crate::{
+    DirectPayments, Discrepancy, Endpoint, EventKind, FailedDelivery, JobSummary, PaymentRecord,
+    RateLimit, Readiness, ReadinessCheck, Reconciliation, TenantConfig, TenantSummary, VolumeEntry,
+    WebhookEvent,
+};
+
+/// Path the OpenAPI description is served at
+pub const OPENAPI_PATH: &str = "/openapi.json";
+
+/// Page rendering the OpenAPI description with Swagger UI, loaded from a CDN
+#[cfg(feature = "swagger-ui")]
+pub const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>x402 Facilitator</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// OpenAPI 3.1 description of the facilitator's endpoints
+///
+/// Covers the protocol endpoints and every admin endpoint, those of
+/// single-tenant and multi-tenant facilitators alike.
+pub fn openapi_spec() -> Value {
+    let mut schemas = Map::new();
+    let mut add = |name: &str, schema: Value| {
+        schemas.insert(name.into(), schema);
+    };
+    add(
+        PaymentRequiredResponse::NAME,
+        PaymentRequiredResponse::schema(),
+    );
+    add(PaymentErrorBody::NAME, PaymentErrorBody::schema());
+    add(PaymentRequirements::NAME, PaymentRequirements::schema());
+    add(AssetAmount::NAME, AssetAmount::schema());
+    add(PaymentPayload::NAME, PaymentPayload::schema());
+    add(SchemePayload::NAME, SchemePayload::schema());
+    add(EscrowPayload::NAME, EscrowPayload::schema());
+    add(TransactionPayload::NAME, TransactionPayload::schema());
+    add(
+        TransactionHashPayload::NAME,
+        TransactionHashPayload::schema(),
+    );
+    add(VerifyRequest::NAME, VerifyRequest::schema());
+    add(VerifyResponse::NAME, VerifyResponse::schema());
+    add(SettleRequest::NAME, SettleRequest::schema());
+    add(SettleResponse::NAME, SettleResponse::schema());
+    add(SettlementSimulation::NAME, SettlementSimulation::schema());
+    add(PreflightResponse::NAME, PreflightResponse::schema());
+    add(SupportedKind::NAME, SupportedKind::schema());
+    add(FeePolicy::NAME, FeePolicy::schema());
+    add(SupportedResponse::NAME, SupportedResponse::schema());
+    add(PaymentResponseHeader::NAME, PaymentResponseHeader::schema());
+    add(JobSummary::NAME, JobSummary::schema());
+    add(PaymentRecord::NAME, PaymentRecord::schema());
+    add(VolumeEntry::NAME, VolumeEntry::schema());
+    add(Discrepancy::NAME, Discrepancy::schema());
+    add(Reconciliation::NAME, Reconciliation::schema());
+    add(EventKind::NAME, EventKind::schema());
+    add(WebhookEvent::NAME, WebhookEvent::schema());
+    add(FailedDelivery::NAME, FailedDelivery::schema());
+    add(Endpoint::NAME, Endpoint::schema());
+    add(RateLimit::NAME, RateLimit::schema());
+    add(DirectPayments::NAME, DirectPayments::schema());
+    add(TenantConfig::NAME, TenantConfig::schema());
+    add(TenantSummary::NAME, TenantSummary::schema());
+    add(Readiness::NAME, Readiness::schema());
+    add(ReadinessCheck::NAME, ReadinessCheck::schema());
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "x402 Facilitator",
+            "description": "Verifies and settles x402 escrow payments on behalf of resource servers",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths(),
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "apiKey": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "API key of a tenant, required by multi-tenant facilitators only",
+                },
+                "adminToken": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "Admin token of the facilitator",
+                },
+            },
+        },
+    })
+}
+
+fn paths() -> Value {
+    let protocol = json!([{}, { "apiKey": [] }]);
+    let admin = json!([{ "adminToken": [] }]);
+    let unauthorized = json!({ "description": "Missing or wrong bearer token" });
+    json!({
+        "/verify": {
+            "post": {
+                "summary": "Check an X-PAYMENT payload against payment requirements",
+                "tags": ["protocol"],
+                "security": protocol,
+                "requestBody": json_body(schema_ref::<VerifyRequest>()),
+                "responses": {
+                    "200": json_response("Verification outcome", schema_ref::<VerifyResponse>()),
+                    "409": json_response(
+                        "The payload was verified before (nonce_replayed)",
+                        schema_ref::<VerifyResponse>(),
+                    ),
+                    "429": json_response(
+                        "Rate limited, retry after the Retry-After seconds",
+                        schema_ref::<VerifyResponse>(),
+                    ),
+                },
+            },
+        },
+        "/settle": {
+            "post": {
+                "summary": "Charge the escrow on-chain and return the transaction hash",
+                "description": "With dryRun, the settlement is simulated instead: no nonce is consumed and nothing is submitted or queued",
+                "tags": ["protocol"],
+                "security": protocol,
+                "requestBody": json_body(schema_ref::<SettleRequest>()),
+                "responses": {
+                    "200": json_response("Settlement outcome", schema_ref::<SettleResponse>()),
+                    "429": json_response(
+                        "Rate limited, retry after the Retry-After seconds",
+                        schema_ref::<SettleResponse>(),
+                    ),
+                    "503": json_response(
+                        "The facilitator is shutting down (shutting_down)",
+                        schema_ref::<SettleResponse>(),
+                    ),
+                },
+            },
+        },
+        "/preflight": {
+            "post": {
+                "summary": "Forecast whether settling a payment would succeed, without settling it",
+                "description": "Checks the payment and simulates its settlement against current chain state. Verdicts are reused for a few seconds, and never binding",
+                "tags": ["protocol"],
+                "security": protocol,
+                "requestBody": json_body(schema_ref::<VerifyRequest>()),
+                "responses": {
+                    "200": json_response("Forecast", schema_ref::<PreflightResponse>()),
+                    "429": json_response(
+                        "Rate limited, retry after the Retry-After seconds",
+                        schema_ref::<PreflightResponse>(),
+                    ),
+                },
+            },
+        },
+        "/supported": {
+            "get": {
+                "summary": "Schemes, networks, assets, and fees the facilitator accepts",
+                "tags": ["protocol"],
+                "security": protocol,
+                "responses": {
+                    "200": json_response("Accepted payments", schema_ref::<SupportedResponse>()),
+                    "502": text_response("The escrow contract cannot be read"),
+                },
+            },
+        },
+        "/metrics": {
+            "get": {
+                "summary": "Prometheus metrics",
+                "tags": ["protocol"],
+                "security": protocol,
+                "responses": {
+                    "200": {
+                        "description": "Metrics in the Prometheus text format",
+                        "content": { "text/plain": { "schema": { "type": "string" } } },
+                    },
+                },
+            },
+        },
+        "/healthz": {
+            "get": {
+                "summary": "Liveness, answering as long as the process serves",
+                "tags": ["health"],
+                "responses": {
+                    "200": json_response("Alive", object_schema(
+                        "Liveness",
+                        json!({ "status": { "type": "string", "const": "ok" } }),
+                        &["status"],
+                    )),
+                },
+            },
+        },
+        "/readyz": {
+            "get": {
+                "summary": "Readiness, checking RPC, the contract, the settlement queue backlog, and the signer",
+                "description": "Not served by multi-tenant facilitators",
+                "tags": ["health"],
+                "responses": {
+                    "200": json_response("Every check passed", schema_ref::<Readiness>()),
+                    "503": json_response("A check failed", schema_ref::<Readiness>()),
+                },
+            },
+        },
+        OPENAPI_PATH: {
+            "get": {
+                "summary": "This description",
+                "tags": ["protocol"],
+                "responses": {
+                    "200": json_response("OpenAPI 3.1 description", json!({ "type": "object" })),
+                },
+            },
+        },
+        "/admin/webhooks/failed": {
+            "get": {
+                "summary": "List dead-lettered webhook deliveries",
+                "tags": ["admin"],
+                "security": admin,
+                "responses": {
+                    "200": json_response(
+                        "Deliveries that failed every attempt",
+                        list_of(schema_ref::<FailedDelivery>()),
+                    ),
+                    "401": unauthorized,
+                },
+            },
+        },
+        "/admin/webhooks/failed/{id}/replay": {
+            "post": {
+                "summary": "Retry a dead-lettered webhook delivery",
+                "tags": ["admin"],
+                "security": admin,
+                "parameters": [path_parameter("Delivery ID", json!({ "type": "string" }))],
+                "responses": {
+                    "204": { "description": "Delivered" },
+                    "401": unauthorized,
+                    "404": { "description": "No such delivery" },
+                    "502": json_response(
+                        "Delivery failed again",
+                        schema_ref::<FailedDelivery>(),
+                    ),
+                },
+            },
+        },
+        "/admin/tenants": {
+            "get": {
+                "summary": "List the tenants of a multi-tenant facilitator",
+                "tags": ["tenants"],
+                "security": admin,
+                "responses": {
+                    "200": json_response(
+                        "Tenants, without their secrets",
+                        list_of(schema_ref::<TenantSummary>()),
+                    ),
+                    "401": unauthorized,
+                },
+            },
+        },
+        "/admin/tenants/{id}": {
+            "put": {
+                "summary": "Add or replace a tenant, its ID taken from the path",
+                "tags": ["tenants"],
+                "security": admin,
+                "parameters": [path_parameter("Tenant ID", json!({ "type": "string" }))],
+                "requestBody": json_body(schema_ref::<TenantConfig>()),
+                "responses": {
+                    "200": json_response("Replaced", schema_ref::<TenantSummary>()),
+                    "201": json_response("Added", schema_ref::<TenantSummary>()),
+                    "401": unauthorized,
+                    "409": text_response("Another tenant has the API key"),
+                    "422": text_response("Invalid tenant"),
+                },
+            },
+            "delete": {
+                "summary": "Remove a tenant",
+                "tags": ["tenants"],
+                "security": admin,
+                "parameters": [path_parameter("Tenant ID", json!({ "type": "string" }))],
+                "responses": {
+                    "204": { "description": "Removed" },
+                    "401": unauthorized,
+                    "404": { "description": "No such tenant" },
+                },
+            },
+        },
+        "/admin/tenants/{id}/next-key": {
+            "put": {
+                "summary": "Stage the settlement key replacing a tenant's active one",
+                "tags": ["tenants"],
+                "security": admin,
+                "parameters": [path_parameter("Tenant ID", json!({ "type": "string" }))],
+                "requestBody": json_body(json!({
+                    "type": "object",
+                    "properties": {
+                        "signingKey": string_schema(
+                            "Where the staged key is read from, as the tenant's signingKey",
+                        ),
+                    },
+                    "required": ["signingKey"],
+                })),
+                "responses": {
+                    "200": json_response("Staged", schema_ref::<TenantSummary>()),
+                    "401": unauthorized,
+                    "404": { "description": "No such tenant" },
+                    "422": text_response("Invalid key"),
+                },
+            },
+        },
+        "/admin/tenants/{id}/next-key/promote": {
+            "post": {
+                "summary": "Sign a tenant's settlements with its staged key, queued ones included",
+                "tags": ["tenants"],
+                "security": admin,
+                "parameters": [path_parameter("Tenant ID", json!({ "type": "string" }))],
+                "responses": {
+                    "200": json_response("Promoted", schema_ref::<TenantSummary>()),
+                    "401": unauthorized,
+                    "404": { "description": "No such tenant" },
+                    "409": text_response("No key is staged"),
+                    "422": text_response("Invalid staged key"),
+                },
+            },
+        },
+        "/admin/volume": {
+            "get": {
+                "summary": "Amount settled by asset, and by tenant when multi-tenant",
+                "tags": ["admin"],
+                "security": admin,
+                "responses": {
+                    "200": json_response(
+                        "Settled amounts since the facilitator started",
+                        list_of(schema_ref::<VolumeEntry>()),
+                    ),
+                    "401": unauthorized,
+                },
+            },
+        },
+        "/admin/jobs": {
+            "get": {
+                "summary": "List unfinished settlement jobs, or those in a state",
+                "tags": ["admin"],
+                "security": admin,
+                "parameters": [{
+                    "name": "state",
+                    "in": "query",
+                    "required": false,
+                    "schema": JobSummary::schema()["properties"]["state"].clone(),
+                }],
+                "responses": {
+                    "200": json_response(
+                        "Jobs, oldest first",
+                        list_of(schema_ref::<JobSummary>()),
+                    ),
+                    "400": text_response("Unknown state"),
+                    "401": unauthorized,
+                    "404": text_response("No settlement queue is configured"),
+                },
+            },
+        },
+        "/admin/jobs/{id}": {
+            "get": {
+                "summary": "Look up a settlement job",
+                "tags": ["admin"],
+                "security": admin,
+                "parameters": [path_parameter("Job ID", json!({ "type": "integer" }))],
+                "responses": {
+                    "200": json_response("The job", schema_ref::<JobSummary>()),
+                    "401": unauthorized,
+                    "404": text_response("No such job, or no settlement queue"),
+                },
+            },
+        },
+        "/admin/jobs/{id}/retry": {
+            "post": {
+                "summary": "Attempt a settlement job now, resuming a failed one",
+                "tags": ["admin"],
+                "security": admin,
+                "parameters": [path_parameter("Job ID", json!({ "type": "integer" }))],
+                "responses": {
+                    "200": json_response("The job after the attempt", schema_ref::<JobSummary>()),
+                    "401": unauthorized,
+                    "404": text_response("No such job, or no settlement queue"),
+                    "409": text_response("The job is settled, or being processed"),
+                },
+            },
+        },
+        "/admin/jobs/{id}/cancel": {
+            "post": {
+                "summary": "Fail a settlement job no transaction is in flight for",
+                "tags": ["admin"],
+                "security": admin,
+                "parameters": [path_parameter("Job ID", json!({ "type": "integer" }))],
+                "responses": {
+                    "200": json_response("The failed job", schema_ref::<JobSummary>()),
+                    "401": unauthorized,
+                    "404": text_response("No such job, or no settlement queue"),
+                    "409": text_response(
+                        "The job is settled, being processed, or has a transaction in flight",
+                    ),
+                },
+            },
+        },
+        "/admin/payments": {
+            "get": {
+                "summary": "List the on-chain payments of an escrow, or of a transaction",
+                "tags": ["admin"],
+                "security": admin,
+                "parameters": [
+                    {
+                        "name": "escrowId",
+                        "in": "query",
+                        "required": false,
+                        "schema": { "type": "integer", "minimum": 0 },
+                    },
+                    {
+                        "name": "txHash",
+                        "in": "query",
+                        "required": false,
+                        "schema": { "type": "string" },
+                    },
+                ],
+                "responses": {
+                    "200": json_response(
+                        "Payments, with the settlement jobs that created them",
+                        list_of(schema_ref::<PaymentRecord>()),
+                    ),
+                    "400": text_response("Not exactly one of escrowId and txHash"),
+                    "401": unauthorized,
+                    "502": text_response("The escrow contract cannot be read"),
+                },
+            },
+        },
+        "/admin/payments/{id}": {
+            "get": {
+                "summary": "Look up an on-chain payment",
+                "tags": ["admin"],
+                "security": admin,
+                "parameters": [path_parameter("Payment ID", json!({ "type": "integer" }))],
+                "responses": {
+                    "200": json_response("The payment", schema_ref::<PaymentRecord>()),
+                    "401": unauthorized,
+                    "404": text_response("No such payment"),
+                    "502": text_response("The escrow contract cannot be read"),
+                },
+            },
+        },
+        "/admin/reconcile": {
+            "post": {
+                "summary": "Compare the settlement queue with on-chain payments",
+                "tags": ["admin"],
+                "security": admin,
+                "responses": {
+                    "200": json_response(
+                        "Repaired jobs and remaining discrepancies",
+                        schema_ref::<Reconciliation>(),
+                    ),
+                    "401": unauthorized,
+                    "404": text_response("No settlement queue is configured"),
+                    "502": text_response("The escrow contract cannot be read"),
+                },
+            },
+        },
+    })
+}
+
+fn list_of(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn json_body(schema: Value) -> Value {
+    json!({
+        "required": true,
+        "content": { "application/json": { "schema": schema } },
+    })
+}
+
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema } },
+    })
+}
+
+fn text_response(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "text/plain": { "schema": { "type": "string" } } },
+    })
+}
+
+fn path_parameter(description: &str, schema: Value) -> Value {
+    json!({
+        "name": "id",
+        "in": "path",
+        "required": true,
+        "description": description,
+        "schema": schema,
+    })
+}
+
+impl JsonSchema for ReadinessCheck {
+    const NAME: &'static str = "ReadinessCheck";
+
+    fn schema() -> Value {
+        object_schema(
+            "Outcome of one dependency check of /readyz",
+            json!({
+                "name": {
+                    "type": "string",
+                    "enum": ["shutdown", "rpc", "contract", "queue", "signer"],
+                },
+                "ok": boolean_schema("Whether the check passed"),
+                "detail": string_schema("What was found, or why the check failed"),
+                "elapsedMs": integer_schema("Time the check took, in milliseconds"),
+            }),
+            &["name", "ok", "detail", "elapsedMs"],
+        )
+    }
+}
+
+impl JsonSchema for Readiness {
+    const NAME: &'static str = "Readiness";
+
+    fn schema() -> Value {
+        object_schema(
+            "Readiness of the facilitator to serve payments",
+            json!({
+                "ready": boolean_schema("Whether every check passed"),
+                "checks": array_schema("Dependency checks", schema_ref::<ReadinessCheck>()),
+            }),
+            &["ready", "checks"],
+        )
+    }
+}
+
+impl JsonSchema for JobSummary {
+    const NAME: &'static str = "JobSummary";
+
+    fn schema() -> Value {
+        object_schema(
+            "Settlement job as listed by the admin endpoints",
+            json!({
+                "id": integer_schema("Job ID"),
+                "escrowId": integer_schema("Escrow account ID"),
+                "nonce": integer_schema("Nonce of the authorization"),
+                "client": string_schema("Client address (G... format)"),
+                "amount": stroops_schema("Amount to charge, in stroops"),
+                "asset": string_schema("Asset label of the settlement"),
+                "state": {
+                    "type": "string",
+                    "enum": ["queued", "created", "settled", "failed"],
+                },
+                "paymentId": nullable_schema(integer_schema("Escrow payment ID, once created")),
+                "txHash": nullable_schema(string_schema("Hash of the settling transaction")),
+                "pendingTxHash": nullable_schema(string_schema(
+                    "Hash of the transaction saved before submission, which may still apply",
+                )),
+                "attempts": integer_schema("Failed attempts"),
+                "nextAttemptAt": integer_schema(
+                    "Unix time in milliseconds before which the job is not retried",
+                ),
+                "error": nullable_schema(string_schema("Error of the last attempt")),
+                "batchId": nullable_schema(integer_schema("Batch settling the payment")),
+                "enqueuedAt": integer_schema("Unix time in milliseconds the job was queued at"),
+            }),
+            &[
+                "id",
+                "escrowId",
+                "nonce",
+                "client",
+                "amount",
+                "asset",
+                "state",
+                "attempts",
+                "nextAttemptAt",
+                "enqueuedAt",
+            ],
+        )
+    }
+}
+
+impl JsonSchema for PaymentRecord {
+    const NAME: &'static str = "PaymentRecord";
+
+    fn schema() -> Value {
+        object_schema(
+            "On-chain payment, with the settlement job that created it if known",
+            json!({
+                "paymentId": integer_schema("Escrow payment ID"),
+                "escrowId": integer_schema("Escrow account ID"),
+                "amount": stroops_schema("Amount, in stroops"),
+                "settled": boolean_schema("Whether the payment was settled"),
+                "timestamp": integer_schema("Ledger timestamp the payment was created at"),
+                "quoteAmount": nullable_schema(string_schema(
+                    "Worth of the amount in quoteCurrency when settled, in cents"
+                )),
+                "quoteCurrency": nullable_schema(string_schema("Currency of quoteAmount (USD)")),
+                "jobId": nullable_schema(integer_schema("Settlement job that created it")),
+            }),
+            &["paymentId", "escrowId", "amount", "settled", "timestamp"],
+        )
+    }
+}
+
+impl JsonSchema for VolumeEntry {
+    const NAME: &'static str = "VolumeEntry";
+
+    fn schema() -> Value {
+        object_schema(
+            "Amount settled in an asset, since the facilitator started",
+            json!({
+                "tenant": string_schema("Tenant of a multi-tenant facilitator"),
+                "asset": string_schema("Asset label of the settlements"),
+                "amount": stroops_schema("Amount, in stroops"),
+            }),
+            &["asset", "amount"],
+        )
+    }
+}
+
+impl JsonSchema for Discrepancy {
+    const NAME: &'static str = "Discrepancy";
+
+    fn schema() -> Value {
+        object_schema(
+            "Settlement job the chain contradicts",
+            json!({
+                "jobId": integer_schema("Job ID"),
+                "paymentId": integer_schema("Escrow payment ID of the job"),
+                "reason": {
+                    "type": "string",
+                    "enum": ["missing_on_chain", "unsettled_on_chain"],
+                },
+            }),
+            &["jobId", "paymentId", "reason"],
+        )
+    }
+}
+
+impl JsonSchema for Reconciliation {
+    const NAME: &'static str = "Reconciliation";
+
+    fn schema() -> Value {
+        object_schema(
+            "Outcome of a reconciliation of the settlement queue with on-chain state",
+            json!({
+                "escrowsChecked": integer_schema("Escrows whose on-chain payments were read"),
+                "jobsChecked": integer_schema("Jobs with an on-chain payment compared"),
+                "paymentsChecked": integer_schema("On-chain payments of the queue's escrows read"),
+                "repaired": array_schema(
+                    "Jobs marked settled, their payment being settled on-chain",
+                    json!({ "type": "integer" }),
+                ),
+                "discrepancies": array_schema(
+                    "Jobs the chain contradicts, left for an operator",
+                    schema_ref::<Discrepancy>(),
+                ),
+                "orphaned": array_schema(
+                    "On-chain payments of the queue's escrows created by no job",
+                    schema_ref::<PaymentRecord>(),
+                ),
+            }),
+            &[
+                "escrowsChecked",
+                "jobsChecked",
+                "paymentsChecked",
+                "repaired",
+                "discrepancies",
+                "orphaned",
+            ],
+        )
+    }
+}
+
+impl JsonSchema for EventKind {
+    const NAME: &'static str = "EventKind";
+
+    fn schema() -> Value {
+        let names: Vec<_> = Self::ALL.iter().map(EventKind::as_str).collect();
+        json!({ "type": "string", "description": "Webhook event type", "enum": names })
+    }
+}
+
+impl JsonSchema for WebhookEvent {
+    const NAME: &'static str = "WebhookEvent";
+
+    fn schema() -> Value {
+        object_schema(
+            "Payload POSTed to webhook endpoints",
+            json!({
+                "id": string_schema("Unique event ID"),
+                "type": schema_ref::<EventKind>(),
+                "createdAt": integer_schema("Unix timestamp the event happened at"),
+                "data": { "type": "object", "description": "Payment the event is about" },
+            }),
+            &["id", "type", "createdAt", "data"],
+        )
+    }
+}
+
+impl JsonSchema for FailedDelivery {
+    const NAME: &'static str = "FailedDelivery";
+
+    fn schema() -> Value {
+        object_schema(
+            "Webhook delivery that failed every attempt",
+            json!({
+                "id": string_schema("Delivery ID, used to replay it"),
+                "url": string_schema("Endpoint URL"),
+                "event": schema_ref::<WebhookEvent>(),
+                "attempts": integer_schema("Attempts made"),
+                "error": string_schema("Error of the last attempt"),
+                "failedAt": integer_schema("Unix timestamp of the last attempt"),
+            }),
+            &["id", "url", "event", "attempts", "error", "failedAt"],
+        )
+    }
+}
+
+impl JsonSchema for Endpoint {
+    const NAME: &'static str = "Endpoint";
+
+    fn schema() -> Value {
+        object_schema(
+            "Receiver of webhook events",
+            json!({
+                "url": string_schema("Endpoint URL"),
+                "secret": string_schema("HMAC key of the signature header"),
+                "events": array_schema(
+                    "Event types delivered, all of them when empty",
+                    schema_ref::<EventKind>(),
+                ),
+            }),
+            &["url", "secret"],
+        )
+    }
+}
+
+impl JsonSchema for RateLimit {
+    const NAME: &'static str = "RateLimit";
+
+    fn schema() -> Value {
+        object_schema(
+            "Token bucket refilled at a steady rate",
+            json!({
+                "burst": integer_schema("Requests allowed in a burst, the bucket capacity"),
+                "perMinute": integer_schema("Tokens added per minute"),
+            }),
+            &["burst", "perMinute"],
+        )
+    }
+}
+
+impl JsonSchema for DirectPayments {
+    const NAME: &'static str = "DirectPayments";
+
+    fn schema() -> Value {
+        object_schema(
+            "Acceptance of direct payments proven by their transaction hash",
+            json!({
+                "confirmations": integer_schema(
+                    "Ledgers closed since the payment, the including one counted",
+                ),
+                "maxAgeSecs": integer_schema(
+                    "Seconds after the payment closed it stops being accepted",
+                ),
+            }),
+            &["confirmations", "maxAgeSecs"],
+        )
+    }
+}
+
+impl JsonSchema for TenantConfig {
+    const NAME: &'static str = "TenantConfig";
+
+    fn schema() -> Value {
+        object_schema(
+            "Resource server of a multi-tenant facilitator",
+            json!({
+                "id": string_schema("Unique tenant ID, taken from the path when PUT"),
+                "apiKey": string_schema("Bearer token of the tenant's protocol requests"),
+                "server": string_schema("Payout address (G... format) payments must be made to"),
+                "signingKey": string_schema(
+                    "Where the settlement key is read from: env:NAME, file:PATH, or a remote signer URL",
+                ),
+                "nextSigningKey": nullable_schema(string_schema(
+                    "Where the key staged to replace the settlement key is read from",
+                )),
+                "rotated": boolean_schema(
+                    "Whether the settlement key was promoted by a rotation, and may be a signer of the server account",
+                ),
+                "assets": array_schema(
+                    "Accepted asset contract addresses (C... format)",
+                    json!({ "type": "string" }),
+                ),
+                "feeBps": integer_schema("Facilitator fee share, in basis points"),
+                "webhooks": array_schema(
+                    "Webhook endpoints notified of the tenant's settlement outcomes",
+                    schema_ref::<Endpoint>(),
+                ),
+                "rateLimit": nullable_schema(schema_ref::<RateLimit>()),
+                "directPayments": nullable_schema(schema_ref::<DirectPayments>()),
+            }),
+            &["apiKey", "server", "signingKey"],
+        )
+    }
+}
+
+impl JsonSchema for TenantSummary {
+    const NAME: &'static str = "TenantSummary";
+
+    fn schema() -> Value {
+        object_schema(
+            "Tenant listed by the admin endpoints, without its secrets",
+            json!({
+                "id": string_schema("Tenant ID"),
+                "server": string_schema("Payout address (G... format)"),
+                "assets": array_schema(
+                    "Accepted asset contract addresses (C... format)",
+                    json!({ "type": "string" }),
+                ),
+                "feeBps": integer_schema("Facilitator fee share, in basis points"),
+                "webhooks": array_schema(
+                    "URLs of the webhook endpoints",
+                    json!({ "type": "string" }),
+                ),
+                "rateLimit": nullable_schema(schema_ref::<RateLimit>()),
+                "directPayments": nullable_schema(schema_ref::<DirectPayments>()),
+                "nextKeyStaged": boolean_schema(
+                    "Whether a settlement key is staged to replace the active one",
+                ),
+            }),
+            &[
+                "id",
+                "server",
+                "assets",
+                "feeBps",
+                "webhooks",
+                "rateLimit",
+                "directPayments",
+                "nextKeyStaged",
+            ],
+        )
+    }
+}
@@ -0,0 +1,530 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+/// Protobuf package of the facilitator service
+pub const PROTO_PACKAGE: &str = "x402.facilitator.v1";
+
+/// Protobuf service name, gRPC paths being `/{package}.{service}/{method}`
+pub const PROTO_SERVICE: &str = "Facilitator";
+
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LEN: u64 = 2;
+const FIXED32: u64 = 5;
+
+/// Error decoding a protobuf message
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    #[error("message truncated")]
+    Truncated,
+    #[error("field {0} has an unexpected wire type")]
+    WireType(u64),
+    #[error("field {0} is not UTF-8")]
+    Utf8(&'static str),
+    #[error("field {0} is not JSON: {1}")]
+    Json(&'static str, serde_json::Error),
+    #[error("invalid message: {0}")]
+    Invalid(serde_json::Error),
+}
+
+/// Type of the values of a field
+#[derive(Clone, Copy, Debug)]
+pub enum Kind {
+    String,
+    Uint32,
+    Uint64,
+    Bool,
+    /// JSON text, for free-form values
+    Json,
+    Message(&'static Message),
+}
+
+/// Number of values a field holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Label {
+    /// One value, the type's default when absent
+    Singular,
+    /// One value or none
+    Optional,
+    /// Any number of values
+    Repeated,
+}
+
+/// Field of a message, named as in the JSON form of its type
+#[derive(Clone, Copy, Debug)]
+pub struct Field {
+    pub number: u32,
+    pub name: &'static str,
+    pub kind: Kind,
+    pub label: Label,
+}
+
+/// Protobuf message mirroring the JSON form of a request or response type
+///
+/// Messages are encoded from and decoded to the type's JSON, so the serde
+/// implementations shared with the HTTP API stay the only mapping between
+/// the types and their wire forms.
+#[derive(Debug)]
+pub struct Message {
+    pub name: &'static str,
+    pub fields: &'static [Field],
+}
+
+/// RPC of the facilitator service
+#[derive(Debug)]
+pub struct Method {
+    pub name: &'static str,
+    pub input: &'static Message,
+    pub output: &'static Message,
+    /// Whether the output is a stream of messages
+    pub streaming: bool,
+}
+
+const fn field(number: u32, name: &'static str, kind: Kind) -> Field {
+    Field {
+        number,
+        name,
+        kind,
+        label: Label::Singular,
+    }
+}
+
+const fn optional(number: u32, name: &'static str, kind: Kind) -> Field {
+    Field {
+        label: Label::Optional,
+        ..field(number, name, kind)
+    }
+}
+
+const fn repeated(number: u32, name: &'static str, kind: Kind) -> Field {
+    Field {
+        label: Label::Repeated,
+        ..field(number, name, kind)
+    }
+}
+
+pub static PAYMENT_REQUIREMENTS: Message = Message {
+    name: "PaymentRequirements",
+    fields: &[
+        field(1, "scheme", Kind::String),
+        field(2, "network", Kind::String),
+        field(3, "maxAmountRequired", Kind::String),
+        field(4, "resource", Kind::String),
+        field(5, "description", Kind::String),
+        field(6, "mimeType", Kind::String),
+        optional(7, "outputSchema", Kind::Json),
+        field(8, "payTo", Kind::String),
+        optional(9, "asset", Kind::String),
+        field(10, "maxTimeoutSeconds", Kind::Uint64),
+        optional(11, "extra", Kind::Json),
+        repeated(12, "alternatives", Kind::Message(&ASSET_AMOUNT)),
+        optional(13, "x402Version", Kind::Uint32),
+    ],
+};
+
+pub static ASSET_AMOUNT: Message = Message {
+    name: "AssetAmount",
+    fields: &[
+        field(1, "asset", Kind::String),
+        field(2, "maxAmountRequired", Kind::String),
+    ],
+};
+
+pub static VERIFY_REQUEST: Message = Message {
+    name: "VerifyRequest",
+    fields: &[
+        field(1, "x402Version", Kind::Uint32),
+        field(2, "paymentHeader", Kind::String),
+        field(
+            3,
+            "paymentRequirements",
+            Kind::Message(&PAYMENT_REQUIREMENTS),
+        ),
+    ],
+};
+
+pub static VERIFY_RESPONSE: Message = Message {
+    name: "VerifyResponse",
+    fields: &[
+        field(1, "isValid", Kind::Bool),
+        optional(2, "invalidReason", Kind::String),
+        repeated(3, "x402Versions", Kind::Uint32),
+        optional(4, "errorCode", Kind::Uint32),
+    ],
+};
+
+pub static SETTLE_REQUEST: Message = Message {
+    name: "SettleRequest",
+    fields: &[
+        field(1, "x402Version", Kind::Uint32),
+        field(2, "paymentHeader", Kind::String),
+        field(
+            3,
+            "paymentRequirements",
+            Kind::Message(&PAYMENT_REQUIREMENTS),
+        ),
+        optional(4, "settleAmount", Kind::String),
+        field(5, "dryRun", Kind::Bool),
+    ],
+};
+
+pub static SETTLE_RESPONSE: Message = Message {
+    name: "SettleResponse",
+    fields: &[
+        field(1, "success", Kind::Bool),
+        optional(2, "error", Kind::String),
+        optional(3, "txHash", Kind::String),
+        optional(4, "networkId", Kind::String),
+        optional(5, "paymentId", Kind::Uint64),
+        optional(6, "ledger", Kind::Uint32),
+        optional(7, "finality", Kind::String),
+        optional(8, "simulation", Kind::Message(&SETTLEMENT_SIMULATION)),
+        optional(9, "errorCode", Kind::Uint32),
+    ],
+};
+
+pub static SETTLEMENT_SIMULATION: Message = Message {
+    name: "SettlementSimulation",
+    fields: &[
+        field(1, "amount", Kind::String),
+        optional(2, "paymentId", Kind::Uint64),
+        optional(3, "estimatedFee", Kind::Uint32),
+    ],
+};
+
+pub static GET_SUPPORTED_REQUEST: Message = Message {
+    name: "GetSupportedRequest",
+    fields: &[],
+};
+
+pub static SUPPORTED_KIND: Message = Message {
+    name: "SupportedKind",
+    fields: &[
+        field(1, "x402Version", Kind::Uint32),
+        field(2, "scheme", Kind::String),
+        field(3, "network", Kind::String),
+        field(4, "networkPassphrase", Kind::String),
+    ],
+};
+
+pub static FEE_POLICY: Message = Message {
+    name: "FeePolicy",
+    fields: &[
+        field(1, "feeBps", Kind::Uint32),
+        field(2, "networkFeeSponsored", Kind::Bool),
+    ],
+};
+
+pub static SUPPORTED_RESPONSE: Message = Message {
+    name: "SupportedResponse",
+    fields: &[
+        repeated(1, "kinds", Kind::Message(&SUPPORTED_KIND)),
+        repeated(2, "assets", Kind::String),
+        field(3, "fee", Kind::Message(&FEE_POLICY)),
+        field(4, "escrowContract", Kind::String),
+    ],
+};
+
+pub static WATCH_SETTLEMENTS_REQUEST: Message = Message {
+    name: "WatchSettlementsRequest",
+    fields: &[repeated(1, "types", Kind::String)],
+};
+
+pub static SETTLEMENT_EVENT: Message = Message {
+    name: "SettlementEvent",
+    fields: &[
+        field(1, "id", Kind::String),
+        field(2, "type", Kind::String),
+        field(3, "createdAt", Kind::Uint64),
+        field(4, "data", Kind::Json),
+    ],
+};
+
+/// Methods of the facilitator service
+pub static METHODS: [Method; 4] = [
+    Method {
+        name: "Verify",
+        input: &VERIFY_REQUEST,
+        output: &VERIFY_RESPONSE,
+        streaming: false,
+    },
+    Method {
+        name: "Settle",
+        input: &SETTLE_REQUEST,
+        output: &SETTLE_RESPONSE,
+        streaming: false,
+    },
+    Method {
+        name: "GetSupported",
+        input: &GET_SUPPORTED_REQUEST,
+        output: &SUPPORTED_RESPONSE,
+        streaming: false,
+    },
+    Method {
+        name: "WatchSettlements",
+        input: &WATCH_SETTLEMENTS_REQUEST,
+        output: &SETTLEMENT_EVENT,
+        streaming: true,
+    },
+];
+
+/// Messages of the facilitator service, in the order of the proto file
+pub static MESSAGES: [&Message; 13] = [
+    &VERIFY_REQUEST,
+    &VERIFY_RESPONSE,
+    &SETTLE_REQUEST,
+    &SETTLE_RESPONSE,
+    &GET_SUPPORTED_REQUEST,
+    &SUPPORTED_RESPONSE,
+    &WATCH_SETTLEMENTS_REQUEST,
+    &SETTLEMENT_EVENT,
+    &PAYMENT_REQUIREMENTS,
+    &ASSET_AMOUNT,
+    &SUPPORTED_KIND,
+    &FEE_POLICY,
+    &SETTLEMENT_SIMULATION,
+];
+
+/// Protobuf description of the facilitator service, as served over gRPC-Web
+pub fn proto_file() -> String {
+    let mut proto = String::from(
+        "// Generated from the x402 facilitator's request and response types by\n\
+         // x402_facilitator::proto_file(), do not edit.\n\
+         //\n\
+         // Fields named as the JSON properties of the HTTP API, string fields\n\
+         // marked JSON carrying JSON text.\n\n\
+         syntax = \"proto3\";\n\n",
+    );
+    proto += &format!("package {PROTO_PACKAGE};\n\nservice {PROTO_SERVICE} {{\n");
+    for method in &METHODS {
+        let stream = if method.streaming { "stream " } else { "" };
+        proto += &format!(
+            "  rpc {}({}) returns ({stream}{});\n",
+            method.name, method.input.name, method.output.name
+        );
+    }
+    proto += "}\n";
+    for message in MESSAGES {
+        if message.fields.is_empty() {
+            proto += &format!("\nmessage {} {{}}\n", message.name);
+            continue;
+        }
+        proto += &format!("\nmessage {} {{\n", message.name);
+        for field in message.fields {
+            let label = match field.label {
+                Label::Singular => "",
+                Label::Optional => "optional ",
+                Label::Repeated => "repeated ",
+            };
+            let (kind, comment) = match field.kind {
+                Kind::String => ("string", ""),
+                Kind::Uint32 => ("uint32", ""),
+                Kind::Uint64 => ("uint64", ""),
+                Kind::Bool => ("bool", ""),
+                Kind::Json => ("string", " // JSON"),
+                Kind::Message(message) => (message.name, ""),
+            };
+            proto += &format!(
+                "  {label}{kind} {} = {};{comment}\n",
+                snake_case(field.name),
+                field.number
+            );
+        }
+        proto += "}\n";
+    }
+    proto
+}
+
+/// Encode `value` as `message`
+pub fn encode<T: Serialize>(message: &Message, value: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    encode_json(
+        message,
+        &serde_json::to_value(value).unwrap_or_default(),
+        &mut buf,
+    );
+    buf
+}
+
+/// Decode `bytes` as `message`
+///
+/// Unknown fields are skipped, absent ones take their proto3 default.
+///
+/// # Errors
+/// * If the bytes are not a valid message, or do not decode as `T`
+pub fn decode<T: DeserializeOwned>(message: &Message, bytes: &[u8]) -> Result<T, DecodeError> {
+    serde_json::from_value(decode_json(message, bytes)?).map_err(DecodeError::Invalid)
+}
+
+fn encode_json(message: &Message, value: &Value, buf: &mut Vec<u8>) {
+    for field in message.fields {
+        match value.get(field.name) {
+            None | Some(Value::Null) => {}
+            Some(Value::Array(items)) if field.label == Label::Repeated => {
+                for item in items {
+                    encode_value(field, item, buf);
+                }
+            }
+            Some(value) => encode_value(field, value, buf),
+        }
+    }
+}
+
+fn encode_value(field: &Field, value: &Value, buf: &mut Vec<u8>) {
+    let key = |wire_type| u64::from(field.number) << 3 | wire_type;
+    match field.kind {
+        Kind::Bool => {
+            put_varint(buf, key(VARINT));
+            put_varint(buf, value.as_bool().unwrap_or_default().into());
+        }
+        Kind::Uint32 | Kind::Uint64 => {
+            put_varint(buf, key(VARINT));
+            put_varint(buf, value.as_u64().unwrap_or_default());
+        }
+        Kind::String => {
+            put_varint(buf, key(LEN));
+            put_bytes(buf, value.as_str().unwrap_or_default().as_bytes());
+        }
+        Kind::Json => {
+            put_varint(buf, key(LEN));
+            put_bytes(buf, value.to_string().as_bytes());
+        }
+        Kind::Message(message) => {
+            let mut nested = Vec::new();
+            encode_json(message, value, &mut nested);
+            put_varint(buf, key(LEN));
+            put_bytes(buf, &nested);
+        }
+    }
+}
+
+fn decode_json(message: &Message, mut bytes: &[u8]) -> Result<Value, DecodeError> {
+    let mut object = Map::new();
+    while !bytes.is_empty() {
+        let key = take_varint(&mut bytes)?;
+        let (number, wire_type) = (key >> 3, key & 7);
+        let value = match wire_type {
+            VARINT => Wire::Varint(take_varint(&mut bytes)?),
+            LEN => {
+                let len = usize::try_from(take_varint(&mut bytes)?)
+                    .map_err(|_| DecodeError::Truncated)?;
+                Wire::Len(take(&mut bytes, len)?)
+            }
+            FIXED64 => take(&mut bytes, 8).map(|_| Wire::Fixed)?,
+            FIXED32 => take(&mut bytes, 4).map(|_| Wire::Fixed)?,
+            _ => return Err(DecodeError::WireType(number)),
+        };
+        let Some(field) = message
+            .fields
+            .iter()
+            .find(|field| u64::from(field.number) == number)
+        else {
+            continue;
+        };
+        let value = decode_value(field, value)?;
+        if field.label == Label::Repeated {
+            if let Value::Array(items) = object
+                .entry(field.name)
+                .or_insert_with(|| Value::Array(Vec::new()))
+            {
+                items.push(value);
+            }
+        } else {
+            object.insert(field.name.into(), value);
+        }
+    }
+    for field in message.fields {
+        if !object.contains_key(field.name) {
+            if let Some(default) = default_value(field)? {
+                object.insert(field.name.into(), default);
+            }
+        }
+    }
+    Ok(Value::Object(object))
+}
+
+/// Value of a field as read off the wire
+enum Wire<'a> {
+    Varint(u64),
+    Len(&'a [u8]),
+    /// Fixed-size value, no field has
+    Fixed,
+}
+
+fn decode_value(field: &Field, value: Wire) -> Result<Value, DecodeError> {
+    let number = field.number.into();
+    Ok(match (field.kind, value) {
+        (Kind::Bool, Wire::Varint(value)) => Value::Bool(value != 0),
+        (Kind::Uint32, Wire::Varint(value)) => (value as u32).into(),
+        (Kind::Uint64, Wire::Varint(value)) => value.into(),
+        (Kind::String, Wire::Len(bytes)) => std::str::from_utf8(bytes)
+            .map_err(|_| DecodeError::Utf8(field.name))?
+            .into(),
+        (Kind::Json, Wire::Len(bytes)) => {
+            serde_json::from_slice(bytes).map_err(|e| DecodeError::Json(field.name, e))?
+        }
+        (Kind::Message(message), Wire::Len(bytes)) => decode_json(message, bytes)?,
+        _ => return Err(DecodeError::WireType(number)),
+    })
+}
+
+/// Proto3 default of an absent field, None for fields absent in JSON too
+fn default_value(field: &Field) -> Result<Option<Value>, DecodeError> {
+    Ok(match (field.label, field.kind) {
+        (Label::Optional, _) | (Label::Singular, Kind::Json) => None,
+        (Label::Repeated, _) => Some(Value::Array(Vec::new())),
+        (Label::Singular, Kind::String) => Some("".into()),
+        (Label::Singular, Kind::Uint32 | Kind::Uint64) => Some(0.into()),
+        (Label::Singular, Kind::Bool) => Some(false.into()),
+        (Label::Singular, Kind::Message(message)) => Some(decode_json(message, &[])?),
+    })
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn take_varint(bytes: &mut &[u8]) -> Result<u64, DecodeError> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let Some((&byte, rest)) = bytes.split_first() else {
+            return Err(DecodeError::Truncated);
+        };
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte < 0x80 {
+            return Ok(value);
+        }
+    }
+    Err(DecodeError::Truncated)
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], DecodeError> {
+    if bytes.len() < len {
+        return Err(DecodeError::Truncated);
+    }
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+/// `snake_case` form of a `camelCase` JSON property, the proto field name
+fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
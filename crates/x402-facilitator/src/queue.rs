@@ -0,0 +1,839 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use x402_client::PreparedTransaction;
+use x402_types::StellarAmount;
+
+use crate::{RetryPolicy, VerifiedPayment};
+
+/// Error of the settlement queue
+#[derive(Debug, thiserror::Error)]
+pub enum QueueError {
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("corrupt settlement job {0}")]
+    Corrupt(i64),
+    #[error("corrupt settlement batch {0}")]
+    CorruptBatch(i64),
+}
+
+/// Progress of a settlement job
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum JobState {
+    /// Accepted, no payment created yet
+    Queued,
+    /// Payment created, not settled yet
+    Created,
+    /// Payment settled
+    Settled,
+    /// Given up on
+    Failed,
+}
+
+impl JobState {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Created => "created",
+            Self::Settled => "settled",
+            Self::Failed => "failed",
+        }
+    }
+
+    pub(crate) fn parse(state: &str) -> Option<Self> {
+        match state {
+            "queued" => Some(Self::Queued),
+            "created" => Some(Self::Created),
+            "settled" => Some(Self::Settled),
+            "failed" => Some(Self::Failed),
+            _ => None,
+        }
+    }
+
+    /// Whether the job is done, successfully or not
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Settled | Self::Failed)
+    }
+}
+
+/// Settlement of a verified payment
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SettlementJob {
+    pub id: i64,
+    /// Escrow account ID
+    pub escrow_id: u64,
+    /// Client-chosen nonce, unique per escrow
+    pub nonce: u64,
+    /// Client address (G... format)
+    pub client: String,
+    /// Amount to charge, in stroops
+    pub amount: i128,
+    /// Asset label of the payment requirements
+    pub asset: String,
+    pub state: JobState,
+    /// Signed transaction of the current step, saved before submission
+    pub pending_tx: Option<PreparedTransaction>,
+    /// Payment created against the escrow
+    pub payment_id: Option<u64>,
+    /// Hash of the settlement transaction
+    pub tx_hash: Option<String>,
+    /// Failed attempts so far
+    pub attempts: u32,
+    /// Unix time in milliseconds before which the job is not retried
+    pub next_attempt_at: u64,
+    /// Error of the last failed attempt
+    pub error: Option<String>,
+    /// Batch the payment was settled in, or is being settled in
+    pub batch_id: Option<i64>,
+    /// Unix time in milliseconds the job was queued at
+    pub enqueued_at: u64,
+}
+
+impl SettlementJob {
+    /// The verified payment being settled
+    pub fn payment(&self) -> VerifiedPayment {
+        VerifiedPayment {
+            escrow_id: self.escrow_id,
+            client: self.client.clone(),
+            amount: self.amount,
+            nonce: self.nonce,
+            tx_hash: None,
+            context: None,
+        }
+    }
+}
+
+/// Progress of a settlement batch
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BatchState {
+    /// Assembled, not settled yet
+    Pending,
+    /// Every payment of the batch settled
+    Settled,
+    /// Rejected by the contract, its payments are settled one by one
+    Failed,
+}
+
+impl BatchState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Settled => "settled",
+            Self::Failed => "failed",
+        }
+    }
+
+    fn parse(state: &str) -> Option<Self> {
+        match state {
+            "pending" => Some(Self::Pending),
+            "settled" => Some(Self::Settled),
+            "failed" => Some(Self::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// Created payments settled by a single `settle_payments` transaction
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SettlementBatch {
+    /// Group ID, recorded on every job of the batch for auditing
+    pub id: i64,
+    pub state: BatchState,
+    /// Jobs of the batch, oldest first
+    pub job_ids: Vec<i64>,
+    /// Payments settled by the batch, in job order
+    pub payment_ids: Vec<u64>,
+    /// Signed `settle_payments` transaction, saved before submission
+    pub pending_tx: Option<PreparedTransaction>,
+    /// Hash of the settlement transaction
+    pub tx_hash: Option<String>,
+    /// Unix time in milliseconds the batch was assembled at
+    pub created_at: u64,
+    /// Error of the last failed attempt
+    pub error: Option<String>,
+}
+
+/// When created payments are flushed in batches
+///
+/// A batch is flushed once `max_size` payments are waiting, or once the
+/// oldest one has waited `max_delay`, whichever comes first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchPolicy {
+    /// Payments triggering a flush, and the most settled per transaction
+    pub max_size: usize,
+    /// Longest a job waits for its batch, counted from when it was queued
+    pub max_delay: Duration,
+}
+
+impl Default for BatchPolicy {
+    fn default() -> Self {
+        Self {
+            max_size: 50,
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Settlement jobs persisted in SQLite
+///
+/// A job is written before the /settle response is sent and updated before
+/// and after every transaction it submits, so a restarted facilitator picks
+/// up where the previous one stopped. Its `(escrow_id, nonce)` pair is
+/// unique, making the queue the durable record of used nonces. Settlements
+/// taking no job, direct payments and those settling zero, are recorded
+/// there too.
+///
+/// With a [`BatchPolicy`], created payments wait for a batch instead of being
+/// settled one by one.
+pub struct SettlementQueue {
+    conn: Mutex<Connection>,
+    claimed: Mutex<HashSet<i64>>,
+    retry: RetryPolicy,
+    batching: Option<BatchPolicy>,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS settlement_jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    escrow_id INTEGER NOT NULL,
+    nonce INTEGER NOT NULL,
+    client TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    asset TEXT NOT NULL,
+    state TEXT NOT NULL,
+    pending_envelope TEXT,
+    pending_hash TEXT,
+    payment_id INTEGER,
+    tx_hash TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL,
+    error TEXT,
+    batch_id INTEGER REFERENCES settlement_batches (id),
+    enqueued_at INTEGER NOT NULL,
+    UNIQUE (escrow_id, nonce)
+);
+CREATE INDEX IF NOT EXISTS settlement_jobs_due ON settlement_jobs (state, next_attempt_at);
+CREATE INDEX IF NOT EXISTS settlement_jobs_batch ON settlement_jobs (batch_id);
+CREATE TABLE IF NOT EXISTS settlement_batches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    state TEXT NOT NULL,
+    pending_envelope TEXT,
+    pending_hash TEXT,
+    tx_hash TEXT,
+    created_at INTEGER NOT NULL,
+    error TEXT
+);
+CREATE TABLE IF NOT EXISTS used_nonces (
+    escrow_id INTEGER NOT NULL,
+    nonce INTEGER NOT NULL,
+    used_at INTEGER NOT NULL,
+    PRIMARY KEY (escrow_id, nonce)
+);
+CREATE TABLE IF NOT EXISTS used_transactions (
+    tx_hash TEXT PRIMARY KEY,
+    used_at INTEGER NOT NULL
+);
+";
+
+const COLUMNS: &str = "id, escrow_id, nonce, client, amount, asset, state, pending_envelope, \
+     pending_hash, payment_id, tx_hash, attempts, next_attempt_at, error, batch_id, enqueued_at";
+
+const BATCH_COLUMNS: &str = "id, state, pending_envelope, pending_hash, tx_hash, created_at, error";
+
+// Created jobs a batch may still settle, unless their batch failed
+const AWAITING_BATCH: &str = "state = 'created' AND (batch_id IS NULL OR batch_id NOT IN
+    (SELECT id FROM settlement_batches WHERE state = 'failed'))";
+
+impl SettlementQueue {
+    /// Open or create the queue database at `path`
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the database cannot be opened or migrated
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, QueueError> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Open a queue that lives only as long as the process
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the database cannot be created
+    pub fn open_in_memory() -> Result<Self, QueueError> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self, QueueError> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            claimed: Mutex::new(HashSet::new()),
+            retry: RetryPolicy::default(),
+            batching: None,
+        })
+    }
+
+    /// Replace the retry policy of failed attempts
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Retry policy of failed attempts
+    pub fn retry(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    /// Settle created payments in batches
+    pub fn with_batching(mut self, policy: BatchPolicy) -> Self {
+        self.batching = Some(policy);
+        self
+    }
+
+    /// Batch policy, None when payments are settled one by one
+    pub fn batching(&self) -> Option<&BatchPolicy> {
+        self.batching.as_ref()
+    }
+
+    /// Queue the settlement of a verified payment
+    ///
+    /// A payment already created on-chain, by the client opening its escrow
+    /// with `open_and_authorize`, is queued as created, to be settled only.
+    ///
+    /// # Returns
+    /// * None if a job already exists for the payment's escrow and nonce
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the job cannot be written
+    pub fn enqueue(
+        &self,
+        payment: &VerifiedPayment,
+        asset: &str,
+        payment_id: Option<u64>,
+    ) -> Result<Option<SettlementJob>, QueueError> {
+        let state = match payment_id {
+            Some(_) => JobState::Created,
+            None => JobState::Queued,
+        };
+        let conn = self.conn.lock().unwrap();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO settlement_jobs
+                (escrow_id, nonce, client, amount, asset, state, payment_id, next_attempt_at,
+                 enqueued_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, ?8)",
+            params![
+                to_sql(payment.escrow_id),
+                to_sql(payment.nonce),
+                payment.client,
+                payment.amount.to_string(),
+                asset,
+                state.as_str(),
+                payment_id.map(to_sql),
+                to_sql(now_millis()),
+            ],
+        )?;
+        if inserted == 0 {
+            return Ok(None);
+        }
+        let id = conn.last_insert_rowid();
+        drop(conn);
+        self.job(id)
+    }
+
+    /// Look up a job
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    /// * `Corrupt` - If the stored job cannot be decoded
+    pub fn job(&self, id: i64) -> Result<Option<SettlementJob>, QueueError> {
+        let conn = self.conn.lock().unwrap();
+        let row = conn
+            .query_row(
+                &format!("SELECT {COLUMNS} FROM settlement_jobs WHERE id = ?1"),
+                [id],
+                RawJob::from_row,
+            )
+            .optional()?;
+        row.map(RawJob::decode).transpose()
+    }
+
+    /// Every job, oldest first
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    /// * `Corrupt` - If a stored job cannot be decoded
+    pub fn jobs(&self) -> Result<Vec<SettlementJob>, QueueError> {
+        self.select(&format!(
+            "SELECT {COLUMNS} FROM settlement_jobs ORDER BY id"
+        ))
+    }
+
+    /// Jobs in one of `states`, oldest first
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    /// * `Corrupt` - If a stored job cannot be decoded
+    pub fn jobs_in(&self, states: &[JobState]) -> Result<Vec<SettlementJob>, QueueError> {
+        let states: Vec<_> = states
+            .iter()
+            .map(|state| format!("'{}'", state.as_str()))
+            .collect();
+        self.select(&format!(
+            "SELECT {COLUMNS} FROM settlement_jobs WHERE state IN ({}) ORDER BY id",
+            states.join(", ")
+        ))
+    }
+
+    /// Jobs settling payments of an escrow, oldest first
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    /// * `Corrupt` - If a stored job cannot be decoded
+    pub fn jobs_of_escrow(&self, escrow_id: u64) -> Result<Vec<SettlementJob>, QueueError> {
+        self.select(&format!(
+            "SELECT {COLUMNS} FROM settlement_jobs WHERE escrow_id = {} ORDER BY id",
+            to_sql(escrow_id)
+        ))
+    }
+
+    /// Jobs settled by the transaction `tx_hash`, several for a batch
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    /// * `Corrupt` - If a stored job cannot be decoded
+    pub fn jobs_with_tx(&self, tx_hash: &str) -> Result<Vec<SettlementJob>, QueueError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(&format!(
+            "SELECT {COLUMNS} FROM settlement_jobs WHERE tx_hash = ?1 ORDER BY id"
+        ))?;
+        let rows = statement
+            .query_map([tx_hash.to_ascii_lowercase()], RawJob::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter().map(RawJob::decode).collect()
+    }
+
+    /// Unfinished jobs whose next attempt is due, oldest first
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    /// * `Corrupt` - If a stored job cannot be decoded
+    ///
+    /// With batching, created jobs are left to their batch.
+    pub fn due(&self) -> Result<Vec<SettlementJob>, QueueError> {
+        let waiting = match self.batching {
+            Some(_) => format!("AND NOT ({AWAITING_BATCH})"),
+            None => String::new(),
+        };
+        self.select(&format!(
+            "SELECT {COLUMNS} FROM settlement_jobs
+             WHERE state IN ('queued', 'created') AND next_attempt_at <= {} {waiting}
+             ORDER BY id",
+            now_millis()
+        ))
+    }
+
+    /// Created jobs not assigned to a batch yet, oldest first
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    /// * `Corrupt` - If a stored job cannot be decoded
+    pub fn unbatched(&self) -> Result<Vec<SettlementJob>, QueueError> {
+        self.select(&format!(
+            "SELECT {COLUMNS} FROM settlement_jobs
+             WHERE state = 'created' AND batch_id IS NULL
+             ORDER BY id"
+        ))
+    }
+
+    /// Look up a batch
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    /// * `CorruptBatch` - If the stored batch cannot be decoded
+    pub fn batch(&self, id: i64) -> Result<Option<SettlementBatch>, QueueError> {
+        Ok(self
+            .select_batches(&format!(
+                "SELECT {BATCH_COLUMNS} FROM settlement_batches WHERE id = {id}"
+            ))?
+            .pop())
+    }
+
+    /// Every batch, oldest first
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    /// * `CorruptBatch` - If a stored batch cannot be decoded
+    pub fn batches(&self) -> Result<Vec<SettlementBatch>, QueueError> {
+        self.select_batches(&format!(
+            "SELECT {BATCH_COLUMNS} FROM settlement_batches ORDER BY id"
+        ))
+    }
+
+    /// Batches assembled but not settled yet, oldest first
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    /// * `CorruptBatch` - If a stored batch cannot be decoded
+    pub fn pending_batches(&self) -> Result<Vec<SettlementBatch>, QueueError> {
+        self.select_batches(&format!(
+            "SELECT {BATCH_COLUMNS} FROM settlement_batches WHERE state = 'pending' ORDER BY id"
+        ))
+    }
+
+    /// Number of unfinished jobs
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    pub fn depth(&self) -> Result<usize, QueueError> {
+        let conn = self.conn.lock().unwrap();
+        let depth: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM settlement_jobs WHERE state IN ('queued', 'created')",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(depth as usize)
+    }
+
+    /// `(escrow_id, nonce)` pairs of every job, and of the settlements
+    /// taking none marked used by [`SettlementQueue::use_nonce`]
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    pub fn nonces(&self) -> Result<Vec<(u64, u64)>, QueueError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT escrow_id, nonce FROM settlement_jobs
+             UNION SELECT escrow_id, nonce FROM used_nonces",
+        )?;
+        let nonces = statement
+            .query_map([], |row| Ok((from_sql(row.get(0)?), from_sql(row.get(1)?))))?
+            .collect::<Result<_, _>>()?;
+        Ok(nonces)
+    }
+
+    /// Mark the nonce of a settlement taking no job used, e.g. one settling
+    /// zero
+    ///
+    /// # Returns
+    /// * False if the nonce was already marked used
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the nonce cannot be written
+    pub fn use_nonce(&self, escrow_id: u64, nonce: u64) -> Result<bool, QueueError> {
+        let conn = self.conn.lock().unwrap();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO used_nonces (escrow_id, nonce, used_at) VALUES (?1, ?2, ?3)",
+            params![to_sql(escrow_id), to_sql(nonce), to_sql(now_millis())],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Mark the transaction of a direct payment used
+    ///
+    /// # Returns
+    /// * False if the transaction was already marked used
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the transaction cannot be written
+    pub fn use_transaction(&self, tx_hash: &str) -> Result<bool, QueueError> {
+        let conn = self.conn.lock().unwrap();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO used_transactions (tx_hash, used_at) VALUES (?1, ?2)",
+            params![tx_hash, to_sql(now_millis())],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Hashes of the direct payment transactions marked used
+    ///
+    /// # Errors
+    /// * `Sqlite` - If the query fails
+    pub fn used_transactions(&self) -> Result<Vec<String>, QueueError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT tx_hash FROM used_transactions")?;
+        let hashes = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(hashes)
+    }
+
+    /// Write the job's progress
+    pub(crate) fn save(&self, job: &SettlementJob) -> Result<(), QueueError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE settlement_jobs SET state = ?2, pending_envelope = ?3, pending_hash = ?4,
+                payment_id = ?5, tx_hash = ?6, attempts = ?7, next_attempt_at = ?8, error = ?9
+             WHERE id = ?1",
+            params![
+                job.id,
+                job.state.as_str(),
+                job.pending_tx.as_ref().map(|tx| &tx.envelope),
+                job.pending_tx.as_ref().map(|tx| &tx.hash),
+                job.payment_id.map(to_sql),
+                job.tx_hash,
+                job.attempts,
+                to_sql(job.next_attempt_at),
+                job.error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Whether a created job is left to a batch
+    pub(crate) fn awaits_batch(&self, job: &SettlementJob) -> Result<bool, QueueError> {
+        if self.batching.is_none() || job.state != JobState::Created {
+            return Ok(false);
+        }
+        match job.batch_id {
+            Some(id) => Ok(self
+                .batch(id)?
+                .is_some_and(|batch| batch.state != BatchState::Failed)),
+            None => Ok(true),
+        }
+    }
+
+    /// Group created jobs in a new pending batch
+    pub(crate) fn create_batch(
+        &self,
+        jobs: &[SettlementJob],
+    ) -> Result<SettlementBatch, QueueError> {
+        let id = {
+            let mut conn = self.conn.lock().unwrap();
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO settlement_batches (state, created_at) VALUES (?1, ?2)",
+                params![BatchState::Pending.as_str(), to_sql(now_millis())],
+            )?;
+            let id = tx.last_insert_rowid();
+            for job in jobs {
+                tx.execute(
+                    "UPDATE settlement_jobs SET batch_id = ?2 WHERE id = ?1",
+                    params![job.id, id],
+                )?;
+            }
+            tx.commit()?;
+            id
+        };
+        self.batch(id)?.ok_or(QueueError::CorruptBatch(id))
+    }
+
+    /// Write the batch's progress, and settle its jobs once it settled
+    pub(crate) fn save_batch(&self, batch: &SettlementBatch) -> Result<(), QueueError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE settlement_batches SET state = ?2, pending_envelope = ?3, pending_hash = ?4,
+                tx_hash = ?5, error = ?6
+             WHERE id = ?1",
+            params![
+                batch.id,
+                batch.state.as_str(),
+                batch.pending_tx.as_ref().map(|tx| &tx.envelope),
+                batch.pending_tx.as_ref().map(|tx| &tx.hash),
+                batch.tx_hash,
+                batch.error,
+            ],
+        )?;
+        if batch.state == BatchState::Settled {
+            tx.execute(
+                "UPDATE settlement_jobs SET state = ?2, tx_hash = ?3, pending_envelope = NULL,
+                    pending_hash = NULL, error = NULL
+                 WHERE batch_id = ?1",
+                params![batch.id, JobState::Settled.as_str(), batch.tx_hash],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Reserve a job for one worker, until the claim is dropped
+    pub(crate) fn claim(&self, id: i64) -> Option<Claim<'_>> {
+        self.claimed
+            .lock()
+            .unwrap()
+            .insert(id)
+            .then_some(Claim { queue: self, id })
+    }
+
+    fn select(&self, sql: &str) -> Result<Vec<SettlementJob>, QueueError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement
+            .query_map([], RawJob::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter().map(RawJob::decode).collect()
+    }
+
+    fn select_batches(&self, sql: &str) -> Result<Vec<SettlementBatch>, QueueError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement
+            .query_map([], RawBatch::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut members = conn.prepare(
+            "SELECT id, payment_id FROM settlement_jobs WHERE batch_id = ?1 ORDER BY id",
+        )?;
+        rows.into_iter()
+            .map(|row| {
+                let jobs = members
+                    .query_map([row.id], |job| {
+                        Ok((job.get::<_, i64>(0)?, job.get::<_, Option<i64>>(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                row.decode(jobs)
+            })
+            .collect()
+    }
+}
+
+/// Job being processed by a worker
+pub(crate) struct Claim<'a> {
+    queue: &'a SettlementQueue,
+    id: i64,
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        self.queue.claimed.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Row of `settlement_jobs` before validation
+struct RawJob {
+    id: i64,
+    escrow_id: i64,
+    nonce: i64,
+    client: String,
+    amount: String,
+    asset: String,
+    state: String,
+    pending_envelope: Option<String>,
+    pending_hash: Option<String>,
+    payment_id: Option<i64>,
+    tx_hash: Option<String>,
+    attempts: u32,
+    next_attempt_at: i64,
+    error: Option<String>,
+    batch_id: Option<i64>,
+    enqueued_at: i64,
+}
+
+impl RawJob {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            escrow_id: row.get(1)?,
+            nonce: row.get(2)?,
+            client: row.get(3)?,
+            amount: row.get(4)?,
+            asset: row.get(5)?,
+            state: row.get(6)?,
+            pending_envelope: row.get(7)?,
+            pending_hash: row.get(8)?,
+            payment_id: row.get(9)?,
+            tx_hash: row.get(10)?,
+            attempts: row.get(11)?,
+            next_attempt_at: row.get(12)?,
+            error: row.get(13)?,
+            batch_id: row.get(14)?,
+            enqueued_at: row.get(15)?,
+        })
+    }
+
+    fn decode(self) -> Result<SettlementJob, QueueError> {
+        let corrupt = || QueueError::Corrupt(self.id);
+        let pending_tx = match (self.pending_envelope, self.pending_hash) {
+            (Some(envelope), Some(hash)) => Some(PreparedTransaction { envelope, hash }),
+            (None, None) => None,
+            _ => return Err(corrupt()),
+        };
+        Ok(SettlementJob {
+            id: self.id,
+            escrow_id: from_sql(self.escrow_id),
+            nonce: from_sql(self.nonce),
+            client: self.client,
+            amount: StellarAmount::from_stroops_str(&self.amount)
+                .map_err(|_| corrupt())?
+                .stroops(),
+            asset: self.asset,
+            state: JobState::parse(&self.state).ok_or_else(corrupt)?,
+            pending_tx,
+            payment_id: self.payment_id.map(from_sql),
+            tx_hash: self.tx_hash,
+            attempts: self.attempts,
+            next_attempt_at: from_sql(self.next_attempt_at),
+            error: self.error,
+            batch_id: self.batch_id,
+            enqueued_at: from_sql(self.enqueued_at),
+        })
+    }
+}
+
+/// Row of `settlement_batches` before validation
+struct RawBatch {
+    id: i64,
+    state: String,
+    pending_envelope: Option<String>,
+    pending_hash: Option<String>,
+    tx_hash: Option<String>,
+    created_at: i64,
+    error: Option<String>,
+}
+
+impl RawBatch {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            state: row.get(1)?,
+            pending_envelope: row.get(2)?,
+            pending_hash: row.get(3)?,
+            tx_hash: row.get(4)?,
+            created_at: row.get(5)?,
+            error: row.get(6)?,
+        })
+    }
+
+    /// Decode the batch and its `(job_id, payment_id)` members
+    fn decode(self, jobs: Vec<(i64, Option<i64>)>) -> Result<SettlementBatch, QueueError> {
+        let corrupt = || QueueError::CorruptBatch(self.id);
+        let pending_tx = match (self.pending_envelope, self.pending_hash) {
+            (Some(envelope), Some(hash)) => Some(PreparedTransaction { envelope, hash }),
+            (None, None) => None,
+            _ => return Err(corrupt()),
+        };
+        let (job_ids, payment_ids) = jobs
+            .into_iter()
+            .map(|(job_id, payment_id)| Some((job_id, from_sql(payment_id?))))
+            .collect::<Option<(Vec<_>, Vec<_>)>>()
+            .ok_or_else(corrupt)?;
+        Ok(SettlementBatch {
+            id: self.id,
+            state: BatchState::parse(&self.state).ok_or_else(corrupt)?,
+            job_ids,
+            payment_ids,
+            pending_tx,
+            tx_hash: self.tx_hash,
+            created_at: from_sql(self.created_at),
+            error: self.error,
+        })
+    }
+}
+
+// SQLite integers are signed, u64 values are stored bit for bit
+fn to_sql(value: u64) -> i64 {
+    value as i64
+}
+
+fn from_sql(value: i64) -> u64 {
+    value as u64
+}
+
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
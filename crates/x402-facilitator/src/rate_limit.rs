@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    redis::{RedisClient, Reply},
+    StoreError,
+};
+
+/// Key prefix of the token buckets kept in Redis
+pub const DEFAULT_RATE_LIMIT_PREFIX: &str = "x402:rate:";
+
+/// Token bucket refilled at a steady rate
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    /// Requests allowed in a burst, the bucket capacity
+    pub burst: u32,
+    /// Tokens added per minute
+    pub per_minute: u32,
+}
+
+impl RateLimit {
+    /// Time to refill one token
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(60) / self.per_minute.max(1)
+    }
+}
+
+/// Token buckets of the clients of a facilitator
+///
+/// /verify, /settle, and /preflight take a token from the bucket of the
+/// paying client, so a single client cannot exhaust the facilitator's RPC
+/// quota.
+#[async_trait]
+pub trait RateLimiter: Send + Sync {
+    /// Take a token from the bucket `key`
+    ///
+    /// # Arguments
+    /// * `key` - Bucket, created full if new
+    /// * `limit` - Capacity and refill rate of the bucket
+    ///
+    /// # Returns
+    /// * None if a token was taken, the time until one is available otherwise
+    ///
+    /// # Errors
+    /// * If the limiter state cannot be reached
+    async fn try_acquire(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+    ) -> Result<Option<Duration>, StoreError>;
+}
+
+/// Bucket of [`MemoryRateLimiter`]
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Rate limiter of a single facilitator process
+#[derive(Debug, Default)]
+pub struct MemoryRateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl MemoryRateLimiter {
+    /// Create a limiter with every bucket full
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimiter for MemoryRateLimiter {
+    async fn try_acquire(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+    ) -> Result<Option<Duration>, StoreError> {
+        let now = Instant::now();
+        let interval = limit.interval().as_secs_f64();
+        let capacity = f64::from(limit.burst);
+        let mut buckets = self.buckets.lock().unwrap();
+        // Buckets refilled to capacity are the same as new ones
+        buckets.retain(|_, bucket| {
+            bucket.tokens + (now - bucket.updated).as_secs_f64() / interval < capacity
+        });
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens =
+            (bucket.tokens + (now - bucket.updated).as_secs_f64() / interval).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(None)
+        } else {
+            Ok(Some(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) * interval,
+            )))
+        }
+    }
+}
+
+/// Token bucket update run atomically by Redis
+///
+/// Takes `capacity` and the refill interval in milliseconds, and returns 0
+/// if a token was taken, the milliseconds until one is available otherwise.
+/// Time is read from the Redis server, so replicas need not agree on it.
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local interval = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = time[1] * 1000 + math.floor(time[2] / 1000)
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+local tokens = tonumber(bucket[1]) or capacity
+local updated = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - updated) / interval)
+local wait = 0
+if tokens >= 1 then
+  tokens = tokens - 1
+else
+  wait = math.ceil((1 - tokens) * interval)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity * interval))
+return wait
+"#;
+
+/// Rate limiter in Redis, shared by facilitator replicas
+///
+/// Each bucket is a hash updated by a Lua script, and expires once it would
+/// be full again.
+pub struct RedisRateLimiter {
+    redis: RedisClient,
+    prefix: String,
+}
+
+impl RedisRateLimiter {
+    /// Create a limiter connecting lazily to `url`
+    ///
+    /// # Arguments
+    /// * `url` - `redis://[[username]:password@]host[:port][/database]`
+    ///
+    /// # Errors
+    /// * `InvalidUrl` - If the URL is malformed
+    pub fn new(url: &str) -> Result<Self, StoreError> {
+        Ok(Self {
+            redis: RedisClient::new(url)?,
+            prefix: DEFAULT_RATE_LIMIT_PREFIX.into(),
+        })
+    }
+
+    /// Prefix keys with `prefix` rather than [`DEFAULT_RATE_LIMIT_PREFIX`]
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+}
+
+#[async_trait]
+impl RateLimiter for RedisRateLimiter {
+    async fn try_acquire(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+    ) -> Result<Option<Duration>, StoreError> {
+        let key = format!("{}{key}", self.prefix);
+        let interval = (limit.interval().as_millis() as u64).max(1);
+        let reply = self
+            .redis
+            .query(&[
+                "EVAL",
+                TOKEN_BUCKET_SCRIPT,
+                "1",
+                &key,
+                &limit.burst.to_string(),
+                &interval.to_string(),
+            ])
+            .await?;
+        match reply {
+            Reply::Integer(0) => Ok(None),
+            Reply::Integer(wait) if wait > 0 => Ok(Some(Duration::from_millis(wait as u64))),
+            other => Err(other.unexpected()),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufStream},
+    net::TcpStream,
+};
+
+/// Longest a Redis command may take, connecting included
+pub const REDIS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Error reaching the state shared by facilitator replicas
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("invalid Redis URL {0}")]
+    InvalidUrl(String),
+    #[error("Redis I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Redis error: {0}")]
+    Redis(String),
+    #[error("Redis did not answer within {0:?}")]
+    Timeout(Duration),
+}
+
+/// Minimal RESP client of the Redis-backed stores
+///
+/// A single connection is kept, and reopened after any error.
+pub(crate) struct RedisClient {
+    address: String,
+    username: Option<String>,
+    password: Option<String>,
+    database: Option<u32>,
+    connection: tokio::sync::Mutex<Option<BufStream<TcpStream>>>,
+}
+
+impl RedisClient {
+    /// Create a client connecting lazily to `url`
+    ///
+    /// # Arguments
+    /// * `url` - `redis://[[username]:password@]host[:port][/database]`
+    ///
+    /// # Errors
+    /// * `InvalidUrl` - If the URL is malformed
+    pub(crate) fn new(url: &str) -> Result<Self, StoreError> {
+        let invalid = || StoreError::InvalidUrl(url.into());
+        let rest = url.strip_prefix("redis://").ok_or_else(invalid)?;
+        let (authority, database) = match rest.split_once('/') {
+            Some((authority, "")) => (authority, None),
+            Some((authority, database)) => {
+                (authority, Some(database.parse().map_err(|_| invalid())?))
+            }
+            None => (rest, None),
+        };
+        let (credentials, host) = match authority.rsplit_once('@') {
+            Some((credentials, host)) => (Some(credentials), host),
+            None => (None, authority),
+        };
+        let (username, password) = match credentials.map(|c| c.split_once(':')) {
+            Some(Some((username, password))) => (
+                Some(username).filter(|u| !u.is_empty()).map(String::from),
+                Some(password.to_string()),
+            ),
+            Some(None) => return Err(invalid()),
+            None => (None, None),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        let address = match host.rsplit_once(':') {
+            Some(_) => host.to_string(),
+            None => format!("{host}:6379"),
+        };
+        Ok(Self {
+            address,
+            username,
+            password,
+            database,
+            connection: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    /// `host:port` of the Redis server
+    pub(crate) fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Run a command, within [`REDIS_TIMEOUT`]
+    pub(crate) async fn query(&self, args: &[&str]) -> Result<Reply, StoreError> {
+        tokio::time::timeout(REDIS_TIMEOUT, self.query_untimed(args))
+            .await
+            .map_err(|_| StoreError::Timeout(REDIS_TIMEOUT))?
+    }
+
+    async fn query_untimed(&self, args: &[&str]) -> Result<Reply, StoreError> {
+        let mut connection = self.connection.lock().await;
+        // Taken out so a failed or timed out command, which may leave a
+        // reply half read, drops the connection
+        let mut stream = match connection.take() {
+            Some(stream) => stream,
+            None => self.connect().await?,
+        };
+        let reply = command(&mut stream, args).await?;
+        *connection = Some(stream);
+        Ok(reply)
+    }
+
+    async fn connect(&self) -> Result<BufStream<TcpStream>, StoreError> {
+        let mut stream = BufStream::new(TcpStream::connect(&self.address).await?);
+        if let Some(password) = &self.password {
+            let mut auth = vec!["AUTH"];
+            if let Some(username) = &self.username {
+                auth.push(username);
+            }
+            auth.push(password);
+            command(&mut stream, &auth).await?.expect_ok()?;
+        }
+        if let Some(database) = self.database {
+            command(&mut stream, &["SELECT", &database.to_string()])
+                .await?
+                .expect_ok()?;
+        }
+        Ok(stream)
+    }
+}
+
+/// Redis reply to the commands sent, which answer a status, an integer, or
+/// null
+#[derive(Debug)]
+pub(crate) enum Reply {
+    Status(String),
+    Integer(i64),
+    Nil,
+}
+
+impl Reply {
+    pub(crate) fn expect_ok(self) -> Result<(), StoreError> {
+        match self {
+            Self::Status(status) if status == "OK" => Ok(()),
+            other => Err(other.unexpected()),
+        }
+    }
+
+    pub(crate) fn unexpected(&self) -> StoreError {
+        StoreError::Redis(format!("unexpected reply {self:?}"))
+    }
+}
+
+/// Send a command and read its reply
+async fn command(stream: &mut BufStream<TcpStream>, args: &[&str]) -> Result<Reply, StoreError> {
+    let mut request = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        request.extend_from_slice(arg.as_bytes());
+        request.extend_from_slice(b"\r\n");
+    }
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+
+    let line = read_line(stream).await?;
+    let (kind, value) = line.split_at(1.min(line.len()));
+    match kind {
+        "+" => Ok(Reply::Status(value.into())),
+        "-" => Err(StoreError::Redis(value.into())),
+        ":" => value
+            .parse()
+            .map(Reply::Integer)
+            .map_err(|_| StoreError::Redis(format!("invalid integer reply {value}"))),
+        // RESP2 and RESP3 null
+        "_" => Ok(Reply::Nil),
+        "$" if value == "-1" => Ok(Reply::Nil),
+        _ => Err(StoreError::Redis(format!("unexpected reply {line}"))),
+    }
+}
+
+async fn read_line(stream: &mut BufStream<TcpStream>) -> Result<String, StoreError> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Err(StoreError::Redis("connection closed".into()));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    redis::{RedisClient, Reply},
+    StoreError,
+};
+
+/// Key prefix of the nonces reserved in Redis
+pub const DEFAULT_REPLAY_PREFIX: &str = "x402:replay:";
+
+/// Longest a nonce stays reserved in Redis, whatever the payload expiry
+pub const MAX_REPLAY_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Nonces seen by /verify, shared by every facilitator accepting a payment
+///
+/// /verify reserves the `(escrow_id, nonce)` of each valid payment, so a
+/// payload submitted to several resource servers at once verifies only once.
+/// Reservations last until the payload expires.
+#[async_trait]
+pub trait ReplayCache: Send + Sync {
+    /// Reserve a nonce until `expires_at`
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow the payment is made from
+    /// * `nonce` - Client-chosen nonce
+    /// * `expires_at` - Unix timestamp the payload expires at
+    ///
+    /// # Returns
+    /// * False if the nonce is already reserved
+    ///
+    /// # Errors
+    /// * If the cache cannot be reached
+    async fn try_reserve(
+        &self,
+        escrow_id: u64,
+        nonce: u64,
+        expires_at: u64,
+    ) -> Result<bool, StoreError>;
+
+    /// Reserve the transaction of a direct payment until `expires_at`
+    ///
+    /// # Arguments
+    /// * `tx_hash` - Hex-encoded hash of the payment transaction
+    /// * `expires_at` - Unix timestamp the payment stops being accepted at
+    ///
+    /// # Returns
+    /// * False if the transaction is already reserved
+    ///
+    /// # Errors
+    /// * If the cache cannot be reached
+    async fn try_reserve_transaction(
+        &self,
+        tx_hash: &str,
+        expires_at: u64,
+    ) -> Result<bool, StoreError>;
+}
+
+/// Replay cache of a single facilitator process
+#[derive(Debug, Default)]
+pub struct MemoryReplayCache {
+    reserved: Mutex<HashMap<(u64, u64), u64>>,
+    transactions: Mutex<HashMap<String, u64>>,
+}
+
+impl MemoryReplayCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of nonces and transactions reserved, expired ones included
+    /// until the next reservation
+    pub fn len(&self) -> usize {
+        self.reserved.lock().unwrap().len() + self.transactions.lock().unwrap().len()
+    }
+
+    /// Whether nothing is reserved
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl ReplayCache for MemoryReplayCache {
+    async fn try_reserve(
+        &self,
+        escrow_id: u64,
+        nonce: u64,
+        expires_at: u64,
+    ) -> Result<bool, StoreError> {
+        let now = now();
+        let mut reserved = self.reserved.lock().unwrap();
+        reserved.retain(|_, expiry| *expiry > now);
+        if reserved.contains_key(&(escrow_id, nonce)) {
+            return Ok(false);
+        }
+        reserved.insert((escrow_id, nonce), expires_at);
+        Ok(true)
+    }
+
+    async fn try_reserve_transaction(
+        &self,
+        tx_hash: &str,
+        expires_at: u64,
+    ) -> Result<bool, StoreError> {
+        let now = now();
+        let mut transactions = self.transactions.lock().unwrap();
+        transactions.retain(|_, expiry| *expiry > now);
+        if transactions.contains_key(tx_hash) {
+            return Ok(false);
+        }
+        transactions.insert(tx_hash.into(), expires_at);
+        Ok(true)
+    }
+}
+
+/// Replay cache in Redis, shared by facilitator replicas
+///
+/// Nonces and transactions are reserved with `SET key 1 NX EX ttl`, which is atomic across
+/// replicas.
+pub struct RedisReplayCache {
+    redis: RedisClient,
+    prefix: String,
+}
+
+impl RedisReplayCache {
+    /// Create a cache connecting lazily to `url`
+    ///
+    /// # Arguments
+    /// * `url` - `redis://[[username]:password@]host[:port][/database]`
+    ///
+    /// # Errors
+    /// * `InvalidUrl` - If the URL is malformed
+    pub fn new(url: &str) -> Result<Self, StoreError> {
+        Ok(Self {
+            redis: RedisClient::new(url)?,
+            prefix: DEFAULT_REPLAY_PREFIX.into(),
+        })
+    }
+
+    /// Prefix keys with `prefix` rather than [`DEFAULT_REPLAY_PREFIX`]
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// `host:port` of the Redis server
+    pub fn address(&self) -> &str {
+        self.redis.address()
+    }
+
+    /// Set `key` unless it exists, expiring at `expires_at`
+    async fn set_nx(&self, key: String, expires_at: u64) -> Result<bool, StoreError> {
+        let ttl = expires_at
+            .saturating_sub(now())
+            .clamp(1, MAX_REPLAY_TTL.as_secs());
+        let reply = self
+            .redis
+            .query(&["SET", &key, "1", "NX", "EX", &ttl.to_string()])
+            .await?;
+        match reply {
+            Reply::Status(status) if status == "OK" => Ok(true),
+            Reply::Nil => Ok(false),
+            other => Err(other.unexpected()),
+        }
+    }
+}
+
+#[async_trait]
+impl ReplayCache for RedisReplayCache {
+    async fn try_reserve(
+        &self,
+        escrow_id: u64,
+        nonce: u64,
+        expires_at: u64,
+    ) -> Result<bool, StoreError> {
+        self.set_nx(format!("{}{escrow_id}:{nonce}", self.prefix), expires_at)
+            .await
+    }
+
+    async fn try_reserve_transaction(
+        &self,
+        tx_hash: &str,
+        expires_at: u64,
+    ) -> Result<bool, StoreError> {
+        self.set_nx(format!("{}tx:{tx_hash}", self.prefix), expires_at)
+            .await
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,510 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{ConnectInfo, Extension, Path, Query, Request, State},
+    http::{
+        header::{HeaderName, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER},
+        StatusCode,
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use x402_types::{SettleRequest, SupportedResponse, VerifyRequest};
+
+use crate::{
+    openapi_spec, service, AdminError, Facilitator, FailedDelivery, JobState, JobSummary,
+    PaymentRecord, Reconciliation, Rejection, Reply, TenantConfig, TenantError, TenantSummary,
+    Tenants, VolumeEntry, Webhooks, METRICS_CONTENT_TYPE, OPENAPI_PATH,
+};
+
+/// Build the facilitator HTTP router
+///
+/// Rate limits fall back to the peer address for requests whose payment
+/// header does not decode, when served with
+/// `into_make_service_with_connect_info::<SocketAddr>()`.
+pub fn router(facilitator: Arc<Facilitator>) -> Router {
+    endpoints()
+        .route("/readyz", get(readyz))
+        .layer(Extension(facilitator))
+        .merge(liveness())
+        .merge(docs())
+}
+
+/// Build the HTTP router of a multi-tenant facilitator
+///
+/// Requests must carry `Authorization: Bearer <api key>`, and are handled by
+/// the facilitator of the tenant with that API key. The endpoints are those
+/// of [`router`], /metrics showing only the tenant's metrics. /healthz is
+/// served without an API key, but not /readyz, each tenant depending on
+/// its own signer.
+pub fn tenant_router(tenants: Arc<Tenants>) -> Router {
+    endpoints()
+        .route_layer(middleware::from_fn(move |request, next| {
+            route_tenant(tenants.clone(), request, next)
+        }))
+        .merge(liveness())
+        .merge(docs())
+}
+
+fn endpoints() -> Router {
+    let router = Router::new()
+        .route("/verify", post(verify))
+        .route("/settle", post(settle))
+        .route("/preflight", post(preflight))
+        .route("/supported", get(supported))
+        .route("/metrics", get(metrics));
+    #[cfg(feature = "grpc")]
+    let router = router.merge(crate::grpc::endpoints());
+    router
+}
+
+/// Liveness probe, served without authentication
+fn liveness() -> Router {
+    Router::new().route(
+        "/healthz",
+        get(|| async { Json(json!({ "status": "ok" })) }),
+    )
+}
+
+/// API description, served without authentication
+fn docs() -> Router {
+    let router = Router::new().route(OPENAPI_PATH, get(|| async { Json(openapi_spec()) }));
+    #[cfg(feature = "swagger-ui")]
+    let router = router.route(
+        "/docs",
+        get(|| async { axum::response::Html(crate::SWAGGER_UI_HTML) }),
+    );
+    router
+}
+
+async fn route_tenant(tenants: Arc<Tenants>, mut request: Request, next: Next) -> Response {
+    match bearer(&request).and_then(|api_key| tenants.by_api_key(api_key)) {
+        Some(facilitator) => {
+            request.extensions_mut().insert(facilitator);
+            next.run(request).await
+        }
+        None => unauthorized(),
+    }
+}
+
+/// Request peer, set on connections accepted with connect info
+pub(crate) type Peer = Option<Extension<ConnectInfo<SocketAddr>>>;
+
+/// Replayed nonces are answered 409, rate limited clients 429, other
+/// outcomes 200
+async fn verify(
+    Extension(facilitator): Extension<Arc<Facilitator>>,
+    peer: Peer,
+    Json(request): Json<VerifyRequest>,
+) -> Response {
+    let source = peer.map(|Extension(ConnectInfo(addr))| addr.ip());
+    reply(service::verify(&facilitator, &request, source).await)
+}
+
+async fn settle(
+    Extension(facilitator): Extension<Arc<Facilitator>>,
+    peer: Peer,
+    Json(request): Json<SettleRequest>,
+) -> Response {
+    let source = peer.map(|Extension(ConnectInfo(addr))| addr.ip());
+    reply(service::settle(&facilitator, &request, source).await)
+}
+
+/// Rate limited clients are answered 429, other outcomes 200
+async fn preflight(
+    Extension(facilitator): Extension<Arc<Facilitator>>,
+    peer: Peer,
+    Json(request): Json<VerifyRequest>,
+) -> Response {
+    let source = peer.map(|Extension(ConnectInfo(addr))| addr.ip());
+    reply(service::preflight(&facilitator, &request, source).await)
+}
+
+fn reply<T: Serialize>(reply: Reply<T>) -> Response {
+    let body = Json(reply.response);
+    match reply.rejection {
+        None => body.into_response(),
+        Some(Rejection::Replayed) => (StatusCode::CONFLICT, body).into_response(),
+        Some(Rejection::RateLimited(retry_after)) => too_many_requests(retry_after, body),
+        Some(Rejection::ShuttingDown) => (StatusCode::SERVICE_UNAVAILABLE, body).into_response(),
+    }
+}
+
+/// 429 with `Retry-After`
+fn too_many_requests(retry_after: Duration, body: impl IntoResponse) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(RETRY_AFTER, retry_after_secs(retry_after).to_string())],
+        body,
+    )
+        .into_response()
+}
+
+/// `Retry-After` value of a delay, in whole seconds rounded up
+pub(crate) fn retry_after_secs(retry_after: Duration) -> u64 {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    seconds.max(1)
+}
+
+async fn supported(
+    Extension(facilitator): Extension<Arc<Facilitator>>,
+) -> Result<Json<SupportedResponse>, (StatusCode, String)> {
+    facilitator
+        .supported()
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))
+}
+
+/// 200 when every dependency check passes, 503 otherwise, with the outcome
+/// of each check
+async fn readyz(Extension(facilitator): Extension<Arc<Facilitator>>) -> Response {
+    let readiness = facilitator.readiness().await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness)).into_response()
+}
+
+async fn metrics(
+    Extension(facilitator): Extension<Arc<Facilitator>>,
+) -> ([(HeaderName, &'static str); 1], String) {
+    (
+        [(CONTENT_TYPE, METRICS_CONTENT_TYPE)],
+        facilitator.metrics().render(),
+    )
+}
+
+/// Build the admin router for dead-lettered webhook deliveries
+///
+/// Requests must carry `Authorization: Bearer <token>`.
+///
+/// # Endpoints
+/// * `GET /admin/webhooks/failed` - List dead-lettered deliveries
+/// * `POST /admin/webhooks/failed/{id}/replay` - Retry a delivery
+pub fn admin_router(webhooks: Arc<Webhooks>, token: impl Into<String>) -> Router {
+    let token: Arc<str> = token.into().into();
+    Router::new()
+        .route("/admin/webhooks/failed", get(failed_deliveries))
+        .route("/admin/webhooks/failed/{id}/replay", post(replay_delivery))
+        .route_layer(middleware::from_fn(move |request, next| {
+            authorize(token.clone(), request, next)
+        }))
+        .with_state(webhooks)
+}
+
+/// Build the admin router for on-call control of a facilitator
+///
+/// Requests must carry `Authorization: Bearer <token>`, the admin token
+/// rather than a tenant API key. Job endpoints require a settlement queue,
+/// and answer 404 without one.
+///
+/// # Endpoints
+/// * `GET /admin/jobs` - List unfinished settlement jobs, or those in
+///   `?state=queued|created|settled|failed`
+/// * `GET /admin/jobs/{id}` - Look up a job
+/// * `POST /admin/jobs/{id}/retry` - Attempt a job now, resuming a failed one
+/// * `POST /admin/jobs/{id}/cancel` - Fail a job no transaction is in flight
+///   for, answered 409 otherwise
+/// * `GET /admin/payments?escrowId=N` - On-chain payments of an escrow
+/// * `GET /admin/payments?txHash=H` - Payments settled by a transaction
+/// * `GET /admin/payments/{id}` - Look up an on-chain payment
+/// * `GET /admin/volume` - Amount settled by asset
+/// * `POST /admin/reconcile` - Compare the queue with on-chain payments,
+///   repairing jobs settled on-chain
+pub fn operations_router(facilitator: Arc<Facilitator>, token: impl Into<String>) -> Router {
+    let token: Arc<str> = token.into().into();
+    Router::new()
+        .route("/admin/jobs", get(list_jobs))
+        .route("/admin/jobs/{id}", get(get_job))
+        .route("/admin/jobs/{id}/retry", post(retry_job))
+        .route("/admin/jobs/{id}/cancel", post(cancel_job))
+        .route("/admin/payments", get(find_payments))
+        .route("/admin/payments/{id}", get(get_payment))
+        .route("/admin/volume", get(volume))
+        .route("/admin/reconcile", post(reconcile))
+        .route_layer(middleware::from_fn(move |request, next| {
+            authorize(token.clone(), request, next)
+        }))
+        .with_state(facilitator)
+}
+
+/// Build the admin router managing the tenants of a multi-tenant facilitator
+///
+/// Requests must carry `Authorization: Bearer <token>`. Changes take effect
+/// for the next request, and are saved to the tenants file if configured.
+///
+/// # Endpoints
+/// * `GET /admin/tenants` - List tenants, without their secrets
+/// * `PUT /admin/tenants/{id}` - Add or replace a tenant from a
+///   [`TenantConfig`], whose `id` is taken from the path
+/// * `DELETE /admin/tenants/{id}` - Remove a tenant
+/// * `PUT /admin/tenants/{id}/next-key` - Stage the key replacing a
+///   tenant's signing key, see [`Tenants::stage_key`]
+/// * `POST /admin/tenants/{id}/next-key/promote` - Sign with the staged
+///   key from now on, see [`Tenants::promote_key`]
+/// * `GET /admin/volume` - Amount settled by tenant and asset
+pub fn tenant_admin_router(tenants: Arc<Tenants>, token: impl Into<String>) -> Router {
+    let token: Arc<str> = token.into().into();
+    Router::new()
+        .route("/admin/tenants", get(list_tenants))
+        .route("/admin/tenants/{id}", put(put_tenant).delete(delete_tenant))
+        .route("/admin/tenants/{id}/next-key", put(stage_key))
+        .route("/admin/tenants/{id}/next-key/promote", post(promote_key))
+        .route("/admin/volume", get(tenant_volume))
+        .route_layer(middleware::from_fn(move |request, next| {
+            authorize(token.clone(), request, next)
+        }))
+        .with_state(tenants)
+}
+
+async fn authorize(token: Arc<str>, request: Request, next: Next) -> Response {
+    if bearer(&request).is_some_and(|value| value == &*token) {
+        next.run(request).await
+    } else {
+        unauthorized()
+    }
+}
+
+fn bearer(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+fn unauthorized() -> Response {
+    let mut response = Response::new(Default::default());
+    *response.status_mut() = StatusCode::UNAUTHORIZED;
+    response
+}
+
+async fn list_tenants(State(tenants): State<Arc<Tenants>>) -> Json<Vec<TenantSummary>> {
+    Json(
+        tenants
+            .configs()
+            .iter()
+            .map(TenantConfig::summary)
+            .collect(),
+    )
+}
+
+async fn put_tenant(
+    State(tenants): State<Arc<Tenants>>,
+    Path(id): Path<String>,
+    Json(mut config): Json<TenantConfig>,
+) -> Result<(StatusCode, Json<TenantSummary>), (StatusCode, String)> {
+    config.id = id;
+    let summary = config.summary();
+    match tenants.upsert(config) {
+        Ok(true) => Ok((StatusCode::OK, Json(summary))),
+        Ok(false) => Ok((StatusCode::CREATED, Json(summary))),
+        Err(e @ TenantError::DuplicateApiKey(_)) => Err((StatusCode::CONFLICT, e.to_string())),
+        Err(e @ (TenantError::Invalid { .. } | TenantError::KeyMismatch { .. })) => {
+            Err((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+        }
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+async fn delete_tenant(
+    State(tenants): State<Arc<Tenants>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    match tenants.remove(&id) {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Ok(StatusCode::NOT_FOUND),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NextKey {
+    signing_key: String,
+}
+
+async fn stage_key(
+    State(tenants): State<Arc<Tenants>>,
+    Path(id): Path<String>,
+    Json(next): Json<NextKey>,
+) -> Result<Json<TenantSummary>, (StatusCode, String)> {
+    match tenants.stage_key(&id, next.signing_key) {
+        Ok(Some(summary)) => Ok(Json(summary)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, format!("tenant {id} not found"))),
+        Err(e @ TenantError::Invalid { .. }) => {
+            Err((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+        }
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+async fn promote_key(
+    State(tenants): State<Arc<Tenants>>,
+    Path(id): Path<String>,
+) -> Result<Json<TenantSummary>, (StatusCode, String)> {
+    match tenants.promote_key(&id) {
+        Ok(Some(summary)) => Ok(Json(summary)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, format!("tenant {id} not found"))),
+        Err(e @ TenantError::NoStagedKey(_)) => Err((StatusCode::CONFLICT, e.to_string())),
+        Err(e @ TenantError::Invalid { .. }) => {
+            Err((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+        }
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+async fn failed_deliveries(State(webhooks): State<Arc<Webhooks>>) -> Json<Vec<FailedDelivery>> {
+    Json(webhooks.failed())
+}
+
+async fn replay_delivery(
+    State(webhooks): State<Arc<Webhooks>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, Json<FailedDelivery>)> {
+    match webhooks.replay(&id).await {
+        None => Ok(StatusCode::NOT_FOUND),
+        Some(Ok(())) => Ok(StatusCode::NO_CONTENT),
+        Some(Err(delivery)) => Err((StatusCode::BAD_GATEWAY, Json(delivery))),
+    }
+}
+
+/// Admin error as a status and message
+type AdminFailure = (StatusCode, String);
+
+impl From<AdminError> for AdminFailure {
+    fn from(e: AdminError) -> Self {
+        let status = match &e {
+            AdminError::NoQueue | AdminError::JobNotFound(_) => StatusCode::NOT_FOUND,
+            AdminError::JobBusy(_) | AdminError::JobSettled(_) | AdminError::JobInFlight(_) => {
+                StatusCode::CONFLICT
+            }
+            AdminError::WrongServer(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AdminError::Queue(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AdminError::Client(_) => StatusCode::BAD_GATEWAY,
+        };
+        (status, e.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+struct JobFilter {
+    state: Option<String>,
+}
+
+async fn list_jobs(
+    State(facilitator): State<Arc<Facilitator>>,
+    Query(filter): Query<JobFilter>,
+) -> Result<Json<Vec<JobSummary>>, AdminFailure> {
+    let state = match filter.state.as_deref() {
+        Some(state) => Some(JobState::parse(state).ok_or((
+            StatusCode::BAD_REQUEST,
+            format!("unknown job state {state}"),
+        ))?),
+        None => None,
+    };
+    let jobs = facilitator.list_jobs(state)?;
+    Ok(Json(jobs.iter().map(JobSummary::from).collect()))
+}
+
+async fn get_job(
+    State(facilitator): State<Arc<Facilitator>>,
+    Path(id): Path<i64>,
+) -> Result<Json<JobSummary>, AdminFailure> {
+    let queue = facilitator.queue().ok_or(AdminError::NoQueue)?;
+    let job = queue
+        .job(id)
+        .map_err(AdminError::from)?
+        .ok_or(AdminError::JobNotFound(id))?;
+    Ok(Json(JobSummary::from(&job)))
+}
+
+async fn retry_job(
+    State(facilitator): State<Arc<Facilitator>>,
+    Path(id): Path<i64>,
+) -> Result<Json<JobSummary>, AdminFailure> {
+    let job = facilitator.retry_job(id).await?;
+    Ok(Json(JobSummary::from(&job)))
+}
+
+async fn cancel_job(
+    State(facilitator): State<Arc<Facilitator>>,
+    Path(id): Path<i64>,
+) -> Result<Json<JobSummary>, AdminFailure> {
+    let job = facilitator.cancel_job(id)?;
+    Ok(Json(JobSummary::from(&job)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PaymentFilter {
+    escrow_id: Option<u64>,
+    tx_hash: Option<String>,
+}
+
+async fn find_payments(
+    State(facilitator): State<Arc<Facilitator>>,
+    Query(filter): Query<PaymentFilter>,
+) -> Result<Json<Vec<PaymentRecord>>, AdminFailure> {
+    let payments = match (filter.escrow_id, filter.tx_hash) {
+        (Some(escrow_id), None) => facilitator.escrow_payments(escrow_id).await?,
+        (None, Some(tx_hash)) => facilitator.transaction_payments(&tx_hash).await?,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "expected one of escrowId or txHash".into(),
+            ))
+        }
+    };
+    Ok(Json(payments))
+}
+
+async fn get_payment(
+    State(facilitator): State<Arc<Facilitator>>,
+    Path(id): Path<u64>,
+) -> Result<Json<PaymentRecord>, AdminFailure> {
+    match facilitator.payment_record(id).await? {
+        Some(payment) => Ok(Json(payment)),
+        None => Err((StatusCode::NOT_FOUND, format!("payment {id} not found"))),
+    }
+}
+
+async fn volume(State(facilitator): State<Arc<Facilitator>>) -> Json<Vec<VolumeEntry>> {
+    Json(volume_entries(None, &facilitator))
+}
+
+async fn tenant_volume(State(tenants): State<Arc<Tenants>>) -> Json<Vec<VolumeEntry>> {
+    let mut entries = vec![];
+    for config in tenants.configs() {
+        if let Some(facilitator) = tenants.get(&config.id) {
+            entries.extend(volume_entries(Some(&config.id), &facilitator));
+        }
+    }
+    Json(entries)
+}
+
+fn volume_entries(tenant: Option<&str>, facilitator: &Facilitator) -> Vec<VolumeEntry> {
+    facilitator
+        .metrics()
+        .settled_volume()
+        .into_iter()
+        .map(|(asset, amount)| VolumeEntry {
+            tenant: tenant.map(String::from),
+            asset,
+            amount: amount.to_string(),
+        })
+        .collect()
+}
+
+async fn reconcile(
+    State(facilitator): State<Arc<Facilitator>>,
+) -> Result<Json<Reconciliation>, AdminFailure> {
+    Ok(Json(facilitator.reconcile().await?))
+}
@@ -0,0 +1,152 @@
+use std::{net::IpAddr, time::Duration};
+
+use x402_errors::{PolicyError, ProtocolError, X402Error};
+use x402_types::{PreflightResponse, SettleRequest, SettleResponse, VerifyRequest, VerifyResponse};
+
+use crate::{Facilitator, VerifyError, SHUTTING_DOWN};
+
+/// Reason reported for requests rejected by the rate limiter
+pub const RATE_LIMITED: &str = "rate_limited";
+
+/// Why a request was turned down, beyond its response
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// The payload's nonce or transaction was already verified
+    Replayed,
+    /// The client is rate limited, and may retry after the delay
+    RateLimited(Duration),
+    /// The facilitator is shutting down, see [`crate::SHUTTING_DOWN`]
+    ShuttingDown,
+}
+
+impl From<Rejection> for X402Error {
+    fn from(rejection: Rejection) -> Self {
+        match rejection {
+            Rejection::Replayed => ProtocolError::NonceReplayed.into(),
+            Rejection::RateLimited(retry_after) => PolicyError::RateLimited {
+                retry_after: Some(retry_after),
+            }
+            .into(),
+            Rejection::ShuttingDown => PolicyError::ShuttingDown.into(),
+        }
+    }
+}
+
+/// Response to a request, whichever transport carried it
+///
+/// HTTP and gRPC handlers both answer through [`verify`] and [`settle`], and
+/// only map rejections to their own status codes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reply<T> {
+    pub response: T,
+    pub rejection: Option<Rejection>,
+}
+
+/// Verify a payment, once the paying client passed the rate limiter
+///
+/// # Arguments
+/// * `facilitator` - Facilitator handling the request
+/// * `request` - Payment to verify
+/// * `source` - IP address the request came from, if known
+pub(crate) async fn verify(
+    facilitator: &Facilitator,
+    request: &VerifyRequest,
+    source: Option<IpAddr>,
+) -> Reply<VerifyResponse> {
+    let limited = facilitator
+        .rate_limit("verify", &request.payment_header, source)
+        .await;
+    if let Some(retry_after) = limited {
+        let rejection = Rejection::RateLimited(retry_after);
+        return Reply {
+            response: VerifyResponse {
+                is_valid: false,
+                invalid_reason: Some(RATE_LIMITED.into()),
+                error_code: Some(X402Error::from(rejection).code()),
+                x402_versions: Vec::new(),
+            },
+            rejection: Some(rejection),
+        };
+    }
+
+    let response = facilitator.verify(request).await;
+    let replayed = response.invalid_reason.as_deref() == Some(VerifyError::NonceReplayed.reason());
+    Reply {
+        response,
+        rejection: replayed.then_some(Rejection::Replayed),
+    }
+}
+
+/// Settle a payment, once the paying client passed the rate limiter
+///
+/// # Arguments
+/// * `facilitator` - Facilitator handling the request
+/// * `request` - Payment to settle
+/// * `source` - IP address the request came from, if known
+pub(crate) async fn settle(
+    facilitator: &Facilitator,
+    request: &SettleRequest,
+    source: Option<IpAddr>,
+) -> Reply<SettleResponse> {
+    let limited = facilitator
+        .rate_limit("settle", &request.payment_header, source)
+        .await;
+    if let Some(retry_after) = limited {
+        let rejection = Rejection::RateLimited(retry_after);
+        return Reply {
+            response: SettleResponse {
+                success: false,
+                error: Some(RATE_LIMITED.into()),
+                error_code: Some(X402Error::from(rejection).code()),
+                tx_hash: None,
+                network_id: Some(facilitator.network().into()),
+                payment_id: None,
+                ledger: None,
+                finality: None,
+                simulation: None,
+            },
+            rejection: Some(rejection),
+        };
+    }
+    let response = facilitator.settle(request).await;
+    let shutting_down = response.error.as_deref() == Some(SHUTTING_DOWN);
+    Reply {
+        response,
+        rejection: shutting_down.then_some(Rejection::ShuttingDown),
+    }
+}
+
+/// Forecast a settlement, once the paying client passed the rate limiter
+///
+/// # Arguments
+/// * `facilitator` - Facilitator handling the request
+/// * `request` - Payment to forecast the settlement of
+/// * `source` - IP address the request came from, if known
+pub(crate) async fn preflight(
+    facilitator: &Facilitator,
+    request: &VerifyRequest,
+    source: Option<IpAddr>,
+) -> Reply<PreflightResponse> {
+    let limited = facilitator
+        .rate_limit("preflight", &request.payment_header, source)
+        .await;
+    if let Some(retry_after) = limited {
+        let rejection = Rejection::RateLimited(retry_after);
+        return Reply {
+            response: PreflightResponse {
+                ok: false,
+                invalid_reason: Some(RATE_LIMITED.into()),
+                error_code: Some(X402Error::from(rejection).code()),
+                estimated_fee: None,
+                available_balance: None,
+                binding: false,
+                cached: false,
+            },
+            rejection: Some(rejection),
+        };
+    }
+    Reply {
+        response: facilitator.preflight(request).await,
+        rejection: None,
+    }
+}
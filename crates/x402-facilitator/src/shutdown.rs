@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::SettlementJob;
+
+/// Reason reported for settlements refused while the facilitator shuts down
+pub const SHUTTING_DOWN: &str = "shutting_down";
+
+/// What a shutdown left for the next facilitator, see
+/// [`crate::Facilitator::shutdown`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShutdownReport {
+    /// Settlements and settlement queue runs still in flight at the deadline
+    pub interrupted: usize,
+    /// Unfinished jobs of the settlement queue, resumed on restart
+    pub unfinished: Vec<SettlementJob>,
+}
+
+impl ShutdownReport {
+    /// Whether everything in flight finished before the deadline
+    pub fn drained(&self) -> bool {
+        self.interrupted == 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct State {
+    draining: bool,
+    in_flight: usize,
+}
+
+/// Settlement work in flight, admitted until the facilitator drains
+pub(crate) struct Drain {
+    state: watch::Sender<State>,
+}
+
+impl Default for Drain {
+    fn default() -> Self {
+        Self {
+            state: watch::channel(State::default()).0,
+        }
+    }
+}
+
+impl Drain {
+    /// Admit work, tracked until the guard is dropped
+    ///
+    /// # Returns
+    /// * None once draining
+    pub fn enter(&self) -> Option<InFlight<'_>> {
+        let admitted = self.state.send_if_modified(|state| {
+            if !state.draining {
+                state.in_flight += 1;
+            }
+            !state.draining
+        });
+        admitted.then_some(InFlight(self))
+    }
+
+    /// Stop admitting work
+    pub fn start(&self) {
+        self.state
+            .send_if_modified(|state| !std::mem::replace(&mut state.draining, true));
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.state.borrow().draining
+    }
+
+    /// Wait until draining starts
+    pub async fn started(&self) {
+        let _ = self
+            .state
+            .subscribe()
+            .wait_for(|state| state.draining)
+            .await;
+    }
+
+    /// Wait up to `grace` for the work in flight
+    ///
+    /// # Returns
+    /// * How much work is still in flight
+    pub async fn wait(&self, grace: Duration) -> usize {
+        let mut state = self.state.subscribe();
+        let _ = tokio::time::timeout(grace, state.wait_for(|state| state.in_flight == 0)).await;
+        self.state.borrow().in_flight
+    }
+}
+
+/// Work admitted by a [`Drain`]
+pub(crate) struct InFlight<'a>(&'a Drain);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.state.send_modify(|state| state.in_flight -= 1);
+    }
+}
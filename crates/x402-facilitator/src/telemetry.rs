@@ -0,0 +1,24 @@
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// Filter of the events logged when `RUST_LOG` is unset
+pub const DEFAULT_LOG_FILTER: &str = "info";
+
+/// Log spans and events as JSON lines on stdout
+///
+/// Events are filtered by `RUST_LOG` (e.g. `x402_facilitator=debug`),
+/// [`DEFAULT_LOG_FILTER`] when unset. Each line carries the fields of the
+/// spans the event happened in, so the `correlation_id` of a payment links
+/// the events of verification, settlement, and the RPC calls they made.
+///
+/// # Errors
+/// * If a global subscriber is already set
+pub fn init_tracing() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    fmt()
+        .json()
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_env_filter(filter)
+        .try_init()
+}
@@ -0,0 +1,507 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use x402_client::{EscrowClient, HttpSigner, LocalSigner, Rpc, Signer};
+use x402_types::FeePolicy;
+
+use crate::{
+    Degradation, DirectPayments, Endpoint, Facilitator, Metrics, RateLimit, RateLimiter,
+    ReadinessPolicy, ReplayCache, Settings, ShutdownReport, Webhooks,
+};
+
+/// Error managing the tenants of a facilitator
+#[derive(Debug, thiserror::Error)]
+pub enum TenantError {
+    #[error("invalid tenant {id}: {message}")]
+    Invalid { id: String, message: String },
+    #[error("tenant {id} signing key does not belong to {server}")]
+    KeyMismatch { id: String, server: String },
+    #[error("API key of tenant {0} is already used by another tenant")]
+    DuplicateApiKey(String),
+    #[error("tenant {0} has no staged signing key")]
+    NoStagedKey(String),
+    #[error("tenants file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("tenants file: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Resource server of a multi-tenant facilitator
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantConfig {
+    /// Unique tenant ID, used in the admin endpoints and metrics
+    ///
+    /// Taken from the path by `PUT /admin/tenants/{id}`.
+    #[serde(default)]
+    pub id: String,
+    /// Bearer token of the tenant's /verify, /settle, /supported, and
+    /// /metrics requests
+    pub api_key: String,
+    /// Payout address (G... format) payments must be made to
+    pub server: String,
+    /// Where the server's settlement key is read from
+    ///
+    /// * `env:NAME` - Secret key (S... format) in an environment variable
+    /// * `file:PATH` - Secret key in a file
+    /// * `http(s)://...` - Remote signer, see [`HttpSigner`]
+    pub signing_key: String,
+    /// Key staged to replace `signing_key`, read the same way, see
+    /// [`Tenants::stage_key`]
+    #[serde(default)]
+    pub next_signing_key: Option<String>,
+    /// Whether `signing_key` was promoted by a key rotation, and may be
+    /// another key added as a signer of the server account
+    #[serde(default)]
+    pub rotated: bool,
+    /// Accepted asset contract addresses (C... format)
+    #[serde(default)]
+    pub assets: Vec<String>,
+    /// Facilitator fee share, in basis points of the settled amount
+    #[serde(default)]
+    pub fee_bps: u32,
+    /// Webhook endpoints notified of the tenant's settlement outcomes
+    #[serde(default)]
+    pub webhooks: Vec<Endpoint>,
+    /// Token bucket of each client of the tenant, unlimited if unset
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    /// Acceptance of direct payments, escrow payments only if unset
+    #[serde(default)]
+    pub direct_payments: Option<DirectPayments>,
+}
+
+impl TenantConfig {
+    /// Settings of the tenant's facilitator
+    pub fn settings(&self) -> Settings {
+        Settings {
+            assets: self.assets.clone(),
+            fee: FeePolicy {
+                fee_bps: self.fee_bps,
+                network_fee_sponsored: true,
+            },
+            rate_limit: self.rate_limit,
+            direct_payments: self.direct_payments,
+            dry_run: false,
+            degradation: Degradation::FailClosed,
+            readiness: ReadinessPolicy::default(),
+            clock_skew: Duration::ZERO,
+            direct_fallback: None,
+        }
+    }
+
+    /// Tenant as listed by the admin endpoints, without its secrets
+    pub fn summary(&self) -> TenantSummary {
+        TenantSummary {
+            id: self.id.clone(),
+            server: self.server.clone(),
+            assets: self.assets.clone(),
+            fee_bps: self.fee_bps,
+            webhooks: self.webhooks.iter().map(|e| e.url.clone()).collect(),
+            rate_limit: self.rate_limit,
+            direct_payments: self.direct_payments,
+            next_key_staged: self.next_signing_key.is_some(),
+        }
+    }
+
+    fn invalid(&self, message: impl Into<String>) -> TenantError {
+        TenantError::Invalid {
+            id: self.id.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Tenant listed by `GET /admin/tenants`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantSummary {
+    pub id: String,
+    pub server: String,
+    pub assets: Vec<String>,
+    pub fee_bps: u32,
+    /// URLs of the webhook endpoints
+    pub webhooks: Vec<String>,
+    pub rate_limit: Option<RateLimit>,
+    pub direct_payments: Option<DirectPayments>,
+    /// Whether a signing key is staged to replace the active one
+    pub next_key_staged: bool,
+}
+
+/// Configured tenant and the facilitator settling its payments
+struct Tenant {
+    config: TenantConfig,
+    facilitator: Arc<Facilitator>,
+}
+
+/// Tenants of a facilitator serving many resource servers
+///
+/// Each tenant gets its own [`Facilitator`], signing with the tenant's key
+/// and accepting only payments to the tenant's server, with its own metrics
+/// and webhooks. Tenants are added, changed, and removed while the service
+/// runs, and saved to the tenants file when one is configured.
+pub struct Tenants {
+    rpc: Rpc,
+    contract_id: String,
+    network: String,
+    network_passphrase: String,
+    tenants: RwLock<HashMap<String, Tenant>>,
+    replay: Option<Arc<dyn ReplayCache>>,
+    limiter: Option<Arc<dyn RateLimiter>>,
+    path: Option<PathBuf>,
+}
+
+impl Tenants {
+    /// Create an empty tenant registry
+    ///
+    /// # Arguments
+    /// * `rpc` - Soroban RPC client shared by the tenants
+    /// * `contract_id` - Escrow contract address (C... format)
+    /// * `network` - x402 network id payments must target
+    /// * `network_passphrase` - Passphrase of the network
+    pub fn new(
+        rpc: Rpc,
+        contract_id: impl Into<String>,
+        network: impl Into<String>,
+        network_passphrase: impl Into<String>,
+    ) -> Self {
+        Self {
+            rpc,
+            contract_id: contract_id.into(),
+            network: network.into(),
+            network_passphrase: network_passphrase.into(),
+            tenants: RwLock::new(HashMap::new()),
+            replay: None,
+            limiter: None,
+            path: None,
+        }
+    }
+
+    /// Reserve the verified nonces of every tenant in `cache`
+    ///
+    /// Applies to tenants added afterwards.
+    pub fn with_replay_cache(mut self, cache: Arc<dyn ReplayCache>) -> Self {
+        self.replay = Some(cache);
+        self
+    }
+
+    /// Keep the token buckets of every tenant in `limiter`
+    ///
+    /// Buckets are keyed by server address, so tenants do not share them.
+    /// Applies to tenants added afterwards.
+    pub fn with_rate_limiter(mut self, limiter: Arc<dyn RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Load the tenants of the JSON file at `path`, and save changes to it
+    ///
+    /// A missing file is created on the first change.
+    ///
+    /// # Errors
+    /// * If the file cannot be read, or a tenant in it is invalid
+    pub fn with_file(mut self, path: impl Into<PathBuf>) -> Result<Self, TenantError> {
+        let path = path.into();
+        if path.exists() {
+            let configs: Vec<TenantConfig> = serde_json::from_slice(&fs::read(&path)?)?;
+            let mut tenants = self.tenants.write().unwrap();
+            for config in configs {
+                self.insert(&mut tenants, config)?;
+            }
+        }
+        self.path = Some(path);
+        Ok(self)
+    }
+
+    /// Tenants file, if configured
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Add a tenant, or replace the tenant with the same ID
+    ///
+    /// A tenant whose server, key, and webhooks are unchanged keeps its
+    /// facilitator, only its settings are replaced.
+    ///
+    /// # Returns
+    /// * Whether a tenant was replaced
+    ///
+    /// # Errors
+    /// * `Invalid` - If the config is malformed or its key cannot be read
+    /// * `KeyMismatch` - If the signing key is not the server's
+    /// * `DuplicateApiKey` - If another tenant has the same API key
+    /// * If the tenants file cannot be written
+    pub fn upsert(&self, config: TenantConfig) -> Result<bool, TenantError> {
+        let mut tenants = self.tenants.write().unwrap();
+        let replaced = self.insert(&mut tenants, config)?;
+        self.save(&tenants)?;
+        Ok(replaced)
+    }
+
+    /// Remove a tenant
+    ///
+    /// # Returns
+    /// * Whether the tenant existed
+    ///
+    /// # Errors
+    /// * If the tenants file cannot be written
+    pub fn remove(&self, id: &str) -> Result<bool, TenantError> {
+        let mut tenants = self.tenants.write().unwrap();
+        let removed = tenants.remove(id).is_some();
+        if removed {
+            self.save(&tenants)?;
+        }
+        Ok(removed)
+    }
+
+    /// Facilitator of the tenant authenticating with `api_key`
+    pub fn by_api_key(&self, api_key: &str) -> Option<Arc<Facilitator>> {
+        self.tenants
+            .read()
+            .unwrap()
+            .values()
+            .find(|tenant| tenant.config.api_key == api_key)
+            .map(|tenant| tenant.facilitator.clone())
+    }
+
+    /// Facilitator of the tenant with ID `id`
+    pub fn get(&self, id: &str) -> Option<Arc<Facilitator>> {
+        let tenants = self.tenants.read().unwrap();
+        tenants.get(id).map(|tenant| tenant.facilitator.clone())
+    }
+
+    /// Configured tenants, ordered by ID
+    pub fn configs(&self) -> Vec<TenantConfig> {
+        sorted_configs(&self.tenants.read().unwrap())
+    }
+
+    /// Shut every tenant's facilitator down, see [`Facilitator::shutdown`]
+    ///
+    /// Every tenant stops admitting settlements at once, then their
+    /// settlements in flight get `grace` altogether.
+    ///
+    /// # Returns
+    /// * What each tenant left unfinished, by tenant ID
+    pub async fn shutdown(&self, grace: Duration) -> Vec<(String, ShutdownReport)> {
+        let deadline = Instant::now() + grace;
+        let mut facilitators: Vec<_> = self
+            .tenants
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, tenant)| (id.clone(), tenant.facilitator.clone()))
+            .collect();
+        facilitators.sort_by(|a, b| a.0.cmp(&b.0));
+        for (_, facilitator) in &facilitators {
+            facilitator.stop_admitting();
+        }
+        let mut reports = Vec::new();
+        for (id, facilitator) in facilitators {
+            let grace = deadline.saturating_duration_since(Instant::now());
+            reports.push((id, facilitator.shutdown(grace).await));
+        }
+        reports
+    }
+
+    /// Stage the key replacing a tenant's signing key
+    ///
+    /// The key is read like `signingKey`, and may be the server's own or
+    /// another key added as a signer of the server account. Settlements are
+    /// signed with the active key until [`Tenants::promote_key`]. Staging
+    /// again replaces the staged key.
+    ///
+    /// # Returns
+    /// * The tenant, None if there is no such tenant
+    ///
+    /// # Errors
+    /// * `Invalid` - If the key cannot be read
+    /// * If the tenants file cannot be written
+    pub fn stage_key(
+        &self,
+        id: &str,
+        signing_key: impl Into<String>,
+    ) -> Result<Option<TenantSummary>, TenantError> {
+        let mut tenants = self.tenants.write().unwrap();
+        let Some(tenant) = tenants.get_mut(id) else {
+            return Ok(None);
+        };
+        let signing_key = signing_key.into();
+        self.escrow_client(&tenant.config, &signing_key, true)?;
+        tenant.config.next_signing_key = Some(signing_key);
+        let summary = tenant.config.summary();
+        self.save(&tenants)?;
+        Ok(Some(summary))
+    }
+
+    /// Sign a tenant's settlements with its staged key from now on
+    ///
+    /// The tenant keeps its facilitator, see [`Facilitator::rotate_signer`].
+    /// The staged key must have been added as a signer of the server
+    /// account first, and the old key may be removed from it once promoted.
+    /// The server address stays the same.
+    ///
+    /// # Returns
+    /// * The tenant, None if there is no such tenant
+    ///
+    /// # Errors
+    /// * `NoStagedKey` - If no key is staged
+    /// * `Invalid` - If the staged key cannot be read
+    /// * If the tenants file cannot be written
+    pub fn promote_key(&self, id: &str) -> Result<Option<TenantSummary>, TenantError> {
+        let mut tenants = self.tenants.write().unwrap();
+        let Some(tenant) = tenants.get_mut(id) else {
+            return Ok(None);
+        };
+        let Some(signing_key) = tenant.config.next_signing_key.clone() else {
+            return Err(TenantError::NoStagedKey(id.into()));
+        };
+        let client = self.escrow_client(&tenant.config, &signing_key, true)?;
+        tenant
+            .facilitator
+            .rotate_signer(client)
+            .map_err(|e| tenant.config.invalid(e.to_string()))?;
+        tenant.config.signing_key = signing_key;
+        tenant.config.next_signing_key = None;
+        tenant.config.rotated = true;
+        let summary = tenant.config.summary();
+        self.save(&tenants)?;
+        Ok(Some(summary))
+    }
+
+    fn insert(
+        &self,
+        tenants: &mut HashMap<String, Tenant>,
+        config: TenantConfig,
+    ) -> Result<bool, TenantError> {
+        if config.id.is_empty() || config.api_key.is_empty() {
+            return Err(config.invalid("id and apiKey are required"));
+        }
+        let taken = tenants
+            .values()
+            .any(|tenant| tenant.config.id != config.id && tenant.config.api_key == config.api_key);
+        if taken {
+            return Err(TenantError::DuplicateApiKey(config.id));
+        }
+
+        if let Some(tenant) = tenants.get_mut(&config.id) {
+            let unchanged = tenant.config.server == config.server
+                && tenant.config.signing_key == config.signing_key
+                && tenant.config.rotated == config.rotated
+                && tenant.config.webhooks == config.webhooks;
+            if unchanged {
+                tenant.facilitator.set_settings(config.settings());
+                tenant.config = config;
+                return Ok(true);
+            }
+        }
+        let facilitator = Arc::new(self.facilitator(&config)?);
+        let tenant = Tenant {
+            config,
+            facilitator,
+        };
+        Ok(tenants.insert(tenant.config.id.clone(), tenant).is_some())
+    }
+
+    fn facilitator(&self, config: &TenantConfig) -> Result<Facilitator, TenantError> {
+        let client = self.escrow_client(config, &config.signing_key, config.rotated)?;
+        if client.address() != config.server {
+            return Err(TenantError::KeyMismatch {
+                id: config.id.clone(),
+                server: config.server.clone(),
+            });
+        }
+        let metrics = Metrics::for_tenant(&self.contract_id, &self.network, &config.id);
+        let mut facilitator = Facilitator::new(client, &self.network)
+            .with_metrics(metrics)
+            .with_settings(config.settings());
+        if !config.webhooks.is_empty() {
+            let webhooks = Webhooks::new(config.webhooks.clone());
+            facilitator = facilitator.with_webhooks(Arc::new(webhooks));
+        }
+        if let Some(replay) = &self.replay {
+            facilitator = facilitator.with_replay_cache(replay.clone());
+        }
+        if let Some(limiter) = &self.limiter {
+            facilitator = facilitator.with_rate_limiter(limiter.clone());
+        }
+        Ok(facilitator)
+    }
+
+    /// Escrow client signing with `key` for the tenant's server
+    ///
+    /// # Arguments
+    /// * `rotated` - Whether the key may be another key added as a signer
+    ///   of the server account, rather than the server's own
+    fn escrow_client(
+        &self,
+        config: &TenantConfig,
+        key: &str,
+        rotated: bool,
+    ) -> Result<EscrowClient, TenantError> {
+        let secret = if let Some(name) = key.strip_prefix("env:") {
+            std::env::var(name).map_err(|_| config.invalid(format!("{name} is not set")))?
+        } else if let Some(path) = key.strip_prefix("file:") {
+            fs::read_to_string(path)
+                .map_err(|e| config.invalid(format!("cannot read {path}: {e}")))?
+        } else if key.starts_with("http://") || key.starts_with("https://") {
+            let signer =
+                HttpSigner::new(&config.server, key).map_err(|e| config.invalid(e.to_string()))?;
+            return self.client_with(config, signer);
+        } else {
+            return Err(config.invalid("signingKey must start with env:, file:, or http(s)://"));
+        };
+        let mut signer =
+            LocalSigner::from_secret(secret.trim()).map_err(|e| config.invalid(e.to_string()))?;
+        if rotated {
+            signer = signer
+                .for_account(&config.server)
+                .map_err(|e| config.invalid(e.to_string()))?;
+        }
+        self.client_with(config, signer)
+    }
+
+    fn client_with(
+        &self,
+        config: &TenantConfig,
+        signer: impl Signer + 'static,
+    ) -> Result<EscrowClient, TenantError> {
+        EscrowClient::new(
+            self.rpc.clone(),
+            &self.contract_id,
+            &self.network_passphrase,
+            signer,
+        )
+        .map_err(|e| config.invalid(e.to_string()))
+    }
+
+    /// Write the tenants file, replacing it atomically
+    ///
+    /// Called with the registry locked, so writes do not interleave.
+    fn save(&self, tenants: &HashMap<String, Tenant>) -> Result<(), TenantError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let staged = path.with_extension("tmp");
+        fs::write(
+            &staged,
+            serde_json::to_vec_pretty(&sorted_configs(tenants))?,
+        )?;
+        fs::rename(&staged, path)?;
+        Ok(())
+    }
+}
+
+fn sorted_configs(tenants: &HashMap<String, Tenant>) -> Vec<TenantConfig> {
+    let mut configs: Vec<_> = tenants
+        .values()
+        .map(|tenant| tenant.config.clone())
+        .collect();
+    configs.sort_by(|a, b| a.id.cmp(&b.id));
+    configs
+}
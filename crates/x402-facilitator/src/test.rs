@@ -0,0 +1,5334 @@
+#![cfg(test)]
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    env, fs,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER},
+        HeaderMap, Request, StatusCode,
+    },
+    routing, Router,
+};
+use ed25519_dalek::{Signer as _, SigningKey};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use stellar_strkey::ed25519;
+use stellar_xdr::curr::{
+    AccountId, AlphaNum4, Asset, AssetCode4, Hash, Int128Parts, Limits, Memo, MuxedAccount,
+    MuxedAccountMed25519, Operation, OperationBody, PaymentOp, Preconditions, PublicKey, ReadXdr,
+    ScSymbol, ScVal, SequenceNumber, Transaction, TransactionEnvelope, TransactionExt,
+    TransactionV1Envelope, Uint256, VecM, WriteXdr,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream},
+    sync::Notify,
+};
+use tower::ServiceExt;
+use tracing_test::traced_test;
+use x402_client::{
+    scval,
+    testutils::{EnvTransport, NETWORK_PASSPHRASE},
+    AuthorizationEntry, ClientOptions, ContractError, Error as ClientError, EscrowClient, EscrowOp,
+    FeeBumpPolicy, Finality, FinalityPolicy, LocalSigner, Rpc, Signer, Transport,
+};
+use x402_errors::{ProtocolError, X402Error};
+use x402_escrow::X402EscrowContract;
+use x402_types::{
+    correlation_id, decode_payment_header, encode_payment_header, AssetAmount, EscrowPayload,
+    FeePolicy, JsonSchema, PaymentErrorBody, PaymentPayload, PaymentRequiredResponse,
+    PaymentRequirements, PaymentResponseHeader, PreflightResponse, SchemePayload, SettleRequest,
+    SettleResponse, SigningDomain, TransactionHashPayload, VerifyRequest, VerifyResponse,
+    ESCROW_SCHEME, EXACT_SCHEME, X402_VERSION, X402_VERSIONS,
+};
+
+use crate::{
+    admin_router, deposit_address, direct::asset_contract_id, openapi_spec, operations_router,
+    parse_endpoint, router, tenant_admin_router, tenant_router, verify_authorization,
+    verify_signature, AdminError, BatchPolicy, BatchState, Degradation, DirectPayments, Endpoint,
+    EventKind, Facilitator, Faults, JobState, MemoryReplayCache, PendingDeposits, RateLimit,
+    RateLimiter, ReadinessPolicy, RedisRateLimiter, RedisReplayCache, ReplayCache, RetryPolicy,
+    Settings, SettlementQueue, Stage, TenantConfig, Tenants, VerifiedPayment, VerifyError,
+    Webhooks, DELIVERY_HEADER, EVENT_HEADER, MAX_REPLAY_TTL, OPENAPI_PATH, SHUTTING_DOWN,
+    SIGNATURE_HEADER,
+};
+
+const NETWORK: &str = "stellar-local";
+const CLIENT_SEED: [u8; 32] = [1; 32];
+const SERVER_SEED: [u8; 32] = [2; 32];
+const WEBHOOK_SECRET: &str = "whsec_test";
+
+struct Setup {
+    app: Router,
+    facilitator: Arc<Facilitator>,
+    client: EscrowClient,
+    client_addr: String,
+    server_addr: String,
+    /// Escrow contract address (C... format)
+    contract_id: String,
+    /// Escrow of the client with the server
+    escrow_id: u64,
+}
+
+async fn setup() -> Setup {
+    setup_with(None).await
+}
+
+async fn setup_with(webhooks: Option<Arc<Webhooks>>) -> Setup {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport);
+
+    let client_key = LocalSigner::from_bytes(&CLIENT_SEED);
+    let client_addr = client_key.address();
+    let client =
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, client_key).unwrap();
+    let server = EscrowClient::new(
+        rpc,
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&SERVER_SEED),
+    )
+    .unwrap();
+    let mut facilitator = Facilitator::new(server, NETWORK);
+    if let Some(webhooks) = webhooks {
+        facilitator = facilitator.with_webhooks(webhooks);
+    }
+    let facilitator = Arc::new(facilitator);
+    let server_addr = facilitator.server().to_string();
+
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    Setup {
+        app: router(facilitator.clone()),
+        facilitator,
+        client,
+        client_addr,
+        server_addr,
+        contract_id,
+        escrow_id,
+    }
+}
+
+fn requirements(pay_to: &str) -> PaymentRequirements {
+    PaymentRequirements {
+        x402_version: None,
+        scheme: ESCROW_SCHEME.into(),
+        network: NETWORK.into(),
+        max_amount_required: "1000000".into(),
+        resource: "https://api.example.com/weather".into(),
+        description: "Weather report".into(),
+        mime_type: "application/json".into(),
+        output_schema: None,
+        pay_to: pay_to.into(),
+        asset: None,
+        alternatives: vec![],
+        max_timeout_seconds: 60,
+        extra: None,
+    }
+}
+
+/// ID of the payment at `index` of `escrow_id`, as the contract allocates it
+fn payment_id(escrow_id: u64, index: u32) -> u64 {
+    escrow_id << 32 | u64::from(index)
+}
+
+/// Signing domain of the escrow contract `contract_id` on the test network
+fn domain(contract_id: &str) -> SigningDomain<'_> {
+    SigningDomain {
+        network_id: Sha256::digest(NETWORK_PASSPHRASE.as_bytes()).into(),
+        contract_id,
+    }
+}
+
+fn signed_payload(
+    contract_id: &str,
+    escrow_id: u64,
+    client: &str,
+    amount: &str,
+    nonce: u64,
+) -> EscrowPayload {
+    let mut payload = EscrowPayload {
+        escrow_id,
+        client: client.into(),
+        amount: amount.into(),
+        nonce,
+        expires_at: u64::MAX,
+        signature: String::new(),
+        deposit_authorization: None,
+        payment_id: None,
+        direct_authorization: None,
+    };
+    let signature = SigningKey::from_bytes(&CLIENT_SEED)
+        .sign(&payload.signing_hash(NETWORK, domain(contract_id)));
+    payload.signature = hex::encode(signature.to_bytes());
+    payload
+}
+
+fn header(payload: EscrowPayload) -> String {
+    encode_payment_header(&PaymentPayload {
+        x402_version: X402_VERSION,
+        scheme: ESCROW_SCHEME.into(),
+        network: NETWORK.into(),
+        asset: None,
+        payload: SchemePayload::Escrow(payload),
+        extensions: Default::default(),
+    })
+}
+
+async fn post(app: &Router, path: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::post(path)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    send(app, request).await
+}
+
+async fn get(app: &Router, path: &str) -> (StatusCode, Value) {
+    send(app, Request::get(path).body(Body::empty()).unwrap()).await
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+async fn verify(s: &Setup, payment_header: String) -> VerifyResponse {
+    let request = VerifyRequest {
+        x402_version: X402_VERSION,
+        payment_header,
+        payment_requirements: requirements(&s.server_addr),
+    };
+    let (status, body) = post(&s.app, "/verify", serde_json::to_value(request).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_value(body).unwrap()
+}
+
+async fn settle(s: &Setup, payment_header: String) -> SettleResponse {
+    let request = SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header,
+        payment_requirements: requirements(&s.server_addr),
+        settle_amount: None,
+        dry_run: false,
+    };
+    let (status, body) = post(&s.app, "/settle", serde_json::to_value(request).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_value(body).unwrap()
+}
+
+#[tokio::test]
+async fn test_verify_and_settle() {
+    let s = setup().await;
+    let payment = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "400000",
+        1,
+    ));
+
+    let verified = verify(&s, payment.clone()).await;
+    assert!(verified.is_valid, "{verified:?}");
+    assert_eq!(verified.invalid_reason, None);
+
+    let settled = settle(&s, payment.clone()).await;
+    assert!(settled.success, "{settled:?}");
+    assert_eq!(settled.payment_id, Some(payment_id(s.escrow_id, 0)));
+    assert_eq!(settled.network_id.as_deref(), Some(NETWORK));
+    assert_eq!(settled.tx_hash.unwrap().len(), 64);
+
+    assert!(
+        s.client
+            .get_payment(payment_id(s.escrow_id, 0))
+            .await
+            .unwrap()
+            .settled
+    );
+    assert_eq!(
+        s.client.get_escrow_balance(s.escrow_id).await.unwrap(),
+        9_600_000
+    );
+
+    // The nonce is spent once settled
+    let replayed = verify(&s, payment.clone()).await;
+    assert_eq!(replayed.invalid_reason.as_deref(), Some("nonce_used"));
+    let replayed = settle(&s, payment).await;
+    assert!(!replayed.success);
+    assert_eq!(replayed.error.as_deref(), Some("nonce_used"));
+}
+
+#[tokio::test]
+async fn test_verify_alternative_asset() {
+    let s = setup().await;
+    let requirements = PaymentRequirements {
+        asset: Some("CUSDC".into()),
+        alternatives: vec![AssetAmount {
+            asset: "native".into(),
+            max_amount_required: "3000000".into(),
+        }],
+        ..requirements(&s.server_addr)
+    };
+    let paying = |asset: Option<&str>, amount: &str, nonce: u64| {
+        encode_payment_header(&PaymentPayload {
+            x402_version: X402_VERSION,
+            scheme: ESCROW_SCHEME.into(),
+            network: NETWORK.into(),
+            asset: asset.map(String::from),
+            payload: SchemePayload::Escrow(signed_payload(
+                &s.contract_id,
+                s.escrow_id,
+                &s.client_addr,
+                amount,
+                nonce,
+            )),
+            extensions: Default::default(),
+        })
+    };
+    let check = |header: String| {
+        let requirements = requirements.clone();
+        let facilitator = s.facilitator.clone();
+        async move {
+            facilitator
+                .check(&header, &requirements)
+                .await
+                .map_err(|e| e.reason())
+        }
+    };
+
+    // Each asset is checked at its own amount
+    let paid = check(paying(Some("native"), "2500000", 1)).await.unwrap();
+    assert_eq!(paid.amount, 2_500_000);
+    assert!(check(paying(Some("CUSDC"), "1000000", 2)).await.is_ok());
+    assert_eq!(
+        check(paying(Some("native"), "3000001", 3)).await,
+        Err("amount_exceeds_requirement")
+    );
+    // Payloads naming no asset pay in the primary one
+    assert_eq!(
+        check(paying(None, "2500000", 4)).await,
+        Err("amount_exceeds_requirement")
+    );
+    assert_eq!(
+        check(paying(Some("CEURC"), "1000000", 5)).await,
+        Err("invalid_asset")
+    );
+}
+
+#[tokio::test]
+async fn test_settle_partial_amount() {
+    let s = setup().await;
+    let facilitator = &s.facilitator;
+    let settle_part = |nonce: u64, amount: &str| {
+        let request = SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: header(signed_payload(
+                &s.contract_id,
+                s.escrow_id,
+                &s.client_addr,
+                "400000",
+                nonce,
+            )),
+            payment_requirements: requirements(&s.server_addr),
+            settle_amount: Some(amount.into()),
+            dry_run: false,
+        };
+        async move { facilitator.settle(&request).await }
+    };
+
+    // Only the part charged is paid on-chain
+    let settled = settle_part(1, "150000").await;
+    assert!(settled.success, "{settled:?}");
+    assert_eq!(
+        s.client
+            .get_payment(payment_id(s.escrow_id, 0))
+            .await
+            .unwrap()
+            .amount,
+        150_000
+    );
+    assert_eq!(
+        s.client.get_escrow_balance(s.escrow_id).await.unwrap(),
+        9_850_000
+    );
+
+    // Charging nothing spends the authorization without a transaction
+    let settled = settle_part(2, "0").await;
+    assert!(settled.success, "{settled:?}");
+    assert_eq!(settled.tx_hash, None);
+    assert_eq!(settled.payment_id, None);
+    assert_eq!(
+        s.client.get_escrow_balance(s.escrow_id).await.unwrap(),
+        9_850_000
+    );
+    let replayed = verify(
+        &s,
+        header(signed_payload(
+            &s.contract_id,
+            s.escrow_id,
+            &s.client_addr,
+            "400000",
+            2,
+        )),
+    )
+    .await;
+    assert_eq!(replayed.invalid_reason.as_deref(), Some("nonce_used"));
+
+    let exceeding = settle_part(3, "400001").await;
+    assert_eq!(
+        exceeding.error.as_deref(),
+        Some("settlement_exceeds_authorization")
+    );
+    let invalid = settle_part(3, "-1").await;
+    assert_eq!(invalid.error.as_deref(), Some("invalid_amount"));
+    // Rejected amounts leave the authorization usable
+    assert!(settle_part(3, "400000").await.success);
+    assert_eq!(
+        s.client.get_escrow_balance(s.escrow_id).await.unwrap(),
+        9_450_000
+    );
+}
+
+#[tokio::test]
+async fn test_settle_paused_by_spend_limit() {
+    let s = setup().await;
+    s.client
+        .set_max_settled_per_hour(s.escrow_id, Some(500_000))
+        .await
+        .unwrap();
+    let payment = |nonce| {
+        header(signed_payload(
+            &s.contract_id,
+            s.escrow_id,
+            &s.client_addr,
+            "400000",
+            nonce,
+        ))
+    };
+
+    let settled = settle(&s, payment(1)).await;
+    assert!(settled.success, "{settled:?}");
+
+    // The second payment breaches the cap, which pauses settlements
+    let paused = settle(&s, payment(2)).await;
+    assert!(!paused.success);
+    assert_eq!(paused.payment_id, Some(payment_id(s.escrow_id, 1)));
+    assert_eq!(
+        X402Error::from_code(paused.error_code.unwrap()),
+        Some(X402Error::Contract(ContractError::SettlementsPaused))
+    );
+    let limit = s
+        .client
+        .get_spend_limit(s.escrow_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(limit.paused);
+    assert_eq!(
+        s.client.get_escrow_balance(s.escrow_id).await.unwrap(),
+        9_600_000
+    );
+}
+
+#[tokio::test]
+async fn test_settle_dry_run() {
+    let s = setup().await;
+    let payment = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "400000",
+        1,
+    ));
+    let dry_run = |payment_header: String| SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header,
+        payment_requirements: requirements(&s.server_addr),
+        settle_amount: Some("150000".into()),
+        dry_run: true,
+    };
+
+    let (status, body) = post(
+        &s.app,
+        "/settle",
+        serde_json::to_value(dry_run(payment.clone())).unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let simulated: SettleResponse = serde_json::from_value(body).unwrap();
+    assert!(simulated.success, "{simulated:?}");
+    assert_eq!((simulated.tx_hash, simulated.payment_id), (None, None));
+    let simulation = simulated.simulation.unwrap();
+    assert_eq!(simulation.amount, "150000");
+    assert_eq!(simulation.payment_id, Some(payment_id(s.escrow_id, 0)));
+    assert!(simulation.estimated_fee.unwrap() > 0);
+
+    // Nothing reached the chain and the nonce is still unused
+    assert!(matches!(
+        s.client.get_payment(payment_id(s.escrow_id, 0)).await,
+        Err(ClientError::Contract(ContractError::PaymentNotFound, _))
+    ));
+    assert_eq!(
+        s.client.get_escrow_balance(s.escrow_id).await.unwrap(),
+        10_000_000
+    );
+    assert!(verify(&s, payment.clone()).await.is_valid);
+
+    // Rejections are reported as for a real settlement
+    let exceeding = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "2000000",
+        2,
+    ));
+    let rejected = s.facilitator.settle(&dry_run(exceeding)).await;
+    assert_eq!(
+        rejected.error.as_deref(),
+        Some("amount_exceeds_requirement")
+    );
+
+    let settled = settle(&s, payment).await;
+    assert!(settled.success, "{settled:?}");
+    assert_eq!(settled.payment_id, Some(payment_id(s.escrow_id, 0)));
+    assert_eq!(settled.simulation, None);
+
+    // Set globally, every settlement is simulated, none queued
+    let path = env::temp_dir().join(format!("x402-dry-run-{}.sqlite", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let server = EscrowClient::new(
+        s.client.rpc().clone(),
+        &s.client.contract_id(),
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&SERVER_SEED),
+    )
+    .unwrap();
+    let facilitator = Facilitator::new(server, NETWORK)
+        .with_queue(SettlementQueue::open(&path).unwrap())
+        .unwrap()
+        .with_settings(Settings {
+            dry_run: true,
+            ..Settings::default()
+        });
+    let request = SettleRequest {
+        dry_run: false,
+        ..dry_run(header(signed_payload(
+            &s.contract_id,
+            s.escrow_id,
+            &s.client_addr,
+            "400000",
+            3,
+        )))
+    };
+    let simulated = facilitator.settle(&request).await;
+    assert!(simulated.success, "{simulated:?}");
+    assert_eq!(
+        simulated.simulation.unwrap().payment_id,
+        Some(payment_id(s.escrow_id, 1))
+    );
+    assert_eq!(facilitator.queue().unwrap().depth().unwrap(), 0);
+    assert!(facilitator.queue().unwrap().jobs().unwrap().is_empty());
+    assert_eq!(
+        s.client.get_escrow_balance(s.escrow_id).await.unwrap(),
+        9_600_000
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_verify_authorization() {
+    let client = ed25519::PublicKey(
+        SigningKey::from_bytes(&CLIENT_SEED)
+            .verifying_key()
+            .to_bytes(),
+    )
+    .to_string();
+    let contract_id = stellar_strkey::Contract([1; 32]).to_string();
+    let payload = signed_payload(&contract_id, 0, &client, "400000", 1);
+    assert!(verify_authorization(&payload, NETWORK, domain(&contract_id)).is_ok());
+    assert!(matches!(
+        verify_authorization(&payload, "stellar-testnet", domain(&contract_id)),
+        Err(VerifyError::InvalidSignature)
+    ));
+    // Signed for another deployment of the contract
+    let other = stellar_strkey::Contract([2; 32]).to_string();
+    assert!(matches!(
+        verify_authorization(&payload, NETWORK, domain(&other)),
+        Err(VerifyError::InvalidSignature)
+    ));
+
+    for signature in [&payload.signature[2..], "zz", ""] {
+        let malformed = EscrowPayload {
+            signature: signature.into(),
+            ..payload.clone()
+        };
+        assert!(matches!(
+            verify_authorization(&malformed, NETWORK, domain(&contract_id)),
+            Err(VerifyError::InvalidSignature)
+        ));
+    }
+    let contract = EscrowPayload {
+        client: stellar_strkey::Contract([3; 32]).to_string(),
+        ..payload
+    };
+    assert!(matches!(
+        verify_authorization(&contract, NETWORK, domain(&contract_id)),
+        Err(VerifyError::ClientMismatch)
+    ));
+}
+
+#[tokio::test]
+async fn test_verify_rejections() {
+    let s = setup().await;
+
+    // Amount above the requirement
+    let response = verify(
+        &s,
+        header(signed_payload(
+            &s.contract_id,
+            s.escrow_id,
+            &s.client_addr,
+            "2000000",
+            1,
+        )),
+    )
+    .await;
+    assert_eq!(
+        response.invalid_reason.as_deref(),
+        Some("amount_exceeds_requirement")
+    );
+
+    // Tampered amount no longer matches the signature
+    let mut tampered = signed_payload(&s.contract_id, s.escrow_id, &s.client_addr, "400000", 1);
+    tampered.amount = "500000".into();
+    let response = verify(&s, header(tampered)).await;
+    assert_eq!(
+        response.invalid_reason.as_deref(),
+        Some("invalid_signature")
+    );
+
+    // Expired authorization
+    let mut expired = signed_payload(&s.contract_id, s.escrow_id, &s.client_addr, "400000", 1);
+    expired.expires_at = 1;
+    let response = verify(&s, header(expired)).await;
+    assert_eq!(
+        response.invalid_reason.as_deref(),
+        Some("authorization_expired")
+    );
+
+    // Escrow that does not exist
+    let mut missing = signed_payload(&s.contract_id, s.escrow_id, &s.client_addr, "400000", 1);
+    missing.escrow_id = u64::MAX;
+    let signature = SigningKey::from_bytes(&CLIENT_SEED)
+        .sign(&missing.signing_hash(NETWORK, domain(&s.contract_id)));
+    missing.signature = hex::encode(signature.to_bytes());
+    let response = verify(&s, header(missing)).await;
+    assert_eq!(response.invalid_reason.as_deref(), Some("escrow_not_found"));
+
+    // Payment claimed created when the escrow opened, which it was not
+    let mut claimed = signed_payload(&s.contract_id, s.escrow_id, &s.client_addr, "400000", 1);
+    claimed.payment_id = Some(payment_id(s.escrow_id, 1));
+    let response = verify(&s, header(claimed)).await;
+    assert_eq!(response.invalid_reason.as_deref(), Some("invalid_payload"));
+
+    // Garbage header
+    let response = verify(&s, "not base64!".into()).await;
+    assert_eq!(response.invalid_reason.as_deref(), Some("invalid_payload"));
+}
+
+#[tokio::test]
+async fn test_expiry_by_ledger_time() {
+    let s = setup().await;
+    let requirements = requirements(&s.server_addr);
+    // Far behind the local clock, so only expiries checked by ledger time
+    // pass
+    let now = s.client.rpc().get_ledger_time().await.unwrap();
+    assert!(now >= 3);
+    let check = |nonce: u64, expires_at: u64| {
+        let mut payload =
+            signed_payload(&s.contract_id, s.escrow_id, &s.client_addr, "1000", nonce);
+        payload.expires_at = expires_at;
+        let signature = SigningKey::from_bytes(&CLIENT_SEED)
+            .sign(&payload.signing_hash(NETWORK, domain(&s.contract_id)));
+        payload.signature = hex::encode(signature.to_bytes());
+        let header = header(payload);
+        let facilitator = s.facilitator.clone();
+        let requirements = requirements.clone();
+        async move { facilitator.check(&header, &requirements).await }
+    };
+    let expired = |result: Result<_, VerifyError>| matches!(result, Err(VerifyError::Expired));
+
+    // Without a tolerance, payloads expire right after `expiresAt`
+    assert!(check(1, now).await.is_ok());
+    assert!(expired(check(2, now - 1).await));
+
+    s.facilitator.set_settings(Settings {
+        clock_skew: Duration::from_secs(2),
+        ..s.facilitator.settings()
+    });
+    assert!(check(3, now + 2).await.is_ok());
+    assert!(check(4, now).await.is_ok());
+    assert!(check(5, now - 2).await.is_ok());
+    assert!(expired(check(6, now - 3).await));
+    assert!(expired(check(7, 0).await));
+
+    // Reserved until the tolerance runs out, however far the ledger trails
+    let mut payload = signed_payload(&s.contract_id, s.escrow_id, &s.client_addr, "1000", 8);
+    payload.expires_at = now;
+    let signature = SigningKey::from_bytes(&CLIENT_SEED)
+        .sign(&payload.signing_hash(NETWORK, domain(&s.contract_id)));
+    payload.signature = hex::encode(signature.to_bytes());
+    let request = VerifyRequest {
+        x402_version: X402_VERSION,
+        payment_header: header(payload),
+        payment_requirements: requirements.clone(),
+    };
+    let request = serde_json::to_value(request).unwrap();
+    let (status, _) = post(&s.app, "/verify", request.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = post(&s.app, "/verify", request).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["invalidReason"], "nonce_replayed");
+}
+
+#[tokio::test]
+async fn test_verify_versions() {
+    let s = setup().await;
+    let signed = signed_payload(&s.contract_id, s.escrow_id, &s.client_addr, "400000", 1);
+    let mut payload = decode_payment_header(&header(signed)).unwrap();
+
+    // Clients predating version 2 are still served
+    payload.x402_version = 1;
+    let response = verify(&s, encode_payment_header(&payload)).await;
+    assert!(response.is_valid, "{response:?}");
+    assert!(response.x402_versions.is_empty());
+
+    // Requirements pinned to a version take payloads of that version only
+    let request = VerifyRequest {
+        x402_version: X402_VERSION,
+        payment_header: encode_payment_header(&payload),
+        payment_requirements: PaymentRequirements {
+            x402_version: Some(X402_VERSION),
+            ..requirements(&s.server_addr)
+        },
+    };
+    let (_, body) = post(&s.app, "/verify", serde_json::to_value(request).unwrap()).await;
+    assert_eq!(body["invalidReason"], "invalid_x402_version");
+    assert_eq!(body["x402Versions"], json!(X402_VERSIONS));
+
+    // Unknown versions are rejected, listing those supported
+    payload.x402_version = X402_VERSION + 1;
+    let response = verify(&s, encode_payment_header(&payload)).await;
+    assert_eq!(
+        response.invalid_reason.as_deref(),
+        Some("invalid_x402_version")
+    );
+    assert_eq!(response.x402_versions, X402_VERSIONS);
+}
+
+#[tokio::test]
+async fn test_verify_checks_balance_and_recipient() {
+    let s = setup().await;
+    let payment = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "1000000",
+        1,
+    ));
+    let mut requirements = requirements(&s.server_addr);
+    let verified = s.facilitator.check(&payment, &requirements).await.unwrap();
+    assert_eq!(verified.amount, 1_000_000);
+
+    // Drain the escrow with other authorizations
+    for nonce in 10..20 {
+        let settled = settle(
+            &s,
+            header(signed_payload(
+                &s.contract_id,
+                s.escrow_id,
+                &s.client_addr,
+                "1000000",
+                nonce,
+            )),
+        )
+        .await;
+        assert!(settled.success, "{settled:?}");
+    }
+    assert_eq!(s.client.get_escrow_balance(s.escrow_id).await.unwrap(), 0);
+    let err = s
+        .facilitator
+        .check(&payment, &requirements)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::InsufficientBalance));
+
+    requirements.pay_to = s.client_addr.clone();
+    let err = s
+        .facilitator
+        .check(&payment, &requirements)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::RecipientMismatch));
+}
+
+#[tokio::test]
+async fn test_presigned_deposit_tops_up_escrow() {
+    let s = setup().await;
+    let requirements = requirements(&s.server_addr);
+    let payment = |nonce, deposit: Option<&AuthorizationEntry>| {
+        let mut payload = signed_payload(
+            &s.contract_id,
+            s.escrow_id,
+            &s.client_addr,
+            "1000000",
+            nonce,
+        );
+        payload.deposit_authorization = deposit.map(|entry| entry.to_xdr_base64().unwrap());
+        header(payload)
+    };
+    let deposit = |escrow_id, amount| EscrowOp::Deposit { escrow_id, amount };
+    for nonce in 10..20 {
+        let settled = settle(&s, payment(nonce, None)).await;
+        assert!(settled.success, "{settled:?}");
+    }
+
+    // Deposits that do not cover the payment
+    let small = s
+        .client
+        .presign(&deposit(s.escrow_id, 500_000), 100)
+        .await
+        .unwrap();
+    let err = s
+        .facilitator
+        .check(&payment(1, Some(&small)), &requirements)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::InsufficientBalance), "{err}");
+    let elsewhere = s
+        .client
+        .presign(&deposit(u64::MAX, 1_000_000), 100)
+        .await
+        .unwrap();
+    let err = s
+        .facilitator
+        .check(&payment(1, Some(&elsewhere)), &requirements)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::InvalidPayload(_)), "{err}");
+    let stranger = EscrowClient::new(
+        s.client.rpc().clone(),
+        &s.client.contract_id(),
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&[5; 32]),
+    )
+    .unwrap();
+    let foreign = stranger
+        .presign(&deposit(s.escrow_id, 1_000_000), 100)
+        .await
+        .unwrap();
+    let err = s
+        .facilitator
+        .check(&payment(1, Some(&foreign)), &requirements)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::ClientMismatch), "{err}");
+    let expired = s
+        .client
+        .presign(&deposit(s.escrow_id, 1_000_000), 0)
+        .await
+        .unwrap();
+    s.client.deposit(s.escrow_id, 1).await.unwrap();
+    let err = s
+        .facilitator
+        .check(&payment(1, Some(&expired)), &requirements)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::Expired), "{err}");
+
+    // Submitted by the facilitator before settling
+    let entry = s
+        .client
+        .presign(&deposit(s.escrow_id, 1_000_000), 100)
+        .await
+        .unwrap();
+    assert!(verify(&s, payment(1, Some(&entry))).await.is_valid);
+    let settled = settle(&s, payment(2, Some(&entry))).await;
+    assert!(settled.success, "{settled:?}");
+    assert_eq!(s.client.get_escrow_balance(s.escrow_id).await.unwrap(), 1);
+
+    // Only once
+    let again = settle(&s, payment(3, Some(&entry))).await;
+    assert!(!again.success, "{again:?}");
+    assert_eq!(s.client.get_escrow_balance(s.escrow_id).await.unwrap(), 1);
+    let retried = settle(&s, payment(3, None)).await;
+    assert_eq!(retried.error.as_deref(), Some("insufficient_funds"));
+}
+
+#[tokio::test]
+async fn test_direct_fallback() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport.clone());
+    let on = |seed| {
+        let key = LocalSigner::from_bytes(&seed);
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, key).unwrap()
+    };
+    let client = on(CLIENT_SEED);
+    let facilitator = Facilitator::new(on(SERVER_SEED), NETWORK);
+    let (client_addr, server_addr) = (client.address(), facilitator.server().to_string());
+    let token = transport.register_token();
+    transport.mint(&token, &client_addr, 10_000_000);
+    let requirements = PaymentRequirements {
+        asset: Some(token.clone()),
+        ..requirements(&server_addr)
+    };
+    // The client has no escrow 7
+    let direct = |payload: &EscrowPayload, amount| EscrowOp::RecordDirectPayment {
+        client: client_addr.clone(),
+        server: server_addr.clone(),
+        token: token.clone(),
+        amount,
+        reference: payload.signing_hash(NETWORK, domain(&contract_id)),
+    };
+    let with = |mut payload: EscrowPayload, entry: &AuthorizationEntry| {
+        payload.direct_authorization = Some(entry.to_xdr_base64().unwrap());
+        header(payload)
+    };
+    let payload = signed_payload(&contract_id, 7, &client_addr, "1000000", 1);
+    let entry = client
+        .presign(&direct(&payload, 1_000_000), 100)
+        .await
+        .unwrap();
+    let paid = with(payload.clone(), &entry);
+
+    // Only with a ceiling covering the payment
+    let err = facilitator.check(&paid, &requirements).await.unwrap_err();
+    assert!(matches!(err, VerifyError::EscrowNotFound), "{err}");
+    facilitator.set_settings(Settings {
+        direct_fallback: Some(999_999),
+        ..facilitator.settings()
+    });
+    let err = facilitator.check(&paid, &requirements).await.unwrap_err();
+    assert!(matches!(err, VerifyError::EscrowNotFound), "{err}");
+    facilitator.set_settings(Settings {
+        direct_fallback: Some(1_000_000),
+        ..facilitator.settings()
+    });
+    assert!(facilitator.check(&paid, &requirements).await.is_ok());
+    let err = facilitator
+        .check(&header(payload.clone()), &requirements)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::EscrowNotFound), "{err}");
+
+    // Transfers of another amount or for another authorization
+    for op in [
+        direct(&payload, 999_999),
+        direct(
+            &signed_payload(&contract_id, 7, &client_addr, "1000000", 2),
+            1_000_000,
+        ),
+        EscrowOp::Deposit {
+            escrow_id: 7,
+            amount: 1_000_000,
+        },
+    ] {
+        let other = client.presign(&op, 100).await.unwrap();
+        let err = facilitator
+            .check(&with(payload.clone(), &other), &requirements)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, VerifyError::InvalidPayload(_)), "{err}");
+    }
+
+    // Settled in full only, then by the transfer to the server
+    let request = |settle_amount: Option<&str>| SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header: paid.clone(),
+        payment_requirements: requirements.clone(),
+        settle_amount: settle_amount.map(String::from),
+        dry_run: false,
+    };
+    let partial = facilitator.settle(&request(Some("500000"))).await;
+    assert!(!partial.success, "{partial:?}");
+    let settled = facilitator.settle(&request(None)).await;
+    assert!(settled.success, "{settled:?}");
+    assert!(settled.tx_hash.is_some());
+    assert_eq!(transport.token_balance(&token, &server_addr), 1_000_000);
+    assert_eq!(transport.token_balance(&token, &client_addr), 9_000_000);
+    let payment = client
+        .get_payment(settled.payment_id.unwrap())
+        .await
+        .unwrap();
+    assert!(payment.settled);
+    assert_eq!(payment.amount, 1_000_000);
+
+    let again = facilitator.settle(&request(None)).await;
+    assert_eq!(again.error.as_deref(), Some("nonce_used"));
+    assert_eq!(transport.token_balance(&token, &server_addr), 1_000_000);
+}
+
+/// Transport failing simulations of one contract function as the contract
+/// would, with `Error(Contract, #code)`
+struct TrappingTransport {
+    inner: EnvTransport,
+    function: &'static str,
+    code: u32,
+}
+
+#[async_trait]
+impl Transport for TrappingTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        let envelope = params["transaction"].as_str().unwrap_or_default();
+        let bytes = TransactionEnvelope::from_xdr_base64(envelope, Limits::none())
+            .and_then(|envelope| envelope.to_xdr(Limits::none()))
+            .unwrap_or_default();
+        let function = self.function.as_bytes();
+        if method == "simulateTransaction" && bytes.windows(function.len()).any(|w| w == function) {
+            return Ok(json!({
+                "latestLedger": 1,
+                "error": format!("HostError: Error(Contract, #{})", self.code),
+            }));
+        }
+        self.inner.request(method, params).await
+    }
+}
+
+#[tokio::test]
+async fn test_errors_round_trip() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(TrappingTransport {
+        inner: transport,
+        function: "create_payment",
+        code: 3,
+    });
+    let client_key = LocalSigner::from_bytes(&CLIENT_SEED);
+    let client_addr = client_key.address();
+    let client =
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, client_key).unwrap();
+    let server = LocalSigner::from_bytes(&SERVER_SEED);
+    let server = EscrowClient::new(rpc, &contract_id, NETWORK_PASSPHRASE, server).unwrap();
+    let facilitator = Arc::new(Facilitator::new(server, NETWORK));
+    let server_addr = facilitator.server().to_string();
+    let app = router(facilitator);
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    // The contract traps, /settle answers its code, the client reads it back
+    let request = SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header: header(signed_payload(
+            &contract_id,
+            escrow_id,
+            &client_addr,
+            "1000000",
+            1,
+        )),
+        payment_requirements: requirements(&server_addr),
+        settle_amount: None,
+        dry_run: false,
+    };
+    let (status, body) = post(&app, "/settle", serde_json::to_value(request).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["errorCode"], 3);
+    let settled: SettleResponse = serde_json::from_value(body).unwrap();
+    assert!(!settled.success);
+    let error = X402Error::from_code(settled.error_code.unwrap()).unwrap();
+    assert_eq!(
+        error,
+        X402Error::Contract(ContractError::InsufficientBalance)
+    );
+    assert_eq!(error.reason(), "insufficient_funds");
+    assert!(!error.retryable());
+
+    // Rejections of the facilitator carry their code too
+    let request = VerifyRequest {
+        x402_version: X402_VERSION,
+        payment_header: header(signed_payload(
+            &contract_id,
+            escrow_id,
+            &client_addr,
+            "1000000",
+            2,
+        )),
+        payment_requirements: requirements(&server_addr),
+    };
+    let request = serde_json::to_value(request).unwrap();
+    let (status, body) = post(&app, "/verify", request.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.get("errorCode"), None);
+    let (status, body) = post(&app, "/verify", request).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let replayed: VerifyResponse = serde_json::from_value(body).unwrap();
+    let error = X402Error::from_code(replayed.error_code.unwrap()).unwrap();
+    assert_eq!(error, X402Error::Protocol(ProtocolError::NonceReplayed));
+    assert_eq!(replayed.invalid_reason.as_deref(), Some(error.reason()));
+}
+
+async fn preflight(app: &Router, payment_header: String, pay_to: &str) -> PreflightResponse {
+    let request = VerifyRequest {
+        x402_version: X402_VERSION,
+        payment_header,
+        payment_requirements: requirements(pay_to),
+    };
+    let (status, body) = post(app, "/preflight", serde_json::to_value(request).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_value(body).unwrap()
+}
+
+#[tokio::test]
+async fn test_preflight() {
+    let s = setup().await;
+    let reason = |verdict: &PreflightResponse| {
+        assert!(!verdict.ok && !verdict.binding, "{verdict:?}");
+        let error = X402Error::from_code(verdict.error_code.unwrap()).unwrap();
+        assert_eq!(verdict.invalid_reason.as_deref(), Some(error.reason()));
+        error.reason()
+    };
+
+    // A payment that would settle, reported without reserving its nonce
+    let payment = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "400000",
+        1,
+    ));
+    let verdict = preflight(&s.app, payment.clone(), &s.server_addr).await;
+    assert!(verdict.ok, "{verdict:?}");
+    assert!(!verdict.binding && !verdict.cached);
+    assert!(verdict.estimated_fee.unwrap() > 0);
+    assert_eq!(verdict.available_balance.as_deref(), Some("10000000"));
+    assert!(matches!(
+        s.client.get_payment(payment_id(s.escrow_id, 0)).await,
+        Err(ClientError::Contract(ContractError::PaymentNotFound, _))
+    ));
+    assert!(verify(&s, payment.clone()).await.is_valid);
+
+    // Reused while fresh, even once it no longer holds
+    assert!(settle(&s, payment.clone()).await.success);
+    let verdict = preflight(&s.app, payment, &s.server_addr).await;
+    assert!(verdict.ok && verdict.cached, "{verdict:?}");
+
+    // Failing checks, with the balance left
+    let mut expired = signed_payload(&s.contract_id, s.escrow_id, &s.client_addr, "400000", 2);
+    expired.expires_at = 1;
+    let verdict = preflight(&s.app, header(expired), &s.server_addr).await;
+    assert_eq!(reason(&verdict), "authorization_expired");
+    assert_eq!(verdict.available_balance.as_deref(), Some("9600000"));
+
+    let used = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "400000",
+        3,
+    ));
+    assert!(settle(&s, used.clone()).await.success);
+    let verdict = preflight(&s.app, used, &s.server_addr).await;
+    assert_eq!(reason(&verdict), "nonce_used");
+
+    let small = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 100_000, None)
+        .await
+        .unwrap()
+        .value;
+    let payment = header(signed_payload(
+        &s.contract_id,
+        small,
+        &s.client_addr,
+        "400000",
+        1,
+    ));
+    let verdict = preflight(&s.app, payment, &s.server_addr).await;
+    assert_eq!(reason(&verdict), "insufficient_funds");
+    assert_eq!(verdict.available_balance.as_deref(), Some("100000"));
+
+    let mut missing = signed_payload(&s.contract_id, u64::MAX - 1, &s.client_addr, "400000", 1);
+    let signature = SigningKey::from_bytes(&CLIENT_SEED)
+        .sign(&missing.signing_hash(NETWORK, domain(&s.contract_id)));
+    missing.signature = hex::encode(signature.to_bytes());
+    let verdict = preflight(&s.app, header(missing), &s.server_addr).await;
+    assert_eq!(reason(&verdict), "escrow_not_found");
+    assert_eq!(verdict.available_balance, None);
+
+    // A settlement the spend limit would refuse, though its payment could
+    // be created
+    s.client
+        .set_max_settled_per_hour(s.escrow_id, Some(1_000_000))
+        .await
+        .unwrap();
+    let payment = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "1000000",
+        4,
+    ));
+    assert!(preflight(&s.app, payment, &s.server_addr).await.ok);
+    let payment = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "400000",
+        5,
+    ));
+    assert!(settle(&s, payment).await.success);
+    let payment = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "700000",
+        6,
+    ));
+    let verdict = preflight(&s.app, payment, &s.server_addr).await;
+    assert_eq!(reason(&verdict), "settlements_paused");
+
+    // A simulation failing as the contract would
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(TrappingTransport {
+        inner: transport,
+        function: "create_payment",
+        code: 15,
+    });
+    let client = LocalSigner::from_bytes(&CLIENT_SEED);
+    let client = EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, client).unwrap();
+    let server = LocalSigner::from_bytes(&SERVER_SEED);
+    let server = EscrowClient::new(rpc, &contract_id, NETWORK_PASSPHRASE, server).unwrap();
+    let app = router(Arc::new(Facilitator::new(server, NETWORK)));
+    let frozen = client
+        .open_escrow(&s.client_addr, &s.server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let payment = header(signed_payload(
+        &contract_id,
+        frozen,
+        &s.client_addr,
+        "400000",
+        1,
+    ));
+    let verdict = preflight(&app, payment, &s.server_addr).await;
+    assert_eq!(reason(&verdict), "escrow_frozen");
+    assert_eq!(verdict.estimated_fee, None);
+
+    // Forecast anew once the TTL elapsed
+    let server = EscrowClient::new(
+        s.client.rpc().clone(),
+        &s.client.contract_id(),
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&SERVER_SEED),
+    )
+    .unwrap();
+    let facilitator =
+        Facilitator::new(server, NETWORK).with_preflight_ttl(Duration::from_millis(200));
+    let escrow_id = s
+        .client
+        .open_escrow(&s.client_addr, &s.server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let request = VerifyRequest {
+        x402_version: X402_VERSION,
+        payment_header: header(signed_payload(
+            &s.contract_id,
+            escrow_id,
+            &s.client_addr,
+            "400000",
+            1,
+        )),
+        payment_requirements: requirements(&s.server_addr),
+    };
+    assert!(facilitator.preflight(&request).await.ok);
+    s.client
+        .set_max_settled_per_hour(escrow_id, Some(100_000))
+        .await
+        .unwrap();
+    let verdict = facilitator.preflight(&request).await;
+    assert!(verdict.ok && verdict.cached, "{verdict:?}");
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    let verdict = facilitator.preflight(&request).await;
+    assert_eq!(reason(&verdict), "settlements_paused");
+    assert!(!verdict.cached);
+
+    // Without a TTL, every request is forecast
+    let facilitator = facilitator.with_preflight_ttl(Duration::ZERO);
+    s.client
+        .set_max_settled_per_hour(escrow_id, None)
+        .await
+        .unwrap();
+    let verdict = facilitator.preflight(&request).await;
+    assert!(verdict.ok && !verdict.cached, "{verdict:?}");
+}
+
+#[tokio::test]
+async fn test_supported() {
+    let s = setup().await;
+
+    let (status, body) = get(&s.app, "/supported").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["kinds"],
+        json!([{
+            "x402Version": X402_VERSION,
+            "scheme": "escrow",
+            "network": NETWORK,
+            "networkPassphrase": NETWORK_PASSPHRASE,
+        }])
+    );
+    assert_eq!(body["assets"], json!([]));
+    assert_eq!(body["fee"]["feeBps"], 0);
+    assert!(body["escrowContract"].as_str().unwrap().starts_with('C'));
+
+    // Settings changes are picked up by the next request
+    let asset = "CDLZFC3SYJYDZT7K67VZ75HPJVIEUVNIXF47ZG2FB2RMQQVU2HHGCYSC".to_string();
+    s.facilitator.set_settings(Settings {
+        assets: vec![asset.clone()],
+        fee: FeePolicy {
+            fee_bps: 25,
+            network_fee_sponsored: true,
+        },
+        rate_limit: None,
+        direct_payments: None,
+        dry_run: false,
+        degradation: Degradation::FailClosed,
+    });
+    let (_, body) = get(&s.app, "/supported").await;
+    assert_eq!(body["assets"], json!([asset]));
+    assert_eq!(body["fee"]["feeBps"], 25);
+    assert_eq!(body["fee"]["networkFeeSponsored"], true);
+}
+
+/// Request received by the mock webhook receiver
+#[derive(Clone, Debug)]
+struct Received {
+    headers: HeaderMap,
+    body: String,
+}
+
+/// Webhook receiver answering 500 to its first `failures` requests
+#[derive(Clone)]
+struct Receiver {
+    url: String,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl Receiver {
+    async fn start(failures: usize) -> Self {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new()
+            .route("/hook", routing::post(Self::handle))
+            .with_state((received.clone(), failures));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Self { url, received }
+    }
+
+    async fn handle(
+        State((received, failures)): State<(Arc<Mutex<Vec<Received>>>, usize)>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        let mut received = received.lock().unwrap();
+        received.push(Received { headers, body });
+        if received.len() <= failures {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::OK
+        }
+    }
+
+    fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+
+    fn endpoint(&self, events: Vec<EventKind>) -> Endpoint {
+        Endpoint {
+            url: self.url.clone(),
+            secret: WEBHOOK_SECRET.into(),
+            events,
+        }
+    }
+}
+
+fn fast_retry(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(40),
+    }
+}
+
+#[tokio::test]
+async fn test_webhook_retries_until_delivered() {
+    let receiver = Receiver::start(2).await;
+    let others = Receiver::start(0).await;
+    let webhooks = Webhooks::new(vec![
+        receiver.endpoint(vec![EventKind::PaymentSettled]),
+        others.endpoint(vec![EventKind::PaymentFailed]),
+    ])
+    .with_retry(fast_retry(5));
+
+    let event = webhooks.event(EventKind::PaymentSettled, json!({ "paymentId": 4 }));
+    assert!(webhooks.dispatch(&event).await.is_empty());
+    assert!(webhooks.failed().is_empty());
+
+    // Two failures then a success, all the same signed delivery
+    let received = receiver.received();
+    assert_eq!(received.len(), 3);
+    for request in &received {
+        assert_eq!(request.headers[EVENT_HEADER], "payment.settled");
+        assert_eq!(
+            request.headers[DELIVERY_HEADER],
+            received[0].headers[DELIVERY_HEADER]
+        );
+        let signature = request.headers[SIGNATURE_HEADER].to_str().unwrap();
+        assert!(verify_signature(WEBHOOK_SECRET, signature, &request.body));
+        assert!(!verify_signature("other", signature, &request.body));
+        assert!(!verify_signature(WEBHOOK_SECRET, signature, "{}"));
+    }
+    let body: Value = serde_json::from_str(&received[2].body).unwrap();
+    assert_eq!(body["type"], "payment.settled");
+    assert_eq!(body["id"], event.id);
+    assert_eq!(body["data"]["paymentId"], 4);
+
+    // Endpoints only get the event types they subscribed to
+    assert!(others.received().is_empty());
+
+    assert_eq!(fast_retry(5).backoff(1), Duration::from_millis(10));
+    assert_eq!(fast_retry(5).backoff(2), Duration::from_millis(20));
+    assert_eq!(fast_retry(5).backoff(4), Duration::from_millis(40));
+}
+
+#[tokio::test]
+async fn test_webhook_dead_letter_and_replay() {
+    let receiver = Receiver::start(2).await;
+    let log = env::temp_dir().join(format!("x402-dead-letter-{}.jsonl", std::process::id()));
+    let _ = fs::remove_file(&log);
+    let webhooks = Arc::new(
+        Webhooks::new(vec![receiver.endpoint(vec![])])
+            .with_retry(fast_retry(2))
+            .with_dead_letter_log(&log),
+    );
+
+    let event = webhooks.event(EventKind::PaymentFailed, json!({ "error": "rpc down" }));
+    let failed = webhooks.dispatch(&event).await;
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].attempts, 2);
+    assert_eq!(failed[0].event, event);
+    assert!(failed[0].error.contains("500"), "{}", failed[0].error);
+    let logged = fs::read_to_string(&log).unwrap();
+    assert_eq!(logged.lines().count(), 1);
+    assert!(logged.contains(&failed[0].id));
+    fs::remove_file(&log).unwrap();
+
+    let app = admin_router(webhooks.clone(), "admin-token");
+    let authorized = |request: axum::http::request::Builder| {
+        request
+            .header(AUTHORIZATION, "Bearer admin-token")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let (status, _) = get(&app, "/admin/webhooks/failed").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = send(&app, authorized(Request::get("/admin/webhooks/failed"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["id"], failed[0].id);
+    assert_eq!(body[0]["event"]["type"], "payment.failed");
+
+    // The third attempt succeeds
+    let replay = format!("/admin/webhooks/failed/{}/replay", failed[0].id);
+    let (status, _) = send(&app, authorized(Request::post(&replay))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(webhooks.failed().is_empty());
+    assert_eq!(receiver.received().len(), 3);
+    let (status, _) = send(&app, authorized(Request::post(&replay))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Beyond its capacity, the oldest dead letters are dropped
+    let down = Receiver::start(usize::MAX).await;
+    let webhooks = Webhooks::new(vec![down.endpoint(vec![])])
+        .with_retry(fast_retry(1))
+        .with_dead_letter_capacity(2);
+    let mut ids = Vec::new();
+    for payment_id in 0..3 {
+        let event = webhooks.event(EventKind::PaymentFailed, json!({ "paymentId": payment_id }));
+        ids.push(webhooks.dispatch(&event).await.remove(0).id);
+    }
+    let kept: Vec<_> = webhooks.failed().into_iter().map(|d| d.id).collect();
+    assert_eq!(kept, ids[1..]);
+}
+
+#[tokio::test]
+async fn test_settle_emits_webhook() {
+    let receiver = Receiver::start(0).await;
+    let webhooks = Arc::new(Webhooks::new(vec![receiver.endpoint(vec![])]));
+    let s = setup_with(Some(webhooks)).await;
+
+    let settled = settle(
+        &s,
+        header(signed_payload(
+            &s.contract_id,
+            s.escrow_id,
+            &s.client_addr,
+            "400000",
+            1,
+        )),
+    )
+    .await;
+    assert!(settled.success, "{settled:?}");
+
+    // Deliveries run in the background
+    let mut received = receiver.received();
+    for _ in 0..100 {
+        if !received.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        received = receiver.received();
+    }
+    assert_eq!(received.len(), 1);
+    let body: Value = serde_json::from_str(&received[0].body).unwrap();
+    assert_eq!(body["type"], "payment.settled");
+    assert_eq!(
+        body["data"],
+        json!({
+            "paymentId": payment_id(s.escrow_id, 0),
+            "escrowId": s.escrow_id,
+            "client": s.client_addr,
+            "server": s.server_addr,
+            "amount": "400000",
+            "txHash": settled.tx_hash.unwrap(),
+            "network": NETWORK,
+        })
+    );
+}
+
+/// Transport reporting the latest ledger `ahead` ledgers past the
+/// environment's
+struct AdvancingLedger {
+    inner: EnvTransport,
+    ahead: Arc<AtomicU32>,
+}
+
+#[async_trait]
+impl Transport for AdvancingLedger {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        let mut response = self.inner.request(method, params).await?;
+        if method == "getLatestLedger" {
+            let sequence = response["sequence"].as_u64().unwrap() as u32;
+            response["sequence"] = json!(sequence + self.ahead.load(Ordering::SeqCst));
+        }
+        Ok(response)
+    }
+}
+
+#[tokio::test]
+async fn test_settlement_finality() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let ahead = Arc::new(AtomicU32::new(0));
+    let rpc = Rpc::new(AdvancingLedger {
+        inner: transport,
+        ahead: ahead.clone(),
+    });
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let client = signer(&CLIENT_SEED);
+    let client_addr = client.address();
+    let receiver = Receiver::start(0).await;
+    let webhooks = Webhooks::new(vec![receiver.endpoint(vec![])]);
+    let facilitator = Facilitator::new(signer(&SERVER_SEED), NETWORK)
+        .with_webhooks(Arc::new(webhooks))
+        .with_finality(FinalityPolicy::new(5).final_up_to(100_000));
+    let server_addr = facilitator.server().to_string();
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let request = |amount: &str, nonce| SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header: header(signed_payload(
+            &contract_id,
+            escrow_id,
+            &client_addr,
+            amount,
+            nonce,
+        )),
+        payment_requirements: requirements(&server_addr),
+        settle_amount: None,
+        dry_run: false,
+    };
+    let received = |count| async move {
+        for _ in 0..100 {
+            if receiver.received().len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let received = receiver.received();
+        assert_eq!(received.len(), count);
+        received
+            .iter()
+            .map(|r| serde_json::from_str::<Value>(&r.body).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    // Large payments wait for the confirmation depth
+    let large = facilitator.settle(&request("400000", 1)).await;
+    assert!(large.success, "{large:?}");
+    assert_eq!(large.finality, Some(Finality::Included));
+    let ledger = large.ledger.expect("inclusion ledger");
+    let events = received.clone()(1).await;
+    assert_eq!(events[0]["type"], "payment.settled");
+    assert_eq!(events[0]["data"]["ledger"], ledger);
+    assert_eq!(events[0]["data"]["finality"], "included");
+
+    // Small ones are final on inclusion
+    let small = facilitator.settle(&request("50000", 2)).await;
+    assert_eq!(small.finality, Some(Finality::Final));
+    let events = received.clone()(2).await;
+    assert_eq!(events[1]["data"]["finality"], "final");
+
+    // The small payment closed the latest ledger, reported `ahead` of it
+    let latest = small.ledger.unwrap();
+    ahead.store(ledger + 4 - latest, Ordering::SeqCst);
+    assert_eq!(facilitator.finalize_settlements().await.unwrap(), 0);
+    ahead.store(ledger + 5 - latest, Ordering::SeqCst);
+    assert_eq!(facilitator.finalize_settlements().await.unwrap(), 1);
+    assert_eq!(facilitator.finalize_settlements().await.unwrap(), 0);
+    let events = received(3).await;
+    assert_eq!(events[2]["type"], "payment.finalized");
+    assert_eq!(events[2]["data"]["paymentId"], large.payment_id.unwrap());
+    assert_eq!(events[2]["data"]["txHash"], large.tx_hash.unwrap());
+    assert_eq!(events[2]["data"]["ledger"], ledger);
+    assert_eq!(events[2]["data"]["finality"], "final");
+}
+
+#[test]
+fn test_webhook_endpoint_config() {
+    let endpoint = parse_endpoint("https://hooks.example.com/x402?a=b", "secret").unwrap();
+    assert_eq!(endpoint.url, "https://hooks.example.com/x402?a=b");
+    assert!(endpoint.events.is_empty());
+
+    let endpoint = parse_endpoint(
+        "payment.settled|payment.failed=https://hooks.example.com/x402",
+        "secret",
+    )
+    .unwrap();
+    assert_eq!(endpoint.url, "https://hooks.example.com/x402");
+    assert_eq!(
+        endpoint.events,
+        [EventKind::PaymentSettled, EventKind::PaymentFailed]
+    );
+
+    assert!(parse_endpoint("payment.disputed=https://hooks.example.com", "secret").is_err());
+}
+
+/// Value of the sample `name` whose labels include `labels`
+fn sample(metrics: &str, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+    metrics.lines().find_map(|line| {
+        let (series, value) = line.rsplit_once(' ')?;
+        let (series_name, series_labels) = match series.split_once('{') {
+            Some((series_name, rest)) => (series_name, rest.trim_end_matches('}')),
+            None => (series, ""),
+        };
+        let matches = series_name == name
+            && labels.iter().all(|(key, value)| {
+                series_labels
+                    .split(',')
+                    .any(|pair| pair == format!("{key}=\"{value}\""))
+            });
+        matches.then(|| value.parse().ok()).flatten()
+    })
+}
+
+#[tokio::test]
+async fn test_metrics() {
+    let s = setup().await;
+    let payment = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "400000",
+        1,
+    ));
+    assert!(verify(&s, payment.clone()).await.is_valid);
+    assert!(settle(&s, payment.clone()).await.success);
+    assert!(!settle(&s, payment).await.success);
+
+    let response = s
+        .app
+        .clone()
+        .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "text/plain; version=0.0.4"
+    );
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let metrics = String::from_utf8(bytes.to_vec()).unwrap();
+
+    // Every sample carries the contract and network labels
+    let contract_id = s.client.contract_id();
+    let scope = [("contract_id", contract_id.as_str()), ("network", NETWORK)];
+    let value = |name: &str, labels: &[(&str, &str)]| {
+        sample(&metrics, name, &[&scope[..], labels].concat())
+    };
+
+    assert_eq!(
+        value("x402_facilitator_requests_total", &[("endpoint", "verify")]),
+        Some(1.0)
+    );
+    assert_eq!(
+        value("x402_facilitator_requests_total", &[("endpoint", "settle")]),
+        Some(2.0)
+    );
+    assert_eq!(
+        value(
+            "x402_facilitator_request_duration_seconds_count",
+            &[("endpoint", "settle")]
+        ),
+        Some(2.0)
+    );
+    assert_eq!(
+        value(
+            "x402_facilitator_settlements_total",
+            &[("result", "success")]
+        ),
+        Some(1.0)
+    );
+    assert_eq!(
+        value(
+            "x402_facilitator_settlements_total",
+            &[("result", "failure"), ("error", "nonce_used")]
+        ),
+        Some(1.0)
+    );
+    assert_eq!(
+        value(
+            "x402_facilitator_settled_volume_total",
+            &[("asset", "native")]
+        ),
+        Some(400_000.0)
+    );
+    assert_eq!(
+        value("x402_facilitator_pending_settlements", &[]),
+        Some(0.0)
+    );
+    for call in ["get_escrow", "create_payment", "settle_payment"] {
+        assert!(
+            value(
+                "x402_facilitator_rpc_duration_seconds_count",
+                &[("call", call)]
+            )
+            .is_some_and(|count| count >= 1.0),
+            "{call}"
+        );
+    }
+}
+
+/// Connection failure injected around a `sendTransaction` call
+#[derive(Clone, Copy)]
+enum Fault {
+    /// Forward the call
+    Pass,
+    /// Fail before the transaction reaches the network
+    Drop,
+    /// Apply the transaction but lose the reply
+    LoseReply,
+    /// Answer that the transaction is pending without applying it, as when
+    /// it is stuck during surge pricing
+    Stick,
+}
+
+/// Transport failing the `sendTransaction` calls it was told to
+struct FlakyTransport {
+    inner: EnvTransport,
+    faults: Arc<Mutex<VecDeque<Fault>>>,
+    /// Envelopes of the transactions left stuck
+    stuck: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl Transport for FlakyTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        let fault = match method {
+            "sendTransaction" => self.faults.lock().unwrap().pop_front(),
+            _ => None,
+        };
+        let lost = || Err(ClientError::Transport("connection reset".into()));
+        match fault.unwrap_or(Fault::Pass) {
+            Fault::Pass => self.inner.request(method, params).await,
+            Fault::Drop => lost(),
+            Fault::LoseReply => {
+                self.inner.request(method, params).await?;
+                lost()
+            }
+            Fault::Stick => {
+                let envelope = params["transaction"].as_str().unwrap_or_default();
+                self.stuck.lock().unwrap().push(envelope.into());
+                Ok(json!({ "status": "PENDING", "hash": "", "latestLedger": 0 }))
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_settlement_queue_recovers_exactly_once() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let faults = Arc::new(Mutex::new(VecDeque::new()));
+    let rpc = Rpc::new(FlakyTransport {
+        inner: transport,
+        faults: faults.clone(),
+        stuck: Default::default(),
+    });
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let client = signer(&CLIENT_SEED);
+    let client_addr = client.address();
+    let path = env::temp_dir().join(format!("x402-queue-{}.sqlite", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let start = || {
+        let queue = SettlementQueue::open(&path)
+            .unwrap()
+            .with_retry(fast_retry(5));
+        let facilitator = Facilitator::new(signer(&SERVER_SEED), NETWORK)
+            .with_queue(queue)
+            .unwrap();
+        Arc::new(facilitator)
+    };
+
+    let facilitator = start();
+    let server_addr = facilitator.server().to_string();
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    // Queue the faults of the next submissions, then build the request
+    let request = |nonce, amount: &str, plan: &[Fault]| {
+        faults.lock().unwrap().extend(plan);
+        SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: header(signed_payload(
+                &contract_id,
+                escrow_id,
+                &client_addr,
+                amount,
+                nonce,
+            )),
+            payment_requirements: requirements(&server_addr),
+            settle_amount: None,
+            dry_run: false,
+        }
+    };
+
+    let settled = facilitator.settle(&request(1, "400000", &[])).await;
+    assert!(settled.tx_hash.is_some(), "{settled:?}");
+
+    // Created on-chain, the reply lost
+    let created = facilitator
+        .settle(&request(2, "300000", &[Fault::LoseReply]))
+        .await;
+    assert!(created.success, "{created:?}");
+    assert_eq!((created.payment_id, created.tx_hash), (None, None));
+
+    // Created and settled on-chain, the settlement reply lost
+    let plan = [Fault::Pass, Fault::LoseReply];
+    let settling = facilitator.settle(&request(3, "200000", &plan)).await;
+    assert!(settling.success, "{settling:?}");
+    assert_eq!(
+        (settling.payment_id, settling.tx_hash),
+        (Some(payment_id(escrow_id, 2)), None)
+    );
+
+    // Never submitted, then its sequence number is taken by another payment
+    let dropped = facilitator
+        .settle(&request(4, "100000", &[Fault::Drop]))
+        .await;
+    assert!(dropped.success, "{dropped:?}");
+    let other = facilitator.settle(&request(5, "50000", &[])).await;
+    assert!(other.tx_hash.is_some(), "{other:?}");
+    assert_eq!(facilitator.queue().unwrap().depth().unwrap(), 3);
+
+    // Kill the facilitator and start another on the same queue
+    drop(facilitator);
+    let facilitator = start();
+    let err = facilitator
+        .check(
+            &header(signed_payload(
+                &contract_id,
+                escrow_id,
+                &client_addr,
+                "300000",
+                2,
+            )),
+            &requirements(&server_addr),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::NonceUsed), "{err}");
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(facilitator.process_queue().await.unwrap(), 3);
+    assert_eq!(facilitator.process_queue().await.unwrap(), 0);
+    let jobs = facilitator.queue().unwrap().jobs().unwrap();
+    assert!(jobs.iter().all(|job| job.state == JobState::Settled));
+    assert!(jobs.iter().all(|job| job.pending_tx.is_none()));
+    let payment_ids: Vec<_> = jobs.iter().map(|job| job.payment_id.unwrap()).collect();
+    let expected: Vec<_> = [0, 1, 2, 4, 3]
+        .into_iter()
+        .map(|index| payment_id(escrow_id, index))
+        .collect();
+    assert_eq!(payment_ids, expected);
+
+    // Exactly one settled payment per authorization
+    for index in 0..5 {
+        assert!(
+            client
+                .get_payment(payment_id(escrow_id, index))
+                .await
+                .unwrap()
+                .settled
+        );
+    }
+    assert!(matches!(
+        client.get_payment(payment_id(escrow_id, 5)).await,
+        Err(ClientError::Contract(ContractError::PaymentNotFound, _))
+    ));
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        10_000_000 - 1_050_000
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_key_rotation_mid_queue() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let handle = transport.clone();
+    let contract_id = transport.contract_id().to_string();
+    let faults = Arc::new(Mutex::new(VecDeque::new()));
+    let rpc = Rpc::new(FlakyTransport {
+        inner: transport,
+        faults: faults.clone(),
+        stuck: Default::default(),
+    });
+    let signer = |signer: LocalSigner| {
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, signer).unwrap()
+    };
+    let client = signer(LocalSigner::from_bytes(&CLIENT_SEED));
+    let client_addr = client.address();
+    let path = env::temp_dir().join(format!("x402-rotation-{}.sqlite", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let queue = SettlementQueue::open(&path)
+        .unwrap()
+        .with_retry(fast_retry(5));
+    let facilitator = Facilitator::new(signer(LocalSigner::from_bytes(&SERVER_SEED)), NETWORK)
+        .with_queue(queue)
+        .unwrap();
+    let server_addr = facilitator.server().to_string();
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let request = |nonce, plan: &[Fault]| {
+        faults.lock().unwrap().extend(plan);
+        SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: header(signed_payload(
+                &contract_id,
+                escrow_id,
+                &client_addr,
+                "250000",
+                nonce,
+            )),
+            payment_requirements: requirements(&server_addr),
+            settle_amount: None,
+            dry_run: false,
+        }
+    };
+
+    let settled = facilitator.settle(&request(1, &[])).await;
+    assert!(settled.tx_hash.is_some(), "{settled:?}");
+    // Queued with a transaction signed by the old key, never submitted
+    let dropped = facilitator.settle(&request(2, &[Fault::Drop])).await;
+    assert!(dropped.success, "{dropped:?}");
+    // Queued with its settlement applied, the reply lost
+    let plan = [Fault::Pass, Fault::LoseReply];
+    let settling = facilitator.settle(&request(3, &plan)).await;
+    assert!(settling.success, "{settling:?}");
+    assert_eq!(facilitator.queue().unwrap().depth().unwrap(), 2);
+
+    // Another account cannot take over
+    let other = signer(LocalSigner::from_bytes(&[4; 32]));
+    let err = facilitator.rotate_signer(other).unwrap_err();
+    assert!(matches!(err, AdminError::WrongServer(_)), "{err}");
+
+    // Add the new key to the server account, rotate, then remove the old one
+    let next = LocalSigner::from_bytes(&[4; 32]);
+    handle.add_signer(&server_addr, &next.address());
+    let rotated = signer(next.for_account(&server_addr).unwrap());
+    facilitator.rotate_signer(rotated).unwrap();
+    handle.remove_signer(&server_addr, &server_addr);
+    assert_eq!(facilitator.server(), server_addr);
+
+    let after = facilitator.settle(&request(4, &[])).await;
+    assert!(after.tx_hash.is_some(), "{after:?}");
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(facilitator.process_queue().await.unwrap(), 2);
+    assert_eq!(facilitator.process_queue().await.unwrap(), 0);
+    let jobs = facilitator.queue().unwrap().jobs().unwrap();
+    assert!(jobs.iter().all(|job| job.state == JobState::Settled));
+
+    // Exactly one settled payment per authorization
+    for index in 0..4 {
+        assert!(
+            client
+                .get_payment(payment_id(escrow_id, index))
+                .await
+                .unwrap()
+                .settled
+        );
+    }
+    assert!(matches!(
+        client.get_payment(payment_id(escrow_id, 4)).await,
+        Err(ClientError::Contract(ContractError::PaymentNotFound, _))
+    ));
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        10_000_000 - 1_000_000
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+/// Transport failing every request while the outage lasts
+struct OutageTransport {
+    inner: EnvTransport,
+    down: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl Transport for OutageTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        if self.down.load(Ordering::SeqCst) {
+            return Err(ClientError::Transport("connection refused".into()));
+        }
+        self.inner.request(method, params).await
+    }
+}
+
+#[tokio::test]
+async fn test_degraded_serve_and_queue() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let down = Arc::new(AtomicBool::new(false));
+    let rpc = Rpc::new(OutageTransport {
+        inner: transport,
+        down: down.clone(),
+    });
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let client = signer(&CLIENT_SEED);
+    let client_addr = client.address();
+    let path = env::temp_dir().join(format!("x402-outage-{}.sqlite", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let queue = SettlementQueue::open(&path)
+        .unwrap()
+        .with_retry(fast_retry(50));
+    let facilitator = Facilitator::new(signer(&SERVER_SEED), NETWORK)
+        .with_queue(queue)
+        .unwrap();
+    let server_addr = facilitator.server().to_string();
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let settle = |nonce, amount: &str| SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header: header(signed_payload(
+            &contract_id,
+            escrow_id,
+            &client_addr,
+            amount,
+            nonce,
+        )),
+        payment_requirements: requirements(&server_addr),
+        settle_amount: None,
+        dry_run: false,
+    };
+    let credit = |facilitator: &Facilitator| {
+        let metrics = facilitator.metrics().render();
+        sample(&metrics, "x402_facilitator_credit_outstanding", &[])
+    };
+
+    // Read while RPC is up, the escrow is known
+    let settled = facilitator.settle(&settle(1, "100000")).await;
+    assert!(settled.tx_hash.is_some(), "{settled:?}");
+
+    // Failing closed by default
+    down.store(true, Ordering::SeqCst);
+    let err = facilitator
+        .check(
+            &settle(2, "300000").payment_header,
+            &requirements(&server_addr),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::Rpc(_)), "{err}");
+
+    facilitator.set_settings(Settings {
+        degradation: Degradation::ServeAndQueue {
+            max_credit: 500_000,
+        },
+        ..Settings::default()
+    });
+    for (nonce, amount) in [(2, "300000"), (3, "200000")] {
+        let queued = facilitator.settle(&settle(nonce, amount)).await;
+        assert!(queued.success, "{queued:?}");
+        assert_eq!(queued.tx_hash, None);
+    }
+    assert_eq!(credit(&facilitator), Some(500_000.0));
+
+    // The credit cap holds, at verification and settlement
+    let err = facilitator
+        .check(
+            &settle(4, "100000").payment_header,
+            &requirements(&server_addr),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::CreditExhausted), "{err}");
+    let rejected = facilitator.settle(&settle(4, "100000")).await;
+    assert_eq!(rejected.error.as_deref(), Some("credit_exhausted"));
+    assert_eq!(facilitator.queue().unwrap().depth().unwrap(), 2);
+
+    // Settled once RPC is back, releasing the credit
+    down.store(false, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(facilitator.process_queue().await.unwrap(), 2);
+    assert_eq!(facilitator.queue().unwrap().depth().unwrap(), 0);
+    assert_eq!(credit(&facilitator), Some(0.0));
+    for index in 0..3 {
+        assert!(
+            client
+                .get_payment(payment_id(escrow_id, index))
+                .await
+                .unwrap()
+                .settled
+        );
+    }
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        10_000_000 - 600_000
+    );
+
+    // Paid again from the escrow as read now
+    let settled = facilitator.settle(&settle(4, "100000")).await;
+    assert!(settled.tx_hash.is_some(), "{settled:?}");
+    fs::remove_file(&path).unwrap();
+}
+
+/// Signer of the server account that cannot sign, e.g. an unreachable HSM
+struct UnavailableSigner;
+
+#[async_trait]
+impl Signer for UnavailableSigner {
+    fn public_key(&self) -> [u8; 32] {
+        LocalSigner::from_bytes(&SERVER_SEED).public_key()
+    }
+
+    async fn sign(&self, _hash: &[u8; 32]) -> Result<[u8; 64], ClientError> {
+        Err(ClientError::Signer("HSM unreachable".into()))
+    }
+}
+
+#[tokio::test]
+async fn test_health_endpoints() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let down = Arc::new(AtomicBool::new(false));
+    let rpc = Rpc::new(OutageTransport {
+        inner: transport,
+        down: down.clone(),
+    });
+    let server = |contract_id: &str| {
+        EscrowClient::new(
+            rpc.clone(),
+            contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(&SERVER_SEED),
+        )
+        .unwrap()
+    };
+    let facilitator = Facilitator::new(server(&contract_id), NETWORK)
+        .with_queue(SettlementQueue::open_in_memory().unwrap())
+        .unwrap()
+        .with_settings(Settings {
+            readiness: ReadinessPolicy {
+                max_queue_depth: 1,
+                ..ReadinessPolicy::default()
+            },
+            ..Settings::default()
+        });
+    let facilitator = Arc::new(facilitator);
+    let app = router(facilitator.clone());
+    let failing = |body: &Value| -> Vec<String> {
+        let checks = body["checks"].as_array().unwrap().iter();
+        checks
+            .filter(|check| check["ok"] == false)
+            .map(|check| check["name"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let (status, body) = get(&app, "/healthz").await;
+    assert_eq!((status, body), (StatusCode::OK, json!({ "status": "ok" })));
+    let (status, body) = get(&app, "/readyz").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["ready"], true);
+    let names: Vec<_> = body["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|check| check["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["shutdown", "rpc", "contract", "queue", "signer"]);
+    assert_eq!(body["checks"][2]["detail"], "version 1");
+    assert_eq!(body["checks"][3]["detail"], "0 unfinished jobs");
+
+    // RPC down, the contract cannot be reached either, the process is alive
+    down.store(true, Ordering::SeqCst);
+    let (status, body) = get(&app, "/readyz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["ready"], false);
+    assert_eq!(failing(&body), ["rpc", "contract"]);
+    assert_eq!(
+        body["checks"][1]["detail"],
+        "transport error: connection refused"
+    );
+    assert_eq!(get(&app, "/healthz").await.0, StatusCode::OK);
+    down.store(false, Ordering::SeqCst);
+
+    // No escrow contract at the configured address
+    let missing = stellar_strkey::Contract([7; 32]).to_string();
+    let readiness = Facilitator::new(server(&missing), NETWORK)
+        .readiness()
+        .await;
+    assert!(!readiness.ready);
+    assert!(!readiness.check("contract").unwrap().ok);
+    assert!(readiness.check("rpc").unwrap().ok);
+
+    // Signer unavailable
+    let unavailable = EscrowClient::new(
+        rpc.clone(),
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        UnavailableSigner,
+    )
+    .unwrap();
+    facilitator.rotate_signer(unavailable).unwrap();
+    let (status, body) = get(&app, "/readyz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(failing(&body), ["signer"]);
+    assert_eq!(body["checks"][4]["detail"], "signer error: HSM unreachable");
+    facilitator.rotate_signer(server(&contract_id)).unwrap();
+    assert_eq!(get(&app, "/readyz").await.0, StatusCode::OK);
+
+    // Settlement queue backlogged beyond the threshold
+    let queue = facilitator.queue().unwrap();
+    for nonce in [1, 2] {
+        let payment = VerifiedPayment {
+            escrow_id: 0,
+            client: LocalSigner::from_bytes(&CLIENT_SEED).address(),
+            amount: 1_000,
+            nonce,
+            tx_hash: None,
+            context: None,
+        };
+        queue.enqueue(&payment, "XLM", None).unwrap().unwrap();
+    }
+    let (status, body) = get(&app, "/readyz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(failing(&body), ["queue"]);
+    assert_eq!(body["checks"][3]["detail"], "2 unfinished jobs, above 1");
+
+    // Draining
+    facilitator.shutdown(Duration::ZERO).await;
+    let (_, body) = get(&app, "/readyz").await;
+    assert_eq!(failing(&body), ["shutdown", "queue"]);
+    assert_eq!(get(&app, "/healthz").await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_settlement_queue_bumps_stuck_fees() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let faults = Arc::new(Mutex::new(VecDeque::new()));
+    let stuck = Arc::new(Mutex::new(vec![]));
+    let rpc = Rpc::new(FlakyTransport {
+        inner: transport,
+        faults: faults.clone(),
+        stuck: stuck.clone(),
+    });
+    let client_key = LocalSigner::from_bytes(&CLIENT_SEED);
+    let client_addr = client_key.address();
+    let client =
+        EscrowClient::new(rpc.clone(), &contract_id, NETWORK_PASSPHRASE, client_key).unwrap();
+    let server = EscrowClient::new(
+        rpc.clone(),
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&SERVER_SEED),
+    )
+    .unwrap()
+    .with_options(ClientOptions {
+        poll_interval: Duration::from_millis(1),
+        ..ClientOptions::default()
+    })
+    .with_fee_bump(
+        LocalSigner::from_bytes(&[3; 32]),
+        FeeBumpPolicy {
+            after: Duration::from_millis(20),
+            ..FeeBumpPolicy::default()
+        },
+    );
+    let path = env::temp_dir().join(format!("x402-bump-{}.sqlite", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let queue = SettlementQueue::open(&path)
+        .unwrap()
+        .with_retry(fast_retry(5));
+    let facilitator = Facilitator::new(server, NETWORK).with_queue(queue).unwrap();
+    let server_addr = facilitator.server().to_string();
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    // Both the payment and its settlement get stuck, then apply as bumps
+    faults.lock().unwrap().extend([Fault::Stick, Fault::Stick]);
+    let settled = facilitator
+        .settle(&SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: header(signed_payload(
+                &contract_id,
+                escrow_id,
+                &client_addr,
+                "400000",
+                1,
+            )),
+            payment_requirements: requirements(&server_addr),
+            settle_amount: None,
+            dry_run: false,
+        })
+        .await;
+    assert!(settled.success, "{settled:?}");
+    assert_eq!(settled.payment_id, Some(payment_id(escrow_id, 0)));
+    let job = &facilitator.queue().unwrap().jobs().unwrap()[0];
+    assert_eq!(job.state, JobState::Settled);
+    assert_eq!(job.tx_hash, settled.tx_hash);
+
+    // The originals landing late are rejected, payment and settlement
+    // applying once
+    let stuck = stuck.lock().unwrap().clone();
+    assert_eq!(stuck.len(), 2);
+    for envelope in stuck {
+        let envelope = TransactionEnvelope::from_xdr_base64(envelope, Limits::none()).unwrap();
+        let sent = rpc.send_transaction(&envelope).await.unwrap();
+        assert_eq!(sent.status, "ERROR");
+    }
+    assert!(
+        client
+            .get_payment(payment_id(escrow_id, 0))
+            .await
+            .unwrap()
+            .settled
+    );
+    assert!(matches!(
+        client.get_payment(payment_id(escrow_id, 1)).await,
+        Err(ClientError::Contract(ContractError::PaymentNotFound, _))
+    ));
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        9_600_000
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_batch_settlement_scheduler() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport);
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let client = signer(&CLIENT_SEED);
+    let client_addr = client.address();
+    let queue = SettlementQueue::open_in_memory()
+        .unwrap()
+        .with_batching(BatchPolicy {
+            max_size: 20,
+            max_delay: Duration::from_millis(200),
+        });
+    let facilitator = Facilitator::new(signer(&SERVER_SEED), NETWORK)
+        .with_queue(queue)
+        .unwrap();
+    let server_addr = facilitator.server().to_string();
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    // A burst of 50 payments, flushed each time 20 are waiting
+    for nonce in 0..50 {
+        let request = SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: header(signed_payload(
+                &contract_id,
+                escrow_id,
+                &client_addr,
+                "10000",
+                nonce,
+            )),
+            payment_requirements: requirements(&server_addr),
+            settle_amount: None,
+            dry_run: false,
+        };
+        let response = facilitator.settle(&request).await;
+        assert!(response.success, "{response:?}");
+        assert_eq!(
+            response.payment_id,
+            Some(payment_id(escrow_id, nonce as u32))
+        );
+        assert_eq!(response.tx_hash.is_some(), nonce % 20 == 19, "{response:?}");
+    }
+    let queue = facilitator.queue().unwrap();
+    assert_eq!(queue.depth().unwrap(), 10);
+
+    // The rest once the oldest waited long enough
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(facilitator.flush_batches().await.unwrap(), 1);
+    assert_eq!(facilitator.flush_batches().await.unwrap(), 0);
+    assert_eq!(queue.depth().unwrap(), 0);
+
+    let batches = queue.batches().unwrap();
+    let sizes: Vec<_> = batches
+        .iter()
+        .map(|batch| batch.payment_ids.len())
+        .collect();
+    assert_eq!(sizes, [20, 20, 10]);
+    assert!(batches
+        .iter()
+        .all(|batch| batch.state == BatchState::Settled && batch.tx_hash.is_some()));
+    for job in queue.jobs().unwrap() {
+        assert_eq!(job.state, JobState::Settled);
+        let batch = batches.iter().find(|batch| Some(batch.id) == job.batch_id);
+        assert_eq!(job.tx_hash, batch.unwrap().tx_hash);
+    }
+    for index in 0..50 {
+        let payment = client.get_payment(payment_id(escrow_id, index)).await;
+        assert!(payment.unwrap().settled);
+    }
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        10_000_000 - 500_000
+    );
+}
+
+/// Transport delaying the replies of the `sendTransaction` calls it was told
+/// to until released, as a slow RPC would
+struct SlowTransport {
+    inner: EnvTransport,
+    /// Whether the reply of each next call is held, calls past the plan
+    /// being answered at once
+    plan: Arc<Mutex<VecDeque<bool>>>,
+    /// Notified once a reply is held, its transaction applied
+    held: Arc<Notify>,
+    /// Notified to let a held reply through
+    release: Arc<Notify>,
+    /// Number of `sendTransaction` calls
+    sent: Arc<AtomicU32>,
+}
+
+#[async_trait]
+impl Transport for SlowTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        if method != "sendTransaction" {
+            return self.inner.request(method, params).await;
+        }
+        self.sent.fetch_add(1, Ordering::SeqCst);
+        let reply = self.inner.request(method, params).await;
+        if self.plan.lock().unwrap().pop_front() == Some(true) {
+            self.held.notify_one();
+            self.release.notified().await;
+        }
+        reply
+    }
+}
+
+/// Facilitators taking turns on one batching queue, over a slow RPC
+struct Restarts {
+    rpc: Rpc,
+    contract_id: String,
+    path: std::path::PathBuf,
+    plan: Arc<Mutex<VecDeque<bool>>>,
+    held: Arc<Notify>,
+    release: Arc<Notify>,
+    sent: Arc<AtomicU32>,
+}
+
+impl Restarts {
+    fn new(name: &str) -> Self {
+        let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+        let contract_id = transport.contract_id().to_string();
+        let (plan, held, release, sent) = Default::default();
+        let rpc = Rpc::new(SlowTransport {
+            inner: transport,
+            plan: Arc::clone(&plan),
+            held: Arc::clone(&held),
+            release: Arc::clone(&release),
+            sent: Arc::clone(&sent),
+        });
+        let path = env::temp_dir().join(format!("x402-{name}-{}.sqlite", std::process::id()));
+        let _ = fs::remove_file(&path);
+        Self {
+            rpc,
+            contract_id,
+            path,
+            plan,
+            held,
+            release,
+            sent,
+        }
+    }
+
+    fn signer(&self, seed: &[u8; 32]) -> EscrowClient {
+        EscrowClient::new(
+            self.rpc.clone(),
+            &self.contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    }
+
+    /// Start a facilitator batching payments three by three
+    fn start(&self) -> Arc<Facilitator> {
+        let queue = SettlementQueue::open(&self.path)
+            .unwrap()
+            .with_batching(BatchPolicy {
+                max_size: 3,
+                max_delay: Duration::from_secs(3600),
+            });
+        let facilitator = Facilitator::new(self.signer(&SERVER_SEED), NETWORK)
+            .with_queue(queue)
+            .unwrap();
+        Arc::new(facilitator)
+    }
+
+    /// Settle two payments waiting for their batch, then start settling the
+    /// third in the background, the batch transaction applied but its reply
+    /// held
+    async fn settle_mid_batch(
+        &self,
+        facilitator: &Arc<Facilitator>,
+        client: &EscrowClient,
+        escrow_id: u64,
+    ) -> tokio::task::JoinHandle<SettleResponse> {
+        let request = |nonce| SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: header(signed_payload(
+                &client.contract_id(),
+                escrow_id,
+                &client.address(),
+                "100000",
+                nonce,
+            )),
+            payment_requirements: requirements(facilitator.server()),
+            settle_amount: None,
+            dry_run: false,
+        };
+        for nonce in 0..2 {
+            let waiting = facilitator.settle(&request(nonce)).await;
+            assert_eq!(
+                waiting.payment_id,
+                Some(payment_id(escrow_id, nonce as u32)),
+                "{waiting:?}"
+            );
+            assert_eq!(waiting.tx_hash, None);
+        }
+        // Created at once, then the batch held
+        self.plan.lock().unwrap().extend([false, true]);
+        let settling = facilitator.clone();
+        let request = request(2);
+        let task = tokio::spawn(async move { settling.settle(&request).await });
+        self.held.notified().await;
+        task
+    }
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_in_flight_batch() {
+    let restarts = Restarts::new("drain");
+    let client = restarts.signer(&CLIENT_SEED);
+    let facilitator = restarts.start();
+    let server_addr = facilitator.server().to_string();
+    let escrow_id = client
+        .open_escrow(&client.address(), &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let settling = restarts
+        .settle_mid_batch(&facilitator, &client, escrow_id)
+        .await;
+
+    // SIGTERM while the batch is in flight
+    let draining = facilitator.clone();
+    let shutdown = tokio::spawn(async move { draining.shutdown(Duration::from_secs(10)).await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(facilitator.is_shutting_down());
+    assert!(!shutdown.is_finished());
+
+    // New settlements are refused, and not queued
+    let app = router(facilitator.clone());
+    let request = SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header: header(signed_payload(
+            &client.contract_id(),
+            escrow_id,
+            &client.address(),
+            "100000",
+            3,
+        )),
+        payment_requirements: requirements(&server_addr),
+        settle_amount: None,
+        dry_run: false,
+    };
+    let (status, body) = post(&app, "/settle", serde_json::to_value(&request).unwrap()).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["error"], SHUTTING_DOWN);
+    assert_eq!(facilitator.process_queue().await.unwrap(), 0);
+    assert_eq!(facilitator.flush_batches().await.unwrap(), 0);
+
+    // The batch lands, then the shutdown completes with nothing left
+    restarts.release.notify_one();
+    let settled = settling.await.unwrap();
+    assert!(settled.tx_hash.is_some(), "{settled:?}");
+    let report = shutdown.await.unwrap();
+    assert!(report.drained(), "{report:?}");
+    assert!(report.unfinished.is_empty());
+    // The escrow, three payments, and their batch
+    assert_eq!(restarts.sent.load(Ordering::SeqCst), 5);
+
+    // The next facilitator has nothing to do
+    drop(facilitator);
+    let facilitator = restarts.start();
+    let queue = facilitator.queue().unwrap();
+    assert_eq!(facilitator.process_queue().await.unwrap(), 0);
+    assert_eq!(facilitator.flush_batches().await.unwrap(), 0);
+    assert_eq!(queue.jobs().unwrap().len(), 3);
+    assert!(queue
+        .jobs()
+        .unwrap()
+        .iter()
+        .all(|job| job.state == JobState::Settled));
+    assert_eq!(queue.batches().unwrap().len(), 1);
+    assert_eq!(restarts.sent.load(Ordering::SeqCst), 5);
+    for index in 0..3 {
+        assert!(
+            client
+                .get_payment(payment_id(escrow_id, index))
+                .await
+                .unwrap()
+                .settled
+        );
+    }
+    assert!(matches!(
+        client.get_payment(payment_id(escrow_id, 3)).await,
+        Err(ClientError::Contract(ContractError::PaymentNotFound, _))
+    ));
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        9_700_000
+    );
+    fs::remove_file(&restarts.path).unwrap();
+}
+
+#[tokio::test]
+async fn test_shutdown_deadline_resumes_on_restart() {
+    let restarts = Restarts::new("deadline");
+    let client = restarts.signer(&CLIENT_SEED);
+    let facilitator = restarts.start();
+    let server_addr = facilitator.server().to_string();
+    let escrow_id = client
+        .open_escrow(&client.address(), &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let settling = restarts
+        .settle_mid_batch(&facilitator, &client, escrow_id)
+        .await;
+
+    // The batch outlives the grace period, then the process exits
+    let report = facilitator.shutdown(Duration::from_millis(50)).await;
+    assert_eq!(report.interrupted, 1);
+    let unfinished: Vec<_> = report.unfinished.iter().map(|job| job.nonce).collect();
+    assert_eq!(unfinished, [0, 1, 2]);
+    assert!(report
+        .unfinished
+        .iter()
+        .all(|job| job.state == JobState::Created && job.batch_id.is_some()));
+    settling.abort();
+    assert!(settling.await.unwrap_err().is_cancelled());
+    drop(facilitator);
+
+    // The batch applied before the exit is found rather than submitted again
+    let facilitator = restarts.start();
+    let err = facilitator
+        .check(
+            &header(signed_payload(
+                &contract_id,
+                escrow_id,
+                &client.address(),
+                "100000",
+                2,
+            )),
+            &requirements(&server_addr),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::NonceUsed), "{err}");
+    assert_eq!(facilitator.flush_batches().await.unwrap(), 1);
+    assert_eq!(facilitator.flush_batches().await.unwrap(), 0);
+    assert_eq!(restarts.sent.load(Ordering::SeqCst), 5);
+
+    let queue = facilitator.queue().unwrap();
+    assert_eq!(queue.depth().unwrap(), 0);
+    let batches = queue.batches().unwrap();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].state, BatchState::Settled);
+    for job in queue.jobs().unwrap() {
+        assert_eq!(job.state, JobState::Settled);
+        assert_eq!(job.tx_hash, batches[0].tx_hash);
+    }
+    for index in 0..3 {
+        assert!(
+            client
+                .get_payment(payment_id(escrow_id, index))
+                .await
+                .unwrap()
+                .settled
+        );
+    }
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        9_700_000
+    );
+    fs::remove_file(&restarts.path).unwrap();
+}
+
+/// Facilitators crashed by injected faults, then restarted on the same
+/// queue, the contract test env being the ground truth
+struct Crashes {
+    rpc: Rpc,
+    contract_id: String,
+    /// Queue database, None to settle without a queue
+    path: Option<std::path::PathBuf>,
+    faults: Arc<Faults>,
+}
+
+impl Crashes {
+    fn new(queue: Option<&str>) -> Self {
+        let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+        let contract_id = transport.contract_id().to_string();
+        let path = queue.map(|name| {
+            let file = format!("x402-crash-{name}-{}.sqlite", std::process::id());
+            let path = env::temp_dir().join(file);
+            let _ = fs::remove_file(&path);
+            path
+        });
+        Self {
+            rpc: Rpc::new(transport),
+            contract_id,
+            path,
+            faults: Arc::new(Faults::new()),
+        }
+    }
+
+    fn signer(&self, seed: &[u8; 32]) -> EscrowClient {
+        EscrowClient::new(
+            self.rpc.clone(),
+            &self.contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    }
+
+    /// Start a facilitator on the queue, if any
+    fn start(&self, batching: Option<BatchPolicy>) -> Arc<Facilitator> {
+        let facilitator =
+            Facilitator::new(self.signer(&SERVER_SEED), NETWORK).with_faults(self.faults.clone());
+        let Some(path) = &self.path else {
+            return Arc::new(facilitator);
+        };
+        let mut queue = SettlementQueue::open(path).unwrap();
+        if let Some(policy) = batching {
+            queue = queue.with_batching(policy);
+        }
+        Arc::new(facilitator.with_queue(queue).unwrap())
+    }
+
+    /// Open an escrow of the client with the server, and a request settling
+    /// 400000 of it
+    async fn open(&self, client: &EscrowClient) -> (u64, SettleRequest) {
+        let server = self.signer(&SERVER_SEED).address();
+        let escrow_id = client
+            .open_escrow(&client.address(), &server, 10_000_000, None)
+            .await
+            .unwrap()
+            .value;
+        let request = SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: header(signed_payload(
+                &contract_id,
+                escrow_id,
+                &client.address(),
+                "400000",
+                1,
+            )),
+            payment_requirements: requirements(&server),
+            settle_amount: None,
+            dry_run: false,
+        };
+        (escrow_id, request)
+    }
+}
+
+/// Settle `request` with a fault armed after `stage`, asserting the
+/// settlement crashed there
+async fn crash_after(
+    facilitator: &Arc<Facilitator>,
+    faults: &Faults,
+    request: &SettleRequest,
+    stage: Stage,
+) {
+    faults.crash_after(stage);
+    let settling = facilitator.clone();
+    let request = request.clone();
+    let crashed = tokio::spawn(async move { settling.settle(&request).await }).await;
+    assert!(crashed.unwrap_err().is_panic(), "no crash after {stage}");
+    assert!(!faults.is_armed(stage));
+}
+
+#[tokio::test]
+async fn test_crash_recovery_with_queue() {
+    let batched = BatchPolicy {
+        max_size: 1,
+        max_delay: Duration::from_secs(3600),
+    };
+    for (stage, batching) in [
+        (Stage::Verify, None),
+        (Stage::Persist, None),
+        (Stage::Submit, None),
+        (Stage::Confirm, None),
+        (Stage::Confirm, Some(batched)),
+    ] {
+        let crashes = Crashes::new(Some(&format!("{stage}-{}", batching.is_some())));
+        let client = crashes.signer(&CLIENT_SEED);
+        let facilitator = crashes.start(batching.clone());
+        let (escrow_id, request) = crashes.open(&client).await;
+        crash_after(&facilitator, &crashes.faults, &request, stage).await;
+        drop(facilitator);
+
+        // Nothing was charged before the job was persisted, so the retry
+        // settles; after, the next facilitator finishes the job
+        let facilitator = crashes.start(batching);
+        let retried = facilitator.settle(&request).await;
+        let resumed =
+            facilitator.process_queue().await.unwrap() + facilitator.flush_batches().await.unwrap();
+        if stage == Stage::Verify {
+            assert!(retried.success, "{retried:?}");
+            assert_eq!(resumed, 0);
+        } else {
+            assert_eq!(retried.error.as_deref(), Some("nonce_used"), "{stage}");
+            assert_eq!(resumed, 1, "{stage}");
+        }
+
+        // Charged exactly once
+        let jobs = facilitator.queue().unwrap().jobs().unwrap();
+        assert_eq!(jobs.len(), 1, "{stage}");
+        assert_eq!(jobs[0].state, JobState::Settled, "{stage}");
+        let payment = client.get_payment(payment_id(escrow_id, 0)).await.unwrap();
+        assert!(payment.settled, "{stage}");
+        assert!(
+            matches!(
+                client.get_payment(payment_id(escrow_id, 1)).await,
+                Err(ClientError::Contract(ContractError::PaymentNotFound, _))
+            ),
+            "{stage}"
+        );
+        let balance = client.get_escrow_balance(escrow_id).await.unwrap();
+        assert_eq!(balance, 9_600_000, "{stage}");
+        fs::remove_file(crashes.path.unwrap()).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_crash_recovery_without_queue() {
+    for stage in [Stage::Verify, Stage::Submit] {
+        let crashes = Crashes::new(None);
+        let client = crashes.signer(&CLIENT_SEED);
+        let facilitator = crashes.start(None);
+        let (escrow_id, request) = crashes.open(&client).await;
+        crash_after(&facilitator, &crashes.faults, &request, stage).await;
+        drop(facilitator);
+
+        // A payment created by the crashed facilitator is left unsettled,
+        // the retry charging once
+        let facilitator = crashes.start(None);
+        let retried = facilitator.settle(&request).await;
+        assert!(retried.success, "{retried:?}");
+        let index = u32::from(stage == Stage::Submit);
+        assert_eq!(retried.payment_id, Some(payment_id(escrow_id, index)));
+        if stage == Stage::Submit {
+            let payment = client.get_payment(payment_id(escrow_id, 0)).await.unwrap();
+            assert!(!payment.settled);
+        }
+        let balance = client.get_escrow_balance(escrow_id).await.unwrap();
+        assert_eq!(balance, 9_600_000, "{stage}");
+    }
+
+    // Nothing is persisted without a queue
+    let crashes = Crashes::new(None);
+    let client = crashes.signer(&CLIENT_SEED);
+    let facilitator = crashes.start(None);
+    let (_, request) = crashes.open(&client).await;
+    crashes.faults.crash_after(Stage::Persist);
+    assert!(facilitator.settle(&request).await.success);
+    assert!(crashes.faults.is_armed(Stage::Persist));
+}
+
+#[tokio::test]
+async fn test_crash_recovery_without_job() {
+    // Settling zero consumes the authorization, also for the next
+    // facilitator
+    let crashes = Crashes::new(Some("zero"));
+    let client = crashes.signer(&CLIENT_SEED);
+    let facilitator = crashes.start(None);
+    let (escrow_id, request) = crashes.open(&client).await;
+    let zero = SettleRequest {
+        settle_amount: Some("0".into()),
+        ..request.clone()
+    };
+    crash_after(&facilitator, &crashes.faults, &zero, Stage::Persist).await;
+    drop(facilitator);
+    let facilitator = crashes.start(None);
+    let retried = facilitator.settle(&request).await;
+    assert_eq!(retried.error.as_deref(), Some("nonce_used"));
+    assert!(facilitator.queue().unwrap().jobs().unwrap().is_empty());
+    let balance = client.get_escrow_balance(escrow_id).await.unwrap();
+    assert_eq!(balance, 10_000_000);
+    fs::remove_file(crashes.path.unwrap()).unwrap();
+
+    // A direct payment's transaction pays once, also for the next
+    // facilitator
+    let server = LocalSigner::from_bytes(&SERVER_SEED);
+    let server_addr = server.address();
+    let network_id: [u8; 32] = Sha256::digest(NETWORK_PASSPHRASE).into();
+    let usdc = Asset::CreditAlphanum4(AlphaNum4 {
+        asset_code: AssetCode4(*b"USDC"),
+        issuer: AccountId(PublicKey::PublicKeyTypeEd25519(Uint256([9; 32]))),
+    });
+    let requirements = PaymentRequirements {
+        scheme: EXACT_SCHEME.into(),
+        asset: Some(asset_contract_id(&usdc, &network_id)),
+        ..requirements(&server_addr)
+    };
+    let tx_hash = hex::encode([1; 32]);
+    let transactions = HashMap::from([(
+        tx_hash.clone(),
+        payment_transaction(
+            requirements.payment_memo(),
+            &[(&server_addr, usdc, 1_000_000)],
+            100,
+        ),
+    )]);
+    let rpc = Rpc::new(CannedTransport { transactions });
+    let contract_id = stellar_strkey::Contract([7; 32]).to_string();
+    let client = EscrowClient::new(rpc, &contract_id, NETWORK_PASSPHRASE, server).unwrap();
+    let path = env::temp_dir().join(format!("x402-crash-direct-{}.sqlite", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let faults = Arc::new(Faults::new());
+    let start = || {
+        let facilitator = Facilitator::new(client.clone(), NETWORK)
+            .with_settings(Settings {
+                direct_payments: Some(DirectPayments {
+                    confirmations: 3,
+                    max_age_secs: 3600,
+                }),
+                ..Settings::default()
+            })
+            .with_faults(faults.clone())
+            .with_queue(SettlementQueue::open(&path).unwrap())
+            .unwrap();
+        Arc::new(facilitator)
+    };
+    let request = SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header: direct_header(&tx_hash),
+        payment_requirements: requirements,
+        settle_amount: None,
+        dry_run: false,
+    };
+    crash_after(&start(), &faults, &request, Stage::Persist).await;
+    let retried = start().settle(&request).await;
+    assert_eq!(retried.error.as_deref(), Some("nonce_used"));
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_admin_settlement_jobs() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let faults = Arc::new(Mutex::new(VecDeque::new()));
+    let rpc = Rpc::new(FlakyTransport {
+        inner: transport,
+        faults: faults.clone(),
+        stuck: Default::default(),
+    });
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let client = signer(&CLIENT_SEED);
+    let client_addr = client.address();
+    let queue = SettlementQueue::open_in_memory()
+        .unwrap()
+        .with_retry(fast_retry(2))
+        .with_batching(BatchPolicy {
+            max_size: 10,
+            max_delay: Duration::from_secs(3600),
+        });
+    let facilitator = Facilitator::new(signer(&SERVER_SEED), NETWORK)
+        .with_queue(queue)
+        .unwrap();
+    let facilitator = Arc::new(facilitator);
+    let server_addr = facilitator.server().to_string();
+    let admin = operations_router(facilitator.clone(), "admin-token");
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let request = |nonce, amount: &str, plan: &[Fault]| {
+        faults.lock().unwrap().extend(plan);
+        SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: header(signed_payload(
+                &contract_id,
+                escrow_id,
+                &client_addr,
+                amount,
+                nonce,
+            )),
+            payment_requirements: requirements(&server_addr),
+            settle_amount: None,
+            dry_run: false,
+        }
+    };
+
+    // Created, waiting for its batch
+    let waiting = facilitator.settle(&request(1, "400000", &[])).await;
+    assert_eq!(
+        waiting.payment_id,
+        Some(payment_id(escrow_id, 0)),
+        "{waiting:?}"
+    );
+    // Out of attempts before its payment was created
+    facilitator
+        .settle(&request(2, "300000", &[Fault::Drop]))
+        .await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    faults.lock().unwrap().push_back(Fault::Drop);
+    assert_eq!(facilitator.process_queue().await.unwrap(), 1);
+    // Backing off, its transaction saved
+    facilitator
+        .settle(&request(3, "200000", &[Fault::Drop]))
+        .await;
+
+    let (status, _) = send_as(&admin, "GET", "/admin/jobs", "key-a", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, jobs) = send_as(&admin, "GET", "/admin/jobs", "admin-token", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let ids: Vec<_> = jobs
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|job| &job["id"])
+        .collect();
+    assert_eq!(ids, [1, 3]);
+    assert_eq!(jobs[0]["state"], "created");
+    assert_eq!(jobs[0]["paymentId"], payment_id(escrow_id, 0));
+    assert!(jobs[1]["pendingTxHash"].is_string());
+    let path = "/admin/jobs?state=failed";
+    let (_, failed) = send_as(&admin, "GET", path, "admin-token", None).await;
+    assert_eq!(failed.as_array().unwrap().len(), 1);
+    assert_eq!(
+        (&failed[0]["id"], &failed[0]["attempts"]),
+        (&json!(2), &json!(2))
+    );
+    let path = "/admin/jobs?state=stuck";
+    let (status, _) = send_as(&admin, "GET", path, "admin-token", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // A saved transaction may still apply
+    let (status, _) = send_as(&admin, "POST", "/admin/jobs/3/cancel", "admin-token", None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, cancelled) =
+        send_as(&admin, "POST", "/admin/jobs/1/cancel", "admin-token", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(cancelled["state"], "failed");
+    assert_eq!(cancelled["error"], "cancelled by an operator");
+    let (_, job) = send_as(&admin, "GET", "/admin/jobs/1", "admin-token", None).await;
+    assert_eq!(job["state"], "failed");
+    assert!(
+        !client
+            .get_payment(payment_id(escrow_id, 0))
+            .await
+            .unwrap()
+            .settled
+    );
+
+    // Retried at once, resuming from the last completed step
+    for (id, index) in [(2, 1), (3, 2)] {
+        let path = format!("/admin/jobs/{id}/retry");
+        let (status, job) = send_as(&admin, "POST", &path, "admin-token", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(job["state"], "created", "{job}");
+        assert_eq!(
+            (&job["paymentId"], &job["attempts"]),
+            (&json!(payment_id(escrow_id, index)), &json!(0))
+        );
+    }
+    let (_, jobs) = send_as(&admin, "GET", "/admin/jobs", "admin-token", None).await;
+    let ids: Vec<_> = jobs
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|job| &job["id"])
+        .collect();
+    assert_eq!(ids, [2, 3]);
+    for path in ["/admin/jobs/99", "/admin/jobs/99/retry"] {
+        let method = if path.ends_with("retry") {
+            "POST"
+        } else {
+            "GET"
+        };
+        let (status, _) = send_as(&admin, method, path, "admin-token", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    // Without a queue there are no jobs to act on
+    let bare = Arc::new(Facilitator::new(signer(&SERVER_SEED), NETWORK));
+    let admin = operations_router(bare, "admin-token");
+    let (status, _) = send_as(&admin, "GET", "/admin/jobs", "admin-token", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_admin_payments_and_reconcile() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let faults = Arc::new(Mutex::new(VecDeque::new()));
+    let rpc = Rpc::new(FlakyTransport {
+        inner: transport,
+        faults: faults.clone(),
+        stuck: Default::default(),
+    });
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let client = signer(&CLIENT_SEED);
+    let client_addr = client.address();
+    let queue = SettlementQueue::open_in_memory()
+        .unwrap()
+        .with_retry(fast_retry(5));
+    let facilitator = Facilitator::new(signer(&SERVER_SEED), NETWORK)
+        .with_queue(queue)
+        .unwrap();
+    let facilitator = Arc::new(facilitator);
+    let server_addr = facilitator.server().to_string();
+    let admin = operations_router(facilitator.clone(), "admin-token");
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let request = |nonce, amount: &str, plan: &[Fault]| {
+        faults.lock().unwrap().extend(plan);
+        SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: header(signed_payload(
+                &contract_id,
+                escrow_id,
+                &client_addr,
+                amount,
+                nonce,
+            )),
+            payment_requirements: requirements(&server_addr),
+            settle_amount: None,
+            dry_run: false,
+        }
+    };
+
+    let settled = facilitator.settle(&request(1, "400000", &[])).await;
+    let tx_hash = settled.tx_hash.unwrap();
+    // Settled on-chain, the settlement reply lost
+    let plan = [Fault::Pass, Fault::LoseReply];
+    let settling = facilitator.settle(&request(2, "300000", &plan)).await;
+    assert_eq!(
+        (settling.payment_id, settling.tx_hash),
+        (Some(payment_id(escrow_id, 1)), None)
+    );
+    // Created by another facilitator with the same key
+    let other = signer(&SERVER_SEED);
+    let created = other.create_payment(escrow_id, 1000).await.unwrap().value;
+    assert_eq!(created, payment_id(escrow_id, 2));
+
+    let path = format!("/admin/payments?escrowId={escrow_id}");
+    let (status, payments) = send_as(&admin, "GET", &path, "admin-token", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let found: Vec<_> = payments
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|payment| {
+            (
+                &payment["paymentId"],
+                &payment["jobId"],
+                &payment["settled"],
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (&json!(payment_id(escrow_id, 0)), &json!(1), &json!(true)),
+            (&json!(payment_id(escrow_id, 1)), &json!(2), &json!(true)),
+            (
+                &json!(payment_id(escrow_id, 2)),
+                &Value::Null,
+                &json!(false)
+            ),
+        ]
+    );
+    let path = format!("/admin/payments?txHash={}", tx_hash.to_uppercase());
+    let (_, payments) = send_as(&admin, "GET", &path, "admin-token", None).await;
+    assert_eq!(payments.as_array().unwrap().len(), 1);
+    assert_eq!(payments[0]["amount"], "400000");
+    let path = format!("/admin/payments/{}", payment_id(escrow_id, 1));
+    let (_, payment) = send_as(&admin, "GET", &path, "admin-token", None).await;
+    assert_eq!(
+        (&payment["escrowId"], &payment["jobId"]),
+        (&json!(escrow_id), &json!(2))
+    );
+    let path = format!("/admin/payments/{}", u64::MAX);
+    let (status, _) = send_as(&admin, "GET", &path, "admin-token", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    for path in ["/admin/payments", "/admin/payments?escrowId=0&txHash=ab"] {
+        let (status, _) = send_as(&admin, "GET", path, "admin-token", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    // The job behind the lost reply is repaired, the other payment reported
+    let (_, volume) = send_as(&admin, "GET", "/admin/volume", "admin-token", None).await;
+    assert_eq!(volume[0]["amount"], "400000");
+    let (status, report) = send_as(&admin, "POST", "/admin/reconcile", "admin-token", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["jobsChecked"], 2);
+    assert_eq!(report["paymentsChecked"], 3);
+    assert_eq!(report["repaired"], json!([2]));
+    assert_eq!(report["discrepancies"], json!([]));
+    assert_eq!(report["orphaned"][0]["paymentId"], payment_id(escrow_id, 2));
+    let job = facilitator.queue().unwrap().job(2).unwrap().unwrap();
+    assert_eq!(job.state, JobState::Settled);
+    assert!(job.pending_tx.is_none());
+    let (_, volume) = send_as(&admin, "GET", "/admin/volume", "admin-token", None).await;
+    assert_eq!(volume.as_array().unwrap().len(), 1);
+    assert_eq!(volume[0]["amount"], "700000");
+    assert!(volume[0].get("tenant").is_none());
+
+    let (_, report) = send_as(&admin, "POST", "/admin/reconcile", "admin-token", None).await;
+    assert_eq!(report["repaired"], json!([]));
+    let (status, _) = send_as(&admin, "POST", "/admin/jobs/2/retry", "admin-token", None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(facilitator.process_queue().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_periodic_reconciliation() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let faults = Arc::new(Mutex::new(VecDeque::new()));
+    let rpc = Rpc::new(FlakyTransport {
+        inner: transport,
+        faults: faults.clone(),
+        stuck: Default::default(),
+    });
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let client = signer(&CLIENT_SEED);
+    let client_addr = client.address();
+    let receiver = Receiver::start(0).await;
+    let webhooks = Webhooks::new(vec![
+        receiver.endpoint(vec![EventKind::ReconciliationDiscrepancy])
+    ]);
+    let queue = SettlementQueue::open_in_memory()
+        .unwrap()
+        .with_retry(fast_retry(5));
+    let facilitator = Facilitator::new(signer(&SERVER_SEED), NETWORK)
+        .with_webhooks(Arc::new(webhooks))
+        .with_queue(queue)
+        .unwrap();
+    let facilitator = Arc::new(facilitator);
+    let server_addr = facilitator.server().to_string();
+    let queue = facilitator.queue().unwrap();
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    // Settled on-chain, pending locally: the settlement reply was lost
+    faults
+        .lock()
+        .unwrap()
+        .extend([Fault::Pass, Fault::LoseReply]);
+    let settling = facilitator
+        .settle(&SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: header(signed_payload(
+                &contract_id,
+                escrow_id,
+                &client_addr,
+                "400000",
+                1,
+            )),
+            payment_requirements: requirements(&server_addr),
+            settle_amount: None,
+            dry_run: false,
+        })
+        .await;
+    assert_eq!(
+        (settling.payment_id, settling.tx_hash),
+        (Some(payment_id(escrow_id, 0)), None)
+    );
+    // Settled locally, pending on-chain
+    let other = signer(&SERVER_SEED);
+    let created = other.create_payment(escrow_id, 1000).await.unwrap().value;
+    assert_eq!(created, payment_id(escrow_id, 1));
+    let mut payment = queue.job(1).unwrap().unwrap().payment();
+    let mut seed_job = |nonce, payment_id| {
+        payment.nonce = nonce;
+        let mut job = queue.enqueue(&payment, "XLM", None).unwrap().unwrap();
+        job.state = JobState::Settled;
+        job.payment_id = Some(payment_id);
+        queue.save(&job).unwrap();
+    };
+    seed_job(2, payment_id(escrow_id, 1));
+    // Settled locally, unknown on-chain
+    seed_job(3, payment_id(escrow_id, 99));
+    // On-chain only, in an escrow of the server no job charged
+    let stranger = signer(&[7; 32]);
+    let stranger_addr = stranger.address();
+    let stranger_escrow = stranger
+        .open_escrow(&stranger_addr, &server_addr, 1_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let orphan = other
+        .create_payment(stranger_escrow, 500)
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(orphan, payment_id(stranger_escrow, 0));
+
+    let reconciler = facilitator.clone();
+    let task =
+        tokio::spawn(async move { reconciler.run_reconciler(Duration::from_millis(20)).await });
+    let mut received = receiver.received();
+    for _ in 0..100 {
+        if !received.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        received = receiver.received();
+    }
+
+    // The safe direction is healed, the rest notified once
+    let job = queue.job(1).unwrap().unwrap();
+    assert_eq!(job.state, JobState::Settled);
+    assert_eq!(received.len(), 1);
+    let body: Value = serde_json::from_str(&received[0].body).unwrap();
+    assert_eq!(body["type"], "reconciliation.discrepancy");
+    assert_eq!(
+        body["data"]["discrepancies"],
+        json!([
+            {
+                "jobId": 2,
+                "paymentId": payment_id(escrow_id, 1),
+                "reason": "unsettled_on_chain",
+            },
+            {
+                "jobId": 3,
+                "paymentId": payment_id(escrow_id, 99),
+                "reason": "missing_on_chain",
+            },
+        ])
+    );
+    assert_eq!(body["data"]["orphaned"][0]["paymentId"], orphan);
+    assert_eq!(body["data"]["orphaned"][0]["escrowId"], stranger_escrow);
+    assert_eq!(body["data"]["server"], server_addr);
+    let metrics = facilitator.metrics().render();
+    assert_eq!(
+        sample(
+            &metrics,
+            "x402_facilitator_reconciliation_repaired_total",
+            &[]
+        ),
+        Some(1.0)
+    );
+    let found = |metrics: &str, reason| {
+        sample(
+            metrics,
+            "x402_facilitator_reconciliation_discrepancies",
+            &[("reason", reason)],
+        )
+    };
+    for reason in ["unsettled_on_chain", "missing_on_chain", "orphaned"] {
+        assert_eq!(found(&metrics, reason), Some(1.0), "{reason}");
+    }
+
+    // Discrepancies resolved on-chain drop out of the next reconciliation,
+    // those left are not notified again
+    other
+        .settle_payment(payment_id(escrow_id, 1))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let metrics = facilitator.metrics().render();
+    assert_eq!(found(&metrics, "unsettled_on_chain"), Some(0.0));
+    assert_eq!(found(&metrics, "missing_on_chain"), Some(1.0));
+    assert_eq!(receiver.received().len(), 1);
+    task.abort();
+
+    let report = facilitator.reconcile().await.unwrap();
+    assert_eq!(report.escrows_checked, 2);
+    assert_eq!(report.jobs_checked, 3);
+    assert_eq!(report.payments_checked, 3);
+    assert!(report.repaired.is_empty());
+}
+
+#[cfg(feature = "grpc")]
+#[test]
+fn test_proto_file() {
+    use x402_types::SettlementSimulation;
+
+    use crate::proto::{self, Message};
+
+    assert_eq!(
+        include_str!("../proto/facilitator.proto"),
+        crate::proto_file(),
+        "proto/facilitator.proto is out of date"
+    );
+
+    // Messages mirror the JSON form of the types the HTTP API describes,
+    // new properties failing here until given a field number
+    let spec = openapi_spec();
+    let mirrored: [(&Message, &str); 10] = [
+        (&proto::VERIFY_REQUEST, "VerifyRequest"),
+        (&proto::VERIFY_RESPONSE, "VerifyResponse"),
+        (&proto::SETTLE_REQUEST, "SettleRequest"),
+        (&proto::SETTLE_RESPONSE, "SettleResponse"),
+        (&proto::SETTLEMENT_SIMULATION, "SettlementSimulation"),
+        (&proto::SUPPORTED_RESPONSE, "SupportedResponse"),
+        (&proto::SUPPORTED_KIND, "SupportedKind"),
+        (&proto::FEE_POLICY, "FeePolicy"),
+        (&proto::PAYMENT_REQUIREMENTS, "PaymentRequirements"),
+        (&proto::SETTLEMENT_EVENT, "WebhookEvent"),
+    ];
+    for (message, schema) in mirrored {
+        let mut fields: Vec<_> = message.fields.iter().map(|field| field.name).collect();
+        fields.sort_unstable();
+        let properties = spec["components"]["schemas"][schema]["properties"]
+            .as_object()
+            .unwrap();
+        let properties: Vec<_> = properties.keys().map(String::as_str).collect();
+        assert_eq!(fields, properties, "{}", message.name);
+        let mut numbers: Vec<_> = message.fields.iter().map(|field| field.number).collect();
+        numbers.dedup();
+        assert_eq!(numbers.len(), message.fields.len(), "{}", message.name);
+    }
+
+    // Round trips through the JSON form, free-form values as JSON text
+    let mut requirements = requirements("GSERVER");
+    requirements.extra = Some(json!({ "escrowContract": "CESCROW", "n": [1, 2] }));
+    let request = SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header: "eyJ9".into(),
+        payment_requirements: requirements,
+        settle_amount: Some("1500".into()),
+        dry_run: true,
+    };
+    let encoded = proto::encode(&proto::SETTLE_REQUEST, &request);
+    let decoded: SettleRequest = proto::decode(&proto::SETTLE_REQUEST, &encoded).unwrap();
+    assert_eq!(decoded, request);
+    let response = SettleResponse {
+        success: true,
+        error: None,
+        error_code: None,
+        tx_hash: Some("ab".repeat(32)),
+        network_id: Some(NETWORK.into()),
+        payment_id: Some(u64::MAX),
+        ledger: Some(120),
+        finality: Some(Finality::Included),
+        simulation: None,
+    };
+    let encoded = proto::encode(&proto::SETTLE_RESPONSE, &response);
+    let decoded: SettleResponse = proto::decode(&proto::SETTLE_RESPONSE, &encoded).unwrap();
+    assert_eq!(decoded, response);
+    let simulated = SettleResponse {
+        tx_hash: None,
+        ledger: None,
+        finality: None,
+        simulation: Some(SettlementSimulation {
+            amount: "1500".into(),
+            payment_id: Some(4),
+            estimated_fee: Some(200),
+        }),
+        ..response
+    };
+    let encoded = proto::encode(&proto::SETTLE_RESPONSE, &simulated);
+    let decoded: SettleResponse = proto::decode(&proto::SETTLE_RESPONSE, &encoded).unwrap();
+    assert_eq!(decoded, simulated);
+
+    // Absent fields take proto3 defaults, unknown ones are skipped
+    let decoded: VerifyResponse = proto::decode(&proto::VERIFY_RESPONSE, &[]).unwrap();
+    assert_eq!((decoded.is_valid, decoded.invalid_reason), (false, None));
+    let unknown = [0x78, 0x01, 0x08, 0x01];
+    let decoded: VerifyResponse = proto::decode(&proto::VERIFY_RESPONSE, &unknown).unwrap();
+    assert!(decoded.is_valid);
+    assert!(proto::decode::<VerifyResponse>(&proto::VERIFY_RESPONSE, &[0x12, 0x05, b'a']).is_err());
+    assert!(proto::decode::<VerifyResponse>(&proto::VERIFY_RESPONSE, &[0x0a, 0x00]).is_err());
+}
+
+/// Call a gRPC-Web method of `app` with `message`
+///
+/// # Returns
+/// * The response headers and body frames, as flag and payload
+#[cfg(feature = "grpc")]
+async fn grpc_call(app: &Router, method: &str, message: &[u8]) -> (HeaderMap, Vec<(u8, Vec<u8>)>) {
+    let response = app
+        .clone()
+        .oneshot(grpc_request(method, message))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers().clone();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (headers, grpc_frames(&bytes))
+}
+
+#[cfg(feature = "grpc")]
+fn grpc_request(method: &str, message: &[u8]) -> Request<Body> {
+    let mut body = vec![0];
+    body.extend((message.len() as u32).to_be_bytes());
+    body.extend(message);
+    Request::post(crate::grpc_path(method))
+        .header(CONTENT_TYPE, crate::GRPC_WEB_CONTENT_TYPE)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(feature = "grpc")]
+fn grpc_frames(mut bytes: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut frames = Vec::new();
+    while let [flag, a, b, c, d, rest @ ..] = bytes {
+        let len = u32::from_be_bytes([*a, *b, *c, *d]) as usize;
+        frames.push((*flag, rest[..len].to_vec()));
+        bytes = &rest[len..];
+    }
+    assert!(bytes.is_empty(), "truncated frame");
+    frames
+}
+
+#[cfg(feature = "grpc")]
+#[tokio::test]
+async fn test_grpc_matches_http() {
+    use x402_types::SupportedResponse;
+
+    use crate::{proto, Code, WebhookEvent, GRPC_MESSAGE, GRPC_STATUS};
+
+    let s = setup().await;
+    let ok = (0x80, b"grpc-status:0\r\n".to_vec());
+    let verify_request = |payment_header| VerifyRequest {
+        x402_version: X402_VERSION,
+        payment_header,
+        payment_requirements: requirements(&s.server_addr),
+    };
+
+    // Verified over gRPC, then replayed over HTTP: both share one core
+    let payment = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "400000",
+        1,
+    ));
+    let message = proto::encode(&proto::VERIFY_REQUEST, &verify_request(payment.clone()));
+    let (_, frames) = grpc_call(&s.app, "Verify", &message).await;
+    assert_eq!(frames.len(), 2);
+    let verified: VerifyResponse = proto::decode(&proto::VERIFY_RESPONSE, &frames[0].1).unwrap();
+    assert!(verified.is_valid, "{verified:?}");
+    assert_eq!(frames[1], ok);
+    let body = serde_json::to_value(verify_request(payment.clone())).unwrap();
+    let (status, _) = post(&s.app, "/verify", body).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (headers, frames) = grpc_call(&s.app, "Verify", &message).await;
+    assert!(frames.is_empty());
+    assert_eq!(
+        headers[GRPC_STATUS],
+        (Code::AlreadyExists as u32).to_string()
+    );
+    assert_eq!(headers[GRPC_MESSAGE], "nonce_replayed");
+
+    // Settlements made over either transport are streamed
+    let watch = proto::encode(&proto::WATCH_SETTLEMENTS_REQUEST, &json!({ "types": [] }));
+    let watching = s
+        .app
+        .clone()
+        .oneshot(grpc_request("WatchSettlements", &watch));
+    let mut watching = watching.await.unwrap().into_body();
+    let settle_request = SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header: payment,
+        payment_requirements: requirements(&s.server_addr),
+        settle_amount: None,
+        dry_run: false,
+    };
+    let message = proto::encode(&proto::SETTLE_REQUEST, &settle_request);
+    let (_, frames) = grpc_call(&s.app, "Settle", &message).await;
+    let settled: SettleResponse = proto::decode(&proto::SETTLE_RESPONSE, &frames[0].1).unwrap();
+    assert!(settled.success, "{settled:?}");
+    assert_eq!(settled.payment_id, Some(payment_id(s.escrow_id, 0)));
+    let over_http = settle(
+        &s,
+        header(signed_payload(
+            &s.contract_id,
+            s.escrow_id,
+            &s.client_addr,
+            "100000",
+            2,
+        )),
+    )
+    .await;
+    assert!(over_http.success, "{over_http:?}");
+
+    for (index, tx_hash) in [(0, &settled.tx_hash), (1, &over_http.tx_hash)] {
+        let frame = tokio::time::timeout(Duration::from_secs(5), watching.frame())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let frames = grpc_frames(frame.data_ref().unwrap());
+        let event: WebhookEvent = proto::decode(&proto::SETTLEMENT_EVENT, &frames[0].1).unwrap();
+        assert_eq!(event.kind, EventKind::PaymentSettled);
+        assert_eq!(event.data["paymentId"], payment_id(s.escrow_id, index));
+        assert_eq!(event.data["txHash"], json!(tx_hash));
+    }
+
+    // Same supported kinds, assets, and fees
+    let (_, frames) = grpc_call(&s.app, "GetSupported", &[]).await;
+    let supported: SupportedResponse =
+        proto::decode(&proto::SUPPORTED_RESPONSE, &frames[0].1).unwrap();
+    let (_, body) = get(&s.app, "/supported").await;
+    assert_eq!(supported, serde_json::from_value(body).unwrap());
+
+    // Malformed calls
+    let (headers, _) = grpc_call(&s.app, "Settle", &[0x0a, 0x05]).await;
+    assert_eq!(
+        headers[GRPC_STATUS],
+        (Code::InvalidArgument as u32).to_string()
+    );
+    let unknown = proto::encode(
+        &proto::WATCH_SETTLEMENTS_REQUEST,
+        &json!({ "types": ["x"] }),
+    );
+    let (headers, _) = grpc_call(&s.app, "WatchSettlements", &unknown).await;
+    assert_eq!(
+        headers[GRPC_STATUS],
+        (Code::InvalidArgument as u32).to_string()
+    );
+    let request = Request::post(crate::grpc_path("Verify"))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from("{}"))
+        .unwrap();
+    let (status, _) = send(&s.app, request).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_verify_double_submit_race() {
+    let s = setup().await;
+    let payment = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "400000",
+        1,
+    ));
+    let request = serde_json::to_value(VerifyRequest {
+        x402_version: X402_VERSION,
+        payment_header: payment.clone(),
+        payment_requirements: requirements(&s.server_addr),
+    })
+    .unwrap();
+
+    // The same payload at many resource servers at once
+    let submissions: Vec<_> = (0..8)
+        .map(|_| {
+            let app = s.app.clone();
+            let request = request.clone();
+            tokio::spawn(async move { post(&app, "/verify", request).await })
+        })
+        .collect();
+    let mut accepted = 0;
+    for submission in submissions {
+        let (status, body) = submission.await.unwrap();
+        let response: VerifyResponse = serde_json::from_value(body).unwrap();
+        match status {
+            StatusCode::OK => {
+                assert!(response.is_valid, "{response:?}");
+                accepted += 1;
+            }
+            StatusCode::CONFLICT => {
+                assert!(!response.is_valid);
+                assert_eq!(response.invalid_reason.as_deref(), Some("nonce_replayed"));
+            }
+            other => panic!("unexpected status {other}"),
+        }
+    }
+    assert_eq!(accepted, 1);
+
+    // Settlement of the verified payload is unaffected
+    let settled = settle(&s, payment).await;
+    assert!(settled.success, "{settled:?}");
+
+    // Invalid payloads do not reserve their nonce
+    let mut tampered = signed_payload(&s.contract_id, s.escrow_id, &s.client_addr, "400000", 2);
+    tampered.amount = "500000".into();
+    let response = verify(&s, header(tampered)).await;
+    assert_eq!(
+        response.invalid_reason.as_deref(),
+        Some("invalid_signature")
+    );
+    let response = verify(
+        &s,
+        header(signed_payload(
+            &s.contract_id,
+            s.escrow_id,
+            &s.client_addr,
+            "400000",
+            2,
+        )),
+    )
+    .await;
+    assert!(response.is_valid, "{response:?}");
+}
+
+/// Commands of the fake Redis server, and the keys it holds
+type RedisState = Arc<Mutex<(Vec<Vec<String>>, HashSet<String>)>>;
+
+/// Fake Redis server answering AUTH, SELECT, SET NX, and the token bucket
+/// script, whose buckets never refill
+async fn fake_redis() -> (String, RedisState) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("redis://:secret@{}/3", listener.local_addr().unwrap());
+    let state = RedisState::default();
+    let shared = state.clone();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let state = shared.clone();
+            tokio::spawn(async move {
+                let mut stream = BufStream::new(stream);
+                while let Some(command) = read_command(&mut stream).await {
+                    let reply = {
+                        let (commands, keys) = &mut *state.lock().unwrap();
+                        commands.push(command.clone());
+                        match command[0].as_str() {
+                            "SET" if !keys.insert(command[1].clone()) => "$-1\r\n".into(),
+                            "AUTH" | "SELECT" | "SET" => "+OK\r\n".into(),
+                            "EVAL" => {
+                                // EVAL script 1 key capacity interval
+                                let taken = commands
+                                    .iter()
+                                    .filter(|c| c[0] == "EVAL" && c[3] == command[3])
+                                    .count();
+                                let capacity: usize = command[4].parse().unwrap();
+                                let wait = if taken <= capacity { "0" } else { &command[5] };
+                                format!(":{wait}\r\n")
+                            }
+                            _ => "-ERR unknown command\r\n".to_string(),
+                        }
+                    };
+                    stream.write_all(reply.as_bytes()).await.unwrap();
+                    stream.flush().await.unwrap();
+                }
+            });
+        }
+    });
+    (url, state)
+}
+
+async fn read_command(stream: &mut BufStream<tokio::net::TcpStream>) -> Option<Vec<String>> {
+    let mut line = String::new();
+    stream.read_line(&mut line).await.ok().filter(|n| *n > 0)?;
+    let count: usize = line.trim().strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        line.clear();
+        stream.read_line(&mut line).await.ok()?;
+        let len: usize = line.trim().strip_prefix('$')?.parse().ok()?;
+        let mut arg = vec![0; len + 2];
+        stream.read_exact(&mut arg).await.ok()?;
+        arg.truncate(len);
+        args.push(String::from_utf8(arg).ok()?);
+    }
+    Some(args)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_replay_caches() {
+    let (url, state) = fake_redis().await;
+    let expires_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 60;
+
+    // Two replicas racing for the same nonce
+    let replicas: Vec<_> = (0..2)
+        .map(|_| Arc::new(RedisReplayCache::new(&url).unwrap()))
+        .collect();
+    let attempts: Vec<_> = replicas
+        .iter()
+        .flat_map(|replica| [replica.clone(), replica.clone()])
+        .map(|replica| tokio::spawn(async move { replica.try_reserve(0, 1, expires_at).await }))
+        .collect();
+    let mut reserved = 0;
+    for attempt in attempts {
+        if attempt.await.unwrap().unwrap() {
+            reserved += 1;
+        }
+    }
+    assert_eq!(reserved, 1);
+    assert!(replicas[1].try_reserve(0, 2, expires_at).await.unwrap());
+    assert!(replicas[0].try_reserve(1, 1, u64::MAX).await.unwrap());
+
+    let commands = state.lock().unwrap().0.clone();
+    // One connection per replica, authenticated and on database 3
+    let auth = commands.iter().filter(|c| c[..] == ["AUTH", "secret"]);
+    assert_eq!(auth.count(), 2);
+    assert_eq!(
+        commands.iter().filter(|c| c[..] == ["SELECT", "3"]).count(),
+        2
+    );
+    let set = commands.iter().find(|c| c[0] == "SET").unwrap();
+    assert_eq!(set[1..5], ["x402:replay:0:1", "1", "NX", "EX"]);
+    let ttl: u64 = set[5].parse().unwrap();
+    assert!((58..=60).contains(&ttl), "{ttl}");
+    // Far expiries are capped
+    let capped = commands.last().unwrap();
+    assert_eq!(capped[5], MAX_REPLAY_TTL.as_secs().to_string());
+
+    let cache = RedisReplayCache::new("redis://localhost").unwrap();
+    assert_eq!(cache.address(), "localhost:6379");
+    assert!(RedisReplayCache::new("http://localhost").is_err());
+    assert!(RedisReplayCache::new("redis://user@localhost").is_err());
+    assert!(RedisReplayCache::new("redis://localhost/db").is_err());
+
+    let cache = MemoryReplayCache::new();
+    assert!(cache.try_reserve(0, 1, u64::MAX).await.unwrap());
+    assert!(!cache.try_reserve(0, 1, u64::MAX).await.unwrap());
+    // Expired reservations are dropped by the next one
+    assert!(cache.try_reserve(0, 2, 1).await.unwrap());
+    assert!(cache.try_reserve(0, 3, 1).await.unwrap());
+    assert_eq!(cache.len(), 2);
+}
+
+#[tokio::test]
+async fn test_verify_fails_without_replay_cache() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport);
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let client = signer(&CLIENT_SEED);
+    let client_addr = client.address();
+
+    // Nothing listens on the port once the listener is dropped
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("redis://{}", listener.local_addr().unwrap());
+    drop(listener);
+    let facilitator = Facilitator::new(signer(&SERVER_SEED), NETWORK)
+        .with_replay_cache(Arc::new(RedisReplayCache::new(&url).unwrap()));
+    let escrow_id = client
+        .open_escrow(&client_addr, facilitator.server(), 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    let request = VerifyRequest {
+        x402_version: X402_VERSION,
+        payment_header: header(signed_payload(
+            &contract_id,
+            escrow_id,
+            &client_addr,
+            "400000",
+            1,
+        )),
+        payment_requirements: requirements(facilitator.server()),
+    };
+    let response = facilitator.verify(&request).await;
+    assert!(!response.is_valid);
+    assert_eq!(response.invalid_reason.as_deref(), Some("unexpected_error"));
+}
+
+/// Send `body` to `path` with `Authorization: Bearer <token>`
+async fn send_as(
+    app: &Router,
+    method: &str,
+    path: &str,
+    token: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(AUTHORIZATION, format!("Bearer {token}"))
+        .header(CONTENT_TYPE, "application/json");
+    let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+    send(app, request.body(body).unwrap()).await
+}
+
+#[tokio::test]
+async fn test_tenants_are_isolated() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport);
+    let client = EscrowClient::new(
+        rpc.clone(),
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&CLIENT_SEED),
+    )
+    .unwrap();
+    let client_addr = client.address();
+
+    let dir = env::temp_dir().join(format!("x402-tenants-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let tenant = |id: &str, seed: [u8; 32]| {
+        let key = dir.join(format!("{id}.key"));
+        fs::write(&key, ed25519::PrivateKey(seed).to_string()).unwrap();
+        json!({
+            "apiKey": format!("key-{id}"),
+            "server": LocalSigner::from_bytes(&seed).address(),
+            "signingKey": format!("file:{}", key.display()),
+        })
+    };
+    let (tenant_a, tenant_b) = (tenant("a", SERVER_SEED), tenant("b", [3; 32]));
+    let server_a = tenant_a["server"].as_str().unwrap().to_string();
+    let server_b = tenant_b["server"].as_str().unwrap().to_string();
+
+    let path = dir.join("tenants.json");
+    let open = || {
+        let tenants = Tenants::new(rpc.clone(), &contract_id, NETWORK, NETWORK_PASSPHRASE);
+        Arc::new(tenants.with_file(&path).unwrap())
+    };
+    let tenants = open();
+    let admin = tenant_admin_router(tenants.clone(), "admin-token");
+    let app = tenant_router(tenants.clone());
+
+    for (id, config) in [("a", &tenant_a), ("b", &tenant_b)] {
+        let path = format!("/admin/tenants/{id}");
+        let (status, _) = send_as(&admin, "PUT", &path, "admin-token", Some(config.clone())).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    let (status, _) = send_as(&admin, "GET", "/admin/tenants", "key-a", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, listed) = send_as(&admin, "GET", "/admin/tenants", "admin-token", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed[1]["server"], server_b);
+    assert!(listed[0].get("apiKey").is_none() && listed[0].get("signingKey").is_none());
+
+    let escrow_a = client
+        .open_escrow(&client_addr, &server_a, 5_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    let escrow_b = client
+        .open_escrow(&client_addr, &server_b, 5_000_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    // A payment into tenant b's escrow
+    let payload = signed_payload(&contract_id, escrow_b, &client_addr, "400000", 1);
+    let request = |pay_to: &str| {
+        serde_json::to_value(SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: header(payload.clone()),
+            payment_requirements: requirements(pay_to),
+            settle_amount: None,
+            dry_run: false,
+        })
+        .unwrap()
+    };
+
+    // Tenant a can neither settle it as b's payment, nor as its own
+    for pay_to in [&server_b, &server_a] {
+        let (status, body) = send_as(&app, "POST", "/settle", "key-a", Some(request(pay_to))).await;
+        assert_eq!(status, StatusCode::OK);
+        let settled: SettleResponse = serde_json::from_value(body).unwrap();
+        assert!(!settled.success);
+        assert_eq!(settled.error.as_deref(), Some("invalid_pay_to"));
+    }
+    assert_eq!(
+        client.get_escrow_balance(escrow_b).await.unwrap(),
+        5_000_000
+    );
+    let (status, _) = send_as(&app, "POST", "/settle", "key-c", Some(request(&server_b))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (_, body) = send_as(&app, "POST", "/settle", "key-b", Some(request(&server_b))).await;
+    let settled: SettleResponse = serde_json::from_value(body).unwrap();
+    assert!(settled.success, "{settled:?}");
+    assert_eq!(
+        client.get_escrow_balance(escrow_b).await.unwrap(),
+        4_600_000
+    );
+    assert_eq!(
+        client.get_escrow_balance(escrow_a).await.unwrap(),
+        5_000_000
+    );
+
+    // Each tenant sees its own settlements only
+    let metrics = |token| {
+        let request = Request::get("/metrics")
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            String::from_utf8(bytes.to_vec()).unwrap()
+        }
+    };
+    let (metrics_a, metrics_b) = (metrics("key-a").await, metrics("key-b").await);
+    let settlements = "x402_facilitator_settlements_total";
+    let success = [("tenant", "b"), ("result", "success")];
+    assert_eq!(sample(&metrics_b, settlements, &success), Some(1.0));
+    assert_eq!(
+        sample(&metrics_a, settlements, &[("result", "success")]),
+        None
+    );
+    let failure = [("tenant", "a"), ("result", "failure")];
+    assert_eq!(sample(&metrics_a, settlements, &failure), Some(2.0));
+    let (_, volume) = send_as(&admin, "GET", "/admin/volume", "admin-token", None).await;
+    assert_eq!(volume.as_array().unwrap().len(), 1);
+    assert_eq!(
+        (&volume[0]["tenant"], &volume[0]["amount"]),
+        (&json!("b"), &json!("400000"))
+    );
+
+    // Settings change without a restart
+    let mut changed = tenant_a.clone();
+    changed["feeBps"] = json!(25);
+    let (status, _) = send_as(
+        &admin,
+        "PUT",
+        "/admin/tenants/a",
+        "admin-token",
+        Some(changed),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, supported) = send_as(&app, "GET", "/supported", "key-a", None).await;
+    assert_eq!(supported["fee"]["feeBps"], 25);
+    assert_eq!(supported["kinds"][0]["network"], NETWORK);
+
+    // Tenants must sign with their own server key and API key
+    let mut stolen = tenant_b.clone();
+    stolen["server"] = json!(server_a);
+    stolen["apiKey"] = json!("key-c");
+    let (status, _) = send_as(
+        &admin,
+        "PUT",
+        "/admin/tenants/c",
+        "admin-token",
+        Some(stolen),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = send_as(
+        &admin,
+        "PUT",
+        "/admin/tenants/c",
+        "admin-token",
+        Some(tenant_b),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _) = send_as(&admin, "DELETE", "/admin/tenants/b", "admin-token", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send_as(&app, "GET", "/supported", "key-b", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Changes were saved to the tenants file
+    let reopened = open();
+    let configs = reopened.configs();
+    assert_eq!(configs.len(), 1);
+    assert_eq!((configs[0].id.as_str(), configs[0].fee_bps), ("a", 25));
+    assert!(reopened.by_api_key("key-a").is_some());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_rate_limit_per_client() {
+    let s = setup().await;
+    let limit = RateLimit {
+        burst: 2,
+        per_minute: 600,
+    };
+    s.facilitator.set_settings(Settings {
+        rate_limit: Some(limit),
+        ..Settings::default()
+    });
+    let verify_from = |payment_header: String, ip: [u8; 4]| {
+        let body = serde_json::to_value(VerifyRequest {
+            x402_version: X402_VERSION,
+            payment_header,
+            payment_requirements: requirements(&s.server_addr),
+        })
+        .unwrap();
+        let mut request = Request::post("/verify")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let peer = SocketAddr::from((ip, 4000));
+        request.extensions_mut().insert(ConnectInfo(peer));
+        let app = s.app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let retry_after = response.headers().get(RETRY_AFTER).cloned();
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            let body: VerifyResponse = serde_json::from_slice(&bytes).unwrap();
+            (status, retry_after, body)
+        }
+    };
+    let payment = |nonce| {
+        header(signed_payload(
+            &s.contract_id,
+            s.escrow_id,
+            &s.client_addr,
+            "400000",
+            nonce,
+        ))
+    };
+
+    // The burst goes through, then the bucket is empty, whatever the source
+    for (nonce, ip) in [(1, [10, 0, 0, 1]), (2, [10, 0, 0, 2])] {
+        let (status, _, body) = verify_from(payment(nonce), ip).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.is_valid, "{body:?}");
+    }
+    let (status, retry_after, body) = verify_from(payment(3), [10, 0, 0, 3]).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(retry_after.unwrap(), "1");
+    assert_eq!(body.invalid_reason.as_deref(), Some("rate_limited"));
+
+    // Other clients have their own bucket
+    let other = LocalSigner::from_bytes(&[4; 32]).address();
+    let (status, _, body) = verify_from(
+        header(signed_payload(
+            &s.contract_id,
+            s.escrow_id,
+            &other,
+            "400000",
+            1,
+        )),
+        [10, 0, 0, 1],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.invalid_reason.as_deref(), Some("invalid_signature"));
+
+    // Undecodable headers are limited by source address
+    for _ in 0..2 {
+        let (status, _, _) = verify_from("not base64!".into(), [10, 0, 0, 9]).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _, _) = verify_from("not base64!".into(), [10, 0, 0, 9]).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let (status, _, _) = verify_from("not base64!".into(), [10, 0, 0, 8]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // /settle draws from the same bucket, refilled at 10 tokens a second
+    let (status, body) = post(
+        &s.app,
+        "/settle",
+        serde_json::to_value(SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: payment(3),
+            payment_requirements: requirements(&s.server_addr),
+            settle_amount: None,
+            dry_run: false,
+        })
+        .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["error"], "rate_limited");
+    tokio::time::sleep(limit.interval() + Duration::from_millis(20)).await;
+    let (status, _, body) = verify_from(payment(3), [10, 0, 0, 1]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.is_valid, "{body:?}");
+
+    let metrics = s.facilitator.metrics().render();
+    let limited = [("endpoint", "verify")];
+    assert_eq!(
+        sample(&metrics, "x402_facilitator_rate_limited_total", &limited),
+        Some(2.0)
+    );
+}
+
+#[tokio::test]
+async fn test_redis_rate_limiter() {
+    let (url, state) = fake_redis().await;
+    let limiter = RedisRateLimiter::new(&url).unwrap();
+    let limit = RateLimit {
+        burst: 2,
+        per_minute: 120,
+    };
+    assert_eq!(
+        limiter.try_acquire("GSERVER:GA", &limit).await.unwrap(),
+        None
+    );
+    assert_eq!(
+        limiter.try_acquire("GSERVER:GA", &limit).await.unwrap(),
+        None
+    );
+    let wait = limiter.try_acquire("GSERVER:GA", &limit).await.unwrap();
+    assert_eq!(wait, Some(Duration::from_millis(500)));
+    assert_eq!(
+        limiter.try_acquire("GSERVER:GB", &limit).await.unwrap(),
+        None
+    );
+
+    let commands = state.lock().unwrap().0.clone();
+    let eval = commands.iter().find(|c| c[0] == "EVAL").unwrap();
+    assert_eq!(eval[2..], ["1", "x402:rate:GSERVER:GA", "2", "500"]);
+}
+
+/// RPC answering `getNetwork`, `getLatestLedger`, and `getTransaction`
+/// from canned responses
+struct CannedTransport {
+    transactions: HashMap<String, Value>,
+}
+
+#[async_trait]
+impl Transport for CannedTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        match method {
+            "getNetwork" => Ok(json!({
+                "passphrase": NETWORK_PASSPHRASE,
+                "protocolVersion": 22,
+            })),
+            // The ledger closing now
+            "getLatestLedger" => Ok(json!({
+                "id": "69",
+                "protocolVersion": 22,
+                "sequence": 105,
+                "closeTime": SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+                    .to_string(),
+            })),
+            "getTransaction" => Ok(self
+                .transactions
+                .get(params["hash"].as_str().unwrap_or_default())
+                .cloned()
+                .unwrap_or_else(|| json!({ "status": "NOT_FOUND", "latestLedger": 105 }))),
+            other => panic!("unexpected RPC call {other}"),
+        }
+    }
+}
+
+/// `getTransaction` response of a successful payment transaction
+fn payment_transaction(memo: [u8; 32], payments: &[(&str, Asset, i64)], ledger: u32) -> Value {
+    classic_transaction(Memo::Hash(Hash(memo)), payments, ledger)
+}
+
+/// `getTransaction` response of a successful transaction of the client,
+/// paying accounts muxed (M...) or not (G...)
+fn classic_transaction(memo: Memo, payments: &[(&str, Asset, i64)], ledger: u32) -> Value {
+    let destination = |address: &str| match stellar_strkey::Strkey::from_string(address) {
+        Ok(stellar_strkey::Strkey::PublicKeyEd25519(key)) => MuxedAccount::Ed25519(Uint256(key.0)),
+        Ok(stellar_strkey::Strkey::MuxedAccountEd25519(muxed)) => {
+            MuxedAccount::MuxedEd25519(MuxedAccountMed25519 {
+                id: muxed.id,
+                ed25519: Uint256(muxed.ed25519),
+            })
+        }
+        _ => panic!("not an account {address}"),
+    };
+    let operations: Vec<_> = payments
+        .iter()
+        .map(|(to, asset, amount)| Operation {
+            source_account: None,
+            body: OperationBody::Payment(PaymentOp {
+                destination: destination(to),
+                asset: asset.clone(),
+                amount: *amount,
+            }),
+        })
+        .collect();
+    let client = LocalSigner::from_bytes(&CLIENT_SEED).address();
+    let envelope = TransactionEnvelope::Tx(TransactionV1Envelope {
+        tx: Transaction {
+            source_account: destination(&client),
+            fee: 100,
+            seq_num: SequenceNumber(1),
+            cond: Preconditions::None,
+            memo,
+            operations: operations.try_into().unwrap(),
+            ext: TransactionExt::V0,
+        },
+        signatures: VecM::default(),
+    });
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        - 30;
+    json!({
+        "status": "SUCCESS",
+        "latestLedger": 105,
+        "ledger": ledger,
+        "createdAt": created_at.to_string(),
+        "envelopeXdr": envelope.to_xdr_base64(Limits::none()).unwrap(),
+    })
+}
+
+fn direct_header(tx_hash: &str) -> String {
+    encode_payment_header(&PaymentPayload {
+        x402_version: X402_VERSION,
+        scheme: EXACT_SCHEME.into(),
+        network: NETWORK.into(),
+        asset: None,
+        payload: SchemePayload::TransactionHash(TransactionHashPayload {
+            tx_hash: tx_hash.into(),
+        }),
+        extensions: Default::default(),
+    })
+}
+
+#[tokio::test]
+async fn test_direct_payments() {
+    let server = LocalSigner::from_bytes(&SERVER_SEED);
+    let server_addr = server.address();
+    let other = LocalSigner::from_bytes(&[4; 32]).address();
+    let network_id: [u8; 32] = Sha256::digest(NETWORK_PASSPHRASE).into();
+    let usdc = Asset::CreditAlphanum4(AlphaNum4 {
+        asset_code: AssetCode4(*b"USDC"),
+        issuer: AccountId(PublicKey::PublicKeyTypeEd25519(Uint256([9; 32]))),
+    });
+    let requirements = PaymentRequirements {
+        scheme: EXACT_SCHEME.into(),
+        asset: Some(asset_contract_id(&usdc, &network_id)),
+        ..requirements(&server_addr)
+    };
+    let memo = requirements.payment_memo();
+    let elsewhere = PaymentRequirements {
+        resource: "https://api.example.com/forecast".into(),
+        ..requirements.clone()
+    };
+
+    let hash = |n: u8| hex::encode([n; 32]);
+    let transactions = HashMap::from([
+        // Split in two payments, next to a payment to someone else
+        (
+            hash(1),
+            payment_transaction(
+                memo,
+                &[
+                    (&server_addr, usdc.clone(), 600_000),
+                    (&other, usdc.clone(), 5_000_000),
+                    (&server_addr, usdc.clone(), 400_000),
+                ],
+                100,
+            ),
+        ),
+        (
+            hash(2),
+            payment_transaction(memo, &[(&server_addr, usdc.clone(), 999_999)], 100),
+        ),
+        (
+            hash(3),
+            payment_transaction(memo, &[(&server_addr, Asset::Native, 1_000_000)], 100),
+        ),
+        (
+            hash(4),
+            payment_transaction(
+                elsewhere.payment_memo(),
+                &[(&server_addr, usdc.clone(), 1_000_000)],
+                100,
+            ),
+        ),
+        (
+            hash(5),
+            payment_transaction(memo, &[(&server_addr, usdc.clone(), 1_000_000)], 104),
+        ),
+        (
+            hash(6),
+            json!({ "status": "FAILED", "latestLedger": 105, "ledger": 100 }),
+        ),
+    ]);
+    let rpc = Rpc::new(CannedTransport { transactions });
+    let contract_id = stellar_strkey::Contract([7; 32]).to_string();
+    let client = EscrowClient::new(rpc, &contract_id, NETWORK_PASSPHRASE, server).unwrap();
+    let facilitator = Arc::new(Facilitator::new(client, NETWORK).with_settings(Settings {
+        direct_payments: Some(DirectPayments {
+            confirmations: 3,
+            max_age_secs: 3600,
+        }),
+        ..Settings::default()
+    }));
+    let app = router(facilitator.clone());
+    let call = |path: &'static str, tx_hash: String| {
+        let body = json!({
+            "x402Version": X402_VERSION,
+            "paymentHeader": direct_header(&tx_hash),
+            "paymentRequirements": requirements,
+        });
+        let app = app.clone();
+        async move { post(&app, path, body).await }
+    };
+
+    let (_, body) = get(&app, "/supported").await;
+    let schemes: Vec<_> = body["kinds"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|kind| kind["scheme"].as_str().unwrap())
+        .collect();
+    assert_eq!(schemes, ["escrow", "exact"]);
+
+    // Valid payment, verified once only
+    let (status, body) = call("/verify", hash(1)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["isValid"], true, "{body}");
+    let (status, body) = call("/verify", hash(1).to_uppercase()).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["invalidReason"], "nonce_replayed");
+
+    // The payer is the transaction source, paying both operations
+    let payment = facilitator
+        .check(&direct_header(&hash(1)), &requirements)
+        .await
+        .unwrap();
+    assert_eq!(
+        payment.client,
+        LocalSigner::from_bytes(&CLIENT_SEED).address()
+    );
+    assert_eq!(payment.amount, 1_000_000);
+    assert_eq!(payment.tx_hash, Some(hash(1)));
+
+    // Settling marks the transaction used rather than charging anything
+    let (_, body) = call("/settle", hash(1)).await;
+    assert_eq!(body["success"], true, "{body}");
+    assert_eq!(body["txHash"], hash(1));
+    assert_eq!(body["paymentId"], Value::Null);
+    let (_, body) = call("/settle", hash(1)).await;
+    assert_eq!(body["error"], "nonce_used");
+    let err = facilitator
+        .check(&direct_header(&hash(1)), &requirements)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::NonceUsed));
+
+    for (n, reason) in [
+        (2, "insufficient_amount"),
+        (3, "invalid_asset"),
+        (4, "invalid_memo"),
+        (5, "insufficient_confirmations"),
+        (6, "transaction_failed"),
+        (7, "transaction_not_found"),
+    ] {
+        let (_, body) = call("/verify", hash(n)).await;
+        assert_eq!(body["invalidReason"], reason, "transaction {n}");
+    }
+    let (_, body) = call("/verify", "abc".into()).await;
+    assert_eq!(body["invalidReason"], "invalid_payload");
+
+    let metrics = facilitator.metrics().render();
+    let volume = [("asset", requirements.asset.as_deref().unwrap())];
+    assert_eq!(
+        sample(&metrics, "x402_facilitator_settled_volume_total", &volume),
+        Some(1_000_000.0)
+    );
+
+    // Facilitators not accepting direct payments reject the scheme
+    facilitator.set_settings(Settings::default());
+    let (_, body) = call("/verify", hash(5)).await;
+    assert_eq!(body["invalidReason"], "invalid_scheme");
+    let (_, body) = get(&app, "/supported").await;
+    assert_eq!(body["kinds"].as_array().unwrap().len(), 1);
+}
+
+/// RPC of the test environment, with canned classic transactions and
+/// `transfer` events of an asset contract
+struct ClassicTransport {
+    env: EnvTransport,
+    transactions: HashMap<String, Value>,
+    asset: String,
+    events: Vec<Value>,
+}
+
+#[async_trait]
+impl Transport for ClassicTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        let hash = params["hash"].as_str().unwrap_or_default();
+        match method {
+            "getTransaction" if self.transactions.contains_key(hash) => {
+                Ok(self.transactions[hash].clone())
+            }
+            "getEvents" if params["filters"][0]["contractIds"][0] == self.asset.as_str() => {
+                // One page, then nothing new
+                let events = match params["pagination"]["cursor"] {
+                    Value::Null => self.events.clone(),
+                    _ => vec![],
+                };
+                Ok(json!({ "events": events, "latestLedger": 105, "cursor": "end" }))
+            }
+            _ => self.env.request(method, params).await,
+        }
+    }
+}
+
+/// `getEvents` entry of a transfer by the asset contract
+fn transfer_event(asset: &str, to: &str, tx_hash: &str) -> Value {
+    let topic = |value: ScVal| value.to_xdr_base64(Limits::none()).unwrap();
+    let client = LocalSigner::from_bytes(&CLIENT_SEED).address();
+    json!({
+        "type": "contract",
+        "ledger": 100,
+        "ledgerClosedAt": "2026-01-01T00:00:00Z",
+        "contractId": asset,
+        "id": format!("{tx_hash}-1"),
+        "topic": [
+            topic(ScVal::Symbol(ScSymbol("transfer".try_into().unwrap()))),
+            topic(ScVal::Address(scval::parse_address(&client).unwrap())),
+            topic(ScVal::Address(scval::parse_address(to).unwrap())),
+        ],
+        "value": topic(ScVal::I128(Int128Parts { hi: 0, lo: 1 })),
+        "inSuccessfulContractCall": true,
+        "txHash": tx_hash,
+    })
+}
+
+#[tokio::test]
+async fn test_pending_deposits() {
+    let env = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = env.contract_id().to_string();
+    let network_id: [u8; 32] = Sha256::digest(NETWORK_PASSPHRASE).into();
+    let usdc = Asset::CreditAlphanum4(AlphaNum4 {
+        asset_code: AssetCode4(*b"USDC"),
+        issuer: AccountId(PublicKey::PublicKeyTypeEd25519(Uint256([9; 32]))),
+    });
+    let asset = asset_contract_id(&usdc, &network_id);
+    let server = LocalSigner::from_bytes(&SERVER_SEED);
+    let server_addr = server.address();
+    let other = LocalSigner::from_bytes(&[4; 32]).address();
+    let muxed = |escrow_id| deposit_address(&server_addr, escrow_id).unwrap();
+    assert!(muxed(0).starts_with('M'));
+    let client_key = LocalSigner::from_bytes(&CLIENT_SEED);
+    let client_addr = client_key.address();
+    let client = EscrowClient::new(
+        Rpc::new(env.clone()),
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        client_key,
+    )
+    .unwrap();
+    let escrow_id = client
+        .open_escrow(&client_addr, &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    let hash = |n: u8| hex::encode([n; 32]);
+    let transactions = HashMap::from([
+        // Split in two payments into the deposit address
+        (
+            hash(1),
+            classic_transaction(
+                Memo::None,
+                &[
+                    (&muxed(escrow_id), usdc.clone(), 300_000),
+                    (&other, usdc.clone(), 5_000_000),
+                    (&muxed(escrow_id), usdc.clone(), 200_000),
+                ],
+                100,
+            ),
+        ),
+        // To the server, naming the escrow in the memo
+        (
+            hash(2),
+            classic_transaction(
+                Memo::Id(escrow_id),
+                &[(&server_addr, usdc.clone(), 100_000)],
+                100,
+            ),
+        ),
+        (
+            hash(3),
+            classic_transaction(
+                Memo::Id(escrow_id),
+                &[(&server_addr, Asset::Native, 100_000)],
+                100,
+            ),
+        ),
+        (
+            hash(4),
+            classic_transaction(Memo::None, &[(&muxed(u64::MAX), usdc.clone(), 1)], 100),
+        ),
+        (
+            hash(5),
+            classic_transaction(
+                Memo::None,
+                &[
+                    (&muxed(escrow_id), usdc.clone(), 1),
+                    (&muxed(u64::MAX), usdc.clone(), 1),
+                ],
+                100,
+            ),
+        ),
+        (
+            hash(6),
+            classic_transaction(Memo::Id(escrow_id), &[(&other, usdc.clone(), 100_000)], 100),
+        ),
+        (
+            hash(7),
+            classic_transaction(
+                Memo::Id(escrow_id),
+                &[(&server_addr, usdc.clone(), 100_000)],
+                104,
+            ),
+        ),
+        (
+            hash(8),
+            payment_transaction([3; 32], &[(&muxed(escrow_id), usdc.clone(), 1)], 100),
+        ),
+    ]);
+    let events = vec![
+        transfer_event(&asset, &server_addr, &hash(1)),
+        transfer_event(&asset, &other, &hash(6)),
+        transfer_event(&asset, &server_addr, &hash(2)),
+        transfer_event(&asset, &server_addr, &hash(3)),
+    ];
+    let rpc = Rpc::new(ClassicTransport {
+        env,
+        transactions,
+        asset: asset.clone(),
+        events,
+    });
+    let server_client = EscrowClient::new(rpc, &contract_id, NETWORK_PASSPHRASE, server).unwrap();
+    let facilitator = Facilitator::new(server_client, NETWORK);
+
+    // Not accepted unless enabled
+    let err = facilitator.claim_deposit(&hash(1)).await.unwrap_err();
+    assert!(matches!(err, VerifyError::InvalidPayload(_)), "{err}");
+    let facilitator = facilitator.with_pending_deposits(PendingDeposits {
+        asset,
+        confirmations: 3,
+    });
+
+    let claimed = facilitator.claim_deposit(&hash(1)).await.unwrap();
+    assert_eq!(claimed.escrow_id, escrow_id);
+    assert_eq!(claimed.from, client_addr);
+    assert_eq!(claimed.amount, 500_000);
+    assert_eq!(claimed.tx_hash, hash(1));
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        10_500_000
+    );
+    assert!(client.is_deposit_claimed([1; 32]).await.unwrap());
+
+    // Credited once only, whether claimed by the facilitator or directly
+    let err = facilitator
+        .claim_deposit(&hash(1).to_uppercase())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::NonceUsed), "{err}");
+    let server_client = EscrowClient::new(
+        client.rpc().clone(),
+        &contract_id,
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(&SERVER_SEED),
+    )
+    .unwrap();
+    let err = server_client
+        .claim_pending_deposit(escrow_id, &client_addr, 500_000, [1; 32])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.contract_error(),
+        Some(ContractError::DepositAlreadyClaimed)
+    );
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        10_500_000
+    );
+
+    for (n, reason) in [
+        (3, "invalid_asset"),
+        (4, "escrow_not_found"),
+        (5, "invalid_payload"),
+        (6, "invalid_pay_to"),
+        (7, "insufficient_confirmations"),
+        (8, "invalid_payload"),
+        (9, "transaction_not_found"),
+    ] {
+        let err = facilitator.claim_deposit(&hash(n)).await.unwrap_err();
+        assert_eq!(err.reason(), reason, "transaction {n}");
+    }
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        10_500_000
+    );
+
+    // The watcher claims the transfers to the server not yet credited
+    let claimed = facilitator.claim_deposits().await.unwrap();
+    assert_eq!(claimed.len(), 1, "{claimed:?}");
+    assert_eq!(claimed[0].tx_hash, hash(2));
+    assert_eq!(claimed[0].amount, 100_000);
+    assert_eq!(
+        client.get_escrow_balance(escrow_id).await.unwrap(),
+        10_600_000
+    );
+    assert_eq!(facilitator.claim_deposits().await.unwrap(), vec![]);
+}
+
+/// Check `value` against the JSON Schema subset used by the OpenAPI
+/// description, resolving references among its components
+fn validate(spec: &Value, schema: &Value, value: &Value) -> Result<(), String> {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.strip_prefix("#/components/schemas/").unwrap();
+        let component = &spec["components"]["schemas"][name];
+        assert!(component.is_object(), "dangling {reference}");
+        return validate(spec, component, value);
+    }
+    if let Some(options) = schema["anyOf"].as_array() {
+        if !options
+            .iter()
+            .any(|option| validate(spec, option, value).is_ok())
+        {
+            return Err(format!("{value} matches none of {schema}"));
+        }
+    }
+    if let Some(names) = schema["enum"].as_array() {
+        if !names.contains(value) {
+            return Err(format!("{value} is not one of {names:?}"));
+        }
+    }
+    let typed = match schema["type"].as_str() {
+        None => true,
+        Some("object") => value.is_object(),
+        Some("array") => value.is_array(),
+        Some("string") => value.is_string(),
+        Some("integer") => value.is_i64() || value.is_u64(),
+        Some("boolean") => value.is_boolean(),
+        Some("null") => value.is_null(),
+        Some(other) => panic!("unsupported type {other}"),
+    };
+    if !typed {
+        return Err(format!("{value} is not of type {}", schema["type"]));
+    }
+    if let Some(minimum) = schema["minimum"].as_f64() {
+        if value.as_f64().is_some_and(|number| number < minimum) {
+            return Err(format!("{value} is below {minimum}"));
+        }
+    }
+    if let Some(pattern) = schema["pattern"].as_str() {
+        assert_eq!(pattern, "^-?[0-9]+$", "unsupported pattern");
+        let digits = value.as_str().unwrap().trim_start_matches('-');
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("{value} is not an integer"));
+        }
+    }
+    for name in schema["required"].as_array().into_iter().flatten() {
+        if value.get(name.as_str().unwrap()).is_none() {
+            return Err(format!("{value} lacks {name}"));
+        }
+    }
+    if let (Some(properties), Some(fields)) = (schema["properties"].as_object(), value.as_object())
+    {
+        for (name, field) in fields {
+            if let Some(property) = properties.get(name) {
+                validate(spec, property, field).map_err(|e| format!("{name}: {e}"))?;
+            }
+        }
+    }
+    if let (Some(items), Some(elements)) = (schema.get("items"), value.as_array()) {
+        for element in elements {
+            validate(spec, items, element)?;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_openapi_spec() {
+    let s = setup().await;
+    let (status, spec) = get(&s.app, "/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(spec, openapi_spec());
+    assert_eq!(spec["openapi"], "3.1.0");
+
+    // Every route, and nothing else
+    let mut operations: Vec<_> = spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, item)| {
+            let methods = item.as_object().unwrap().keys();
+            methods.map(move |method| (method.to_uppercase(), path.clone()))
+        })
+        .collect();
+    operations.sort();
+    let mut expected = vec![
+        ("POST", "/verify"),
+        ("POST", "/settle"),
+        ("POST", "/preflight"),
+        ("GET", "/supported"),
+        ("GET", "/metrics"),
+        ("GET", "/healthz"),
+        ("GET", "/readyz"),
+        ("GET", "/openapi.json"),
+        ("GET", "/admin/webhooks/failed"),
+        ("POST", "/admin/webhooks/failed/{id}/replay"),
+        ("GET", "/admin/tenants"),
+        ("PUT", "/admin/tenants/{id}"),
+        ("DELETE", "/admin/tenants/{id}"),
+        ("PUT", "/admin/tenants/{id}/next-key"),
+        ("POST", "/admin/tenants/{id}/next-key/promote"),
+        ("GET", "/admin/volume"),
+        ("GET", "/admin/jobs"),
+        ("GET", "/admin/jobs/{id}"),
+        ("POST", "/admin/jobs/{id}/retry"),
+        ("POST", "/admin/jobs/{id}/cancel"),
+        ("GET", "/admin/payments"),
+        ("GET", "/admin/payments/{id}"),
+        ("POST", "/admin/reconcile"),
+    ];
+    expected.sort();
+    let expected: Vec<_> = expected
+        .into_iter()
+        .map(|(method, path)| (method.to_string(), path.to_string()))
+        .collect();
+    assert_eq!(operations, expected);
+
+    // Each described operation is served, admin ones behind the token
+    let webhooks = Arc::new(Webhooks::new(vec![]));
+    let app = s
+        .app
+        .clone()
+        .merge(operations_router(s.facilitator.clone(), "admin-token"))
+        .merge(admin_router(webhooks, "admin-token"));
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let tenants = Tenants::new(
+        Rpc::new(transport),
+        &contract_id,
+        NETWORK,
+        NETWORK_PASSPHRASE,
+    );
+    let tenants = Arc::new(tenants);
+    let tenant_admin = tenant_admin_router(tenants.clone(), "admin-token");
+    for (method, path) in &operations {
+        let uri = path.replace("{id}", "1");
+        let request = || {
+            Request::builder()
+                .method(method.as_str())
+                .uri(&uri)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let (status, _) = send(&app, request()).await;
+        if path.starts_with("/admin/tenants") {
+            let (status, _) = send(&tenant_admin, request()).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{method} {path}");
+        } else if path.starts_with("/admin/") {
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{method} {path}");
+        } else {
+            let unrouted = [StatusCode::NOT_FOUND, StatusCode::METHOD_NOT_ALLOWED];
+            assert!(!unrouted.contains(&status), "{method} {path}: {status}");
+        }
+    }
+    let (status, _) = get(&tenant_router(tenants), "/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Requests and responses validate against their schemas
+    let schema = |name: &str| json!({ "$ref": format!("#/components/schemas/{name}") });
+    let check = |name: &str, value: &Value| {
+        validate(&spec, &schema(name), value).unwrap_or_else(|e| panic!("{name}: {e}"));
+    };
+    let payment = header(signed_payload(
+        &s.contract_id,
+        s.escrow_id,
+        &s.client_addr,
+        "400000",
+        1,
+    ));
+    let verify_request = serde_json::to_value(VerifyRequest {
+        x402_version: X402_VERSION,
+        payment_header: payment.clone(),
+        payment_requirements: requirements(&s.server_addr),
+    })
+    .unwrap();
+    check("VerifyRequest", &verify_request);
+    let (_, verified) = post(&s.app, "/verify", verify_request.clone()).await;
+    check("VerifyResponse", &verified);
+    let (_, replayed) = post(&s.app, "/verify", verify_request.clone()).await;
+    assert_eq!(replayed["invalidReason"], "nonce_replayed");
+    check("VerifyResponse", &replayed);
+    let settle_request = json!({
+        "x402Version": X402_VERSION,
+        "paymentHeader": header(signed_payload(&s.contract_id, s.escrow_id, &s.client_addr, "400000", 2)),
+        "paymentRequirements": requirements(&s.server_addr),
+        "settleAmount": "300000",
+    });
+    check("SettleRequest", &settle_request);
+    let (_, settled) = post(&s.app, "/settle", settle_request).await;
+    assert_eq!(settled["success"], true, "{settled}");
+    check("SettleResponse", &settled);
+    let (_, supported) = get(&s.app, "/supported").await;
+    check("SupportedResponse", &supported);
+    let decoded = decode_payment_header(&payment).unwrap();
+    check("PaymentPayload", &serde_json::to_value(decoded).unwrap());
+
+    let path = format!("/admin/payments/{}", payment_id(s.escrow_id, 0));
+    let (_, record) = send_as(&app, "GET", &path, "admin-token", None).await;
+    check("PaymentRecord", &record);
+    assert_eq!(record["quoteAmount"], Value::Null);
+    let (_, volume) = send_as(&app, "GET", "/admin/volume", "admin-token", None).await;
+    assert_eq!(volume.as_array().unwrap().len(), 1);
+    check("VolumeEntry", &volume[0]);
+    let summary = TenantConfig {
+        id: "a".into(),
+        api_key: "key-a".into(),
+        server: s.server_addr.clone(),
+        signing_key: "env:TENANT_A_KEY".into(),
+        next_signing_key: None,
+        rotated: false,
+        assets: vec![],
+        fee_bps: 10,
+        webhooks: vec![],
+        rate_limit: Some(RateLimit {
+            burst: 5,
+            per_minute: 60,
+        }),
+        direct_payments: None,
+    }
+    .summary();
+    check("TenantSummary", &serde_json::to_value(summary).unwrap());
+
+    // Malformed payloads do not
+    let mut missing = verify_request.clone();
+    missing.as_object_mut().unwrap().remove("paymentHeader");
+    assert!(validate(&spec, &schema("VerifyRequest"), &missing).is_err());
+    let mut numeric = verify_request;
+    numeric["paymentRequirements"]["maxAmountRequired"] = json!(1000000);
+    assert!(validate(&spec, &schema("VerifyRequest"), &numeric).is_err());
+    let negative = json!({ "isValid": true, "invalidReason": 7 });
+    assert!(validate(&spec, &schema("VerifyResponse"), &negative).is_err());
+}
+
+/// Route of the HTTP routers, as `routes.rs` declares it
+#[derive(Debug)]
+struct DeclaredRoute {
+    method: String,
+    path: String,
+    /// Type of the handler's JSON body
+    request: Option<String>,
+    /// Types of the handler's JSON responses, success and failure
+    responses: Vec<String>,
+}
+
+/// Routes of the HTTP routers, read from the `.route(...)` calls of
+/// `routes.rs` with the `Json` types of their handlers' signatures
+///
+/// Closures and handlers answering a `Response` declare no types.
+fn declared_routes() -> Vec<DeclaredRoute> {
+    let source = include_str!("routes.rs");
+    // Position of the parenthesis closing the one opened before `text`
+    let closing = |text: &str| {
+        let mut depth = 1;
+        text.find(|c| {
+            depth += match c {
+                '(' => 1,
+                ')' => -1,
+                _ => 0,
+            };
+            depth == 0
+        })
+        .unwrap()
+    };
+    // Types in `Json<...>`, lists unwrapped
+    let json_types = |text: &str| -> Vec<String> {
+        text.split("Json<")
+            .skip(1)
+            .map(|rest| {
+                let mut depth = 1;
+                let end = rest
+                    .find(|c| {
+                        depth += match c {
+                            '<' => 1,
+                            '>' => -1,
+                            _ => 0,
+                        };
+                        depth == 0
+                    })
+                    .unwrap();
+                let ty = &rest[..end];
+                ty.strip_prefix("Vec<")
+                    .and_then(|ty| ty.strip_suffix('>'))
+                    .unwrap_or(ty)
+                    .to_string()
+            })
+            .collect()
+    };
+
+    let mut routes = Vec::new();
+    for (at, call) in source.match_indices(".route(") {
+        let call = &source[at + call.len()..];
+        let (path, mut handlers) = call.split_once(',').unwrap();
+        let path = match path.trim() {
+            "OPENAPI_PATH" => OPENAPI_PATH,
+            literal => literal.trim_matches('"'),
+        };
+        // Handlers chained as `put(put_tenant).delete(delete_tenant)`
+        loop {
+            let (method, rest) = handlers.trim_start().split_once('(').unwrap();
+            let end = closing(rest);
+            let handler = rest[..end].trim();
+            let signature = source
+                .find(&format!("fn {handler}("))
+                .map(|start| &source[start..][..source[start..].find("{\n").unwrap()]);
+            let (params, returns) = signature.map_or(("", ""), |signature| {
+                signature.split_once("->").unwrap_or((signature, ""))
+            });
+            routes.push(DeclaredRoute {
+                method: method.to_string(),
+                path: path.to_string(),
+                request: json_types(params).pop(),
+                responses: json_types(returns),
+            });
+            match rest[end + 1..].strip_prefix('.') {
+                Some(next) => handlers = next,
+                None => break,
+            }
+        }
+    }
+    routes
+}
+
+#[test]
+fn test_openapi_matches_routes() {
+    let spec = openapi_spec();
+    let schemas = spec["components"]["schemas"].as_object().unwrap();
+    let reference = |ty: &str| format!("#/components/schemas/{ty}");
+
+    // Every declared route is described, and nothing else, Swagger UI
+    // rendering the description aside
+    let routes: Vec<_> = declared_routes()
+        .into_iter()
+        .filter(|route| route.path != "/docs")
+        .collect();
+    let mut declared: Vec<_> = routes
+        .iter()
+        .map(|route| (route.method.clone(), route.path.clone()))
+        .collect();
+    declared.sort();
+    declared.dedup();
+    let mut described: Vec<_> = spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, item)| {
+            let methods = item.as_object().unwrap().keys();
+            methods.map(move |method| (method.clone(), path.clone()))
+        })
+        .collect();
+    described.sort();
+    assert_eq!(declared, described);
+
+    // Handlers take and answer the types their operation names
+    for route in &routes {
+        let operation = &spec["paths"][&route.path][&route.method];
+        let body = &operation["requestBody"]["content"]["application/json"]["schema"];
+        match route.request.as_deref() {
+            // Described inline, the type being private to the router
+            Some("NextKey") => assert!(body.is_object(), "{route:?}"),
+            Some(ty) => assert_eq!(body["$ref"], reference(ty), "{route:?}"),
+            None => assert!(operation.get("requestBody").is_none(), "{route:?}"),
+        }
+        let answered: Vec<_> = operation["responses"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|response| &response["content"]["application/json"]["schema"])
+            .flat_map(|schema| [&schema["$ref"], &schema["items"]["$ref"]])
+            .filter_map(Value::as_str)
+            .collect();
+        for ty in &route.responses {
+            assert!(answered.contains(&reference(ty).as_str()), "{route:?}");
+        }
+    }
+
+    // Every type named has a schema, and every schema is named by an
+    // operation or by the types integrators decode: the X-PAYMENT payload
+    // carried in paymentHeader, and the 402 body and X-PAYMENT-RESPONSE
+    // header of resource servers
+    let mut named = std::collections::BTreeSet::new();
+    let documented = [
+        PaymentPayload::NAME,
+        PaymentRequiredResponse::NAME,
+        PaymentErrorBody::NAME,
+        PaymentResponseHeader::NAME,
+    ]
+    .map(|name| json!({ "$ref": reference(name) }));
+    let mut pending: Vec<&Value> = documented.iter().collect();
+    pending.push(&spec["paths"]);
+    while let Some(value) = pending.pop() {
+        match value {
+            Value::Object(fields) => {
+                if let Some(name) = fields.get("$ref").and_then(Value::as_str) {
+                    let name = name.strip_prefix("#/components/schemas/").unwrap();
+                    assert!(schemas.contains_key(name), "no schema for {name}");
+                    if named.insert(name) {
+                        pending.push(&schemas[name]);
+                    }
+                }
+                pending.extend(fields.values());
+            }
+            Value::Array(values) => pending.extend(values),
+            _ => {}
+        }
+    }
+    let unnamed: Vec<_> = schemas
+        .keys()
+        .filter(|name| !named.contains(name.as_str()))
+        .collect();
+    assert!(unnamed.is_empty(), "{unnamed:?}");
+}
+
+/// Transport keeping the envelopes of the transactions sent
+struct RecordingTransport {
+    inner: EnvTransport,
+    sent: Arc<Mutex<Vec<TransactionEnvelope>>>,
+}
+
+#[async_trait]
+impl Transport for RecordingTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        if method == "sendTransaction" {
+            let envelope = params["transaction"].as_str().unwrap_or_default();
+            let envelope = TransactionEnvelope::from_xdr_base64(envelope, Limits::none()).unwrap();
+            self.sent.lock().unwrap().push(envelope);
+        }
+        self.inner.request(method, params).await
+    }
+}
+
+#[tokio::test]
+#[traced_test]
+async fn test_settlement_traces() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let rpc = Rpc::new(RecordingTransport {
+        inner: transport,
+        sent: sent.clone(),
+    });
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let client = signer(&CLIENT_SEED);
+    let facilitator = Facilitator::new(signer(&SERVER_SEED), NETWORK);
+    let server_addr = facilitator.server().to_string();
+    let escrow_id = client
+        .open_escrow(&client.address(), &server_addr, 10_000_000, None)
+        .await
+        .unwrap()
+        .value;
+    sent.lock().unwrap().clear();
+
+    let payment_header = header(signed_payload(
+        &contract_id,
+        escrow_id,
+        &client.address(),
+        "400000",
+        9,
+    ));
+    let request = SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header: payment_header.clone(),
+        payment_requirements: requirements(&server_addr),
+        settle_amount: None,
+        dry_run: false,
+    };
+    let verified = facilitator
+        .verify(&VerifyRequest {
+            x402_version: X402_VERSION,
+            payment_header,
+            payment_requirements: requirements(&server_addr),
+        })
+        .await;
+    assert!(verified.is_valid, "{verified:?}");
+    let settled = facilitator.settle(&request).await;
+    assert!(settled.success, "{settled:?}");
+
+    // Both settlement transactions carry the payment's correlation ID
+    let id = correlation_id(escrow_id, 9);
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.len(), 2);
+    for envelope in sent.iter() {
+        let TransactionEnvelope::Tx(TransactionV1Envelope { tx, .. }) = envelope else {
+            panic!("unexpected envelope {envelope:?}");
+        };
+        assert_eq!(tx.memo, Memo::Text(id.as_str().try_into().unwrap()));
+    }
+
+    // And so do the spans of its verification and settlement
+    assert!(logs_contain("facilitator.verify"));
+    assert!(logs_contain("facilitator.settle"));
+    assert!(logs_contain(&format!("correlation_id=\"{id}\"")));
+    assert!(logs_contain("payment settled"));
+}
@@ -0,0 +1,417 @@
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use x402_types::canonical_json;
+
+/// Header carrying the event type
+pub const EVENT_HEADER: &str = "X-X402-Event";
+
+/// Header carrying the delivery ID, stable across retries and replays
+pub const DELIVERY_HEADER: &str = "X-X402-Delivery";
+
+/// Header carrying the `t=<unix>,v1=<hex>` HMAC-SHA256 signature
+pub const SIGNATURE_HEADER: &str = "X-X402-Signature";
+
+/// Default number of dead-lettered deliveries kept in memory, see
+/// [`Webhooks::with_dead_letter_capacity`]
+pub const DEAD_LETTER_CAPACITY: usize = 1000;
+
+/// Type of a webhook event
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum EventKind {
+    /// A payment was charged to its escrow
+    #[serde(rename = "payment.settled")]
+    PaymentSettled,
+    /// A verified payment failed on-chain
+    #[serde(rename = "payment.failed")]
+    PaymentFailed,
+    /// A settlement became final, its ledger being deep enough
+    #[serde(rename = "payment.finalized")]
+    PaymentFinalized,
+    /// A reconciliation found settlements the chain contradicts
+    #[serde(rename = "reconciliation.discrepancy")]
+    ReconciliationDiscrepancy,
+    /// An indexer alert rule started firing
+    #[serde(rename = "alert.triggered")]
+    AlertTriggered,
+    /// A firing indexer alert rule stopped firing
+    #[serde(rename = "alert.resolved")]
+    AlertResolved,
+    /// An escrow closed with payments left unsettled
+    #[serde(rename = "escrow.disputed")]
+    EscrowDisputed,
+}
+
+impl EventKind {
+    /// Every event type
+    pub const ALL: [Self; 7] = [
+        Self::PaymentSettled,
+        Self::PaymentFailed,
+        Self::PaymentFinalized,
+        Self::ReconciliationDiscrepancy,
+        Self::AlertTriggered,
+        Self::AlertResolved,
+        Self::EscrowDisputed,
+    ];
+
+    /// Name used in payloads and configuration
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PaymentSettled => "payment.settled",
+            Self::PaymentFailed => "payment.failed",
+            Self::PaymentFinalized => "payment.finalized",
+            Self::ReconciliationDiscrepancy => "reconciliation.discrepancy",
+            Self::AlertTriggered => "alert.triggered",
+            Self::AlertResolved => "alert.resolved",
+            Self::EscrowDisputed => "escrow.disputed",
+        }
+    }
+
+    /// Parse an event type name
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name)
+    }
+}
+
+/// Payload POSTed to webhook endpoints
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookEvent {
+    /// Unique event ID
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: EventKind,
+    /// Unix timestamp the event happened at
+    pub created_at: u64,
+    pub data: Value,
+}
+
+impl WebhookEvent {
+    /// Build an event of type `kind` happening now, with an ID unique to
+    /// the process
+    pub fn new(kind: EventKind, data: Value) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let created_at = now();
+        let sequence = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        Self {
+            id: format!("evt_{created_at}_{sequence}"),
+            kind,
+            created_at,
+            data,
+        }
+    }
+}
+
+/// Receiver of webhook events
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub url: String,
+    /// HMAC key of the signature header
+    pub secret: String,
+    /// Event types delivered, all of them when empty
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+}
+
+impl Endpoint {
+    fn accepts(&self, kind: EventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+}
+
+/// How failed deliveries and settlement attempts are retried
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts before giving up, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each following one
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay after failed attempt number `attempt`, starting at 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Delivery that failed every attempt
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedDelivery {
+    /// Delivery ID, used to replay it
+    pub id: String,
+    pub url: String,
+    pub event: WebhookEvent,
+    pub attempts: u32,
+    /// Error of the last attempt
+    pub error: String,
+    /// Unix timestamp of the last attempt
+    pub failed_at: u64,
+}
+
+/// Signs and delivers webhook events to the configured endpoints
+///
+/// Each delivery is retried with exponential backoff. Deliveries failing
+/// every attempt are kept in a dead-letter list, optionally appended as JSON
+/// lines to a log file, until replayed. Once the list is full, the oldest
+/// deliveries are dropped from it, remaining in the log file only.
+pub struct Webhooks {
+    http: reqwest::Client,
+    endpoints: Vec<Endpoint>,
+    retry: RetryPolicy,
+    dead_letter_log: Option<PathBuf>,
+    dead_letter_capacity: usize,
+    dead_letters: Mutex<VecDeque<FailedDelivery>>,
+}
+
+impl Webhooks {
+    /// Create a dispatcher for `endpoints`
+    pub fn new(endpoints: Vec<Endpoint>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            endpoints,
+            retry: RetryPolicy::default(),
+            dead_letter_log: None,
+            dead_letter_capacity: DEAD_LETTER_CAPACITY,
+            dead_letters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Replace the retry policy
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Append dead-lettered deliveries to the file at `path`
+    pub fn with_dead_letter_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dead_letter_log = Some(path.into());
+        self
+    }
+
+    /// Keep at most `capacity` dead-lettered deliveries in memory,
+    /// [`DEAD_LETTER_CAPACITY`] by default
+    pub fn with_dead_letter_capacity(mut self, capacity: usize) -> Self {
+        self.dead_letter_capacity = capacity;
+        self
+    }
+
+    /// Configured endpoints
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    /// Build an event of type `kind` happening now
+    pub fn event(&self, kind: EventKind, data: Value) -> WebhookEvent {
+        WebhookEvent::new(kind, data)
+    }
+
+    /// Deliver an event in the background
+    pub fn emit(self: &Arc<Self>, kind: EventKind, data: Value) {
+        self.emit_event(self.event(kind, data));
+    }
+
+    /// Deliver a built event in the background
+    pub fn emit_event(self: &Arc<Self>, event: WebhookEvent) {
+        let webhooks = self.clone();
+        tokio::spawn(async move { webhooks.dispatch(&event).await });
+    }
+
+    /// Deliver an event to every endpoint accepting its type
+    ///
+    /// # Returns
+    /// * The deliveries that were dead-lettered
+    pub async fn dispatch(&self, event: &WebhookEvent) -> Vec<FailedDelivery> {
+        let mut failed = Vec::new();
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            if !endpoint.accepts(event.kind) {
+                continue;
+            }
+            let id = format!("{}_{index}", event.id);
+            if let Err(delivery) = self.deliver(&id, endpoint, event).await {
+                self.dead_letter(delivery.clone());
+                failed.push(delivery);
+            }
+        }
+        failed
+    }
+
+    /// Dead-lettered deliveries, oldest first
+    pub fn failed(&self) -> Vec<FailedDelivery> {
+        self.dead_letters.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Retry a dead-lettered delivery
+    ///
+    /// # Returns
+    /// * None if no dead-lettered delivery has this ID
+    /// * The delivery, dead-lettered again, if it failed every attempt
+    pub async fn replay(&self, id: &str) -> Option<Result<(), FailedDelivery>> {
+        let delivery = {
+            let mut dead_letters = self.dead_letters.lock().unwrap();
+            let index = dead_letters.iter().position(|d| d.id == id)?;
+            dead_letters.remove(index)?
+        };
+        let Some(endpoint) = self.endpoints.iter().find(|e| e.url == delivery.url) else {
+            // The endpoint was removed from the configuration
+            self.keep(delivery.clone());
+            return Some(Err(delivery));
+        };
+        let result = self.deliver(&delivery.id, endpoint, &delivery.event).await;
+        if let Err(delivery) = &result {
+            self.dead_letter(delivery.clone());
+        }
+        Some(result)
+    }
+
+    async fn deliver(
+        &self,
+        id: &str,
+        endpoint: &Endpoint,
+        event: &WebhookEvent,
+    ) -> Result<(), FailedDelivery> {
+        // Canonical, as all JSON the facilitator signs
+        let body = canonical_json(event).unwrap_or_default();
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let error = match self.attempt(id, endpoint, event.kind, &body).await {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+            if attempt >= self.retry.max_attempts {
+                return Err(FailedDelivery {
+                    id: id.into(),
+                    url: endpoint.url.clone(),
+                    event: event.clone(),
+                    attempts: attempt,
+                    error,
+                    failed_at: now(),
+                });
+            }
+            tokio::time::sleep(self.retry.backoff(attempt)).await;
+        }
+    }
+
+    async fn attempt(
+        &self,
+        id: &str,
+        endpoint: &Endpoint,
+        kind: EventKind,
+        body: &str,
+    ) -> Result<(), String> {
+        let response = self
+            .http
+            .post(&endpoint.url)
+            .header("content-type", "application/json")
+            .header(EVENT_HEADER, kind.as_str())
+            .header(DELIVERY_HEADER, id)
+            .header(SIGNATURE_HEADER, sign(&endpoint.secret, now(), body))
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("endpoint answered {}", response.status()))
+        }
+    }
+
+    fn dead_letter(&self, delivery: FailedDelivery) {
+        if let Some(path) = &self.dead_letter_log {
+            let line = serde_json::to_string(&delivery).unwrap_or_default();
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{line}"));
+            if let Err(e) = written {
+                tracing::warn!(error = %e, path = %path.display(), "cannot write dead letter");
+            }
+        }
+        self.keep(delivery);
+    }
+
+    /// Add a delivery to the dead-letter list, dropping the oldest ones
+    /// beyond its capacity
+    fn keep(&self, delivery: FailedDelivery) {
+        let mut dead_letters = self.dead_letters.lock().unwrap();
+        dead_letters.push_back(delivery);
+        while dead_letters.len() > self.dead_letter_capacity {
+            if let Some(dropped) = dead_letters.pop_front() {
+                tracing::warn!(delivery = %dropped.id, "dead-letter list full, dropping");
+            }
+        }
+    }
+}
+
+/// Signature header value for `body` sent at `timestamp`
+///
+/// The HMAC-SHA256 covers `<timestamp>.<body>`, so receivers can reject
+/// replayed requests by their age.
+pub fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    let signature = mac(secret, timestamp, body).finalize().into_bytes();
+    format!("t={timestamp},v1={}", hex::encode(signature))
+}
+
+/// Check a signature header produced by [`sign`]
+pub fn verify_signature(secret: &str, header: &str, body: &str) -> bool {
+    let mut timestamp: Option<u64> = None;
+    let mut signature = None;
+    for part in header.split(',') {
+        match part.split_once('=') {
+            Some(("t", value)) => timestamp = value.parse().ok(),
+            Some(("v1", value)) => signature = hex::decode(value).ok(),
+            _ => {}
+        }
+    }
+    let (Some(timestamp), Some(signature)) = (timestamp, signature) else {
+        return false;
+    };
+    mac(secret, timestamp, body)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+fn mac(secret: &str, timestamp: u64, body: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("any key length");
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    mac
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,320 @@
+use std::sync::Arc;
+
+use rusqlite::{params, types::Type, Row, ToSql, Transaction};
+use serde_json::json;
+use x402_facilitator::{EventKind, WebhookEvent, Webhooks};
+
+use crate::{
+    store::{integer, now},
+    Error,
+};
+
+/// What an alert rule watches
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AlertScope {
+    /// A single escrow
+    Escrow(u64),
+    /// Every escrow paying a server, aggregated
+    Server(String),
+}
+
+/// Quantity an alert rule compares with its threshold
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum AlertMetric {
+    /// Deposited minus settled on open escrows, firing below the threshold
+    ///
+    /// The opening deposit is not in the events, so this is a lower bound
+    /// of the on-chain balance.
+    Balance,
+    /// Total of unsettled payments on open escrows, firing above the threshold
+    PendingExposure,
+    /// Payments left unsettled when their escrow closed, firing above the
+    /// threshold
+    DisputeCount,
+    /// Seconds since the last event of an open escrow, firing above the
+    /// threshold
+    Inactivity,
+}
+
+impl AlertMetric {
+    /// Every metric
+    pub const ALL: [Self; 4] = [
+        Self::Balance,
+        Self::PendingExposure,
+        Self::DisputeCount,
+        Self::Inactivity,
+    ];
+
+    /// Name used in the database, the HTTP API, and webhook payloads
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Balance => "balance",
+            Self::PendingExposure => "pending_exposure",
+            Self::DisputeCount => "dispute_count",
+            Self::Inactivity => "inactivity",
+        }
+    }
+
+    /// Parse a metric name
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|metric| metric.as_str() == name)
+    }
+
+    /// Whether `value` breaches `threshold`
+    pub fn breached(&self, value: i128, threshold: i128) -> bool {
+        match self {
+            Self::Balance => value < threshold,
+            Self::PendingExposure | Self::DisputeCount | Self::Inactivity => value > threshold,
+        }
+    }
+}
+
+/// Threshold on a metric of an escrow or server
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AlertRule {
+    pub scope: AlertScope,
+    pub metric: AlertMetric,
+    /// Stroops, a count, or seconds, depending on the metric
+    pub threshold: i128,
+}
+
+/// State an alert moved to
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AlertState {
+    Triggered,
+    Resolved,
+}
+
+impl AlertState {
+    /// Name used in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Triggered => "triggered",
+            Self::Resolved => "resolved",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        [Self::Triggered, Self::Resolved]
+            .into_iter()
+            .find(|state| state.as_str() == name)
+    }
+}
+
+/// Alert of a rule, firing or as announced by a notification
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Alert {
+    pub rule_id: u64,
+    /// Rule as it was when the alert changed state
+    pub rule: AlertRule,
+    pub state: AlertState,
+    /// Value of the metric, None once nothing it applies to is open
+    pub value: Option<i128>,
+    /// Unix time of the state change
+    pub at: u64,
+}
+
+/// Alert state change waiting to be delivered
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AlertNotification {
+    /// Sequence number, unique within the database
+    pub id: u64,
+    pub alert: Alert,
+}
+
+impl AlertNotification {
+    /// Webhook event announcing the change
+    ///
+    /// The event ID derives from the notification's, so receivers can drop
+    /// a notification delivered twice.
+    pub fn webhook_event(&self) -> WebhookEvent {
+        let alert = &self.alert;
+        let kind = match alert.state {
+            AlertState::Triggered => EventKind::AlertTriggered,
+            AlertState::Resolved => EventKind::AlertResolved,
+        };
+        let mut data = json!({
+            "ruleId": alert.rule_id,
+            "metric": alert.rule.metric.as_str(),
+            "threshold": alert.rule.threshold.to_string(),
+            "value": alert.value.map(|value| value.to_string()),
+        });
+        match &alert.rule.scope {
+            AlertScope::Escrow(escrow_id) => data["escrowId"] = json!(escrow_id),
+            AlertScope::Server(server) => data["server"] = json!(server),
+        }
+        WebhookEvent {
+            id: format!("alert_{}", self.id),
+            kind,
+            created_at: alert.at,
+            data,
+        }
+    }
+}
+
+/// Deliver notifications in the background
+pub(crate) fn deliver(webhooks: &Arc<Webhooks>, notifications: &[AlertNotification]) {
+    for notification in notifications {
+        webhooks.emit_event(notification.webhook_event());
+    }
+}
+
+/// Evaluate every rule against the materialized tables, recording and
+/// announcing the alerts that changed state
+///
+/// A rule already firing is not announced again until it resolved.
+pub(crate) fn evaluate(tx: &Transaction) -> Result<(), Error> {
+    let now = now();
+    let rules = {
+        let mut statement = tx.prepare("SELECT * FROM alert_rules ORDER BY rule_id")?;
+        let rows = statement.query_map([], |row| Ok((row.get("rule_id")?, rule(row)?)))?;
+        rows.collect::<Result<Vec<(u64, AlertRule)>, _>>()?
+    };
+    for (rule_id, rule) in rules {
+        let value = metric_value(tx, &rule, now)?;
+        let breached = value.is_some_and(|value| rule.metric.breached(value, rule.threshold));
+        let firing: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM alerts WHERE rule_id = ?1)",
+            [rule_id],
+            |row| row.get(0),
+        )?;
+        let state = match (breached, firing) {
+            (true, false) => {
+                tx.execute(
+                    "INSERT INTO alerts (rule_id, value, triggered_at) VALUES (?1, ?2, ?3)",
+                    params![rule_id, value.map(integer).transpose()?, now],
+                )?;
+                AlertState::Triggered
+            }
+            (false, true) => {
+                tx.execute("DELETE FROM alerts WHERE rule_id = ?1", [rule_id])?;
+                AlertState::Resolved
+            }
+            (true, true) => {
+                tx.execute(
+                    "UPDATE alerts SET value = ?2 WHERE rule_id = ?1",
+                    params![rule_id, value.map(integer).transpose()?],
+                )?;
+                continue;
+            }
+            (false, false) => continue,
+        };
+        notify(
+            tx,
+            &Alert {
+                rule_id,
+                rule,
+                state,
+                value,
+                at: now,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Queue a notification of an alert state change
+pub(crate) fn notify(tx: &Transaction, alert: &Alert) -> Result<(), Error> {
+    let (escrow_id, server) = scope_columns(&alert.rule.scope);
+    tx.execute(
+        "INSERT INTO alert_outbox (rule_id, escrow_id, server, metric, threshold, state, value, at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            alert.rule_id,
+            escrow_id,
+            server,
+            alert.rule.metric.as_str(),
+            integer(alert.rule.threshold)?,
+            alert.state.as_str(),
+            alert.value.map(integer).transpose()?,
+            alert.at
+        ],
+    )?;
+    Ok(())
+}
+
+/// Current value of the rule's metric, None if nothing it applies to is open
+fn metric_value(tx: &Transaction, rule: &AlertRule, now: u64) -> Result<Option<i128>, Error> {
+    let (filter, subject): (_, &dyn ToSql) = match &rule.scope {
+        AlertScope::Escrow(escrow_id) => ("e.escrow_id = ?1", escrow_id),
+        AlertScope::Server(server) => ("e.server = ?1", server),
+    };
+    let (sql, now) = match rule.metric {
+        AlertMetric::Balance => (
+            format!(
+                "SELECT SUM(e.deposited - e.settled) FROM escrows e
+                 WHERE {filter} AND e.closed_ledger IS NULL"
+            ),
+            None,
+        ),
+        AlertMetric::PendingExposure => (
+            format!(
+                "SELECT CASE WHEN COUNT(DISTINCT e.escrow_id) > 0
+                             THEN COALESCE(SUM(p.amount), 0) END
+                 FROM escrows e LEFT JOIN payments p
+                 ON p.escrow_id = e.escrow_id AND p.settled = 0
+                 WHERE {filter} AND e.closed_ledger IS NULL"
+            ),
+            None,
+        ),
+        AlertMetric::DisputeCount => (
+            format!(
+                "SELECT COUNT(*) FROM payments p JOIN escrows e ON p.escrow_id = e.escrow_id
+                 WHERE {filter} AND e.closed_ledger IS NOT NULL AND p.settled = 0"
+            ),
+            None,
+        ),
+        AlertMetric::Inactivity => (
+            format!(
+                "SELECT MAX(?2 - MAX(e.active_at), 0) FROM escrows e
+                 WHERE {filter} AND e.closed_ledger IS NULL"
+            ),
+            Some(now),
+        ),
+    };
+    let value: Option<i64> = match now {
+        Some(now) => tx.query_row(&sql, params![subject, now], |row| row.get(0))?,
+        None => tx.query_row(&sql, params![subject], |row| row.get(0))?,
+    };
+    Ok(value.map(i128::from))
+}
+
+pub(crate) fn scope_columns(scope: &AlertScope) -> (Option<u64>, Option<&str>) {
+    match scope {
+        AlertScope::Escrow(escrow_id) => (Some(*escrow_id), None),
+        AlertScope::Server(server) => (None, Some(server)),
+    }
+}
+
+/// Rule from the `escrow_id`, `server`, `metric`, and `threshold` columns
+pub(crate) fn rule(row: &Row) -> rusqlite::Result<AlertRule> {
+    let scope = match row.get::<_, Option<u64>>("escrow_id")? {
+        Some(escrow_id) => AlertScope::Escrow(escrow_id),
+        None => AlertScope::Server(row.get("server")?),
+    };
+    let metric: String = row.get("metric")?;
+    Ok(AlertRule {
+        scope,
+        metric: AlertMetric::parse(&metric).ok_or_else(|| invalid_text("metric", &metric))?,
+        threshold: row.get::<_, i64>("threshold")?.into(),
+    })
+}
+
+pub(crate) fn alert_notification(row: &Row) -> rusqlite::Result<AlertNotification> {
+    let state: String = row.get("state")?;
+    Ok(AlertNotification {
+        id: row.get("id")?,
+        alert: Alert {
+            rule_id: row.get("rule_id")?,
+            rule: rule(row)?,
+            state: AlertState::parse(&state).ok_or_else(|| invalid_text("state", &state))?,
+            value: row.get::<_, Option<i64>>("value")?.map(i128::from),
+            at: row.get("at")?,
+        },
+    })
+}
+
+pub(crate) fn invalid_text(column: &str, value: &str) -> rusqlite::Error {
+    let message = format!("unknown {column} {value}");
+    rusqlite::Error::FromSqlConversionFailure(0, Type::Text, message.into())
+}
@@ -0,0 +1,261 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, Request, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        StatusCode,
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{Alert, AlertMetric, AlertRule, AlertScope, Error, ExportQuery, Store};
+
+/// Chunks of an export sent but not yet taken by the HTTP response
+const EXPORT_CHUNKS_IN_FLIGHT: usize = 16;
+
+/// Alert rule as accepted and listed by the HTTP API
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleSummary {
+    /// Assigned by the indexer, ignored when adding a rule
+    #[serde(default)]
+    pub id: u64,
+    /// Escrow watched, exclusive with `server`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escrow_id: Option<u64>,
+    /// Server whose escrows are watched, exclusive with `escrowId`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    /// "balance", "pending_exposure", "dispute_count", or "inactivity"
+    pub metric: String,
+    /// Stroops, a count, or seconds, depending on the metric
+    pub threshold: String,
+}
+
+impl RuleSummary {
+    fn new(id: u64, rule: &AlertRule) -> Self {
+        let (escrow_id, server) = match &rule.scope {
+            AlertScope::Escrow(escrow_id) => (Some(*escrow_id), None),
+            AlertScope::Server(server) => (None, Some(server.clone())),
+        };
+        Self {
+            id,
+            escrow_id,
+            server,
+            metric: rule.metric.as_str().into(),
+            threshold: rule.threshold.to_string(),
+        }
+    }
+
+    /// Rule described by the summary
+    ///
+    /// # Errors
+    /// * A message if the scope, metric, or threshold is invalid
+    pub fn rule(&self) -> Result<AlertRule, String> {
+        let scope = match (self.escrow_id, &self.server) {
+            (Some(escrow_id), None) => AlertScope::Escrow(escrow_id),
+            (None, Some(server)) => AlertScope::Server(server.clone()),
+            _ => return Err("exactly one of escrowId and server is required".into()),
+        };
+        let metric = AlertMetric::parse(&self.metric)
+            .ok_or_else(|| format!("unknown metric {}", self.metric))?;
+        let threshold = self
+            .threshold
+            .parse()
+            .map_err(|_| format!("invalid threshold {}", self.threshold))?;
+        Ok(AlertRule {
+            scope,
+            metric,
+            threshold,
+        })
+    }
+}
+
+/// Firing alert as listed by the HTTP API
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertSummary {
+    pub rule: RuleSummary,
+    /// Value of the metric at the last evaluation
+    pub value: Option<String>,
+    /// Unix time the alert triggered at
+    pub triggered_at: u64,
+}
+
+impl From<&Alert> for AlertSummary {
+    fn from(alert: &Alert) -> Self {
+        Self {
+            rule: RuleSummary::new(alert.rule_id, &alert.rule),
+            value: alert.value.map(|value| value.to_string()),
+            triggered_at: alert.at,
+        }
+    }
+}
+
+/// Build the HTTP router managing alert rules
+///
+/// Requests must carry `Authorization: Bearer <token>`. The store should be
+/// a connection of its own to the indexer's database, rules taking effect
+/// with the next ingested page.
+///
+/// # Endpoints
+/// * `GET /alerts/rules` - List rules
+/// * `POST /alerts/rules` - Add a rule from a [`RuleSummary`]
+/// * `DELETE /alerts/rules/{id}` - Remove a rule, resolving its alert
+/// * `GET /alerts` - List firing alerts
+pub fn alerts_router(store: Arc<Mutex<Store>>, token: impl Into<String>) -> Router {
+    let token: Arc<str> = token.into().into();
+    Router::new()
+        .route("/alerts", get(firing_alerts))
+        .route("/alerts/rules", get(list_rules).post(add_rule))
+        .route("/alerts/rules/{id}", delete(remove_rule))
+        .route_layer(middleware::from_fn(move |request, next| {
+            authorize(token.clone(), request, next)
+        }))
+        .with_state(store)
+}
+
+/// Build the HTTP router exporting payments
+///
+/// Requests must carry `Authorization: Bearer <token>`. Exports stream
+/// from the store while the response is sent, in chunks of at most
+/// [`EXPORT_CHUNK`](crate::EXPORT_CHUNK) bytes; an export failing midway
+/// ends the response early, without its manifest.
+///
+/// # Endpoints
+/// * `GET /exports/payments` - Payments matching the [`ExportQuery`] in the
+///   query string, e.g. `?format=jsonl&server=G...&from=2026-01-01`
+pub fn exports_router(store: Arc<Mutex<Store>>, token: impl Into<String>) -> Router {
+    let token: Arc<str> = token.into().into();
+    Router::new()
+        .route("/exports/payments", get(export_payments))
+        .route_layer(middleware::from_fn(move |request, next| {
+            authorize(token.clone(), request, next)
+        }))
+        .with_state(store)
+}
+
+/// Store error as a status and message
+type Failure = (StatusCode, String);
+
+fn failure(e: Error) -> Failure {
+    let status = match &e {
+        Error::AmountOutOfRange(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string())
+}
+
+async fn list_rules(
+    State(store): State<Arc<Mutex<Store>>>,
+) -> Result<Json<Vec<RuleSummary>>, Failure> {
+    let rules = store.lock().unwrap().alert_rules().map_err(failure)?;
+    Ok(Json(
+        rules
+            .iter()
+            .map(|(id, rule)| RuleSummary::new(*id, rule))
+            .collect(),
+    ))
+}
+
+async fn add_rule(
+    State(store): State<Arc<Mutex<Store>>>,
+    Json(summary): Json<RuleSummary>,
+) -> Result<impl IntoResponse, Failure> {
+    let rule = summary
+        .rule()
+        .map_err(|message| (StatusCode::UNPROCESSABLE_ENTITY, message))?;
+    let id = store
+        .lock()
+        .unwrap()
+        .add_alert_rule(&rule)
+        .map_err(failure)?;
+    Ok((StatusCode::CREATED, Json(RuleSummary::new(id, &rule))))
+}
+
+async fn remove_rule(
+    State(store): State<Arc<Mutex<Store>>>,
+    Path(id): Path<u64>,
+) -> Result<StatusCode, Failure> {
+    let removed = store
+        .lock()
+        .unwrap()
+        .remove_alert_rule(id)
+        .map_err(failure)?;
+    Ok(if removed {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    })
+}
+
+async fn firing_alerts(
+    State(store): State<Arc<Mutex<Store>>>,
+) -> Result<Json<Vec<AlertSummary>>, Failure> {
+    let alerts = store.lock().unwrap().firing_alerts().map_err(failure)?;
+    Ok(Json(alerts.iter().map(AlertSummary::from).collect()))
+}
+
+async fn export_payments(
+    State(store): State<Arc<Mutex<Store>>>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, Failure> {
+    let (format, filter) = query
+        .parse()
+        .map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+    let (chunks, receiver) = mpsc::channel(EXPORT_CHUNKS_IN_FLIGHT);
+    tokio::task::spawn_blocking(move || {
+        let out = ChannelWriter(chunks.clone());
+        if let Err(e) = store.lock().unwrap().export_payments(&filter, format, out) {
+            let _ = chunks.blocking_send(Err(io::Error::other(e.to_string())));
+        }
+    });
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        let chunk = receiver.recv().await?;
+        Some((chunk, receiver))
+    });
+    Ok((
+        [(CONTENT_TYPE, format.content_type())],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+/// Sends what is written to an HTTP response, waiting while it lags behind
+struct ChannelWriter(mpsc::Sender<io::Result<Vec<u8>>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+async fn authorize(token: Arc<str>, request: Request, next: Next) -> Response {
+    let bearer = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if bearer.is_some_and(|value| value == &*token) {
+        next.run(request).await
+    } else {
+        StatusCode::UNAUTHORIZED.into_response()
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::Router;
+use x402_client::{HttpTransport, Rpc};
+use x402_facilitator::{Endpoint, Webhooks};
+
+use crate::{alerts_router, exports_router, Error, Indexer, Notifier, Store};
+
+/// Default path of the SQLite database
+pub const DEFAULT_DATABASE: &str = "x402-indexer.sqlite";
+
+/// Default delay between syncs
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Error reading the indexer configuration
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("missing environment variable {0}")]
+    Missing(&'static str),
+    #[error("invalid {name}: {message}")]
+    Invalid { name: &'static str, message: String },
+}
+
+/// Indexer configuration
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Soroban RPC URL
+    pub rpc_url: String,
+    /// Escrow contract address (C... format)
+    pub contract_id: String,
+    /// Path of the SQLite database
+    pub database: String,
+    /// Ledger to start at when the database has no cursor
+    pub start_ledger: Option<u32>,
+    /// Delay between syncs
+    pub poll_interval: Duration,
+    /// Endpoints alert state changes are POSTed to
+    pub alert_webhooks: Vec<Endpoint>,
+    /// HMAC key of each server notified at the URL it registered, by
+    /// address
+    pub notify_secrets: HashMap<String, String>,
+    /// Address the HTTP API of alert rules and exports listens on, with
+    /// its bearer token
+    pub api: Option<(String, String)>,
+}
+
+impl Config {
+    /// Read the configuration from the environment
+    ///
+    /// # Variables
+    /// * `X402_RPC_URL` - Soroban RPC URL
+    /// * `X402_CONTRACT_ID` - Escrow contract address
+    /// * `X402_INDEXER_DB` - Database path (default `x402-indexer.sqlite`)
+    /// * `X402_START_LEDGER` - First ledger to index (default latest)
+    /// * `X402_POLL_INTERVAL_SECS` - Seconds between syncs (default 5)
+    /// * `X402_ALERT_WEBHOOKS` - Comma-separated URLs alerts are POSTed to
+    /// * `X402_ALERT_WEBHOOK_SECRET` - HMAC key, required with
+    ///   `X402_ALERT_WEBHOOKS`
+    /// * `X402_NOTIFY_SECRETS` - Comma-separated `<server>=<key>` HMAC keys
+    ///   of the servers notified at the URL they registered
+    /// * `X402_INDEXER_BIND` - Address of the HTTP API of alert rules and
+    ///   exports, served only if set
+    /// * `X402_INDEXER_TOKEN` - Bearer token of the HTTP API, required with
+    ///   `X402_INDEXER_BIND`
+    ///
+    /// # Errors
+    /// * `Missing` - If a required variable is not set
+    /// * `Invalid` - If a variable cannot be parsed
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let start_ledger = optional("X402_START_LEDGER")
+            .map(|ledger| parse("X402_START_LEDGER", &ledger))
+            .transpose()?;
+        let poll_interval = optional("X402_POLL_INTERVAL_SECS")
+            .map(|secs| parse("X402_POLL_INTERVAL_SECS", &secs).map(Duration::from_secs))
+            .transpose()?
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+        let alert_webhooks = match optional("X402_ALERT_WEBHOOKS") {
+            Some(urls) => {
+                let secret = required("X402_ALERT_WEBHOOK_SECRET")?;
+                urls.split(',')
+                    .map(str::trim)
+                    .filter(|url| !url.is_empty())
+                    .map(|url| Endpoint {
+                        url: url.into(),
+                        secret: secret.clone(),
+                        events: Vec::new(),
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        let notify_secrets = optional("X402_NOTIFY_SECRETS")
+            .map(|secrets| parse_secrets(&secrets))
+            .transpose()?
+            .unwrap_or_default();
+        let api = optional("X402_INDEXER_BIND")
+            .map(|bind| required("X402_INDEXER_TOKEN").map(|token| (bind, token)))
+            .transpose()?;
+
+        Ok(Self {
+            rpc_url: required("X402_RPC_URL")?,
+            contract_id: required("X402_CONTRACT_ID")?,
+            database: database_path(),
+            start_ledger,
+            poll_interval,
+            alert_webhooks,
+            notify_secrets,
+            api,
+        })
+    }
+
+    /// Open the database and build the indexer
+    pub fn indexer(&self) -> Result<Indexer, Error> {
+        let rpc = Rpc::new(HttpTransport::new(&self.rpc_url));
+        let mut indexer = Indexer::new(rpc, &self.contract_id, Store::open(&self.database)?);
+        if let Some(ledger) = self.start_ledger {
+            indexer = indexer.with_start_ledger(ledger);
+        }
+        if !self.alert_webhooks.is_empty() {
+            indexer = indexer.with_webhooks(Arc::new(Webhooks::new(self.alert_webhooks.clone())));
+        }
+        if !self.notify_secrets.is_empty() {
+            indexer = indexer.with_notifier(Notifier::new(self.notify_secrets.clone()));
+        }
+        Ok(indexer)
+    }
+
+    /// Build the HTTP API of alert rules and exports, on a connection of
+    /// its own to the database
+    ///
+    /// # Returns
+    /// * None if no address is configured
+    pub fn api_router(&self) -> Result<Option<(String, Router)>, Error> {
+        let Some((bind, token)) = &self.api else {
+            return Ok(None);
+        };
+        let store = Arc::new(Mutex::new(Store::open(&self.database)?));
+        let router = alerts_router(store.clone(), token.as_str())
+            .merge(exports_router(store, token.as_str()));
+        Ok(Some((bind.clone(), router)))
+    }
+}
+
+/// Path of the SQLite database, from `X402_INDEXER_DB` (default
+/// `x402-indexer.sqlite`)
+pub fn database_path() -> String {
+    optional("X402_INDEXER_DB").unwrap_or_else(|| DEFAULT_DATABASE.into())
+}
+
+/// HMAC keys of `X402_NOTIFY_SECRETS`, by server address
+fn parse_secrets(secrets: &str) -> Result<HashMap<String, String>, ConfigError> {
+    secrets
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((server, secret)) if !server.is_empty() && !secret.is_empty() => {
+                Ok((server.into(), secret.into()))
+            }
+            _ => Err(ConfigError::Invalid {
+                name: "X402_NOTIFY_SECRETS",
+                message: format!("expected <server>=<key>, got {entry}"),
+            }),
+        })
+        .collect()
+}
+
+fn optional(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn required(name: &'static str) -> Result<String, ConfigError> {
+    optional(name).ok_or(ConfigError::Missing(name))
+}
+
+fn parse<T: std::str::FromStr>(name: &'static str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: std::fmt::Display,
+{
+    value.parse().map_err(|e: T::Err| ConfigError::Invalid {
+        name,
+        message: e.to_string(),
+    })
+}
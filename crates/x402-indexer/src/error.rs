@@ -0,0 +1,19 @@
+/// Errors returned by the indexer
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Fetching events failed
+    #[error(transparent)]
+    Rpc(#[from] x402_client::Error),
+    /// The database rejected a statement
+    #[error("database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    /// An escrow event did not have the expected shape
+    #[error("invalid event {id}: {message}")]
+    InvalidEvent { id: String, message: String },
+    /// An amount does not fit the database integer type
+    #[error("amount {0} out of range")]
+    AmountOutOfRange(i128),
+    /// Writing an export failed
+    #[error("export failed: {0}")]
+    Io(#[from] std::io::Error),
+}
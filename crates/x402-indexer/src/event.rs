@@ -0,0 +1,150 @@
+use stellar_xdr::curr::{Limits, ReadXdr, ScVal};
+use x402_client::EventInfo;
+
+use crate::Error;
+
+/// Typed event emitted by the escrow contract
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EscrowEvent {
+    /// `open_escrow`, which does not publish the opening deposit
+    Opened {
+        escrow_id: u64,
+        client: String,
+        server: String,
+    },
+    /// `create_payment`, identifying the escrow by its parties only
+    PaymentCreated {
+        payment_id: u64,
+        client: String,
+        server: String,
+        amount: i128,
+    },
+    /// `settle_payment`
+    PaymentSettled { payment_id: u64, amount: i128 },
+    /// `deposit`
+    Deposited { escrow_id: u64, amount: i128 },
+    /// Closure by the second party, releasing the remaining balance
+    Closed { escrow_id: u64, released: i128 },
+    /// `set_terms`
+    TermsUpdated {
+        server: String,
+        terms_hash: [u8; 32],
+        previous: Option<[u8; 32]>,
+    },
+    /// `set_notification_url`, None once the URL is removed
+    NotificationUrlUpdated { server: String, url: Option<String> },
+}
+
+impl EscrowEvent {
+    /// Decode an event from its topics and data, as the SDK does
+    ///
+    /// # Returns
+    /// * None for events this indexer does not know about
+    ///
+    /// # Errors
+    /// * `InvalidResponse` - If a known event has unexpected topics or data
+    pub fn decode(topics: &[ScVal], value: &ScVal) -> Result<Option<Self>, x402_client::Error> {
+        Ok(x402_client::EscrowEvent::decode(topics, value)?.and_then(Self::from_sdk))
+    }
+
+    /// Decode a `getEvents` entry
+    ///
+    /// # Errors
+    /// * `InvalidEvent` - If the XDR or a known event payload is malformed
+    pub fn from_info(info: &EventInfo) -> Result<Option<Self>, Error> {
+        let invalid = |message: String| Error::InvalidEvent {
+            id: info.id.clone(),
+            message,
+        };
+        let topics = info
+            .topic
+            .iter()
+            .map(|topic| ScVal::from_xdr_base64(topic, Limits::none()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| invalid(e.to_string()))?;
+        let value = ScVal::from_xdr_base64(&info.value, Limits::none())
+            .map_err(|e| invalid(e.to_string()))?;
+        Self::decode(&topics, &value).map_err(|e| invalid(e.to_string()))
+    }
+
+    /// Event decoded by the SDK, None for those this indexer does not keep
+    fn from_sdk(event: x402_client::EscrowEvent) -> Option<Self> {
+        use x402_client::EscrowEvent as Sdk;
+        Some(match event {
+            Sdk::Opened {
+                escrow_id,
+                client,
+                server,
+            } => Self::Opened {
+                escrow_id,
+                client,
+                server,
+            },
+            Sdk::PaymentCreated {
+                payment_id,
+                client,
+                server,
+                amount,
+            } => Self::PaymentCreated {
+                payment_id,
+                client,
+                server,
+                amount,
+            },
+            Sdk::PaymentSettled { payment_id, amount } => {
+                Self::PaymentSettled { payment_id, amount }
+            }
+            Sdk::Deposited { escrow_id, amount } => Self::Deposited { escrow_id, amount },
+            Sdk::Closed {
+                escrow_id,
+                released,
+            } => Self::Closed {
+                escrow_id,
+                released,
+            },
+            Sdk::TermsUpdated {
+                server,
+                terms_hash,
+                previous,
+            } => Self::TermsUpdated {
+                server,
+                terms_hash,
+                previous,
+            },
+            Sdk::NotificationUrlUpdated { server, url } => {
+                Self::NotificationUrlUpdated { server, url }
+            }
+            Sdk::PaymentRefunded { .. }
+            | Sdk::Reclaimed { .. }
+            | Sdk::Swept { .. }
+            | Sdk::Split { .. }
+            | Sdk::DirectPaid { .. } => return None,
+        })
+    }
+}
+
+/// Parse an ISO 8601 UTC timestamp such as `2024-06-01T12:00:00Z` into unix seconds
+pub fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let timestamp = timestamp.strip_suffix('Z')?;
+    let (date, time) = timestamp.split_once('T')?;
+    let mut date = date.split('-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut time = time.split(':');
+    let hours: u64 = time.next()?.parse().ok()?;
+    let minutes: u64 = time.next()?.parse().ok()?;
+    // Fractional seconds are dropped
+    let seconds: u64 = time.next()?.split('.').next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since 1970-01-01, after Howard Hinnant
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = u64::try_from(era * 146_097 + doe - 719_468).ok()?;
+    Some(days * 86_400 + hours * 3600 + minutes * 60 + seconds)
+}
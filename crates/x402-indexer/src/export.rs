@@ -0,0 +1,303 @@
+use std::io::{BufWriter, Write};
+
+use serde::{Deserialize, Serialize};
+use x402_client::format_timestamp;
+use x402_types::StellarAmount;
+
+use crate::{parse_timestamp, Error, PaymentRecord};
+
+/// Columns of a CSV export, in order
+///
+/// The last row is the manifest: `record` is `manifest`, `payment_id` the
+/// number of payments exported, and the amount columns their total.
+pub const CSV_COLUMNS: [&str; 11] = [
+    "record",
+    "payment_id",
+    "escrow_id",
+    "client",
+    "server",
+    "status",
+    "amount_stroops",
+    "amount",
+    "created_ledger",
+    "created_at",
+    "settled_ledger",
+];
+
+/// Bytes buffered before an export writes them out
+pub const EXPORT_CHUNK: usize = 8 * 1024;
+
+/// Encoding of an export
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ExportFormat {
+    /// Comma-separated, with a header of [`CSV_COLUMNS`]
+    #[default]
+    Csv,
+    /// One JSON object per line, the manifest last
+    Jsonl,
+}
+
+impl ExportFormat {
+    /// Parse a format name, `csv` or `jsonl`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(Self::Csv),
+            "jsonl" => Some(Self::Jsonl),
+            _ => None,
+        }
+    }
+
+    /// Media type of exports in the format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv",
+            Self::Jsonl => "application/jsonl",
+        }
+    }
+}
+
+/// Whether a payment settled
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PaymentStatus {
+    Pending,
+    Settled,
+}
+
+impl PaymentStatus {
+    /// Name used in exports and their filters
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Settled => "settled",
+        }
+    }
+
+    /// Parse a status name
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Pending, Self::Settled]
+            .into_iter()
+            .find(|status| status.as_str() == name)
+    }
+}
+
+/// Payments to export, every one when empty
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ExportFilter {
+    pub server: Option<String>,
+    pub client: Option<String>,
+    /// Unix time payments were created at or after
+    pub from: Option<u64>,
+    /// Unix time payments were created before
+    pub to: Option<u64>,
+    pub status: Option<PaymentStatus>,
+}
+
+/// Export parameters as given in a query string or on the command line
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+pub struct ExportQuery {
+    /// `csv` (default) or `jsonl`
+    pub format: Option<String>,
+    pub server: Option<String>,
+    pub client: Option<String>,
+    /// Unix time, RFC 3339 timestamp, or date, inclusive
+    pub from: Option<String>,
+    /// Unix time, RFC 3339 timestamp, or date, exclusive
+    pub to: Option<String>,
+    /// `pending` or `settled`
+    pub status: Option<String>,
+}
+
+impl ExportQuery {
+    /// Set a parameter by name
+    ///
+    /// # Errors
+    /// * A message if there is no such parameter
+    pub fn set(&mut self, name: &str, value: String) -> Result<(), String> {
+        let field = match name {
+            "format" => &mut self.format,
+            "server" => &mut self.server,
+            "client" => &mut self.client,
+            "from" => &mut self.from,
+            "to" => &mut self.to,
+            "status" => &mut self.status,
+            _ => return Err(format!("unknown export parameter {name}")),
+        };
+        *field = Some(value);
+        Ok(())
+    }
+
+    /// Format and filter asked for
+    ///
+    /// # Errors
+    /// * A message if a parameter is invalid
+    pub fn parse(&self) -> Result<(ExportFormat, ExportFilter), String> {
+        let format = match &self.format {
+            Some(name) => {
+                ExportFormat::parse(name).ok_or_else(|| format!("unknown format {name}"))?
+            }
+            None => ExportFormat::default(),
+        };
+        let time = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|value| parse_time(value).ok_or_else(|| format!("invalid time {value}")))
+                .transpose()
+        };
+        let status = self
+            .status
+            .as_deref()
+            .map(|name| PaymentStatus::parse(name).ok_or_else(|| format!("unknown status {name}")))
+            .transpose()?;
+        Ok((
+            format,
+            ExportFilter {
+                server: self.server.clone(),
+                client: self.client.clone(),
+                from: time(&self.from)?,
+                to: time(&self.to)?,
+                status,
+            },
+        ))
+    }
+}
+
+/// Unix time from seconds, an RFC 3339 timestamp, or a date at midnight UTC
+fn parse_time(value: &str) -> Option<u64> {
+    if let Ok(seconds) = value.parse() {
+        return Some(seconds);
+    }
+    match value.len() {
+        10 => parse_timestamp(&format!("{value}T00:00:00Z")),
+        _ => parse_timestamp(value),
+    }
+}
+
+/// Totals of an export, written as its last record
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ExportManifest {
+    /// Payments exported
+    pub rows: u64,
+    /// Their total amount, in stroops
+    pub amount: i128,
+}
+
+/// One line of a JSON-lines export
+#[derive(Serialize)]
+#[serde(tag = "record", rename_all = "lowercase")]
+enum JsonRecord<'a> {
+    #[serde(rename_all = "camelCase")]
+    Payment {
+        payment_id: u64,
+        escrow_id: Option<u64>,
+        client: &'a str,
+        server: &'a str,
+        status: &'static str,
+        amount_stroops: String,
+        amount: String,
+        created_ledger: u32,
+        created_at: String,
+        settled_ledger: Option<u32>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Manifest {
+        rows: u64,
+        amount_stroops: String,
+        amount: String,
+    },
+}
+
+/// Writes payments as they are read, then the manifest of their totals
+pub(crate) struct ExportWriter<W: Write> {
+    format: ExportFormat,
+    out: BufWriter<W>,
+    manifest: ExportManifest,
+}
+
+impl<W: Write> ExportWriter<W> {
+    /// Start an export, writing its CSV header
+    pub(crate) fn new(format: ExportFormat, out: W) -> Result<Self, Error> {
+        let mut out = BufWriter::with_capacity(EXPORT_CHUNK, out);
+        if format == ExportFormat::Csv {
+            writeln!(out, "{}", CSV_COLUMNS.join(","))?;
+        }
+        Ok(Self {
+            format,
+            out,
+            manifest: ExportManifest::default(),
+        })
+    }
+
+    pub(crate) fn write(&mut self, payment: &PaymentRecord) -> Result<(), Error> {
+        self.manifest.rows += 1;
+        self.manifest.amount += payment.amount;
+        let status = if payment.settled {
+            PaymentStatus::Settled
+        } else {
+            PaymentStatus::Pending
+        };
+        match self.format {
+            ExportFormat::Csv => writeln!(
+                self.out,
+                "payment,{},{},{},{},{},{},{},{},{},{}",
+                payment.payment_id,
+                optional(payment.escrow_id),
+                payment.client,
+                payment.server,
+                status.as_str(),
+                payment.amount,
+                decimal(payment.amount),
+                payment.created_ledger,
+                format_timestamp(payment.created_at),
+                optional(payment.settled_ledger),
+            )?,
+            ExportFormat::Jsonl => self.json(&JsonRecord::Payment {
+                payment_id: payment.payment_id,
+                escrow_id: payment.escrow_id,
+                client: &payment.client,
+                server: &payment.server,
+                status: status.as_str(),
+                amount_stroops: payment.amount.to_string(),
+                amount: decimal(payment.amount),
+                created_ledger: payment.created_ledger,
+                created_at: format_timestamp(payment.created_at),
+                settled_ledger: payment.settled_ledger,
+            })?,
+        }
+        Ok(())
+    }
+
+    /// Write the manifest and flush the export
+    pub(crate) fn finish(mut self) -> Result<ExportManifest, Error> {
+        let ExportManifest { rows, amount } = self.manifest.clone();
+        match self.format {
+            ExportFormat::Csv => writeln!(
+                self.out,
+                "manifest,{rows},,,,,{amount},{},,,",
+                decimal(amount)
+            )?,
+            ExportFormat::Jsonl => self.json(&JsonRecord::Manifest {
+                rows,
+                amount_stroops: amount.to_string(),
+                amount: decimal(amount),
+            })?,
+        }
+        self.out.flush()?;
+        Ok(self.manifest)
+    }
+
+    fn json(&mut self, record: &JsonRecord) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.out, record).map_err(std::io::Error::from)?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// Decimal amount with all seven decimals, e.g. "1.5000000"
+fn decimal(stroops: i128) -> String {
+    StellarAmount::from_stroops(stroops).to_decimal_string()
+}
+
+/// Value of an optional column, empty when missing
+fn optional(value: Option<impl ToString>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
@@ -0,0 +1,130 @@
+use std::{sync::Arc, time::Duration};
+
+use x402_client::{EventsFrom, Rpc};
+use x402_facilitator::Webhooks;
+
+use crate::{alert, Error, Notifier, Store};
+
+/// Default number of events fetched per `getEvents` page
+pub const DEFAULT_PAGE_LIMIT: u32 = 100;
+
+/// Polls `getEvents` for the escrow contract and feeds a [`Store`]
+///
+/// The first poll starts at the configured ledger, or the latest one;
+/// later polls, including after a restart, resume from the stored cursor.
+/// Alert state changes are delivered through the webhooks, if any, after
+/// each poll, and settlements and disputes through the notifier, if any.
+pub struct Indexer {
+    rpc: Rpc,
+    contract_id: String,
+    store: Store,
+    start_ledger: Option<u32>,
+    limit: u32,
+    webhooks: Option<Arc<Webhooks>>,
+    notifier: Option<Notifier>,
+}
+
+impl Indexer {
+    /// Create an indexer for the contract at `contract_id`
+    pub fn new(rpc: Rpc, contract_id: impl Into<String>, store: Store) -> Self {
+        Self {
+            rpc,
+            contract_id: contract_id.into(),
+            store,
+            start_ledger: None,
+            limit: DEFAULT_PAGE_LIMIT,
+            webhooks: None,
+            notifier: None,
+        }
+    }
+
+    /// Start at `ledger` when the store has no cursor yet
+    pub fn with_start_ledger(mut self, ledger: u32) -> Self {
+        self.start_ledger = Some(ledger);
+        self
+    }
+
+    /// Fetch at most `limit` events per page
+    pub fn with_limit(mut self, limit: u32) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Deliver alert state changes through `webhooks`
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Deliver settlements and disputes to the URLs servers registered
+    /// through `notifier`
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Store being fed
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Fetch and ingest one page of events
+    ///
+    /// # Returns
+    /// * Number of events in the page
+    pub async fn poll(&mut self) -> Result<usize, Error> {
+        let from = match self.store.cursor()? {
+            Some(cursor) => EventsFrom::Cursor(cursor),
+            None => match self.start_ledger {
+                Some(ledger) => EventsFrom::Ledger(ledger),
+                None => EventsFrom::Ledger(self.rpc.get_latest_ledger().await?.sequence),
+            },
+        };
+        let page = self
+            .rpc
+            .get_events(&self.contract_id, &from, self.limit)
+            .await?;
+        let cursor = page
+            .cursor
+            .clone()
+            .filter(|cursor| !cursor.is_empty())
+            .or_else(|| page.events.last().map(|event| event.id.clone()));
+        self.store.ingest(&page.events, cursor.as_deref())?;
+        let notifications = self.store.take_alert_notifications()?;
+        if let Some(webhooks) = &self.webhooks {
+            alert::deliver(webhooks, &notifications);
+        }
+        let notifications = self.store.take_notifications()?;
+        if let Some(notifier) = &mut self.notifier {
+            notifier.deliver(&notifications);
+        }
+        Ok(page.events.len())
+    }
+
+    /// Poll until caught up with the latest ledger
+    ///
+    /// # Returns
+    /// * Number of events fetched
+    pub async fn sync(&mut self) -> Result<usize, Error> {
+        let mut total = 0;
+        loop {
+            let fetched = self.poll().await?;
+            total += fetched;
+            if fetched < self.limit as usize {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Sync every `interval`, forever
+    ///
+    /// Failed syncs are logged as warnings and retried at the next tick.
+    pub async fn run(&mut self, interval: Duration) {
+        loop {
+            if let Err(e) = self.sync().await {
+                tracing::warn!(error = %e, "sync failed, retrying at the next tick");
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
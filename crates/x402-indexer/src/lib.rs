@@ -0,0 +1,66 @@
+//! # x402 Indexer
+//!
+//! Materializes the events of the x402 escrow contract into SQLite for
+//! dashboards and reconciliation.
+//!
+//! ## Key Features
+//! - [`Indexer`] polling Soroban RPC `getEvents` with a persisted cursor
+//! - [`EscrowEvent`] decoding of the contract's event payloads, versioned
+//!   or emitted before payloads carried an `event_version`
+//! - [`Store`] tables of escrows, payments, deposits, and closures
+//! - Duplicate delivery ignored, rewritten ledger ranges replayed
+//! - Queries such as [`Store::escrows_for_client`] and
+//!   [`Store::unsettled_payments_older_than`]
+//! - [`AlertRule`] thresholds on escrows and servers, managed through
+//!   [`alerts_router`]
+//! - Payment exports in CSV or JSON lines for finance, see
+//!   [`Store::export_payments`] and [`exports_router`]
+//! - Settlement and dispute webhooks delivered by [`Notifier`] to the URLs
+//!   servers register on-chain
+//!
+//! ## Alerts
+//! Rules on the balance, pending exposure, dispute count, or inactivity of
+//! an escrow, or of every escrow paying a server, are evaluated as each page
+//! of events is ingested. A rule starting to fire is announced once as an
+//! `alert.triggered` webhook event, and as `alert.resolved` when it stops,
+//! through the facilitator's [`Webhooks`](x402_facilitator::Webhooks).
+//!
+//! ## Exports
+//! Payments are exported by server, client, creation time, and status, as
+//! CSV with the columns of [`CSV_COLUMNS`] or as JSON lines. Amounts are
+//! given in stroops and in decimal units, and a last manifest record counts
+//! the payments and totals their amounts, to check an import against.
+//! Exports stream from the database, whatever their size, through
+//! `GET /exports/payments` or `x402-indexer export`.
+//!
+//! ## Notifications
+//! Servers register where they want to be notified with the contract's
+//! `set_notification_url`. The indexer follows these registrations as it
+//! ingests them, so a URL registered, changed, or removed applies from the
+//! next event on without a restart. Each settled payment is then POSTed to
+//! its server as a `payment.settled` webhook event, and each escrow closing
+//! with payments unsettled as `escrow.disputed`. Deliveries are signed with
+//! the server's HMAC key, exchanged out of band and configured by
+//! `X402_NOTIFY_SECRETS`; servers without one are not notified.
+
+mod alert;
+mod api;
+mod config;
+mod error;
+mod event;
+mod export;
+mod indexer;
+mod notify;
+mod store;
+
+pub use alert::*;
+pub use api::*;
+pub use config::*;
+pub use error::*;
+pub use event::*;
+pub use export::*;
+pub use indexer::*;
+pub use notify::*;
+pub use store::*;
+
+mod test;
@@ -0,0 +1,89 @@
+use std::{io, process::ExitCode};
+
+use x402_indexer::{database_path, Config, ExportQuery, Store};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("export") {
+        return export(args);
+    }
+    // Notification failures are logged through tracing
+    if let Err(e) = x402_facilitator::init_tracing() {
+        eprintln!("x402-indexer: tracing: {e}");
+        return ExitCode::FAILURE;
+    }
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("x402-indexer: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut indexer = match config.indexer() {
+        Ok(indexer) => indexer,
+        Err(e) => {
+            eprintln!("x402-indexer: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match config.api_router() {
+        Ok(Some((bind, router))) => {
+            let listener = match tokio::net::TcpListener::bind(&bind).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    eprintln!("x402-indexer: cannot bind {bind}: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            println!("x402-indexer: HTTP API on {bind}");
+            tokio::spawn(async move { axum::serve(listener, router).await });
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("x402-indexer: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    println!(
+        "x402-indexer: indexing {} into {}",
+        config.contract_id, config.database
+    );
+    indexer.run(config.poll_interval).await;
+    ExitCode::SUCCESS
+}
+
+/// Write payments to stdout, filtered by `--format`, `--server`, `--client`,
+/// `--from`, `--to`, and `--status` as the `/exports/payments` query
+fn export(mut args: impl Iterator<Item = String>) -> ExitCode {
+    let mut query = ExportQuery::default();
+    while let Some(arg) = args.next() {
+        let set = match (arg.strip_prefix("--"), args.next()) {
+            (Some(name), Some(value)) => query.set(name, value),
+            _ => Err(format!("expected --<parameter> <value>, got {arg}")),
+        };
+        if let Err(e) = set {
+            eprintln!("x402-indexer: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    let exported = query.parse().and_then(|(format, filter)| {
+        let store = Store::open(database_path()).map_err(|e| e.to_string())?;
+        store
+            .export_payments(&filter, format, io::stdout().lock())
+            .map_err(|e| e.to_string())
+    });
+    match exported {
+        Ok(manifest) => {
+            eprintln!(
+                "x402-indexer: exported {} payments totalling {} stroops",
+                manifest.rows, manifest.amount
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("x402-indexer: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
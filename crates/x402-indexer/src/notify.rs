@@ -0,0 +1,225 @@
+use std::{collections::HashMap, sync::Arc};
+
+use rusqlite::{params, OptionalExtension, Row, Transaction};
+use serde_json::{json, Value};
+use x402_facilitator::{Endpoint, EventKind, RetryPolicy, WebhookEvent, Webhooks};
+
+use crate::{alert::invalid_text, Error, EscrowEvent};
+
+/// Settlement or dispute waiting to be delivered to its server
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notification {
+    /// ID of the contract event notified
+    pub event_id: String,
+    pub server: String,
+    /// URL the server had registered when the event was ingested
+    pub url: String,
+    /// `payment.settled` or `escrow.disputed`
+    pub kind: EventKind,
+    pub data: Value,
+    /// Unix close time of the event's ledger
+    pub at: u64,
+}
+
+impl Notification {
+    /// Webhook event announcing the settlement or dispute
+    ///
+    /// The event ID derives from the contract event's, so receivers can
+    /// drop a notification delivered twice.
+    pub fn webhook_event(&self) -> WebhookEvent {
+        WebhookEvent {
+            id: format!("notify_{}", self.event_id),
+            kind: self.kind,
+            created_at: self.at,
+            data: self.data.clone(),
+        }
+    }
+}
+
+/// Delivers notifications to the URLs servers registered on-chain
+///
+/// Each server's webhooks are signed with its own HMAC key, exchanged out
+/// of band. Notifications of servers without a key are dropped, as they
+/// could not be told from forgeries.
+pub struct Notifier {
+    secrets: HashMap<String, String>,
+    retry: RetryPolicy,
+    /// Dispatcher of each server, for the URL it was created for
+    webhooks: HashMap<String, Arc<Webhooks>>,
+}
+
+impl Notifier {
+    /// Create a notifier signing with the HMAC key of each server, by
+    /// address
+    pub fn new(secrets: HashMap<String, String>) -> Self {
+        Self {
+            secrets,
+            retry: RetryPolicy::default(),
+            webhooks: HashMap::new(),
+        }
+    }
+
+    /// Replace the retry policy
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Deliver notifications in the background
+    pub(crate) fn deliver(&mut self, notifications: &[Notification]) {
+        for notification in notifications {
+            let Some(secret) = self.secrets.get(&notification.server) else {
+                tracing::warn!(
+                    server = notification.server,
+                    event = notification.event_id,
+                    "no notification key, dropping"
+                );
+                continue;
+            };
+            let webhooks = match self.webhooks.get(&notification.server) {
+                Some(webhooks) if webhooks.endpoints()[0].url == notification.url => {
+                    webhooks.clone()
+                }
+                // First notification of the server, or its URL changed
+                _ => {
+                    let endpoint = Endpoint {
+                        url: notification.url.clone(),
+                        secret: secret.clone(),
+                        events: Vec::new(),
+                    };
+                    let webhooks =
+                        Arc::new(Webhooks::new(vec![endpoint]).with_retry(self.retry.clone()));
+                    self.webhooks
+                        .insert(notification.server.clone(), webhooks.clone());
+                    webhooks
+                }
+            };
+            webhooks.emit_event(notification.webhook_event());
+        }
+    }
+}
+
+/// Record or remove a server's notification URL
+pub(crate) fn register(tx: &Transaction, server: &str, url: Option<&str>) -> Result<(), Error> {
+    match url {
+        Some(url) => tx.execute(
+            "INSERT OR REPLACE INTO notify_urls (server, url) VALUES (?1, ?2)",
+            params![server, url],
+        )?,
+        None => tx.execute("DELETE FROM notify_urls WHERE server = ?1", [server])?,
+    };
+    Ok(())
+}
+
+/// Queue the notification of a newly ingested event, if it settled a
+/// payment or closed an escrow with payments unsettled, and its server
+/// registered a URL
+///
+/// Called once the event is applied, never when rebuilding, so replayed
+/// events are not notified again.
+pub(crate) fn queue(
+    tx: &Transaction,
+    event_id: &str,
+    ledger: u32,
+    closed_at: u64,
+    event: &EscrowEvent,
+) -> Result<(), Error> {
+    let (server, kind, mut data) = match event {
+        EscrowEvent::PaymentSettled { payment_id, amount } => {
+            let payment = tx
+                .query_row(
+                    "SELECT escrow_id, client, server FROM payments WHERE payment_id = ?1",
+                    [payment_id],
+                    |row| {
+                        Ok((
+                            row.get::<_, Option<u64>>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                        ))
+                    },
+                )
+                .optional()?;
+            let Some((escrow_id, client, server)) = payment else {
+                return Ok(());
+            };
+            let data = json!({
+                "paymentId": payment_id,
+                "escrowId": escrow_id,
+                "client": client,
+                "amount": amount.to_string(),
+            });
+            (server, EventKind::PaymentSettled, data)
+        }
+        EscrowEvent::Closed {
+            escrow_id,
+            released,
+        } => {
+            let (unsettled, pending): (u64, i64) = tx.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(amount), 0) FROM payments
+                 WHERE escrow_id = ?1 AND settled = 0",
+                [escrow_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            if unsettled == 0 {
+                return Ok(());
+            }
+            let escrow = tx
+                .query_row(
+                    "SELECT client, server FROM escrows WHERE escrow_id = ?1",
+                    [escrow_id],
+                    |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+                )
+                .optional()?;
+            let Some((client, server)) = escrow else {
+                return Ok(());
+            };
+            let data = json!({
+                "escrowId": escrow_id,
+                "client": client,
+                "unsettledPayments": unsettled,
+                "unsettledAmount": pending.to_string(),
+                "released": released.to_string(),
+            });
+            (server, EventKind::EscrowDisputed, data)
+        }
+        _ => return Ok(()),
+    };
+    let url: Option<String> = tx
+        .query_row(
+            "SELECT url FROM notify_urls WHERE server = ?1",
+            [&server],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(url) = url else {
+        return Ok(());
+    };
+    data["server"] = json!(server);
+    data["ledger"] = json!(ledger);
+    tx.execute(
+        "INSERT INTO notify_outbox (event_id, server, url, kind, data, at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            event_id,
+            server,
+            url,
+            kind.as_str(),
+            data.to_string(),
+            closed_at
+        ],
+    )?;
+    Ok(())
+}
+
+pub(crate) fn notification(row: &Row) -> rusqlite::Result<Notification> {
+    let kind: String = row.get("kind")?;
+    let data: String = row.get("data")?;
+    Ok(Notification {
+        event_id: row.get("event_id")?,
+        server: row.get("server")?,
+        url: row.get("url")?,
+        kind: EventKind::parse(&kind).ok_or_else(|| invalid_text("kind", &kind))?,
+        data: serde_json::from_str(&data).map_err(|_| invalid_text("data", &data))?,
+        at: row.get("at")?,
+    })
+}
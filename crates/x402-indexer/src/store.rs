@@ -0,0 +1,630 @@
+use std::{
+    io::Write,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
+use x402_client::EventInfo;
+
+use crate::{
+    alert::{self, alert_notification, scope_columns},
+    export::ExportWriter,
+    notify, parse_timestamp, Alert, AlertNotification, AlertRule, AlertState, Error, EscrowEvent,
+    ExportFilter, ExportFormat, ExportManifest, Notification, PaymentStatus,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS events (
+    id TEXT PRIMARY KEY,
+    ledger INTEGER NOT NULL,
+    closed_at INTEGER NOT NULL,
+    tx_hash TEXT,
+    topic TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS escrows (
+    escrow_id INTEGER PRIMARY KEY,
+    client TEXT NOT NULL,
+    server TEXT NOT NULL,
+    deposited INTEGER NOT NULL DEFAULT 0,
+    settled INTEGER NOT NULL DEFAULT 0,
+    released INTEGER,
+    opened_ledger INTEGER NOT NULL,
+    closed_ledger INTEGER,
+    active_at INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS escrows_client ON escrows (client);
+CREATE TABLE IF NOT EXISTS payments (
+    payment_id INTEGER PRIMARY KEY,
+    escrow_id INTEGER,
+    client TEXT NOT NULL,
+    server TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    created_ledger INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    settled INTEGER NOT NULL DEFAULT 0,
+    settled_ledger INTEGER
+);
+CREATE INDEX IF NOT EXISTS payments_unsettled ON payments (settled, created_at);
+CREATE TABLE IF NOT EXISTS deposits (
+    event_id TEXT PRIMARY KEY,
+    escrow_id INTEGER NOT NULL,
+    amount INTEGER NOT NULL,
+    ledger INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS closures (
+    event_id TEXT PRIMARY KEY,
+    escrow_id INTEGER NOT NULL,
+    released INTEGER NOT NULL,
+    ledger INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS alert_rules (
+    rule_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    escrow_id INTEGER,
+    server TEXT,
+    metric TEXT NOT NULL,
+    threshold INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS alerts (
+    rule_id INTEGER PRIMARY KEY,
+    value INTEGER,
+    triggered_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS alert_outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    rule_id INTEGER NOT NULL,
+    escrow_id INTEGER,
+    server TEXT,
+    metric TEXT NOT NULL,
+    threshold INTEGER NOT NULL,
+    state TEXT NOT NULL,
+    value INTEGER,
+    at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS notify_urls (
+    server TEXT PRIMARY KEY,
+    url TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS notify_outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id TEXT NOT NULL,
+    server TEXT NOT NULL,
+    url TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    data TEXT NOT NULL,
+    at INTEGER NOT NULL
+);
+";
+
+/// Escrow as materialized from its events
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowRecord {
+    pub escrow_id: u64,
+    pub client: String,
+    pub server: String,
+    /// Deposited after opening, the opening deposit is not in the events
+    pub deposited: i128,
+    /// Total of settled payments
+    pub settled: i128,
+    /// Balance released on closure
+    pub released: Option<i128>,
+    pub opened_ledger: u32,
+    pub closed_ledger: Option<u32>,
+    /// Unix close time of the last ledger with an event of the escrow
+    pub active_at: u64,
+}
+
+/// Payment as materialized from its events
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PaymentRecord {
+    pub payment_id: u64,
+    /// None if no open escrow of the pair was indexed
+    pub escrow_id: Option<u64>,
+    pub client: String,
+    pub server: String,
+    pub amount: i128,
+    pub created_ledger: u32,
+    /// Unix close time of the creating ledger
+    pub created_at: u64,
+    pub settled: bool,
+    pub settled_ledger: Option<u32>,
+}
+
+/// SQLite database of escrow contract events and the state they produce
+///
+/// Raw events are kept alongside the materialized tables, so redelivered
+/// events are ignored and a rewritten ledger range can be replayed.
+///
+/// Alert rules are evaluated after each ingested page. Alerts changing
+/// state are queued until taken by [`Store::take_alert_notifications`],
+/// settlements and disputes of servers with a notification URL until taken
+/// by [`Store::take_notifications`].
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Open or create the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::init(Connection::open(path)?)
+    }
+
+    /// Open a database living in memory
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, Error> {
+        // Databases created before notification URLs have none, and those
+        // created before alerts no activity time, which the stored events
+        // rebuild
+        let without_urls = conn.prepare("SELECT url FROM notify_urls").is_err();
+        conn.execute_batch(SCHEMA)?;
+        let without_activity = conn.prepare("SELECT active_at FROM escrows").is_err();
+        if without_urls || without_activity {
+            let tx = conn.transaction()?;
+            if without_activity {
+                tx.execute(
+                    "ALTER TABLE escrows ADD COLUMN active_at INTEGER NOT NULL DEFAULT 0",
+                    [],
+                )?;
+            }
+            rebuild(&tx)?;
+            tx.commit()?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Cursor after the last ingested page
+    pub fn cursor(&self) -> Result<Option<String>, Error> {
+        Ok(self
+            .conn
+            .query_row("SELECT value FROM meta WHERE key = 'cursor'", [], |row| {
+                row.get(0)
+            })
+            .optional()?)
+    }
+
+    /// Ingest a page of events and persist its cursor, atomically
+    ///
+    /// Events already ingested are skipped. An unknown event ordered before
+    /// the latest ingested one means the ledger range was rewritten: events
+    /// from its ledger on are dropped and the state rebuilt before ingesting.
+    /// Alert rules are evaluated once the page is applied, even an empty one.
+    /// Settlements and disputes are queued for the notification URL their
+    /// server registered as of the event, a registration earlier in the
+    /// page included.
+    ///
+    /// # Arguments
+    /// * `events` - Events in the order returned by `getEvents`
+    /// * `cursor` - Cursor to resume after this page
+    ///
+    /// # Returns
+    /// * Number of newly ingested events
+    ///
+    /// # Errors
+    /// * `InvalidEvent` - If an escrow event is malformed, nothing is ingested
+    pub fn ingest(&mut self, events: &[EventInfo], cursor: Option<&str>) -> Result<usize, Error> {
+        let tx = self.conn.transaction()?;
+        let mut ingested = 0;
+        for info in events.iter().filter(|e| e.in_successful_contract_call) {
+            let known: bool = tx.query_row(
+                "SELECT EXISTS (SELECT 1 FROM events WHERE id = ?1)",
+                [&info.id],
+                |row| row.get(0),
+            )?;
+            if known {
+                continue;
+            }
+            let latest: Option<String> =
+                tx.query_row("SELECT MAX(id) FROM events", [], |row| row.get(0))?;
+            if latest.is_some_and(|latest| info.id < latest) {
+                tx.execute("DELETE FROM events WHERE ledger >= ?1", [info.ledger])?;
+                rebuild(&tx)?;
+            }
+
+            let closed_at = parse_timestamp(&info.ledger_closed_at).unwrap_or_default();
+            tx.execute(
+                "INSERT INTO events (id, ledger, closed_at, tx_hash, topic, value)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    info.id,
+                    info.ledger,
+                    closed_at,
+                    info.tx_hash,
+                    info.topic.join(","),
+                    info.value
+                ],
+            )?;
+            if let Some(event) = EscrowEvent::from_info(info)? {
+                apply(&tx, &info.id, info.ledger, closed_at, &event)?;
+                notify::queue(&tx, &info.id, info.ledger, closed_at, &event)?;
+            }
+            ingested += 1;
+        }
+        if let Some(cursor) = cursor {
+            tx.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('cursor', ?1)",
+                [cursor],
+            )?;
+        }
+        alert::evaluate(&tx)?;
+        tx.commit()?;
+        Ok(ingested)
+    }
+
+    /// Add an alert rule, first evaluated with the next ingested page
+    ///
+    /// # Returns
+    /// * ID of the rule
+    ///
+    /// # Errors
+    /// * `AmountOutOfRange` - If the threshold does not fit the database
+    pub fn add_alert_rule(&mut self, rule: &AlertRule) -> Result<u64, Error> {
+        let (escrow_id, server) = scope_columns(&rule.scope);
+        self.conn.execute(
+            "INSERT INTO alert_rules (escrow_id, server, metric, threshold)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                escrow_id,
+                server,
+                rule.metric.as_str(),
+                integer(rule.threshold)?
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid() as u64)
+    }
+
+    /// Alert rules with their IDs, by ID
+    pub fn alert_rules(&self) -> Result<Vec<(u64, AlertRule)>, Error> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT * FROM alert_rules ORDER BY rule_id")?;
+        let rows = statement.query_map([], |row| Ok((row.get("rule_id")?, alert::rule(row)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Remove an alert rule, resolving its alert if firing
+    ///
+    /// # Returns
+    /// * Whether the rule existed
+    pub fn remove_alert_rule(&mut self, rule_id: u64) -> Result<bool, Error> {
+        let tx = self.conn.transaction()?;
+        let firing = firing_alerts(&tx, Some(rule_id))?;
+        for firing in firing {
+            alert::notify(
+                &tx,
+                &Alert {
+                    state: AlertState::Resolved,
+                    at: now(),
+                    ..firing
+                },
+            )?;
+        }
+        tx.execute("DELETE FROM alerts WHERE rule_id = ?1", [rule_id])?;
+        let removed = tx.execute("DELETE FROM alert_rules WHERE rule_id = ?1", [rule_id])?;
+        tx.commit()?;
+        Ok(removed > 0)
+    }
+
+    /// Alerts currently firing, by rule ID
+    pub fn firing_alerts(&self) -> Result<Vec<Alert>, Error> {
+        firing_alerts(&self.conn, None)
+    }
+
+    /// Take the queued alert state changes, oldest first
+    pub fn take_alert_notifications(&mut self) -> Result<Vec<AlertNotification>, Error> {
+        let tx = self.conn.transaction()?;
+        let notifications = {
+            let mut statement = tx.prepare("SELECT * FROM alert_outbox ORDER BY id")?;
+            let rows = statement.query_map([], alert_notification)?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+        tx.execute("DELETE FROM alert_outbox", [])?;
+        tx.commit()?;
+        Ok(notifications)
+    }
+
+    /// Take the queued settlement and dispute notifications, oldest first
+    pub fn take_notifications(&mut self) -> Result<Vec<Notification>, Error> {
+        let tx = self.conn.transaction()?;
+        let notifications = {
+            let mut statement = tx.prepare("SELECT * FROM notify_outbox ORDER BY id")?;
+            let rows = statement.query_map([], notify::notification)?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+        tx.execute("DELETE FROM notify_outbox", [])?;
+        tx.commit()?;
+        Ok(notifications)
+    }
+
+    /// URL the server registered for notifications, None if it registered
+    /// none or removed it
+    pub fn notification_url(&self, server: &str) -> Result<Option<String>, Error> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT url FROM notify_urls WHERE server = ?1",
+                [server],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Escrow by ID
+    pub fn escrow(&self, escrow_id: u64) -> Result<Option<EscrowRecord>, Error> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT * FROM escrows WHERE escrow_id = ?1",
+                [escrow_id],
+                escrow_record,
+            )
+            .optional()?)
+    }
+
+    /// Payment by ID
+    pub fn payment(&self, payment_id: u64) -> Result<Option<PaymentRecord>, Error> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT * FROM payments WHERE payment_id = ?1",
+                [payment_id],
+                payment_record,
+            )
+            .optional()?)
+    }
+
+    /// Escrows opened by `client`, open or closed, by ID
+    pub fn escrows_for_client(&self, client: &str) -> Result<Vec<EscrowRecord>, Error> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT * FROM escrows WHERE client = ?1 ORDER BY escrow_id")?;
+        let rows = statement.query_map([client], escrow_record)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Unsettled payments created more than `age` ago, by ID
+    pub fn unsettled_payments_older_than(
+        &self,
+        age: Duration,
+    ) -> Result<Vec<PaymentRecord>, Error> {
+        let cutoff = now().saturating_sub(age.as_secs());
+        let mut statement = self.conn.prepare(
+            "SELECT * FROM payments WHERE settled = 0 AND created_at < ?1 ORDER BY payment_id",
+        )?;
+        let rows = statement.query_map([cutoff], payment_record)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Write the payments matching `filter` to `out` by ID, then the
+    /// manifest of their totals
+    ///
+    /// Payments are written as they are read, in chunks of at most
+    /// [`EXPORT_CHUNK`](crate::EXPORT_CHUNK) bytes, so exports of any size
+    /// run in bounded memory.
+    ///
+    /// # Errors
+    /// * `Io` - If writing to `out` fails, the export being cut short
+    pub fn export_payments(
+        &self,
+        filter: &ExportFilter,
+        format: ExportFormat,
+        out: impl Write,
+    ) -> Result<ExportManifest, Error> {
+        let mut statement = self.conn.prepare(
+            "SELECT * FROM payments
+             WHERE (?1 IS NULL OR server = ?1) AND (?2 IS NULL OR client = ?2)
+               AND (?3 IS NULL OR created_at >= ?3) AND (?4 IS NULL OR created_at < ?4)
+               AND (?5 IS NULL OR settled = ?5)
+             ORDER BY payment_id",
+        )?;
+        let settled = filter.status.map(|status| status == PaymentStatus::Settled);
+        let mut rows = statement.query(params![
+            filter.server,
+            filter.client,
+            filter.from,
+            filter.to,
+            settled
+        ])?;
+        let mut export = ExportWriter::new(format, out)?;
+        while let Some(row) = rows.next()? {
+            export.write(&payment_record(row)?)?;
+        }
+        export.finish()
+    }
+}
+
+/// Alerts firing, of every rule or only `rule_id`
+fn firing_alerts(conn: &Connection, rule_id: Option<u64>) -> Result<Vec<Alert>, Error> {
+    let mut statement = conn.prepare(
+        "SELECT * FROM alerts JOIN alert_rules USING (rule_id)
+         WHERE ?1 IS NULL OR rule_id = ?1 ORDER BY rule_id",
+    )?;
+    let rows = statement.query_map([rule_id], |row| {
+        Ok(Alert {
+            rule_id: row.get("rule_id")?,
+            rule: alert::rule(row)?,
+            state: AlertState::Triggered,
+            value: row.get::<_, Option<i64>>("value")?.map(i128::from),
+            at: row.get("triggered_at")?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Recompute the materialized tables from the stored events
+fn rebuild(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "DELETE FROM escrows; DELETE FROM payments; DELETE FROM deposits; DELETE FROM closures;
+         DELETE FROM notify_urls;",
+    )?;
+    let mut statement =
+        tx.prepare("SELECT id, ledger, closed_at, topic, value FROM events ORDER BY id")?;
+    let events = statement
+        .query_map([], |row| {
+            let topic: String = row.get(3)?;
+            Ok((
+                EventInfo {
+                    kind: "contract".into(),
+                    ledger: row.get(1)?,
+                    ledger_closed_at: String::new(),
+                    contract_id: String::new(),
+                    id: row.get(0)?,
+                    topic: topic.split(',').map(String::from).collect(),
+                    value: row.get(4)?,
+                    in_successful_contract_call: true,
+                    tx_hash: None,
+                },
+                row.get::<_, u64>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (info, closed_at) in events {
+        if let Some(event) = EscrowEvent::from_info(&info)? {
+            apply(tx, &info.id, info.ledger, closed_at, &event)?;
+        }
+    }
+    Ok(())
+}
+
+/// Apply one event to the materialized tables
+fn apply(
+    tx: &Transaction,
+    id: &str,
+    ledger: u32,
+    closed_at: u64,
+    event: &EscrowEvent,
+) -> Result<(), Error> {
+    match event {
+        EscrowEvent::Opened {
+            escrow_id,
+            client,
+            server,
+        } => {
+            tx.execute(
+                "INSERT OR REPLACE INTO escrows
+                 (escrow_id, client, server, opened_ledger, active_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![escrow_id, client, server, ledger, closed_at],
+            )?;
+        }
+        EscrowEvent::Deposited { escrow_id, amount } => {
+            let amount = integer(*amount)?;
+            tx.execute(
+                "INSERT INTO deposits (event_id, escrow_id, amount, ledger) VALUES (?1, ?2, ?3, ?4)",
+                params![id, escrow_id, amount, ledger],
+            )?;
+            tx.execute(
+                "UPDATE escrows SET deposited = deposited + ?2, active_at = ?3
+                 WHERE escrow_id = ?1",
+                params![escrow_id, amount, closed_at],
+            )?;
+        }
+        EscrowEvent::PaymentCreated {
+            payment_id,
+            client,
+            server,
+            amount,
+        } => {
+            tx.execute(
+                "INSERT OR REPLACE INTO payments
+                 (payment_id, escrow_id, client, server, amount, created_ledger, created_at)
+                 VALUES (?1, (SELECT MAX(escrow_id) FROM escrows
+                              WHERE client = ?2 AND server = ?3 AND closed_ledger IS NULL),
+                         ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    payment_id,
+                    client,
+                    server,
+                    integer(*amount)?,
+                    ledger,
+                    closed_at
+                ],
+            )?;
+            tx.execute(
+                "UPDATE escrows SET active_at = ?2
+                 WHERE escrow_id = (SELECT escrow_id FROM payments WHERE payment_id = ?1)",
+                params![payment_id, closed_at],
+            )?;
+        }
+        EscrowEvent::PaymentSettled { payment_id, amount } => {
+            tx.execute(
+                "UPDATE payments SET settled = 1, settled_ledger = ?2 WHERE payment_id = ?1",
+                params![payment_id, ledger],
+            )?;
+            tx.execute(
+                "UPDATE escrows SET settled = settled + ?2, active_at = ?3
+                 WHERE escrow_id = (SELECT escrow_id FROM payments WHERE payment_id = ?1)",
+                params![payment_id, integer(*amount)?, closed_at],
+            )?;
+        }
+        EscrowEvent::Closed {
+            escrow_id,
+            released,
+        } => {
+            let released = integer(*released)?;
+            tx.execute(
+                "INSERT INTO closures (event_id, escrow_id, released, ledger) VALUES (?1, ?2, ?3, ?4)",
+                params![id, escrow_id, released, ledger],
+            )?;
+            tx.execute(
+                "UPDATE escrows SET released = ?2, closed_ledger = ?3, active_at = ?4
+                 WHERE escrow_id = ?1",
+                params![escrow_id, released, ledger, closed_at],
+            )?;
+        }
+        // Terms are read from the contract, escrows keep those they accepted
+        EscrowEvent::TermsUpdated { .. } => {}
+        EscrowEvent::NotificationUrlUpdated { server, url } => {
+            notify::register(tx, server, url.as_deref())?;
+        }
+    }
+    Ok(())
+}
+
+/// SQLite integers are 64-bit
+pub(crate) fn integer(amount: i128) -> Result<i64, Error> {
+    i64::try_from(amount).map_err(|_| Error::AmountOutOfRange(amount))
+}
+
+/// Unix time in seconds
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn escrow_record(row: &Row) -> rusqlite::Result<EscrowRecord> {
+    Ok(EscrowRecord {
+        escrow_id: row.get("escrow_id")?,
+        client: row.get("client")?,
+        server: row.get("server")?,
+        deposited: row.get::<_, i64>("deposited")?.into(),
+        settled: row.get::<_, i64>("settled")?.into(),
+        released: row.get::<_, Option<i64>>("released")?.map(i128::from),
+        opened_ledger: row.get("opened_ledger")?,
+        closed_ledger: row.get("closed_ledger")?,
+        active_at: row.get("active_at")?,
+    })
+}
+
+fn payment_record(row: &Row) -> rusqlite::Result<PaymentRecord> {
+    Ok(PaymentRecord {
+        payment_id: row.get("payment_id")?,
+        escrow_id: row.get("escrow_id")?,
+        client: row.get("client")?,
+        server: row.get("server")?,
+        amount: row.get::<_, i64>("amount")?.into(),
+        created_ledger: row.get("created_ledger")?,
+        created_at: row.get("created_at")?,
+        settled: row.get("settled")?,
+        settled_ledger: row.get("settled_ledger")?,
+    })
+}
@@ -0,0 +1,1079 @@
+#![cfg(test)]
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        HeaderMap, Method, Request, StatusCode,
+    },
+    routing, Router,
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use stellar_xdr::curr::{Limits, ScBytes, ScMap, ScMapEntry, ScVal, WriteXdr};
+use tower::ServiceExt;
+use x402_client::{
+    scval,
+    testutils::{EnvTransport, NETWORK_PASSPHRASE},
+    EscrowClient, EventInfo, GetEventsResponse, LocalSigner, Rpc, EVENT_VERSION,
+};
+use x402_escrow::X402EscrowContract;
+use x402_facilitator::{verify_signature, Endpoint, EventKind, Webhooks, SIGNATURE_HEADER};
+
+use crate::{
+    alerts_router, exports_router, parse_timestamp, AlertMetric, AlertNotification, AlertRule,
+    AlertScope, AlertState, Error, EscrowEvent, ExportFilter, ExportFormat, ExportManifest,
+    ExportQuery, Indexer, Notifier, PaymentStatus, Store, CSV_COLUMNS, EXPORT_CHUNK,
+};
+
+const OLD: &str = "2020-01-01T00:00:00Z";
+const RECENT: &str = "2999-01-01T00:00:00Z";
+
+fn client() -> String {
+    LocalSigner::from_bytes(&[1; 32]).address()
+}
+
+fn server() -> String {
+    LocalSigner::from_bytes(&[2; 32]).address()
+}
+
+fn symbol(name: &str) -> ScVal {
+    ScVal::Symbol(name.try_into().unwrap())
+}
+
+/// Synthetic `getEvents` entry, the ID ordered by ledger then `index`
+fn event(ledger: u32, index: u32, closed_at: &str, topic: Vec<ScVal>, value: ScVal) -> EventInfo {
+    EventInfo {
+        kind: "contract".into(),
+        ledger,
+        ledger_closed_at: closed_at.into(),
+        contract_id: "CDLZFC3SYJYDZT7K67VZ75HPJVIEUVNIXF47ZG2FB2RMQQVU2HHGCYSC".into(),
+        id: format!("{:019}-{index:010}", u64::from(ledger) << 32),
+        topic: topic
+            .iter()
+            .map(|t| t.to_xdr_base64(Limits::none()).unwrap())
+            .collect(),
+        value: value.to_xdr_base64(Limits::none()).unwrap(),
+        in_successful_contract_call: true,
+        tx_hash: Some("ab".repeat(32)),
+    }
+}
+
+fn opened(ledger: u32, escrow_id: u64) -> EventInfo {
+    let topic = vec![
+        symbol("open"),
+        scval::address(&client()).unwrap(),
+        scval::address(&server()).unwrap(),
+    ];
+    event(ledger, 0, OLD, topic, scval::u64(escrow_id))
+}
+
+fn deposited(ledger: u32, index: u32, escrow_id: u64, amount: i128) -> EventInfo {
+    let topic = vec![symbol("deposit"), scval::u64(escrow_id)];
+    event(ledger, index, OLD, topic, scval::i128(amount))
+}
+
+fn paid(ledger: u32, closed_at: &str, payment_id: u64, amount: i128) -> EventInfo {
+    let topic = vec![
+        symbol("pay"),
+        scval::address(&server()).unwrap(),
+        scval::address(&client()).unwrap(),
+    ];
+    let value = ScVal::Vec(Some(
+        vec![scval::u64(payment_id), scval::i128(amount)]
+            .try_into()
+            .unwrap(),
+    ));
+    event(ledger, 0, closed_at, topic, value)
+}
+
+fn settled(ledger: u32, payment_id: u64, amount: i128) -> EventInfo {
+    let topic = vec![symbol("settled"), scval::u64(payment_id)];
+    event(ledger, 0, OLD, topic, scval::i128(amount))
+}
+
+fn closed(ledger: u32, escrow_id: u64, released: i128) -> EventInfo {
+    let topic = vec![symbol("closed"), scval::u64(escrow_id)];
+    event(ledger, 0, OLD, topic, scval::i128(released))
+}
+
+fn notify(ledger: u32, url: Option<&str>) -> EventInfo {
+    let topic = vec![symbol("notify"), scval::address(&server()).unwrap()];
+    let url = url.map_or(ScVal::Void, |url| {
+        ScVal::Bytes(ScBytes(url.as_bytes().try_into().unwrap()))
+    });
+    event(ledger, 0, OLD, topic, versioned(vec![("url", url)]))
+}
+
+#[test]
+fn test_decode_events() {
+    let info = paid(5, OLD, 3, 250);
+    assert_eq!(
+        EscrowEvent::from_info(&info).unwrap(),
+        Some(EscrowEvent::PaymentCreated {
+            payment_id: 3,
+            client: client(),
+            server: server(),
+            amount: 250,
+        })
+    );
+
+    // Unknown events are skipped, malformed known ones rejected
+    let unknown = event(5, 1, OLD, vec![symbol("upgrade")], ScVal::Void);
+    assert_eq!(EscrowEvent::from_info(&unknown).unwrap(), None);
+    let malformed = event(5, 2, OLD, vec![symbol("deposit")], scval::i128(1));
+    let err = EscrowEvent::from_info(&malformed).unwrap_err();
+    assert!(matches!(err, Error::InvalidEvent { .. }), "{err}");
+
+    assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+    assert_eq!(parse_timestamp("2000-02-29T12:34:56Z"), Some(951_827_696));
+    assert_eq!(
+        parse_timestamp("2024-12-31T23:59:59.5Z"),
+        Some(1_735_689_599)
+    );
+    assert_eq!(parse_timestamp("2024-13-01T00:00:00Z"), None);
+    assert_eq!(parse_timestamp("yesterday"), None);
+}
+
+/// Struct payload of an event as emitted since payloads are versioned
+fn versioned(mut fields: Vec<(&str, ScVal)>) -> ScVal {
+    fields.push(("event_version", ScVal::U32(EVENT_VERSION)));
+    // Struct fields are encoded in name order
+    fields.sort_by_key(|(name, _)| *name);
+    let entries: Vec<ScMapEntry> = fields
+        .into_iter()
+        .map(|(name, val)| ScMapEntry {
+            key: symbol(name),
+            val,
+        })
+        .collect();
+    ScVal::Map(Some(ScMap(entries.try_into().unwrap())))
+}
+
+#[test]
+fn test_ingest_across_event_versions() {
+    let mut store = Store::open_in_memory().unwrap();
+
+    // History emitted before the upgrade, as captured by the SDK's fixture
+    let page: GetEventsResponse =
+        serde_json::from_str(include_str!("../../x402-client/fixtures/events_v1.json")).unwrap();
+    assert_eq!(
+        store.ingest(&page.events, page.cursor.as_deref()).unwrap(),
+        6
+    );
+    let Some(EscrowEvent::Opened {
+        client: fixture_client,
+        ..
+    }) = EscrowEvent::from_info(&page.events[0]).unwrap()
+    else {
+        panic!("fixture starts with an opened escrow");
+    };
+    let escrows = store.escrows_for_client(&fixture_client).unwrap();
+    assert_eq!(escrows[0].deposited, 500);
+    assert_eq!(escrows[0].settled, 300);
+    assert_eq!(escrows[0].released, Some(1_200));
+
+    // An escrow opened before the upgrade and used after it
+    let batch = [
+        opened(20, 1),
+        deposited(21, 0, 1, 500),
+        event(
+            22,
+            0,
+            OLD,
+            vec![symbol("deposit"), scval::u64(1)],
+            versioned(vec![("amount", scval::i128(250))]),
+        ),
+        event(
+            23,
+            0,
+            OLD,
+            vec![
+                symbol("pay"),
+                scval::address(&server()).unwrap(),
+                scval::address(&client()).unwrap(),
+            ],
+            versioned(vec![
+                ("payment_id", scval::u64(4)),
+                ("amount", scval::i128(100)),
+            ]),
+        ),
+        event(
+            24,
+            0,
+            OLD,
+            vec![symbol("settled"), scval::u64(4)],
+            versioned(vec![("amount", scval::i128(100))]),
+        ),
+    ];
+    assert_eq!(store.ingest(&batch, None).unwrap(), 5);
+    let escrows = store.escrows_for_client(&client()).unwrap();
+    assert_eq!(escrows[0].escrow_id, 1);
+    assert_eq!(escrows[0].deposited, 750);
+    assert_eq!(escrows[0].settled, 100);
+    assert_eq!(store.payment(4).unwrap().unwrap().escrow_id, Some(1));
+}
+
+#[test]
+fn test_materialized_state() {
+    let mut store = Store::open_in_memory().unwrap();
+    let batch = [
+        opened(10, 0),
+        deposited(11, 0, 0, 500),
+        paid(12, OLD, 0, 200),
+        paid(13, RECENT, 1, 300),
+        paid(14, OLD, 2, 100),
+        settled(15, 0, 200),
+    ];
+    assert_eq!(store.ingest(&batch, Some("cursor-1")).unwrap(), 6);
+    assert_eq!(store.cursor().unwrap().as_deref(), Some("cursor-1"));
+
+    let escrows = store.escrows_for_client(&client()).unwrap();
+    assert_eq!(escrows.len(), 1);
+    assert_eq!(escrows[0].server, server());
+    assert_eq!(escrows[0].deposited, 500);
+    assert_eq!(escrows[0].settled, 200);
+    assert_eq!(escrows[0].closed_ledger, None);
+    assert!(store.escrows_for_client(&server()).unwrap().is_empty());
+
+    // Payments are matched to the open escrow of their parties
+    let payment = store.payment(1).unwrap().unwrap();
+    assert_eq!(payment.escrow_id, Some(0));
+    assert_eq!(payment.created_at, parse_timestamp(RECENT).unwrap());
+    assert!(store.payment(0).unwrap().unwrap().settled);
+
+    let stale = store
+        .unsettled_payments_older_than(Duration::from_secs(3600))
+        .unwrap();
+    let ids: Vec<u64> = stale.iter().map(|p| p.payment_id).collect();
+    assert_eq!(ids, vec![2]);
+
+    // A closed pair may open a new escrow, later payments go to it
+    let batch = [closed(16, 0, 300), opened(17, 1), paid(18, OLD, 3, 50)];
+    assert_eq!(store.ingest(&batch, None).unwrap(), 3);
+    let escrows = store.escrows_for_client(&client()).unwrap();
+    assert_eq!(escrows[0].released, Some(300));
+    assert_eq!(escrows[0].closed_ledger, Some(16));
+    assert_eq!(escrows[1].escrow_id, 1);
+    assert_eq!(store.payment(3).unwrap().unwrap().escrow_id, Some(1));
+    // The cursor is kept when a page has none
+    assert_eq!(store.cursor().unwrap().as_deref(), Some("cursor-1"));
+}
+
+#[test]
+fn test_duplicate_delivery() {
+    let mut store = Store::open_in_memory().unwrap();
+    let batch = [opened(10, 0), deposited(11, 0, 0, 500), settled(12, 7, 1)];
+    assert_eq!(store.ingest(&batch, None).unwrap(), 3);
+
+    // The same page again, then an overlapping one
+    assert_eq!(store.ingest(&batch, None).unwrap(), 0);
+    let overlap = [deposited(11, 0, 0, 500), deposited(13, 0, 0, 250)];
+    assert_eq!(store.ingest(&overlap, None).unwrap(), 1);
+    assert_eq!(store.escrow(0).unwrap().unwrap().deposited, 750);
+
+    // Events of failed calls are not state changes
+    let mut failed = deposited(14, 0, 0, 1_000);
+    failed.in_successful_contract_call = false;
+    assert_eq!(store.ingest(&[failed], None).unwrap(), 0);
+    assert_eq!(store.escrow(0).unwrap().unwrap().deposited, 750);
+}
+
+#[test]
+fn test_rewritten_ledgers_are_replayed() {
+    let mut store = Store::open_in_memory().unwrap();
+    let batch = [
+        opened(10, 0),
+        deposited(11, 0, 0, 100),
+        deposited(12, 0, 0, 500),
+        closed(13, 0, 600),
+    ];
+    store.ingest(&batch, None).unwrap();
+
+    // Ledger 12 onwards was rewritten: a different deposit, no closure
+    let rewritten = [deposited(12, 1, 0, 200)];
+    assert_eq!(store.ingest(&rewritten, None).unwrap(), 1);
+    let escrow = store.escrow(0).unwrap().unwrap();
+    assert_eq!(escrow.deposited, 300);
+    assert_eq!(escrow.released, None);
+    assert_eq!(escrow.closed_ledger, None);
+
+    // Later events apply on top of the replayed state
+    store.ingest(&[deposited(14, 0, 0, 50)], None).unwrap();
+    assert_eq!(store.escrow(0).unwrap().unwrap().deposited, 350);
+}
+
+#[test]
+fn test_invalid_batch_is_not_ingested() {
+    let mut store = Store::open_in_memory().unwrap();
+    let malformed = event(11, 0, OLD, vec![symbol("deposit")], scval::i128(1));
+    let err = store
+        .ingest(&[opened(10, 0), malformed], Some("cursor-1"))
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidEvent { .. }), "{err}");
+    assert!(store.escrow(0).unwrap().is_none());
+    assert_eq!(store.cursor().unwrap(), None);
+}
+
+#[test]
+fn test_cursor_survives_restart() {
+    let dir = env::temp_dir().join(format!("x402-indexer-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("index.sqlite");
+
+    let mut store = Store::open(&path).unwrap();
+    store.ingest(&[opened(10, 0)], Some("cursor-1")).unwrap();
+    drop(store);
+
+    let store = Store::open(&path).unwrap();
+    assert_eq!(store.cursor().unwrap().as_deref(), Some("cursor-1"));
+    assert_eq!(store.escrows_for_client(&client()).unwrap().len(), 1);
+    drop(store);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_indexer_follows_contract() {
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport);
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let (client, server) = (signer(&[1; 32]), signer(&[2; 32]));
+
+    let escrow_id = client
+        .open_escrow(&client.address(), &server.address(), 1_000, None)
+        .await
+        .unwrap()
+        .value;
+    client.deposit(escrow_id, 500).await.unwrap();
+    let payment_id = server.create_payment(escrow_id, 300).await.unwrap().value;
+    server.settle_payment(payment_id).await.unwrap();
+
+    let mut indexer = Indexer::new(rpc.clone(), &contract_id, Store::open_in_memory().unwrap())
+        .with_start_ledger(0)
+        .with_limit(3);
+    assert_eq!(indexer.sync().await.unwrap(), 4);
+    let escrow = indexer.store().escrow(escrow_id).unwrap().unwrap();
+    assert_eq!(escrow.client, client.address());
+    assert_eq!(escrow.deposited, 500);
+    assert_eq!(escrow.settled, 300);
+
+    // Later polls resume from the cursor
+    assert_eq!(indexer.poll().await.unwrap(), 0);
+    let payment_id = server.create_payment(escrow_id, 100).await.unwrap().value;
+    assert_eq!(indexer.poll().await.unwrap(), 1);
+    let payment = indexer.store().payment(payment_id).unwrap().unwrap();
+    assert_eq!(payment.escrow_id, Some(escrow_id));
+    assert!(!payment.settled);
+}
+
+fn rule(scope: AlertScope, metric: AlertMetric, threshold: i128) -> AlertRule {
+    AlertRule {
+        scope,
+        metric,
+        threshold,
+    }
+}
+
+/// States and values of the queued notifications, taking them
+fn transitions(store: &mut Store) -> Vec<(u64, AlertState, Option<i128>)> {
+    store
+        .take_alert_notifications()
+        .unwrap()
+        .into_iter()
+        .map(|n| (n.alert.rule_id, n.alert.state, n.alert.value))
+        .collect()
+}
+
+#[test]
+fn test_alert_transitions() {
+    let mut store = Store::open_in_memory().unwrap();
+    let balance = store
+        .add_alert_rule(&rule(AlertScope::Escrow(0), AlertMetric::Balance, 300))
+        .unwrap();
+    let exposure = store
+        .add_alert_rule(&rule(
+            AlertScope::Server(server()),
+            AlertMetric::PendingExposure,
+            150,
+        ))
+        .unwrap();
+    let disputes = store
+        .add_alert_rule(&rule(AlertScope::Escrow(0), AlertMetric::DisputeCount, 0))
+        .unwrap();
+    assert_eq!(store.alert_rules().unwrap().len(), 3);
+
+    // Nothing to watch yet, then a healthy escrow
+    store.ingest(&[], None).unwrap();
+    store
+        .ingest(&[opened(10, 0), deposited(11, 0, 0, 500)], None)
+        .unwrap();
+    assert!(transitions(&mut store).is_empty());
+
+    // Settling 200 leaves 300, at the threshold, then settling 100 more
+    // while 250 are pending breaches both rules
+    store
+        .ingest(&[paid(12, OLD, 0, 200), settled(13, 0, 200)], None)
+        .unwrap();
+    assert!(transitions(&mut store).is_empty());
+    let batch = [
+        paid(14, OLD, 1, 250),
+        paid(15, OLD, 2, 100),
+        settled(16, 2, 100),
+    ];
+    store.ingest(&batch, None).unwrap();
+    assert_eq!(
+        transitions(&mut store),
+        vec![
+            (balance, AlertState::Triggered, Some(200)),
+            (exposure, AlertState::Triggered, Some(250)),
+        ]
+    );
+    let firing = store.firing_alerts().unwrap();
+    assert_eq!(firing.len(), 2);
+    assert_eq!(firing[0].rule.metric, AlertMetric::Balance);
+
+    // Still breached: the alerts are not announced again
+    store.ingest(&[], None).unwrap();
+    store.ingest(&[deposited(17, 0, 0, 50)], None).unwrap();
+    assert!(transitions(&mut store).is_empty());
+    assert_eq!(store.firing_alerts().unwrap()[0].value, Some(250));
+
+    // A deposit resolves the balance alert, closing with the payment
+    // unsettled resolves the exposure one and raises a dispute
+    store.ingest(&[deposited(18, 0, 0, 100)], None).unwrap();
+    assert_eq!(
+        transitions(&mut store),
+        vec![(balance, AlertState::Resolved, Some(350))]
+    );
+    store.ingest(&[closed(19, 0, 350)], None).unwrap();
+    assert_eq!(
+        transitions(&mut store),
+        vec![
+            (exposure, AlertState::Resolved, None),
+            (disputes, AlertState::Triggered, Some(1)),
+        ]
+    );
+
+    // Removing a firing rule resolves its alert
+    assert!(store.remove_alert_rule(disputes).unwrap());
+    assert!(!store.remove_alert_rule(disputes).unwrap());
+    assert_eq!(
+        transitions(&mut store),
+        vec![(disputes, AlertState::Resolved, Some(1))]
+    );
+    assert!(store.firing_alerts().unwrap().is_empty());
+    store.ingest(&[], None).unwrap();
+    assert!(transitions(&mut store).is_empty());
+}
+
+#[test]
+fn test_inactivity_alert() {
+    let mut store = Store::open_in_memory().unwrap();
+    let inactivity = store
+        .add_alert_rule(&rule(
+            AlertScope::Server(server()),
+            AlertMetric::Inactivity,
+            3600,
+        ))
+        .unwrap();
+
+    // Opened long ago, then active again
+    store.ingest(&[opened(10, 0)], None).unwrap();
+    let triggered = transitions(&mut store);
+    assert_eq!(triggered[0].0, inactivity);
+    assert_eq!(triggered[0].1, AlertState::Triggered);
+    assert!(triggered[0].2.unwrap() > 3600);
+    assert_eq!(
+        store.escrow(0).unwrap().unwrap().active_at,
+        parse_timestamp(OLD).unwrap()
+    );
+
+    store.ingest(&[paid(11, RECENT, 0, 10)], None).unwrap();
+    assert_eq!(
+        transitions(&mut store),
+        vec![(inactivity, AlertState::Resolved, Some(0))]
+    );
+}
+
+#[test]
+fn test_alert_webhook_event() {
+    let mut store = Store::open_in_memory().unwrap();
+    store
+        .add_alert_rule(&rule(AlertScope::Escrow(0), AlertMetric::Balance, 1_000))
+        .unwrap();
+    store
+        .ingest(&[opened(10, 0), deposited(11, 0, 0, 500)], None)
+        .unwrap();
+    let notifications: Vec<AlertNotification> = store.take_alert_notifications().unwrap();
+    let event = notifications[0].webhook_event();
+    assert_eq!(event.id, format!("alert_{}", notifications[0].id));
+    assert_eq!(event.kind, EventKind::AlertTriggered);
+    assert_eq!(
+        event.data,
+        json!({
+            "ruleId": 1,
+            "escrowId": 0,
+            "metric": "balance",
+            "threshold": "1000",
+            "value": "500",
+        })
+    );
+    assert_eq!(
+        EventKind::parse("alert.resolved"),
+        Some(EventKind::AlertResolved)
+    );
+}
+
+#[test]
+fn test_notification_urls() {
+    let mut store = Store::open_in_memory().unwrap();
+    let batch = [opened(10, 0), paid(11, OLD, 1, 100), settled(12, 1, 100)];
+    store.ingest(&batch, None).unwrap();
+    assert_eq!(store.notification_url(&server()).unwrap(), None);
+    assert_eq!(store.take_notifications().unwrap(), vec![]);
+
+    // Registration applies to the events after it, in the same page too
+    let first = "https://a.example/hooks";
+    let batch = [
+        notify(13, Some(first)),
+        paid(14, OLD, 2, 200),
+        settled(15, 2, 200),
+    ];
+    store.ingest(&batch, None).unwrap();
+    assert_eq!(
+        store.notification_url(&server()).unwrap(),
+        Some(first.into())
+    );
+    let notifications = store.take_notifications().unwrap();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].url, first);
+    let event = notifications[0].webhook_event();
+    assert_eq!(event.id, format!("notify_{}", batch[2].id));
+    assert_eq!(event.kind, EventKind::PaymentSettled);
+    assert_eq!(
+        event.data,
+        json!({
+            "paymentId": 2,
+            "escrowId": 0,
+            "client": client(),
+            "server": server(),
+            "amount": "200",
+            "ledger": 15,
+        })
+    );
+    assert_eq!(store.take_notifications().unwrap(), vec![]);
+
+    // Update: closing with a payment unsettled is a dispute, sent to the
+    // new URL
+    let second = "https://b.example/hooks";
+    let batch = [
+        notify(16, Some(second)),
+        paid(17, OLD, 3, 300),
+        closed(18, 0, 400),
+    ];
+    store.ingest(&batch, None).unwrap();
+    let notifications = store.take_notifications().unwrap();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].url, second);
+    assert_eq!(notifications[0].kind, EventKind::EscrowDisputed);
+    assert_eq!(
+        notifications[0].data,
+        json!({
+            "escrowId": 0,
+            "client": client(),
+            "server": server(),
+            "unsettledPayments": 1,
+            "unsettledAmount": "300",
+            "released": "400",
+            "ledger": 18,
+        })
+    );
+
+    // Replaying a rewritten range keeps the registration, not notifying
+    // again
+    store.ingest(&[deposited(17, 1, 0, 10)], None).unwrap();
+    assert_eq!(
+        store.notification_url(&server()).unwrap(),
+        Some(second.into())
+    );
+    assert_eq!(store.take_notifications().unwrap(), vec![]);
+
+    // Removal
+    let batch = [notify(19, None), paid(20, OLD, 4, 50), settled(21, 4, 50)];
+    store.ingest(&batch, None).unwrap();
+    assert_eq!(store.notification_url(&server()).unwrap(), None);
+    assert_eq!(store.take_notifications().unwrap(), vec![]);
+}
+
+async fn call(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(AUTHORIZATION, "Bearer token")
+        .header("content-type", "application/json")
+        .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[tokio::test]
+async fn test_alert_rules_api() {
+    let dir = env::temp_dir().join(format!("x402-indexer-alerts-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("index.sqlite");
+    let mut store = Store::open(&path).unwrap();
+    let router = alerts_router(Arc::new(Mutex::new(Store::open(&path).unwrap())), "token");
+
+    let request = Request::get("/alerts/rules").body(Body::empty()).unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let body = json!({ "escrowId": 0, "metric": "balance", "threshold": "1000" });
+    let (status, created) = call(&router, Method::POST, "/alerts/rules", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created["id"], 1);
+    for invalid in [
+        json!({ "metric": "balance", "threshold": "1" }),
+        json!({ "escrowId": 0, "server": server(), "metric": "balance", "threshold": "1" }),
+        json!({ "escrowId": 0, "metric": "disputes", "threshold": "1" }),
+        json!({ "escrowId": 0, "metric": "balance", "threshold": "lots" }),
+    ] {
+        let (status, _) = call(&router, Method::POST, "/alerts/rules", Some(invalid)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let (_, rules) = call(&router, Method::GET, "/alerts/rules", None).await;
+    assert_eq!(rules, json!([created]));
+
+    // The indexer's connection evaluates rules added through the API
+    store
+        .ingest(&[opened(10, 0), deposited(11, 0, 0, 500)], None)
+        .unwrap();
+    let (_, alerts) = call(&router, Method::GET, "/alerts", None).await;
+    assert_eq!(alerts[0]["rule"], created);
+    assert_eq!(alerts[0]["value"], "500");
+
+    let (status, _) = call(&router, Method::DELETE, "/alerts/rules/1", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = call(&router, Method::DELETE, "/alerts/rules/1", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, alerts) = call(&router, Method::GET, "/alerts", None).await;
+    assert_eq!(alerts, json!([]));
+    assert_eq!(
+        transitions(&mut store),
+        vec![
+            (1, AlertState::Triggered, Some(500)),
+            (1, AlertState::Resolved, Some(500)),
+        ]
+    );
+
+    drop((store, router));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+async fn receive(State(received): State<Arc<Mutex<Vec<Value>>>>, body: String) -> StatusCode {
+    received
+        .lock()
+        .unwrap()
+        .push(serde_json::from_str(&body).unwrap());
+    StatusCode::OK
+}
+
+#[tokio::test]
+async fn test_indexer_delivers_alerts() {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let app = Router::new()
+        .route("/hook", routing::post(receive))
+        .with_state(received.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport);
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let (client, server) = (signer(&[1; 32]), signer(&[2; 32]));
+    let escrow_id = client
+        .open_escrow(&client.address(), &server.address(), 1_000, None)
+        .await
+        .unwrap()
+        .value;
+    client.deposit(escrow_id, 500).await.unwrap();
+
+    let mut store = Store::open_in_memory().unwrap();
+    store
+        .add_alert_rule(&rule(
+            AlertScope::Escrow(escrow_id),
+            AlertMetric::Balance,
+            250,
+        ))
+        .unwrap();
+    let webhooks = Webhooks::new(vec![Endpoint {
+        url,
+        secret: "secret".into(),
+        events: vec![EventKind::AlertTriggered],
+    }]);
+    let mut indexer = Indexer::new(rpc.clone(), &contract_id, store)
+        .with_start_ledger(0)
+        .with_webhooks(Arc::new(webhooks));
+    indexer.sync().await.unwrap();
+
+    // Settling 300 of the deposit leaves 200 known from the events
+    let payment_id = server.create_payment(escrow_id, 300).await.unwrap().value;
+    server.settle_payment(payment_id).await.unwrap();
+    indexer.sync().await.unwrap();
+    indexer.sync().await.unwrap();
+    for _ in 0..100 {
+        if !received.lock().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0]["type"], "alert.triggered");
+    assert_eq!(received[0]["data"]["value"], "200");
+}
+
+async fn receive_signed(
+    State(received): State<Arc<Mutex<Vec<(String, String)>>>>,
+    headers: HeaderMap,
+    body: String,
+) -> StatusCode {
+    let signature = headers[SIGNATURE_HEADER].to_str().unwrap().to_string();
+    received.lock().unwrap().push((signature, body));
+    StatusCode::OK
+}
+
+#[tokio::test]
+async fn test_indexer_delivers_notifications() {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let app = Router::new()
+        .route("/notify", routing::post(receive_signed))
+        .with_state(received.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/notify", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let transport = EnvTransport::new(|env| env.register(X402EscrowContract, ()));
+    let contract_id = transport.contract_id().to_string();
+    let rpc = Rpc::new(transport);
+    let signer = |seed| {
+        EscrowClient::new(
+            rpc.clone(),
+            &contract_id,
+            NETWORK_PASSPHRASE,
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let (client, server) = (signer(&[1; 32]), signer(&[2; 32]));
+    let escrow_id = client
+        .open_escrow(&client.address(), &server.address(), 1_000, None)
+        .await
+        .unwrap()
+        .value;
+
+    // The key is exchanged out of band, before any URL is registered
+    let secrets = HashMap::from([(server.address(), "secret".to_string())]);
+    let mut indexer = Indexer::new(rpc.clone(), &contract_id, Store::open_in_memory().unwrap())
+        .with_start_ledger(0)
+        .with_notifier(Notifier::new(secrets));
+    let unnotified = server.create_payment(escrow_id, 100).await.unwrap().value;
+    server.settle_payment(unnotified).await.unwrap();
+    indexer.sync().await.unwrap();
+
+    // Registered while the indexer runs, and followed from the next sync
+    server.set_notification_url(&url).await.unwrap();
+    let payment_id = server.create_payment(escrow_id, 200).await.unwrap().value;
+    server.settle_payment(payment_id).await.unwrap();
+    server.create_payment(escrow_id, 300).await.unwrap();
+    client.client_close_escrow(escrow_id).await.unwrap();
+    server.server_close_escrow(escrow_id).await.unwrap();
+    indexer.sync().await.unwrap();
+    assert_eq!(
+        indexer.store().notification_url(&server.address()).unwrap(),
+        Some(url)
+    );
+    for _ in 0..100 {
+        if received.lock().unwrap().len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 2);
+    for (signature, body) in &received {
+        assert!(verify_signature("secret", signature, body));
+    }
+    // Deliveries run concurrently, in no particular order
+    let events: Vec<Value> = received
+        .iter()
+        .map(|(_, body)| serde_json::from_str(body).unwrap())
+        .collect();
+    let of_type = |kind: &str| events.iter().find(|e| e["type"] == kind).unwrap();
+    let settled = of_type("payment.settled");
+    assert_eq!(settled["data"]["paymentId"], payment_id);
+    assert_eq!(settled["data"]["amount"], "200");
+    let disputed = of_type("escrow.disputed");
+    assert_eq!(disputed["data"]["escrowId"], escrow_id);
+    assert_eq!(disputed["data"]["unsettledAmount"], "300");
+}
+
+/// Store with a settled payment of 200 created in 2020 and a pending one
+/// of 300 created in 2999
+fn export_store() -> Store {
+    let mut store = Store::open_in_memory().unwrap();
+    let batch = [
+        opened(10, 0),
+        paid(12, OLD, 0, 200),
+        paid(13, RECENT, 1, 300),
+        settled(15, 0, 200),
+    ];
+    store.ingest(&batch, None).unwrap();
+    store
+}
+
+fn export(store: &Store, query: &[(&str, &str)]) -> String {
+    let mut export = ExportQuery::default();
+    for (name, value) in query {
+        export.set(name, value.to_string()).unwrap();
+    }
+    let (format, filter) = export.parse().unwrap();
+    let mut out = Vec::new();
+    store.export_payments(&filter, format, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_export_payments() {
+    let store = export_store();
+    let (client, server) = (client(), server());
+    let settled =
+        format!("payment,0,0,{client},{server},settled,200,0.0000200,12,2020-01-01T00:00:00Z,15");
+    let pending =
+        format!("payment,1,0,{client},{server},pending,300,0.0000300,13,2999-01-01T00:00:00Z,");
+    let csv = |rows: &[&str], manifest: &str| {
+        let mut lines = vec![CSV_COLUMNS.join(",")];
+        lines.extend(rows.iter().map(|row| row.to_string()));
+        lines.push(manifest.into());
+        lines.join("\n") + "\n"
+    };
+    assert_eq!(
+        export(&store, &[]),
+        csv(&[&settled, &pending], "manifest,2,,,,,500,0.0000500,,,")
+    );
+
+    // Filters combine
+    assert_eq!(
+        export(
+            &store,
+            &[("status", "pending"), ("server", server.as_str())]
+        ),
+        csv(&[&pending], "manifest,1,,,,,300,0.0000300,,,")
+    );
+    assert_eq!(
+        export(&store, &[("from", "2020-01-02")]),
+        export(&store, &[("status", "pending")])
+    );
+    assert_eq!(
+        export(&store, &[("to", "1577923200"), ("client", client.as_str())]),
+        csv(&[&settled], "manifest,1,,,,,200,0.0000200,,,")
+    );
+    assert_eq!(
+        export(&store, &[("server", client.as_str())]),
+        csv(&[], "manifest,0,,,,,0,0.0000000,,,")
+    );
+
+    let jsonl = export(&store, &[("format", "jsonl"), ("status", "settled")]);
+    let lines: Vec<&str> = jsonl.lines().collect();
+    assert_eq!(
+        lines[0],
+        json!({
+            "record": "payment",
+            "paymentId": 0,
+            "escrowId": 0,
+            "client": client,
+            "server": server,
+            "status": "settled",
+            "amountStroops": "200",
+            "amount": "0.0000200",
+            "createdLedger": 12,
+            "createdAt": "2020-01-01T00:00:00Z",
+            "settledLedger": 15,
+        })
+        .to_string()
+    );
+    assert_eq!(
+        serde_json::from_str::<Value>(lines[1]).unwrap(),
+        json!({ "record": "manifest", "rows": 1, "amountStroops": "200", "amount": "0.0000200" })
+    );
+
+    for (name, value) in [
+        ("format", "xlsx"),
+        ("status", "disputed"),
+        ("from", "last week"),
+    ] {
+        let mut query = ExportQuery::default();
+        query.set(name, value.into()).unwrap();
+        assert!(query.parse().is_err(), "{name}={value}");
+    }
+    assert!(ExportQuery::default().set("limit", "10".into()).is_err());
+}
+
+/// Writer checking an export as it streams, keeping only its current line
+#[derive(Default)]
+struct ExportSink {
+    line: Vec<u8>,
+    bytes: usize,
+    largest_write: usize,
+    rows: u64,
+    amount: i128,
+    manifest: Option<String>,
+}
+
+impl Write for ExportSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes += buf.len();
+        self.largest_write = self.largest_write.max(buf.len());
+        for &byte in buf {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+            let line = String::from_utf8(std::mem::take(&mut self.line)).unwrap();
+            let columns: Vec<&str> = line.split(',').collect();
+            assert_eq!(columns.len(), CSV_COLUMNS.len(), "{line}");
+            match columns[0] {
+                "payment" => {
+                    self.rows += 1;
+                    self.amount += columns[6].parse::<i128>().unwrap();
+                }
+                "manifest" => self.manifest = Some(line),
+                _ => {}
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_export_streams_100k_payments() {
+    const PAYMENTS: u64 = 100_000;
+    let mut store = Store::open_in_memory().unwrap();
+    store.ingest(&[opened(10, 0)], None).unwrap();
+    for page in 0..PAYMENTS / 1_000 {
+        let batch: Vec<EventInfo> = (page * 1_000..(page + 1) * 1_000)
+            .map(|id| paid(11 + id as u32, OLD, id, i128::from(id) + 1))
+            .collect();
+        store.ingest(&batch, None).unwrap();
+    }
+
+    let mut sink = ExportSink::default();
+    let manifest = store
+        .export_payments(&ExportFilter::default(), ExportFormat::Csv, &mut sink)
+        .unwrap();
+    let total = i128::from(PAYMENTS) * (i128::from(PAYMENTS) + 1) / 2;
+    assert_eq!(
+        manifest,
+        ExportManifest {
+            rows: PAYMENTS,
+            amount: total
+        }
+    );
+    assert_eq!((sink.rows, sink.amount), (PAYMENTS, total));
+    assert_eq!(
+        sink.manifest.as_deref(),
+        Some("manifest,100000,,,,,5000050000,500.0050000,,,")
+    );
+    // Written in chunks as rows are read, never buffered whole
+    assert!(sink.largest_write <= EXPORT_CHUNK, "{}", sink.largest_write);
+    assert!(sink.bytes > 1_000 * EXPORT_CHUNK, "{}", sink.bytes);
+
+    let filter = ExportFilter {
+        status: Some(PaymentStatus::Settled),
+        ..ExportFilter::default()
+    };
+    let manifest = store
+        .export_payments(&filter, ExportFormat::Jsonl, io::sink())
+        .unwrap();
+    assert_eq!(manifest, ExportManifest::default());
+}
+
+#[tokio::test]
+async fn test_exports_api() {
+    let router = exports_router(Arc::new(Mutex::new(export_store())), "token");
+    let request = |uri: &str, token: &str| {
+        Request::get(uri)
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = router
+        .clone()
+        .oneshot(request("/exports/payments", "guess"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&router, Method::GET, "/exports/payments?status=lost", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let response = router
+        .clone()
+        .oneshot(request("/exports/payments?format=jsonl", "token"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/jsonl");
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert_eq!(body, export(&export_store(), &[("format", "jsonl")]));
+    let manifest: Value = serde_json::from_str(body.lines().last().unwrap()).unwrap();
+    assert_eq!(
+        (manifest["rows"].clone(), manifest["amount"].clone()),
+        (json!(2), json!("0.0000500"))
+    );
+}
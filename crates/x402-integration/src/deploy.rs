@@ -0,0 +1,249 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::Command,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use ed25519_dalek::{Signer as _, SigningKey};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use stellar_xdr::curr::{
+    AccountId, ContractExecutable, ContractIdPreimage, ContractIdPreimageFromAddress,
+    CreateContractArgsV2, DecoratedSignature, Hash, HostFunction, InvokeHostFunctionOp, Limits,
+    Memo, MuxedAccount, Operation, OperationBody, Preconditions, PublicKey, ReadXdr, ScAddress,
+    ScVal, SequenceNumber, Signature, SignatureHint, SorobanAuthorizationEntry,
+    SorobanTransactionData, Transaction, TransactionEnvelope, TransactionExt, TransactionMeta,
+    TransactionSignaturePayload, TransactionSignaturePayloadTaggedTransaction,
+    TransactionV1Envelope, Uint256, WriteXdr,
+};
+use x402_client::{scval, Transport};
+
+use crate::{describe_transaction, HarnessError, Quickstart};
+
+/// Path of a prebuilt escrow contract wasm, built from the workspace if unset
+pub const ESCROW_WASM_VAR: &str = "X402_ESCROW_WASM";
+
+/// Base fee of the deployment transactions, before resource fees
+const BASE_FEE: u32 = 1_000;
+
+/// Longest wait for a deployment transaction to be confirmed
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Wasm of the escrow contract
+///
+/// Read from [`ESCROW_WASM_VAR`] if set, otherwise built with
+/// `cargo build -p x402-escrow --release --target wasm32v1-none`.
+///
+/// # Errors
+/// * `Wasm` - If the build fails or the wasm cannot be read
+pub fn escrow_wasm() -> Result<Vec<u8>, HarnessError> {
+    let path = match env::var(ESCROW_WASM_VAR) {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => {
+            let workspace = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../..");
+            let status = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".into()))
+                .current_dir(&workspace)
+                .args(["build", "-p", "x402-escrow", "--release"])
+                .args(["--target", "wasm32v1-none"])
+                .status()
+                .map_err(|e| HarnessError::Wasm(format!("cannot run cargo: {e}")))?;
+            if !status.success() {
+                return Err(HarnessError::Wasm(format!(
+                    "cargo build exited with {status}"
+                )));
+            }
+            let target = env::var("CARGO_TARGET_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| workspace.join("target"));
+            target.join("wasm32v1-none/release/x402_escrow.wasm")
+        }
+    };
+    fs::read(&path).map_err(|e| HarnessError::Wasm(format!("{}: {e}", path.display())))
+}
+
+impl Quickstart {
+    /// Upload `wasm` and create a contract from it, without constructor
+    /// arguments
+    ///
+    /// # Arguments
+    /// * `wasm` - Contract wasm
+    /// * `deployer` - Seed of the funded account paying for the deployment
+    ///
+    /// # Returns
+    /// * The contract address (C... format)
+    ///
+    /// # Errors
+    /// * `Simulation` - If the network rejects the upload or creation
+    /// * `Transaction` - If a transaction fails, with its diagnostics
+    pub async fn deploy(&self, wasm: &[u8], deployer: &[u8; 32]) -> Result<String, HarnessError> {
+        let upload = HostFunction::UploadContractWasm(wasm.to_vec().try_into()?);
+        let wasm_hash = match self.invoke_host_function(deployer, upload).await? {
+            ScVal::Bytes(bytes) => Hash(
+                bytes
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| HarnessError::Simulation("wasm hash is not 32 bytes".into()))?,
+            ),
+            other => {
+                return Err(HarnessError::Simulation(format!(
+                    "upload returned {other:?}"
+                )))
+            }
+        };
+
+        let key = SigningKey::from_bytes(deployer);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let salt: [u8; 32] = Sha256::digest(nanos.to_be_bytes()).into();
+        let create = HostFunction::CreateContractV2(CreateContractArgsV2 {
+            contract_id_preimage: ContractIdPreimage::Address(ContractIdPreimageFromAddress {
+                address: ScAddress::Account(account_id(&key)),
+                salt: Uint256(salt),
+            }),
+            executable: ContractExecutable::Wasm(wasm_hash),
+            constructor_args: Default::default(),
+        });
+        match self.invoke_host_function(deployer, create).await? {
+            ScVal::Address(address) => Ok(scval::format_address(&address)),
+            other => Err(HarnessError::Simulation(format!(
+                "create returned {other:?}"
+            ))),
+        }
+    }
+
+    /// Simulate, sign, submit, and confirm a transaction running `function`
+    async fn invoke_host_function(
+        &self,
+        seed: &[u8; 32],
+        function: HostFunction,
+    ) -> Result<ScVal, HarnessError> {
+        let key = SigningKey::from_bytes(seed);
+        let public_key = key.verifying_key().to_bytes();
+        let account = self.rpc().get_account(&public_key).await?;
+        let mut tx = Transaction {
+            source_account: MuxedAccount::Ed25519(Uint256(public_key)),
+            fee: BASE_FEE,
+            seq_num: SequenceNumber(account.seq_num.0 + 1),
+            cond: Preconditions::None,
+            memo: Memo::None,
+            operations: vec![operation(function.clone(), vec![])].try_into()?,
+            ext: TransactionExt::V0,
+        };
+
+        let unsigned = TransactionEnvelope::Tx(TransactionV1Envelope {
+            tx: tx.clone(),
+            signatures: Default::default(),
+        });
+        let simulation = self.rpc().simulate_transaction(&unsigned).await?;
+        if let Some(error) = simulation.error {
+            return Err(HarnessError::Simulation(error));
+        }
+        let data = simulation
+            .transaction_data
+            .as_deref()
+            .ok_or_else(|| HarnessError::Simulation("missing transactionData".into()))?;
+        let resource_fee: u32 = simulation
+            .min_resource_fee
+            .as_deref()
+            .unwrap_or("0")
+            .parse()
+            .map_err(|_| HarnessError::Simulation("invalid minResourceFee".into()))?;
+        let auth = simulation
+            .results
+            .first()
+            .map(|result| result.auth.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .map(|entry| SorobanAuthorizationEntry::from_xdr_base64(entry, Limits::none()))
+            .collect::<Result<Vec<_>, _>>()?;
+        tx.operations = vec![operation(function, auth)].try_into()?;
+        tx.fee = tx.fee.saturating_add(resource_fee);
+        tx.ext = TransactionExt::V1(SorobanTransactionData::from_xdr_base64(
+            data,
+            Limits::none(),
+        )?);
+
+        let payload = TransactionSignaturePayload {
+            network_id: Hash(Sha256::digest(self.passphrase()).into()),
+            tagged_transaction: TransactionSignaturePayloadTaggedTransaction::Tx(tx.clone()),
+        };
+        let hash: [u8; 32] = Sha256::digest(payload.to_xdr(Limits::none())?).into();
+        let signature = DecoratedSignature {
+            hint: SignatureHint(public_key[28..].try_into().expect("4-byte hint")),
+            signature: Signature(key.sign(&hash).to_bytes().to_vec().try_into()?),
+        };
+        let envelope = TransactionEnvelope::Tx(TransactionV1Envelope {
+            tx,
+            signatures: vec![signature].try_into()?,
+        });
+        let sent = self.rpc().send_transaction(&envelope).await?;
+        if sent.status != "PENDING" && sent.status != "DUPLICATE" {
+            return Err(HarnessError::Transaction {
+                hash: sent.hash,
+                status: sent.status,
+                diagnostics: sent.error_result_xdr.unwrap_or_default(),
+            });
+        }
+        self.confirm(&sent.hash).await
+    }
+
+    /// Poll `getTransaction` until the transaction succeeds, and return the
+    /// value of its host function
+    async fn confirm(&self, hash: &str) -> Result<ScVal, HarnessError> {
+        let deadline = Instant::now() + CONFIRMATION_TIMEOUT;
+        loop {
+            let response = self
+                .raw_transport()
+                .request("getTransaction", json!({ "hash": hash }))
+                .await?;
+            let status = response["status"].as_str().unwrap_or_default().to_string();
+            match status.as_str() {
+                "SUCCESS" => {
+                    let meta = response["resultMetaXdr"].as_str().unwrap_or_default();
+                    return return_value(meta).ok_or_else(|| HarnessError::Transaction {
+                        hash: hash.into(),
+                        status: "returned no value".into(),
+                        diagnostics: describe_transaction(&response),
+                    });
+                }
+                "NOT_FOUND" if Instant::now() < deadline => {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                _ => {
+                    return Err(HarnessError::Transaction {
+                        hash: hash.into(),
+                        status,
+                        diagnostics: describe_transaction(&response),
+                    })
+                }
+            }
+        }
+    }
+}
+
+fn operation(function: HostFunction, auth: Vec<SorobanAuthorizationEntry>) -> Operation {
+    Operation {
+        source_account: None,
+        body: OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+            host_function: function,
+            auth: auth.try_into().unwrap_or_default(),
+        }),
+    }
+}
+
+fn account_id(key: &SigningKey) -> AccountId {
+    AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(
+        key.verifying_key().to_bytes(),
+    )))
+}
+
+/// Return value of a Soroban transaction from its meta
+fn return_value(meta: &str) -> Option<ScVal> {
+    match TransactionMeta::from_xdr_base64(meta, Limits::none()).ok()? {
+        TransactionMeta::V3(meta) => meta.soroban_meta.map(|soroban| soroban.return_value),
+        _ => None,
+    }
+}
@@ -0,0 +1,32 @@
+use x402_client::Error as ClientError;
+
+/// Errors of the integration harness
+#[derive(Debug, thiserror::Error)]
+pub enum HarnessError {
+    /// A docker command failed
+    #[error("docker: {0}")]
+    Docker(String),
+    /// The network did not become ready in time
+    #[error("network not ready: {0}")]
+    NotReady(String),
+    /// Friendbot did not fund an account
+    #[error("friendbot: {0}")]
+    Friendbot(String),
+    /// The contract wasm could not be built or read
+    #[error("contract wasm: {0}")]
+    Wasm(String),
+    /// Simulation of a host function failed
+    #[error("simulation failed: {0}")]
+    Simulation(String),
+    /// A transaction was rejected or failed, with the RPC's diagnostics
+    #[error("transaction {hash} {status}\n{diagnostics}")]
+    Transaction {
+        hash: String,
+        status: String,
+        diagnostics: String,
+    },
+    #[error(transparent)]
+    Client(#[from] ClientError),
+    #[error("XDR error: {0}")]
+    Xdr(#[from] stellar_xdr::curr::Error),
+}
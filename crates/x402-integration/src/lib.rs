@@ -0,0 +1,34 @@
+//! # x402 Integration
+//!
+//! End-to-end tests of the escrow contract, the client SDK, the facilitator,
+//! and the payment middleware against a real Stellar network, exercising
+//! transaction submission, fees, footprints, and RPC behaviour the
+//! in-process Soroban env does not.
+//!
+//! ## Running
+//! The tests start a `stellar/quickstart` container and are ignored by
+//! default. With Docker and the `wasm32v1-none` target installed:
+//!
+//! `cargo test -p x402-integration -- --ignored`
+//!
+//! Set `X402_IT_RPC_URL` (and `X402_IT_FRIENDBOT_URL`,
+//! `X402_IT_NETWORK_PASSPHRASE` when not a local quickstart) to run against
+//! a network already running instead, and `X402_ESCROW_WASM` to deploy a
+//! prebuilt contract.
+//!
+//! ## Key Features
+//! - [`Quickstart`] starting the container, or attaching to a running network
+//! - [`Quickstart::fund`] funding accounts with friendbot
+//! - [`Quickstart::deploy`] uploading and instantiating a contract wasm
+//! - [`Quickstart::diagnose`] explaining a failed call with the RPC's view
+//!   of its transaction and the container logs
+
+mod deploy;
+mod error;
+mod network;
+
+pub use deploy::*;
+pub use error::*;
+pub use network::*;
+
+mod test;
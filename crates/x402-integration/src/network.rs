@@ -0,0 +1,313 @@
+use std::{
+    env,
+    process::Command as StdCommand,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use stellar_strkey::ed25519;
+use tokio::process::Command;
+use x402_client::{Error as ClientError, HttpTransport, Rpc, Transport};
+
+use crate::HarnessError;
+
+/// Docker image started by [`Quickstart::start`]
+pub const QUICKSTART_IMAGE: &str = "stellar/quickstart:testing";
+
+/// Passphrase of a local quickstart network
+pub const LOCAL_PASSPHRASE: &str = "Standalone Network ; February 2017";
+
+/// x402 network id payments on the local network are made on
+pub const NETWORK: &str = "stellar-local";
+
+/// RPC URL of a running network to use instead of a container
+pub const RPC_URL_VAR: &str = "X402_IT_RPC_URL";
+
+/// Friendbot URL of the network of [`RPC_URL_VAR`]
+pub const FRIENDBOT_URL_VAR: &str = "X402_IT_FRIENDBOT_URL";
+
+/// Passphrase of the network of [`RPC_URL_VAR`]
+pub const PASSPHRASE_VAR: &str = "X402_IT_NETWORK_PASSPHRASE";
+
+/// Longest wait for a fresh container to serve RPC and friendbot
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Delay between readiness and confirmation checks
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Container log lines attached to diagnostics
+const LOG_LINES: usize = 40;
+
+/// Stellar network the integration tests run against
+///
+/// Either a `stellar/quickstart` container started for the test, removed
+/// when dropped, or the network named by [`RPC_URL_VAR`].
+pub struct Quickstart {
+    /// ID of the container started, None for a network already running
+    container: Option<String>,
+    rpc_url: String,
+    friendbot_url: String,
+    passphrase: String,
+    /// Transport of the harness's own requests, answered as raw JSON
+    transport: HttpTransport,
+    rpc: Rpc,
+    http: reqwest::Client,
+}
+
+impl Quickstart {
+    /// Start a local quickstart container, or attach to the network of
+    /// [`RPC_URL_VAR`] if set, and wait until it is ready
+    ///
+    /// # Errors
+    /// * `Docker` - If the container cannot be started
+    /// * `NotReady` - If RPC is not healthy within five minutes
+    pub async fn start() -> Result<Self, HarnessError> {
+        let (container, rpc_url, friendbot_url, passphrase) = match env::var(RPC_URL_VAR) {
+            Ok(rpc_url) => {
+                let base = rpc_url.trim_end_matches("/rpc").trim_end_matches('/');
+                let friendbot =
+                    env::var(FRIENDBOT_URL_VAR).unwrap_or_else(|_| format!("{base}/friendbot"));
+                let passphrase =
+                    env::var(PASSPHRASE_VAR).unwrap_or_else(|_| LOCAL_PASSPHRASE.into());
+                (None, rpc_url, friendbot, passphrase)
+            }
+            Err(_) => {
+                let container = docker(&[
+                    "run",
+                    "--detach",
+                    "--rm",
+                    "--publish",
+                    "127.0.0.1::8000",
+                    QUICKSTART_IMAGE,
+                    "--local",
+                    "--enable",
+                    "core,horizon,rpc",
+                ])
+                .await?;
+                let port = docker(&["port", &container, "8000/tcp"]).await;
+                let address = match port {
+                    Ok(port) => port.lines().next().unwrap_or_default().to_string(),
+                    Err(e) => {
+                        remove(&container);
+                        return Err(e);
+                    }
+                };
+                (
+                    Some(container),
+                    format!("http://{address}/rpc"),
+                    format!("http://{address}/friendbot"),
+                    LOCAL_PASSPHRASE.to_string(),
+                )
+            }
+        };
+
+        let network = Self {
+            container,
+            rpc: Rpc::new(HttpTransport::new(rpc_url.clone())),
+            transport: HttpTransport::new(rpc_url.clone()),
+            rpc_url,
+            friendbot_url,
+            passphrase,
+            http: reqwest::Client::new(),
+        };
+        network.wait_until_healthy().await?;
+        Ok(network)
+    }
+
+    /// RPC client of the network
+    pub fn rpc(&self) -> &Rpc {
+        &self.rpc
+    }
+
+    /// URL of the network's Soroban RPC endpoint
+    pub fn rpc_url(&self) -> &str {
+        &self.rpc_url
+    }
+
+    /// Network passphrase
+    pub fn passphrase(&self) -> &str {
+        &self.passphrase
+    }
+
+    /// Transport answering requests as raw JSON, for fields the typed
+    /// responses of [`Rpc`] leave out
+    pub(crate) fn raw_transport(&self) -> &HttpTransport {
+        &self.transport
+    }
+
+    /// Fund the account of `address` (G... format) with friendbot, and wait
+    /// until RPC sees it
+    ///
+    /// Accounts funded before are left as they are.
+    ///
+    /// # Errors
+    /// * `Friendbot` - If the address is invalid or friendbot keeps failing
+    /// * `NotReady` - If the account does not show up in time
+    pub async fn fund(&self, address: &str) -> Result<(), HarnessError> {
+        let public_key = ed25519::PublicKey::from_string(address)
+            .map_err(|e| HarnessError::Friendbot(format!("{address}: {e}")))?
+            .0;
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            let response = self
+                .http
+                .get(&self.friendbot_url)
+                .query(&[("addr", address)])
+                .send()
+                .await;
+            let failure = match response {
+                Ok(response) if response.status().is_success() => break,
+                Ok(response) => {
+                    let body = response.text().await.unwrap_or_default();
+                    if body.contains("createAccountAlreadyExist") {
+                        break;
+                    }
+                    body
+                }
+                // Friendbot starts after RPC
+                Err(e) => e.to_string(),
+            };
+            if Instant::now() >= deadline {
+                return Err(HarnessError::Friendbot(format!("{address}: {failure}")));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while let Err(e) = self.rpc.get_account(&public_key).await {
+            if Instant::now() >= deadline {
+                return Err(HarnessError::NotReady(format!("account {address}: {e}")));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        Ok(())
+    }
+
+    /// Explain a failed call with what the network knows about it
+    ///
+    /// The transaction of a rejected, failed, or unconfirmed call is looked
+    /// up with `getTransaction`, whose result and diagnostic events are
+    /// decoded. The last lines of the container log are appended.
+    pub async fn diagnose(&self, error: &ClientError) -> String {
+        let mut report = error.to_string();
+        let hash = match error {
+            ClientError::Rejected { hash, .. }
+            | ClientError::TransactionFailed { hash }
+            | ClientError::Timeout { hash, .. } => Some(hash),
+            _ => None,
+        };
+        if let Some(hash) = hash {
+            report.push('\n');
+            report.push_str(&self.transaction_diagnostics(hash).await);
+        }
+        if let Some(logs) = self.logs() {
+            report.push_str("\ncontainer log:\n");
+            report.push_str(&logs);
+        }
+        report
+    }
+
+    /// Status, result, and diagnostic events of a transaction, as reported
+    /// by `getTransaction`
+    pub async fn transaction_diagnostics(&self, hash: &str) -> String {
+        let response = self
+            .transport
+            .request("getTransaction", json!({ "hash": hash }))
+            .await;
+        match response {
+            Ok(response) => describe_transaction(&response),
+            Err(e) => format!("getTransaction {hash}: {e}"),
+        }
+    }
+
+    /// Last lines of the container log, None for a network already running
+    pub fn logs(&self) -> Option<String> {
+        let container = self.container.as_ref()?;
+        let output = StdCommand::new("docker")
+            .args(["logs", "--tail", &LOG_LINES.to_string(), container])
+            .output()
+            .ok()?;
+        let mut logs = String::from_utf8_lossy(&output.stdout).into_owned();
+        logs.push_str(&String::from_utf8_lossy(&output.stderr));
+        Some(logs)
+    }
+
+    async fn wait_until_healthy(&self) -> Result<(), HarnessError> {
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            let health = self.transport.request("getHealth", Value::Null).await;
+            let status = match health {
+                Ok(health) if health["status"] == "healthy" => return Ok(()),
+                Ok(health) => health.to_string(),
+                Err(e) => e.to_string(),
+            };
+            if Instant::now() >= deadline {
+                let logs = self.logs().unwrap_or_default();
+                return Err(HarnessError::NotReady(format!(
+                    "{}: {status}\n{logs}",
+                    self.rpc_url
+                )));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+impl Drop for Quickstart {
+    fn drop(&mut self) {
+        if let Some(container) = &self.container {
+            remove(container);
+        }
+    }
+}
+
+/// Run docker with `args`, returning its trimmed standard output
+async fn docker(args: &[&str]) -> Result<String, HarnessError> {
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| HarnessError::Docker(format!("cannot run docker: {e}")))?;
+    if !output.status.success() {
+        return Err(HarnessError::Docker(format!(
+            "docker {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn remove(container: &str) {
+    let _ = StdCommand::new("docker")
+        .args(["rm", "--force", container])
+        .output();
+}
+
+/// Human-readable summary of a `getTransaction` response
+pub(crate) fn describe_transaction(response: &Value) -> String {
+    use stellar_xdr::curr::{DiagnosticEvent, Limits, ReadXdr, TransactionResult};
+
+    let mut lines = vec![format!(
+        "status {}",
+        response["status"].as_str().unwrap_or("unknown")
+    )];
+    if let Some(result) = response["resultXdr"].as_str() {
+        match TransactionResult::from_xdr_base64(result, Limits::none()) {
+            Ok(result) => lines.push(format!("result {:?}", result.result)),
+            Err(_) => lines.push(format!("result {result}")),
+        }
+    }
+    for event in response["diagnosticEventsXdr"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        match DiagnosticEvent::from_xdr_base64(event, Limits::none()) {
+            Ok(event) => lines.push(format!("event {:?}", event.event.body)),
+            Err(_) => lines.push(format!("event {event}")),
+        }
+    }
+    lines.join("\n")
+}
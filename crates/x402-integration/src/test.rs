@@ -0,0 +1,209 @@
+#![cfg(test)]
+
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{http::StatusCode, routing::get, Router};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use x402_axum::{HttpFacilitator, Paid, PaymentLayer};
+use x402_client::{
+    AuthorizationEntry, ContractError, EscrowClient, EscrowOp, LocalSigner, X402HttpClient,
+};
+use x402_facilitator::Facilitator;
+use x402_types::NATIVE_ASSET;
+
+use crate::{describe_transaction, escrow_wasm, Quickstart, NETWORK};
+
+/// Fresh seed per account and run, so reruns against a long-lived network
+/// start from new accounts
+fn seed(name: &str) -> [u8; 32] {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    Sha256::digest(format!("{name}-{nanos}")).into()
+}
+
+async fn serve(router: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    url
+}
+
+/// Unwrap a client result, explaining failed transactions with the network's
+/// diagnostics
+macro_rules! check {
+    ($network:expr, $result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(e) => panic!("{}", $network.diagnose(&e).await),
+        }
+    };
+}
+
+#[test]
+fn test_describe_transaction() {
+    let report = describe_transaction(&json!({
+        "status": "FAILED",
+        "resultXdr": "not xdr",
+        "diagnosticEventsXdr": ["garbage"],
+    }));
+    assert_eq!(report, "status FAILED\nresult not xdr\nevent garbage");
+    assert_eq!(describe_transaction(&json!({})), "status unknown");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "starts a stellar/quickstart container, run with --ignored"]
+async fn test_escrow_flow_on_quickstart() {
+    let network = Quickstart::start().await.unwrap();
+    let (deployer, client_seed, server_seed) = (seed("deployer"), seed("client"), seed("server"));
+    let client_addr = LocalSigner::from_bytes(&client_seed).address();
+    let server_addr = LocalSigner::from_bytes(&server_seed).address();
+    for seed in [&deployer, &client_seed, &server_seed] {
+        network
+            .fund(&LocalSigner::from_bytes(seed).address())
+            .await
+            .unwrap();
+    }
+
+    let wasm = escrow_wasm().unwrap();
+    let contract = network.deploy(&wasm, &deployer).await.unwrap();
+    let escrow = |seed: &[u8; 32]| {
+        EscrowClient::new(
+            network.rpc().clone(),
+            &contract,
+            network.passphrase(),
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let client = escrow(&client_seed);
+    let server = escrow(&server_seed);
+
+    // Facilitator settling as the server, behind the middleware
+    let facilitator = Arc::new(Facilitator::new(escrow(&server_seed), NETWORK));
+    let facilitator_url = serve(x402_facilitator::router(facilitator)).await;
+    async fn handler(Paid(payment): Paid) -> String {
+        format!("paid {} from escrow {}", payment.amount, payment.escrow_id)
+    }
+    let app = Router::new().route("/weather", get(handler)).layer(
+        PaymentLayer::new(100_000, NATIVE_ASSET, &server_addr)
+            .with_verifier(HttpFacilitator::new(facilitator_url, NETWORK)),
+    );
+    let url = format!("{}/weather", serve(app).await);
+
+    // Open and top up
+    let escrow_id = check!(
+        network,
+        client
+            .open_escrow(&client_addr, &server_addr, 1_000_000, None)
+            .await
+    )
+    .value;
+    check!(network, client.deposit(escrow_id, 500_000).await);
+    assert_eq!(
+        check!(network, client.get_escrow_balance(escrow_id).await),
+        1_500_000
+    );
+
+    // Unpaid requests are challenged
+    let unpaid = reqwest::get(&url).await.unwrap();
+    assert_eq!(unpaid.status(), StatusCode::PAYMENT_REQUIRED);
+
+    // Paid through the middleware and settled by the facilitator
+    let paying = X402HttpClient::builder(escrow(&client_seed), NETWORK).build();
+    let paid = check!(network, paying.get(&url).await);
+    assert_eq!(paid.response.status(), StatusCode::OK);
+    let receipt = paid.receipt.expect("payment receipt");
+    assert_eq!(receipt.escrow_id, escrow_id);
+    let settlement = receipt.settlement.expect("settlement");
+    assert!(settlement.success, "{:?}", settlement.error);
+    assert!(settlement.tx_hash.is_some());
+    let payment_id = settlement.payment_id.expect("payment id");
+    assert!(check!(network, client.get_payment(payment_id).await).settled);
+    assert_eq!(
+        check!(network, client.get_escrow_balance(escrow_id).await),
+        1_400_000
+    );
+
+    // Closed by both sides, refunding the client
+    assert_eq!(
+        check!(network, client.client_close_escrow(escrow_id).await).value,
+        None
+    );
+    assert_eq!(
+        check!(network, server.server_close_escrow(escrow_id).await).value,
+        Some(1_400_000)
+    );
+    let closed = client.get_escrow(escrow_id).await.unwrap_err();
+    assert_eq!(closed.contract_error(), Some(ContractError::EscrowNotFound));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "starts a stellar/quickstart container, run with --ignored"]
+async fn test_presigned_deposit_on_quickstart() {
+    let network = Quickstart::start().await.unwrap();
+    let (deployer, client_seed, agent_seed) = (seed("deployer"), seed("client"), seed("agent"));
+    let client_addr = LocalSigner::from_bytes(&client_seed).address();
+    let server_addr = LocalSigner::from_bytes(&seed("server")).address();
+    for seed in [&deployer, &client_seed, &agent_seed] {
+        network
+            .fund(&LocalSigner::from_bytes(seed).address())
+            .await
+            .unwrap();
+    }
+
+    let wasm = escrow_wasm().unwrap();
+    let contract = network.deploy(&wasm, &deployer).await.unwrap();
+    let escrow = |seed: &[u8; 32]| {
+        EscrowClient::new(
+            network.rpc().clone(),
+            &contract,
+            network.passphrase(),
+            LocalSigner::from_bytes(seed),
+        )
+        .unwrap()
+    };
+    let client = escrow(&client_seed);
+    let escrow_id = check!(
+        network,
+        client
+            .open_escrow(&client_addr, &server_addr, 1_000_000, None)
+            .await
+    )
+    .value;
+
+    // Up to three deposits of at most 1 XLM, valid for the next 1000 ledgers
+    let mut entries = vec![];
+    for amount in [10_000_000, 5_000_000, 2_500_000] {
+        let op = EscrowOp::Deposit { escrow_id, amount };
+        entries.push(
+            check!(network, client.presign(&op, 1000).await)
+                .to_xdr_base64()
+                .unwrap(),
+        );
+    }
+
+    // Submitted by an agent paying the fees, holding none of the client's keys
+    let agent = escrow(&agent_seed);
+    for entry in &entries {
+        let entry = AuthorizationEntry::from_xdr_base64(entry).unwrap();
+        check!(network, agent.submit_authorized(&entry).await);
+    }
+    assert_eq!(
+        check!(network, client.get_escrow_balance(escrow_id).await),
+        1_000_000 + 17_500_000
+    );
+
+    // Each entry applies once
+    let replayed = AuthorizationEntry::from_xdr_base64(&entries[0]).unwrap();
+    assert!(agent.submit_authorized(&replayed).await.is_err());
+    assert_eq!(
+        check!(network, client.get_escrow_balance(escrow_id).await),
+        1_000_000 + 17_500_000
+    );
+}
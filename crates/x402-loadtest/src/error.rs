@@ -0,0 +1,15 @@
+use x402_client::Error as ClientError;
+use x402_integration::HarnessError;
+
+/// Error setting up a load test
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    /// The quickstart network could not be started, funded, or deployed to
+    #[error(transparent)]
+    Harness(#[from] HarnessError),
+    /// A client could not open its escrow
+    #[error("cannot open escrow: {0}")]
+    OpenEscrow(ClientError),
+    #[error(transparent)]
+    Client(#[from] ClientError),
+}
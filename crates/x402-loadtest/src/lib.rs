@@ -0,0 +1,43 @@
+//! # x402 Load Test
+//!
+//! Load generator measuring the sustained settlement throughput of the x402
+//! facilitator and escrow contract, and where it falls over.
+//!
+//! ## Running
+//! `cargo run -p x402-loadtest --release -- --clients 32 --payments 20`
+//!
+//! Synthetic clients each open an escrow, then pay through the facilitator's
+//! verify and settle, one payment after the other. By default the contract
+//! runs in an in-process Soroban env, measuring the facilitator and the
+//! contract alone. With `--quickstart` it is deployed to a `stellar/quickstart`
+//! container, or the network of `X402_IT_RPC_URL`, as by `x402-integration`,
+//! and every transaction goes through Soroban RPC.
+//!
+//! The run fails when more payments fail than `--max-error-rate` allows, so
+//! CI can catch regressions.
+//!
+//! ## Key Features
+//! - [`LoadConfig`] of the clients, payments, and target network
+//! - [`run`] driving the clients concurrently through the full pipeline
+//! - [`Report`] of throughput, latency percentiles per stage, and failures
+//!   by reason, as text or JSON
+//!
+//! ## Findings
+//! Concurrent settlements of one facilitator signed their transactions with
+//! the same sequence number of the server account, and all but one of them
+//! were rejected, so the error rate grew with the number of clients. Calls
+//! of an `EscrowClient` and its clones now wait for each other instead.
+//! Settlements of one server key are thus sequential, two transactions
+//! each; batching with the settlement queue raises throughput past that.
+
+mod error;
+mod load;
+mod report;
+mod target;
+
+pub use error::*;
+pub use load::*;
+pub use report::*;
+pub use target::*;
+
+mod test;
@@ -0,0 +1,95 @@
+use std::time::Instant;
+
+use tokio::task::JoinSet;
+use x402_types::{SettleRequest, VerifyRequest, X402_VERSION};
+
+use crate::{LoadError, Report, Samples, Setup, Target};
+
+/// Shape of a load test
+#[derive(Clone, Debug)]
+pub struct LoadConfig {
+    pub target: Target,
+    /// Concurrent synthetic clients, each with its own escrow
+    pub clients: usize,
+    /// Payments made by each client, one after the other
+    pub payments: usize,
+    /// Amount of each payment, in stroops
+    pub amount: i128,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self {
+            target: Target::InProcess,
+            clients: 16,
+            payments: 10,
+            amount: 100,
+        }
+    }
+}
+
+/// Set up the target, then drive the load
+///
+/// # Errors
+/// * If the setup fails, see [`Setup::new`]
+pub async fn run(config: &LoadConfig) -> Result<Report, LoadError> {
+    let setup = Setup::new(config).await?;
+    Ok(drive(&setup, config).await)
+}
+
+/// Drive every client of `setup` concurrently through verify then settle,
+/// `config.payments` times each
+///
+/// Failed payments are counted, never retried.
+pub async fn drive(setup: &Setup, config: &LoadConfig) -> Report {
+    let started = Instant::now();
+    let mut running = JoinSet::new();
+    for client in setup.clients() {
+        let (client, facilitator) = (client.clone(), setup.facilitator().clone());
+        let requirements = setup.requirements().clone();
+        let (payments, amount) = (config.payments, config.amount);
+        running.spawn(async move {
+            let mut samples = Samples::default();
+            for nonce in 0..payments as u64 {
+                let payment_header = client.payment_header(amount, nonce);
+                let begun = Instant::now();
+                let verified = facilitator
+                    .verify(&VerifyRequest {
+                        x402_version: X402_VERSION,
+                        payment_header: payment_header.clone(),
+                        payment_requirements: requirements.clone(),
+                    })
+                    .await;
+                samples.verify.push(begun.elapsed());
+                if !verified.is_valid {
+                    samples.fail("verify", verified.invalid_reason.as_deref());
+                    continue;
+                }
+
+                let begun = Instant::now();
+                let settled = facilitator
+                    .settle(&SettleRequest {
+                        x402_version: X402_VERSION,
+                        payment_header,
+                        payment_requirements: requirements.clone(),
+                        settle_amount: None,
+                        dry_run: false,
+                    })
+                    .await;
+                samples.settle.push(begun.elapsed());
+                if settled.success {
+                    samples.settled += 1;
+                } else {
+                    samples.fail("settle", settled.error.as_deref());
+                }
+            }
+            samples
+        });
+    }
+
+    let mut samples = Samples::default();
+    while let Some(joined) = running.join_next().await {
+        samples.merge(joined.expect("load clients do not panic"));
+    }
+    Report::new(config, samples, started.elapsed())
+}
@@ -0,0 +1,64 @@
+use std::process::ExitCode;
+
+use clap::Parser;
+use x402_loadtest::{run, LoadConfig, Target};
+
+/// Measure the settlement throughput of the x402 facilitator
+#[derive(Debug, Parser)]
+#[command(name = "x402-loadtest", version)]
+struct Args {
+    /// Concurrent synthetic clients, each with its own escrow
+    #[arg(long, default_value_t = 16)]
+    clients: usize,
+    /// Payments made by each client, one after the other
+    #[arg(long, default_value_t = 10)]
+    payments: usize,
+    /// Amount of each payment, in stroops
+    #[arg(long, default_value_t = 100)]
+    amount: i128,
+    /// Run against a quickstart network rather than an in-process env
+    #[arg(long)]
+    quickstart: bool,
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+    /// Largest share of failed payments before the run fails
+    #[arg(long, default_value_t = 0.0)]
+    max_error_rate: f64,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let config = LoadConfig {
+        target: if args.quickstart {
+            Target::Quickstart
+        } else {
+            Target::InProcess
+        },
+        clients: args.clients,
+        payments: args.payments,
+        amount: args.amount,
+    };
+    let report = match run(&config).await {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("x402-loadtest: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print!("{report}");
+    }
+    if report.error_rate() > args.max_error_rate {
+        eprintln!(
+            "x402-loadtest: error rate {:.2}% above {:.2}%",
+            report.error_rate() * 100.0,
+            args.max_error_rate * 100.0
+        );
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,153 @@
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+use serde::Serialize;
+
+use crate::LoadConfig;
+
+/// Measurements of some of the payments of a load test
+#[derive(Clone, Debug, Default)]
+pub struct Samples {
+    /// Latency of each /verify
+    pub verify: Vec<Duration>,
+    /// Latency of each /settle, for payments that verified
+    pub settle: Vec<Duration>,
+    /// Payments verified and settled
+    pub settled: usize,
+    /// Failed payments by `<stage>: <reason>`
+    pub failures: BTreeMap<String, usize>,
+}
+
+impl Samples {
+    /// Count a payment failing at `stage`
+    pub fn fail(&mut self, stage: &str, reason: Option<&str>) {
+        let reason = reason.unwrap_or("unknown");
+        *self
+            .failures
+            .entry(format!("{stage}: {reason}"))
+            .or_default() += 1;
+    }
+
+    pub fn merge(&mut self, other: Samples) {
+        self.verify.extend(other.verify);
+        self.settle.extend(other.settle);
+        self.settled += other.settled;
+        for (reason, count) in other.failures {
+            *self.failures.entry(reason).or_default() += count;
+        }
+    }
+}
+
+/// Latency distribution of a pipeline stage, in milliseconds
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencyStats {
+    /// Nearest-rank percentiles of `samples`, all zero when there are none
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        let millis = |d: Duration| d.as_secs_f64() * 1e3;
+        let percentile = |p: usize| {
+            let rank = (p * sorted.len()).div_ceil(100).max(1);
+            millis(sorted[rank - 1])
+        };
+        Self {
+            count: sorted.len(),
+            mean_ms: millis(sorted.iter().sum::<Duration>()) / sorted.len() as f64,
+            p50_ms: percentile(50),
+            p90_ms: percentile(90),
+            p99_ms: percentile(99),
+            max_ms: millis(sorted[sorted.len() - 1]),
+        }
+    }
+}
+
+/// Outcome of a load test
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Report {
+    pub target: String,
+    pub clients: usize,
+    /// Payments attempted
+    pub payments: usize,
+    /// Payments verified and settled
+    pub settled: usize,
+    /// Time the load took, setup excluded, in seconds
+    pub elapsed_secs: f64,
+    /// Settled payments per second
+    pub settlements_per_sec: f64,
+    pub verify: LatencyStats,
+    pub settle: LatencyStats,
+    /// Failed payments by `<stage>: <reason>`
+    pub failures: BTreeMap<String, usize>,
+}
+
+impl Report {
+    pub fn new(config: &LoadConfig, samples: Samples, elapsed: Duration) -> Self {
+        let elapsed_secs = elapsed.as_secs_f64();
+        Self {
+            target: config.target.name().into(),
+            clients: config.clients,
+            payments: config.clients * config.payments,
+            settled: samples.settled,
+            elapsed_secs,
+            settlements_per_sec: match elapsed_secs {
+                secs if secs > 0.0 => samples.settled as f64 / secs,
+                _ => 0.0,
+            },
+            verify: LatencyStats::from_samples(&samples.verify),
+            settle: LatencyStats::from_samples(&samples.settle),
+            failures: samples.failures,
+        }
+    }
+
+    /// Share of the attempted payments that failed
+    pub fn error_rate(&self) -> f64 {
+        match self.payments {
+            0 => 0.0,
+            payments => (payments - self.settled) as f64 / payments as f64,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} clients, {} payments on {} in {:.2}s",
+            self.clients, self.payments, self.target, self.elapsed_secs
+        )?;
+        writeln!(
+            f,
+            "settled {} ({:.1}/s), error rate {:.2}%",
+            self.settled,
+            self.settlements_per_sec,
+            self.error_rate() * 100.0
+        )?;
+        writeln!(
+            f,
+            "{:<8} {:>7} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "stage", "count", "mean ms", "p50 ms", "p90 ms", "p99 ms", "max ms"
+        )?;
+        for (stage, stats) in [("verify", &self.verify), ("settle", &self.settle)] {
+            writeln!(
+                f,
+                "{stage:<8} {:>7} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+                stats.count, stats.mean_ms, stats.p50_ms, stats.p90_ms, stats.p99_ms, stats.max_ms
+            )?;
+        }
+        for (reason, count) in &self.failures {
+            writeln!(f, "failed {count}x {reason}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use sha2::{Digest, Sha256};
+use tokio::task::JoinSet;
+use x402_client::{EscrowClient, LocalSigner};
+use x402_facilitator::Facilitator;
+use x402_integration::{escrow_wasm, Quickstart};
+use x402_testkit::{TestKit, Wallet, NETWORK};
+use x402_types::{
+    encode_payment_header, PaymentPayload, PaymentRequirements, SchemePayload, ESCROW_SCHEME,
+    X402_VERSION,
+};
+
+use crate::{LoadConfig, LoadError};
+
+/// Network a load test runs against
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Target {
+    /// Escrow contract in an in-process Soroban env
+    #[default]
+    InProcess,
+    /// Escrow contract deployed to a quickstart network
+    Quickstart,
+}
+
+impl Target {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::InProcess => "in-process",
+            Self::Quickstart => "quickstart",
+        }
+    }
+}
+
+/// Synthetic client paying from its own escrow
+pub struct SyntheticClient {
+    wallet: Wallet,
+    escrow_id: u64,
+}
+
+impl SyntheticClient {
+    /// Client address (G... format)
+    pub fn address(&self) -> &str {
+        self.wallet.address()
+    }
+
+    pub fn escrow_id(&self) -> u64 {
+        self.escrow_id
+    }
+
+    /// X-PAYMENT header paying `amount` from the client's escrow
+    pub fn payment_header(&self, amount: i128, nonce: u64) -> String {
+        encode_payment_header(&PaymentPayload {
+            x402_version: X402_VERSION,
+            scheme: ESCROW_SCHEME.into(),
+            network: NETWORK.into(),
+            asset: None,
+            payload: SchemePayload::Escrow(self.wallet.payload(self.escrow_id, amount, nonce)),
+            extensions: Default::default(),
+        })
+    }
+}
+
+/// What the facilitator runs on, kept alive for the length of the test
+enum Network {
+    InProcess(TestKit),
+    Quickstart(Quickstart),
+}
+
+/// Facilitator and funded clients of a load test
+pub struct Setup {
+    facilitator: Arc<Facilitator>,
+    clients: Vec<Arc<SyntheticClient>>,
+    requirements: PaymentRequirements,
+    network: Network,
+}
+
+impl Setup {
+    /// Start the target network and open one escrow per client, funded for
+    /// all its payments
+    ///
+    /// # Errors
+    /// * `Harness` - If the quickstart network cannot be started, funded, or
+    ///   deployed to
+    /// * `OpenEscrow` - If a client cannot open its escrow
+    pub async fn new(config: &LoadConfig) -> Result<Self, LoadError> {
+        let seeds: Vec<[u8; 32]> = (0..config.clients)
+            .map(|i| seed(&format!("client-{i}")))
+            .collect();
+        let (facilitator, wallets, network) = match config.target {
+            Target::InProcess => {
+                let kit = TestKit::new();
+                let wallets = seeds.iter().map(|seed| kit.wallet(seed)).collect();
+                (kit.facilitator(), wallets, Network::InProcess(kit))
+            }
+            Target::Quickstart => {
+                let network = Quickstart::start().await?;
+                let (deployer, server) = (seed("deployer"), seed("server"));
+                for seed in [&deployer, &server].into_iter().chain(&seeds) {
+                    network
+                        .fund(&LocalSigner::from_bytes(seed).address())
+                        .await?;
+                }
+                let contract = network.deploy(&escrow_wasm()?, &deployer).await?;
+                let escrow = |seed: &[u8; 32]| {
+                    EscrowClient::new(
+                        network.rpc().clone(),
+                        &contract,
+                        network.passphrase(),
+                        LocalSigner::from_bytes(seed),
+                    )
+                };
+                let facilitator = Facilitator::new(escrow(&server)?, x402_integration::NETWORK);
+                let server = facilitator.server().to_string();
+                let wallets = seeds
+                    .iter()
+                    .map(|seed| Ok(Wallet::new(escrow(seed)?, seed, &server)))
+                    .collect::<Result<_, LoadError>>()?;
+                (Arc::new(facilitator), wallets, Network::Quickstart(network))
+            }
+        };
+
+        // Clients sign with their own keys, so escrows open concurrently
+        let deposit = config.amount * config.payments.max(1) as i128;
+        let server = facilitator.server().to_string();
+        let mut opening = JoinSet::new();
+        for (index, wallet) in wallets.into_iter().enumerate() {
+            let server = server.clone();
+            opening.spawn(async move {
+                let opened = wallet
+                    .client()
+                    .open_escrow(wallet.address(), &server, deposit, None)
+                    .await;
+                (index, wallet, opened)
+            });
+        }
+        let mut clients = Vec::with_capacity(config.clients);
+        while let Some(joined) = opening.join_next().await {
+            let (index, wallet, opened) = joined.expect("escrow opening does not panic");
+            let escrow_id = opened.map_err(LoadError::OpenEscrow)?.value;
+            clients.push((index, Arc::new(SyntheticClient { wallet, escrow_id })));
+        }
+        clients.sort_by_key(|(index, _)| *index);
+
+        Ok(Self {
+            requirements: requirements(&server, config.amount),
+            facilitator,
+            clients: clients.into_iter().map(|(_, client)| client).collect(),
+            network,
+        })
+    }
+
+    pub fn facilitator(&self) -> &Arc<Facilitator> {
+        &self.facilitator
+    }
+
+    pub fn clients(&self) -> &[Arc<SyntheticClient>] {
+        &self.clients
+    }
+
+    /// Requirements every payment is made against
+    pub fn requirements(&self) -> &PaymentRequirements {
+        &self.requirements
+    }
+
+    /// In-process test env, None on a quickstart network
+    pub fn kit(&self) -> Option<&TestKit> {
+        match &self.network {
+            Network::InProcess(kit) => Some(kit),
+            Network::Quickstart(_) => None,
+        }
+    }
+
+    /// Quickstart network, e.g. to diagnose failed settlements
+    pub fn quickstart(&self) -> Option<&Quickstart> {
+        match &self.network {
+            Network::InProcess(_) => None,
+            Network::Quickstart(network) => Some(network),
+        }
+    }
+}
+
+/// Escrow payment requirements of `amount` stroops paid to `server`
+fn requirements(server: &str, amount: i128) -> PaymentRequirements {
+    PaymentRequirements {
+        x402_version: None,
+        scheme: ESCROW_SCHEME.into(),
+        network: NETWORK.into(),
+        max_amount_required: amount.to_string(),
+        resource: "https://loadtest.invalid/resource".into(),
+        description: "Load test resource".into(),
+        mime_type: "application/json".into(),
+        output_schema: None,
+        pay_to: server.into(),
+        asset: None,
+        alternatives: vec![],
+        max_timeout_seconds: 60,
+        extra: None,
+    }
+}
+
+/// Fresh seed per account and run, so reruns against a long-lived network
+/// start from new accounts
+fn seed(name: &str) -> [u8; 32] {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    Sha256::digest(format!("x402-loadtest-{name}-{nanos}")).into()
+}
@@ -0,0 +1,84 @@
+#![cfg(test)]
+
+use std::time::Duration;
+
+use crate::{drive, LatencyStats, LoadConfig, Report, Samples, Setup, Target};
+
+#[test]
+fn test_latency_stats() {
+    let samples: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+    let stats = LatencyStats::from_samples(&samples);
+    assert_eq!(stats.count, 100);
+    assert_eq!(stats.mean_ms, 50.5);
+    assert_eq!(stats.p50_ms, 50.0);
+    assert_eq!(stats.p90_ms, 90.0);
+    assert_eq!(stats.p99_ms, 99.0);
+    assert_eq!(stats.max_ms, 100.0);
+
+    let single = LatencyStats::from_samples(&[Duration::from_millis(7)]);
+    assert_eq!((single.p50_ms, single.p99_ms), (7.0, 7.0));
+    assert_eq!(LatencyStats::from_samples(&[]), LatencyStats::default());
+}
+
+#[test]
+fn test_report() {
+    let config = LoadConfig {
+        clients: 2,
+        payments: 2,
+        ..LoadConfig::default()
+    };
+    let mut samples = Samples {
+        verify: vec![Duration::from_millis(1); 4],
+        settle: vec![Duration::from_millis(10); 3],
+        settled: 2,
+        ..Samples::default()
+    };
+    let mut other = Samples::default();
+    other.fail("verify", Some("nonce_replayed"));
+    other.fail("settle", None);
+    samples.merge(other);
+
+    let report = Report::new(&config, samples, Duration::from_secs(2));
+    assert_eq!(report.payments, 4);
+    assert_eq!(report.settlements_per_sec, 1.0);
+    assert_eq!(report.error_rate(), 0.5);
+    assert_eq!(report.failures["verify: nonce_replayed"], 1);
+    assert_eq!(report.failures["settle: unknown"], 1);
+
+    let text = report.to_string();
+    assert!(
+        text.contains("settled 2 (1.0/s), error rate 50.00%"),
+        "{text}"
+    );
+    assert!(text.contains("failed 1x verify: nonce_replayed"), "{text}");
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["target"], "in-process");
+    assert_eq!(json["settle"]["count"], 3);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_in_process_load() {
+    let config = LoadConfig {
+        target: Target::InProcess,
+        clients: 8,
+        payments: 3,
+        amount: 250,
+    };
+    let setup = Setup::new(&config).await.unwrap();
+    assert_eq!(setup.clients().len(), 8);
+
+    // Settlements of concurrent clients share the server's sequence numbers
+    let report = drive(&setup, &config).await;
+    assert_eq!(report.failures, Default::default());
+    assert_eq!(report.settled, 24);
+    assert_eq!(report.verify.count, 24);
+    assert_eq!(report.settle.count, 24);
+    assert!(report.settlements_per_sec > 0.0);
+
+    let kit = setup.kit().unwrap();
+    let server = kit.escrow_client(&x402_testkit::SERVER_SEED);
+    for client in setup.clients() {
+        let balance = server.get_escrow_balance(client.escrow_id()).await.unwrap();
+        assert_eq!(balance, 0, "escrow of {}", client.address());
+    }
+}
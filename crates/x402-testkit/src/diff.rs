@@ -0,0 +1,730 @@
+use std::collections::BTreeMap;
+
+use ed25519_dalek::{Signer as _, SigningKey};
+use soroban_sdk::{
+    testutils::{Address as _, Events as _, Ledger as _},
+    xdr::{ContractDataDurability, LedgerEntryData, ScAddress, ScVal},
+    Address, Bytes, BytesN, Env, IntoVal, InvokeError, String as SorobanString, Symbol, TryFromVal,
+    Val, Vec as SorobanVec,
+};
+use x402_escrow::{
+    Asset, Authorization, ChannelState, Error, PaymentIntent, PaymentStatus, Settlement, Voucher,
+    X402EscrowContractClient,
+};
+use x402_types::{
+    account_strkey, network_passphrase, EscrowChannelState, EscrowVoucher, SigningDomain,
+    STELLAR_TESTNET, STRKEY_LEN,
+};
+
+use crate::{TestOracle, TestOracleClient, CLIENT_SEED, SERVER_SEED};
+
+/// Path of the previous release's escrow wasm, to check the current build
+/// against
+pub const PREVIOUS_ESCROW_WASM_VAR: &str = "X402_PREVIOUS_ESCROW_WASM";
+
+/// Value a call returned, or the error it failed with
+pub type Outcome = Result<ScVal, InvokeError>;
+
+/// Event published by a contract, as topics and data
+pub type ContractEvent = (Vec<ScVal>, ScVal);
+
+/// Storage entry of a contract, by durability
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StorageKey {
+    Instance(ScVal),
+    Persistent(ScVal),
+    Temporary(ScVal),
+}
+
+/// Way the new build behaved unlike the old one
+#[derive(Clone, Debug, PartialEq)]
+pub enum Difference {
+    /// A call returned another value or error
+    Return {
+        step: usize,
+        function: &'static str,
+        old: Outcome,
+        new: Outcome,
+    },
+    /// A call published other events
+    Events {
+        step: usize,
+        function: &'static str,
+        old: Vec<ContractEvent>,
+        new: Vec<ContractEvent>,
+    },
+    /// A storage entry differs once the script ran, None where it is missing
+    Storage {
+        key: StorageKey,
+        old: Option<ScVal>,
+        new: Option<ScVal>,
+    },
+}
+
+/// Accounts and contracts shared by both builds, for the arguments of calls
+pub struct Parties {
+    /// Escrow client, the account of `client_key`
+    pub client: Address,
+    /// ed25519 key of the client, signing authorizations and vouchers
+    pub client_key: SigningKey,
+    pub server: Address,
+    /// Admin of USD pricing
+    pub admin: Address,
+    /// [`TestOracle`] price feed
+    pub oracle: Address,
+}
+
+/// Build a call is made on, to derive its arguments
+pub struct Call<'a> {
+    pub env: &'a Env,
+    /// Escrow contract called, the domain of signed messages
+    pub contract: &'a Address,
+    pub parties: &'a Parties,
+}
+
+type Args = Box<dyn Fn(&Call) -> SorobanVec<Val>>;
+
+/// Sequence of contract calls replayed against both builds
+///
+/// Arguments are derived per build, so messages bound to a contract are
+/// signed for each.
+#[derive(Default)]
+pub struct Script {
+    steps: Vec<(&'static str, Args)>,
+}
+
+impl Script {
+    /// Empty script
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a call of `function`
+    ///
+    /// # Arguments
+    /// * `function` - Contract function called
+    /// * `args` - Its arguments, for the build called
+    pub fn call(
+        mut self,
+        function: &'static str,
+        args: impl Fn(&Call) -> SorobanVec<Val> + 'static,
+    ) -> Self {
+        self.steps.push((function, Box::new(args)));
+        self
+    }
+
+    /// Functions called, in order, with repeats
+    pub fn functions(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.steps.iter().map(|(function, _)| *function)
+    }
+}
+
+/// Differences the new build is expected to have
+///
+/// Intentional changes of an upgrade are listed here, so the harness only
+/// reports the others.
+#[derive(Default)]
+pub struct Whitelist {
+    rules: Vec<Box<dyn Fn(&Difference) -> bool>>,
+}
+
+impl Whitelist {
+    /// Nothing expected to differ
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow the differences `rule` matches
+    pub fn allow(mut self, rule: impl Fn(&Difference) -> bool + 'static) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Allow calls of `function` to return or publish something else
+    pub fn allow_function(self, function: &'static str) -> Self {
+        self.allow(move |difference| match difference {
+            Difference::Return { function: f, .. } | Difference::Events { function: f, .. } => {
+                *f == function
+            }
+            Difference::Storage { .. } => false,
+        })
+    }
+
+    /// Allow the storage entries `rule` matches to differ
+    pub fn allow_storage(self, rule: impl Fn(&StorageKey) -> bool + 'static) -> Self {
+        self.allow(move |difference| match difference {
+            Difference::Storage { key, .. } => rule(key),
+            _ => false,
+        })
+    }
+
+    fn allows(&self, difference: &Difference) -> bool {
+        self.rules.iter().any(|rule| rule(difference))
+    }
+}
+
+/// Two builds of the escrow contract in one test `Env`
+///
+/// Scripts run call by call against both, with all auths mocked, on a
+/// ledger of [`STELLAR_TESTNET`]. Each build starts with empty storage.
+pub struct Differential {
+    env: Env,
+    old: Address,
+    new: Address,
+    parties: Parties,
+}
+
+impl Differential {
+    /// Register both builds, e.g. the previous release wasm and the current
+    /// one with `env.register(wasm, ())`
+    pub fn new(old: impl FnOnce(&Env) -> Address, new: impl FnOnce(&Env) -> Address) -> Self {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.cost_estimate().budget().reset_unlimited();
+        let passphrase = network_passphrase(STELLAR_TESTNET).expect("known network");
+        let network_id = env
+            .crypto()
+            .sha256(&Bytes::from_slice(&env, passphrase.as_bytes()))
+            .to_array();
+        env.ledger().with_mut(|ledger| {
+            ledger.network_id = network_id;
+            ledger.timestamp = 1_700_000_000;
+        });
+
+        let oracle = env.register(TestOracle, ());
+        // 0.25 USD per unit
+        TestOracleClient::new(&env, &oracle).set_price(&25_000_000_000_000);
+        let client_key = SigningKey::from_bytes(&CLIENT_SEED);
+        let parties = Parties {
+            client: account(&env, &client_key),
+            server: account(&env, &SigningKey::from_bytes(&SERVER_SEED)),
+            admin: Address::generate(&env),
+            oracle,
+            client_key,
+        };
+        Self {
+            old: old(&env),
+            new: new(&env),
+            env,
+            parties,
+        }
+    }
+
+    /// Test environment of both builds
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Replay `script` against both builds
+    ///
+    /// # Returns
+    /// * Differences of return values and events, call by call, then of
+    ///   storage once the script ran, leaving out those `whitelist` allows
+    pub fn run(&self, script: &Script, whitelist: &Whitelist) -> Vec<Difference> {
+        let mut differences = vec![];
+        for (step, (function, args)) in script.steps.iter().enumerate() {
+            let (old, old_events) = self.invoke(&self.old, function, args);
+            let (new, new_events) = self.invoke(&self.new, function, args);
+            if old != new {
+                differences.push(Difference::Return {
+                    step,
+                    function,
+                    old,
+                    new,
+                });
+            }
+            if old_events != new_events {
+                differences.push(Difference::Events {
+                    step,
+                    function,
+                    old: old_events,
+                    new: new_events,
+                });
+            }
+        }
+
+        let mut old = self.storage(&self.old);
+        let mut new = self.storage(&self.new);
+        let keys: Vec<_> = old.keys().chain(new.keys()).cloned().collect();
+        for key in keys {
+            let (old, new) = (old.remove(&key), new.remove(&key));
+            if old != new {
+                differences.push(Difference::Storage { key, old, new });
+            }
+        }
+
+        differences.retain(|difference| !whitelist.allows(difference));
+        differences
+    }
+
+    fn invoke(
+        &self,
+        contract: &Address,
+        function: &str,
+        args: &Args,
+    ) -> (Outcome, Vec<ContractEvent>) {
+        let env = &self.env;
+        let args = args(&Call {
+            env,
+            contract,
+            parties: &self.parties,
+        });
+        // Codes unknown to this crate's contract error, of a newer build,
+        // come back as invoke errors with their code
+        let outcome = match env.try_invoke_contract::<Val, Error>(
+            contract,
+            &Symbol::new(env, function),
+            args,
+        ) {
+            Ok(value) => Ok(scval(env, value.expect("values convert to themselves"))),
+            Err(Ok(e)) => Err(e.into()),
+            Err(Err(e)) => Err(e),
+        };
+        let events = env
+            .events()
+            .all()
+            .iter()
+            .filter(|(emitter, _, _)| emitter == contract)
+            .map(|(_, topics, data)| {
+                let topics = topics.iter().map(|topic| scval(env, topic)).collect();
+                (topics, scval(env, data))
+            })
+            .collect();
+        (outcome, events)
+    }
+
+    /// Storage of `contract`, instance entries included
+    fn storage(&self, contract: &Address) -> BTreeMap<StorageKey, ScVal> {
+        let contract = ScAddress::from(contract);
+        let mut storage = BTreeMap::new();
+        for (_, (entry, _)) in self.env.to_ledger_snapshot().ledger_entries {
+            let LedgerEntryData::ContractData(data) = entry.data else {
+                continue;
+            };
+            if data.contract != contract {
+                continue;
+            }
+            match (data.key, data.val) {
+                // The executable differs by design, only the storage counts
+                (ScVal::LedgerKeyContractInstance, ScVal::ContractInstance(instance)) => {
+                    for entry in instance.storage.iter().flat_map(|map| map.iter()) {
+                        storage.insert(StorageKey::Instance(entry.key.clone()), entry.val.clone());
+                    }
+                }
+                (key, val) => {
+                    let key = match data.durability {
+                        ContractDataDurability::Persistent => StorageKey::Persistent(key),
+                        ContractDataDurability::Temporary => StorageKey::Temporary(key),
+                    };
+                    storage.insert(key, val);
+                }
+            }
+        }
+        storage
+    }
+}
+
+/// Script calling every public function of the escrow contract
+///
+/// Covers the lifecycle of an escrow, batch settlement, pending deposits,
+/// signed messages, USD pricing, and the errors of calls made out of turn.
+/// Extend it along with the contract, so upgrades are checked against
+/// every entry point.
+pub fn escrow_script() -> Script {
+    let resource = |cx: &Call| SorobanString::from_str(cx.env, "/weather");
+    // Builds may allocate other IDs, so each call looks up those of the
+    // build called, u64::MAX standing for one never allocated
+    let escrow = |cx: &Call| {
+        let p = cx.parties;
+        X402EscrowContractClient::new(cx.env, cx.contract)
+            .find_escrow(&p.client, &p.server)
+            .unwrap_or(u64::MAX)
+    };
+    let payment = move |cx: &Call, index: u32| {
+        X402EscrowContractClient::new(cx.env, cx.contract)
+            .get_payments(&escrow(cx), &None, &index, &1)
+            .payments
+            .first()
+            .map_or(u64::MAX, |entry| entry.payment_id)
+    };
+    // Payments of the script are all charged to its one escrow
+    let settlement = move |cx: &Call, index, amount| Settlement {
+        payment_id: payment(cx, index),
+        escrow_id: escrow(cx),
+        amount,
+        client: cx.parties.client.clone(),
+    };
+    let memo_hash = |cx: &Call| BytesN::from_array(cx.env, &[9; 32]);
+    let terms_hash = |cx: &Call| BytesN::from_array(cx.env, &[7; 32]);
+    Script::new()
+        .call("get_price_oracle", |cx| SorobanVec::new(cx.env))
+        .call("initialize", |cx| {
+            (cx.parties.admin.clone(),).into_val(cx.env)
+        })
+        .call("set_price_oracle", |cx| {
+            let asset = Asset::Other(Symbol::new(cx.env, "XLM"));
+            let p = cx.parties;
+            (p.admin.clone(), p.oracle.clone(), asset).into_val(cx.env)
+        })
+        .call("set_price_limits", |cx| {
+            (cx.parties.admin.clone(), 600u64, 500u32).into_val(cx.env)
+        })
+        .call("get_price_oracle", |cx| SorobanVec::new(cx.env))
+        .call("open_escrow", |cx| {
+            let p = cx.parties;
+            let accepted: Option<BytesN<32>> = None;
+            (p.client.clone(), p.server.clone(), 10_000_000i128, accepted).into_val(cx.env)
+        })
+        .call("open_escrow", |cx| {
+            let p = cx.parties;
+            let accepted: Option<BytesN<32>> = None;
+            (p.client.clone(), p.server.clone(), 1i128, accepted).into_val(cx.env)
+        })
+        .call("find_escrow", |cx| {
+            let p = cx.parties;
+            (p.client.clone(), p.server.clone()).into_val(cx.env)
+        })
+        .call("deposit", move |cx| {
+            (escrow(cx), 5_000_000i128).into_val(cx.env)
+        })
+        .call("deposit", |cx| (u64::MAX, 5_000_000i128).into_val(cx.env))
+        .call("claim_pending_deposit", move |cx| {
+            let from = cx.parties.client.clone();
+            (escrow(cx), from, 1_000i128, memo_hash(cx)).into_val(cx.env)
+        })
+        .call("claim_pending_deposit", move |cx| {
+            let from = cx.parties.client.clone();
+            (escrow(cx), from, 1_000i128, memo_hash(cx)).into_val(cx.env)
+        })
+        .call("is_deposit_claimed", move |cx| {
+            (memo_hash(cx),).into_val(cx.env)
+        })
+        // Refused before any transfer, the parties holding no token
+        .call("record_direct_payment", move |cx| {
+            let p = cx.parties;
+            let token = p.oracle.clone();
+            (
+                p.client.clone(),
+                p.server.clone(),
+                token,
+                0i128,
+                memo_hash(cx),
+            )
+                .into_val(cx.env)
+        })
+        .call("create_payment", move |cx| {
+            (escrow(cx), 1_000_000i128).into_val(cx.env)
+        })
+        .call("create_payment", move |cx| {
+            (escrow(cx), 2_000_000i128).into_val(cx.env)
+        })
+        .call("create_payment", move |cx| {
+            (escrow(cx), i128::MAX).into_val(cx.env)
+        })
+        .call("create_payments", move |cx| {
+            let intent = |amount| PaymentIntent {
+                escrow_id: escrow(cx),
+                amount,
+            };
+            (soroban_sdk::vec![cx.env, intent(300_000), intent(400_000)],).into_val(cx.env)
+        })
+        .call("settle_payment", move |cx| {
+            (settlement(cx, 0, 1_000_000),).into_val(cx.env)
+        })
+        .call("settle_payment", move |cx| {
+            (settlement(cx, 0, 1_000_000),).into_val(cx.env)
+        })
+        .call("settle_payments", move |cx| {
+            (soroban_sdk::vec![cx.env, settlement(cx, 1, 2_000_000)],).into_val(cx.env)
+        })
+        .call("get_payment", move |cx| (payment(cx, 1),).into_val(cx.env))
+        .call("get_payments", move |cx| {
+            (escrow(cx), None::<PaymentStatus>, 0u32, 50u32).into_val(cx.env)
+        })
+        .call("get_direct_payments", |cx| {
+            let server = cx.parties.server.clone();
+            (server, None::<PaymentStatus>, 0u32, 50u32).into_val(cx.env)
+        })
+        .call("export_escrows", |cx| {
+            (None::<Address>, 0u64, 50u32).into_val(cx.env)
+        })
+        .call("quote_usd", move |cx| {
+            (cx.parties.server.clone(), resource(cx)).into_val(cx.env)
+        })
+        .call("set_usd_price", move |cx| {
+            (cx.parties.server.clone(), resource(cx), 25i128).into_val(cx.env)
+        })
+        .call("quote_usd", move |cx| {
+            (cx.parties.server.clone(), resource(cx)).into_val(cx.env)
+        })
+        .call("create_payment_usd", move |cx| {
+            (escrow(cx), 25i128, resource(cx)).into_val(cx.env)
+        })
+        .call("suspend_price", move |cx| {
+            (cx.parties.server.clone(), resource(cx)).into_val(cx.env)
+        })
+        .call("quote", move |cx| {
+            (cx.parties.server.clone(), resource(cx)).into_val(cx.env)
+        })
+        .call("create_payment_usd", move |cx| {
+            (escrow(cx), 25i128, resource(cx)).into_val(cx.env)
+        })
+        .call("resume_price", move |cx| {
+            (cx.parties.server.clone(), resource(cx)).into_val(cx.env)
+        })
+        .call("verify_authorization", |cx| {
+            let authorization = Authorization {
+                escrow_id: 0,
+                network: SorobanString::from_str(cx.env, STELLAR_TESTNET),
+                amount: 1_000_000,
+                nonce: 42,
+                expires_at: 1_700_000_600,
+            };
+            let strkey = contract_strkey(cx.contract);
+            let message = x402_types::EscrowAuthorization {
+                domain: domain(cx, &strkey),
+                network: STELLAR_TESTNET,
+                escrow_id: 0,
+                amount: "1000000",
+                nonce: 42,
+                expires_at: 1_700_000_600,
+            };
+            let (public_key, signature) = sign(cx, message.signing_hash());
+            (authorization, public_key, signature).into_val(cx.env)
+        })
+        .call("verify_voucher", |cx| {
+            let strkey = contract_strkey(cx.contract);
+            let message = EscrowVoucher {
+                domain: domain(cx, &strkey),
+                escrow_id: 0,
+                amount: 400,
+                sequence: 2,
+                expires_at: 1_700_000_600,
+            };
+            let voucher = Voucher {
+                escrow_id: 0,
+                amount: 400,
+                sequence: 2,
+                expires_at: 1_700_000_600,
+            };
+            let (public_key, signature) = sign(cx, message.signing_hash());
+            (voucher, public_key, signature).into_val(cx.env)
+        })
+        .call("verify_channel_state", |cx| {
+            let strkey = contract_strkey(cx.contract);
+            let message = EscrowChannelState {
+                domain: domain(cx, &strkey),
+                escrow_id: 0,
+                sequence: 3,
+                balance: 8_500_000,
+                settled: 1_500_000,
+            };
+            let state = ChannelState {
+                escrow_id: 0,
+                sequence: 3,
+                balance: 8_500_000,
+                settled: 1_500_000,
+            };
+            let (public_key, signature) = sign(cx, message.signing_hash());
+            (state, public_key, signature).into_val(cx.env)
+        })
+        .call("get_escrow", move |cx| (escrow(cx),).into_val(cx.env))
+        .call("get_escrow_balance", move |cx| {
+            (escrow(cx),).into_val(cx.env)
+        })
+        .call("client_close_escrow", move |cx| {
+            (escrow(cx),).into_val(cx.env)
+        })
+        .call("server_close_escrow", move |cx| {
+            (escrow(cx),).into_val(cx.env)
+        })
+        .call("get_escrow", move |cx| (escrow(cx),).into_val(cx.env))
+        .call("get_escrow_balance", move |cx| {
+            (escrow(cx),).into_val(cx.env)
+        })
+        .call("find_escrow", |cx| {
+            let p = cx.parties;
+            (p.client.clone(), p.server.clone()).into_val(cx.env)
+        })
+        .call("get_escrow_index", |cx| {
+            (cx.parties.client.clone(), true, 0u32).into_val(cx.env)
+        })
+        .call("compact_escrow_index", |cx| {
+            (cx.parties.client.clone(), 0u32, 1u32).into_val(cx.env)
+        })
+        .call("set_terms", move |cx| {
+            (cx.parties.server.clone(), terms_hash(cx)).into_val(cx.env)
+        })
+        .call("get_terms", |cx| {
+            (cx.parties.server.clone(),).into_val(cx.env)
+        })
+        // Reopening must now accept the published terms
+        .call("open_escrow", |cx| {
+            let p = cx.parties;
+            let accepted: Option<BytesN<32>> = None;
+            (p.client.clone(), p.server.clone(), 1i128, accepted).into_val(cx.env)
+        })
+        .call("open_escrow", move |cx| {
+            let p = cx.parties;
+            let accepted = Some(terms_hash(cx));
+            (p.client.clone(), p.server.clone(), 1i128, accepted).into_val(cx.env)
+        })
+        // A second environment of the server, publishing the same terms
+        .call("set_terms", move |cx| {
+            (cx.parties.admin.clone(), terms_hash(cx)).into_val(cx.env)
+        })
+        .call("clone_escrow_config", move |cx| {
+            (escrow(cx), cx.parties.admin.clone(), 1i128).into_val(cx.env)
+        })
+        .call("open_sponsored_escrow", |cx| {
+            let p = cx.parties;
+            (p.admin.clone(), p.oracle.clone(), 1i128).into_val(cx.env)
+        })
+        .call("set_notification_url", |cx| {
+            let url = Bytes::from_slice(cx.env, b"https://server.example/x402/hooks");
+            (cx.parties.server.clone(), url).into_val(cx.env)
+        })
+        .call("get_notification_url", |cx| {
+            (cx.parties.server.clone(),).into_val(cx.env)
+        })
+        // Signed for an escrow ID the open does not assign, so it rolls back
+        .call("open_and_authorize", |cx| {
+            let p = cx.parties;
+            let accepted: Option<BytesN<32>> = None;
+            let authorization = Authorization {
+                escrow_id: 0,
+                network: SorobanString::from_str(cx.env, STELLAR_TESTNET),
+                amount: 1,
+                nonce: 43,
+                expires_at: 1_700_000_600,
+            };
+            let strkey = contract_strkey(cx.contract);
+            let message = x402_types::EscrowAuthorization {
+                domain: domain(cx, &strkey),
+                network: STELLAR_TESTNET,
+                escrow_id: 0,
+                amount: "1",
+                nonce: 43,
+                expires_at: 1_700_000_600,
+            };
+            let (public_key, signature) = sign(cx, message.signing_hash());
+            (
+                p.client.clone(),
+                p.oracle.clone(),
+                1i128,
+                accepted,
+                authorization,
+                public_key,
+                signature,
+            )
+                .into_val(cx.env)
+        })
+        .call("get_preauthorized_payment", move |cx| {
+            (escrow(cx), 43u64).into_val(cx.env)
+        })
+        .call("set_max_settled_per_hour", move |cx| {
+            (escrow(cx), Some(1_000_000i128)).into_val(cx.env)
+        })
+        .call("acknowledge_anomaly", move |cx| {
+            (escrow(cx),).into_val(cx.env)
+        })
+        .call("get_spend_limit", move |cx| (escrow(cx),).into_val(cx.env))
+        .call("split_escrow", move |cx| {
+            let metadata = Bytes::from_slice(cx.env, b"tier:batch");
+            (escrow(cx), 1i128, metadata).into_val(cx.env)
+        })
+        .call("get_escrow_links", move |cx| (escrow(cx),).into_val(cx.env))
+}
+
+/// Intentional changes of the current build since the previous release
+///
+/// Entries are dropped once released.
+pub fn upgrade_whitelist() -> Whitelist {
+    // Escrow and payment IDs are no longer sequential, so the calls and
+    // events carrying them differ, and records moved to persistent storage.
+    // `initialize`, `create_payments`, `set_terms`, `get_terms`,
+    // `clone_escrow_config`, `open_and_authorize`, `get_preauthorized_payment`,
+    // `open_sponsored_escrow`, the spend limit functions, the price
+    // suspension functions, `quote`, the notification URL functions,
+    // `split_escrow`, `get_escrow_links`, the direct payment functions and
+    // the escrow index functions are new, opens carry the terms of service
+    // accepted, and escrows their trial.
+    [
+        "initialize",
+        "open_escrow",
+        "deposit",
+        "claim_pending_deposit",
+        "create_payment",
+        "create_payments",
+        "create_payment_usd",
+        "settle_payment",
+        "settle_payments",
+        "get_payment",
+        "get_payments",
+        "export_escrows",
+        "find_escrow",
+        "client_close_escrow",
+        "server_close_escrow",
+        "set_terms",
+        "get_terms",
+        "clone_escrow_config",
+        "set_notification_url",
+        "get_notification_url",
+        "open_and_authorize",
+        "get_preauthorized_payment",
+        "open_sponsored_escrow",
+        "set_max_settled_per_hour",
+        "acknowledge_anomaly",
+        "get_spend_limit",
+        "suspend_price",
+        "resume_price",
+        "quote",
+        "split_escrow",
+        "get_escrow_links",
+        "record_direct_payment",
+        "get_direct_payments",
+        "get_escrow_index",
+        "compact_escrow_index",
+        "get_escrow",
+    ]
+    .into_iter()
+    .fold(Whitelist::new(), |whitelist, function| {
+        whitelist.allow_function(function)
+    })
+    .allow_storage(|_| true)
+}
+
+/// Account of an ed25519 key
+fn account(env: &Env, key: &SigningKey) -> Address {
+    let strkey = account_strkey(key.verifying_key().as_bytes());
+    Address::from_str(
+        env,
+        std::str::from_utf8(&strkey).expect("strkeys are ASCII"),
+    )
+}
+
+/// Public key and signature of the client over `hash`
+fn sign(cx: &Call, hash: [u8; 32]) -> (BytesN<32>, BytesN<64>) {
+    let key = &cx.parties.client_key;
+    (
+        BytesN::from_array(cx.env, key.verifying_key().as_bytes()),
+        BytesN::from_array(cx.env, &key.sign(&hash).to_bytes()),
+    )
+}
+
+fn contract_strkey(contract: &Address) -> [u8; STRKEY_LEN] {
+    let mut strkey = [0; STRKEY_LEN];
+    contract.to_string().copy_into_slice(&mut strkey);
+    strkey
+}
+
+fn domain<'a>(cx: &Call, strkey: &'a [u8; STRKEY_LEN]) -> SigningDomain<'a> {
+    SigningDomain {
+        network_id: cx.env.ledger().network_id().to_array(),
+        contract_id: std::str::from_utf8(strkey).expect("strkeys are ASCII"),
+    }
+}
+
+fn scval(env: &Env, value: Val) -> ScVal {
+    ScVal::try_from_val(env, &value).expect("contract values convert to XDR")
+}
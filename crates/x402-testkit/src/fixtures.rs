@@ -0,0 +1,418 @@
+use ed25519_dalek::{Signer as _, SigningKey};
+use serde_json::{json, Value};
+use soroban_sdk::{
+    testutils::{Events as _, Ledger as _},
+    xdr::{Limits, ScVal, WriteXdr},
+    Address, Bytes, BytesN, Env, InvokeError, String as SorobanString, TryFromVal, Val,
+};
+use x402_client::{format_timestamp, ReceiptBody, SettlementReceipt, RECEIPT_VERSION};
+use x402_errors::{ContractError, X402Error, INTERNAL_CODE};
+use x402_escrow::{
+    Authorization, Error, Settlement, Voucher, X402EscrowContract, X402EscrowContractClient,
+};
+use x402_types::{
+    account_strkey, canonical_json, network_passphrase, EscrowAuthorization, EscrowVoucher,
+    SigningDomain, STELLAR_MAINNET, STELLAR_TESTNET,
+};
+
+use crate::{CLIENT_SEED, SERVER_SEED};
+
+/// Directory the fixtures are checked in to
+pub const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures");
+
+/// Escrow contract of the fixtures, the one of the SDK's own fixtures
+pub const FIXTURE_CONTRACT_ID: &str = "CAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABSC4";
+
+/// Seed of a key the escrow's client granted no allowance
+const STRANGER_SEED: [u8; 32] = [3; 32];
+
+/// Close time of the ledger the scenario starts in, 2025-03-01T00:00:00Z
+const START: u64 = 1_740_787_200;
+
+const START_LEDGER: u32 = 100;
+
+/// Seconds between the ledgers of the scenario
+const LEDGER_CLOSE: u64 = 5;
+
+const DEPOSIT: i128 = 10_000_000;
+
+const PAYMENT: i128 = 1_000_000;
+
+/// Hash standing for the settling transaction, none being submitted
+/// in-process
+const SETTLEMENT_TX_HASH: [u8; 32] = [7; 32];
+
+/// Test vectors of the escrow contract, for implementations in other
+/// languages
+///
+/// Drives the contract through a scripted scenario on a ledger of
+/// [`STELLAR_TESTNET`]: the client opens an escrow with the server, pays
+/// with signed authorizations, some rejected, signs vouchers, and the
+/// server settles the payment and signs its receipt. Every key derives from
+/// a fixed seed and ed25519 signatures are deterministic, so the fixtures
+/// are the same on every run.
+///
+/// # Returns
+/// * JSON of each fixture file, by file name: signed authorizations with
+///   their outcome on-chain, vouchers as `verify_voucher` checks them before
+///   they are redeemed, the settlement receipt with its canonical JSON,
+///   the events published as Soroban RPC encodes them, and every
+///   [`X402Error`] code
+pub fn contract_fixtures() -> Vec<(&'static str, Value)> {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.cost_estimate().budget().reset_unlimited();
+    let passphrase = network_passphrase(STELLAR_TESTNET).expect("known network");
+    let network_id = env
+        .crypto()
+        .sha256(&Bytes::from_slice(&env, passphrase.as_bytes()))
+        .to_array();
+    env.ledger().with_mut(|ledger| {
+        ledger.network_id = network_id;
+        ledger.sequence_number = START_LEDGER;
+        ledger.timestamp = START;
+    });
+    let contract = Address::from_str(&env, FIXTURE_CONTRACT_ID);
+    env.register_at(&contract, X402EscrowContract, ());
+    let escrow = X402EscrowContractClient::new(&env, &contract);
+
+    let client_key = SigningKey::from_bytes(&CLIENT_SEED);
+    let server_key = SigningKey::from_bytes(&SERVER_SEED);
+    let stranger_key = SigningKey::from_bytes(&STRANGER_SEED);
+    let (client, server) = (account(&env, &client_key), account(&env, &server_key));
+    let mut events = vec![];
+
+    let escrow_id = escrow.open_escrow(&client, &server, &DEPOSIT, &None);
+    events.extend(published(&env, &contract, "open_escrow"));
+    let scenario = json!({
+        "network": STELLAR_TESTNET,
+        "networkId": hex::encode(network_id),
+        "contractId": FIXTURE_CONTRACT_ID,
+        "escrowId": escrow_id,
+        "ledgerTimestamp": START,
+        "keys": {
+            "client": key(&client_key),
+            "server": key(&server_key),
+            "stranger": key(&stranger_key),
+        },
+    });
+
+    // Authorizations, paid once
+    let (authorization, public_key, signature, fields) = authorize(
+        &env,
+        &client_key,
+        STELLAR_TESTNET,
+        escrow_id,
+        PAYMENT,
+        1,
+        START + 600,
+    );
+    let payment_id = escrow.create_authorized_payment(&authorization, &public_key, &signature);
+    events.extend(published(&env, &contract, "create_authorized_payment"));
+    let mut authorizations = vec![vector(
+        "accepted",
+        &fields,
+        json!({ "paymentId": payment_id }),
+    )];
+    let replayed = escrow
+        .try_create_authorized_payment(&authorization, &public_key, &signature)
+        .expect_err("authorizations are paid once");
+    authorizations.push(vector("replayed", &fields, rejection(replayed)));
+    let rejected = [
+        (
+            "expired",
+            &client_key,
+            STELLAR_TESTNET,
+            PAYMENT,
+            2,
+            START - 1,
+        ),
+        (
+            "other_network",
+            &client_key,
+            STELLAR_MAINNET,
+            PAYMENT,
+            3,
+            START + 600,
+        ),
+        (
+            "not_client",
+            &stranger_key,
+            STELLAR_TESTNET,
+            PAYMENT,
+            4,
+            START + 600,
+        ),
+        (
+            "insufficient_balance",
+            &client_key,
+            STELLAR_TESTNET,
+            2 * DEPOSIT,
+            5,
+            START + 600,
+        ),
+    ];
+    for (name, signer, network, amount, nonce, expires_at) in rejected {
+        let (authorization, public_key, signature, fields) =
+            authorize(&env, signer, network, escrow_id, amount, nonce, expires_at);
+        let error = escrow
+            .try_create_authorized_payment(&authorization, &public_key, &signature)
+            .expect_err("authorization is rejected");
+        authorizations.push(vector(name, &fields, rejection(error)));
+    }
+
+    // Vouchers are the client's to sign
+    let voucher = Voucher {
+        escrow_id,
+        amount: 2_500_000,
+        sequence: 1,
+        expires_at: START + 3_600,
+    };
+    let mut vouchers = vec![];
+    for (name, signer) in [
+        ("signed_by_client", &client_key),
+        ("signed_by_server", &server_key),
+    ] {
+        let (public_key, signature, fields) = sign_voucher(&env, signer, &voucher);
+        let result = match escrow.try_verify_voucher(&voucher, &public_key, &signature) {
+            Ok(_) => json!({ "valid": true }),
+            Err(error) => rejection(error),
+        };
+        vouchers.push(vector(name, &fields, result));
+    }
+
+    // Settled in the next ledger
+    env.ledger().with_mut(|ledger| {
+        ledger.sequence_number += 1;
+        ledger.timestamp += LEDGER_CLOSE;
+    });
+    escrow.settle_payment(&Settlement {
+        payment_id,
+        escrow_id,
+        amount: PAYMENT,
+        client: client.clone(),
+    });
+    events.extend(published(&env, &contract, "settle_payment"));
+    let payment = escrow.get_payment(&payment_id);
+    let body = ReceiptBody {
+        version: RECEIPT_VERSION,
+        network_id: hex::encode(network_id),
+        contract_id: FIXTURE_CONTRACT_ID.into(),
+        payment_id,
+        escrow_id,
+        client: account_id(&client_key),
+        server: account_id(&server_key),
+        amount: payment.amount.to_string(),
+        asset: "native".into(),
+        created_at: format_timestamp(payment.timestamp),
+        settled_at: format_timestamp(env.ledger().timestamp()),
+        ledger: env.ledger().sequence(),
+        tx_hash: hex::encode(SETTLEMENT_TX_HASH),
+    };
+    let hash = body.hash();
+    let receipt = SettlementReceipt {
+        body,
+        receipt_hash: hex::encode(hash),
+        signature: hex::encode(server_key.sign(&hash).to_bytes()),
+    };
+
+    let canonical_body = canonical_json(&receipt.body).expect("receipt bodies have a JSON form");
+
+    let with_vectors = |vectors: Vec<Value>| {
+        let mut fixture = scenario.clone();
+        fixture["vectors"] = Value::Array(vectors);
+        fixture
+    };
+    vec![
+        ("authorizations.json", with_vectors(authorizations)),
+        ("vouchers.json", with_vectors(vouchers)),
+        (
+            "receipts.json",
+            json!({
+                "keys": { "server": key(&server_key) },
+                "canonicalBody": canonical_body,
+                "receipt": receipt,
+            }),
+        ),
+        (
+            "events.json",
+            json!({ "contractId": FIXTURE_CONTRACT_ID, "events": events }),
+        ),
+        ("errors.json", json!({ "errors": errors() })),
+    ]
+}
+
+/// Every assigned [`X402Error`] code, with the reason and message it maps to
+fn errors() -> Vec<Value> {
+    (1..=INTERNAL_CODE)
+        .filter_map(X402Error::from_code)
+        .filter(|error| !matches!(error, X402Error::Contract(ContractError::Unknown(_))))
+        .map(|error| {
+            let layer = match error {
+                X402Error::Contract(_) => "contract",
+                X402Error::Transport(_) => "transport",
+                X402Error::Protocol(_) => "protocol",
+                X402Error::Policy(_) => "policy",
+                X402Error::Internal => "internal",
+            };
+            json!({
+                "code": error.code(),
+                "layer": layer,
+                "reason": error.reason(),
+                "message": error.to_string(),
+                "retryable": error.retryable(),
+            })
+        })
+        .collect()
+}
+
+/// Authorization signed by `signer`, with its fixture fields
+fn authorize(
+    env: &Env,
+    signer: &SigningKey,
+    network: &'static str,
+    escrow_id: u64,
+    amount: i128,
+    nonce: u64,
+    expires_at: u64,
+) -> (Authorization, BytesN<32>, BytesN<64>, Value) {
+    let decimal = amount.to_string();
+    let message = EscrowAuthorization {
+        domain: SigningDomain {
+            network_id: env.ledger().network_id().to_array(),
+            contract_id: FIXTURE_CONTRACT_ID,
+        },
+        network,
+        escrow_id,
+        amount: &decimal,
+        nonce,
+        expires_at,
+    };
+    let mut encoded = vec![];
+    message.encode(&mut encoded);
+    let hash = message.signing_hash();
+    let (public_key, signature) = sign(env, signer, hash);
+    let fields = json!({
+        "authorization": {
+            "escrowId": escrow_id,
+            "network": network,
+            "amount": decimal,
+            "nonce": nonce,
+            "expiresAt": expires_at,
+        },
+        "message": hex::encode(encoded),
+        "signingHash": hex::encode(hash),
+        "publicKey": hex::encode(public_key.to_array()),
+        "signature": hex::encode(signature.to_array()),
+    });
+    let authorization = Authorization {
+        escrow_id,
+        network: SorobanString::from_str(env, network),
+        amount,
+        nonce,
+        expires_at,
+    };
+    (authorization, public_key, signature, fields)
+}
+
+/// Signature of `voucher` by `signer`, with its fixture fields
+fn sign_voucher(
+    env: &Env,
+    signer: &SigningKey,
+    voucher: &Voucher,
+) -> (BytesN<32>, BytesN<64>, Value) {
+    let message = EscrowVoucher {
+        domain: SigningDomain {
+            network_id: env.ledger().network_id().to_array(),
+            contract_id: FIXTURE_CONTRACT_ID,
+        },
+        escrow_id: voucher.escrow_id,
+        amount: voucher.amount,
+        sequence: voucher.sequence,
+        expires_at: voucher.expires_at,
+    };
+    let mut encoded = vec![];
+    message.encode(&mut encoded);
+    let hash = message.signing_hash();
+    let (public_key, signature) = sign(env, signer, hash);
+    let fields = json!({
+        "voucher": {
+            "escrowId": voucher.escrow_id,
+            "amount": voucher.amount.to_string(),
+            "sequence": voucher.sequence,
+            "expiresAt": voucher.expires_at,
+        },
+        "message": hex::encode(encoded),
+        "signingHash": hex::encode(hash),
+        "publicKey": hex::encode(public_key.to_array()),
+        "signature": hex::encode(signature.to_array()),
+    });
+    (public_key, signature, fields)
+}
+
+fn sign(env: &Env, signer: &SigningKey, hash: [u8; 32]) -> (BytesN<32>, BytesN<64>) {
+    (
+        BytesN::from_array(env, signer.verifying_key().as_bytes()),
+        BytesN::from_array(env, &signer.sign(&hash).to_bytes()),
+    )
+}
+
+/// Vector named `name`, its fields followed by the contract's answer
+fn vector(name: &str, fields: &Value, result: Value) -> Value {
+    let mut vector = fields.clone();
+    vector["name"] = json!(name);
+    vector["result"] = result;
+    vector
+}
+
+/// Code and reason of the contract error a call failed with
+fn rejection(error: Result<Error, InvokeError>) -> Value {
+    let error = error.expect("calls fail with contract errors");
+    let error = X402Error::Contract(ContractError::from_code(error as u32));
+    json!({ "errorCode": error.code(), "reason": error.reason() })
+}
+
+/// Events `contract` published in the last call, topics and value being
+/// base64 XDR as `getEvents` returns them
+fn published(env: &Env, contract: &Address, function: &str) -> Vec<Value> {
+    env.events()
+        .all()
+        .iter()
+        .filter(|(emitter, _, _)| emitter == contract)
+        .map(|(_, topics, data)| {
+            let topic: Vec<_> = topics.iter().map(|topic| xdr(env, topic)).collect();
+            json!({
+                "function": function,
+                "ledger": env.ledger().sequence(),
+                "topic": topic,
+                "value": xdr(env, data),
+            })
+        })
+        .collect()
+}
+
+fn xdr(env: &Env, value: Val) -> String {
+    ScVal::try_from_val(env, &value)
+        .expect("contract values convert to XDR")
+        .to_xdr_base64(Limits::none())
+        .expect("contract values encode")
+}
+
+/// Seed, public key, and account of a fixture key
+fn key(signer: &SigningKey) -> Value {
+    json!({
+        "seed": hex::encode(signer.to_bytes()),
+        "publicKey": hex::encode(signer.verifying_key().as_bytes()),
+        "address": account_id(signer),
+    })
+}
+
+/// Account of an ed25519 key (G... format)
+fn account_id(key: &SigningKey) -> String {
+    let strkey = account_strkey(key.verifying_key().as_bytes());
+    String::from_utf8(strkey.to_vec()).expect("strkeys are ASCII")
+}
+
+fn account(env: &Env, key: &SigningKey) -> Address {
+    Address::from_str(env, &account_id(key))
+}
@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use soroban_sdk::{xdr::ScAddress, Address, Env, String as SorobanString};
+use x402_client::{
+    scval,
+    testutils::{EnvTransport, NETWORK_PASSPHRASE},
+    Error as ClientError, EscrowClient, LocalSigner, Rpc, Transport,
+};
+use x402_escrow::X402EscrowContract;
+use x402_facilitator::Facilitator;
+use x402_types::{PaymentRequirements, ESCROW_SCHEME};
+
+use crate::{TestToken, TestTokenClient, Wallet};
+
+/// x402 network id of the test environment
+pub const NETWORK: &str = "stellar-local";
+
+/// Seed of the default client wallet
+pub const CLIENT_SEED: [u8; 32] = [1; 32];
+
+/// Seed of the server key the facilitator settles with
+pub const SERVER_SEED: [u8; 32] = [2; 32];
+
+/// Escrow contract, test token, and facilitator running in-process
+///
+/// Every handle shares one Soroban test `Env` with all auths mocked, so
+/// wallets and the facilitator see each other's transactions immediately.
+pub struct TestKit {
+    transport: SharedTransport,
+    rpc: Rpc,
+    facilitator: Arc<Facilitator>,
+    token: String,
+}
+
+impl TestKit {
+    /// Start a test environment with a facilitator settling for [`SERVER_SEED`]
+    pub fn new() -> Self {
+        Self::with_facilitator(|facilitator| facilitator)
+    }
+
+    /// Start a test environment, configuring the facilitator first
+    ///
+    /// # Arguments
+    /// * `configure` - Adds settings, webhooks, or a queue to the facilitator
+    pub fn with_facilitator(configure: impl FnOnce(Facilitator) -> Facilitator) -> Self {
+        let transport = SharedTransport(Arc::new(EnvTransport::new(|env| {
+            env.register(X402EscrowContract, ())
+        })));
+        let token = transport
+            .0
+            .with_env(|env| scval::format_address(&ScAddress::from(&env.register(TestToken, ()))));
+        let rpc = Rpc::new(transport.clone());
+        let server = escrow_client(&rpc, transport.0.contract_id(), &SERVER_SEED);
+        Self {
+            facilitator: Arc::new(configure(Facilitator::new(server, NETWORK))),
+            transport,
+            rpc,
+            token,
+        }
+    }
+
+    /// In-process facilitator, usable as the verifier of any middleware
+    pub fn facilitator(&self) -> Arc<Facilitator> {
+        self.facilitator.clone()
+    }
+
+    /// Server address payments must be made to (G... format)
+    pub fn server(&self) -> &str {
+        self.facilitator.server()
+    }
+
+    /// Escrow contract address (C... format)
+    pub fn contract_id(&self) -> &str {
+        self.transport.0.contract_id()
+    }
+
+    /// RPC client of the test environment
+    pub fn rpc(&self) -> &Rpc {
+        &self.rpc
+    }
+
+    /// Escrow client signing with the key of `seed`
+    pub fn escrow_client(&self, seed: &[u8; 32]) -> EscrowClient {
+        escrow_client(&self.rpc, self.contract_id(), seed)
+    }
+
+    /// Wallet of the key of `seed`, paying the facilitator's server
+    pub fn wallet(&self, seed: &[u8; 32]) -> Wallet {
+        Wallet::new(self.escrow_client(seed), seed, self.server())
+    }
+
+    /// Escrow payment requirements of up to `max_amount` stroops
+    pub fn requirements(&self, max_amount: i128) -> PaymentRequirements {
+        PaymentRequirements {
+            x402_version: None,
+            scheme: ESCROW_SCHEME.into(),
+            network: NETWORK.into(),
+            max_amount_required: max_amount.to_string(),
+            resource: "https://api.example.com/resource".into(),
+            description: "Test resource".into(),
+            mime_type: "application/json".into(),
+            output_schema: None,
+            pay_to: self.server().into(),
+            asset: None,
+            alternatives: vec![],
+            max_timeout_seconds: 60,
+            extra: None,
+        }
+    }
+
+    /// Contract address of the [`TestToken`] (C... format)
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Mint `amount` of the test token to `to`
+    ///
+    /// Escrow balances are tracked by the escrow contract itself, the token is
+    /// for services that also check holdings.
+    pub fn mint(&self, to: &str, amount: i128) {
+        let (token, to) = (self.token.clone(), to.to_string());
+        self.transport.0.with_env(move |env| {
+            TestTokenClient::new(env, &soroban_address(env, &token))
+                .mint(&soroban_address(env, &to), &amount);
+        });
+    }
+
+    /// Test token balance of `address`
+    pub fn token_balance(&self, address: &str) -> i128 {
+        let (token, holder) = (self.token.clone(), address.to_string());
+        self.transport.0.with_env(move |env| {
+            TestTokenClient::new(env, &soroban_address(env, &token))
+                .balance(&soroban_address(env, &holder))
+        })
+    }
+
+    /// Run a closure against the Soroban test environment
+    pub fn with_env<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&Env) -> R + Send + 'static,
+    {
+        self.transport.0.with_env(f)
+    }
+
+    /// Assert that the payment exists and was settled
+    ///
+    /// # Panics
+    /// * If the payment cannot be read or is not settled
+    pub async fn assert_settled(&self, payment_id: u64) {
+        let client = escrow_client(&self.rpc, self.contract_id(), &SERVER_SEED);
+        match client.get_payment(payment_id).await {
+            Ok(payment) => assert!(payment.settled, "payment {payment_id} is not settled"),
+            Err(e) => panic!("payment {payment_id} cannot be read: {e}"),
+        }
+    }
+}
+
+impl Default for TestKit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Test environment shared by the kit and its RPC clients
+#[derive(Clone)]
+struct SharedTransport(Arc<EnvTransport>);
+
+#[async_trait]
+impl Transport for SharedTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        self.0.request(method, params).await
+    }
+}
+
+fn escrow_client(rpc: &Rpc, contract_id: &str, seed: &[u8; 32]) -> EscrowClient {
+    EscrowClient::new(
+        rpc.clone(),
+        contract_id,
+        NETWORK_PASSPHRASE,
+        LocalSigner::from_bytes(seed),
+    )
+    .expect("valid test contract ID")
+}
+
+fn soroban_address(env: &Env, strkey: &str) -> Address {
+    Address::from_string(&SorobanString::from_str(env, strkey))
+}
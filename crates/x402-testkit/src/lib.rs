@@ -0,0 +1,38 @@
+//! # x402 Test Kit
+//!
+//! Offline fixtures for testing x402-gated services, without a network or a
+//! deployed contract.
+//!
+//! ## Key Features
+//! - [`TestKit`] running the escrow contract in a Soroban test `Env`, with an
+//!   in-process [`Facilitator`](x402_facilitator::Facilitator) settling on it
+//! - [`Wallet`] opening escrows and signing X-PAYMENT headers
+//! - A [`TestToken`] minted to any address
+//! - [`TestKit::assert_settled`] checking a payment on-chain
+//! - A [`Differential`] harness replaying a [`Script`] against two builds of
+//!   the escrow contract, to check an upgrade changes nothing unintended
+//! - [`contract_fixtures`] generating the test vectors checked in to
+//!   `fixtures/`, for implementations in other languages
+//!
+//! ## Fixtures
+//! `fixtures/` holds signed authorizations and vouchers with the contract's
+//! answer to each, a settlement receipt, the events of the scenario, and
+//! every error code. The crate's tests check them against the generator,
+//! regenerate them with `cargo test -p x402-testkit --features gen-fixtures`
+//! after an intended change.
+
+mod diff;
+mod fixtures;
+mod kit;
+mod oracle;
+mod token;
+mod wallet;
+
+pub use diff::*;
+pub use fixtures::*;
+pub use kit::*;
+pub use oracle::*;
+pub use token::*;
+pub use wallet::*;
+
+mod test;
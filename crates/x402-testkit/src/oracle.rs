@@ -0,0 +1,38 @@
+use soroban_sdk::{contract, contractimpl, symbol_short, Env};
+use x402_escrow::{Asset, PriceData};
+
+/// Decimals of [`TestOracle`] prices, those of Reflector feeds
+pub const ORACLE_DECIMALS: u32 = 14;
+
+/// Minimal SEP-40 price feed of the test environment
+///
+/// Quotes every asset at one price, always fresh: `lastprice` is stamped
+/// with the current ledger time and the TWAP equals it.
+#[contract]
+pub struct TestOracle;
+
+#[contractimpl]
+impl TestOracle {
+    /// Quote every asset at `price` USD, with [`ORACLE_DECIMALS`] decimals
+    pub fn set_price(env: Env, price: i128) {
+        env.storage()
+            .instance()
+            .set(&symbol_short!("price"), &price);
+    }
+
+    pub fn decimals(_env: Env) -> u32 {
+        ORACLE_DECIMALS
+    }
+
+    pub fn lastprice(env: Env, _asset: Asset) -> Option<PriceData> {
+        let price = env.storage().instance().get(&symbol_short!("price"))?;
+        Some(PriceData {
+            price,
+            timestamp: env.ledger().timestamp(),
+        })
+    }
+
+    pub fn twap(env: Env, _asset: Asset, _records: u32) -> Option<i128> {
+        env.storage().instance().get(&symbol_short!("price"))
+    }
+}
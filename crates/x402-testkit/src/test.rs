@@ -0,0 +1,295 @@
+#![cfg(test)]
+
+use std::path::Path;
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde_json::Value;
+use soroban_sdk::{testutils::Address as _, Address, Symbol};
+use x402_client::SettlementReceipt;
+use x402_errors::X402Error;
+use x402_escrow::{Asset, X402EscrowContract, X402EscrowContractClient};
+use x402_types::{
+    account_strkey, canonical_json, EscrowAuthorization, EscrowVoucher, SettleRequest, X402_VERSION,
+};
+
+use crate::{
+    contract_fixtures, escrow_script, upgrade_whitelist, Difference, Differential, TestKit,
+    Whitelist, CLIENT_SEED, FIXTURES_DIR, PREVIOUS_ESCROW_WASM_VAR,
+};
+
+#[tokio::test]
+async fn test_payment_is_settled() {
+    let kit = TestKit::new();
+    let wallet = kit.wallet(&CLIENT_SEED);
+    let escrow_id = wallet.open_escrow(1_000_000).await;
+    assert_eq!(wallet.escrow_id(), Some(escrow_id));
+
+    let response = kit
+        .facilitator()
+        .settle(&SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: wallet.payment_header(250_000, 1),
+            payment_requirements: kit.requirements(250_000),
+            settle_amount: None,
+            dry_run: false,
+        })
+        .await;
+    assert!(response.success, "{response:?}");
+    kit.assert_settled(response.payment_id.unwrap()).await;
+    assert_eq!(
+        wallet.client().get_escrow_balance(escrow_id).await.unwrap(),
+        750_000
+    );
+}
+
+#[tokio::test]
+#[should_panic(expected = "payment 0 cannot be read")]
+async fn test_assert_settled_missing_payment() {
+    TestKit::new().assert_settled(0).await;
+}
+
+#[test]
+fn test_mint() {
+    let kit = TestKit::new();
+    let wallet = kit.wallet(&CLIENT_SEED);
+    assert_eq!(kit.token_balance(wallet.address()), 0);
+    kit.mint(wallet.address(), 5_000);
+    kit.mint(wallet.address(), 2_500);
+    assert_eq!(kit.token_balance(wallet.address()), 7_500);
+}
+
+/// Public functions of the escrow contract, each called by [`escrow_script`]
+const ESCROW_FUNCTIONS: [&str; 46] = [
+    "initialize",
+    "open_escrow",
+    "create_payment",
+    "create_payments",
+    "settle_payment",
+    "settle_payments",
+    "deposit",
+    "claim_pending_deposit",
+    "is_deposit_claimed",
+    "record_direct_payment",
+    "client_close_escrow",
+    "server_close_escrow",
+    "get_escrow_balance",
+    "get_escrow",
+    "get_payment",
+    "get_payments",
+    "get_direct_payments",
+    "export_escrows",
+    "find_escrow",
+    "get_escrow_index",
+    "compact_escrow_index",
+    "verify_authorization",
+    "verify_voucher",
+    "verify_channel_state",
+    "set_price_oracle",
+    "set_price_limits",
+    "get_price_oracle",
+    "set_usd_price",
+    "quote_usd",
+    "create_payment_usd",
+    "set_terms",
+    "get_terms",
+    "clone_escrow_config",
+    "set_notification_url",
+    "get_notification_url",
+    "open_and_authorize",
+    "get_preauthorized_payment",
+    "open_sponsored_escrow",
+    "set_max_settled_per_hour",
+    "acknowledge_anomaly",
+    "get_spend_limit",
+    "suspend_price",
+    "resume_price",
+    "quote",
+    "split_escrow",
+    "get_escrow_links",
+];
+
+#[test]
+fn test_differential_same_build() {
+    let script = escrow_script();
+    let called: Vec<_> = script.functions().collect();
+    for function in ESCROW_FUNCTIONS {
+        assert!(called.contains(&function), "{function} is not called");
+    }
+
+    let diff = Differential::new(
+        |env| env.register(X402EscrowContract, ()),
+        |env| env.register(X402EscrowContract, ()),
+    );
+    assert_eq!(diff.run(&script, &Whitelist::new()), vec![]);
+}
+
+#[test]
+fn test_differential_reports_changes() {
+    // Against a build whose admin, another one, already set its oracle
+    let differential = || {
+        Differential::new(
+            |env| env.register(X402EscrowContract, ()),
+            |env| {
+                let contract = env.register(X402EscrowContract, ());
+                let asset = Asset::Other(Symbol::new(env, "XLM"));
+                let client = X402EscrowContractClient::new(env, &contract);
+                let admin = Address::generate(env);
+                client.initialize(&admin);
+                client.set_price_oracle(&admin, &contract, &asset);
+                contract
+            },
+        )
+    };
+    let script = escrow_script();
+
+    let differences = differential().run(&script, &Whitelist::new());
+    assert!(matches!(
+        differences[0],
+        Difference::Return {
+            step: 0,
+            function: "get_price_oracle",
+            ..
+        }
+    ));
+    assert!(differences
+        .iter()
+        .any(|difference| matches!(difference, Difference::Storage { .. })));
+
+    let whitelist = Whitelist::new().allow_function("get_price_oracle");
+    let remaining = differential().run(&script, &whitelist);
+    assert!(!remaining.is_empty());
+    assert!(remaining.len() < differences.len());
+    assert!(!remaining.iter().any(|difference| matches!(
+        difference,
+        Difference::Return {
+            function: "get_price_oracle",
+            ..
+        } | Difference::Events {
+            function: "get_price_oracle",
+            ..
+        }
+    )));
+
+    let whitelist = Whitelist::new().allow(|_| true);
+    assert_eq!(differential().run(&script, &whitelist), vec![]);
+}
+
+/// Checks the current build against the previous release's wasm, when
+/// [`PREVIOUS_ESCROW_WASM_VAR`] points at it
+#[test]
+fn test_upgrade_from_previous_release() {
+    let Ok(path) = std::env::var(PREVIOUS_ESCROW_WASM_VAR) else {
+        return;
+    };
+    let previous = std::fs::read(&path).expect("previous release wasm is readable");
+    let diff = Differential::new(
+        |env| env.register(previous.as_slice(), ()),
+        |env| env.register(X402EscrowContract, ()),
+    );
+    let differences = diff.run(&escrow_script(), &upgrade_whitelist());
+    assert!(differences.is_empty(), "{differences:#?}");
+}
+
+/// Regenerates the fixtures, rewriting them with the `gen-fixtures` feature
+#[test]
+fn test_fixtures_match_generator() {
+    for (name, generated) in contract_fixtures() {
+        let path = Path::new(FIXTURES_DIR).join(name);
+        if cfg!(feature = "gen-fixtures") {
+            let json = serde_json::to_string_pretty(&generated).unwrap();
+            std::fs::write(&path, json + "\n").unwrap();
+            continue;
+        }
+        let checked_in: Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            generated, checked_in,
+            "{name} is stale, regenerate it with `cargo test -p x402-testkit --features gen-fixtures`"
+        );
+    }
+}
+
+/// Checks the fixtures as another implementation would, without the
+/// contract
+#[test]
+fn test_fixtures_verify() {
+    let fixture = |name| -> Value {
+        let path = Path::new(FIXTURES_DIR).join(name);
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    };
+    let verify = |vector: &Value, hash: [u8; 32]| {
+        assert_eq!(vector["signingHash"], hex::encode(hash));
+        let public_key: [u8; 32] = hex::decode(vector["publicKey"].as_str().unwrap())
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let signature: [u8; 64] = hex::decode(vector["signature"].as_str().unwrap())
+            .unwrap()
+            .try_into()
+            .unwrap();
+        VerifyingKey::from_bytes(&public_key)
+            .unwrap()
+            .verify_strict(&hash, &Signature::from_bytes(&signature))
+            .unwrap();
+        public_key
+    };
+
+    let authorizations = fixture("authorizations.json");
+    let client = &authorizations["keys"]["client"];
+    for vector in authorizations["vectors"].as_array().unwrap() {
+        let message = hex::decode(vector["message"].as_str().unwrap()).unwrap();
+        let message = EscrowAuthorization::decode(&message).unwrap();
+        assert_eq!(message.domain.contract_id, authorizations["contractId"]);
+        assert_eq!(
+            hex::encode(message.domain.network_id),
+            authorizations["networkId"]
+        );
+        let fields = &vector["authorization"];
+        assert_eq!(fields["network"], message.network);
+        assert_eq!(fields["escrowId"], message.escrow_id);
+        assert_eq!(fields["amount"], message.amount);
+        assert_eq!(fields["nonce"], message.nonce);
+        assert_eq!(fields["expiresAt"], message.expires_at);
+        let public_key = verify(vector, message.signing_hash());
+        // Only payments the client signed are accepted
+        let signed_by_client = hex::encode(public_key) == client["publicKey"];
+        assert!(signed_by_client || vector["result"]["errorCode"].is_u64());
+    }
+
+    let vouchers = fixture("vouchers.json");
+    for vector in vouchers["vectors"].as_array().unwrap() {
+        let message = hex::decode(vector["message"].as_str().unwrap()).unwrap();
+        let message = EscrowVoucher::decode(&message).unwrap();
+        assert_eq!(message.domain.contract_id, vouchers["contractId"]);
+        assert_eq!(
+            hex::encode(message.domain.network_id),
+            vouchers["networkId"]
+        );
+        assert_eq!(vector["voucher"]["amount"], message.amount.to_string());
+        assert_eq!(vector["voucher"]["sequence"], message.sequence);
+        verify(vector, message.signing_hash());
+    }
+
+    let receipts = fixture("receipts.json");
+    let receipt: SettlementReceipt = serde_json::from_value(receipts["receipt"].clone()).unwrap();
+    receipt.verify().unwrap();
+    assert_eq!(
+        receipts["canonicalBody"],
+        canonical_json(&receipt.body).unwrap()
+    );
+    let server: [u8; 32] = hex::decode(receipts["keys"]["server"]["publicKey"].as_str().unwrap())
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(&account_strkey(&server)).unwrap(),
+        receipt.body.server
+    );
+
+    for error in fixture("errors.json")["errors"].as_array().unwrap() {
+        let code = error["code"].as_u64().unwrap() as u32;
+        let known = X402Error::from_code(code).unwrap();
+        assert_eq!(error["reason"], known.reason());
+        assert_eq!(error["retryable"], known.retryable());
+    }
+}
@@ -0,0 +1,44 @@
+use soroban_sdk::{contract, contractimpl, contracttype, Address, Env};
+
+#[contracttype]
+enum DataKey {
+    Balance(Address),
+}
+
+/// Minimal token of the test environment
+///
+/// Anyone may mint, and balances are not tied to Stellar accounts, so test
+/// wallets need no trustline. `balance` and `transfer` follow the token
+/// interface, so `soroban_sdk::token::TokenClient` can call them.
+#[contract]
+pub struct TestToken;
+
+#[contractimpl]
+impl TestToken {
+    /// Credit `amount` to `to`
+    pub fn mint(env: Env, to: Address, amount: i128) {
+        let balance = Self::balance(env.clone(), to.clone());
+        env.storage()
+            .persistent()
+            .set(&DataKey::Balance(to), &(balance + amount));
+    }
+
+    /// Balance of `id`
+    pub fn balance(env: Env, id: Address) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Balance(id))
+            .unwrap_or(0)
+    }
+
+    /// Move `amount` from `from`, who must authorize it, to `to`
+    pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+        from.require_auth();
+        let balance = Self::balance(env.clone(), from.clone());
+        assert!(balance >= amount, "insufficient balance");
+        env.storage()
+            .persistent()
+            .set(&DataKey::Balance(from), &(balance - amount));
+        Self::mint(env, to, amount);
+    }
+}
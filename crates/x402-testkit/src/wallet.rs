@@ -0,0 +1,114 @@
+use std::sync::OnceLock;
+
+use ed25519_dalek::{Signer as _, SigningKey};
+use x402_client::EscrowClient;
+use x402_types::{
+    encode_payment_header, EscrowPayload, PaymentPayload, SchemePayload, SigningDomain,
+    ESCROW_SCHEME, X402_VERSION,
+};
+
+use crate::NETWORK;
+
+/// Test client paying the kit's server from its escrow
+pub struct Wallet {
+    client: EscrowClient,
+    key: SigningKey,
+    address: String,
+    server: String,
+    escrow_id: OnceLock<u64>,
+}
+
+impl Wallet {
+    /// Wallet paying `server` from escrows of `client`, which must sign
+    /// with the key of `seed`
+    ///
+    /// [`TestKit::wallet`](crate::TestKit::wallet) builds wallets of the kit,
+    /// this one those of any network, e.g. a local quickstart.
+    pub fn new(client: EscrowClient, seed: &[u8; 32], server: &str) -> Self {
+        Self {
+            address: client.address(),
+            client,
+            key: SigningKey::from_bytes(seed),
+            server: server.into(),
+            escrow_id: OnceLock::new(),
+        }
+    }
+
+    /// Client address (G... format)
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Escrow client signing with the wallet key
+    pub fn client(&self) -> &EscrowClient {
+        &self.client
+    }
+
+    /// Open the wallet's escrow with the server
+    ///
+    /// # Returns
+    /// * Escrow ID, used by [`Wallet::payment_header`]
+    ///
+    /// # Panics
+    /// * If the escrow cannot be opened, or the wallet already opened one
+    pub async fn open_escrow(&self, deposit: i128) -> u64 {
+        let opened = self
+            .client
+            .open_escrow(&self.address, &self.server, deposit, None)
+            .await
+            .unwrap_or_else(|e| panic!("cannot open escrow: {e}"));
+        self.escrow_id
+            .set(opened.value)
+            .expect("wallet already opened an escrow");
+        opened.value
+    }
+
+    /// Escrow opened by [`Wallet::open_escrow`]
+    pub fn escrow_id(&self) -> Option<u64> {
+        self.escrow_id.get().copied()
+    }
+
+    /// Authorization of `amount` signed with the wallet key
+    ///
+    /// # Arguments
+    /// * `escrow_id` - Escrow charged
+    /// * `amount` - Amount authorized, in stroops
+    /// * `nonce` - Nonce, unique per escrow
+    pub fn payload(&self, escrow_id: u64, amount: i128, nonce: u64) -> EscrowPayload {
+        let mut payload = EscrowPayload {
+            escrow_id,
+            client: self.address.clone(),
+            amount: amount.to_string(),
+            nonce,
+            expires_at: u64::MAX,
+            signature: String::new(),
+            deposit_authorization: None,
+            payment_id: None,
+            direct_authorization: None,
+        };
+        let contract_id = self.client.contract_id();
+        let domain = SigningDomain {
+            network_id: self.client.network_id(),
+            contract_id: &contract_id,
+        };
+        let signature = self.key.sign(&payload.signing_hash(NETWORK, domain));
+        payload.signature = hex::encode(signature.to_bytes());
+        payload
+    }
+
+    /// X-PAYMENT header paying `amount` from the wallet's escrow
+    ///
+    /// # Panics
+    /// * If the wallet has not opened an escrow
+    pub fn payment_header(&self, amount: i128, nonce: u64) -> String {
+        let escrow_id = self.escrow_id().expect("wallet has no escrow");
+        encode_payment_header(&PaymentPayload {
+            x402_version: X402_VERSION,
+            scheme: ESCROW_SCHEME.into(),
+            network: NETWORK.into(),
+            asset: None,
+            payload: SchemePayload::Escrow(self.payload(escrow_id, amount, nonce)),
+            extensions: Default::default(),
+        })
+    }
+}
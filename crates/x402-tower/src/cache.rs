@@ -0,0 +1,138 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+use x402_types::{canonical_json, PaymentRequirements};
+
+/// Key of `extra` carrying the nonce of cached requirements
+pub const NONCE_KEY: &str = "nonce";
+
+/// Route and client requirements are cached for
+type Key = (String, String, String);
+
+struct Entry {
+    requirements: PaymentRequirements,
+    /// Unix time in milliseconds the nonce was issued at
+    issued_at: u64,
+}
+
+/// Payment requirements cached per route and client for a short time
+///
+/// Cached requirements carry a nonce in `extra`, an HMAC of the
+/// requirements, the client, and the time it was issued at. A nonce is
+/// verifiable without the cache until the time-to-live elapsed, and only
+/// against the same requirements, so price changes invalidate it.
+pub(crate) struct RequirementsCache {
+    ttl: Duration,
+    secret: Vec<u8>,
+    entries: Mutex<HashMap<Key, Entry>>,
+}
+
+impl RequirementsCache {
+    pub fn new(ttl: Duration, secret: Vec<u8>) -> Self {
+        Self {
+            ttl,
+            secret,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Requirements of a route for a client, built by `build` and stamped
+    /// with a fresh nonce unless cached requirements are still fresh
+    pub fn get_or_insert(
+        &self,
+        method: &str,
+        resource: &str,
+        client: &str,
+        build: impl FnOnce() -> PaymentRequirements,
+    ) -> PaymentRequirements {
+        let now = now_millis();
+        let ttl = self.ttl.as_millis() as u64;
+        let key = (method.into(), resource.into(), client.into());
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get(&key) {
+            if now.saturating_sub(entry.issued_at) < ttl {
+                return entry.requirements.clone();
+            }
+        }
+        entries.retain(|_, entry| now.saturating_sub(entry.issued_at) < ttl);
+
+        let mut requirements = build();
+        let nonce = self.nonce(&requirements, client, now);
+        let extra = match &mut requirements.extra {
+            Some(Value::Object(extra)) => extra,
+            extra => extra
+                .insert(Value::Object(Map::new()))
+                .as_object_mut()
+                .unwrap(),
+        };
+        extra.insert(NONCE_KEY.into(), nonce.into());
+        entries.insert(
+            key,
+            Entry {
+                requirements: requirements.clone(),
+                issued_at: now,
+            },
+        );
+        requirements
+    }
+
+    /// Drop every cached entry
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Whether `nonce` was issued to `client` for `requirements`, whatever
+    /// their own nonce, less than the time-to-live ago
+    pub fn verify(&self, nonce: &str, requirements: &PaymentRequirements, client: &str) -> bool {
+        let Some(issued_at) = nonce
+            .split_once('-')
+            .and_then(|(issued_at, _)| issued_at.parse::<u64>().ok())
+        else {
+            return false;
+        };
+        let age = now_millis().saturating_sub(issued_at);
+        age < self.ttl.as_millis() as u64
+            && self.nonce(&without_nonce(requirements), client, issued_at) == nonce
+    }
+
+    /// `<issued at>-<hex HMAC-SHA256>`, the HMAC truncated to 128 bits
+    fn nonce(&self, requirements: &PaymentRequirements, client: &str, issued_at: u64) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("any key length");
+        mac.update(&issued_at.to_be_bytes());
+        mac.update(client.as_bytes());
+        mac.update(&[0]);
+        mac.update(canonical_json(requirements).unwrap_or_default().as_bytes());
+        let digest = mac.finalize().into_bytes();
+        format!("{issued_at}-{}", hex::encode(&digest[..16]))
+    }
+}
+
+/// Nonce of cached requirements
+pub(crate) fn nonce_of(requirements: &PaymentRequirements) -> Option<&str> {
+    requirements.extra.as_ref()?.get(NONCE_KEY)?.as_str()
+}
+
+/// Requirements as built, before a nonce was added
+fn without_nonce(requirements: &PaymentRequirements) -> PaymentRequirements {
+    let mut requirements = requirements.clone();
+    if let Some(Value::Object(extra)) = &mut requirements.extra {
+        extra.remove(NONCE_KEY);
+        if extra.is_empty() {
+            requirements.extra = None;
+        }
+    }
+    requirements
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use x402_facilitator::{verify_authorization, VerifiedPayment, VerifyError};
+use x402_types::{EscrowPayload, SigningDomain};
+
+/// Escrow and client payments are verified locally for
+type Key = (u64, String);
+
+struct Entry {
+    /// When the verifier last accepted a payment and the balance was read
+    synced_at: Instant,
+    /// Credit left until the next sync, in stroops
+    credit: i128,
+    /// Nonces verified, with the Unix time their authorization expires at
+    nonces: HashMap<u64, u64>,
+}
+
+/// Outcome of verifying a payment locally
+pub(crate) enum Local {
+    Verified(VerifiedPayment),
+    Rejected(VerifyError),
+    /// The entry is missing, stale, or out of credit, the verifier must be
+    /// asked
+    Sync,
+}
+
+/// Credit extended to escrow clients between verifications by the verifier
+///
+/// Each time the verifier accepts a payment, the escrow's available balance
+/// is read and the client is extended credit for the payments to follow,
+/// the balance left after the payment, at most `max_credit`. Until the
+/// time-to-live elapsed or the credit ran out, payments of the escrow and
+/// client are verified locally: unexpired, with a nonce not seen since,
+/// signed by the client, and within the credit left, which they decrement.
+///
+/// A drained escrow goes unnoticed for at most one time-to-live, during
+/// which at most `max_credit` is accepted without being covered.
+pub(crate) struct VerificationCache {
+    ttl: Duration,
+    max_credit: i128,
+    entries: Mutex<HashMap<Key, Entry>>,
+}
+
+impl VerificationCache {
+    pub fn new(ttl: Duration, max_credit: i128) -> Self {
+        Self {
+            ttl,
+            max_credit,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Verify a payment of `amount` stroops from the credit of its escrow
+    /// and client, authorized on `network` for the contract of `domain`
+    pub fn verify(
+        &self,
+        payload: &EscrowPayload,
+        amount: i128,
+        network: &str,
+        domain: SigningDomain<'_>,
+    ) -> Local {
+        let key = (payload.escrow_id, payload.client.clone());
+        if !self.is_fresh(&key) {
+            return Local::Sync;
+        }
+        if now() > payload.expires_at {
+            return Local::Rejected(VerifyError::Expired);
+        }
+        // Checked without the lock, the signature being the costly check
+        if let Err(e) = verify_authorization(payload, network, domain) {
+            return Local::Rejected(e);
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let Some(entry) = entries
+            .get_mut(&key)
+            .filter(|entry| entry.synced_at.elapsed() < self.ttl)
+        else {
+            return Local::Sync;
+        };
+        if entry.nonces.contains_key(&payload.nonce) {
+            return Local::Rejected(VerifyError::NonceReplayed);
+        }
+        if entry.credit < amount {
+            return Local::Sync;
+        }
+        entry.credit -= amount;
+        entry.nonces.insert(payload.nonce, payload.expires_at);
+        Local::Verified(VerifiedPayment {
+            escrow_id: payload.escrow_id,
+            client: payload.client.clone(),
+            amount,
+            nonce: payload.nonce,
+            tx_hash: None,
+            context: None,
+        })
+    }
+
+    /// Start a new sync window for the escrow and client of a payment the
+    /// verifier accepted, `balance` being available to them
+    ///
+    /// Nonces verified earlier are kept until their authorization expires.
+    pub fn sync(&self, payment: &VerifiedPayment, expires_at: u64, balance: i128) {
+        let now = now();
+        let key = (payment.escrow_id, payment.client.clone());
+        let mut entries = self.entries.lock().unwrap();
+        let mut nonces = entries
+            .remove(&key)
+            .map(|entry| entry.nonces)
+            .unwrap_or_default();
+        nonces.retain(|_, expires_at| *expires_at >= now);
+        nonces.insert(payment.nonce, expires_at);
+        entries.retain(|_, entry| entry.synced_at.elapsed() < self.ttl);
+        entries.insert(
+            key,
+            Entry {
+                synced_at: Instant::now(),
+                credit: (balance - payment.amount).clamp(0, self.max_credit),
+                nonces,
+            },
+        );
+    }
+
+    /// Forget the escrow and client, whose next payment is verified by the
+    /// verifier
+    pub fn invalidate(&self, escrow_id: u64, client: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .remove(&(escrow_id, client.to_string()));
+    }
+
+    fn is_fresh(&self, key: &Key) -> bool {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(key)
+            .is_some_and(|entry| entry.synced_at.elapsed() < self.ttl)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,274 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use x402_facilitator::VerifiedPayment;
+use x402_types::{PaymentRequirements, PreflightResponse, SettleResponse};
+
+use crate::{Verifier, FACILITATOR_UNAVAILABLE};
+
+/// Default time a facilitator that failed to answer is skipped for
+pub const DEFAULT_FAILOVER_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Reason a settlement is refused when its payment carries no context
+/// signed by this verifier
+pub const INVALID_CONTEXT: &str = "invalid_settlement_context";
+
+struct Member {
+    verifier: Arc<dyn Verifier>,
+    /// Until when the facilitator is skipped, after it failed to answer
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl Member {
+    fn is_healthy(&self) -> bool {
+        self.down_until
+            .lock()
+            .unwrap()
+            .is_none_or(|until| Instant::now() >= until)
+    }
+}
+
+/// Verifies through an ordered list of facilitators, failing over when one
+/// is unavailable
+///
+/// Payments are verified by the first healthy facilitator. One that could
+/// not be reached, timed out, or failed is skipped for a cooldown while the
+/// next ones are asked, then tried again. Invalid payments are rejected by
+/// the facilitator that checked them, without asking the others. When every
+/// facilitator is down, they are all tried anyway.
+///
+/// A verified payment carries a [`context`](VerifiedPayment::context), a
+/// token naming the facilitator that verified it with an HMAC keyed by this
+/// verifier's secret, and is settled through that facilitator only: its
+/// nonce reservation and the idempotency of its settlement stay with it. If
+/// it went down since, the settlement fails rather than being charged
+/// twice. Payments verified on credit, see
+/// [`X402Layer::with_verification_cache`](crate::X402Layer::with_verification_cache),
+/// were verified by none and are settled by the first healthy one.
+pub struct FailoverVerifier {
+    members: Vec<Member>,
+    secret: Vec<u8>,
+    cooldown: Duration,
+}
+
+impl FailoverVerifier {
+    /// Create a verifier signing the contexts of the payments it verifies
+    /// with `secret`, without facilitators yet
+    ///
+    /// Servers sharing the secret settle each other's payments.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            members: Vec::new(),
+            secret: secret.into(),
+            cooldown: DEFAULT_FAILOVER_COOLDOWN,
+        }
+    }
+
+    /// Add a facilitator, tried after the ones added before
+    ///
+    /// # Panics
+    /// * If it settles on another network than the first facilitator
+    pub fn with_facilitator(self, verifier: impl Verifier + 'static) -> Self {
+        self.with_shared_facilitator(Arc::new(verifier))
+    }
+
+    /// Add a facilitator shared with other verifiers, tried after the ones
+    /// added before
+    ///
+    /// # Panics
+    /// * If it settles on another network than the first facilitator
+    pub fn with_shared_facilitator(mut self, verifier: Arc<dyn Verifier>) -> Self {
+        if let Some(first) = self.members.first() {
+            assert_eq!(
+                verifier.network(),
+                first.verifier.network(),
+                "FailoverVerifier facilitators must settle on the same network"
+            );
+        }
+        self.members.push(Member {
+            verifier,
+            down_until: Mutex::new(None),
+        });
+        self
+    }
+
+    /// Set the time a facilitator that failed to answer is skipped for
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Whether each facilitator, in order, is currently tried first
+    pub fn health(&self) -> Vec<bool> {
+        self.members.iter().map(Member::is_healthy).collect()
+    }
+
+    /// Indexes of the facilitators to try, the healthy ones first
+    fn order(&self) -> Vec<usize> {
+        let (healthy, down): (Vec<_>, Vec<_>) =
+            (0..self.members.len()).partition(|&index| self.members[index].is_healthy());
+        healthy.into_iter().chain(down).collect()
+    }
+
+    /// `<index>.<hex HMAC-SHA256>` of the facilitator that verified the
+    /// payment, the HMAC truncated to 128 bits
+    fn context(&self, index: usize, payment: &VerifiedPayment) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("any key length");
+        mac.update(&(index as u64).to_be_bytes());
+        mac.update(payment.correlation_id().as_bytes());
+        let digest = mac.finalize().into_bytes();
+        format!("{index}.{}", hex::encode(&digest[..16]))
+    }
+
+    /// Facilitator named by the context of a payment, if signed for it
+    fn verified_by(&self, payment: &VerifiedPayment) -> Option<usize> {
+        let context = payment.context.as_deref()?;
+        let index = context.split_once('.')?.0.parse().ok()?;
+        (index < self.members.len() && self.context(index, payment) == context).then_some(index)
+    }
+
+    fn refused(&self, reason: &str) -> SettleResponse {
+        SettleResponse {
+            success: false,
+            error: Some(reason.into()),
+            error_code: None,
+            tx_hash: None,
+            network_id: Some(self.network().into()),
+            payment_id: None,
+            ledger: None,
+            finality: None,
+            simulation: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Verifier for FailoverVerifier {
+    fn network(&self) -> &str {
+        self.members
+            .first()
+            .map_or("", |member| member.verifier.network())
+    }
+
+    async fn verify(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Result<VerifiedPayment, String> {
+        for index in self.order() {
+            let member = &self.members[index];
+            match member.verifier.verify(header, requirements).await {
+                Err(reason) if reason == FACILITATOR_UNAVAILABLE => {
+                    tracing::warn!(facilitator = index, "facilitator unavailable, failing over");
+                    *member.down_until.lock().unwrap() = Some(Instant::now() + self.cooldown);
+                }
+                verified => {
+                    *member.down_until.lock().unwrap() = None;
+                    return verified.map(|mut payment| {
+                        payment.context = Some(self.context(index, &payment));
+                        payment
+                    });
+                }
+            }
+        }
+        Err(FACILITATOR_UNAVAILABLE.into())
+    }
+
+    /// Settle through the first healthy facilitator, see
+    /// [`FailoverVerifier::settle_verified`](Verifier::settle_verified)
+    async fn settle(&self, header: &str, requirements: &PaymentRequirements) -> SettleResponse {
+        match self.order().first() {
+            Some(&index) => {
+                self.members[index]
+                    .verifier
+                    .settle(header, requirements)
+                    .await
+            }
+            None => self.refused(FACILITATOR_UNAVAILABLE),
+        }
+    }
+
+    async fn settle_amount(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+        amount: i128,
+    ) -> SettleResponse {
+        match self.order().first() {
+            Some(&index) => {
+                let member = &self.members[index];
+                member
+                    .verifier
+                    .settle_amount(header, requirements, amount)
+                    .await
+            }
+            None => self.refused(FACILITATOR_UNAVAILABLE),
+        }
+    }
+
+    async fn settle_verified(
+        &self,
+        payment: &VerifiedPayment,
+        header: &str,
+        requirements: &PaymentRequirements,
+        amount: Option<i128>,
+    ) -> SettleResponse {
+        // Verified on credit, by no facilitator
+        if payment.context.is_none() {
+            return match amount {
+                Some(amount) => self.settle_amount(header, requirements, amount).await,
+                None => self.settle(header, requirements).await,
+            };
+        }
+        let Some(index) = self.verified_by(payment) else {
+            return self.refused(INVALID_CONTEXT);
+        };
+        let verifier = &self.members[index].verifier;
+        match amount {
+            Some(amount) => verifier.settle_amount(header, requirements, amount).await,
+            None => verifier.settle(header, requirements).await,
+        }
+    }
+
+    fn escrow_domain(&self) -> Option<([u8; 32], String)> {
+        self.members
+            .first()
+            .and_then(|member| member.verifier.escrow_domain())
+    }
+
+    async fn available_balance(&self, escrow_id: u64) -> Option<i128> {
+        for index in self.order() {
+            let balance = self.members[index]
+                .verifier
+                .available_balance(escrow_id)
+                .await;
+            if balance.is_some() {
+                return balance;
+            }
+        }
+        None
+    }
+
+    /// Forecast by the first facilitator able to, the healthy ones first
+    async fn preflight(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Option<PreflightResponse> {
+        for index in self.order() {
+            let verdict = self.members[index]
+                .verifier
+                .preflight(header, requirements)
+                .await;
+            if verdict.is_some() {
+                return verdict;
+            }
+        }
+        None
+    }
+}
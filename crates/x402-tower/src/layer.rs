@@ -0,0 +1,451 @@
+use std::{
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::FutureExt;
+use http::{
+    header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH, RETRY_AFTER},
+    HeaderMap, HeaderValue, Method, Request, Response, StatusCode,
+};
+use tower::{Layer, Service};
+use x402_types::{PaymentRequirements, ESCROW_SCHEME, PAYMENT_HEADER, PAYMENT_RESPONSE_HEADER};
+
+use crate::{
+    paywall::Settings,
+    route::{Route, RoutePattern},
+    Challenge, Charge, Paywall, Price, VerifiedPayment, Verifier, DEFAULT_MAX_TIMEOUT_SECONDS,
+};
+
+/// Header identifying the client requirements are cached for, its first
+/// address
+pub const CLIENT_HEADER: &str = "x-forwarded-for";
+
+/// Layer requiring an x402 payment before the inner service runs
+///
+/// Requests without a valid `X-PAYMENT` header get a `402 Payment Required`
+/// response listing the payment requirements. Valid payments are passed to
+/// the inner service as a [`VerifiedPayment`](x402_facilitator::VerifiedPayment)
+/// request extension and settled once it returns a success status, with the
+/// settlement in the `X-PAYMENT-RESPONSE` header.
+#[derive(Clone)]
+pub struct X402Layer {
+    settings: Settings,
+    verifier: Option<Arc<dyn Verifier>>,
+}
+
+impl X402Layer {
+    /// Create a layer charging `price` for every request
+    ///
+    /// # Arguments
+    /// * `price` - Amount, in stroops
+    /// * `asset` - Asset the amount is denominated in
+    /// * `pay_to` - Server address payments are made to (G... format)
+    pub fn new(price: i128, asset: impl Into<String>, pay_to: impl Into<String>) -> Self {
+        Self {
+            settings: Settings {
+                scheme: ESCROW_SCHEME.into(),
+                price: Price {
+                    amount: price,
+                    description: String::new(),
+                    alternatives: vec![],
+                },
+                routes: vec![],
+                asset: asset.into(),
+                pay_to: pay_to.into(),
+                mime_type: "application/json".into(),
+                max_timeout_seconds: DEFAULT_MAX_TIMEOUT_SECONDS,
+                metered: false,
+                cache: None,
+                credit: None,
+                preflight: None,
+            },
+            verifier: None,
+        }
+    }
+
+    /// Set the verifier checking and settling payments (required)
+    pub fn with_verifier(self, verifier: impl Verifier + 'static) -> Self {
+        self.with_shared_verifier(Arc::new(verifier))
+    }
+
+    /// Set a verifier shared with other layers (required)
+    pub fn with_shared_verifier(mut self, verifier: Arc<dyn Verifier>) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// Also accept `amount` stroops of `asset` for requests at the default
+    /// price, may be called for several assets
+    ///
+    /// 402 responses offer every accepted asset, each as its own entry, and
+    /// payments name the asset they pay in. Routes set with
+    /// [`X402Layer::with_route`] are paid in the primary asset only.
+    pub fn with_asset(mut self, asset: impl Into<String>, amount: i128) -> Self {
+        self.settings
+            .price
+            .alternatives
+            .push((asset.into(), amount));
+        self
+    }
+
+    /// Set the description of the paid resource
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.settings.price.description = description.into();
+        self
+    }
+
+    /// Charge a different price for requests matching `pattern`
+    ///
+    /// Patterns are paths whose segments may match any single segment, with
+    /// `*` or `{name}`, or as the last segment one or more remaining ones,
+    /// with `**` or `{*name}`. A request matching several routes pays the
+    /// price of the most specific: the first segment their patterns differ
+    /// at ranks literals over single-segment wildcards over catch-alls.
+    ///
+    /// # Panics
+    /// * If the pattern is malformed
+    pub fn with_route(self, pattern: &str, amount: i128, description: impl Into<String>) -> Self {
+        self.add_route(None, pattern, amount, description.into())
+    }
+
+    /// Charge a different price for `method` requests matching `pattern`
+    ///
+    /// A method route takes precedence over a route of any method with an
+    /// equally specific pattern, see [`X402Layer::with_route`].
+    ///
+    /// # Panics
+    /// * If the pattern is malformed
+    pub fn with_method_route(
+        self,
+        method: Method,
+        pattern: &str,
+        amount: i128,
+        description: impl Into<String>,
+    ) -> Self {
+        self.add_route(Some(method), pattern, amount, description.into())
+    }
+
+    fn add_route(
+        mut self,
+        method: Option<Method>,
+        pattern: &str,
+        amount: i128,
+        description: String,
+    ) -> Self {
+        let pattern = RoutePattern::parse(pattern).unwrap_or_else(|e| panic!("{e}"));
+        self.settings.routes.push(Route {
+            pattern,
+            method,
+            price: Price {
+                amount,
+                description,
+                alternatives: vec![],
+            },
+        });
+        self
+    }
+
+    /// Set the payment scheme required, "escrow" by default
+    ///
+    /// With "exact", clients pay with a classic Stellar payment carrying the
+    /// memo of the requirements, and send its transaction hash. The verifier
+    /// must accept direct payments.
+    pub fn with_scheme(mut self, scheme: impl Into<String>) -> Self {
+        self.settings.scheme = scheme.into();
+        self
+    }
+
+    /// Price responses once served, for handlers whose cost depends on the
+    /// response, e.g. by the tokens they generate
+    ///
+    /// Prices become the most a request may cost: clients authorize them up
+    /// front, and only the [`Charge`] the handler sets as a response
+    /// extension is settled, capped at the price. A successful response
+    /// without a charge costs the full price. Error statuses and handler
+    /// panics settle zero, consuming the authorization without charging it.
+    /// The charge is reported in the `X-PAYMENT-RESPONSE` header.
+    ///
+    /// Requires the "escrow" scheme.
+    pub fn with_metering(mut self) -> Self {
+        self.settings.metered = true;
+        self
+    }
+
+    /// Set the MIME type of the paid responses
+    pub fn with_mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.settings.mime_type = mime_type.into();
+        self
+    }
+
+    /// Reuse the payment requirements of a route for a client during `ttl`
+    ///
+    /// Cached requirements carry a nonce, an HMAC keyed by `secret` of the
+    /// requirements, the client, and their creation time, which 402
+    /// responses also send as their `ETag`. A request without payment whose
+    /// `If-None-Match` names a nonce issued to its client less than `ttl`
+    /// ago, for the current price, gets `304 Not Modified`: the client may
+    /// reuse the challenge it has. Servers sharing the secret accept each
+    /// other's nonces. Clients are told apart by the first address of
+    /// [`CLIENT_HEADER`], if the proxy in front sets it.
+    ///
+    /// Prices changed with [`Paywall::set_price`] invalidate the cache.
+    pub fn with_requirements_cache(mut self, ttl: Duration, secret: impl Into<Vec<u8>>) -> Self {
+        self.settings.cache = Some((ttl, secret.into()));
+        self
+    }
+
+    /// Verify repeat escrow payments locally, each client being extended up
+    /// to `max_credit` stroops between verifications by the verifier
+    ///
+    /// When the verifier accepts a payment, the escrow's available balance
+    /// is read and its client may spend what the payment leaves of it, at
+    /// most `max_credit`, during `ttl`. Until then, the client's payments
+    /// from the escrow are checked in process: their signature, expiry, a
+    /// nonce not seen before, and the credit left, which they draw on. Once
+    /// the credit ran out or `ttl` elapsed, the next payment goes to the
+    /// verifier again.
+    ///
+    /// A drained escrow, or a nonce used through another server, is thus
+    /// noticed within `ttl`, at most `max_credit` having been served on
+    /// credit meanwhile, or when a payment fails to settle. Requires a
+    /// verifier reporting balances, such as an in-process
+    /// [`Facilitator`](x402_facilitator::Facilitator).
+    pub fn with_verification_cache(mut self, ttl: Duration, max_credit: i128) -> Self {
+        self.settings.credit = Some((ttl, max_credit));
+        self
+    }
+
+    /// Forecast the settlement of payments for requests costing at least
+    /// `threshold` stroops before serving them
+    ///
+    /// The verifier simulates the settlement without settling, see
+    /// [`Verifier::preflight`], and payments forecast to fail are answered
+    /// `402 Payment Required` before verification, their nonce left unused,
+    /// rather than once the handler did the work. Forecasts are not binding,
+    /// so settlement may still fail, and payments the verifier cannot
+    /// forecast are served as usual.
+    pub fn with_preflight(mut self, threshold: i128) -> Self {
+        self.settings.preflight = Some(threshold);
+        self
+    }
+
+    /// Set the time in seconds the server takes to respond
+    pub fn with_max_timeout_seconds(mut self, seconds: u64) -> Self {
+        self.settings.max_timeout_seconds = seconds;
+        self
+    }
+
+    /// Build the gating logic, for adapters to other frameworks
+    ///
+    /// # Panics
+    /// * If no verifier was set
+    /// * If metering is enabled with a scheme other than "escrow"
+    pub fn paywall(&self) -> Paywall {
+        let verifier = self
+            .verifier
+            .clone()
+            .expect("X402Layer requires a verifier, see X402Layer::with_verifier");
+        assert!(
+            !self.settings.metered || self.settings.scheme == ESCROW_SCHEME,
+            "X402Layer::with_metering requires the escrow scheme"
+        );
+        Paywall::new(self.settings.clone(), verifier)
+    }
+}
+
+impl<S> Layer<S> for X402Layer {
+    type Service = X402Service<S>;
+
+    /// # Panics
+    /// * If no verifier was set
+    fn layer(&self, inner: S) -> Self::Service {
+        X402Service {
+            inner,
+            paywall: self.paywall(),
+        }
+    }
+}
+
+/// A paywall is a layer too, its services sharing its prices and cache so
+/// that [`Paywall::set_price`] applies to them
+impl<S> Layer<S> for Paywall {
+    type Service = X402Service<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        X402Service {
+            inner,
+            paywall: self.clone(),
+        }
+    }
+}
+
+/// Service produced by [`X402Layer`]
+#[derive(Clone)]
+pub struct X402Service<S> {
+    inner: S,
+    paywall: Paywall,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for X402Service<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Send,
+    ReqBody: Send + 'static,
+    ResBody: From<String> + Send,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        // Use the service that was polled ready, leave a fresh clone behind
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let paywall = self.paywall.clone();
+
+        Box::pin(async move {
+            let client = client(request.headers());
+            let requirements = paywall.requirements_for_client(
+                request.method().as_str(),
+                &request.uri().to_string(),
+                client.as_deref(),
+            );
+            let header = request
+                .headers()
+                .get(PAYMENT_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from);
+            let if_none_match = request
+                .headers()
+                .get(IF_NONE_MATCH)
+                .and_then(|value| value.to_str().ok());
+            // Without payment, a client may check its challenge is current
+            let etag = match (&header, if_none_match) {
+                (None, Some(if_none_match)) => {
+                    paywall.revalidate(if_none_match, &requirements, client.as_deref())
+                }
+                _ => None,
+            };
+            if let Some(etag) = etag {
+                let mut response = Response::new(ResBody::from(String::new()));
+                *response.status_mut() = StatusCode::NOT_MODIFIED;
+                if let Ok(etag) = etag.parse() {
+                    response.headers_mut().insert(ETAG, etag);
+                }
+                return Ok(response);
+            }
+
+            let payment = match paywall.verify(header.as_deref(), &requirements).await {
+                Ok(payment) => payment,
+                Err(challenge) => return Ok(payment_required(&challenge)),
+            };
+            // The header is present, verification succeeded
+            let header = header.unwrap_or_default();
+            if paywall.is_metered() {
+                request.extensions_mut().insert(payment.clone());
+                let served = AssertUnwindSafe(inner.call(request)).catch_unwind().await;
+                let response = match served {
+                    Ok(Ok(response)) => response,
+                    Ok(Err(e)) => {
+                        let _ = paywall
+                            .settle_charge(&payment, &header, &requirements, 0)
+                            .await;
+                        return Err(e);
+                    }
+                    // Settled as a failed response, charging nothing
+                    Err(_) => {
+                        let mut response = Response::new(ResBody::from(String::new()));
+                        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                        response
+                    }
+                };
+                let settled = settle_charge(&paywall, &payment, &header, &requirements, response);
+                return Ok(settled.await);
+            }
+            request.extensions_mut().insert(payment.clone());
+
+            let mut response = inner.call(request).await?;
+            if !response.status().is_success() {
+                return Ok(response);
+            }
+
+            match paywall.settle(&payment, &header, &requirements).await {
+                Ok(value) => {
+                    // Base64 is always a valid header value
+                    if let Ok(value) = HeaderValue::from_str(&value) {
+                        response
+                            .headers_mut()
+                            .insert(PAYMENT_RESPONSE_HEADER, value);
+                    }
+                    Ok(response)
+                }
+                Err(challenge) => Ok(payment_required(&challenge)),
+            }
+        })
+    }
+}
+
+/// Settle the charge of a metered response, zero for error statuses
+async fn settle_charge<B: From<String>>(
+    paywall: &Paywall,
+    payment: &VerifiedPayment,
+    header: &str,
+    requirements: &PaymentRequirements,
+    mut response: Response<B>,
+) -> Response<B> {
+    let limit = payment.amount;
+    let charge = match response.extensions().get::<Charge>() {
+        _ if !response.status().is_success() => 0,
+        Some(Charge(amount)) => (*amount).clamp(0, limit),
+        None => limit,
+    };
+    match paywall
+        .settle_charge(payment, header, requirements, charge)
+        .await
+    {
+        Ok(value) => {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                response
+                    .headers_mut()
+                    .insert(PAYMENT_RESPONSE_HEADER, value);
+            }
+            response
+        }
+        // Failures of zero charges leave nothing unpaid
+        Err(_) if charge == 0 => response,
+        Err(challenge) => payment_required(&challenge),
+    }
+}
+
+fn payment_required<B: From<String>>(challenge: &Challenge) -> Response<B> {
+    let mut response = Response::new(B::from(challenge.to_json()));
+    *response.status_mut() = StatusCode::PAYMENT_REQUIRED;
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if let Some(etag) = challenge.etag().and_then(|etag| etag.parse().ok()) {
+        // Stored by the client, revalidated before reuse
+        headers.insert(ETAG, etag);
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("private, no-cache"));
+    }
+    if let Some(seconds) = challenge.retry_after() {
+        headers.insert(RETRY_AFTER, HeaderValue::from(seconds));
+    }
+    response
+}
+
+/// Client requirements are cached for, see [`CLIENT_HEADER`]
+fn client(headers: &HeaderMap) -> Option<String> {
+    let forwarded = headers.get(CLIENT_HEADER)?.to_str().ok()?;
+    let client = forwarded.split(',').next()?.trim();
+    (!client.is_empty()).then(|| client.into())
+}
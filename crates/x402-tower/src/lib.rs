@@ -0,0 +1,50 @@
+//! # x402 Tower
+//!
+//! Framework-agnostic middleware gating HTTP services on x402 escrow
+//! payments.
+//!
+//! ## Key Features
+//! - [`X402Layer`] answering unpaid requests with `402 Payment Required`,
+//!   usable with any `tower` based server (axum, hyper, tonic, warp)
+//! - Per-route prices via [`X402Layer::with_route`], with wildcard patterns
+//!   and per-method overrides via [`X402Layer::with_method_route`]
+//! - Quotes in several assets via [`X402Layer::with_asset`], every asset
+//!   offered in the `402` body and payments checked at the price of theirs
+//! - Metered responses charging the [`Charge`] reported by the handler, up
+//!   to the route price, via [`X402Layer::with_metering`]
+//! - Verification through a remote [`HttpFacilitator`] or directly against
+//!   the contract with an in-process [`Facilitator`](x402_facilitator::Facilitator)
+//! - Failover across an ordered list of facilitators with
+//!   [`FailoverVerifier`], each payment settled by the one that verified it
+//! - [`Paywall`] exposing the gating logic to adapters for other frameworks
+//! - Challenges reused per route and client with ETag revalidation via
+//!   [`X402Layer::with_requirements_cache`], and prices changed at runtime
+//!   via [`Paywall::set_price`]
+//! - Repeat payments of a client verified in process, on credit bounded per
+//!   sync with the verifier, via [`X402Layer::with_verification_cache`]
+//! - Every x402 version the middleware speaks listed in the `402` body, so
+//!   clients pay with the highest one they share
+//! - Settlements of expensive requests forecast before serving them, via
+//!   [`X402Layer::with_preflight`], payments bound to fail rejected upfront
+//! - Rejected payments described by a `paymentError` with their stable
+//!   code, the amount to top up an escrow with or the requirements to sign
+//!   again for, and `Retry-After` for transient failures
+
+mod cache;
+mod credit;
+mod failover;
+mod layer;
+mod paywall;
+mod route;
+mod verifier;
+
+pub use cache::NONCE_KEY;
+pub use failover::*;
+pub use layer::*;
+pub use paywall::{
+    Challenge, Charge, Paywall, Price, DEFAULT_MAX_TIMEOUT_SECONDS, DEFAULT_RETRY_AFTER_SECONDS,
+};
+pub use verifier::*;
+pub use x402_facilitator::VerifiedPayment;
+
+mod test;
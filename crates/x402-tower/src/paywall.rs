@@ -0,0 +1,619 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use serde_json::json;
+use x402_errors::{ContractError, ProtocolError, TransportError, X402Error};
+use x402_facilitator::VerifiedPayment;
+use x402_types::{
+    decode_payment_header, encode_payment_response_header, AssetAmount, EscrowPayload,
+    PaymentErrorBody, PaymentPayload, PaymentRequiredResponse, PaymentRequirements,
+    PaymentResponseHeader, SchemePayload, SettleResponse, SigningDomain, ESCROW_SCHEME,
+    EXACT_SCHEME, X402_VERSION, X402_VERSIONS,
+};
+
+use crate::{
+    cache::{self, RequirementsCache},
+    credit::{Local, VerificationCache},
+    route::{self, Route, RoutePattern},
+    Verifier, FACILITATOR_UNAVAILABLE,
+};
+
+/// Default time in seconds the server takes to respond
+pub const DEFAULT_MAX_TIMEOUT_SECONDS: u64 = 60;
+
+/// Time in seconds clients are told to wait before retrying a payment
+/// rejected for a transient reason
+pub const DEFAULT_RETRY_AFTER_SECONDS: u64 = 5;
+
+/// Reason of the challenge answering a request without payment
+const PAYMENT_REQUIRED: &str = "payment_required";
+
+/// Price of a route
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Price {
+    /// Amount, in stroops
+    pub amount: i128,
+    /// Description of the paid resource
+    pub description: String,
+    /// Amounts in other assets accepted instead, in their stroops
+    pub alternatives: Vec<(String, i128)>,
+}
+
+/// Amount a handler charges for its response, set as a response extension
+///
+/// With [`X402Layer::with_metering`](crate::X402Layer::with_metering),
+/// payments authorize up to the route price and only the charge reported by
+/// the handler is settled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Charge(pub i128);
+
+/// Prices and recipient of the paid routes
+#[derive(Clone, Debug)]
+pub(crate) struct Settings {
+    pub scheme: String,
+    pub price: Price,
+    pub routes: Vec<Route>,
+    pub asset: String,
+    pub pay_to: String,
+    pub mime_type: String,
+    pub max_timeout_seconds: u64,
+    pub metered: bool,
+    /// Time-to-live and HMAC key of cached requirements
+    pub cache: Option<(Duration, Vec<u8>)>,
+    /// Time-to-live and most credit per sync window of locally verified
+    /// payments
+    pub credit: Option<(Duration, i128)>,
+    /// Price, in stroops, from which settlements are forecast before
+    /// verification
+    pub preflight: Option<i128>,
+}
+
+/// `402 Payment Required` answer to a request
+#[derive(Clone, Debug, PartialEq)]
+pub struct Challenge {
+    /// Response body
+    pub body: PaymentRequiredResponse,
+}
+
+impl Challenge {
+    /// Challenge offering `requirements`, rejected for `reason`
+    ///
+    /// Each asset the requirements accept is offered as its own entry, and
+    /// every x402 version the middleware speaks is listed. A payment
+    /// rejected for any reason but `payment_required` is described by a
+    /// [`PaymentErrorBody`], telling clients to back off for transient
+    /// reasons.
+    pub fn new(requirements: PaymentRequirements, reason: &str) -> Self {
+        Self {
+            body: PaymentRequiredResponse {
+                x402_version: X402_VERSION,
+                x402_versions: X402_VERSIONS.to_vec(),
+                accepts: requirements.expand(),
+                error: Some(reason.into()),
+                payment_error: (reason != PAYMENT_REQUIRED).then(|| payment_error(reason)),
+            },
+        }
+    }
+
+    /// Seconds the client should wait before paying again, for the
+    /// `Retry-After` header
+    pub fn retry_after(&self) -> Option<u64> {
+        self.body.payment_error.as_ref()?.retry_after
+    }
+
+    /// Response body as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.body).unwrap_or_default()
+    }
+
+    /// ETag of the challenge, the quoted nonce of cached requirements
+    pub fn etag(&self) -> Option<String> {
+        let nonce = cache::nonce_of(self.body.accepts.first()?)?;
+        Some(format!("\"{nonce}\""))
+    }
+}
+
+/// Framework-independent payment gating logic
+///
+/// Framework adapters call [`Paywall::requirements`] for each request, then
+/// [`Paywall::verify`] before the handler and [`Paywall::settle`] with the
+/// payment verified after it returned a success status. When
+/// [metered](Paywall::is_metered), they instead call
+/// [`Paywall::settle_charge`] whatever the outcome.
+///
+/// Clones share their prices, cached requirements, and credit.
+#[derive(Clone)]
+pub struct Paywall {
+    settings: Arc<RwLock<Settings>>,
+    verifier: Arc<dyn Verifier>,
+    cache: Option<Arc<RequirementsCache>>,
+    credit: Option<Arc<VerificationCache>>,
+}
+
+impl Paywall {
+    pub(crate) fn new(settings: Settings, verifier: Arc<dyn Verifier>) -> Self {
+        let cache = settings
+            .cache
+            .clone()
+            .map(|(ttl, secret)| Arc::new(RequirementsCache::new(ttl, secret)));
+        let credit = settings
+            .credit
+            .map(|(ttl, max_credit)| Arc::new(VerificationCache::new(ttl, max_credit)));
+        Self {
+            settings: Arc::new(RwLock::new(settings)),
+            verifier,
+            cache,
+            credit,
+        }
+    }
+
+    /// Payment requirements of a GET request to `resource`
+    ///
+    /// See [`Paywall::requirements_for`].
+    pub fn requirements(&self, resource: &str) -> PaymentRequirements {
+        self.requirements_for("GET", resource)
+    }
+
+    /// Payment requirements of a `method` request to `resource`
+    ///
+    /// The method is taken by name for adapters to frameworks using other
+    /// versions of the `http` crate. The price is that of the most specific route matching the method and
+    /// the path, without the query string, or the default price if none
+    /// does. Requirements of the "exact" scheme carry the hex-encoded
+    /// [payment memo](PaymentRequirements::payment_memo) as `extra.memo`.
+    /// With a requirements cache, they are cached for anonymous clients.
+    pub fn requirements_for(&self, method: &str, resource: &str) -> PaymentRequirements {
+        self.requirements_for_client(method, resource, None)
+    }
+
+    /// Payment requirements of a `method` request to `resource` by `client`
+    ///
+    /// With [`X402Layer::with_requirements_cache`](crate::X402Layer::with_requirements_cache),
+    /// requirements are reused per route and client until their
+    /// time-to-live elapsed, carrying a nonce as `extra.nonce`. Anonymous
+    /// clients share their requirements.
+    pub fn requirements_for_client(
+        &self,
+        method: &str,
+        resource: &str,
+        client: Option<&str>,
+    ) -> PaymentRequirements {
+        match &self.cache {
+            Some(cache) => {
+                cache.get_or_insert(method, resource, client.unwrap_or_default(), || {
+                    self.build_requirements(method, resource)
+                })
+            }
+            None => self.build_requirements(method, resource),
+        }
+    }
+
+    /// ETag named by an `If-None-Match` header whose requirements are still
+    /// valid for `client`, who may reuse its challenge
+    ///
+    /// # Returns
+    /// * None if no ETag is valid, always without a requirements cache
+    pub fn revalidate(
+        &self,
+        if_none_match: &str,
+        requirements: &PaymentRequirements,
+        client: Option<&str>,
+    ) -> Option<String> {
+        let cache = self.cache.as_ref()?;
+        if_none_match
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/").trim_matches('"'))
+            .find(|nonce| cache.verify(nonce, requirements, client.unwrap_or_default()))
+            .map(|nonce| format!("\"{nonce}\""))
+    }
+
+    /// Replace the default price, or the price of the routes added with
+    /// `pattern`, invalidating cached requirements
+    ///
+    /// Applies to the clones of this paywall, and to the services it layers.
+    ///
+    /// # Returns
+    /// * Whether a price was replaced
+    pub fn set_price(&self, pattern: Option<&str>, price: Price) -> bool {
+        let replaced = {
+            let mut settings = self.settings.write().unwrap();
+            match pattern {
+                None => {
+                    settings.price = price;
+                    true
+                }
+                Some(pattern) => {
+                    let Ok(pattern) = RoutePattern::parse(pattern) else {
+                        return false;
+                    };
+                    let mut replaced = false;
+                    for route in settings.routes.iter_mut() {
+                        if route.pattern == pattern {
+                            route.price = price.clone();
+                            replaced = true;
+                        }
+                    }
+                    replaced
+                }
+            }
+        };
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+        replaced
+    }
+
+    fn build_requirements(&self, method: &str, resource: &str) -> PaymentRequirements {
+        let settings = self.settings.read().unwrap();
+        let path = resource.split('?').next().unwrap_or_default();
+        let price = route::select(&settings.routes, method, path)
+            .map_or(&settings.price, |route| &route.price);
+        let mut requirements = PaymentRequirements {
+            x402_version: None,
+            scheme: settings.scheme.clone(),
+            network: self.verifier.network().into(),
+            max_amount_required: price.amount.to_string(),
+            resource: resource.into(),
+            description: price.description.clone(),
+            mime_type: settings.mime_type.clone(),
+            output_schema: None,
+            pay_to: settings.pay_to.clone(),
+            asset: Some(settings.asset.clone()),
+            alternatives: price
+                .alternatives
+                .iter()
+                .map(|(asset, amount)| AssetAmount {
+                    asset: asset.clone(),
+                    max_amount_required: amount.to_string(),
+                })
+                .collect(),
+            max_timeout_seconds: settings.max_timeout_seconds,
+            extra: None,
+        };
+        if requirements.scheme == EXACT_SCHEME {
+            let memo = hex::encode(requirements.payment_memo());
+            requirements.extra = Some(json!({ "memo": memo }));
+        }
+        requirements
+    }
+
+    /// Whether prices are maximums, the handler reporting the [`Charge`]
+    pub fn is_metered(&self) -> bool {
+        self.settings.read().unwrap().metered
+    }
+
+    /// Verify the X-PAYMENT header of a request
+    ///
+    /// A payment in one of the requirements' alternative assets must pay the
+    /// price in that asset. With
+    /// [`X402Layer::with_verification_cache`](crate::X402Layer::with_verification_cache),
+    /// escrow payments are verified locally while their client has credit.
+    /// With [`X402Layer::with_preflight`](crate::X402Layer::with_preflight),
+    /// payments of expensive requests whose settlement is forecast to fail
+    /// are rejected first.
+    ///
+    /// # Errors
+    /// * The challenge to answer with if the header is missing or invalid,
+    ///   pays in an asset not offered, or pays an amount other than the
+    ///   price in `requirements`
+    #[tracing::instrument(
+        name = "x402.verify",
+        skip_all,
+        fields(
+            resource = %requirements.resource,
+            correlation_id = header.and_then(correlation_id),
+        )
+    )]
+    pub async fn verify(
+        &self,
+        header: Option<&str>,
+        requirements: &PaymentRequirements,
+    ) -> Result<VerifiedPayment, Challenge> {
+        let Some(header) = header else {
+            return Err(Challenge::new(requirements.clone(), PAYMENT_REQUIRED));
+        };
+        let paid = paid_requirements(header, requirements)?;
+        if let Some(reason) = self.preflight(header, &paid).await {
+            return Err(self.reject(header, &paid, requirements, &reason).await);
+        }
+        let verified = match &self.credit {
+            Some(credit) => self.verify_on_credit(credit, header, &paid).await,
+            None => self.verifier.verify(header, &paid).await,
+        };
+        let payment = match verified {
+            Ok(payment) => payment,
+            Err(reason) => return Err(self.reject(header, &paid, requirements, &reason).await),
+        };
+        // A payment made for a cheaper route must not unlock this one
+        if payment.amount.to_string() != paid.max_amount_required {
+            let reason = format!(
+                "amount_mismatch: paid {} stroops, {} costs {}",
+                payment.amount, requirements.resource, paid.max_amount_required
+            );
+            return Err(Challenge::new(requirements.clone(), &reason));
+        }
+        tracing::debug!(escrow_id = payment.escrow_id, "payment verified");
+        Ok(payment)
+    }
+
+    /// Reason the settlement of a payment of `paid` is forecast to fail,
+    /// when its price reaches the preflight threshold
+    ///
+    /// Payments the verifier cannot forecast go on to verification.
+    async fn preflight(&self, header: &str, paid: &PaymentRequirements) -> Option<String> {
+        let threshold = self.settings.read().unwrap().preflight?;
+        let price: i128 = paid.max_amount_required.parse().ok()?;
+        if price < threshold {
+            return None;
+        }
+        let verdict = self.verifier.preflight(header, paid).await?;
+        if verdict.ok {
+            return None;
+        }
+        let reason = verdict
+            .invalid_reason
+            .unwrap_or_else(|| "settlement_failed".into());
+        tracing::info!(
+            reason,
+            cached = verdict.cached,
+            "settlement forecast to fail"
+        );
+        Some(reason)
+    }
+
+    /// Verify an escrow payment from its client's credit, or through the
+    /// verifier, extending the client credit for the next ones
+    async fn verify_on_credit(
+        &self,
+        credit: &VerificationCache,
+        header: &str,
+        paid: &PaymentRequirements,
+    ) -> Result<VerifiedPayment, String> {
+        let network = self.verifier.network();
+        // Direct payments, and payments on other networks, are left to the
+        // verifier
+        let payload = match decode_payment_header(header) {
+            Ok(PaymentPayload {
+                scheme,
+                network: paid_on,
+                payload: SchemePayload::Escrow(payload),
+                ..
+            }) if scheme == ESCROW_SCHEME && paid.scheme == ESCROW_SCHEME && paid_on == network => {
+                payload
+            }
+            _ => return self.verifier.verify(header, paid).await,
+        };
+        // Signatures are bound to the verifier's contract, payments of
+        // verifiers that do not name it are left to them
+        let domain = self
+            .verifier
+            .escrow_domain()
+            .filter(|_| payload.amount == paid.max_amount_required);
+        if let Some((network_id, contract_id)) = &domain {
+            let amount = paid.max_amount_required.parse().unwrap_or_default();
+            let domain = SigningDomain {
+                network_id: *network_id,
+                contract_id,
+            };
+            match credit.verify(&payload, amount, network, domain) {
+                Local::Verified(payment) => {
+                    tracing::debug!(escrow_id = payment.escrow_id, "payment verified on credit");
+                    return Ok(payment);
+                }
+                Local::Rejected(e) => return Err(e.reason().into()),
+                Local::Sync => {}
+            }
+        }
+
+        let verified = self.verifier.verify(header, paid).await;
+        let balance = match &verified {
+            Ok(payment) => self.verifier.available_balance(payment.escrow_id).await,
+            Err(_) => None,
+        };
+        match (&verified, balance) {
+            (Ok(payment), Some(balance)) => credit.sync(payment, payload.expires_at, balance),
+            _ => credit.invalidate(payload.escrow_id, &payload.client),
+        }
+        verified
+    }
+
+    /// Challenge answering a payment of `paid` rejected for `reason`
+    ///
+    /// A payment its escrow cannot cover is told the amount to top up the
+    /// escrow with, and an expired or already used authorization the
+    /// requirements to sign a new one for.
+    async fn reject(
+        &self,
+        header: &str,
+        paid: &PaymentRequirements,
+        requirements: &PaymentRequirements,
+        reason: &str,
+    ) -> Challenge {
+        let mut challenge = Challenge::new(requirements.clone(), reason);
+        let Some(hint) = challenge.body.payment_error.as_mut() else {
+            return challenge;
+        };
+        match X402Error::from_code(hint.code) {
+            Some(X402Error::Contract(ContractError::InsufficientBalance)) => {
+                let price: i128 = paid.max_amount_required.parse().unwrap_or_default();
+                let balance = match escrow_payload(header) {
+                    Some(payload) => self.verifier.available_balance(payload.escrow_id).await,
+                    None => None,
+                };
+                let topup = balance
+                    .map(|balance| price - balance)
+                    .filter(|topup| *topup > 0)
+                    .unwrap_or(price);
+                hint.required_topup_amount = Some(topup.to_string());
+                hint.requirements = Some(paid.clone());
+            }
+            Some(
+                X402Error::Protocol(
+                    ProtocolError::Expired
+                    | ProtocolError::NonceUsed
+                    | ProtocolError::NonceReplayed,
+                )
+                | X402Error::Contract(
+                    ContractError::AuthorizationExpired | ContractError::AuthorizationUsed,
+                ),
+            ) => hint.requirements = Some(paid.clone()),
+            _ => {}
+        }
+        challenge
+    }
+
+    /// Stop extending credit to the client of a payment whose settlement
+    /// failed, e.g. as its escrow was drained
+    fn revoke_credit(&self, header: &str) {
+        let (Some(credit), Some(payload)) = (&self.credit, escrow_payload(header)) else {
+            return;
+        };
+        credit.invalidate(payload.escrow_id, &payload.client);
+    }
+
+    /// Settle a payment verified by [`Paywall::verify`]
+    ///
+    /// # Returns
+    /// * The X-PAYMENT-RESPONSE header value
+    ///
+    /// # Errors
+    /// * The challenge to answer with if settlement failed
+    #[tracing::instrument(
+        name = "x402.settle",
+        skip_all,
+        fields(resource = %requirements.resource, correlation_id = correlation_id(header))
+    )]
+    pub async fn settle(
+        &self,
+        payment: &VerifiedPayment,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Result<String, Challenge> {
+        let paid = paid_requirements(header, requirements)?;
+        let settlement = self
+            .verifier
+            .settle_verified(payment, header, &paid, None)
+            .await;
+        self.response_header(settlement, header, &paid, requirements, None)
+            .await
+    }
+
+    /// Settle `amount` stroops of a payment verified by [`Paywall::verify`],
+    /// the charge of a metered response
+    ///
+    /// # Returns
+    /// * The X-PAYMENT-RESPONSE header value, carrying the charge
+    ///
+    /// # Errors
+    /// * The challenge to answer with if settlement failed
+    #[tracing::instrument(
+        name = "x402.settle",
+        skip_all,
+        fields(
+            resource = %requirements.resource,
+            correlation_id = correlation_id(header),
+            charge = %amount,
+        )
+    )]
+    pub async fn settle_charge(
+        &self,
+        payment: &VerifiedPayment,
+        header: &str,
+        requirements: &PaymentRequirements,
+        amount: i128,
+    ) -> Result<String, Challenge> {
+        let paid = paid_requirements(header, requirements)?;
+        let settlement = self
+            .verifier
+            .settle_verified(payment, header, &paid, Some(amount))
+            .await;
+        self.response_header(settlement, header, &paid, requirements, Some(amount))
+            .await
+    }
+
+    /// X-PAYMENT-RESPONSE header value of a settlement
+    ///
+    /// # Errors
+    /// * The challenge to answer with if settlement failed, the client's
+    ///   credit being revoked
+    async fn response_header(
+        &self,
+        settlement: SettleResponse,
+        header: &str,
+        paid: &PaymentRequirements,
+        requirements: &PaymentRequirements,
+        amount: Option<i128>,
+    ) -> Result<String, Challenge> {
+        if !settlement.success {
+            self.revoke_credit(header);
+            let reason = settlement.error.as_deref().unwrap_or("settlement_failed");
+            tracing::warn!(reason, "settlement failed");
+            return Err(self.reject(header, paid, requirements, reason).await);
+        }
+        Ok(encode_payment_response_header(&PaymentResponseHeader {
+            settlement,
+            amount: amount.map(|amount| amount.to_string()),
+        }))
+    }
+}
+
+/// Requirements of the asset an X-PAYMENT header pays in
+///
+/// Headers that do not decode are left to the verifier to reject.
+///
+/// # Errors
+/// * The challenge to answer with if the asset is not offered
+fn paid_requirements(
+    header: &str,
+    requirements: &PaymentRequirements,
+) -> Result<PaymentRequirements, Challenge> {
+    let Ok(payload) = decode_payment_header(header) else {
+        return Ok(requirements.clone());
+    };
+    requirements
+        .for_asset(payload.asset.as_deref())
+        .ok_or_else(|| Challenge::new(requirements.clone(), "invalid_asset"))
+}
+
+/// Escrow payload of an X-PAYMENT header, if it carries one
+fn escrow_payload(header: &str) -> Option<EscrowPayload> {
+    match decode_payment_header(header).ok()?.payload {
+        SchemePayload::Escrow(payload) => Some(payload),
+        _ => None,
+    }
+}
+
+/// Correlation ID of the payment in an X-PAYMENT header, as the facilitator
+/// derives it
+fn correlation_id(header: &str) -> Option<String> {
+    match decode_payment_header(header).ok()?.payload {
+        SchemePayload::Escrow(payload) => Some(payload.correlation_id()),
+        SchemePayload::TransactionHash(payload) => Some(payload.tx_hash.to_ascii_lowercase()),
+        SchemePayload::Transaction(_) => None,
+    }
+}
+
+/// Description of a payment rejected for `reason`, a snake_case label
+/// optionally followed by `: <detail>`
+///
+/// Reasons of no [`X402Error`] are described as internal errors.
+fn payment_error(reason: &str) -> PaymentErrorBody {
+    let (label, detail) = match reason.split_once(':') {
+        Some((label, detail)) => (label, Some(detail.trim())),
+        None => (reason, None),
+    };
+    let error = match label {
+        FACILITATOR_UNAVAILABLE => TransportError::Unreachable.into(),
+        "amount_mismatch" => ProtocolError::InvalidAmount.into(),
+        _ => X402Error::from_reason(label).unwrap_or(X402Error::Internal),
+    };
+    PaymentErrorBody {
+        code: error.code(),
+        message: detail.map_or_else(|| error.to_string(), str::to_string),
+        retry_after: error.retryable().then_some(DEFAULT_RETRY_AFTER_SECONDS),
+        required_topup_amount: None,
+        requirements: None,
+    }
+}
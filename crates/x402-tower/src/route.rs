@@ -0,0 +1,120 @@
+use std::cmp::Ordering;
+
+use http::Method;
+
+use crate::Price;
+
+/// Segment of a [`RoutePattern`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    /// Any single segment
+    Param,
+    /// One or more remaining segments
+    CatchAll,
+}
+
+impl Segment {
+    /// Rank of the segment, more specific segments ranking higher
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Literal(_) => 2,
+            Self::Param => 1,
+            Self::CatchAll => 0,
+        }
+    }
+}
+
+/// Path pattern of a priced route
+///
+/// Patterns start with `/`. Each segment is literal, matches any single
+/// segment (`*` or `{name}`), or, last, matches one or more remaining
+/// segments (`**` or `{*name}`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RoutePattern {
+    segments: Vec<Segment>,
+}
+
+impl RoutePattern {
+    /// Parse a pattern
+    ///
+    /// # Errors
+    /// * A description of the problem if the pattern is malformed
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let Some(path) = pattern.strip_prefix('/') else {
+            return Err(format!("route pattern {pattern:?} does not start with /"));
+        };
+        let mut segments = vec![];
+        let mut parts = path.split('/').peekable();
+        while let Some(part) = parts.next() {
+            let segment = match part {
+                "*" => Segment::Param,
+                "**" => Segment::CatchAll,
+                _ if part.starts_with("{*") && part.ends_with('}') => Segment::CatchAll,
+                _ if part.starts_with('{') && part.ends_with('}') => Segment::Param,
+                _ if part.contains(['{', '}', '*']) => {
+                    return Err(format!("invalid segment {part:?} in route {pattern:?}"))
+                }
+                _ => Segment::Literal(part.into()),
+            };
+            if segment == Segment::CatchAll && parts.peek().is_some() {
+                return Err(format!("catch-all is not last in route {pattern:?}"));
+            }
+            segments.push(segment);
+        }
+        Ok(Self { segments })
+    }
+
+    /// Whether the pattern matches `path`, without its query string
+    pub fn matches(&self, path: &str) -> bool {
+        let parts: Vec<&str> = path.strip_prefix('/').unwrap_or(path).split('/').collect();
+        for (index, segment) in self.segments.iter().enumerate() {
+            match (segment, parts.get(index)) {
+                (Segment::CatchAll, Some(_)) => return true,
+                (Segment::Param, Some(_)) => {}
+                (Segment::Literal(literal), Some(part)) if literal == part => {}
+                _ => return false,
+            }
+        }
+        parts.len() == self.segments.len()
+    }
+
+    /// Order of two patterns matching the same path, the more specific
+    /// being greater: the first segment they differ at decides
+    fn specificity(&self, other: &Self) -> Ordering {
+        let ranks =
+            |pattern: &Self| -> Vec<u8> { pattern.segments.iter().map(Segment::rank).collect() };
+        ranks(self).cmp(&ranks(other))
+    }
+}
+
+/// Price of the requests matching a pattern, and a method if set
+#[derive(Clone, Debug)]
+pub(crate) struct Route {
+    pub pattern: RoutePattern,
+    pub method: Option<Method>,
+    pub price: Price,
+}
+
+/// Route pricing a request
+///
+/// Among the matching routes, the most specific pattern wins, then a route
+/// of the request's method over one of any method, then the first added.
+pub(crate) fn select<'a>(routes: &'a [Route], method: &str, path: &str) -> Option<&'a Route> {
+    routes
+        .iter()
+        .filter(|route| route.method.as_ref().is_none_or(|m| m.as_str() == method))
+        .filter(|route| route.pattern.matches(path))
+        .fold(None, |best: Option<&Route>, route| match best {
+            Some(best)
+                if route
+                    .pattern
+                    .specificity(&best.pattern)
+                    .then(route.method.is_some().cmp(&best.method.is_some()))
+                    .is_le() =>
+            {
+                Some(best)
+            }
+            _ => Some(route),
+        })
+}
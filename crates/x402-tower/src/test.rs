@@ -0,0 +1,1088 @@
+#![cfg(test)]
+
+use std::{
+    convert::Infallible,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use http::{
+    header::{IF_NONE_MATCH, RETRY_AFTER},
+    Method, Request, Response, StatusCode,
+};
+use tower::{service_fn, Layer, ServiceExt};
+use tracing_test::traced_test;
+use x402_errors::{ContractError, PolicyError, ProtocolError, TransportError, INTERNAL_CODE};
+use x402_facilitator::{Facilitator, VerifyError};
+use x402_testkit::{TestKit, CLIENT_SEED, NETWORK};
+use x402_types::{
+    decode_payment_header, decode_payment_response_header, encode_payment_header, EscrowPayload,
+    PaymentPayload, PaymentRequiredResponse, PaymentRequirements, SchemePayload, SettleRequest,
+    SettleResponse, ESCROW_SCHEME, EXACT_SCHEME, NATIVE_ASSET, PAYMENT_HEADER,
+    PAYMENT_RESPONSE_HEADER, X402_VERSION, X402_VERSIONS,
+};
+
+use crate::{
+    Charge, FailoverVerifier, Paywall, Price, VerifiedPayment, Verifier, X402Layer, CLIENT_HEADER,
+    FACILITATOR_UNAVAILABLE, INVALID_CONTEXT, NONCE_KEY,
+};
+
+const SERVER: &str = "GSERVER";
+
+/// Accepts the header "valid" or any well-formed header, paying the required
+/// amount, or "paid:N", paying N, unless set to reject payments, and records
+/// settlements
+#[derive(Default)]
+struct MockVerifier {
+    rejection: Option<&'static str>,
+    fail_settlement: bool,
+    settled: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl Verifier for MockVerifier {
+    fn network(&self) -> &str {
+        "stellar-local"
+    }
+
+    async fn verify(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Result<VerifiedPayment, String> {
+        if let Some(reason) = self.rejection {
+            return Err(reason.into());
+        }
+        let amount = match header.strip_prefix("paid:") {
+            Some(amount) => amount.parse().unwrap(),
+            None if header == "valid" || decode_payment_header(header).is_ok() => {
+                requirements.max_amount_required.parse().unwrap()
+            }
+            None => return Err("invalid_payload".into()),
+        };
+        Ok(VerifiedPayment {
+            escrow_id: 1,
+            client: "GCLIENT".into(),
+            amount,
+            nonce: 7,
+            tx_hash: None,
+            context: None,
+        })
+    }
+
+    async fn settle(&self, header: &str, requirements: &PaymentRequirements) -> SettleResponse {
+        if self.fail_settlement {
+            return SettleResponse {
+                success: false,
+                error: Some("insufficient_funds".into()),
+                error_code: None,
+                tx_hash: None,
+                network_id: None,
+                payment_id: None,
+                ledger: None,
+                finality: None,
+                simulation: None,
+            };
+        }
+        self.settled
+            .lock()
+            .unwrap()
+            .push(format!("{header}:{}", requirements.max_amount_required));
+        SettleResponse {
+            success: true,
+            error: None,
+            error_code: None,
+            tx_hash: Some("ab".repeat(32)),
+            network_id: Some(self.network().into()),
+            payment_id: Some(3),
+            ledger: None,
+            finality: None,
+            simulation: None,
+        }
+    }
+
+    async fn settle_amount(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+        amount: i128,
+    ) -> SettleResponse {
+        let mut requirements = requirements.clone();
+        requirements.max_amount_required = amount.to_string();
+        self.settle(header, &requirements).await
+    }
+}
+
+async fn call(
+    verifier: Arc<MockVerifier>,
+    path: &str,
+    header: Option<&str>,
+    status: StatusCode,
+) -> Response<String> {
+    let layer = X402Layer::new(1_000, NATIVE_ASSET, SERVER)
+        .with_route("/premium", 5_000, "Premium")
+        .with_shared_verifier(verifier);
+    let service = layer.layer(service_fn(move |request: Request<String>| async move {
+        let payment = request.extensions().get::<VerifiedPayment>().unwrap();
+        let mut response = Response::new(format!("paid {}", payment.amount));
+        *response.status_mut() = status;
+        Ok::<_, Infallible>(response)
+    }));
+
+    let mut request = Request::get(path);
+    if let Some(header) = header {
+        request = request.header(PAYMENT_HEADER, header);
+    }
+    service
+        .oneshot(request.body(String::new()).unwrap())
+        .await
+        .unwrap()
+}
+
+fn challenge(response: &Response<String>) -> PaymentRequiredResponse {
+    assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    serde_json::from_str(response.body()).unwrap()
+}
+
+#[tokio::test]
+async fn test_missing_header() {
+    let verifier = Arc::new(MockVerifier::default());
+    let response = call(
+        verifier.clone(),
+        "/weather?city=paris",
+        None,
+        StatusCode::OK,
+    )
+    .await;
+
+    let challenge = challenge(&response);
+    assert_eq!(challenge.error.as_deref(), Some("payment_required"));
+    assert_eq!(challenge.x402_versions, X402_VERSIONS);
+    let requirements = &challenge.accepts[0];
+    assert_eq!(requirements.max_amount_required, "1000");
+    assert_eq!(requirements.pay_to, SERVER);
+    assert_eq!(requirements.network, "stellar-local");
+    assert_eq!(requirements.resource, "/weather?city=paris");
+    assert!(verifier.settled.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_invalid_header() {
+    let verifier = Arc::new(MockVerifier::default());
+    let response = call(verifier, "/weather", Some("forged"), StatusCode::OK).await;
+    assert_eq!(
+        challenge(&response).error.as_deref(),
+        Some("invalid_payload")
+    );
+}
+
+#[tokio::test]
+async fn test_paid_request_is_settled() {
+    let verifier = Arc::new(MockVerifier::default());
+    let response = call(verifier.clone(), "/premium", Some("valid"), StatusCode::OK).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body(), "paid 5000");
+    let settlement = response.headers()[PAYMENT_RESPONSE_HEADER]
+        .to_str()
+        .unwrap();
+    let settlement = decode_payment_response_header(settlement)
+        .unwrap()
+        .settlement;
+    assert_eq!(settlement.payment_id, Some(3));
+    assert_eq!(*verifier.settled.lock().unwrap(), ["valid:5000"]);
+}
+
+#[tokio::test]
+async fn test_failed_handler_is_not_settled() {
+    let verifier = Arc::new(MockVerifier::default());
+    let response = call(
+        verifier.clone(),
+        "/weather",
+        Some("valid"),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(!response.headers().contains_key(PAYMENT_RESPONSE_HEADER));
+    assert!(verifier.settled.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_failed_settlement() {
+    let verifier = Arc::new(MockVerifier {
+        fail_settlement: true,
+        ..Default::default()
+    });
+    let response = call(verifier, "/weather", Some("valid"), StatusCode::OK).await;
+    let challenge = challenge(&response);
+    assert_eq!(challenge.error.as_deref(), Some("insufficient_funds"));
+
+    // The balance of the escrow unknown, the whole price is to be topped up
+    let hint = challenge.payment_error.unwrap();
+    assert_eq!(hint.code, ContractError::InsufficientBalance.code());
+    assert_eq!(hint.required_topup_amount.as_deref(), Some("1000"));
+    assert_eq!(hint.requirements.unwrap().max_amount_required, "1000");
+    assert_eq!(hint.retry_after, None);
+}
+
+#[tokio::test]
+async fn test_rejection_hints() {
+    let kit = TestKit::new();
+    let wallet = kit.wallet(&CLIENT_SEED);
+    wallet.open_escrow(1_600).await;
+    let paywall = |price| {
+        X402Layer::new(price, NATIVE_ASSET, kit.server())
+            .with_shared_verifier(kit.facilitator())
+            .paywall()
+    };
+
+    // A reused nonce is answered with the requirements to sign again for
+    let cheap = paywall(500);
+    let requirements = cheap.requirements("/weather");
+    let header = wallet.payment_header(500, 1);
+    let payment = cheap.verify(Some(&header), &requirements).await.unwrap();
+    cheap
+        .settle(&payment, &header, &requirements)
+        .await
+        .unwrap();
+    let replayed = cheap.verify(Some(&header), &requirements).await;
+    let hint = replayed.unwrap_err().body.payment_error.unwrap();
+    assert_eq!(hint.code, ProtocolError::NonceUsed.code());
+    assert_eq!(hint.requirements, Some(requirements));
+    assert_eq!((hint.required_topup_amount, hint.retry_after), (None, None));
+
+    // An escrow short of the price is to be topped up with the difference
+    let expensive = paywall(2_000);
+    let requirements = expensive.requirements("/weather");
+    let header = wallet.payment_header(2_000, 2);
+    let rejected = expensive.verify(Some(&header), &requirements).await;
+    let hint = rejected.unwrap_err().body.payment_error.unwrap();
+    assert_eq!(hint.code, ContractError::InsufficientBalance.code());
+    assert_eq!(hint.required_topup_amount.as_deref(), Some("900"));
+    assert_eq!(hint.requirements, Some(requirements));
+    assert_eq!(hint.retry_after, None);
+}
+
+#[tokio::test]
+async fn test_rejection_hint_per_reason() {
+    for (reason, code, resign, retry_after) in [
+        (
+            "authorization_expired",
+            ProtocolError::Expired.code(),
+            true,
+            None,
+        ),
+        (
+            "resource_suspended",
+            ContractError::ResourceSuspended.code(),
+            false,
+            None,
+        ),
+        (
+            "settlements_paused",
+            ContractError::SettlementsPaused.code(),
+            false,
+            None,
+        ),
+        (
+            FACILITATOR_UNAVAILABLE,
+            TransportError::Unreachable.code(),
+            false,
+            Some(5),
+        ),
+        (
+            "shutting_down",
+            PolicyError::ShuttingDown.code(),
+            false,
+            Some(5),
+        ),
+        ("no_such_reason", INTERNAL_CODE, false, None),
+    ] {
+        let verifier = Arc::new(MockVerifier {
+            rejection: Some(reason),
+            ..Default::default()
+        });
+        let response = call(verifier, "/weather", Some("valid"), StatusCode::OK).await;
+        assert_eq!(
+            response
+                .headers()
+                .get(RETRY_AFTER)
+                .map(|value| value.to_str().unwrap()),
+            retry_after.map(|_| "5"),
+            "{reason}"
+        );
+        let hint = challenge(&response).payment_error.unwrap();
+        assert_eq!(hint.code, code, "{reason}");
+        assert_eq!(hint.requirements.is_some(), resign, "{reason}");
+        assert_eq!(hint.required_topup_amount, None, "{reason}");
+        assert_eq!(hint.retry_after, retry_after, "{reason}");
+    }
+
+    // Payments the server does not describe are rejected without a hint
+    let verifier = Arc::new(MockVerifier::default());
+    let response = call(verifier, "/weather", None, StatusCode::OK).await;
+    assert_eq!(challenge(&response).payment_error, None);
+}
+
+#[tokio::test]
+async fn test_exact_scheme_requirements() {
+    let verifier = Arc::new(MockVerifier::default());
+    let paywall = X402Layer::new(1_000, NATIVE_ASSET, SERVER)
+        .with_scheme(EXACT_SCHEME)
+        .with_shared_verifier(verifier)
+        .paywall();
+
+    let requirements = paywall.requirements("/weather?city=paris");
+    assert_eq!(requirements.scheme, EXACT_SCHEME);
+    let memo = hex::encode(requirements.payment_memo());
+    assert_eq!(
+        requirements.extra,
+        Some(serde_json::json!({ "memo": memo }))
+    );
+
+    // Each resource gets its own memo
+    let other = paywall.requirements("/weather?city=oslo");
+    assert_ne!(other.payment_memo(), requirements.payment_memo());
+}
+
+fn priced_layer(verifier: Arc<MockVerifier>) -> X402Layer {
+    X402Layer::new(1_000, NATIVE_ASSET, SERVER)
+        .with_route("/v1/**", 2_000, "API")
+        .with_route("/v1/chat", 5_000, "Chat")
+        .with_method_route(Method::POST, "/v1/chat", 8_000, "Chat completion")
+        .with_route("/v1/{model}/embeddings", 300, "Embeddings")
+        .with_method_route(Method::DELETE, "/v1/{*rest}", 0, "Deletion")
+        .with_shared_verifier(verifier)
+}
+
+#[test]
+fn test_overlapping_route_patterns() {
+    let paywall = priced_layer(Arc::new(MockVerifier::default())).paywall();
+    let price = |method: &str, resource: &str| {
+        let requirements = paywall.requirements_for(method, resource);
+        (requirements.max_amount_required, requirements.description)
+    };
+
+    // Literal segments beat wildcards, wherever the patterns differ
+    assert_eq!(price("GET", "/v1/chat"), ("5000".into(), "Chat".into()));
+    assert_eq!(
+        price("GET", "/v1/ada/embeddings"),
+        ("300".into(), "Embeddings".into())
+    );
+    assert_eq!(
+        price("GET", "/v1/models/list?page=2"),
+        ("2000".into(), "API".into())
+    );
+    // A catch-all needs at least one segment, other paths pay the default
+    assert_eq!(price("GET", "/v1"), ("1000".into(), String::new()));
+    assert_eq!(price("GET", "/v2/chat"), ("1000".into(), String::new()));
+    assert_eq!(price("GET", "/v1/ada/embeddings/extra").0, "2000");
+}
+
+#[test]
+fn test_method_route_prices() {
+    let paywall = priced_layer(Arc::new(MockVerifier::default())).paywall();
+    let price = |method: &str, resource: &str| {
+        paywall
+            .requirements_for(method, resource)
+            .max_amount_required
+    };
+
+    assert_eq!(price("POST", "/v1/chat"), "8000");
+    assert_eq!(price("PUT", "/v1/chat"), "5000");
+    assert_eq!(paywall.requirements("/v1/chat").max_amount_required, "5000");
+    // Routes of any method apply to every method
+    assert_eq!(price("POST", "/v1/ada/embeddings"), "300");
+    // A method route only beats equally specific patterns
+    assert_eq!(price("DELETE", "/v1/models"), "0");
+    assert_eq!(price("DELETE", "/v1/chat"), "5000");
+}
+
+#[test]
+#[should_panic(expected = "catch-all is not last")]
+fn test_malformed_route_pattern() {
+    let _ = X402Layer::new(1_000, NATIVE_ASSET, SERVER).with_route("/v1/**/chat", 5_000, "Chat");
+}
+
+#[tokio::test]
+async fn test_mismatched_amount_is_rejected() {
+    let verifier = Arc::new(MockVerifier::default());
+    let service = priced_layer(verifier.clone()).layer(service_fn(|_: Request<String>| async {
+        Ok::<_, Infallible>(Response::new(String::from("completion")))
+    }));
+    let request = |header: &str| {
+        Request::post("/v1/chat")
+            .header(PAYMENT_HEADER, header)
+            .body(String::new())
+            .unwrap()
+    };
+
+    // A payment for the GET price does not unlock the POST route
+    let response = service.clone().oneshot(request("paid:5000")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+    let body = challenge(&response);
+    assert_eq!(
+        body.error.as_deref(),
+        Some("amount_mismatch: paid 5000 stroops, /v1/chat costs 8000")
+    );
+    assert_eq!(body.accepts[0].max_amount_required, "8000");
+    assert!(verifier.settled.lock().unwrap().is_empty());
+
+    let response = service.oneshot(request("paid:8000")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(*verifier.settled.lock().unwrap(), ["paid:8000:8000"]);
+}
+
+/// Metered handler charging the `cost` query parameter, failing with
+/// `cost=error` and panicking with `cost=panic`
+async fn metered(verifier: Arc<MockVerifier>, query: &str) -> Response<String> {
+    let layer = X402Layer::new(1_000, NATIVE_ASSET, SERVER)
+        .with_metering()
+        .with_shared_verifier(verifier);
+    let service = layer.layer(service_fn(|request: Request<String>| async move {
+        let query = request.uri().query().unwrap_or_default().to_string();
+        let mut response = Response::new(String::from("completion"));
+        match query.strip_prefix("cost=") {
+            Some("error") => *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR,
+            Some("panic") => panic!("generation failed"),
+            Some(cost) => {
+                response
+                    .extensions_mut()
+                    .insert(Charge(cost.parse().unwrap()));
+            }
+            None => {}
+        }
+        Ok::<_, Infallible>(response)
+    }));
+    let request = Request::get(format!("/generate?{query}"))
+        .header(PAYMENT_HEADER, "valid")
+        .body(String::new())
+        .unwrap();
+    service.oneshot(request).await.unwrap()
+}
+
+fn charged(response: &Response<String>) -> Option<String> {
+    let header = response.headers().get(PAYMENT_RESPONSE_HEADER)?;
+    decode_payment_response_header(header.to_str().unwrap())
+        .unwrap()
+        .amount
+}
+
+#[tokio::test]
+async fn test_metered_charges() {
+    let verifier = Arc::new(MockVerifier::default());
+
+    let under = metered(verifier.clone(), "cost=250").await;
+    assert_eq!(under.status(), StatusCode::OK);
+    assert_eq!(charged(&under).as_deref(), Some("250"));
+
+    let exact = metered(verifier.clone(), "cost=1000").await;
+    assert_eq!(charged(&exact).as_deref(), Some("1000"));
+
+    // Charges are capped at the authorized price
+    let over = metered(verifier.clone(), "cost=5000").await;
+    assert_eq!(charged(&over).as_deref(), Some("1000"));
+
+    // A handler not reporting its cost charges the full price
+    let unreported = metered(verifier.clone(), "").await;
+    assert_eq!(charged(&unreported).as_deref(), Some("1000"));
+
+    assert_eq!(
+        *verifier.settled.lock().unwrap(),
+        ["valid:250", "valid:1000", "valid:1000", "valid:1000"]
+    );
+}
+
+#[tokio::test]
+async fn test_metered_failures_charge_zero() {
+    let verifier = Arc::new(MockVerifier::default());
+
+    let failed = metered(verifier.clone(), "cost=error").await;
+    assert_eq!(failed.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(charged(&failed).as_deref(), Some("0"));
+
+    let panicked = metered(verifier.clone(), "cost=panic").await;
+    assert_eq!(panicked.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(charged(&panicked).as_deref(), Some("0"));
+
+    assert_eq!(*verifier.settled.lock().unwrap(), ["valid:0", "valid:0"]);
+}
+
+#[tokio::test]
+#[traced_test]
+async fn test_spans_carry_correlation_id() {
+    let verifier = Arc::new(MockVerifier::default());
+    let payload = EscrowPayload {
+        escrow_id: 1,
+        client: "GCLIENT".into(),
+        amount: "1000".into(),
+        nonce: 7,
+        expires_at: u64::MAX,
+        signature: String::new(),
+        deposit_authorization: None,
+        payment_id: None,
+        direct_authorization: None,
+    };
+    let correlation_id = payload.correlation_id();
+    let header = encode_payment_header(&PaymentPayload {
+        x402_version: X402_VERSION,
+        scheme: ESCROW_SCHEME.into(),
+        network: "stellar-local".into(),
+        asset: None,
+        payload: SchemePayload::Escrow(payload),
+        extensions: Default::default(),
+    });
+
+    let response = call(verifier, "/weather", Some(&header), StatusCode::OK).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(logs_contain("x402.verify"));
+    assert!(logs_contain("x402.settle"));
+    assert!(logs_contain(&format!(
+        "correlation_id=\"{correlation_id}\""
+    )));
+}
+
+#[tokio::test]
+async fn test_alternative_assets() {
+    let verifier = Arc::new(MockVerifier::default());
+    let layer = X402Layer::new(1_000, NATIVE_ASSET, SERVER)
+        .with_asset("CUSDC", 300)
+        .with_shared_verifier(verifier.clone());
+    let service = layer.layer(service_fn(|request: Request<String>| async move {
+        let payment = request.extensions().get::<VerifiedPayment>().unwrap();
+        Ok::<_, Infallible>(Response::new(format!("paid {}", payment.amount)))
+    }));
+    let request = |asset: Option<&str>| {
+        let mut request = Request::get("/weather");
+        if let Some(asset) = asset {
+            let header = encode_payment_header(&PaymentPayload {
+                x402_version: X402_VERSION,
+                scheme: ESCROW_SCHEME.into(),
+                network: "stellar-local".into(),
+                asset: Some(asset.into()),
+                payload: SchemePayload::Escrow(EscrowPayload {
+                    escrow_id: 1,
+                    client: "GCLIENT".into(),
+                    amount: "300".into(),
+                    nonce: 7,
+                    expires_at: u64::MAX,
+                    signature: String::new(),
+                    deposit_authorization: None,
+                    payment_id: None,
+                    direct_authorization: None,
+                }),
+                extensions: Default::default(),
+            });
+            request = request.header(PAYMENT_HEADER, header);
+        }
+        service
+            .clone()
+            .oneshot(request.body(String::new()).unwrap())
+    };
+
+    // Every asset is offered as its own requirements
+    let unpaid = request(None).await.unwrap();
+    let accepts = challenge(&unpaid).accepts;
+    assert_eq!(accepts.len(), 2);
+    assert_eq!(accepts[0].asset.as_deref(), Some(NATIVE_ASSET));
+    assert_eq!(accepts[0].max_amount_required, "1000");
+    assert_eq!(accepts[1].asset.as_deref(), Some("CUSDC"));
+    assert_eq!(accepts[1].max_amount_required, "300");
+    assert!(accepts.iter().all(|offer| offer.alternatives.is_empty()));
+
+    // Payments are verified and settled at the price of their asset
+    let paid = request(Some("CUSDC")).await.unwrap();
+    assert_eq!(paid.status(), StatusCode::OK);
+    assert_eq!(paid.body(), "paid 300");
+    assert_eq!(verifier.settled.lock().unwrap().len(), 1);
+    assert!(verifier.settled.lock().unwrap()[0].ends_with(":300"));
+
+    let other = request(Some("CEURC")).await.unwrap();
+    assert_eq!(challenge(&other).error.as_deref(), Some("invalid_asset"));
+}
+
+fn cached_paywall(ttl: Duration) -> Paywall {
+    X402Layer::new(1_000, NATIVE_ASSET, SERVER)
+        .with_route("/premium", 5_000, "Premium")
+        .with_requirements_cache(ttl, "secret")
+        .with_shared_verifier(Arc::new(MockVerifier::default()))
+        .paywall()
+}
+
+async fn revalidate(
+    paywall: &Paywall,
+    path: &str,
+    client: &str,
+    if_none_match: Option<&str>,
+    payment: Option<&str>,
+) -> Response<String> {
+    let service = paywall.layer(service_fn(|_: Request<String>| async {
+        Ok::<_, Infallible>(Response::new("served".to_string()))
+    }));
+    let mut request = Request::get(path).header(CLIENT_HEADER, format!("{client}, 10.0.0.254"));
+    if let Some(if_none_match) = if_none_match {
+        request = request.header(IF_NONE_MATCH, if_none_match);
+    }
+    if let Some(payment) = payment {
+        request = request.header(PAYMENT_HEADER, payment);
+    }
+    service
+        .oneshot(request.body(String::new()).unwrap())
+        .await
+        .unwrap()
+}
+
+fn etag(response: &Response<String>) -> String {
+    response.headers()["etag"].to_str().unwrap().into()
+}
+
+#[tokio::test]
+async fn test_cached_requirements_are_reused() {
+    let uncached = call(Arc::default(), "/weather", None, StatusCode::OK).await;
+    assert!(!uncached.headers().contains_key("etag"));
+
+    let paywall = cached_paywall(Duration::from_secs(60));
+    let first = revalidate(&paywall, "/weather", "10.0.0.1", None, None).await;
+    let tag = etag(&first);
+    assert_eq!(first.headers()["cache-control"], "private, no-cache");
+    let accepts = challenge(&first).accepts;
+    let nonce = accepts[0].extra.as_ref().unwrap()[NONCE_KEY]
+        .as_str()
+        .unwrap();
+    assert_eq!(tag, format!("\"{nonce}\""));
+
+    // The client gets the same challenge, and may revalidate it
+    let second = revalidate(&paywall, "/weather", "10.0.0.1", None, None).await;
+    assert_eq!(etag(&second), tag);
+    let unchanged = revalidate(&paywall, "/weather", "10.0.0.1", Some(&tag), None).await;
+    assert_eq!(unchanged.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(etag(&unchanged), tag);
+    assert!(unchanged.body().is_empty());
+    let listed = format!("\"stale\", W/{tag}");
+    let unchanged = revalidate(&paywall, "/weather", "10.0.0.1", Some(&listed), None).await;
+    assert_eq!(unchanged.status(), StatusCode::NOT_MODIFIED);
+
+    // Nonces are bound to their client and route, and cannot be forged
+    let other = revalidate(&paywall, "/weather", "10.0.0.2", Some(&tag), None).await;
+    assert_ne!(etag(&other), tag);
+    assert_eq!(challenge(&other).error.as_deref(), Some("payment_required"));
+    let premium = revalidate(&paywall, "/premium", "10.0.0.1", Some(&tag), None).await;
+    assert_eq!(premium.status(), StatusCode::PAYMENT_REQUIRED);
+    let flipped = if nonce.ends_with('0') { '1' } else { '0' };
+    let forged = format!("\"{}{flipped}\"", &nonce[..nonce.len() - 1]);
+    let forged = revalidate(&paywall, "/weather", "10.0.0.1", Some(&forged), None).await;
+    assert_eq!(forged.status(), StatusCode::PAYMENT_REQUIRED);
+
+    // A server sharing the secret honors the nonce
+    let replica = cached_paywall(Duration::from_secs(60));
+    let unchanged = revalidate(&replica, "/weather", "10.0.0.1", Some(&tag), None).await;
+    assert_eq!(unchanged.status(), StatusCode::NOT_MODIFIED);
+
+    // Payments are not answered from the cache
+    let paid = revalidate(&paywall, "/weather", "10.0.0.1", Some(&tag), Some("valid")).await;
+    assert_eq!(paid.status(), StatusCode::OK);
+    assert_eq!(paid.body(), "served");
+}
+
+#[tokio::test]
+async fn test_cached_requirements_expire() {
+    let paywall = cached_paywall(Duration::from_millis(100));
+    let first = revalidate(&paywall, "/weather", "10.0.0.1", None, None).await;
+    let tag = etag(&first);
+    tokio::time::sleep(Duration::from_millis(150)).await;
+
+    let expired = revalidate(&paywall, "/weather", "10.0.0.1", Some(&tag), None).await;
+    assert_eq!(expired.status(), StatusCode::PAYMENT_REQUIRED);
+    assert_ne!(etag(&expired), tag);
+}
+
+#[tokio::test]
+async fn test_price_change_invalidates_cached_requirements() {
+    let paywall = cached_paywall(Duration::from_secs(60));
+    let weather = revalidate(&paywall, "/weather", "10.0.0.1", None, None).await;
+    let premium = revalidate(&paywall, "/premium", "10.0.0.1", None, None).await;
+
+    // A new default price, accepting another asset
+    let price = Price {
+        amount: 2_000,
+        description: "Weather".into(),
+        alternatives: vec![("CUSDC".into(), 600)],
+    };
+    assert!(paywall.set_price(None, price));
+    let repriced = revalidate(
+        &paywall,
+        "/weather",
+        "10.0.0.1",
+        Some(&etag(&weather)),
+        None,
+    )
+    .await;
+    let accepts = challenge(&repriced).accepts;
+    assert_eq!(accepts.len(), 2);
+    assert_eq!(accepts[0].max_amount_required, "2000");
+    assert_eq!(accepts[1].max_amount_required, "600");
+    assert_ne!(etag(&repriced), etag(&weather));
+
+    // The old nonce stays invalid once the new requirements are cached,
+    // while one of an unchanged route remains valid elsewhere
+    let stale = revalidate(
+        &paywall,
+        "/weather",
+        "10.0.0.1",
+        Some(&etag(&weather)),
+        None,
+    )
+    .await;
+    assert_eq!(stale.status(), StatusCode::PAYMENT_REQUIRED);
+    let unchanged = revalidate(
+        &paywall,
+        "/premium",
+        "10.0.0.1",
+        Some(&etag(&premium)),
+        None,
+    )
+    .await;
+    assert_eq!(unchanged.status(), StatusCode::NOT_MODIFIED);
+
+    let price = Price {
+        amount: 9_000,
+        description: "Premium".into(),
+        alternatives: vec![],
+    };
+    assert!(!paywall.set_price(Some("/missing"), price.clone()));
+    assert!(paywall.set_price(Some("/premium"), price));
+    let repriced = revalidate(
+        &paywall,
+        "/premium",
+        "10.0.0.1",
+        Some(&etag(&premium)),
+        None,
+    )
+    .await;
+    assert_eq!(challenge(&repriced).accepts[0].max_amount_required, "9000");
+}
+
+/// In-process facilitator counting the payments it verifies
+struct CountingVerifier {
+    facilitator: Arc<Facilitator>,
+    verified: AtomicUsize,
+}
+
+#[async_trait]
+impl Verifier for CountingVerifier {
+    fn network(&self) -> &str {
+        self.facilitator.network()
+    }
+
+    async fn verify(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Result<VerifiedPayment, String> {
+        self.verified.fetch_add(1, Ordering::SeqCst);
+        Verifier::verify(self.facilitator.as_ref(), header, requirements).await
+    }
+
+    async fn settle(&self, header: &str, requirements: &PaymentRequirements) -> SettleResponse {
+        Verifier::settle(self.facilitator.as_ref(), header, requirements).await
+    }
+
+    async fn available_balance(&self, escrow_id: u64) -> Option<i128> {
+        self.facilitator.available_balance(escrow_id).await.ok()
+    }
+
+    fn escrow_domain(&self) -> Option<([u8; 32], String)> {
+        Verifier::escrow_domain(self.facilitator.as_ref())
+    }
+}
+
+#[tokio::test]
+async fn test_verification_cache() {
+    let kit = TestKit::new();
+    let wallet = kit.wallet(&CLIENT_SEED);
+    let escrow_id = wallet.open_escrow(10_000).await;
+    let verifier = Arc::new(CountingVerifier {
+        facilitator: kit.facilitator(),
+        verified: AtomicUsize::new(0),
+    });
+    let ttl = Duration::from_millis(500);
+    let paywall = X402Layer::new(1_000, NATIVE_ASSET, kit.server())
+        .with_verification_cache(ttl, 3_000)
+        .with_shared_verifier(verifier.clone())
+        .paywall();
+    let requirements = paywall.requirements("/weather");
+    let verify = |header: String| {
+        let paywall = paywall.clone();
+        let requirements = requirements.clone();
+        async move {
+            paywall
+                .verify(Some(&header), &requirements)
+                .await
+                .map_err(|challenge| challenge.body.error.unwrap())
+        }
+    };
+    let verified = || verifier.verified.load(Ordering::SeqCst);
+
+    // The first payment is verified by the facilitator, the next three on
+    // the credit it extended
+    for nonce in 1..=4 {
+        let payment = verify(wallet.payment_header(1_000, nonce)).await.unwrap();
+        assert_eq!((payment.escrow_id, payment.nonce), (escrow_id, nonce));
+    }
+    assert_eq!(verified(), 1);
+
+    // Replayed and forged payments are rejected locally
+    let replayed = verify(wallet.payment_header(1_000, 3)).await;
+    assert_eq!(replayed.unwrap_err(), VerifyError::NonceReplayed.reason());
+    let mut forged = wallet.payload(escrow_id, 1_000, 9);
+    forged.signature = "00".repeat(64);
+    let forged = encode_payment_header(&PaymentPayload {
+        x402_version: X402_VERSION,
+        scheme: ESCROW_SCHEME.into(),
+        network: NETWORK.into(),
+        asset: None,
+        payload: SchemePayload::Escrow(forged),
+        extensions: Default::default(),
+    });
+    let forged = verify(forged).await;
+    assert_eq!(forged.unwrap_err(), VerifyError::InvalidSignature.reason());
+    assert_eq!(verified(), 1);
+
+    // Out of credit, the next payment syncs with the facilitator
+    verify(wallet.payment_header(1_000, 5)).await.unwrap();
+    assert_eq!(verified(), 2);
+
+    // The escrow is drained elsewhere, its credit is still spent until the
+    // time-to-live elapsed
+    let drained = kit
+        .facilitator()
+        .settle(&SettleRequest {
+            x402_version: X402_VERSION,
+            payment_header: wallet.payment_header(10_000, 100),
+            payment_requirements: kit.requirements(10_000),
+            settle_amount: None,
+            dry_run: false,
+        })
+        .await;
+    assert!(drained.success, "{:?}", drained.error);
+    verify(wallet.payment_header(1_000, 6)).await.unwrap();
+    assert_eq!(verified(), 2);
+
+    // Within one time-to-live, the drained escrow is detected
+    tokio::time::sleep(ttl).await;
+    let rejected = verify(wallet.payment_header(1_000, 7)).await;
+    assert_eq!(
+        rejected.unwrap_err(),
+        VerifyError::InsufficientBalance.reason()
+    );
+    let rejected = verify(wallet.payment_header(1_000, 8)).await;
+    assert_eq!(
+        rejected.unwrap_err(),
+        VerifyError::InsufficientBalance.reason()
+    );
+    assert_eq!(verified(), 4);
+}
+
+#[tokio::test]
+async fn test_preflight_above_threshold() {
+    let kit =
+        TestKit::with_facilitator(|facilitator| facilitator.with_preflight_ttl(Duration::ZERO));
+    let wallet = kit.wallet(&CLIENT_SEED);
+    let escrow_id = wallet.open_escrow(10_000).await;
+    wallet
+        .client()
+        .set_max_settled_per_hour(escrow_id, Some(500))
+        .await
+        .unwrap();
+    let served = Arc::new(AtomicUsize::new(0));
+    let layer = X402Layer::new(1_000, NATIVE_ASSET, kit.server())
+        .with_route("/report", 5_000, "Report")
+        .with_preflight(2_000)
+        .with_shared_verifier(kit.facilitator());
+    let service = layer.layer(service_fn({
+        let served = served.clone();
+        move |_: Request<String>| {
+            served.fetch_add(1, Ordering::SeqCst);
+            async { Ok::<_, Infallible>(Response::new("paid".to_string())) }
+        }
+    }));
+    let call = |path: &str, header: String| {
+        let request = Request::get(path)
+            .header(PAYMENT_HEADER, header)
+            .body(String::new())
+            .unwrap();
+        service.clone().oneshot(request)
+    };
+
+    // Below the threshold, the handler runs before the spend limit refuses
+    // the settlement
+    let response = call("/weather", wallet.payment_header(1_000, 1))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+    assert_eq!(served.load(Ordering::SeqCst), 1);
+
+    // Above, the forecast turns the payment down before it
+    let report = wallet.payment_header(5_000, 2);
+    let response = call("/report", report.clone()).await.unwrap();
+    let challenge = challenge(&response);
+    assert_eq!(challenge.error.as_deref(), Some("settlements_paused"));
+    let hint = challenge.payment_error.unwrap();
+    assert_eq!(hint.code, ContractError::SettlementsPaused.code());
+    assert_eq!(served.load(Ordering::SeqCst), 1);
+
+    // Its nonce unused, the payment goes through once the limit is lifted
+    wallet
+        .client()
+        .set_max_settled_per_hour(escrow_id, None)
+        .await
+        .unwrap();
+    let response = call("/report", report).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(served.load(Ordering::SeqCst), 2);
+}
+
+/// Mock facilitator that may go down, accepting "nonce:N" headers and
+/// recording the nonces it settles
+#[derive(Default)]
+struct Replica {
+    down: AtomicBool,
+    verified: AtomicUsize,
+    settled: Mutex<Vec<u64>>,
+}
+
+#[async_trait]
+impl Verifier for Replica {
+    fn network(&self) -> &str {
+        "stellar-local"
+    }
+
+    async fn verify(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Result<VerifiedPayment, String> {
+        if self.down.load(Ordering::SeqCst) {
+            return Err(FACILITATOR_UNAVAILABLE.into());
+        }
+        self.verified.fetch_add(1, Ordering::SeqCst);
+        let Some(nonce) = header.strip_prefix("nonce:") else {
+            return Err("invalid_payload".into());
+        };
+        Ok(VerifiedPayment {
+            escrow_id: 1,
+            client: "GCLIENT".into(),
+            amount: requirements.max_amount_required.parse().unwrap(),
+            nonce: nonce.parse().unwrap(),
+            tx_hash: None,
+            context: None,
+        })
+    }
+
+    async fn settle(&self, header: &str, _requirements: &PaymentRequirements) -> SettleResponse {
+        let success = !self.down.load(Ordering::SeqCst);
+        if success {
+            let nonce = header.strip_prefix("nonce:").unwrap().parse().unwrap();
+            self.settled.lock().unwrap().push(nonce);
+        }
+        SettleResponse {
+            success,
+            error: (!success).then(|| FACILITATOR_UNAVAILABLE.into()),
+            error_code: None,
+            tx_hash: success.then(|| "ab".repeat(32)),
+            network_id: Some(self.network().into()),
+            payment_id: None,
+            ledger: None,
+            finality: None,
+            simulation: None,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_failover() {
+    let primary = Arc::new(Replica::default());
+    let secondary = Arc::new(Replica::default());
+    let verifier = Arc::new(
+        FailoverVerifier::new("failover secret")
+            .with_shared_facilitator(primary.clone())
+            .with_shared_facilitator(secondary.clone()),
+    );
+    let paywall = X402Layer::new(1_000, NATIVE_ASSET, SERVER)
+        .with_shared_verifier(verifier.clone())
+        .paywall();
+    let service = paywall.layer(service_fn(|_: Request<String>| async {
+        Ok::<_, Infallible>(Response::new("paid".to_string()))
+    }));
+    let call = |header: &str| {
+        let request = Request::get("/weather")
+            .header(PAYMENT_HEADER, header)
+            .body(String::new())
+            .unwrap();
+        service.clone().oneshot(request)
+    };
+    let settled = |replica: &Replica| replica.settled.lock().unwrap().clone();
+
+    // Invalid payments are rejected by the primary, without failing over
+    let rejected = call("bogus").await.unwrap();
+    assert_eq!(
+        challenge(&rejected).error.as_deref(),
+        Some("invalid_payload")
+    );
+    assert_eq!(secondary.verified.load(Ordering::SeqCst), 0);
+
+    assert_eq!(call("nonce:1").await.unwrap().status(), StatusCode::OK);
+    assert_eq!(settled(&primary), vec![1]);
+
+    // The primary dies mid-session, requests keep being served through the
+    // secondary, which settles the payments it verified
+    primary.down.store(true, Ordering::SeqCst);
+    for nonce in 2..=3 {
+        let response = call(&format!("nonce:{nonce}")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    assert_eq!(verifier.health(), vec![false, true]);
+    assert_eq!(
+        (settled(&primary), settled(&secondary)),
+        (vec![1], vec![2, 3])
+    );
+    assert_eq!(primary.verified.load(Ordering::SeqCst), 2);
+
+    // A payment is only settled by the facilitator that verified it, even
+    // once it is down and the other one is back
+    let requirements = paywall.requirements("/weather");
+    let payment = paywall
+        .verify(Some("nonce:4"), &requirements)
+        .await
+        .unwrap();
+    secondary.down.store(true, Ordering::SeqCst);
+    primary.down.store(false, Ordering::SeqCst);
+    let failed = paywall.settle(&payment, "nonce:4", &requirements).await;
+    assert_eq!(
+        failed.unwrap_err().body.error.as_deref(),
+        Some(FACILITATOR_UNAVAILABLE)
+    );
+    assert_eq!(
+        (settled(&primary), settled(&secondary)),
+        (vec![1], vec![2, 3])
+    );
+
+    // Contexts are signed, payments cannot be rerouted
+    let mut rerouted = payment.clone();
+    rerouted.context = rerouted
+        .context
+        .map(|context| context.replacen('1', "0", 1));
+    let refused = paywall.settle(&rerouted, "nonce:4", &requirements).await;
+    assert_eq!(
+        refused.unwrap_err().body.error.as_deref(),
+        Some(INVALID_CONTEXT)
+    );
+    assert_eq!(settled(&primary), vec![1]);
+}
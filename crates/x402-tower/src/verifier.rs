@@ -0,0 +1,328 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use x402_facilitator::{Facilitator, VerifiedPayment};
+use x402_types::{
+    decode_payment_header, PaymentRequirements, PreflightResponse, SchemePayload, SettleRequest,
+    SettleResponse, StellarAmount, VerifyRequest, VerifyResponse, X402_VERSION,
+};
+
+/// Reason a payment is rejected when the facilitator could not be reached,
+/// timed out, or failed
+pub const FACILITATOR_UNAVAILABLE: &str = "facilitator_unavailable";
+
+/// Verifies and settles payments for the middleware
+#[async_trait]
+pub trait Verifier: Send + Sync {
+    /// x402 network id payments are accepted on
+    fn network(&self) -> &str;
+
+    /// Verify an X-PAYMENT header against the requirements
+    ///
+    /// # Errors
+    /// * The machine-readable reason the payment is invalid
+    async fn verify(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Result<VerifiedPayment, String>;
+
+    /// Settle a verified payment
+    async fn settle(&self, header: &str, requirements: &PaymentRequirements) -> SettleResponse;
+
+    /// Settle `amount` stroops of a verified payment, at most its authorized
+    /// amount
+    ///
+    /// Verifiers that cannot charge part of a payment only settle the full
+    /// required amount.
+    async fn settle_amount(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+        amount: i128,
+    ) -> SettleResponse {
+        if amount.to_string() == requirements.max_amount_required {
+            return self.settle(header, requirements).await;
+        }
+        SettleResponse {
+            success: false,
+            error: Some("partial_settlement_unsupported".into()),
+            error_code: None,
+            tx_hash: None,
+            network_id: Some(self.network().into()),
+            payment_id: None,
+            ledger: None,
+            finality: None,
+            simulation: None,
+        }
+    }
+
+    /// Settle a payment this verifier verified, `amount` stroops of it if
+    /// given
+    ///
+    /// Verifiers routing payments among several facilitators settle it
+    /// through the one named by the payment's
+    /// [`context`](VerifiedPayment::context), others ignore it.
+    async fn settle_verified(
+        &self,
+        _payment: &VerifiedPayment,
+        header: &str,
+        requirements: &PaymentRequirements,
+        amount: Option<i128>,
+    ) -> SettleResponse {
+        match amount {
+            Some(amount) => self.settle_amount(header, requirements, amount).await,
+            None => self.settle(header, requirements).await,
+        }
+    }
+
+    /// Balance of an escrow payments may still draw on, in stroops
+    ///
+    /// Read to extend credit to the escrow's client, see
+    /// [`X402Layer::with_verification_cache`](crate::X402Layer::with_verification_cache).
+    /// Verifiers that cannot read balances return None, and payments they
+    /// verify are never verified locally.
+    async fn available_balance(&self, _escrow_id: u64) -> Option<i128> {
+        None
+    }
+
+    /// Network ID and escrow contract (C... format) authorizations are
+    /// bound to
+    ///
+    /// Needed to check signatures of payments verified on credit. Verifiers
+    /// that do not know them return None, and payments they verify are
+    /// never verified locally.
+    fn escrow_domain(&self) -> Option<([u8; 32], String)> {
+        None
+    }
+
+    /// Forecast whether settling a payment would succeed, without settling
+    /// it or reserving its nonce
+    ///
+    /// Consulted before serving expensive requests, see
+    /// [`X402Layer::with_preflight`](crate::X402Layer::with_preflight). The
+    /// verdict is not binding. Verifiers that cannot forecast settlements,
+    /// or failed to, return None.
+    async fn preflight(
+        &self,
+        _header: &str,
+        _requirements: &PaymentRequirements,
+    ) -> Option<PreflightResponse> {
+        None
+    }
+}
+
+/// Verifies directly against the escrow contract
+#[async_trait]
+impl Verifier for Facilitator {
+    fn network(&self) -> &str {
+        Facilitator::network(self)
+    }
+
+    async fn verify(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Result<VerifiedPayment, String> {
+        self.check(header, requirements)
+            .await
+            .map_err(|e| e.reason().into())
+    }
+
+    async fn settle(&self, header: &str, requirements: &PaymentRequirements) -> SettleResponse {
+        Facilitator::settle(self, &settle_request(header, requirements, None)).await
+    }
+
+    async fn settle_amount(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+        amount: i128,
+    ) -> SettleResponse {
+        let request = settle_request(header, requirements, Some(amount));
+        Facilitator::settle(self, &request).await
+    }
+
+    async fn available_balance(&self, escrow_id: u64) -> Option<i128> {
+        Facilitator::available_balance(self, escrow_id).await.ok()
+    }
+
+    fn escrow_domain(&self) -> Option<([u8; 32], String)> {
+        Some((Facilitator::network_id(self), self.escrow_contract()))
+    }
+
+    async fn preflight(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Option<PreflightResponse> {
+        Some(Facilitator::preflight(self, &verify_request(header, requirements)).await)
+    }
+}
+
+/// Verifies through a remote facilitator's /verify and /settle endpoints,
+/// forecasting settlements through /preflight
+pub struct HttpFacilitator {
+    url: String,
+    network: String,
+    http: reqwest::Client,
+}
+
+impl HttpFacilitator {
+    /// Create a verifier for the facilitator at `url`
+    ///
+    /// # Arguments
+    /// * `url` - Facilitator base URL, without a trailing slash
+    /// * `network` - x402 network id the facilitator settles on
+    pub fn new(url: impl Into<String>, network: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            network: network.into(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Give up on requests to the facilitator after `timeout`, verifications
+    /// failing with [`FACILITATOR_UNAVAILABLE`]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap_or_default();
+        self
+    }
+
+    async fn post<Req: serde::Serialize, Res: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &Req,
+    ) -> Result<Res, reqwest::Error> {
+        let response = self
+            .http
+            .post(format!("{}{path}", self.url))
+            .json(body)
+            .send()
+            .await?;
+        // /verify answers replayed nonces 409, with the reason in the body
+        if response.status() == reqwest::StatusCode::CONFLICT {
+            return response.json().await;
+        }
+        response.error_for_status()?.json().await
+    }
+
+    async fn post_settle(&self, request: &SettleRequest) -> SettleResponse {
+        self.post("/settle", request)
+            .await
+            .unwrap_or_else(|e| SettleResponse {
+                success: false,
+                error: Some(e.to_string()),
+                error_code: None,
+                tx_hash: None,
+                network_id: Some(self.network.clone()),
+                payment_id: None,
+                ledger: None,
+                finality: None,
+                simulation: None,
+            })
+    }
+}
+
+#[async_trait]
+impl Verifier for HttpFacilitator {
+    fn network(&self) -> &str {
+        &self.network
+    }
+
+    async fn verify(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Result<VerifiedPayment, String> {
+        let response: VerifyResponse = self
+            .post("/verify", &verify_request(header, requirements))
+            .await
+            .map_err(|_| FACILITATOR_UNAVAILABLE.to_string())?;
+        if !response.is_valid {
+            return Err(response
+                .invalid_reason
+                .unwrap_or_else(|| "invalid_payment".into()));
+        }
+
+        // The facilitator accepted the header, so it decodes
+        match decode_payment_header(header).map(|payload| payload.payload) {
+            Ok(SchemePayload::Escrow(payload)) => Ok(VerifiedPayment {
+                escrow_id: payload.escrow_id,
+                amount: parse_amount(&payload.amount)?,
+                client: payload.client,
+                nonce: payload.nonce,
+                tx_hash: None,
+                context: None,
+            }),
+            // The facilitator does not report the payer, and checked the
+            // transaction pays at least the amount required
+            Ok(SchemePayload::TransactionHash(payload)) => Ok(VerifiedPayment {
+                escrow_id: 0,
+                client: String::new(),
+                amount: parse_amount(&requirements.max_amount_required)?,
+                nonce: 0,
+                tx_hash: Some(payload.tx_hash.to_ascii_lowercase()),
+                context: None,
+            }),
+            _ => Err("invalid_payload".into()),
+        }
+    }
+
+    async fn settle(&self, header: &str, requirements: &PaymentRequirements) -> SettleResponse {
+        self.post_settle(&settle_request(header, requirements, None))
+            .await
+    }
+
+    async fn settle_amount(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+        amount: i128,
+    ) -> SettleResponse {
+        self.post_settle(&settle_request(header, requirements, Some(amount)))
+            .await
+    }
+
+    async fn preflight(
+        &self,
+        header: &str,
+        requirements: &PaymentRequirements,
+    ) -> Option<PreflightResponse> {
+        self.post("/preflight", &verify_request(header, requirements))
+            .await
+            .ok()
+    }
+}
+
+fn verify_request(header: &str, requirements: &PaymentRequirements) -> VerifyRequest {
+    VerifyRequest {
+        x402_version: X402_VERSION,
+        payment_header: header.into(),
+        payment_requirements: requirements.clone(),
+    }
+}
+
+fn settle_request(
+    header: &str,
+    requirements: &PaymentRequirements,
+    amount: Option<i128>,
+) -> SettleRequest {
+    SettleRequest {
+        x402_version: X402_VERSION,
+        payment_header: header.into(),
+        payment_requirements: requirements.clone(),
+        settle_amount: amount.map(|amount| amount.to_string()),
+        dry_run: false,
+    }
+}
+
+/// Parse a protocol amount, in stroops
+fn parse_amount(amount: &str) -> Result<i128, String> {
+    StellarAmount::from_stroops_str(amount)
+        .map(StellarAmount::stroops)
+        .map_err(|_| "invalid_amount".into())
+}
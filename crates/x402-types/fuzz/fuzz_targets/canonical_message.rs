@@ -0,0 +1,27 @@
+//! Signing messages, as decoded from bytes a client claims to have signed
+//!
+//! Any message accepted must encode back to the same bytes, so no two
+//! messages carry the same fields and no fields sign two messages.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use x402_types::{EscrowAuthorization, EscrowChannelState, EscrowVoucher};
+
+fuzz_target!(|message: &[u8]| {
+    if let Ok(authorization) = EscrowAuthorization::decode(message) {
+        let mut encoded = Vec::new();
+        authorization.encode(&mut encoded);
+        assert_eq!(encoded, message);
+    }
+    if let Ok(voucher) = EscrowVoucher::decode(message) {
+        let mut encoded = Vec::new();
+        voucher.encode(&mut encoded);
+        assert_eq!(encoded, message);
+    }
+    if let Ok(state) = EscrowChannelState::decode(message) {
+        let mut encoded = Vec::new();
+        state.encode(&mut encoded);
+        assert_eq!(encoded, message);
+    }
+});
@@ -0,0 +1,25 @@
+//! `X-PAYMENT` and `X-PAYMENT-RESPONSE` header values, as sent by anyone
+//!
+//! Any value accepted must survive encoding and decoding again, within the
+//! header size limit.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use x402_types::{
+    decode_payment_header, decode_payment_response_header, encode_payment_header,
+    encode_payment_response_header, MAX_HEADER_LEN,
+};
+
+fuzz_target!(|value: &str| {
+    if let Ok(payload) = decode_payment_header(value) {
+        let encoded = encode_payment_header(&payload);
+        assert!(encoded.len() <= MAX_HEADER_LEN);
+        assert_eq!(decode_payment_header(&encoded).unwrap(), payload);
+    }
+    if let Ok(response) = decode_payment_response_header(value) {
+        let encoded = encode_payment_response_header(&response);
+        assert!(encoded.len() <= MAX_HEADER_LEN);
+        assert_eq!(decode_payment_response_header(&encoded).unwrap(), response);
+    }
+});
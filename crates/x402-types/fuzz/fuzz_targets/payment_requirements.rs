@@ -0,0 +1,26 @@
+//! Payment requirements, as parsed from 402 responses and facilitator
+//! requests
+//!
+//! Any requirements accepted must survive serializing and parsing again,
+//! and expand into their asset options without panicking.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use x402_types::{PaymentRequiredResponse, PaymentRequirements};
+
+fuzz_target!(|json: &[u8]| {
+    if let Ok(requirements) = serde_json::from_slice::<PaymentRequirements>(json) {
+        let encoded = serde_json::to_vec(&requirements).unwrap();
+        let decoded: PaymentRequirements = serde_json::from_slice(&encoded).unwrap();
+        assert_eq!(decoded, requirements);
+        for option in requirements.expand() {
+            assert!(option.alternatives.is_empty());
+        }
+    }
+    if let Ok(response) = serde_json::from_slice::<PaymentRequiredResponse>(json) {
+        let encoded = serde_json::to_vec(&response).unwrap();
+        let decoded: PaymentRequiredResponse = serde_json::from_slice(&encoded).unwrap();
+        assert_eq!(decoded, response);
+    }
+});
@@ -0,0 +1,246 @@
+use alloc::{format, string::String};
+use core::{fmt, str::FromStr};
+
+/// Decimal places of Stellar amounts, one stroop being the smallest unit
+pub const STROOP_DECIMALS: u32 = 7;
+
+/// Stroops in one unit of an asset
+pub const STROOPS_PER_UNIT: i128 = 10_i128.pow(STROOP_DECIMALS);
+
+/// What to do with digits beyond the precision of a stroop
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Rounding {
+    /// Round toward negative infinity, never producing more than the exact
+    /// value
+    Down,
+    /// Round toward positive infinity, never producing less than the exact
+    /// value
+    Up,
+    /// Fail with [`AmountError::PrecisionLoss`]
+    #[default]
+    Reject,
+}
+
+/// Errors returned when parsing or computing amounts
+#[derive(Debug, Eq, PartialEq, thiserror::Error)]
+pub enum AmountError {
+    #[error("invalid amount {0:?}")]
+    Invalid(String),
+    #[error("amount {0:?} is more precise than a stroop")]
+    PrecisionLoss(String),
+    #[error("amount out of range")]
+    Overflow,
+    #[error("division by zero")]
+    DivisionByZero,
+}
+
+/// Amount of an asset, in stroops
+///
+/// Protocol fields such as `maxAmountRequired` carry integer stroops, read
+/// with [`StellarAmount::from_stroops_str`]. People write decimal units of
+/// the asset, read with [`StellarAmount::from_str_decimal`] and printed with
+/// [`StellarAmount::to_decimal_string`]; `FromStr` and `Display` use that
+/// decimal form, rejecting precision loss.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct StellarAmount(i128);
+
+impl StellarAmount {
+    /// Zero stroops
+    pub const ZERO: Self = Self(0);
+
+    /// Amount of `stroops` stroops
+    pub const fn from_stroops(stroops: i128) -> Self {
+        Self(stroops)
+    }
+
+    /// Amount in stroops
+    pub const fn stroops(self) -> i128 {
+        self.0
+    }
+
+    /// Whether the amount is more than zero
+    pub const fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    /// Parse an integer number of stroops, the form of protocol fields
+    ///
+    /// # Errors
+    /// * `Invalid` - If `value` is not an optionally negative integer
+    /// * `Overflow` - If it does not fit an i128
+    pub fn from_stroops_str(value: &str) -> Result<Self, AmountError> {
+        let digits = value.strip_prefix('-').unwrap_or(value);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(AmountError::Invalid(value.into()));
+        }
+        value.parse().map(Self).map_err(|_| AmountError::Overflow)
+    }
+
+    /// Parse a decimal number of units, "1.2345678" being 12345678 stroops
+    ///
+    /// # Arguments
+    /// * `value` - Optionally negative integer part, optionally followed by
+    ///   a point and fractional digits
+    /// * `rounding` - What to do with digits beyond the seventh decimal
+    ///
+    /// # Errors
+    /// * `Invalid` - If `value` is not a decimal number
+    /// * `PrecisionLoss` - If it has nonzero digits beyond the seventh
+    ///   decimal and `rounding` is [`Rounding::Reject`]
+    /// * `Overflow` - If it does not fit an i128 of stroops
+    pub fn from_str_decimal(value: &str, rounding: Rounding) -> Result<Self, AmountError> {
+        let invalid = || AmountError::Invalid(value.into());
+        let (negative, unsigned) = match value.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, value),
+        };
+        let (integer, fraction) = match unsigned.split_once('.') {
+            Some((integer, fraction)) if !fraction.is_empty() => (integer, fraction),
+            Some(_) => return Err(invalid()),
+            None => (unsigned, ""),
+        };
+        if integer.is_empty()
+            || !integer.bytes().all(|b| b.is_ascii_digit())
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let split = fraction.len().min(STROOP_DECIMALS as usize);
+        let (kept, dropped) = fraction.split_at(split);
+        let mut magnitude: i128 = 0;
+        for digit in integer.bytes().chain(kept.bytes()) {
+            magnitude = magnitude
+                .checked_mul(10)
+                .and_then(|m| m.checked_add(i128::from(digit - b'0')))
+                .ok_or(AmountError::Overflow)?;
+        }
+        magnitude = magnitude
+            .checked_mul(10_i128.pow(STROOP_DECIMALS - split as u32))
+            .ok_or(AmountError::Overflow)?;
+
+        // Rounding acts on the signed value, so the magnitude of a negative
+        // amount grows when rounding down
+        let inexact = dropped.bytes().any(|b| b != b'0');
+        let away_from_zero = match (inexact, rounding) {
+            (false, _) => false,
+            (true, Rounding::Reject) => return Err(AmountError::PrecisionLoss(value.into())),
+            (true, Rounding::Down) => negative,
+            (true, Rounding::Up) => !negative,
+        };
+        if away_from_zero {
+            magnitude = magnitude.checked_add(1).ok_or(AmountError::Overflow)?;
+        }
+        Ok(Self(if negative { -magnitude } else { magnitude }))
+    }
+
+    /// Format as a decimal number of units with all seven decimals,
+    /// 12345678 stroops being "1.2345678"
+    pub fn to_decimal_string(self) -> String {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let magnitude = self.0.unsigned_abs();
+        let unit = STROOPS_PER_UNIT as u128;
+        format!(
+            "{sign}{}.{:0width$}",
+            magnitude / unit,
+            magnitude % unit,
+            width = STROOP_DECIMALS as usize
+        )
+    }
+
+    /// Add two amounts
+    ///
+    /// # Errors
+    /// * `Overflow` - If the sum does not fit an i128
+    pub fn checked_add(self, rhs: Self) -> Result<Self, AmountError> {
+        self.0
+            .checked_add(rhs.0)
+            .map(Self)
+            .ok_or(AmountError::Overflow)
+    }
+
+    /// Subtract `rhs` from the amount
+    ///
+    /// # Errors
+    /// * `Overflow` - If the difference does not fit an i128
+    pub fn checked_sub(self, rhs: Self) -> Result<Self, AmountError> {
+        self.0
+            .checked_sub(rhs.0)
+            .map(Self)
+            .ok_or(AmountError::Overflow)
+    }
+
+    /// Multiply the amount by `factor`
+    ///
+    /// # Errors
+    /// * `Overflow` - If the product does not fit an i128
+    pub fn checked_mul(self, factor: i128) -> Result<Self, AmountError> {
+        self.0
+            .checked_mul(factor)
+            .map(Self)
+            .ok_or(AmountError::Overflow)
+    }
+
+    /// Divide the amount by `divisor`, to the stroop
+    ///
+    /// # Arguments
+    /// * `divisor` - Number of parts
+    /// * `rounding` - What to do with a remainder
+    ///
+    /// # Errors
+    /// * `DivisionByZero` - If `divisor` is zero
+    /// * `PrecisionLoss` - If there is a remainder and `rounding` is
+    ///   [`Rounding::Reject`]
+    /// * `Overflow` - If the quotient does not fit an i128
+    pub fn checked_div(self, divisor: i128, rounding: Rounding) -> Result<Self, AmountError> {
+        if divisor == 0 {
+            return Err(AmountError::DivisionByZero);
+        }
+        let quotient = self.0.checked_div(divisor).ok_or(AmountError::Overflow)?;
+        let remainder = self.0 % divisor;
+        if remainder == 0 {
+            return Ok(Self(quotient));
+        }
+        // Integer division truncates toward zero, below the exact value when
+        // it is positive
+        let positive = (remainder > 0) == (divisor > 0);
+        let quotient = match (rounding, positive) {
+            (Rounding::Reject, _) => {
+                return Err(AmountError::PrecisionLoss(format!(
+                    "{} / {divisor}",
+                    self.to_decimal_string()
+                )))
+            }
+            (Rounding::Down, true) | (Rounding::Up, false) => quotient,
+            (Rounding::Down, false) => quotient - 1,
+            (Rounding::Up, true) => quotient + 1,
+        };
+        Ok(Self(quotient))
+    }
+}
+
+impl From<i128> for StellarAmount {
+    fn from(stroops: i128) -> Self {
+        Self(stroops)
+    }
+}
+
+impl From<StellarAmount> for i128 {
+    fn from(amount: StellarAmount) -> Self {
+        amount.0
+    }
+}
+
+impl FromStr for StellarAmount {
+    type Err = AmountError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::from_str_decimal(value, Rounding::Reject)
+    }
+}
+
+impl fmt::Display for StellarAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_decimal_string())
+    }
+}
@@ -0,0 +1,526 @@
+use sha2::{Digest, Sha256};
+
+/// Domain separator of escrow authorization messages
+pub const ESCROW_MESSAGE_PREFIX: &str = "x402-escrow:v2";
+
+/// Domain prefix of the memo preimage of "exact" scheme payments
+pub const EXACT_MEMO_PREFIX: &str = "x402-exact:v1";
+
+/// Domain separator of payment voucher messages
+pub const VOUCHER_DOMAIN: &str = "x402-voucher:v1";
+
+/// Domain separator of channel state messages
+pub const CHANNEL_STATE_DOMAIN: &str = "x402-channel:v1";
+
+/// Longest decimal form of an i128, sign included
+pub const MAX_DECIMAL_LEN: usize = 40;
+
+/// Length of a strkey, `G...` account or `C...` contract
+pub const STRKEY_LEN: usize = 56;
+
+/// Longest canonical message decoded, in bytes, and the buffer contracts
+/// encode into, enough for any message naming a network of up to 32 bytes
+pub const MAX_MESSAGE_LEN: usize = 256;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Strkey version byte of ed25519 public keys
+const ACCOUNT_VERSION: u8 = 6 << 3;
+
+/// Errors returned when decoding a canonical message
+#[derive(Copy, Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum CanonicalError {
+    #[error("message is {len} bytes, exceeding the {max} byte limit")]
+    TooLarge { len: usize, max: usize },
+    #[error("message does not start with its domain separator")]
+    Domain,
+    #[error("message ends before its {0} field")]
+    Truncated(&'static str),
+    #[error("message has bytes past its last field")]
+    Trailing,
+    #[error("field {0} is not in canonical form")]
+    Field(&'static str),
+}
+
+/// Destination of canonically encoded bytes
+///
+/// Encoders write through a sink rather than returning a buffer so they run
+/// without an allocator, straight into a hasher or a fixed buffer.
+pub trait Sink {
+    /// Append `bytes` to the output
+    fn put(&mut self, bytes: &[u8]);
+}
+
+impl Sink for Sha256 {
+    fn put(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Sink for alloc::vec::Vec<u8> {
+    fn put(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// Sink writing into a fixed buffer, for callers without an allocator
+///
+/// Bytes past the end of the buffer are dropped and flag the sink as
+/// overflowed, so a truncated message is never mistaken for a whole one.
+pub struct SliceSink<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    overflowed: bool,
+}
+
+impl<'a> SliceSink<'a> {
+    /// Sink writing from the start of `buf`
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Bytes written so far
+    ///
+    /// # Returns
+    /// * The written bytes, or None if the buffer was too small for them
+    pub fn written(&self) -> Option<&[u8]> {
+        (!self.overflowed).then(|| &self.buf[..self.len])
+    }
+}
+
+impl Sink for SliceSink<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        let end = self.len + bytes.len();
+        match self.buf.get_mut(self.len..end) {
+            Some(target) if !self.overflowed => {
+                target.copy_from_slice(bytes);
+                self.len = end;
+            }
+            _ => self.overflowed = true,
+        }
+    }
+}
+
+/// Decimal form of an integer, formatted without an allocator
+#[derive(Copy, Clone)]
+pub struct Decimal {
+    buf: [u8; MAX_DECIMAL_LEN],
+    start: usize,
+}
+
+impl Decimal {
+    /// Digits of `value`, prefixed with `-` when negative
+    pub fn new(value: i128) -> Self {
+        let mut buf = [0; MAX_DECIMAL_LEN];
+        let mut start = MAX_DECIMAL_LEN;
+        let mut magnitude = value.unsigned_abs();
+        loop {
+            start -= 1;
+            buf[start] = b'0' + (magnitude % 10) as u8;
+            magnitude /= 10;
+            if magnitude == 0 {
+                break;
+            }
+        }
+        if value < 0 {
+            start -= 1;
+            buf[start] = b'-';
+        }
+        Self { buf, start }
+    }
+
+    /// ASCII digits
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[self.start..]
+    }
+
+    /// Digits as a string
+    pub fn as_str(&self) -> &str {
+        // Only ASCII digits and '-' are written
+        core::str::from_utf8(self.as_bytes()).expect("decimal digits are ASCII")
+    }
+
+    /// Value of digits in the form [`Decimal::new`] writes, the inverse of
+    /// [`Decimal::as_bytes`]
+    ///
+    /// # Returns
+    /// * The value, or None for anything else, e.g. a `+` sign, leading
+    ///   zeros, or `-0`, which would sign the same value as other bytes
+    pub fn parse(digits: &[u8]) -> Option<i128> {
+        let value = core::str::from_utf8(digits).ok()?.parse().ok()?;
+        (Self::new(value).as_bytes() == digits).then_some(value)
+    }
+}
+
+/// Fields of an escrow payment authorization, the message the client's
+/// ed25519 key signs
+///
+/// Encoded as [`ESCROW_MESSAGE_PREFIX`] and the [`SigningDomain`], then
+/// `:{network}:{escrow_id}:{amount}:{nonce}:{expires_at}`, the same bytes
+/// whether built off-chain from an [`EscrowPayload`](crate::EscrowPayload)
+/// or inside a contract.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EscrowAuthorization<'a> {
+    pub domain: SigningDomain<'a>,
+    /// x402 network id, binding the authorization to one network
+    pub network: &'a str,
+    /// Escrow account ID
+    pub escrow_id: u64,
+    /// Authorized amount, in stroops, as carried by the payload
+    pub amount: &'a str,
+    /// Client-chosen nonce
+    pub nonce: u64,
+    /// Unix timestamp after which the authorization is void
+    pub expires_at: u64,
+}
+
+impl EscrowAuthorization<'_> {
+    /// Write the signing message to `sink`
+    pub fn encode(&self, sink: &mut impl Sink) {
+        let escrow_id = Decimal::new(self.escrow_id.into());
+        let nonce = Decimal::new(self.nonce.into());
+        let expires_at = Decimal::new(self.expires_at.into());
+        self.domain.encode(ESCROW_MESSAGE_PREFIX, sink);
+        for field in [
+            self.network.as_bytes(),
+            escrow_id.as_bytes(),
+            self.amount.as_bytes(),
+            nonce.as_bytes(),
+            expires_at.as_bytes(),
+        ] {
+            sink.put(b":");
+            sink.put(field);
+        }
+    }
+
+    /// SHA-256 of the signing message
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        self.encode(&mut hasher);
+        hasher.finalize().into()
+    }
+
+    /// Fields of a signing message, the inverse of [`Self::encode`]
+    ///
+    /// Only messages `encode` writes are accepted: the network may not hold
+    /// a `:`, and numbers are in [canonical form](Decimal::parse), so one
+    /// message never reads as two sets of fields.
+    ///
+    /// # Errors
+    /// * If the message is too large, lacks the domain, or a field is
+    ///   missing or not canonical
+    pub fn decode(message: &[u8]) -> Result<EscrowAuthorization<'_>, CanonicalError> {
+        check_len(message)?;
+        let (domain, rest) = SigningDomain::decode(ESCROW_MESSAGE_PREFIX, message)?;
+        let fields = rest
+            .strip_prefix(b":")
+            .ok_or(CanonicalError::Truncated("network"))?;
+
+        let mut fields = fields.split(|&byte| byte == b':');
+        let mut next = |name| fields.next().ok_or(CanonicalError::Truncated(name));
+        let network =
+            core::str::from_utf8(next("network")?).map_err(|_| CanonicalError::Field("network"))?;
+        let escrow_id = decimal(next("escrow_id")?, "escrow_id")?;
+        let amount = next("amount")?;
+        Decimal::parse(amount).ok_or(CanonicalError::Field("amount"))?;
+        let nonce = decimal(next("nonce")?, "nonce")?;
+        let expires_at = decimal(next("expires_at")?, "expires_at")?;
+        if fields.next().is_some() {
+            return Err(CanonicalError::Trailing);
+        }
+
+        Ok(EscrowAuthorization {
+            domain,
+            network,
+            escrow_id,
+            // Canonical digits are ASCII
+            amount: core::str::from_utf8(amount).map_err(|_| CanonicalError::Field("amount"))?,
+            nonce,
+            expires_at,
+        })
+    }
+}
+
+/// Preimage of the memo binding an "exact" scheme payment to its
+/// requirements
+///
+/// Encoded as `x402-exact:v1:{network}:{pay_to}:{resource}`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExactMemo<'a> {
+    /// x402 network id
+    pub network: &'a str,
+    /// Recipient address (G... format)
+    pub pay_to: &'a str,
+    /// URL of the resource paid for
+    pub resource: &'a str,
+}
+
+impl ExactMemo<'_> {
+    /// Write the memo preimage to `sink`
+    pub fn encode(&self, sink: &mut impl Sink) {
+        sink.put(EXACT_MEMO_PREFIX.as_bytes());
+        for field in [self.network, self.pay_to, self.resource] {
+            sink.put(b":");
+            sink.put(field.as_bytes());
+        }
+    }
+
+    /// SHA-256 of the preimage, the transaction's `MEMO_HASH`
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        self.encode(&mut hasher);
+        hasher.finalize().into()
+    }
+}
+
+/// Network and contract a binary message is bound to
+///
+/// Encoded after the message's domain separator and a zero byte as the
+/// 32-byte network ID, then the contract strkey prefixed with its length as
+/// one byte, so a signature is void on any other network or contract.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SigningDomain<'a> {
+    /// SHA-256 of the Stellar network passphrase
+    pub network_id: [u8; 32],
+    /// Escrow contract address (C... format)
+    pub contract_id: &'a str,
+}
+
+impl SigningDomain<'_> {
+    /// Write the domain of a message separated by `separator` to `sink`
+    pub fn encode(&self, separator: &str, sink: &mut impl Sink) {
+        sink.put(separator.as_bytes());
+        sink.put(&[0]);
+        sink.put(&self.network_id);
+        // Longer contract IDs are not strkeys, only as many bytes as the
+        // length byte counts are written so it always matches
+        let contract_id =
+            &self.contract_id.as_bytes()[..self.contract_id.len().min(usize::from(u8::MAX))];
+        sink.put(&[contract_id.len() as u8]);
+        sink.put(contract_id);
+    }
+
+    /// Domain of a message separated by `separator`, and the bytes after it
+    ///
+    /// # Errors
+    /// * If the message lacks the separator, or ends within the domain
+    pub fn decode<'a>(
+        separator: &str,
+        message: &'a [u8],
+    ) -> Result<(SigningDomain<'a>, &'a [u8]), CanonicalError> {
+        let rest = message
+            .strip_prefix(separator.as_bytes())
+            .and_then(|rest| rest.strip_prefix(&[0]))
+            .ok_or(CanonicalError::Domain)?;
+        let (network_id, rest) = take(rest, "network_id")?;
+        let ([len], rest) = take(rest, "contract_id")?;
+        let (contract_id, rest) = rest
+            .split_at_checked(len.into())
+            .ok_or(CanonicalError::Truncated("contract_id"))?;
+        let contract_id =
+            core::str::from_utf8(contract_id).map_err(|_| CanonicalError::Field("contract_id"))?;
+        Ok((
+            SigningDomain {
+                network_id,
+                contract_id,
+            },
+            rest,
+        ))
+    }
+}
+
+/// Fields of a payment voucher, the message the client's ed25519 key signs
+/// to authorize a running total from an escrow
+///
+/// Encoded as [`VOUCHER_DOMAIN`] and the [`SigningDomain`], then
+/// `escrow_id` (u64), `amount` (i128), `sequence` (u64), and `expires_at`
+/// (u64), big-endian.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EscrowVoucher<'a> {
+    pub domain: SigningDomain<'a>,
+    /// Escrow account ID
+    pub escrow_id: u64,
+    /// Total amount authorized so far, in stroops
+    pub amount: i128,
+    /// Number of the voucher, superseding those with lower numbers
+    pub sequence: u64,
+    /// Unix timestamp after which the voucher is void
+    pub expires_at: u64,
+}
+
+impl EscrowVoucher<'_> {
+    /// Write the signing message to `sink`
+    pub fn encode(&self, sink: &mut impl Sink) {
+        self.domain.encode(VOUCHER_DOMAIN, sink);
+        sink.put(&self.escrow_id.to_be_bytes());
+        sink.put(&self.amount.to_be_bytes());
+        sink.put(&self.sequence.to_be_bytes());
+        sink.put(&self.expires_at.to_be_bytes());
+    }
+
+    /// SHA-256 of the signing message
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        self.encode(&mut hasher);
+        hasher.finalize().into()
+    }
+
+    /// Fields of a signing message, the inverse of [`Self::encode`]
+    ///
+    /// # Errors
+    /// * If the message is too large, lacks the domain, or is not exactly
+    ///   as long as its fields
+    pub fn decode(message: &[u8]) -> Result<EscrowVoucher<'_>, CanonicalError> {
+        check_len(message)?;
+        let (domain, rest) = SigningDomain::decode(VOUCHER_DOMAIN, message)?;
+        let (escrow_id, rest) = take(rest, "escrow_id")?;
+        let (amount, rest) = take(rest, "amount")?;
+        let (sequence, rest) = take(rest, "sequence")?;
+        let (expires_at, rest) = take(rest, "expires_at")?;
+        if !rest.is_empty() {
+            return Err(CanonicalError::Trailing);
+        }
+        Ok(EscrowVoucher {
+            domain,
+            escrow_id: u64::from_be_bytes(escrow_id),
+            amount: i128::from_be_bytes(amount),
+            sequence: u64::from_be_bytes(sequence),
+            expires_at: u64::from_be_bytes(expires_at),
+        })
+    }
+}
+
+/// Fields of a channel state, the message each party's ed25519 key signs to
+/// agree on an escrow
+///
+/// Encoded as [`CHANNEL_STATE_DOMAIN`] and the [`SigningDomain`], then
+/// `escrow_id` (u64), `sequence` (u64), `balance` (i128), and `settled`
+/// (i128), big-endian.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EscrowChannelState<'a> {
+    pub domain: SigningDomain<'a>,
+    /// Escrow account ID
+    pub escrow_id: u64,
+    /// Number of the state, superseding those with lower numbers
+    pub sequence: u64,
+    /// Balance left to the client, in stroops
+    pub balance: i128,
+    /// Total paid to the server, in stroops
+    pub settled: i128,
+}
+
+impl EscrowChannelState<'_> {
+    /// Write the signing message to `sink`
+    pub fn encode(&self, sink: &mut impl Sink) {
+        self.domain.encode(CHANNEL_STATE_DOMAIN, sink);
+        sink.put(&self.escrow_id.to_be_bytes());
+        sink.put(&self.sequence.to_be_bytes());
+        sink.put(&self.balance.to_be_bytes());
+        sink.put(&self.settled.to_be_bytes());
+    }
+
+    /// SHA-256 of the signing message
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        self.encode(&mut hasher);
+        hasher.finalize().into()
+    }
+
+    /// Fields of a signing message, the inverse of [`Self::encode`]
+    ///
+    /// # Errors
+    /// * If the message is too large, lacks the domain, or is not exactly
+    ///   as long as its fields
+    pub fn decode(message: &[u8]) -> Result<EscrowChannelState<'_>, CanonicalError> {
+        check_len(message)?;
+        let (domain, rest) = SigningDomain::decode(CHANNEL_STATE_DOMAIN, message)?;
+        let (escrow_id, rest) = take(rest, "escrow_id")?;
+        let (sequence, rest) = take(rest, "sequence")?;
+        let (balance, rest) = take(rest, "balance")?;
+        let (settled, rest) = take(rest, "settled")?;
+        if !rest.is_empty() {
+            return Err(CanonicalError::Trailing);
+        }
+        Ok(EscrowChannelState {
+            domain,
+            escrow_id: u64::from_be_bytes(escrow_id),
+            sequence: u64::from_be_bytes(sequence),
+            balance: i128::from_be_bytes(balance),
+            settled: i128::from_be_bytes(settled),
+        })
+    }
+}
+
+/// Reject messages no contract would verify before parsing them
+fn check_len(message: &[u8]) -> Result<(), CanonicalError> {
+    if message.len() > MAX_MESSAGE_LEN {
+        return Err(CanonicalError::TooLarge {
+            len: message.len(),
+            max: MAX_MESSAGE_LEN,
+        });
+    }
+    Ok(())
+}
+
+/// First `N` bytes of `bytes`, and the bytes after them
+fn take<'a, const N: usize>(
+    bytes: &'a [u8],
+    field: &'static str,
+) -> Result<([u8; N], &'a [u8]), CanonicalError> {
+    let (head, rest) = bytes
+        .split_first_chunk()
+        .ok_or(CanonicalError::Truncated(field))?;
+    Ok((*head, rest))
+}
+
+/// Unsigned number of a text message, in canonical form
+fn decimal(digits: &[u8], field: &'static str) -> Result<u64, CanonicalError> {
+    Decimal::parse(digits)
+        .and_then(|value| value.try_into().ok())
+        .ok_or(CanonicalError::Field(field))
+}
+
+/// `G...` strkey of an ed25519 public key, encoded without an allocator
+///
+/// Lets a contract match a key against the address of an escrow party.
+pub fn account_strkey(public_key: &[u8; 32]) -> [u8; STRKEY_LEN] {
+    let mut data = [0; 35];
+    data[0] = ACCOUNT_VERSION;
+    data[1..33].copy_from_slice(public_key);
+    let checksum = crc16_xmodem(&data[..33]);
+    data[33..].copy_from_slice(&checksum.to_le_bytes());
+
+    // 35 bytes are exactly 56 base32 digits, without padding
+    let mut strkey = [0; STRKEY_LEN];
+    let (mut bits, mut pending, mut len) = (0u32, 0u32, 0);
+    for byte in data {
+        bits = (bits << 8) | u32::from(byte);
+        pending += 8;
+        while pending >= 5 {
+            pending -= 5;
+            strkey[len] = BASE32_ALPHABET[((bits >> pending) & 31) as usize];
+            len += 1;
+        }
+    }
+    strkey
+}
+
+fn crc16_xmodem(bytes: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in bytes {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 == 0 {
+                crc << 1
+            } else {
+                (crc << 1) ^ 0x1021
+            };
+        }
+    }
+    crc
+}
@@ -0,0 +1,163 @@
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{PaymentPayload, PaymentResponseHeader, SchemePayload, X402_VERSIONS};
+
+/// Name of the request header carrying the payment payload
+pub const PAYMENT_HEADER: &str = "X-PAYMENT";
+
+/// Name of the response header carrying the settlement details
+pub const PAYMENT_RESPONSE_HEADER: &str = "X-PAYMENT-RESPONSE";
+
+/// Maximum accepted length of an encoded header value, in bytes
+pub const MAX_HEADER_LEN: usize = 8 * 1024;
+
+/// Prefix of the names of payload extension fields
+pub const EXTENSION_PREFIX: &str = "x-";
+
+// Fields of each scheme payload, any other being rejected
+const ESCROW_FIELDS: [&str; 8] = [
+    "escrowId",
+    "client",
+    "amount",
+    "nonce",
+    "expiresAt",
+    "signature",
+    "depositAuthorization",
+    "paymentId",
+];
+const TRANSACTION_FIELDS: [&str; 2] = ["transaction", "signatures"];
+const TRANSACTION_HASH_FIELDS: [&str; 1] = ["txHash"];
+
+// Encodes with padding, decodes with or without it
+const ENGINE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Errors returned when decoding an x402 header value
+#[derive(Debug, thiserror::Error)]
+pub enum HeaderError {
+    #[error("header value is empty")]
+    Empty,
+    #[error("header value is {len} bytes, exceeding the {max} byte limit")]
+    TooLarge { len: usize, max: usize },
+    #[error("header value is not valid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("header value is not valid payload JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("x402 version {version} is not supported, expected one of {supported:?}")]
+    UnsupportedVersion {
+        version: u64,
+        supported: &'static [u32],
+    },
+    #[error("payload has unknown field {0}")]
+    UnknownField(String),
+}
+
+/// Encode a payment payload as an `X-PAYMENT` header value
+pub fn encode_payment_header(payload: &PaymentPayload) -> String {
+    encode(payload)
+}
+
+/// Decode an `X-PAYMENT` header value
+///
+/// Decoding is strict: fields the payload's version does not define are
+/// rejected, but for the [extension fields](PaymentPayload::extensions) of
+/// version 2 payloads.
+///
+/// # Errors
+/// * If the value is empty, too large, not base64, or not a valid payload
+/// * If the payload's version is not in [`X402_VERSIONS`]
+/// * If the payload has an unknown field
+pub fn decode_payment_header(value: &str) -> Result<PaymentPayload, HeaderError> {
+    let json: Value = decode(value)?;
+
+    // Check the version first, the fields of other versions being unknown
+    if let Some(version) = json.get("x402Version").and_then(Value::as_u64) {
+        if !u32::try_from(version).is_ok_and(|version| X402_VERSIONS.contains(&version)) {
+            return Err(HeaderError::UnsupportedVersion {
+                version,
+                supported: &X402_VERSIONS,
+            });
+        }
+    }
+
+    let scheme_fields: Vec<String> = json
+        .get("payload")
+        .and_then(Value::as_object)
+        .map(|fields| fields.keys().cloned().collect())
+        .unwrap_or_default();
+    let mut payload: PaymentPayload = serde_json::from_value(json)?;
+
+    // Fields left over are extensions, which came with version 2
+    let unknown = payload
+        .extensions
+        .keys()
+        .find(|name| payload.x402_version < 2 || !name.starts_with(EXTENSION_PREFIX));
+    if let Some(name) = unknown {
+        return Err(HeaderError::UnknownField(name.clone()));
+    }
+    // Keep extension values as they encode, e.g. 1.0 as 1, so payloads
+    // survive a round trip
+    for value in payload.extensions.values_mut() {
+        *value = serde_json::from_str(&crate::canonical_json(value)?)?;
+    }
+    let known: &[&str] = match payload.payload {
+        SchemePayload::Escrow(_) => &ESCROW_FIELDS,
+        SchemePayload::Transaction(_) => &TRANSACTION_FIELDS,
+        SchemePayload::TransactionHash(_) => &TRANSACTION_HASH_FIELDS,
+    };
+    if let Some(name) = scheme_fields
+        .iter()
+        .find(|name| !known.contains(&name.as_str()))
+    {
+        return Err(HeaderError::UnknownField(format!("payload.{name}")));
+    }
+    Ok(payload)
+}
+
+/// Encode settlement details as an `X-PAYMENT-RESPONSE` header value
+pub fn encode_payment_response_header(response: &PaymentResponseHeader) -> String {
+    encode(response)
+}
+
+/// Decode an `X-PAYMENT-RESPONSE` header value
+///
+/// Unknown fields are ignored, so clients read the responses of servers
+/// speaking later versions.
+///
+/// # Errors
+/// * If the value is empty, too large, not base64, or not a valid response
+pub fn decode_payment_response_header(value: &str) -> Result<PaymentResponseHeader, HeaderError> {
+    decode(value)
+}
+
+fn encode<T: Serialize>(value: &T) -> String {
+    // Serializing plain data structs to JSON cannot fail
+    let json = crate::canonical_json(value).expect("header payload serializes to JSON");
+    ENGINE.encode(json)
+}
+
+fn decode<T: DeserializeOwned>(value: &str) -> Result<T, HeaderError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(HeaderError::Empty);
+    }
+
+    // Check the size before allocating anything for the decoded bytes
+    if value.len() > MAX_HEADER_LEN {
+        return Err(HeaderError::TooLarge {
+            len: value.len(),
+            max: MAX_HEADER_LEN,
+        });
+    }
+
+    let json = ENGINE.decode(value)?;
+    Ok(serde_json::from_slice(&json)?)
+}
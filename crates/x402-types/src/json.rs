@@ -0,0 +1,145 @@
+use serde::Serialize;
+use serde_json::{Number, Value};
+use sha2::{Digest, Sha256};
+
+/// Largest integer an IEEE-754 double holds exactly
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Canonical JSON text of `value`, per RFC 8785 (JSON Canonicalization
+/// Scheme)
+///
+/// Object members are sorted by the UTF-16 code units of their names,
+/// strings escape only what JSON requires, and numbers are the doubles
+/// ECMAScript's `Number.prototype.toString` prints, so the TypeScript
+/// clients produce the same bytes with `JSON.stringify` over sorted keys.
+/// Integers beyond 2^53 lose precision as they would in JavaScript, which
+/// is why protocol amounts are strings.
+///
+/// Use it wherever JSON is hashed or signed off-chain. Messages the
+/// contract verifies keep their [byte encoding](EscrowAuthorization), which
+/// needs no JSON parser on-chain.
+///
+/// # Errors
+/// * If `value` has no JSON form, e.g. a map with non-string keys
+pub fn canonical_json<T: Serialize + ?Sized>(value: &T) -> Result<String, serde_json::Error> {
+    let value = serde_json::to_value(value)?;
+    let mut out = String::new();
+    write_value(&value, &mut out);
+    Ok(out)
+}
+
+/// SHA-256 of the [canonical JSON](canonical_json) of `value`
+///
+/// # Errors
+/// * If `value` has no JSON form
+pub fn canonical_json_hash<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<[u8; 32], serde_json::Error> {
+    Ok(Sha256::digest(canonical_json(value)?).into())
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(true) => out.push_str("true"),
+        Value::Bool(false) => out.push_str("false"),
+        Value::Number(number) => write_number(number, out),
+        Value::String(s) => write_string(s, out),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(members) => {
+            let mut members: Vec<_> = members.iter().collect();
+            members.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push('{');
+            for (i, (name, member)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(name, out);
+                out.push(':');
+                write_value(member, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_number(number: &Number, out: &mut String) {
+    match (number.as_u64(), number.as_i64()) {
+        (Some(n), _) if n <= MAX_SAFE_INTEGER => out.push_str(&n.to_string()),
+        (_, Some(n)) if n.unsigned_abs() <= MAX_SAFE_INTEGER => out.push_str(&n.to_string()),
+        // Every number is a double without `arbitrary_precision`
+        _ => write_double(number.as_f64().unwrap_or_default(), out),
+    }
+}
+
+/// ECMAScript `Number::toString` of a finite double
+fn write_double(value: f64, out: &mut String) {
+    if value == 0.0 {
+        // Negative zero included
+        out.push('0');
+        return;
+    }
+    if value < 0.0 {
+        out.push('-');
+    }
+
+    // Shortest round-tripping digits d.ddd, with the exponent of d
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("floats format with an exponent");
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let k = digits.len() as i32;
+    // value = 0.digits * 10^n
+    let n = exponent.parse::<i32>().expect("exponent is an integer") + 1;
+
+    if k <= n && n <= 21 {
+        out.push_str(&digits);
+        out.extend(std::iter::repeat_n('0', (n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        let (int, frac) = digits.split_at(n as usize);
+        out.push_str(int);
+        out.push('.');
+        out.push_str(frac);
+    } else if -6 < n && n <= 0 {
+        out.push_str("0.");
+        out.extend(std::iter::repeat_n('0', -n as usize));
+        out.push_str(&digits);
+    } else {
+        let (first, rest) = digits.split_at(1);
+        out.push_str(first);
+        if !rest.is_empty() {
+            out.push('.');
+            out.push_str(rest);
+        }
+        let sign = if n > 0 { '+' } else { '-' };
+        out.push_str(&format!("e{sign}{}", (n - 1).abs()));
+    }
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -0,0 +1,132 @@
+//! # x402 Types
+//!
+//! Rust definitions of the x402 protocol types for Stellar, mirroring
+//! `lib/x402-protocol-types.ts`, plus codecs for the `X-PAYMENT` and
+//! `X-PAYMENT-RESPONSE` headers.
+//!
+//! ## Usage
+//! - Resource servers decode `X-PAYMENT` with [`decode_payment_header`]
+//! - Clients encode their payload with [`encode_payment_header`]
+//! - Settlement details travel back in `X-PAYMENT-RESPONSE`
+//!
+//! ## Versions
+//! The crate speaks every version in [`X402_VERSIONS`]. Resource servers
+//! advertise the versions they accept in their 402 response, and clients
+//! pay with the highest one both sides support, see [`negotiate_version`].
+//! Version 2 added the `x402Version` of payment requirements, the
+//! `x402Versions` of 402 responses, and `x-` extension fields of payloads.
+//!
+//! [`decode_payment_header`] is strict: payloads of versions not
+//! supported and unknown fields are rejected, except extension fields of
+//! version 2 payloads, which are kept in [`PaymentPayload::extensions`].
+//!
+//! ## Amounts
+//! Protocol fields carry integer stroops. [`StellarAmount`] converts them
+//! to and from decimal units of the asset, rounding as told by
+//! [`Rounding`] rather than silently truncating.
+//!
+//! ## Canonical JSON
+//! JSON that is hashed or signed off-chain goes through [`canonical_json`],
+//! RFC 8785 canonicalization, so implementations in other languages produce
+//! the same bytes. `fixtures/canonical_json.json` holds test vectors, each
+//! JSON input with its canonical form and SHA-256, for them to conform to.
+//! Header values are canonical JSON too.
+//!
+//! ## Schemas
+//! Wire types implement [`JsonSchema`], describing their JSON form for the
+//! OpenAPI description of the facilitator.
+//!
+//! ## `no_std`
+//! Without the default `std` feature the crate builds for `no_std` targets.
+//! With the `alloc` feature it keeps the protocol data structures and
+//! amounts. Header codecs, schemas, and the free-form JSON fields
+//! `outputSchema` and `extra` of [`PaymentRequirements`] need `std`.
+//!
+//! Without any feature only the [canonical encoders](EscrowAuthorization)
+//! of the bytes clients sign remain, needing no allocator, so the escrow
+//! contract and off-chain code share one encoding.
+//!
+//! ## Fuzzing
+//! The header codecs, canonical message decoders, and requirements parser
+//! face untrusted input. `fuzz/` holds cargo-fuzz targets for them, with
+//! seed corpora: `cargo +nightly fuzz run payment_header` from this crate.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+mod amount;
+mod canonical;
+#[cfg(feature = "std")]
+mod header;
+#[cfg(feature = "std")]
+mod json;
+#[cfg(feature = "alloc")]
+mod protocol;
+#[cfg(feature = "std")]
+mod schema;
+
+#[cfg(feature = "alloc")]
+pub use amount::*;
+pub use canonical::*;
+#[cfg(feature = "std")]
+pub use header::*;
+#[cfg(feature = "std")]
+pub use json::*;
+#[cfg(feature = "alloc")]
+pub use protocol::*;
+#[cfg(feature = "std")]
+pub use schema::*;
+
+/// Highest version of the x402 payment protocol implemented by this crate
+pub const X402_VERSION: u32 = 2;
+
+/// Every version of the x402 payment protocol this crate speaks, oldest
+/// first
+pub const X402_VERSIONS: [u32; 2] = [1, X402_VERSION];
+
+/// Highest version of the x402 payment protocol both sides support
+///
+/// # Arguments
+/// * `offered` - Versions the other side supports, in any order
+///
+/// # Returns
+/// * The version to use, or None if no offered version is supported
+pub fn negotiate_version(offered: &[u32]) -> Option<u32> {
+    offered
+        .iter()
+        .copied()
+        .filter(|version| X402_VERSIONS.contains(version))
+        .max()
+}
+
+/// Scheme for a pre-signed Stellar payment transaction
+pub const EXACT_SCHEME: &str = "exact";
+
+/// Scheme for payments drawn from an x402 escrow account
+pub const ESCROW_SCHEME: &str = "escrow";
+
+/// Asset identifier for escrow balances held in stroops of XLM
+pub const NATIVE_ASSET: &str = "native";
+
+// Stellar-specific network identifiers
+pub const STELLAR_MAINNET: &str = "stellar-mainnet";
+pub const STELLAR_TESTNET: &str = "stellar-testnet";
+pub const STELLAR_FUTURENET: &str = "stellar-futurenet";
+
+/// Map a network identifier to its Stellar network passphrase
+///
+/// # Returns
+/// * The passphrase, or None for unknown networks
+pub fn network_passphrase(network: &str) -> Option<&'static str> {
+    match network {
+        STELLAR_MAINNET => Some("Public Global Stellar Network ; September 2015"),
+        STELLAR_TESTNET => Some("Test SDF Network ; September 2015"),
+        STELLAR_FUTURENET => Some("Test SDF Future Network ; October 2022"),
+        _ => None,
+    }
+}
+
+mod test;
@@ -0,0 +1,527 @@
+use alloc::{format, string::String, vec::Vec};
+
+#[cfg(feature = "std")]
+use alloc::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{EscrowAuthorization, ExactMemo, SigningDomain, NATIVE_ASSET};
+
+/// Payment Required Response (402 response body)
+///
+/// Tells the client how it can pay for the resource.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentRequiredResponse {
+    /// Version of the x402 payment protocol
+    pub x402_version: u32,
+    /// Every version the resource server accepts (version 2, optional)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub x402_versions: Vec<u32>,
+    /// Payment requirements the resource server accepts
+    pub accepts: Vec<PaymentRequirements>,
+    /// Error message (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Why a payment was rejected, and what the client may do about it
+    /// (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_error: Option<PaymentErrorBody>,
+}
+
+impl PaymentRequiredResponse {
+    /// Versions the resource server accepts, `x402_version` alone for
+    /// responses listing none
+    pub fn versions(&self) -> Vec<u32> {
+        if self.x402_versions.is_empty() {
+            alloc::vec![self.x402_version]
+        } else {
+            self.x402_versions.clone()
+        }
+    }
+}
+
+/// Rejection of a payment, with hints for the client to act on
+///
+/// Clients top up their escrow by `required_topup_amount`, sign a new
+/// payment for `requirements`, or wait `retry_after` seconds, as given;
+/// other rejections will not succeed by paying again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentErrorBody {
+    /// Stable code of the error, as the `errorCode` of facilitator
+    /// responses
+    pub code: u32,
+    /// Human-readable description of the error
+    pub message: String,
+    /// Seconds to wait before paying again, when the rejection is
+    /// temporary (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+    /// Amount to deposit into the escrow before paying again, in stroops
+    /// (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_topup_amount: Option<String>,
+    /// Requirements to sign a new payment for, when paying again may
+    /// succeed (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requirements: Option<PaymentRequirements>,
+}
+
+/// Payment Requirements
+///
+/// Specifies the network, amount, recipient, and resource being paid for.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentRequirements {
+    /// Version of the x402 payment protocol the requirements are paid with,
+    /// any supported version if absent (version 2, optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x402_version: Option<u32>,
+    /// Scheme of the payment protocol to use (e.g., "escrow")
+    pub scheme: String,
+    /// Network to send payment on (e.g., "stellar-testnet")
+    pub network: String,
+    /// Maximum amount required to pay for the resource, in stroops
+    pub max_amount_required: String,
+    /// URL of resource to pay for
+    pub resource: String,
+    /// Description of the resource
+    pub description: String,
+    /// MIME type of the resource response
+    pub mime_type: String,
+    /// Output schema of the resource response (optional, `std` only)
+    #[cfg(feature = "std")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<Value>,
+    /// Stellar address to pay value to (G... format)
+    pub pay_to: String,
+    /// Asset the amount is denominated in, a contract address (C... format)
+    /// or "native" (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>,
+    /// Other assets accepted instead of `asset`, each at its own amount
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<AssetAmount>,
+    /// Maximum time in seconds for the resource server to respond
+    pub max_timeout_seconds: u64,
+    /// Extra information specific to the scheme (optional, `std` only)
+    #[cfg(feature = "std")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra: Option<Value>,
+}
+
+/// Amount of an asset a resource can be paid with
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetAmount {
+    /// Asset, a contract address (C... format) or "native"
+    pub asset: String,
+    /// Maximum amount required in the asset, in its stroops
+    pub max_amount_required: String,
+}
+
+impl PaymentRequirements {
+    /// Every asset the requirements accept with its amount, `asset` first
+    ///
+    /// Requirements naming no asset are paid in "native".
+    pub fn asset_options(&self) -> Vec<AssetAmount> {
+        let primary = AssetAmount {
+            asset: self.primary_asset().into(),
+            max_amount_required: self.max_amount_required.clone(),
+        };
+        let mut options = Vec::with_capacity(1 + self.alternatives.len());
+        options.push(primary);
+        for option in &self.alternatives {
+            if options.iter().all(|known| known.asset != option.asset) {
+                options.push(option.clone());
+            }
+        }
+        options
+    }
+
+    /// Requirements of paying in `asset` alone, `asset` None standing for
+    /// the primary asset
+    ///
+    /// # Returns
+    /// * The requirements with the asset and amount of the option and no
+    ///   alternatives, or None if `asset` is not accepted
+    pub fn for_asset(&self, asset: Option<&str>) -> Option<PaymentRequirements> {
+        let asset = asset.unwrap_or(self.primary_asset());
+        let option = self
+            .asset_options()
+            .into_iter()
+            .find(|option| option.asset == asset)?;
+        let mut requirements = self.clone();
+        if option.asset != self.primary_asset() {
+            requirements.asset = Some(option.asset);
+        }
+        requirements.max_amount_required = option.max_amount_required;
+        requirements.alternatives = Vec::new();
+        Some(requirements)
+    }
+
+    /// Requirements of each accepted asset, as listed in a 402 response
+    pub fn expand(&self) -> Vec<PaymentRequirements> {
+        self.asset_options()
+            .iter()
+            .filter_map(|option| self.for_asset(Some(&option.asset)))
+            .collect()
+    }
+
+    /// Asset of the amount, "native" if the requirements name none
+    fn primary_asset(&self) -> &str {
+        self.asset.as_deref().unwrap_or(NATIVE_ASSET)
+    }
+
+    /// Memo hash binding a direct payment to these requirements
+    ///
+    /// Payment transactions of the "exact" scheme carry this value as their
+    /// `MEMO_HASH`, so a transaction paying for one resource cannot pay for
+    /// another: SHA-256 of `x402-exact:v1:{network}:{pay_to}:{resource}`
+    pub fn payment_memo(&self) -> [u8; 32] {
+        ExactMemo {
+            network: &self.network,
+            pay_to: &self.pay_to,
+            resource: &self.resource,
+        }
+        .hash()
+    }
+}
+
+/// Payment Payload (X-PAYMENT header content, base64 encoded JSON)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentPayload {
+    /// Version of the x402 payment protocol
+    pub x402_version: u32,
+    /// Scheme value of the accepted payment requirements
+    pub scheme: String,
+    /// Network id of the accepted payment requirements
+    pub network: String,
+    /// Asset paid in, one of the accepted requirements' options when they
+    /// offer several (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>,
+    /// Scheme-dependent payload
+    pub payload: SchemePayload,
+    /// Extension fields, named `x-...` (version 2, `std` only)
+    #[cfg(feature = "std")]
+    #[serde(flatten)]
+    pub extensions: BTreeMap<String, Value>,
+}
+
+/// Scheme-dependent part of a [`PaymentPayload`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SchemePayload {
+    /// Payment drawn from an escrow account ("escrow" scheme)
+    Escrow(EscrowPayload),
+    /// Pre-signed Stellar transaction ("exact" scheme)
+    Transaction(TransactionPayload),
+    /// Payment transaction already on the ledger ("exact" scheme)
+    TransactionHash(TransactionHashPayload),
+}
+
+/// Authorization to charge an escrow, signed by the escrow client
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EscrowPayload {
+    /// Escrow account ID
+    pub escrow_id: u64,
+    /// Client address (G... format)
+    pub client: String,
+    /// Authorized amount, in stroops
+    pub amount: String,
+    /// Client-chosen nonce, unique per escrow
+    pub nonce: u64,
+    /// Unix timestamp after which the authorization is void
+    pub expires_at: u64,
+    /// Hex-encoded ed25519 signature of the [signing hash](Self::signing_hash)
+    /// by the client
+    pub signature: String,
+    /// Base64 XDR `SorobanAuthorizationEntry` pre-signed by the client for a
+    /// `deposit` into the escrow, which the facilitator submits before
+    /// settling when the balance falls short (optional, not signed over)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deposit_authorization: Option<String>,
+    /// Payment the contract already created from this authorization when
+    /// opening the escrow, which the facilitator settles rather than
+    /// creating another (optional, not signed over)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<u64>,
+    /// Base64 XDR `SorobanAuthorizationEntry` pre-signed by the client for a
+    /// `record_direct_payment` to the server, which the facilitator submits
+    /// instead when the escrow does not exist (optional, not signed over)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direct_authorization: Option<String>,
+}
+
+impl EscrowPayload {
+    /// Bytes the client signs to authorize this payment
+    ///
+    /// The message binds the network and the escrow contract's
+    /// [domain](SigningDomain) so an authorization cannot be replayed on
+    /// another network or deployment, see [`EscrowAuthorization`]
+    pub fn signing_message(&self, network: &str, domain: SigningDomain<'_>) -> Vec<u8> {
+        let mut message = Vec::new();
+        self.authorization(network, domain).encode(&mut message);
+        message
+    }
+
+    /// SHA-256 of the [signing message](Self::signing_message), the value
+    /// the client's ed25519 key signs
+    pub fn signing_hash(&self, network: &str, domain: SigningDomain<'_>) -> [u8; 32] {
+        self.authorization(network, domain).signing_hash()
+    }
+
+    /// Signed fields of the payload, for encoding on `network` against the
+    /// contract of `domain`
+    pub fn authorization<'a>(
+        &'a self,
+        network: &'a str,
+        domain: SigningDomain<'a>,
+    ) -> EscrowAuthorization<'a> {
+        EscrowAuthorization {
+            domain,
+            network,
+            escrow_id: self.escrow_id,
+            amount: &self.amount,
+            nonce: self.nonce,
+            expires_at: self.expires_at,
+        }
+    }
+
+    /// [Correlation ID](correlation_id) of the payment
+    pub fn correlation_id(&self) -> String {
+        correlation_id(self.escrow_id, self.nonce)
+    }
+}
+
+/// Length of correlation IDs, short enough for a transaction text memo
+pub const CORRELATION_ID_LEN: usize = 16;
+
+/// Correlation ID of the escrow payment authorized with `nonce`
+///
+/// Every party derives it from the payload alone, so the traces of the
+/// client, resource server, and facilitator and the memo of the settlement
+/// transactions link up without passing it along: the first 8 bytes of
+/// `SHA-256("x402-escrow:{escrow_id}:{nonce}")`, hex encoded.
+pub fn correlation_id(escrow_id: u64, nonce: u64) -> String {
+    let hash = Sha256::digest(format!("x402-escrow:{escrow_id}:{nonce}"));
+    hash[..CORRELATION_ID_LEN / 2]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Pre-signed Stellar transaction
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionPayload {
+    /// XDR-encoded transaction envelope (includes signatures)
+    pub transaction: String,
+    /// XDR-encoded signatures, if not in the envelope
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signatures: Option<Vec<String>>,
+}
+
+/// Proof of a payment made with a classic Stellar payment transaction
+///
+/// The transaction pays `payTo` the required amount of the asset, with the
+/// [payment memo](PaymentRequirements::payment_memo) of the requirements.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionHashPayload {
+    /// Hex-encoded hash of the payment transaction
+    pub tx_hash: String,
+}
+
+/// Facilitator /verify endpoint request
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyRequest {
+    /// Version of the x402 payment protocol
+    pub x402_version: u32,
+    /// The X-PAYMENT header value (base64 encoded PaymentPayload)
+    pub payment_header: String,
+    /// The payment requirements being verified against
+    pub payment_requirements: PaymentRequirements,
+}
+
+/// Facilitator /verify endpoint response
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyResponse {
+    /// Whether the payment is valid
+    pub is_valid: bool,
+    /// Reason for invalidity (if is_valid is false)
+    pub invalid_reason: Option<String>,
+    /// Stable code of the reason, see the `x402-errors` crate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<u32>,
+    /// Versions the facilitator supports, when the payment's is not
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub x402_versions: Vec<u32>,
+}
+
+/// Facilitator /settle endpoint request
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettleRequest {
+    /// Version of the x402 payment protocol
+    pub x402_version: u32,
+    /// The X-PAYMENT header value (base64 encoded PaymentPayload)
+    pub payment_header: String,
+    /// The payment requirements being settled
+    pub payment_requirements: PaymentRequirements,
+    /// Amount to charge, in stroops, at most the authorized amount, for
+    /// resources priced once served; the authorized amount if absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settle_amount: Option<String>,
+    /// Simulate the settlement without submitting it
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub dry_run: bool,
+}
+
+/// Facilitator /settle endpoint response
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettleResponse {
+    /// Whether the payment was successful
+    pub success: bool,
+    /// Error message from the facilitator (if success is false)
+    pub error: Option<String>,
+    /// Stable code of the error, see the `x402-errors` crate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<u32>,
+    /// Transaction hash of the settled payment
+    pub tx_hash: Option<String>,
+    /// Network id the payment was settled on
+    pub network_id: Option<String>,
+    /// Escrow payment ID (escrow scheme only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<u64>,
+    /// Ledger the settlement transaction was included in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ledger: Option<u32>,
+    /// Finality of the settlement, if the facilitator tracks it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finality: Option<Finality>,
+    /// What the settlement would have done, for dry runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simulation: Option<SettlementSimulation>,
+}
+
+/// Outcome of a simulated settlement
+///
+/// Only the `create_payment` invocation is simulated: `settle_payment` needs
+/// the payment to exist on chain, so it cannot be simulated ahead of it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementSimulation {
+    /// Amount that would be charged, in stroops
+    pub amount: String,
+    /// Payment ID the contract would assign (escrow scheme only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<u64>,
+    /// Estimated total fee of the transaction, in stroops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_fee: Option<u32>,
+}
+
+/// Facilitator /preflight endpoint response, whose request is a
+/// [`VerifyRequest`]
+///
+/// A forecast of the payment's settlement against the chain state when it
+/// was simulated, possibly a few seconds ago: it is never binding, and the
+/// settlement may still fail, or succeed after a failed preflight.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreflightResponse {
+    /// Whether the settlement would succeed
+    pub ok: bool,
+    /// Reason the settlement would fail (if ok is false)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invalid_reason: Option<String>,
+    /// Stable code of the reason, see the `x402-errors` crate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<u32>,
+    /// Estimated total fee of the settlement, in stroops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_fee: Option<u32>,
+    /// Balance of the escrow payments may still draw on, in stroops
+    /// (escrow scheme only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available_balance: Option<String>,
+    /// Whether the facilitator commits to the verdict, always false
+    pub binding: bool,
+    /// Whether the verdict was reused from an earlier preflight
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub cached: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// How settled a payment is
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Finality {
+    /// In a ledger not yet as old as the facilitator's finality depth
+    Included,
+    /// In a ledger at least the finality depth old
+    Final,
+}
+
+/// Scheme and network pair a facilitator can settle
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupportedKind {
+    /// Version of the x402 payment protocol
+    pub x402_version: u32,
+    /// Payment scheme (e.g., "escrow")
+    pub scheme: String,
+    /// Network id (e.g., "stellar-testnet")
+    pub network: String,
+    /// Stellar network passphrase of the network
+    pub network_passphrase: String,
+}
+
+/// Fees charged by a facilitator on top of the payment
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeePolicy {
+    /// Facilitator fee, in basis points of the settled amount
+    pub fee_bps: u32,
+    /// Whether the facilitator pays the network transaction fees
+    pub network_fee_sponsored: bool,
+}
+
+/// Facilitator /supported endpoint response
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupportedResponse {
+    /// Scheme and network pairs the facilitator settles
+    pub kinds: Vec<SupportedKind>,
+    /// Accepted asset contract addresses (C... format)
+    pub assets: Vec<String>,
+    /// Fee policy
+    pub fee: FeePolicy,
+    /// Escrow contract address (C... format)
+    pub escrow_contract: String,
+}
+
+/// X-PAYMENT-RESPONSE header content (base64 encoded JSON)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentResponseHeader {
+    /// Settlement response from facilitator
+    pub settlement: SettleResponse,
+    /// Amount charged, in stroops, for resources priced once served
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<String>,
+}
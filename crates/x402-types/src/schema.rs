@@ -0,0 +1,501 @@
+use serde_json::{json, Value};
+
+use crate::{
+    AssetAmount, EscrowPayload, FeePolicy, PaymentErrorBody, PaymentPayload,
+    PaymentRequiredResponse, PaymentRequirements, PaymentResponseHeader, PreflightResponse,
+    SchemePayload, SettleRequest, SettleResponse, SettlementSimulation, SupportedKind,
+    SupportedResponse, TransactionHashPayload, TransactionPayload, VerifyRequest, VerifyResponse,
+};
+
+/// Type with a JSON Schema of its serialized form, for API descriptions
+pub trait JsonSchema {
+    /// Name of the schema among the components of an API description
+    const NAME: &'static str;
+
+    /// JSON Schema (2020-12) of the type, referring to other types as
+    /// `#/components/schemas/{NAME}`
+    fn schema() -> Value;
+}
+
+/// Reference to the schema of `T` among the components
+pub fn schema_ref<T: JsonSchema>() -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", T::NAME) })
+}
+
+/// Schema of an object with `properties`, `required` being present
+///
+/// Other properties are allowed, so clients ignore fields added later.
+pub fn object_schema(description: &str, properties: Value, required: &[&str]) -> Value {
+    json!({
+        "type": "object",
+        "description": description,
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// Schema of a string
+pub fn string_schema(description: &str) -> Value {
+    json!({ "type": "string", "description": description })
+}
+
+/// Schema of an integer amount in stroops, carried as a string
+pub fn stroops_schema(description: &str) -> Value {
+    json!({ "type": "string", "pattern": "^-?[0-9]+$", "description": description })
+}
+
+/// Schema of a non-negative integer
+pub fn integer_schema(description: &str) -> Value {
+    json!({ "type": "integer", "minimum": 0, "description": description })
+}
+
+/// Schema of a boolean
+pub fn boolean_schema(description: &str) -> Value {
+    json!({ "type": "boolean", "description": description })
+}
+
+/// Schema of an array of `items`
+pub fn array_schema(description: &str, items: Value) -> Value {
+    json!({ "type": "array", "description": description, "items": items })
+}
+
+/// Schema of `schema` or null, for optional fields serialized as null
+pub fn nullable_schema(schema: Value) -> Value {
+    json!({ "anyOf": [schema, { "type": "null" }] })
+}
+
+impl JsonSchema for PaymentRequiredResponse {
+    const NAME: &'static str = "PaymentRequiredResponse";
+
+    fn schema() -> Value {
+        object_schema(
+            "Payment Required Response (402 response body)",
+            json!({
+                "x402Version": integer_schema("Version of the x402 payment protocol"),
+                "x402Versions": array_schema(
+                    "Every version the resource server accepts",
+                    json!({ "type": "integer" }),
+                ),
+                "accepts": array_schema(
+                    "Payment requirements the resource server accepts",
+                    schema_ref::<PaymentRequirements>(),
+                ),
+                "error": string_schema("Error message"),
+                "paymentError": schema_ref::<PaymentErrorBody>(),
+            }),
+            &["x402Version", "accepts"],
+        )
+    }
+}
+
+impl JsonSchema for PaymentErrorBody {
+    const NAME: &'static str = "PaymentErrorBody";
+
+    fn schema() -> Value {
+        object_schema(
+            "Rejection of a payment, with hints for the client to act on",
+            json!({
+                "code": integer_schema("Stable code of the error"),
+                "message": string_schema("Human-readable description of the error"),
+                "retryAfter": integer_schema(
+                    "Seconds to wait before paying again, when the rejection is temporary",
+                ),
+                "requiredTopupAmount": stroops_schema(
+                    "Amount to deposit into the escrow before paying again, in stroops",
+                ),
+                "requirements": schema_ref::<PaymentRequirements>(),
+            }),
+            &["code", "message"],
+        )
+    }
+}
+
+impl JsonSchema for PaymentRequirements {
+    const NAME: &'static str = "PaymentRequirements";
+
+    fn schema() -> Value {
+        object_schema(
+            "Network, amount, recipient, and resource being paid for",
+            json!({
+                "x402Version": integer_schema(
+                    "Version of the x402 payment protocol the requirements are paid with",
+                ),
+                "scheme": string_schema("Scheme of the payment protocol to use (e.g., \"escrow\")"),
+                "network": string_schema("Network to send payment on (e.g., \"stellar-testnet\")"),
+                "maxAmountRequired": stroops_schema(
+                    "Maximum amount required to pay for the resource, in stroops",
+                ),
+                "resource": string_schema("URL of resource to pay for"),
+                "description": string_schema("Description of the resource"),
+                "mimeType": string_schema("MIME type of the resource response"),
+                "outputSchema": { "description": "Output schema of the resource response" },
+                "payTo": string_schema("Stellar address to pay value to (G... format)"),
+                "asset": string_schema(
+                    "Asset the amount is denominated in, a contract address (C... format) or \"native\"",
+                ),
+                "alternatives": array_schema(
+                    "Other assets accepted instead of asset, each at its own amount",
+                    schema_ref::<AssetAmount>(),
+                ),
+                "maxTimeoutSeconds": integer_schema(
+                    "Maximum time in seconds for the resource server to respond",
+                ),
+                "extra": { "description": "Extra information specific to the scheme" },
+            }),
+            &[
+                "scheme",
+                "network",
+                "maxAmountRequired",
+                "resource",
+                "description",
+                "mimeType",
+                "payTo",
+                "maxTimeoutSeconds",
+            ],
+        )
+    }
+}
+
+impl JsonSchema for AssetAmount {
+    const NAME: &'static str = "AssetAmount";
+
+    fn schema() -> Value {
+        object_schema(
+            "Amount of an asset a resource can be paid with",
+            json!({
+                "asset": string_schema(
+                    "Asset, a contract address (C... format) or \"native\"",
+                ),
+                "maxAmountRequired": stroops_schema(
+                    "Maximum amount required in the asset, in its stroops",
+                ),
+            }),
+            &["asset", "maxAmountRequired"],
+        )
+    }
+}
+
+impl JsonSchema for PaymentPayload {
+    const NAME: &'static str = "PaymentPayload";
+
+    fn schema() -> Value {
+        let mut schema = object_schema(
+            "X-PAYMENT header content, base64 encoded JSON",
+            json!({
+                "x402Version": integer_schema("Version of the x402 payment protocol"),
+                "scheme": string_schema("Scheme value of the accepted payment requirements"),
+                "network": string_schema("Network id of the accepted payment requirements"),
+                "asset": string_schema(
+                    "Asset paid in, one of the accepted requirements' options when they offer several",
+                ),
+                "payload": schema_ref::<SchemePayload>(),
+            }),
+            &["x402Version", "scheme", "network", "payload"],
+        );
+        schema["patternProperties"] = json!({
+            "^x-": { "description": "Extension field (version 2)" },
+        });
+        schema
+    }
+}
+
+impl JsonSchema for SchemePayload {
+    const NAME: &'static str = "SchemePayload";
+
+    fn schema() -> Value {
+        json!({
+            "description": "Scheme-dependent part of a PaymentPayload",
+            "anyOf": [
+                schema_ref::<EscrowPayload>(),
+                schema_ref::<TransactionPayload>(),
+                schema_ref::<TransactionHashPayload>(),
+            ],
+        })
+    }
+}
+
+impl JsonSchema for EscrowPayload {
+    const NAME: &'static str = "EscrowPayload";
+
+    fn schema() -> Value {
+        object_schema(
+            "Authorization to charge an escrow, signed by the escrow client",
+            json!({
+                "escrowId": integer_schema("Escrow account ID"),
+                "client": string_schema("Client address (G... format)"),
+                "amount": stroops_schema("Authorized amount, in stroops"),
+                "nonce": integer_schema("Client-chosen nonce, unique per escrow"),
+                "expiresAt": integer_schema(
+                    "Unix timestamp after which the authorization is void",
+                ),
+                "signature": string_schema(
+                    "Hex-encoded ed25519 signature of the signing hash by the client",
+                ),
+                "depositAuthorization": string_schema(
+                    "Base64 XDR SorobanAuthorizationEntry pre-signed by the client for a deposit into the escrow, submitted before settling when the balance falls short",
+                ),
+                "paymentId": integer_schema(
+                    "Payment the contract created from this authorization when opening the escrow, settled rather than created again",
+                ),
+            }),
+            &[
+                "escrowId",
+                "client",
+                "amount",
+                "nonce",
+                "expiresAt",
+                "signature",
+            ],
+        )
+    }
+}
+
+impl JsonSchema for TransactionPayload {
+    const NAME: &'static str = "TransactionPayload";
+
+    fn schema() -> Value {
+        object_schema(
+            "Pre-signed Stellar transaction",
+            json!({
+                "transaction": string_schema(
+                    "XDR-encoded transaction envelope (includes signatures)",
+                ),
+                "signatures": array_schema(
+                    "XDR-encoded signatures, if not in the envelope",
+                    json!({ "type": "string" }),
+                ),
+            }),
+            &["transaction"],
+        )
+    }
+}
+
+impl JsonSchema for TransactionHashPayload {
+    const NAME: &'static str = "TransactionHashPayload";
+
+    fn schema() -> Value {
+        object_schema(
+            "Proof of a payment made with a classic Stellar payment transaction",
+            json!({
+                "txHash": string_schema("Hex-encoded hash of the payment transaction"),
+            }),
+            &["txHash"],
+        )
+    }
+}
+
+impl JsonSchema for VerifyRequest {
+    const NAME: &'static str = "VerifyRequest";
+
+    fn schema() -> Value {
+        object_schema(
+            "Facilitator /verify endpoint request",
+            json!({
+                "x402Version": integer_schema("Version of the x402 payment protocol"),
+                "paymentHeader": string_schema(
+                    "The X-PAYMENT header value (base64 encoded PaymentPayload)",
+                ),
+                "paymentRequirements": schema_ref::<PaymentRequirements>(),
+            }),
+            &["x402Version", "paymentHeader", "paymentRequirements"],
+        )
+    }
+}
+
+impl JsonSchema for VerifyResponse {
+    const NAME: &'static str = "VerifyResponse";
+
+    fn schema() -> Value {
+        object_schema(
+            "Facilitator /verify endpoint response",
+            json!({
+                "isValid": boolean_schema("Whether the payment is valid"),
+                "invalidReason": nullable_schema(string_schema(
+                    "Reason for invalidity (if isValid is false)",
+                )),
+                "errorCode": integer_schema("Stable code of the reason, see x402-errors"),
+                "x402Versions": array_schema(
+                    "Versions the facilitator supports, when the payment's is not",
+                    json!({ "type": "integer" }),
+                ),
+            }),
+            &["isValid"],
+        )
+    }
+}
+
+impl JsonSchema for SettleRequest {
+    const NAME: &'static str = "SettleRequest";
+
+    fn schema() -> Value {
+        object_schema(
+            "Facilitator /settle endpoint request",
+            json!({
+                "x402Version": integer_schema("Version of the x402 payment protocol"),
+                "paymentHeader": string_schema(
+                    "The X-PAYMENT header value (base64 encoded PaymentPayload)",
+                ),
+                "paymentRequirements": schema_ref::<PaymentRequirements>(),
+                "settleAmount": stroops_schema(
+                    "Amount to charge, in stroops, at most the authorized amount; the authorized amount if absent",
+                ),
+                "dryRun": boolean_schema("Simulate the settlement without submitting it"),
+            }),
+            &["x402Version", "paymentHeader", "paymentRequirements"],
+        )
+    }
+}
+
+impl JsonSchema for SettleResponse {
+    const NAME: &'static str = "SettleResponse";
+
+    fn schema() -> Value {
+        object_schema(
+            "Facilitator /settle endpoint response",
+            json!({
+                "success": boolean_schema("Whether the payment was successful"),
+                "error": nullable_schema(string_schema(
+                    "Error message from the facilitator (if success is false)",
+                )),
+                "errorCode": integer_schema("Stable code of the error, see x402-errors"),
+                "txHash": nullable_schema(string_schema(
+                    "Transaction hash of the settled payment",
+                )),
+                "networkId": nullable_schema(string_schema(
+                    "Network id the payment was settled on",
+                )),
+                "paymentId": integer_schema("Escrow payment ID (escrow scheme only)"),
+                "ledger": integer_schema("Ledger the settlement transaction was included in"),
+                "finality": {
+                    "type": "string",
+                    "enum": ["included", "final"],
+                    "description": "Finality of the settlement, if the facilitator tracks it",
+                },
+                "simulation": schema_ref::<SettlementSimulation>(),
+            }),
+            &["success"],
+        )
+    }
+}
+
+impl JsonSchema for SettlementSimulation {
+    const NAME: &'static str = "SettlementSimulation";
+
+    fn schema() -> Value {
+        object_schema(
+            "Outcome of a simulated settlement",
+            json!({
+                "amount": stroops_schema("Amount that would be charged, in stroops"),
+                "paymentId": integer_schema(
+                    "Payment ID the contract would assign (escrow scheme only)",
+                ),
+                "estimatedFee": integer_schema(
+                    "Estimated total fee of the transaction, in stroops",
+                ),
+            }),
+            &["amount"],
+        )
+    }
+}
+
+impl JsonSchema for PreflightResponse {
+    const NAME: &'static str = "PreflightResponse";
+
+    fn schema() -> Value {
+        object_schema(
+            "Facilitator /preflight endpoint response, a non-binding forecast of the settlement",
+            json!({
+                "ok": boolean_schema("Whether the settlement would succeed"),
+                "invalidReason": string_schema("Reason the settlement would fail (if ok is false)"),
+                "errorCode": integer_schema("Stable code of the reason, see x402-errors"),
+                "estimatedFee": integer_schema(
+                    "Estimated total fee of the settlement, in stroops",
+                ),
+                "availableBalance": stroops_schema(
+                    "Balance of the escrow payments may still draw on, in stroops (escrow scheme only)",
+                ),
+                "binding": {
+                    "type": "boolean",
+                    "enum": [false],
+                    "description": "Whether the facilitator commits to the verdict, always false",
+                },
+                "cached": boolean_schema("Whether the verdict was reused from an earlier preflight"),
+            }),
+            &["ok", "binding"],
+        )
+    }
+}
+
+impl JsonSchema for SupportedKind {
+    const NAME: &'static str = "SupportedKind";
+
+    fn schema() -> Value {
+        object_schema(
+            "Scheme and network pair a facilitator can settle",
+            json!({
+                "x402Version": integer_schema("Version of the x402 payment protocol"),
+                "scheme": string_schema("Payment scheme (e.g., \"escrow\")"),
+                "network": string_schema("Network id (e.g., \"stellar-testnet\")"),
+                "networkPassphrase": string_schema("Stellar network passphrase of the network"),
+            }),
+            &["x402Version", "scheme", "network", "networkPassphrase"],
+        )
+    }
+}
+
+impl JsonSchema for FeePolicy {
+    const NAME: &'static str = "FeePolicy";
+
+    fn schema() -> Value {
+        object_schema(
+            "Fees charged by a facilitator on top of the payment",
+            json!({
+                "feeBps": integer_schema("Facilitator fee, in basis points of the settled amount"),
+                "networkFeeSponsored": boolean_schema(
+                    "Whether the facilitator pays the network transaction fees",
+                ),
+            }),
+            &["feeBps", "networkFeeSponsored"],
+        )
+    }
+}
+
+impl JsonSchema for SupportedResponse {
+    const NAME: &'static str = "SupportedResponse";
+
+    fn schema() -> Value {
+        object_schema(
+            "Facilitator /supported endpoint response",
+            json!({
+                "kinds": array_schema(
+                    "Scheme and network pairs the facilitator settles",
+                    schema_ref::<SupportedKind>(),
+                ),
+                "assets": array_schema(
+                    "Accepted asset contract addresses (C... format)",
+                    json!({ "type": "string" }),
+                ),
+                "fee": schema_ref::<FeePolicy>(),
+                "escrowContract": string_schema("Escrow contract address (C... format)"),
+            }),
+            &["kinds", "assets", "fee", "escrowContract"],
+        )
+    }
+}
+
+impl JsonSchema for PaymentResponseHeader {
+    const NAME: &'static str = "PaymentResponseHeader";
+
+    fn schema() -> Value {
+        object_schema(
+            "X-PAYMENT-RESPONSE header content, base64 encoded JSON",
+            json!({
+                "settlement": schema_ref::<SettleResponse>(),
+                "amount": stroops_schema(
+                    "Amount charged, in stroops, for resources priced once served",
+                ),
+            }),
+            &["settlement"],
+        )
+    }
+}
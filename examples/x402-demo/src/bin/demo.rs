@@ -0,0 +1,104 @@
+//! End-to-end demo: deploy the escrow contract, open an escrow, and pay for
+//! a request, printing the receipt
+//!
+//! Runs against a fresh quickstart container, or the network of
+//! `X402_IT_RPC_URL`, see the `x402-integration` crate.
+
+use std::{
+    error::Error,
+    net::SocketAddr,
+    process::ExitCode,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use sha2::{Digest, Sha256};
+use x402_axum::HttpFacilitator;
+use x402_client::{EscrowClient, LocalSigner, X402HttpClient};
+use x402_demo::{app, describe, network_name, serve, DEPOSIT};
+use x402_facilitator::Facilitator;
+use x402_integration::{escrow_wasm, Quickstart};
+
+/// Any free port on the loopback interface
+const LOCALHOST: SocketAddr =
+    SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), 0);
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("demo: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
+    println!("Starting the network...");
+    let network = Quickstart::start().await?;
+    let name = network_name(network.passphrase());
+
+    // Fresh accounts per run, so reruns on testnet start clean
+    let (deployer, client_seed, server_seed) = (seed("deployer"), seed("client"), seed("server"));
+    let client_addr = LocalSigner::from_bytes(&client_seed).address();
+    let server_addr = LocalSigner::from_bytes(&server_seed).address();
+    for seed in [&deployer, &client_seed, &server_seed] {
+        network
+            .fund(&LocalSigner::from_bytes(seed).address())
+            .await?;
+    }
+
+    println!("Deploying the escrow contract...");
+    let contract = network.deploy(&escrow_wasm()?, &deployer).await?;
+    println!("Escrow contract {contract} on {name}");
+    let escrow = |seed: &[u8; 32]| {
+        EscrowClient::new(
+            network.rpc().clone(),
+            &contract,
+            network.passphrase(),
+            LocalSigner::from_bytes(seed),
+        )
+    };
+
+    // Facilitator settling as the server, and the app verifying through it
+    let facilitator = Arc::new(Facilitator::new(escrow(&server_seed)?, name));
+    let facilitator_url = serve(x402_facilitator::router(facilitator), LOCALHOST).await?;
+    let verifier = Arc::new(HttpFacilitator::new(facilitator_url, name));
+    let url = serve(app(&server_addr, verifier), LOCALHOST).await?;
+    println!("Serving {url}");
+
+    let client = escrow(&client_seed)?;
+    let opened = client
+        .open_escrow(&client_addr, &server_addr, DEPOSIT)
+        .await;
+    let escrow_id = match opened {
+        Ok(submitted) => submitted.value,
+        Err(e) => return Err(network.diagnose(&e).await.into()),
+    };
+    println!("Opened escrow {escrow_id} with {DEPOSIT} stroops");
+
+    let paying = X402HttpClient::builder(client, name).build();
+    let free = paying.get(format!("{url}/")).await?;
+    println!("GET / -> {}", free.response.status());
+
+    let paid = match paying.get(format!("{url}/weather")).await {
+        Ok(paid) => paid,
+        Err(e) => return Err(network.diagnose(&e).await.into()),
+    };
+    println!("GET /weather -> {}", paid.response.status());
+    let receipt = paid
+        .receipt
+        .ok_or("the paid route was served without payment")?;
+    println!("{}", paid.response.text().await?);
+    println!("\nReceipt\n{}", describe(&receipt));
+    Ok(())
+}
+
+fn seed(name: &str) -> [u8; 32] {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    Sha256::digest(format!("{name}-{nanos}")).into()
+}
@@ -0,0 +1,41 @@
+//! Demo app alone, verifying payments through a running facilitator
+//!
+//! * `X402_DEMO_PAY_TO` - Server address payments are made to (required)
+//! * `X402_DEMO_BIND` - Address to listen on, default `127.0.0.1:3000`
+//! * `X402_FACILITATOR_URL` - Facilitator, default `http://127.0.0.1:4020`
+//! * `X402_NETWORK` - x402 network id, default `stellar-testnet`
+
+use std::{env, process::ExitCode, sync::Arc};
+
+use x402_axum::HttpFacilitator;
+use x402_demo::app;
+use x402_types::STELLAR_TESTNET;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let Ok(pay_to) = env::var("X402_DEMO_PAY_TO") else {
+        eprintln!("demo-server: X402_DEMO_PAY_TO is not set");
+        return ExitCode::FAILURE;
+    };
+    let bind = env::var("X402_DEMO_BIND").unwrap_or_else(|_| "127.0.0.1:3000".into());
+    let facilitator =
+        env::var("X402_FACILITATOR_URL").unwrap_or_else(|_| "http://127.0.0.1:4020".into());
+    let network = env::var("X402_NETWORK").unwrap_or_else(|_| STELLAR_TESTNET.into());
+
+    let listener = match tokio::net::TcpListener::bind(&bind).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("demo-server: cannot bind {bind}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let verifier = Arc::new(HttpFacilitator::new(facilitator, network));
+    println!("demo-server: listening on http://{bind}, GET /weather to pay");
+    match axum::serve(listener, app(&pay_to, verifier)).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("demo-server: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
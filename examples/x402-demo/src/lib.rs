@@ -0,0 +1,107 @@
+//! # x402 Demo
+//!
+//! Runnable reference of an x402-gated service: an axum app with a free
+//! route and a paid one, a facilitator settling its payments, and a client
+//! paying through the SDK.
+//!
+//! ## Running
+//! `cargo demo` starts a `stellar/quickstart` container, deploys the escrow
+//! contract, funds a client and a server, opens an escrow, and pays for
+//! `/weather`, printing the receipt. It needs Docker and the
+//! `wasm32v1-none` target. Set `X402_IT_RPC_URL`, `X402_IT_FRIENDBOT_URL`,
+//! and `X402_IT_NETWORK_PASSPHRASE` to run it on testnet instead, as for
+//! the `x402-integration` tests.
+//!
+//! `cargo run -p x402-demo --bin demo-server` serves the app alone,
+//! verifying through a facilitator started with the bundled
+//! `facilitator.env`.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{routing::get, Router};
+use x402_axum::{Paid, PaymentLayer, Verifier};
+use x402_client::PaymentReceipt;
+use x402_types::{network_passphrase, NATIVE_ASSET, STELLAR_TESTNET};
+
+/// Price of the paid route, in stroops
+pub const PRICE: i128 = 100_000;
+
+/// Initial deposit of the client's escrow, in stroops
+pub const DEPOSIT: i128 = 1_000_000;
+
+/// App with a free `/` and a `/weather` paid to `pay_to`
+///
+/// # Arguments
+/// * `pay_to` - Server address payments are made to (G... format)
+/// * `verifier` - Facilitator verifying and settling the payments
+pub fn app(pay_to: &str, verifier: Arc<dyn Verifier>) -> Router {
+    async fn free() -> &'static str {
+        "x402 demo: GET /weather for a paid forecast"
+    }
+
+    async fn weather(Paid(payment): Paid) -> String {
+        format!(
+            "Sunny, 21°C. Paid {} stroops from escrow {}",
+            payment.amount, payment.escrow_id
+        )
+    }
+
+    // Only routes added before the layer are gated
+    Router::new()
+        .route("/weather", get(weather))
+        .layer(
+            PaymentLayer::new(PRICE, NATIVE_ASSET, pay_to)
+                .with_description("Weather forecast")
+                .with_shared_verifier(verifier),
+        )
+        .route("/", get(free))
+}
+
+/// Serve `router` on `addr` in the background
+///
+/// # Returns
+/// * Base URL of the server, with the port bound when `addr` has none
+pub async fn serve(router: Router, addr: SocketAddr) -> std::io::Result<String> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(url)
+}
+
+/// x402 network id of the network of `passphrase`, "stellar-local" for a
+/// quickstart container
+pub fn network_name(passphrase: &str) -> &'static str {
+    if network_passphrase(STELLAR_TESTNET) == Some(passphrase) {
+        STELLAR_TESTNET
+    } else {
+        x402_integration::NETWORK
+    }
+}
+
+/// Receipt of a payment, as printed by the demo
+pub fn describe(receipt: &PaymentReceipt) -> String {
+    let mut lines = vec![
+        format!("resource    {}", receipt.requirements.resource),
+        format!("escrow      {}", receipt.escrow_id),
+        format!("amount      {} stroops", receipt.amount),
+        format!("nonce       {}", receipt.nonce),
+    ];
+    match &receipt.settlement {
+        Some(settlement) if settlement.success => {
+            let field = |value: Option<String>| value.unwrap_or_else(|| "-".into());
+            lines.push(format!(
+                "payment     {}",
+                field(settlement.payment_id.map(|id| id.to_string()))
+            ));
+            lines.push(format!("transaction {}", field(settlement.tx_hash.clone())));
+        }
+        Some(settlement) => lines.push(format!(
+            "settlement  failed: {}",
+            settlement.error.as_deref().unwrap_or("unknown error")
+        )),
+        None => lines.push("settlement  not reported".into()),
+    }
+    lines.join("\n")
+}
+
+mod test;
@@ -0,0 +1,57 @@
+#![cfg(test)]
+
+use std::net::{Ipv4Addr, SocketAddr};
+
+use axum::http::StatusCode;
+use x402_client::X402HttpClient;
+use x402_testkit::{TestKit, CLIENT_SEED, NETWORK};
+use x402_types::{network_passphrase, STELLAR_TESTNET};
+
+use crate::{app, describe, network_name, serve, DEPOSIT, PRICE};
+
+/// The demo flow against the in-process test kit, so the example keeps
+/// working without a network
+#[tokio::test]
+async fn test_demo_flow() {
+    let kit = TestKit::new();
+    let wallet = kit.wallet(&CLIENT_SEED);
+    let escrow_id = wallet.open_escrow(DEPOSIT).await;
+    let url = serve(
+        app(kit.server(), kit.facilitator()),
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+    )
+    .await
+    .unwrap();
+
+    let paying = X402HttpClient::builder(kit.escrow_client(&CLIENT_SEED), NETWORK).build();
+    let free = paying.get(format!("{url}/")).await.unwrap();
+    assert_eq!(free.response.status(), StatusCode::OK);
+    assert!(free.receipt.is_none());
+
+    let paid = paying.get(format!("{url}/weather")).await.unwrap();
+    assert_eq!(paid.response.status(), StatusCode::OK);
+    let receipt = paid.receipt.expect("payment receipt");
+    assert_eq!(receipt.escrow_id, escrow_id);
+    assert_eq!(receipt.amount, PRICE);
+    let settlement = receipt.settlement.clone().expect("settlement");
+    assert!(settlement.success, "{:?}", settlement.error);
+    kit.assert_settled(settlement.payment_id.unwrap()).await;
+    assert_eq!(
+        wallet.client().get_escrow_balance(escrow_id).await.unwrap(),
+        DEPOSIT - PRICE
+    );
+
+    let printed = describe(&receipt);
+    assert!(printed.contains(&format!("amount      {PRICE} stroops")));
+    assert!(printed.contains("transaction "));
+}
+
+#[test]
+fn test_network_name() {
+    let testnet = network_passphrase(STELLAR_TESTNET).unwrap();
+    assert_eq!(network_name(testnet), STELLAR_TESTNET);
+    assert_eq!(
+        network_name(x402_integration::LOCAL_PASSPHRASE),
+        x402_integration::NETWORK
+    );
+}